@@ -0,0 +1,97 @@
+//! Compile-time benchmarks.
+//!
+//! Run with `cargo bench`. This compiles the functions in the `benches/corpus` directory for 64-bit
+//! Intel with `opt_level=speed` and the verifier disabled, and reports the compilation speed of
+//! each file and the time spent in each compiler pass. The settings are fixed so the numbers can
+//! be compared between runs. Use `cton-util bench` to benchmark other files or settings.
+
+extern crate cretonne;
+extern crate cton_reader;
+extern crate cton_wasm;
+extern crate wat;
+
+use cretonne::{settings, timing, Context};
+use cretonne::ir::Function;
+use cretonne::isa::{self, TargetIsa};
+use cretonne::settings::Configurable;
+use cton_reader::parse_functions;
+use cton_wasm::{translate_module, DummyEnvironment};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// The number of times each function is compiled.
+const ITERATIONS: usize = 100;
+
+fn main() {
+    let mut flag_builder = settings::builder();
+    flag_builder.set_bool("is_64bit", true).unwrap();
+    flag_builder.set_bool("enable_verifier", false).unwrap();
+    flag_builder.set("opt_level", "speed").unwrap();
+    let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&flag_builder));
+
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches").join("corpus");
+    let mut paths: Vec<_> = fs::read_dir(&corpus)
+        .expect("corpus directory")
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+
+    timing::set_enabled(true);
+    for path in paths {
+        let funcs = load(&path, &*isa);
+        let insts: usize = funcs
+            .iter()
+            .map(|func| {
+                     func.layout
+                         .ebbs()
+                         .map(|ebb| func.layout.ebb_insts(ebb).count())
+                         .sum::<usize>()
+                 })
+            .sum();
+        let elapsed = compile(&funcs, &*isa);
+        let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        println!("{}: {} functions, {} instructions, {:.3} ms, {:.0} instructions/s",
+                 path.file_name().unwrap().to_string_lossy(),
+                 funcs.len(),
+                 insts,
+                 secs * 1e3 / ITERATIONS as f64,
+                 (insts * ITERATIONS) as f64 / secs);
+    }
+    println!("\nPass times for {} iterations:", ITERATIONS);
+    print!("{}", timing::take_current());
+}
+
+/// Load the functions in the IL file or WebAssembly module at `path`.
+fn load(path: &Path, isa: &TargetIsa) -> Vec<Function> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("wasm") | Some("wat") => {
+            let data = wat::parse_file(path).unwrap();
+            let mut env = DummyEnvironment::with_flags(isa.flags().clone());
+            translate_module(&data, &mut env).unwrap();
+            env.info.function_bodies
+        }
+        _ => {
+            let mut text = String::new();
+            File::open(path).unwrap().read_to_string(&mut text).unwrap();
+            parse_functions(&text).unwrap()
+        }
+    }
+}
+
+/// Compile `funcs` `ITERATIONS` times, and return the time spent compiling.
+fn compile(funcs: &[Function], isa: &TargetIsa) -> Duration {
+    let mut ctx = Context::new();
+    let mut elapsed = Duration::new(0, 0);
+    for _ in 0..ITERATIONS {
+        for func in funcs {
+            ctx.clear();
+            ctx.func = func.clone();
+            let start = Instant::now();
+            ctx.compile(isa).unwrap();
+            elapsed += start.elapsed();
+        }
+    }
+    elapsed
+}
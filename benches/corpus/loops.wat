@@ -0,0 +1,57 @@
+;; Loops, as produced by a WebAssembly compiler.
+;;
+;; The Intel ISA can't compile integer comparisons, heap accesses, and calls
+;; yet, so the loops count down to zero and there are no memory accesses or
+;; calls.
+(module
+  (func $fib (param $n i32) (result i64)
+    (local $a i64) (local $b i64) (local $t i64)
+    (local.set $b (i64.const 1))
+    (if (local.get $n)
+      (then
+        (loop $next
+          (local.set $t (i64.add (local.get $a) (local.get $b)))
+          (local.set $a (local.get $b))
+          (local.set $b (local.get $t))
+          (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+          (br_if $next (local.get $n)))))
+    (local.get $a))
+
+  (func $gcd (param $a i32) (param $b i32) (result i32)
+    (local $t i32)
+    (if (local.get $b)
+      (then
+        (loop $next
+          (local.set $t (i32.rem_u (local.get $a) (local.get $b)))
+          (local.set $a (local.get $b))
+          (local.set $b (local.get $t))
+          (br_if $next (local.get $b)))))
+    (local.get $a))
+
+  (func $pow (param $x i64) (param $n i32) (result i64)
+    (local $acc i64)
+    (local.set $acc (i64.const 1))
+    (if (local.get $n)
+      (then
+        (loop $next
+          (if (i32.and (local.get $n) (i32.const 1))
+            (then (local.set $acc (i64.mul (local.get $acc) (local.get $x)))))
+          (local.set $x (i64.mul (local.get $x) (local.get $x)))
+          (local.set $n (i32.shr_u (local.get $n) (i32.const 1)))
+          (br_if $next (local.get $n)))))
+    (local.get $acc))
+
+  (func $hash (param $x i32) (param $rounds i32) (result i32)
+    (if (local.get $rounds)
+      (then
+        (loop $next
+          (local.set $x (i32.xor (local.get $x) (i32.shr_u (local.get $x) (i32.const 16))))
+          (local.set $x (i32.mul (local.get $x) (i32.const 0x45d9f3b)))
+          (local.set $x (i32.xor (local.get $x) (i32.shr_u (local.get $x) (i32.const 16))))
+          (local.set $rounds (i32.sub (local.get $rounds) (i32.const 1)))
+          (br_if $next (local.get $rounds)))))
+    (local.get $x))
+
+  (func $pick (param $a i32) (param $b i32) (param $c i32) (result i32)
+    (select (local.get $a) (local.get $b) (local.get $c)))
+)
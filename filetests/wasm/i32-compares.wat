@@ -0,0 +1,4 @@
+(module
+  (func (param i32) (param i32) (result i32)
+    (i32.lt_u (local.get 0) (local.get 1)))
+)
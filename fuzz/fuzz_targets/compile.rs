@@ -0,0 +1,10 @@
+//! Compile functions generated from arbitrary input with the verifier enabled.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cton_reader;
+
+fuzz_target!(|data: &[u8]| {
+    cton_reader::fuzz::compile(data);
+});
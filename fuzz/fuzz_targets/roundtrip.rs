@@ -0,0 +1,10 @@
+//! Parse arbitrary input as Cretonne IL, and check that printing and parsing again is stable.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cton_reader;
+
+fuzz_target!(|data: &[u8]| {
+    cton_reader::fuzz::roundtrip(data);
+});
@@ -0,0 +1,95 @@
+/*
+ * C API for the Cretonne code generator.
+ *
+ * Link with the static library built by the cretonne-capi crate. See the documentation of the
+ * cton_capi Rust crate for the details of each function.
+ */
+
+#ifndef CRETONNE_H
+#define CRETONNE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes returned by the functions that can fail. */
+typedef uint32_t cton_status;
+#define CTON_OK 0
+#define CTON_ERROR_BAD_NAME 1
+#define CTON_ERROR_BAD_TYPE 2
+#define CTON_ERROR_BAD_VALUE 3
+#define CTON_ERROR_PARSE 4
+#define CTON_ERROR_INVALID 5
+#define CTON_ERROR_COMPILE 6
+
+/* References to EBBs and values. */
+typedef uint32_t cton_ref;
+#define CTON_INVALID UINT32_MAX
+
+/* Scalar value types. */
+typedef uint32_t cton_type;
+#define CTON_TYPE_B1 1
+#define CTON_TYPE_I8 2
+#define CTON_TYPE_I16 3
+#define CTON_TYPE_I32 4
+#define CTON_TYPE_I64 5
+#define CTON_TYPE_F32 6
+#define CTON_TYPE_F64 7
+#define CTON_TYPE_I128 8
+
+typedef struct CtonIsaBuilder cton_isa_builder;
+typedef struct CtonIsa cton_isa;
+typedef struct CtonContext cton_context;
+
+/* Target ISAs. */
+cton_isa_builder *cton_isa_builder_new(const char *name);
+cton_status cton_isa_builder_set(cton_isa_builder *builder, const char *name, const char *value);
+void cton_isa_builder_free(cton_isa_builder *builder);
+cton_isa *cton_isa_builder_finish(cton_isa_builder *builder);
+void cton_isa_free(cton_isa *isa);
+
+/* Compilation contexts. */
+cton_context *cton_context_new(void);
+void cton_context_free(cton_context *ctx);
+void cton_context_clear(cton_context *ctx);
+const char *cton_context_error(const cton_context *ctx);
+cton_status cton_context_parse(cton_context *ctx, const char *text);
+cton_status cton_context_compile(cton_context *ctx, const cton_isa *isa, uint32_t *code_size);
+
+/* Building functions. */
+cton_status cton_signature_append_param(cton_context *ctx, cton_type ty);
+cton_status cton_signature_append_return(cton_context *ctx, cton_type ty);
+cton_ref cton_ebb_create(cton_context *ctx);
+cton_ref cton_ebb_append_param(cton_context *ctx, cton_ref ebb, cton_type ty);
+cton_status cton_position_at_end(cton_context *ctx, cton_ref ebb);
+cton_ref cton_ins_iconst(cton_context *ctx, cton_type ty, int64_t imm);
+cton_ref cton_ins_unary(cton_context *ctx, const char *opcode, cton_type ty, cton_ref arg);
+cton_ref cton_ins_binary(cton_context *ctx, const char *opcode, cton_ref lhs, cton_ref rhs);
+cton_ref cton_ins_binary_imm(cton_context *ctx, const char *opcode, cton_ref lhs, int64_t imm);
+cton_ref cton_ins_icmp(cton_context *ctx, const char *cond, cton_ref lhs, cton_ref rhs);
+cton_status cton_ins_jump(cton_context *ctx, cton_ref ebb, const cton_ref *args, size_t nargs);
+cton_status cton_ins_branch(cton_context *ctx, const char *opcode, cton_ref cond, cton_ref ebb,
+                            const cton_ref *args, size_t nargs);
+cton_status cton_ins_return(cton_context *ctx, const cton_ref *args, size_t nargs);
+
+/* Emitting machine code. Any of the callbacks can be NULL. */
+typedef struct {
+    void *user_data;
+    void (*reloc_external)(void *user_data, uint32_t offset, const char *reloc, const char *name,
+                           int64_t addend);
+    void (*reloc_internal)(void *user_data, uint32_t offset, const char *reloc,
+                           uint32_t target_offset);
+    void (*trap)(void *user_data, uint32_t offset, const char *code, uint32_t srcloc);
+} cton_emit_callbacks;
+
+void cton_context_emit(const cton_context *ctx, const cton_isa *isa, uint8_t *mem,
+                       const cton_emit_callbacks *callbacks);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CRETONNE_H */
@@ -0,0 +1,453 @@
+//! Building a function one instruction at a time.
+//!
+//! EBBs are created in layout order, and the first one is the entry block. Instructions are
+//! appended to the EBB selected by `cton_position_at_end()`.
+//!
+//! The generic instruction functions take the opcode name as it appears in the IL text, like
+//! "iadd" or "brz". The opcode must have the instruction format matching the function.
+
+use cretonne::entity_map::EntityRef;
+use cretonne::ir::{types, Type, Value, Ebb, Inst, Opcode, ArgumentType, Cursor, InstBuilder,
+                   DataFlowGraph, VariableArgs};
+use cretonne::ir::condcodes::IntCC;
+use cretonne::ir::instructions::InstructionFormat;
+use context::CtonContext;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::slice;
+use super::{CtonStatus, CtonRef, CtonType, CTON_OK, CTON_ERROR_INVALID, CTON_INVALID,
+            CTON_TYPE_B1, CTON_TYPE_I8, CTON_TYPE_I16, CTON_TYPE_I32, CTON_TYPE_I64, CTON_TYPE_F32,
+            CTON_TYPE_F64, CTON_TYPE_I128};
+
+type BuildResult<T> = Result<T, String>;
+
+fn type_from_code(ty: CtonType) -> BuildResult<Type> {
+    match ty {
+        CTON_TYPE_B1 => Ok(types::B1),
+        CTON_TYPE_I8 => Ok(types::I8),
+        CTON_TYPE_I16 => Ok(types::I16),
+        CTON_TYPE_I32 => Ok(types::I32),
+        CTON_TYPE_I64 => Ok(types::I64),
+        CTON_TYPE_F32 => Ok(types::F32),
+        CTON_TYPE_F64 => Ok(types::F64),
+        CTON_TYPE_I128 => Ok(types::I128),
+        _ => Err(format!("unknown type code {}", ty)),
+    }
+}
+
+fn get_value(ctx: &CtonContext, v: CtonRef) -> BuildResult<Value> {
+    let value = Value::new(v as usize);
+    if v != CTON_INVALID && ctx.ctx.func.dfg.value_is_valid(value) {
+        Ok(value)
+    } else {
+        Err(format!("invalid value reference {}", v))
+    }
+}
+
+fn get_values(ctx: &CtonContext, args: *const CtonRef, nargs: usize) -> BuildResult<VariableArgs> {
+    let mut values = VariableArgs::new();
+    if nargs > 0 {
+        for &v in unsafe { slice::from_raw_parts(args, nargs) } {
+            values.push(get_value(ctx, v)?);
+        }
+    }
+    Ok(values)
+}
+
+fn get_ebb(ctx: &CtonContext, ebb: CtonRef) -> BuildResult<Ebb> {
+    if (ebb as usize) < ctx.ctx.func.dfg.num_ebbs() {
+        Ok(Ebb::new(ebb as usize))
+    } else {
+        Err(format!("invalid EBB reference {}", ebb))
+    }
+}
+
+/// Look up the opcode called `name`, which must have the instruction format `format`.
+fn get_opcode(name: *const c_char, format: InstructionFormat) -> BuildResult<Opcode> {
+    let name = unsafe { CStr::from_ptr(name) }.to_str().map_err(|e| e.to_string())?;
+    let opcode: Opcode = name.parse().map_err(|e: &str| format!("{}: {}", e, name))?;
+    if opcode.format() != format {
+        return Err(format!("{} doesn't have the {:?} format", name, format));
+    }
+    Ok(opcode)
+}
+
+/// Get the result type of a `opcode` instruction whose first value operand is `arg`.
+///
+/// If `opcode` doesn't get its controlling type variable from an operand, it is `ctrl_type`.
+fn result_type(ctx: &CtonContext, opcode: Opcode, arg: Option<Value>, ctrl_type: Type) -> Type {
+    let constraints = opcode.constraints();
+    let ctrl_type = match arg {
+        Some(arg) if constraints.use_typevar_operand() => ctx.ctx.func.dfg.value_type(arg),
+        _ => ctrl_type,
+    };
+    if constraints.fixed_results() == 0 {
+        types::VOID
+    } else {
+        constraints.result_type(0, ctrl_type)
+    }
+}
+
+/// Append an instruction at the current position with `build`.
+fn insert<F>(ctx: &mut CtonContext, build: F) -> BuildResult<Inst>
+    where F: FnOnce(&mut DataFlowGraph, &mut Cursor) -> Inst
+{
+    let ebb = ctx.position.ok_or("no insertion position".to_string())?;
+    let func = &mut ctx.ctx.func;
+    let mut pos = Cursor::new(&mut func.layout);
+    pos.goto_bottom(ebb);
+    Ok(build(&mut func.dfg, &mut pos))
+}
+
+/// Convert the result of building an instruction with a result value.
+fn value_result(ctx: &mut CtonContext, result: BuildResult<Inst>) -> CtonRef {
+    match result {
+        Ok(inst) => ctx.ctx.func.dfg.first_result(inst).index() as CtonRef,
+        Err(e) => {
+            ctx.fail(CTON_ERROR_INVALID, e);
+            CTON_INVALID
+        }
+    }
+}
+
+/// Convert the result of building an instruction without result values.
+fn status_result<T>(ctx: &mut CtonContext, result: BuildResult<T>) -> CtonStatus {
+    match result {
+        Ok(_) => CTON_OK,
+        Err(e) => ctx.fail(CTON_ERROR_INVALID, e),
+    }
+}
+
+/// Append a parameter of type `ty` to the function signature.
+///
+/// # Safety
+///
+/// `ctx` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn cton_signature_append_param(ctx: *mut CtonContext,
+                                                     ty: CtonType)
+                                                     -> CtonStatus {
+    let ctx = &mut *ctx;
+    let result = type_from_code(ty).map(|ty| {
+        ctx.ctx.func.signature.argument_types.push(ArgumentType::new(ty))
+    });
+    status_result(ctx, result)
+}
+
+/// Append a return value of type `ty` to the function signature.
+///
+/// # Safety
+///
+/// `ctx` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn cton_signature_append_return(ctx: *mut CtonContext,
+                                                      ty: CtonType)
+                                                      -> CtonStatus {
+    let ctx = &mut *ctx;
+    let result = type_from_code(ty).map(|ty| {
+        ctx.ctx.func.signature.return_types.push(ArgumentType::new(ty))
+    });
+    status_result(ctx, result)
+}
+
+/// Create a new EBB at the end of the function.
+///
+/// The entry block must have the function parameters as its parameters.
+///
+/// # Safety
+///
+/// `ctx` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ebb_create(ctx: *mut CtonContext) -> CtonRef {
+    let func = &mut (*ctx).ctx.func;
+    let ebb = func.dfg.make_ebb();
+    func.layout.append_ebb(ebb);
+    ebb.index() as CtonRef
+}
+
+/// Append a parameter of type `ty` to `ebb`, and return the parameter value.
+///
+/// # Safety
+///
+/// `ctx` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ebb_append_param(ctx: *mut CtonContext,
+                                               ebb: CtonRef,
+                                               ty: CtonType)
+                                               -> CtonRef {
+    let ctx = &mut *ctx;
+    let result = get_ebb(ctx, ebb).and_then(|ebb| {
+        let ty = type_from_code(ty)?;
+        Ok(ctx.ctx.func.dfg.append_ebb_arg(ebb, ty))
+    });
+    match result {
+        Ok(value) => value.index() as CtonRef,
+        Err(e) => {
+            ctx.fail(CTON_ERROR_INVALID, e);
+            CTON_INVALID
+        }
+    }
+}
+
+/// Append the following instructions to the end of `ebb`.
+///
+/// # Safety
+///
+/// `ctx` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn cton_position_at_end(ctx: *mut CtonContext, ebb: CtonRef) -> CtonStatus {
+    let ctx = &mut *ctx;
+    let result = get_ebb(ctx, ebb).map(|ebb| ctx.position = Some(ebb));
+    status_result(ctx, result)
+}
+
+/// Append an `iconst` instruction with the integer type `ty`.
+///
+/// # Safety
+///
+/// `ctx` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_iconst(ctx: *mut CtonContext, ty: CtonType, imm: i64) -> CtonRef {
+    let ctx = &mut *ctx;
+    let result = type_from_code(ty).and_then(|ty| {
+        insert(ctx,
+               |dfg, pos| dfg.ins(pos).UnaryImm(Opcode::Iconst, ty, imm.into()).0)
+    });
+    value_result(ctx, result)
+}
+
+/// Append an instruction with one value operand, like `ineg` or `sextend`.
+///
+/// For conversions, `ty` is the result type. It is ignored when the type can be inferred from the
+/// operand.
+///
+/// # Safety
+///
+/// `ctx` must be a live context, and `opcode` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_unary(ctx: *mut CtonContext,
+                                        opcode: *const c_char,
+                                        ty: CtonType,
+                                        arg: CtonRef)
+                                        -> CtonRef {
+    let ctx = &mut *ctx;
+    let result = (|| {
+        let opcode = get_opcode(opcode, InstructionFormat::Unary)?;
+        let arg = get_value(ctx, arg)?;
+        let ctrl = if opcode.constraints().use_typevar_operand() {
+            types::VOID
+        } else {
+            type_from_code(ty)?
+        };
+        let ty = result_type(ctx, opcode, Some(arg), ctrl);
+        insert(ctx, |dfg, pos| dfg.ins(pos).Unary(opcode, ty, arg).0)
+    })();
+    value_result(ctx, result)
+}
+
+/// Append an instruction with two value operands, like `iadd`.
+///
+/// # Safety
+///
+/// `ctx` must be a live context, and `opcode` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_binary(ctx: *mut CtonContext,
+                                         opcode: *const c_char,
+                                         lhs: CtonRef,
+                                         rhs: CtonRef)
+                                         -> CtonRef {
+    let ctx = &mut *ctx;
+    let result = (|| {
+        let opcode = get_opcode(opcode, InstructionFormat::Binary)?;
+        let lhs = get_value(ctx, lhs)?;
+        let rhs = get_value(ctx, rhs)?;
+        let ty = result_type(ctx, opcode, Some(lhs), types::VOID);
+        insert(ctx, |dfg, pos| dfg.ins(pos).Binary(opcode, ty, lhs, rhs).0)
+    })();
+    value_result(ctx, result)
+}
+
+/// Append an instruction with a value and an immediate operand, like `iadd_imm`.
+///
+/// # Safety
+///
+/// `ctx` must be a live context, and `opcode` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_binary_imm(ctx: *mut CtonContext,
+                                             opcode: *const c_char,
+                                             lhs: CtonRef,
+                                             imm: i64)
+                                             -> CtonRef {
+    let ctx = &mut *ctx;
+    let result = (|| {
+        let opcode = get_opcode(opcode, InstructionFormat::BinaryImm)?;
+        let lhs = get_value(ctx, lhs)?;
+        let ty = result_type(ctx, opcode, Some(lhs), types::VOID);
+        insert(ctx, |dfg, pos| dfg.ins(pos).BinaryImm(opcode, ty, lhs, imm.into()).0)
+    })();
+    value_result(ctx, result)
+}
+
+/// Append an `icmp` instruction with the condition code `cond`, like "eq" or "slt".
+///
+/// # Safety
+///
+/// `ctx` must be a live context, and `cond` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_icmp(ctx: *mut CtonContext,
+                                       cond: *const c_char,
+                                       lhs: CtonRef,
+                                       rhs: CtonRef)
+                                       -> CtonRef {
+    let ctx = &mut *ctx;
+    let result = (|| {
+        let name = CStr::from_ptr(cond).to_str().map_err(|e| e.to_string())?;
+        let cond: IntCC = name.parse().map_err(|_| format!("unknown condition code {}", name))?;
+        let lhs = get_value(ctx, lhs)?;
+        let rhs = get_value(ctx, rhs)?;
+        let ty = result_type(ctx, Opcode::Icmp, Some(lhs), types::VOID);
+        insert(ctx, |dfg, pos| dfg.ins(pos).IntCompare(Opcode::Icmp, ty, cond, lhs, rhs).0)
+    })();
+    value_result(ctx, result)
+}
+
+/// Append a `jump` to `ebb` passing the `nargs` values in `args`.
+///
+/// # Safety
+///
+/// `ctx` must be a live context, and `args` must point to `nargs` value references.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_jump(ctx: *mut CtonContext,
+                                       ebb: CtonRef,
+                                       args: *const CtonRef,
+                                       nargs: usize)
+                                       -> CtonStatus {
+    let ctx = &mut *ctx;
+    let result = (|| {
+        let ebb = get_ebb(ctx, ebb)?;
+        let args = get_values(ctx, args, nargs)?;
+        insert(ctx, |dfg, pos| dfg.ins(pos).jump(ebb, args))
+    })();
+    status_result(ctx, result)
+}
+
+/// Append a conditional branch like `brz` or `brnz` testing `cond`.
+///
+/// The branch goes to `ebb` passing the `nargs` values in `args`.
+///
+/// # Safety
+///
+/// `ctx` must be a live context, `opcode` a NUL-terminated string, and `args` must point to
+/// `nargs` value references.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_branch(ctx: *mut CtonContext,
+                                         opcode: *const c_char,
+                                         cond: CtonRef,
+                                         ebb: CtonRef,
+                                         args: *const CtonRef,
+                                         nargs: usize)
+                                         -> CtonStatus {
+    let ctx = &mut *ctx;
+    let result = (|| {
+        let opcode = get_opcode(opcode, InstructionFormat::Branch)?;
+        let cond = get_value(ctx, cond)?;
+        let ebb = get_ebb(ctx, ebb)?;
+        let args = get_values(ctx, args, nargs)?;
+        insert(ctx, |dfg, pos| dfg.ins(pos).Branch(opcode, types::VOID, cond, ebb, args).0)
+    })();
+    status_result(ctx, result)
+}
+
+/// Append a `return` of the `nargs` values in `args`.
+///
+/// # Safety
+///
+/// `ctx` must be a live context, and `args` must point to `nargs` value references.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_return(ctx: *mut CtonContext,
+                                         args: *const CtonRef,
+                                         nargs: usize)
+                                         -> CtonStatus {
+    let ctx = &mut *ctx;
+    let result = (|| {
+        let args = get_values(ctx, args, nargs)?;
+        insert(ctx, |dfg, pos| dfg.ins(pos).return_(args))
+    })();
+    status_result(ctx, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use context::{cton_context_new, cton_context_free, cton_context_error};
+    use cretonne::verify_function;
+    use std::ffi::CStr;
+    use std::os::raw::c_char;
+    use std::ptr;
+    use super::*;
+
+    fn c(s: &'static [u8]) -> *const c_char {
+        s.as_ptr() as *const c_char
+    }
+
+    #[test]
+    fn build() {
+        unsafe {
+            let ctx = cton_context_new();
+            assert_eq!(cton_signature_append_param(ctx, CTON_TYPE_I64), CTON_OK);
+            assert_eq!(cton_signature_append_param(ctx, CTON_TYPE_I64), CTON_OK);
+            assert_eq!(cton_signature_append_return(ctx, CTON_TYPE_I64), CTON_OK);
+
+            let ebb0 = cton_ebb_create(ctx);
+            let ebb1 = cton_ebb_create(ctx);
+            let a = cton_ebb_append_param(ctx, ebb0, CTON_TYPE_I64);
+            let b = cton_ebb_append_param(ctx, ebb0, CTON_TYPE_I64);
+            let x = cton_ebb_append_param(ctx, ebb1, CTON_TYPE_I64);
+
+            assert_eq!(cton_position_at_end(ctx, ebb0), CTON_OK);
+            let sum = cton_ins_binary(ctx, c(b"iadd\0"), a, b);
+            let cmp = cton_ins_icmp(ctx, c(b"slt\0"), a, b);
+            assert_eq!(cton_ins_branch(ctx, c(b"brz\0"), cmp, ebb1, &sum, 1), CTON_OK);
+            let one = cton_ins_unary(ctx, c(b"bint\0"), CTON_TYPE_I64, cmp);
+            let inc = cton_ins_binary_imm(ctx, c(b"iadd_imm\0"), one, 10);
+            assert_eq!(cton_ins_jump(ctx, ebb1, &inc, 1), CTON_OK);
+
+            assert_eq!(cton_position_at_end(ctx, ebb1), CTON_OK);
+            let k = cton_ins_iconst(ctx, CTON_TYPE_I64, 3);
+            let ret = cton_ins_binary(ctx, c(b"imul\0"), x, k);
+            assert_eq!(cton_ins_return(ctx, &ret, 1), CTON_OK);
+
+            assert_eq!((*ctx).ctx.func.to_string(),
+                       "function \"\"(i64, i64) -> i64 {
+ebb0(vx0: i64, vx1: i64):
+    v0 = iadd vx0, vx1
+    v1 = icmp slt, vx0, vx1
+    brz v1, ebb1(v0)
+    v3 = bint.i64 v1
+    v4 = iadd_imm v3, 10
+    jump ebb1(v4)
+
+ebb1(vx2: i64):
+    v6 = iconst.i64 3
+    v7 = imul vx2, v6
+    return v7
+}
+");
+            verify_function(&(*ctx).ctx.func).unwrap();
+
+            // Errors leave a message in the context.
+            let error = || CStr::from_ptr(cton_context_error(ctx)).to_str().unwrap().to_string();
+            assert_eq!(cton_ins_binary(ctx, c(b"iconst\0"), a, b), CTON_INVALID);
+            assert_eq!(error(), "iconst doesn't have the Binary format");
+            assert_eq!(cton_ins_binary(ctx, c(b"iadd\0"), a, 1000), CTON_INVALID);
+            assert_eq!(error(), "invalid value reference 1000");
+            assert_eq!(cton_ins_icmp(ctx, c(b"less\0"), a, b), CTON_INVALID);
+            assert_eq!(error(), "unknown condition code less");
+            assert_eq!(cton_position_at_end(ctx, 7), CTON_ERROR_INVALID);
+            assert_eq!(cton_ins_return(ctx, ptr::null(), 0), CTON_OK);
+            assert_eq!(cton_ebb_append_param(ctx, ebb1, 99), CTON_INVALID);
+            assert_eq!(error(), "unknown type code 99");
+
+            cton_context_free(ctx);
+        }
+    }
+}
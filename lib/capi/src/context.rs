@@ -0,0 +1,130 @@
+//! Compilation contexts.
+
+use cretonne::Context;
+use cretonne::ir::Ebb;
+use cton_reader::parse_functions;
+use isa::CtonIsa;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use super::{CtonStatus, CTON_OK, CTON_ERROR_PARSE, CTON_ERROR_COMPILE};
+
+/// A compilation context holding the function being built and compiled.
+pub struct CtonContext {
+    /// The Cretonne context.
+    pub ctx: Context,
+    /// The EBB where new instructions are appended.
+    pub position: Option<Ebb>,
+    /// Message describing the most recent failure.
+    error: CString,
+}
+
+impl CtonContext {
+    /// Record the failure described by `message`, and return `status`.
+    pub fn fail<S: Into<String>>(&mut self, status: CtonStatus, message: S) -> CtonStatus {
+        let mut message = message.into().into_bytes();
+        message.retain(|&b| b != 0);
+        self.error = CString::new(message).expect("Interior NUL bytes were removed");
+        status
+    }
+}
+
+/// Create a new context holding an empty function.
+#[no_mangle]
+pub extern "C" fn cton_context_new() -> *mut CtonContext {
+    Box::into_raw(Box::new(CtonContext {
+                               ctx: Context::new(),
+                               position: None,
+                               error: CString::default(),
+                           }))
+}
+
+/// Free a context.
+///
+/// # Safety
+///
+/// `ctx` must be a context created by `cton_context_new()` or null, and it must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_free(ctx: *mut CtonContext) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
+    }
+}
+
+/// Clear the function in `ctx` and the data computed for it, so a new function can be built.
+///
+/// The memory allocated for the previous function is reused for the next one.
+///
+/// # Safety
+///
+/// `ctx` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_clear(ctx: *mut CtonContext) {
+    let ctx = &mut *ctx;
+    ctx.ctx.clear();
+    ctx.position = None;
+}
+
+/// Get the message describing the most recent failure of a function taking `ctx`.
+///
+/// The string is owned by the context, and remains valid until the next failure.
+///
+/// # Safety
+///
+/// `ctx` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_error(ctx: *const CtonContext) -> *const c_char {
+    (*ctx).error.as_ptr()
+}
+
+/// Parse the function in the NUL-terminated IL `text` into `ctx`.
+///
+/// The text must contain exactly one function, which replaces the function in the context.
+///
+/// # Safety
+///
+/// `ctx` must be a live context, and `text` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_parse(ctx: *mut CtonContext,
+                                            text: *const c_char)
+                                            -> CtonStatus {
+    let ctx = &mut *ctx;
+    let text = match CStr::from_ptr(text).to_str() {
+        Ok(text) => text,
+        Err(e) => return ctx.fail(CTON_ERROR_PARSE, e.to_string()),
+    };
+    let mut funcs = match parse_functions(text) {
+        Ok(funcs) => funcs,
+        Err(e) => return ctx.fail(CTON_ERROR_PARSE, e.to_string()),
+    };
+    if funcs.len() != 1 {
+        return ctx.fail(CTON_ERROR_PARSE,
+                        format!("expected one function, found {}", funcs.len()));
+    }
+    ctx.ctx.func = funcs.remove(0);
+    ctx.position = None;
+    CTON_OK
+}
+
+/// Compile the function in `ctx` for `isa`.
+///
+/// On success, the size of the machine code in bytes is stored in `code_size`. The code can then
+/// be emitted with `cton_context_emit()`.
+///
+/// # Safety
+///
+/// `ctx` must be a live context, `isa` a live ISA, and `code_size` valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_compile(ctx: *mut CtonContext,
+                                              isa: *const CtonIsa,
+                                              code_size: *mut u32)
+                                              -> CtonStatus {
+    let ctx = &mut *ctx;
+    match ctx.ctx.compile(&*(*isa).isa) {
+        Ok(size) => {
+            *code_size = size;
+            CTON_OK
+        }
+        Err(e) => ctx.fail(CTON_ERROR_COMPILE, e.to_string()),
+    }
+}
@@ -0,0 +1,199 @@
+//! Emitting machine code with relocation and trap callbacks.
+
+use cretonne::binemit::{RelocSink, TrapSink, NullStackmapSink, CodeOffset, Reloc, Addend};
+use cretonne::ir::{ExternalName, Function, JumpTable, SourceLoc, TrapCode};
+use cretonne::isa::TargetIsa;
+use context::CtonContext;
+use isa::CtonIsa;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_void};
+
+/// Callbacks receiving the relocations and trap sites of the emitted code.
+///
+/// The string arguments are only valid for the duration of the call. Callbacks that aren't
+/// needed can be null.
+#[repr(C)]
+pub struct CtonEmitCallbacks {
+    /// Passed as the first argument to all the callbacks.
+    pub user_data: *mut c_void,
+
+    /// Called for a relocation at `offset` referencing the external symbol `name` plus `addend`.
+    ///
+    /// The relocation kind is given by its ISA-specific name, like "Abs8". The symbol name is
+    /// formatted as in the IL text, like "u0:3" or "puts".
+    pub reloc_external: Option<unsafe extern "C" fn(user_data: *mut c_void,
+                                                    offset: u32,
+                                                    reloc: *const c_char,
+                                                    name: *const c_char,
+                                                    addend: i64)>,
+
+    /// Called for a relocation at `offset` that references `target_offset` in the same function.
+    ///
+    /// This covers both EBBs and jump tables. The emitted bytes already hold the right value for
+    /// code that stays where it was emitted.
+    pub reloc_internal: Option<unsafe extern "C" fn(user_data: *mut c_void,
+                                                    offset: u32,
+                                                    reloc: *const c_char,
+                                                    target_offset: u32)>,
+
+    /// Called for an instruction at `offset` that can trap with the trap code `code`.
+    ///
+    /// The `srcloc` is the source location of the trapping instruction, or `u32::MAX` if it has
+    /// none.
+    pub trap: Option<unsafe extern "C" fn(user_data: *mut c_void,
+                                          offset: u32,
+                                          code: *const c_char,
+                                          srcloc: u32)>,
+}
+
+/// Forwards relocations to the C callbacks.
+struct RelocCallbacks<'a> {
+    callbacks: &'a CtonEmitCallbacks,
+    isa: &'a TargetIsa,
+    func: &'a Function,
+}
+
+impl<'a> RelocCallbacks<'a> {
+    fn reloc_name(&self, reloc: Reloc) -> CString {
+        CString::new(self.isa.reloc_names()[reloc.0 as usize]).unwrap()
+    }
+
+    fn reloc_internal(&mut self, offset: CodeOffset, reloc: Reloc, target: CodeOffset) {
+        if let Some(cb) = self.callbacks.reloc_internal {
+            let name = self.reloc_name(reloc);
+            unsafe { cb(self.callbacks.user_data, offset, name.as_ptr(), target) }
+        }
+    }
+}
+
+impl<'a> RelocSink for RelocCallbacks<'a> {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        self.reloc_internal(offset, reloc, ebb_offset);
+    }
+
+    fn reloc_external(&mut self,
+                      offset: CodeOffset,
+                      reloc: Reloc,
+                      name: &ExternalName,
+                      addend: Addend) {
+        if let Some(cb) = self.callbacks.reloc_external {
+            let reloc = self.reloc_name(reloc);
+            let name = CString::new(name.to_string()).unwrap();
+            unsafe { cb(self.callbacks.user_data, offset, reloc.as_ptr(), name.as_ptr(), addend) }
+        }
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        let target = self.func.jt_offsets[jt];
+        self.reloc_internal(offset, reloc, target);
+    }
+}
+
+/// Forwards trap sites to the C callbacks.
+struct TrapCallbacks<'a> {
+    callbacks: &'a CtonEmitCallbacks,
+}
+
+impl<'a> TrapSink for TrapCallbacks<'a> {
+    fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode) {
+        if let Some(cb) = self.callbacks.trap {
+            let code = CString::new(code.to_string()).unwrap();
+            unsafe { cb(self.callbacks.user_data, offset, code.as_ptr(), srcloc.bits()) }
+        }
+    }
+}
+
+/// Emit the machine code of the function compiled in `ctx` to `mem`.
+///
+/// The function must have been compiled for `isa` by `cton_context_compile()`, and `mem` must be
+/// valid for writing the code size it returned. The relocations and trap sites are reported to
+/// `callbacks`, which may be null.
+///
+/// # Safety
+///
+/// There is no bounds checking on the memory buffer. `ctx` and `isa` must be the context and
+/// ISA passed to a successful `cton_context_compile()`, with no changes to the function since.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_emit(ctx: *const CtonContext,
+                                           isa: *const CtonIsa,
+                                           mem: *mut u8,
+                                           callbacks: *const CtonEmitCallbacks) {
+    let ctx = &(*ctx).ctx;
+    let isa = &*(*isa).isa;
+    let none = CtonEmitCallbacks {
+        user_data: ::std::ptr::null_mut(),
+        reloc_external: None,
+        reloc_internal: None,
+        trap: None,
+    };
+    let callbacks = if callbacks.is_null() { &none } else { &*callbacks };
+    let mut relocs = RelocCallbacks {
+        callbacks: callbacks,
+        isa: isa,
+        func: &ctx.func,
+    };
+    let mut traps = TrapCallbacks { callbacks: callbacks };
+    ctx.emit_to_memory(mem, &mut relocs, &mut traps, &mut NullStackmapSink {}, isa);
+}
+
+#[cfg(test)]
+mod tests {
+    use context::{cton_context_new, cton_context_free, cton_context_parse, cton_context_compile};
+    use isa::{cton_isa_builder_new, cton_isa_builder_set, cton_isa_builder_finish, cton_isa_free};
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_void};
+    use super::*;
+    use super::super::CTON_OK;
+
+    fn c(s: &'static [u8]) -> *const c_char {
+        s.as_ptr() as *const c_char
+    }
+
+    unsafe extern "C" fn reloc_external(user_data: *mut c_void,
+                                        offset: u32,
+                                        reloc: *const c_char,
+                                        name: *const c_char,
+                                        addend: i64) {
+        let relocs = &mut *(user_data as *mut Vec<String>);
+        relocs.push(format!("{}: {} {}{:+}",
+                            offset,
+                            CStr::from_ptr(reloc).to_str().unwrap(),
+                            CStr::from_ptr(name).to_str().unwrap(),
+                            addend));
+    }
+
+    #[test]
+    fn callbacks() {
+        unsafe {
+            let builder = cton_isa_builder_new(c(b"intel\0"));
+            assert_eq!(cton_isa_builder_set(builder, c(b"is_64bit\0"), c(b"true\0")), CTON_OK);
+            let isa = cton_isa_builder_finish(builder);
+
+            let ctx = cton_context_new();
+            let text = b"function addr() -> i64 {
+                             fn0 = function puts(i64)
+                         ebb0:
+                             v0 = func_addr.i64 fn0
+                             return v0
+                         }\0";
+            assert_eq!(cton_context_parse(ctx, c(text)), CTON_OK);
+            let mut size = 0;
+            assert_eq!(cton_context_compile(ctx, isa, &mut size), CTON_OK);
+
+            let mut code = vec![0; size as usize];
+            let mut relocs = Vec::<String>::new();
+            let callbacks = CtonEmitCallbacks {
+                user_data: &mut relocs as *mut Vec<String> as *mut c_void,
+                reloc_external: Some(reloc_external),
+                reloc_internal: None,
+                trap: None,
+            };
+            cton_context_emit(ctx, isa, code.as_mut_ptr(), &callbacks);
+            assert_eq!(relocs.len(), 1);
+            assert!(relocs[0].ends_with(": Abs8 puts+0"), "{}", relocs[0]);
+
+            cton_context_free(ctx);
+            cton_isa_free(isa);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Configuring and creating target ISAs.
+
+use cretonne::isa::{self, TargetIsa};
+use cretonne::settings::{self, Configurable};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::ptr;
+use super::{CtonStatus, CTON_OK, CTON_ERROR_BAD_NAME, CTON_ERROR_BAD_TYPE, CTON_ERROR_BAD_VALUE};
+
+/// The settings for a target ISA under construction.
+pub struct CtonIsaBuilder {
+    shared: settings::Builder,
+    isa: isa::Builder,
+}
+
+/// A target ISA that functions can be compiled for.
+pub struct CtonIsa {
+    /// The ISA.
+    pub isa: Box<TargetIsa>,
+}
+
+/// Create a builder for the ISA named `name`, which may also be a target triple.
+///
+/// Returns a null pointer if the ISA is unknown or wasn't compiled in.
+///
+/// # Safety
+///
+/// `name` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cton_isa_builder_new(name: *const c_char) -> *mut CtonIsaBuilder {
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(name) => name,
+        Err(_) => return ptr::null_mut(),
+    };
+    match isa::lookup(name) {
+        Ok(isa) => {
+            Box::into_raw(Box::new(CtonIsaBuilder {
+                                       shared: settings::builder(),
+                                       isa: isa,
+                                   }))
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Set the shared or ISA-specific setting `name` to `value`.
+///
+/// Boolean settings accept the values "true" and "false".
+///
+/// # Safety
+///
+/// `builder` must be a live ISA builder, and `name` and `value` NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn cton_isa_builder_set(builder: *mut CtonIsaBuilder,
+                                              name: *const c_char,
+                                              value: *const c_char)
+                                              -> CtonStatus {
+    let builder = &mut *builder;
+    let (name, value) = match (CStr::from_ptr(name).to_str(), CStr::from_ptr(value).to_str()) {
+        (Ok(name), Ok(value)) => (name, value),
+        _ => return CTON_ERROR_BAD_NAME,
+    };
+    let result = match builder.shared.set(name, value) {
+        Err(settings::Error::BadName) => builder.isa.set(name, value),
+        result => result,
+    };
+    match result {
+        Ok(()) => CTON_OK,
+        Err(settings::Error::BadName) => CTON_ERROR_BAD_NAME,
+        Err(settings::Error::BadType) => CTON_ERROR_BAD_TYPE,
+        Err(settings::Error::BadValue) => CTON_ERROR_BAD_VALUE,
+    }
+}
+
+/// Free an ISA builder without creating an ISA.
+///
+/// # Safety
+///
+/// `builder` must be a live ISA builder or null.
+#[no_mangle]
+pub unsafe extern "C" fn cton_isa_builder_free(builder: *mut CtonIsaBuilder) {
+    if !builder.is_null() {
+        drop(Box::from_raw(builder));
+    }
+}
+
+/// Create the ISA configured by `builder`.
+///
+/// The builder is consumed, and must not be used or freed afterwards.
+///
+/// # Safety
+///
+/// `builder` must be a live ISA builder.
+#[no_mangle]
+pub unsafe extern "C" fn cton_isa_builder_finish(builder: *mut CtonIsaBuilder) -> *mut CtonIsa {
+    let builder = *Box::from_raw(builder);
+    let isa = builder.isa.finish(settings::Flags::new(&builder.shared));
+    Box::into_raw(Box::new(CtonIsa { isa: isa }))
+}
+
+/// Free an ISA.
+///
+/// # Safety
+///
+/// `isa` must be an ISA created by `cton_isa_builder_finish()` or null, and it must not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn cton_isa_free(isa: *mut CtonIsa) {
+    if !isa.is_null() {
+        drop(Box::from_raw(isa));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::raw::c_char;
+    use super::*;
+    use super::super::{CTON_OK, CTON_ERROR_BAD_NAME, CTON_ERROR_BAD_VALUE};
+
+    fn c(s: &'static [u8]) -> *const c_char {
+        s.as_ptr() as *const c_char
+    }
+
+    #[test]
+    fn settings() {
+        unsafe {
+            assert!(cton_isa_builder_new(c(b"vax\0")).is_null());
+
+            let builder = cton_isa_builder_new(c(b"intel\0"));
+            assert!(!builder.is_null());
+            assert_eq!(cton_isa_builder_set(builder, c(b"is_64bit\0"), c(b"true\0")), CTON_OK);
+            assert_eq!(cton_isa_builder_set(builder, c(b"has_sse41\0"), c(b"false\0")), CTON_OK);
+            assert_eq!(cton_isa_builder_set(builder, c(b"is_65bit\0"), c(b"true\0")),
+                       CTON_ERROR_BAD_NAME);
+            assert_eq!(cton_isa_builder_set(builder, c(b"is_64bit\0"), c(b"maybe\0")),
+                       CTON_ERROR_BAD_VALUE);
+            assert_eq!(cton_isa_builder_set(builder, c(b"opt_level\0"), c(b"fast\0")),
+                       CTON_ERROR_BAD_VALUE);
+            let isa = cton_isa_builder_finish(builder);
+            assert_eq!((*isa).isa.name(), "intel");
+            assert!((*isa).isa.flags().is_64bit());
+            cton_isa_free(isa);
+        }
+    }
+}
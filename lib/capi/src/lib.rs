@@ -0,0 +1,99 @@
+//! Cretonne C API.
+//!
+//! The cton_capi library exposes the Cretonne code generator through a stable `extern "C"`
+//! interface, so virtual machines written in other languages can embed it. The declarations are
+//! available to C code in `include/cretonne.h`.
+//!
+//! The API is built around three opaque handles:
+//!
+//! - A `CtonIsaBuilder` collects the shared and ISA-specific settings for a target ISA, and is
+//!   consumed by `cton_isa_builder_finish()` to create a `CtonIsa`.
+//! - A `CtonIsa` is the target ISA that functions are compiled for.
+//! - A `CtonContext` holds the function being compiled. The function can be parsed from the
+//!   textual IL format, or constructed one instruction at a time.
+//!
+//! Functions that can fail return a `CtonStatus` code. The functions taking a context also leave
+//! an error message in the context, which can be retrieved with `cton_context_error()`.
+//!
+//! All the functions taking handles or pointers are unsafe to call with invalid pointers. Handles
+//! must be freed with the matching `_free` function.
+
+#![deny(missing_docs)]
+
+extern crate cretonne;
+extern crate cton_reader;
+
+pub use builder::{cton_signature_append_param, cton_signature_append_return, cton_ebb_create,
+                  cton_ebb_append_param, cton_position_at_end, cton_ins_iconst, cton_ins_unary,
+                  cton_ins_binary, cton_ins_binary_imm, cton_ins_icmp, cton_ins_jump,
+                  cton_ins_branch, cton_ins_return};
+pub use context::{CtonContext, cton_context_new, cton_context_free, cton_context_clear,
+                  cton_context_error, cton_context_parse, cton_context_compile};
+pub use emit::{CtonEmitCallbacks, cton_context_emit};
+pub use isa::{CtonIsaBuilder, CtonIsa, cton_isa_builder_new, cton_isa_builder_set,
+              cton_isa_builder_free, cton_isa_builder_finish, cton_isa_free};
+
+mod builder;
+mod context;
+mod emit;
+mod isa;
+
+/// Status code returned by the functions that can fail.
+pub type CtonStatus = u32;
+
+/// The operation succeeded.
+pub const CTON_OK: CtonStatus = 0;
+
+/// A setting or ISA name is not known.
+pub const CTON_ERROR_BAD_NAME: CtonStatus = 1;
+
+/// A setting was given a value of the wrong type.
+pub const CTON_ERROR_BAD_TYPE: CtonStatus = 2;
+
+/// A setting was given an invalid value.
+pub const CTON_ERROR_BAD_VALUE: CtonStatus = 3;
+
+/// The IL text could not be parsed.
+pub const CTON_ERROR_PARSE: CtonStatus = 4;
+
+/// An instruction could not be constructed from the given operands.
+pub const CTON_ERROR_INVALID: CtonStatus = 5;
+
+/// The function failed to compile.
+pub const CTON_ERROR_COMPILE: CtonStatus = 6;
+
+/// A reference to an EBB or a value.
+///
+/// EBBs and values are numbered from 0 in the order they are created. The `CTON_INVALID`
+/// reference is returned when an instruction can't be constructed.
+pub type CtonRef = u32;
+
+/// The reference returned on failure.
+pub const CTON_INVALID: CtonRef = u32::max_value();
+
+/// Code identifying a scalar value type.
+pub type CtonType = u32;
+
+/// A boolean.
+pub const CTON_TYPE_B1: CtonType = 1;
+
+/// An 8-bit integer.
+pub const CTON_TYPE_I8: CtonType = 2;
+
+/// A 16-bit integer.
+pub const CTON_TYPE_I16: CtonType = 3;
+
+/// A 32-bit integer.
+pub const CTON_TYPE_I32: CtonType = 4;
+
+/// A 64-bit integer.
+pub const CTON_TYPE_I64: CtonType = 5;
+
+/// A 32-bit IEEE float.
+pub const CTON_TYPE_F32: CtonType = 6;
+
+/// A 64-bit IEEE float.
+pub const CTON_TYPE_F64: CtonType = 7;
+
+/// A 128-bit integer.
+pub const CTON_TYPE_I128: CtonType = 8;
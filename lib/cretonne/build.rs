@@ -8,6 +8,10 @@
 // OUT_DIR
 //     Directory where generated files should be placed.
 //
+// CRETONNE_JSON
+//     If set, also generate a machine-readable description of the instruction set in
+//     `$OUT_DIR/isa.json`.
+//
 // The build script expects to be run from the directory where this build.rs file lives. The
 // current directory is used to find the sources.
 
@@ -32,13 +36,18 @@ fn main() {
     let meta_dir = crate_dir.join("meta");
     let build_script = meta_dir.join("build.py");
 
+    println!("cargo:rerun-if-env-changed=CRETONNE_JSON");
+
     // Launch build script with Python. We'll just find python in the path.
-    let status = process::Command::new("python")
-        .current_dir(crate_dir)
+    let mut cmd = process::Command::new("python");
+    cmd.current_dir(crate_dir)
         .arg(build_script)
         .arg("--out-dir")
-        .arg(out_dir)
-        .status()
+        .arg(out_dir);
+    if env::var_os("CRETONNE_JSON").is_some() {
+        cmd.arg("--json");
+    }
+    let status = cmd.status()
         .expect("Failed to launch second-level build script");
     if !status.success() {
         process::exit(status.code().unwrap());
@@ -0,0 +1,79 @@
+"""
+Patterns for combining instructions in the `base` instruction set.
+
+Each pattern matches a tree of instructions in the data flow graph, rooted at
+the last instruction in the source pattern. The other instructions in the
+tree must be pure and their results can't have any other uses. When a pattern
+matches, the root instruction is replaced by the destination pattern, and the
+rest of the tree becomes dead.
+
+These combines are target-independent. They simplify the code without making
+it any harder to legalize.
+"""
+from __future__ import absolute_import
+from .instructions import iconst, iadd, iadd_imm, imul, imul_imm
+from .instructions import isub, isub_imm, band, band_imm, bor, bor_imm
+from .instructions import bxor, bxor_imm, ishl, ishl_imm, ushr, ushr_imm
+from .instructions import sshr, sshr_imm, bnot, fneg, copy
+from cdsl.ast import Var
+from cdsl.xform import Rtl, XFormGroup
+
+
+combine = XFormGroup('combine', """
+        Combine trees of instructions into simpler equivalents.
+        """)
+
+x = Var('x')
+y = Var('y')
+a = Var('a')
+c = Var('c')
+t = Var('t')
+imm = Var('imm')
+
+# Fold constant operands into the immediate forms of binary instructions.
+for inst, inst_imm in [
+        (iadd, iadd_imm),
+        (imul, imul_imm),
+        (band, band_imm),
+        (bor, bor_imm),
+        (bxor, bxor_imm)]:
+    # These operations are commutative, so the constant can be on either side.
+    combine.combine(
+            Rtl(
+                c << iconst(imm),
+                a << inst(x, c)
+            ),
+            Rtl(a << inst_imm(x, imm)))
+    combine.combine(
+            Rtl(
+                c << iconst(imm),
+                a << inst(c, x)
+            ),
+            Rtl(a << inst_imm(x, imm)))
+
+combine.combine(
+        Rtl(
+            c << iconst(imm),
+            a << isub(c, y)
+        ),
+        Rtl(a << isub_imm(imm, y)))
+
+for inst, inst_imm in [
+        (ishl, ishl_imm),
+        (ushr, ushr_imm),
+        (sshr, sshr_imm)]:
+    combine.combine(
+            Rtl(
+                c << iconst(imm),
+                a << inst(x, c)
+            ),
+            Rtl(a << inst_imm(x, imm)))
+
+# Cancel out double negations.
+for inst in [bnot, fneg]:
+    combine.combine(
+            Rtl(
+                t << inst(x),
+                a << inst(t)
+            ),
+            Rtl(a << copy(x)))
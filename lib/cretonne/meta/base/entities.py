@@ -27,3 +27,11 @@ func_ref = EntityRefKind('func_ref', 'An external function.')
 #: A reference to a jump table declared in the function preamble.
 jump_table = EntityRefKind(
         'jump_table', 'A jump table.', default_member='table')
+
+#: A reference to a heap declared in the function preamble.
+#: This is used to provide the heap in the heap access instructions.
+heap = EntityRefKind('heap', 'A heap.')
+
+#: A reference to a global variable declared in the function preamble.
+#: This is used to provide the symbol in the global address instructions.
+global_var = EntityRefKind('global_var', 'A global variable.')
@@ -9,12 +9,16 @@ from __future__ import absolute_import
 from cdsl.formats import InstructionFormat
 from cdsl.operands import VALUE, VARIABLE_ARGS
 from .immediates import imm64, uimm8, ieee32, ieee64, immvector, intcc, floatcc
-from .entities import ebb, sig_ref, func_ref, jump_table
+from .immediates import trapcode, boolean, uimm32, offset32, memflags
+from .immediates import regunit, ordering
+from .entities import ebb, sig_ref, func_ref, jump_table, heap, stack_slot
+from .entities import global_var
 
 Nullary = InstructionFormat()
 
 Unary = InstructionFormat(VALUE)
 UnaryImm = InstructionFormat(imm64)
+UnaryBool = InstructionFormat(boolean)
 UnaryIeee32 = InstructionFormat(ieee32)
 UnaryIeee64 = InstructionFormat(ieee64)
 UnaryImmVector = InstructionFormat(immvector, boxed_storage=True)
@@ -42,17 +46,59 @@ ExtractLane = InstructionFormat(VALUE, ('lane', uimm8))
 IntCompare = InstructionFormat(intcc, VALUE, VALUE)
 FloatCompare = InstructionFormat(floatcc, VALUE, VALUE)
 
-Jump = InstructionFormat(ebb, VARIABLE_ARGS, boxed_storage=True)
-Branch = InstructionFormat(VALUE, ebb, VARIABLE_ARGS, boxed_storage=True)
+# Test a CPU flags value against a condition code.
+IntCond = InstructionFormat(intcc, VALUE)
+FloatCond = InstructionFormat(floatcc, VALUE)
+
+# The `selectif` instruction is controlled by the type of the selected values,
+# not the CPU flags in the first VALUE operand.
+IntSelect = InstructionFormat(intcc, VALUE, VALUE, VALUE, typevar_operand=2)
+
+Jump = InstructionFormat(ebb, VARIABLE_ARGS)
+Branch = InstructionFormat(VALUE, ebb, VARIABLE_ARGS)
+BranchIcmp = InstructionFormat(intcc, VALUE, VALUE, ebb, VARIABLE_ARGS)
 BranchTable = InstructionFormat(VALUE, jump_table)
+BranchTableEntry = InstructionFormat(VALUE, VALUE, uimm8, jump_table)
+BranchTableBase = InstructionFormat(jump_table)
+
+Trap = InstructionFormat(trapcode)
+CondTrap = InstructionFormat(VALUE, trapcode)
+IntCondTrap = InstructionFormat(intcc, VALUE, trapcode)
 
-Call = InstructionFormat(
-        func_ref, VARIABLE_ARGS, multiple_results=True, boxed_storage=True)
+# Arithmetic that traps with `code` instead of producing a wrapped result.
+BinaryTrap = InstructionFormat(VALUE, VALUE, trapcode)
+
+Call = InstructionFormat(func_ref, VARIABLE_ARGS, multiple_results=True)
 IndirectCall = InstructionFormat(
-        sig_ref, VALUE, VARIABLE_ARGS,
-        multiple_results=True, boxed_storage=True)
-Return = InstructionFormat(VARIABLE_ARGS, boxed_storage=True)
-ReturnReg = InstructionFormat(VALUE, VARIABLE_ARGS, boxed_storage=True)
+        sig_ref, VALUE, VARIABLE_ARGS, multiple_results=True)
+FuncAddr = InstructionFormat(func_ref)
+Return = InstructionFormat(VARIABLE_ARGS)
+ReturnReg = InstructionFormat(VALUE, VARIABLE_ARGS)
+
+Load = InstructionFormat(memflags, VALUE, offset32)
+Store = InstructionFormat(memflags, VALUE, VALUE, offset32)
+LoadComplex = InstructionFormat(
+        memflags, VALUE, VALUE, ('shift', uimm8), offset32,
+        boxed_storage=True)
+StoreComplex = InstructionFormat(
+        memflags, VALUE, VALUE, VALUE, ('shift', uimm8), offset32,
+        boxed_storage=True)
+
+# The atomic memory operations take the address as their first operand, and
+# the controlling type is the type of the value operand that follows.
+AtomicLoad = InstructionFormat(ordering, VALUE)
+AtomicRmw = InstructionFormat(ordering, VALUE, VALUE, typevar_operand=2)
+AtomicCas = InstructionFormat(
+        ordering, VALUE, VALUE, VALUE, typevar_operand=2)
+
+StackLoad = InstructionFormat(stack_slot, ('offset', uimm32))
+StackStore = InstructionFormat(VALUE, stack_slot, ('offset', uimm32))
+
+HeapAddr = InstructionFormat(heap, VALUE, uimm32)
+
+UnaryGlobalVar = InstructionFormat(global_var)
+
+RegMove = InstructionFormat(VALUE, ('src', regunit), ('dst', regunit))
 
 # Finally extract the names of global variables in this module.
 InstructionFormat.extract_names(globals())
@@ -17,6 +17,26 @@ imm64 = ImmediateKind('imm64', 'A 64-bit immediate integer.')
 #: immediate bit counts on shift instructions.
 uimm8 = ImmediateKind('uimm8', 'An 8-bit immediate unsigned integer.')
 
+#: An unsigned 32-bit immediate integer operand.
+#:
+#: This is used for the byte counts of heap range checks.
+uimm32 = ImmediateKind('uimm32', 'A 32-bit immediate unsigned integer.')
+
+#: A 32-bit immediate signed offset.
+#:
+#: This is used for the byte offset from the base address of memory
+#: operations.
+offset32 = ImmediateKind(
+        'offset32',
+        'A 32-bit immediate signed offset.',
+        default_member='offset')
+
+#: An immediate boolean operand.
+#:
+#: This type of immediate boolean can interact with SSA values with any
+#: :py:class:`cretonne.BoolType` type.
+boolean = ImmediateKind('boolean', 'An immediate boolean.', rust_type='bool')
+
 #: A 32-bit immediate floating point operand.
 #:
 #: IEEE 754-2008 binary32 interchange format.
@@ -50,3 +70,36 @@ floatcc = ImmediateKind(
         'floatcc',
         'A floating point comparison condition code.',
         default_member='cond', rust_type='FloatCC')
+
+#: A trap code indicating the reason for trapping.
+#:
+#: This enumerated operand kind is used for the :cton:inst:`trap` family of
+#: instructions and corresponds to the `TrapCode` Rust type.
+trapcode = ImmediateKind(
+        'trapcode',
+        'A trap reason code.',
+        default_member='code', rust_type='TrapCode')
+
+#: Flags for memory operations like :cton:inst:`load` and :cton:inst:`store`.
+memflags = ImmediateKind(
+        'memflags',
+        'Memory operation flags',
+        default_member='flags', rust_type='MemFlags')
+
+#: A memory ordering constraint for atomic instructions like
+#: :cton:inst:`atomic_load` and :cton:inst:`atomic_cas`.
+#:
+#: This enumerated operand kind corresponds to the `AtomicOrdering` Rust type.
+ordering = ImmediateKind(
+        'ordering',
+        'An atomic memory ordering',
+        default_member='ordering', rust_type='AtomicOrdering')
+
+#: A register unit in the target ISA.
+#:
+#: This is used by the :cton:inst:`regmove` instruction to name the source and
+#: destination registers, and corresponds to the `isa::RegUnit` Rust type.
+regunit = ImmediateKind(
+        'regunit',
+        'A register unit in the target ISA',
+        rust_type='RegUnit')
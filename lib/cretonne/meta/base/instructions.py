@@ -8,9 +8,10 @@ from __future__ import absolute_import
 from cdsl.operands import Operand, VARIABLE_ARGS
 from cdsl.typevar import TypeVar
 from cdsl.instructions import Instruction, InstructionGroup
-from base.types import i8, f32, f64, b1
+from base.types import i8, f32, f64, b1, iflags, fflags
 from base.immediates import imm64, uimm8, ieee32, ieee64, immvector
-from base.immediates import intcc, floatcc
+from base.immediates import intcc, floatcc, trapcode, boolean, uimm32
+from base.immediates import offset32, memflags, regunit, ordering
 from base import entities
 import base.formats  # noqa
 
@@ -26,8 +27,9 @@ TxN = TypeVar(
         'TxN', 'A SIMD vector type',
         ints=True, floats=True, bools=True, scalars=False, simd=True)
 Any = TypeVar(
-        'Any', 'Any integer, float, or boolean scalar or vector type',
-        ints=True, floats=True, bools=True, scalars=True, simd=True)
+        'Any', 'Any integer, float, boolean, or reference type',
+        ints=True, floats=True, bools=True, refs=True, scalars=True,
+        simd=True)
 
 #
 # Control flow
@@ -46,6 +48,19 @@ jump = Instruction(
         """,
         ins=(EBB, args), is_terminator=True)
 
+fallthrough = Instruction(
+        'fallthrough', r"""
+        Fall through to the next EBB.
+
+        This is the same as :inst:`jump`, except the destination EBB must be
+        the next one in the layout. No code is emitted for a fall-through.
+
+        Jumps are turned into fall-throughs by the late layout passes when the
+        EBB order is final. Passes that reorder EBBs must turn them back into
+        jumps.
+        """,
+        ins=(EBB, args), is_terminator=True)
+
 brz = Instruction(
         'brz', r"""
         Branch when zero.
@@ -64,6 +79,31 @@ brnz = Instruction(
         """,
         ins=(c, EBB, args), is_branch=True)
 
+Cond = Operand('Cond', intcc)
+x = Operand('x', iB)
+y = Operand('y', iB)
+
+br_icmp = Instruction(
+        'br_icmp', r"""
+        Compare scalar integers and branch.
+
+        Compare ``x`` and ``y`` in the same way as the :inst:`icmp` instruction
+        and take the branch if the condition is true::
+
+            br_icmp ugt, v1, v2, ebb4(v5, v6)
+
+        is semantically equivalent to::
+
+            v10 = icmp ugt, v1, v2
+            brnz v10, ebb4(v5, v6)
+
+        Some RISC architectures like RISC-V provide instructions that implement
+        some of the condition codes directly. The post-optimization pass forms
+        these instructions from :inst:`icmp` and :inst:`brz` / :inst:`brnz`
+        pairs after register allocation.
+        """,
+        ins=(Cond, x, y, EBB, args), is_branch=True)
+
 x = Operand('x', iB, doc='index into jump table')
 JT = Operand('JT', entities.jump_table)
 br_table = Instruction(
@@ -79,11 +119,57 @@ br_table = Instruction(
         """,
         ins=(x, JT), is_branch=True)
 
+x = Operand('x', iAddr, doc='index into jump table')
+addr = Operand('addr', iAddr, doc='Address of the jump table')
+Size = Operand('Size', uimm8, doc='Size of a jump table entry in bytes')
+entry = Operand('entry', iAddr, doc='Entry from the jump table')
+
+jump_table_base = Instruction(
+        'jump_table_base', r"""
+        Get the address of a jump table.
+
+        The jump tables are emitted in a read-only data area after the code of
+        the function.
+        """,
+        ins=JT, outs=addr)
+
+jump_table_entry = Instruction(
+        'jump_table_entry', r"""
+        Get an entry from a jump table.
+
+        Load the ``Size``-byte entry at index ``x`` from the jump table ``JT``
+        at address ``addr``. In position-independent code, the entries are
+        32-bit offsets of the destinations from the start of the table, and the
+        loaded entry is sign-extended. Otherwise, the entries are the absolute
+        addresses of the destinations.
+
+        The index must be in range for the table.
+        """,
+        ins=(x, addr, Size, JT), outs=entry, can_load=True)
+
+addr = Operand('addr', iAddr, doc='Destination address')
+
+indirect_jump_table_br = Instruction(
+        'indirect_jump_table_br', r"""
+        Branch indirectly to an entry of a jump table.
+
+        Jump to ``addr``, which must be the address of one of the destinations
+        in the jump table ``JT``. The address is computed from the entry loaded
+        by :inst:`jump_table_entry`.
+
+        This is how :inst:`br_table` is legalized on targets that don't have a
+        native jump table instruction. The jump table entries must all be
+        present.
+        """,
+        ins=(addr, JT), is_branch=True, is_terminator=True)
+
+code = Operand('code', trapcode)
+
 trap = Instruction(
         'trap', r"""
         Terminate execution unconditionally.
         """,
-        is_terminator=True, can_trap=True)
+        ins=code, is_terminator=True, can_trap=True)
 
 trapz = Instruction(
         'trapz', r"""
@@ -91,7 +177,7 @@ trapz = Instruction(
 
         if ``c`` is non-zero, execution continues at the following instruction.
         """,
-        ins=c, can_trap=True)
+        ins=(c, code), can_trap=True)
 
 trapnz = Instruction(
         'trapnz', r"""
@@ -99,7 +185,20 @@ trapnz = Instruction(
 
         if ``c`` is zero, execution continues at the following instruction.
         """,
-        ins=c, can_trap=True)
+        ins=(c, code), can_trap=True)
+
+Cond = Operand('Cond', intcc)
+f = Operand('f', iflags)
+
+trapif = Instruction(
+        'trapif', r"""
+        Trap when condition is true in integer CPU flags.
+
+        Check the CPU flags in ``f`` against the ``Cond`` condition code. If
+        the condition is false, execution continues at the following
+        instruction.
+        """,
+        ins=(Cond, f, code), can_trap=True)
 
 rvals = Operand('rvals', VARIABLE_ARGS, doc='return values')
 
@@ -111,7 +210,7 @@ x_return = Instruction(
         provided return values. The list of return values must match the
         function signature's return types.
         """,
-        ins=rvals, is_terminator=True)
+        ins=rvals, is_terminator=True, is_return=True)
 
 raddr = Operand('raddr', iAddr, doc='Return address')
 
@@ -130,7 +229,7 @@ return_reg = Instruction(
         :inst:`return` will be legalized into this instruction on these
         architectures.
         """,
-        ins=(raddr, rvals), is_terminator=True)
+        ins=(raddr, rvals), is_terminator=True, is_return=True)
 
 FN = Operand(
         'FN',
@@ -161,6 +260,49 @@ call_indirect = Instruction(
         ins=(SIG, callee, args),
         outs=rvals)
 
+addr = Operand('addr', iAddr, doc='Address of the function')
+
+func_addr = Instruction(
+        'func_addr', r"""
+        Get the address of a function.
+
+        Compute the absolute address of a function declared in the preamble.
+        The returned address can be used as a ``callee`` argument to
+        :inst:`call_indirect`. This is also a method for calling functions that
+        are too far away to be addressable by a direct :inst:`call`
+        instruction.
+        """,
+        ins=FN, outs=addr)
+
+return_call = Instruction(
+        'return_call', r"""
+        Direct tail call.
+
+        Call a function which has been declared in the preamble, and return
+        its results directly to the caller of the current function. The
+        current function's stack frame is released before the call, so the
+        arguments can't point into it.
+
+        The called function must return the same types as the current
+        function. When the calling conventions of the two functions don't
+        allow a tail call, it is legalized into a :inst:`call` followed by a
+        :inst:`return`.
+        """,
+        ins=(FN, args), is_terminator=True, is_return=True)
+
+return_call_indirect = Instruction(
+        'return_call_indirect', r"""
+        Indirect tail call.
+
+        Call the function pointed to by `callee` with the given arguments, and
+        return its results directly to the caller of the current function.
+        The called function must match the specified signature, and it must
+        return the same types as the current function.
+
+        See :inst:`return_call` for the requirements on tail calls.
+        """,
+        ins=(SIG, callee, args), is_terminator=True, is_return=True)
+
 #
 # Materializing constants.
 #
@@ -173,6 +315,9 @@ iconst = Instruction(
 
         Create a scalar integer SSA value with an immediate constant value, or
         an integer vector where all the lanes have the same value.
+
+        The 64-bit immediate is sign-extended to the width of :type:`i128`
+        lanes. Wider constants can be formed with :inst:`iconcat_lohi`.
         """,
         ins=N, outs=a)
 
@@ -198,6 +343,21 @@ f64const = Instruction(
         """,
         ins=N, outs=a)
 
+Bool = TypeVar(
+        'Bool', 'A scalar or vector boolean type',
+        bools=True, simd=True)
+
+N = Operand('N', boolean)
+a = Operand('a', Bool, doc='A constant boolean scalar or vector value')
+bconst = Instruction(
+        'bconst', r"""
+        Boolean constant.
+
+        Create a scalar boolean SSA value with an immediate constant value, or
+        a boolean vector where all the lanes have the same value.
+        """,
+        ins=N, outs=a)
+
 N = Operand('N', immvector)
 a = Operand('a', TxN, doc='A constant vector value')
 vconst = Instruction(
@@ -208,6 +368,31 @@ vconst = Instruction(
         """,
         ins=N, outs=a)
 
+a = Operand('a', Any, doc='A value of any type')
+null = Instruction(
+        'null', r"""
+        Zero value.
+
+        Create an SSA value of any type where all the bits are zero. This is
+        the integer or floating point zero, :type:`b1` false, the null
+        reference, or a vector with all the lanes zero or false.
+        """,
+        outs=a)
+
+undef = Instruction(
+        'undef', r"""
+        Undefined value.
+
+        Create an SSA value of any type with unspecified contents. This is used
+        for uninitialized variables, and for vector lanes whose contents don't
+        matter. Reading the value is not an error, but each use of it can
+        observe a different value.
+
+        An :inst:`undef` can always be replaced with a :inst:`null` of the
+        same type.
+        """,
+        outs=a)
+
 #
 # Generics.
 #
@@ -226,6 +411,19 @@ select = Instruction(
         """,
         ins=(c, x, y), outs=a)
 
+cc = Operand('cc', intcc, doc='Controlling condition code')
+flags = Operand('flags', iflags, doc='The CPU flags to test')
+
+selectif = Instruction(
+        'selectif', r"""
+        Conditional select, dependent on integer condition codes.
+
+        Select ``x`` when the CPU flags in ``flags`` satisfy the condition
+        code ``cc``, and ``y`` otherwise. The flags are usually computed by
+        :inst:`ifcmp`.
+        """,
+        ins=(cc, flags, x, y), outs=a)
+
 x = Operand('x', Any)
 
 copy = Instruction(
@@ -259,6 +457,275 @@ fill = Instruction(
         """,
         ins=x, outs=a)
 
+src = Operand('src', regunit)
+dst = Operand('dst', regunit)
+
+regmove = Instruction(
+        'regmove', r"""
+        Temporarily divert ``x`` from ``src`` to ``dst``.
+
+        This instruction moves the location of a value from one register to
+        another without creating a new SSA value. It is used by the register
+        allocator to temporarily rearrange register assignments in order to
+        satisfy instruction constraints.
+
+        The register diversions created by this instruction must be undone
+        before the value leaves the EBB. At the entry to a new EBB, all live
+        values must be in their originally assigned registers.
+        """,
+        ins=(x, src, dst))
+
+refs = Operand('refs', VARIABLE_ARGS, doc='Live reference values')
+
+safepoint = Instruction(
+        'safepoint', r"""
+        Garbage collection safepoint.
+
+        Mark a point in the code where the garbage collector may need to walk
+        the stack frame. The arguments are all the reference values that are
+        live across the safepoint. When the function is emitted, a stack map
+        recording the registers and stack slots holding those references is
+        reported to the code sink at the offset of the safepoint.
+
+        The register allocator inserts a :inst:`safepoint` before every call
+        in a function that has live references, but it can also be used
+        explicitly, in a loop header for example. The instruction itself
+        doesn't generate any code.
+        """,
+        ins=refs)
+
+#
+# Memory operations
+#
+
+Mem = TypeVar(
+        'Mem', 'Any type that can be stored in memory',
+        ints=True, floats=True, refs=True, simd=True)
+
+Flags = Operand('Flags', memflags)
+p = Operand('p', iAddr, doc='Base address')
+Offset = Operand('Offset', offset32, doc='Byte offset from base address')
+x = Operand('x', Mem, doc='Value to be stored')
+a = Operand('a', Mem, doc='Value loaded')
+
+load = Instruction(
+        'load', r"""
+        Load from memory at ``p + Offset``.
+
+        This is a polymorphic instruction that can load any value type which
+        has a memory representation.
+        """,
+        ins=(Flags, p, Offset), outs=a, can_load=True)
+
+store = Instruction(
+        'store', r"""
+        Store ``x`` to memory at ``p + Offset``.
+
+        This is a polymorphic instruction that can store any value type with a
+        memory representation.
+        """,
+        ins=(Flags, x, p, Offset), can_store=True)
+
+iIndex = TypeVar('iIndex', 'An integer index type', ints=(32, 64))
+q = Operand('q', iIndex, doc='Index, must have the same type as `p`')
+Shift = Operand('Shift', uimm8, doc='Left shift amount applied to `q`')
+
+load_complex = Instruction(
+        'load_complex', r"""
+        Load from memory at ``p + (q << Shift) + Offset``.
+
+        This is the same as :inst:`load` with an address computed from a base
+        and a scaled index. ISAs with complex addressing modes can encode it as
+        a single instruction, and the legalizer expands it into an
+        :inst:`ishl_imm`, an :inst:`iadd`, and a :inst:`load` on the others.
+        """,
+        ins=(Flags, p, q, Shift, Offset), outs=a, can_load=True)
+
+store_complex = Instruction(
+        'store_complex', r"""
+        Store ``x`` to memory at ``p + (q << Shift) + Offset``.
+
+        This is the same as :inst:`store` with an address computed from a base
+        and a scaled index, like :inst:`load_complex`.
+        """,
+        ins=(Flags, x, p, q, Shift, Offset), can_store=True)
+
+#
+# Atomic memory operations
+#
+
+iWord = TypeVar('iWord', 'A scalar integer machine word', ints=(32, 64))
+
+Ord = Operand('Ord', ordering)
+x = Operand('x', iWord, doc='Value to be stored')
+a = Operand('a', iWord, doc='Value loaded')
+
+atomic_load = Instruction(
+        'atomic_load', r"""
+        Atomically load from memory at ``p``.
+
+        The address must be aligned to the size of the loaded type. The
+        ordering ``Ord`` constrains how other memory accesses can be moved
+        around the load.
+        """,
+        ins=(Ord, p), outs=a, can_load=True, can_store=True)
+
+atomic_store = Instruction(
+        'atomic_store', r"""
+        Atomically store ``x`` to memory at ``p``.
+
+        The address must be aligned to the size of the stored type.
+        """,
+        ins=(Ord, p, x), can_load=True, can_store=True)
+
+e = Operand('e', iWord, doc='Expected value')
+a = Operand('a', iWord, doc='Value previously in memory')
+
+atomic_cas = Instruction(
+        'atomic_cas', r"""
+        Atomic compare and swap.
+
+        Load the value at ``p``, and if it is equal to ``e``, replace it with
+        ``x``. The whole operation is atomic. The value previously in memory is
+        returned, so the swap succeeded if it is equal to ``e``.
+        """,
+        ins=(Ord, p, e, x), outs=a, can_load=True, can_store=True)
+
+atomic_xchg = Instruction(
+        'atomic_xchg', r"""
+        Atomically replace the value at ``p`` with ``x``.
+
+        Return the value previously in memory.
+        """,
+        ins=(Ord, p, x), outs=a, can_load=True, can_store=True)
+
+atomic_add = Instruction(
+        'atomic_add', r"""
+        Atomically add ``x`` to the value at ``p``.
+
+        Return the value previously in memory.
+        """,
+        ins=(Ord, p, x), outs=a, can_load=True, can_store=True)
+
+atomic_sub = Instruction(
+        'atomic_sub', r"""
+        Atomically subtract ``x`` from the value at ``p``.
+
+        Return the value previously in memory.
+        """,
+        ins=(Ord, p, x), outs=a, can_load=True, can_store=True)
+
+atomic_and = Instruction(
+        'atomic_and', r"""
+        Atomically replace the value at ``p`` with its bitwise and with ``x``.
+
+        Return the value previously in memory.
+        """,
+        ins=(Ord, p, x), outs=a, can_load=True, can_store=True)
+
+atomic_or = Instruction(
+        'atomic_or', r"""
+        Atomically replace the value at ``p`` with its bitwise or with ``x``.
+
+        Return the value previously in memory.
+        """,
+        ins=(Ord, p, x), outs=a, can_load=True, can_store=True)
+
+atomic_xor = Instruction(
+        'atomic_xor', r"""
+        Atomically replace the value at ``p`` with its bitwise xor with ``x``.
+
+        Return the value previously in memory.
+        """,
+        ins=(Ord, p, x), outs=a, can_load=True, can_store=True)
+
+x = Operand('x', Mem, doc='Value to be stored')
+a = Operand('a', Mem, doc='Value loaded')
+
+#
+# Stack slots
+#
+
+SS = Operand('SS', entities.stack_slot)
+Offset = Operand('Offset', uimm32, doc='In-bounds offset into stack slot')
+
+stack_load = Instruction(
+        'stack_load', r"""
+        Load a value from a stack slot at the constant offset.
+
+        This is a polymorphic instruction that can load any value type which
+        has a memory representation.
+
+        The offset is an immediate constant, not an SSA value. The memory
+        access cannot go out of bounds, i.e. ``sizeof(a) + Offset <=
+        sizeof(SS)``.
+        """,
+        ins=(SS, Offset), outs=a, can_load=True)
+
+stack_store = Instruction(
+        'stack_store', r"""
+        Store a value to a stack slot at a constant offset.
+
+        This is a polymorphic instruction that can store any value type with a
+        memory representation.
+
+        The offset is an immediate constant, not an SSA value. The memory
+        access cannot go out of bounds, i.e. ``sizeof(a) + Offset <=
+        sizeof(SS)``.
+        """,
+        ins=(x, SS, Offset), can_store=True)
+
+addr = Operand('addr', iAddr, doc='Address of the byte at ``Offset``')
+
+stack_addr = Instruction(
+        'stack_addr', r"""
+        Get the address of a stack slot.
+
+        Compute the absolute address of a byte in a stack slot. The offset must
+        refer to a byte inside the stack slot: ``0 <= Offset < sizeof(SS)``.
+        """,
+        ins=(SS, Offset), outs=addr)
+
+#
+# Global variables
+#
+
+GV = Operand('GV', entities.global_var, doc='A global variable.')
+addr = Operand('addr', iAddr, doc='Address of the global variable')
+
+globalsym_addr = Instruction(
+        'globalsym_addr', r"""
+        Compute the address of global variable GV, which is a symbolic name.
+
+        The address is resolved by the linker, or through the GOT when the
+        code is position independent and the symbol isn't colocated with the
+        function.
+        """,
+        ins=GV, outs=addr)
+
+#
+# Heaps
+#
+
+HeapOffset = TypeVar('HeapOffset', 'An unsigned heap offset', ints=(32, 64))
+
+H = Operand('H', entities.heap)
+p = Operand('p', HeapOffset, doc='Unsigned base address in heap')
+Size = Operand('Size', uimm32, doc='Size in bytes')
+addr = Operand('addr', iAddr, doc='Absolute address corresponding to `p`')
+
+heap_addr = Instruction(
+        'heap_addr', r"""
+        Bounds check and compute absolute address of heap memory.
+
+        Verify that the address range ``p .. p + Size - 1`` is valid in the
+        heap H, and trap if not.
+
+        Convert the heap-relative address in ``p`` to a real absolute address
+        and return it.
+        """,
+        ins=(H, p, Size), outs=addr, can_trap=True)
+
 
 #
 # Vector operations
@@ -345,6 +812,32 @@ icmp = Instruction(
         """,
         ins=(Cond, x, y), outs=a)
 
+f = Operand('f', iflags)
+x = Operand('x', iB)
+y = Operand('y', iB)
+
+ifcmp = Instruction(
+        'ifcmp', r"""
+        Compare scalar integers and return flags.
+
+        Compare two scalar integer values and return integer CPU flags
+        representing the result. The flags can be tested by
+        :inst:`trueif` and the other instructions taking an :type:`iflags`
+        operand.
+        """,
+        ins=(x, y), outs=f)
+
+a = Operand('a', b1)
+
+trueif = Instruction(
+        'trueif', r"""
+        Test integer CPU flags for a specific condition.
+
+        Check the CPU flags in ``f`` against the ``Cond`` condition code and
+        return true when the condition code is satisfied.
+        """,
+        ins=(Cond, f), outs=a)
+
 a = Operand('a', Int)
 x = Operand('x', Int)
 y = Operand('y', Int)
@@ -492,6 +985,26 @@ isub_imm = Instruction(
         """,
         ins=(X, y), outs=a)
 
+umulhi = Instruction(
+        'umulhi', """
+        Unsigned integer multiplication, producing the high half of a
+        double-length result.
+
+        Polymorphic over all scalar integer types, but does not support vector
+        types.
+        """,
+        ins=(x, y), outs=a)
+
+smulhi = Instruction(
+        'smulhi', """
+        Signed integer multiplication, producing the high half of a
+        double-length result.
+
+        Polymorphic over all scalar integer types, but does not support vector
+        types.
+        """,
+        ins=(x, y), outs=a)
+
 #
 # Integer arithmetic with carry and/or borrow.
 #
@@ -597,6 +1110,70 @@ isub_borrow = Instruction(
         """,
         ins=(x, y, b_in), outs=(a, b_out))
 
+uadd_overflow_trap = Instruction(
+        'uadd_overflow_trap', r"""
+        Add unsigned integers, trapping on overflow.
+
+        Same as :inst:`iadd`, but traps with ``code`` when the unsigned sum
+        doesn't fit in the type:
+
+        .. math::
+
+            a = x + y \quad \text{if } x + y < 2^B
+
+        Polymorphic over all scalar integer types, but does not support vector
+        types.
+        """,
+        ins=(x, y, code), outs=a, can_trap=True)
+
+sadd_overflow_trap = Instruction(
+        'sadd_overflow_trap', r"""
+        Add signed integers, trapping on overflow.
+
+        Same as :inst:`iadd`, but traps with ``code`` when the signed sum
+        doesn't fit in the type:
+
+        .. math::
+
+            a = x + y \quad \text{if } -2^{B-1} \le x + y < 2^{B-1}
+
+        Polymorphic over all scalar integer types, but does not support vector
+        types.
+        """,
+        ins=(x, y, code), outs=a, can_trap=True)
+
+usub_overflow_trap = Instruction(
+        'usub_overflow_trap', r"""
+        Subtract unsigned integers, trapping on overflow.
+
+        Same as :inst:`isub`, but traps with ``code`` when ``y`` is larger
+        than ``x``:
+
+        .. math::
+
+            a = x - y \quad \text{if } x \ge y
+
+        Polymorphic over all scalar integer types, but does not support vector
+        types.
+        """,
+        ins=(x, y, code), outs=a, can_trap=True)
+
+ssub_overflow_trap = Instruction(
+        'ssub_overflow_trap', r"""
+        Subtract signed integers, trapping on overflow.
+
+        Same as :inst:`isub`, but traps with ``code`` when the signed
+        difference doesn't fit in the type:
+
+        .. math::
+
+            a = x - y \quad \text{if } -2^{B-1} \le x - y < 2^{B-1}
+
+        Polymorphic over all scalar integer types, but does not support vector
+        types.
+        """,
+        ins=(x, y, code), outs=a, can_trap=True)
+
 #
 # Bitwise operations.
 #
@@ -802,6 +1379,26 @@ popcnt = Instruction(
         """,
         ins=x, outs=a)
 
+a = Operand('a', iB)
+
+bitrev = Instruction(
+        'bitrev', r"""
+        Reverse the bits of an integer.
+
+        The LSB of ``x`` becomes the MSB of the result and vice versa.
+        """,
+        ins=x, outs=a)
+
+bswap = Instruction(
+        'bswap', r"""
+        Reverse the bytes of an integer.
+
+        This converts between little-endian and big-endian representations.
+        The least significant byte of ``x`` becomes the most significant byte
+        of the result and vice versa.
+        """,
+        ins=x, outs=a)
+
 #
 # Floating point.
 #
@@ -879,6 +1476,28 @@ fcmp = Instruction(
         """,
         ins=(Cond, x, y), outs=a)
 
+f = Operand('f', fflags)
+
+ffcmp = Instruction(
+        'ffcmp', r"""
+        Floating point comparison returning flags.
+
+        Compares two numbers like :inst:`fcmp`, but returns floating point CPU
+        flags instead of testing a specific condition.
+        """,
+        ins=(x, y), outs=f)
+
+a = Operand('a', b1)
+
+trueff = Instruction(
+        'trueff', r"""
+        Test floating point CPU flags for a specific condition.
+
+        Check the CPU flags in ``f`` against the ``Cond`` condition code and
+        return true when the condition code is satisfied.
+        """,
+        ins=(Cond, f), outs=a)
+
 x = Operand('x', Float)
 y = Operand('y', Float)
 z = Operand('z', Float)
@@ -1045,6 +1664,25 @@ bitcast = Instruction(
         """,
         ins=x, outs=a)
 
+Bool = TypeVar(
+        'Bool', 'A scalar or vector boolean type',
+        bools=True, simd=True)
+IntTo = TypeVar(
+        'IntTo', 'An integer type with the same number of lanes',
+        ints=True, simd=True)
+
+x = Operand('x', Bool)
+a = Operand('a', IntTo)
+
+bint = Instruction(
+        'bint', r"""
+        Convert `x` to an integer.
+
+        True maps to 1 and false maps to 0. The result type must have the same
+        number of vector lanes as the input.
+        """,
+        ins=x, outs=a)
+
 Int = TypeVar('Int', 'A scalar or vector integer type', ints=True, simd=True)
 IntTo = TypeVar(
         'IntTo', 'A smaller integer type with the same number of lanes',
@@ -1157,6 +1795,18 @@ fcvt_to_uint = Instruction(
         """,
         ins=x, outs=a, can_trap=True)
 
+fcvt_to_uint_sat = Instruction(
+        'fcvt_to_uint_sat', r"""
+        Convert floating point to unsigned integer like :inst:`fcvt_to_uint`,
+        but saturate instead of trapping.
+
+        Values that are too large for the result type produce the largest
+        unsigned integer, and negative values produce 0. NaN also produces 0.
+
+        The result type must have the same number of vector lanes as the input.
+        """,
+        ins=x, outs=a)
+
 fcvt_to_sint = Instruction(
         'fcvt_to_sint', r"""
         Convert floating point to signed integer.
@@ -1169,6 +1819,18 @@ fcvt_to_sint = Instruction(
         """,
         ins=x, outs=a, can_trap=True)
 
+fcvt_to_sint_sat = Instruction(
+        'fcvt_to_sint_sat', r"""
+        Convert floating point to signed integer like :inst:`fcvt_to_sint`,
+        but saturate instead of trapping.
+
+        Values that are out of range for the result type are clamped to the
+        smallest or largest signed integer. NaN produces 0.
+
+        The result type must have the same number of vector lanes as the input.
+        """,
+        ins=x, outs=a)
+
 x = Operand('x', Int)
 a = Operand('a', FloatTo)
 
@@ -1200,7 +1862,7 @@ fcvt_from_sint = Instruction(
 
 WideInt = TypeVar(
         'WideInt', 'A scalar integer type from `i16` upwards',
-        ints=(16, 64))
+        ints=(16, 128))
 x = Operand('x', WideInt)
 lo = Operand(
         'lo', WideInt.half_width(), 'The low bits of `x`')
@@ -1218,8 +1880,8 @@ isplit_lohi = Instruction(
 
 
 NarrowInt = TypeVar(
-        'NarrowInt', 'A scalar integer type up to `i32`',
-        ints=(8, 32))
+        'NarrowInt', 'A scalar integer type up to `i64`',
+        ints=(8, 64))
 lo = Operand('lo', NarrowInt)
 hi = Operand('hi', NarrowInt)
 a = Operand(
@@ -1232,4 +1894,37 @@ iconcat_lohi = Instruction(
         """,
         ins=(lo, hi), outs=a)
 
+x = Operand('x', TxN)
+lo = Operand('lo', TxN.half_vector(), 'The low-numbered lanes of `x`')
+hi = Operand('hi', TxN.half_vector(), 'The high-numbered lanes of `x`')
+
+vsplit = Instruction(
+        'vsplit', r"""
+        Split a vector into two halves.
+
+        Returns the low-numbered lanes and the high-numbered lanes of `x` as
+        two independent values. The results are scalars if `x` only has two
+        lanes.
+        """,
+        ins=x, outs=(lo, hi))
+
+Any128 = TypeVar(
+        'Any128', 'Any scalar or vector type with at most 128 lanes',
+        ints=True, floats=True, bools=True, scalars=True, simd=(1, 128))
+lo = Operand('lo', Any128, doc='The low-numbered lanes')
+hi = Operand('hi', Any128, doc='The high-numbered lanes')
+a = Operand(
+        'a', Any128.double_vector(),
+        doc='The concatenation of `lo` and `hi`')
+
+vconcat = Instruction(
+        'vconcat', r"""
+        Concatenate two vectors to form a vector with twice as many lanes.
+
+        The lanes of `lo` become the low-numbered lanes of `a`, and the lanes
+        of `hi` become the high-numbered lanes. Two scalars can be concatenated
+        to form a vector with two lanes.
+        """,
+        ins=(lo, hi), outs=a)
+
 GROUP.close()
@@ -8,9 +8,14 @@ instructions that are legal.
 """
 from __future__ import absolute_import
 from .instructions import iadd, iadd_cout, iadd_cin, iadd_carry, iadd_imm
-from .instructions import isub, isub_bin, isub_bout, isub_borrow
+from .instructions import isub, isub_bin, isub_bout, isub_borrow, isub_imm
+from .instructions import uadd_overflow_trap, sadd_overflow_trap
+from .instructions import usub_overflow_trap, ssub_overflow_trap
 from .instructions import band, bor, bxor, isplit_lohi, iconcat_lohi
-from .instructions import icmp, iconst
+from .instructions import band_imm, bor_imm, bxor_imm
+from .instructions import imul, udiv, sdiv, urem, srem
+from .instructions import imul_imm, udiv_imm, sdiv_imm, urem_imm, srem_imm
+from .instructions import icmp, iconst, bint, null, undef, trapnz
 from cdsl.ast import Var
 from cdsl.xform import Rtl, XFormGroup
 
@@ -40,10 +45,14 @@ b = Var('b')
 b1 = Var('b1')
 b2 = Var('b2')
 b_in = Var('b_in')
+b_int = Var('b_int')
 c = Var('c')
+code = Var('code')
 c1 = Var('c1')
 c2 = Var('c2')
 c_in = Var('c_in')
+c_int = Var('c_int')
+z = Var('z')
 xl = Var('xl')
 xh = Var('xh')
 yl = Var('yl')
@@ -102,14 +111,16 @@ expand.legalize(
         a << iadd_cin(x, y, c),
         Rtl(
             a1 << iadd(x, y),
-            a << iadd(a1, c)
+            c_int << bint(c),
+            a << iadd(a1, c_int)
         ))
 
 expand.legalize(
         a << isub_bin(x, y, b),
         Rtl(
             a1 << isub(x, y),
-            a << isub(a1, b)
+            b_int << bint(b),
+            a << isub(a1, b_int)
         ))
 
 expand.legalize(
@@ -128,6 +139,48 @@ expand.legalize(
             b << bor(b1, b2)
         ))
 
+# Expand the overflow traps for RISC architectures that don't have the flags.
+# The unsigned checks are the same as the carry and borrow outputs above. A
+# signed sum is smaller than `x` exactly when `y` is negative, unless it
+# overflowed, and the other way around for a difference.
+expand.legalize(
+        a << uadd_overflow_trap(x, y, code),
+        Rtl(
+            a << iadd(x, y),
+            c << icmp('IntCC::UnsignedLessThan', a, x),
+            trapnz(c, code)
+        ))
+
+expand.legalize(
+        a << usub_overflow_trap(x, y, code),
+        Rtl(
+            a << isub(x, y),
+            b << icmp('IntCC::UnsignedGreaterThan', a, x),
+            trapnz(b, code)
+        ))
+
+expand.legalize(
+        a << sadd_overflow_trap(x, y, code),
+        Rtl(
+            a << iadd(x, y),
+            z << iconst(0),
+            c1 << icmp('IntCC::SignedLessThan', y, z),
+            c2 << icmp('IntCC::SignedLessThan', a, x),
+            c << bxor(c1, c2),
+            trapnz(c, code)
+        ))
+
+expand.legalize(
+        a << ssub_overflow_trap(x, y, code),
+        Rtl(
+            a << isub(x, y),
+            z << iconst(0),
+            c1 << icmp('IntCC::SignedLessThan', y, z),
+            c2 << icmp('IntCC::SignedGreaterThan', a, x),
+            c << bxor(c1, c2),
+            trapnz(c, code)
+        ))
+
 # Expansions for immediates that are too large.
 expand.legalize(
         a << iadd_imm(x, y),
@@ -135,3 +188,36 @@ expand.legalize(
             a1 << iconst(y),
             a << iadd(x, a1)
         ))
+
+expand.legalize(
+        a << isub_imm(x, y),
+        Rtl(
+            a1 << iconst(x),
+            a << isub(a1, y)
+        ))
+
+for bitop, bitop_imm in [(band, band_imm), (bor, bor_imm), (bxor, bxor_imm)]:
+    expand.legalize(
+            a << bitop_imm(x, y),
+            Rtl(
+                a1 << iconst(y),
+                a << bitop(x, a1)
+            ))
+
+for inst, inst_imm in [
+        (imul, imul_imm),
+        (udiv, udiv_imm),
+        (sdiv, sdiv_imm),
+        (urem, urem_imm),
+        (srem, srem_imm)]:
+    expand.legalize(
+            a << inst_imm(x, y),
+            Rtl(
+                a1 << iconst(y),
+                a << inst(x, a1)
+            ))
+
+# The contents of an undefined value don't matter, so any value will do.
+expand.legalize(
+        a << undef(),
+        Rtl(a << null()))
@@ -4,34 +4,122 @@ Cretonne shared settings.
 This module defines settings are are relevant for all code generators.
 """
 from __future__ import absolute_import
-from cdsl.settings import SettingGroup, BoolSetting, EnumSetting
+from cdsl.settings import SettingGroup, BoolSetting, NumSetting, EnumSetting
 
 group = SettingGroup('shared')
 
 opt_level = EnumSetting(
         """
-        Optimization level:
+        Optimization level used by `Context::compile()`:
 
-        - default: Very profitable optimizations enabled, none slow.
-        - best: Enable all optimizations
-        - fastest: Optimize for compile time by disabling most optimizations.
+        - none: Minimize compile time by skipping all the optional passes.
+        - speed: Run the optimizations that make the generated code faster.
+        - speed_and_size: Like `speed`, and also prefer smaller code where it
+          doesn't cost speed.
         """,
-        'default', 'best', 'fastest')
+        'none', 'speed', 'speed_and_size')
+
+enable_verifier = BoolSetting(
+        """
+        Run the IL verifier after each pass in the compilation context.
+
+        This turns miscompilations into early errors, at the cost of slower
+        compilation.
+        """,
+        default=True)
 
 is_64bit = BoolSetting("Enable 64-bit code generation")
 
 is_compressed = BoolSetting("Enable compressed instructions")
 
+is_pic = BoolSetting(
+        """
+        Enable Position-Independent Code generation.
+
+        Symbols are addressed relative to the program counter or through the
+        Global Offset Table, and calls to functions that may be defined in
+        another object go through the Procedure Linkage Table.
+        """)
+
+avoid_div_traps = BoolSetting(
+        """
+        Generate explicit checks around native division instructions to avoid
+        their trapping.
+
+        By default, integer division relies on the ISA's division instruction
+        faulting when the divisor is zero or the quotient overflows, and the
+        instruction is reported as a trap site. Embedders that can't handle
+        that fault, like a `SIGFPE` signal, should enable this setting to get
+        explicit `trapz` and `trapnz` checks before the division instead.
+
+        This only affects ISAs whose division instructions can fault.
+        """)
+
 enable_float = BoolSetting(
-        """Enable the use of floating-point instructions""",
+        """
+        Enable the use of floating-point instructions.
+
+        When disabled, the verifier rejects functions using floating point
+        types, and instructions using them have no legal encodings.
+        """,
         default=True)
 
 enable_simd = BoolSetting(
-        """Enable the use of SIMD instructions.""",
+        """
+        Enable the use of SIMD instructions.
+
+        When disabled, the verifier rejects functions using vector types, and
+        instructions using them have no legal encodings.
+        """,
         default=True)
 
 enable_atomics = BoolSetting(
         """Enable the use of atomic instructions""",
         default=True)
 
+stack_align_log2 = NumSetting(
+        """
+        The log2 of the stack alignment at calls, in bytes.
+
+        The default of 0 uses the alignment required by the target ISA's
+        ABI. Embedders calling into or out of code with a different stack
+        discipline can override it, for example with 2 for 4-byte aligned
+        embedded ABIs. The frame layout and the outgoing argument areas of
+        calls use this alignment.
+        """,
+        default=0)
+
+preserve_frame_pointers = BoolSetting(
+        """
+        Never allocate the frame pointer register.
+
+        Generated functions don't set up frame records of their own, but
+        with this setting they leave the frame pointer register untouched,
+        so a frame pointer chain maintained by the surrounding runtime
+        stays intact for profilers and stack walkers.
+        """)
+
+baldrdash_prologue_words = NumSetting(
+        """
+        Number of pointer-sized words pushed by the Baldrdash prologue.
+
+        Functions using the `baldrdash` calling convention are entered
+        through a prologue generated by the embedder, which pushes this many
+        words below the return address before the function's own code runs.
+        The stack frame is padded and described by the unwind information
+        with these words taken into account.
+        """,
+        default=0)
+
+probestack_size_log2 = NumSetting(
+        """
+        The log2 of the size of the guard region below the stack.
+
+        Stack frames larger than this are probed one page at a time from the
+        top down, so the guard page is always hit before the stack pointer
+        moves past it. This is needed on Windows, which commits the stack
+        pages on demand, and on hardened Linux systems.
+        """,
+        default=12)
+
 group.close(globals())
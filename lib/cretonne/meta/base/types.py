@@ -1,8 +1,9 @@
 """
-The base.types module predefines all the Cretonne scalar types.
+The base.types module predefines all the Cretonne scalar and special types.
 """
 from __future__ import absolute_import
-from cdsl.types import ScalarType, IntType, FloatType, BoolType
+from cdsl.types import ScalarType, IntType, FloatType, BoolType, RefType
+from cdsl.types import FlagsType
 
 #: Boolean.
 b1 = ScalarType(
@@ -36,3 +37,24 @@ f64 = FloatType(
         *binary64* interchange format. This corresponds to the :c:type:`double`
         type in most C implementations.
         """)
+
+r32 = RefType(32)   #: 32-bit reference.
+r64 = RefType(64)   #: 64-bit reference.
+
+#: 128-bit int. This is defined after the other scalar types to keep their
+#: numbering stable.
+i128 = IntType(128)
+
+#: CPU flags from an integer comparison.
+iflags = FlagsType(
+        'iflags', """
+        CPU flags representing the result of an integer comparison. These flags
+        can be tested with an :type:`intcc` condition code.
+        """)
+
+#: CPU flags from a floating point comparison.
+fflags = FlagsType(
+        'fflags', """
+        CPU flags representing the result of a floating point comparison. These
+        flags can be tested with a :type:`floatcc` condition code.
+        """)
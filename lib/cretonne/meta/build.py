@@ -11,10 +11,16 @@ import gen_settings
 import gen_build_deps
 import gen_encoding
 import gen_legalizer
+import gen_combine
 import gen_registers
+import gen_binemit
+import gen_json
 
 parser = argparse.ArgumentParser(description='Generate sources for Cretonne.')
 parser.add_argument('--out-dir', help='set output directory')
+parser.add_argument(
+        '--json', action='store_true',
+        help='also write a JSON description of the instruction set')
 
 args = parser.parse_args()
 out_dir = args.out_dir  # type: ignore
@@ -26,5 +32,10 @@ gen_instr.generate(isas, out_dir)
 gen_settings.generate(isas, out_dir)
 gen_encoding.generate(isas, out_dir)
 gen_legalizer.generate(isas, out_dir)
+gen_combine.generate(isas, out_dir)
 gen_registers.generate(isas, out_dir)
+gen_binemit.generate(isas, out_dir)
 gen_build_deps.generate()
+
+if args.json:  # type: ignore
+    gen_json.generate(isas, out_dir)
@@ -11,6 +11,23 @@ except ImportError:
     pass
 
 
+class InstructionContext(object):
+    """
+    Most instruction predicates refer to immediate fields of a specific
+    instruction format, so their `predicate_context()` is the specific
+    `InstructionFormat`.
+
+    Type predicates apply to any instruction format, so their context is the
+    general instruction context which is the parent of all instruction
+    formats.
+    """
+    name = 'inst'
+
+
+# The single instruction context that is the parent of all instruction formats.
+instruction_context = InstructionContext()
+
+
 class InstructionFormat(object):
     """
     Every instruction opcode has a corresponding instruction format which
@@ -37,6 +54,10 @@ class InstructionFormat(object):
     :param boxed_storage: Set to `True` is this instruction format requires a
         `data: Box<...>` pointer to additional storage in its `InstructionData`
         variant.
+
+    Formats with `variable_args` operands keep all of their value operands in
+    an `args: ValueList` member whose values are stored in the
+    `dfg.value_lists` pool. The fixed value operands come first in the list.
     :param typevar_operand: Index of the input operand that is used to infer
         the controlling type variable. By default, this is the first `value`
         operand.
@@ -53,9 +74,15 @@ class InstructionFormat(object):
         self.name = kwargs.get('name', None)  # type: str
         self.multiple_results = kwargs.get('multiple_results', False)
         self.boxed_storage = kwargs.get('boxed_storage', False)
+        self.parent = instruction_context
         self.members = list()  # type: List[str]
         self.kinds = tuple(self._process_member_names(kinds))
 
+        # Are the value operands stored in a value list?
+        self.has_value_list = VARIABLE_ARGS in self.kinds
+        assert not (self.has_value_list and self.boxed_storage), \
+            "value list formats don't need boxed storage"
+
         # Which of self.kinds are `value`?
         self.value_operands = tuple(
                 i for i, k in enumerate(self.kinds) if k is VALUE)
@@ -133,12 +160,17 @@ class InstructionFormat(object):
         The `ins` and `outs` arguments correspond to the
         :py:class:`Instruction` arguments of the same name, except they must be
         tuples of :py:`Operand` objects.
+
+        An instruction without results can also use a format that allows
+        multiple results when there is no better match.
         """
         if len(outs) == 1:
             multiple_results = outs[0].kind == VARIABLE_ARGS
         else:
             multiple_results = len(outs) > 1
         sig = (multiple_results, tuple(op.kind for op in ins))
+        if sig not in InstructionFormat._registry and len(outs) == 0:
+            sig = (True, sig[1])
         if sig not in InstructionFormat._registry:
             raise RuntimeError(
                     "No instruction format matches ins = ({}){}".format(
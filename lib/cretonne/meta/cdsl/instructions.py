@@ -80,7 +80,11 @@ class Instruction(object):
                 values or `variable_args`.
     :param is_terminator: This is a terminator instruction.
     :param is_branch: This is a branch instruction.
+    :param is_return: This instruction returns from the function, possibly
+                      after making a tail call.
     :param can_trap: This instruction can trap.
+    :param can_load: This instruction can read from memory.
+    :param can_store: This instruction can write to memory.
     """
 
     def __init__(self, name, doc, ins=(), outs=(), **kwargs):
@@ -97,7 +101,10 @@ class Instruction(object):
         self._verify_polymorphic()
         self.is_branch = 'is_branch' in kwargs
         self.is_terminator = 'is_terminator' in kwargs
+        self.is_return = 'is_return' in kwargs
         self.can_trap = 'can_trap' in kwargs
+        self.can_load = 'can_load' in kwargs
+        self.can_store = 'can_store' in kwargs
         InstructionGroup.append(self)
 
     def __str__(self):
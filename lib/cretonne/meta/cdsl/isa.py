@@ -1,7 +1,7 @@
 """Defining instruction set architectures."""
 from __future__ import absolute_import
-from .predicates import And
-from .registers import RegClass, Register
+from .predicates import And, TypePredicate
+from .registers import RegClass, Register, Stack
 
 # The typing module is only required by mypy, and we don't use these imports
 # outside type comments.
@@ -13,8 +13,9 @@ try:
     from .types import ValueType  # noqa
     from .registers import RegBank  # noqa
     AnyPredicate = Union[Predicate, FieldPredicate]
-    OperandConstraint = Union[RegClass, Register, int]
+    OperandConstraint = Union[RegClass, Register, int, Stack]
     ConstraintSeq = Union[OperandConstraint, Tuple[OperandConstraint, ...]]
+    BranchRange = Tuple[int, int]
 except ImportError:
     pass
 
@@ -155,22 +156,44 @@ class EncRecipe(object):
 
     - A `RegClass` specifying the set of allowed registers.
     - A `Register` specifying a fixed-register operand.
+    - A `Stack` specifying a value in a stack slot.
     - An integer indicating that this result is tied to a value operand, so
       they must use the same register.
 
+    Each recipe encodes instructions with a fixed size in bytes. Branch recipes
+    also specify the range of destinations they can reach as a `(origin,
+    bits)` tuple: The branch displacement is a signed `bits`-bit byte offset
+    relative to the address `origin` bytes after the start of the branch
+    instruction.
+
     :param name: Short mnemonic name for this recipe.
     :param format: All encoded instructions must have this
             :py:class:`InstructionFormat`.
+    :param size: Number of bytes in the binary encoded instruction.
     :param: ins Tuple of register constraints for value operands.
     :param: outs Tuple of register constraints for results.
+    :param branch_range: `(origin, bits)` range of the branch displacement
+            for branch recipes.
+    :param clobbers_flags: Instructions encoded with this recipe overwrite
+            the CPU flags register as a side effect.
     """
 
-    def __init__(self, name, format, ins, outs, instp=None, isap=None):
-        # type: (str, InstructionFormat, ConstraintSeq, ConstraintSeq, AnyPredicate, AnyPredicate) -> None  # noqa
+    def __init__(
+            self, name, format, size, ins, outs, branch_range=None,
+            instp=None, isap=None, clobbers_flags=False):
+        # type: (str, InstructionFormat, int, ConstraintSeq, ConstraintSeq, BranchRange, AnyPredicate, AnyPredicate, bool) -> None  # noqa
         self.name = name
         self.format = format
+        assert size >= 0
+        self.size = size
+        self.branch_range = branch_range
+        if branch_range:
+            origin, bits = branch_range
+            assert 0 <= origin <= size
+            assert bits <= 32
         self.instp = instp
         self.isap = isap
+        self.clobbers_flags = clobbers_flags
         if instp:
             assert instp.predicate_context() == format
         self.number = None  # type: int
@@ -195,7 +218,7 @@ class EncRecipe(object):
                 # Check that it is in range.
                 assert c >= 0 and c < len(self.format.value_operands)
             else:
-                assert isinstance(c, RegClass) or isinstance(c, Register)
+                assert isinstance(c, (RegClass, Register, Stack))
         return seq
 
 
@@ -228,6 +251,13 @@ class Encoding(object):
         self.encbits = encbits
         # Combine recipe predicates with the manually specified ones.
         self.instp = And.combine(recipe.instp, instp)
+
+        # The encoding tables are keyed by the controlling type variable, so
+        # add type predicates that check any secondary type variables.
+        if len(self.typevars) > 1:
+            for tv, vt in zip(self.inst.other_typevars, self.typevars[1:]):
+                typred = TypePredicate.typevar_check(self.inst, tv, vt)
+                self.instp = And.combine(self.instp, typred)
         self.isap = And.combine(recipe.isap, isap)
 
     def __str__(self):
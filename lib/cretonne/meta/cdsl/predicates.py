@@ -19,10 +19,13 @@ and `Not` combinators defined in this module.
 
 All predicates have a *context* which determines where they can be evaluated.
 For an ISA predicate, the context is the ISA settings group. For an instruction
-predicate, the context is the instruction format.
+predicate, the context is the instruction format. Type predicates can be
+evaluated on any instruction, so their context is the instruction context which
+is the parent of all the instruction formats.
 """
 from __future__ import absolute_import
 from functools import reduce
+from .formats import instruction_context
 
 
 def _is_parent(a, b):
@@ -238,3 +241,137 @@ class IsUnsignedInt(FieldPredicate):
         self.scale = scale
         assert width >= 0 and width <= 64
         assert scale >= 0 and scale < width
+
+
+class IsBitmaskImm(FieldPredicate):
+    """
+    Instruction predicate that checks if an immediate instruction format field
+    is a bitmask immediate of a `width`-bit operation.
+
+    A bitmask immediate is a `width`-bit value consisting of a repeated
+    element of 2, 4, 8, 16, 32, or 64 bits, where each element is a rotated
+    run of ones. These are the logical immediates of AArch64. Only the low
+    `width` bits of the field are tested.
+
+    :param field: `FormatField` to be checked.
+    :param width: Number of bits in the operation, 32 or 64.
+    """
+
+    def __init__(self, field, width):
+        super(IsBitmaskImm, self).__init__(
+                field, 'is_bitmask_imm', (width,))
+        self.width = width
+        assert width in (32, 64)
+
+
+class IsEqual(FieldPredicate):
+    """
+    Instruction predicate that checks if an immediate instruction format field
+    is equal to a constant value.
+
+    :param field: `FormatField` to be checked.
+    :param value: Rust expression for the value to compare against.
+    """
+
+    def __init__(self, field, value):
+        super(IsEqual, self).__init__(field, 'is_equal', (value,))
+        self.value = value
+
+
+class IsColocatedFunc(FieldPredicate):
+    """
+    Instruction predicate that checks if the external function referenced by
+    a `func_ref` field is declared `colocated`, so it is defined in the same
+    object as the current function.
+
+    :param field: `FormatField` to be checked.
+    """
+
+    def __init__(self, field):
+        super(IsColocatedFunc, self).__init__(
+                field, 'is_colocated_func', ('dfg',))
+
+
+class IsColocatedData(FieldPredicate):
+    """
+    Instruction predicate that checks if the global variable referenced by a
+    `global_var` field is declared `colocated`, so it is defined in the same
+    object as the current function.
+
+    :param field: `FormatField` to be checked.
+    """
+
+    def __init__(self, field):
+        super(IsColocatedData, self).__init__(
+                field, 'is_colocated_data', ('dfg',))
+
+
+class IsAligned(FieldPredicate):
+    """
+    Instruction predicate that checks if the `aligned` flag is set in a
+    `memflags` instruction format field.
+
+    :param field: `FormatField` to be checked.
+    """
+
+    def __init__(self, field):
+        super(IsAligned, self).__init__(field, 'is_aligned', ())
+
+
+class TypePredicate(object):
+    """
+    An instruction predicate that checks the type of an SSA argument value.
+
+    Type predicates are used to implement encodings for instructions with
+    multiple type variables. The encoding tables are keyed by the controlling
+    type variable, type predicates check any secondary type variables.
+
+    A type predicate is not bound to any specific instruction format.
+
+    :param value_arg: Index of the value argument to type check.
+    :param value_type: The required value type.
+    """
+
+    def __init__(self, value_arg, value_type):
+        assert value_arg >= 0
+        assert value_type is not None
+        self.value_arg = value_arg
+        self.value_type = value_type
+
+    def __str__(self):
+        return 'args[{}]:{}'.format(self.value_arg, self.value_type)
+
+    def predicate_context(self):
+        return instruction_context
+
+    def predicate_leafs(self, leafs):
+        leafs.add(self)
+
+    @staticmethod
+    def typevar_check(inst, typevar, value_type):
+        """
+        Return a type check predicate for the given type variable in `inst`.
+
+        The type variable must appear directly as the type of one of the value
+        operands to `inst`, so this is only guaranteed to work for secondary
+        type variables.
+        """
+        # Find the first value operand whose type is `typevar`.
+        value_arg = next(
+                i for i, opnum in enumerate(inst.format.value_operands)
+                if inst.ins[opnum].typevar is typevar)
+        return TypePredicate(value_arg, value_type)
+
+    def rust_predicate(self, prec):
+        """
+        Return Rust code for evaluating this predicate.
+
+        It is assumed that the context has `dfg` and `inst` variables.
+        """
+        s = 'dfg.value_type({}) == {}'.format(
+                'inst.arguments(&dfg.value_lists)[0][{}]'
+                .format(self.value_arg),
+                self.value_type.rust_name())
+        if prec > And.precedence:
+            s = '({})'.format(s)
+        return s
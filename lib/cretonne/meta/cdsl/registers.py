@@ -93,6 +93,20 @@ class RegBank(object):
         return ('RegBank({}, units={}, first_unit={})'
                 .format(self.name, self.units, self.first_unit))
 
+    def unit_by_name(self, name):
+        # type: (str) -> int
+        """
+        Get a register unit in this bank by name.
+        """
+        if name in self.names:
+            r = self.names.index(name)
+        elif name.startswith(self.prefix):
+            r = int(name[len(self.prefix):])
+        else:
+            raise AttributeError('No register named ' + name)
+        assert r < self.units, 'Invalid register name: ' + name
+        return self.first_unit + r
+
     def finish_regclasses(self, first_index):
         # type: (int) -> None
         """
@@ -150,6 +164,13 @@ class RegBank(object):
                     # sub-class.
                     rc2.subclasses.append(rc1)
 
+        # The top-level class containing a register class is its first
+        # super-class. Any super-class of that one would have an even smaller
+        # index, and it would also be a super-class of the original class.
+        for rc in self.classes:
+            rc.toprc = next(
+                    (p for p in self.classes if rc in p.subclasses), rc)
+
 
 class RegClass(object):
     """
@@ -176,8 +197,9 @@ class RegClass(object):
         self.start = start
         self.width = width
 
-        # This is computed later in `finish_regclasses()`.
+        # These are computed later in `finish_regclasses()`.
         self.subclasses = list()  # type: List[RegClass]
+        self.toprc = None  # type: RegClass
 
         assert width > 0
         assert start >= 0 and start < bank.units
@@ -239,6 +261,17 @@ class RegClass(object):
 
         return RegClass(self.bank, count=c, width=w, start=s)
 
+    def __getattr__(self, attr):
+        # type: (str) -> Register
+        """
+        Get a specific register in the class by name.
+
+        For example: `GPR.r5`.
+        """
+        if attr.startswith('_'):
+            raise AttributeError(attr)
+        return Register(self, self.bank.unit_by_name(attr))
+
     def mask(self):
         # type: () -> List[int]
         """
@@ -294,10 +327,23 @@ class Register(object):
     Specific registers are used to describe constraints on instructions where
     some operands must use a fixed register.
 
-    Register objects should be created using the indexing syntax on the
-    register class.
+    Register objects should be created using the attribute syntax on the
+    register class, for example `GPR.rcx`.
     """
     def __init__(self, rc, unit):
         # type: (RegClass, int) -> None
         self.regclass = rc
         self.unit = unit
+
+
+class Stack(object):
+    """
+    An operand that must be in a stack slot.
+
+    A `Stack` object can be used to indicate an operand constraint for a value
+    operand that must live in a stack slot. The register class is the one that
+    would normally be used to load and store values of the operand's type.
+    """
+    def __init__(self, rc):
+        # type: (RegClass) -> None
+        self.regclass = rc
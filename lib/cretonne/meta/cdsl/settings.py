@@ -103,6 +103,75 @@ class EnumSetting(Setting):
         return 0
 
 
+class Preset(object):
+    """
+    A collection of setting values that are applied at once.
+
+    The `Preset` class provides a way of configuring a set of settings to
+    known values. This is useful for naming CPU models that support a
+    collection of ISA features, like `haswell`.
+
+    A preset is enabled in the same way as a boolean setting, and it simply
+    changes the values of the settings it names. Presets are not settings
+    themselves, and they don't appear in the printed settings.
+
+    Presets are not named when they are created. They get their name from the
+    `extract_names` method, just like settings.
+
+    :param args: Applicable settings. Each argument is either a
+        :class:`BoolSetting` which is enabled by the preset, a
+        `(setting, value)` tuple, or another :class:`Preset` whose settings are
+        included.
+    """
+
+    def __init__(self, *args):
+        self.name = None  # Assigned later by `SettingGroup.close()`.
+        # Ordered list of (setting, value) pairs.
+        self.values = list()
+        for arg in args:
+            if isinstance(arg, Preset):
+                self.values.extend(arg.values)
+            elif isinstance(arg, tuple):
+                self.values.append(arg)
+            else:
+                assert isinstance(arg, BoolSetting), \
+                    "Preset arguments must be settings or presets"
+                self.values.append((arg, True))
+        self.group = SettingGroup.append_preset(self)
+
+    def __str__(self):
+        return '{}.{}'.format(self.group.name, self.name)
+
+    def layout(self):
+        """
+        Compute a list of `(mask, value)` pairs, one for each byte in the
+        settings prefix of the group's byte vector.
+
+        Applying the preset to a byte vector `v` computes
+        `v[i] = (v[i] & ~mask) | value` for each byte.
+        """
+        layout = [(0, 0)] * self.group.settings_size
+        for setting, value in self.values:
+            assert setting.group is self.group, \
+                "{} is not in group {}".format(setting, self.group)
+            mask, byte = layout[setting.byte_offset]
+            if isinstance(setting, BoolSetting):
+                bit = 1 << setting.bit_offset
+                mask |= bit
+                if value:
+                    byte |= bit
+                else:
+                    byte &= ~bit
+            elif isinstance(setting, EnumSetting):
+                mask = 0xff
+                byte = setting.values.index(value)
+            else:
+                mask = 0xff
+                byte = int(value)
+            layout[setting.byte_offset] = (mask, byte)
+        return layout
+
+
 class SettingGroup(object):
     """
     A group of settings.
@@ -122,6 +191,8 @@ class SettingGroup(object):
         self.name = name
         self.parent = parent
         self.settings = []
+        # Named presets in this group.
+        self.presets = []
         # Named predicates computed from settings in this group or its
         # parents.
         self.named_predicates = []
@@ -159,7 +230,7 @@ class SettingGroup(object):
         SettingGroup._current = None
         if globs:
             for name, obj in globs.items():
-                if isinstance(obj, Setting):
+                if isinstance(obj, Setting) or isinstance(obj, Preset):
                     assert obj.name is None, obj.name
                     obj.name = name
                 if isinstance(obj, Predicate):
@@ -175,6 +246,13 @@ class SettingGroup(object):
         g.settings.append(setting)
         return g
 
+    @staticmethod
+    def append_preset(preset):
+        g = SettingGroup._current
+        assert g, "Open a setting group before defining presets."
+        g.presets.append(preset)
+        return g
+
     def number_predicate(self, pred):
         """
         Make sure that `pred` has an assigned number, and will be included in
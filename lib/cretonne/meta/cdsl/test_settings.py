@@ -0,0 +1,23 @@
+from __future__ import absolute_import
+from unittest import TestCase
+from .settings import SettingGroup, BoolSetting, EnumSetting, Preset
+
+
+class TestPreset(TestCase):
+    def test_layout(self):
+        g = SettingGroup('test')
+        e = EnumSetting('enum', 'x', 'y', 'z')
+        a = BoolSetting('a')
+        b = BoolSetting('b', default=True)
+        c = BoolSetting('c')
+        p1 = Preset(a, c)
+        p2 = Preset(p1, (b, False), (e, 'z'))
+        g.close({'e': e, 'a': a, 'b': b, 'c': c, 'p1': p1, 'p2': p2})
+
+        self.assertEqual(g.presets, [p1, p2])
+        self.assertEqual(p1.name, 'p1')
+        self.assertEqual(str(p2), 'test.p2')
+
+        # Byte 0 holds the enum, byte 1 holds the booleans.
+        self.assertEqual(p1.layout(), [(0, 0), (0x5, 0x5)])
+        self.assertEqual(p2.layout(), [(0xff, 2), (0x7, 0x5)])
@@ -3,7 +3,7 @@ from unittest import TestCase
 from doctest import DocTestSuite
 from . import typevar
 from .typevar import TypeSet, TypeVar
-from base.types import i32
+from base.types import i32, iflags
 
 
 def load_tests(loader, tests, ignore):
@@ -54,7 +54,7 @@ class TestTypeVar(TestCase):
         with self.assertRaises(AssertionError):
             x.half_width()
 
-        x2 = TypeVar('x2', 'i16 and up', ints=(16, 64))
+        x2 = TypeVar('x2', 'i16 and up', ints=(16, 128))
         with self.assertRaises(AssertionError):
             x2.double_width()
         self.assertEqual(str(x2.half_width()), '`half_width(x2)`')
@@ -68,6 +68,16 @@ class TestTypeVar(TestCase):
         with self.assertRaises(AssertionError):
             x3.half_width()
 
+        x4 = TypeVar('x4', 'all ints', ints=True, simd=True)
+        with self.assertRaises(AssertionError):
+            x4.half_vector()
+        with self.assertRaises(AssertionError):
+            x4.double_vector()
+
+        x5 = TypeVar('x5', 'vectors', ints=True, scalars=False, simd=(2, 64))
+        self.assertEqual(str(x5.half_vector()), '`half_vector(x5)`')
+        self.assertEqual(x5.double_vector().rust_expr(), 'x5.double_vector()')
+
     def test_singleton(self):
         x = TypeVar.singleton(i32)
         self.assertEqual(str(x), '`i32`')
@@ -82,3 +92,11 @@ class TestTypeVar(TestCase):
         self.assertEqual(x.type_set.max_int, 32)
         self.assertEqual(x.type_set.min_lanes, 4)
         self.assertEqual(x.type_set.max_lanes, 4)
+
+        x = TypeVar.singleton(iflags)
+        self.assertEqual(str(x), '`iflags`')
+        self.assertIs(x.singleton_type, iflags)
+        self.assertEqual(x.type_set.min_int, None)
+        self.assertEqual(x.type_set.min_bool, None)
+        self.assertEqual(x.type_set.min_lanes, 1)
+        self.assertEqual(x.type_set.max_lanes, 1)
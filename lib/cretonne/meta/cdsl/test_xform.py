@@ -57,3 +57,19 @@ class TestXForm(TestCase):
         dst = Rtl(a << iadd(x, y))
         with self.assertRaisesRegexp(AssertionError, "'a' multiply defined"):
             XForm(src, dst)
+
+    def test_combine(self):
+        src = Rtl(
+                c << iconst(y),
+                a << iadd(x, c))
+        dst = Rtl(a << iadd_imm(x, y))
+        XForm(src, dst).verify_combine()
+
+    def test_combine_unused(self):
+        # The inner instruction doesn't feed into the root.
+        src = Rtl(
+                c << iconst(y),
+                a << iadd(x, x))
+        dst = Rtl(a << iadd_imm(x, y))
+        with self.assertRaisesRegexp(AssertionError, "c is not used"):
+            XForm(src, dst).verify_combine()
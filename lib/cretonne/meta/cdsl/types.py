@@ -23,6 +23,9 @@ class ValueType(object):
     # List of all the scalar types.
     all_scalars = list()  # type: List[ValueType]
 
+    # List of all the special types (neither scalars nor vectors).
+    all_special_types = list()  # type: List[ValueType]
+
     def __init__(self, name, membytes, doc):
         # type: (str, int, str) -> None
         self.name = name
@@ -84,6 +87,27 @@ class ScalarType(ValueType):
             return v
 
 
+class SpecialType(ValueType):
+    """
+    A concrete special type that is neither a scalar nor a vector.
+
+    Special types can't be SIMD lanes, and they can't be loaded or stored.
+    They are numbered from 0xf0 so they don't collide with the scalar and
+    vector type numbers.
+    """
+
+    def __init__(self, name, doc):
+        # type: (str, str) -> None
+        super(SpecialType, self).__init__(name, membytes=0, doc=doc)
+        ValueType.all_special_types.append(self)
+        self.number = 0xef + len(ValueType.all_special_types)
+        assert self.number <= 0xff, 'Too many special types'
+
+    def __repr__(self):
+        # type: () -> str
+        return 'SpecialType({})'.format(self.name)
+
+
 class VectorType(ValueType):
     """
     A concrete SIMD vector type.
@@ -160,3 +184,45 @@ class BoolType(ScalarType):
     def __repr__(self):
         # type: () -> str
         return 'BoolType(bits={})'.format(self.bits)
+
+
+class RefType(ScalarType):
+    """
+    A concrete scalar reference type.
+
+    References are opaque pointers into a garbage collected heap. They can't
+    be used as SIMD lanes.
+    """
+
+    def __init__(self, bits):
+        # type: (int) -> None
+        assert bits > 0, 'RefType must have positive number of bits'
+        super(RefType, self).__init__(
+                name='r{:d}'.format(bits),
+                membytes=bits // 8,
+                doc="A {}-bit reference type.".format(bits))
+        self.bits = bits
+
+    def __repr__(self):
+        # type: () -> str
+        return 'RefType(bits={})'.format(self.bits)
+
+    def by(self, lanes):
+        # type: (int) -> VectorType
+        raise AssertionError('Reference types can\'t be SIMD lanes')
+
+
+class FlagsType(SpecialType):
+    """
+    A type representing CPU flags.
+
+    Flags can't be stored in memory.
+    """
+
+    def __init__(self, name, doc):
+        # type: (str, str) -> None
+        super(FlagsType, self).__init__(name, doc)
+
+    def __repr__(self):
+        # type: () -> str
+        return 'FlagsType({})'.format(self.name)
@@ -18,6 +18,7 @@ except ImportError:
 
 MAX_LANES = 256
 MAX_BITS = 64
+MAX_INT_BITS = 128
 
 
 def int_log2(x):
@@ -85,8 +86,9 @@ class TypeSet(object):
 
     - The permitted range of vector lanes, where 1 indicates a scalar type.
     - The permitted range of integer types.
-    - The permitted range of floating point types, and
-    - The permitted range of boolean types.
+    - The permitted range of floating point types,
+    - The permitted range of boolean types, and
+    - The permitted range of reference types.
 
     The ranges are inclusive from smallest bit-width to largest bit-width.
 
@@ -98,17 +100,19 @@ class TypeSet(object):
     Passing `True` instead of a range selects all available scalar types:
 
     >>> TypeSet(ints=True)
-    TypeSet(lanes=(1, 1), ints=(8, 64))
+    TypeSet(lanes=(1, 1), ints=(8, 128))
     >>> TypeSet(floats=True)
     TypeSet(lanes=(1, 1), floats=(32, 64))
     >>> TypeSet(bools=True)
     TypeSet(lanes=(1, 1), bools=(1, 64))
+    >>> TypeSet(refs=True)
+    TypeSet(lanes=(1, 1), refs=(32, 64))
 
     Similarly, passing `True` for the lanes selects all possible scalar and
     vector types:
 
     >>> TypeSet(lanes=True, ints=True)
-    TypeSet(lanes=(1, 256), ints=(8, 64))
+    TypeSet(lanes=(1, 256), ints=(8, 128))
 
     :param lanes: `(min, max)` inclusive range of permitted vector lane counts.
     :param ints: `(min, max)` inclusive range of permitted scalar integer
@@ -117,23 +121,28 @@ class TypeSet(object):
                    point widths.
     :param bools: `(min, max)` inclusive range of permitted scalar boolean
                   widths.
+    :param refs: `(min, max)` inclusive range of permitted scalar reference
+                 widths.
     """
 
-    def __init__(self, lanes=None, ints=None, floats=None, bools=None):
-        # type: (BoolInterval, BoolInterval, BoolInterval, BoolInterval) -> None # noqa
+    def __init__(
+            self, lanes=None, ints=None, floats=None, bools=None, refs=None):
+        # type: (BoolInterval, BoolInterval, BoolInterval, BoolInterval, BoolInterval) -> None # noqa
         self.min_lanes, self.max_lanes = decode_interval(
                 lanes, (1, MAX_LANES), 1)
-        self.min_int, self.max_int = decode_interval(ints, (8, MAX_BITS))
+        self.min_int, self.max_int = decode_interval(ints, (8, MAX_INT_BITS))
         self.min_float, self.max_float = decode_interval(floats, (32, 64))
         self.min_bool, self.max_bool = decode_interval(bools, (1, MAX_BITS))
+        self.min_ref, self.max_ref = decode_interval(refs, (32, 64))
 
     def typeset_key(self):
-        # type: () -> Tuple[int, int, int, int, int, int, int, int]
+        # type: () -> Tuple[int, int, int, int, int, int, int, int, int, int]
         """Key tuple used for hashing and equality."""
         return (self.min_lanes, self.max_lanes,
                 self.min_int, self.max_int,
                 self.min_float, self.max_float,
-                self.min_bool, self.max_bool)
+                self.min_bool, self.max_bool,
+                self.min_ref, self.max_ref)
 
     def __hash__(self):
         # type: () -> int
@@ -154,12 +163,14 @@ class TypeSet(object):
             s += ', floats=({}, {})'.format(self.min_float, self.max_float)
         if self.min_bool is not None:
             s += ', bools=({}, {})'.format(self.min_bool, self.max_bool)
+        if self.min_ref is not None:
+            s += ', refs=({}, {})'.format(self.min_ref, self.max_ref)
         return s + ')'
 
     def emit_fields(self, fmt):
         """Emit field initializers for this typeset."""
         fmt.comment(repr(self))
-        fields = ('lanes', 'int', 'float', 'bool')
+        fields = ('lanes', 'int', 'float', 'bool', 'ref')
         for field in fields:
             min_val = getattr(self, 'min_' + field)
             max_val = getattr(self, 'max_' + field)
@@ -206,6 +217,10 @@ class TypeSet(object):
                 (self.min_bool, self.max_bool),
                 (other.min_bool, other.max_bool))
 
+        self.min_ref, self.max_ref = intersect(
+                (self.min_ref, self.max_ref),
+                (other.min_ref, other.max_ref))
+
         return self
 
 
@@ -225,6 +240,7 @@ class TypeVar(object):
     :param floats: Allow all floating point base types, or `(min, max)`
                    bit-range.
     :param bools: Allow all boolean base types, or `(min, max)` bit-range.
+    :param refs: Allow all reference base types, or `(min, max)` bit-range.
     :param scalars: Allow type variable to assume scalar types.
     :param simd: Allow type variable to assume vector types, or `(min, max)`
                  lane count range.
@@ -232,10 +248,10 @@ class TypeVar(object):
 
     def __init__(
             self, name, doc,
-            ints=False, floats=False, bools=False,
+            ints=False, floats=False, bools=False, refs=False,
             scalars=True, simd=False,
             base=None, derived_func=None):
-        # type: (str, str, BoolInterval, BoolInterval, BoolInterval, bool, BoolInterval, TypeVar, str) -> None # noqa
+        # type: (str, str, BoolInterval, BoolInterval, BoolInterval, BoolInterval, bool, BoolInterval, TypeVar, str) -> None # noqa
         self.name = name
         self.__doc__ = doc
         self.singleton_type = None  # type: types.ValueType
@@ -253,7 +269,8 @@ class TypeVar(object):
                     lanes=lanes,
                     ints=ints,
                     floats=floats,
-                    bools=bools)
+                    bools=bools,
+                    refs=refs)
 
     @staticmethod
     def singleton(typ):
@@ -265,10 +282,17 @@ class TypeVar(object):
         elif isinstance(typ, types.ScalarType):
             scalar = typ
             lanes = (1, 1)
+        else:
+            # Special types like `iflags` aren't described by a type set. The
+            # type variable gets an empty type set with only the singleton.
+            assert isinstance(typ, types.SpecialType)
+            scalar = None
+            lanes = (1, 1)
 
         ints = None
         floats = None
         bools = None
+        refs = None
 
         if isinstance(scalar, types.IntType):
             ints = (scalar.bits, scalar.bits)
@@ -276,10 +300,12 @@ class TypeVar(object):
             floats = (scalar.bits, scalar.bits)
         elif isinstance(scalar, types.BoolType):
             bools = (scalar.bits, scalar.bits)
+        elif isinstance(scalar, types.RefType):
+            refs = (scalar.bits, scalar.bits)
 
         tv = TypeVar(
                 typ.name, 'typeof({})'.format(typ),
-                ints, floats, bools, simd=lanes)
+                ints, floats, bools, refs, simd=lanes)
         tv.singleton_type = typ
         return tv
 
@@ -315,6 +341,8 @@ class TypeVar(object):
     ASBOOL = 'as_bool'
     HALFWIDTH = 'half_width'
     DOUBLEWIDTH = 'double_width'
+    HALFVECTOR = 'half_vector'
+    DOUBLEVECTOR = 'double_vector'
 
     @staticmethod
     def derived(base, derived_func):
@@ -388,7 +416,8 @@ class TypeVar(object):
         if not self.is_derived:
             ts = self.type_set
             if ts.max_int:
-                assert ts.max_int < MAX_BITS, "Can't double all integer types."
+                assert ts.max_int < MAX_INT_BITS, \
+                        "Can't double all integer types."
             if ts.max_float:
                 assert ts.max_float < MAX_BITS, "Can't double all float types."
             if ts.max_bool:
@@ -396,6 +425,30 @@ class TypeVar(object):
 
         return TypeVar.derived(self, self.DOUBLEWIDTH)
 
+    def half_vector(self):
+        # type: () -> TypeVar
+        """
+        Return a derived type variable that has half the number of vector lanes
+        as this one, with the same lane type.
+        """
+        if not self.is_derived:
+            ts = self.type_set
+            assert ts.min_lanes > 1, "Can't halve a scalar type"
+
+        return TypeVar.derived(self, self.HALFVECTOR)
+
+    def double_vector(self):
+        # type: () -> TypeVar
+        """
+        Return a derived type variable that has twice the number of vector
+        lanes as this one, with the same lane type.
+        """
+        if not self.is_derived:
+            ts = self.type_set
+            assert ts.max_lanes < MAX_LANES, "Can't double 256 lanes."
+
+        return TypeVar.derived(self, self.DOUBLEVECTOR)
+
     def free_typevar(self):
         # type: () -> TypeVar
         """
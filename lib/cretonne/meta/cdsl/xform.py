@@ -205,6 +205,42 @@ class XForm(object):
                 raise AssertionError(
                         '{} not defined in dest pattern'.format(d))
 
+    def verify_combine(self):
+        # type: () -> None
+        """
+        Verify that this is a valid instruction combining XForm.
+
+        - The last instruction in the source pattern is the root which gets
+          replaced. All of its results must be defined in the destination
+          pattern.
+        - The other source instructions must have a single result which is
+          used by a later source instruction, forming a tree. They can't be
+          branches or have side effects.
+        - The destination pattern can't redefine values from the non-root
+          source instructions.
+        """
+        root = self.src.rtl[-1]
+        inner = self.src.rtl[:-1]
+        for d in root.defs:
+            if not d.is_output():
+                raise AssertionError(
+                        '{} not defined in dest pattern'.format(d))
+        for i, node in enumerate(inner):
+            inst = node.expr.inst
+            if inst.is_branch or inst.is_terminator or inst.can_trap:
+                raise AssertionError(
+                        '{} is not a pure instruction'.format(inst))
+            if len(node.defs) != 1 or len(inst.value_results) != 1:
+                raise AssertionError(
+                        '{} must define a single value'.format(node))
+            d = node.defs[0]
+            if d.is_output():
+                raise AssertionError(
+                        '{} is redefined in dest pattern'.format(d))
+            if not any(d in later.expr.args
+                       for later in self.src.rtl[i + 1:]):
+                raise AssertionError('{} is not used'.format(d))
+
     def _infer_types(self, rtl):
         # type: (Rtl) -> None
         """Assign type variables to all value variables used in `rtl`."""
@@ -285,3 +321,16 @@ class XFormGroup(object):
         xform = XForm(Rtl(src), dst)
         xform.verify_legalize()
         self.xforms.append(xform)
+
+    def combine(self, src, dst):
+        # type: (Rtl, Rtl) -> None
+        """
+        Add an instruction combining pattern to this group.
+
+        :param src: `Rtl` list of instructions to match. The last one is the
+                    root of the matched tree.
+        :param dst: `Rtl` list of replacement instructions.
+        """
+        xform = XForm(src, dst)
+        xform.verify_combine()
+        self.xforms.append(xform)
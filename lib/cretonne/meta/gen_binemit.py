@@ -0,0 +1,54 @@
+"""
+Generate binary emission code for each ISA.
+
+Each encoding recipe has a hand-written function in the ISA's `binemit.rs`
+module that emits the machine code bits for an instruction after registers
+have been allocated. The function for a recipe named `Op1rr` is called
+`recipe_op1rr`.
+
+We generate an `emit_inst()` function which dispatches on the recipe number of
+the instruction's encoding. The generated file is only included by the ISAs
+that can emit machine code.
+"""
+from __future__ import absolute_import
+import srcgen
+
+try:
+    from typing import Sequence  # noqa
+    from cdsl.isa import TargetISA  # noqa
+except ImportError:
+    pass
+
+
+def gen_isa(isa, fmt):
+    # type: (TargetISA, srcgen.Formatter) -> None
+    """
+    Generate the `emit_inst()` dispatch function for `isa`.
+    """
+    fmt.doc_comment(
+            'Emit binary machine code for `inst` for the {} ISA.'
+            .format(isa.name))
+    fmt.doc_comment('')
+    fmt.doc_comment(
+            'The location of the instruction operands is given by '
+            '`func.locations`, as')
+    fmt.doc_comment('modified by the register diversions in `divert`.')
+    with fmt.indented(
+            'pub fn emit_inst<CS: CodeSink + ?Sized>(func: &Function, '
+            'inst: Inst, divert: &mut RegDiversions, sink: &mut CS) {',
+            '}'):
+        with fmt.indented('match func.encodings[inst].recipe() {', '}'):
+            for recipe in isa.all_recipes:
+                fmt.format(
+                        '{} => recipe_{}(func, inst, divert, sink),',
+                        recipe.number, recipe.name.lower())
+            fmt.line('_ => bad_encoding(func, inst),')
+        fmt.line('divert.apply(&func.dfg[inst]);')
+
+
+def generate(isas, out_dir):
+    # type: (Sequence[TargetISA], str) -> None
+    for isa in isas:
+        fmt = srcgen.Formatter()
+        gen_isa(isa, fmt)
+        fmt.update_file('binemit-{}.rs'.format(isa.name), out_dir)
@@ -0,0 +1,103 @@
+"""
+Generate instruction combining patterns.
+
+The patterns defined in the `base.combine` module match a tree of instructions
+rooted at the last instruction of the source pattern. We generate a Rust
+function for each pattern which tries to match it at the instruction pointed to
+by a `Cursor`, and a `combine()` function which tries all the patterns whose
+root matches the opcode of the current instruction.
+"""
+from __future__ import absolute_import
+from srcgen import Formatter
+from base import combine
+from gen_legalizer import unwrap_inst, emit_dst_inst
+
+try:
+    from typing import Dict, List  # noqa
+    from cdsl.instructions import Instruction  # noqa
+    from cdsl.xform import XForm, XFormGroup  # noqa
+except ImportError:
+    pass
+
+
+def gen_xform(name, xform, fmt):
+    # type: (str, XForm, Formatter) -> None
+    """
+    Emit a function that tries to apply `xform` at the instruction pointed to
+    by `pos`.
+
+    Return `true` if the pattern matched and the root instruction was
+    replaced.
+    """
+    fmt.doc_comment(
+            'Try to combine `{}`.'.format('; '.join(map(str, xform.src.rtl))))
+    fmt.line('#[allow(unused_variables,unused_assignments,unused_parens)]')
+    with fmt.indented(
+            'fn {}(pos: &mut Cursor, dfg: &mut DataFlowGraph, '
+            'uses: &EntityMap<Value, u32>) -> bool {{'.format(name),
+            '}'):
+        fmt.line('let inst = pos.current_inst().expect("need instruction");')
+        root = xform.src.rtl[-1]
+        unwrap_inst('inst', root, fmt)
+
+        # Match the rest of the tree, starting from the root. Every inner
+        # node is used by a later node which has already been unwrapped.
+        for node in reversed(xform.src.rtl[:-1]):
+            d = node.defs[0]
+            fmt.comment('Match {}'.format(node))
+            with fmt.indented(
+                    'let inst_{} = match dfg.value_def({}) {{'.format(d, d),
+                    '};'):
+                fmt.line(
+                        'ValueDef::Res(def, 0) if uses[{}] == 1 && '
+                        'dfg[def].opcode() == Opcode::{} => def,'
+                        .format(d, node.expr.inst.camel_name))
+                fmt.line('_ => return false,')
+            unwrap_inst('inst_{}'.format(d), node, fmt)
+
+        # Emit the destination pattern.
+        for dst in xform.dst.rtl:
+            emit_dst_inst(dst, fmt)
+        fmt.line('true')
+
+
+def gen_xform_group(xgrp, fmt):
+    # type: (XFormGroup, Formatter) -> None
+    # Group the patterns by the opcode of their root instruction.
+    by_root = dict()  # type: Dict[Instruction, List[str]]
+    roots = list()  # type: List[Instruction]
+    for n, xform in enumerate(xgrp.xforms):
+        name = '{}_{}'.format(xgrp.name, n)
+        gen_xform(name, xform, fmt)
+        fmt.line()
+        inst = xform.src.rtl[-1].expr.inst
+        if inst not in by_root:
+            by_root[inst] = list()
+            roots.append(inst)
+        by_root[inst].append(name)
+
+    fmt.doc_comment(
+            'Try to combine the instruction pointed to by `pos` with the '
+            'instructions')
+    fmt.doc_comment('defining its arguments.')
+    with fmt.indented(
+            'fn ' + xgrp.name +
+            '(pos: &mut Cursor, dfg: &mut DataFlowGraph, '
+            'uses: &EntityMap<Value, u32>) -> bool {',
+            '}'):
+        fmt.line('let inst = pos.current_inst().expect("need instruction");')
+        with fmt.indented('match dfg[inst].opcode() {', '}'):
+            for inst in roots:
+                fmt.line(
+                        'Opcode::{} => {},'.format(
+                            inst.camel_name,
+                            ' || '.join(
+                                '{}(pos, dfg, uses)'.format(name)
+                                for name in by_root[inst])))
+            fmt.line('_ => false,')
+
+
+def generate(isas, out_dir):
+    fmt = Formatter()
+    gen_xform_group(combine.combine, fmt)
+    fmt.update_file('combine.rs', out_dir)
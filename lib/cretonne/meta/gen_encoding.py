@@ -7,7 +7,7 @@ function which determines if a given instruction is legal, and if so, it's
 
 The `encode` function doesn't actually generate the binary machine bits. Each
 recipe has a corresponding hand-written function to do that after registers
-are allocated.
+are allocated. See `gen_binemit.py`.
 
 This is the information available to us:
 
@@ -56,11 +56,12 @@ from unique_table import UniqueSeqTable
 from collections import OrderedDict, defaultdict
 import math
 import itertools
-from cdsl.registers import RegClass, Register
+from cdsl.registers import RegClass, Register, Stack
+from cdsl.formats import instruction_context
 
 try:
     from typing import Sequence  # noqa
-    from cdsl.isa import TargetISA, OperandConstraint  # noqa
+    from cdsl.isa import TargetISA, OperandConstraint, EncRecipe  # noqa
 except ImportError:
     pass
 
@@ -75,20 +76,28 @@ def emit_instp(instp, fmt):
     """
     iform = instp.predicate_context()
 
+    # A predicate that only checks value types applies to any instruction
+    # format, so it doesn't need a pattern match.
+    if iform is instruction_context:
+        with fmt.indented('{} => {{'.format(instp.number), '}'):
+            fmt.line('return {};'.format(instp.rust_predicate(0)))
+        return
+
     # Which fields do we need in the InstructionData pattern match?
     if iform.boxed_storage:
-        fields = 'ref data'
+        fields = 'ref data, '
     else:
         # Collect the leaf predicates
         leafs = set()
         instp.predicate_leafs(leafs)
-        # All the leafs are FieldPredicate instances. Here we just care about
-        # the field names.
-        fields = ', '.join(sorted(set(p.field.name for p in leafs)))
+        # The leafs are FieldPredicate or TypePredicate instances. Here we
+        # just care about the field names.
+        fields = ''.join(sorted(set(
+            p.field.name + ', ' for p in leafs if hasattr(p, 'field'))))
 
     with fmt.indented('{} => {{'.format(instp.number), '}'):
         with fmt.indented(
-                'if let InstructionData::{} {{ {}, .. }} = *inst {{'
+                'if let InstructionData::{} {{ {}.. }} = *inst {{'
                 .format(iform.name, fields), '}'):
             fmt.line('return {};'.format(instp.rust_predicate(0)))
 
@@ -101,14 +110,16 @@ def emit_instps(instps, fmt):
     if not instps:
         # If the ISA has no predicates, just emit a stub.
         with fmt.indented(
-                'pub fn check_instp(_: &InstructionData, _: u16) ' +
-                '-> bool {', '}'):
+                'pub fn check_instp(_: &InstructionData, _: &DataFlowGraph, ' +
+                '_: u16) -> bool {', '}'):
             fmt.line('unimplemented!()')
         return
 
+    # Type predicates use `dfg`, but not all ISAs have them.
+    fmt.line('#[allow(unused_variables)]')
     with fmt.indented(
-            'pub fn check_instp(inst: &InstructionData, instp_idx: u16) ' +
-            '-> bool {', '}'):
+            'pub fn check_instp(inst: &InstructionData, ' +
+            'dfg: &DataFlowGraph, instp_idx: u16) -> bool {', '}'):
         # The matches emitted by `emit_instp` need this.
         fmt.line('use ir::instructions::InstructionFormat;')
         with fmt.indented('match instp_idx {', '}'):
@@ -282,7 +293,7 @@ class Level2Table(object):
         level2_doc[self.hash_table_offset].append(
                 '{:06x}: {}, {} entries'.format(
                     self.hash_table_offset,
-                    self.ty.name,
+                    self.ty.name if self.ty else 'typeless',
                     self.hash_table_len))
         level2_hashtables.extend(hash_table)
 
@@ -378,7 +389,7 @@ def emit_level1_hashtable(cpumode, level1, offt, fmt):
     """
     hash_table = compute_quadratic(
             level1.tables.values(),
-            lambda level2: level2.ty.number)
+            lambda level2: level2.ty.number if level2.ty else 0)
 
     with fmt.indented(
             'pub static LEVEL1_{}: [Level1Entry<{}>; {}] = ['
@@ -391,7 +402,7 @@ def emit_level1_hashtable(cpumode, level1, offt, fmt):
                         'Level1Entry ' +
                         '{{ ty: types::{}, log2len: {}, offset: {:#08x} }},'
                         .format(
-                            level2.ty.name.upper(),
+                            level2.ty.name.upper() if level2.ty else 'VOID',
                             l2l,
                             level2.hash_table_offset))
             else:
@@ -433,7 +444,8 @@ def emit_recipe_constraints(isa, fmt):
     Emit a table of encoding recipe operand constraints keyed by recipe number.
 
     These are used by the register allocator to pick registers that can be
-    properly encoded.
+    properly encoded. The `fixed_ins`, `fixed_outs`, and `tied_ops` flags
+    summarize the operand constraints so they can be skipped quickly.
     """
     with fmt.indented(
             'pub static RECIPE_CONSTRAINTS: [RecipeConstraints; {}] = ['
@@ -441,14 +453,31 @@ def emit_recipe_constraints(isa, fmt):
         for r in isa.all_recipes:
             fmt.comment(r.name)
             with fmt.indented('RecipeConstraints {', '},'):
-                emit_operand_constraints(r.ins, 'ins', fmt)
-                emit_operand_constraints(r.outs, 'outs', fmt)
-
-
-def emit_operand_constraints(seq, field, fmt):
-    # type: (Sequence[OperandConstraint], str, srcgen.Formatter) -> None
+                emit_operand_constraints(r, r.ins, 'ins', fmt)
+                emit_operand_constraints(r, r.outs, 'outs', fmt)
+                fmt.format(
+                        'clobbers_flags: {},',
+                        'true' if r.clobbers_flags else 'false')
+                fmt.format(
+                        'fixed_ins: {},',
+                        'true' if any(isinstance(c, Register) for c in r.ins)
+                        else 'false')
+                fmt.format(
+                        'fixed_outs: {},',
+                        'true' if any(isinstance(c, Register) for c in r.outs)
+                        else 'false')
+                fmt.format(
+                        'tied_ops: {},',
+                        'true' if any(isinstance(c, int) for c in r.outs)
+                        else 'false')
+
+
+def emit_operand_constraints(recipe, seq, field, fmt):
+    # type: (EncRecipe, Sequence[OperandConstraint], str, srcgen.Formatter) -> None  # noqa
     """
     Emit a struct field initializer for an array of operand constraints.
+
+    Integer constraints in `seq` refer to the value operands of `recipe`.
     """
     if len(seq) == 0:
         fmt.line('{}: &[],'.format(field))
@@ -464,11 +493,51 @@ def emit_operand_constraints(seq, field, fmt):
                             'kind: ConstraintKind::FixedReg({}),'
                             .format(cons.unit))
                     fmt.line('regclass: {},'.format(cons.regclass))
+                elif isinstance(cons, Stack):
+                    fmt.line('kind: ConstraintKind::Stack,')
+                    fmt.line('regclass: {},'.format(cons.regclass))
+                elif isinstance(cons, int):
+                    # This is a tied output constraint.
+                    assert field == 'outs', 'Input operands can\'t be tied'
+                    tied = recipe.ins[cons]
+                    assert isinstance(tied, (RegClass, Register)), \
+                        'Result tied to a {} operand'.format(tied)
+                    fmt.line('kind: ConstraintKind::Tied({}),'.format(cons))
+                    if isinstance(tied, RegClass):
+                        fmt.line('regclass: {},'.format(tied))
+                    else:
+                        fmt.line('regclass: {},'.format(tied.regclass))
                 else:
                     raise AssertionError(
                             'Unsupported constraint {}'.format(cons))
 
 
+def emit_recipe_sizing(isa, fmt):
+    # type: (TargetISA, srcgen.Formatter) -> None
+    """
+    Emit a table of encoding recipe code size information keyed by recipe
+    number.
+
+    These are used by branch relaxation to compute code offsets and branch
+    ranges.
+    """
+    with fmt.indented(
+            'pub static RECIPE_SIZING: [RecipeSizing; {}] = ['
+            .format(len(isa.all_recipes)), '];'):
+        for r in isa.all_recipes:
+            fmt.comment(r.name)
+            with fmt.indented('RecipeSizing {', '},'):
+                fmt.format('bytes: {},', r.size)
+                if r.branch_range:
+                    origin, bits = r.branch_range
+                    fmt.format(
+                            'branch_range: '
+                            'Some(BranchRange {{ origin: {}, bits: {} }}),',
+                            origin, bits)
+                else:
+                    fmt.line('branch_range: None,')
+
+
 def gen_isa(isa, fmt):
     # First assign numbers to relevant instruction predicates and generate the
     # check_instp() function..
@@ -505,6 +574,7 @@ def gen_isa(isa, fmt):
 
     emit_recipe_names(isa, fmt)
     emit_recipe_constraints(isa, fmt)
+    emit_recipe_sizing(isa, fmt)
 
 
 def generate(isas, out_dir):
@@ -6,12 +6,12 @@ import srcgen
 import constant_hash
 from unique_table import UniqueTable, UniqueSeqTable
 from cdsl import camel_case
-from cdsl.operands import ImmediateKind
+from cdsl.operands import ImmediateKind, VALUE, VARIABLE_ARGS
 import cdsl.types
 from cdsl.formats import InstructionFormat
 
 from cdsl.instructions import Instruction  # noqa
-from cdsl.operands import Operand  # noqa
+from cdsl.operands import Operand, OperandKind  # noqa
 from cdsl.typevar import TypeVar  # noqa
 
 # The typing module is only required by mypy, and we don't use these imports
@@ -52,31 +52,136 @@ def gen_formats(fmt):
                              .format(f.name, f.name))
     fmt.line()
 
+    gen_operand_kinds(fmt)
+    gen_format_queries(fmt)
+
+
+def imm_kinds():
+    # type: () -> List[OperandKind]
+    """
+    Collect all the non-value operand kinds used by instruction formats in
+    order of first appearance.
+    """
+    kinds = list()  # type: List[OperandKind]
+    for f in InstructionFormat.all_formats:
+        for k in f.kinds:
+            if k is VALUE or k is VARIABLE_ARGS:
+                continue
+            if k not in kinds:
+                kinds.append(k)
+    return kinds
+
+
+def gen_operand_kinds(fmt):
+    # type: (srcgen.Formatter) -> None
+    """
+    Generate an enumeration of the immediate operand kinds.
+
+    The enum variants are named after the Rust types used to represent the
+    operands in `InstructionData`. Primitive types like `bool` are capitalized.
+    """
+    fmt.doc_comment('The kind of an immediate or entity reference field in an')
+    fmt.doc_comment('instruction format.')
+    fmt.line('#[derive(Copy, Clone, PartialEq, Eq, Debug)]')
+    with fmt.indented('pub enum OperandKind {', '}'):
+        for k in imm_kinds():
+            fmt.doc_comment(k.__doc__.strip().split('\n')[0])
+            fmt.line(camel_case(k.rust_type) + ',')
+    fmt.line()
+
+
+def gen_format_queries(fmt):
+    # type: (srcgen.Formatter) -> None
+    """
+    Generate `impl InstructionFormat` with methods describing the operands of
+    each format.
+    """
+    with fmt.indented('impl InstructionFormat {', '}'):
+        fmt.doc_comment(
+                """
+                Get the number of fixed value operands in this format, not
+                counting any variable arguments.
+                """)
+        with fmt.indented(
+                'pub fn num_value_operands(self) -> usize {', '}'):
+            with fmt.indented('match self {', '}'):
+                for f in InstructionFormat.all_formats:
+                    fmt.format(
+                            'InstructionFormat::{} => {},',
+                            f.name, len(f.value_operands))
+
+        fmt.doc_comment(
+                'Does this format have a variable number of value operands?')
+        with fmt.indented('pub fn has_value_list(self) -> bool {', '}'):
+            with fmt.indented('match self {', '}'):
+                for f in InstructionFormat.all_formats:
+                    fmt.format(
+                            'InstructionFormat::{} => {},',
+                            f.name,
+                            'true' if VARIABLE_ARGS in f.kinds else 'false')
+
+        fmt.doc_comment(
+                'Can this format produce more than one result?')
+        with fmt.indented('pub fn multiple_results(self) -> bool {', '}'):
+            with fmt.indented('match self {', '}'):
+                for f in InstructionFormat.all_formats:
+                    fmt.format(
+                            'InstructionFormat::{} => {},',
+                            f.name,
+                            'true' if f.multiple_results else 'false')
+
+        fmt.doc_comment(
+                """
+                Get the immediate and entity reference fields in this format,
+                in operand order.
+                """)
+        with fmt.indented(
+                "pub fn imm_fields(self) -> &'static [FormatField] {", '}'):
+            with fmt.indented('match self {', '}'):
+                for f in InstructionFormat.all_formats:
+                    fields = ', '.join(
+                            'FormatField {{ member: "{}", '
+                            'kind: OperandKind::{} }}'
+                            .format(m, camel_case(k.rust_type))
+                            for m, k in zip(f.members, f.kinds)
+                            if k is not VALUE and k is not VARIABLE_ARGS)
+                    fmt.format(
+                            'InstructionFormat::{} => &[{}],', f.name, fields)
+    fmt.line()
+
 
 def gen_arguments_method(fmt, is_mut):
     # type: (srcgen.Formatter, bool) -> None
     method = 'arguments'
     mut = ''
     rslice = 'ref_slice'
+    as_slice = 'as_slice'
+    split_at = 'split_at'
     if is_mut:
         method += '_mut'
         mut = 'mut '
         rslice += '_mut'
+        as_slice = 'as_mut_slice'
+        split_at = 'split_at_mut'
 
     with fmt.indented(
-            'pub fn {f}(&{m}self) -> [&{m}[Value]; 2] {{'
+            "pub fn {f}<'a>(&'a {m}self, pool: &'a {m}ValueListPool) -> "
+            "[&'a {m}[Value]; 2] {{"
             .format(f=method, m=mut), '}'):
         with fmt.indented('match *self {', '}'):
             for f in InstructionFormat.all_formats:
                 n = 'InstructionData::' + f.name
-                has_varargs = cdsl.operands.VARIABLE_ARGS in f.kinds
-                # Formats with both fixed and variable arguments delegate to
-                # the data struct. We need to work around borrow checker quirks
-                # when extracting two mutable references.
-                if has_varargs and len(f.value_operands) > 0:
-                    fmt.line(
-                        '{} {{ ref {}data, .. }} => data.{}(),'
-                        .format(n, mut, method))
+                # The value list holds the fixed arguments followed by the
+                # variable arguments.
+                if f.has_value_list:
+                    with fmt.indented(
+                            '{} {{ ref {}args, .. }} => {{'
+                            .format(n, mut), '}'):
+                        fmt.line(
+                            'let (fixed, varargs) = args.{}(pool).{}({});'
+                            .format(as_slice, split_at,
+                                    len(f.value_operands)))
+                        fmt.line('[fixed, varargs]')
                     continue
                 # Fixed args.
                 if len(f.value_operands) == 0:
@@ -96,16 +201,9 @@ def gen_arguments_method(fmt, is_mut):
                     else:
                         capture = 'ref {}args, '.format(mut)
                         arg = 'args'
-                # Varargs.
-                if cdsl.operands.VARIABLE_ARGS in f.kinds:
-                    varg = '&{}data.varargs'.format(mut)
-                    capture = 'ref {}data, '.format(mut)
-                else:
-                    varg = '&{}[]'.format(mut)
-
                 fmt.line(
-                        '{} {{ {} .. }} => [{}, {}],'
-                        .format(n, capture, arg, varg))
+                        '{} {{ {} .. }} => [{}, &{}[]],'
+                        .format(n, capture, arg, mut))
 
 
 def gen_instruction_data_impl(fmt):
@@ -121,7 +219,9 @@ def gen_instruction_data_impl(fmt):
     - `pub fn second_result(&self) -> Option<Value>`
     - `pub fn second_result_mut<'a>(&'a mut self)
          -> Option<&'a mut PackedOption<Value>>`
-    - `pub fn arguments(&self) -> (&[Value], &[Value])`
+    - `pub fn typevar_operand(&self, pool) -> Option<Value>`
+    - `pub fn arguments(&self, pool) -> [&[Value]; 2]`
+    - `pub fn arguments_mut(&mut self, pool) -> [&mut [Value]; 2]`
     """
 
     # The `opcode` and `first_type` methods simply read the `opcode` and `ty`
@@ -191,12 +291,20 @@ def gen_instruction_data_impl(fmt):
 
         fmt.doc_comment('Get the controlling type variable operand.')
         with fmt.indented(
-                'pub fn typevar_operand(&self) -> Option<Value> {', '}'):
+                'pub fn typevar_operand(&self, pool: &ValueListPool) '
+                '-> Option<Value> {', '}'):
             with fmt.indented('match *self {', '}'):
                 for f in InstructionFormat.all_formats:
                     n = 'InstructionData::' + f.name
                     if f.typevar_operand is None:
                         fmt.line(n + ' { .. } => None,')
+                    elif f.has_value_list:
+                        # The fixed value operands are at the front of the
+                        # value list.
+                        i = f.value_operands.index(f.typevar_operand)
+                        fmt.line(
+                                n + ' {{ ref args, .. }} => args.get({}, pool),'
+                                .format(i))
                     elif len(f.value_operands) == 1:
                         # We have a single value operand called 'arg'.
                         if f.boxed_storage:
@@ -297,9 +405,22 @@ def gen_opcodes(groups, fmt):
                 'name': 'is_terminator',
                 'comment': 'True for instructions that terminate EBB.'
             },
+            {
+                'name': 'is_return',
+                'comment':
+                    'True for instructions that return from the function.'
+            },
             {
                 'name': 'can_trap',
                 'comment': 'True if instruction could trap.'
+            },
+            {
+                'name': 'can_load',
+                'comment': 'True if instruction could read from memory.'
+            },
+            {
+                'name': 'can_store',
+                'comment': 'True if instruction could write to memory.'
             }
         ]
 
@@ -436,7 +557,7 @@ def gen_type_constraints(fmt, instrs):
             use_typevar_operand = i.is_polymorphic and i.use_typevar_operand
             # Can the controlling type variable be inferred from the result?
             use_result = (fixed_results > 0 and
-                          i.outs[i.value_results[0]].typevar != ctrl_typevar)
+                          i.outs[i.value_results[0]].typevar == ctrl_typevar)
             # Are we required to use the designated operand instead of the
             # result?
             requires_typevar_operand = use_typevar_operand and not use_result
@@ -490,7 +611,10 @@ def gen_format_constructor(iform, fmt):
     """
 
     # Construct method arguments.
-    args = ['self', 'opcode: Opcode']
+    if iform.has_value_list:
+        args = ['mut self', 'opcode: Opcode']
+    else:
+        args = ['self', 'opcode: Opcode']
 
     if iform.multiple_results:
         args.append('ctrl_typevar: Type')
@@ -510,6 +634,21 @@ def gen_format_constructor(iform, fmt):
     fmt.doc_comment(str(iform))
     fmt.line('#[allow(non_snake_case)]')
     with fmt.indented('fn {} {{'.format(proto), '}'):
+        # Collect the value operands in a value list.
+        if iform.has_value_list:
+            fmt.line('let mut args = ValueList::default();')
+            with fmt.indented('{', '}'):
+                fmt.line(
+                        'let pool = '
+                        '&mut self.data_flow_graph_mut().value_lists;')
+                for idx, kind in enumerate(iform.kinds):
+                    if kind is VALUE:
+                        fmt.line('args.push(op{}, pool);'.format(idx))
+                    elif kind is VARIABLE_ARGS:
+                        fmt.line(
+                            'args.extend(op{}.iter().cloned(), pool);'
+                            .format(idx))
+
         # Generate the instruction data.
         with fmt.indented(
                 'let data = InstructionData::{} {{'.format(iform.name), '};'):
@@ -538,7 +677,9 @@ def gen_member_inits(iform, fmt):
     """
 
     # Values first.
-    if len(iform.value_operands) == 1:
+    if iform.has_value_list:
+        fmt.line('args: args,')
+    elif len(iform.value_operands) == 1:
         fmt.line('arg: op{},'.format(iform.value_operands[0]))
     elif len(iform.value_operands) > 1:
         fmt.line('args: [{}],'.format(
@@ -546,7 +687,7 @@ def gen_member_inits(iform, fmt):
 
     # Immediates and entity references.
     for idx, member in enumerate(iform.members):
-        if member:
+        if member and iform.kinds[idx] is not VARIABLE_ARGS:
             fmt.line('{}: op{},'.format(member, idx))
 
 
@@ -0,0 +1,89 @@
+"""
+Generate a machine-readable JSON description of the instruction set.
+
+The JSON file describes the opcodes, the encoding recipes, and the per-ISA
+encodings that are also used to generate the Rust tables. It is intended for
+external tools that need to inspect the instruction set programmatically, and
+it is only generated when the build script is given the `--json` option.
+"""
+from __future__ import absolute_import
+import json
+import os
+from gen_instr import collect_instr_groups
+
+try:
+    from typing import Any, Dict, List, Sequence  # noqa
+    from cdsl.isa import TargetISA, EncRecipe, Encoding  # noqa
+    from cdsl.instructions import Instruction  # noqa
+    from cdsl.operands import Operand  # noqa
+except ImportError:
+    pass
+
+
+def operand_json(op):
+    # type: (Operand) -> Dict[str, Any]
+    return {'name': op.name, 'kind': op.kind.name}
+
+
+def instruction_json(inst):
+    # type: (Instruction) -> Dict[str, Any]
+    return {
+            'name': inst.name,
+            'format': inst.format.name,
+            'doc': (inst.__doc__ or '').strip(),
+            'ins': [operand_json(op) for op in inst.ins],
+            'outs': [operand_json(op) for op in inst.outs],
+            'is_branch': inst.is_branch,
+            'is_terminator': inst.is_terminator,
+            'can_trap': inst.can_trap,
+            }
+
+
+def recipe_json(recipe):
+    # type: (EncRecipe) -> Dict[str, Any]
+    return {
+            'name': recipe.name,
+            'number': recipe.number,
+            'format': recipe.format.name,
+            }
+
+
+def predicate_json(pred):
+    # type: (Any) -> Any
+    return str(pred) if pred else None
+
+
+def encoding_json(enc):
+    # type: (Encoding) -> Dict[str, Any]
+    return {
+            'inst': enc.inst.name,
+            'types': [str(t) for t in enc.typevars],
+            'recipe': enc.recipe.name,
+            'bits': enc.encbits,
+            'instp': predicate_json(enc.instp),
+            'isap': predicate_json(enc.isap),
+            }
+
+
+def isa_json(isa):
+    # type: (TargetISA) -> Dict[str, Any]
+    return {
+            'recipes': [recipe_json(r) for r in isa.all_recipes],
+            'cpumodes': dict(
+                (cpumode.name, [encoding_json(e) for e in cpumode.encodings])
+                for cpumode in isa.cpumodes),
+            }
+
+
+def generate(isas, out_dir):
+    # type: (Sequence[TargetISA], str) -> None
+    instructions = []  # type: List[Dict[str, Any]]
+    for group in collect_instr_groups(isas):
+        instructions += [instruction_json(i) for i in group.instructions]
+    data = {
+            'instructions': instructions,
+            'isas': dict((isa.name, isa_json(isa)) for isa in isas),
+            }
+    with open(os.path.join(out_dir, 'isa.json'), 'w') as f:
+        json.dump(data, f, indent=2, sort_keys=True)
+        f.write('\n')
@@ -36,6 +36,8 @@ def unwrap_inst(iref, node, fmt):
     expr = node.expr
     iform = expr.inst.format
     nvops = len(iform.value_operands)
+    assert not iform.has_value_list, \
+        "can't unwrap {} with a value list".format(iform)
 
     # The tuple of locals we're extracting is `expr.args`.
     with fmt.indented(
@@ -54,7 +56,7 @@ def unwrap_inst(iref, node, fmt):
             elif nvops > 1:
                 fmt.line('args,')
         fmt.line('..')
-        fmt.outdented_line('} = dfg[inst] {')
+        fmt.outdented_line('}} = dfg[{}] {{'.format(iref))
         # Generate the values for the tuple.
         outs = list()
         prefix = 'data.' if iform.boxed_storage else ''
@@ -79,6 +81,13 @@ def unwrap_inst(iref, node, fmt):
         if isinstance(v, Var) and v.has_free_typevar():
             fmt.line('let typeof_{0} = dfg.value_type({0});'.format(v))
 
+    # Without value operands, the controlling type variable comes from the
+    # first result.
+    if nvops == 0 and node.defs and node.defs[0].has_free_typevar():
+        fmt.line(
+                'let typeof_{} = dfg.value_type(dfg.first_result({}));'
+                .format(node.defs[0], iref))
+
     # If the node has multiple results, detach the values.
     # Place the secondary values in 'src_{}' locals.
     if len(node.defs) > 1:
@@ -94,7 +103,9 @@ def unwrap_inst(iref, node, fmt):
             for d in node.defs[1:]:
                 fmt.line('let src_{};'.format(d))
             with fmt.indented('{', '}'):
-                fmt.line('let mut vals = dfg.detach_secondary_results(inst);')
+                fmt.line(
+                        'let mut vals = dfg.detach_secondary_results({});'
+                        .format(iref))
                 for d in node.defs[1:]:
                     fmt.line('src_{} = vals.next().unwrap();'.format(d))
                 fmt.line('assert_eq!(vals.next(), None);')
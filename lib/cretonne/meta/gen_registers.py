@@ -36,6 +36,8 @@ def gen_regclass(rc, fmt):
     with fmt.indented('RegClassData {', '},'):
         fmt.line('name: "{}",'.format(rc.name))
         fmt.line('index: {},'.format(rc.index))
+        fmt.line('bank: {},'.format(rc.bank.isa.regbanks.index(rc.bank)))
+        fmt.line('toprc: {},'.format(rc.toprc.index))
         fmt.line('width: {},'.format(rc.width))
         fmt.line('first: {},'.format(rc.bank.first_unit + rc.start))
         fmt.line('subclasses: 0x{:x},'.format(rc.subclass_mask()))
@@ -80,6 +80,9 @@ def gen_getters(sgrp, fmt):
             fmt.line(
                     'self.bytes[{} + p/8] & (1 << (p%8)) != 0'
                     .format(sgrp.boolean_offset))
+        fmt.doc_comment('Iterate over the settings and their values.')
+        with fmt.indented('pub fn iter(&self) -> Iter {', '}'):
+            fmt.line('Iter::new(&TEMPLATE, &self.bytes)')
         for setting in sgrp.settings:
             gen_getter(setting, sgrp, fmt)
         for pred in sgrp.named_predicates:
@@ -88,14 +91,14 @@ def gen_getters(sgrp, fmt):
 
 def gen_descriptors(sgrp, fmt):
     """
-    Generate the DESCRIPTORS and ENUMERATORS tables.
+    Generate the DESCRIPTORS, ENUMERATORS, and PRESETS tables.
     """
 
     enums = UniqueSeqTable()
 
     with fmt.indented(
             'static DESCRIPTORS: [detail::Descriptor; {}] = ['
-            .format(len(sgrp.settings)),
+            .format(len(sgrp.settings) + len(sgrp.presets)),
             '];'):
         for idx, setting in enumerate(sgrp.settings):
             setting.descriptor_index = idx
@@ -117,6 +120,15 @@ def gen_descriptors(sgrp, fmt):
                 else:
                     raise AssertionError("Unknown setting kind")
 
+        # Presets are looked up by name just like settings. The descriptor
+        # offset points into the PRESETS table.
+        for idx, preset in enumerate(sgrp.presets):
+            preset.descriptor_index = len(sgrp.settings) + idx
+            with fmt.indented('detail::Descriptor {', '},'):
+                fmt.line('name: "{}",'.format(preset.name))
+                fmt.line('offset: {},'.format(idx * sgrp.settings_size))
+                fmt.line('detail: detail::Detail::Preset,')
+
     with fmt.indented(
             'static ENUMERATORS: [&\'static str; {}] = ['
             .format(len(enums.table)),
@@ -127,7 +139,8 @@ def gen_descriptors(sgrp, fmt):
     def hash_setting(s):
         return constant_hash.simple_hash(s.name)
 
-    hash_table = constant_hash.compute_quadratic(sgrp.settings, hash_setting)
+    hash_table = constant_hash.compute_quadratic(
+            sgrp.settings + sgrp.presets, hash_setting)
     with fmt.indented(
             'static HASH_TABLE: [u16; {}] = ['
             .format(len(hash_table)),
@@ -138,6 +151,15 @@ def gen_descriptors(sgrp, fmt):
             else:
                 fmt.line('{},'.format(h.descriptor_index))
 
+    with fmt.indented(
+            'static PRESETS: [(u8, u8); {}] = ['
+            .format(len(sgrp.presets) * sgrp.settings_size),
+            '];'):
+        for preset in sgrp.presets:
+            fmt.comment(preset.name)
+            for mask, value in preset.layout():
+                fmt.format('({:#04x}, {:#04x}),', mask, value)
+
 
 def gen_template(sgrp, fmt):
     """
@@ -153,6 +175,7 @@ def gen_template(sgrp, fmt):
         fmt.line('descriptors: &DESCRIPTORS,')
         fmt.line('enumerators: &ENUMERATORS,')
         fmt.line('hash_table: &HASH_TABLE,')
+        fmt.line('presets: &PRESETS,')
         vs = ', '.join('{:#04x}'.format(x) for x in v)
         fmt.line('defaults: &[ {} ],'.format(vs))
 
@@ -173,6 +196,9 @@ def gen_display(sgrp, fmt):
                 '}'):
             fmt.line('writeln!(f, "[{}]")?;'.format(sgrp.name))
             with fmt.indented('for d in &DESCRIPTORS {', '}'):
+                with fmt.indented(
+                        'if let detail::Detail::Preset = d.detail {', '}'):
+                    fmt.line('continue;')
                 fmt.line('write!(f, "{} = ", d.name)?;')
                 fmt.line(
                         'TEMPLATE.format_toml_value(d.detail,' +
@@ -9,7 +9,7 @@ This ensures that Python and Rust use the same type numbering.
 """
 from __future__ import absolute_import
 import srcgen
-from cdsl.types import ValueType
+from cdsl.types import ValueType, RefType
 import base.types  # noqa
 
 
@@ -31,7 +31,7 @@ def emit_vectors(bits, fmt):
     size = bits // 8
     for ty in ValueType.all_scalars:
         mb = ty.membytes
-        if mb == 0 or mb >= size:
+        if mb == 0 or mb >= size or isinstance(ty, RefType):
             continue
         emit_type(ty.by(size // mb), fmt)
 
@@ -39,6 +39,8 @@ def emit_vectors(bits, fmt):
 def emit_types(fmt):
     for ty in ValueType.all_scalars:
         emit_type(ty, fmt)
+    for ty in ValueType.all_special_types:
+        emit_type(ty, fmt)
     # Emit vector definitions for common SIMD sizes.
     emit_vectors(64, fmt)
     emit_vectors(128, fmt)
@@ -0,0 +1,103 @@
+"""
+ARM32 Encodings.
+"""
+from __future__ import absolute_import
+from base import instructions as base
+from .defs import A32
+from .recipes import OP, DP, SHIFT
+from .recipes import AND, EOR, SUB, RSB, ADD, CMP, ORR, MOV, LSL, LSR, ASR
+from .recipes import DPrr, DPri, DPrsbi, DPshi, DPshr, DPmov, DPrmov, DPz
+from .recipes import DPicmp, DPsel, MOVW, MOVWT, MUL, MULhi, UNrr, CTZ
+from .recipes import LDimm, LDreg, STimm, STreg, SPILL, FILL
+from .recipes import B, CMPBz, CMPB, BXret
+
+# Data-processing instructions with a register operand, or with an 8-bit
+# immediate.
+for inst,           inst_imm,      opcode in [
+        (base.iadd, base.iadd_imm, ADD),
+        (base.isub, None,          SUB),
+        (base.band, base.band_imm, AND),
+        (base.bor,  base.bor_imm,  ORR),
+        (base.bxor, base.bxor_imm, EOR)
+        ]:
+    A32.enc(inst.i32, DPrr, DP(opcode))
+    if inst_imm:
+        A32.enc(inst_imm.i32, DPri, DP(opcode, imm=1))
+
+# Boolean operations on `b1` values, which are 0 or 1 in a register.
+for inst,           opcode in [
+        (base.band, AND),
+        (base.bor,  ORR),
+        (base.bxor, EOR)
+        ]:
+    A32.enc(inst.b1, DPrr, DP(opcode))
+
+# The immediate subtraction `X - y` is a reverse subtract.
+A32.enc(base.isub_imm.i32, DPrsbi, DP(RSB, imm=1))
+
+# Shifts are `mov` instructions with a shifted register operand.
+for inst,           inst_imm,      shift in [
+        (base.ishl, base.ishl_imm, LSL),
+        (base.ushr, base.ushr_imm, LSR),
+        (base.sshr, base.sshr_imm, ASR)
+        ]:
+    A32.enc(inst.i32.i32, DPshr, SHIFT(shift, reg=1))
+    A32.enc(inst_imm.i32, DPshi, SHIFT(shift))
+
+# Multiplication: `mul`, and the high half of `umull` and `smull`.
+A32.enc(base.imul.i32, MUL, OP(0x00, 0b1001))
+A32.enc(base.umulhi.i32, MULhi, OP(0x08, 0b1001))
+A32.enc(base.smulhi.i32, MULhi, OP(0x0c, 0b1001))
+
+# Bit manipulation: `clz`, `rbit`, and `rev`.
+A32.enc(base.clz.i32, UNrr, OP(0x16, 0b0001))
+A32.enc(base.ctz.i32, CTZ, OP(0x6f, 0b0011))
+A32.enc(base.bitrev.i32, UNrr, OP(0x6f, 0b0011))
+A32.enc(base.bswap.i32, UNrr, OP(0x6b, 0b0011))
+
+# Integer comparisons with any condition code.
+A32.enc(base.icmp.i32, DPicmp, DP(CMP, s=1))
+
+# Conditional moves.
+A32.enc(base.select.i32.b1, DPsel, DP(CMP, imm=1, s=1))
+A32.enc(base.select.i32.i32, DPsel, DP(CMP, imm=1, s=1))
+
+# Integer constants: `movw` for 16-bit constants and `movw` + `movt` for the
+# others.
+A32.enc(base.iconst.i32, MOVW, OP(0x30))
+A32.enc(base.iconst.i32, MOVWT, OP(0x30))
+
+# Zero constants.
+A32.enc(base.null.i32, DPz, DP(MOV, imm=1))
+
+# Register copies and diversions are `mov` instructions.
+A32.enc(base.copy.i32, DPmov, DP(MOV))
+A32.enc(base.regmove.i32, DPrmov, DP(MOV))
+
+# The `b1` values are already 0 or 1, so `bint` is a plain register move.
+A32.enc(base.bint.i32.b1, DPmov, DP(MOV))
+
+# Loads and stores with a 12-bit offset or an offset in the scratch register.
+A32.enc(base.load.i32.i32, LDimm, OP(0x59))
+A32.enc(base.load.i32.i32, LDreg, OP(0x79))
+A32.enc(base.store.i32.i32, STimm, OP(0x58))
+A32.enc(base.store.i32.i32, STreg, OP(0x78))
+
+# Spill and fill with `str` and `ldr` relative to the stack pointer.
+A32.enc(base.spill.i32, SPILL, OP(0x58))
+A32.enc(base.fill.i32, FILL, OP(0x59))
+
+# Control flow.
+
+A32.enc(base.jump, B, OP(0xa0))
+
+# Branches on a zero or non-zero register.
+for inst in [base.brz, base.brnz]:
+    A32.enc(inst.i32, CMPBz, DP(CMP, imm=1, s=1))
+    A32.enc(inst.b1, CMPBz, DP(CMP, imm=1, s=1))
+
+# Conditional branches comparing two registers.
+A32.enc(base.br_icmp.i32, CMPB, DP(CMP, s=1))
+
+# The return address is kept in `lr`, which is never allocated to values.
+A32.enc(base.x_return, BXret, OP(0x12, 0b0001))
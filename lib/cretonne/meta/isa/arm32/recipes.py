@@ -0,0 +1,202 @@
+"""
+ARM32 Encoding recipes.
+
+The recipes defined here generate A32 instructions for ARMv7 CPUs. The Thumb-2
+T32 encodings are not implemented yet.
+"""
+from __future__ import absolute_import
+from cdsl.isa import EncRecipe
+from cdsl.predicates import IsSignedInt, IsUnsignedInt
+from base.formats import Nullary, Unary, UnaryImm, Binary, BinaryImm
+from base.formats import BinaryImmRev, Ternary, IntCompare, RegMove
+from base.formats import Load, Store, Jump, Branch, BranchIcmp, Return
+from cdsl.registers import Stack
+from .registers import GPR
+
+# All A32 instructions are 32 bits wide. The condition field in bits 31:28 is
+# always AL, except in the multi-instruction recipes that execute some of
+# their instructions conditionally.
+#
+# The A32 decoding tables select an instruction with the `op1` field in bits
+# 27:20 and the `op2` field in bits 7:4. The encbits for all the recipes are
+# `op1 | (op2 << 8)`, and the recipes fill in the register and immediate
+# fields.
+#
+# The recipes that need a scratch register use `ip` (`r12`), which is never
+# allocated to values.
+
+
+def OP(op1, op2=0):
+    # type: (int, int) -> int
+    assert op1 <= 0xff
+    assert op2 <= 0xf
+    return op1 | (op2 << 8)
+
+
+# Data-processing opcodes in bits 24:21.
+AND = 0b0000
+EOR = 0b0001
+SUB = 0b0010
+RSB = 0b0011
+ADD = 0b0100
+CMP = 0b1010
+ORR = 0b1100
+MOV = 0b1101
+
+
+def DP(opcode, imm=0, s=0):
+    # type: (int, int, int) -> int
+    """
+    Data-processing instruction with a register or an immediate operand.
+
+    :param opcode: Data-processing opcode.
+    :param imm: Use the modified immediate operand form.
+    :param s: Set the condition flags.
+    """
+    assert opcode <= 0b1111
+    assert imm <= 1
+    assert s <= 1
+    return OP((imm << 5) | (opcode << 1) | s)
+
+
+# Shift types in bits 6:5 of a shifted register operand.
+LSL = 0b00
+LSR = 0b01
+ASR = 0b10
+
+
+def SHIFT(shift, reg=0):
+    # type: (int, int) -> int
+    """
+    A `mov` with a shifted register operand.
+
+    :param shift: The shift type.
+    :param reg: Shift by a register instead of an immediate.
+    """
+    assert shift <= 0b11
+    assert reg <= 1
+    return OP(DP(MOV), (shift << 1) | reg)
+
+
+# Data-processing with a register operand: `add rd, rn, rm`.
+DPrr = EncRecipe('DPrr', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# Data-processing with a modified immediate operand: `add rd, rn, #imm`. The
+# immediate is an 8-bit value rotated right by an even amount, but only the
+# unrotated values are used.
+DPri = EncRecipe(
+        'DPri', BinaryImm, size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImm.imm, 8))
+
+# Reverse subtract with an immediate operand: `rsb rd, rn, #imm`.
+DPrsbi = EncRecipe(
+        'DPrsbi', BinaryImmRev, size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImmRev.imm, 8))
+
+# Shift by an immediate amount: `mov rd, rm, lsl #imm`. The shifts by zero are
+# always emitted as `lsl #0`, because `lsr #0` and `asr #0` encode shifts by
+# 32.
+DPshi = EncRecipe(
+        'DPshi', BinaryImm, size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImm.imm, 5))
+
+# Shift by a register amount: `and ip, rs, #31` followed by `mov rd, rm, lsl
+# ip`. The A32 shifts use the low byte of the shift register without masking
+# it to the operand size.
+DPshr = EncRecipe('DPshr', Binary, size=8, ins=(GPR, GPR), outs=GPR)
+
+# Register copy: `mov rd, rm`.
+DPmov = EncRecipe('DPmov', Unary, size=4, ins=GPR, outs=GPR)
+
+# Register move for a register diversion. The source and destination
+# registers come from the instruction's immediate operands.
+DPrmov = EncRecipe('DPrmov', RegMove, size=4, ins=GPR, outs=())
+
+# Materialize a zero value: `mov rd, #0`.
+DPz = EncRecipe('DPz', Nullary, size=4, ins=(), outs=GPR)
+
+# Integer comparison: `cmp rn, rm`, `mov rd, #0`, and a conditional `mov rd,
+# #1`. All the integer condition codes have native A32 conditions.
+DPicmp = EncRecipe(
+        'DPicmp', IntCompare, size=12, ins=(GPR, GPR), outs=GPR,
+        clobbers_flags=True)
+
+# Select: `cmp rc, #0` and `movne rd, rx`. The result is tied to the third
+# operand which is kept when the condition is zero.
+DPsel = EncRecipe(
+        'DPsel', Ternary, size=8, ins=(GPR, GPR, GPR), outs=2,
+        clobbers_flags=True)
+
+# 16-bit constant: `movw rd, #imm16`.
+MOVW = EncRecipe(
+        'MOVW', UnaryImm, size=4, ins=(), outs=GPR,
+        instp=IsUnsignedInt(UnaryImm.imm, 16))
+
+# 32-bit constant: `movw rd, #lo16` followed by `movt rd, #hi16`.
+MOVWT = EncRecipe('MOVWT', UnaryImm, size=8, ins=(), outs=GPR)
+
+# Multiply: `mul rd, rn, rm`. The destination register is in bits 19:16.
+MUL = EncRecipe('MUL', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# High half of a long multiply: `umull ip, rd, rn, rm` or `smull`. The low
+# half of the product is discarded in the scratch register.
+MULhi = EncRecipe('MULhi', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# Miscellaneous unary instructions: `clz rd, rm`, `rbit rd, rm`, and `rev rd,
+# rm`.
+UNrr = EncRecipe('UNrr', Unary, size=4, ins=GPR, outs=GPR)
+
+# Count trailing zeros: `rbit rd, rm` followed by `clz rd, rd`.
+CTZ = EncRecipe('CTZ', Unary, size=8, ins=GPR, outs=GPR)
+
+# Load from the address in a register plus a 12-bit offset: `ldr rt, [rn,
+# #imm12]`. The offset is added or subtracted depending on the U bit.
+LDimm = EncRecipe(
+        'LDimm', Load, size=4, ins=GPR, outs=GPR,
+        instp=IsSignedInt(Load.offset, 12))
+
+# Load with a large offset materialized in the scratch register: `movw ip,
+# #lo16`, `movt ip, #hi16`, and `ldr rt, [rn, ip]`.
+LDreg = EncRecipe('LDreg', Load, size=12, ins=GPR, outs=GPR)
+
+# Store of the first operand to the address in the second operand plus a
+# 12-bit offset: `str rt, [rn, #imm12]`.
+STimm = EncRecipe(
+        'STimm', Store, size=4, ins=(GPR, GPR), outs=(),
+        instp=IsSignedInt(Store.offset, 12))
+
+# Store with a large offset materialized in the scratch register.
+STreg = EncRecipe('STreg', Store, size=12, ins=(GPR, GPR), outs=())
+
+# Store of a register to a stack slot addressed relative to the stack pointer:
+# `str rt, [sp, #imm12]`. The spill slots must be in the first 4 KB of the
+# stack frame.
+SPILL = EncRecipe('SPILL', Unary, size=4, ins=GPR, outs=Stack(GPR))
+
+# Load of a register from a stack slot addressed relative to the stack
+# pointer: `ldr rt, [sp, #imm12]`.
+FILL = EncRecipe('FILL', Unary, size=4, ins=Stack(GPR), outs=GPR)
+
+# Branches.
+#
+# The A32 branch displacement is a signed 26-bit byte offset relative to the
+# address of the branch instruction plus 8. That origin can be beyond the end
+# of the recipe, so the branch ranges below are relative to the end of the
+# recipe with one less bit, which is a conservative approximation.
+
+# Unconditional branch: `b ebb`. The variable EBB arguments are not encoded.
+B = EncRecipe('B', Jump, size=4, branch_range=(4, 25), ins=(), outs=())
+
+# Compare a register with zero and branch: `cmp rn, #0` followed by `beq` or
+# `bne`. The condition is selected by the opcode.
+CMPBz = EncRecipe(
+        'CMPBz', Branch, size=8, branch_range=(8, 25), ins=GPR, outs=(),
+        clobbers_flags=True)
+
+# Compare two registers and branch: `cmp rn, rm` followed by `b<cond>`.
+CMPB = EncRecipe(
+        'CMPB', BranchIcmp, size=8, branch_range=(8, 25),
+        ins=(GPR, GPR), outs=(), clobbers_flags=True)
+
+# Return to the address in the link register: `bx lr`.
+BXret = EncRecipe('BXret', Return, size=4, ins=(), outs=())
@@ -2,10 +2,15 @@
 ARM32 settings.
 """
 from __future__ import absolute_import
-from cdsl.settings import SettingGroup
+from cdsl.settings import SettingGroup, BoolSetting
 import base.settings as shared
 from .defs import ISA
 
 ISA.settings = SettingGroup('arm32', parent=shared.group)
 
+# The VFPv3-D16 and VFPv4-D16 units only have the registers `d0`-`d15`. The
+# upper half of the float register bank is only available with 32 double
+# precision registers.
+has_d32 = BoolSetting("CPU has the 32 double precision registers d0-d31")
+
 ISA.settings.close(globals())
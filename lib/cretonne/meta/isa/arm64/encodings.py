@@ -0,0 +1,114 @@
+"""
+ARM64 Encodings.
+"""
+from __future__ import absolute_import
+from base import instructions as base
+from base.types import b1, i32, i64
+from base.formats import BinaryImm, Load, Store
+from cdsl.predicates import IsBitmaskImm, IsUnsignedInt
+from .defs import A64
+from .recipes import OP, ADDSUB, ADDSUBI, LOGIC, LOGICI, DP2, DP3, BFM
+from .recipes import MOVW, CSEL, LDST, CB, AND, ORR, EOR
+from .recipes import DPrr, MUL, DPai, DPli, DPshi, DPmov, DPrmov, DPz
+from .recipes import DPicmp, DPcsel, MOVZ, MOVZK, MOVZKKK
+from .recipes import LDur, LDui, STur, STui, SPILL, FILL
+from .recipes import B, CBZ, CMPB, RET
+
+# The 32-bit and 64-bit operations use the same opcodes. The `sf` bit selects
+# the 64-bit operand size.
+
+for ty, sf in [(i32, 0), (i64, 1)]:
+    # Arithmetic.
+    A64.enc(base.iadd.bind(ty), DPrr, ADDSUB(sf, 0))
+    A64.enc(base.isub.bind(ty), DPrr, ADDSUB(sf, 1))
+    A64.enc(base.iadd_imm.bind(ty), DPai, ADDSUBI(sf, 0))
+    A64.enc(base.imul.bind(ty), MUL, DP3(sf, 0b000))
+
+    # Logical operations. Only the bitmask immediates can be encoded, and
+    # which values are bitmask immediates depends on the operand size.
+    bitmask = IsBitmaskImm(BinaryImm.imm, 32 << sf)
+    for inst,           inst_imm,      opc in [
+            (base.band, base.band_imm, AND),
+            (base.bor,  base.bor_imm,  ORR),
+            (base.bxor, base.bxor_imm, EOR)
+            ]:
+        A64.enc(inst.bind(ty), DPrr, LOGIC(sf, opc))
+        A64.enc(inst_imm.bind(ty), DPli, LOGICI(sf, opc), instp=bitmask)
+
+    # Immediate shifts are aliases of `ubfm` and `sbfm`.
+    A64.enc(base.ishl_imm.bind(ty), DPshi, BFM(sf, 0b10))
+    A64.enc(base.ushr_imm.bind(ty), DPshi, BFM(sf, 0b10))
+    A64.enc(base.sshr_imm.bind(ty), DPshi, BFM(sf, 0b00))
+
+    # Comparisons and selects. The condition of a `select` must have the same
+    # width as the selected values, or be a `b1`.
+    A64.enc(base.icmp.bind(ty), DPicmp, ADDSUB(sf, 1, s=1))
+    A64.enc(base.select.bind(ty, b1), DPcsel, CSEL(sf))
+    A64.enc(base.select.bind(ty, ty), DPcsel, CSEL(sf))
+
+    # Copies, diversions, and zero constants use the zero register.
+    A64.enc(base.copy.bind(ty), DPmov, LOGIC(sf, ORR))
+    A64.enc(base.regmove.bind(ty), DPrmov, LOGIC(sf, ORR))
+    A64.enc(base.null.bind(ty), DPz, LOGIC(sf, ORR))
+
+    # The `b1` values are already 0 or 1, so `bint` is a plain register move.
+    A64.enc(base.bint.bind(ty, b1), DPmov, LOGIC(sf, ORR))
+
+    # Spill and fill with `str` and `ldr` relative to the stack pointer.
+    A64.enc(base.spill.bind(ty), SPILL, LDST(2 + sf, 0, scaled=1))
+    A64.enc(base.fill.bind(ty), FILL, LDST(2 + sf, 1, scaled=1))
+
+    # Branches on a zero or non-zero register.
+    A64.enc(base.brz.bind(ty), CBZ, CB(sf, 0))
+    A64.enc(base.brnz.bind(ty), CBZ, CB(sf, 1))
+
+    # Conditional branches comparing two registers.
+    A64.enc(base.br_icmp.bind(ty), CMPB, ADDSUB(sf, 1, s=1))
+
+# The `b1` values are 0 or 1 in a `w` register.
+A64.enc(base.brz.b1, CBZ, CB(0, 0))
+A64.enc(base.brnz.b1, CBZ, CB(0, 1))
+
+# The multiplications producing the high half only exist for 64-bit operands.
+A64.enc(base.smulhi.i64, MUL, DP3(1, 0b010))
+A64.enc(base.umulhi.i64, MUL, DP3(1, 0b110))
+
+# Variable shifts. The shift amount is taken modulo the operand size, so its
+# type doesn't matter.
+for inst,           opcode in [
+        (base.ishl, 0b1000),
+        (base.ushr, 0b1001),
+        (base.sshr, 0b1010)
+        ]:
+    A64.enc(inst.i32.i32, DPrr, DP2(0, opcode))
+    A64.enc(inst.i32.i64, DPrr, DP2(0, opcode))
+    A64.enc(inst.i64.i64, DPrr, DP2(1, opcode))
+    A64.enc(inst.i64.i32, DPrr, DP2(1, opcode))
+
+# Integer constants: `movz` for 16-bit constants and `movz` followed by `movk`
+# instructions for the others.
+A64.enc(base.iconst.i32, MOVZ, MOVW(0, 0b10))
+A64.enc(base.iconst.i32, MOVZK, MOVW(0, 0b10))
+A64.enc(base.iconst.i64, MOVZ, MOVW(1, 0b10))
+A64.enc(base.iconst.i64, MOVZKKK, MOVW(1, 0b10))
+
+# Loads and stores with a 64-bit address. The unscaled form is used for small
+# negative or misaligned offsets, and the scaled form reaches further.
+for ty,  size in [(i32, 2), (i64, 3)]:
+    scaled_load = IsUnsignedInt(Load.offset, 12 + size, size)
+    scaled_store = IsUnsignedInt(Store.offset, 12 + size, size)
+    A64.enc(base.load.bind(ty, i64), LDur, LDST(size, 1, scaled=0))
+    A64.enc(
+            base.load.bind(ty, i64), LDui, LDST(size, 1, scaled=1),
+            instp=scaled_load)
+    A64.enc(base.store.bind(ty, i64), STur, LDST(size, 0, scaled=0))
+    A64.enc(
+            base.store.bind(ty, i64), STui, LDST(size, 0, scaled=1),
+            instp=scaled_store)
+
+# Control flow.
+
+A64.enc(base.jump, B, OP(0b000101 << 5))
+
+# The return address is kept in `x30`, which is never allocated to values.
+A64.enc(base.x_return, RET, OP(0x6b2))
@@ -0,0 +1,239 @@
+"""
+ARM64 Encoding recipes.
+
+All A64 instructions are 32 bits wide. The register number 31 encodes either
+the stack pointer `sp` or the zero register `xzr` depending on the
+instruction, and the recipes use it in both roles. Register 31 is not
+allocatable.
+"""
+from __future__ import absolute_import
+from cdsl.isa import EncRecipe
+from cdsl.predicates import IsSignedInt, IsUnsignedInt
+from base.formats import Nullary, Unary, UnaryImm, Binary, BinaryImm
+from base.formats import Ternary, IntCompare, RegMove, Load, Store
+from base.formats import Jump, Branch, BranchIcmp, Return
+from cdsl.registers import Stack
+from .registers import GPR
+
+# The encbits for all the recipes are `op | (op2 << 11)`, where `op` is bits
+# 31:21 of the instruction, and `op2` is bits 13:10, which select the
+# data-processing instructions with two register sources. The recipes fill in
+# the register and immediate fields.
+#
+# Bit 31 is the `sf` bit selecting a 64-bit operation in most of the integer
+# instructions. The 32-bit instructions operate on the `w` registers and
+# clear the high 32 bits of the destination.
+
+
+def OP(op, op2=0):
+    # type: (int, int) -> int
+    assert op <= 0x7ff
+    assert op2 <= 0xf
+    return op | (op2 << 11)
+
+
+def ADDSUB(sf, op, s=0):
+    # type: (int, int, int) -> int
+    """
+    Add or subtract with a shifted register operand.
+
+    :param sf: 64-bit operation.
+    :param op: Subtract instead of add.
+    :param s: Set the condition flags.
+    """
+    return OP((sf << 10) | (op << 9) | (s << 8) | (0b01011 << 3))
+
+
+def ADDSUBI(sf, op, s=0):
+    # type: (int, int, int) -> int
+    """
+    Add or subtract with a 12-bit unsigned immediate operand.
+    """
+    return OP((sf << 10) | (op << 9) | (s << 8) | (0b100010 << 2))
+
+
+# Opcodes for the logical instructions.
+AND = 0b00
+ORR = 0b01
+EOR = 0b10
+
+
+def LOGIC(sf, opc):
+    # type: (int, int) -> int
+    """
+    Logical operation with a shifted register operand.
+    """
+    return OP((sf << 10) | (opc << 8) | (0b01010 << 3))
+
+
+def LOGICI(sf, opc):
+    # type: (int, int) -> int
+    """
+    Logical operation with a bitmask immediate operand.
+    """
+    return OP((sf << 10) | (opc << 8) | (0b100100 << 2))
+
+
+def DP2(sf, opcode):
+    # type: (int, int) -> int
+    """
+    Data-processing with two register sources, like the variable shifts.
+    """
+    assert opcode <= 0xf
+    return OP((sf << 10) | 0b0011010110, opcode)
+
+
+def DP3(sf, op31):
+    # type: (int, int) -> int
+    """
+    Data-processing with three register sources, like the multiplications.
+    """
+    return OP((sf << 10) | (0b11011 << 3) | op31)
+
+
+def BFM(sf, opc):
+    # type: (int, int) -> int
+    """
+    Bitfield move. The immediate shifts are aliases of `ubfm` and `sbfm`.
+    """
+    return OP((sf << 10) | (opc << 8) | (0b100110 << 2) | (sf << 1))
+
+
+def MOVW(sf, opc):
+    # type: (int, int) -> int
+    """
+    Move wide immediate.
+    """
+    return OP((sf << 10) | (opc << 8) | (0b100101 << 2))
+
+
+def CSEL(sf):
+    # type: (int) -> int
+    """
+    Conditional select.
+    """
+    return OP((sf << 10) | 0b0011010100)
+
+
+def LDST(size, opc, scaled):
+    # type: (int, int, int) -> int
+    """
+    Load or store with an immediate offset.
+
+    :param size: Log2 of the access size in bytes.
+    :param opc: 0 for a store, 1 for a load.
+    :param scaled: Use the scaled unsigned offset form instead of the
+            unscaled signed offset form.
+    """
+    return OP((size << 9) | (0b111 << 6) | (scaled << 3) | (opc << 1))
+
+
+def CB(sf, op):
+    # type: (int, int) -> int
+    """
+    Compare a register with zero and branch.
+    """
+    return OP((sf << 10) | (0b011010 << 4) | (op << 3))
+
+
+# Data-processing with register operands: `add rd, rn, rm` or `lslv rd, rn,
+# rm`. The variable shifts mask the shift amount to the operand size like the
+# Cretonne instructions.
+DPrr = EncRecipe('DPrr', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# Multiplication: `madd rd, rn, rm, xzr`, `smulh rd, rn, rm`, or `umulh rd,
+# rn, rm`.
+MUL = EncRecipe('MUL', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# Add or subtract with a 12-bit unsigned immediate: `add rd, rn, #imm12`.
+DPai = EncRecipe(
+        'DPai', BinaryImm, size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImm.imm, 12))
+
+# Logical operation with a bitmask immediate: `and rd, rn, #imm`. Which
+# immediates can be encoded depends on the operand size, so the encodings
+# provide the instruction predicate.
+DPli = EncRecipe('DPli', BinaryImm, size=4, ins=GPR, outs=GPR)
+
+# Shift by an immediate amount: `lsl rd, rn, #imm` as an alias of `ubfm`, or
+# `lsr` and `asr`. The shift amount is masked to the operand size.
+DPshi = EncRecipe('DPshi', BinaryImm, size=4, ins=GPR, outs=GPR)
+
+# Register copy: `mov rd, rm` as an alias of `orr rd, xzr, rm`.
+DPmov = EncRecipe('DPmov', Unary, size=4, ins=GPR, outs=GPR)
+
+# Register move for a register diversion. The source and destination
+# registers come from the instruction's immediate operands.
+DPrmov = EncRecipe('DPrmov', RegMove, size=4, ins=GPR, outs=())
+
+# Materialize a zero value: `mov rd, xzr`.
+DPz = EncRecipe('DPz', Nullary, size=4, ins=(), outs=GPR)
+
+# Integer comparison: `cmp rn, rm` followed by `cset rd, cond`.
+DPicmp = EncRecipe(
+        'DPicmp', IntCompare, size=8, ins=(GPR, GPR), outs=GPR,
+        clobbers_flags=True)
+
+# Select: `cmp rc, #0` followed by `csel rd, rx, ry, ne`.
+DPcsel = EncRecipe(
+        'DPcsel', Ternary, size=8, ins=(GPR, GPR, GPR), outs=GPR,
+        clobbers_flags=True)
+
+# 16-bit constant: `movz rd, #imm16`.
+MOVZ = EncRecipe(
+        'MOVZ', UnaryImm, size=4, ins=(), outs=GPR,
+        instp=IsUnsignedInt(UnaryImm.imm, 16))
+
+# 32-bit constant: `movz rd, #imm16` followed by `movk rd, #imm16, lsl 16`.
+MOVZK = EncRecipe('MOVZK', UnaryImm, size=8, ins=(), outs=GPR)
+
+# 64-bit constant: `movz` followed by three `movk` instructions.
+MOVZKKK = EncRecipe('MOVZKKK', UnaryImm, size=16, ins=(), outs=GPR)
+
+# Load from the address in a register plus a signed 9-bit offset: `ldur rt,
+# [rn, #imm9]`.
+LDur = EncRecipe(
+        'LDur', Load, size=4, ins=GPR, outs=GPR,
+        instp=IsSignedInt(Load.offset, 9))
+
+# Load from the address in a register plus an unsigned 12-bit offset scaled
+# by the access size: `ldr rt, [rn, #imm12]`. The range of offsets depends on
+# the access size, so the encodings provide the instruction predicate.
+LDui = EncRecipe('LDui', Load, size=4, ins=GPR, outs=GPR)
+
+# Store of the first operand to the address in the second operand plus a
+# signed 9-bit offset: `stur rt, [rn, #imm9]`.
+STur = EncRecipe(
+        'STur', Store, size=4, ins=(GPR, GPR), outs=(),
+        instp=IsSignedInt(Store.offset, 9))
+
+# Store with an unsigned 12-bit scaled offset: `str rt, [rn, #imm12]`.
+STui = EncRecipe('STui', Store, size=4, ins=(GPR, GPR), outs=())
+
+# Store of a register to a stack slot addressed relative to the stack pointer:
+# `str rt, [sp, #imm12]`.
+SPILL = EncRecipe('SPILL', Unary, size=4, ins=GPR, outs=Stack(GPR))
+
+# Load of a register from a stack slot addressed relative to the stack
+# pointer: `ldr rt, [sp, #imm12]`.
+FILL = EncRecipe('FILL', Unary, size=4, ins=Stack(GPR), outs=GPR)
+
+# Branches.
+#
+# The branch displacements are word offsets relative to the address of the
+# branch instruction.
+
+# Unconditional branch: `b ebb`. The variable EBB arguments are not encoded.
+B = EncRecipe('B', Jump, size=4, branch_range=(0, 28), ins=(), outs=())
+
+# Compare a register with zero and branch: `cbz rt, ebb` or `cbnz`.
+CBZ = EncRecipe(
+        'CBZ', Branch, size=4, branch_range=(0, 21), ins=GPR, outs=())
+
+# Compare two registers and branch: `cmp rn, rm` followed by `b.cond ebb`.
+CMPB = EncRecipe(
+        'CMPB', BranchIcmp, size=8, branch_range=(4, 21),
+        ins=(GPR, GPR), outs=(), clobbers_flags=True)
+
+# Return to the address in the link register: `ret`.
+RET = EncRecipe('RET', Return, size=4, ins=(), outs=())
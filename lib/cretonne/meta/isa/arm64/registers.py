@@ -7,7 +7,8 @@ from .defs import ISA
 
 
 # The `x31` regunit serves as the stack pointer / zero register depending on
-# context. We reserve it and don't model the difference.
+# context. It is not part of any register class, so it is never allocated to
+# values. The encoding recipes use it as `sp` or `xzr` where needed.
 IntRegs = RegBank(
         'IntRegs', ISA,
         'General purpose registers',
@@ -18,7 +19,8 @@ FloatRegs = RegBank(
         'Floating point registers',
         units=32, prefix='v')
 
-GPR = RegClass(IntRegs)
+# The 31 general purpose registers `x0`-`x30`.
+GPR = RegClass(IntRegs, count=31)
 FPR = RegClass(FloatRegs)
 
 RegClass.extract_names(globals())
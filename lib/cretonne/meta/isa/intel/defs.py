@@ -6,8 +6,9 @@ Commonly used definitions.
 from __future__ import absolute_import
 from cdsl.isa import TargetISA, CPUMode
 import base.instructions
+from . import instructions as x86
 
-ISA = TargetISA('intel', [base.instructions.GROUP])
+ISA = TargetISA('intel', [base.instructions.GROUP, x86.GROUP])
 
 # CPU modes for 32-bit and 64-bit operation.
 I32 = CPUMode('I32', ISA)
@@ -0,0 +1,591 @@
+"""
+Intel Encodings.
+"""
+from __future__ import absolute_import
+from cdsl.predicates import Not, IsColocatedFunc, IsColocatedData, IsAligned
+from base import instructions as base
+from base.types import i8, i16, i32, i64, f32, f64
+from base.formats import FuncAddr, UnaryGlobalVar, Call, Load, Store
+from base.settings import is_pic, enable_atomics
+from .defs import I32, I64
+from . import instructions as x86
+from .recipes import OP, MP
+from .recipes import Op1rr, Op2rr, Op1rc, Op1rib, Op1rid, Op1pu_id, Op1umr
+from .recipes import Op1rout, Op1rin, Op1rio, Op1rtrap
+from .recipes import RexOp1rout, RexOp1rin, RexOp1rio, RexOp1rtrap
+from .recipes import Op1rmov, Op1ldDisp8, Op1ldDisp32, Op1stDisp8, Op1stDisp32
+from .recipes import Op1ldIdxDisp8, Op1ldIdxDisp32
+from .recipes import Op1stIdxDisp8, Op1stIdxDisp32
+from .recipes import RexOp1ldIdxDisp8, RexOp1ldIdxDisp32
+from .recipes import RexOp1stIdxDisp8, RexOp1stIdxDisp32
+from .recipes import Op1spill, Op1fill, Op1jmpd, Op1tjccd, Op1cmpjccd, Op1ret
+from .recipes import Op1jmpb, Op1tjccb, Op1cmpjccb, RexOp1tjccb, RexOp1cmpjccb
+from .recipes import RexOp1rr, RexOp2rr, RexOp1rc, RexOp1rib, RexOp1rid
+from .recipes import Op1div, RexOp1div, Op1mulx, RexOp1mulx
+from .recipes import Op1ald, RexOp1ald, Op1ast, RexOp1ast, Op1armw, RexOp1armw
+from .recipes import LkOp2armw, LkRexOp2armw, LkOp2acas, LkRexOp2acas
+from .recipes import RexOp1pu_id, RexOp1u_id, RexOp1pu_iq, RexOp1umr
+from .recipes import Op1pu_bool, RexOp1pu_bool
+from .recipes import RexOp1rmov, RexOp1ldDisp8, RexOp1ldDisp32
+from .recipes import RexOp1stDisp8, RexOp1stDisp32, RexOp1spill, RexOp1fill
+from .recipes import RexOp1tjccd, RexOp1cmpjccd, Op2tcmov, RexOp2tcmov
+from .recipes import Op1rcmp, RexOp1rcmp, Op2seti, RexOp2seti
+from .recipes import Op1icscc, RexOp1icscc
+from .recipes import Op1call, Op1call_plt, RexOp1call_abs
+from .recipes import Op1call_r, RexOp1call_r
+from .recipes import Op2cmov, RexOp2cmov
+from .recipes import Op2trap, Op1ttrap, RexOp1ttrap, Op1trapif, Op1probe
+from .recipes import Op1adjustsp_ib, Op1adjustsp_id
+from .recipes import RexOp1adjustsp_ib, RexOp1adjustsp_id
+from .recipes import Op1cmpsp, RexOp1cmpsp
+from .recipes import Mp2fa, Mp2frurm, Mp2rfurm, Mp2rfumr, Op2furm, Op2frmov
+from .recipes import Mp2fspill, Mp2ffill, Op2fcscc, Mp2fcscc
+from .recipes import RexMp2fa, RexMp2frurm, RexMp2rfurm, RexMp2rfumr
+from .recipes import RexOp2furm, RexOp2frmov, RexMp2fspill, RexMp2ffill
+from .recipes import RexOp2fcscc, RexMp2fcscc
+from .recipes import Mp2furm, RexMp2furm, Mp2urm, RexMp2urm
+from .recipes import Op2fldDisp8, Op2fldDisp32, RexOp2fldDisp8, RexOp2fldDisp32
+from .recipes import Op2fstDisp8, Op2fstDisp32, RexOp2fstDisp8, RexOp2fstDisp32
+from .recipes import Op2fspill, Op2ffill, RexOp2fspill, RexOp2ffill
+from .recipes import Mp3furmi_rnd, RexMp3furmi_rnd
+from .recipes import Op1fnaddr, RexOp1fnaddr, Op1gvaddr, RexOp1gvaddr
+from .recipes import RexOp1pcrel_fnaddr, RexOp1pcrel_gvaddr
+from .recipes import RexOp1got_fnaddr, RexOp1got_gvaddr, Op1tcall, Op1tcall_plt
+from .recipes import RexOp1tcall_abs
+from .recipes import Op1jt_base, RexOp1jt_base, Op1jt_entry, RexOp1jt_entry
+from .recipes import Op1indirect_jmp, RexOp1indirect_jmp, safepoint
+from .settings import has_sse2, has_sse41, has_bmi1, has_lzcnt, use_popcnt
+
+# In 64-bit mode, the 32-bit and 64-bit operations use the same opcodes. The
+# REX.W bit selects the 64-bit operand size.
+
+# Two-address arithmetic: `add r/m32, r32` and friends.
+for inst,           op in [
+        (base.iadd, 0x01),
+        (base.isub, 0x29),
+        (base.band, 0x21),
+        (base.bor,  0x09),
+        (base.bxor, 0x31)
+        ]:
+    I32.enc(inst.i32, Op1rr, OP(op))
+    I64.enc(inst.i32, RexOp1rr, OP(op))
+    I64.enc(inst.i64, RexOp1rr, OP(op, w=1))
+
+# Arithmetic with carry and borrow: `add`, `adc`, `sub`, and `sbb`, with the
+# flags moved to and from `b1` registers. This is also how the `i64`
+# arithmetic is narrowed in 32-bit mode.
+for inst,                  recipe,  rex_recipe,  op in [
+        (base.iadd_cout,   Op1rout, RexOp1rout,  0x01),
+        (base.iadd_cin,    Op1rin,  RexOp1rin,   0x11),
+        (base.iadd_carry,  Op1rio,  RexOp1rio,   0x11),
+        (base.isub_bout,   Op1rout, RexOp1rout,  0x29),
+        (base.isub_bin,    Op1rin,  RexOp1rin,   0x19),
+        (base.isub_borrow, Op1rio,  RexOp1rio,   0x19)
+        ]:
+    I32.enc(inst.i32, recipe, OP(op))
+    I64.enc(inst.i32, rex_recipe, OP(op))
+    I64.enc(inst.i64, rex_recipe, OP(op, w=1))
+
+# Arithmetic that traps on overflow: `add` or `sub` followed by a `jno` or
+# `jnc` over a `ud2`.
+for inst,                        op in [
+        (base.uadd_overflow_trap, 0x01),
+        (base.sadd_overflow_trap, 0x01),
+        (base.usub_overflow_trap, 0x29),
+        (base.ssub_overflow_trap, 0x29)
+        ]:
+    I32.enc(inst.i32, Op1rtrap, OP(op))
+    I64.enc(inst.i32, RexOp1rtrap, OP(op))
+    I64.enc(inst.i64, RexOp1rtrap, OP(op, w=1))
+
+# Boolean operations on `b1` values, which are 0 or 1 in a register.
+for inst,           op in [
+        (base.band, 0x21),
+        (base.bor,  0x09),
+        (base.bxor, 0x31)
+        ]:
+    I32.enc(inst.b1, Op1rr, OP(op))
+    I64.enc(inst.b1, RexOp1rr, OP(op))
+
+# `imul r32, r/m32`.
+I32.enc(base.imul.i32, Op2rr, OP(0xaf))
+I64.enc(base.imul.i32, RexOp2rr, OP(0xaf))
+I64.enc(base.imul.i64, RexOp2rr, OP(0xaf, w=1))
+
+# Division: `div r/m32` and `idiv r/m32` divide RDX:RAX by the operand.
+for inst,             rrr in [
+        (x86.udivmodx, 6),
+        (x86.sdivmodx, 7)
+        ]:
+    I32.enc(inst.i32, Op1div, OP(0xf7, rrr))
+    I64.enc(inst.i32, RexOp1div, OP(0xf7, rrr))
+    I64.enc(inst.i64, RexOp1div, OP(0xf7, rrr, w=1))
+
+# Widening multiplication: `mul r/m32` and `imul r/m32` multiply EAX by the
+# operand into EDX:EAX.
+for inst,         rrr in [
+        (x86.umulx, 4),
+        (x86.smulx, 5)
+        ]:
+    I32.enc(inst.i32, Op1mulx, OP(0xf7, rrr))
+    I64.enc(inst.i32, RexOp1mulx, OP(0xf7, rrr))
+    I64.enc(inst.i64, RexOp1mulx, OP(0xf7, rrr, w=1))
+
+# Immediate arithmetic: `add r/m32, imm8` and `add r/m32, imm32` and friends.
+# The 8-bit immediate encoding is preferred when the immediate fits.
+for inst,               rrr in [
+        (base.iadd_imm, 0),
+        (base.bor_imm,  1),
+        (base.band_imm, 4),
+        (base.bxor_imm, 6)
+        ]:
+    I32.enc(inst.i32, Op1rib, OP(0x83, rrr))
+    I32.enc(inst.i32, Op1rid, OP(0x81, rrr))
+    I64.enc(inst.i32, RexOp1rib, OP(0x83, rrr))
+    I64.enc(inst.i32, RexOp1rid, OP(0x81, rrr))
+    I64.enc(inst.i64, RexOp1rib, OP(0x83, rrr, w=1))
+    I64.enc(inst.i64, RexOp1rid, OP(0x81, rrr, w=1))
+
+# Shifts by CL: `D3 /n`.
+for inst,           rrr in [
+        (base.ishl, 4),
+        (base.ushr, 5),
+        (base.sshr, 7)
+        ]:
+    I32.enc(inst.i32.i32, Op1rc, OP(0xd3, rrr))
+    I64.enc(inst.i32.i32, RexOp1rc, OP(0xd3, rrr))
+    I64.enc(inst.i64.i64, RexOp1rc, OP(0xd3, rrr, w=1))
+    # The shift amount is only read from CL, so its type doesn't matter.
+    I64.enc(inst.i32.i64, RexOp1rc, OP(0xd3, rrr))
+    I64.enc(inst.i64.i32, RexOp1rc, OP(0xd3, rrr, w=1))
+
+# Shifts by an immediate: `C1 /n ib`. Immediates that don't fit in 8 bits are
+# masked to the operand size by the legalizer.
+for inst,               rrr in [
+        (base.ishl_imm, 4),
+        (base.ushr_imm, 5),
+        (base.sshr_imm, 7)
+        ]:
+    I32.enc(inst.i32, Op1rib, OP(0xc1, rrr))
+    I64.enc(inst.i32, RexOp1rib, OP(0xc1, rrr))
+    I64.enc(inst.i64, RexOp1rib, OP(0xc1, rrr, w=1))
+
+# Bit counting instructions that depend on CPU features. On CPUs without
+# LZCNT and BMI1, the `lzcnt` and `tzcnt` opcodes are decoded as `bsr` and
+# `bsf` which compute something else, so the encodings must be gated on the
+# settings.
+for inst,             op,   isap in [
+        (base.popcnt, 0xb8, use_popcnt),
+        (base.clz,    0xbd, has_lzcnt),
+        (base.ctz,    0xbc, has_bmi1)
+        ]:
+    I32.enc(inst.i32, Mp2urm, MP(0xf3, op), isap=isap)
+    I64.enc(inst.i32, RexMp2urm, MP(0xf3, op), isap=isap)
+    I64.enc(inst.i64, RexMp2urm, MP(0xf3, op, w=1), isap=isap)
+
+# Integer constants: `mov r32, imm32`, which zero-extends to 64 bits.
+# Sign-extended 32-bit constants use `mov r/m64, imm32`, and only the other
+# 64-bit constants need the 10-byte `mov r64, imm64`.
+I32.enc(base.iconst.i32, Op1pu_id, OP(0xb8))
+I64.enc(base.iconst.i32, RexOp1pu_id, OP(0xb8))
+I64.enc(base.iconst.i64, RexOp1u_id, OP(0xc7, 0, w=1))
+I64.enc(base.iconst.i64, RexOp1pu_iq, OP(0xb8, w=1))
+
+# Boolean constants are 0 or 1 in a register, like the other `b1` values.
+I32.enc(base.bconst.b1, Op1pu_bool, OP(0xb8))
+I64.enc(base.bconst.b1, RexOp1pu_bool, OP(0xb8))
+
+# Loads and stores with the address in a register: `mov r32, m32` and
+# `mov m32, r32`. Addresses are 32 bits in 32-bit mode and 64 bits in 64-bit
+# mode.
+I32.enc(base.load.i32.i32, Op1ldDisp8, OP(0x8b))
+I32.enc(base.load.i32.i32, Op1ldDisp32, OP(0x8b))
+I64.enc(base.load.i32.i64, RexOp1ldDisp8, OP(0x8b))
+I64.enc(base.load.i32.i64, RexOp1ldDisp32, OP(0x8b))
+I64.enc(base.load.i64.i64, RexOp1ldDisp8, OP(0x8b, w=1))
+I64.enc(base.load.i64.i64, RexOp1ldDisp32, OP(0x8b, w=1))
+
+I32.enc(base.store.i32.i32, Op1stDisp8, OP(0x89))
+I32.enc(base.store.i32.i32, Op1stDisp32, OP(0x89))
+I64.enc(base.store.i32.i64, RexOp1stDisp8, OP(0x89))
+I64.enc(base.store.i32.i64, RexOp1stDisp32, OP(0x89))
+I64.enc(base.store.i64.i64, RexOp1stDisp8, OP(0x89, w=1))
+I64.enc(base.store.i64.i64, RexOp1stDisp32, OP(0x89, w=1))
+
+# The same loads and stores with a scaled index register added to the address.
+I32.enc(base.load_complex.i32.i32.i32, Op1ldIdxDisp8, OP(0x8b))
+I32.enc(base.load_complex.i32.i32.i32, Op1ldIdxDisp32, OP(0x8b))
+I64.enc(base.load_complex.i32.i64.i64, RexOp1ldIdxDisp8, OP(0x8b))
+I64.enc(base.load_complex.i32.i64.i64, RexOp1ldIdxDisp32, OP(0x8b))
+I64.enc(base.load_complex.i64.i64.i64, RexOp1ldIdxDisp8, OP(0x8b, w=1))
+I64.enc(base.load_complex.i64.i64.i64, RexOp1ldIdxDisp32, OP(0x8b, w=1))
+
+I32.enc(base.store_complex.i32.i32.i32, Op1stIdxDisp8, OP(0x89))
+I32.enc(base.store_complex.i32.i32.i32, Op1stIdxDisp32, OP(0x89))
+I64.enc(base.store_complex.i32.i64.i64, RexOp1stIdxDisp8, OP(0x89))
+I64.enc(base.store_complex.i32.i64.i64, RexOp1stIdxDisp32, OP(0x89))
+I64.enc(base.store_complex.i64.i64.i64, RexOp1stIdxDisp8, OP(0x89, w=1))
+I64.enc(base.store_complex.i64.i64.i64, RexOp1stIdxDisp32, OP(0x89, w=1))
+
+# Atomic memory operations. Aligned loads and stores are atomic, `xchg` with a
+# memory operand is implicitly locked, and the other read-modify-write
+# operations need a LOCK prefix. The custom legalization turns the
+# sequentially consistent stores into `xchg`, and the atomic bitwise operations
+# into `cmpxchg` loops. An `atomic_sub` is an `atomic_add` of the negation.
+for inst,               recipe,    rex_recipe,   op in [
+        (base.atomic_load,  Op1ald,    RexOp1ald,    0x8b),
+        (base.atomic_store, Op1ast,    RexOp1ast,    0x89),
+        (base.atomic_xchg,  Op1armw,   RexOp1armw,   0x87),
+        (base.atomic_add,   LkOp2armw, LkRexOp2armw, 0xc1),
+        (base.atomic_cas,   LkOp2acas, LkRexOp2acas, 0xb1)
+        ]:
+    I32.enc(inst.i32.i32, recipe, OP(op), isap=enable_atomics)
+    I64.enc(inst.i32.i64, rex_recipe, OP(op), isap=enable_atomics)
+    I64.enc(inst.i64.i64, rex_recipe, OP(op, w=1), isap=enable_atomics)
+
+I32.enc(base.copy.i32, Op1umr, OP(0x89))
+I64.enc(base.copy.i32, RexOp1umr, OP(0x89))
+I64.enc(base.copy.i64, RexOp1umr, OP(0x89, w=1))
+
+# The `b1` values are already 0 or 1, so `bint` is a plain register move.
+I32.enc(base.bint.i32.b1, Op1umr, OP(0x89))
+I64.enc(base.bint.i32.b1, RexOp1umr, OP(0x89))
+I64.enc(base.bint.i64.b1, RexOp1umr, OP(0x89))
+
+# Writing a 32-bit register clears the high bits, so `uextend.i64.i32` is a
+# 32-bit register move.
+I64.enc(base.uextend.i64.i32, RexOp1umr, OP(0x89))
+
+I32.enc(base.regmove.i32, Op1rmov, OP(0x89))
+I64.enc(base.regmove.i32, RexOp1rmov, OP(0x89))
+I64.enc(base.regmove.i64, RexOp1rmov, OP(0x89, w=1))
+
+# Stack probes only address the stack pointer, so they don't need a REX prefix.
+I32.enc(x86.probe, Op1probe, OP(0x83, rrr=1))
+I64.enc(x86.probe, Op1probe, OP(0x83, rrr=1))
+
+# The stack frame is allocated and freed with `add rsp, imm`.
+I32.enc(x86.adjust_sp, Op1adjustsp_ib, OP(0x83))
+I32.enc(x86.adjust_sp, Op1adjustsp_id, OP(0x81))
+I64.enc(x86.adjust_sp, RexOp1adjustsp_ib, OP(0x83, w=1))
+I64.enc(x86.adjust_sp, RexOp1adjustsp_id, OP(0x81, w=1))
+
+# The stack limit check compares the stack pointer with `cmp rsp, r`.
+I32.enc(x86.cmp_sp.i32, Op1cmpsp, OP(0x39))
+I64.enc(x86.cmp_sp.i64, RexOp1cmpsp, OP(0x39, w=1))
+
+# Spill and fill with `mov r/m32, r32` and `mov r32, r/m32` relative to the
+# stack pointer.
+I32.enc(base.spill.i32, Op1spill, OP(0x89))
+I32.enc(base.fill.i32, Op1fill, OP(0x8b))
+I64.enc(base.spill.i32, RexOp1spill, OP(0x89))
+I64.enc(base.fill.i32, RexOp1fill, OP(0x8b))
+I64.enc(base.spill.i64, RexOp1spill, OP(0x89, w=1))
+I64.enc(base.fill.i64, RexOp1fill, OP(0x8b, w=1))
+
+# References are pointer-sized, so they are moved, spilled, and stored like
+# integers. Only the native pointer width is supported.
+I32.enc(base.copy.r32, Op1umr, OP(0x89))
+I32.enc(base.regmove.r32, Op1rmov, OP(0x89))
+I32.enc(base.spill.r32, Op1spill, OP(0x89))
+I32.enc(base.fill.r32, Op1fill, OP(0x8b))
+I32.enc(base.load.r32.i32, Op1ldDisp8, OP(0x8b))
+I32.enc(base.load.r32.i32, Op1ldDisp32, OP(0x8b))
+I32.enc(base.store.r32.i32, Op1stDisp8, OP(0x89))
+I32.enc(base.store.r32.i32, Op1stDisp32, OP(0x89))
+I64.enc(base.copy.r64, RexOp1umr, OP(0x89, w=1))
+I64.enc(base.regmove.r64, RexOp1rmov, OP(0x89, w=1))
+I64.enc(base.spill.r64, RexOp1spill, OP(0x89, w=1))
+I64.enc(base.fill.r64, RexOp1fill, OP(0x8b, w=1))
+I64.enc(base.load.r64.i64, RexOp1ldDisp8, OP(0x8b, w=1))
+I64.enc(base.load.r64.i64, RexOp1ldDisp32, OP(0x8b, w=1))
+I64.enc(base.store.r64.i64, RexOp1stDisp8, OP(0x89, w=1))
+I64.enc(base.store.r64.i64, RexOp1stDisp32, OP(0x89, w=1))
+
+# Garbage collection safepoints.
+I32.enc(base.safepoint, safepoint, 0)
+I64.enc(base.safepoint, safepoint, 0)
+
+# Control flow.
+#
+# Each branch has a short form with an 8-bit displacement and a near form with
+# a 32-bit displacement. The legalizer picks the near form which can reach any
+# destination, and branch relaxation shrinks the branches that don't need it.
+# The opcode in the encbits is the same for both forms; the recipes emit the
+# right jump opcode. The spilling pass moves the values that are live across a
+# call to the stack, since the register allocator doesn't know which registers
+# the callee clobbers.
+
+# Unconditional jump: `jmp rel8` or `jmp rel32`.
+I32.enc(base.jump, Op1jmpb, OP(0xeb))
+I32.enc(base.jump, Op1jmpd, OP(0xe9))
+I64.enc(base.jump, Op1jmpb, OP(0xeb))
+I64.enc(base.jump, Op1jmpd, OP(0xe9))
+
+# Branches on a zero or non-zero register: `test r32, r32` and `jz`/`jnz`.
+for inst in [base.brz, base.brnz]:
+    I32.enc(inst.i32, Op1tjccb, OP(0x85))
+    I32.enc(inst.i32, Op1tjccd, OP(0x85))
+    I32.enc(inst.b1, Op1tjccb, OP(0x85))
+    I32.enc(inst.b1, Op1tjccd, OP(0x85))
+    I64.enc(inst.i32, RexOp1tjccb, OP(0x85))
+    I64.enc(inst.i32, RexOp1tjccd, OP(0x85))
+    I64.enc(inst.b1, RexOp1tjccb, OP(0x85))
+    I64.enc(inst.b1, RexOp1tjccd, OP(0x85))
+    I64.enc(inst.i64, RexOp1tjccb, OP(0x85, w=1))
+    I64.enc(inst.i64, RexOp1tjccd, OP(0x85, w=1))
+
+# Conditional branches comparing two registers: `cmp r/m32, r32` and `jcc`.
+I32.enc(base.br_icmp.i32, Op1cmpjccb, OP(0x39))
+I32.enc(base.br_icmp.i32, Op1cmpjccd, OP(0x39))
+I64.enc(base.br_icmp.i32, RexOp1cmpjccb, OP(0x39))
+I64.enc(base.br_icmp.i32, RexOp1cmpjccd, OP(0x39))
+I64.enc(base.br_icmp.i64, RexOp1cmpjccb, OP(0x39, w=1))
+I64.enc(base.br_icmp.i64, RexOp1cmpjccd, OP(0x39, w=1))
+
+# Traps: `ud2`, and `test c, c` followed by a `jz` or `jnz` over a `ud2`.
+I32.enc(base.trap, Op2trap, OP(0x0b))
+I64.enc(base.trap, Op2trap, OP(0x0b))
+for inst in [base.trapz, base.trapnz]:
+    I32.enc(inst.i32, Op1ttrap, OP(0x85))
+    I32.enc(inst.b1, Op1ttrap, OP(0x85))
+    I64.enc(inst.i32, RexOp1ttrap, OP(0x85))
+    I64.enc(inst.b1, RexOp1ttrap, OP(0x85))
+    I64.enc(inst.i64, RexOp1ttrap, OP(0x85, w=1))
+
+# Trap on a condition in the CPU flags: `jcc` over a `ud2` with the inverted
+# condition.
+I32.enc(base.trapif, Op1trapif, OP(0x70))
+I64.enc(base.trapif, Op1trapif, OP(0x70))
+
+# Conditional moves: `test c, c` and `cmovnz y, x`. The REX.W bit applies to
+# both the test and the move, so the condition must be a `b1` or have the same
+# width as the selected values. The other `select` instructions are expanded
+# into branches.
+I32.enc(base.select.i32.b1, Op2tcmov, OP(0x45))
+I32.enc(base.select.i32.i32, Op2tcmov, OP(0x45))
+I64.enc(base.select.i32.b1, RexOp2tcmov, OP(0x45))
+I64.enc(base.select.i32.i32, RexOp2tcmov, OP(0x45))
+I64.enc(base.select.i64.b1, RexOp2tcmov, OP(0x45, w=1))
+I64.enc(base.select.i64.i64, RexOp2tcmov, OP(0x45, w=1))
+
+# CPU flags: `cmp x, y` produces the flags of `x - y`, which `setcc` and
+# `cmovcc` test with the condition code of the instruction.
+I32.enc(base.ifcmp.i32, Op1rcmp, OP(0x39))
+I64.enc(base.ifcmp.i32, RexOp1rcmp, OP(0x39))
+I64.enc(base.ifcmp.i64, RexOp1rcmp, OP(0x39, w=1))
+I32.enc(base.trueif, Op2seti, OP(0x90))
+I64.enc(base.trueif, RexOp2seti, OP(0x90))
+I32.enc(base.selectif.i32, Op2cmov, OP(0x40))
+I64.enc(base.selectif.i32, RexOp2cmov, OP(0x40))
+I64.enc(base.selectif.i64, RexOp2cmov, OP(0x40, w=1))
+
+# Comparisons: `cmp x, y` followed by `setcc` and `movzx`.
+I32.enc(base.icmp.i32, Op1icscc, OP(0x39))
+I64.enc(base.icmp.i32, RexOp1icscc, OP(0x39))
+I64.enc(base.icmp.i64, RexOp1icscc, OP(0x39, w=1))
+
+I32.enc(base.x_return, Op1ret, OP(0xc3))
+I64.enc(base.x_return, Op1ret, OP(0xc3))
+
+not_pic = Not(is_pic)
+
+# The 64-bit code model is chosen per reference: Colocated symbols are within
+# reach of a 32-bit displacement, so they are always addressed relative to
+# RIP. Other symbols may be anywhere in the address space. Position-independent
+# code reaches them through the PLT and the GOT, and other code uses their
+# 64-bit absolute address. In 32-bit mode, all addresses fit in 32 bits.
+
+# Tail calls: `jmp rel32` to colocated functions.
+local_call = IsColocatedFunc(Call.func_ref)
+I32.enc(base.return_call, Op1tcall, OP(0xe9), isap=not_pic)
+I64.enc(base.return_call, Op1tcall, OP(0xe9), instp=local_call)
+I64.enc(
+        base.return_call, Op1tcall_plt, OP(0xe9),
+        instp=Not(local_call), isap=is_pic)
+I64.enc(
+        base.return_call, RexOp1tcall_abs, OP(0xff, 4),
+        instp=Not(local_call), isap=not_pic)
+
+# Calls: `call rel32` to colocated functions. Other functions are called
+# through the PLT or at their absolute address like the tail calls. Indirect
+# calls are `call r/m`.
+I32.enc(base.call, Op1call, OP(0xe8), isap=not_pic)
+I64.enc(base.call, Op1call, OP(0xe8), instp=local_call)
+I64.enc(
+        base.call, Op1call_plt, OP(0xe8),
+        instp=Not(local_call), isap=is_pic)
+I64.enc(
+        base.call, RexOp1call_abs, OP(0xff, 2),
+        instp=Not(local_call), isap=not_pic)
+I32.enc(base.call_indirect.i32, Op1call_r, OP(0xff, 2))
+I64.enc(base.call_indirect.i64, RexOp1call_r, OP(0xff, 2))
+
+# Symbol addresses: An immediate absolute address, `lea` relative to RIP, or
+# a load from the GOT.
+local_func = IsColocatedFunc(FuncAddr.func_ref)
+I32.enc(base.func_addr.i32, Op1fnaddr, OP(0xb8), isap=not_pic)
+I64.enc(
+        base.func_addr.i64, RexOp1pcrel_fnaddr, OP(0x8d, w=1),
+        instp=local_func)
+I64.enc(
+        base.func_addr.i64, RexOp1fnaddr, OP(0xb8, w=1),
+        instp=Not(local_func), isap=not_pic)
+I64.enc(
+        base.func_addr.i64, RexOp1got_fnaddr, OP(0x8b, w=1),
+        instp=Not(local_func), isap=is_pic)
+
+local_data = IsColocatedData(UnaryGlobalVar.global_var)
+I32.enc(base.globalsym_addr.i32, Op1gvaddr, OP(0xb8), isap=not_pic)
+I64.enc(
+        base.globalsym_addr.i64, RexOp1pcrel_gvaddr, OP(0x8d, w=1),
+        instp=local_data)
+I64.enc(
+        base.globalsym_addr.i64, RexOp1gvaddr, OP(0xb8, w=1),
+        instp=Not(local_data), isap=not_pic)
+I64.enc(
+        base.globalsym_addr.i64, RexOp1got_gvaddr, OP(0x8b, w=1),
+        instp=Not(local_data), isap=is_pic)
+
+# Jump tables: The `br_table` instructions are legalized into a load from the
+# jump table followed by an indirect jump. The 64-bit code always addresses
+# the table relative to RIP. Position-independent code uses 32-bit entries
+# relative to the table, which are sign-extended with `movsxd`.
+I32.enc(base.jump_table_base.i32, Op1jt_base, OP(0xb8), isap=not_pic)
+I64.enc(base.jump_table_base.i64, RexOp1jt_base, OP(0x8d, w=1))
+I32.enc(base.jump_table_entry.i32, Op1jt_entry, OP(0x8b), isap=not_pic)
+I64.enc(
+        base.jump_table_entry.i64, RexOp1jt_entry, OP(0x8b, w=1),
+        isap=not_pic)
+I64.enc(
+        base.jump_table_entry.i64, RexOp1jt_entry, OP(0x63, w=1),
+        isap=is_pic)
+I32.enc(base.indirect_jump_table_br.i32, Op1indirect_jmp, OP(0xff, rrr=4))
+I64.enc(
+        base.indirect_jump_table_br.i64, RexOp1indirect_jmp,
+        OP(0xff, rrr=4))
+
+# Floating point.
+#
+# All 64-bit CPUs support SSE2, but the 32-bit encodings depend on the
+# `has_sse2` setting.
+
+# Scalar arithmetic: `addss xmm1, xmm2/m32` and `addsd xmm1, xmm2/m64`.
+for inst,           op in [
+        (base.fadd, 0x58),
+        (base.fsub, 0x5c),
+        (base.fmul, 0x59),
+        (base.fdiv, 0x5e)
+        ]:
+    I32.enc(inst.f32, Mp2fa, MP(0xf3, op), isap=has_sse2)
+    I32.enc(inst.f64, Mp2fa, MP(0xf2, op), isap=has_sse2)
+    I64.enc(inst.f32, RexMp2fa, MP(0xf3, op))
+    I64.enc(inst.f64, RexMp2fa, MP(0xf2, op))
+
+# Square root: `sqrtss xmm1, xmm2/m32` and `sqrtsd xmm1, xmm2/m64`.
+I32.enc(base.sqrt.f32, Mp2furm, MP(0xf3, 0x51), isap=has_sse2)
+I32.enc(base.sqrt.f64, Mp2furm, MP(0xf2, 0x51), isap=has_sse2)
+I64.enc(base.sqrt.f32, RexMp2furm, MP(0xf3, 0x51))
+I64.enc(base.sqrt.f64, RexMp2furm, MP(0xf2, 0x51))
+
+# Signed integer conversions: `cvtsi2ss xmm, r/m32` and `cvttss2si r32,
+# xmm/m32`. The REX.W bit selects a 64-bit integer operand. The conversions to
+# integers don't trap yet. Instead, NaN and out-of-range inputs produce the
+# integer indefinite value with only the sign bit set.
+I32.enc(base.fcvt_from_sint.f32.i32, Mp2frurm, MP(0xf3, 0x2a), isap=has_sse2)
+I32.enc(base.fcvt_from_sint.f64.i32, Mp2frurm, MP(0xf2, 0x2a), isap=has_sse2)
+I64.enc(base.fcvt_from_sint.f32.i32, RexMp2frurm, MP(0xf3, 0x2a))
+I64.enc(base.fcvt_from_sint.f64.i32, RexMp2frurm, MP(0xf2, 0x2a))
+I64.enc(base.fcvt_from_sint.f32.i64, RexMp2frurm, MP(0xf3, 0x2a, w=1))
+I64.enc(base.fcvt_from_sint.f64.i64, RexMp2frurm, MP(0xf2, 0x2a, w=1))
+
+I32.enc(base.fcvt_to_sint.i32.f32, Mp2rfurm, MP(0xf3, 0x2c), isap=has_sse2)
+I32.enc(base.fcvt_to_sint.i32.f64, Mp2rfurm, MP(0xf2, 0x2c), isap=has_sse2)
+I64.enc(base.fcvt_to_sint.i32.f32, RexMp2rfurm, MP(0xf3, 0x2c))
+I64.enc(base.fcvt_to_sint.i32.f64, RexMp2rfurm, MP(0xf2, 0x2c))
+I64.enc(base.fcvt_to_sint.i64.f32, RexMp2rfurm, MP(0xf3, 0x2c, w=1))
+I64.enc(base.fcvt_to_sint.i64.f64, RexMp2rfurm, MP(0xf2, 0x2c, w=1))
+
+# There are no saturating conversions before AVX-512. The custom legalization
+# of `fcvt_to_sint_sat` and `fcvt_to_uint_sat` fixes up the results of the
+# signed conversions above with comparisons and conditional moves.
+
+# Moves between GPRs and XMM registers: `movd xmm, r/m32` and `movd r/m32,
+# xmm`, or `movq` with REX.W.
+I32.enc(base.bitcast.f32.i32, Mp2frurm, MP(0x66, 0x6e), isap=has_sse2)
+I32.enc(base.bitcast.i32.f32, Mp2rfumr, MP(0x66, 0x7e), isap=has_sse2)
+I64.enc(base.bitcast.f32.i32, RexMp2frurm, MP(0x66, 0x6e))
+I64.enc(base.bitcast.i32.f32, RexMp2rfumr, MP(0x66, 0x7e))
+I64.enc(base.bitcast.f64.i64, RexMp2frurm, MP(0x66, 0x6e, w=1))
+I64.enc(base.bitcast.i64.f64, RexMp2rfumr, MP(0x66, 0x7e, w=1))
+
+# Comparisons: `ucomiss` or `ucomisd` followed by `setcc` and `movzx`.
+I32.enc(base.fcmp.f32, Op2fcscc, OP(0x2e), isap=has_sse2)
+I32.enc(base.fcmp.f64, Mp2fcscc, MP(0x66, 0x2e), isap=has_sse2)
+I64.enc(base.fcmp.f32, RexOp2fcscc, OP(0x2e))
+I64.enc(base.fcmp.f64, RexMp2fcscc, MP(0x66, 0x2e))
+
+# Rounding with SSE4.1: `roundss xmm1, xmm2/m32, imm8` and `roundsd`. The
+# immediate selects the rounding mode. Without SSE4.1, the custom legalization
+# calls the C library rounding functions instead.
+for inst,              mode in [
+        (base.nearest, 0b00),
+        (base.floor,   0b01),
+        (base.ceil,    0b10),
+        (base.trunc,   0b11)
+        ]:
+    I32.enc(inst.f32, Mp3furmi_rnd, MP(0x66, 0x0a, mode), isap=has_sse41)
+    I32.enc(inst.f64, Mp3furmi_rnd, MP(0x66, 0x0b, mode), isap=has_sse41)
+    I64.enc(inst.f32, RexMp3furmi_rnd, MP(0x66, 0x0a, mode), isap=has_sse41)
+    I64.enc(inst.f64, RexMp3furmi_rnd, MP(0x66, 0x0b, mode), isap=has_sse41)
+
+# Register copies use `movaps` for both types, which avoids a dependency on
+# the old contents of the destination register.
+for ty in [f32, f64]:
+    I32.enc(base.copy.bind(ty), Op2furm, OP(0x28), isap=has_sse2)
+    I32.enc(base.regmove.bind(ty), Op2frmov, OP(0x28), isap=has_sse2)
+    I64.enc(base.copy.bind(ty), RexOp2furm, OP(0x28))
+    I64.enc(base.regmove.bind(ty), RexOp2frmov, OP(0x28))
+
+# Spill and fill with `movss` and `movsd`.
+for ty,  pp in [
+        (f32, 0xf3),
+        (f64, 0xf2)
+        ]:
+    I32.enc(base.spill.bind(ty), Mp2fspill, MP(pp, 0x11), isap=has_sse2)
+    I32.enc(base.fill.bind(ty), Mp2ffill, MP(pp, 0x10), isap=has_sse2)
+    I64.enc(base.spill.bind(ty), RexMp2fspill, MP(pp, 0x11))
+    I64.enc(base.fill.bind(ty), RexMp2ffill, MP(pp, 0x10))
+
+# The 128-bit vector types live in whole XMM registers. Loads and stores use
+# `movaps` when the `aligned` flag promises a 16-byte aligned address, since it
+# is faster on older processors, and `movups` otherwise. Spill slots are not
+# guaranteed to be aligned to 16 bytes, so spills and fills use `movups`.
+load_aligned = IsAligned(Load.flags)
+store_aligned = IsAligned(Store.flags)
+for ty in [i8.by(16), i16.by(8), i32.by(4), i64.by(2), f32.by(4), f64.by(2)]:
+    for ld, st, ldp, stp in [
+            (0x28, 0x29, load_aligned, store_aligned),
+            (0x10, 0x11, Not(load_aligned), Not(store_aligned))
+            ]:
+        I32.enc(base.load.bind(ty, i32), Op2fldDisp8, OP(ld),
+                instp=ldp, isap=has_sse2)
+        I32.enc(base.load.bind(ty, i32), Op2fldDisp32, OP(ld),
+                instp=ldp, isap=has_sse2)
+        I64.enc(base.load.bind(ty, i64), RexOp2fldDisp8, OP(ld), instp=ldp)
+        I64.enc(base.load.bind(ty, i64), RexOp2fldDisp32, OP(ld), instp=ldp)
+        I32.enc(base.store.bind(ty, i32), Op2fstDisp8, OP(st),
+                instp=stp, isap=has_sse2)
+        I32.enc(base.store.bind(ty, i32), Op2fstDisp32, OP(st),
+                instp=stp, isap=has_sse2)
+        I64.enc(base.store.bind(ty, i64), RexOp2fstDisp8, OP(st), instp=stp)
+        I64.enc(base.store.bind(ty, i64), RexOp2fstDisp32, OP(st), instp=stp)
+
+    I32.enc(base.copy.bind(ty), Op2furm, OP(0x28), isap=has_sse2)
+    I32.enc(base.regmove.bind(ty), Op2frmov, OP(0x28), isap=has_sse2)
+    I32.enc(base.spill.bind(ty), Op2fspill, OP(0x11), isap=has_sse2)
+    I32.enc(base.fill.bind(ty), Op2ffill, OP(0x10), isap=has_sse2)
+    I64.enc(base.copy.bind(ty), RexOp2furm, OP(0x28))
+    I64.enc(base.regmove.bind(ty), RexOp2frmov, OP(0x28))
+    I64.enc(base.spill.bind(ty), RexOp2fspill, OP(0x11))
+    I64.enc(base.fill.bind(ty), RexOp2ffill, OP(0x10))
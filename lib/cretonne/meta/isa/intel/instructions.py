@@ -0,0 +1,111 @@
+"""
+Supplementary instruction definitions for Intel.
+
+This module defines additional instructions that are useful only to the Intel
+target ISA.
+"""
+from __future__ import absolute_import
+from cdsl.operands import Operand
+from cdsl.typevar import TypeVar
+from cdsl.instructions import Instruction, InstructionGroup
+from base.immediates import imm64
+from base.types import iflags
+
+
+GROUP = InstructionGroup("x86", "Intel-specific instruction set")
+
+iWord = TypeVar('iWord', 'A scalar integer machine word', ints=(32, 64))
+
+nlo = Operand('nlo', iWord, doc='Low part of numerator')
+nhi = Operand('nhi', iWord, doc='High part of numerator')
+d = Operand('d', iWord, doc='Denominator')
+q = Operand('q', iWord, doc='Quotient')
+r = Operand('r', iWord, doc='Remainder')
+
+udivmodx = Instruction(
+        'x86_udivmodx', r"""
+        Extended unsigned division.
+
+        Concatenate the bits in `nhi` and `nlo` to form the numerator.
+        Interpret the bits as an unsigned number and divide by the unsigned
+        denominator `d`. Trap when `d` is zero or if the quotient is larger
+        than the range of the output.
+
+        Return both quotient and remainder.
+        """,
+        ins=(nlo, nhi, d), outs=(q, r), can_trap=True)
+
+sdivmodx = Instruction(
+        'x86_sdivmodx', r"""
+        Extended signed division.
+
+        Concatenate the bits in `nhi` and `nlo` to form the numerator.
+        Interpret the bits as a signed number and divide by the signed
+        denominator `d`. Trap when `d` is zero or if the quotient is outside
+        the range of the output.
+
+        Return both quotient and remainder.
+        """,
+        ins=(nlo, nhi, d), outs=(q, r), can_trap=True)
+
+x = Operand('x', iWord)
+y = Operand('y', iWord)
+lo = Operand('lo', iWord, doc='Low part of the product')
+hi = Operand('hi', iWord, doc='High part of the product')
+
+umulx = Instruction(
+        'x86_umulx', r"""
+        Extended unsigned multiplication.
+
+        Interpret `x` and `y` as unsigned numbers and compute the
+        double-width product. Return its low and high parts.
+        """,
+        ins=(x, y), outs=(lo, hi))
+
+smulx = Instruction(
+        'x86_smulx', r"""
+        Extended signed multiplication.
+
+        Interpret `x` and `y` as signed numbers and compute the double-width
+        product. Return its low and high parts.
+        """,
+        ins=(x, y), outs=(lo, hi))
+
+Offset = Operand('Offset', imm64, 'Byte offset from the stack pointer')
+
+probe = Instruction(
+        'x86_probe', r"""
+        Probe the stack.
+
+        Touch the stack memory ``Offset`` bytes above the stack pointer
+        without changing it. This is inserted in the prologue of functions
+        with large stack frames, so each page of the frame is touched in order
+        from the top down.
+        """,
+        ins=Offset, can_load=True, can_store=True)
+
+adjust_sp = Instruction(
+        'x86_adjust_sp', r"""
+        Adjust the stack pointer.
+
+        Add ``Offset`` to the stack pointer. This is inserted with a negative
+        offset at the top of the prologue to allocate the stack frame, and
+        with a positive offset before each return to free it again.
+        """,
+        ins=Offset)
+
+limit = Operand('limit', iWord, doc='Lowest permitted stack pointer')
+f = Operand('f', iflags)
+
+cmp_sp = Instruction(
+        'x86_cmp_sp', r"""
+        Compare the stack pointer to a limit.
+
+        Set the CPU flags like ``ifcmp`` comparing the stack pointer to
+        ``limit``. This is inserted in the prologue of functions with a stack
+        limit, followed by a ``trapif`` that traps with ``stk_ovf`` when the
+        stack pointer is below the limit.
+        """,
+        ins=limit, outs=f)
+
+GROUP.close()
@@ -0,0 +1,673 @@
+"""
+Intel Encoding recipes.
+"""
+from __future__ import absolute_import
+from cdsl.isa import EncRecipe
+from cdsl.predicates import IsSignedInt, IsUnsignedInt, IsEqual, And, Or, Not
+from base.formats import Unary, UnaryImm, UnaryBool, Binary, BinaryImm, Ternary, Return
+from base.formats import BinaryOverflow, TernaryOverflow, BinaryTrap
+from base.formats import AtomicLoad, AtomicRmw, AtomicCas
+from base.formats import RegMove, FuncAddr, UnaryGlobalVar, Call, IndirectCall
+from base.formats import Load, Store, LoadComplex, StoreComplex
+from base.formats import Jump, Branch, BranchIcmp, IntCompare, FloatCompare
+from base.formats import IntCond, IntSelect, IntCondTrap
+from base.formats import Trap, CondTrap
+from base.formats import BranchTable, BranchTableBase, BranchTableEntry
+from cdsl.registers import Stack
+from .registers import GPR, ABCD, FPR, FLAG
+
+# Opcode representation.
+#
+# Cretonne requires each recipe to have a single encoding size in bytes, and
+# Intel opcodes are variable length, so we use separate recipes for different
+# styles of opcodes and prefixes. The opcode format is indicated by the recipe
+# name prefix:
+#
+# Op1*     OP
+# Op2*     0F OP
+# RexOp1*  REX OP
+# RexOp2*  REX 0F OP
+# Mp2*     PP 0F OP
+# RexMp2*  PP REX 0F OP
+# Mp3*     PP 0F 3A OP
+# RexMp3*  PP REX 0F 3A OP
+# LkOp2*   F0 0F OP
+# LkRexOp2* F0 REX 0F OP
+#
+# The encbits for these recipes are `op | (rrr << 8) | (w << 11) | (pp << 12)`,
+# where `rrr` is the opcode extension that goes in the reg field of the ModR/M
+# byte, `w` is the REX.W bit selecting a 64-bit operand size, and `pp` selects
+# the mandatory prefix of the Mp* recipes. The `pp` field uses the same
+# numbering as the VEX prefix: 1 = 66, 2 = F3, 3 = F2. The rounding recipes
+# don't have an opcode extension, so they keep the rounding mode immediate in
+# the `rrr` field instead. The Lk* recipes emit a LOCK prefix to make a
+# read-modify-write memory access atomic.
+#
+# The Rex* recipes always emit a REX prefix, even when it only holds the high
+# bits of the register numbers. This makes the recipe size independent of the
+# register assignment, so the extended registers `r8`-`r15` can be used by any
+# operand. The recipes without a REX prefix can only address the first eight
+# registers, so they are only used in 32-bit mode.
+
+
+def OP(op, rrr=0, w=0):
+    # type: (int, int, int) -> int
+    assert op <= 0xff
+    assert rrr <= 0b111
+    assert w <= 1
+    return op | (rrr << 8) | (w << 11)
+
+
+# Mandatory prefix bytes indexed by the `pp` field in the encbits.
+PREFIXES = (None, 0x66, 0xf3, 0xf2)
+
+
+def MP(pp, op, rrr=0, w=0):
+    # type: (int, int, int, int) -> int
+    assert pp in PREFIXES[1:]
+    return OP(op, rrr, w) | (PREFIXES.index(pp) << 12)
+
+
+# XX /r with the register operands reversed. The two-address instructions
+# overwrite their first operand, so the result is tied to it.
+Op1rr = EncRecipe(
+        'Op1rr', Binary, size=2, ins=(GPR, GPR), outs=0, clobbers_flags=True)
+RexOp1rr = EncRecipe(
+        'RexOp1rr', Binary, size=3, ins=(GPR, GPR), outs=0,
+        clobbers_flags=True)
+
+# Arithmetic with carry and borrow flags.
+#
+# The `b1` carry and borrow values live in registers, so they are moved to and
+# from the CF flag around the `adc` and `sbb` style instructions: `bt c, 0`
+# copies the low bit of the carry input to CF, and `setc` followed by `movzx`
+# materializes the carry output. Without a REX prefix, only the first four
+# registers have an addressable low byte for `setc`.
+
+# XX /r followed by `0F 92` and `0F B6`: Two-address arithmetic that also
+# produces the carry or borrow flag, like `iadd_cout`.
+Op1rout = EncRecipe(
+        'Op1rout', BinaryOverflow, size=8, ins=(GPR, GPR), outs=(0, ABCD),
+        clobbers_flags=True)
+RexOp1rout = EncRecipe(
+        'RexOp1rout', BinaryOverflow, size=11, ins=(GPR, GPR), outs=(0, GPR),
+        clobbers_flags=True)
+
+# `0F BA /4 ib` followed by XX /r: Two-address arithmetic that consumes a
+# carry or borrow flag, like `iadd_cin`.
+Op1rin = EncRecipe(
+        'Op1rin', Ternary, size=6, ins=(GPR, GPR, GPR), outs=0,
+        clobbers_flags=True)
+RexOp1rin = EncRecipe(
+        'RexOp1rin', Ternary, size=8, ins=(GPR, GPR, GPR), outs=0,
+        clobbers_flags=True)
+
+# Both of the above, for `iadd_carry` and `isub_borrow`.
+Op1rio = EncRecipe(
+        'Op1rio', TernaryOverflow, size=12, ins=(GPR, GPR, GPR),
+        outs=(0, ABCD), clobbers_flags=True)
+RexOp1rio = EncRecipe(
+        'RexOp1rio', TernaryOverflow, size=16, ins=(GPR, GPR, GPR),
+        outs=(0, GPR), clobbers_flags=True)
+
+# XX /r followed by `7x 02` and `0F 0B`: Two-address arithmetic that jumps
+# over a `ud2` unless it overflowed. The signed instructions test OF with
+# `jno`, and the unsigned ones test CF with `jnc`.
+Op1rtrap = EncRecipe(
+        'Op1rtrap', BinaryTrap, size=6, ins=(GPR, GPR), outs=0,
+        clobbers_flags=True)
+RexOp1rtrap = EncRecipe(
+        'RexOp1rtrap', BinaryTrap, size=7, ins=(GPR, GPR), outs=0,
+        clobbers_flags=True)
+
+# 0F XX /r with the result in the reg field of the ModR/M byte, like
+# `imul r32, r/m32`. The result is also tied to the first operand.
+Op2rr = EncRecipe(
+        'Op2rr', Binary, size=3, ins=(GPR, GPR), outs=0, clobbers_flags=True)
+RexOp2rr = EncRecipe(
+        'RexOp2rr', Binary, size=4, ins=(GPR, GPR), outs=0,
+        clobbers_flags=True)
+
+# XX /n for a shift or rotate by the count in CL.
+Op1rc = EncRecipe(
+        'Op1rc', Binary, size=2, ins=(GPR, GPR.rcx), outs=0,
+        clobbers_flags=True)
+RexOp1rc = EncRecipe(
+        'RexOp1rc', Binary, size=3, ins=(GPR, GPR.rcx), outs=0,
+        clobbers_flags=True)
+
+# XX /n for division: the numerator is in RDX:RAX, the divisor is the r/m
+# operand, and the quotient and remainder are returned in RAX and RDX.
+Op1div = EncRecipe(
+        'Op1div', TernaryOverflow, size=2, ins=(GPR.rax, GPR.rdx, GPR),
+        outs=(GPR.rax, GPR.rdx), clobbers_flags=True)
+RexOp1div = EncRecipe(
+        'RexOp1div', TernaryOverflow, size=3, ins=(GPR.rax, GPR.rdx, GPR),
+        outs=(GPR.rax, GPR.rdx), clobbers_flags=True)
+
+# XX /n for a widening multiplication of RAX by the r/m operand. The low and
+# high parts of the product are returned in RAX and RDX.
+Op1mulx = EncRecipe(
+        'Op1mulx', BinaryOverflow, size=2, ins=(GPR.rax, GPR),
+        outs=(GPR.rax, GPR.rdx), clobbers_flags=True)
+RexOp1mulx = EncRecipe(
+        'RexOp1mulx', BinaryOverflow, size=3, ins=(GPR.rax, GPR),
+        outs=(GPR.rax, GPR.rdx), clobbers_flags=True)
+
+# XX /n ib with an 8-bit immediate sign-extended to the operand size.
+Op1rib = EncRecipe(
+        'Op1rib', BinaryImm, size=3, ins=GPR, outs=0, clobbers_flags=True,
+        instp=IsSignedInt(BinaryImm.imm, 8))
+RexOp1rib = EncRecipe(
+        'RexOp1rib', BinaryImm, size=4, ins=GPR, outs=0, clobbers_flags=True,
+        instp=IsSignedInt(BinaryImm.imm, 8))
+
+# XX /n id with a 32-bit immediate sign-extended to the operand size.
+Op1rid = EncRecipe(
+        'Op1rid', BinaryImm, size=6, ins=GPR, outs=0, clobbers_flags=True,
+        instp=IsSignedInt(BinaryImm.imm, 32))
+RexOp1rid = EncRecipe(
+        'RexOp1rid', BinaryImm, size=7, ins=GPR, outs=0, clobbers_flags=True,
+        instp=IsSignedInt(BinaryImm.imm, 32))
+
+# XX+rd id with a 32-bit immediate. The register is encoded in the low bits of
+# the opcode byte.
+Op1pu_id = EncRecipe('Op1pu_id', UnaryImm, size=5, ins=(), outs=GPR)
+RexOp1pu_id = EncRecipe('RexOp1pu_id', UnaryImm, size=6, ins=(), outs=GPR)
+
+# XX+rd id with a boolean constant as a 32-bit immediate 0 or 1.
+Op1pu_bool = EncRecipe('Op1pu_bool', UnaryBool, size=5, ins=(), outs=GPR)
+RexOp1pu_bool = EncRecipe(
+        'RexOp1pu_bool', UnaryBool, size=6, ins=(), outs=GPR)
+
+# REX.W XX /n id with a 32-bit immediate sign-extended to 64 bits.
+RexOp1u_id = EncRecipe(
+        'RexOp1u_id', UnaryImm, size=7, ins=(), outs=GPR,
+        instp=IsSignedInt(UnaryImm.imm, 32))
+
+# REX.W XX+rd iq with a full 64-bit immediate.
+RexOp1pu_iq = EncRecipe('RexOp1pu_iq', UnaryImm, size=10, ins=(), outs=GPR)
+
+# XX /r register-to-register move.
+Op1umr = EncRecipe('Op1umr', Unary, size=2, ins=GPR, outs=GPR)
+RexOp1umr = EncRecipe('RexOp1umr', Unary, size=3, ins=GPR, outs=GPR)
+
+# XX /r register-to-register move for a register diversion. The source and
+# destination registers come from the instruction's immediate operands.
+Op1rmov = EncRecipe('Op1rmov', RegMove, size=2, ins=GPR, outs=())
+RexOp1rmov = EncRecipe('RexOp1rmov', RegMove, size=3, ins=GPR, outs=())
+
+# XX /r load from the address in a register plus an 8-bit or 32-bit
+# displacement. A SIB byte is always emitted so any register can be the base,
+# including `rsp` and `r12`.
+Op1ldDisp8 = EncRecipe(
+        'Op1ldDisp8', Load, size=4, ins=GPR, outs=GPR,
+        instp=IsSignedInt(Load.offset, 8))
+RexOp1ldDisp8 = EncRecipe(
+        'RexOp1ldDisp8', Load, size=5, ins=GPR, outs=GPR,
+        instp=IsSignedInt(Load.offset, 8))
+Op1ldDisp32 = EncRecipe('Op1ldDisp32', Load, size=7, ins=GPR, outs=GPR)
+RexOp1ldDisp32 = EncRecipe('RexOp1ldDisp32', Load, size=8, ins=GPR, outs=GPR)
+
+# XX /r store of the first operand to the address in the second operand plus
+# an 8-bit or 32-bit displacement. A SIB byte is always emitted.
+Op1stDisp8 = EncRecipe(
+        'Op1stDisp8', Store, size=4, ins=(GPR, GPR), outs=(),
+        instp=IsSignedInt(Store.offset, 8))
+RexOp1stDisp8 = EncRecipe(
+        'RexOp1stDisp8', Store, size=5, ins=(GPR, GPR), outs=(),
+        instp=IsSignedInt(Store.offset, 8))
+Op1stDisp32 = EncRecipe('Op1stDisp32', Store, size=7, ins=(GPR, GPR), outs=())
+RexOp1stDisp32 = EncRecipe(
+        'RexOp1stDisp32', Store, size=8, ins=(GPR, GPR), outs=())
+
+# XX /r load from `base + (index << shift) + disp` with the base and index in
+# registers and an 8-bit or 32-bit displacement. The shift goes in the scale
+# field of the SIB byte, so it can't be larger than 3. The index can't be
+# `rsp`, but that register is never allocated.
+Op1ldIdxDisp8 = EncRecipe(
+        'Op1ldIdxDisp8', LoadComplex, size=4, ins=(GPR, GPR), outs=GPR,
+        instp=And(IsUnsignedInt(LoadComplex.shift, 2),
+                  IsSignedInt(LoadComplex.offset, 8)))
+RexOp1ldIdxDisp8 = EncRecipe(
+        'RexOp1ldIdxDisp8', LoadComplex, size=5, ins=(GPR, GPR), outs=GPR,
+        instp=And(IsUnsignedInt(LoadComplex.shift, 2),
+                  IsSignedInt(LoadComplex.offset, 8)))
+Op1ldIdxDisp32 = EncRecipe(
+        'Op1ldIdxDisp32', LoadComplex, size=7, ins=(GPR, GPR), outs=GPR,
+        instp=IsUnsignedInt(LoadComplex.shift, 2))
+RexOp1ldIdxDisp32 = EncRecipe(
+        'RexOp1ldIdxDisp32', LoadComplex, size=8, ins=(GPR, GPR), outs=GPR,
+        instp=IsUnsignedInt(LoadComplex.shift, 2))
+
+# XX /r store of the first operand to `base + (index << shift) + disp`, with
+# the same SIB byte as the loads above.
+Op1stIdxDisp8 = EncRecipe(
+        'Op1stIdxDisp8', StoreComplex, size=4, ins=(GPR, GPR, GPR), outs=(),
+        instp=And(IsUnsignedInt(StoreComplex.shift, 2),
+                  IsSignedInt(StoreComplex.offset, 8)))
+RexOp1stIdxDisp8 = EncRecipe(
+        'RexOp1stIdxDisp8', StoreComplex, size=5, ins=(GPR, GPR, GPR), outs=(),
+        instp=And(IsUnsignedInt(StoreComplex.shift, 2),
+                  IsSignedInt(StoreComplex.offset, 8)))
+Op1stIdxDisp32 = EncRecipe(
+        'Op1stIdxDisp32', StoreComplex, size=7, ins=(GPR, GPR, GPR), outs=(),
+        instp=IsUnsignedInt(StoreComplex.shift, 2))
+RexOp1stIdxDisp32 = EncRecipe(
+        'RexOp1stIdxDisp32', StoreComplex, size=8,
+        ins=(GPR, GPR, GPR), outs=(),
+        instp=IsUnsignedInt(StoreComplex.shift, 2))
+
+# The atomic memory operations address memory with a SIB byte and a zero 8-bit
+# displacement, like the loads and stores above.
+#
+# XX /r atomic load like `mov r32, m32`. Aligned loads are atomic, and they
+# are never reordered with other loads.
+Op1ald = EncRecipe('Op1ald', AtomicLoad, size=4, ins=GPR, outs=GPR)
+RexOp1ald = EncRecipe('RexOp1ald', AtomicLoad, size=5, ins=GPR, outs=GPR)
+
+# XX /r atomic store like `mov m32, r32`. Aligned stores are atomic with
+# release ordering. A sequentially consistent store needs a full barrier, so
+# it is legalized into an `xchg` instead.
+not_seq_cst = Not(IsEqual(AtomicRmw.ordering, 'AtomicOrdering::SeqCst'))
+Op1ast = EncRecipe(
+        'Op1ast', AtomicRmw, size=4, ins=(GPR, GPR), outs=(),
+        instp=not_seq_cst)
+RexOp1ast = EncRecipe(
+        'RexOp1ast', AtomicRmw, size=5, ins=(GPR, GPR), outs=(),
+        instp=not_seq_cst)
+
+# XX /r read-modify-write access which is atomic without a LOCK prefix, like
+# `xchg m32, r32`. The old value is returned in the register operand.
+Op1armw = EncRecipe('Op1armw', AtomicRmw, size=4, ins=(GPR, GPR), outs=1)
+RexOp1armw = EncRecipe(
+        'RexOp1armw', AtomicRmw, size=5, ins=(GPR, GPR), outs=1)
+
+# F0 0F XX /r locked read-modify-write access like `lock xadd m32, r32`. The
+# old value is returned in the register operand.
+LkOp2armw = EncRecipe(
+        'LkOp2armw', AtomicRmw, size=6, ins=(GPR, GPR), outs=1,
+        clobbers_flags=True)
+LkRexOp2armw = EncRecipe(
+        'LkRexOp2armw', AtomicRmw, size=7, ins=(GPR, GPR), outs=1,
+        clobbers_flags=True)
+
+# F0 0F XX /r `lock cmpxchg m32, r32` compares RAX with the memory operand, and
+# stores the register operand if they are equal. The old value is returned in
+# RAX either way.
+LkOp2acas = EncRecipe(
+        'LkOp2acas', AtomicCas, size=6, ins=(GPR, GPR.rax, GPR),
+        outs=GPR.rax, clobbers_flags=True)
+LkRexOp2acas = EncRecipe(
+        'LkRexOp2acas', AtomicCas, size=7, ins=(GPR, GPR.rax, GPR),
+        outs=GPR.rax, clobbers_flags=True)
+
+# XX /1 ib: `or dword [rsp+disp32], 0` probes the stack without changing the
+# memory.
+Op1probe = EncRecipe(
+        'Op1probe', UnaryImm, size=8, ins=(), outs=(), clobbers_flags=True)
+
+# XX /0 ib or XX /0 id: `add rsp, imm` adjusts the stack pointer by an 8-bit
+# or 32-bit immediate.
+Op1adjustsp_ib = EncRecipe(
+        'Op1adjustsp_ib', UnaryImm, size=3, ins=(), outs=(),
+        clobbers_flags=True, instp=IsSignedInt(UnaryImm.imm, 8))
+RexOp1adjustsp_ib = EncRecipe(
+        'RexOp1adjustsp_ib', UnaryImm, size=4, ins=(), outs=(),
+        clobbers_flags=True, instp=IsSignedInt(UnaryImm.imm, 8))
+Op1adjustsp_id = EncRecipe(
+        'Op1adjustsp_id', UnaryImm, size=6, ins=(), outs=(),
+        clobbers_flags=True, instp=IsSignedInt(UnaryImm.imm, 32))
+RexOp1adjustsp_id = EncRecipe(
+        'RexOp1adjustsp_id', UnaryImm, size=7, ins=(), outs=(),
+        clobbers_flags=True, instp=IsSignedInt(UnaryImm.imm, 32))
+
+# XX /r with the stack pointer in the r/m field: `cmp rsp, r` compares the
+# stack pointer to a register.
+Op1cmpsp = EncRecipe(
+        'Op1cmpsp', Unary, size=2, ins=GPR, outs=FLAG.rflags,
+        clobbers_flags=True)
+RexOp1cmpsp = EncRecipe(
+        'RexOp1cmpsp', Unary, size=3, ins=GPR, outs=FLAG.rflags,
+        clobbers_flags=True)
+
+# XX /r store of a register to a stack slot addressed relative to the stack
+# pointer.
+Op1spill = EncRecipe('Op1spill', Unary, size=7, ins=GPR, outs=Stack(GPR))
+RexOp1spill = EncRecipe('RexOp1spill', Unary, size=8, ins=GPR, outs=Stack(GPR))
+
+# XX /r load of a register from a stack slot addressed relative to the stack
+# pointer.
+Op1fill = EncRecipe('Op1fill', Unary, size=7, ins=Stack(GPR), outs=GPR)
+RexOp1fill = EncRecipe('RexOp1fill', Unary, size=8, ins=Stack(GPR), outs=GPR)
+
+# Branches come in two sizes: The short forms with an 8-bit displacement
+# (`*b` recipes) and the near forms with a 32-bit displacement (`*d` recipes).
+# The displacement is relative to the end of the branch instruction. The
+# legalizer assigns the near forms, and branch relaxation switches to the short
+# forms when the destination is close enough.
+
+# XX cb and XX cd: Unconditional jump. The variable EBB arguments are not
+# encoded.
+Op1jmpb = EncRecipe(
+        'Op1jmpb', Jump, size=2, branch_range=(2, 8), ins=(), outs=())
+Op1jmpd = EncRecipe(
+        'Op1jmpd', Jump, size=5, branch_range=(5, 32), ins=(), outs=())
+
+# XX /r followed by `7x cb` or `0F 8x cd`: Test a register against itself and
+# branch on the result. The `jz` or `jnz` condition is selected by the opcode
+# of the branch instruction.
+Op1tjccb = EncRecipe(
+        'Op1tjccb', Branch, size=4, branch_range=(4, 8), ins=GPR, outs=(),
+        clobbers_flags=True)
+RexOp1tjccb = EncRecipe(
+        'RexOp1tjccb', Branch, size=5, branch_range=(5, 8), ins=GPR, outs=(),
+        clobbers_flags=True)
+Op1tjccd = EncRecipe(
+        'Op1tjccd', Branch, size=8, branch_range=(8, 32), ins=GPR, outs=(),
+        clobbers_flags=True)
+RexOp1tjccd = EncRecipe(
+        'RexOp1tjccd', Branch, size=9, branch_range=(9, 32), ins=GPR, outs=(),
+        clobbers_flags=True)
+
+# XX /r followed by `7x cb` or `0F 8x cd`: Compare two registers and branch on
+# the result. All the integer condition codes have a native `jcc` instruction,
+# so the condition is taken from the `cond` field instead of the encbits.
+Op1cmpjccb = EncRecipe(
+        'Op1cmpjccb', BranchIcmp, size=4, branch_range=(4, 8),
+        ins=(GPR, GPR), outs=(), clobbers_flags=True)
+RexOp1cmpjccb = EncRecipe(
+        'RexOp1cmpjccb', BranchIcmp, size=5, branch_range=(5, 8),
+        ins=(GPR, GPR), outs=(), clobbers_flags=True)
+Op1cmpjccd = EncRecipe(
+        'Op1cmpjccd', BranchIcmp, size=8, branch_range=(8, 32),
+        ins=(GPR, GPR), outs=(), clobbers_flags=True)
+RexOp1cmpjccd = EncRecipe(
+        'RexOp1cmpjccd', BranchIcmp, size=9, branch_range=(9, 32),
+        ins=(GPR, GPR), outs=(), clobbers_flags=True)
+
+# XX /r followed by `0F 4x /r`: Test the condition register against itself and
+# conditionally move the second operand over the third with `cmovnz`. The
+# result is tied to the third operand which is kept when the condition is
+# zero.
+Op2tcmov = EncRecipe(
+        'Op2tcmov', Ternary, size=5, ins=(GPR, GPR, GPR), outs=2,
+        clobbers_flags=True)
+RexOp2tcmov = EncRecipe(
+        'RexOp2tcmov', Ternary, size=7, ins=(GPR, GPR, GPR), outs=2,
+        clobbers_flags=True)
+
+# XX /r: Compare two registers with `cmp` and produce the CPU flags.
+Op1rcmp = EncRecipe(
+        'Op1rcmp', Binary, size=2, ins=(GPR, GPR), outs=FLAG.rflags,
+        clobbers_flags=True)
+RexOp1rcmp = EncRecipe(
+        'RexOp1rcmp', Binary, size=3, ins=(GPR, GPR), outs=FLAG.rflags,
+        clobbers_flags=True)
+
+# XX /r followed by `0F 9x` and `0F B6`: Compare two registers with `cmp`,
+# then set the low byte of the result with `setcc` and zero-extend it with
+# `movzx`. The condition is taken from the `cond` field. Without a REX prefix,
+# only the first four registers have an addressable low byte.
+Op1icscc = EncRecipe(
+        'Op1icscc', IntCompare, size=8, ins=(GPR, GPR), outs=ABCD,
+        clobbers_flags=True)
+RexOp1icscc = EncRecipe(
+        'RexOp1icscc', IntCompare, size=11, ins=(GPR, GPR), outs=GPR,
+        clobbers_flags=True)
+
+# 0F 9x /r followed by `0F B6`: Test the CPU flags with `setcc` and
+# zero-extend the low byte with `movzx`. The condition is taken from the
+# `cond` field. Without a REX prefix, only the first four registers have an
+# addressable low byte.
+Op2seti = EncRecipe(
+        'Op2seti', IntCond, size=6, ins=FLAG.rflags, outs=ABCD)
+RexOp2seti = EncRecipe(
+        'RexOp2seti', IntCond, size=8, ins=FLAG.rflags, outs=GPR)
+
+# 0F 4x /r: Conditionally move the second operand over the third with
+# `cmovcc`, testing the CPU flags in the first operand. The condition is taken
+# from the `cond` field. The result is tied to the third operand which is kept
+# when the condition doesn't hold.
+Op2cmov = EncRecipe(
+        'Op2cmov', IntSelect, size=3, ins=(FLAG.rflags, GPR, GPR), outs=2)
+RexOp2cmov = EncRecipe(
+        'RexOp2cmov', IntSelect, size=4, ins=(FLAG.rflags, GPR, GPR), outs=2)
+
+# 0F 0B: The `ud2` instruction raises an invalid opcode exception.
+Op2trap = EncRecipe('Op2trap', Trap, size=2, ins=(), outs=())
+
+# XX /r followed by `7x 02` and `0F 0B`: Test a register against itself and
+# jump over a `ud2` unless the trap condition holds. The `jz` or `jnz`
+# condition is selected by the opcode of the trap instruction.
+Op1ttrap = EncRecipe(
+        'Op1ttrap', CondTrap, size=6, ins=GPR, outs=(), clobbers_flags=True)
+RexOp1ttrap = EncRecipe(
+        'RexOp1ttrap', CondTrap, size=7, ins=GPR, outs=(),
+        clobbers_flags=True)
+
+# 7x 02 and 0F 0B: Jump over a `ud2` unless the CPU flags satisfy the
+# condition in the `cond` field.
+Op1trapif = EncRecipe(
+        'Op1trapif', IntCondTrap, size=4, ins=FLAG.rflags, outs=())
+
+# XX: Return instruction.
+Op1ret = EncRecipe('Op1ret', Return, size=1, ins=(), outs=())
+
+# Safepoints don't generate any code. The emitter reports a stack map with the
+# locations of the live references to the code sink.
+safepoint = EncRecipe('safepoint', Return, size=0, ins=(), outs=())
+
+# Symbol addresses.
+#
+# The recipes that reference a symbol leave room for a relocation, named after
+# the `RelocKind` in `isa/intel/binemit.rs` that the linker uses to fill it in.
+#
+# B8+r id / REX.W B8+r iq: Absolute address with an Abs4 or Abs8 relocation.
+# This is only used when position-independent code is disabled.
+Op1fnaddr = EncRecipe('Op1fnaddr', FuncAddr, size=5, ins=(), outs=GPR)
+RexOp1fnaddr = EncRecipe('RexOp1fnaddr', FuncAddr, size=10, ins=(), outs=GPR)
+Op1gvaddr = EncRecipe('Op1gvaddr', UnaryGlobalVar, size=5, ins=(), outs=GPR)
+RexOp1gvaddr = EncRecipe(
+        'RexOp1gvaddr', UnaryGlobalVar, size=10, ins=(), outs=GPR)
+
+# REX.W 8D /r: `lea r64, [rip+disp32]` with a PCRel4 relocation. This is used
+# for colocated symbols in 64-bit code.
+RexOp1pcrel_fnaddr = EncRecipe(
+        'RexOp1pcrel_fnaddr', FuncAddr, size=7, ins=(), outs=GPR)
+RexOp1pcrel_gvaddr = EncRecipe(
+        'RexOp1pcrel_gvaddr', UnaryGlobalVar, size=7, ins=(), outs=GPR)
+
+# REX.W 8B /r: `mov r64, [rip+disp32]` with a GOTPCRel4 relocation. This loads
+# the address of a symbol that may be defined in another object from the GOT.
+RexOp1got_fnaddr = EncRecipe(
+        'RexOp1got_fnaddr', FuncAddr, size=7, ins=(), outs=GPR)
+RexOp1got_gvaddr = EncRecipe(
+        'RexOp1got_gvaddr', UnaryGlobalVar, size=7, ins=(), outs=GPR)
+
+# E9 cd: Tail call with `jmp rel32`. The arguments are placed by the register
+# allocator according to the callee's signature, so they are not encoded. The
+# `Op1tcall` recipe uses a PCRel4 relocation, and `Op1tcall_plt` a PLTRel4
+# relocation which lets the dynamic linker redirect the call through the PLT.
+Op1tcall = EncRecipe('Op1tcall', Call, size=5, ins=(), outs=())
+Op1tcall_plt = EncRecipe('Op1tcall_plt', Call, size=5, ins=(), outs=())
+
+# REX FF /4: Tail call with `movabs r11, imm64` and an Abs8 relocation,
+# followed by `jmp r11`. The `r11` scratch register is never used for
+# arguments, so it is free at a tail call.
+RexOp1tcall_abs = EncRecipe('RexOp1tcall_abs', Call, size=13, ins=(), outs=())
+
+# E8 cd: Call with `call rel32`. Like for tail calls, the arguments and the
+# results are placed by the register allocator according to the callee's
+# signature. The callee may clobber the CPU flags.
+Op1call = EncRecipe(
+        'Op1call', Call, size=5, ins=(), outs=(), clobbers_flags=True)
+Op1call_plt = EncRecipe(
+        'Op1call_plt', Call, size=5, ins=(), outs=(), clobbers_flags=True)
+
+# REX FF /2: Call with `movabs r11, imm64` and an Abs8 relocation, followed by
+# `call r11`. No value is kept in a register across a call, and `r11` is never
+# used for arguments, so it is free.
+RexOp1call_abs = EncRecipe(
+        'RexOp1call_abs', Call, size=13, ins=(), outs=(), clobbers_flags=True)
+
+# FF /2: Indirect call with `call r/m` through the callee address in a
+# register.
+Op1call_r = EncRecipe(
+        'Op1call_r', IndirectCall, size=2, ins=GPR, outs=(),
+        clobbers_flags=True)
+RexOp1call_r = EncRecipe(
+        'RexOp1call_r', IndirectCall, size=3, ins=GPR, outs=(),
+        clobbers_flags=True)
+
+# Jump tables.
+#
+# The jump tables are emitted after the function's code, so their addresses
+# are known when the code is emitted. The recipes still report them to the
+# code sink with a relocation against the jump table.
+#
+# REX.W 8D /r: `lea r64, [rip+disp32]` with a PCRel4 relocation.
+# B8+r id: `mov r32, imm32` with an Abs4 relocation. This is only used when
+# position-independent code is disabled.
+RexOp1jt_base = EncRecipe(
+        'RexOp1jt_base', BranchTableBase, size=7, ins=(), outs=GPR)
+Op1jt_base = EncRecipe('Op1jt_base', BranchTableBase, size=5, ins=(), outs=GPR)
+
+# XX /r load of the entry at `[base + index*size]`. The entry size goes in
+# the scale field of the SIB byte, and the displacement is always 0.
+Op1jt_entry = EncRecipe(
+        'Op1jt_entry', BranchTableEntry, size=4, ins=(GPR, GPR), outs=GPR)
+RexOp1jt_entry = EncRecipe(
+        'RexOp1jt_entry', BranchTableEntry, size=5, ins=(GPR, GPR), outs=GPR)
+
+# FF /4: `jmp r/m` to the address in a register.
+Op1indirect_jmp = EncRecipe(
+        'Op1indirect_jmp', BranchTable, size=2, ins=GPR, outs=())
+RexOp1indirect_jmp = EncRecipe(
+        'RexOp1indirect_jmp', BranchTable, size=3, ins=GPR, outs=())
+
+# Floating point recipes.
+#
+# The SSE2 scalar instructions use the mandatory prefix to select the operand
+# type: F3 for `f32` and F2 for `f64` arithmetic, and none for `f32` and 66 for
+# `f64` comparisons.
+
+# PP 0F XX /r two-address arithmetic like `addss xmm1, xmm2/m32`. The result
+# is tied to the first operand.
+Mp2fa = EncRecipe('Mp2fa', Binary, size=4, ins=(FPR, FPR), outs=0)
+RexMp2fa = EncRecipe('RexMp2fa', Binary, size=5, ins=(FPR, FPR), outs=0)
+
+# PP 0F XX /r with an XMM result in the reg field and a GPR operand in the r/m
+# field, like `cvtsi2sd xmm, r/m32` and `movd xmm, r/m32`.
+Mp2frurm = EncRecipe('Mp2frurm', Unary, size=4, ins=GPR, outs=FPR)
+RexMp2frurm = EncRecipe('RexMp2frurm', Unary, size=5, ins=GPR, outs=FPR)
+
+# PP 0F XX /r with a GPR result in the reg field and an XMM operand in the r/m
+# field, like `cvttsd2si r32, xmm/m64`.
+Mp2rfurm = EncRecipe('Mp2rfurm', Unary, size=4, ins=FPR, outs=GPR)
+RexMp2rfurm = EncRecipe('RexMp2rfurm', Unary, size=5, ins=FPR, outs=GPR)
+
+# PP 0F XX /r with an XMM operand in the reg field and a GPR result in the r/m
+# field, like `movd r/m32, xmm`.
+Mp2rfumr = EncRecipe('Mp2rfumr', Unary, size=4, ins=FPR, outs=GPR)
+RexMp2rfumr = EncRecipe('RexMp2rfumr', Unary, size=5, ins=FPR, outs=GPR)
+
+# PP 0F XX /r with an XMM operand and result, like `sqrtsd xmm1, xmm2/m64`.
+Mp2furm = EncRecipe('Mp2furm', Unary, size=4, ins=FPR, outs=FPR)
+RexMp2furm = EncRecipe('RexMp2furm', Unary, size=5, ins=FPR, outs=FPR)
+
+# PP 0F XX /r with a GPR operand and result, like `popcnt r32, r/m32`.
+Mp2urm = EncRecipe(
+        'Mp2urm', Unary, size=4, ins=GPR, outs=GPR, clobbers_flags=True)
+RexMp2urm = EncRecipe(
+        'RexMp2urm', Unary, size=5, ins=GPR, outs=GPR, clobbers_flags=True)
+
+# PP 0F 3A XX /r ib rounding of an XMM register, like `roundsd xmm1,
+# xmm2/m64, imm8`. The rounding mode immediate comes from the `rrr` field of
+# the encbits.
+Mp3furmi_rnd = EncRecipe('Mp3furmi_rnd', Unary, size=6, ins=FPR, outs=FPR)
+RexMp3furmi_rnd = EncRecipe(
+        'RexMp3furmi_rnd', Unary, size=7, ins=FPR, outs=FPR)
+
+# 0F 28 /r register-to-register move of a whole XMM register: `movaps`.
+Op2furm = EncRecipe('Op2furm', Unary, size=3, ins=FPR, outs=FPR)
+RexOp2furm = EncRecipe('RexOp2furm', Unary, size=4, ins=FPR, outs=FPR)
+
+# 0F 28 /r register-to-register move for a register diversion.
+Op2frmov = EncRecipe('Op2frmov', RegMove, size=3, ins=FPR, outs=())
+RexOp2frmov = EncRecipe('RexOp2frmov', RegMove, size=4, ins=FPR, outs=())
+
+# 0F XX /r load of a whole XMM register from the address in a register plus
+# an 8-bit or 32-bit displacement: `movaps` or `movups`. A SIB byte is always
+# emitted.
+Op2fldDisp8 = EncRecipe(
+        'Op2fldDisp8', Load, size=5, ins=GPR, outs=FPR,
+        instp=IsSignedInt(Load.offset, 8))
+RexOp2fldDisp8 = EncRecipe(
+        'RexOp2fldDisp8', Load, size=6, ins=GPR, outs=FPR,
+        instp=IsSignedInt(Load.offset, 8))
+Op2fldDisp32 = EncRecipe('Op2fldDisp32', Load, size=8, ins=GPR, outs=FPR)
+RexOp2fldDisp32 = EncRecipe(
+        'RexOp2fldDisp32', Load, size=9, ins=GPR, outs=FPR)
+
+# 0F XX /r store of a whole XMM register to the address in the second operand
+# plus an 8-bit or 32-bit displacement.
+Op2fstDisp8 = EncRecipe(
+        'Op2fstDisp8', Store, size=5, ins=(FPR, GPR), outs=(),
+        instp=IsSignedInt(Store.offset, 8))
+RexOp2fstDisp8 = EncRecipe(
+        'RexOp2fstDisp8', Store, size=6, ins=(FPR, GPR), outs=(),
+        instp=IsSignedInt(Store.offset, 8))
+Op2fstDisp32 = EncRecipe(
+        'Op2fstDisp32', Store, size=8, ins=(FPR, GPR), outs=())
+RexOp2fstDisp32 = EncRecipe(
+        'RexOp2fstDisp32', Store, size=9, ins=(FPR, GPR), outs=())
+
+# 0F 11 /r store of a whole XMM register to a stack slot: `movups`.
+Op2fspill = EncRecipe('Op2fspill', Unary, size=8, ins=FPR, outs=Stack(FPR))
+RexOp2fspill = EncRecipe(
+        'RexOp2fspill', Unary, size=9, ins=FPR, outs=Stack(FPR))
+
+# 0F 10 /r load of a whole XMM register from a stack slot.
+Op2ffill = EncRecipe('Op2ffill', Unary, size=8, ins=Stack(FPR), outs=FPR)
+RexOp2ffill = EncRecipe(
+        'RexOp2ffill', Unary, size=9, ins=Stack(FPR), outs=FPR)
+
+# PP 0F 11 /r store of an XMM register to a stack slot: `movss` or `movsd`.
+Mp2fspill = EncRecipe('Mp2fspill', Unary, size=9, ins=FPR, outs=Stack(FPR))
+RexMp2fspill = EncRecipe(
+        'RexMp2fspill', Unary, size=10, ins=FPR, outs=Stack(FPR))
+
+# PP 0F 10 /r load of an XMM register from a stack slot.
+Mp2ffill = EncRecipe('Mp2ffill', Unary, size=9, ins=Stack(FPR), outs=FPR)
+RexMp2ffill = EncRecipe(
+        'RexMp2ffill', Unary, size=10, ins=Stack(FPR), outs=FPR)
+
+# The floating point condition codes that can be tested with a single `setcc`
+# after `ucomiss` or `ucomisd`. The unordered result sets ZF, PF, and CF, so
+# these are the conditions that are either true or false for all of the
+# flags. The Intel custom legalization rewrites the other conditions in terms
+# of these.
+supported_floatccs = [
+        'Ordered', 'Unordered', 'OrderedNotEqual', 'UnorderedOrEqual',
+        'GreaterThan', 'GreaterThanOrEqual', 'UnorderedOrLessThan',
+        'UnorderedOrLessThanOrEqual']
+floatcc_instp = Or(*(IsEqual(FloatCompare.cond, 'FloatCC::' + cc)
+                     for cc in supported_floatccs))
+
+# [PP] 0F 2E /r followed by `0F 9x` and `0F B6`: Compare two XMM registers
+# with `ucomiss` or `ucomisd`, then set the low byte of the result with
+# `setcc` and zero-extend it with `movzx`. Without a REX prefix, only the
+# first four registers have an addressable low byte.
+Op2fcscc = EncRecipe(
+        'Op2fcscc', FloatCompare, size=9, ins=(FPR, FPR), outs=ABCD,
+        clobbers_flags=True, instp=floatcc_instp)
+RexOp2fcscc = EncRecipe(
+        'RexOp2fcscc', FloatCompare, size=12, ins=(FPR, FPR), outs=GPR,
+        clobbers_flags=True, instp=floatcc_instp)
+Mp2fcscc = EncRecipe(
+        'Mp2fcscc', FloatCompare, size=10, ins=(FPR, FPR), outs=ABCD,
+        clobbers_flags=True, instp=floatcc_instp)
+RexMp2fcscc = EncRecipe(
+        'RexMp2fcscc', FloatCompare, size=13, ins=(FPR, FPR), outs=GPR,
+        clobbers_flags=True, instp=floatcc_instp)
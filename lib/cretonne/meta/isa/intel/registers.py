@@ -38,8 +38,16 @@ FloatRegs = RegBank(
         'SSE floating point registers',
         units=16, prefix='xmm')
 
+# The CPU flags are modeled as a single register so the register allocator can
+# track the `iflags` and `fflags` values living in it.
+FlagRegs = RegBank(
+        'FlagRegs', ISA,
+        'Flag registers',
+        units=1, names=['rflags'])
+
 GPR = RegClass(IntRegs)
 ABCD = GPR[0:4]
 FPR = RegClass(FloatRegs)
+FLAG = RegClass(FlagRegs)
 
 RegClass.extract_names(globals())
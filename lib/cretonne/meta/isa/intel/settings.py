@@ -2,10 +2,47 @@
 Intel settings.
 """
 from __future__ import absolute_import
-from cdsl.settings import SettingGroup
+from cdsl.settings import SettingGroup, BoolSetting, Preset
+from cdsl.predicates import And
 import base.settings as shared
 from .defs import ISA
 
 ISA.settings = SettingGroup('intel', parent=shared.group)
 
+# The has_* settings here correspond to CPUID bits.
+
+# CPUID.01H:EDX
+has_sse2 = BoolSetting("SSE2: CPUID.01H:EDX.SSE2[bit 26]")
+
+# CPUID.01H:ECX
+has_sse3 = BoolSetting("SSE3: CPUID.01H:ECX.SSE3[bit 0]")
+has_ssse3 = BoolSetting("SSSE3: CPUID.01H:ECX.SSSE3[bit 9]")
+has_sse41 = BoolSetting("SSE4.1: CPUID.01H:ECX.SSE4_1[bit 19]")
+has_sse42 = BoolSetting("SSE4.2: CPUID.01H:ECX.SSE4_2[bit 20]")
+has_movbe = BoolSetting("MOVBE: CPUID.01H:ECX.MOVBE[bit 22]")
+has_popcnt = BoolSetting("POPCNT: CPUID.01H:ECX.POPCNT[bit 23]")
+has_avx = BoolSetting("AVX: CPUID.01H:ECX.AVX[bit 28]")
+
+# CPUID.(EAX=07H, ECX=0H):EBX
+has_bmi1 = BoolSetting("BMI1: CPUID.(EAX=07H, ECX=0H):EBX.BMI1[bit 3]")
+has_avx2 = BoolSetting("AVX2: CPUID.(EAX=07H, ECX=0H):EBX.AVX2[bit 5]")
+has_bmi2 = BoolSetting("BMI2: CPUID.(EAX=07H, ECX=0H):EBX.BMI2[bit 8]")
+
+# CPUID.EAX=80000001H:ECX
+has_lzcnt = BoolSetting("LZCNT: CPUID.EAX=80000001H:ECX.LZCNT[bit 5]")
+
+use_sse41 = And(has_sse41, shared.enable_simd)
+use_sse42 = And(has_sse42, shared.enable_simd)
+use_popcnt = And(has_popcnt, has_sse42)
+use_avx = And(has_avx, shared.enable_simd)
+
+# Presets corresponding to common CPU feature levels.
+#
+# All 64-bit Intel CPUs support SSE2, so the baseline only enables that.
+baseline = Preset(has_sse2)
+nehalem = Preset(
+        baseline, has_sse3, has_ssse3, has_sse41, has_sse42, has_popcnt)
+haswell = Preset(
+        nehalem, has_movbe, has_avx, has_avx2, has_bmi1, has_bmi2, has_lzcnt)
+
 ISA.settings.close(globals())
@@ -4,7 +4,7 @@ RISC-V Target
 
 `RISC-V <http://riscv.org/>`_ is an open instruction set architecture
 originally developed at UC Berkeley. It is a RISC-style ISA with either a
-32-bit (RV32I) or 64-bit (RV32I) base instruction set and a number of optional
+32-bit (RV32I) or 64-bit (RV64I) base instruction set and a number of optional
 extensions:
 
 RV32M / RV64M
@@ -3,9 +3,47 @@ RISC-V Encodings.
 """
 from __future__ import absolute_import
 from base import instructions as base
+from base.formats import UnaryImm, IntCompare, BranchIcmp, BinaryImm
+from base.types import i8, i16
+from cdsl.predicates import IsEqual, IsSignedInt, IsUnsignedInt, Or
 from .defs import RV32, RV64
-from .recipes import OPIMM, OPIMM32, OP, OP32, JALR, R, Rshamt, I, Iret
-from .settings import use_m
+from . import instructions as riscv
+from .recipes import OPIMM, OPIMM32, OP, OP32, LOAD, STORE, BRANCH, JAL, JALR
+from .recipes import LUI, Iimm, U
+from .recipes import R, Rshamt, Rext, Ricmp, I, Iz, SB, SBzero, UJ, Iret
+from .recipes import UJcall, UJlink, Ilink, Icall, Icopy, Irmov, GPsp, GPfi
+from .recipes import Iload, Sstore, C1, C2, CBzero, CJ
+from .recipes import CR, CA, CI, CIshamt, CBshamt, CBi, CIz, CRcopy, CRrmov
+from .recipes import CRret, AMO, Ramo, Ramoz, Rlr, SYSTEM, Iebreak, SBtrap
+from .settings import use_m, use_a, supports_c
+
+# The 'C' extension has compressed encodings for the common instructions. They
+# come first in the encoding lists, so the legalizer still picks the general
+# encodings. Branch relaxation switches to a compressed encoding when the
+# assigned registers and the immediate fit.
+RV32.enc(base.iadd.i32, CR, C2(0b100, 1), isap=supports_c)
+RV64.enc(base.iadd.i64, CR, C2(0b100, 1), isap=supports_c)
+RV32.enc(base.iadd_imm.i32, CI, C1(0b000), isap=supports_c)
+RV64.enc(base.iadd_imm.i64, CI, C1(0b000), isap=supports_c)
+RV64.enc(base.iadd_imm.i32, CI, C1(0b001), isap=supports_c)
+RV32.enc(base.band_imm.i32, CBi, C1(0b100, 0b10), isap=supports_c)
+RV64.enc(base.band_imm.i64, CBi, C1(0b100, 0b10), isap=supports_c)
+RV64.enc(base.band_imm.i32, CBi, C1(0b100, 0b10), isap=supports_c)
+
+for inst,       f2 in [
+        (base.isub, 0b00),
+        (base.bxor, 0b01),
+        (base.bor,  0b10),
+        (base.band, 0b11)
+        ]:
+    RV32.enc(inst.i32, CA, C1(0b100, 0b11, f2), isap=supports_c)
+    RV64.enc(inst.i64, CA, C1(0b100, 0b11, f2), isap=supports_c)
+    if inst is not base.isub:
+        RV64.enc(inst.i32, CA, C1(0b100, 0b11, f2), isap=supports_c)
+
+# The `c.subw` and `c.addw` instructions operate on 32-bit values in RV64.
+RV64.enc(base.isub.i32, CA, C1(0b100, 0b11, 0b00, 1), isap=supports_c)
+RV64.enc(base.iadd.i32, CA, C1(0b100, 0b11, 0b01, 1), isap=supports_c)
 
 # Basic arithmetic binary instructions are encoded in an R-type instruction.
 for inst,           inst_imm,      f3,    f7 in [
@@ -23,12 +61,99 @@ for inst,           inst_imm,      f3,    f7 in [
         RV32.enc(inst_imm.i32, I, OPIMM(f3))
         RV64.enc(inst_imm.i64, I, OPIMM(f3))
 
+# RV64 keeps 32-bit values sign-extended to 64 bits in registers. The bitwise
+# operations preserve that, so they don't need 32-bit variants.
+for inst,           inst_imm,      f3 in [
+        (base.bxor, base.bxor_imm, 0b100),
+        (base.bor,  base.bor_imm,  0b110),
+        (base.band, base.band_imm, 0b111)
+        ]:
+    RV64.enc(inst.i32, R, OP(f3, 0b0000000))
+    RV64.enc(inst_imm.i32, I, OPIMM(f3))
+
+# The `b1` values are 0 or 1 in a register, so they can be combined with the
+# same instructions. The expanded carry and overflow checks need them.
+for inst,           f3 in [
+        (base.bxor, 0b100),
+        (base.bor,  0b110),
+        (base.band, 0b111)
+        ]:
+    RV32.enc(inst.b1, R, OP(f3, 0b0000000))
+    RV64.enc(inst.b1, R, OP(f3, 0b0000000))
+
+# Integer comparisons. The custom `icmp` legalization expresses the other
+# conditions with `slt` and `sltu`.
+for cond,               f3 in [
+        ('SignedLessThan',   0b010),
+        ('UnsignedLessThan', 0b011)
+        ]:
+    instp = IsEqual(IntCompare.cond, 'IntCC::' + cond)
+    RV32.enc(base.icmp.i32, Ricmp, OP(f3, 0b0000000), instp=instp)
+    RV64.enc(base.icmp.i64, Ricmp, OP(f3, 0b0000000), instp=instp)
+    RV64.enc(base.icmp.i32, Ricmp, OP(f3, 0b0000000), instp=instp)
+
+# Zero constants.
+RV32.enc(base.null.i32, CIz, C1(0b010), isap=supports_c)
+RV64.enc(base.null.i64, CIz, C1(0b010), isap=supports_c)
+RV64.enc(base.null.i32, CIz, C1(0b010), isap=supports_c)
+RV32.enc(base.null.i32, Iz, OPIMM(0b000))
+RV64.enc(base.null.i64, Iz, OPIMM(0b000))
+RV64.enc(base.null.i32, Iz, OPIMM32(0b000))
+
+# Other constants are an `addi` from `x0` or a `lui`. The custom `iconst`
+# legalization builds the constants that don't fit either of them. The `lui`
+# result is sign-extended in RV64, so a 64-bit constant must be a sign-extended
+# 32-bit value. The high bits of a 32-bit constant don't matter.
+lui_i64 = IsSignedInt(UnaryImm.imm, 32, 12)
+lui_i32 = Or(lui_i64, IsUnsignedInt(UnaryImm.imm, 32, 12))
+RV32.enc(base.iconst.i32, U, LUI(), instp=lui_i32)
+RV64.enc(base.iconst.i64, U, LUI(), instp=lui_i64)
+RV64.enc(base.iconst.i32, U, LUI(), instp=lui_i32)
+RV32.enc(base.iconst.i32, Iimm, OPIMM(0b000))
+RV64.enc(base.iconst.i64, Iimm, OPIMM(0b000))
+RV64.enc(base.iconst.i32, Iimm, OPIMM(0b000))
+
 # 32-bit ops in RV64.
 RV64.enc(base.iadd.i32, R, OP32(0b000, 0b0000000))
 RV64.enc(base.isub.i32, R, OP32(0b000, 0b0100000))
 # There are no andiw/oriw/xoriw variations.
 RV64.enc(base.iadd_imm.i32, I, OPIMM32(0b000))
 
+# Compressed immediate shifts can only encode shift amounts that fit in the
+# register size.
+for inst,           recipe,  bits in [
+        (base.ishl_imm, CIshamt, C2(0b000)),
+        (base.ushr_imm, CBshamt, C1(0b100, 0b00)),
+        (base.sshr_imm, CBshamt, C1(0b100, 0b01))
+        ]:
+    RV32.enc(
+            inst.i32, recipe, bits, isap=supports_c,
+            instp=IsUnsignedInt(BinaryImm.imm, 5))
+    RV64.enc(
+            inst.i64, recipe, bits, isap=supports_c,
+            instp=IsUnsignedInt(BinaryImm.imm, 6))
+
+# Sign-extending a 32-bit value in RV64 is `addiw rd, rs1, 0`, a.k.a. `sext.w`.
+# Reducing a 64-bit value to 32 bits must sign-extend the low half too. The
+# custom `uextend` legalization clears the high bits with shifts.
+RV64.enc(base.sextend.i64.i32, Icopy, OPIMM32(0b000))
+RV64.enc(base.ireduce.i32.i64, Icopy, OPIMM32(0b000))
+
+# The high bits of a register holding an `i8` or `i16` value are undefined, so
+# reducing to those types is a plain register move. Extending them shifts the
+# value to the top of the register and back.
+for ty in [i8, i16]:
+    RV32.enc(base.ireduce.bind(ty).i32, Icopy, OPIMM(0b000))
+    RV64.enc(base.ireduce.bind(ty).i32, Icopy, OPIMM(0b000))
+    RV64.enc(base.ireduce.bind(ty).i64, Icopy, OPIMM(0b000))
+    for inst,         f7 in [
+            (base.uextend, 0b0000000),
+            (base.sextend, 0b0100000)
+            ]:
+        RV32.enc(inst.i32.bind(ty), Rext, OPIMM(0b101, f7))
+        RV64.enc(inst.i32.bind(ty), Rext, OPIMM32(0b101, f7))
+        RV64.enc(inst.i64.bind(ty), Rext, OPIMM(0b101, f7))
+
 # Dynamic shifts have the same masking semantics as the cton base instructions.
 for inst,           inst_imm,      f3,    f7 in [
         (base.ishl, base.ishl_imm, 0b001, 0b0000000),
@@ -52,12 +177,155 @@ for inst,           inst_imm,      f3,    f7 in [
 RV32.enc(base.imul.i32, R, OP(0b000, 0b0000001), isap=use_m)
 RV64.enc(base.imul.i64, R, OP(0b000, 0b0000001), isap=use_m)
 RV64.enc(base.imul.i32, R, OP32(0b000, 0b0000001), isap=use_m)
+RV32.enc(base.smulhi.i32, R, OP(0b001, 0b0000001), isap=use_m)
+RV64.enc(base.smulhi.i64, R, OP(0b001, 0b0000001), isap=use_m)
+RV32.enc(base.umulhi.i32, R, OP(0b011, 0b0000001), isap=use_m)
+RV64.enc(base.umulhi.i64, R, OP(0b011, 0b0000001), isap=use_m)
+
+# Without the 'M' extension, the custom legalizations call the runtime library.
+for inst,      f3 in [
+        (base.sdiv, 0b100),
+        (base.udiv, 0b101),
+        (base.srem, 0b110),
+        (base.urem, 0b111)
+        ]:
+    RV32.enc(inst.i32, R, OP(f3, 0b0000001), isap=use_m)
+    RV64.enc(inst.i64, R, OP(f3, 0b0000001), isap=use_m)
+    RV64.enc(inst.i32, R, OP32(f3, 0b0000001), isap=use_m)
+
+# "A" Standard Extension for Atomic Instructions.
+# Gated by the `use_a` flag. Atomic loads are encoded as load-reserved, and
+# atomic stores are swaps that discard the old value. The custom legalization
+# expands `atomic_cas` into an `lr`/`sc` loop.
+for inst,              recipe, f5 in [
+        (base.atomic_load,  Rlr,   0b00010),
+        (riscv.lr,          Rlr,   0b00010),
+        (riscv.sc,          Ramo,  0b00011),
+        (base.atomic_store, Ramoz, 0b00001),
+        (base.atomic_xchg,  Ramo,  0b00001),
+        (base.atomic_add,   Ramo,  0b00000),
+        (base.atomic_xor,   Ramo,  0b00100),
+        (base.atomic_and,   Ramo,  0b01100),
+        (base.atomic_or,    Ramo,  0b01000)
+        ]:
+    RV32.enc(inst.i32.i32, recipe, AMO(0b010, f5), isap=use_a)
+    RV64.enc(inst.i64.i64, recipe, AMO(0b011, f5), isap=use_a)
+    RV64.enc(inst.i32.i64, recipe, AMO(0b010, f5), isap=use_a)
 
 # Control flow.
 
+# Branches on a zero or non-zero register are `beq` and `bne` against `x0`.
+# With the 'C' extension, branch relaxation can shrink them to `c.beqz` and
+# `c.bnez` when the register and the destination fit.
+for inst,      f3,    cf3 in [
+        (base.brz,  0b000, 0b110),
+        (base.brnz, 0b001, 0b111)
+        ]:
+    RV32.enc(inst.i32, CBzero, C1(cf3), isap=supports_c)
+    RV64.enc(inst.i64, CBzero, C1(cf3), isap=supports_c)
+    RV64.enc(inst.i32, CBzero, C1(cf3), isap=supports_c)
+    RV32.enc(inst.b1, CBzero, C1(cf3), isap=supports_c)
+    RV64.enc(inst.b1, CBzero, C1(cf3), isap=supports_c)
+    RV32.enc(inst.i32, SBzero, BRANCH(f3))
+    RV64.enc(inst.i64, SBzero, BRANCH(f3))
+    RV64.enc(inst.i32, SBzero, BRANCH(f3))
+    RV32.enc(inst.b1, SBzero, BRANCH(f3))
+    RV64.enc(inst.b1, SBzero, BRANCH(f3))
+
+# Traps.
+RV32.enc(base.trap, Iebreak, SYSTEM())
+RV64.enc(base.trap, Iebreak, SYSTEM())
+for inst,        f3 in [
+        (base.trapz,  0b001),
+        (base.trapnz, 0b000)
+        ]:
+    RV32.enc(inst.i32, SBtrap, BRANCH(f3))
+    RV64.enc(inst.i64, SBtrap, BRANCH(f3))
+    RV64.enc(inst.i32, SBtrap, BRANCH(f3))
+    RV32.enc(inst.b1, SBtrap, BRANCH(f3))
+    RV64.enc(inst.b1, SBtrap, BRANCH(f3))
+
+# Conditional branches. Only six condition codes have native instructions; the
+# others are canonicalized by the custom `icmp` legalization.
+for cond,                         f3 in [
+        ('Equal',                      0b000),
+        ('NotEqual',                   0b001),
+        ('SignedLessThan',             0b100),
+        ('SignedGreaterThanOrEqual',   0b101),
+        ('UnsignedLessThan',           0b110),
+        ('UnsignedGreaterThanOrEqual', 0b111)
+        ]:
+    instp = IsEqual(BranchIcmp.cond, 'IntCC::' + cond)
+    RV32.enc(base.br_icmp.i32, SB, BRANCH(f3), instp=instp)
+    RV64.enc(base.br_icmp.i64, SB, BRANCH(f3), instp=instp)
+    RV64.enc(base.br_icmp.i32, SB, BRANCH(f3), instp=instp)
+
+# Unconditional branches are `jal` without saving the return address, or `c.j`
+# with the 'C' extension.
+RV32.enc(base.jump, CJ, C1(0b101), isap=supports_c)
+RV64.enc(base.jump, CJ, C1(0b101), isap=supports_c)
+RV32.enc(base.jump, UJ, JAL())
+RV64.enc(base.jump, UJ, JAL())
+
 # Returns are a special case of JALR.
 # Note: Return stack predictors will only recognize this as a return when the
 # return address is provided in `x1`. We may want a special encoding to enforce
 # that.
+RV32.enc(base.return_reg.i32, CRret, C2(0b100), isap=supports_c)
+RV64.enc(base.return_reg.i64, CRret, C2(0b100), isap=supports_c)
 RV32.enc(base.return_reg.i32, Iret, JALR())
 RV64.enc(base.return_reg.i64, Iret, JALR())
+
+# Tail calls jump to the callee without saving a return address, so it returns
+# directly to our caller.
+RV32.enc(base.return_call, UJcall, JAL())
+RV64.enc(base.return_call, UJcall, JAL())
+RV32.enc(base.return_call_indirect.i32, Icall, JALR())
+RV64.enc(base.return_call_indirect.i64, Icall, JALR())
+
+# Calls save the return address in `x1`. The spilling pass moves the values
+# that are live across a call to the stack, including our own return address.
+RV32.enc(base.call, UJlink, JAL())
+RV64.enc(base.call, UJlink, JAL())
+RV32.enc(base.call_indirect.i32, Ilink, JALR())
+RV64.enc(base.call_indirect.i64, Ilink, JALR())
+
+# Register copies are an `addi` with a zero immediate, or `c.mv`.
+RV32.enc(base.copy.i32, CRcopy, C2(0b100), isap=supports_c)
+RV64.enc(base.copy.i32, CRcopy, C2(0b100), isap=supports_c)
+RV64.enc(base.copy.i64, CRcopy, C2(0b100), isap=supports_c)
+RV32.enc(base.copy.i32, Icopy, OPIMM(0b000))
+RV64.enc(base.copy.i32, Icopy, OPIMM(0b000))
+RV64.enc(base.copy.i64, Icopy, OPIMM(0b000))
+
+# The `b1` values are already 0 or 1, so `bint` is a plain register move.
+RV32.enc(base.bint.i32.b1, Icopy, OPIMM(0b000))
+RV64.enc(base.bint.i32.b1, Icopy, OPIMM(0b000))
+RV64.enc(base.bint.i64.b1, Icopy, OPIMM(0b000))
+
+# Register diversions use the same encodings as copies.
+RV32.enc(base.regmove.i32, CRrmov, C2(0b100), isap=supports_c)
+RV64.enc(base.regmove.i32, CRrmov, C2(0b100), isap=supports_c)
+RV64.enc(base.regmove.i64, CRrmov, C2(0b100), isap=supports_c)
+RV32.enc(base.regmove.i32, Irmov, OPIMM(0b000))
+RV64.enc(base.regmove.i32, Irmov, OPIMM(0b000))
+RV64.enc(base.regmove.i64, Irmov, OPIMM(0b000))
+
+# Loads and stores with `lw`/`sw` and `ld`/`sd`. RV64 `lw` sign-extends the
+# loaded value like the 32-bit arithmetic. Offsets that don't fit in 12 bits are
+# added to the address first.
+RV32.enc(base.load.i32.i32, Iload, LOAD(0b010))
+RV32.enc(base.store.i32.i32, Sstore, STORE(0b010))
+RV64.enc(base.load.i32.i64, Iload, LOAD(0b010))
+RV64.enc(base.store.i32.i64, Sstore, STORE(0b010))
+RV64.enc(base.load.i64.i64, Iload, LOAD(0b011))
+RV64.enc(base.store.i64.i64, Sstore, STORE(0b011))
+
+# Spill and fill registers with `sw`/`lw` and `sd`/`ld` relative to the stack
+# pointer.
+RV32.enc(base.spill.i32, GPsp, STORE(0b010))
+RV32.enc(base.fill.i32, GPfi, LOAD(0b010))
+RV64.enc(base.spill.i32, GPsp, STORE(0b010))
+RV64.enc(base.fill.i32, GPfi, LOAD(0b010))
+RV64.enc(base.spill.i64, GPsp, STORE(0b011))
+RV64.enc(base.fill.i64, GPfi, LOAD(0b011))
@@ -0,0 +1,45 @@
+"""
+Supplementary instruction definitions for RISC-V.
+
+This module defines additional instructions that are useful only to the RISC-V
+target ISA.
+"""
+from __future__ import absolute_import
+from cdsl.operands import Operand
+from cdsl.typevar import TypeVar
+from cdsl.instructions import Instruction, InstructionGroup
+from base.immediates import ordering
+from base.instructions import iAddr
+
+
+GROUP = InstructionGroup("riscv", "RISC-V-specific instruction set")
+
+iWord = TypeVar('iWord', 'A scalar integer machine word', ints=(32, 64))
+
+Ord = Operand('Ord', ordering)
+p = Operand('p', iAddr, doc='Address')
+x = Operand('x', iWord, doc='Value to be stored')
+a = Operand('a', iWord, doc='Value loaded')
+s = Operand('s', iWord, doc='Zero if the store succeeded')
+
+lr = Instruction(
+        'riscv_lr', r"""
+        Load-reserved.
+
+        Load the value at ``p`` and register a reservation on the memory
+        around it. This is the ``lr.w`` or ``lr.d`` instruction from the 'A'
+        extension.
+        """,
+        ins=(Ord, p), outs=a, can_load=True, can_store=True)
+
+sc = Instruction(
+        'riscv_sc', r"""
+        Store-conditional.
+
+        Store ``x`` to memory at ``p`` if the reservation from the preceding
+        :inst:`riscv_lr` is still valid. Return zero if the store succeeded,
+        and a non-zero value if it failed and nothing was stored.
+        """,
+        ins=(Ord, p, x), outs=s, can_load=True, can_store=True)
+
+GROUP.close()
@@ -11,8 +11,12 @@ instruction formats described in the reference:
 from __future__ import absolute_import
 from cdsl.isa import EncRecipe
 from cdsl.predicates import IsSignedInt
-from base.formats import Binary, BinaryImm, ReturnReg
-from .registers import GPR
+from base.formats import Unary, Nullary, Binary, BinaryImm, IntCompare, Branch
+from base.formats import UnaryImm, Load, Store
+from base.formats import BranchIcmp, Jump, ReturnReg, Call, IndirectCall
+from base.formats import RegMove, AtomicLoad, AtomicRmw, Trap, CondTrap
+from cdsl.registers import Stack
+from .registers import GPR, GPR8
 
 # The low 7 bits of a RISC-V instruction is the base opcode. All 32-bit
 # instructions have 11 as the two low bits, with bits 6:2 determining the base
@@ -46,6 +50,22 @@ def JALR(funct3=0):
     return 0b11001 | (funct3 << 5)
 
 
+def JAL():
+    # type: () -> int
+    return 0b11011
+
+
+def LUI():
+    # type: () -> int
+    return 0b01101
+
+
+def SYSTEM(funct3=0):
+    # type: (int) -> int
+    assert funct3 <= 0b111
+    return 0b11100 | (funct3 << 5)
+
+
 def OPIMM(funct3, funct7=0):
     # type: (int, int) -> int
     assert funct3 <= 0b111
@@ -72,18 +92,203 @@ def OP32(funct3, funct7):
     return 0b01110 | (funct3 << 5) | (funct7 << 8)
 
 
+def AMO(funct3, funct5):
+    # type: (int, int) -> int
+    assert funct3 <= 0b111
+    assert funct5 <= 0b11111
+    return 0b01011 | (funct3 << 5) | (funct5 << 10)
+
+
+# The 'C' extension adds 16-bit compressed instructions. The two low bits of a
+# compressed instruction select one of the three quadrants, and funct3 in bits
+# 15:13 selects the instruction within the quadrant.
+#
+# Encbits for the compressed recipes are quadrant | (funct3 << 2) | ...
+# The arithmetic instructions in quadrant 1 are further selected by the funct2
+# fields in bits 11:10 and 6:5, and by bit 12.
+
+
+def C1(funct3, funct2=0, funct2b=0, bit12=0):
+    # type: (int, int, int, int) -> int
+    assert funct3 <= 0b111
+    assert funct2 <= 0b11
+    assert funct2b <= 0b11
+    assert bit12 <= 1
+    return 0b01 | (funct3 << 2) | (funct2 << 5) | (funct2b << 7) | (bit12 << 9)
+
+
+def C2(funct3, bit12=0):
+    # type: (int, int) -> int
+    assert funct3 <= 0b111
+    assert bit12 <= 1
+    return 0b10 | (funct3 << 2) | (bit12 << 9)
+
+
 # R-type 32-bit instructions: These are mostly binary arithmetic instructions.
 # The encbits are `opcode[6:2] | (funct3 << 5) | (funct7 << 8)
-R = EncRecipe('R', Binary, ins=(GPR, GPR), outs=GPR)
+R = EncRecipe('R', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# R-type atomic memory operations from the 'A' extension. The address is rs1,
+# the value operand is rs2, and the aq and rl bits in funct7 come from the
+# memory ordering. The encbits only hold the top 5 bits of funct7.
+Ramo = EncRecipe('Ramo', AtomicRmw, size=4, ins=(GPR, GPR), outs=GPR)
+
+# R-type atomic memory operation with `rd = x0` discarding the old value, like
+# `amoswap.w x0, rs2, (rs1)` for an atomic store.
+Ramoz = EncRecipe('Ramoz', AtomicRmw, size=4, ins=(GPR, GPR), outs=())
+
+# R-type load-reserved `lr.w rd, (rs1)` with `rs2 = x0`.
+Rlr = EncRecipe('Rlr', AtomicLoad, size=4, ins=GPR, outs=GPR)
 
 # R-type with an immediate shift amount instead of rs2.
-Rshamt = EncRecipe('Rshamt', BinaryImm, ins=GPR, outs=GPR)
+Rshamt = EncRecipe('Rshamt', BinaryImm, size=4, ins=GPR, outs=GPR)
+
+# Pair of shifts `slli rd, rs1, n` followed by `srli rd, rd, n` or
+# `srai rd, rd, n` for extending a narrow integer. The encoding bits are the
+# ones of the right shift, and the amount is computed from the types.
+Rext = EncRecipe('Rext', Unary, size=8, ins=GPR, outs=GPR)
 
 I = EncRecipe(
-        'I', BinaryImm, ins=GPR, outs=GPR,
+        'I', BinaryImm, size=4, ins=GPR, outs=GPR,
         instp=IsSignedInt(BinaryImm.imm, 12))
 
+# I-type encoding of `addi rd, rs1, 0` for register copies, and of
+# `addiw rd, rs1, 0` for sign extensions in RV64.
+Icopy = EncRecipe('Icopy', Unary, size=4, ins=GPR, outs=GPR)
+
+# I-type encoding of `addi rd, rs1, 0` for register diversions. The registers
+# come from the `src` and `dst` immediate operands.
+Irmov = EncRecipe('Irmov', RegMove, size=4, ins=GPR, outs=())
+
+# I-type with `rs1 = x0` and a zero immediate. This materializes a zero value
+# as `addi rd, x0, 0`.
+Iz = EncRecipe('Iz', Nullary, size=4, ins=(), outs=GPR)
+
+# I-type with `rs1 = x0`. This materializes a 12-bit signed constant as
+# `addi rd, x0, imm`.
+Iimm = EncRecipe(
+        'Iimm', UnaryImm, size=4, ins=(), outs=GPR,
+        instp=IsSignedInt(UnaryImm.imm, 12))
+
+# U-type `lui rd, imm` materializing a 32-bit constant with the low 12 bits
+# clear. The encodings check the range of the immediate, which depends on the
+# type. The custom `iconst` legalization adds the low bits with `addi`.
+U = EncRecipe('U', UnaryImm, size=4, ins=(), outs=GPR)
+
+# R-type integer comparison. Only the `slt` and `sltu` conditions have native
+# instructions, so each encoding needs an instruction predicate on the `cond`
+# field.
+Ricmp = EncRecipe('Ricmp', IntCompare, size=4, ins=(GPR, GPR), outs=GPR)
+
+# SB-type conditional branch comparing two registers. The condition code is
+# selected by funct3, so each encoding needs an instruction predicate on the
+# `cond` field.
+SB = EncRecipe(
+        'SB', BranchIcmp, size=4, branch_range=(0, 13),
+        ins=(GPR, GPR), outs=())
+
+# SB-type branch comparing a register against `x0`, used for `brz` and `brnz`.
+SBzero = EncRecipe(
+        'SBzero', Branch, size=4, branch_range=(0, 13),
+        ins=GPR, outs=())
+
+# I-type `ebreak` with `rs1 = rd = x0` and an immediate of 1.
+Iebreak = EncRecipe('Iebreak', Trap, size=4, ins=(), outs=())
+
+# SB-type branch comparing a register against `x0` over an `ebreak`. The branch
+# skips the trap unless the condition holds, so `trapz` is encoded with `bne`
+# and `trapnz` with `beq`.
+SBtrap = EncRecipe('SBtrap', CondTrap, size=8, ins=GPR, outs=())
+
+# CB-type compressed branch comparing a register in `x8`-`x15` against `x0`,
+# encoded as `c.beqz` or `c.bnez`.
+CBzero = EncRecipe(
+        'CBzero', Branch, size=2, branch_range=(0, 9),
+        ins=GPR8, outs=())
+
+# CR-type compressed `c.add rd, rs2`. The first operand is also the result.
+CR = EncRecipe('CR', Binary, size=2, ins=(GPR, GPR), outs=0)
+
+# CA-type compressed arithmetic like `c.sub rd', rs2'` on registers in
+# `x8`-`x15`. The first operand is also the result.
+CA = EncRecipe('CA', Binary, size=2, ins=(GPR8, GPR8), outs=0)
+
+# CI-type compressed `c.addi rd, imm` with a 6-bit signed immediate. The
+# operand is also the result.
+CI = EncRecipe(
+        'CI', BinaryImm, size=2, ins=GPR, outs=0,
+        instp=IsSignedInt(BinaryImm.imm, 6))
+
+# CI-type compressed `c.slli rd, shamt`. The operand is also the result.
+CIshamt = EncRecipe('CIshamt', BinaryImm, size=2, ins=GPR, outs=0)
+
+# CB-type compressed `c.srli rd', shamt` and `c.srai rd', shamt` on registers
+# in `x8`-`x15`.
+CBshamt = EncRecipe('CBshamt', BinaryImm, size=2, ins=GPR8, outs=0)
+
+# CB-type compressed `c.andi rd', imm` with a 6-bit signed immediate.
+CBi = EncRecipe(
+        'CBi', BinaryImm, size=2, ins=GPR8, outs=0,
+        instp=IsSignedInt(BinaryImm.imm, 6))
+
+# CI-type compressed `c.li rd, 0` materializing a zero value.
+CIz = EncRecipe('CIz', Nullary, size=2, ins=(), outs=GPR)
+
+# CR-type compressed `c.mv rd, rs2` for register copies.
+CRcopy = EncRecipe('CRcopy', Unary, size=2, ins=GPR, outs=GPR)
+
+# CR-type compressed `c.mv rd, rs2` for register diversions.
+CRrmov = EncRecipe('CRrmov', RegMove, size=2, ins=GPR, outs=())
+
+# CR-type compressed `c.jr rs1` as a return instruction.
+CRret = EncRecipe('CRret', ReturnReg, size=2, ins=GPR, outs=())
+
+# UJ-type unconditional branch encoded as `jal x0, ebb`.
+# The variable EBB arguments are not encoded.
+UJ = EncRecipe(
+        'UJ', Jump, size=4, branch_range=(0, 21),
+        ins=(), outs=())
+
+# CJ-type compressed unconditional branch encoded as `c.j ebb`.
+CJ = EncRecipe(
+        'CJ', Jump, size=2, branch_range=(0, 12),
+        ins=(), outs=())
+
 # I-type encoding for `jalr` as a return instruction. We won't use the
 # immediate offset.
 # The variable return values are not encoded.
-Iret = EncRecipe('Iret', ReturnReg, ins=GPR, outs=())
+Iret = EncRecipe('Iret', ReturnReg, size=4, ins=GPR, outs=())
+
+# UJ-type encoding for `jal x0, fn` as a direct tail call. The callee address
+# is filled in by a relocation.
+# The variable call arguments are not encoded.
+UJcall = EncRecipe('UJcall', Call, size=4, ins=(), outs=())
+
+# I-type encoding for `jalr x0, rs1, 0` as an indirect tail call.
+# The variable call arguments are not encoded.
+Icall = EncRecipe('Icall', IndirectCall, size=4, ins=GPR, outs=())
+
+# UJ-type encoding for `jal x1, fn` as a direct call, saving the return
+# address in `x1`. The callee address is filled in by a relocation.
+UJlink = EncRecipe('UJlink', Call, size=4, ins=(), outs=())
+
+# I-type encoding for `jalr x1, rs1, 0` as an indirect call.
+Ilink = EncRecipe('Ilink', IndirectCall, size=4, ins=GPR, outs=())
+
+# I-type load `lw rd, offset(rs1)` with a 12-bit signed offset.
+Iload = EncRecipe(
+        'Iload', Load, size=4, ins=GPR, outs=GPR,
+        instp=IsSignedInt(Load.offset, 12))
+
+# S-type store `sw rs2, offset(rs1)` with a 12-bit signed offset.
+Sstore = EncRecipe(
+        'Sstore', Store, size=4, ins=(GPR, GPR), outs=(),
+        instp=IsSignedInt(Store.offset, 12))
+
+# Spill a register to the stack with an S-type store relative to the stack
+# pointer.
+GPsp = EncRecipe('GPsp', Unary, size=4, ins=GPR, outs=Stack(GPR))
+
+# Fill a register from the stack with an I-type load relative to the stack
+# pointer.
+GPfi = EncRecipe('GPfi', Unary, size=4, ins=Stack(GPR), outs=GPR)
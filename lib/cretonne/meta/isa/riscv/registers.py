@@ -18,6 +18,8 @@ FloatRegs = RegBank(
         units=32, prefix='f')
 
 GPR = RegClass(IntRegs)
+# The registers `x8`-`x15` that compressed instructions can encode.
+GPR8 = GPR[8:16]
 FPR = RegClass(FloatRegs)
 
 RegClass.extract_names(globals())
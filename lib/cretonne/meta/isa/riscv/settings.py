@@ -13,6 +13,8 @@ supports_m = BoolSetting("CPU supports the 'M' extension (mul/div)")
 supports_a = BoolSetting("CPU supports the 'A' extension (atomics)")
 supports_f = BoolSetting("CPU supports the 'F' extension (float)")
 supports_d = BoolSetting("CPU supports the 'D' extension (double)")
+supports_c = BoolSetting(
+        "CPU supports the 'C' extension (compressed instructions)")
 
 enable_m = BoolSetting(
         "Enable the use of 'M' instructions if available",
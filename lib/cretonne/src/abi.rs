@@ -3,7 +3,9 @@
 //! This module provides functions and data structures that are useful for implementing the
 //! `TargetIsa::legalize_signature()` method.
 
-use ir::{ArgumentLoc, ArgumentType, Type};
+use ir::{ArgumentLoc, ArgumentType, ArgumentExtension, Type};
+use std::cmp::Ordering;
+use std::vec::Vec;
 
 /// Legalization action to perform on a single argument or return value.
 ///
@@ -13,13 +15,62 @@ pub enum ArgAction {
     /// Assign the argument to the given location.
     Assign(ArgumentLoc),
 
-    /// Split the argument into smaller parts, then call again.
+    /// Convert the argument, then call again.
     ///
-    /// This action can split an integer type into two smaller integer arguments, or it can split a
-    /// SIMD vector into halves.
+    /// This action can split an integer type into two smaller integer arguments, split a SIMD
+    /// vector into halves, or extend a small integer to the size of a register.
     ///
     /// Floating point scalar types can't be split.
-    Split,
+    Convert(ValueConversion),
+}
+
+impl From<ArgumentLoc> for ArgAction {
+    fn from(x: ArgumentLoc) -> ArgAction {
+        ArgAction::Assign(x)
+    }
+}
+
+impl From<ValueConversion> for ArgAction {
+    fn from(x: ValueConversion) -> ArgAction {
+        ArgAction::Convert(x)
+    }
+}
+
+/// Legalization action to be applied to a value that is being passed to or from a legalized ABI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueConversion {
+    /// Split an integer type into low and high parts, using `isplit_lohi`.
+    IntSplit,
+
+    /// Split a vector type into halves, using `vsplit`.
+    VectorSplit,
+
+    /// Zero-extend the value to the required type.
+    Uext(Type),
+
+    /// Sign-extend the value to the required type.
+    Sext(Type),
+}
+
+impl ValueConversion {
+    /// Apply this conversion to a type, return the converted type.
+    pub fn apply(self, ty: Type) -> Type {
+        match self {
+            ValueConversion::IntSplit => ty.half_width().expect("Integer type too small to split"),
+            ValueConversion::VectorSplit => ty.half_vector().expect("Not a vector"),
+            ValueConversion::Uext(nty) |
+            ValueConversion::Sext(nty) => nty,
+        }
+    }
+
+    /// Is this a split conversion that results in two arguments?
+    pub fn is_split(self) -> bool {
+        match self {
+            ValueConversion::IntSplit |
+            ValueConversion::VectorSplit => true,
+            _ => false,
+        }
+    }
 }
 
 /// Common trait for assigning arguments to registers or stack locations.
@@ -52,21 +103,83 @@ pub fn legalize_args<AA: ArgAssigner>(args: &mut Vec<ArgumentType>, aa: &mut AA)
                 args[argno].location = loc;
                 argno += 1;
             }
-            // Split this argument into two smaller ones. Then revisit both.
-            ArgAction::Split => {
-                let new_arg = ArgumentType { value_type: split_type(arg.value_type), ..arg };
+            // Convert this argument, possibly splitting it into two smaller ones. Then revisit.
+            ArgAction::Convert(conv) => {
+                let new_arg = ArgumentType { value_type: conv.apply(arg.value_type), ..arg };
                 args[argno].value_type = new_arg.value_type;
-                args.insert(argno + 1, new_arg);
+                if conv.is_split() {
+                    args.insert(argno + 1, new_arg);
+                }
+            }
+        }
+    }
+}
+
+/// Determine the right action to take when passing a `have` value type to a call signature where
+/// the next argument is `arg` which has a different value type.
+///
+/// The signature legalization process in `legalize_args` above can replace a single argument value
+/// with multiple arguments of smaller types. It can also change the type of an integer argument to
+/// a larger integer type, requiring the smaller value to be sign- or zero-extended.
+///
+/// The legalizer needs to repair the values at all ABI boundaries: Function arguments, call
+/// arguments, return values, and call results. This function figures out how to do that.
+pub fn legalize_abi_value(have: Type, arg: &ArgumentType) -> ValueConversion {
+    let have_bits = have.bits();
+    let arg_bits = arg.value_type.bits();
+
+    match have_bits.cmp(&arg_bits) {
+        // We have fewer bits than the ABI argument.
+        Ordering::Less => {
+            assert!(have.is_int() && arg.value_type.is_int(),
+                    "Can only extend integer values");
+            match arg.extension {
+                ArgumentExtension::Uext => ValueConversion::Uext(arg.value_type),
+                ArgumentExtension::Sext => ValueConversion::Sext(arg.value_type),
+                _ => panic!("No argument extension specified"),
+            }
+        }
+        // We have the same number of bits as the argument.
+        Ordering::Equal => {
+            // This must be an integer vector that is split and then extended.
+            assert!(arg.value_type.is_int());
+            assert!(!have.is_scalar());
+            ValueConversion::VectorSplit
+        }
+        // We have more bits than the argument.
+        Ordering::Greater => {
+            if have.is_scalar() {
+                assert!(!have.is_float(), "Can't split floating point values");
+                ValueConversion::IntSplit
+            } else {
+                ValueConversion::VectorSplit
             }
         }
     }
 }
 
-/// Given a value type that isn't legal, compute a replacement type half the size.
-fn split_type(ty: Type) -> Type {
-    if ty.is_int() {
-        ty.half_width().expect("Integer type too small to split")
-    } else {
-        ty.half_vector().expect("Can only split integers and vectors")
+#[cfg(test)]
+mod tests {
+    use ir::types;
+    use ir::{ArgumentType, ArgumentExtension};
+    use super::{legalize_abi_value, ValueConversion};
+
+    #[test]
+    fn legalize() {
+        let mut arg = ArgumentType::new(types::I32);
+
+        assert_eq!(legalize_abi_value(types::I64X2, &arg),
+                   ValueConversion::VectorSplit);
+        assert_eq!(legalize_abi_value(types::I64, &arg), ValueConversion::IntSplit);
+        assert_eq!(legalize_abi_value(types::I128, &arg), ValueConversion::IntSplit);
+
+        // Vector of integers is broken down, then sign-extended.
+        arg.extension = ArgumentExtension::Sext;
+        assert_eq!(legalize_abi_value(types::I16X4, &arg),
+                   ValueConversion::VectorSplit);
+        assert_eq!(legalize_abi_value(types::I16.by(2).unwrap(), &arg),
+                   ValueConversion::VectorSplit);
+        assert_eq!(legalize_abi_value(types::I16, &arg),
+                   ValueConversion::Sext(types::I32));
     }
 }
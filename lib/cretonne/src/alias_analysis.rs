@@ -0,0 +1,317 @@
+//! Alias analysis for memory optimizations.
+//!
+//! The memory optimizations like redundant load elimination and dead store elimination need to
+//! know if two memory accesses can touch the same bytes. This module describes memory accesses
+//! with the `MemAccess` struct and defines the `AliasAnalysis` trait which answers the questions
+//! the optimizations ask about them.
+//!
+//! The `BasicAliasAnalysis` implementation only uses facts that are evident in the IL:
+//!
+//! - Every memory access goes to a stack slot, a heap, or unknown memory. Different stack slots and
+//!   heaps never overlap.
+//! - Stack slots whose address is never taken with `stack_addr` can't be accessed through pointers
+//!   or by called functions.
+//! - Accesses with the same base address and disjoint offset ranges don't overlap.
+//! - Memory accessed with the `readonly` flag is never written.
+//!
+//! Embedders that know more about the memory accessed by their code, like type-based aliasing
+//! rules of the source language, can implement the trait themselves and install their
+//! implementation in the compilation context.
+
+use entity_map::EntityRef;
+use ir::{Function, DataFlowGraph, Inst, Value, Heap, StackSlot, MemFlags, InstructionData,
+         Opcode, ValueDef};
+use std::vec::Vec;
+
+/// The class of memory that an access goes to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemClass {
+    /// A stack slot.
+    Stack(StackSlot),
+    /// A heap.
+    Heap(Heap),
+    /// Anywhere else, or we don't know.
+    Unknown,
+}
+
+/// The base of an address: Either a stack slot accessed directly, or an SSA value.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Base {
+    /// A stack slot accessed by `stack_load` or `stack_store`.
+    Slot(StackSlot),
+    /// An address computed by an SSA value.
+    Addr(Value),
+}
+
+/// A memory access performed by a load or store instruction.
+#[derive(Clone, Copy, Debug)]
+pub struct MemAccess {
+    /// The base address of the access.
+    pub base: Base,
+
+    /// Byte offset from the base address.
+    pub offset: i64,
+
+    /// The number of bytes accessed.
+    pub size: u32,
+
+    /// The class of memory accessed.
+    pub class: MemClass,
+
+    /// Flags on the memory instruction.
+    pub flags: MemFlags,
+
+    /// Does the access write memory?
+    pub is_store: bool,
+}
+
+impl MemAccess {
+    /// Get the memory access performed by `inst`, if it is a load or store.
+    ///
+    /// Instructions that access memory in other ways, like calls, return `None`.
+    pub fn from_inst(func: &Function, inst: Inst) -> Option<MemAccess> {
+        let dfg = &func.dfg;
+        let (base, offset, value, class, flags, is_store) = match dfg[inst] {
+            InstructionData::Load { flags, arg, offset, .. } => {
+                let addr = dfg.resolve_aliases(arg);
+                (Base::Addr(addr),
+                 offset as i64,
+                 dfg.first_result(inst),
+                 addr_class(dfg, addr),
+                 flags,
+                 false)
+            }
+            InstructionData::Store { flags, args, offset, .. } => {
+                let addr = dfg.resolve_aliases(args[1]);
+                (Base::Addr(addr), offset as i64, args[0], addr_class(dfg, addr), flags, true)
+            }
+            InstructionData::StackLoad { opcode: Opcode::StackLoad, stack_slot, offset, .. } => {
+                (Base::Slot(stack_slot),
+                 offset as i64,
+                 dfg.first_result(inst),
+                 MemClass::Stack(stack_slot),
+                 MemFlags::new(),
+                 false)
+            }
+            InstructionData::StackStore { arg, stack_slot, offset, .. } => {
+                (Base::Slot(stack_slot),
+                 offset as i64,
+                 arg,
+                 MemClass::Stack(stack_slot),
+                 MemFlags::new(),
+                 true)
+            }
+            _ => return None,
+        };
+        Some(MemAccess {
+                 base: base,
+                 offset: offset,
+                 size: ((dfg.value_type(value).bits() as i64 + 7) / 8) as u32,
+                 class: class,
+                 flags: flags,
+                 is_store: is_store,
+             })
+    }
+
+    /// Get an access covering the whole stack slot `ss`.
+    pub fn stack_slot(func: &Function, ss: StackSlot, is_store: bool) -> MemAccess {
+        MemAccess {
+            base: Base::Slot(ss),
+            offset: 0,
+            size: func.stack_slots[ss].size,
+            class: MemClass::Stack(ss),
+            flags: MemFlags::new(),
+            is_store: is_store,
+        }
+    }
+
+    /// Get the range of bytes accessed relative to the base.
+    pub fn range(&self) -> (i64, i64) {
+        (self.offset, self.offset + self.size as i64)
+    }
+}
+
+/// Get the memory class of the memory pointed to by `addr`.
+///
+/// Constant offsets are followed back to the `stack_addr` or `heap_addr` instruction that computed
+/// the address.
+fn addr_class(dfg: &DataFlowGraph, addr: Value) -> MemClass {
+    let mut addr = dfg.resolve_aliases(addr);
+    loop {
+        let inst = match dfg.value_def(addr) {
+            ValueDef::Res(inst, 0) => inst,
+            _ => return MemClass::Unknown,
+        };
+        match dfg[inst] {
+            InstructionData::StackLoad { opcode: Opcode::StackAddr, stack_slot, .. } => {
+                return MemClass::Stack(stack_slot)
+            }
+            InstructionData::HeapAddr { heap, .. } => return MemClass::Heap(heap),
+            InstructionData::BinaryImm { opcode: Opcode::IaddImm, arg, .. } => {
+                addr = dfg.resolve_aliases(arg);
+            }
+            _ => return MemClass::Unknown,
+        }
+    }
+}
+
+/// An oracle answering aliasing questions about the memory accesses in a function.
+///
+/// The answers must be conservative: When in doubt, accesses may alias and memory may escape.
+pub trait AliasAnalysis {
+    /// Prepare for answering questions about `func`.
+    ///
+    /// This is called before an optimization pass uses the analysis, and again whenever the
+    /// function has changed.
+    fn compute(&mut self, func: &Function);
+
+    /// Can the accesses `a` and `b` touch the same bytes, and at least one of them write them?
+    fn may_alias(&self, a: &MemAccess, b: &MemAccess) -> bool;
+
+    /// Can the memory accessed by `access` also be accessed by instructions that aren't plain
+    /// loads and stores, like calls?
+    fn may_escape(&self, access: &MemAccess) -> bool;
+
+    /// Can the memory instructions `a` and `b` access the same bytes, and at least one of them
+    /// write them?
+    ///
+    /// Instructions that are not plain loads and stores may alias anything that escapes.
+    fn may_alias_insts(&self, func: &Function, a: Inst, b: Inst) -> bool {
+        match (MemAccess::from_inst(func, a), MemAccess::from_inst(func, b)) {
+            (Some(a), Some(b)) => self.may_alias(&a, &b),
+            (Some(access), None) |
+            (None, Some(access)) => self.may_escape(&access),
+            (None, None) => true,
+        }
+    }
+}
+
+/// The conservative default alias analysis.
+pub struct BasicAliasAnalysis {
+    /// Stack slots whose address has been taken.
+    escaping: Vec<bool>,
+}
+
+impl BasicAliasAnalysis {
+    /// Create a new alias analysis. Use `compute` to prepare it for a function.
+    pub fn new() -> BasicAliasAnalysis {
+        BasicAliasAnalysis { escaping: Vec::new() }
+    }
+
+    /// Create an alias analysis for `func`.
+    pub fn with_function(func: &Function) -> BasicAliasAnalysis {
+        let mut aa = BasicAliasAnalysis::new();
+        aa.compute(func);
+        aa
+    }
+
+    /// Can an access to memory of class `class` be made through a pointer of unknown origin?
+    fn class_escapes(&self, class: MemClass) -> bool {
+        match class {
+            MemClass::Stack(ss) => self.escaping.get(ss.index()).cloned().unwrap_or(true),
+            _ => true,
+        }
+    }
+}
+
+impl AliasAnalysis for BasicAliasAnalysis {
+    fn compute(&mut self, func: &Function) {
+        self.escaping.clear();
+        self.escaping.resize(func.stack_slots.len(), false);
+        for ebb in &func.layout {
+            for inst in func.layout.ebb_insts(ebb) {
+                if let InstructionData::StackLoad { opcode: Opcode::StackAddr, stack_slot, .. } =
+                    func.dfg[inst] {
+                    self.escaping[stack_slot.index()] = true;
+                }
+            }
+        }
+    }
+
+    fn may_alias(&self, a: &MemAccess, b: &MemAccess) -> bool {
+        if !a.is_store && !b.is_store {
+            return false;
+        }
+        // A store can't change `readonly` memory.
+        if a.flags.readonly() || b.flags.readonly() {
+            return false;
+        }
+        let overlap = match (a.class, b.class) {
+            (MemClass::Unknown, MemClass::Unknown) => true,
+            (MemClass::Unknown, c) |
+            (c, MemClass::Unknown) => self.class_escapes(c),
+            (c1, c2) => c1 == c2,
+        };
+        if !overlap {
+            return false;
+        }
+        if a.base != b.base {
+            return true;
+        }
+        let (lo1, hi1) = a.range();
+        let (lo2, hi2) = b.range();
+        lo1 < hi2 && lo2 < hi1
+    }
+
+    fn may_escape(&self, access: &MemAccess) -> bool {
+        self.class_escapes(access.class)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, InstBuilder, MemFlags, Signature, StackSlotData, StackSlotKind,
+             VariableArgs};
+    use ir::types;
+    use super::{AliasAnalysis, BasicAliasAnalysis};
+
+    #[test]
+    fn basic() {
+        let mut func = Function::new();
+        let ss0 = func.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+        let ss1 = func.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I64);
+        let sig = func.dfg.signatures.push(Signature::new());
+        let mut ro = MemFlags::new();
+        ro.set_readonly();
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).stack_store(v0, ss0, 0u32);
+            dfg.ins(pos).stack_store(v0, ss1, 0u32);
+            dfg.ins(pos).store(MemFlags::new(), v0, v1, 0);
+            dfg.ins(pos).stack_load(types::I32, ss0, 0u32);
+            dfg.ins(pos).stack_load(types::I32, ss0, 4u32);
+            dfg.ins(pos).load(types::I32, ro, v1, 0);
+            let addr = dfg.ins(pos).stack_addr(types::I64, ss1, 0u32);
+            dfg.ins(pos).load(types::I32, MemFlags::new(), addr, 4);
+            dfg.ins(pos).call_indirect(sig, v1, VariableArgs::new());
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+        let insts: Vec<_> = func.layout.ebb_insts(ebb0).collect();
+        let (st0, st1, st_ptr, ld_lo, ld_hi, ld_ro, ld_addr, call) =
+            (insts[0], insts[1], insts[2], insts[3], insts[4], insts[5], insts[7], insts[8]);
+
+        let aa = BasicAliasAnalysis::with_function(&func);
+        let may_alias = |a, b| aa.may_alias_insts(&func, a, b);
+
+        // Different stack slots, and disjoint ranges of the same slot.
+        assert!(may_alias(st0, ld_lo));
+        assert!(!may_alias(st0, ld_hi));
+        assert!(!may_alias(st0, st1));
+        assert!(!may_alias(ld_lo, ld_hi));
+
+        // Only `ss1` has its address taken.
+        assert!(!may_alias(st_ptr, ld_lo));
+        assert!(may_alias(st_ptr, ld_addr));
+        assert!(!may_alias(st0, ld_addr));
+        assert!(!may_alias(call, st0));
+        assert!(may_alias(call, st1));
+
+        // Nothing writes `readonly` memory.
+        assert!(!may_alias(st_ptr, ld_ro));
+    }
+}
@@ -0,0 +1,133 @@
+//! A code sink that hashes the emitted code.
+
+use binemit::{CodeSink, CodeOffset, Reloc, Addend, Stackmap};
+use ir::{ExternalName, JumpTable, TrapCode, SourceLoc};
+use stable_hash::StableHasher;
+use std::fmt::Write;
+use std::hash::Hasher;
+
+/// A `CodeSink` that hashes the machine code bytes along with the relocations, traps, and stack
+/// maps, instead of storing them.
+///
+/// Two functions emitted to a `HashSink` get the same hash when their code would be the same, so
+/// this can be used to check that compilation is deterministic, or as a cache key.
+pub struct HashSink {
+    offset: CodeOffset,
+    hasher: StableHasher,
+}
+
+impl HashSink {
+    /// Create a new empty hash sink.
+    pub fn new() -> HashSink {
+        HashSink {
+            offset: 0,
+            hasher: StableHasher::new(),
+        }
+    }
+
+    /// Get the hash of everything emitted so far.
+    pub fn finish(&self) -> u64 {
+        self.hasher.finish()
+    }
+}
+
+impl CodeSink for HashSink {
+    fn offset(&self) -> CodeOffset {
+        self.offset
+    }
+
+    fn put1(&mut self, x: u8) {
+        self.hasher.write_u8(x);
+        self.offset += 1;
+    }
+
+    fn put2(&mut self, x: u16) {
+        self.hasher.write_u16(x);
+        self.offset += 2;
+    }
+
+    fn put4(&mut self, x: u32) {
+        self.hasher.write_u32(x);
+        self.offset += 4;
+    }
+
+    fn put8(&mut self, x: u64) {
+        self.hasher.write_u64(x);
+        self.offset += 8;
+    }
+
+    // The metadata is hashed along with the current offset, and a tag byte that keeps it apart
+    // from the code bytes.
+
+    fn reloc_ebb(&mut self, reloc: Reloc, ebb_offset: CodeOffset) {
+        self.hasher.write_u8(0xf0);
+        self.hasher.write_u32(self.offset);
+        self.hasher.write_u16(reloc.0);
+        self.hasher.write_u32(ebb_offset);
+    }
+
+    fn reloc_external(&mut self, reloc: Reloc, name: &ExternalName, addend: Addend) {
+        self.hasher.write_u8(0xf1);
+        self.hasher.write_u32(self.offset);
+        self.hasher.write_u16(reloc.0);
+        // Writing to a `StableHasher` can't fail.
+        let _ = write!(self.hasher, "{}", name);
+        self.hasher.write_u8(0);
+        self.hasher.write_u64(addend as u64);
+    }
+
+    fn reloc_jt(&mut self, reloc: Reloc, jt: JumpTable) {
+        self.hasher.write_u8(0xf2);
+        self.hasher.write_u32(self.offset);
+        self.hasher.write_u16(reloc.0);
+        let _ = write!(self.hasher, "{}", jt);
+        self.hasher.write_u8(0);
+    }
+
+    fn trap(&mut self, code: TrapCode, srcloc: SourceLoc) {
+        self.hasher.write_u8(0xf3);
+        self.hasher.write_u32(self.offset);
+        let _ = write!(self.hasher, "{} {}", code, srcloc);
+        self.hasher.write_u8(0);
+    }
+
+    fn add_stackmap(&mut self, map: &Stackmap) {
+        self.hasher.write_u8(0xf4);
+        self.hasher.write_u32(self.offset);
+        self.hasher.write_usize(map.registers.len());
+        for &reg in &map.registers {
+            self.hasher.write_u16(reg);
+        }
+        self.hasher.write_usize(map.stack_offsets.len());
+        for &offset in &map.stack_offsets {
+            self.hasher.write_u32(offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashSink;
+    use binemit::{CodeSink, Reloc};
+    use ir::{ExternalName, TrapCode, SourceLoc};
+
+    #[test]
+    fn metadata() {
+        let mut a = HashSink::new();
+        let mut b = HashSink::new();
+        a.put4(0x1234_5678);
+        b.put2(0x5678);
+        b.put2(0x1234);
+        assert_eq!(a.offset(), 4);
+        assert_eq!(a.finish(), b.finish());
+
+        a.reloc_external(Reloc(1), &ExternalName::testcase("foo"), -4);
+        assert!(a.finish() != b.finish());
+        b.reloc_external(Reloc(1), &ExternalName::testcase("foo"), -4);
+        assert_eq!(a.finish(), b.finish());
+
+        a.trap(TrapCode::HeapOutOfBounds, SourceLoc::default());
+        b.trap(TrapCode::StackOverflow, SourceLoc::default());
+        assert!(a.finish() != b.finish());
+    }
+}
@@ -0,0 +1,232 @@
+//! Code sink that writes binary machine code into contiguous memory.
+//!
+//! A JIT compiler embedding Cretonne usually knows where the function's code will live before it
+//! is emitted: `Context::compile()` returns the code size, so the embedder can allocate
+//! executable memory up front. The `MemoryCodeSink` writes the machine code straight into that
+//! memory, avoiding the intermediate `Vec<u8>` and the copy out of it.
+//!
+//! The relocations, trap sites, and stack maps are forwarded to the `RelocSink`, `TrapSink`, and
+//! `StackmapSink` traits, which receive the code offset of each one along with the information
+//! passed to the `CodeSink`.
+
+use ir::{ExternalName, JumpTable, TrapCode, SourceLoc};
+use super::{CodeSink, CodeOffset, Reloc, Addend, Stackmap};
+use std::ptr::write_unaligned;
+
+/// A `CodeSink` that writes binary machine code directly into memory.
+///
+/// A `MemoryCodeSink` object should be used when emitting a Cretonne IR function into executable
+/// memory. It writes machine code directly to a raw pointer without any bounds checking, so make
+/// sure to allocate enough memory for the whole function. The number of bytes required is
+/// returned by the `Context::compile()` function.
+///
+/// Any relocations in the function are forwarded to the `RelocSink` trait object, the trap
+/// sites to the `TrapSink` trait object, and the stack maps to the `StackmapSink` trait object.
+pub struct MemoryCodeSink<'a> {
+    data: *mut u8,
+    offset: isize,
+    relocs: &'a mut RelocSink,
+    traps: &'a mut TrapSink,
+    stackmaps: &'a mut StackmapSink,
+}
+
+impl<'a> MemoryCodeSink<'a> {
+    /// Create a new memory code sink that writes a function to the memory pointed to by `data`.
+    ///
+    /// # Safety
+    ///
+    /// `MemoryCodeSink` does not perform bounds checking on the memory buffer, so `data` must be
+    /// valid for writing all of the function's machine code.
+    pub unsafe fn new(data: *mut u8,
+                      relocs: &'a mut RelocSink,
+                      traps: &'a mut TrapSink,
+                      stackmaps: &'a mut StackmapSink)
+                      -> MemoryCodeSink<'a> {
+        MemoryCodeSink {
+            data: data,
+            offset: 0,
+            relocs: relocs,
+            traps: traps,
+            stackmaps: stackmaps,
+        }
+    }
+
+    /// Write `x` at the current position and advance past it.
+    fn write<T>(&mut self, x: T, size: isize) {
+        unsafe {
+            write_unaligned(self.data.offset(self.offset) as *mut T, x);
+        }
+        self.offset += size;
+    }
+}
+
+/// A trait for receiving relocations for code that is emitted directly into memory.
+pub trait RelocSink {
+    /// Add a relocation referencing an EBB at `offset`.
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset);
+
+    /// Add a relocation referencing an external symbol plus an addend at `offset`.
+    fn reloc_external(&mut self,
+                      offset: CodeOffset,
+                      reloc: Reloc,
+                      name: &ExternalName,
+                      addend: Addend);
+
+    /// Add a relocation referencing a jump table at `offset`.
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable);
+}
+
+/// A trait for receiving trap codes and offsets.
+///
+/// If you don't need information about possible traps, you can use the `NullTrapSink`
+/// implementation. The `TrapTable` implementation collects the trap sites in a table that can be
+/// searched by code offset.
+pub trait TrapSink {
+    /// Add trap information for the instruction at `offset`.
+    fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode);
+}
+
+/// A trait for receiving the stack maps of safepoints.
+///
+/// If the function doesn't use reference types, you can use the `NullStackmapSink`
+/// implementation.
+pub trait StackmapSink {
+    /// Add the stack map for the safepoint at `offset`.
+    fn add_stackmap(&mut self, offset: CodeOffset, map: &Stackmap);
+}
+
+impl<'a> CodeSink for MemoryCodeSink<'a> {
+    fn offset(&self) -> CodeOffset {
+        self.offset as CodeOffset
+    }
+
+    fn put1(&mut self, x: u8) {
+        self.write(x, 1);
+    }
+
+    fn put2(&mut self, x: u16) {
+        self.write(x.to_le(), 2);
+    }
+
+    fn put4(&mut self, x: u32) {
+        self.write(x.to_le(), 4);
+    }
+
+    fn put8(&mut self, x: u64) {
+        self.write(x.to_le(), 8);
+    }
+
+    fn reloc_ebb(&mut self, reloc: Reloc, ebb_offset: CodeOffset) {
+        let ofs = self.offset();
+        self.relocs.reloc_ebb(ofs, reloc, ebb_offset);
+    }
+
+    fn reloc_external(&mut self, reloc: Reloc, name: &ExternalName, addend: Addend) {
+        let ofs = self.offset();
+        self.relocs.reloc_external(ofs, reloc, name, addend);
+    }
+
+    fn reloc_jt(&mut self, reloc: Reloc, jt: JumpTable) {
+        let ofs = self.offset();
+        self.relocs.reloc_jt(ofs, reloc, jt);
+    }
+
+    fn trap(&mut self, code: TrapCode, srcloc: SourceLoc) {
+        let ofs = self.offset();
+        self.traps.trap(ofs, srcloc, code);
+    }
+
+    fn add_stackmap(&mut self, map: &Stackmap) {
+        let ofs = self.offset();
+        self.stackmaps.add_stackmap(ofs, map);
+    }
+}
+
+/// A `TrapSink` implementation that does nothing, which is convenient when compiling code that
+/// doesn't need trap information.
+pub struct NullTrapSink {}
+
+impl TrapSink for NullTrapSink {
+    fn trap(&mut self, _offset: CodeOffset, _srcloc: SourceLoc, _code: TrapCode) {}
+}
+
+/// A `StackmapSink` implementation that does nothing, which is convenient when compiling code
+/// that doesn't use reference types.
+pub struct NullStackmapSink {}
+
+impl StackmapSink for NullStackmapSink {
+    fn add_stackmap(&mut self, _offset: CodeOffset, _map: &Stackmap) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::{ExternalName, JumpTable, TrapCode, SourceLoc};
+    use binemit::{CodeSink, CodeOffset, Reloc, Addend, Stackmap};
+
+    /// Record the relocations, traps, and stack maps as strings.
+    struct Recorder(Vec<String>);
+
+    impl RelocSink for Recorder {
+        fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+            self.0.push(format!("{}: ebb {} {}", offset, reloc.0, ebb_offset));
+        }
+
+        fn reloc_external(&mut self,
+                          offset: CodeOffset,
+                          reloc: Reloc,
+                          name: &ExternalName,
+                          addend: Addend) {
+            self.0.push(format!("{}: {} {} {}", offset, reloc.0, name, addend));
+        }
+
+        fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+            self.0.push(format!("{}: {} {}", offset, reloc.0, jt));
+        }
+    }
+
+    impl TrapSink for Recorder {
+        fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode) {
+            self.0.push(format!("{}: trap {} {}", offset, code, srcloc));
+        }
+    }
+
+    impl StackmapSink for Recorder {
+        fn add_stackmap(&mut self, offset: CodeOffset, map: &Stackmap) {
+            self.0
+                .push(format!("{}: regs {:?} stack {:?}",
+                              offset,
+                              map.registers,
+                              map.stack_offsets));
+        }
+    }
+
+    #[test]
+    fn write_to_memory() {
+        let mut mem = [0u8; 16];
+        let mut relocs = Recorder(Vec::new());
+        let mut traps = Recorder(Vec::new());
+        let mut stackmaps = Recorder(Vec::new());
+        {
+            let mut sink = unsafe {
+                MemoryCodeSink::new(mem.as_mut_ptr(), &mut relocs, &mut traps, &mut stackmaps)
+            };
+            sink.put1(0x01);
+            sink.put2(0x0302);
+            sink.reloc_external(Reloc(1), &ExternalName::testcase("foo"), -4);
+            sink.put4(0x07060504);
+            sink.trap(TrapCode::HeapOutOfBounds, SourceLoc::new(0x20));
+            sink.put8(0x0f0e0d0c0b0a0908);
+            sink.add_stackmap(&Stackmap {
+                                  registers: vec![3],
+                                  stack_offsets: vec![8, 16],
+                              });
+            assert_eq!(sink.offset(), 15);
+        }
+        assert_eq!(mem[..15], [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+        assert_eq!(mem[15], 0);
+        assert_eq!(relocs.0, ["3: 1 foo -4"]);
+        assert_eq!(traps.0, ["7: trap heap_oob @0020"]);
+        assert_eq!(stackmaps.0, ["15: regs [3] stack [8, 16]"]);
+    }
+}
@@ -0,0 +1,240 @@
+//! Binary machine code emission.
+//!
+//! The `binemit` module contains code for translating Cretonne's intermediate representation into
+//! binary machine code.
+//!
+//! The code size of every encoding recipe is known, so the layout of the emitted code is computed
+//! ahead of time by `relax_branches()`, and can be recomputed with `code_size()`. After that,
+//! `emit_function()` writes the machine code bytes of each instruction to a `CodeSink`. The Intel
+//! and RISC-V ISAs can emit machine code.
+//!
+//! The jump tables follow the code in a read-only data area. In position-independent code, the
+//! entries are 32-bit offsets of the destination EBBs from the start of the table. Otherwise they
+//! are the absolute addresses of the destinations, emitted with relocations.
+//!
+//! A `Vec<u8>` can be used as a code sink that collects the bytes, and the `MemoryCodeSink` writes
+//! them directly into memory allocated by a JIT compiler. The `HashSink` computes a stable hash of
+//! the code and its relocations instead.
+//!
+//! Functions using reference types also report a `Stackmap` at each safepoint, so a garbage
+//! collector can find the live references in their stack frames.
+
+mod hashsink;
+mod memorysink;
+mod relaxation;
+mod stackmap;
+mod traptable;
+
+pub use self::hashsink::HashSink;
+pub use self::memorysink::{MemoryCodeSink, RelocSink, TrapSink, NullTrapSink, StackmapSink,
+                           NullStackmapSink};
+pub use self::relaxation::{relax_branches, code_size};
+pub use self::stackmap::Stackmap;
+pub use self::traptable::{TrapSite, TrapTable};
+
+use ir::{Function, ExternalName, Inst, JumpTable, StackSlot, TrapCode, SourceLoc, Value,
+         ValueLoc};
+use isa::{RegUnit, TargetIsa};
+use regalloc::diversion::RegDiversions;
+use std::vec::Vec;
+
+/// Offset in bytes from the beginning of the function.
+///
+/// Cretonne can be used as a cross compiler, so we don't want to use a type like `usize` which
+/// depends on the *host* platform, not the *target* platform.
+pub type CodeOffset = u32;
+
+/// Relocation kinds depend on the current ISA.
+///
+/// Each ISA defines its relocation kinds as an enum which can be converted to a `Reloc`. The name
+/// of a relocation kind is given by `TargetIsa::reloc_names()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reloc(pub u16);
+
+/// Addend to add to the symbol value when applying a relocation.
+pub type Addend = i64;
+
+/// Abstract interface for adding bytes to the code segment.
+///
+/// A `CodeSink` receives the machine code bytes of a function in order. Multi-byte values are
+/// written in little-endian byte order, which is what all the supported ISAs use.
+pub trait CodeSink {
+    /// Get the current position, which is the number of bytes emitted so far.
+    fn offset(&self) -> CodeOffset;
+
+    /// Add 1 byte to the code section.
+    fn put1(&mut self, byte: u8);
+
+    /// Add 2 bytes to the code section.
+    fn put2(&mut self, half: u16);
+
+    /// Add 4 bytes to the code section.
+    fn put4(&mut self, word: u32);
+
+    /// Add 8 bytes to the code section.
+    fn put8(&mut self, dword: u64);
+
+    /// Add a relocation referencing an EBB at the current offset.
+    ///
+    /// The emitted bytes already hold the displacement to `ebb_offset`, so the relocation is only
+    /// needed by a consumer that moves the code around.
+    fn reloc_ebb(&mut self, reloc: Reloc, ebb_offset: CodeOffset);
+
+    /// Add a relocation referencing an external symbol plus an addend at the current offset.
+    ///
+    /// The bytes at the current offset are left as zeros to be filled in when the relocation is
+    /// applied.
+    fn reloc_external(&mut self, reloc: Reloc, name: &ExternalName, addend: Addend);
+
+    /// Add a relocation referencing a jump table at the current offset.
+    fn reloc_jt(&mut self, reloc: Reloc, jt: JumpTable);
+
+    /// Add trap information for the current offset.
+    ///
+    /// This is called before emitting an instruction that traps with `code`, so the runtime can
+    /// map the faulting address back to the reason for the trap and the source location `srcloc`
+    /// of the trapping instruction.
+    fn trap(&mut self, code: TrapCode, srcloc: SourceLoc);
+
+    /// Add a stack map for the safepoint at the current offset.
+    ///
+    /// The stack map lists the locations of the references that are live across the safepoint.
+    fn add_stackmap(&mut self, map: &Stackmap);
+}
+
+/// A `CodeSink` that appends the emitted bytes to a vector and ignores the relocations, traps, and
+/// stack maps.
+impl CodeSink for Vec<u8> {
+    fn offset(&self) -> CodeOffset {
+        self.len() as CodeOffset
+    }
+
+    fn put1(&mut self, x: u8) {
+        self.push(x);
+    }
+
+    fn put2(&mut self, x: u16) {
+        self.put1(x as u8);
+        self.put1((x >> 8) as u8);
+    }
+
+    fn put4(&mut self, x: u32) {
+        self.put2(x as u16);
+        self.put2((x >> 16) as u16);
+    }
+
+    fn put8(&mut self, x: u64) {
+        self.put4(x as u32);
+        self.put4((x >> 32) as u32);
+    }
+
+    fn reloc_ebb(&mut self, _reloc: Reloc, _ebb_offset: CodeOffset) {}
+
+    fn reloc_external(&mut self, _reloc: Reloc, _name: &ExternalName, _addend: Addend) {}
+
+    fn reloc_jt(&mut self, _reloc: Reloc, _jt: JumpTable) {}
+
+    fn trap(&mut self, _code: TrapCode, _srcloc: SourceLoc) {}
+
+    fn add_stackmap(&mut self, _map: &Stackmap) {}
+}
+
+/// Emit the machine code for all the instructions in `func` to `sink`, followed by the jump
+/// tables.
+///
+/// This must be called after `relax_branches()` has picked the final encodings and computed the
+/// EBB and jump table offsets. The EBBs are emitted in layout order, so the offset of each EBB
+/// header matches `func.offsets` when the sink starts out empty.
+pub fn emit_function(func: &Function, isa: &TargetIsa, sink: &mut CodeSink) {
+    let mut divert = RegDiversions::new();
+    for ebb in func.layout.ebbs() {
+        divert.clear();
+        debug_assert_eq!(func.offsets[ebb], sink.offset());
+        for inst in func.layout.ebb_insts(ebb) {
+            if func.encodings.get(inst).map_or(false, |enc| enc.is_legal()) {
+                isa.emit_inst(func, inst, &mut divert, sink);
+            }
+        }
+    }
+    emit_jump_tables(func, isa, sink);
+}
+
+/// Get the size in bytes of the jump table entries for `isa`.
+///
+/// Position-independent code uses 32-bit relative entries, and other code uses absolute addresses.
+pub fn jump_table_entry_size(isa: &TargetIsa) -> u8 {
+    let flags = isa.flags();
+    if !flags.is_pic() && flags.is_64bit() {
+        8
+    } else {
+        4
+    }
+}
+
+/// Emit the jump tables of `func` after its code, padded to the offsets in `func.jt_offsets`.
+///
+/// The absolute entries are relocated with `TargetIsa::jump_table_reloc()`, and the emitted
+/// bytes hold the offset of the destination from the start of the function. Missing entries
+/// are emitted as zeros since the legalizer never uses a jump table with holes.
+fn emit_jump_tables(func: &Function, isa: &TargetIsa, sink: &mut CodeSink) {
+    let entry_size = jump_table_entry_size(isa);
+    let relative = isa.flags().is_pic();
+    for jt in func.jump_tables.keys() {
+        let jt_offset = func.jt_offsets[jt];
+        while sink.offset() < jt_offset {
+            sink.put1(0);
+        }
+        for idx in 0..func.jump_tables[jt].len() {
+            let dest = match func.jump_tables[jt].get_entry(idx) {
+                Some(ebb) => func.offsets[ebb],
+                None => {
+                    put_entry(sink, entry_size, 0);
+                    continue;
+                }
+            };
+            if relative {
+                put_entry(sink, entry_size, dest.wrapping_sub(jt_offset) as u64);
+            } else {
+                if let Some(reloc) = isa.jump_table_reloc() {
+                    sink.reloc_ebb(reloc, dest);
+                }
+                put_entry(sink, entry_size, dest as u64);
+            }
+        }
+    }
+}
+
+/// Emit a jump table entry of `size` bytes.
+fn put_entry(sink: &mut CodeSink, size: u8, value: u64) {
+    if size == 8 {
+        sink.put8(value);
+    } else {
+        sink.put4(value as u32);
+    }
+}
+
+/// Report a bad encoding error.
+#[inline(never)]
+pub fn bad_encoding(func: &Function, inst: Inst) -> ! {
+    panic!("Bad encoding {} for {}",
+           func.encodings[inst],
+           func.dfg[inst].opcode());
+}
+
+/// Get the register holding `value` while emitting an instruction.
+///
+/// The register assigned in `func.locations` is overridden by the diversions in `divert`.
+pub fn value_reg(func: &Function, divert: &RegDiversions, value: Value) -> RegUnit {
+    match divert.location(func.dfg.resolve_aliases(value), &func.locations) {
+        ValueLoc::Reg(reg) => reg,
+        loc => panic!("Expected {} in a register, found {:?}", value, loc),
+    }
+}
+
+/// Get the stack slot holding `value` while emitting an instruction.
+pub fn value_stack(func: &Function, value: Value) -> StackSlot {
+    match func.locations.get(func.dfg.resolve_aliases(value)) {
+        Some(&ValueLoc::Stack(ss)) => ss,
+        loc => panic!("Expected {} in a stack slot, found {:?}", value, loc),
+    }
+}
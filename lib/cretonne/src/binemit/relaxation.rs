@@ -0,0 +1,277 @@
+//! Branch relaxation and offset computation.
+//!
+//! # EBB header offsets
+//!
+//! Before we can generate binary machine code for branch instructions, we need to know the final
+//! offsets of all the EBB headers in the function. This information is encoded in the
+//! `func.offsets` table.
+//!
+//! # Branch relaxation
+//!
+//! Branch relaxation is the process of ensuring that all branches in the function have enough
+//! range to encode their destination. It is common to have multiple branch encodings in an ISA.
+//! For example, Intel branches can have either an 8-bit or a 32-bit displacement, and the RISC-V
+//! 'C' extension has compressed branches with a shorter range than the normal ones.
+//!
+//! The legalizer assigns the most general branch encodings, which can reach any destination. This
+//! pass starts by shrinking every branch to its smallest encoding whose operand constraints are
+//! satisfied by the registers assigned to its arguments. It then computes the code offsets and
+//! switches the branches that can't reach their destination to a larger encoding with enough
+//! range. Branches only ever grow, so the offsets keep increasing until they converge.
+//!
+//! # Jump tables
+//!
+//! The jump tables are placed in a read-only data area after the code, aligned to the size of
+//! their entries. Their offsets are computed along with the EBB offsets and stored in
+//! `func.jt_offsets`, and the returned function size includes them.
+//!
+//! # Compressed encodings
+//!
+//! The other instructions are shrunk the same way before the offsets are computed. Some ISAs have
+//! short encodings that can only be used when the operands are in a restricted set of registers,
+//! like the RISC-V 'C' extension. The register allocator works with the general encodings chosen
+//! by the legalizer, so the short encodings are only picked here once the registers are known.
+
+use binemit::{CodeOffset, jump_table_entry_size};
+use entity_map::EntityMap;
+use ir::{Function, DataFlowGraph, Ebb, Inst, Value, ValueLoc};
+use ir::instructions::BranchInfo;
+use isa::{TargetIsa, Encoding, ConstraintKind, OperandConstraint, RecipeSizing, RegUnit};
+use regalloc::diversion::RegDiversions;
+use result::CtonError;
+use stack_layout::layout_stack;
+use std::vec::Vec;
+
+/// Relax branches and compute the final layout of EBB headers in `func`.
+///
+/// Fill in the `func.offsets`, `func.stack_offsets`, and `func.jt_offsets` tables so the function
+/// is ready for binary emission, and return the total size of the function in bytes.
+pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> Result<CodeOffset, CtonError> {
+    let sizing = isa.recipe_sizing();
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    func.stack_offsets = layout_stack(func, isa.stack_alignment()).offsets;
+
+    // The first pass picks the smallest encodings, so the offsets it computes are lower bounds.
+    let candidates = shrink_encodings(func, isa, &ebbs);
+
+    loop {
+        let mut changed = false;
+        let mut out_of_range = false;
+        let mut offset = 0;
+        for &ebb in &ebbs {
+            if func.offsets[ebb] != offset {
+                func.offsets[ebb] = offset;
+                changed = true;
+            }
+            for inst in func.layout.ebb_insts(ebb) {
+                let mut enc = encoding(func, inst);
+                if let Some(dest) = branch_destination(&func.dfg, inst) {
+                    if let Some(range) = recipe_sizing(sizing, enc).branch_range {
+                        let dest_offset = func.offsets[dest];
+                        if !range.contains(offset, dest_offset) {
+                            let encs = &candidates[inst];
+                            match relax_branch(sizing, encs, enc, offset, dest_offset) {
+                                Some(relaxed) => {
+                                    enc = relaxed;
+                                    *func.encodings.ensure(inst) = enc;
+                                    changed = true;
+                                }
+                                None => out_of_range = true,
+                            }
+                        }
+                    }
+                }
+                offset += recipe_sizing(sizing, enc).bytes as CodeOffset;
+            }
+        }
+
+        if !changed {
+            if out_of_range {
+                return Err(CtonError::CodeTooLarge);
+            }
+            return Ok(layout_jump_tables(func, isa, offset));
+        }
+    }
+}
+
+/// Compute the size of the code for `func` and the offsets of its EBB headers.
+///
+/// This sums the sizes of the encoding recipes of the instructions as they are currently encoded,
+/// so it should be called after `relax_branches()` has picked the final encodings. The EBB offsets
+/// are written to `func.offsets`, the jump table offsets to `func.jt_offsets`, and the total size
+/// of the function in bytes is returned.
+///
+/// This can be used to allocate exactly the memory needed by `emit_function()`, or to report the
+/// code size without emitting the machine code.
+pub fn code_size(func: &mut Function, isa: &TargetIsa) -> CodeOffset {
+    let sizing = isa.recipe_sizing();
+    func.offsets.clear();
+    func.offsets.resize(func.dfg.num_ebbs());
+
+    let mut offset = 0;
+    for ebb in func.layout.ebbs() {
+        func.offsets[ebb] = offset;
+        for inst in func.layout.ebb_insts(ebb) {
+            offset += recipe_sizing(sizing, encoding(func, inst)).bytes as CodeOffset;
+        }
+    }
+    layout_jump_tables(func, isa, offset)
+}
+
+/// Compute the offsets of the jump tables following `code_size` bytes of code.
+///
+/// Return the total size of the code and the jump tables.
+fn layout_jump_tables(func: &mut Function, isa: &TargetIsa, code_size: CodeOffset) -> CodeOffset {
+    let entry_size = jump_table_entry_size(isa) as CodeOffset;
+    func.jt_offsets.clear();
+    func.jt_offsets.resize(func.jump_tables.len());
+
+    let mut offset = code_size;
+    for jt in func.jump_tables.keys() {
+        offset = (offset + entry_size - 1) & !(entry_size - 1);
+        func.jt_offsets[jt] = offset;
+        offset += func.jump_tables[jt].len() as CodeOffset * entry_size;
+    }
+    offset
+}
+
+/// Switch all the instructions in `func` to their smallest encoding, and compute the EBB offsets
+/// for the shrunk code.
+///
+/// Return the branch encodings that can be used with the registers assigned to each branch,
+/// ordered by size.
+fn shrink_encodings(func: &mut Function,
+                    isa: &TargetIsa,
+                    ebbs: &[Ebb])
+                    -> EntityMap<Inst, Vec<Encoding>> {
+    let sizing = isa.recipe_sizing();
+    let mut candidates = EntityMap::new();
+    let mut divert = RegDiversions::new();
+
+    func.offsets.clear();
+    func.offsets.resize(func.dfg.num_ebbs());
+
+    let mut offset = 0;
+    for &ebb in ebbs {
+        func.offsets[ebb] = offset;
+        divert.clear();
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        for inst in insts {
+            let enc = encoding(func, inst);
+            if enc.is_legal() {
+                let is_branch = branch_destination(&func.dfg, inst).is_some() &&
+                                recipe_sizing(sizing, enc).branch_range.is_some();
+                // Keep the current encoding first so it wins ties.
+                let mut encs = vec![enc];
+                if let Ok(legal) = isa.legal_encodings(&func.dfg, &func.dfg[inst]) {
+                    for e in legal {
+                        if e != enc &&
+                           recipe_sizing(sizing, e).branch_range.is_some() == is_branch &&
+                           operands_fit(func, isa, &divert, inst, enc, e) {
+                            encs.push(e);
+                        }
+                    }
+                }
+                encs.sort_by_key(|&e| recipe_sizing(sizing, e).bytes);
+                *func.encodings.ensure(inst) = encs[0];
+                if is_branch {
+                    *candidates.ensure(inst) = encs;
+                }
+            }
+            divert.apply(&func.dfg[inst]);
+            offset += recipe_sizing(sizing, encoding(func, inst)).bytes as CodeOffset;
+        }
+    }
+
+    candidates
+}
+
+/// Pick a larger encoding for a branch at `offset` that can't reach `dest_offset` with `enc`.
+///
+/// Return `None` if none of the `candidates` that are larger than `enc` can reach the destination.
+fn relax_branch(sizing: &[RecipeSizing],
+                candidates: &[Encoding],
+                enc: Encoding,
+                offset: CodeOffset,
+                dest_offset: CodeOffset)
+                -> Option<Encoding> {
+    let bytes = recipe_sizing(sizing, enc).bytes;
+    candidates
+        .iter()
+        .cloned()
+        .find(|&e| {
+                  let s = recipe_sizing(sizing, e);
+                  s.bytes > bytes &&
+                  s.branch_range
+                      .map_or(false, |range| range.contains(offset, dest_offset))
+              })
+}
+
+/// Get the destination of `inst` if it is a branch to a single EBB.
+fn branch_destination(dfg: &DataFlowGraph, inst: Inst) -> Option<Ebb> {
+    match dfg.analyze_branch(inst) {
+        BranchInfo::SingleDest(dest, _) => Some(dest),
+        _ => None,
+    }
+}
+
+/// Get the encoding of `inst`, or the illegal encoding if it doesn't have one.
+fn encoding(func: &Function, inst: Inst) -> Encoding {
+    func.encodings.get(inst).cloned().unwrap_or_default()
+}
+
+/// Get the size information for `enc`. Instructions without a legal encoding take no space.
+fn recipe_sizing(sizing: &[RecipeSizing], enc: Encoding) -> &RecipeSizing {
+    static NO_SIZE: RecipeSizing = RecipeSizing {
+        bytes: 0,
+        branch_range: None,
+    };
+    if enc.is_legal() {
+        &sizing[enc.recipe()]
+    } else {
+        &NO_SIZE
+    }
+}
+
+/// Check that the locations currently assigned to the fixed operands and results of `inst`
+/// satisfy the constraints of `enc`, so it can replace the current encoding `cur`.
+///
+/// The new encoding must not clobber the CPU flags unless the current one already does.
+fn operands_fit(func: &Function,
+                isa: &TargetIsa,
+                divert: &RegDiversions,
+                inst: Inst,
+                cur: Encoding,
+                enc: Encoding)
+                -> bool {
+    let constraints = &isa.recipe_constraints()[enc.recipe()];
+    if constraints.clobbers_flags && !isa.recipe_constraints()[cur.recipe()].clobbers_flags {
+        return false;
+    }
+    let args = func.dfg.inst_args(inst)[0];
+    let fits = |value: Value, constraint: &OperandConstraint| {
+        let loc = divert.location(func.dfg.resolve_aliases(value), &func.locations);
+        match (constraint.kind, loc) {
+            (ConstraintKind::Reg, ValueLoc::Reg(reg)) => constraint.regclass.contains(reg),
+            (ConstraintKind::FixedReg(fixed), ValueLoc::Reg(reg)) => fixed == reg,
+            (ConstraintKind::Tied(num), ValueLoc::Reg(reg)) => {
+                value_reg(func, divert, args[num as usize]) == Some(reg)
+            }
+            (ConstraintKind::Stack, ValueLoc::Stack(_)) => true,
+            _ => false,
+        }
+    };
+    args.iter().zip(constraints.ins).all(|(&arg, constraint)| fits(arg, constraint)) &&
+    func.dfg
+        .inst_results(inst)
+        .zip(constraints.outs)
+        .all(|(res, constraint)| fits(res, constraint))
+}
+
+/// Get the register currently holding `value`, if any.
+fn value_reg(func: &Function, divert: &RegDiversions, value: Value) -> Option<RegUnit> {
+    match divert.location(func.dfg.resolve_aliases(value), &func.locations) {
+        ValueLoc::Reg(reg) => Some(reg),
+        _ => None,
+    }
+}
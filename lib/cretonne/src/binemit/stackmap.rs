@@ -0,0 +1,85 @@
+//! Stack maps for garbage collection.
+//!
+//! A garbage collector that walks the stack frames of JIT-compiled code needs to know where the
+//! live references are whenever it can interrupt a function. Those points are the `safepoint`
+//! instructions, which the register allocator inserts before the calls in a function using
+//! reference types. Emitting a safepoint reports a `Stackmap` to the code sink, listing the
+//! registers and stack slots that hold the references live across it.
+
+use ir::{Function, Value, ValueLoc};
+use isa::RegUnit;
+use regalloc::diversion::RegDiversions;
+use std::vec::Vec;
+
+/// The locations of the live references at a safepoint.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Stackmap {
+    /// Registers holding live references, in increasing order.
+    pub registers: Vec<RegUnit>,
+
+    /// Byte offsets of the stack slots holding live references from the stack pointer, in
+    /// increasing order.
+    pub stack_offsets: Vec<u32>,
+}
+
+impl Stackmap {
+    /// Create a stack map with the current locations of the references in `refs`.
+    ///
+    /// The stack slot offsets in `func.stack_offsets` must have been computed, and `divert` holds
+    /// the register diversions in effect at the safepoint.
+    pub fn new(refs: &[Value], func: &Function, divert: &RegDiversions) -> Stackmap {
+        let mut map = Stackmap::default();
+        for &value in refs {
+            match divert.location(value, &func.locations) {
+                ValueLoc::Reg(reg) => map.registers.push(reg),
+                ValueLoc::Stack(ss) => map.stack_offsets.push(func.stack_offsets[ss]),
+                ValueLoc::Unassigned => panic!("Live reference {} has no location", value),
+            }
+        }
+        map.registers.sort();
+        map.registers.dedup();
+        map.stack_offsets.sort();
+        map.stack_offsets.dedup();
+        map
+    }
+
+    /// Are there no live references at the safepoint?
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.stack_offsets.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stackmap;
+    use ir::{Function, StackSlotData, StackSlotKind, ValueLoc};
+    use ir::types::R64;
+    use regalloc::diversion::RegDiversions;
+
+    #[test]
+    fn locations() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let r0 = func.dfg.append_ebb_arg(ebb0, R64);
+        let r1 = func.dfg.append_ebb_arg(ebb0, R64);
+        let r2 = func.dfg.append_ebb_arg(ebb0, R64);
+        let ss0 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 8));
+        *func.locations.ensure(r0) = ValueLoc::Reg(3);
+        *func.locations.ensure(r1) = ValueLoc::Stack(ss0);
+        *func.locations.ensure(r2) = ValueLoc::Reg(1);
+        *func.stack_offsets.ensure(ss0) = 16;
+
+        let mut divert = RegDiversions::new();
+        let map = Stackmap::new(&[r0, r1, r2], &func, &divert);
+        assert_eq!(map.registers, [1, 3]);
+        assert_eq!(map.stack_offsets, [16]);
+        assert!(!map.is_empty());
+
+        divert.regmove(r0, 3, 5);
+        let map = Stackmap::new(&[r0], &func, &divert);
+        assert_eq!(map.registers, [5]);
+        assert!(map.stack_offsets.is_empty());
+
+        assert!(Stackmap::new(&[], &func, &divert).is_empty());
+    }
+}
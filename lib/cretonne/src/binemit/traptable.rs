@@ -0,0 +1,101 @@
+//! Table of trap sites.
+//!
+//! A WebAssembly embedder catches hardware faults like the `SIGSEGV` from an out-of-bounds heap
+//! access or the `SIGILL` from a `ud2` instruction, and it needs to turn them into language-level
+//! traps. The `TrapTable` collects the trap sites reported while a function is emitted, so the
+//! faulting code offset can be mapped back to the trap code and the source location of the
+//! trapping instruction.
+
+use ir::{SourceLoc, TrapCode};
+use super::{CodeOffset, TrapSink};
+use std::vec::Vec;
+
+/// A trap site in the emitted code.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TrapSite {
+    /// Code offset of the instruction that can trap.
+    pub offset: CodeOffset,
+    /// Source location of the trapping instruction.
+    pub srcloc: SourceLoc,
+    /// The reason for the trap.
+    pub code: TrapCode,
+}
+
+/// A table of the trap sites in a function, sorted by code offset.
+///
+/// The instructions are emitted in order, so the table is built by passing it as the `TrapSink`
+/// when emitting the function.
+#[derive(Clone, Debug, Default)]
+pub struct TrapTable {
+    sites: Vec<TrapSite>,
+}
+
+impl TrapTable {
+    /// Create a new empty trap table.
+    pub fn new() -> TrapTable {
+        TrapTable { sites: Vec::new() }
+    }
+
+    /// Clear all the trap sites so the table can be reused for another function.
+    pub fn clear(&mut self) {
+        self.sites.clear()
+    }
+
+    /// Get all the trap sites, sorted by code offset.
+    pub fn sites(&self) -> &[TrapSite] {
+        &self.sites
+    }
+
+    /// Look up the trap site for the instruction at `offset`.
+    pub fn lookup(&self, offset: CodeOffset) -> Option<&TrapSite> {
+        self.sites
+            .binary_search_by_key(&offset, |site| site.offset)
+            .ok()
+            .map(|idx| &self.sites[idx])
+    }
+}
+
+impl TrapSink for TrapTable {
+    fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode) {
+        let site = TrapSite {
+            offset: offset,
+            srcloc: srcloc,
+            code: code,
+        };
+        // Keep the table sorted, even if the sites were reported out of order.
+        match self.sites.binary_search_by_key(&offset, |s| s.offset) {
+            Ok(idx) => self.sites[idx] = site,
+            Err(idx) => self.sites.insert(idx, site),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrapSite, TrapTable};
+    use binemit::TrapSink;
+    use ir::{SourceLoc, TrapCode};
+
+    #[test]
+    fn sorted() {
+        let mut table = TrapTable::new();
+        table.trap(8, SourceLoc::new(3), TrapCode::HeapOutOfBounds);
+        table.trap(2, SourceLoc::new(1), TrapCode::IntegerDivisionByZero);
+        table.trap(20, SourceLoc::default(), TrapCode::User(0));
+
+        let offsets: Vec<_> = table.sites().iter().map(|site| site.offset).collect();
+        assert_eq!(offsets, [2, 8, 20]);
+
+        assert_eq!(table.lookup(8),
+                   Some(&TrapSite {
+                            offset: 8,
+                            srcloc: SourceLoc::new(3),
+                            code: TrapCode::HeapOutOfBounds,
+                        }));
+        assert_eq!(table.lookup(20).map(|site| site.code), Some(TrapCode::User(0)));
+        assert_eq!(table.lookup(3), None);
+
+        table.clear();
+        assert!(table.sites().is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+//! Profile-guided EBB ordering.
+//!
+//! Front ends can annotate the branches in a function with edge weights taken from a profile or
+//! from branch hints in the source language. This layout pass uses the weights to order the EBBs
+//! so the hot paths fall through and the cold code ends up at the end of the function:
+//!
+//! - EBBs are placed in chains. A chain continues with the unplaced successor reached by the
+//!   heaviest edge, so the most frequently taken branch becomes a jump to the next EBB.
+//! - When none of the edges leaving an EBB have a weight, the chain continues with the EBB that
+//!   followed it in the original layout if it is a successor. A function without edge weights
+//!   keeps its EBB order.
+//! - Cold EBBs are placed after all the other EBBs. An EBB is cold when all the edges reaching it
+//!   have a zero weight, or when it contains a trap or a call to a cold function.
+//!
+//! Moving EBBs around invalidates fall-throughs, so they are turned back into jumps first.
+
+use cfg::ControlFlowGraph;
+use cold::{is_cold_ebb, move_ebb_to_end};
+use entity_map::EntityMap;
+use ir::{Function, Ebb};
+use ir::instructions::BranchInfo;
+use straighten::remove_fallthroughs;
+use std::vec::Vec;
+
+/// Reorder the EBBs in `func` according to the edge weights on its branches.
+///
+/// The control flow graph must be up to date. It remains valid after reordering.
+pub fn order_ebbs(func: &mut Function, cfg: &ControlFlowGraph) {
+    let layout_order: Vec<Ebb> = func.layout.ebbs().collect();
+    let entry = match layout_order.first() {
+        Some(&entry) => entry,
+        None => return,
+    };
+
+    let mut cold = EntityMap::new();
+    cold.resize(func.dfg.num_ebbs());
+    for &ebb in &layout_order {
+        cold[ebb] = ebb != entry && is_cold(func, cfg, ebb);
+    }
+
+    let mut placed = EntityMap::new();
+    placed.resize(func.dfg.num_ebbs());
+    let mut order = Vec::with_capacity(layout_order.len());
+    for &place_cold in &[false, true] {
+        for &seed in &layout_order {
+            if placed[seed] || cold[seed] != place_cold {
+                continue;
+            }
+            let mut next = Some(seed);
+            while let Some(ebb) = next {
+                placed[ebb] = true;
+                order.push(ebb);
+                next = next_in_chain(func, ebb, &placed, &cold);
+            }
+        }
+    }
+
+    if order != layout_order {
+        remove_fallthroughs(func);
+        for ebb in order {
+            move_ebb_to_end(&mut func.layout, ebb);
+        }
+    }
+}
+
+/// Is `ebb` expected to be rarely executed, either because the edge weights say so or because of
+/// the code it contains?
+fn is_cold(func: &Function, cfg: &ControlFlowGraph, ebb: Ebb) -> bool {
+    let preds = cfg.get_predecessors(ebb);
+    (!preds.is_empty() && preds.iter().all(|&(_, branch)| func.edge_weight(branch) == Some(0))) ||
+    is_cold_ebb(func, ebb)
+}
+
+/// Choose the EBB to place after `ebb` in its chain.
+fn next_in_chain(func: &Function,
+                 ebb: Ebb,
+                 placed: &EntityMap<Ebb, bool>,
+                 cold: &EntityMap<Ebb, bool>)
+                 -> Option<Ebb> {
+    let mut best: Option<(Ebb, u32)> = None;
+    let mut successors = Vec::new();
+    for inst in func.layout.ebb_insts(ebb) {
+        if let BranchInfo::SingleDest(dest, _) = func.dfg.analyze_branch(inst) {
+            if placed[dest] || cold[dest] != cold[ebb] {
+                continue;
+            }
+            successors.push(dest);
+            if let Some(weight) = func.edge_weight(inst) {
+                if weight > 0 && best.map_or(true, |(_, w)| weight > w) {
+                    best = Some((dest, weight));
+                }
+            }
+        }
+    }
+
+    match best {
+        Some((dest, _)) => Some(dest),
+        None => {
+            func.layout
+                .next_ebb(ebb)
+                .and_then(|next| if successors.contains(&next) {
+                              Some(next)
+                          } else {
+                              None
+                          })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cfg::ControlFlowGraph;
+    use ir::{Function, Cursor, Ebb, InstBuilder, TrapCode, VariableArgs};
+    use ir::types;
+    use super::order_ebbs;
+
+    #[test]
+    fn hot_and_cold() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let ebb3 = func.dfg.make_ebb();
+        let ebb4 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let (br_cold, br_hot, jmp);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            br_cold = dfg.ins(pos).brz(v0, ebb1, VariableArgs::new());
+            br_hot = dfg.ins(pos).brnz(v0, ebb3, VariableArgs::new());
+            jmp = dfg.ins(pos).jump(ebb2, VariableArgs::new());
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).return_(VariableArgs::new());
+            pos.insert_ebb(ebb2);
+            dfg.ins(pos).jump(ebb4, VariableArgs::new());
+            pos.insert_ebb(ebb3);
+            dfg.ins(pos).return_(VariableArgs::new());
+            pos.insert_ebb(ebb4);
+            dfg.ins(pos).trap(TrapCode::User(0));
+        }
+
+        // Without weights, the order doesn't change. The trapping EBB is already last.
+        let cfg = ControlFlowGraph::with_function(&func);
+        order_ebbs(&mut func, &cfg);
+        let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(ebbs, [ebb0, ebb1, ebb2, ebb3, ebb4]);
+
+        func.set_edge_weight(br_cold, 0);
+        func.set_edge_weight(br_hot, 90);
+        func.set_edge_weight(jmp, 10);
+        order_ebbs(&mut func, &cfg);
+        let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(ebbs, [ebb0, ebb3, ebb2, ebb1, ebb4]);
+    }
+}
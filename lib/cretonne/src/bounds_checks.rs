@@ -0,0 +1,107 @@
+//! Redundant heap bounds check elimination.
+//!
+//! A `heap_addr H, p, Size` instruction traps unless the range `p .. p + Size - 1` is inside the
+//! heap `H`. Code compiled from WebAssembly tends to check the same heap offset over and over,
+//! especially in loops, and most of those checks are redundant:
+//!
+//! - A check that is dominated by a check of the same heap and offset value with at least the
+//!   same size can never fail. It is replaced by a copy of the dominating address.
+//! - A check that follows a smaller check of the same heap and offset in the same EBB is folded
+//!   into the earlier check by widening it. This is only done when no branches, calls, stores, or
+//!   other trapping instructions come between the two checks, so the widened check can't trap on
+//!   a path that wouldn't have trapped anyway, or before a store that would have happened.
+//!
+//! The offsets must be the same SSA value. Checks of `p` and `p + k` are not merged since the
+//! offset computation may wrap around.
+
+use std::collections::HashMap;
+use std::vec::Vec;
+use dominator_tree::DominatorTree;
+use ir::{Function, Ebb, Inst, Heap, Value, InstructionData, InstBuilder, Opcode};
+use ir::instructions::CallInfo;
+
+/// Remove the redundant `heap_addr` bounds checks in `func`.
+pub fn eliminate_bounds_checks(func: &mut Function, domtree: &DominatorTree) {
+    // Visit the EBBs in reverse post-order so dominating checks are seen first.
+    let mut ebbs: Vec<Ebb> = func.layout.ebbs().filter(|&ebb| domtree.is_reachable(ebb)).collect();
+    ebbs.sort_by(|&a, &b| domtree.rpo_cmp(a, b));
+
+    // The checks seen so far, keyed by heap and offset.
+    let mut checks: HashMap<(Heap, Value), Vec<Inst>> = HashMap::new();
+
+    for ebb in ebbs {
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        for inst in insts {
+            let (heap, arg, size) = match func.dfg[inst] {
+                InstructionData::HeapAddr { heap, arg, imm, .. } => (heap, arg, imm),
+                _ => continue,
+            };
+            let key = (heap, func.dfg.resolve_aliases(arg));
+            let avail = checks.entry(key).or_insert_with(Vec::new);
+            match find_covering_check(func, domtree, avail, inst, size) {
+                Some(check) => {
+                    let check_addr = func.dfg.first_result(check);
+                    func.dfg.replace(inst).copy(check_addr);
+                }
+                None => avail.push(inst),
+            }
+        }
+    }
+}
+
+/// Find a check in `avail` that makes the `size` bytes check `inst` redundant, widening it if
+/// necessary.
+fn find_covering_check(func: &mut Function,
+                       domtree: &DominatorTree,
+                       avail: &[Inst],
+                       inst: Inst,
+                       size: u32)
+                       -> Option<Inst> {
+    let addr_type = func.dfg.value_type(func.dfg.first_result(inst));
+
+    // Prefer the closest checks since they are the most likely to be widened.
+    for &check in avail.iter().rev() {
+        if func.dfg.value_type(func.dfg.first_result(check)) != addr_type ||
+           !domtree.dominates(check, inst, &func.layout) {
+            continue;
+        }
+        if let InstructionData::HeapAddr { imm, .. } = func.dfg[check] {
+            if imm >= size {
+                return Some(check);
+            }
+        }
+        if can_widen(func, check, inst) {
+            if let InstructionData::HeapAddr { ref mut imm, .. } = func.dfg[check] {
+                *imm = size;
+            }
+            return Some(check);
+        }
+    }
+    None
+}
+
+/// Can the check `from` be widened to cover the later check `to`?
+///
+/// This requires the two instructions to be in the same EBB with nothing observable between them
+/// other than heap bounds checks which trap the same way.
+fn can_widen(func: &Function, from: Inst, to: Inst) -> bool {
+    let ebb = func.layout.inst_ebb(from).expect("check not in layout");
+    if func.layout.inst_ebb(to) != Some(ebb) {
+        return false;
+    }
+    func.layout
+        .ebb_insts(ebb)
+        .skip_while(|&inst| inst != from)
+        .skip(1)
+        .take_while(|&inst| inst != to)
+        .all(|inst| {
+            let data = &func.dfg[inst];
+            let opcode = data.opcode();
+            let is_call = match data.analyze_call(&func.dfg.value_lists) {
+                CallInfo::NotACall => false,
+                _ => true,
+            };
+            !is_call && !opcode.is_branch() && !opcode.is_terminator() && !opcode.can_store() &&
+            (!opcode.can_trap() || opcode == Opcode::HeapAddr)
+        })
+}
@@ -0,0 +1,60 @@
+//! Cancelling compilations.
+//!
+//! An interactive embedder may find that a function it is compiling is no longer needed. Rather
+//! than killing the compilation thread, it can use a `CancellationToken` to ask the compiler to
+//! stop early. The token is checked between passes and between the phases of the most expensive
+//! passes, so the compilation returns `CtonError::Cancelled` shortly after the token is cancelled.
+
+use result::{CtonError, CtonResult};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A shared flag indicating that a compilation should be abandoned.
+///
+/// Clones of a token share the same flag, so a clone can be handed to another thread which
+/// cancels the compilation.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a new token which is not cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel all compilations using this token or any of its clones.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Has this token been cancelled?
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Return `Err(CtonError::Cancelled)` if this token has been cancelled.
+    pub fn check(&self) -> CtonResult {
+        if self.is_cancelled() {
+            Err(CtonError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use result::CtonError;
+    use super::CancellationToken;
+
+    #[test]
+    fn cancel() {
+        let token = CancellationToken::new();
+        let other = token.clone();
+        assert_eq!(token.check(), Ok(()));
+        other.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(token.check(), Err(CtonError::Cancelled));
+        assert!(!CancellationToken::new().is_cancelled());
+    }
+}
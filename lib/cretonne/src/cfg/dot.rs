@@ -0,0 +1,179 @@
+//! Graphviz output for control flow graphs.
+//!
+//! The `write` function prints the control flow graph of a function in the DOT language, which
+//! the Graphviz tools can render:
+//!
+//! ```text
+//! $ cton-util print-cfg --domtree --loops file.cton | dot -Tsvg -o cfg.svg
+//! ```
+//!
+//! Each EBB is a node listing its branch instructions, and the CFG edges start at the branch that
+//! goes to the successor EBB. The dominator tree can be overlaid as dashed edges from the
+//! immediate dominator of each EBB, and the loops found by the loop analysis can be drawn as
+//! nested boxes around the EBBs that belong to them.
+
+use cfg::ControlFlowGraph;
+use dominator_tree::DominatorTree;
+use entity_map::EntityRef;
+use ir::Function;
+use ir::instructions::BranchInfo;
+use loop_analysis::{Loop, LoopAnalysis};
+use std::fmt::{Result, Write};
+
+/// Write the control flow graph `cfg` of `func` to `w` as a Graphviz graph.
+///
+/// When `domtree` is given, the dominator tree is drawn as dashed edges. When `loops` is given,
+/// the EBBs in each loop are drawn inside a box, and the boxes of nested loops are nested.
+pub fn write(w: &mut Write,
+             func: &Function,
+             cfg: &ControlFlowGraph,
+             domtree: Option<&DominatorTree>,
+             loops: Option<&LoopAnalysis>)
+             -> Result {
+    writeln!(w, "digraph {} {{", func.name)?;
+    if let Some(entry) = func.layout.entry_block() {
+        writeln!(w, "    {{rank=min; {}}}", entry)?;
+    }
+    write_ebb_nodes(w, func)?;
+    if let Some(loops) = loops {
+        for lp in loops.loops() {
+            if loops.loop_parent(lp).is_none() {
+                write_loop(w, func, loops, lp, 1)?;
+            }
+        }
+    }
+    write_cfg_edges(w, func, cfg)?;
+    if let Some(domtree) = domtree {
+        write_domtree_edges(w, func, domtree)?;
+    }
+    writeln!(w, "}}")
+}
+
+/// Write a node for each EBB, listing its branch instructions.
+fn write_ebb_nodes(w: &mut Write, func: &Function) -> Result {
+    for ebb in &func.layout {
+        write!(w, "    {} [shape=record, label=\"{{{}", ebb, ebb)?;
+        // Add all outgoing branch instructions to the label.
+        for inst in func.layout.ebb_insts(ebb) {
+            let idata = &func.dfg[inst];
+            match idata.analyze_branch(&func.dfg.value_lists) {
+                BranchInfo::SingleDest(dest, _) => {
+                    write!(w, " | <{}>{} {}", inst, idata.opcode(), dest)?
+                }
+                BranchInfo::Table(table) => write!(w, " | <{}>{} {}", inst, idata.opcode(), table)?,
+                BranchInfo::NotABranch => {}
+            }
+        }
+        writeln!(w, "}}\"]")?
+    }
+    Ok(())
+}
+
+/// Write a cluster containing the EBBs in `lp` and the clusters of its child loops.
+fn write_loop(w: &mut Write,
+              func: &Function,
+              loops: &LoopAnalysis,
+              lp: Loop,
+              depth: usize)
+              -> Result {
+    let indent = "    ".repeat(depth);
+    writeln!(w, "{}subgraph cluster_loop{} {{", indent, lp.index())?;
+    writeln!(w,
+             "{}    label=\"loop{}\"; style=rounded;",
+             indent,
+             lp.index())?;
+    for ebb in &func.layout {
+        if loops.innermost_loop(ebb) == Some(lp) {
+            writeln!(w, "{}    {};", indent, ebb)?;
+        }
+    }
+    for child in loops.loops() {
+        if loops.loop_parent(child) == Some(lp) {
+            write_loop(w, func, loops, child, depth + 1)?;
+        }
+    }
+    writeln!(w, "{}}}", indent)
+}
+
+/// Write an edge from each branch to its destination.
+fn write_cfg_edges(w: &mut Write, func: &Function, cfg: &ControlFlowGraph) -> Result {
+    for ebb in &func.layout {
+        for &(parent, inst) in cfg.get_predecessors(ebb) {
+            writeln!(w, "    {}:{} -> {}", parent, inst, ebb)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a dashed edge from the immediate dominator of each EBB.
+///
+/// The dominator edges don't affect the layout of the graph, which is determined by the CFG.
+fn write_domtree_edges(w: &mut Write, func: &Function, domtree: &DominatorTree) -> Result {
+    for ebb in &func.layout {
+        if let Some(idom) = domtree.idom(ebb) {
+            let parent = func.layout.inst_ebb(idom).expect("dominator must be in the layout");
+            writeln!(w,
+                     "    {}:{} -> {} [style=dashed, color=blue, constraint=false]",
+                     parent,
+                     idom,
+                     ebb)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cfg::ControlFlowGraph;
+    use dominator_tree::DominatorTree;
+    use ir::{Function, Cursor, InstBuilder, VariableArgs};
+    use ir::types::I32;
+    use loop_analysis::LoopAnalysis;
+    use super::write;
+
+    #[test]
+    fn empty() {
+        let func = Function::new();
+        let cfg = ControlFlowGraph::with_function(&func);
+        let mut text = String::new();
+        write(&mut text, &func, &cfg, None, None).unwrap();
+        assert_eq!(text, "digraph \"\" {\n}\n");
+    }
+
+    #[test]
+    fn overlays() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let cond = func.dfg.append_ebb_arg(ebb0, I32);
+        let (jump, brnz) = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let jump = dfg.ins(pos).jump(ebb1, VariableArgs::new());
+            pos.insert_ebb(ebb1);
+            let brnz = dfg.ins(pos).brnz(cond, ebb1, VariableArgs::new());
+            dfg.ins(pos).jump(ebb2, VariableArgs::new());
+            pos.insert_ebb(ebb2);
+            dfg.ins(pos).return_(VariableArgs::new());
+            (jump, brnz)
+        };
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+        let loops = LoopAnalysis::with_function(&func, &cfg, &domtree);
+
+        let mut plain = String::new();
+        write(&mut plain, &func, &cfg, None, None).unwrap();
+        assert!(plain.contains(&format!("    ebb0:{} -> ebb1\n", jump)));
+        assert!(plain.contains(&format!("    ebb1:{} -> ebb1\n", brnz)));
+        assert!(!plain.contains("dashed"));
+        assert!(!plain.contains("cluster"));
+
+        let mut text = String::new();
+        write(&mut text, &func, &cfg, Some(&domtree), Some(&loops)).unwrap();
+        assert!(text.contains(&format!("    ebb0:{} -> ebb1 [style=dashed", jump)));
+        assert!(text.contains("    subgraph cluster_loop0 {\n        label=\"loop0\"; \
+                               style=rounded;\n        ebb1;\n    }\n"));
+    }
+}
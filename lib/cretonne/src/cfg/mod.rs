@@ -22,11 +22,17 @@
 //!
 //! Here `Ebb1` and `Ebb2` would each have a single predecessor denoted as `(Ebb0, brz)`
 //! and `(Ebb0, jmp Ebb2)` respectively.
+//!
+//! The `dot` module writes the control flow graph in the Graphviz format.
 
 use ir::{Function, Inst, Ebb};
 use ir::instructions::BranchInfo;
 use entity_map::{EntityMap, Keys};
+use mem_usage::MemUsage;
 use std::collections::HashSet;
+use std::vec::Vec;
+
+pub mod dot;
 
 /// A basic block denoted by its enclosing Ebb and last instruction.
 pub type BasicBlock = (Ebb, Inst);
@@ -58,6 +64,23 @@ impl ControlFlowGraph {
         }
     }
 
+    /// Clear all data structures in this control flow graph.
+    pub fn clear(&mut self) {
+        self.entry_block = None;
+        self.data.clear();
+    }
+
+    /// Get the memory used by the control flow graph, including the lists of predecessors and
+    /// successors.
+    pub fn mem_usage(&self) -> MemUsage {
+        let mut usage = self.data.mem_usage();
+        for ebb in self.data.keys() {
+            usage += MemUsage::of_vec(&self.data[ebb].successors);
+            usage += MemUsage::of_vec(&self.data[ebb].predecessors);
+        }
+        usage
+    }
+
     /// Allocate and compute the control flow graph for `func`.
     pub fn with_function(func: &Function) -> ControlFlowGraph {
         let mut cfg = ControlFlowGraph::new();
@@ -74,22 +97,40 @@ impl ControlFlowGraph {
         self.data.resize(func.dfg.num_ebbs());
 
         for ebb in &func.layout {
-            for inst in func.layout.ebb_insts(ebb) {
-                match func.dfg[inst].analyze_branch() {
-                    BranchInfo::SingleDest(dest, _) => {
+            self.compute_ebb(func, ebb);
+        }
+    }
+
+    fn compute_ebb(&mut self, func: &Function, ebb: Ebb) {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg.analyze_branch(inst) {
+                BranchInfo::SingleDest(dest, _) => {
+                    self.add_edge((ebb, inst), dest);
+                }
+                BranchInfo::Table(jt) => {
+                    for (_, dest) in func.jump_tables[jt].entries() {
                         self.add_edge((ebb, inst), dest);
                     }
-                    BranchInfo::Table(jt) => {
-                        for (_, dest) in func.jump_tables[jt].entries() {
-                            self.add_edge((ebb, inst), dest);
-                        }
-                    }
-                    BranchInfo::NotABranch => {}
                 }
+                BranchInfo::NotABranch => {}
             }
         }
     }
 
+    /// Recompute the outgoing edges of `ebb` after its branches have been changed.
+    ///
+    /// The edges into `ebb` are not changed. If `ebb` is new, it must be inserted in the layout
+    /// already.
+    pub fn recompute_ebb(&mut self, func: &Function, ebb: Ebb) {
+        self.data.resize(func.dfg.num_ebbs());
+        let successors = self.data[ebb].successors.clone();
+        self.data[ebb].successors.clear();
+        for succ in successors {
+            self.data[succ].predecessors.retain(|&(pred, _)| pred != ebb);
+        }
+        self.compute_ebb(func, ebb);
+    }
+
     fn add_edge(&mut self, from: BasicBlock, to: Ebb) {
         self.data[from.0].successors.push(to);
         self.data[to].predecessors.push(from);
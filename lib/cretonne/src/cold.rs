@@ -0,0 +1,147 @@
+//! Cold code layout.
+//!
+//! External functions can be declared `cold` or `noreturn`. Calls to such functions are typically
+//! on error paths like trap helpers and panics, so they are rarely executed.
+//!
+//! - `prune_noreturn` removes the instructions following a call to a `noreturn` function and
+//!   terminates the EBB with a `trap` instead.
+//! - `sink_cold_ebbs` moves the EBBs containing rarely executed code to the end of the layout, so
+//!   the hot code is kept together.
+//!
+//! Since every EBB ends in a terminator, moving EBBs around doesn't change the semantics of the
+//! function. Removing branches after a `noreturn` call does change the control flow graph, so it
+//! must be recomputed after `prune_noreturn`.
+
+use ir::{Function, Cursor, DataFlowGraph, Ebb, Inst, InstBuilder, Layout, Opcode, TrapCode};
+use ir::instructions::CallInfo;
+use straighten::remove_fallthroughs;
+use std::vec::Vec;
+
+/// Does `inst` call an external function that never returns?
+fn calls_noreturn(dfg: &DataFlowGraph, inst: Inst) -> bool {
+    match dfg[inst].analyze_call(&dfg.value_lists) {
+        CallInfo::Direct(fref, _) => dfg.ext_funcs[fref].noreturn,
+        _ => false,
+    }
+}
+
+/// Does `inst` call an external function that is rarely called?
+fn calls_cold(dfg: &DataFlowGraph, inst: Inst) -> bool {
+    match dfg[inst].analyze_call(&dfg.value_lists) {
+        CallInfo::Direct(fref, _) => dfg.ext_funcs[fref].cold || dfg.ext_funcs[fref].noreturn,
+        _ => false,
+    }
+}
+
+/// Is `ebb` expected to be rarely executed?
+///
+/// An EBB is cold if it calls a `cold` or `noreturn` function, or if it ends in a `trap`. The
+/// entry block is never cold.
+pub fn is_cold_ebb(func: &Function, ebb: Ebb) -> bool {
+    if func.layout.entry_block() == Some(ebb) {
+        return false;
+    }
+    func.layout
+        .ebb_insts(ebb)
+        .any(|inst| func.dfg[inst].opcode() == Opcode::Trap || calls_cold(&func.dfg, inst))
+}
+
+/// Remove the code following calls to `noreturn` functions.
+///
+/// The call becomes the last instruction before a `trap` terminator.
+pub fn prune_noreturn(func: &mut Function) {
+    let mut pos = Cursor::new(&mut func.layout);
+    while let Some(_) = pos.next_ebb() {
+        while let Some(inst) = pos.next_inst() {
+            if !calls_noreturn(&func.dfg, inst) {
+                continue;
+            }
+
+            // Leave a call that is already followed by a `trap` terminator alone.
+            match pos.next_inst() {
+                Some(next) if func.dfg[next].opcode() == Opcode::Trap => break,
+                _ => {}
+            }
+            while pos.current_inst().is_some() {
+                pos.remove_inst();
+            }
+            func.dfg.ins(&mut pos).trap(TrapCode::User(0));
+            break;
+        }
+    }
+}
+
+/// Move the cold EBBs in `func` to the end of the layout, preserving their relative order.
+pub fn sink_cold_ebbs(func: &mut Function) {
+    let cold: Vec<Ebb> = func.layout.ebbs().filter(|&ebb| is_cold_ebb(func, ebb)).collect();
+    if !cold.is_empty() {
+        // The moved EBBs may be the destinations of fall-throughs.
+        remove_fallthroughs(func);
+    }
+    for ebb in cold {
+        move_ebb_to_end(&mut func.layout, ebb);
+    }
+}
+
+/// Move `ebb` and its instructions to the end of the layout.
+pub fn move_ebb_to_end(layout: &mut Layout, ebb: Ebb) {
+    let insts: Vec<Inst> = layout.ebb_insts(ebb).collect();
+    for &inst in &insts {
+        layout.remove_inst(inst);
+    }
+    layout.remove_ebb(ebb);
+    layout.append_ebb(ebb);
+    for inst in insts {
+        layout.append_inst(inst, ebb);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, Ebb, ExtFuncData, ExternalName, InstBuilder, Opcode, Signature,
+             VariableArgs};
+    use ir::types;
+    use super::{prune_noreturn, sink_cold_ebbs};
+
+    #[test]
+    fn cold_code() {
+        let mut func = Function::new();
+        let sig = func.dfg.signatures.push(Signature::new());
+        let mut abort = ExtFuncData::new(ExternalName::testcase("abort"), sig);
+        abort.noreturn = true;
+        let abort = func.dfg.ext_funcs.push(abort);
+
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).brz(v0, ebb1, VariableArgs::new());
+            dfg.ins(pos).jump(ebb2, VariableArgs::new());
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).call(abort, VariableArgs::new());
+            dfg.ins(pos).jump(ebb2, VariableArgs::new());
+            pos.insert_ebb(ebb2);
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+
+        prune_noreturn(&mut func);
+        let opcodes: Vec<Opcode> = func.layout
+            .ebb_insts(ebb1)
+            .map(|inst| func.dfg[inst].opcode())
+            .collect();
+        assert_eq!(opcodes, [Opcode::Call, Opcode::Trap]);
+
+        // Pruning again doesn't change anything.
+        let before = func.to_string();
+        prune_noreturn(&mut func);
+        assert_eq!(func.to_string(), before);
+
+        sink_cold_ebbs(&mut func);
+        let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(ebbs, [ebb0, ebb2, ebb1]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Instruction combining.
+//!
+//! The combiner replaces trees of instructions in the data flow graph with simpler equivalents.
+//! The patterns are declared in `meta/base/combine.py` as `XForms` whose source pattern is a tree
+//! rooted at its last instruction. The other instructions in the tree must be pure, and their
+//! results can't be used anywhere else, so the whole tree is dead once the root is replaced.
+//!
+//! The matched instructions are not removed from the layout. They are left for a dead code
+//! elimination pass to clean up.
+
+use entity_map::EntityMap;
+use ir::{Function, Cursor, DataFlowGraph, Inst, InstructionData, Opcode, InstBuilder, Value,
+         ValueDef};
+
+/// Combine instructions in `func` using the target-independent patterns.
+pub fn combine_function(func: &mut Function) {
+    let mut uses = EntityMap::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            count_uses(&func.dfg, inst, &mut uses);
+        }
+    }
+
+    let mut pos = Cursor::new(&mut func.layout);
+    while let Some(_ebb) = pos.next_ebb() {
+        // Keep track of the cursor position before the instruction being processed, so we can
+        // double back when replacing instructions.
+        let mut prev_pos = pos.position();
+
+        while let Some(inst) = pos.next_inst() {
+            if combine(&mut pos, &mut func.dfg, &uses) {
+                // Count the uses in the replacement sequence which ends with `inst`. The uses by
+                // the replaced instruction are not subtracted, so `uses` can overestimate. That
+                // only makes the single-use checks more conservative.
+                pos.set_position(prev_pos);
+                while let Some(new_inst) = pos.next_inst() {
+                    count_uses(&func.dfg, new_inst, &mut uses);
+                    if new_inst == inst {
+                        break;
+                    }
+                }
+
+                // Revisit the replacement sequence in case it can be combined further.
+                pos.set_position(prev_pos);
+                continue;
+            }
+
+            // Remember this position in case we need to double back.
+            prev_pos = pos.position();
+        }
+    }
+}
+
+/// Increment the use counts of the arguments to `inst`.
+fn count_uses(dfg: &DataFlowGraph, inst: Inst, uses: &mut EntityMap<Value, u32>) {
+    for part in &dfg.inst_args(inst) {
+        for &arg in part.iter() {
+            *uses.ensure(dfg.resolve_aliases(arg)) += 1;
+        }
+    }
+}
+
+// Include the combining patterns that were generated by `gen_combine.py` from the `XForms` in
+// `meta/base/combine.py`.
+//
+// Concretely, this defines a private function `combine()`.
+include!(concat!(env!("OUT_DIR"), "/combine.rs"));
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, InstBuilder, Opcode, Value, ValueDef, VariableArgs};
+    use ir::types;
+    use super::combine_function;
+
+    fn def_opcode(func: &Function, value: Value) -> Opcode {
+        match func.dfg.value_def(value) {
+            ValueDef::Res(inst, _) => func.dfg[inst].opcode(),
+            ValueDef::Arg(..) => panic!("{} is an EBB argument", value),
+        }
+    }
+
+    #[test]
+    fn combine() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let (v2, v4, v5, v7) = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v1 = dfg.ins(pos).iconst(types::I32, 5);
+            let v2 = dfg.ins(pos).iadd(v0, v1);
+            let v3 = dfg.ins(pos).iconst(types::I32, 3);
+            let v4 = dfg.ins(pos).imul(v2, v3);
+            let v5 = dfg.ins(pos).bxor(v4, v3);
+            let v6 = dfg.ins(pos).bnot(v5);
+            let v7 = dfg.ins(pos).bnot(v6);
+            let mut rvals = VariableArgs::new();
+            rvals.push(v7);
+            dfg.ins(pos).return_(rvals);
+            (v2, v4, v5, v7)
+        };
+        combine_function(&mut func);
+
+        // The first constant has a single use, so it is folded into an immediate.
+        assert_eq!(def_opcode(&func, v2), Opcode::IaddImm);
+        // The second constant has two uses.
+        assert_eq!(def_opcode(&func, v4), Opcode::Imul);
+        assert_eq!(def_opcode(&func, v5), Opcode::Bxor);
+        // Double negation.
+        assert_eq!(def_opcode(&func, v7), Opcode::Copy);
+    }
+}
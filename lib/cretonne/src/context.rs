@@ -4,17 +4,77 @@
 //! deallocating the data structures needed for compilation. The `Context` struct is used to hold
 //! on to memory allocations between function compilations.
 //!
+//! Call `clear()` before building the next function directly in the `func` field. That way the
+//! function and the analyses computed for it reuse the memory allocated for the previous
+//! functions, and compiling a function that fits in the previously used capacities doesn't grow
+//! any of the context's data structures.
+//!
 //! The context does not hold a `TargetIsa` instance which has to be provided as an argument
 //! instead. This is because an ISA instance is immutable and can be used by multiple compilation
 //! contexts concurrently. Typically, you would have one context per compilation thread and only a
-//! single ISA instance.
+//! single ISA instance. The `Session` type bundles the ISA with a pool of contexts that can be
+//! shared by the compilation threads.
+//!
+//! For debugging, the context can record a snapshot of the function before each compiler pass.
+//! When a pass crashes or produces bad code, the snapshot taken before it is a reproduction of the
+//! exact input to the failing pass. The snapshots use the binary serialization format, so they
+//! keep the entity numbers, encodings, and value locations that the textual IL doesn't preserve.
+//! The context can also print the function to stderr after each pass, optionally only for the
+//! functions whose name matches a filter, which helps finding the pass that breaks a function in a
+//! large module.
+//!
+//! When the `enable_verifier` setting is on, the passes run the verifier on their result and
+//! return the first error found.
+//!
+//! The passes are timed by the `timing` module when statistics collection is enabled on the
+//! compilation thread. Embedders with their own profiling can install a `CompileObserver` instead,
+//! which is notified at the start and end of each pass.
+//!
+//! An embedder can cancel a compilation from another thread through a clone of the context's
+//! `cancel` token. The passes then return `CtonError::Cancelled`.
+//!
+//! The `compile()` method runs the whole compilation pipeline. Tests and embedders that want a
+//! custom pipeline can call the methods running the individual passes instead. Passes that change
+//! the control flow graph must be followed by a call to `flowgraph()` before running passes that
+//! use the control flow graph, the dominator tree, or the loop analysis.
 
+use alias_analysis::{AliasAnalysis, BasicAliasAnalysis};
+use binemit::{CodeOffset, HashSink, MemoryCodeSink, RelocSink, TrapSink, StackmapSink,
+               emit_function, relax_branches};
+use cancel::CancellationToken;
 use cfg::ControlFlowGraph;
+use debuginfo::{CompiledFunctionDebugInfo, compute_debug_info};
 use dominator_tree::DominatorTree;
-use ir::Function;
+use combine_function;
+use cold;
+use order_ebbs;
+use convert_ifs;
+use eliminate_dead_stores;
+use eliminate_redundant_loads;
+use fold_constants;
+use function_size;
+use ir::{Function, GlobalVar};
 use isa::TargetIsa;
 use legalize_function;
+use loop_analysis::LoopAnalysis;
+use mem_usage::MemUsage;
+use do_preopt;
+use do_postopt;
+use insert_prologue_epilogue;
+use instrument_heap_accesses;
 use regalloc;
+use straighten_layout;
+use result::{CtonError, CtonResult};
+use serialize::{self, encode_function, decode_function};
+use settings::OptLevel;
+use std::boxed::Box;
+use std::fmt::{self, Write};
+#[cfg(feature = "std")]
+use std::io::{self, Write as IoWrite};
+use std::string::{String, ToString};
+use std::vec::Vec;
+use timing;
+use verifier;
 
 /// Persistent data structures and compilation pipeline.
 pub struct Context {
@@ -27,8 +87,75 @@ pub struct Context {
     /// Dominator tree for `func`.
     pub domtree: DominatorTree,
 
+    /// Loop analysis of `func`.
+    pub loop_analysis: LoopAnalysis,
+
     /// Register allocation context.
     pub regalloc: regalloc::Context,
+
+    /// Alias analysis used by the memory optimizations.
+    ///
+    /// This is a `BasicAliasAnalysis` by default. Embedders can replace it with an implementation
+    /// that knows more about the memory accessed by their code.
+    pub alias_analysis: Box<AliasAnalysis + Send>,
+
+    /// Snapshots of `func` taken before each pass, or `None` when not recording.
+    pub snapshots: Option<Vec<Snapshot>>,
+
+    /// Print the function to stderr after each pass when set.
+    ///
+    /// Only the functions whose name contains this string are printed, so an empty string prints
+    /// all of them. This is ignored when the library is built without the `std` feature.
+    pub print_after: Option<String>,
+
+    /// Token checked by the passes to see if the compilation should be abandoned.
+    ///
+    /// Once cancelled, a token stays cancelled. Replace it with a new token before compiling the
+    /// next function.
+    pub cancel: CancellationToken,
+
+    /// Observer notified at the start and end of each pass, or `None`.
+    pub observer: Option<Box<CompileObserver + Send>>,
+
+    /// The size of the function when the current pass started, for the observer.
+    size_before_pass: usize,
+}
+
+/// Callbacks at the boundaries of the compiler passes run by a `Context`.
+///
+/// Embedders can implement this trait to feed their own profiling or telemetry systems, without
+/// depending on the `timing` module. The pass names are the ones used for the snapshots. The size
+/// of a function is the number of instructions in its layout, as computed by `function_size()`.
+pub trait CompileObserver {
+    /// The pass named `pass` is about to run on `func`.
+    fn pass_started(&mut self, func: &Function, pass: &'static str);
+
+    /// The pass named `pass` finished running on `func`, changing its size from `size_before` to
+    /// `size_after` instructions.
+    ///
+    /// This is called before the verifier checks the result of the pass. It isn't called when the
+    /// pass itself fails.
+    fn pass_finished(&mut self,
+                     func: &Function,
+                     pass: &'static str,
+                     size_before: usize,
+                     size_after: usize);
+}
+
+/// A copy of the function taken before running a compiler pass.
+pub struct Snapshot {
+    /// The name of the pass that was about to run.
+    pub pass: &'static str,
+
+    /// The function encoded with `serialize::encode_function()`.
+    pub bytes: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Decode the function in this snapshot.
+    pub fn function(&self) -> serialize::Result<Function> {
+        decode_function(&self.bytes)
+    }
 }
 
 impl Context {
@@ -41,23 +168,743 @@ impl Context {
             func: Function::new(),
             cfg: ControlFlowGraph::new(),
             domtree: DominatorTree::new(),
+            loop_analysis: LoopAnalysis::new(),
             regalloc: regalloc::Context::new(),
+            alias_analysis: Box::new(BasicAliasAnalysis::new()),
+            snapshots: None,
+            print_after: None,
+            cancel: CancellationToken::new(),
+            observer: None,
+            size_before_pass: 0,
+        }
+    }
+
+    /// Clear all data structures in this context.
+    ///
+    /// This makes `func` identical to `Function::new()` and discards the analyses computed for
+    /// it, but keeps the allocated memory so it can be reused for the next function. The alias
+    /// analysis, the cancellation token, the observer, the snapshot recording mode, and the
+    /// `print_after` filter are kept, while the recorded snapshots are discarded.
+    pub fn clear(&mut self) {
+        self.func.clear();
+        self.cfg.clear();
+        self.domtree.clear();
+        self.loop_analysis.clear();
+        self.regalloc.clear();
+        if let Some(ref mut snapshots) = self.snapshots {
+            snapshots.clear();
+        }
+    }
+
+    /// Get the memory used by the function and the analyses computed for it.
+    ///
+    /// Most of the allocated memory stays with the context when it is cleared, so `used` drops
+    /// while `allocated` keeps the high-water mark of the functions compiled so far. The recorded
+    /// snapshots are not included.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.func.mem_usage() + self.cfg.mem_usage() + self.domtree.mem_usage() +
+        self.loop_analysis.mem_usage() + self.regalloc.mem_usage()
+    }
+
+    /// Start or stop recording snapshots of the function before each pass.
+    ///
+    /// Any existing snapshots are discarded.
+    pub fn record_snapshots(&mut self, enable: bool) {
+        self.snapshots = if enable { Some(Vec::new()) } else { None };
+    }
+
+    /// Write all the recorded snapshots to `w` in the textual IL format, oldest first.
+    ///
+    /// Each function is preceded by a comment naming the pass that ran next.
+    pub fn write_snapshots(&self, w: &mut Write) -> fmt::Result {
+        for snapshot in self.snapshots.iter().flat_map(|s| s.iter()) {
+            writeln!(w, "; Before {}:", snapshot.pass)?;
+            match snapshot.function() {
+                Ok(func) => writeln!(w, "{}", func)?,
+                Err(e) => writeln!(w, "; {}", e)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Prepare for running `pass`: record a snapshot of the function if recording is enabled, and
+    /// notify the observer.
+    fn before_pass(&mut self, pass: &'static str) {
+        if let Some(ref mut snapshots) = self.snapshots {
+            snapshots.push(Snapshot {
+                               pass: pass,
+                               bytes: encode_function(&self.func),
+                           });
+        }
+        if let Some(ref mut observer) = self.observer {
+            self.size_before_pass = function_size(&self.func);
+            observer.pass_started(&self.func, pass);
+        }
+    }
+
+    /// Notify the observer that `pass` finished, and print the function if requested.
+    fn pass_finished(&mut self, pass: &'static str) {
+        if let Some(ref mut observer) = self.observer {
+            let size = function_size(&self.func);
+            observer.pass_finished(&self.func, pass, self.size_before_pass, size);
+        }
+        self.print_after_pass(pass);
+    }
+
+    /// Should the function be printed after each pass?
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    fn prints_after_passes(&self) -> bool {
+        match self.print_after {
+            Some(ref filter) => self.func.name.to_string().contains(filter.as_str()),
+            None => false,
+        }
+    }
+
+    /// Print the function to stderr after `pass` if it matches the `print_after` filter.
+    #[cfg(feature = "std")]
+    fn print_after_pass(&self, pass: &'static str) {
+        if self.prints_after_passes() {
+            // There is nothing sensible to do when stderr can't be written.
+            let _ = writeln!(io::stderr(), "; After {}:\n{}", pass, self.func);
         }
     }
 
+    /// There is no stderr without `std`, so the `print_after` filter is ignored.
+    #[cfg(not(feature = "std"))]
+    fn print_after_pass(&self, _pass: &'static str) {}
+
+    /// Finish running `pass`: notify the observer, print the function if requested, and run the
+    /// verifier if the `enable_verifier` setting is on.
+    fn after_pass(&mut self, pass: &'static str, isa: &TargetIsa) -> verifier::Result<()> {
+        self.pass_finished(pass);
+        self.verify_if(isa)
+    }
+
+    /// Run the verifier on the function, the control flow graph, and the dominator tree.
+    ///
+    /// The control flow graph and dominator tree must have been computed by `flowgraph()`. When
+    /// `isa` is given, also verify that the instruction encodings are legal for it.
+    pub fn verify(&self, isa: Option<&TargetIsa>) -> verifier::Result<()> {
+        let _tt = timing::start_pass(timing::Pass::Verifier);
+        verifier::verify_context(&self.func, &self.cfg, &self.domtree, isa)
+    }
+
+    /// Run the verifier on the function if the `enable_verifier` setting is on.
+    ///
+    /// This computes its own control flow graph and dominator tree, so it can be used after passes
+    /// that don't keep them up to date.
+    pub fn verify_if(&self, isa: &TargetIsa) -> verifier::Result<()> {
+        if !isa.flags().enable_verifier() {
+            return Ok(());
+        }
+        let _tt = timing::start_pass(timing::Pass::Verifier);
+        let cfg = ControlFlowGraph::with_function(&self.func);
+        let domtree = DominatorTree::with_function(&self.func, &cfg);
+        verifier::verify_context(&self.func, &cfg, &domtree, Some(isa))
+    }
+
+    /// Run the liveness verifier on the function.
+    ///
+    /// This must be called after `regalloc()`, and it checks the liveness analysis that was used
+    /// by the register allocator.
+    pub fn verify_liveness(&self) -> verifier::Result<()> {
+        let _tt = timing::start_pass(timing::Pass::Verifier);
+        verifier::verify_liveness(&self.func, &self.cfg, self.regalloc.liveness())
+    }
+
+    /// Run the location verifier on the function.
+    ///
+    /// This must be called after `regalloc()`, and it checks the value locations assigned by the
+    /// register allocator.
+    pub fn verify_locations(&self, isa: &TargetIsa) -> verifier::Result<()> {
+        let _tt = timing::start_pass(timing::Pass::Verifier);
+        verifier::verify_locations(isa,
+                                   &self.func,
+                                   self.regalloc.liveness(),
+                                   self.regalloc.virtregs())
+    }
+
+    /// Compile the function for `isa`.
+    ///
+    /// This runs the passes selected by the `opt_level` setting in order, ending with branch
+    /// relaxation. The `none` level skips all the optional optimizations, and the register
+    /// allocator spills whole live ranges instead of splitting them.
+    ///
+    /// Return the size of the function's code in bytes. The machine code can then be emitted with
+    /// `binemit::emit_function()` or `emit_to_memory()`.
+    pub fn compile(&mut self, isa: &TargetIsa) -> Result<CodeOffset, CtonError> {
+        let optimize = isa.flags().opt_level() != OptLevel::None;
+        self.flowgraph();
+        if optimize {
+            self.preopt(isa)?;
+            self.fold(isa)?;
+            self.combine(isa)?;
+            self.redundant_loads(isa)?;
+            self.cold_code(isa)?;
+            self.if_convert(isa)?;
+            self.flowgraph();
+            self.dead_stores(isa)?;
+            self.order_ebbs(isa)?;
+        }
+        self.legalize(isa)?;
+        self.flowgraph();
+        self.regalloc(isa)?;
+        self.prologue_epilogue(isa)?;
+        if optimize {
+            self.postopt(isa)?;
+        }
+        self.straighten(isa)?;
+        self.relax_branches(isa)
+    }
+
+    /// Emit machine code directly into raw memory.
+    ///
+    /// Write all of the function's machine code to the memory at `mem`. The size of the machine
+    /// code is returned by `compile()` above. The relocations and trap sites are reported to
+    /// `relocs` and `traps`. Pass a `binemit::TrapTable` as `traps` to collect the trap sites in a
+    /// table sorted by code offset. The stack maps of the safepoints are reported to `stackmaps`.
+    ///
+    /// # Safety
+    ///
+    /// There is no bounds checking on the memory buffer, so `mem` must be valid for writing the
+    /// number of bytes returned by `compile()`.
+    pub unsafe fn emit_to_memory(&self,
+                                 mem: *mut u8,
+                                 relocs: &mut RelocSink,
+                                 traps: &mut TrapSink,
+                                 stackmaps: &mut StackmapSink,
+                                 isa: &TargetIsa) {
+        emit_function(&self.func,
+                      isa,
+                      &mut MemoryCodeSink::new(mem, relocs, traps, stackmaps));
+    }
+
+    /// Compute a stable hash of the machine code of the compiled function.
+    ///
+    /// This must be called after `compile()`. The hash covers the code bytes, relocations, traps,
+    /// and stack maps that `emit_to_memory()` would produce. Compiling the same function with the
+    /// same ISA and settings always gives the same hash, on any host.
+    pub fn code_hash(&self, isa: &TargetIsa) -> u64 {
+        let mut sink = HashSink::new();
+        emit_function(&self.func, isa, &mut sink);
+        sink.finish()
+    }
+
+    /// Compute the debug information for the compiled function.
+    ///
+    /// This must be called after `compile()`, and it uses the liveness analysis computed by the
+    /// register allocator.
+    pub fn debug_info(&self, isa: &TargetIsa) -> CompiledFunctionDebugInfo {
+        compute_debug_info(&self.func, isa, self.regalloc.liveness())
+    }
+
+    /// Run the pre-optimization peephole pass on the function.
+    pub fn preopt(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("preopt");
+        let _tt = timing::start_pass(timing::Pass::Preopt);
+        do_preopt(&mut self.func);
+        self.after_pass("preopt", isa).map_err(Into::into)
+    }
+
+    /// Fold the instructions with constant arguments in the function.
+    pub fn fold(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("constant folding");
+        let _tt = timing::start_pass(timing::Pass::Fold);
+        fold_constants(&mut self.func);
+        self.after_pass("constant folding", isa).map_err(Into::into)
+    }
+
+    /// Run the target-independent instruction combiner on the function.
+    pub fn combine(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("combine");
+        let _tt = timing::start_pass(timing::Pass::Combine);
+        combine_function(&mut self.func);
+        self.after_pass("combine", isa).map_err(Into::into)
+    }
+
+    /// Prune the code following calls to `noreturn` functions, and move cold code out of line.
+    ///
+    /// This changes the control flow graph, so `flowgraph()` must be called afterwards.
+    pub fn cold_code(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("cold code");
+        let _tt = timing::start_pass(timing::Pass::ColdCode);
+        cold::prune_noreturn(&mut self.func);
+        cold::sink_cold_ebbs(&mut self.func);
+        self.after_pass("cold code", isa).map_err(Into::into)
+    }
+
+    /// Replace short conditional code sequences with `select` instructions where `isa` considers
+    /// it profitable.
+    ///
+    /// This changes the control flow graph, so `flowgraph()` must be called afterwards.
+    pub fn if_convert(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("if-conversion");
+        let _tt = timing::start_pass(timing::Pass::IfConversion);
+        convert_ifs(&mut self.func, isa.if_conversion_limit());
+        self.after_pass("if-conversion", isa).map_err(Into::into)
+    }
+
+    /// Replace the redundant loads in the function with copies.
+    ///
+    /// This uses the control flow graph and dominator tree computed by `flowgraph()`, and the
+    /// context's alias analysis.
+    pub fn redundant_loads(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("redundant loads");
+        let _tt = timing::start_pass(timing::Pass::RedundantLoads);
+        self.alias_analysis.compute(&self.func);
+        eliminate_redundant_loads(&mut self.func, &self.cfg, &self.domtree, &*self.alias_analysis);
+        self.after_pass("redundant loads", isa).map_err(Into::into)
+    }
+
+    /// Delete the dead stack stores and the unused stack slots in the function.
+    ///
+    /// This uses the control flow graph computed by `flowgraph()`, and the context's alias
+    /// analysis.
+    pub fn dead_stores(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("dead stores");
+        let _tt = timing::start_pass(timing::Pass::DeadStores);
+        self.alias_analysis.compute(&self.func);
+        eliminate_dead_stores(&mut self.func, &self.cfg, &*self.alias_analysis);
+        self.after_pass("dead stores", isa).map_err(Into::into)
+    }
+
+    /// Reorder the EBBs in the function according to the edge weights in `func.edge_weights`.
+    ///
+    /// This uses the control flow graph computed by `flowgraph()`, which remains valid.
+    pub fn order_ebbs(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("block ordering");
+        let _tt = timing::start_pass(timing::Pass::BlockOrder);
+        order_ebbs(&mut self.func, &self.cfg);
+        self.after_pass("block ordering", isa).map_err(Into::into)
+    }
+
+    /// Insert shadow memory checks before the heap accesses in the function.
+    ///
+    /// This pass is not run by `compile()`. Embedders that want the checks call it before
+    /// compiling the function, passing the global variable whose address is the shadow memory
+    /// base.
+    pub fn instrument_heap_accesses(&mut self, shadow: GlobalVar, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("shadow memory");
+        let _tt = timing::start_pass(timing::Pass::ShadowMemory);
+        instrument_heap_accesses(&mut self.func, shadow);
+        self.after_pass("shadow memory", isa).map_err(Into::into)
+    }
+
     /// Run the legalizer for `isa` on the function.
-    pub fn legalize(&mut self, isa: &TargetIsa) {
-        legalize_function(&mut self.func, isa);
+    pub fn legalize(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("legalizer");
+        let _tt = timing::start_pass(timing::Pass::Legalize);
+        legalize_function(&mut self.func, isa)?;
+        self.after_pass("legalizer", isa).map_err(Into::into)
+    }
+
+    /// Run the post-optimization pass on the function.
+    ///
+    /// This must be called after `regalloc()`. The liveness analysis is not updated, so the
+    /// liveness verifier can't be used afterwards.
+    pub fn postopt(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("postopt");
+        let _tt = timing::start_pass(timing::Pass::Postopt);
+        do_postopt(&mut self.func, isa);
+        self.after_pass("postopt", isa).map_err(Into::into)
     }
 
-    /// Recompute the control flow graph and dominator tree.
+    /// Merge the EBB chains in the function and turn jumps to the next EBB into fall-throughs.
+    ///
+    /// This must run when the EBB order is final, so `compile()` runs it just before branch
+    /// relaxation.
+    pub fn straighten(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("straightening");
+        let _tt = timing::start_pass(timing::Pass::Straighten);
+        straighten_layout(&mut self.func);
+        self.after_pass("straightening", isa).map_err(Into::into)
+    }
+
+    /// Run the branch relaxation pass and return the final code size.
+    ///
+    /// This must be called last, after all the passes that change the code. It picks the
+    /// encodings of the branches and computes the EBB offsets in `func.offsets`.
+    pub fn relax_branches(&mut self, isa: &TargetIsa) -> Result<CodeOffset, CtonError> {
+        self.cancel.check()?;
+        self.before_pass("branch relaxation");
+        let size = {
+            let _tt = timing::start_pass(timing::Pass::BranchRelaxation);
+            relax_branches(&mut self.func, isa)?
+        };
+        self.after_pass("branch relaxation", isa)?;
+        Ok(size)
+    }
+
+    /// Save and restore the callee-saved registers used by the function.
+    ///
+    /// This must be called after `regalloc()`. The liveness analysis is not updated, so the
+    /// liveness verifier can't be used afterwards.
+    pub fn prologue_epilogue(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("prologue/epilogue");
+        let _tt = timing::start_pass(timing::Pass::PrologueEpilogue);
+        insert_prologue_epilogue(&mut self.func, isa)?;
+        self.after_pass("prologue/epilogue", isa).map_err(Into::into)
+    }
+
+    /// Recompute the control flow graph, the dominator tree, and the loop analysis.
     pub fn flowgraph(&mut self) {
+        let _tt = timing::start_pass(timing::Pass::Flowgraph);
         self.cfg.compute(&self.func);
         self.domtree.compute(&self.func, &self.cfg);
+        self.loop_analysis.compute(&self.func, &self.cfg, &self.domtree);
     }
 
     /// Run the register allocator.
-    pub fn regalloc(&mut self, isa: &TargetIsa) {
-        self.regalloc.run(isa, &mut self.func, &self.cfg, &self.domtree);
+    ///
+    /// Every instruction must have a legal encoding, or a verifier error is returned.
+    ///
+    /// When the verifier is enabled, this also checks the liveness analysis and the value locations
+    /// assigned by the register allocator.
+    pub fn regalloc(&mut self, isa: &TargetIsa) -> CtonResult {
+        self.cancel.check()?;
+        self.before_pass("regalloc");
+        let _tt = timing::start_pass(timing::Pass::Regalloc);
+        verifier::verify_encoded(&self.func)?;
+        self.regalloc.run(isa, &mut self.func, &self.cfg, &self.domtree, &self.cancel)?;
+        self.pass_finished("regalloc");
+        if !isa.flags().enable_verifier() {
+            return Ok(());
+        }
+        self.verify(Some(isa))?;
+        self.verify_liveness()?;
+        self.verify_locations(isa).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use function_size;
+    use ir::{Function, Cursor, InstBuilder, Opcode, VariableArgs, ArgumentType, ExternalName};
+    use ir::types;
+    use ir::immediates::Ieee32;
+    use isa;
+    use result::CtonError;
+    use settings::{self, Configurable};
+    use std::sync::{Arc, Mutex};
+    use super::{Context, CompileObserver};
+    use write::write_function;
+
+    #[test]
+    fn snapshots() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut ctx = Context::new();
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let arg = ctx.func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).iadd(arg, arg);
+            dfg.ins(pos).return_reg(v0, VariableArgs::new());
+        }
+
+        // Nothing is recorded by default.
+        ctx.legalize(&*isa).unwrap();
+        assert!(ctx.snapshots.is_none());
+
+        ctx.record_snapshots(true);
+        let before = ctx.func.to_string();
+        ctx.legalize(&*isa).unwrap();
+        let mut legalized = String::new();
+        write_function(&mut legalized, &ctx.func, Some(&*isa)).unwrap();
+        ctx.flowgraph();
+        ctx.regalloc(&*isa).unwrap();
+
+        let mut text = String::new();
+        ctx.write_snapshots(&mut text).unwrap();
+        let snapshots = ctx.snapshots.as_ref().unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].pass, "legalizer");
+        assert_eq!(snapshots[0].function().unwrap().to_string(), before);
+        assert_eq!(snapshots[1].pass, "regalloc");
+        // The snapshot keeps the encodings chosen by the legalizer.
+        let mut snapshot = String::new();
+        write_function(&mut snapshot, &snapshots[1].function().unwrap(), Some(&*isa)).unwrap();
+        assert_eq!(snapshot, legalized);
+        assert!(text.starts_with("; Before legalizer:\nfunction"));
+
+        ctx.func = Function::new();
+        ctx.record_snapshots(false);
+        ctx.legalize(&*isa).unwrap();
+        assert!(ctx.snapshots.is_none());
+    }
+
+    #[test]
+    fn clear() {
+        fn build(func: &mut Function) {
+            let ebb0 = func.dfg.make_ebb();
+            let arg = func.dfg.append_ebb_arg(ebb0, types::I32);
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).iadd_imm(arg, 7);
+            dfg.ins(pos).return_reg(v0, VariableArgs::new());
+        }
+
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut ctx = Context::new();
+        ctx.record_snapshots(true);
+        build(&mut ctx.func);
+        let size = ctx.compile(&*isa).unwrap();
+        let text = ctx.func.to_string();
+        let hash = ctx.code_hash(&*isa);
+
+        ctx.clear();
+        assert_eq!(ctx.func.to_string(), Function::new().to_string());
+        assert_eq!(ctx.func.dfg.num_insts(), 0);
+        assert!(ctx.regalloc.liveness().iter().next().is_none());
+        assert_eq!(ctx.snapshots.as_ref().map(Vec::len), Some(0));
+
+        // Compiling the same function again in the cleared context gives the same result.
+        build(&mut ctx.func);
+        assert_eq!(ctx.compile(&*isa), Ok(size));
+        assert_eq!(ctx.func.to_string(), text);
+        assert_eq!(ctx.code_hash(&*isa), hash);
+    }
+
+    #[test]
+    fn mem_usage() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut ctx = Context::new();
+        assert_eq!(ctx.mem_usage().allocated, 0);
+
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let arg = ctx.func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).iadd_imm(arg, 7);
+            dfg.ins(pos).return_reg(v0, VariableArgs::new());
+        }
+        ctx.compile(&*isa).unwrap();
+        let usage = ctx.mem_usage();
+        let func_usage = ctx.func.mem_usage();
+        assert!(func_usage.used > 0);
+        assert!(usage.used > func_usage.used);
+        assert!(usage.allocated >= usage.used);
+
+        // Clearing the context keeps the memory allocated for the function.
+        ctx.clear();
+        assert_eq!(ctx.func.mem_usage().used, 0);
+        assert_eq!(ctx.func.mem_usage().allocated, func_usage.allocated);
+        assert!(ctx.mem_usage().used < usage.used);
+    }
+
+    #[test]
+    fn compile() {
+        let mut b = settings::builder();
+        b.set("opt_level", "speed").unwrap();
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&b));
+        let mut ctx = Context::new();
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let arg = ctx.func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).iconst(types::I32, 4);
+            let v1 = dfg.ins(pos).imul(arg, v0);
+            dfg.ins(pos).return_reg(v1, VariableArgs::new());
+        }
+
+        ctx.record_snapshots(true);
+        let size = ctx.compile(&*isa).unwrap();
+        let passes: Vec<_> = ctx.snapshots.as_ref().unwrap().iter().map(|s| s.pass).collect();
+        assert_eq!(passes[0], "preopt");
+        assert!(passes.contains(&"legalizer"));
+        assert!(passes.contains(&"postopt"));
+        assert_eq!(passes[passes.len() - 2], "straightening");
+        assert_eq!(passes[passes.len() - 1], "branch relaxation");
+        // All RISC-V instructions are 4 bytes.
+        assert!(size > 0 && size % 4 == 0);
+        assert!(ctx.func.encodings.is_valid(ctx.func.layout.last_inst(ebb0).unwrap()));
+    }
+
+    /// Observer recording the events it is notified of.
+    struct Recorder(Arc<Mutex<Vec<(&'static str, usize, usize)>>>);
+
+    impl CompileObserver for Recorder {
+        fn pass_started(&mut self, func: &Function, pass: &'static str) {
+            assert_eq!(func.name, ExternalName::testcase("observed"));
+            self.0.lock().unwrap().push((pass, 0, 0));
+        }
+
+        fn pass_finished(&mut self,
+                         _func: &Function,
+                         pass: &'static str,
+                         size_before: usize,
+                         size_after: usize) {
+            let mut events = self.0.lock().unwrap();
+            assert_eq!(events.last().unwrap().0, pass);
+            events.pop();
+            events.push((pass, size_before, size_after));
+        }
+    }
+
+    #[test]
+    fn observer() {
+        let mut b = settings::builder();
+        b.set("opt_level", "speed").unwrap();
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&b));
+        let mut ctx = Context::new();
+        ctx.func.name = ExternalName::testcase("observed");
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let arg = ctx.func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).iconst(types::I32, 4);
+            let v1 = dfg.ins(pos).imul(arg, v0);
+            dfg.ins(pos).return_reg(v1, VariableArgs::new());
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        ctx.observer = Some(Box::new(Recorder(events.clone())));
+        ctx.compile(&*isa).unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events[0].0, "preopt");
+        assert_eq!(events[0].1, 3);
+        assert_eq!(events.last().unwrap().0, "branch relaxation");
+        assert!(events.iter().any(|e| e.0 == "regalloc"));
+        assert_eq!(events.last().unwrap().2, function_size(&ctx.func));
+        for pair in events.windows(2) {
+            assert_eq!(pair[0].2, pair[1].1);
+        }
+    }
+
+    #[test]
+    fn print_after() {
+        let mut ctx = Context::new();
+        ctx.func.name = ExternalName::testcase("add_two");
+        assert!(!ctx.prints_after_passes());
+
+        ctx.print_after = Some(String::new());
+        assert!(ctx.prints_after_passes());
+        ctx.print_after = Some("add".to_string());
+        assert!(ctx.prints_after_passes());
+        ctx.print_after = Some("sub".to_string());
+        assert!(!ctx.prints_after_passes());
+
+        // The filter is kept for the next function.
+        ctx.clear();
+        assert_eq!(ctx.print_after, Some("sub".to_string()));
+    }
+
+    #[test]
+    fn regalloc_error() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut ctx = Context::new();
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let ebb1 = ctx.func.dfg.make_ebb();
+        let link = ctx.func.dfg.append_ebb_arg(ebb0, types::I32);
+        ctx.func.signature.return_types.push(ArgumentType::new(types::I32));
+        let jump = {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            // More EBB arguments than there are registers. They can't all be passed in registers.
+            let mut args = VariableArgs::new();
+            for i in 0..40 {
+                args.push(dfg.ins(pos).iadd_imm(link, i));
+            }
+            let jump = dfg.ins(pos).jump(ebb1, args);
+
+            pos.insert_ebb(ebb1);
+            let mut sum = dfg.append_ebb_arg(ebb1, types::I32);
+            for _ in 1..40 {
+                let arg = dfg.append_ebb_arg(ebb1, types::I32);
+                sum = dfg.ins(pos).iadd(sum, arg);
+            }
+            let mut rets = VariableArgs::new();
+            rets.push(sum);
+            dfg.ins(pos).return_reg(link, rets);
+            jump
+        };
+
+        ctx.legalize(&*isa).unwrap();
+        ctx.flowgraph();
+        match ctx.regalloc(&*isa) {
+            Err(CtonError::RegAlloc(e)) => {
+                assert_eq!(e.location, jump.into());
+                assert_eq!(e.constraint, "GPR");
+                assert_eq!(e.live.len(), 40);
+            }
+            res => panic!("Unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn regalloc_illegal() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut ctx = Context::new();
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let fc = {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let fc = dfg.ins(pos).UnaryIeee32(Opcode::F32const, types::F32, Ieee32::new(1.0)).0;
+            dfg.ins(pos).return_(VariableArgs::new());
+            fc
+        };
+
+        // RISC-V has no float encodings, so the legalizer rejects the constant.
+        match ctx.legalize(&*isa) {
+            Err(CtonError::Verifier(e)) => {
+                assert_eq!(e.location, fc.into());
+                assert_eq!(e.message, "f32const has no legal encoding");
+            }
+            res => panic!("Unexpected {:?}", res),
+        }
+
+        // The verifier run before register allocation finds the constant without an encoding.
+        ctx.flowgraph();
+        match ctx.regalloc(&*isa) {
+            Err(CtonError::Verifier(e)) => {
+                assert_eq!(e.location, fc.into());
+                assert_eq!(e.message, "f32const has no legal encoding");
+            }
+            res => panic!("Unexpected {:?}", res),
+        }
+
+        // The register allocator reports the instruction without an encoding too.
+        match ctx.regalloc.run(&*isa, &mut ctx.func, &ctx.cfg, &ctx.domtree, &ctx.cancel) {
+            Err(CtonError::RegAlloc(e)) => {
+                assert_eq!(e.location, fc.into());
+                assert_eq!(e.constraint, "f32const encoding");
+            }
+            res => panic!("Unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn cancel() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut ctx = Context::new();
+        let token = ctx.cancel.clone();
+        assert_eq!(ctx.legalize(&*isa), Ok(()));
+        token.cancel();
+        assert_eq!(ctx.legalize(&*isa), Err(CtonError::Cancelled));
+        assert_eq!(ctx.regalloc(&*isa), Err(CtonError::Cancelled));
     }
 }
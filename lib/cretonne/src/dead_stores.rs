@@ -0,0 +1,231 @@
+//! Dead stack store elimination.
+//!
+//! Explicit stack slots and spill slots often receive `stack_store` instructions that are never
+//! read back. This pass computes which stack slots are live at each program point, deletes the
+//! stores to slots that are dead after the store, and then removes the stack slots that are no
+//! longer referenced at all, shrinking the stack frame.
+//!
+//! Liveness is tracked per slot, not per byte. A `stack_load` of any part of a slot makes the whole
+//! slot live, and only a `stack_store` that overwrites the entire slot makes it dead again. A slot
+//! that the alias analysis says may escape can be accessed through pointers, and a slot holding
+//! spilled values is accessed by `spill` and `fill` instructions, so these slots are always live.
+//!
+//! Outgoing argument slots are read by the called functions, so they are never removed.
+
+use alias_analysis::{AliasAnalysis, MemAccess};
+use cfg::ControlFlowGraph;
+use entity_map::{EntityMap, EntityRef};
+use ir::{Function, Ebb, Inst, StackSlot, StackSlotKind, InstructionData, Opcode, ValueLoc};
+use ir::instructions::BranchInfo;
+use std::vec::Vec;
+
+/// Delete the dead `stack_store` instructions in `func`, and remove unused stack slots.
+///
+/// The alias analysis `aa` must have been computed for `func`.
+pub fn eliminate_dead_stores(func: &mut Function, cfg: &ControlFlowGraph, aa: &AliasAnalysis) {
+    let always_live = find_escaping_slots(func, aa);
+
+    // Solve the backwards dataflow problem for the live-in slots of each EBB. Visiting the EBBs in
+    // post-order means that successors are usually seen first.
+    let postorder = cfg.postorder_ebbs();
+    let mut live_ins: EntityMap<Ebb, Vec<bool>> = EntityMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &ebb in &postorder {
+            let live = scan_ebb(func, cfg, &live_ins, &always_live, ebb, &mut Vec::new());
+            let live_in = live_ins.ensure(ebb);
+            if *live_in != live {
+                *live_in = live;
+                changed = true;
+            }
+        }
+    }
+
+    // Stores to slots that are dead right after the store can be deleted.
+    let mut dead = Vec::new();
+    for &ebb in &postorder {
+        scan_ebb(func, cfg, &live_ins, &always_live, ebb, &mut dead);
+    }
+    for inst in dead {
+        func.layout.remove_inst(inst);
+    }
+
+    remove_unused_slots(func);
+}
+
+/// Find the stack slots that can be accessed other than by `stack_load` and `stack_store`.
+fn find_escaping_slots(func: &Function, aa: &AliasAnalysis) -> Vec<bool> {
+    let mut escaping: Vec<bool> = func.stack_slots
+        .keys()
+        .map(|ss| aa.may_escape(&MemAccess::stack_slot(func, ss, false)))
+        .collect();
+    for value in func.locations.keys() {
+        if let ValueLoc::Stack(ss) = func.locations[value] {
+            escaping[ss.index()] = true;
+        }
+    }
+    escaping
+}
+
+/// Compute the live-in slots of `ebb` from the live-in slots of its successors.
+///
+/// The stores in `ebb` that write a slot that is dead after the store are appended to `dead`.
+fn scan_ebb(func: &Function,
+            cfg: &ControlFlowGraph,
+            live_ins: &EntityMap<Ebb, Vec<bool>>,
+            always_live: &[bool],
+            ebb: Ebb,
+            dead: &mut Vec<Inst>)
+            -> Vec<bool> {
+    let mut live = always_live.to_vec();
+    for &succ in cfg.get_successors(ebb) {
+        add_live_in(&mut live, live_ins, succ);
+    }
+
+    for inst in func.layout.ebb_insts(ebb).rev() {
+        match func.dfg.analyze_branch(inst) {
+            BranchInfo::SingleDest(dest, _) => add_live_in(&mut live, live_ins, dest),
+            BranchInfo::Table(jt) => {
+                for (_, dest) in func.jump_tables[jt].entries() {
+                    add_live_in(&mut live, live_ins, dest);
+                }
+            }
+            BranchInfo::NotABranch => {}
+        }
+
+        match func.dfg[inst] {
+            InstructionData::StackLoad { opcode: Opcode::StackLoad, stack_slot, .. } => {
+                live[stack_slot.index()] = true;
+            }
+            InstructionData::StackStore { arg, stack_slot, offset, .. } => {
+                let slot = stack_slot.index();
+                if !live[slot] {
+                    dead.push(inst);
+                } else if !always_live[slot] && offset == 0 {
+                    let bits = func.dfg.value_type(arg).bits() as u32;
+                    if bits >= 8 * func.stack_slots[stack_slot].size {
+                        live[slot] = false;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    live
+}
+
+/// Add the live-in slots of `ebb` to `live`.
+fn add_live_in(live: &mut [bool], live_ins: &EntityMap<Ebb, Vec<bool>>, ebb: Ebb) {
+    if let Some(live_in) = live_ins.get(ebb) {
+        for (l, &li) in live.iter_mut().zip(live_in) {
+            *l |= li;
+        }
+    }
+}
+
+/// Remove the explicit and spill slots that are not referenced by any instruction or value
+/// location, and renumber the remaining slots.
+fn remove_unused_slots(func: &mut Function) {
+    let mut used: Vec<bool> = func.stack_slots
+        .keys()
+        .map(|ss| func.stack_slots[ss].kind == StackSlotKind::OutgoingArg)
+        .collect();
+    for ebb in &func.layout {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst] {
+                InstructionData::StackLoad { stack_slot, .. } |
+                InstructionData::StackStore { stack_slot, .. } => used[stack_slot.index()] = true,
+                _ => {}
+            }
+        }
+    }
+    for value in func.locations.keys() {
+        if let ValueLoc::Stack(ss) = func.locations[value] {
+            used[ss.index()] = true;
+        }
+    }
+    if used.iter().all(|&u| u) {
+        return;
+    }
+
+    let mut slots = EntityMap::new();
+    let mut renumbered = Vec::with_capacity(used.len());
+    for ss in func.stack_slots.keys() {
+        if used[ss.index()] {
+            renumbered.push(Some(slots.push(func.stack_slots[ss].clone())));
+        } else {
+            renumbered.push(None);
+        }
+    }
+    func.stack_slots = slots;
+
+    let rename = |ss: StackSlot| renumbered[ss.index()].expect("removed a used stack slot");
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for ebb in ebbs {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst] {
+                InstructionData::StackLoad { ref mut stack_slot, .. } |
+                InstructionData::StackStore { ref mut stack_slot, .. } => {
+                    *stack_slot = rename(*stack_slot);
+                }
+                _ => {}
+            }
+        }
+    }
+    for value in func.locations.keys() {
+        if let ValueLoc::Stack(ref mut ss) = func.locations[value] {
+            *ss = rename(*ss);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alias_analysis::BasicAliasAnalysis;
+    use cfg::ControlFlowGraph;
+    use ir::{Function, Cursor, InstBuilder, Opcode, StackSlotData, StackSlotKind, VariableArgs};
+    use ir::types;
+    use verifier::verify_function;
+    use super::eliminate_dead_stores;
+
+    #[test]
+    fn overwritten_store() {
+        let mut func = Function::new();
+        let ss0 = func.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 4));
+        let ss1 = func.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 4));
+        let ss2 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 4));
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            // Never loaded.
+            dfg.ins(pos).stack_store(v0, ss0, 0u32);
+            // Overwritten before it is loaded.
+            dfg.ins(pos).stack_store(v0, ss2, 0u32);
+            dfg.ins(pos).stack_store(v1, ss2, 0u32);
+            let v2 = dfg.ins(pos).stack_load(types::I32, ss2, 0u32);
+            let mut args = VariableArgs::new();
+            args.push(v2);
+            dfg.ins(pos).return_(args);
+        }
+
+        let cfg = ControlFlowGraph::with_function(&func);
+        let aa = BasicAliasAnalysis::with_function(&func);
+        eliminate_dead_stores(&mut func, &cfg, &aa);
+        verify_function(&func).unwrap();
+
+        // Only the slot that was loaded from remains, and it was renumbered.
+        assert_eq!(func.stack_slots.len(), 1);
+        assert_eq!(func.stack_slots[ss0].kind, StackSlotKind::SpillSlot);
+        assert!(!func.stack_slots.is_valid(ss1));
+        let opcodes: Vec<Opcode> = func.layout
+            .ebb_insts(ebb0)
+            .map(|inst| func.dfg[inst].opcode())
+            .collect();
+        assert_eq!(opcodes, [Opcode::StackStore, Opcode::StackLoad, Opcode::Return]);
+    }
+}
@@ -0,0 +1,280 @@
+//! Debug information for compiled functions.
+//!
+//! After a function has been compiled, its machine code can be described in terms of the input
+//! for the benefit of a debugger:
+//!
+//! - The line table maps code offsets to the source locations of the instructions, as given by
+//!   `Function::srclocs`.
+//! - The value location ranges tell where the values labeled in `Function::value_labels` live
+//!   over which ranges of code.
+//!
+//! The `CompiledFunctionDebugInfo` structure holds both, and an embedder can translate it to
+//! DWARF or any other debug info format.
+//!
+//! The register allocator creates new values when it copies, spills, or fills a value. These
+//! values inherit the label of the original value, so a variable can be followed as it moves
+//! between registers and stack slots. When several values with the same label are live at the
+//! same time, the most recently defined one is taken to hold the variable.
+
+use binemit::CodeOffset;
+use entity_map::EntityMap;
+use ir::{Function, Inst, InstructionData, Opcode, ExpandedProgramPoint, SourceLoc, Value,
+         ValueDef, ValueLabel, ValueLoc};
+use isa::TargetIsa;
+use regalloc::diversion::RegDiversions;
+use regalloc::liveness::Liveness;
+use std::collections::{BTreeMap, HashMap};
+use std::vec::Vec;
+
+/// A row in the line table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineRow {
+    /// Code offset of the first instruction with this source location.
+    pub offset: CodeOffset,
+    /// Source location of the code from `offset` up to the next row.
+    pub srcloc: SourceLoc,
+}
+
+/// A range of code where a labeled value stays in the same location.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValueLocRange {
+    /// The register or stack slot holding the value.
+    pub loc: ValueLoc,
+    /// Code offset of the first instruction in the range.
+    pub start: CodeOffset,
+    /// Code offset following the last instruction in the range.
+    pub end: CodeOffset,
+}
+
+/// Debug information for a compiled function.
+#[derive(Clone, Debug, Default)]
+pub struct CompiledFunctionDebugInfo {
+    /// The line table, sorted by code offset. There is a row wherever the source location
+    /// changes, including changes to the default source location.
+    pub line_table: Vec<LineRow>,
+
+    /// The location ranges of each value label, sorted by code offset. The ranges for a label
+    /// don't overlap.
+    pub value_ranges: BTreeMap<ValueLabel, Vec<ValueLocRange>>,
+}
+
+impl CompiledFunctionDebugInfo {
+    /// Find the location of the variable `label` at the code offset `offset`.
+    pub fn location_at(&self, label: ValueLabel, offset: CodeOffset) -> Option<ValueLoc> {
+        self.value_ranges
+            .get(&label)
+            .and_then(|ranges| {
+                          ranges
+                              .iter()
+                              .find(|r| r.start <= offset && offset < r.end)
+                              .map(|r| r.loc)
+                      })
+    }
+
+    /// Append a range where `label` is in `loc`, merging it with the previous range if possible.
+    fn add_range(&mut self, label: ValueLabel, loc: ValueLoc, start: CodeOffset, end: CodeOffset) {
+        let ranges = self.value_ranges.entry(label).or_insert_with(Vec::new);
+        if let Some(last) = ranges.last_mut() {
+            if last.loc == loc && last.end == start {
+                last.end = end;
+                return;
+            }
+        }
+        ranges.push(ValueLocRange {
+                        loc: loc,
+                        start: start,
+                        end: end,
+                    });
+    }
+
+    /// Add a line table row for `srcloc` at `offset` unless it is the current source location.
+    fn add_row(&mut self, offset: CodeOffset, srcloc: SourceLoc) {
+        if self.line_table.last().map_or(true, |row| row.srcloc != srcloc) {
+            self.line_table.push(LineRow {
+                                     offset: offset,
+                                     srcloc: srcloc,
+                                 });
+        }
+    }
+}
+
+/// Compute the debug information for the compiled function `func`.
+///
+/// This must be called after branch relaxation has computed the final code layout, and
+/// `liveness` must be the liveness analysis used by the register allocator.
+pub fn compute_debug_info(func: &Function,
+                          isa: &TargetIsa,
+                          liveness: &Liveness)
+                          -> CompiledFunctionDebugInfo {
+    let labels = propagate_labels(func);
+    let labeled: Vec<Value> = labels.keys().filter(|&v| labels[v].is_some()).collect();
+    let sizing = isa.recipe_sizing();
+    let mut info = CompiledFunctionDebugInfo::default();
+    let mut divert = RegDiversions::new();
+
+    for ebb in func.layout.ebbs() {
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        let position: HashMap<Inst, usize> = insts
+            .iter()
+            .enumerate()
+            .map(|(i, &inst)| (inst, i))
+            .collect();
+        // Index of the last instruction where a live range ends in this EBB. Instructions
+        // removed after register allocation are conservatively treated as the end of the EBB.
+        let end_index = |pp: ExpandedProgramPoint| match pp {
+            ExpandedProgramPoint::Inst(inst) => {
+                Some(position.get(&inst).cloned().unwrap_or(insts.len() - 1))
+            }
+            ExpandedProgramPoint::Ebb(_) => None,
+        };
+
+        // The labeled values that are live at the current instruction, in definition order,
+        // along with the index of their last instruction in this EBB.
+        let mut live: BTreeMap<ValueLabel, Vec<(Value, usize)>> = BTreeMap::new();
+        for &value in &labeled {
+            let lr = match liveness.get(value) {
+                Some(lr) => lr,
+                None => continue,
+            };
+            let end = if lr.def() == ebb.into() {
+                end_index(lr.def_local_end().into())
+            } else {
+                lr.livein_local_end(ebb, &func.layout)
+                    .and_then(|inst| end_index(inst.into()))
+            };
+            if let Some(end) = end {
+                live.entry(labels[value].unwrap())
+                    .or_insert_with(Vec::new)
+                    .push((value, end));
+            }
+        }
+        // Prefer the EBB arguments over values that are live through, and later values over
+        // earlier ones. The values are already sorted by number, and the sort is stable.
+        for values in live.values_mut() {
+            values.sort_by_key(|&(v, _)| match func.dfg.value_def(v) {
+                                   ValueDef::Arg(..) => true,
+                                   ValueDef::Res(..) => false,
+                               });
+        }
+
+        divert.clear();
+        let mut offset = func.offsets[ebb];
+        for (i, &inst) in insts.iter().enumerate() {
+            let enc = func.encodings.get(inst).cloned().unwrap_or_default();
+            let size = if enc.is_legal() {
+                sizing[enc.recipe()].bytes as CodeOffset
+            } else {
+                0
+            };
+            if size > 0 {
+                info.add_row(offset, func.srcloc(inst));
+                for (&label, values) in &live {
+                    if let Some(&(value, _)) = values.last() {
+                        let loc = divert.location(value, &func.locations);
+                        if loc != ValueLoc::Unassigned {
+                            info.add_range(label, loc, offset, offset + size);
+                        }
+                    }
+                }
+            }
+            divert.apply(&func.dfg[inst]);
+            offset += size;
+
+            // Values whose live range ends here are no longer available, and the labeled results
+            // of the instruction become available.
+            for values in live.values_mut() {
+                values.retain(|&(_, end)| end > i);
+            }
+            for value in func.dfg.inst_results(inst) {
+                let label = match labels.get(value).cloned().unwrap_or(None) {
+                    Some(label) => label,
+                    None => continue,
+                };
+                if let Some(end) = liveness
+                       .get(value)
+                       .and_then(|lr| end_index(lr.def_local_end().into())) {
+                    if end > i {
+                        live.entry(label)
+                            .or_insert_with(Vec::new)
+                            .push((value, end));
+                    }
+                }
+            }
+        }
+    }
+    info
+}
+
+/// Extend the value labels of `func` to the copies, spills, and fills of labeled values.
+fn propagate_labels(func: &Function) -> EntityMap<Value, Option<ValueLabel>> {
+    let mut labels = func.value_labels.clone();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            let arg = match func.dfg[inst] {
+                InstructionData::Unary { opcode: Opcode::Copy, arg, .. } |
+                InstructionData::Unary { opcode: Opcode::Spill, arg, .. } |
+                InstructionData::Unary { opcode: Opcode::Fill, arg, .. } => arg,
+                _ => continue,
+            };
+            let result = func.dfg.first_result(inst);
+            if labels.get(result).cloned().unwrap_or(None).is_none() {
+                if let Some(label) = labels.get(arg).cloned().unwrap_or(None) {
+                    *labels.ensure(result) = Some(label);
+                }
+            }
+        }
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use context::Context;
+    use ir::{Cursor, InstBuilder, VariableArgs, ValueLabel, ValueLoc, SourceLoc, types};
+    use isa;
+    use settings;
+
+    #[test]
+    fn line_table_and_ranges() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut ctx = Context::new();
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let arg = ctx.func.dfg.append_ebb_arg(ebb0, types::I32);
+        let (v0, v1) = {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).iadd_imm(arg, 4);
+            let v1 = dfg.ins(pos).iadd(v0, arg);
+            dfg.ins(pos).return_reg(v1, VariableArgs::new());
+            (v0, v1)
+        };
+        let insts: Vec<_> = ctx.func.layout.ebb_insts(ebb0).collect();
+        ctx.func.set_srcloc(insts[0], SourceLoc::new(10));
+        ctx.func.set_srcloc(insts[1], SourceLoc::new(20));
+        ctx.func.set_srcloc(insts[2], SourceLoc::new(20));
+        let x = ValueLabel::new(0);
+        ctx.func.set_value_label(v0, x);
+        ctx.func.set_value_label(v1, x);
+
+        ctx.compile(&*isa).unwrap();
+        let info = ctx.debug_info(&*isa);
+
+        // All RISC-V instructions are 4 bytes.
+        assert_eq!(info.line_table.len(), 2);
+        assert_eq!(info.line_table[0].offset, 0);
+        assert_eq!(info.line_table[0].srcloc, SourceLoc::new(10));
+        assert_eq!(info.line_table[1].offset, 4);
+        assert_eq!(info.line_table[1].srcloc, SourceLoc::new(20));
+
+        // The variable is available after the `iadd_imm` until the return.
+        let ranges = &info.value_ranges[&x];
+        assert_eq!(ranges[0].start, 4);
+        assert_eq!(ranges[ranges.len() - 1].end, 12);
+        assert!(info.location_at(x, 0).is_none());
+        match info.location_at(x, 8) {
+            Some(ValueLoc::Reg(_)) => {}
+            loc => panic!("Unexpected location {:?}", loc),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Magic numbers for integer division by a constant.
+//!
+//! Division by a constant `d` can be replaced by a multiplication by a "magic number" `M` keeping
+//! the high half of the double-width product, followed by a shift. The algorithms here are from
+//! Henry S. Warren's "Hacker's Delight", chapter 10.
+//!
+//! The functions work on `bits`-wide integers represented in 64-bit integers, so the same code
+//! handles all the scalar integer types.
+
+/// Magic numbers for unsigned division by a constant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MagicU {
+    /// The multiplier.
+    pub mul: u64,
+    /// Does the quotient need an extra add step because the multiplier overflowed?
+    pub add: bool,
+    /// The final shift amount.
+    pub shift: u32,
+}
+
+/// Magic numbers for signed division by a constant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MagicS {
+    /// The multiplier, sign-extended from `bits` bits.
+    pub mul: i64,
+    /// The final shift amount.
+    pub shift: u32,
+}
+
+/// Get a mask of the low `bits` bits.
+fn mask(bits: u32) -> u64 {
+    if bits == 64 { !0 } else { (1 << bits) - 1 }
+}
+
+/// Compute the magic numbers for unsigned division of `bits`-wide integers by `d`.
+///
+/// The divisor must not be 0 or 1.
+pub fn magic_u(d: u64, bits: u32) -> MagicU {
+    let m = mask(bits);
+    let d = d & m;
+    assert!(d > 1, "Bad divisor {}", d);
+    let min = 1u64 << (bits - 1);
+    let max = min - 1;
+
+    let mut add = false;
+    let mut p = bits - 1;
+    let nc = m - (d.wrapping_neg() & m) % d;
+    let mut q1 = min / nc;
+    let mut r1 = min - q1 * nc;
+    let mut q2 = max / d;
+    let mut r2 = max - q2 * d;
+    loop {
+        p += 1;
+        if r1 >= nc - r1 {
+            q1 = (q1 << 1 | 1) & m;
+            r1 = (r1 << 1).wrapping_sub(nc) & m;
+        } else {
+            q1 = (q1 << 1) & m;
+            r1 = (r1 << 1) & m;
+        }
+        if r2 + 1 >= d - r2 {
+            if q2 >= max {
+                add = true;
+            }
+            q2 = (q2 << 1 | 1) & m;
+            r2 = ((r2 << 1) + 1).wrapping_sub(d) & m;
+        } else {
+            if q2 >= min {
+                add = true;
+            }
+            q2 = (q2 << 1) & m;
+            r2 = ((r2 << 1) + 1) & m;
+        }
+        let delta = d - 1 - r2;
+        if !(p < 2 * bits && (q1 < delta || (q1 == delta && r1 == 0))) {
+            break;
+        }
+    }
+
+    MagicU {
+        mul: q2.wrapping_add(1) & m,
+        add: add,
+        shift: p - bits,
+    }
+}
+
+/// Compute the magic numbers for signed division of `bits`-wide integers by `d`.
+///
+/// The divisor is given sign-extended, and it must not be -1, 0, or 1.
+pub fn magic_s(d: i64, bits: u32) -> MagicS {
+    let m = mask(bits);
+    let min = 1u64 << (bits - 1);
+    let ad = (d.wrapping_abs() as u64) & m;
+    assert!(ad > 1, "Bad divisor {}", d);
+
+    let t = min + (d < 0) as u64;
+    let anc = t - 1 - t % ad;
+    let mut p = bits - 1;
+    let mut q1 = min / anc;
+    let mut r1 = min - q1 * anc;
+    let mut q2 = min / ad;
+    let mut r2 = min - q2 * ad;
+    loop {
+        p += 1;
+        q1 = (q1 << 1) & m;
+        r1 = (r1 << 1) & m;
+        if r1 >= anc {
+            q1 = q1.wrapping_add(1) & m;
+            r1 = (r1 - anc) & m;
+        }
+        q2 = (q2 << 1) & m;
+        r2 = (r2 << 1) & m;
+        if r2 >= ad {
+            q2 = q2.wrapping_add(1) & m;
+            r2 = (r2 - ad) & m;
+        }
+        let delta = ad - r2;
+        if !(q1 < delta || (q1 == delta && r1 == 0)) {
+            break;
+        }
+    }
+
+    let mul = q2.wrapping_add(1) & m;
+    let mul = if d < 0 { mul.wrapping_neg() & m } else { mul };
+    let shift = 64 - bits;
+    MagicS {
+        mul: ((mul << shift) as i64) >> shift,
+        shift: p - bits,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{magic_u, magic_s, MagicU, MagicS};
+
+    fn mu(mul: u64, add: bool, shift: u32) -> MagicU {
+        MagicU {
+            mul: mul,
+            add: add,
+            shift: shift,
+        }
+    }
+
+    fn ms(mul: i64, shift: u32) -> MagicS {
+        MagicS {
+            mul: mul,
+            shift: shift,
+        }
+    }
+
+    #[test]
+    fn known_values() {
+        // Values from the tables in Hacker's Delight.
+        assert_eq!(magic_u(3, 32), mu(0xaaaaaaab, false, 1));
+        assert_eq!(magic_u(5, 32), mu(0xcccccccd, false, 2));
+        assert_eq!(magic_u(7, 32), mu(0x24924925, true, 3));
+        assert_eq!(magic_u(10, 32), mu(0xcccccccd, false, 3));
+        assert_eq!(magic_u(3, 64), mu(0xaaaaaaaaaaaaaaab, false, 1));
+        assert_eq!(magic_u(7, 64), mu(0x2492492492492493, true, 3));
+
+        assert_eq!(magic_s(3, 32), ms(0x55555556, 0));
+        assert_eq!(magic_s(5, 32), ms(0x66666667, 1));
+        assert_eq!(magic_s(7, 32), ms(0x92492493u32 as i32 as i64, 2));
+        assert_eq!(magic_s(-5, 32), ms(0x99999999u32 as i32 as i64, 1));
+        assert_eq!(magic_s(-3, 32), ms(0x55555555, 1));
+        assert_eq!(magic_s(3, 64), ms(0x5555555555555556, 0));
+        assert_eq!(magic_s(7, 64), ms(0x4924924924924925, 1));
+    }
+
+    /// Divide the 16-bit `x` by `d` the way the generated code does.
+    fn udiv16(x: u64, d: u64) -> u64 {
+        let magic = magic_u(d, 16);
+        let q = (x * magic.mul) >> 16;
+        if magic.add {
+            (((x - q) >> 1) + q) >> (magic.shift - 1)
+        } else {
+            q >> magic.shift
+        }
+    }
+
+    fn sdiv16(x: i64, d: i64) -> i64 {
+        let magic = magic_s(d, 16);
+        let mut q = (x * magic.mul) >> 16;
+        if d > 0 && magic.mul < 0 {
+            q += x;
+        }
+        if d < 0 && magic.mul > 0 {
+            q -= x;
+        }
+        q >>= magic.shift;
+        q + ((q >> 15) & 1)
+    }
+
+    #[test]
+    fn exhaustive_16bit() {
+        for &d in &[3, 5, 6, 7, 10, 11, 25, 100, 641, 0x7fff, 0x8001, 0xfffe, 0xffff] {
+            for x in 0..0x10000 {
+                assert_eq!(udiv16(x, d), x / d, "{} / {}", x, d);
+            }
+        }
+        for &d in &[3, 5, 6, 7, 10, -3, -5, -7, -100, 0x7fff, -0x7fff] {
+            for x in -0x8000..0x8000 {
+                assert_eq!(sdiv16(x, d), x / d, "{} / {}", x, d);
+            }
+        }
+    }
+}
@@ -3,6 +3,7 @@
 use cfg::{ControlFlowGraph, BasicBlock};
 use ir::{Ebb, Inst, Function, Layout, ProgramOrder};
 use entity_map::EntityMap;
+use mem_usage::MemUsage;
 use packed_option::PackedOption;
 
 use std::cmp::Ordering;
@@ -125,6 +126,16 @@ impl DominatorTree {
         DominatorTree { nodes: EntityMap::new() }
     }
 
+    /// Clear the data structures used to represent the dominator tree.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+
+    /// Get the memory used by the dominator tree.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.nodes.mem_usage()
+    }
+
     /// Allocate and compute a dominator tree.
     pub fn with_function(func: &Function, cfg: &ControlFlowGraph) -> DominatorTree {
         let mut domtree = DominatorTree::new();
@@ -185,6 +196,41 @@ impl DominatorTree {
         }
     }
 
+    /// Update the dominator tree after the CFG edge from `pred` to `succ` has been split by
+    /// inserting `new_ebb`.
+    ///
+    /// The control flow graph must already be updated. The new EBB is immediately dominated by
+    /// `pred`, and the immediate dominator of `succ` is recomputed. This renumbers the EBBs
+    /// following `pred` in the reverse post-order, but it doesn't need to recompute the whole tree.
+    pub fn split_edge(&mut self,
+                      func: &Function,
+                      cfg: &ControlFlowGraph,
+                      pred: BasicBlock,
+                      new_ebb: Ebb,
+                      succ: Ebb) {
+        self.nodes.resize(func.dfg.num_ebbs());
+
+        // Nothing is reachable through an unreachable predecessor.
+        let rpo = self.nodes[pred.0].rpo_number;
+        if rpo == 0 {
+            return;
+        }
+
+        // Make room for `new_ebb` right after `pred` in the RPO.
+        for ebb in self.nodes.keys() {
+            if self.nodes[ebb].rpo_number > rpo {
+                self.nodes[ebb].rpo_number += 1;
+            }
+        }
+        self.nodes[new_ebb] = DomNode {
+            rpo_number: rpo + 1,
+            idom: pred.1.into(),
+        };
+
+        let idom = self.compute_idom(succ, cfg, &func.layout);
+        self.nodes[succ].idom = idom.into();
+    }
+
     // Compute the immediate dominator for `ebb` using the current `idom` states for the reachable
     // nodes.
     fn compute_idom(&self, ebb: Ebb, cfg: &ControlFlowGraph, layout: &Layout) -> Inst {
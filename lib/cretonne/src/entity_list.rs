@@ -46,14 +46,21 @@
 //! The index stored in an `EntityList` points to part 2, the list elements. The value 0 is
 //! reserved for the empty list which isn't allocated in the vector.
 
+use std::cmp::Ordering;
 use std::marker::PhantomData;
+use std::vec::Vec;
 
 use entity_map::EntityRef;
+use mem_usage::MemUsage;
 
 /// A small list of entity references allocated from a pool.
 ///
 /// All of the list methods that take a pool reference must be given the same pool reference every
 /// time they are called. Otherwise data structures will be corrupted.
+///
+/// Cloning an `EntityList` only copies the reference to the elements in the pool, so the clone
+/// must not be modified while the original is in use.
+#[derive(Clone, Debug)]
 pub struct EntityList<T: EntityRef> {
     index: u32,
     unused: PhantomData<T>,
@@ -70,6 +77,7 @@ impl<T: EntityRef> Default for EntityList<T> {
 }
 
 /// A memory pool for storing lists of `T`.
+#[derive(Clone)]
 pub struct ListPool<T: EntityRef> {
     // The main array containing the lists.
     data: Vec<T>,
@@ -119,6 +127,13 @@ impl<T: EntityRef> ListPool<T> {
         self.free.clear();
     }
 
+    /// Get the memory used by this pool.
+    ///
+    /// Blocks on the free lists count as used since the pool can't release them.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage::of_vec(&self.data) + MemUsage::of_vec(&self.free)
+    }
+
     /// Read the length of a list field, if it exists.
     fn len_of(&self, list: &EntityList<T>) -> Option<usize> {
         let idx = list.index as usize;
@@ -357,13 +372,44 @@ impl<T: EntityRef> EntityList<T> {
         // Finally adjust the length.
         pool.data[block] = T::new(len - 1);
     }
+
+    /// Sorts the list in place with a comparator function, without preserving the order of equal
+    /// elements.
+    ///
+    /// The list elements are sorted where they live in the pool, so no reallocation happens.
+    pub fn sort_unstable_by<F>(&mut self, compare: F, pool: &mut ListPool<T>)
+        where F: FnMut(&T, &T) -> Ordering
+    {
+        self.as_mut_slice(pool).sort_unstable_by(compare);
+    }
+
+    /// Binary searches a sorted list for an element.
+    ///
+    /// Returns `Ok(index)` if `x` was found, or `Err(index)` with the position where `x` could be
+    /// inserted to keep the list sorted. See `slice::binary_search`.
+    pub fn binary_search(&self, x: &T, pool: &ListPool<T>) -> Result<usize, usize>
+        where T: Ord
+    {
+        self.as_slice(pool).binary_search(x)
+    }
+
+    /// Binary searches a list that is sorted according to the comparator function `f`.
+    ///
+    /// This is the same as `slice::binary_search_by`, and it works for entity references that
+    /// don't implement `Ord`. The list should have been sorted by `sort_unstable_by` with a
+    /// compatible comparator.
+    pub fn binary_search_by<F>(&self, f: F, pool: &ListPool<T>) -> Result<usize, usize>
+        where F: FnMut(&T) -> Ordering
+    {
+        self.as_slice(pool).binary_search_by(f)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use super::{sclass_size, sclass_for_length};
-    use ir::Inst;
+    use ir::{Ebb, Inst};
     use entity_map::EntityRef;
 
     #[test]
@@ -516,4 +562,40 @@ mod tests {
         assert_eq!(list.as_slice(pool), &[]);
         assert!(list.is_empty());
     }
+
+    #[test]
+    fn sort_search() {
+        let pool = &mut ListPool::<Inst>::new();
+        let mut list = EntityList::<Inst>::default();
+
+        let i1 = Inst::new(1);
+        let i2 = Inst::new(2);
+        let i3 = Inst::new(3);
+        let i4 = Inst::new(4);
+
+        list.extend([i3, i1, i4, i2, i1].iter().cloned(), pool);
+        list.sort_unstable_by(|a, b| a.index().cmp(&b.index()), pool);
+        assert_eq!(list.as_slice(pool), &[i1, i1, i2, i3, i4]);
+
+        assert_eq!(list.binary_search_by(|x| x.index().cmp(&3), pool), Ok(3));
+        assert_eq!(list.binary_search_by(|x| x.index().cmp(&0), pool), Err(0));
+        assert_eq!(list.binary_search_by(|x| x.index().cmp(&5), pool), Err(5));
+
+        // Sorting and searching an empty list is fine.
+        let mut empty = EntityList::<Inst>::default();
+        empty.sort_unstable_by(|a, b| a.index().cmp(&b.index()), pool);
+        assert_eq!(empty.binary_search_by(|x| x.index().cmp(&1), pool), Err(0));
+
+        // `Ebb` implements `Ord`, so it can use the plain `binary_search`.
+        let epool = &mut ListPool::<Ebb>::new();
+        let mut elist = EntityList::<Ebb>::default();
+        let e0 = Ebb::new(0);
+        let e5 = Ebb::new(5);
+        let e7 = Ebb::new(7);
+        elist.extend([e7, e0, e5].iter().cloned(), epool);
+        elist.sort_unstable_by(|a, b| a.cmp(b), epool);
+        assert_eq!(elist.as_slice(epool), &[e0, e5, e7]);
+        assert_eq!(elist.binary_search(&e5, epool), Ok(1));
+        assert_eq!(elist.binary_search(&Ebb::new(6), epool), Err(2));
+    }
 }
@@ -10,10 +10,11 @@
 //! - A *secondary* `EntityMap` contains additional data about entities kept in a primary map. The
 //!   values need to implement `Clone + Default` traits so the map can be grown with `ensure`.
 
-use std::vec::Vec;
+use mem_usage::MemUsage;
 use std::default::Default;
 use std::marker::PhantomData;
 use std::ops::{Index, IndexMut};
+use std::vec::Vec;
 
 /// A type wrapping a small integer index should implement `EntityRef` so it can be used as the key
 /// of an `EntityMap`.
@@ -67,6 +68,11 @@ impl<K, V> EntityMap<K, V>
         self.elems.clear()
     }
 
+    /// Get the memory used by this map.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage::of_vec(&self.elems)
+    }
+
     /// Iterate over all the keys in this map.
     pub fn keys(&self) -> Keys<K> {
         Keys {
@@ -0,0 +1,494 @@
+//! Constant folding.
+//!
+//! The constant folding pass evaluates instructions whose arguments are all defined by constant
+//! instructions like `iconst`, `f32const`, `f64const`, and `bconst`. The folded instruction is
+//! replaced in place by a new constant instruction, so its result values are preserved, and the
+//! folded constants can be used to fold later instructions.
+//!
+//! Integer arithmetic wraps around, and shift amounts are masked to the width of the type.
+//! Integer constants are evaluated in 64 bits, so instructions on wider types are not folded.
+//! Instructions that would trap at runtime, like division by zero or an out-of-range float to
+//! integer conversion, are not folded. Floating point operations that produce a NaN are not
+//! folded either since the NaN payload can depend on the target.
+//!
+//! Constants that are no longer used after folding are removed from the layout.
+
+use entity_map::EntityMap;
+use ir::{Function, Cursor, DataFlowGraph, Inst, InstructionData, InstBuilder, Opcode, Type,
+         Value, ValueDef};
+use ir::condcodes::{IntCC, FloatCC};
+use ir::immediates::{Ieee32, Ieee64};
+use std::cmp::Ordering;
+use std::vec::Vec;
+use timing;
+
+/// The value of a constant instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Const {
+    Int(i64),
+    Bool(bool),
+    F32(f32),
+    F64(f64),
+}
+
+/// Fold the instructions with constant arguments in `func`.
+pub fn fold_constants(func: &mut Function) {
+    let mut folded = 0;
+    {
+        let mut pos = Cursor::new(&mut func.layout);
+        while let Some(_ebb) = pos.next_ebb() {
+            while let Some(inst) = pos.next_inst() {
+                if let Some(value) = evaluate(&func.dfg, inst) {
+                    replace_with_const(&mut func.dfg, inst, value);
+                    folded += 1;
+                }
+            }
+        }
+    }
+    timing::add_count(timing::Counter::InstsFolded, folded);
+    remove_dead_constants(func);
+}
+
+/// Get the constant value of `value`, if it is defined by a constant instruction.
+fn constant(dfg: &DataFlowGraph, value: Value) -> Option<Const> {
+    let inst = match dfg.value_def(dfg.resolve_aliases(value)) {
+        ValueDef::Res(inst, 0) => inst,
+        _ => return None,
+    };
+    match dfg[inst] {
+        InstructionData::UnaryImm { opcode: Opcode::Iconst, imm, .. } => {
+            Some(Const::Int(imm.into()))
+        }
+        InstructionData::UnaryBool { opcode: Opcode::Bconst, imm, .. } => Some(Const::Bool(imm)),
+        InstructionData::UnaryIeee32 { opcode: Opcode::F32const, imm, .. } => {
+            Some(Const::F32(imm.into()))
+        }
+        InstructionData::UnaryIeee64 { opcode: Opcode::F64const, imm, .. } => {
+            Some(Const::F64(imm.into()))
+        }
+        _ => None,
+    }
+}
+
+/// Is `opcode` one of the constant materializing instructions?
+fn is_constant(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::Iconst | Opcode::Bconst | Opcode::F32const | Opcode::F64const => true,
+        _ => false,
+    }
+}
+
+/// Evaluate `inst` if all of its arguments are constants.
+fn evaluate(dfg: &DataFlowGraph, inst: Inst) -> Option<Const> {
+    let opcode = dfg[inst].opcode();
+    if is_constant(opcode) || dfg.inst_results(inst).count() != 1 {
+        return None;
+    }
+    let ty = dfg.value_type(dfg.first_result(inst));
+    if !ty.is_scalar() {
+        return None;
+    }
+
+    match dfg[inst] {
+        InstructionData::Unary { arg, .. } => {
+            unary(opcode, ty, dfg.value_type(arg), constant(dfg, arg)?)
+        }
+        InstructionData::Binary { args, .. } => {
+            binary(opcode, ty, constant(dfg, args[0])?, constant(dfg, args[1])?)
+        }
+        InstructionData::BinaryImm { arg, imm, .. } => {
+            let opcode = match opcode {
+                Opcode::IaddImm => Opcode::Iadd,
+                Opcode::ImulImm => Opcode::Imul,
+                Opcode::UdivImm => Opcode::Udiv,
+                Opcode::SdivImm => Opcode::Sdiv,
+                Opcode::UremImm => Opcode::Urem,
+                Opcode::SremImm => Opcode::Srem,
+                Opcode::BandImm => Opcode::Band,
+                Opcode::BorImm => Opcode::Bor,
+                Opcode::BxorImm => Opcode::Bxor,
+                Opcode::RotlImm => Opcode::Rotl,
+                Opcode::RotrImm => Opcode::Rotr,
+                Opcode::IshlImm => Opcode::Ishl,
+                Opcode::UshrImm => Opcode::Ushr,
+                Opcode::SshrImm => Opcode::Sshr,
+                _ => return None,
+            };
+            binary(opcode, ty, constant(dfg, arg)?, Const::Int(imm.into()))
+        }
+        InstructionData::BinaryImmRev { opcode: Opcode::IsubImm, arg, imm, .. } => {
+            binary(Opcode::Isub, ty, Const::Int(imm.into()), constant(dfg, arg)?)
+        }
+        InstructionData::IntCompare { cond, args, .. } => {
+            let arg_ty = dfg.value_type(args[0]);
+            match (constant(dfg, args[0])?, constant(dfg, args[1])?) {
+                (Const::Int(x), Const::Int(y)) if arg_ty.bits() <= 64 => {
+                    Some(Const::Bool(icmp(cond, arg_ty.bits() as u32, x, y)))
+                }
+                _ => None,
+            }
+        }
+        InstructionData::FloatCompare { cond, args, .. } => {
+            let x = float_value(constant(dfg, args[0])?)?;
+            let y = float_value(constant(dfg, args[1])?)?;
+            Some(Const::Bool(fcmp(cond, x, y)))
+        }
+        _ => None,
+    }
+}
+
+/// Replace `inst` with a constant instruction producing `value`.
+fn replace_with_const(dfg: &mut DataFlowGraph, inst: Inst, value: Const) {
+    let ty = dfg.value_type(dfg.first_result(inst));
+    match value {
+        Const::Int(x) => {
+            dfg.replace(inst).iconst(ty, sext(x, ty.bits() as u32));
+        }
+        Const::Bool(b) => {
+            dfg.replace(inst).bconst(ty, b);
+        }
+        Const::F32(x) => {
+            dfg.replace(inst).f32const(Ieee32::new(x));
+        }
+        Const::F64(x) => {
+            dfg.replace(inst).f64const(Ieee64::new(x));
+        }
+    }
+}
+
+/// Remove the constant instructions whose results are not used.
+fn remove_dead_constants(func: &mut Function) {
+    let mut uses = EntityMap::<Value, u32>::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            for part in &func.dfg.inst_args(inst) {
+                for &arg in part.iter() {
+                    *uses.ensure(func.dfg.resolve_aliases(arg)) += 1;
+                }
+            }
+        }
+    }
+
+    let mut dead = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if is_constant(func.dfg[inst].opcode()) &&
+               uses.get(func.dfg.first_result(inst)).cloned().unwrap_or(0) == 0 {
+                dead.push(inst);
+            }
+        }
+    }
+    for inst in dead {
+        func.layout.remove_inst(inst);
+    }
+}
+
+/// Sign-extend the low `bits` bits of `x`, where `bits` is at most 64.
+fn sext(x: i64, bits: u32) -> i64 {
+    debug_assert!(bits <= 64);
+    let shift = 64 - bits;
+    (x << shift) >> shift
+}
+
+/// Zero-extend the low `bits` bits of `x`, where `bits` is at most 64.
+fn zext(x: i64, bits: u32) -> u64 {
+    debug_assert!(bits <= 64);
+    let shift = 64 - bits;
+    ((x as u64) << shift) >> shift
+}
+
+/// Get a floating point constant as an `f64`. The conversion is exact for `f32` constants.
+fn float_value(value: Const) -> Option<f64> {
+    match value {
+        Const::F32(x) => Some(x as f64),
+        Const::F64(x) => Some(x),
+        _ => None,
+    }
+}
+
+/// Accept a floating point result unless it is a NaN.
+fn float_result(value: Const) -> Option<Const> {
+    match float_value(value) {
+        Some(x) if x.is_nan() => None,
+        _ => Some(value),
+    }
+}
+
+/// Evaluate a unary instruction producing a `ty` result from an `arg_ty` argument.
+fn unary(opcode: Opcode, ty: Type, arg_ty: Type, arg: Const) -> Option<Const> {
+    let bits = ty.bits() as u32;
+    let arg_bits = arg_ty.bits() as u32;
+    if bits > 64 || arg_bits > 64 {
+        return None;
+    }
+    match (opcode, arg) {
+        (Opcode::Copy, _) => Some(arg),
+        (Opcode::Bnot, Const::Int(x)) => Some(Const::Int(!x)),
+        (Opcode::Clz, Const::Int(x)) => {
+            Some(Const::Int((zext(x, arg_bits).leading_zeros() - (64 - arg_bits)) as i64))
+        }
+        (Opcode::Ctz, Const::Int(x)) => {
+            Some(Const::Int(match zext(x, arg_bits) {
+                                0 => arg_bits as i64,
+                                x => x.trailing_zeros() as i64,
+                            }))
+        }
+        (Opcode::Popcnt, Const::Int(x)) => Some(Const::Int(zext(x, arg_bits).count_ones() as i64)),
+        (Opcode::Ireduce, Const::Int(x)) => Some(Const::Int(x)),
+        (Opcode::Uextend, Const::Int(x)) => Some(Const::Int(zext(x, arg_bits) as i64)),
+        (Opcode::Sextend, Const::Int(x)) => Some(Const::Int(sext(x, arg_bits))),
+        (Opcode::Bint, Const::Bool(b)) => Some(Const::Int(b as i64)),
+        (Opcode::Fneg, Const::F32(x)) => Some(Const::F32(-x)),
+        (Opcode::Fneg, Const::F64(x)) => Some(Const::F64(-x)),
+        (Opcode::Fabs, Const::F32(x)) => Some(Const::F32(x.abs())),
+        (Opcode::Fabs, Const::F64(x)) => Some(Const::F64(x.abs())),
+        (Opcode::Fpromote, Const::F32(x)) => float_result(Const::F64(x as f64)),
+        (Opcode::Fdemote, Const::F64(x)) => float_result(Const::F32(x as f32)),
+        (Opcode::Bitcast, Const::Int(x)) if ty.is_float() => {
+            match bits {
+                32 => Some(Const::F32(Ieee32::from_bits(x as u32).into())),
+                64 => Some(Const::F64(Ieee64::from_bits(x as u64).into())),
+                _ => None,
+            }
+        }
+        (Opcode::Bitcast, Const::F32(x)) => Some(Const::Int(x.to_bits() as i64)),
+        (Opcode::Bitcast, Const::F64(x)) => Some(Const::Int(x.to_bits() as i64)),
+        (Opcode::FcvtToUint, _) => {
+            let x = float_value(arg)?.trunc();
+            if x > -1.0 && x < 2f64.powi(bits as i32) {
+                Some(Const::Int(x as u64 as i64))
+            } else {
+                None
+            }
+        }
+        (Opcode::FcvtToSint, _) => {
+            let x = float_value(arg)?.trunc();
+            let limit = 2f64.powi(bits as i32 - 1);
+            if x >= -limit && x < limit {
+                Some(Const::Int(x as i64))
+            } else {
+                None
+            }
+        }
+        (Opcode::FcvtFromUint, Const::Int(x)) => {
+            let x = zext(x, arg_bits);
+            if bits == 32 {
+                Some(Const::F32(x as f32))
+            } else {
+                Some(Const::F64(x as f64))
+            }
+        }
+        (Opcode::FcvtFromSint, Const::Int(x)) => {
+            let x = sext(x, arg_bits);
+            if bits == 32 {
+                Some(Const::F32(x as f32))
+            } else {
+                Some(Const::F64(x as f64))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Evaluate a binary instruction producing a `ty` result.
+fn binary(opcode: Opcode, ty: Type, x: Const, y: Const) -> Option<Const> {
+    match (x, y) {
+        (Const::Int(x), Const::Int(y)) => {
+            int_binary(opcode, ty.bits() as u32, x, y).map(Const::Int)
+        }
+        (Const::Bool(x), Const::Bool(y)) => {
+            match opcode {
+                Opcode::Band => Some(Const::Bool(x & y)),
+                Opcode::Bor => Some(Const::Bool(x | y)),
+                Opcode::Bxor => Some(Const::Bool(x ^ y)),
+                _ => None,
+            }
+        }
+        (Const::F32(x), Const::F32(y)) => {
+            match opcode {
+                Opcode::Fadd => float_result(Const::F32(x + y)),
+                Opcode::Fsub => float_result(Const::F32(x - y)),
+                Opcode::Fmul => float_result(Const::F32(x * y)),
+                Opcode::Fdiv => float_result(Const::F32(x / y)),
+                _ => None,
+            }
+        }
+        (Const::F64(x), Const::F64(y)) => {
+            match opcode {
+                Opcode::Fadd => float_result(Const::F64(x + y)),
+                Opcode::Fsub => float_result(Const::F64(x - y)),
+                Opcode::Fmul => float_result(Const::F64(x * y)),
+                Opcode::Fdiv => float_result(Const::F64(x / y)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Evaluate a binary integer operation on `bits`-wide integers.
+///
+/// Returns `None` if the operation would trap, or if the integers are wider than 64 bits.
+fn int_binary(opcode: Opcode, bits: u32, x: i64, y: i64) -> Option<i64> {
+    if bits > 64 {
+        return None;
+    }
+    let amount = (y as u32) & (bits - 1);
+    match opcode {
+        Opcode::Iadd => Some(x.wrapping_add(y)),
+        Opcode::Isub => Some(x.wrapping_sub(y)),
+        Opcode::Imul => Some(x.wrapping_mul(y)),
+        Opcode::Band => Some(x & y),
+        Opcode::Bor => Some(x | y),
+        Opcode::Bxor => Some(x ^ y),
+        Opcode::Udiv | Opcode::Urem => {
+            let (x, y) = (zext(x, bits), zext(y, bits));
+            if y == 0 {
+                None
+            } else if opcode == Opcode::Udiv {
+                Some((x / y) as i64)
+            } else {
+                Some((x % y) as i64)
+            }
+        }
+        Opcode::Sdiv | Opcode::Srem => {
+            let (x, y) = (sext(x, bits), sext(y, bits));
+            if y == 0 {
+                None
+            } else if opcode == Opcode::Srem {
+                Some(x.wrapping_rem(y))
+            } else if y == -1 && x == sext(1 << (bits - 1), bits) {
+                // The quotient overflows.
+                None
+            } else {
+                Some(x / y)
+            }
+        }
+        Opcode::Ishl => Some(x << amount),
+        Opcode::Ushr => Some((zext(x, bits) >> amount) as i64),
+        Opcode::Sshr => Some(sext(x, bits) >> amount),
+        Opcode::Rotl | Opcode::Rotr => {
+            let x = zext(x, bits);
+            let amount = if opcode == Opcode::Rotl {
+                amount
+            } else {
+                (bits - amount) & (bits - 1)
+            };
+            if amount == 0 {
+                Some(x as i64)
+            } else {
+                Some(zext(((x << amount) | (x >> (bits - amount))) as i64, bits) as i64)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Evaluate an integer comparison of `bits`-wide integers.
+fn icmp(cond: IntCC, bits: u32, x: i64, y: i64) -> bool {
+    let (sx, sy) = (sext(x, bits), sext(y, bits));
+    let (ux, uy) = (zext(x, bits), zext(y, bits));
+    match cond {
+        IntCC::Equal => ux == uy,
+        IntCC::NotEqual => ux != uy,
+        IntCC::SignedLessThan => sx < sy,
+        IntCC::SignedGreaterThanOrEqual => sx >= sy,
+        IntCC::SignedGreaterThan => sx > sy,
+        IntCC::SignedLessThanOrEqual => sx <= sy,
+        IntCC::UnsignedLessThan => ux < uy,
+        IntCC::UnsignedGreaterThanOrEqual => ux >= uy,
+        IntCC::UnsignedGreaterThan => ux > uy,
+        IntCC::UnsignedLessThanOrEqual => ux <= uy,
+    }
+}
+
+/// Evaluate a floating point comparison.
+fn fcmp(cond: FloatCC, x: f64, y: f64) -> bool {
+    let ord = x.partial_cmp(&y);
+    match cond {
+        FloatCC::Ordered => ord.is_some(),
+        FloatCC::Unordered => ord.is_none(),
+        FloatCC::Equal => ord == Some(Ordering::Equal),
+        FloatCC::NotEqual => ord != Some(Ordering::Equal),
+        FloatCC::OrderedNotEqual => ord.is_some() && ord != Some(Ordering::Equal),
+        FloatCC::UnorderedOrEqual => ord.is_none() || ord == Some(Ordering::Equal),
+        FloatCC::LessThan => ord == Some(Ordering::Less),
+        FloatCC::LessThanOrEqual => ord.is_some() && ord != Some(Ordering::Greater),
+        FloatCC::GreaterThan => ord == Some(Ordering::Greater),
+        FloatCC::GreaterThanOrEqual => ord.is_some() && ord != Some(Ordering::Less),
+        FloatCC::UnorderedOrLessThan => ord.is_none() || ord == Some(Ordering::Less),
+        FloatCC::UnorderedOrLessThanOrEqual => ord != Some(Ordering::Greater),
+        FloatCC::UnorderedOrGreaterThan => ord.is_none() || ord == Some(Ordering::Greater),
+        FloatCC::UnorderedOrGreaterThanOrEqual => ord != Some(Ordering::Less),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::condcodes::{IntCC, FloatCC};
+    use ir::types::{I8, I32, I64, I128};
+    use std::f64;
+
+    #[test]
+    fn integers() {
+        assert_eq!(int_binary(Opcode::Iadd, 8, 0x7f, 1).map(|x| sext(x, 8)), Some(-128));
+        assert_eq!(int_binary(Opcode::Udiv, 32, -1, 2), Some(0x7fff_ffff));
+        assert_eq!(int_binary(Opcode::Sdiv, 32, -7, 2), Some(-3));
+        assert_eq!(int_binary(Opcode::Srem, 32, -7, 2), Some(-1));
+        assert_eq!(int_binary(Opcode::Sdiv, 8, -128, -1), None);
+        assert_eq!(int_binary(Opcode::Urem, 64, 5, 0), None);
+        assert_eq!(int_binary(Opcode::Ushr, 8, -1, 9), Some(0x7f));
+        assert_eq!(int_binary(Opcode::Sshr, 8, 0x80, 7), Some(-1));
+        assert_eq!(int_binary(Opcode::Rotl, 8, 0x81, 1), Some(0x03));
+        assert_eq!(int_binary(Opcode::Rotr, 8, 0x81, 1), Some(0xc0));
+        assert_eq!(int_binary(Opcode::Rotr, 64, 1, 64), Some(1));
+
+        assert!(icmp(IntCC::UnsignedLessThan, 8, 1, -1));
+        assert!(!icmp(IntCC::SignedLessThan, 8, 1, 0xff));
+        assert!(icmp(IntCC::Equal, 8, -1, 0xff));
+
+        assert_eq!(unary(Opcode::Clz, I8, I32, Const::Int(1)), Some(Const::Int(31)));
+        assert_eq!(unary(Opcode::Ctz, I8, I32, Const::Int(0)), Some(Const::Int(32)));
+        assert_eq!(unary(Opcode::Uextend, I64, I8, Const::Int(-1)), Some(Const::Int(0xff)));
+        assert_eq!(unary(Opcode::Sextend, I64, I8, Const::Int(0xff)), Some(Const::Int(-1)));
+    }
+
+    #[test]
+    fn wide_integers() {
+        // Integers wider than 64 bits are not folded.
+        assert_eq!(int_binary(Opcode::Ishl, 128, 1, 64), None);
+        assert_eq!(int_binary(Opcode::Sshr, 128, -1, 1), None);
+        assert_eq!(int_binary(Opcode::Iadd, 128, 1, 1), None);
+        assert_eq!(unary(Opcode::Sextend, I128, I64, Const::Int(-1)), None);
+        assert_eq!(unary(Opcode::Ireduce, I64, I128, Const::Int(-1)), None);
+    }
+
+    #[test]
+    fn floats() {
+        use ir::types::{F32, F64};
+
+        assert_eq!(binary(Opcode::Fadd, F32, Const::F32(1.5), Const::F32(2.0)),
+                   Some(Const::F32(3.5)));
+        assert_eq!(binary(Opcode::Fdiv, F64, Const::F64(1.0), Const::F64(0.0)),
+                   Some(Const::F64(f64::INFINITY)));
+        // NaN results are left alone.
+        assert_eq!(binary(Opcode::Fsub,
+                          F64,
+                          Const::F64(f64::INFINITY),
+                          Const::F64(f64::INFINITY)),
+                   None);
+
+        assert!(fcmp(FloatCC::Unordered, f64::NAN, 1.0));
+        assert!(!fcmp(FloatCC::Equal, f64::NAN, f64::NAN));
+        assert!(fcmp(FloatCC::NotEqual, f64::NAN, f64::NAN));
+        assert!(fcmp(FloatCC::UnorderedOrLessThan, f64::NAN, 1.0));
+        assert!(fcmp(FloatCC::GreaterThanOrEqual, 0.0, -0.0));
+
+        assert_eq!(unary(Opcode::FcvtToSint, I8, F32, Const::F32(-128.9)),
+                   Some(Const::Int(-128)));
+        assert_eq!(unary(Opcode::FcvtToSint, I8, F32, Const::F32(128.0)), None);
+        assert_eq!(unary(Opcode::FcvtToUint, I32, F64, Const::F64(-0.5)), Some(Const::Int(0)));
+        assert_eq!(unary(Opcode::FcvtToUint, I32, F64, Const::F64(f64::NAN)), None);
+    }
+}
@@ -0,0 +1,265 @@
+//! If-conversion.
+//!
+//! Short conditional code sequences are cheaper to execute unconditionally than to branch around
+//! when the branch is hard to predict. This pass looks for diamonds and triangles in the control
+//! flow graph where a conditional branch skips a few instructions without side effects:
+//!
+//! ```cton
+//! ebb0:
+//!     brz v1, ebb1(v2)
+//!     v3 = iadd v2, v2      ; Then part.
+//!     jump ebb2(v3)
+//!
+//! ebb1(v4: i32):           ; Else part, only reached from the `brz`.
+//!     v5 = imul v4, v4
+//!     jump ebb2(v5)
+//!
+//! ebb2(v6: i32):
+//! ```
+//!
+//! Both sides are executed unconditionally, and the values passed to the join EBB are chosen with
+//! `select` instructions. The else EBB disappears, and the join EBB is left with a single
+//! predecessor that the `straighten_layout()` pass can merge.
+//!
+//! Target ISAs decide how many instructions are worth executing speculatively with
+//! `TargetIsa::if_conversion_limit()`. ISAs without a conditional move expand `select` instructions
+//! back into branches during legalization.
+
+use cfg::ControlFlowGraph;
+use ir::{Function, Ebb, Inst, InstructionData, InstBuilder, Opcode, Cursor};
+use ir::instructions::CallInfo;
+use std::vec::Vec;
+
+/// Convert the short conditional code sequences in `func` into `select` instructions.
+///
+/// The code on both sides of a branch can be at most `limit` instructions in total.
+pub fn convert_ifs(func: &mut Function, limit: usize) {
+    if limit == 0 {
+        return;
+    }
+
+    // Converting a diamond only removes edges from the control flow graph, so the single
+    // predecessor checks remain valid.
+    let cfg = ControlFlowGraph::with_function(func);
+    let entry = func.layout.entry_block();
+
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for ebb in ebbs {
+        if !func.layout.is_ebb_inserted(ebb) {
+            // This EBB was the else part of a converted diamond.
+            continue;
+        }
+        if let Some(diamond) = find_diamond(func, &cfg, entry, ebb, limit) {
+            convert_diamond(func, diamond);
+        }
+    }
+}
+
+/// A conditional branch around some speculatable code.
+struct Diamond {
+    /// The `brz` or `brnz` instruction.
+    branch: Inst,
+    /// The jump terminating the header EBB.
+    then_jump: Inst,
+    /// The else EBB, or `None` when the branch goes straight to the join EBB.
+    else_ebb: Option<Ebb>,
+    /// The jump terminating the else EBB, or the branch itself for a triangle.
+    else_jump: Inst,
+}
+
+/// Can `inst` be executed speculatively?
+fn is_speculatable(func: &Function, inst: Inst) -> bool {
+    let data = &func.dfg[inst];
+    let opcode = data.opcode();
+    let is_call = match data.analyze_call(&func.dfg.value_lists) {
+        CallInfo::NotACall => false,
+        _ => true,
+    };
+    !is_call && !opcode.is_branch() && !opcode.is_terminator() && !opcode.can_trap()
+}
+
+/// Get the destination of the `jump` instruction `inst`.
+fn jump_destination(func: &Function, inst: Inst) -> Option<Ebb> {
+    match func.dfg[inst] {
+        InstructionData::Jump { opcode: Opcode::Jump, destination, .. } => Some(destination),
+        _ => None,
+    }
+}
+
+/// Look for a diamond or triangle headed by `ebb`.
+fn find_diamond(func: &Function,
+                cfg: &ControlFlowGraph,
+                entry: Option<Ebb>,
+                ebb: Ebb,
+                limit: usize)
+                -> Option<Diamond> {
+    let then_jump = func.layout.last_inst(ebb)?;
+    let join = jump_destination(func, then_jump)?;
+
+    // The then part is the code between the conditional branch and the final jump.
+    let mut then_size = 0;
+    let mut branch = None;
+    for inst in func.layout.ebb_insts(ebb).rev().skip(1) {
+        match func.dfg[inst].opcode() {
+            Opcode::Brz | Opcode::Brnz => {
+                branch = Some(inst);
+                break;
+            }
+            _ if is_speculatable(func, inst) => then_size += 1,
+            _ => return None,
+        }
+    }
+    let branch = branch?;
+    let dest = match func.dfg[branch] {
+        InstructionData::Branch { destination, .. } => destination,
+        _ => return None,
+    };
+
+    if dest == join {
+        return if then_size <= limit && dest != ebb {
+                   Some(Diamond {
+                            branch: branch,
+                            then_jump: then_jump,
+                            else_ebb: None,
+                            else_jump: branch,
+                        })
+               } else {
+                   None
+               };
+    }
+
+    // The else EBB must only be reachable from the branch.
+    let preds = cfg.get_predecessors(dest);
+    if dest == ebb || Some(dest) == entry || preds.len() != 1 || preds[0].1 != branch {
+        return None;
+    }
+    let else_jump = func.layout.last_inst(dest)?;
+    if jump_destination(func, else_jump) != Some(join) {
+        return None;
+    }
+    let mut else_size = 0;
+    for inst in func.layout.ebb_insts(dest) {
+        if inst == else_jump {
+            break;
+        }
+        if !is_speculatable(func, inst) {
+            return None;
+        }
+        else_size += 1;
+    }
+
+    if then_size + else_size <= limit {
+        Some(Diamond {
+                 branch: branch,
+                 then_jump: then_jump,
+                 else_ebb: Some(dest),
+                 else_jump: else_jump,
+             })
+    } else {
+        None
+    }
+}
+
+/// Replace `diamond` with `select` instructions.
+fn convert_diamond(func: &mut Function, diamond: Diamond) {
+    let cond = func.dfg.inst_args(diamond.branch)[0][0];
+    let branch_taken_if_zero = func.dfg[diamond.branch].opcode() == Opcode::Brz;
+
+    // Hoist the else part into the header EBB in front of the final jump.
+    if let Some(else_ebb) = diamond.else_ebb {
+        let args: Vec<_> = func.dfg.detach_ebb_args(else_ebb).collect();
+        let values = func.dfg.inst_variable_args(diamond.branch).to_vec();
+        for (&arg, &value) in args.iter().zip(values.iter()) {
+            func.dfg.change_to_alias(arg, value);
+        }
+        let insts: Vec<Inst> = func.layout.ebb_insts(else_ebb).collect();
+        for inst in insts {
+            func.layout.remove_inst(inst);
+            if inst != diamond.else_jump {
+                func.layout.insert_inst(inst, diamond.then_jump);
+            }
+        }
+        func.layout.remove_ebb(else_ebb);
+    }
+
+    // Select the values passed to the join EBB.
+    let then_args = func.dfg.inst_variable_args(diamond.then_jump).to_vec();
+    let else_args = func.dfg.inst_variable_args(diamond.else_jump).to_vec();
+    let mut joined = Vec::with_capacity(then_args.len());
+    {
+        let pos = &mut Cursor::new(&mut func.layout);
+        pos.goto_inst(diamond.then_jump);
+        for (&then_arg, &else_arg) in then_args.iter().zip(else_args.iter()) {
+            if func.dfg.resolve_aliases(then_arg) == func.dfg.resolve_aliases(else_arg) {
+                joined.push(then_arg);
+            } else if branch_taken_if_zero {
+                joined.push(func.dfg.ins(pos).select(cond, then_arg, else_arg));
+            } else {
+                joined.push(func.dfg.ins(pos).select(cond, else_arg, then_arg));
+            }
+        }
+    }
+    func.dfg
+        .inst_variable_args_mut(diamond.then_jump)
+        .copy_from_slice(&joined);
+
+    func.layout.remove_inst(diamond.branch);
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, Ebb, InstBuilder, Opcode, VariableArgs};
+    use ir::types;
+    use verifier::verify_function;
+    use super::convert_ifs;
+
+    #[test]
+    fn diamond() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v2 = func.dfg.append_ebb_arg(ebb2, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).brz(v0, ebb1, VariableArgs::new());
+            let v3 = dfg.ins(pos).iadd(v1, v1);
+            let mut args = VariableArgs::new();
+            args.push(v3);
+            dfg.ins(pos).jump(ebb2, args);
+
+            pos.insert_ebb(ebb1);
+            let v4 = dfg.ins(pos).imul(v1, v1);
+            let mut args = VariableArgs::new();
+            args.push(v4);
+            dfg.ins(pos).jump(ebb2, args);
+
+            pos.insert_ebb(ebb2);
+            let mut args = VariableArgs::new();
+            args.push(v2);
+            dfg.ins(pos).return_(args);
+        }
+
+        // A limit of 0 disables the pass.
+        convert_ifs(&mut func, 0);
+        assert_eq!(func.layout.ebbs().count(), 3);
+
+        // The diamond has two instructions in total.
+        convert_ifs(&mut func, 1);
+        assert_eq!(func.layout.ebbs().count(), 3);
+
+        convert_ifs(&mut func, 2);
+        verify_function(&func).unwrap();
+        let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(ebbs, [ebb0, ebb2]);
+        let opcodes: Vec<Opcode> = func.layout
+            .ebb_insts(ebb0)
+            .map(|inst| func.dfg[inst].opcode())
+            .collect();
+        assert_eq!(opcodes, [Opcode::Iadd, Opcode::Imul, Opcode::Select, Opcode::Jump]);
+    }
+}
@@ -0,0 +1,443 @@
+//! Function inlining.
+//!
+//! The inliner splices the body of a callee function into a caller at a `call` site:
+//!
+//! - The caller's EBB is split after the call, and the second half becomes a *continuation* EBB
+//!   whose arguments receive the values returned by the callee.
+//! - The instructions in the callee's entry EBB are appended to the caller's EBB in place of the
+//!   call, with the callee's function arguments replaced by the call arguments. The other callee
+//!   EBBs are copied into the caller following it.
+//! - All the entities referenced by the callee instructions are copied into the caller: values,
+//!   EBBs, jump tables, signatures, external functions, heaps, and global variables. The callee's
+//!   stack slots are added to the caller's stack frame.
+//! - The callee's `return` instructions become jumps to the continuation EBB.
+//!
+//! The inlined instructions keep the callee's source locations. Callee instructions without a
+//! source location get the location of the call site instead.
+//!
+//! Inlining should happen before legalization since the callee's calling convention details like
+//! `return_reg` can't be translated. Use `can_inline()` to check if a callee is suitable.
+//!
+//! The `inline_call()` function is the mechanism. Embedders with their own inlining policies can
+//! call it directly. The `inline_small_functions()` driver in the cton_module library implements a
+//! simple size heuristic for a collection of functions.
+
+use std::collections::HashMap;
+use std::vec::Vec;
+use ir::{Function, ExternalName, Ebb, Inst, Value, SigRef, FuncRef, JumpTable, JumpTableData,
+         Heap, GlobalVar, StackSlot, InstructionData, InstBuilder, Opcode};
+use ir::instructions::{ValueList, ValueListPool};
+use ir::types::VOID;
+
+/// Can `callee` be inlined by `inline_call()`?
+///
+/// The callee must have a body, and it can't use the `return_reg` instruction which only appears
+/// after legalization. Tail calls in the callee would return from the caller, so they can't be
+/// inlined either.
+pub fn can_inline(callee: &Function) -> bool {
+    callee.layout.entry_block().is_some() &&
+    callee.layout
+        .ebbs()
+        .flat_map(|ebb| callee.layout.ebb_insts(ebb))
+        .all(|inst| match callee.dfg[inst].opcode() {
+                 Opcode::ReturnReg |
+                 Opcode::ReturnCall |
+                 Opcode::ReturnCallIndirect => false,
+                 _ => true,
+             })
+}
+
+/// Count the instructions in the layout of `func`.
+///
+/// This is the size measure used by the `inline_small_functions()` driver in cton_module.
+pub fn function_size(func: &Function) -> usize {
+    func.layout.ebbs().map(|ebb| func.layout.ebb_insts(ebb).count()).sum()
+}
+
+/// Inline `callee` at the `call` instruction in `caller`.
+///
+/// The `call` instruction must be a direct call whose arguments and results match the signature
+/// of `callee`, and `can_inline(callee)` must be true.
+///
+/// Returns the continuation EBB which begins with the instructions following the call.
+pub fn inline_call(caller: &mut Function, call: Inst, callee: &Function) -> Ebb {
+    let args: Vec<Value> = match caller.dfg[call] {
+        InstructionData::Call { opcode: Opcode::Call, .. } => {
+            caller.dfg.inst_variable_args(call).to_vec()
+        }
+        _ => panic!("{} is not a direct call", call),
+    };
+    let callee_entry = callee.layout.entry_block().expect("Callee has no body");
+    let ebb = caller.layout.inst_ebb(call).expect("Call not in layout");
+    let call_srcloc = caller.srcloc(call);
+
+    // Split off the continuation EBB and turn the call results into its arguments.
+    let cont = caller.dfg.make_ebb();
+    caller.layout.split_ebb(cont, call);
+    let results: Vec<Value> = caller.dfg.inst_results(call).collect();
+    let cont_args: Vec<Value> = results.iter()
+        .map(|&res| {
+                 let ty = caller.dfg.value_type(res);
+                 caller.dfg.append_ebb_arg(cont, ty)
+             })
+        .collect();
+    let secondary: Vec<Value> = caller.dfg.detach_secondary_results(call).collect();
+    for (&res, &arg) in secondary.iter().zip(cont_args.iter().skip(1)) {
+        caller.dfg.change_to_alias(res, arg);
+    }
+    match cont_args.first() {
+        Some(&arg) => {
+            caller.dfg.replace(call).copy(arg);
+        }
+        None => caller.layout.remove_inst(call),
+    }
+
+    let mut map = InlineMap::new(caller, callee, callee_entry, ebb, cont);
+    assert_eq!(callee.dfg.num_ebb_args(callee_entry),
+               args.len(),
+               "Bad number of call arguments");
+    for (callee_arg, &arg) in callee.dfg.ebb_args(callee_entry).zip(args.iter()) {
+        map.values.insert(callee_arg, arg);
+    }
+
+    // Copy the instructions, mapping all the entities except values which may be used before
+    // they are defined in the layout order.
+    let mut new_insts = Vec::new();
+    for callee_ebb in callee.layout.ebbs() {
+        let new_ebb = map.ebbs[&callee_ebb];
+        for callee_inst in callee.layout.ebb_insts(callee_ebb) {
+            let data = map.inst_data(&callee.dfg[callee_inst],
+                                     &callee.dfg.value_lists,
+                                     &mut caller.dfg.value_lists);
+            let inst = caller.dfg.make_inst(data);
+            let ctrl_typevar = callee.dfg[callee_inst].ctrl_typevar(&callee.dfg);
+            caller.dfg.make_inst_results(inst, ctrl_typevar);
+            for (callee_res, res) in callee.dfg
+                    .inst_results(callee_inst)
+                    .zip(caller.dfg.inst_results(inst)) {
+                map.values.insert(callee_res, res);
+            }
+            let srcloc = callee.srcloc(callee_inst);
+            let srcloc = if srcloc.is_default() {
+                call_srcloc
+            } else {
+                srcloc
+            };
+            if !srcloc.is_default() {
+                caller.set_srcloc(inst, srcloc);
+            }
+            caller.layout.append_inst(inst, new_ebb);
+            new_insts.push(inst);
+        }
+    }
+
+    for inst in new_insts {
+        for part in &mut caller.dfg.inst_args_mut(inst) {
+            for arg in part.iter_mut() {
+                *arg = map.value(callee, *arg);
+            }
+        }
+    }
+
+    cont
+}
+
+/// Mapping from callee entities to caller entities.
+struct InlineMap {
+    values: HashMap<Value, Value>,
+    ebbs: HashMap<Ebb, Ebb>,
+    jump_tables: HashMap<JumpTable, JumpTable>,
+    signatures: HashMap<SigRef, SigRef>,
+    ext_funcs: HashMap<FuncRef, FuncRef>,
+    heaps: HashMap<Heap, Heap>,
+    global_vars: HashMap<GlobalVar, GlobalVar>,
+    stack_slots: HashMap<StackSlot, StackSlot>,
+    cont: Ebb,
+}
+
+impl InlineMap {
+    /// Create EBBs and preamble entities in `caller` corresponding to the ones in `callee`.
+    ///
+    /// The callee's entry EBB is mapped to `entry`, and the other EBBs are inserted in the caller
+    /// layout before `cont`.
+    fn new(caller: &mut Function,
+           callee: &Function,
+           callee_entry: Ebb,
+           entry: Ebb,
+           cont: Ebb)
+           -> InlineMap {
+        let mut map = InlineMap {
+            values: HashMap::new(),
+            ebbs: HashMap::new(),
+            jump_tables: HashMap::new(),
+            signatures: HashMap::new(),
+            ext_funcs: HashMap::new(),
+            heaps: HashMap::new(),
+            global_vars: HashMap::new(),
+            stack_slots: HashMap::new(),
+            cont: cont,
+        };
+
+        map.ebbs.insert(callee_entry, entry);
+        let mut last = entry;
+        for callee_ebb in callee.layout.ebbs().skip(1) {
+            let ebb = caller.dfg.make_ebb();
+            caller.layout.insert_ebb_after(ebb, last);
+            last = ebb;
+            for arg in callee.dfg.ebb_args(callee_ebb) {
+                let new_arg = caller.dfg.append_ebb_arg(ebb, callee.dfg.value_type(arg));
+                map.values.insert(arg, new_arg);
+            }
+            map.ebbs.insert(callee_ebb, ebb);
+        }
+
+        for jt in callee.jump_tables.keys() {
+            let mut data = JumpTableData::new();
+            for (idx, dest) in callee.jump_tables[jt].entries() {
+                data.set_entry(idx, map.ebbs[&dest]);
+            }
+            map.jump_tables.insert(jt, caller.jump_tables.push(data));
+        }
+
+        for sig in callee.dfg.signatures.keys() {
+            let new_sig = caller.dfg.signatures.push(callee.dfg.signatures[sig].clone());
+            map.signatures.insert(sig, new_sig);
+        }
+
+        for fref in callee.dfg.ext_funcs.keys() {
+            let mut data = callee.dfg.ext_funcs[fref].clone();
+            data.signature = map.signatures[&data.signature];
+            map.ext_funcs.insert(fref, caller.dfg.ext_funcs.push(data));
+        }
+
+        // Heaps are identified by name, so reuse the caller's heap declarations.
+        for heap in callee.heaps.keys() {
+            let name = &callee.heaps[heap].name;
+            let new_heap = match find_heap(caller, name) {
+                Some(h) => h,
+                None => caller.heaps.push(callee.heaps[heap].clone()),
+            };
+            map.heaps.insert(heap, new_heap);
+        }
+
+        // Global variables are symbols, so they are also identified by name.
+        for gv in callee.dfg.global_vars.keys() {
+            let name = &callee.dfg.global_vars[gv].name;
+            let new_gv = match find_global_var(caller, name) {
+                Some(g) => g,
+                None => caller.dfg.global_vars.push(callee.dfg.global_vars[gv].clone()),
+            };
+            map.global_vars.insert(gv, new_gv);
+        }
+
+        for ss in callee.stack_slots.keys() {
+            let new_ss = caller.stack_slots.push(callee.stack_slots[ss].clone());
+            map.stack_slots.insert(ss, new_ss);
+        }
+
+        map
+    }
+
+    /// Get the caller value corresponding to the callee value `v`.
+    fn value(&self, callee: &Function, v: Value) -> Value {
+        let v = callee.dfg.resolve_aliases(v);
+        *self.values.get(&v).expect("Callee value not defined")
+    }
+
+    /// Copy the callee instruction `data`, mapping all entity references except values.
+    ///
+    /// The value list of the instruction is copied from the callee's `callee_pool` to the
+    /// caller's `pool`. A `return` instruction is turned into a jump to the continuation EBB.
+    fn inst_data(&self,
+                 data: &InstructionData,
+                 callee_pool: &ValueListPool,
+                 pool: &mut ValueListPool)
+                 -> InstructionData {
+        let mut data = data.clone();
+        if let Some(second_result) = data.second_result_mut() {
+            *second_result = None.into();
+        }
+        if let Some(args) = data.value_list_mut() {
+            let mut copy = ValueList::default();
+            copy.extend(args.as_slice(callee_pool).iter().cloned(), pool);
+            *args = copy;
+        }
+        match data {
+            InstructionData::Return { ref args, .. } => {
+                return InstructionData::Jump {
+                           opcode: Opcode::Jump,
+                           ty: VOID,
+                           destination: self.cont,
+                           args: args.clone(),
+                       };
+            }
+            InstructionData::Jump { ref mut destination, .. } |
+            InstructionData::Branch { ref mut destination, .. } |
+            InstructionData::BranchIcmp { ref mut destination, .. } => {
+                *destination = self.ebbs[destination];
+            }
+            InstructionData::BranchTable { ref mut table, .. } |
+            InstructionData::BranchTableEntry { ref mut table, .. } |
+            InstructionData::BranchTableBase { ref mut table, .. } => {
+                *table = self.jump_tables[table];
+            }
+            InstructionData::Call { ref mut func_ref, .. } => {
+                *func_ref = self.ext_funcs[func_ref];
+            }
+            InstructionData::FuncAddr { ref mut func_ref, .. } => {
+                *func_ref = self.ext_funcs[func_ref];
+            }
+            InstructionData::IndirectCall { ref mut sig_ref, .. } => {
+                *sig_ref = self.signatures[sig_ref];
+            }
+            InstructionData::HeapAddr { ref mut heap, .. } => {
+                *heap = self.heaps[heap];
+            }
+            InstructionData::UnaryGlobalVar { ref mut global_var, .. } => {
+                *global_var = self.global_vars[global_var];
+            }
+            InstructionData::StackLoad { ref mut stack_slot, .. } |
+            InstructionData::StackStore { ref mut stack_slot, .. } => {
+                *stack_slot = self.stack_slots[stack_slot];
+            }
+            _ => {}
+        }
+        data
+    }
+}
+
+/// Find the heap named `name` in `func`.
+fn find_heap(func: &Function, name: &ExternalName) -> Option<Heap> {
+    func.heaps.keys().find(|&heap| func.heaps[heap].name == *name)
+}
+
+/// Find the global variable named `name` in `func`.
+fn find_global_var(func: &Function, name: &ExternalName) -> Option<GlobalVar> {
+    func.dfg.global_vars.keys().find(|&gv| func.dfg.global_vars[gv].name == *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, ExternalName, Signature, ArgumentType, ExtFuncData, Cursor, Ebb,
+             InstBuilder, Opcode, SourceLoc, VariableArgs};
+    use ir::types::I32;
+    use verifier::verify_function;
+    use super::{inline_call, can_inline, function_size};
+
+    fn sig_i32() -> Signature {
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(I32));
+        sig.return_types.push(ArgumentType::new(I32));
+        sig
+    }
+
+    // function callee(i32) -> i32 {
+    // ebb0(v0: i32):
+    //     brz v0, ebb1
+    //     v1 = iadd_imm v0, 1
+    //     return v1
+    // ebb1:
+    //     v2 = iconst.i32 0
+    //     return v2
+    // }
+    fn callee() -> Function {
+        let mut func = Function::with_name_signature(ExternalName::testcase("callee"), sig_i32());
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).brz(v0, ebb1, VariableArgs::new());
+            let v1 = dfg.ins(pos).iadd_imm(v0, 1);
+            let mut rets = VariableArgs::new();
+            rets.push(v1);
+            dfg.ins(pos).return_(rets);
+
+            pos.insert_ebb(ebb1);
+            let v2 = dfg.ins(pos).iconst(I32, 0);
+            let mut rets = VariableArgs::new();
+            rets.push(v2);
+            dfg.ins(pos).return_(rets);
+        }
+        func
+    }
+
+    // function caller(i32) -> i32 {
+    //     fn0 = function callee(i32) -> i32
+    // ebb0(v0: i32):
+    //     v1 = call fn0(v0)
+    //     v2 = imul v1, v1
+    //     return v2
+    // }
+    fn caller() -> Function {
+        let mut func = Function::with_name_signature(ExternalName::testcase("caller"), sig_i32());
+        let sig = func.dfg.signatures.push(sig_i32());
+        let fref = func.dfg.ext_funcs.push(ExtFuncData::new(ExternalName::testcase("callee"), sig));
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let mut args = VariableArgs::new();
+            args.push(v0);
+            let call = dfg.ins(pos).call(fref, args);
+            let v1 = dfg.first_result(call);
+            let v2 = dfg.ins(pos).imul(v1, v1);
+            let mut rets = VariableArgs::new();
+            rets.push(v2);
+            dfg.ins(pos).return_(rets);
+        }
+        func
+    }
+
+    fn opcodes(func: &Function, ebb: Ebb) -> Vec<Opcode> {
+        func.layout.ebb_insts(ebb).map(|inst| func.dfg[inst].opcode()).collect()
+    }
+
+    #[test]
+    fn inline_one_call() {
+        let callee = callee();
+        let mut func = caller();
+        assert!(can_inline(&callee));
+        assert_eq!(function_size(&callee), 5);
+        let ebb0 = func.layout.entry_block().unwrap();
+        let call = func.layout.ebb_insts(ebb0).next().unwrap();
+
+        let cont = inline_call(&mut func, call, &callee);
+        verify_function(&func).unwrap();
+
+        let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(ebbs.len(), 3);
+        assert_eq!(ebbs[0], ebb0);
+        assert_eq!(ebbs[2], cont);
+        assert_eq!(opcodes(&func, ebb0), [Opcode::Brz, Opcode::IaddImm, Opcode::Jump]);
+        assert_eq!(opcodes(&func, ebbs[1]), [Opcode::Iconst, Opcode::Jump]);
+        assert_eq!(opcodes(&func, cont), [Opcode::Copy, Opcode::Imul, Opcode::Return]);
+
+        // The call result is now computed from the continuation argument.
+        let arg = func.dfg.ebb_args(cont).next().unwrap();
+        assert_eq!(func.dfg.resolve_copies(func.dfg.first_result(call)), arg);
+    }
+
+    #[test]
+    fn source_locations() {
+        let mut callee = callee();
+        let callee_entry = callee.layout.entry_block().unwrap();
+        let iadd = callee.layout.ebb_insts(callee_entry).nth(1).unwrap();
+        callee.set_srcloc(iadd, SourceLoc::new(0x10));
+
+        let mut func = caller();
+        let ebb0 = func.layout.entry_block().unwrap();
+        let call = func.layout.ebb_insts(ebb0).next().unwrap();
+        func.set_srcloc(call, SourceLoc::new(0x20));
+        inline_call(&mut func, call, &callee);
+
+        // The callee's location is kept, and the others come from the call site.
+        let locs: Vec<SourceLoc> = func.layout.ebb_insts(ebb0).map(|i| func.srcloc(i)).collect();
+        assert_eq!(locs,
+                   [SourceLoc::new(0x20), SourceLoc::new(0x10), SourceLoc::new(0x20)]);
+    }
+}
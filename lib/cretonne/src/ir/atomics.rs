@@ -0,0 +1,108 @@
+//! Memory ordering constraints for atomic instructions.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// The memory ordering of an atomic instruction.
+///
+/// The orderings have the same meaning as in the C++11 memory model. Targets may implement an
+/// ordering with a stronger one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AtomicOrdering {
+    /// Only the atomic access itself is ordered.
+    Relaxed,
+
+    /// No later memory accesses can be moved before this load.
+    Acquire,
+
+    /// No earlier memory accesses can be moved after this store.
+    Release,
+
+    /// Both `Acquire` and `Release` for a read-modify-write operation.
+    AcqRel,
+
+    /// `AcqRel`, and all sequentially consistent operations have a single total order.
+    SeqCst,
+}
+
+impl AtomicOrdering {
+    /// Does this ordering have acquire semantics?
+    pub fn is_acquire(self) -> bool {
+        use self::AtomicOrdering::*;
+        match self {
+            Acquire | AcqRel | SeqCst => true,
+            Relaxed | Release => false,
+        }
+    }
+
+    /// Does this ordering have release semantics?
+    pub fn is_release(self) -> bool {
+        use self::AtomicOrdering::*;
+        match self {
+            Release | AcqRel | SeqCst => true,
+            Relaxed | Acquire => false,
+        }
+    }
+}
+
+impl Display for AtomicOrdering {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::AtomicOrdering::*;
+        let identifier = match *self {
+            Relaxed => "relaxed",
+            Acquire => "acquire",
+            Release => "release",
+            AcqRel => "acq_rel",
+            SeqCst => "seq_cst",
+        };
+        f.write_str(identifier)
+    }
+}
+
+impl FromStr for AtomicOrdering {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::AtomicOrdering::*;
+        match s {
+            "relaxed" => Ok(Relaxed),
+            "acquire" => Ok(Acquire),
+            "release" => Ok(Release),
+            "acq_rel" => Ok(AcqRel),
+            "seq_cst" => Ok(SeqCst),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDERINGS: [AtomicOrdering; 5] = [AtomicOrdering::Relaxed,
+                                            AtomicOrdering::Acquire,
+                                            AtomicOrdering::Release,
+                                            AtomicOrdering::AcqRel,
+                                            AtomicOrdering::SeqCst];
+
+    #[test]
+    fn display() {
+        for &ord in &ORDERINGS {
+            assert_eq!(ord.to_string().parse(), Ok(ord));
+        }
+        assert_eq!("bogus".parse::<AtomicOrdering>(), Err(()));
+        assert_eq!(AtomicOrdering::SeqCst.to_string(), "seq_cst");
+    }
+
+    #[test]
+    fn semantics() {
+        assert!(!AtomicOrdering::Relaxed.is_acquire());
+        assert!(!AtomicOrdering::Relaxed.is_release());
+        assert!(AtomicOrdering::Acquire.is_acquire());
+        assert!(!AtomicOrdering::Acquire.is_release());
+        assert!(AtomicOrdering::AcqRel.is_acquire());
+        assert!(AtomicOrdering::AcqRel.is_release());
+        assert!(AtomicOrdering::SeqCst.is_acquire());
+        assert!(AtomicOrdering::SeqCst.is_release());
+    }
+}
@@ -4,10 +4,15 @@
 //! function. Many of its methods are generated from the meta language instruction definitions.
 
 use ir::{types, instructions};
+use ir::instructions::ValueList;
 use ir::{InstructionData, DataFlowGraph, Cursor};
-use ir::{Opcode, Type, Inst, Value, Ebb, JumpTable, VariableArgs, SigRef, FuncRef};
-use ir::immediates::{Imm64, Uimm8, Ieee32, Ieee64, ImmVector};
+use ir::{Opcode, Type, Inst, Value, Ebb, JumpTable, VariableArgs, SigRef, FuncRef, TrapCode,
+         Heap, GlobalVar, StackSlot, MemFlags, AtomicOrdering};
+use ir::immediates::{Imm64, Uimm8, Uimm32, Offset32, Ieee32, Ieee64, ImmVector};
 use ir::condcodes::{IntCC, FloatCC};
+use isa::RegUnit;
+use std::boxed::Box;
+use std::vec::Vec;
 
 /// Base trait for instruction builders.
 ///
@@ -22,6 +27,10 @@ pub trait InstBuilderBase<'f>: Sized {
     /// instructions.
     fn data_flow_graph(&self) -> &DataFlowGraph;
 
+    /// Get a mutable reference to the data flow graph that will hold the constructed
+    /// instructions.
+    fn data_flow_graph_mut(&mut self) -> &mut DataFlowGraph;
+
     /// Insert a simple instruction and return a reference to it.
     ///
     /// A 'simple' instruction has at most one result, and the `data.ty` field must contain the
@@ -76,6 +85,10 @@ impl<'c, 'fc, 'fd> InstBuilderBase<'fd> for InsertBuilder<'c, 'fc, 'fd> {
         self.dfg
     }
 
+    fn data_flow_graph_mut(&mut self) -> &mut DataFlowGraph {
+        self.dfg
+    }
+
     fn simple_instruction(self, data: InstructionData) -> (Inst, &'fd mut DataFlowGraph) {
         let inst = self.dfg.make_inst(data);
         self.pos.insert_inst(inst);
@@ -129,6 +142,10 @@ impl<'f> InstBuilderBase<'f> for ReplaceBuilder<'f> {
         self.dfg
     }
 
+    fn data_flow_graph_mut(&mut self) -> &mut DataFlowGraph {
+        self.dfg
+    }
+
     fn simple_instruction(self, data: InstructionData) -> (Inst, &'f mut DataFlowGraph) {
         // The replacement instruction cannot generate multiple results, so verify that the old
         // instruction's secondary results have been detached.
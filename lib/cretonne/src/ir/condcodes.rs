@@ -85,6 +85,36 @@ impl CondCode for IntCC {
     }
 }
 
+impl IntCC {
+    /// Get the unsigned condition code corresponding to `self`.
+    ///
+    /// Equality comparisons and unsigned comparisons are returned unchanged.
+    pub fn unsigned(self) -> IntCC {
+        use self::IntCC::*;
+        match self {
+            SignedLessThan => UnsignedLessThan,
+            SignedGreaterThanOrEqual => UnsignedGreaterThanOrEqual,
+            SignedGreaterThan => UnsignedGreaterThan,
+            SignedLessThanOrEqual => UnsignedLessThanOrEqual,
+            cc => cc,
+        }
+    }
+
+    /// Get the strict version of `self`, which is false when the two numbers are equal.
+    ///
+    /// For `Equal` and `NotEqual`, returns `NotEqual`.
+    pub fn without_equal(self) -> IntCC {
+        use self::IntCC::*;
+        match self {
+            Equal | NotEqual => NotEqual,
+            SignedLessThan | SignedLessThanOrEqual => SignedLessThan,
+            SignedGreaterThan | SignedGreaterThanOrEqual => SignedGreaterThan,
+            UnsignedLessThan | UnsignedLessThanOrEqual => UnsignedLessThan,
+            UnsignedGreaterThan | UnsignedGreaterThanOrEqual => UnsignedGreaterThan,
+        }
+    }
+}
+
 impl Display for IntCC {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         use self::IntCC::*;
@@ -297,6 +327,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn int_unsigned() {
+        for r in &INT_ALL {
+            let cc = *r;
+            let uns = cc.unsigned();
+            assert_eq!(uns.unsigned(), uns);
+            assert_eq!(uns.without_equal(), cc.without_equal().unsigned());
+            assert_eq!(cc.inverse().unsigned(), uns.inverse());
+        }
+        assert_eq!(IntCC::SignedLessThanOrEqual.unsigned(),
+                   IntCC::UnsignedLessThanOrEqual);
+    }
+
     #[test]
     fn int_display() {
         for r in &INT_ALL {
@@ -1,16 +1,20 @@
 //! Data flow graph tracking Instructions, Values, and EBBs.
 
-use ir::{Ebb, Inst, Value, Type, SigRef, Signature, FuncRef};
+use ir::{Ebb, Inst, Value, Type, SigRef, Signature, FuncRef, GlobalVar, GlobalVarData};
+use ir::types;
 use ir::entities::ExpandedValue;
-use ir::instructions::{Opcode, InstructionData, CallInfo};
+use ir::instructions::{Opcode, InstructionData, BranchInfo, CallInfo, ValueListPool};
 use ir::extfunc::ExtFuncData;
 use entity_map::{EntityMap, PrimaryEntityData};
+use mem_usage::MemUsage;
 use ir::builder::{InsertBuilder, ReplaceBuilder};
 use ir::layout::Cursor;
 use packed_option::PackedOption;
+use serialize::{self, Serialize, Encoder, Decoder, DecodeError};
 
 use std::ops::{Index, IndexMut};
 use std::u16;
+use std::vec::Vec;
 
 /// A data flow graph defines all instructions and extended basic blocks in a function as well as
 /// the data flow dependencies between them. The DFG also tracks values which can be either
@@ -26,6 +30,9 @@ pub struct DataFlowGraph {
     /// with the EBB containing each instruction.
     insts: EntityMap<Inst, InstructionData>,
 
+    /// Memory pool of value lists referenced by the instructions in `insts`.
+    pub value_lists: ValueListPool,
+
     /// Extended basic blocks in the function and their arguments.
     /// This map is not in program order. That is handled by `Layout`, and so is the sequence of
     /// instructions contained in each EBB.
@@ -44,25 +51,50 @@ pub struct DataFlowGraph {
 
     /// External function references. These are functions that can be called directly.
     pub ext_funcs: EntityMap<FuncRef, ExtFuncData>,
+
+    /// Global variables referenced by `globalsym_addr` instructions. Like the external functions,
+    /// they are kept here so the ISA encoding predicates can check where the symbols live.
+    pub global_vars: EntityMap<GlobalVar, GlobalVarData>,
 }
 
 impl PrimaryEntityData for InstructionData {}
 impl PrimaryEntityData for EbbData {}
 impl PrimaryEntityData for Signature {}
 impl PrimaryEntityData for ExtFuncData {}
+impl PrimaryEntityData for GlobalVarData {}
 
 impl DataFlowGraph {
     /// Create a new empty `DataFlowGraph`.
     pub fn new() -> DataFlowGraph {
         DataFlowGraph {
             insts: EntityMap::new(),
+            value_lists: ValueListPool::new(),
             ebbs: EntityMap::new(),
             extended_values: Vec::new(),
             signatures: EntityMap::new(),
             ext_funcs: EntityMap::new(),
+            global_vars: EntityMap::new(),
         }
     }
 
+    /// Clear everything, keeping the allocated memory for reuse.
+    pub fn clear(&mut self) {
+        self.insts.clear();
+        self.value_lists.clear();
+        self.ebbs.clear();
+        self.extended_values.clear();
+        self.signatures.clear();
+        self.ext_funcs.clear();
+        self.global_vars.clear();
+    }
+
+    /// Get the memory used by the data flow graph.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.insts.mem_usage() + self.value_lists.mem_usage() + self.ebbs.mem_usage() +
+        MemUsage::of_vec(&self.extended_values) +
+        self.signatures.mem_usage() + self.ext_funcs.mem_usage() + self.global_vars.mem_usage()
+    }
+
     /// Get the total number of instructions created in this function, whether they are currently
     /// inserted in the layout or not.
     ///
@@ -91,6 +123,18 @@ impl DataFlowGraph {
         vref
     }
 
+    /// Check if a value reference is valid.
+    ///
+    /// A direct value is valid if it refers to an existing instruction that has at least one
+    /// result. A table value is valid if it refers to an existing extended value entry.
+    pub fn value_is_valid(&self, v: Value) -> bool {
+        use ir::entities::ExpandedValue::*;
+        match v.expand() {
+            Direct(inst) => self.insts.is_valid(inst) && self.insts[inst].first_type() != types::VOID,
+            Table(idx) => idx < self.extended_values.len(),
+        }
+    }
+
     /// Get the type of a value.
     pub fn value_type(&self, v: Value) -> Type {
         use ir::entities::ExpandedValue::*;
@@ -180,6 +224,24 @@ impl DataFlowGraph {
         panic!("Copy loop detected for {}", value);
     }
 
+    /// Replace the arguments of `inst` that are value aliases with their original values.
+    pub fn resolve_aliases_in_arguments(&mut self, inst: Inst) {
+        let resolved: Vec<Value> = {
+            let args = self.inst_args(inst);
+            args[0]
+                .iter()
+                .chain(args[1].iter())
+                .map(|&arg| self.resolve_aliases(arg))
+                .collect()
+        };
+        let mut resolved = resolved.into_iter();
+        for args in self.inst_args_mut(inst).iter_mut() {
+            for arg in args.iter_mut() {
+                *arg = resolved.next().expect("same number of arguments");
+            }
+        }
+    }
+
     /// Turn a value into an alias of another.
     ///
     /// Change the `dest` value to behave as an alias of `src`. This means that all uses of `dest`
@@ -216,6 +278,20 @@ impl DataFlowGraph {
             panic!("Cannot change direct value {} into an alias", dest);
         }
     }
+
+    /// Create a new value that is an alias of `src`.
+    ///
+    /// The new alias value has the same type as `src`. This is mostly useful when reading a
+    /// function from text where the value aliases are given explicitly.
+    pub fn make_value_alias(&mut self, src: Value) -> Value {
+        // Point directly to the original value to keep alias chains short.
+        let original = self.resolve_aliases(src);
+        let ty = self.value_type(original);
+        self.make_value(ValueData::Alias {
+            ty: ty,
+            original: original,
+        })
+    }
 }
 
 /// Where did a value come from?
@@ -336,7 +412,7 @@ impl DataFlowGraph {
         let mut rev_num = 1;
 
         // Get the call signature if this is a function call.
-        if let Some(sig) = self.call_signature(inst) {
+        if let Some(sig) = self.call_results_signature(inst) {
             // Create result values corresponding to the call return types.
             let var_results = self.signatures[sig].return_types.len();
             total_results += var_results;
@@ -426,16 +502,82 @@ impl DataFlowGraph {
         }
     }
 
+    /// Get the value arguments of `inst`: The fixed arguments followed by the variable arguments.
+    pub fn inst_args(&self, inst: Inst) -> [&[Value]; 2] {
+        self.insts[inst].arguments(&self.value_lists)
+    }
+
+    /// Get mutable references to the value arguments of `inst`.
+    pub fn inst_args_mut(&mut self, inst: Inst) -> [&mut [Value]; 2] {
+        self.insts[inst].arguments_mut(&mut self.value_lists)
+    }
+
+    /// Get the variable value arguments of `inst`, like the arguments passed to a branch
+    /// destination or the arguments of a call.
+    pub fn inst_variable_args(&self, inst: Inst) -> &[Value] {
+        self.inst_args(inst)[1]
+    }
+
+    /// Get mutable references to the variable value arguments of `inst`.
+    pub fn inst_variable_args_mut(&mut self, inst: Inst) -> &mut [Value] {
+        let fixed = self.insts[inst].opcode().format().num_value_operands();
+        match self.insts[inst].value_list_mut() {
+            Some(args) => &mut args.as_mut_slice(&mut self.value_lists)[fixed..],
+            None => &mut [],
+        }
+    }
+
+    /// Replace the variable value arguments of `inst` with `args`.
+    ///
+    /// Panics if `inst` doesn't take variable arguments.
+    pub fn set_inst_variable_args(&mut self, inst: Inst, args: &[Value]) {
+        let fixed = self.insts[inst].opcode().format().num_value_operands();
+        let list = self.insts[inst]
+            .value_list_mut()
+            .expect("instruction has no variable arguments");
+        let mut values = list.as_slice(&self.value_lists)[..fixed].to_vec();
+        values.extend_from_slice(args);
+        list.clear(&mut self.value_lists);
+        list.extend(values, &mut self.value_lists);
+    }
+
+    /// Append `arg` to the variable value arguments of `inst`.
+    ///
+    /// Panics if `inst` doesn't take variable arguments.
+    pub fn append_inst_arg(&mut self, inst: Inst, arg: Value) {
+        let args = self.insts[inst]
+            .value_list_mut()
+            .expect("instruction has no variable arguments");
+        args.push(arg, &mut self.value_lists);
+    }
+
+    /// Return information about the destination of the branch or jump instruction `inst`.
+    pub fn analyze_branch(&self, inst: Inst) -> BranchInfo {
+        self.insts[inst].analyze_branch(&self.value_lists)
+    }
+
     /// Get the call signature of a direct or indirect call instruction.
     /// Returns `None` if `inst` is not a call instruction.
     pub fn call_signature(&self, inst: Inst) -> Option<SigRef> {
-        match self.insts[inst].analyze_call() {
+        match self.insts[inst].analyze_call(&self.value_lists) {
             CallInfo::NotACall => None,
             CallInfo::Direct(f, _) => Some(self.ext_funcs[f].signature),
             CallInfo::Indirect(s, _) => Some(s),
         }
     }
 
+    /// Get the signature providing the result types of a call instruction.
+    ///
+    /// Tail calls return directly to the caller of the current function, so they don't have any
+    /// results.
+    fn call_results_signature(&self, inst: Inst) -> Option<SigRef> {
+        if self.insts[inst].opcode().is_return() {
+            None
+        } else {
+            self.call_signature(inst)
+        }
+    }
+
     /// Compute the type of an instruction result from opcode constraints and call signatures.
     ///
     /// This computes the same sequence of result types that `make_inst_results()` above would
@@ -456,7 +598,7 @@ impl DataFlowGraph {
         }
 
         // Not a fixed result, try to extract a return type from the call signature.
-        self.call_signature(inst).and_then(|sigref| {
+        self.call_results_signature(inst).and_then(|sigref| {
             self.signatures[sigref]
                 .return_types
                 .get(result_idx - fixed_results)
@@ -505,37 +647,152 @@ impl DataFlowGraph {
 
     /// Append an argument with type `ty` to `ebb`.
     pub fn append_ebb_arg(&mut self, ebb: Ebb, ty: Type) -> Value {
-        let num_args = self.num_ebb_args(ebb);
-        assert!(num_args <= u16::MAX as usize, "Too many arguments to EBB");
         let val = self.make_value(ValueData::Arg {
             ty: ty,
             ebb: ebb,
-            num: num_args as u16,
+            num: 0,
             next: None.into(),
         });
+        self.attach_ebb_arg(ebb, val);
+        val
+    }
+
+    /// Detach all the arguments from `ebb` and return them as an iterator.
+    ///
+    /// The detached values keep their types, and they can be reattached to an EBB with
+    /// `attach_ebb_arg()`. Collect the returned iterator before reattaching any of the values.
+    ///
+    /// Use this when the argument list of an EBB needs to be rewritten, for example when
+    /// legalizing the function arguments on the entry block.
+    pub fn detach_ebb_args(&mut self, ebb: Ebb) -> Values {
+        let first = self.ebbs[ebb].first_arg.take();
+        self.ebbs[ebb].last_arg = None.into();
+        Values {
+            dfg: self,
+            cur: first,
+        }
+    }
+
+    /// Append an existing argument value to `ebb`.
+    ///
+    /// The `arg` value must be an EBB argument that has been detached from its original EBB with
+    /// `detach_ebb_args()`, `remove_ebb_arg()`, or `replace_ebb_arg()`. It keeps its type.
+    pub fn attach_ebb_arg(&mut self, ebb: Ebb, arg: Value) {
+        let num_args = self.num_ebb_args(ebb);
+        assert!(num_args <= u16::MAX as usize, "Too many arguments to EBB");
+        match *self.arg_data_mut(arg) {
+            ValueData::Arg { ebb: ref mut e, ref mut num, ref mut next, .. } => {
+                *e = ebb;
+                *num = num_args as u16;
+                *next = None.into();
+            }
+            _ => unreachable!(),
+        }
         match self.ebbs[ebb].last_arg.expand() {
             // If last_argument is `None`, we're adding the first EBB argument.
-            None => {
-                self.ebbs[ebb].first_arg = val.into();
-            }
-            Some(last_arg) => {
-                match last_arg.expand() {
-                    // Append to linked list of arguments.
-                    ExpandedValue::Table(idx) => {
-                        if let ValueData::Arg { ref mut next, .. } = self.extended_values[idx] {
-                            *next = val.into();
-                        } else {
-                            panic!("inconsistent value table entry for EBB argument");
-                        }
-                    }
-                    ExpandedValue::Direct(_) => {
-                        panic!("inconsistent value table entry for EBB argument")
-                    }
+            None => self.ebbs[ebb].first_arg = arg.into(),
+            // Append to linked list of arguments.
+            Some(last_arg) => self.set_next_arg(last_arg, arg.into()),
+        }
+        self.ebbs[ebb].last_arg = arg.into();
+    }
+
+    /// Remove the EBB argument `arg` from its EBB.
+    ///
+    /// The following arguments are renumbered to fill the gap. The removed value is detached, so
+    /// any remaining uses of it should be rewritten, or it should be turned into an alias with
+    /// `change_to_alias()`.
+    pub fn remove_ebb_arg(&mut self, arg: Value) {
+        let (ebb, num, next) = self.arg_position(arg);
+        let prev = self.unlink_ebb_arg(ebb, num, arg, next);
+        if prev.is_none() {
+            self.ebbs[ebb].first_arg = next;
+        }
+        if self.ebbs[ebb].last_arg == arg.into() {
+            self.ebbs[ebb].last_arg = prev.into();
+        }
+
+        // Renumber the arguments following `arg`.
+        let mut cur = next.expand();
+        while let Some(v) = cur {
+            cur = match *self.arg_data_mut(v) {
+                ValueData::Arg { ref mut num, next, .. } => {
+                    *num -= 1;
+                    next.expand()
                 }
+                _ => unreachable!(),
+            };
+        }
+    }
+
+    /// Replace the EBB argument `old_arg` with a new argument of type `new_type`.
+    ///
+    /// The new argument takes the place of `old_arg` in the argument list and is returned. The
+    /// old value is detached, and it should typically be turned into an alias of a value computed
+    /// from the new argument. This is how the type of an EBB argument is changed.
+    pub fn replace_ebb_arg(&mut self, old_arg: Value, new_type: Type) -> Value {
+        let (ebb, num, next) = self.arg_position(old_arg);
+        let new_arg = self.make_value(ValueData::Arg {
+            ty: new_type,
+            ebb: ebb,
+            num: num,
+            next: next,
+        });
+        if self.unlink_ebb_arg(ebb, num, old_arg, new_arg.into()).is_none() {
+            self.ebbs[ebb].first_arg = new_arg.into();
+        }
+        if self.ebbs[ebb].last_arg == old_arg.into() {
+            self.ebbs[ebb].last_arg = new_arg.into();
+        }
+        new_arg
+    }
+
+    // Get the value table entry for the EBB argument `arg`.
+    fn arg_data_mut(&mut self, arg: Value) -> &mut ValueData {
+        if let ExpandedValue::Table(idx) = arg.expand() {
+            let data = &mut self.extended_values[idx];
+            if let ValueData::Arg { .. } = *data {
+                return data;
             }
         }
-        self.ebbs[ebb].last_arg = val.into();
-        val
+        panic!("{} is not an EBB argument", arg);
+    }
+
+    // Get the EBB, argument number, and next argument of the EBB argument `arg`.
+    fn arg_position(&mut self, arg: Value) -> (Ebb, u16, PackedOption<Value>) {
+        match *self.arg_data_mut(arg) {
+            ValueData::Arg { ebb, num, next, .. } => (ebb, num, next),
+            _ => unreachable!(),
+        }
+    }
+
+    // Set the `next` link of the EBB argument `arg`.
+    fn set_next_arg(&mut self, arg: Value, new_next: PackedOption<Value>) {
+        match *self.arg_data_mut(arg) {
+            ValueData::Arg { ref mut next, .. } => *next = new_next,
+            _ => unreachable!(),
+        }
+    }
+
+    // Unlink `arg` which is argument number `num` of `ebb`, and make its predecessor point to
+    // `new_next` instead. Return the predecessor argument, or `None` if `arg` was the first one.
+    //
+    // The caller is responsible for updating `first_arg` and `last_arg`.
+    fn unlink_ebb_arg(&mut self,
+                      ebb: Ebb,
+                      num: u16,
+                      arg: Value,
+                      new_next: PackedOption<Value>)
+                      -> Option<Value> {
+        self.set_next_arg(arg, None.into());
+        if num == 0 {
+            return None;
+        }
+        let prev = self.ebb_args(ebb)
+            .nth(num as usize - 1)
+            .expect("inconsistent EBB argument list");
+        self.set_next_arg(prev, new_next);
+        Some(prev)
     }
 
     /// Iterate through the arguments to an EBB.
@@ -573,6 +830,208 @@ impl EbbData {
     }
 }
 
+/// Serialization of the data flow graph with all its entity numbers.
+///
+/// This lives here rather than in the `serialize` module because it needs the internal value
+/// table.
+impl Serialize for DataFlowGraph {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.uint(self.signatures.len() as u64);
+        enc.uint(self.ext_funcs.len() as u64);
+        enc.uint(self.global_vars.len() as u64);
+        enc.uint(self.ebbs.len() as u64);
+        enc.uint(self.insts.len() as u64);
+        enc.uint(self.extended_values.len() as u64);
+
+        for sig in self.signatures.keys() {
+            self.signatures[sig].encode(enc);
+        }
+        for func in self.ext_funcs.keys() {
+            self.ext_funcs[func].encode(enc);
+        }
+        for gv in self.global_vars.keys() {
+            self.global_vars[gv].encode(enc);
+        }
+        for inst in self.insts.keys() {
+            serialize::encode_inst(enc, &self.insts[inst], &self.value_lists);
+        }
+        for ebb in self.ebbs.keys() {
+            enc.packed(self.ebbs[ebb].first_arg);
+            enc.packed(self.ebbs[ebb].last_arg);
+        }
+        for data in &self.extended_values {
+            match *data {
+                ValueData::Inst { ty, num, inst, next } => {
+                    enc.u8(0);
+                    ty.encode(enc);
+                    enc.uint(num as u64);
+                    enc.entity(inst);
+                    enc.packed(next);
+                }
+                ValueData::Arg { ty, num, ebb, next } => {
+                    enc.u8(1);
+                    ty.encode(enc);
+                    enc.uint(num as u64);
+                    enc.entity(ebb);
+                    enc.packed(next);
+                }
+                ValueData::Alias { ty, original } => {
+                    enc.u8(2);
+                    ty.encode(enc);
+                    enc.entity(original);
+                }
+            }
+        }
+    }
+
+    fn decode(dec: &mut Decoder) -> serialize::Result<DataFlowGraph> {
+        dec.counts.signatures = dec.len()?;
+        dec.counts.ext_funcs = dec.len()?;
+        dec.counts.global_vars = dec.len()?;
+        dec.counts.ebbs = dec.len()?;
+        dec.counts.insts = dec.len()?;
+        dec.counts.extended_values = dec.len()?;
+
+        let mut dfg = DataFlowGraph::new();
+        for _ in 0..dec.counts.signatures {
+            dfg.signatures.push(Signature::decode(dec)?);
+        }
+        for _ in 0..dec.counts.ext_funcs {
+            dfg.ext_funcs.push(ExtFuncData::decode(dec)?);
+        }
+        for _ in 0..dec.counts.global_vars {
+            dfg.global_vars.push(GlobalVarData::decode(dec)?);
+        }
+        for _ in 0..dec.counts.insts {
+            let data = serialize::decode_inst(dec, &mut dfg.value_lists)?;
+            dfg.insts.push(data);
+        }
+        for _ in 0..dec.counts.ebbs {
+            dfg.ebbs.push(EbbData {
+                              first_arg: dec.packed_value()?,
+                              last_arg: dec.packed_value()?,
+                          });
+        }
+        for _ in 0..dec.counts.extended_values {
+            let data = match dec.u8()? {
+                0 => {
+                    ValueData::Inst {
+                        ty: Type::decode(dec)?,
+                        num: dec.u16()?,
+                        inst: dec.inst()?,
+                        next: dec.packed_value()?,
+                    }
+                }
+                1 => {
+                    ValueData::Arg {
+                        ty: Type::decode(dec)?,
+                        num: dec.u16()?,
+                        ebb: dec.ebb()?,
+                        next: dec.packed_value()?,
+                    }
+                }
+                2 => {
+                    ValueData::Alias {
+                        ty: Type::decode(dec)?,
+                        original: dec.value()?,
+                    }
+                }
+                _ => return Err(DecodeError::Invalid("bad value kind")),
+            };
+            dfg.extended_values.push(data);
+        }
+
+        dfg.check_value_links()?;
+        Ok(dfg)
+    }
+}
+
+impl DataFlowGraph {
+    // Check that the lists of instruction results and EBB arguments are well formed, and that
+    // there are no alias loops, so a decoded data flow graph can't send the value iterators into
+    // an infinite loop.
+    fn check_value_links(&self) -> serialize::Result<()> {
+        use ir::entities::ExpandedValue::Table;
+        let invalid = Err(DecodeError::Invalid("inconsistent value links"));
+        let max_len = self.extended_values.len();
+
+        for ebb in self.ebbs.keys() {
+            let mut cur = self.ebbs[ebb].first_arg.expand();
+            let mut last = None;
+            for num in 0..max_len + 1 {
+                let v = match cur {
+                    Some(v) => v,
+                    None => break,
+                };
+                cur = match v.expand() {
+                    Table(idx) => {
+                        match self.extended_values[idx] {
+                            ValueData::Arg { ebb: e, num: n, next, .. } if e == ebb &&
+                                                                        n as usize == num => {
+                                next.expand()
+                            }
+                            _ => return invalid,
+                        }
+                    }
+                    _ => return invalid,
+                };
+                last = Some(v);
+            }
+            if cur.is_some() || last != self.ebbs[ebb].last_arg.expand() {
+                return invalid;
+            }
+        }
+
+        for inst in self.insts.keys() {
+            let mut cur = self.insts[inst].second_result();
+            for num in 1..max_len + 2 {
+                let v = match cur {
+                    Some(v) => v,
+                    None => break,
+                };
+                cur = match v.expand() {
+                    Table(idx) => {
+                        match self.extended_values[idx] {
+                            ValueData::Inst { inst: i, num: n, next, .. } if i == inst &&
+                                                                         n as usize == num => {
+                                next.expand()
+                            }
+                            _ => return invalid,
+                        }
+                    }
+                    _ => return invalid,
+                };
+            }
+            if cur.is_some() {
+                return invalid;
+            }
+        }
+
+        for data in &self.extended_values {
+            if let ValueData::Alias { original, .. } = *data {
+                let mut v = original;
+                for _ in 0..max_len + 1 {
+                    v = match v.expand() {
+                        Table(idx) => {
+                            match self.extended_values[idx] {
+                                ValueData::Alias { original, .. } => original,
+                                _ => break,
+                            }
+                        }
+                        _ => break,
+                    };
+                }
+                if let Table(idx) = v.expand() {
+                    if let ValueData::Alias { .. } = self.extended_values[idx] {
+                        return Err(DecodeError::Invalid("value alias loop"));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -655,6 +1114,49 @@ mod tests {
         assert_eq!(dfg.value_type(arg2), types::I16);
     }
 
+    #[test]
+    fn edit_ebb_args() {
+        let mut dfg = DataFlowGraph::new();
+        let ebb = dfg.make_ebb();
+        let arg1 = dfg.append_ebb_arg(ebb, types::I32);
+        let arg2 = dfg.append_ebb_arg(ebb, types::I64);
+        let arg3 = dfg.append_ebb_arg(ebb, types::F32);
+
+        // Retype the middle argument.
+        let arg4 = dfg.replace_ebb_arg(arg2, types::I8);
+        assert_eq!(dfg.ebb_args(ebb).collect::<Vec<_>>(), [arg1, arg4, arg3]);
+        assert_eq!(dfg.value_def(arg4), ValueDef::Arg(ebb, 1));
+        assert_eq!(dfg.value_type(arg4), types::I8);
+
+        // Replace the last argument.
+        let arg5 = dfg.replace_ebb_arg(arg3, types::F64);
+        assert_eq!(dfg.ebb_args(ebb).collect::<Vec<_>>(), [arg1, arg4, arg5]);
+        let arg6 = dfg.append_ebb_arg(ebb, types::I16);
+        assert_eq!(dfg.value_def(arg6), ValueDef::Arg(ebb, 3));
+
+        // Remove the first argument and renumber the rest.
+        dfg.remove_ebb_arg(arg1);
+        assert_eq!(dfg.ebb_args(ebb).collect::<Vec<_>>(), [arg4, arg5, arg6]);
+        assert_eq!(dfg.num_ebb_args(ebb), 3);
+        assert_eq!(dfg.value_def(arg5), ValueDef::Arg(ebb, 1));
+        assert_eq!(dfg.value_def(arg6), ValueDef::Arg(ebb, 2));
+
+        // Remove the last argument.
+        dfg.remove_ebb_arg(arg6);
+        assert_eq!(dfg.ebb_args(ebb).collect::<Vec<_>>(), [arg4, arg5]);
+        assert_eq!(dfg.num_ebb_args(ebb), 2);
+
+        // Detach all arguments and reattach them in reverse order.
+        let args = dfg.detach_ebb_args(ebb).collect::<Vec<_>>();
+        assert_eq!(args, [arg4, arg5]);
+        assert_eq!(dfg.num_ebb_args(ebb), 0);
+        dfg.attach_ebb_arg(ebb, arg5);
+        dfg.attach_ebb_arg(ebb, arg4);
+        assert_eq!(dfg.ebb_args(ebb).collect::<Vec<_>>(), [arg5, arg4]);
+        assert_eq!(dfg.value_def(arg5), ValueDef::Arg(ebb, 0));
+        assert_eq!(dfg.value_def(arg4), ValueDef::Arg(ebb, 1));
+    }
+
     #[test]
     fn aliases() {
         use ir::InstBuilder;
@@ -194,6 +194,16 @@ entity_impl!(FuncRef, "fn");
 pub struct SigRef(u32);
 entity_impl!(SigRef, "sig");
 
+/// A reference to a heap.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Heap(u32);
+entity_impl!(Heap, "heap");
+
+/// A reference to a global variable.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct GlobalVar(u32);
+entity_impl!(GlobalVar, "gv");
+
 /// A reference to any of the entities defined in this module.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum AnyEntity {
@@ -213,6 +223,10 @@ pub enum AnyEntity {
     FuncRef(FuncRef),
     /// A function call signature.
     SigRef(SigRef),
+    /// A heap.
+    Heap(Heap),
+    /// A global variable.
+    GlobalVar(GlobalVar),
 }
 
 impl Display for AnyEntity {
@@ -226,6 +240,8 @@ impl Display for AnyEntity {
             AnyEntity::JumpTable(r) => r.fmt(fmt),
             AnyEntity::FuncRef(r) => r.fmt(fmt),
             AnyEntity::SigRef(r) => r.fmt(fmt),
+            AnyEntity::Heap(r) => r.fmt(fmt),
+            AnyEntity::GlobalVar(r) => r.fmt(fmt),
         }
     }
 }
@@ -272,6 +288,18 @@ impl From<SigRef> for AnyEntity {
     }
 }
 
+impl From<Heap> for AnyEntity {
+    fn from(r: Heap) -> AnyEntity {
+        AnyEntity::Heap(r)
+    }
+}
+
+impl From<GlobalVar> for AnyEntity {
+    fn from(r: GlobalVar) -> AnyEntity {
+        AnyEntity::GlobalVar(r)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,10 +5,12 @@
 //!
 //! This module declares the data types used to represent external functions and call signatures.
 
-use ir::{Type, FunctionName, SigRef, ArgumentLoc};
+use ir::{Type, ExternalName, SigRef, ArgumentLoc};
 use isa::RegInfo;
 use std::cmp;
 use std::fmt;
+use std::str::FromStr;
+use std::vec::Vec;
 
 /// Function signature.
 ///
@@ -17,7 +19,7 @@ use std::fmt;
 ///
 /// A signature can optionally include ISA-specific ABI information which specifies exactly how
 /// arguments and return values are passed.
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Signature {
     /// Types of the arguments passed to the function.
     pub argument_types: Vec<ArgumentType>,
@@ -30,6 +32,9 @@ pub struct Signature {
     /// This can be computed from the legalized `argument_types` array as the maximum (offset plus
     /// byte size) of the `ArgumentLoc::Stack(offset)` argument.
     pub argument_bytes: Option<u32>,
+
+    /// Calling convention used to pass arguments and return values.
+    pub call_conv: CallConv,
 }
 
 impl Signature {
@@ -39,14 +44,28 @@ impl Signature {
             argument_types: Vec::new(),
             return_types: Vec::new(),
             argument_bytes: None,
+            call_conv: CallConv::SystemV,
         }
     }
 
+    /// Clear the signature so it is identical to a fresh one returned by `new()`.
+    pub fn clear(&mut self) {
+        self.argument_types.clear();
+        self.return_types.clear();
+        self.argument_bytes = None;
+        self.call_conv = CallConv::SystemV;
+    }
+
     /// Compute the size of the stack arguments and mark signature as legalized.
     ///
+    /// The size is rounded up to a multiple of `stack_align` which must be a power of two, so the
+    /// stack pointer stays aligned after the arguments have been pushed.
+    ///
     /// Even if there are no stack arguments, this will set `argument_types` to `Some(0)` instead
     /// of `None`. This indicates that the signature has been legalized.
-    pub fn compute_argument_bytes(&mut self) {
+    pub fn compute_argument_bytes(&mut self, stack_align: u32) {
+        assert!(stack_align.is_power_of_two(),
+                "Stack alignment must be a power of two");
         let bytes = self.argument_types
             .iter()
             .filter_map(|arg| match arg.location {
@@ -54,7 +73,34 @@ impl Signature {
                 _ => None,
             })
             .fold(0, cmp::max);
-        self.argument_bytes = Some(bytes);
+        self.argument_bytes = Some((bytes + stack_align - 1) & !(stack_align - 1));
+    }
+
+    /// Find the index of the argument with the special `purpose`, if any.
+    ///
+    /// Only the first argument with the given purpose is returned.
+    pub fn special_arg_index(&self, purpose: ArgumentPurpose) -> Option<usize> {
+        self.argument_types.iter().position(|arg| arg.purpose == purpose)
+    }
+
+    /// Can a function with this signature make a tail call to a function with the `callee`
+    /// signature?
+    ///
+    /// Both signatures must have been legalized. The callee must use the same calling convention,
+    /// and it must return its values in the same locations, so they are passed straight through to
+    /// our caller. The callee can't take any arguments on the stack since the stack frame of the
+    /// calling function is released before the tail call.
+    pub fn can_tail_call(&self, callee: &Signature) -> bool {
+        self.argument_bytes.is_some() && callee.argument_bytes == Some(0) &&
+        self.call_conv == callee.call_conv &&
+        self.return_types.len() == callee.return_types.len() &&
+        self.return_types
+            .iter()
+            .zip(&callee.return_types)
+            .all(|(a, b)| {
+                     a.value_type == b.value_type && a.purpose == b.purpose &&
+                     a.location == b.location
+                 })
     }
 
     /// Return an object that can display `self` with correct register names.
@@ -91,6 +137,9 @@ impl<'a> fmt::Display for DisplaySignature<'a> {
             write!(f, " -> ")?;
             write_list(f, &self.0.return_types, self.1)?;
         }
+        if self.0.call_conv != CallConv::SystemV {
+            write!(f, " {}", self.0.call_conv)?;
+        }
         Ok(())
     }
 }
@@ -105,7 +154,7 @@ impl fmt::Display for Signature {
 ///
 /// This describes the value type being passed to or from a function along with flags that affect
 /// how the argument is passed.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct ArgumentType {
     /// Type of the argument value.
     pub value_type: Type,
@@ -113,6 +162,8 @@ pub struct ArgumentType {
     pub extension: ArgumentExtension,
     /// Place this argument in a register if possible.
     pub inreg: bool,
+    /// Special purpose of the argument, or `Normal`.
+    pub purpose: ArgumentPurpose,
 
     /// ABI-specific location of this argument, or `Unassigned` for arguments that have not yet
     /// been legalized.
@@ -126,10 +177,16 @@ impl ArgumentType {
             value_type: vt,
             extension: ArgumentExtension::None,
             inreg: false,
+            purpose: ArgumentPurpose::Normal,
             location: Default::default(),
         }
     }
 
+    /// Create a special-purpose argument type that is not subject to extension.
+    pub fn special(vt: Type, purpose: ArgumentPurpose) -> ArgumentType {
+        ArgumentType { purpose: purpose, ..ArgumentType::new(vt) }
+    }
+
     /// Return an object that can display `self` with correct register names.
     pub fn display<'a, R: Into<Option<&'a RegInfo>>>(&'a self, regs: R) -> DisplayArgumentType<'a> {
         DisplayArgumentType(self, regs.into())
@@ -150,6 +207,9 @@ impl<'a> fmt::Display for DisplayArgumentType<'a> {
         if self.0.inreg {
             write!(f, " inreg")?;
         }
+        if self.0.purpose != ArgumentPurpose::Normal {
+            write!(f, " {}", self.0.purpose)?;
+        }
 
         if self.0.location.is_assigned() {
             write!(f, " [{}]", self.0.location.display(self.1))?;
@@ -179,20 +239,149 @@ pub enum ArgumentExtension {
     Sext,
 }
 
+/// The special purpose of a function argument.
+///
+/// Function arguments and return values are used to pass user program values between functions,
+/// but they are also used to represent special registers with significance to the ABI. The
+/// argument purpose tells the register allocator and the prologue generation where these special
+/// values live.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ArgumentPurpose {
+    /// A normal user program value passed to or from a function.
+    Normal,
+
+    /// Struct return pointer.
+    ///
+    /// When a function needs to return more data than will fit in registers, the caller passes a
+    /// pointer to a memory location where the return value can be written.
+    StructReturn,
+
+    /// The link register.
+    ///
+    /// The return address, for ISAs that pass it in a register instead of on the stack.
+    Link,
+
+    /// VM context pointer.
+    ///
+    /// This is a pointer to a context struct containing details about the current sandbox. It is
+    /// used as a base pointer for global variables and the heap.
+    VMContext,
+
+    /// A callee-saved register.
+    ///
+    /// The incoming value of a callee-saved register that is used by the function. It is saved by
+    /// the prologue and restored by the epilogue, which returns it to the caller unchanged.
+    CalleeSaved,
+}
+
+static PURPOSE_NAMES: [&'static str; 5] = ["normal", "sret", "link", "vmctx", "csr"];
+
+impl fmt::Display for ArgumentPurpose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(PURPOSE_NAMES[*self as usize])
+    }
+}
+
+impl FromStr for ArgumentPurpose {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ArgumentPurpose, ()> {
+        match s {
+            "normal" => Ok(ArgumentPurpose::Normal),
+            "sret" => Ok(ArgumentPurpose::StructReturn),
+            "link" => Ok(ArgumentPurpose::Link),
+            "vmctx" => Ok(ArgumentPurpose::VMContext),
+            "csr" => Ok(ArgumentPurpose::CalleeSaved),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Calling convention identifiers.
+///
+/// The calling convention of a signature determines the argument and return value locations, the
+/// registers preserved across calls, and the stack layout at the call site. ISAs with a single
+/// calling convention ignore it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CallConv {
+    /// The System V ABI used on Unix-like systems. This is the default.
+    SystemV,
+
+    /// The Windows x64 calling convention, or `__fastcall` in 32-bit mode.
+    WindowsFastcall,
+
+    /// The convention used by SpiderMonkey's WebAssembly compiler, Baldrdash, for calls between
+    /// compiled functions. It is the System V convention with the VM context pointer pinned to a
+    /// fixed register, and the function is entered through a prologue generated by the embedder.
+    Baldrdash,
+}
+
+static CALL_CONV_NAMES: [&'static str; 3] = ["system_v", "windows_fastcall", "baldrdash"];
+
+impl fmt::Display for CallConv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(CALL_CONV_NAMES[*self as usize])
+    }
+}
+
+impl FromStr for CallConv {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<CallConv, ()> {
+        match s {
+            "system_v" => Ok(CallConv::SystemV),
+            "windows_fastcall" => Ok(CallConv::WindowsFastcall),
+            "baldrdash" => Ok(CallConv::Baldrdash),
+            _ => Err(()),
+        }
+    }
+}
+
 /// An external function.
 ///
 /// Information about a function that can be called directly with a direct `call` instruction.
 #[derive(Clone, Debug)]
 pub struct ExtFuncData {
     /// Name of the external function.
-    pub name: FunctionName,
+    pub name: ExternalName,
     /// Call signature of function.
     pub signature: SigRef,
+    /// Calls to this function are rarely executed, so they should be kept out of the hot path.
+    pub cold: bool,
+    /// This function never returns to its caller.
+    pub noreturn: bool,
+    /// The function is defined in the same object as the caller, close enough to be referenced
+    /// relative to the program counter instead of with an absolute address or through the PLT or
+    /// the GOT.
+    pub colocated: bool,
+}
+
+impl ExtFuncData {
+    /// Create an external function reference with default attributes.
+    pub fn new(name: ExternalName, signature: SigRef) -> ExtFuncData {
+        ExtFuncData {
+            name: name,
+            signature: signature,
+            cold: false,
+            noreturn: false,
+            colocated: false,
+        }
+    }
 }
 
 impl fmt::Display for ExtFuncData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} {}", self.signature, self.name)
+        write!(f, "{} {}", self.signature, self.name)?;
+        if self.cold {
+            write!(f, " cold")?;
+        }
+        if self.noreturn {
+            write!(f, " noreturn")?;
+        }
+        if self.colocated {
+            write!(f, " colocated")?;
+        }
+        Ok(())
     }
 }
 
@@ -209,6 +398,30 @@ mod tests {
         assert_eq!(t.to_string(), "i32 uext");
         t.inreg = true;
         assert_eq!(t.to_string(), "i32 uext inreg");
+        t.purpose = ArgumentPurpose::VMContext;
+        assert_eq!(t.to_string(), "i32 uext inreg vmctx");
+    }
+
+    #[test]
+    fn argument_purpose() {
+        let all_purpose = [ArgumentPurpose::Normal,
+                           ArgumentPurpose::StructReturn,
+                           ArgumentPurpose::Link,
+                           ArgumentPurpose::VMContext,
+                           ArgumentPurpose::CalleeSaved];
+        for (&e, &n) in all_purpose.iter().zip(PURPOSE_NAMES.iter()) {
+            assert_eq!(e.to_string(), n);
+            assert_eq!(Ok(e), n.parse());
+        }
+    }
+
+    #[test]
+    fn call_conv() {
+        let all_call_conv = [CallConv::SystemV, CallConv::WindowsFastcall, CallConv::Baldrdash];
+        for (&e, &n) in all_call_conv.iter().zip(CALL_CONV_NAMES.iter()) {
+            assert_eq!(e.to_string(), n);
+            assert_eq!(Ok(e), n.parse());
+        }
     }
 
     #[test]
@@ -227,15 +440,58 @@ mod tests {
         // Test the offset computation algorithm.
         assert_eq!(sig.argument_bytes, None);
         sig.argument_types[1].location = ArgumentLoc::Stack(8);
-        sig.compute_argument_bytes();
+        sig.compute_argument_bytes(4);
         // An `i32x4` at offset 8 requires a 24-byte argument array.
         assert_eq!(sig.argument_bytes, Some(24));
         // Order does not matter.
         sig.argument_types[0].location = ArgumentLoc::Stack(24);
-        sig.compute_argument_bytes();
+        sig.compute_argument_bytes(4);
         assert_eq!(sig.argument_bytes, Some(28));
+        // The argument array is padded to the stack alignment.
+        sig.compute_argument_bytes(16);
+        assert_eq!(sig.argument_bytes, Some(32));
 
         // Writing ABI-annotated signatures.
         assert_eq!(sig.to_string(), "(i32 [24], i32x4 [8]) -> f32, b8");
+
+        // Special purpose arguments.
+        assert_eq!(sig.special_arg_index(ArgumentPurpose::VMContext), None);
+        sig.argument_types.push(ArgumentType::special(I32, ArgumentPurpose::VMContext));
+        assert_eq!(sig.special_arg_index(ArgumentPurpose::VMContext), Some(2));
+        assert_eq!(sig.to_string(), "(i32 [24], i32x4 [8], i32 vmctx) -> f32, b8");
+
+        // The default calling convention is not printed.
+        sig.call_conv = CallConv::WindowsFastcall;
+        assert_eq!(sig.to_string(),
+                   "(i32 [24], i32x4 [8], i32 vmctx) -> f32, b8 windows_fastcall");
+    }
+
+    #[test]
+    fn tail_calls() {
+        let mut caller = Signature::new();
+        caller.return_types.push(ArgumentType::new(I32));
+        let mut callee = caller.clone();
+
+        // Both signatures must be legalized.
+        assert!(!caller.can_tail_call(&callee));
+        caller.compute_argument_bytes(16);
+        callee.compute_argument_bytes(16);
+        assert!(caller.can_tail_call(&callee));
+
+        // No stack arguments in the callee.
+        callee.argument_types.push(ArgumentType::new(I32));
+        callee.argument_types[0].location = ArgumentLoc::Stack(0);
+        callee.compute_argument_bytes(16);
+        assert!(!caller.can_tail_call(&callee));
+        callee.argument_types.clear();
+        callee.compute_argument_bytes(16);
+
+        // Return values must match.
+        callee.return_types[0].location = ArgumentLoc::Stack(0);
+        assert!(!caller.can_tail_call(&callee));
+        callee.return_types[0].location = caller.return_types[0].location;
+
+        callee.call_conv = CallConv::WindowsFastcall;
+        assert!(!caller.can_tail_call(&callee));
     }
 }
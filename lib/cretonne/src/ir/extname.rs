@@ -0,0 +1,173 @@
+//! External names.
+//!
+//! These are identifiers for declaring entities defined outside the current function. The name of
+//! an external declaration doesn't have any meaning to Cretonne, which compiles functions
+//! independently. The names are passed on to the embedder in relocations.
+//!
+//! The `User` namespaces from `FIRST_RESERVED_NAMESPACE` up are reserved for the names that
+//! Cretonne creates itself, like the runtime library functions called by legalized code.
+
+use ir::LibCall;
+use std::fmt::{self, Write};
+use std::string::String;
+
+/// The first `ExternalName::User` namespace reserved for Cretonne. Embedders should only use the
+/// namespaces below it for their own symbol tables.
+pub const FIRST_RESERVED_NAMESPACE: u32 = 0xffff_0000;
+
+/// The `ExternalName::User` namespace of the runtime library functions, indexed by
+/// `LibCall::index()`.
+pub const LIBCALL_NAMESPACE: u32 = FIRST_RESERVED_NAMESPACE;
+
+/// The name of an external entity like a function, a global variable, or a heap.
+///
+/// Embedders usually refer to their entities by index into a table of their own, so the `User`
+/// form is just two numbers that Cretonne doesn't interpret. The `TestCase` form is any UTF-8
+/// string. It is mostly a testing and debugging tool: `.cton` files use these names to identify
+/// functions.
+///
+/// Names in the reserved `User` namespaces are displayed as `%` followed by their symbol, like
+/// `%memcpy` for `ExternalName::libcall(LibCall::Memcpy)`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExternalName {
+    /// A name in a user-defined symbol table.
+    User {
+        /// Arbitrary namespace, like the kind of entity the index refers to.
+        namespace: u32,
+        /// Index into the namespace.
+        index: u32,
+    },
+
+    /// A name given as a string.
+    TestCase(String),
+}
+
+impl ExternalName {
+    /// Create a new external name in a user-defined symbol table.
+    pub fn user(namespace: u32, index: u32) -> ExternalName {
+        ExternalName::User {
+            namespace: namespace,
+            index: index,
+        }
+    }
+
+    /// Create a new external name from the string `s`.
+    pub fn testcase<S: Into<String>>(s: S) -> ExternalName {
+        ExternalName::TestCase(s.into())
+    }
+
+    /// Create the external name of the runtime library function `libcall`.
+    pub fn libcall(libcall: LibCall) -> ExternalName {
+        ExternalName::user(LIBCALL_NAMESPACE, libcall.index())
+    }
+
+    /// Get the runtime library function named by this external name, if it is one.
+    pub fn as_libcall(&self) -> Option<LibCall> {
+        match *self {
+            ExternalName::User { namespace: LIBCALL_NAMESPACE, index } => {
+                LibCall::from_index(index)
+            }
+            _ => None,
+        }
+    }
+
+    /// Is this a name in one of the namespaces reserved for Cretonne?
+    pub fn is_reserved(&self) -> bool {
+        match *self {
+            ExternalName::User { namespace, .. } => namespace >= FIRST_RESERVED_NAMESPACE,
+            ExternalName::TestCase(_) => false,
+        }
+    }
+}
+
+impl Default for ExternalName {
+    fn default() -> ExternalName {
+        ExternalName::testcase("")
+    }
+}
+
+fn is_id_start(c: char) -> bool {
+    c.is_ascii() && (c == '_' || c.is_alphabetic())
+}
+
+fn is_id_continue(c: char) -> bool {
+    c.is_ascii() && (c == '_' || c.is_alphanumeric())
+}
+
+// The name may need quotes if it doesn't parse as an identifier.
+fn needs_quotes(name: &str) -> bool {
+    let mut iter = name.chars();
+    if let Some(ch) = iter.next() {
+        !is_id_start(ch) || !iter.all(is_id_continue)
+    } else {
+        // A blank name needs quotes.
+        true
+    }
+}
+
+impl fmt::Display for ExternalName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(libcall) = self.as_libcall() {
+            return write!(f, "%{}", libcall);
+        }
+        match *self {
+            ExternalName::User { namespace, index } => write!(f, "u{}:{}", namespace, index),
+            ExternalName::TestCase(ref name) if needs_quotes(name) => {
+                f.write_char('"')?;
+                for c in name.chars().flat_map(char::escape_default) {
+                    f.write_char(c)?;
+                }
+                f.write_char('"')
+            }
+            ExternalName::TestCase(ref name) => f.write_str(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{needs_quotes, ExternalName, FIRST_RESERVED_NAMESPACE};
+    use ir::LibCall;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(needs_quotes(""), true);
+        assert_eq!(needs_quotes("x"), false);
+        assert_eq!(needs_quotes(" "), true);
+        assert_eq!(needs_quotes("0"), true);
+        assert_eq!(needs_quotes("x0"), false);
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(ExternalName::testcase("").to_string(), "\"\"");
+        assert_eq!(ExternalName::testcase("x").to_string(), "x");
+        assert_eq!(ExternalName::testcase(" ").to_string(), "\" \"");
+        assert_eq!(ExternalName::testcase(" \n").to_string(), "\" \\n\"");
+        assert_eq!(ExternalName::testcase("a\u{1000}v").to_string(),
+                   "\"a\\u{1000}v\"");
+    }
+
+    #[test]
+    fn user() {
+        assert_eq!(ExternalName::user(0, 0).to_string(), "u0:0");
+        assert_eq!(ExternalName::user(1, 4294967295).to_string(), "u1:4294967295");
+        assert!(ExternalName::user(1, 2) != ExternalName::user(2, 1));
+        assert!(!ExternalName::user(1, 2).is_reserved());
+    }
+
+    #[test]
+    fn libcall() {
+        let name = ExternalName::libcall(LibCall::UdivI64);
+        assert_eq!(name.to_string(), "%__udivdi3");
+        assert_eq!(name.as_libcall(), Some(LibCall::UdivI64));
+        assert!(name.is_reserved());
+        assert_eq!(ExternalName::testcase("__udivdi3").as_libcall(), None);
+
+        // Unknown indexes in the reserved namespaces are displayed as numbers.
+        let name = ExternalName::user(FIRST_RESERVED_NAMESPACE, 1000);
+        assert_eq!(name.as_libcall(), None);
+        assert!(name.is_reserved());
+        assert_eq!(name.to_string(), "u4294901760:1000");
+    }
+}
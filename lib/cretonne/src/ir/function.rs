@@ -4,10 +4,16 @@
 //! instructions.
 
 use std::fmt::{self, Display, Debug, Formatter};
-use ir::{FunctionName, Signature, Value, Inst, StackSlot, StackSlotData, JumpTable, JumpTableData,
-         ValueLoc, DataFlowGraph, Layout};
-use isa::Encoding;
+use binemit::CodeOffset;
+use ir::{ExternalName, Signature, ArgumentType, Type, Value, Inst, Ebb, StackSlot, StackSlotData,
+         JumpTable, JumpTableData, Heap, HeapData, GlobalVar, ValueLoc, DataFlowGraph, Layout,
+         SourceLoc, ValueLabel};
+use isa::{Encoding, TargetIsa};
 use entity_map::{EntityMap, PrimaryEntityData};
+use mem_usage::MemUsage;
+use stable_hash::StableHasher;
+use structural::canonical_form;
+use std::hash::Hasher;
 use write::write_function;
 
 /// A function.
@@ -17,7 +23,7 @@ use write::write_function;
 #[derive(Clone)]
 pub struct Function {
     /// Name of this function. Mostly used by `.cton` files.
-    pub name: FunctionName,
+    pub name: ExternalName,
 
     /// Signature of this function.
     pub signature: Signature,
@@ -28,6 +34,16 @@ pub struct Function {
     /// Jump tables used in this function.
     pub jump_tables: EntityMap<JumpTable, JumpTableData>,
 
+    /// Heaps accessed by this function.
+    pub heaps: EntityMap<Heap, HeapData>,
+
+    /// Global variable holding the stack limit, if the prologue should check for stack overflow.
+    ///
+    /// The embedder stores the lowest address the stack pointer may reach at the address of the
+    /// global variable. The prologue traps with `stk_ovf` when allocating the stack frame would
+    /// move the stack pointer below it.
+    pub stack_limit: Option<GlobalVar>,
+
     /// Data flow graph containing the primary definition of all instructions, EBBs and values.
     pub dfg: DataFlowGraph,
 
@@ -40,29 +56,203 @@ pub struct Function {
 
     /// Location assigned to every value.
     pub locations: EntityMap<Value, ValueLoc>,
+
+    /// Profile weights of the control flow edges, indexed by the branch instruction.
+    /// Branches without an entry have an unknown weight.
+    pub edge_weights: EntityMap<Inst, Option<u32>>,
+
+    /// Source locations of the instructions.
+    /// Instructions without an entry have the default source location.
+    pub srclocs: EntityMap<Inst, SourceLoc>,
+
+    /// Labels of the values holding source-level variables, for the debug info.
+    /// Values without an entry aren't labeled.
+    pub value_labels: EntityMap<Value, Option<ValueLabel>>,
+
+    /// Code offsets of the EBB headers.
+    ///
+    /// This information is only transiently available after the `binemit::relax_branches` function
+    /// computes it, and it can easily go stale.
+    pub offsets: EntityMap<Ebb, CodeOffset>,
+
+    /// Byte offsets of the stack slots from the stack pointer in the function body.
+    ///
+    /// This is computed by `binemit::relax_branches` along with the EBB offsets. Binary emission
+    /// uses it to address the spill slots.
+    pub stack_offsets: EntityMap<StackSlot, u32>,
+
+    /// Code offsets of the jump tables, which are emitted after the code of the function.
+    ///
+    /// This is computed by `binemit::relax_branches` along with the EBB offsets.
+    pub jt_offsets: EntityMap<JumpTable, CodeOffset>,
 }
 
 impl PrimaryEntityData for StackSlotData {}
 impl PrimaryEntityData for JumpTableData {}
+impl PrimaryEntityData for HeapData {}
 
 impl Function {
     /// Create a function with the given name and signature.
-    pub fn with_name_signature(name: FunctionName, sig: Signature) -> Function {
+    pub fn with_name_signature(name: ExternalName, sig: Signature) -> Function {
         Function {
             name: name,
             signature: sig,
             stack_slots: EntityMap::new(),
             jump_tables: EntityMap::new(),
+            heaps: EntityMap::new(),
+            stack_limit: None,
             dfg: DataFlowGraph::new(),
             layout: Layout::new(),
             encodings: EntityMap::new(),
             locations: EntityMap::new(),
+            edge_weights: EntityMap::new(),
+            srclocs: EntityMap::new(),
+            value_labels: EntityMap::new(),
+            offsets: EntityMap::new(),
+            stack_offsets: EntityMap::new(),
+            jt_offsets: EntityMap::new(),
         }
     }
 
     /// Create a new empty, anonymous function.
     pub fn new() -> Function {
-        Self::with_name_signature(FunctionName::default(), Signature::new())
+        Self::with_name_signature(ExternalName::default(), Signature::new())
+    }
+
+    /// Clear all data structures in this function, making it identical to `Function::new()`.
+    ///
+    /// The memory allocated for the function is kept, so building the next function in the same
+    /// `Function` instance doesn't need to allocate as long as it fits.
+    pub fn clear(&mut self) {
+        self.name = ExternalName::default();
+        self.signature.clear();
+        self.stack_slots.clear();
+        self.jump_tables.clear();
+        self.heaps.clear();
+        self.stack_limit = None;
+        self.dfg.clear();
+        self.layout.clear();
+        self.encodings.clear();
+        self.locations.clear();
+        self.edge_weights.clear();
+        self.srclocs.clear();
+        self.value_labels.clear();
+        self.offsets.clear();
+        self.stack_offsets.clear();
+        self.jt_offsets.clear();
+    }
+
+    /// Get the memory used by the function.
+    ///
+    /// This includes the data flow graph, the layout, and all the tables indexed by entities.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.stack_slots.mem_usage() + self.jump_tables.mem_usage() + self.heaps.mem_usage() +
+        self.dfg.mem_usage() + self.layout.mem_usage() + self.encodings.mem_usage() +
+        self.locations.mem_usage() + self.edge_weights.mem_usage() +
+        self.srclocs.mem_usage() + self.value_labels.mem_usage() +
+        self.offsets.mem_usage() + self.stack_offsets.mem_usage() + self.jt_offsets.mem_usage()
+    }
+
+    /// Compute a hash of this function that is the same on all hosts and in all runs.
+    ///
+    /// The hash covers the function as written in the textual IL, so two functions get the same
+    /// hash when they print the same. With an `isa`, the instruction encodings and the value
+    /// locations are part of the text too. The edge weights and the value labels aren't printed,
+    /// so they don't affect the hash.
+    pub fn stable_hash(&self, isa: Option<&TargetIsa>) -> u64 {
+        let mut hasher = StableHasher::new();
+        // Writing to a `StableHasher` can't fail.
+        let _ = write_function(&mut hasher, self, isa);
+        hasher.finish()
+    }
+
+    /// Compute a hash of this function that doesn't depend on its entity numbering.
+    ///
+    /// Functions that are `cretonne::equivalent()` get the same hash, even when their entities
+    /// were created in different orders. Like `stable_hash()`, the hash is the same on all hosts
+    /// and in all runs. The name of the function and its source locations don't affect the hash.
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        hasher.write(&canonical_form(self));
+        hasher.finish()
+    }
+
+    /// Get the weight of the edge taken by the branch instruction `inst`, if it is known.
+    pub fn edge_weight(&self, inst: Inst) -> Option<u32> {
+        self.edge_weights.get(inst).cloned().unwrap_or(None)
+    }
+
+    /// Set the weight of the edge taken by the branch instruction `inst`.
+    ///
+    /// Weights are relative: An edge with twice the weight of another edge is expected to be taken
+    /// twice as often. A zero weight means the edge is never expected to be taken.
+    pub fn set_edge_weight(&mut self, inst: Inst, weight: u32) {
+        *self.edge_weights.ensure(inst) = Some(weight);
+    }
+
+    /// Get the source location of `inst`.
+    pub fn srcloc(&self, inst: Inst) -> SourceLoc {
+        self.srclocs.get(inst).cloned().unwrap_or_default()
+    }
+
+    /// Set the source location of `inst`.
+    pub fn set_srcloc(&mut self, inst: Inst, srcloc: SourceLoc) {
+        *self.srclocs.ensure(inst) = srcloc;
+    }
+
+    /// Get the label of `value`, if it has one.
+    pub fn value_label(&self, value: Value) -> Option<ValueLabel> {
+        self.value_labels.get(value).cloned().unwrap_or(None)
+    }
+
+    /// Label `value` as holding the source-level variable `label`.
+    pub fn set_value_label(&mut self, value: Value, label: ValueLabel) {
+        *self.value_labels.ensure(value) = Some(label);
+    }
+
+    /// Append the parameter `param` to the function signature.
+    ///
+    /// If the function has an entry block, a matching argument is appended to it and returned.
+    pub fn append_param(&mut self, param: ArgumentType) -> Option<Value> {
+        let ty = param.value_type;
+        self.signature.argument_types.push(param);
+        self.layout
+            .entry_block()
+            .map(|entry| self.dfg.append_ebb_arg(entry, ty))
+    }
+
+    /// Remove parameter number `idx` from the function signature.
+    ///
+    /// If the function has an entry block, the corresponding argument is removed from it and
+    /// returned. The removed value is detached like with `DataFlowGraph::remove_ebb_arg()`, so
+    /// any remaining uses of it should be rewritten.
+    pub fn remove_param(&mut self, idx: usize) -> Option<Value> {
+        self.signature.argument_types.remove(idx);
+        let arg = self.entry_arg(idx);
+        if let Some(arg) = arg {
+            self.dfg.remove_ebb_arg(arg);
+        }
+        arg
+    }
+
+    /// Change the type of parameter number `idx` in the function signature to `ty`.
+    ///
+    /// If the function has an entry block, the corresponding argument is replaced with a new
+    /// argument of type `ty`, and the new and old arguments are returned. The old argument is
+    /// detached like with `DataFlowGraph::replace_ebb_arg()`.
+    pub fn change_param_type(&mut self, idx: usize, ty: Type) -> Option<(Value, Value)> {
+        self.signature.argument_types[idx].value_type = ty;
+        self.entry_arg(idx).map(|old| (self.dfg.replace_ebb_arg(old, ty), old))
+    }
+
+    // Get argument number `idx` of the entry block, if there is one.
+    fn entry_arg(&self, idx: usize) -> Option<Value> {
+        self.layout.entry_block().map(|entry| {
+            self.dfg
+                .ebb_args(entry)
+                .nth(idx)
+                .expect("Entry block doesn't match the signature")
+        })
     }
 }
 
@@ -77,3 +267,33 @@ impl Debug for Function {
         write_function(fmt, self, None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, ArgumentType};
+    use ir::types;
+
+    #[test]
+    fn params() {
+        let mut func = Function::new();
+        assert_eq!(func.append_param(ArgumentType::new(types::I32)), None);
+
+        let ebb0 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.append_param(ArgumentType::new(types::I64)).unwrap();
+        let v2 = func.append_param(ArgumentType::new(types::F32)).unwrap();
+        assert_eq!(func.signature.to_string(), "(i32, i64, f32)");
+        assert_eq!(func.dfg.ebb_args(ebb0).collect::<Vec<_>>(), [v0, v1, v2]);
+
+        assert_eq!(func.remove_param(1), Some(v1));
+        assert_eq!(func.signature.to_string(), "(i32, f32)");
+        assert_eq!(func.dfg.ebb_args(ebb0).collect::<Vec<_>>(), [v0, v2]);
+
+        let (v3, old) = func.change_param_type(1, types::F64).unwrap();
+        assert_eq!(old, v2);
+        assert_eq!(func.signature.to_string(), "(i32, f64)");
+        assert_eq!(func.dfg.value_type(v3), types::F64);
+        assert_eq!(func.dfg.ebb_args(ebb0).collect::<Vec<_>>(), [v0, v3]);
+    }
+}
@@ -0,0 +1,38 @@
+//! Global variables.
+//!
+//! A global variable is a symbol whose address is resolved by the linker. Global variables are
+//! declared in the function preamble and assigned an `ir::entities::GlobalVar` reference.
+
+use ir::ExternalName;
+use std::fmt::{self, Display, Formatter};
+
+/// Information about a global variable declaration.
+#[derive(Clone, Debug)]
+pub struct GlobalVarData {
+    /// Name of the symbol, passed to the linker for resolution.
+    pub name: ExternalName,
+
+    /// The symbol is defined in the same object as the function, close enough to be addressed
+    /// relative to the program counter instead of with an absolute address or through the GOT.
+    pub colocated: bool,
+}
+
+impl GlobalVarData {
+    /// Create a global variable declaration with the given name.
+    pub fn new(name: ExternalName) -> GlobalVarData {
+        GlobalVarData {
+            name: name,
+            colocated: false,
+        }
+    }
+}
+
+impl Display for GlobalVarData {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "globalsym {}", self.name)?;
+        if self.colocated {
+            write!(f, " colocated")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+//! Heaps.
+//!
+//! A heap is a sandboxed memory area used by code compiled from WebAssembly or asm.js. Heaps are
+//! declared in the function preamble and assigned an `ir::entities::Heap` reference.
+
+use ir::ExternalName;
+use std::fmt::{self, Display, Formatter};
+
+/// Information about a heap declaration.
+#[derive(Clone, Debug)]
+pub struct HeapData {
+    /// Name identifying the heap in the runtime environment.
+    pub name: ExternalName,
+}
+
+impl HeapData {
+    /// Create a heap declaration with the given name.
+    pub fn new(name: ExternalName) -> HeapData {
+        HeapData { name: name }
+    }
+}
+
+impl Display for HeapData {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "heap {}", self.name)
+    }
+}
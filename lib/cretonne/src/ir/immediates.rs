@@ -8,6 +8,7 @@
 use std::fmt::{self, Display, Formatter};
 use std::mem;
 use std::str::FromStr;
+use std::vec::Vec;
 
 /// 64-bit immediate integer operand.
 ///
@@ -21,6 +22,67 @@ impl Imm64 {
     pub fn new(x: i64) -> Imm64 {
         Imm64(x)
     }
+
+    /// Get an object that displays this immediate as the sign-extended 128-bit value of an `i128`
+    /// constant.
+    pub fn display_i128(self) -> DisplayI128 {
+        DisplayI128(self)
+    }
+
+    /// Parse the immediate of an `i128` constant, formatted as by `display_i128()`.
+    ///
+    /// Hexadecimal numbers can have up to 32 digits, but the value must be the sign extension of a
+    /// 64-bit number. Unlike `Imm64::from_str()`, positive numbers are never wrapped around.
+    pub fn from_str_i128(s: &str) -> Result<Imm64, &'static str> {
+        let negative = s.starts_with('-');
+        let s2 = if negative { &s[1..] } else { s };
+
+        if !s2.starts_with("0x") {
+            // Decimal numbers are limited to the `i64` range.
+            let imm: Imm64 = s.parse()?;
+            if !negative && imm.0 < 0 {
+                return Err("Too large decimal i128 immediate");
+            }
+            return Ok(imm);
+        }
+
+        let mut hi: u64 = 0;
+        let mut lo: u64 = 0;
+        let mut digits = 0;
+        for ch in s2[2..].chars() {
+            match ch.to_digit(16) {
+                Some(digit) => {
+                    digits += 1;
+                    if digits > 32 {
+                        return Err("Too many hexadecimal digits in i128 immediate");
+                    }
+                    hi = (hi << 4) | (lo >> 60);
+                    lo = (lo << 4) | digit as u64;
+                }
+                None => {
+                    // Allow embedded underscores, but fail on anything else.
+                    if ch != '_' {
+                        return Err("Invalid character in hexadecimal i128 immediate");
+                    }
+                }
+            }
+        }
+
+        if digits == 0 {
+            return Err("No digits in i128 immediate");
+        }
+
+        if negative {
+            if hi != 0 || lo > 1 << 63 {
+                return Err("Negative number too small for i128 immediate");
+            }
+            Ok(Imm64(lo.wrapping_neg() as i64))
+        } else if hi == ((lo as i64) >> 63) as u64 {
+            Ok(Imm64(lo as i64))
+        } else {
+            Err("i128 immediate is not a sign-extended 64-bit number")
+        }
+    }
 }
 
 impl Into<i64> for Imm64 {
@@ -130,11 +192,41 @@ impl FromStr for Imm64 {
     }
 }
 
+/// Display an `Imm64` as the immediate of an `i128` constant.
+///
+/// Small numbers and positive numbers are displayed like an `Imm64`. Large negative numbers are
+/// displayed in hexadecimal with all 128 bits, so the text has the same value as the constant.
+pub struct DisplayI128(Imm64);
+
+impl Display for DisplayI128 {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let x = (self.0).0;
+        if x > -10_000 {
+            return write!(f, "{}", self.0);
+        }
+        write!(f, "0xffff_ffff_ffff_ffff")?;
+        for &pos in &[48, 32, 16, 0] {
+            write!(f, "_{:04x}", (x >> pos) & 0xffff)?;
+        }
+        Ok(())
+    }
+}
+
 /// 8-bit unsigned integer immediate operand.
 ///
 /// This is used to indicate lane indexes typically.
 pub type Uimm8 = u8;
 
+/// 32-bit unsigned integer immediate operand.
+///
+/// This is used for the byte count of heap range checks.
+pub type Uimm32 = u32;
+
+/// 32-bit signed immediate offset.
+///
+/// This is used as the byte offset from the base address of memory operations.
+pub type Offset32 = i32;
+
 /// An IEEE binary32 immediate floating point value.
 ///
 /// All bit patterns are allowed.
@@ -258,6 +350,11 @@ fn parse_float(s: &str, w: u8, t: u8) -> Result<u64, &'static str> {
                 _ => Err("Invalid NaN payload"),
             };
         }
+        if s2 == "sNaN" {
+            // Signaling NaN with the smallest possible payload. The payload of a signaling NaN
+            // can't be zero since that would encode infinity.
+            return Ok(sign_bit | max_e_bits | 1);
+        }
         if s2.starts_with("sNaN:0x") {
             // Signaling NaN with payload.
             return match u64::from_str_radix(&s2[7..], 16) {
@@ -384,6 +481,17 @@ impl Ieee32 {
     pub fn from_bits(x: u32) -> Ieee32 {
         Ieee32(unsafe { mem::transmute(x) })
     }
+
+    /// Get the raw bits of this immediate.
+    pub fn bits(self) -> u32 {
+        unsafe { mem::transmute(self.0) }
+    }
+}
+
+impl Into<f32> for Ieee32 {
+    fn into(self) -> f32 {
+        self.0
+    }
 }
 
 impl Display for Ieee32 {
@@ -414,6 +522,17 @@ impl Ieee64 {
     pub fn from_bits(x: u64) -> Ieee64 {
         Ieee64(unsafe { mem::transmute(x) })
     }
+
+    /// Get the raw bits of this immediate.
+    pub fn bits(self) -> u64 {
+        unsafe { mem::transmute(self.0) }
+    }
+}
+
+impl Into<f64> for Ieee64 {
+    fn into(self) -> f64 {
+        self.0
+    }
 }
 
 impl Display for Ieee64 {
@@ -526,6 +645,46 @@ mod tests {
                            "Too many hexadecimal digits in Imm64");
     }
 
+    // Verify that `text` parses as an `i128` immediate that displays as `want`.
+    fn parse_i128_ok(text: &str, want: &str) {
+        match Imm64::from_str_i128(text) {
+            Err(s) => panic!("\"{}\" i128 parse error: {}", text, s),
+            Ok(x) => assert_eq!(x.display_i128().to_string(), want),
+        }
+    }
+
+    #[test]
+    fn i128_immediates() {
+        assert_eq!(Imm64(-1).display_i128().to_string(), "-1");
+        assert_eq!(Imm64(10000).display_i128().to_string(), "0x2710");
+        assert_eq!(Imm64(-10000).display_i128().to_string(),
+                   "0xffff_ffff_ffff_ffff_ffff_ffff_ffff_d8f0");
+        assert_eq!(Imm64(i64::min_value()).display_i128().to_string(),
+                   "0xffff_ffff_ffff_ffff_8000_0000_0000_0000");
+
+        parse_i128_ok("-1", "-1");
+        parse_i128_ok("0x7fff_ffff_ffff_ffff", "0x7fff_ffff_ffff_ffff");
+        parse_i128_ok("0xffff_ffff_ffff_ffff_ffff_ffff_ffff_d8f0",
+                      "0xffff_ffff_ffff_ffff_ffff_ffff_ffff_d8f0");
+        parse_i128_ok("0xffff_ffff_ffff_ffff_ffff_ffff_ffff_ffff", "-1");
+        parse_i128_ok("-0x8000_0000_0000_0000",
+                      "0xffff_ffff_ffff_ffff_8000_0000_0000_0000");
+        parse_i128_ok("-9223372036854775808",
+                      "0xffff_ffff_ffff_ffff_8000_0000_0000_0000");
+
+        assert_eq!(Imm64::from_str_i128("0x8000_0000_0000_0000"),
+                   Err("i128 immediate is not a sign-extended 64-bit number"));
+        assert_eq!(Imm64::from_str_i128("0x1_0000_0000_0000_0000"),
+                   Err("i128 immediate is not a sign-extended 64-bit number"));
+        assert_eq!(Imm64::from_str_i128("18446744073709551615"),
+                   Err("Too large decimal i128 immediate"));
+        assert_eq!(Imm64::from_str_i128("-0x8000_0000_0000_0001"),
+                   Err("Negative number too small for i128 immediate"));
+        assert_eq!(Imm64::from_str_i128("0x1_0000_0000_0000_0000_0000_0000_0000_0000"),
+                   Err("Too many hexadecimal digits in i128 immediate"));
+        assert_eq!(Imm64::from_str_i128("0x"), Err("No digits in i128 immediate"));
+    }
+
     #[test]
     fn format_ieee32() {
         assert_eq!(Ieee32::new(0.0).to_string(), "0.0");
@@ -618,6 +777,8 @@ mod tests {
         parse_ok::<Ieee32>("NaN:0x300001", "NaN:0x300001");
         parse_err::<Ieee32>("NaN:0x400001", "Invalid NaN payload");
         parse_ok::<Ieee32>("sNaN:0x1", "sNaN:0x1");
+        parse_ok::<Ieee32>("sNaN", "sNaN:0x1");
+        parse_ok::<Ieee32>("-sNaN", "-sNaN:0x1");
         parse_err::<Ieee32>("sNaN:0x0", "Invalid sNaN payload");
         parse_ok::<Ieee32>("sNaN:0x200001", "sNaN:0x200001");
         parse_err::<Ieee32>("sNaN:0x400001", "Invalid sNaN payload");
@@ -719,6 +880,8 @@ mod tests {
         parse_ok::<Ieee64>("NaN:0x4000000000001", "NaN:0x4000000000001");
         parse_err::<Ieee64>("NaN:0x8000000000001", "Invalid NaN payload");
         parse_ok::<Ieee64>("sNaN:0x1", "sNaN:0x1");
+        parse_ok::<Ieee64>("sNaN", "sNaN:0x1");
+        parse_ok::<Ieee64>("-sNaN", "-sNaN:0x1");
         parse_err::<Ieee64>("sNaN:0x0", "Invalid sNaN payload");
         parse_ok::<Ieee64>("sNaN:0x4000000000001", "sNaN:0x4000000000001");
         parse_err::<Ieee64>("sNaN:0x8000000000001", "Invalid sNaN payload");
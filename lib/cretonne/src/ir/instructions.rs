@@ -8,20 +8,36 @@
 
 use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
+use std::iter::FromIterator;
 use std::ops::{Deref, DerefMut};
+use std::boxed::Box;
+use std::vec::Vec;
 
-use ir::{Value, Type, Ebb, JumpTable, SigRef, FuncRef};
-use ir::immediates::{Imm64, Uimm8, Ieee32, Ieee64, ImmVector};
+use ir::{Value, Type, Ebb, JumpTable, SigRef, FuncRef, TrapCode, Heap, GlobalVar,
+         StackSlot, MemFlags, AtomicOrdering};
+use ir::immediates::{Imm64, Uimm8, Uimm32, Offset32, Ieee32, Ieee64, ImmVector};
 use ir::condcodes::*;
 use ir::types;
 use ir::DataFlowGraph;
+use isa::RegUnit;
 
+use entity_list;
 use ref_slice::*;
 use packed_option::PackedOption;
 
+/// Some instructions use an external list of argument values because there is not enough space in
+/// the 16-byte `InstructionData` struct. These value lists are stored in a memory pool in
+/// `dfg.value_lists`.
+pub type ValueList = entity_list::EntityList<Value>;
+
+/// Memory pool for holding value lists. See `ValueList`.
+pub type ValueListPool = entity_list::ListPool<Value>;
+
 // Include code generated by `lib/cretonne/meta/gen_instr.py`. This file contains:
 //
 // - The `pub enum InstructionFormat` enum with all the instruction formats.
+// - The `pub enum OperandKind` enum with the kinds of immediate fields in the formats.
+// - The `impl InstructionFormat` block describing the operands of each format.
 // - The `pub enum Opcode` definition with all known opcodes,
 // - The `const OPCODE_FORMAT: [InstructionFormat; N]` table.
 // - The private `fn opcode_name(Opcode) -> &'static str` function, and
@@ -35,6 +51,19 @@ use packed_option::PackedOption;
 //
 include!(concat!(env!("OUT_DIR"), "/opcodes.rs"));
 
+/// Descriptor for an immediate or entity reference field in an instruction format.
+///
+/// The value operands of a format are described by `InstructionFormat::num_value_operands()` and
+/// `InstructionFormat::has_value_list()`. The remaining fields are described by a list of
+/// `FormatField` descriptors returned from `InstructionFormat::imm_fields()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FormatField {
+    /// Name of the member in the `InstructionData` variant.
+    pub member: &'static str,
+    /// The kind of operand stored in this field.
+    pub kind: OperandKind,
+}
+
 impl Display for Opcode {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "{}", opcode_name(*self))
@@ -52,6 +81,13 @@ impl Opcode {
     pub fn constraints(self) -> OpcodeConstraints {
         OPCODE_CONSTRAINTS[self as usize - 1]
     }
+
+    /// Get all the opcodes in the order they are defined.
+    pub fn all() -> Vec<Opcode> {
+        let mut opcodes: Vec<Opcode> = OPCODE_HASH_TABLE.iter().filter_map(|&op| op).collect();
+        opcodes.sort_by_key(|&op| op as usize);
+        opcodes
+    }
 }
 
 // This trait really belongs in lib/reader where it is used by the `.cton` file parser, but since
@@ -88,8 +124,9 @@ impl FromStr for Opcode {
 ///
 /// Every variant must contain `opcode` and `ty` fields. An instruction that doesn't produce a
 /// value should have its `ty` field set to `VOID`. The size of `InstructionData` should be kept at
-/// 16 bytes on 64-bit architectures. If more space is needed to represent an instruction, use a
-/// `Box<AuxData>` to store the additional information out of line.
+/// 16 bytes on 64-bit architectures, and the build fails if a variant makes it larger. If more
+/// space is needed to represent an instruction, use a `Box<AuxData>` to store the additional
+/// information out of line. Variable lists of value operands are stored in a `ValueList` instead.
 #[derive(Clone, Debug)]
 #[allow(missing_docs)]
 pub enum InstructionData {
@@ -104,6 +141,11 @@ pub enum InstructionData {
         ty: Type,
         imm: Imm64,
     },
+    UnaryBool {
+        opcode: Opcode,
+        ty: Type,
+        imm: bool,
+    },
     UnaryIeee32 {
         opcode: Opcode,
         ty: Type,
@@ -184,15 +226,42 @@ pub enum InstructionData {
         cond: FloatCC,
         args: [Value; 2],
     },
+    IntCond {
+        opcode: Opcode,
+        ty: Type,
+        cond: IntCC,
+        arg: Value,
+    },
+    FloatCond {
+        opcode: Opcode,
+        ty: Type,
+        cond: FloatCC,
+        arg: Value,
+    },
+    IntSelect {
+        opcode: Opcode,
+        ty: Type,
+        cond: IntCC,
+        args: [Value; 3],
+    },
     Jump {
         opcode: Opcode,
         ty: Type,
-        data: Box<JumpData>,
+        destination: Ebb,
+        args: ValueList,
     },
     Branch {
         opcode: Opcode,
         ty: Type,
-        data: Box<BranchData>,
+        destination: Ebb,
+        args: ValueList,
+    },
+    BranchIcmp {
+        opcode: Opcode,
+        ty: Type,
+        cond: IntCC,
+        destination: Ebb,
+        args: ValueList,
     },
     BranchTable {
         opcode: Opcode,
@@ -200,30 +269,152 @@ pub enum InstructionData {
         arg: Value,
         table: JumpTable,
     },
+    BranchTableEntry {
+        opcode: Opcode,
+        ty: Type,
+        args: [Value; 2],
+        imm: Uimm8,
+        table: JumpTable,
+    },
+    BranchTableBase {
+        opcode: Opcode,
+        ty: Type,
+        table: JumpTable,
+    },
+    Trap {
+        opcode: Opcode,
+        ty: Type,
+        code: TrapCode,
+    },
+    CondTrap {
+        opcode: Opcode,
+        ty: Type,
+        arg: Value,
+        code: TrapCode,
+    },
+    IntCondTrap {
+        opcode: Opcode,
+        ty: Type,
+        cond: IntCC,
+        arg: Value,
+        code: TrapCode,
+    },
+    BinaryTrap {
+        opcode: Opcode,
+        ty: Type,
+        args: [Value; 2],
+        code: TrapCode,
+    },
     Call {
         opcode: Opcode,
         ty: Type,
         second_result: PackedOption<Value>,
-        data: Box<CallData>,
+        func_ref: FuncRef,
+        args: ValueList,
     },
     IndirectCall {
         opcode: Opcode,
         ty: Type,
         second_result: PackedOption<Value>,
-        data: Box<IndirectCallData>,
+        sig_ref: SigRef,
+        args: ValueList,
+    },
+    FuncAddr {
+        opcode: Opcode,
+        ty: Type,
+        func_ref: FuncRef,
     },
     Return {
         opcode: Opcode,
         ty: Type,
-        data: Box<ReturnData>,
+        args: ValueList,
     },
     ReturnReg {
         opcode: Opcode,
         ty: Type,
-        data: Box<ReturnRegData>,
+        args: ValueList,
+    },
+    HeapAddr {
+        opcode: Opcode,
+        ty: Type,
+        heap: Heap,
+        arg: Value,
+        imm: Uimm32,
+    },
+    UnaryGlobalVar {
+        opcode: Opcode,
+        ty: Type,
+        global_var: GlobalVar,
+    },
+    Load {
+        opcode: Opcode,
+        ty: Type,
+        flags: MemFlags,
+        arg: Value,
+        offset: Offset32,
+    },
+    Store {
+        opcode: Opcode,
+        ty: Type,
+        flags: MemFlags,
+        args: [Value; 2],
+        offset: Offset32,
+    },
+    LoadComplex {
+        opcode: Opcode,
+        ty: Type,
+        data: Box<LoadComplexData>,
+    },
+    StoreComplex {
+        opcode: Opcode,
+        ty: Type,
+        data: Box<StoreComplexData>,
+    },
+    AtomicLoad {
+        opcode: Opcode,
+        ty: Type,
+        ordering: AtomicOrdering,
+        arg: Value,
+    },
+    AtomicRmw {
+        opcode: Opcode,
+        ty: Type,
+        ordering: AtomicOrdering,
+        args: [Value; 2],
+    },
+    AtomicCas {
+        opcode: Opcode,
+        ty: Type,
+        ordering: AtomicOrdering,
+        args: [Value; 3],
+    },
+    StackLoad {
+        opcode: Opcode,
+        ty: Type,
+        stack_slot: StackSlot,
+        offset: Uimm32,
+    },
+    StackStore {
+        opcode: Opcode,
+        ty: Type,
+        arg: Value,
+        stack_slot: StackSlot,
+        offset: Uimm32,
+    },
+    RegMove {
+        opcode: Opcode,
+        ty: Type,
+        arg: Value,
+        src: RegUnit,
+        dst: RegUnit,
     },
 }
 
+// The size of `InstructionData` is important for performance. The array lengths only match when
+// it is 16 bytes, so a change in its size is a build failure instead of a silent slowdown.
+#[cfg(target_pointer_width = "64")]
+const _INSTRUCTION_DATA_SIZE: [(); 16] = [(); ::std::mem::size_of::<InstructionData>()];
+
 /// A variable list of `Value` operands used for function call arguments and passing arguments to
 /// basic blocks.
 #[derive(Clone, Debug)]
@@ -280,6 +471,12 @@ impl Default for VariableArgs {
     }
 }
 
+impl FromIterator<Value> for VariableArgs {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> VariableArgs {
+        VariableArgs(iter.into_iter().collect())
+    }
+}
+
 /// Payload data for `vconst`.
 #[derive(Clone, Debug)]
 pub struct UnaryImmVectorData {
@@ -310,121 +507,30 @@ impl Display for TernaryOverflowData {
     }
 }
 
-/// Payload data for jump instructions. These need to carry lists of EBB arguments that won't fit
-/// in the allowed `InstructionData` size.
-#[derive(Clone, Debug)]
-pub struct JumpData {
-    /// Jump destination EBB.
-    pub destination: Ebb,
-    /// Arguments passed to destination EBB.
-    pub varargs: VariableArgs,
-}
-
-impl Display for JumpData {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        if self.varargs.is_empty() {
-            write!(f, "{}", self.destination)
-        } else {
-            write!(f, "{}({})", self.destination, self.varargs)
-        }
-    }
-}
-
-/// Payload data for branch instructions. These need to carry lists of EBB arguments that won't fit
-/// in the allowed `InstructionData` size.
-#[derive(Clone, Debug)]
-pub struct BranchData {
-    /// Value argument controlling the branch.
-    pub arg: Value,
-    /// Branch destination EBB.
-    pub destination: Ebb,
-    /// Arguments passed to destination EBB.
-    pub varargs: VariableArgs,
-}
-
-impl BranchData {
-    /// Get references to the arguments.
-    pub fn arguments(&self) -> [&[Value]; 2] {
-        [ref_slice(&self.arg), &self.varargs]
-    }
-
-    /// Get mutable references to the arguments.
-    pub fn arguments_mut(&mut self) -> [&mut [Value]; 2] {
-        [ref_slice_mut(&mut self.arg), &mut self.varargs]
-    }
-}
-
-impl Display for BranchData {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{}, {}", self.arg, self.destination)?;
-        if !self.varargs.is_empty() {
-            write!(f, "({})", self.varargs)?;
-        }
-        Ok(())
-    }
-}
-
-/// Payload of a call instruction.
+/// Payload data for `load_complex` instructions.
 #[derive(Clone, Debug)]
-pub struct CallData {
-    /// Callee function.
-    pub func_ref: FuncRef,
-
-    /// Dynamically sized array containing call argument values.
-    pub varargs: VariableArgs,
+pub struct LoadComplexData {
+    /// Memory flags.
+    pub flags: MemFlags,
+    /// The base address and the index.
+    pub args: [Value; 2],
+    /// Left shift amount applied to the index.
+    pub shift: Uimm8,
+    /// Byte offset added to the address.
+    pub offset: Offset32,
 }
 
-/// Payload of an indirect call instruction.
+/// Payload data for `store_complex` instructions.
 #[derive(Clone, Debug)]
-pub struct IndirectCallData {
-    /// Callee function.
-    pub arg: Value,
-
-    /// Signature of the callee function.
-    pub sig_ref: SigRef,
-
-    /// Dynamically sized array containing call argument values.
-    pub varargs: VariableArgs,
-}
-
-impl IndirectCallData {
-    /// Get references to the arguments.
-    pub fn arguments(&self) -> [&[Value]; 2] {
-        [ref_slice(&self.arg), &self.varargs]
-    }
-
-    /// Get mutable references to the arguments.
-    pub fn arguments_mut(&mut self) -> [&mut [Value]; 2] {
-        [ref_slice_mut(&mut self.arg), &mut self.varargs]
-    }
-}
-
-/// Payload of a return instruction.
-#[derive(Clone, Debug)]
-pub struct ReturnData {
-    /// Dynamically sized array containing return values.
-    pub varargs: VariableArgs,
-}
-
-/// Payload of a return instruction.
-#[derive(Clone, Debug)]
-pub struct ReturnRegData {
-    /// Return address.
-    pub arg: Value,
-    /// Dynamically sized array containing return values.
-    pub varargs: VariableArgs,
-}
-
-impl ReturnRegData {
-    /// Get references to the arguments.
-    pub fn arguments(&self) -> [&[Value]; 2] {
-        [ref_slice(&self.arg), &self.varargs]
-    }
-
-    /// Get mutable references to the arguments.
-    pub fn arguments_mut(&mut self) -> [&mut [Value]; 2] {
-        [ref_slice_mut(&mut self.arg), &mut self.varargs]
-    }
+pub struct StoreComplexData {
+    /// Memory flags.
+    pub flags: MemFlags,
+    /// The value to store, the base address, and the index.
+    pub args: [Value; 3],
+    /// Left shift amount applied to the index.
+    pub shift: Uimm8,
+    /// Byte offset added to the address.
+    pub offset: Offset32,
 }
 
 /// Analyzing an instruction.
@@ -434,10 +540,10 @@ impl ReturnRegData {
 impl InstructionData {
     /// Execute a closure once for each argument to this instruction.
     /// See also the `arguments()` method.
-    pub fn each_arg<F>(&self, mut func: F)
+    pub fn each_arg<F>(&self, pool: &ValueListPool, mut func: F)
         where F: FnMut(Value)
     {
-        for part in &self.arguments() {
+        for part in &self.arguments(pool) {
             for &arg in part.iter() {
                 func(arg);
             }
@@ -446,29 +552,47 @@ impl InstructionData {
 
     /// Execute a closure with a mutable reference to each argument to this instruction.
     /// See also the `arguments_mut()` method.
-    pub fn each_arg_mut<F>(&mut self, mut func: F)
+    pub fn each_arg_mut<F>(&mut self, pool: &mut ValueListPool, mut func: F)
         where F: FnMut(&mut Value)
     {
-        for part in &mut self.arguments_mut() {
+        for part in &mut self.arguments_mut(pool) {
             for arg in part.iter_mut() {
                 func(arg);
             }
         }
     }
 
+    /// Get a mutable reference to the value list holding the arguments of this instruction, if it
+    /// has one.
+    pub fn value_list_mut(&mut self) -> Option<&mut ValueList> {
+        match *self {
+            InstructionData::Jump { ref mut args, .. } |
+            InstructionData::Branch { ref mut args, .. } |
+            InstructionData::BranchIcmp { ref mut args, .. } |
+            InstructionData::Call { ref mut args, .. } |
+            InstructionData::IndirectCall { ref mut args, .. } |
+            InstructionData::Return { ref mut args, .. } |
+            InstructionData::ReturnReg { ref mut args, .. } => Some(args),
+            _ => None,
+        }
+    }
+
     /// Return information about the destination of a branch or jump instruction.
     ///
     /// Any instruction that can transfer control to another EBB reveals its possible destinations
     /// here.
-    pub fn analyze_branch<'a>(&'a self) -> BranchInfo<'a> {
-        match self {
-            &InstructionData::Jump { ref data, .. } => {
-                BranchInfo::SingleDest(data.destination, &data.varargs)
+    pub fn analyze_branch<'a>(&'a self, pool: &'a ValueListPool) -> BranchInfo<'a> {
+        match *self {
+            InstructionData::Jump { destination, ref args, .. } => {
+                BranchInfo::SingleDest(destination, args.as_slice(pool))
             }
-            &InstructionData::Branch { ref data, .. } => {
-                BranchInfo::SingleDest(data.destination, &data.varargs)
+            InstructionData::Branch { destination, ref args, .. } => {
+                BranchInfo::SingleDest(destination, &args.as_slice(pool)[1..])
             }
-            &InstructionData::BranchTable { table, .. } => BranchInfo::Table(table),
+            InstructionData::BranchIcmp { destination, ref args, .. } => {
+                BranchInfo::SingleDest(destination, &args.as_slice(pool)[2..])
+            }
+            InstructionData::BranchTable { table, .. } => BranchInfo::Table(table),
             _ => BranchInfo::NotABranch,
         }
     }
@@ -476,13 +600,13 @@ impl InstructionData {
     /// Return information about a call instruction.
     ///
     /// Any instruction that can call another function reveals its call signature here.
-    pub fn analyze_call<'a>(&'a self) -> CallInfo<'a> {
-        match self {
-            &InstructionData::Call { ref data, .. } => {
-                CallInfo::Direct(data.func_ref, &data.varargs)
+    pub fn analyze_call<'a>(&'a self, pool: &'a ValueListPool) -> CallInfo<'a> {
+        match *self {
+            InstructionData::Call { func_ref, ref args, .. } => {
+                CallInfo::Direct(func_ref, args.as_slice(pool))
             }
-            &InstructionData::IndirectCall { ref data, .. } => {
-                CallInfo::Indirect(data.sig_ref, &data.varargs)
+            InstructionData::IndirectCall { sig_ref, ref args, .. } => {
+                CallInfo::Indirect(sig_ref, &args.as_slice(pool)[1..])
             }
             _ => CallInfo::NotACall,
         }
@@ -500,7 +624,7 @@ impl InstructionData {
         } else if constraints.requires_typevar_operand() {
             // Not all instruction formats have a designated operand, but in that case
             // `requires_typevar_operand()` should never be true.
-            dfg.value_type(self.typevar_operand()
+            dfg.value_type(self.typevar_operand(&dfg.value_lists)
                 .expect("Instruction format doesn't have a designated operand, bad opcode."))
         } else {
             // For locality of reference, we prefer to get the controlling type variable from
@@ -620,6 +744,22 @@ impl OpcodeConstraints {
             .expect("Result constraints can't be free")
     }
 
+    /// Get the constraint on fixed value argument number `n`, having resolved the controlling type
+    /// variable to `ctrl_type`.
+    ///
+    /// Unlike results, some arguments can vary freely within a type set. This is represented by
+    /// `ResolvedConstraint::Free`.
+    pub fn value_argument_constraint(self, n: usize, ctrl_type: Type) -> ResolvedConstraint {
+        let constraint = &OPERAND_CONSTRAINTS[self.constraint_offset() + self.fixed_results() + n];
+        match *constraint {
+            OperandConstraint::Free(offset) => ResolvedConstraint::Free(TYPE_SETS[offset as usize]),
+            _ => {
+                ResolvedConstraint::Bound(constraint.resolve(ctrl_type)
+                                              .expect("Bound constraint must resolve"))
+            }
+        }
+    }
+
     /// Get the typeset of allowed types for the controlling type variable in a polymorphic
     /// instruction.
     pub fn ctrl_typeset(self) -> Option<ValueTypeSet> {
@@ -643,6 +783,8 @@ pub struct ValueTypeSet {
     max_float: u8,
     min_bool: u8,
     max_bool: u8,
+    min_ref: u8,
+    max_ref: u8,
 }
 
 impl ValueTypeSet {
@@ -657,6 +799,8 @@ impl ValueTypeSet {
             self.min_float <= l2b && l2b < self.max_float
         } else if scalar.is_bool() {
             self.min_bool <= l2b && l2b < self.max_bool
+        } else if scalar.is_ref() {
+            self.min_ref <= l2b && l2b < self.max_ref
         } else {
             false
         }
@@ -678,6 +822,8 @@ impl ValueTypeSet {
             types::F32
         } else if self.max_bool > 5 {
             types::B32
+        } else if self.max_ref > 5 {
+            types::R32
         } else {
             types::B1
         };
@@ -708,6 +854,22 @@ enum OperandConstraint {
 
     /// This operand is `ctrlType.double_width()`.
     DoubleWidth,
+
+    /// This operand is `ctrlType.half_vector()`.
+    HalfVector,
+
+    /// This operand is `ctrlType.double_vector()`.
+    DoubleVector,
+}
+
+/// The type constraint on a value argument once the controlling type variable is known.
+#[derive(Clone, Copy)]
+pub enum ResolvedConstraint {
+    /// The argument must have this exact type.
+    Bound(Type),
+
+    /// The argument can have any type in this set.
+    Free(ValueTypeSet),
 }
 
 impl OperandConstraint {
@@ -725,6 +887,10 @@ impl OperandConstraint {
             AsBool => Some(ctrl_type.as_bool()),
             HalfWidth => Some(ctrl_type.half_width().expect("invalid type for half_width")),
             DoubleWidth => Some(ctrl_type.double_width().expect("invalid type for double_width")),
+            HalfVector => Some(ctrl_type.half_vector().expect("invalid type for half_vector")),
+            DoubleVector => {
+                Some(ctrl_type.double_vector().expect("invalid type for double_vector"))
+            }
         }
     }
 }
@@ -734,6 +900,47 @@ impl OperandConstraint {
 mod tests {
     use super::*;
 
+    #[test]
+    fn format_queries() {
+        let f = Opcode::Iadd.format();
+        assert_eq!(f.num_value_operands(), 2);
+        assert!(!f.has_value_list());
+        assert!(!f.multiple_results());
+        assert_eq!(f.imm_fields(), &[]);
+
+        let f = Opcode::IaddImm.format();
+        assert_eq!(f.num_value_operands(), 1);
+        assert_eq!(f.imm_fields(),
+                   &[FormatField {
+                         member: "imm",
+                         kind: OperandKind::Imm64,
+                     }]);
+
+        let f = Opcode::Brz.format();
+        assert_eq!(f, InstructionFormat::Branch);
+        assert_eq!(f.num_value_operands(), 1);
+        assert!(f.has_value_list());
+        assert_eq!(f.imm_fields(),
+                   &[FormatField {
+                         member: "destination",
+                         kind: OperandKind::Ebb,
+                     }]);
+
+        let f = Opcode::Insertlane.format();
+        assert_eq!(f.num_value_operands(), 2);
+        assert_eq!(f.imm_fields(),
+                   &[FormatField {
+                         member: "lane",
+                         kind: OperandKind::Uimm8,
+                     }]);
+
+        let f = Opcode::Call.format();
+        assert_eq!(f.num_value_operands(), 0);
+        assert!(f.has_value_list());
+        assert!(f.multiple_results());
+        assert_eq!(f.imm_fields()[0].kind, OperandKind::FuncRef);
+    }
+
     #[test]
     fn opcodes() {
         use std::mem;
@@ -756,6 +963,13 @@ mod tests {
         assert_eq!("".parse::<Opcode>(), Err("Unknown opcode"));
         assert_eq!("\0".parse::<Opcode>(), Err("Unknown opcode"));
 
+        // All the opcodes are listed once, in order.
+        let all = Opcode::all();
+        assert_eq!(all.len(), OPCODE_FORMAT.len());
+        for (i, &op) in all.iter().enumerate() {
+            assert_eq!(op as usize, i + 1);
+        }
+
         // Opcode is a single byte, and because Option<Opcode> originally came to 2 bytes, early on
         // Opcode included a variant NotAnOpcode to avoid the unnecessary bloat. Since then the Rust
         // compiler has brought in NonZero optimization, meaning that an enum not using the 0 value
@@ -764,13 +978,22 @@ mod tests {
     }
 
     #[test]
-    fn instruction_data() {
-        use std::mem;
-        // The size of the `InstructionData` enum is important for performance. It should not
-        // exceed 16 bytes. Use `Box<FooData>` out-of-line payloads for instruction formats that
-        // require more space than that. It would be fine with a data structure smaller than 16
-        // bytes, but what are the odds of that?
-        assert_eq!(mem::size_of::<InstructionData>(), 16);
+    fn typevar_operand() {
+        // The controlling type variable of `iadd` is its result type.
+        let c = Opcode::Iadd.constraints();
+        assert!(c.use_typevar_operand());
+        assert!(!c.requires_typevar_operand());
+
+        // The result of `icmp` is a boolean, so the controlling type variable has to come from the
+        // designated operand.
+        let c = Opcode::Icmp.constraints();
+        assert!(c.use_typevar_operand());
+        assert!(c.requires_typevar_operand());
+
+        // So does `brz` which has no results at all.
+        let c = Opcode::Brz.constraints();
+        assert_eq!(c.fixed_results(), 0);
+        assert!(c.requires_typevar_operand());
     }
 
     #[test]
@@ -786,6 +1009,8 @@ mod tests {
             max_float: 0,
             min_bool: 3,
             max_bool: 7,
+            min_ref: 0,
+            max_ref: 0,
         };
         assert!(vts.contains(I32));
         assert!(vts.contains(I64));
@@ -794,6 +1019,7 @@ mod tests {
         assert!(!vts.contains(B1));
         assert!(vts.contains(B8));
         assert!(vts.contains(B64));
+        assert!(!vts.contains(R64));
         assert_eq!(vts.example().to_string(), "i32");
 
         let vts = ValueTypeSet {
@@ -805,6 +1031,8 @@ mod tests {
             max_float: 7,
             min_bool: 3,
             max_bool: 7,
+            min_ref: 0,
+            max_ref: 0,
         };
         assert_eq!(vts.example().to_string(), "f32");
 
@@ -817,6 +1045,8 @@ mod tests {
             max_float: 7,
             min_bool: 3,
             max_bool: 7,
+            min_ref: 0,
+            max_ref: 0,
         };
         assert_eq!(vts.example().to_string(), "f32x2");
 
@@ -829,6 +1059,8 @@ mod tests {
             max_float: 0,
             min_bool: 3,
             max_bool: 7,
+            min_ref: 0,
+            max_ref: 0,
         };
         assert!(!vts.contains(B32X2));
         assert!(vts.contains(B32X4));
@@ -844,8 +1076,28 @@ mod tests {
             max_float: 0,
             min_bool: 0,
             max_bool: 0,
+            min_ref: 0,
+            max_ref: 0,
         };
         assert!(vts.contains(I32));
         assert!(vts.contains(I32X4));
+
+        let vts = ValueTypeSet {
+            // TypeSet(lanes=(1, 1), refs=(32, 64))
+            min_lanes: 0,
+            max_lanes: 1,
+            min_int: 0,
+            max_int: 0,
+            min_float: 0,
+            max_float: 0,
+            min_bool: 0,
+            max_bool: 0,
+            min_ref: 5,
+            max_ref: 7,
+        };
+        assert!(vts.contains(R32));
+        assert!(vts.contains(R64));
+        assert!(!vts.contains(I64));
+        assert_eq!(vts.example().to_string(), "r32");
     }
 }
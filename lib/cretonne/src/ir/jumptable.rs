@@ -8,6 +8,7 @@ use ir::entities::Ebb;
 use std::iter;
 use std::slice;
 use std::fmt::{self, Display, Formatter};
+use std::vec::Vec;
 
 /// Contents of a jump table.
 ///
@@ -57,6 +58,21 @@ impl JumpTableData {
         }
     }
 
+    /// Get the number of entries in the table, including the missing ones.
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Is the table empty?
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Are there any missing entries in the table?
+    pub fn has_holes(&self) -> bool {
+        self.holes > 0
+    }
+
     /// Get the entry for `idx`, or `None`.
     pub fn get_entry(&self, idx: usize) -> Option<Ebb> {
         self.table.get(idx).and_then(|e| e.expand())
@@ -125,6 +141,9 @@ mod tests {
         assert_eq!(jt.get_entry(10), None);
 
         assert_eq!(jt.to_string(), "jump_table 0");
+        assert_eq!(jt.len(), 0);
+        assert!(jt.is_empty());
+        assert!(!jt.has_holes());
 
         let v: Vec<(usize, Ebb)> = jt.entries().collect();
         assert_eq!(v, []);
@@ -140,6 +159,8 @@ mod tests {
         jt.set_entry(0, e1);
         jt.set_entry(0, e2);
         jt.set_entry(10, e1);
+        assert_eq!(jt.len(), 11);
+        assert!(jt.has_holes());
 
         assert_eq!(jt.to_string(),
                    "jump_table ebb2, 0, 0, 0, 0, 0, 0, 0, 0, 0, ebb1");
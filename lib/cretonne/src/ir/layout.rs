@@ -6,6 +6,7 @@
 use std::cmp;
 use std::iter::{Iterator, IntoIterator};
 use entity_map::EntityMap;
+use mem_usage::MemUsage;
 use packed_option::PackedOption;
 use ir::entities::{Ebb, Inst};
 use ir::progpoint::{ProgramOrder, ExpandedProgramPoint};
@@ -50,6 +51,19 @@ impl Layout {
             last_ebb: None,
         }
     }
+
+    /// Clear the layout, keeping the allocated memory for reuse.
+    pub fn clear(&mut self) {
+        self.ebbs.clear();
+        self.insts.clear();
+        self.first_ebb = None;
+        self.last_ebb = None;
+    }
+
+    /// Get the memory used by the layout.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.ebbs.mem_usage() + self.insts.mem_usage()
+    }
 }
 
 // Sequence numbers.
@@ -335,6 +349,32 @@ impl Layout {
         self.assign_ebb_seq(ebb);
     }
 
+    /// Remove `ebb` from the layout. The EBB must be empty.
+    pub fn remove_ebb(&mut self, ebb: Ebb) {
+        assert!(self.is_ebb_inserted(ebb), "EBB not in the layout");
+        assert!(self.ebbs[ebb].first_inst.is_none(),
+                "Cannot remove EBB with instructions");
+        // Clear the `ebb` node and extract links.
+        let prev;
+        let next;
+        {
+            let n = &mut self.ebbs[ebb];
+            prev = n.prev;
+            next = n.next;
+            n.prev = None.into();
+            n.next = None.into();
+        }
+        // Fix up links to `ebb`.
+        match prev.expand() {
+            None => self.first_ebb = next.expand(),
+            Some(p) => self.ebbs[p].next = next,
+        }
+        match next.expand() {
+            None => self.last_ebb = prev.expand(),
+            Some(n) => self.ebbs[n].prev = prev,
+        }
+    }
+
     /// Return an iterator over all EBBs in layout order.
     pub fn ebbs<'f>(&'f self) -> Ebbs<'f> {
         Ebbs {
@@ -436,6 +476,11 @@ impl Layout {
         self.ebbs[ebb].last_inst.into()
     }
 
+    /// Get the instruction following `inst` in its EBB, or `None` if `inst` is the last one.
+    pub fn next_inst(&self, inst: Inst) -> Option<Inst> {
+        self.insts[inst].next.expand()
+    }
+
     /// Insert `inst` before the instruction `before` in the same EBB.
     pub fn insert_inst(&mut self, inst: Inst, before: Inst) {
         assert_eq!(self.inst_ebb(inst), None);
@@ -456,6 +501,31 @@ impl Layout {
         self.assign_inst_seq(inst);
     }
 
+    /// Remove `inst` from the layout.
+    pub fn remove_inst(&mut self, inst: Inst) {
+        let ebb = self.inst_ebb(inst).expect("Instruction already removed.");
+        // Clear the `inst` node and extract links.
+        let prev;
+        let next;
+        {
+            let n = &mut self.insts[inst];
+            prev = n.prev;
+            next = n.next;
+            n.ebb = None.into();
+            n.prev = None.into();
+            n.next = None.into();
+        }
+        // Fix up links to `inst`.
+        match prev.expand() {
+            None => self.ebbs[ebb].first_inst = next,
+            Some(p) => self.insts[p].next = next,
+        }
+        match next.expand() {
+            None => self.ebbs[ebb].last_inst = prev,
+            Some(n) => self.insts[n].prev = prev,
+        }
+    }
+
     /// Iterate over the instructions in `ebb` in layout order.
     pub fn ebb_insts<'f>(&'f self, ebb: Ebb) -> Insts<'f> {
         Insts {
@@ -874,6 +944,18 @@ impl<'f> Cursor<'f> {
         }
     }
 
+    /// Remove the instruction under the cursor.
+    ///
+    /// The cursor is left pointing at the position following the current instruction.
+    ///
+    /// Return the instruction that was removed.
+    pub fn remove_inst(&mut self) -> Inst {
+        let inst = self.current_inst().expect("No instruction to remove");
+        self.next_inst();
+        self.layout.remove_inst(inst);
+        inst
+    }
+
     /// Insert an EBB at the current position and switch to it.
     ///
     /// As far as possible, this method behaves as if the EBB header were an instruction inserted
@@ -1074,6 +1156,35 @@ mod tests {
         verify(&mut layout, &[(e1, &[]), (e0, &[]), (e2, &[])]);
     }
 
+    #[test]
+    fn remove_ebb() {
+        let mut layout = Layout::new();
+        let e0 = Ebb::new(0);
+        let e1 = Ebb::new(1);
+        let e2 = Ebb::new(2);
+
+        layout.append_ebb(e0);
+        layout.append_ebb(e1);
+        layout.append_ebb(e2);
+
+        layout.remove_ebb(e1);
+        assert!(!layout.is_ebb_inserted(e1));
+        verify(&mut layout, &[(e0, &[]), (e2, &[])]);
+
+        layout.remove_ebb(e0);
+        assert!(!layout.is_ebb_inserted(e0));
+        verify(&mut layout, &[(e2, &[])]);
+        assert_eq!(layout.entry_block(), Some(e2));
+
+        layout.remove_ebb(e2);
+        verify(&mut layout, &[]);
+        assert_eq!(layout.entry_block(), None);
+
+        // Removed EBBs can be inserted again.
+        layout.append_ebb(e1);
+        verify(&mut layout, &[(e1, &[])]);
+    }
+
     #[test]
     fn append_inst() {
         let mut layout = Layout::new();
@@ -1170,6 +1281,19 @@ mod tests {
 
         layout.insert_inst(i0, i1);
         verify(&mut layout, &[(e1, &[i2, i0, i1])]);
+
+        // Test cursor positioning after removals.
+        let mut cur = Cursor::new(&mut layout);
+        cur.goto_inst(i0);
+        assert_eq!(cur.remove_inst(), i0);
+        assert_eq!(cur.current_inst(), Some(i1));
+        assert_eq!(cur.remove_inst(), i1);
+        assert_eq!(cur.position(), CursorPosition::After(e1));
+        assert_eq!(cur.prev_inst(), Some(i2));
+        assert_eq!(cur.remove_inst(), i2);
+        assert_eq!(cur.position(), CursorPosition::After(e1));
+        assert_eq!(cur.layout.inst_ebb(i2), None);
+        verify(cur.layout, &[(e1, &[])]);
     }
 
     #[test]
@@ -0,0 +1,216 @@
+//! Runtime library functions.
+//!
+//! Some instructions are expanded into calls to well-known functions in the runtime library when
+//! the target doesn't have a native implementation. These functions are identified by a
+//! `LibCall`, and they are referenced with `ExternalName::libcall()` names in a namespace that is
+//! reserved for them, so the embedder can bind them to its own implementations when it resolves
+//! the relocations.
+
+use ir::{Opcode, Type, Signature, ArgumentType};
+use ir::types::{I32, I64, F32, F64};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// A function in the runtime library.
+///
+/// The integer multiplication and division helpers use the conventional compiler-rt and libgcc
+/// names, like `__divsi3` for a 32-bit signed division. The floating point rounding helpers and
+/// the bulk memory functions are the C library functions, like `floorf` for an `f32` floor. The
+/// `nearest` instruction rounds ties to even like `nearbyint` in the default rounding mode.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum LibCall {
+    /// `__mulsi3`: 32-bit `imul`.
+    MulI32,
+    /// `__muldi3`: 64-bit `imul`.
+    MulI64,
+    /// `__udivsi3`: 32-bit `udiv`.
+    UdivI32,
+    /// `__udivdi3`: 64-bit `udiv`.
+    UdivI64,
+    /// `__divsi3`: 32-bit `sdiv`.
+    SdivI32,
+    /// `__divdi3`: 64-bit `sdiv`.
+    SdivI64,
+    /// `__umodsi3`: 32-bit `urem`.
+    UremI32,
+    /// `__umoddi3`: 64-bit `urem`.
+    UremI64,
+    /// `__modsi3`: 32-bit `srem`.
+    SremI32,
+    /// `__moddi3`: 64-bit `srem`.
+    SremI64,
+    /// `ceilf`: `f32` `ceil`.
+    CeilF32,
+    /// `ceil`: `f64` `ceil`.
+    CeilF64,
+    /// `floorf`: `f32` `floor`.
+    FloorF32,
+    /// `floor`: `f64` `floor`.
+    FloorF64,
+    /// `truncf`: `f32` `trunc`.
+    TruncF32,
+    /// `trunc`: `f64` `trunc`.
+    TruncF64,
+    /// `nearbyintf`: `f32` `nearest`.
+    NearestF32,
+    /// `nearbyint`: `f64` `nearest`.
+    NearestF64,
+    /// `memcpy`: copy between non-overlapping memory regions.
+    Memcpy,
+    /// `memset`: fill a memory region with a byte.
+    Memset,
+    /// `memmove`: copy between possibly overlapping memory regions.
+    Memmove,
+}
+
+/// All the library functions, in index order.
+const ALL: [LibCall; 21] = [LibCall::MulI32,
+                            LibCall::MulI64,
+                            LibCall::UdivI32,
+                            LibCall::UdivI64,
+                            LibCall::SdivI32,
+                            LibCall::SdivI64,
+                            LibCall::UremI32,
+                            LibCall::UremI64,
+                            LibCall::SremI32,
+                            LibCall::SremI64,
+                            LibCall::CeilF32,
+                            LibCall::CeilF64,
+                            LibCall::FloorF32,
+                            LibCall::FloorF64,
+                            LibCall::TruncF32,
+                            LibCall::TruncF64,
+                            LibCall::NearestF32,
+                            LibCall::NearestF64,
+                            LibCall::Memcpy,
+                            LibCall::Memset,
+                            LibCall::Memmove];
+
+impl LibCall {
+    /// Get the library function that implements `opcode` for values of type `ty`, if any.
+    pub fn for_inst(opcode: Opcode, ty: Type) -> Option<LibCall> {
+        use self::LibCall::*;
+        let libcall = match (opcode, ty) {
+            (Opcode::Imul, I32) => MulI32,
+            (Opcode::Imul, I64) => MulI64,
+            (Opcode::Udiv, I32) => UdivI32,
+            (Opcode::Udiv, I64) => UdivI64,
+            (Opcode::Sdiv, I32) => SdivI32,
+            (Opcode::Sdiv, I64) => SdivI64,
+            (Opcode::Urem, I32) => UremI32,
+            (Opcode::Urem, I64) => UremI64,
+            (Opcode::Srem, I32) => SremI32,
+            (Opcode::Srem, I64) => SremI64,
+            (Opcode::Ceil, F32) => CeilF32,
+            (Opcode::Ceil, F64) => CeilF64,
+            (Opcode::Floor, F32) => FloorF32,
+            (Opcode::Floor, F64) => FloorF64,
+            (Opcode::Trunc, F32) => TruncF32,
+            (Opcode::Trunc, F64) => TruncF64,
+            (Opcode::Nearest, F32) => NearestF32,
+            (Opcode::Nearest, F64) => NearestF64,
+            _ => return None,
+        };
+        Some(libcall)
+    }
+
+    /// Get the library function with the index `index`, as returned by `index()`.
+    pub fn from_index(index: u32) -> Option<LibCall> {
+        ALL.get(index as usize).cloned()
+    }
+
+    /// Get the index of this library function in the `ExternalName` namespace reserved for them.
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+
+    /// Get the conventional symbol name of this library function.
+    pub fn symbol(self) -> &'static str {
+        use self::LibCall::*;
+        match self {
+            MulI32 => "__mulsi3",
+            MulI64 => "__muldi3",
+            UdivI32 => "__udivsi3",
+            UdivI64 => "__udivdi3",
+            SdivI32 => "__divsi3",
+            SdivI64 => "__divdi3",
+            UremI32 => "__umodsi3",
+            UremI64 => "__umoddi3",
+            SremI32 => "__modsi3",
+            SremI64 => "__moddi3",
+            CeilF32 => "ceilf",
+            CeilF64 => "ceil",
+            FloorF32 => "floorf",
+            FloorF64 => "floor",
+            TruncF32 => "truncf",
+            TruncF64 => "trunc",
+            NearestF32 => "nearbyintf",
+            NearestF64 => "nearbyint",
+            Memcpy => "memcpy",
+            Memset => "memset",
+            Memmove => "memmove",
+        }
+    }
+
+    /// Get the signature of this library function before ABI legalization.
+    ///
+    /// The bulk memory functions take and return pointers of type `pointer_type`.
+    pub fn signature(self, pointer_type: Type) -> Signature {
+        use self::LibCall::*;
+        let (args, ret) = match self {
+            MulI32 | UdivI32 | SdivI32 | UremI32 | SremI32 => (vec![I32, I32], I32),
+            MulI64 | UdivI64 | SdivI64 | UremI64 | SremI64 => (vec![I64, I64], I64),
+            CeilF32 | FloorF32 | TruncF32 | NearestF32 => (vec![F32], F32),
+            CeilF64 | FloorF64 | TruncF64 | NearestF64 => (vec![F64], F64),
+            Memcpy | Memmove => (vec![pointer_type; 3], pointer_type),
+            Memset => (vec![pointer_type, I32, pointer_type], pointer_type),
+        };
+        let mut sig = Signature::new();
+        sig.argument_types = args.into_iter().map(ArgumentType::new).collect();
+        sig.return_types.push(ArgumentType::new(ret));
+        sig
+    }
+}
+
+impl Display for LibCall {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl FromStr for LibCall {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALL.iter().cloned().find(|lc| lc.symbol() == s).ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index() {
+        for (i, &lc) in ALL.iter().enumerate() {
+            assert_eq!(lc.index(), i as u32);
+            assert_eq!(LibCall::from_index(i as u32), Some(lc));
+        }
+        assert_eq!(LibCall::from_index(ALL.len() as u32), None);
+    }
+
+    #[test]
+    fn display() {
+        for &lc in &ALL {
+            assert_eq!(lc.to_string().parse(), Ok(lc));
+        }
+        assert_eq!(LibCall::Memcpy.to_string(), "memcpy");
+        assert_eq!("bogus".parse::<LibCall>(), Err(()));
+    }
+
+    #[test]
+    fn signature() {
+        assert_eq!(LibCall::SdivI64.signature(I32).to_string(), "(i64, i64) -> i64");
+        assert_eq!(LibCall::Memset.signature(I64).to_string(), "(i64, i32, i64) -> i64");
+    }
+}
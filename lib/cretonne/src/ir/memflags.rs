@@ -0,0 +1,113 @@
+//! Memory operation flags.
+
+use std::fmt::{self, Display, Formatter};
+
+/// Flags for memory operations like `load` and `store`.
+///
+/// Each flag describes a property of the memory access that the code generator is allowed to rely
+/// on. The flags default to all off, which is always correct.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Hash)]
+pub struct MemFlags {
+    bits: u8,
+}
+
+const NOTRAP: u8 = 1 << 0;
+const ALIGNED: u8 = 1 << 1;
+const READONLY: u8 = 1 << 2;
+
+const NAMES: [(&'static str, u8); 3] = [("notrap", NOTRAP),
+                                         ("aligned", ALIGNED),
+                                         ("readonly", READONLY)];
+
+impl MemFlags {
+    /// Create a new empty set of flags.
+    pub fn new() -> MemFlags {
+        MemFlags { bits: 0 }
+    }
+
+    /// Are all the flags off?
+    pub fn is_empty(self) -> bool {
+        self.bits == 0
+    }
+
+    /// Set a flag by name. Return `false` if `name` is not a known flag.
+    pub fn set_by_name(&mut self, name: &str) -> bool {
+        match NAMES.iter().find(|&&(n, _)| n == name) {
+            Some(&(_, bit)) => {
+                self.bits |= bit;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Test if the `notrap` flag is set.
+    ///
+    /// The memory access is known to be valid, so it can't trap. Accesses without this flag may
+    /// trap, for example when they rely on a guard page to catch out-of-bounds heap accesses.
+    pub fn notrap(self) -> bool {
+        self.bits & NOTRAP != 0
+    }
+
+    /// Set the `notrap` flag.
+    pub fn set_notrap(&mut self) {
+        self.bits |= NOTRAP;
+    }
+
+    /// Test if the `aligned` flag is set.
+    ///
+    /// The effective address is known to be a multiple of the access size.
+    pub fn aligned(self) -> bool {
+        self.bits & ALIGNED != 0
+    }
+
+    /// Set the `aligned` flag.
+    pub fn set_aligned(&mut self) {
+        self.bits |= ALIGNED;
+    }
+
+    /// Test if the `readonly` flag is set.
+    ///
+    /// The accessed memory is never written while the function is running, so a load always
+    /// produces the same value. This flag only makes sense on loads.
+    pub fn readonly(self) -> bool {
+        self.bits & READONLY != 0
+    }
+
+    /// Set the `readonly` flag.
+    pub fn set_readonly(&mut self) {
+        self.bits |= READONLY;
+    }
+}
+
+/// Display the flags that are set as a comma separated list.
+impl Display for MemFlags {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut sep = "";
+        for &(name, bit) in &NAMES {
+            if self.bits & bit != 0 {
+                write!(f, "{}{}", sep, name)?;
+                sep = ", ";
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemFlags;
+
+    #[test]
+    fn names() {
+        let mut flags = MemFlags::new();
+        assert!(flags.is_empty());
+        assert_eq!(flags.to_string(), "");
+        assert!(!flags.set_by_name("bogus"));
+        assert!(flags.set_by_name("readonly"));
+        assert!(flags.readonly());
+        assert!(!flags.notrap());
+        flags.set_notrap();
+        assert_eq!(flags.to_string(), "notrap, readonly");
+    }
+}
@@ -10,22 +10,39 @@ pub mod jumptable;
 pub mod dfg;
 pub mod layout;
 pub mod function;
-mod funcname;
+mod extname;
+mod libcall;
+mod trapcode;
+mod memflags;
+mod atomics;
 mod extfunc;
+mod heap;
+mod globalvar;
 mod builder;
 mod valueloc;
 mod progpoint;
+mod sourceloc;
+mod valuelabel;
 
-pub use ir::funcname::FunctionName;
-pub use ir::extfunc::{Signature, ArgumentType, ArgumentExtension, ExtFuncData};
+pub use ir::extname::{ExternalName, FIRST_RESERVED_NAMESPACE, LIBCALL_NAMESPACE};
+pub use ir::extfunc::{Signature, CallConv, ArgumentType, ArgumentExtension, ArgumentPurpose,
+                       ExtFuncData};
 pub use ir::types::Type;
-pub use ir::entities::{Ebb, Inst, Value, StackSlot, JumpTable, FuncRef, SigRef};
+pub use ir::trapcode::TrapCode;
+pub use ir::libcall::LibCall;
+pub use ir::memflags::MemFlags;
+pub use ir::atomics::AtomicOrdering;
+pub use ir::entities::{Ebb, Inst, Value, StackSlot, JumpTable, FuncRef, SigRef, Heap, GlobalVar};
 pub use ir::instructions::{Opcode, InstructionData, VariableArgs};
-pub use ir::stackslot::StackSlotData;
+pub use ir::stackslot::{StackSlotData, StackSlotKind};
 pub use ir::jumptable::JumpTableData;
+pub use ir::heap::HeapData;
+pub use ir::globalvar::GlobalVarData;
 pub use ir::valueloc::{ValueLoc, ArgumentLoc};
 pub use ir::dfg::{DataFlowGraph, ValueDef};
 pub use ir::layout::{Layout, Cursor};
 pub use ir::function::Function;
-pub use ir::builder::InstBuilder;
+pub use ir::builder::{InstBuilder, InstBuilderBase};
 pub use ir::progpoint::{ProgramPoint, ProgramOrder, ExpandedProgramPoint};
+pub use ir::sourceloc::SourceLoc;
+pub use ir::valuelabel::ValueLabel;
@@ -4,42 +4,131 @@
 //!
 
 use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// The kind of a stack slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackSlotKind {
+    /// An explicit stack slot. This is a chunk of stack memory for use by the `stack_load`
+    /// and `stack_store` instructions.
+    ExplicitSlot,
+
+    /// A spill slot. This is a stack slot created by the register allocator.
+    SpillSlot,
+
+    /// An outgoing function argument.
+    ///
+    /// When passing arguments on the stack to a called function, they are written to the outgoing
+    /// argument area at the bottom of the caller's stack frame. The `offset` of the stack slot is
+    /// the byte offset of the argument in the outgoing argument area, as given by the
+    /// `ArgumentLoc::Stack` location in the legalized call signature.
+    OutgoingArg,
+}
+
+impl FromStr for StackSlotKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<StackSlotKind, ()> {
+        use self::StackSlotKind::*;
+        match s {
+            "explicit_slot" => Ok(ExplicitSlot),
+            "spill_slot" => Ok(SpillSlot),
+            "outgoing_arg" => Ok(OutgoingArg),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for StackSlotKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::StackSlotKind::*;
+        f.write_str(match *self {
+                        ExplicitSlot => "explicit_slot",
+                        SpillSlot => "spill_slot",
+                        OutgoingArg => "outgoing_arg",
+                    })
+    }
+}
 
 /// Contents of a stack slot.
 #[derive(Clone, Debug)]
 pub struct StackSlotData {
+    /// The kind of stack slot.
+    pub kind: StackSlotKind,
+
     /// Size of stack slot in bytes.
     pub size: u32,
+
+    /// Required alignment of the stack slot in bytes, or `None` to let Cretonne pick an
+    /// appropriate alignment. When present, this is always a power of two. Alignments larger than
+    /// the target's stack alignment are reduced to the stack alignment by the frame layout.
+    pub align: Option<u32>,
+
+    /// Byte offset of an `OutgoingArg` slot in the outgoing argument area. This is 0 for other
+    /// kinds of stack slots.
+    pub offset: u32,
 }
 
 impl StackSlotData {
-    /// Create a stack slot with the specified byte size.
-    pub fn new(size: u32) -> StackSlotData {
-        StackSlotData { size: size }
+    /// Create a stack slot with the specified kind and byte size.
+    pub fn new(kind: StackSlotKind, size: u32) -> StackSlotData {
+        StackSlotData {
+            kind: kind,
+            size: size,
+            align: None,
+            offset: 0,
+        }
+    }
+
+    /// Create an outgoing argument stack slot at `offset` in the outgoing argument area.
+    pub fn outgoing_arg(size: u32, offset: u32) -> StackSlotData {
+        StackSlotData { offset: offset, ..StackSlotData::new(StackSlotKind::OutgoingArg, size) }
     }
 }
 
 impl Display for StackSlotData {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
-        write!(fmt, "stack_slot {}", self.size)
+        write!(fmt, "{} {}", self.kind, self.size)?;
+        if let Some(align) = self.align {
+            write!(fmt, ", align = {}", align)?;
+        }
+        if self.kind == StackSlotKind::OutgoingArg {
+            write!(fmt, ", offset = {}", self.offset)?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use ir::Function;
-    use super::StackSlotData;
+    use super::{StackSlotData, StackSlotKind};
 
     #[test]
     fn stack_slot() {
         let mut func = Function::new();
 
-        let ss0 = func.stack_slots.push(StackSlotData::new(4));
-        let ss1 = func.stack_slots.push(StackSlotData::new(8));
+        let ss0 = func.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 4));
+        let ss1 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 8));
         assert_eq!(ss0.to_string(), "ss0");
         assert_eq!(ss1.to_string(), "ss1");
 
         assert_eq!(func.stack_slots[ss0].size, 4);
         assert_eq!(func.stack_slots[ss1].size, 8);
+
+        assert_eq!(func.stack_slots[ss0].to_string(), "explicit_slot 4");
+        func.stack_slots[ss1].align = Some(16);
+        assert_eq!(func.stack_slots[ss1].to_string(), "spill_slot 8, align = 16");
+
+        let ss2 = func.stack_slots.push(StackSlotData::outgoing_arg(4, 8));
+        assert_eq!(func.stack_slots[ss2].to_string(), "outgoing_arg 4, offset = 8");
+    }
+
+    #[test]
+    fn kind_from_str() {
+        assert_eq!("explicit_slot".parse(), Ok(StackSlotKind::ExplicitSlot));
+        assert_eq!("spill_slot".parse(), Ok(StackSlotKind::SpillSlot));
+        assert_eq!("outgoing_arg".parse(), Ok(StackSlotKind::OutgoingArg));
+        assert_eq!("stack_slot".parse::<StackSlotKind>(), Err(()));
     }
 }
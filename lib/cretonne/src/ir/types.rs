@@ -14,15 +14,21 @@ use std::fmt::{self, Display, Debug, Formatter};
 /// The `VOID` type is only used for instructions that produce no value. It can't be part of a SIMD
 /// vector.
 ///
-/// Basic integer types: `I8`, `I16`, `I32`, and `I64`. These types are sign-agnostic.
+/// Basic integer types: `I8`, `I16`, `I32`, `I64`, and `I128`. These types are sign-agnostic.
 ///
 /// Basic floating point types: `F32` and `F64`. IEEE single and double precision.
 ///
 /// Boolean types: `B1`, `B8`, `B16`, `B32`, and `B64`. These all encode 'true' or 'false'. The
 /// larger types use redundant bits.
 ///
+/// Reference types: `R32` and `R64`. These are opaque pointers into a garbage collected heap, and
+/// the register allocator tracks them so their locations can be reported in stack maps.
+///
 /// SIMD vector types have power-of-two lanes, up to 256. Lanes can be any int/float/bool type.
 ///
+/// CPU flags types: `IFLAGS` and `FFLAGS`. These represent the CPU flags produced by an integer or
+/// floating point comparison. They can't be SIMD lanes, and they can't be stored in memory.
+///
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Type(u8);
 
@@ -31,16 +37,21 @@ pub struct Type(u8);
 pub const VOID: Type = Type(0);
 
 // Include code generated by `lib/cretonne/meta/gen_types.py`. This file contains constant
-// definitions for all the scalar types as well as common vector types for 64, 128, 256, and
-// 512-bit SIMD vectors.
+// definitions for all the scalar and special types as well as common vector types for 64, 128,
+// 256, and 512-bit SIMD vectors.
 include!(concat!(env!("OUT_DIR"), "/types.rs"));
 
 impl Type {
     /// Get the lane type of this SIMD vector type.
     ///
-    /// A scalar type is the same as a SIMD vector type with one lane, so it returns itself.
+    /// A scalar type is the same as a SIMD vector type with one lane, so it returns itself. So does
+    /// a special type like `IFLAGS`.
     pub fn lane_type(self) -> Type {
-        Type(self.0 & 0x0f)
+        if self.is_special() {
+            self
+        } else {
+            Type(self.0 & 0x0f)
+        }
     }
 
     /// Get log_2 of the number of bits in a lane.
@@ -49,8 +60,9 @@ impl Type {
             B1 => 0,
             B8 | I8 => 3,
             B16 | I16 => 4,
-            B32 | I32 | F32 => 5,
-            B64 | I64 | F64 => 6,
+            B32 | I32 | F32 | R32 => 5,
+            B64 | I64 | F64 | R64 => 6,
+            I128 => 7,
             _ => 0,
         }
     }
@@ -61,8 +73,9 @@ impl Type {
             B1 => 1,
             B8 | I8 => 8,
             B16 | I16 => 16,
-            B32 | I32 | F32 => 32,
-            B64 | I64 | F64 => 64,
+            B32 | I32 | F32 | R32 => 32,
+            B64 | I64 | F64 | R64 => 64,
+            I128 => 128,
             _ => 0,
         }
     }
@@ -77,11 +90,11 @@ impl Type {
         let lane = match self.lane_type() {
             B8 | I8 => B8,
             B16 | I16 => B16,
-            B32 | I32 | F32 => B32,
-            B64 | I64 | F64 => B64,
+            B32 | I32 | F32 | R32 => B32,
+            B64 | I64 | F64 | R64 => B64,
             _ => B1,
         };
-        Type(lane.0 | (self.0 & 0xf0))
+        Type(lane.0 | (self.log2_lane_count() << 4))
     }
 
     /// Get a type with the same number of lanes as this type, but with the lanes replaced by
@@ -103,6 +116,7 @@ impl Type {
             I16 => I8,
             I32 => I16,
             I64 => I32,
+            I128 => I64,
             F64 => F32,
             B16 => B8,
             B32 => B16,
@@ -119,6 +133,7 @@ impl Type {
             I8 => I16,
             I16 => I32,
             I32 => I64,
+            I64 => I128,
             F32 => F64,
             B8 => B16,
             B16 => B32,
@@ -144,7 +159,7 @@ impl Type {
     /// Is this a scalar integer type?
     pub fn is_int(self) -> bool {
         match self {
-            I8 | I16 | I32 | I64 => true,
+            I8 | I16 | I32 | I64 | I128 => true,
             _ => false,
         }
     }
@@ -157,6 +172,29 @@ impl Type {
         }
     }
 
+    /// Is this a scalar reference type?
+    pub fn is_ref(self) -> bool {
+        match self {
+            R32 | R64 => true,
+            _ => false,
+        }
+    }
+
+    /// Is this a CPU flags type?
+    pub fn is_flags(self) -> bool {
+        match self {
+            IFLAGS | FFLAGS => true,
+            _ => false,
+        }
+    }
+
+    /// Is this a special type that is neither a scalar nor a vector type?
+    ///
+    /// Special types are numbered from 0xf0, above all the vector types.
+    pub fn is_special(self) -> bool {
+        self.0 >= 0xf0
+    }
+
     /// Get log_2 of the number of lanes in this SIMD vector type.
     ///
     /// All SIMD types have a lane count that is a power of two and no larger than 256, so this
@@ -164,7 +202,11 @@ impl Type {
     ///
     /// A scalar type is the same as a SIMD vector type with one lane, so it return 0.
     pub fn log2_lane_count(self) -> u8 {
-        self.0 >> 4
+        if self.is_special() {
+            0
+        } else {
+            self.0 >> 4
+        }
     }
 
     /// Is this a scalar type? (That is, not a SIMD vector type).
@@ -192,8 +234,10 @@ impl Type {
     ///
     /// If this is already a SIMD vector type, this produces a SIMD vector type with `n *
     /// self.lane_count()` lanes.
+    ///
+    /// Reference types can't be used as SIMD lanes, so they only support `n = 1`.
     pub fn by(self, n: u16) -> Option<Type> {
-        if self.lane_bits() == 0 || !n.is_power_of_two() {
+        if self.lane_bits() == 0 || (self.is_ref() && n > 1) || !n.is_power_of_two() {
             return None;
         }
         let log2_lanes: u32 = n.trailing_zeros();
@@ -214,6 +258,13 @@ impl Type {
         }
     }
 
+    /// Get a SIMD vector with twice the number of lanes.
+    ///
+    /// Scalar types produce a vector with two lanes.
+    pub fn double_vector(self) -> Option<Type> {
+        self.by(2)
+    }
+
     /// Index of this type, for use with hash tables etc.
     pub fn index(self) -> usize {
         self.0 as usize
@@ -230,6 +281,12 @@ impl Display for Type {
             write!(f, "i{}", self.lane_bits())
         } else if self.is_float() {
             write!(f, "f{}", self.lane_bits())
+        } else if self.is_ref() {
+            write!(f, "r{}", self.lane_bits())
+        } else if *self == IFLAGS {
+            write!(f, "iflags")
+        } else if *self == FFLAGS {
+            write!(f, "fflags")
         } else if !self.is_scalar() {
             write!(f, "{}x{}", self.lane_type(), self.lane_count())
         } else {
@@ -248,6 +305,12 @@ impl Debug for Type {
             write!(f, "types::I{}", self.lane_bits())
         } else if self.is_float() {
             write!(f, "types::F{}", self.lane_bits())
+        } else if self.is_ref() {
+            write!(f, "types::R{}", self.lane_bits())
+        } else if *self == IFLAGS {
+            write!(f, "types::IFLAGS")
+        } else if *self == FFLAGS {
+            write!(f, "types::FFLAGS")
         } else if !self.is_scalar() {
             write!(f, "{:?}X{}", self.lane_type(), self.lane_count())
         } else {
@@ -279,8 +342,11 @@ mod tests {
         assert_eq!(I16, I16.lane_type());
         assert_eq!(I32, I32.lane_type());
         assert_eq!(I64, I64.lane_type());
+        assert_eq!(I128, I128.lane_type());
         assert_eq!(F32, F32.lane_type());
         assert_eq!(F64, F64.lane_type());
+        assert_eq!(R32, R32.lane_type());
+        assert_eq!(R64, R64.lane_type());
 
         assert_eq!(VOID.lane_bits(), 0);
         assert_eq!(B1.lane_bits(), 1);
@@ -292,8 +358,11 @@ mod tests {
         assert_eq!(I16.lane_bits(), 16);
         assert_eq!(I32.lane_bits(), 32);
         assert_eq!(I64.lane_bits(), 64);
+        assert_eq!(I128.lane_bits(), 128);
         assert_eq!(F32.lane_bits(), 32);
         assert_eq!(F64.lane_bits(), 64);
+        assert_eq!(R32.lane_bits(), 32);
+        assert_eq!(R64.lane_bits(), 64);
     }
 
     #[test]
@@ -309,6 +378,7 @@ mod tests {
         assert_eq!(I32.half_width(), Some(I16));
         assert_eq!(I32X4.half_width(), Some(I16X4));
         assert_eq!(I64.half_width(), Some(I32));
+        assert_eq!(I128.half_width(), Some(I64));
         assert_eq!(F32.half_width(), None);
         assert_eq!(F64.half_width(), Some(F32));
 
@@ -322,7 +392,8 @@ mod tests {
         assert_eq!(I16.double_width(), Some(I32));
         assert_eq!(I32.double_width(), Some(I64));
         assert_eq!(I32X4.double_width(), Some(I64X4));
-        assert_eq!(I64.double_width(), None);
+        assert_eq!(I64.double_width(), Some(I128));
+        assert_eq!(I128.double_width(), None);
         assert_eq!(F32.double_width(), Some(F64));
         assert_eq!(F64.double_width(), None);
     }
@@ -338,6 +409,9 @@ mod tests {
         assert_eq!(B1.by(2).unwrap().half_vector().unwrap().to_string(), "b1");
         assert_eq!(I32.half_vector(), None);
         assert_eq!(VOID.half_vector(), None);
+        assert_eq!(I32.double_vector(), Some(I32X2));
+        assert_eq!(I32X4.double_vector(), Some(I32X8));
+        assert_eq!(big.double_vector(), None);
 
         // Check that the generated constants match the computed vector types.
         assert_eq!(I32.by(4), Some(I32X4));
@@ -356,8 +430,11 @@ mod tests {
         assert_eq!(I16.to_string(), "i16");
         assert_eq!(I32.to_string(), "i32");
         assert_eq!(I64.to_string(), "i64");
+        assert_eq!(I128.to_string(), "i128");
         assert_eq!(F32.to_string(), "f32");
         assert_eq!(F64.to_string(), "f64");
+        assert_eq!(R32.to_string(), "r32");
+        assert_eq!(R64.to_string(), "r64");
     }
 
     #[test]
@@ -369,9 +446,12 @@ mod tests {
         assert_eq!(B64.by(8).unwrap().to_string(), "b64x8");
         assert_eq!(I8.by(64).unwrap().to_string(), "i8x64");
         assert_eq!(F64.by(2).unwrap().to_string(), "f64x2");
+        assert_eq!(I128.by(2).unwrap().to_string(), "i128x2");
         assert_eq!(I8.by(3), None);
         assert_eq!(I8.by(512), None);
         assert_eq!(VOID.by(4), None);
+        assert_eq!(R64.by(1), Some(R64));
+        assert_eq!(R64.by(2), None);
     }
 
     #[test]
@@ -380,5 +460,37 @@ mod tests {
         assert_eq!(I32.as_bool(), B1);
         assert_eq!(I32X4.as_bool_pedantic(), B32X4);
         assert_eq!(I32.as_bool_pedantic(), B32);
+        assert_eq!(R64.as_bool_pedantic(), B64);
+        assert_eq!(I128.as_bool(), B1);
+    }
+
+    #[test]
+    fn references() {
+        assert!(R32.is_ref());
+        assert!(R64.is_ref());
+        assert!(!I64.is_ref());
+        assert!(!R64.is_int());
+        assert_eq!(R64.half_width(), None);
+        assert_eq!(R32.double_width(), None);
+        assert_eq!(format!("{:?}", R64), "types::R64");
+    }
+
+    #[test]
+    fn flags() {
+        assert!(IFLAGS.is_flags());
+        assert!(FFLAGS.is_flags());
+        assert!(!I32.is_flags());
+        assert!(IFLAGS.is_special());
+        assert!(!I32X4.is_special());
+        assert_eq!(IFLAGS.lane_type(), IFLAGS);
+        assert_eq!(IFLAGS.lane_count(), 1);
+        assert_eq!(FFLAGS.bits(), 0);
+        assert_eq!(IFLAGS.by(4), None);
+        assert_eq!(FFLAGS.half_vector(), None);
+        assert_eq!(IFLAGS.as_bool(), B1);
+        assert_eq!(FFLAGS.as_bool_pedantic(), B1);
+        assert_eq!(IFLAGS.to_string(), "iflags");
+        assert_eq!(FFLAGS.to_string(), "fflags");
+        assert_eq!(format!("{:?}", IFLAGS), "types::IFLAGS");
     }
 }
@@ -0,0 +1,32 @@
+//! Value labels.
+//!
+//! A front end can attach a label to the values that hold the contents of one of its source-level
+//! variables. The labels are stored in `Function::value_labels`. After compilation, the
+//! `debuginfo` module uses them to describe where each variable lives in the generated code.
+
+use std::fmt;
+
+/// A label identifying a source-level variable.
+///
+/// This is an opaque 32-bit number chosen by the front end. Several values can have the same
+/// label when the variable is assigned more than once.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct ValueLabel(u32);
+
+impl ValueLabel {
+    /// Create a new value label with the given number.
+    pub fn new(index: u32) -> ValueLabel {
+        ValueLabel(index)
+    }
+
+    /// Get the number of this value label.
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for ValueLabel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "vl{}", self.0)
+    }
+}
@@ -8,7 +8,7 @@ use ir::StackSlot;
 use std::fmt;
 
 /// Value location.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ValueLoc {
     /// This value has not been assigned to a location yet.
     Unassigned,
@@ -71,7 +71,7 @@ impl<'a> fmt::Display for DisplayValueLoc<'a> {
 ///   outgoing arguments.
 /// - For register arguments, there is usually no difference, but if we ever add support for a
 ///   register-window ISA like SPARC, register arguments would also need to be translated.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum ArgumentLoc {
     /// This argument has not been assigned to a location yet.
     Unassigned,
@@ -96,6 +96,14 @@ impl ArgumentLoc {
         }
     }
 
+    /// Get the register unit of a register argument, or `None` for other locations.
+    pub fn reg(&self) -> Option<RegUnit> {
+        match *self {
+            ArgumentLoc::Reg(reg) => Some(reg),
+            _ => None,
+        }
+    }
+
     /// Return an object that can display this argument location, using the register info from the
     /// target ISA.
     pub fn display<'a, R: Into<Option<&'a RegInfo>>>(self, regs: R) -> DisplayArgumentLoc<'a> {
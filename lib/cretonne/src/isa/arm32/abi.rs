@@ -0,0 +1,109 @@
+//! ARM 32-bit ABI implementation.
+//!
+//! This module implements the AAPCS calling convention with the VFP variant for floating point
+//! arguments through the primary `legalize_signature()` entry point.
+//!
+//! The first four integer arguments are passed in `r0`-`r3`, and the first eight floating point
+//! arguments in `d0`-`d7`, or the lower half of those registers for `f32`. This doesn't implement
+//! the back-filling of unused `s` registers. Small integer arguments with a `uext` or `sext` flag
+//! are extended to 32 bits. The return address is passed in `lr`, so `link` arguments and return
+//! values are assigned to it.
+//!
+//! The registers `r4`-`r11` are callee-saved. The VFP registers `d8`-`d15` are callee-saved too,
+//! but there is no floating point code generation yet.
+
+use abi::{ArgAction, ArgAssigner, ValueConversion, legalize_args};
+use ir::{Signature, ArgumentType, ArgumentLoc, ArgumentPurpose, ArgumentExtension};
+use ir::types;
+use isa::RegUnit;
+use isa::arm32::registers::{GPR, D};
+
+/// Callee-saved registers: `r4`-`r11`.
+static CSR: [RegUnit; 8] = [68, 69, 70, 71, 72, 73, 74, 75];
+
+struct Args {
+    regs: u32,
+    fprs: u32,
+    offset: u32,
+}
+
+impl Args {
+    fn new() -> Args {
+        Args {
+            regs: 0,
+            fprs: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl ArgAssigner for Args {
+    fn assign(&mut self, arg: &ArgumentType) -> ArgAction {
+        fn align(value: u32, to: u32) -> u32 {
+            (value + to - 1) & !(to - 1)
+        }
+
+        let ty = arg.value_type;
+
+        // The return address lives in `lr`.
+        if arg.purpose == ArgumentPurpose::Link {
+            return ArgAction::Assign(ArgumentLoc::Reg(GPR.unit(14)));
+        }
+
+        // Break down SIMD vectors, we don't support passing them in registers yet.
+        if !ty.is_scalar() {
+            return ValueConversion::VectorSplit.into();
+        }
+
+        if ty.is_float() {
+            if self.fprs < 8 {
+                let reg = D.unit(self.fprs as usize);
+                self.fprs += 1;
+                return ArgAction::Assign(ArgumentLoc::Reg(reg));
+            }
+            self.offset = align(self.offset, ty.bits() as u32 / 8);
+            let loc = ArgumentLoc::Stack(self.offset);
+            self.offset += ty.bits() as u32 / 8;
+            return ArgAction::Assign(loc);
+        }
+
+        // Large integers and booleans are broken down into an even-odd register pair.
+        if ty.bits() > 32 {
+            self.regs = align(self.regs, 2);
+            self.offset = align(self.offset, 8);
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Small integers are extended to 32 bits.
+        if ty.is_int() && ty.bits() < 32 {
+            match arg.extension {
+                ArgumentExtension::None => {}
+                ArgumentExtension::Uext => return ValueConversion::Uext(types::I32).into(),
+                ArgumentExtension::Sext => return ValueConversion::Sext(types::I32).into(),
+            }
+        }
+
+        if self.regs < 4 {
+            let reg = GPR.unit(self.regs as usize);
+            self.regs += 1;
+            ArgAction::Assign(ArgumentLoc::Reg(reg))
+        } else {
+            let loc = ArgumentLoc::Stack(self.offset);
+            self.offset += 4;
+            ArgAction::Assign(loc)
+        }
+    }
+}
+
+/// Legalize `sig` for ARM32.
+pub fn legalize_signature(sig: &mut Signature) {
+    legalize_args(&mut sig.argument_types, &mut Args::new());
+    legalize_args(&mut sig.return_types, &mut Args::new());
+}
+
+/// Get the registers preserved across calls.
+///
+/// The AAPCS is the only calling convention, so this is the same for all signatures.
+pub fn callee_saved_registers() -> &'static [RegUnit] {
+    &CSR
+}
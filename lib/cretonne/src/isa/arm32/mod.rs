@@ -1,14 +1,19 @@
 //! ARM 32-bit Instruction Set Architecture.
 
 pub mod settings;
+mod abi;
 mod enc_tables;
 mod registers;
 
 use super::super::settings as shared_settings;
-use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding};
+use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding,
+                      legal_encodings, allowed_types};
 use isa::Builder as IsaBuilder;
-use isa::{TargetIsa, RegInfo, Encoding, Legalize, RecipeConstraints};
-use ir::{InstructionData, DataFlowGraph};
+use isa::{TargetIsa, RegInfo, RegUnit, Encoding, Legalize, RecipeConstraints, RecipeSizing};
+use ir::{InstructionData, DataFlowGraph, Signature, CallConv};
+use regalloc::AllocatableSet;
+use std::boxed::Box;
+use std::vec::Vec;
 
 #[allow(dead_code)]
 struct Isa {
@@ -22,12 +27,14 @@ pub fn isa_builder() -> IsaBuilder {
     IsaBuilder {
         setup: settings::builder(),
         constructor: isa_constructor,
+        is_64bit: None,
     }
 }
 
 fn isa_constructor(shared_flags: shared_settings::Flags,
                    builder: &shared_settings::Builder)
                    -> Box<TargetIsa> {
+    // There are no Thumb-2 encodings yet, so the T32 tables are empty.
     let level1 = if shared_flags.is_compressed() {
         &enc_tables::LEVEL1_T32[..]
     } else {
@@ -49,11 +56,18 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn isa_flags(&self) -> shared_settings::Iter {
+        self.isa_flags.iter()
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
 
     fn encode(&self, dfg: &DataFlowGraph, inst: &InstructionData) -> Result<Encoding, Legalize> {
+        if !allowed_types(&self.shared_flags, dfg, inst) {
+            return Err(Legalize::Expand);
+        }
         lookup_enclist(inst.ctrl_typevar(dfg),
                        inst.opcode(),
                        self.cpumode,
@@ -61,17 +75,143 @@ impl TargetIsa for Isa {
             .and_then(|enclist_offset| {
                 general_encoding(enclist_offset,
                                  &enc_tables::ENCLISTS[..],
-                                 |instp| enc_tables::check_instp(inst, instp),
+                                 |instp| enc_tables::check_instp(inst, dfg, instp),
                                  |isap| self.isa_flags.numbered_predicate(isap as usize))
                     .ok_or(Legalize::Expand)
             })
     }
 
+    fn legal_encodings(&self,
+                       dfg: &DataFlowGraph,
+                       inst: &InstructionData)
+                       -> Result<Vec<Encoding>, Legalize> {
+        if !allowed_types(&self.shared_flags, dfg, inst) {
+            return Err(Legalize::Expand);
+        }
+        lookup_enclist(inst.ctrl_typevar(dfg),
+                       inst.opcode(),
+                       self.cpumode,
+                       &enc_tables::LEVEL2[..])
+            .and_then(|enclist_offset| {
+                let encodings = legal_encodings(enclist_offset,
+                                                &enc_tables::ENCLISTS[..],
+                                                |instp| enc_tables::check_instp(inst, dfg, instp),
+                                                |isap| {
+                                                    self.isa_flags
+                                                        .numbered_predicate(isap as usize)
+                                                });
+                if encodings.is_empty() {
+                    Err(Legalize::Expand)
+                } else {
+                    Ok(encodings)
+                }
+            })
+    }
+
     fn recipe_names(&self) -> &'static [&'static str] {
         &enc_tables::RECIPE_NAMES[..]
     }
 
+    fn stack_alignment(&self) -> u32 {
+        // The AAPCS requires 8-byte stack alignment at public interfaces.
+        self.shared_flags.stack_alignment(8)
+    }
+
+    fn if_conversion_limit(&self) -> usize {
+        // Short sequences can use conditional execution instead of branches.
+        2
+    }
+
     fn recipe_constraints(&self) -> &'static [RecipeConstraints] {
         &enc_tables::RECIPE_CONSTRAINTS
     }
+
+    fn recipe_sizing(&self) -> &'static [RecipeSizing] {
+        &enc_tables::RECIPE_SIZING
+    }
+
+    fn allocatable_registers(&self) -> AllocatableSet {
+        let mut regs = AllocatableSet::new();
+        // Reserve the scratch register `ip` used by some encoding recipes, the stack pointer `sp`,
+        // the link register `lr` holding the return address, and the program counter `pc`.
+        for reg in 12..16 {
+            regs.take(registers::GPR, registers::GPR.unit(reg));
+        }
+        if self.shared_flags.preserve_frame_pointers() {
+            // Reserve the frame pointer `fp`.
+            regs.take(registers::GPR, registers::GPR.unit(11));
+        }
+        // The registers `d16`-`d31` are only available on some VFP units.
+        if !self.isa_flags.has_d32() {
+            for reg in 16..32 {
+                regs.take(registers::D, registers::D.unit(reg));
+            }
+        }
+        regs
+    }
+
+    fn legalize_signature(&self, sig: &mut Signature, _current: bool) {
+        abi::legalize_signature(sig)
+    }
+
+    fn callee_saved_registers(&self, _call_conv: CallConv) -> &'static [RegUnit] {
+        abi::callee_saved_registers()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use settings;
+    use isa;
+    use ir::{DataFlowGraph, InstructionData, Opcode};
+    use ir::{types, immediates};
+
+    fn encstr(isa: &isa::TargetIsa, enc: isa::Encoding) -> String {
+        isa.display_enc(enc).to_string()
+    }
+
+    #[test]
+    fn test_a32enc() {
+        let shared_flags = settings::Flags::new(&settings::builder());
+        let isa = isa::lookup("arm32").unwrap().finish(shared_flags);
+
+        let mut dfg = DataFlowGraph::new();
+        let ebb = dfg.make_ebb();
+        let arg32 = dfg.append_ebb_arg(ebb, types::I32);
+        let arg64 = dfg.append_ebb_arg(ebb, types::I64);
+
+        // ADD is opcode 0b0100 in bits 24:21.
+        let add32 = InstructionData::Binary {
+            opcode: Opcode::Iadd,
+            ty: types::I32,
+            args: [arg32, arg32],
+        };
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &add32).unwrap()), "DPrr#08");
+
+        // The immediate form sets the I bit.
+        let add_imm = InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            ty: types::I32,
+            arg: arg32,
+            imm: immediates::Imm64::new(255),
+        };
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &add_imm).unwrap()), "DPri#28");
+
+        // Only 8-bit immediates are encoded.
+        let add_large = InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            ty: types::I32,
+            arg: arg32,
+            imm: immediates::Imm64::new(256),
+        };
+        assert_eq!(isa.encode(&dfg, &add_large), Err(isa::Legalize::Expand));
+
+        // 64-bit arithmetic must be narrowed.
+        let add64 = InstructionData::Binary {
+            opcode: Opcode::Iadd,
+            ty: types::I64,
+            args: [arg64, arg64],
+        };
+        assert_eq!(isa.encode(&dfg, &add64), Err(isa::Legalize::Narrow));
+    }
 }
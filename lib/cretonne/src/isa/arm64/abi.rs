@@ -0,0 +1,88 @@
+//! ARM 64-bit ABI implementation.
+//!
+//! This module implements the AAPCS64 calling convention through the primary
+//! `legalize_signature()` entry point.
+//!
+//! The first eight integer arguments are passed in `x0`-`x7`, and the first eight floating point
+//! arguments in `v0`-`v7`. The struct return pointer is passed in the indirect result register
+//! `x8`, and the return address in `x30`.
+//!
+//! The registers `x19`-`x29` are callee-saved. The low halves of `v8`-`v15` are callee-saved too,
+//! but there is no floating point code generation yet.
+
+use abi::{ArgAction, ArgAssigner, ValueConversion, legalize_args};
+use ir::{Signature, ArgumentType, ArgumentLoc, ArgumentPurpose};
+use isa::RegUnit;
+use isa::arm64::registers::{GPR, FPR};
+
+/// Callee-saved registers: `x19`-`x29`.
+static CSR: [RegUnit; 11] = [19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29];
+
+struct Args {
+    regs: u32,
+    fprs: u32,
+    offset: u32,
+}
+
+impl Args {
+    fn new() -> Args {
+        Args {
+            regs: 0,
+            fprs: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl ArgAssigner for Args {
+    fn assign(&mut self, arg: &ArgumentType) -> ArgAction {
+        let ty = arg.value_type;
+
+        match arg.purpose {
+            ArgumentPurpose::Link => return ArgAction::Assign(ArgumentLoc::Reg(GPR.unit(30))),
+            ArgumentPurpose::StructReturn => {
+                return ArgAction::Assign(ArgumentLoc::Reg(GPR.unit(8)))
+            }
+            _ => {}
+        }
+
+        // Break down SIMD vectors, we don't support passing them in registers yet.
+        if !ty.is_scalar() {
+            return ValueConversion::VectorSplit.into();
+        }
+
+        // Large integers are broken down to fit in a register.
+        if !ty.is_float() && ty.bits() > 64 {
+            return ValueConversion::IntSplit.into();
+        }
+
+        if ty.is_float() {
+            if self.fprs < 8 {
+                let reg = FPR.unit(self.fprs as usize);
+                self.fprs += 1;
+                return ArgAction::Assign(ArgumentLoc::Reg(reg));
+            }
+        } else if self.regs < 8 {
+            let reg = GPR.unit(self.regs as usize);
+            self.regs += 1;
+            return ArgAction::Assign(ArgumentLoc::Reg(reg));
+        }
+
+        let loc = ArgumentLoc::Stack(self.offset);
+        self.offset += 8;
+        ArgAction::Assign(loc)
+    }
+}
+
+/// Legalize `sig` for ARM64.
+pub fn legalize_signature(sig: &mut Signature) {
+    legalize_args(&mut sig.argument_types, &mut Args::new());
+    legalize_args(&mut sig.return_types, &mut Args::new());
+}
+
+/// Get the registers preserved across calls.
+///
+/// The AAPCS64 is the only calling convention, so this is the same for all signatures.
+pub fn callee_saved_registers() -> &'static [RegUnit] {
+    &CSR
+}
@@ -1,8 +1,11 @@
 //! Encoding tables for ARM64 ISA.
 
-use ir::InstructionData;
+use ir::{Opcode, DataFlowGraph, InstructionData};
 use ir::types;
+use predicates;
 use isa::enc_tables::{Level1Entry, Level2Entry};
 use isa::constraints::*;
+use isa::encoding::{RecipeSizing, BranchRange};
+use super::registers::*;
 
 include!(concat!(env!("OUT_DIR"), "/encoding-arm64.rs"));
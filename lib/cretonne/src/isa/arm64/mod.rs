@@ -1,14 +1,18 @@
 //! ARM 64-bit Instruction Set Architecture.
 
 pub mod settings;
+mod abi;
 mod enc_tables;
 mod registers;
 
 use super::super::settings as shared_settings;
-use isa::enc_tables::{lookup_enclist, general_encoding};
+use isa::enc_tables::{lookup_enclist, general_encoding, legal_encodings, allowed_types};
 use isa::Builder as IsaBuilder;
-use isa::{TargetIsa, RegInfo, Encoding, Legalize, RecipeConstraints};
-use ir::{InstructionData, DataFlowGraph};
+use isa::{TargetIsa, RegInfo, RegUnit, Encoding, Legalize, RecipeConstraints, RecipeSizing};
+use ir::{InstructionData, DataFlowGraph, Signature, CallConv};
+use regalloc::AllocatableSet;
+use std::boxed::Box;
+use std::vec::Vec;
 
 #[allow(dead_code)]
 struct Isa {
@@ -21,6 +25,7 @@ pub fn isa_builder() -> IsaBuilder {
     IsaBuilder {
         setup: settings::builder(),
         constructor: isa_constructor,
+        is_64bit: None,
     }
 }
 
@@ -42,11 +47,18 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn isa_flags(&self) -> shared_settings::Iter {
+        self.isa_flags.iter()
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
 
     fn encode(&self, dfg: &DataFlowGraph, inst: &InstructionData) -> Result<Encoding, Legalize> {
+        if !allowed_types(&self.shared_flags, dfg, inst) {
+            return Err(Legalize::Expand);
+        }
         lookup_enclist(inst.ctrl_typevar(dfg),
                        inst.opcode(),
                        &enc_tables::LEVEL1_A64[..],
@@ -54,17 +66,129 @@ impl TargetIsa for Isa {
             .and_then(|enclist_offset| {
                 general_encoding(enclist_offset,
                                  &enc_tables::ENCLISTS[..],
-                                 |instp| enc_tables::check_instp(inst, instp),
+                                 |instp| enc_tables::check_instp(inst, dfg, instp),
                                  |isap| self.isa_flags.numbered_predicate(isap as usize))
                     .ok_or(Legalize::Expand)
             })
     }
 
+    fn legal_encodings(&self,
+                       dfg: &DataFlowGraph,
+                       inst: &InstructionData)
+                       -> Result<Vec<Encoding>, Legalize> {
+        if !allowed_types(&self.shared_flags, dfg, inst) {
+            return Err(Legalize::Expand);
+        }
+        lookup_enclist(inst.ctrl_typevar(dfg),
+                       inst.opcode(),
+                       &enc_tables::LEVEL1_A64[..],
+                       &enc_tables::LEVEL2[..])
+            .and_then(|enclist_offset| {
+                let encodings = legal_encodings(enclist_offset,
+                                                &enc_tables::ENCLISTS[..],
+                                                |instp| enc_tables::check_instp(inst, dfg, instp),
+                                                |isap| {
+                                                    self.isa_flags
+                                                        .numbered_predicate(isap as usize)
+                                                });
+                if encodings.is_empty() {
+                    Err(Legalize::Expand)
+                } else {
+                    Ok(encodings)
+                }
+            })
+    }
+
     fn recipe_names(&self) -> &'static [&'static str] {
         &enc_tables::RECIPE_NAMES[..]
     }
 
+    fn stack_alignment(&self) -> u32 {
+        self.shared_flags.stack_alignment(16)
+    }
+
+    fn if_conversion_limit(&self) -> usize {
+        // `csel` makes the selects cheap.
+        3
+    }
+
     fn recipe_constraints(&self) -> &'static [RecipeConstraints] {
         &enc_tables::RECIPE_CONSTRAINTS
     }
+
+    fn recipe_sizing(&self) -> &'static [RecipeSizing] {
+        &enc_tables::RECIPE_SIZING
+    }
+
+    fn allocatable_registers(&self) -> AllocatableSet {
+        let mut regs = AllocatableSet::new();
+        // Reserve the platform register `x18` and the link register `x30` holding the return
+        // address. The stack pointer is not in the `GPR` class.
+        for &reg in &[18, 30] {
+            regs.take(registers::GPR, registers::GPR.unit(reg));
+        }
+        if self.shared_flags.preserve_frame_pointers() {
+            // Reserve the frame pointer `x29`.
+            regs.take(registers::GPR, registers::GPR.unit(29));
+        }
+        regs
+    }
+
+    fn legalize_signature(&self, sig: &mut Signature, _current: bool) {
+        abi::legalize_signature(sig)
+    }
+
+    fn callee_saved_registers(&self, _call_conv: CallConv) -> &'static [RegUnit] {
+        abi::callee_saved_registers()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use settings;
+    use isa;
+    use ir::{DataFlowGraph, InstructionData, Opcode};
+    use ir::{types, immediates};
+
+    fn encstr(isa: &isa::TargetIsa, enc: isa::Encoding) -> String {
+        isa.display_enc(enc).to_string()
+    }
+
+    #[test]
+    fn logical_immediates() {
+        let shared_flags = settings::Flags::new(&settings::builder());
+        let isa = isa::lookup("arm64").unwrap().finish(shared_flags);
+
+        let mut dfg = DataFlowGraph::new();
+        let ebb = dfg.make_ebb();
+        let arg32 = dfg.append_ebb_arg(ebb, types::I32);
+        let arg64 = dfg.append_ebb_arg(ebb, types::I64);
+
+        let band_imm = |ty, arg, imm| {
+            InstructionData::BinaryImm {
+                opcode: Opcode::BandImm,
+                ty: ty,
+                arg: arg,
+                imm: immediates::Imm64::new(imm),
+            }
+        };
+
+        // A run of ones is a bitmask immediate for both operand sizes.
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &band_imm(types::I32, arg32, 0xff)).unwrap()),
+                   "DPli#90");
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &band_imm(types::I64, arg64, 0xff)).unwrap()),
+                   "DPli#490");
+
+        // The high 32 bits of a 32-bit immediate are ignored, but `0xffff_ffff` is all ones in
+        // 32 bits.
+        assert_eq!(isa.encode(&dfg, &band_imm(types::I32, arg32, 0xffff_ffff)),
+                   Err(isa::Legalize::Expand));
+        assert_eq!(encstr(&*isa,
+                          isa.encode(&dfg, &band_imm(types::I64, arg64, 0xffff_ffff)).unwrap()),
+                   "DPli#490");
+
+        // Arbitrary constants are not encodable.
+        assert_eq!(isa.encode(&dfg, &band_imm(types::I64, arg64, 0x1234)),
+                   Err(isa::Legalize::Expand));
+    }
 }
@@ -1,6 +1,6 @@
 //! ARM64 Settings.
 
-use settings::{self, detail, Builder};
+use settings::{self, detail, Builder, Iter};
 use std::fmt;
 
 // Include code generated by `lib/cretonne/meta/gen_settings.py`. This file contains a public
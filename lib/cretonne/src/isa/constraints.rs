@@ -10,6 +10,7 @@
 use isa::{RegClass, RegUnit};
 
 /// Register constraint for a single value operand or instruction result.
+#[derive(Clone, Copy, Debug)]
 pub struct OperandConstraint {
     /// The kind of constraint.
     pub kind: ConstraintKind,
@@ -21,7 +22,7 @@ pub struct OperandConstraint {
 }
 
 /// The different kinds of operand constraints.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ConstraintKind {
     /// This operand or result must be a register from the given register class.
     Reg,
@@ -49,7 +50,7 @@ pub enum ConstraintKind {
 }
 
 /// Constraints for an encoding recipe.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct RecipeConstraints {
     /// Constraints for the instruction's fixed value operands.
     ///
@@ -65,4 +66,37 @@ pub struct RecipeConstraints {
     /// If the instruction produces a variable number of results, it's probably a call and the
     /// constraints must be derived from the calling convention ABI.
     pub outs: &'static [OperandConstraint],
+
+    /// Does the encoded instruction overwrite the CPU flags as a side effect?
+    ///
+    /// This is the case for most arithmetic instructions on Intel. A CPU flags value can't be live
+    /// across such an instruction.
+    pub clobbers_flags: bool,
+
+    /// Are any of the input constraints `FixedReg`?
+    pub fixed_ins: bool,
+
+    /// Are any of the output constraints `FixedReg`?
+    pub fixed_outs: bool,
+
+    /// Are any of the output constraints `Tied`?
+    pub tied_ops: bool,
+}
+
+impl OperandConstraint {
+    /// Get the fixed register required by this constraint, if any.
+    pub fn fixed_reg(&self) -> Option<RegUnit> {
+        match self.kind {
+            ConstraintKind::FixedReg(reg) => Some(reg),
+            _ => None,
+        }
+    }
+
+    /// Get the index of the input operand that this result is tied to, if any.
+    pub fn tied_input(&self) -> Option<usize> {
+        match self.kind {
+            ConstraintKind::Tied(num) => Some(num as usize),
+            _ => None,
+        }
+    }
 }
@@ -2,9 +2,11 @@
 //!
 //! This module contains types and functions for working with the encoding tables generated by
 //! `lib/cretonne/meta/gen_encoding.py`.
-use ir::{Type, Opcode};
-use isa::{Encoding, Legalize};
+use ir::{Type, Opcode, DataFlowGraph, InstructionData};
+use isa::{Encoding, Legalize, LegalizeFn};
 use constant_hash::{Table, probe};
+use settings::Flags;
+use std::vec::Vec;
 
 /// Level 1 hash table entry.
 ///
@@ -95,6 +97,36 @@ pub fn lookup_enclist<OffT1, OffT2>(ctrl_typevar: Type,
         })
 }
 
+/// Replace a generic `Legalize::Expand` action with a custom action from `table`.
+///
+/// The `table` lists the opcodes that have a custom legalization routine in a target ISA. The
+/// action `Legalize::Custom(n)` refers to the routine in entry `n`. Instructions with a
+/// controlling type that needs to be narrowed keep the generic action.
+pub fn custom_action(action: Legalize,
+                     opcode: Opcode,
+                     table: &[(Opcode, LegalizeFn)])
+                     -> Legalize {
+    if action != Legalize::Expand {
+        return action;
+    }
+    match table.iter().position(|&(op, _)| op == opcode) {
+        Some(n) => Legalize::Custom(n as u8),
+        None => action,
+    }
+}
+
+/// Check the types used by `inst` against the `enable_float` and `enable_simd` settings in
+/// `flags`.
+///
+/// Both the controlling type variable and the argument types are checked. An instruction using a
+/// disallowed type has no legal encodings, even if the target ISA supports the type.
+pub fn allowed_types(flags: &Flags, dfg: &DataFlowGraph, inst: &InstructionData) -> bool {
+    flags.allows_type(inst.ctrl_typevar(dfg)) &&
+    inst.arguments(&dfg.value_lists)
+        .iter()
+        .all(|args| args.iter().all(|&arg| flags.allows_type(dfg.value_type(arg))))
+}
+
 /// Encoding list entry.
 ///
 /// Encoding lists are represented as sequences of u16 words.
@@ -110,33 +142,31 @@ const CODE_ALWAYS: EncListEntry = PRED_MASK;
 /// The encoding list terminator.
 const CODE_FAIL: EncListEntry = 0xffff;
 
-/// Find the most general encoding of `inst`.
+/// Visit all the encodings of `inst` in an encoding list.
 ///
-/// Given an encoding list offset as returned by `lookup_enclist` above, search the encoding list
-/// for the most general encoding that applies to `inst`. The encoding lists are laid out such that
-/// this is the last valid entry in the list.
+/// Given an encoding list offset as returned by `lookup_enclist` above, call `visit` with each
+/// encoding in the list whose predicates are satisfied. The encodings are visited in list order,
+/// which goes from the most specific to the most general encoding.
 ///
 /// This function takes two closures that are used to evaluate predicates:
 /// - `instp` is passed an instruction predicate number to be evaluated on the current instruction.
 /// - `isap` is passed an ISA predicate number to evaluate.
-///
-/// Returns the corresponding encoding, or `None` if no list entries are satisfied by `inst`.
-pub fn general_encoding<InstP, IsaP>(offset: usize,
-                                     enclist: &[EncListEntry],
-                                     instp: InstP,
-                                     isap: IsaP)
-                                     -> Option<Encoding>
+fn visit_encodings<InstP, IsaP, Visit>(offset: usize,
+                                       enclist: &[EncListEntry],
+                                       instp: InstP,
+                                       isap: IsaP,
+                                       mut visit: Visit)
     where InstP: Fn(EncListEntry) -> bool,
-          IsaP: Fn(EncListEntry) -> bool
+          IsaP: Fn(EncListEntry) -> bool,
+          Visit: FnMut(Encoding)
 {
-    let mut found = None;
     let mut pos = offset;
     while enclist[pos] != CODE_FAIL {
         let pred = enclist[pos];
         if pred <= CODE_ALWAYS {
             // This is an instruction predicate followed by recipe and encbits entries.
             if pred == CODE_ALWAYS || instp(pred) {
-                found = Some(Encoding::new(enclist[pos + 1], enclist[pos + 2]))
+                visit(Encoding::new(enclist[pos + 1], enclist[pos + 2]))
             }
             pos += 3;
         } else {
@@ -148,5 +178,39 @@ pub fn general_encoding<InstP, IsaP>(offset: usize,
             }
         }
     }
+}
+
+/// Find the most general encoding of `inst`.
+///
+/// The encoding lists are laid out such that the most general encoding is the last valid entry in
+/// the list. See `visit_encodings` for the arguments.
+///
+/// Returns the corresponding encoding, or `None` if no list entries are satisfied by `inst`.
+pub fn general_encoding<InstP, IsaP>(offset: usize,
+                                     enclist: &[EncListEntry],
+                                     instp: InstP,
+                                     isap: IsaP)
+                                     -> Option<Encoding>
+    where InstP: Fn(EncListEntry) -> bool,
+          IsaP: Fn(EncListEntry) -> bool
+{
+    let mut found = None;
+    visit_encodings(offset, enclist, instp, isap, |enc| found = Some(enc));
+    found
+}
+
+/// Find all the encodings of `inst`, from the most specific to the most general.
+///
+/// See `visit_encodings` for the arguments.
+pub fn legal_encodings<InstP, IsaP>(offset: usize,
+                                    enclist: &[EncListEntry],
+                                    instp: InstP,
+                                    isap: IsaP)
+                                    -> Vec<Encoding>
+    where InstP: Fn(EncListEntry) -> bool,
+          IsaP: Fn(EncListEntry) -> bool
+{
+    let mut found = Vec::new();
+    visit_encodings(offset, enclist, instp, isap, |enc| found.push(enc));
     found
 }
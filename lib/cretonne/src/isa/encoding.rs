@@ -1,5 +1,6 @@
 //! The `Encoding` struct.
 
+use binemit::CodeOffset;
 use std::fmt;
 
 /// Bits needed to encode an instruction as binary machine code.
@@ -76,3 +77,67 @@ impl fmt::Display for DisplayEncoding {
         }
     }
 }
+
+/// Code size information for an encoding recipe.
+///
+/// All encoding recipes correspond to an exact instruction size.
+pub struct RecipeSizing {
+    /// Size in bytes of instructions encoded with this recipe.
+    pub bytes: u8,
+
+    /// Allowed branch range in this recipe, if any.
+    ///
+    /// All encoding recipes for branches have exact branch range information.
+    pub branch_range: Option<BranchRange>,
+}
+
+/// The range of a relative branch.
+#[derive(Clone, Copy, Debug)]
+pub struct BranchRange {
+    /// Offset in bytes from the address of the branch instruction to the origin used for computing
+    /// the branch displacement. This is the destination of a branch that encodes a 0 displacement.
+    pub origin: u8,
+
+    /// Number of bits in the signed byte displacement encoded in the instruction. This does not
+    /// account for branches that can only target aligned addresses.
+    pub bits: u8,
+}
+
+impl BranchRange {
+    /// Determine if this branch range can represent the range from `branch` to `dest`, where
+    /// `branch` is the code offset of the branch instruction itself and `dest` is the code offset
+    /// of the destination EBB header.
+    ///
+    /// This method does not detect if the range is larger than 2 GB.
+    pub fn contains(self, branch: CodeOffset, dest: CodeOffset) -> bool {
+        let d = dest.wrapping_sub(branch + self.origin as CodeOffset) as i32;
+        let s = 32 - self.bits;
+        d == d << s >> s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BranchRange;
+
+    #[test]
+    fn branch_range() {
+        // An Intel `jmp rel8` is relative to the end of the 2-byte instruction.
+        let short = BranchRange { origin: 2, bits: 8 };
+        assert!(short.contains(100, 102));
+        assert!(short.contains(100, 102 + 127));
+        assert!(!short.contains(100, 102 + 128));
+        assert!(short.contains(200, 202 - 128));
+        assert!(!short.contains(200, 202 - 129));
+
+        // A RISC-V branch is relative to the branch itself.
+        let sb = BranchRange { origin: 0, bits: 13 };
+        assert!(sb.contains(0, 4092));
+        assert!(!sb.contains(0, 4096));
+        assert!(sb.contains(4096, 0));
+
+        let near = BranchRange { origin: 5, bits: 32 };
+        assert!(near.contains(0, 0x7000_0000));
+        assert!(near.contains(0x7000_0000, 0));
+    }
+}
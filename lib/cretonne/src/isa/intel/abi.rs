@@ -0,0 +1,472 @@
+//! Intel ABI implementation.
+//!
+//! This module implements the System V, Windows fastcall, and Baldrdash calling conventions
+//! through the primary `legalize_signature()` entry point. The calling convention is selected per
+//! signature.
+//!
+//! System V:
+//!
+//! - In 64-bit mode, the first six integer arguments are passed in `rdi`, `rsi`, `rdx`, `rcx`,
+//!   `r8`, and `r9`, and the first eight floating point arguments in `xmm0`-`xmm7`.
+//! - In 32-bit mode, all arguments are passed on the stack.
+//! - The stack pointer is 16-byte aligned at calls.
+//!
+//! Windows fastcall:
+//!
+//! - In 64-bit mode, the first four arguments are passed in `rcx`, `rdx`, `r8`, and `r9`, or
+//!   `xmm0`-`xmm3` for floating point arguments. The register is chosen by the argument position,
+//!   so a floating point first argument uses up `rcx` too. The caller always reserves a 32-byte
+//!   shadow area on the stack where the callee can spill the register arguments, so stack
+//!   arguments start at offset 32. The stack pointer is 16-byte aligned at calls.
+//! - In 32-bit mode, the first two integer arguments are passed in `ecx` and `edx`, and the
+//!   rest on the stack. The stack pointer is only 4-byte aligned.
+//!
+//! Baldrdash:
+//!
+//! - Arguments are passed like System V, except the `vmctx` argument which is pinned to `r14` in
+//!   64-bit mode and `esi` in 32-bit mode wherever it appears in the argument list. Both registers
+//!   are callee-saved, so the VM context survives calls.
+//! - The function is entered through a prologue generated by the embedder, which pushes
+//!   `baldrdash_prologue_words` words below the return address. The unwind information accounts
+//!   for them.
+//!
+//! Return values are passed in `rax` and `rdx`, or `xmm0` and `xmm1`. The return address is always
+//! on the stack, so `link` arguments are not used. Other special purpose arguments are passed like
+//! normal arguments.
+//!
+//! A function with an `sret` argument also returns the struct return pointer in `rax`, so the
+//! legalized signature gets an extra `sret` return value.
+//!
+//! The prologue allocates the stack frame with an `x86_adjust_sp` instruction, and the frame is
+//! freed again before each return. The allocation is padded so the bottom of the frame stays
+//! aligned to the stack alignment below the return address and any Baldrdash prologue words.
+//!
+//! When the function has a `stack_limit`, the prologue starts by loading the limit into a scratch
+//! register that doesn't hold an argument. It adds the size of the frame allocation, compares the
+//! stack pointer to the sum with `x86_cmp_sp`, and traps with `stk_ovf` if the stack pointer is
+//! below it.
+//!
+//! Stack frames larger than the guard region are probed in the prologue with `x86_probe`
+//! instructions touching one page at a time from the top of the frame down. Windows commits stack
+//! pages on demand, so skipping past the guard page would crash instead of growing the stack.
+
+use abi::{ArgAction, ArgAssigner, ValueConversion, legalize_args};
+use ir::{Function, Cursor, Inst, InstBuilder, Opcode, Signature, ArgumentType, ArgumentLoc,
+         ArgumentPurpose, CallConv, GlobalVar, MemFlags, TrapCode, ValueLoc};
+use ir::condcodes::IntCC;
+use ir::types;
+use isa::{TargetIsa, RegUnit};
+use isa::intel::registers::{FPR, FLAG};
+use settings as shared_settings;
+use std::cmp;
+use std::vec::Vec;
+
+/// Argument registers for the 64-bit System V ABI: `rdi`, `rsi`, `rdx`, `rcx`, `r8`, `r9`.
+static ARG_GPRS: [RegUnit; 6] = [7, 6, 2, 1, 8, 9];
+
+/// Argument registers for the 64-bit Windows ABI: `rcx`, `rdx`, `r8`, `r9`.
+static WIN64_ARG_GPRS: [RegUnit; 4] = [1, 2, 8, 9];
+
+/// Argument registers for 32-bit fastcall: `ecx`, `edx`.
+static WIN32_ARG_GPRS: [RegUnit; 2] = [1, 2];
+
+/// Pinned VM context register for 64-bit Baldrdash: `r14`.
+const BALDRDASH_VMCTX64: RegUnit = 14;
+
+/// Pinned VM context register for 32-bit Baldrdash: `esi`.
+const BALDRDASH_VMCTX32: RegUnit = 6;
+
+/// Return value registers: `rax`, `rdx`.
+static RET_GPRS: [RegUnit; 2] = [0, 2];
+
+/// Scratch registers that can hold the stack limit in the prologue: `rax`, `r11`, `r10`.
+static LIMIT_GPRS64: [RegUnit; 3] = [0, 11, 10];
+
+/// Scratch registers that can hold the stack limit in the 32-bit prologue: `eax`, `ecx`, `edx`.
+static LIMIT_GPRS32: [RegUnit; 3] = [0, 1, 2];
+
+/// Size of the register argument shadow area reserved by 64-bit Windows callers.
+const WIN64_SHADOW_BYTES: u32 = 32;
+
+/// Callee-saved registers for the 64-bit System V ABI: `rbx`, `rbp`, `r12`-`r15`.
+static CSR_SYSV64: [RegUnit; 6] = [3, 5, 12, 13, 14, 15];
+
+/// Callee-saved registers for the 64-bit Windows ABI: `rbx`, `rbp`, `rsi`, `rdi`, `r12`-`r15`,
+/// and `xmm6`-`xmm15`.
+static CSR_WIN64: [RegUnit; 18] = [3, 5, 6, 7, 12, 13, 14, 15, 22, 23, 24, 25, 26, 27, 28, 29,
+                                   30, 31];
+
+/// Callee-saved registers for both 32-bit ABIs: `ebx`, `ebp`, `esi`, `edi`.
+static CSR_32: [RegUnit; 4] = [3, 5, 6, 7];
+
+struct Args {
+    pointer_bits: u16,
+    pointer_bytes: u32,
+    gpr: &'static [RegUnit],
+    gpr_used: usize,
+    fpr_limit: usize,
+    fpr_used: usize,
+    // Integer and floating point arguments share the same argument positions.
+    shared_positions: bool,
+    // Register holding the `vmctx` argument, if it is pinned.
+    vmctx: Option<RegUnit>,
+    offset: u32,
+}
+
+impl Args {
+    fn new(bits: u16, gpr: &'static [RegUnit], fpr_limit: usize) -> Args {
+        Args {
+            pointer_bits: bits,
+            pointer_bytes: bits as u32 / 8,
+            gpr: gpr,
+            gpr_used: 0,
+            fpr_limit: fpr_limit,
+            fpr_used: 0,
+            shared_positions: false,
+            vmctx: None,
+            offset: 0,
+        }
+    }
+
+    /// Create an argument assigner for the arguments of a `call_conv` signature.
+    fn arguments(bits: u16, call_conv: CallConv) -> Args {
+        match (bits, call_conv) {
+            (64, CallConv::SystemV) => Args::new(64, &ARG_GPRS, 8),
+            (64, CallConv::WindowsFastcall) => {
+                let mut args = Args::new(64, &WIN64_ARG_GPRS, WIN64_ARG_GPRS.len());
+                args.shared_positions = true;
+                args.offset = WIN64_SHADOW_BYTES;
+                args
+            }
+            (64, CallConv::Baldrdash) => {
+                let mut args = Args::new(64, &ARG_GPRS, 8);
+                args.vmctx = Some(BALDRDASH_VMCTX64);
+                args
+            }
+            (_, CallConv::SystemV) => Args::new(32, &[], 0),
+            (_, CallConv::WindowsFastcall) => Args::new(32, &WIN32_ARG_GPRS, 0),
+            (_, CallConv::Baldrdash) => {
+                let mut args = Args::new(32, &[], 0);
+                args.vmctx = Some(BALDRDASH_VMCTX32);
+                args
+            }
+        }
+    }
+
+    /// Mark an argument register as used.
+    fn use_register(&mut self, float: bool) {
+        if self.shared_positions || float {
+            self.fpr_used += 1;
+        }
+        if self.shared_positions || !float {
+            self.gpr_used += 1;
+        }
+    }
+}
+
+impl ArgAssigner for Args {
+    fn assign(&mut self, arg: &ArgumentType) -> ArgAction {
+        let ty = arg.value_type;
+
+        // A pinned VM context doesn't use up an argument register.
+        if arg.purpose == ArgumentPurpose::VMContext && ty.bits() == self.pointer_bits {
+            if let Some(reg) = self.vmctx {
+                return ArgAction::Assign(ArgumentLoc::Reg(reg));
+            }
+        }
+
+        // Break down SIMD vectors, we don't support passing them in registers yet.
+        if !ty.is_scalar() {
+            return ValueConversion::VectorSplit.into();
+        }
+
+        // Large integers and booleans are broken down to fit in a register.
+        if !ty.is_float() && ty.bits() > self.pointer_bits {
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Try to use a register.
+        if ty.is_float() {
+            if self.fpr_used < self.fpr_limit {
+                let reg = FPR.unit(self.fpr_used);
+                self.use_register(true);
+                return ArgAction::Assign(ArgumentLoc::Reg(reg));
+            }
+        } else if self.gpr_used < self.gpr.len() {
+            let reg = self.gpr[self.gpr_used];
+            self.use_register(false);
+            return ArgAction::Assign(ArgumentLoc::Reg(reg));
+        }
+
+        // Assign a stack location. A `f64` takes two slots in 32-bit mode.
+        let loc = ArgumentLoc::Stack(self.offset);
+        let size = ty.bits() as u32 / 8;
+        self.offset += if size > self.pointer_bytes {
+            size
+        } else {
+            self.pointer_bytes
+        };
+        ArgAction::Assign(loc)
+    }
+}
+
+/// Legalize `sig` for Intel.
+///
+/// This also computes the size of the stack argument array since it depends on the calling
+/// convention.
+pub fn legalize_signature(sig: &mut Signature, flags: &shared_settings::Flags) {
+    let bits = if flags.is_64bit() { 64 } else { 32 };
+
+    let mut args = Args::arguments(bits, sig.call_conv);
+    legalize_args(&mut sig.argument_types, &mut args);
+
+    if let Some(sret) = sig.special_arg_index(ArgumentPurpose::StructReturn) {
+        if !sig.return_types.iter().any(|ret| ret.purpose == ArgumentPurpose::StructReturn) {
+            let ty = sig.argument_types[sret].value_type;
+            sig.return_types.push(ArgumentType::special(ty, ArgumentPurpose::StructReturn));
+        }
+    }
+
+    let mut rets = Args::new(bits, &RET_GPRS, 2);
+    legalize_args(&mut sig.return_types, &mut rets);
+
+    sig.compute_argument_bytes(flags.stack_alignment(stack_alignment(bits, sig.call_conv)));
+    if bits == 64 && sig.call_conv == CallConv::WindowsFastcall {
+        // The shadow area is reserved even when no arguments are passed on the stack.
+        let bytes = sig.argument_bytes.unwrap_or(0);
+        sig.argument_bytes = Some(cmp::max(bytes, WIN64_SHADOW_BYTES));
+    }
+}
+
+/// Get the alignment of the stack pointer at calls using `call_conv`.
+pub fn stack_alignment(bits: u16, call_conv: CallConv) -> u32 {
+    match (bits, call_conv) {
+        (32, CallConv::WindowsFastcall) => 4,
+        _ => 16,
+    }
+}
+
+/// Get the registers preserved across calls using `call_conv`.
+pub fn callee_saved_registers(bits: u16, call_conv: CallConv) -> &'static [RegUnit] {
+    match (bits, call_conv) {
+        (64, CallConv::SystemV) |
+        (64, CallConv::Baldrdash) => &CSR_SYSV64,
+        (64, CallConv::WindowsFastcall) => &CSR_WIN64,
+        _ => &CSR_32,
+    }
+}
+
+/// Get the number of bytes between the stack pointer on entry to `func` and the top of its frame.
+///
+/// This is the return address pushed by the caller, and the words pushed by the embedder's
+/// prologue for the Baldrdash calling convention.
+pub fn frame_header_bytes(func: &Function, isa: &TargetIsa) -> u32 {
+    let pointer_bytes = if isa.flags().is_64bit() { 8 } else { 4 };
+    let mut header = pointer_bytes;
+    if func.signature.call_conv == CallConv::Baldrdash {
+        header += pointer_bytes * isa.flags().baldrdash_prologue_words() as u32;
+    }
+    header
+}
+
+/// Get the number of bytes the prologue of `func` subtracts from the stack pointer to allocate a
+/// `frame_size` byte stack frame.
+///
+/// The allocation is padded so the bottom of the frame is aligned to the stack alignment. A
+/// function that makes calls allocates the padding even when its frame is empty, so the stack
+/// pointer is aligned at the calls.
+pub fn frame_allocation(func: &Function, isa: &TargetIsa, frame_size: u32) -> u32 {
+    let has_calls = func.layout
+        .ebbs()
+        .flat_map(|ebb| func.layout.ebb_insts(ebb))
+        .any(|inst| match func.dfg[inst].opcode() {
+                 Opcode::Call | Opcode::CallIndirect => true,
+                 _ => false,
+             });
+    if frame_size == 0 && !has_calls {
+        return 0;
+    }
+    let header = frame_header_bytes(func, isa);
+    let align = isa.stack_alignment();
+    ((header + frame_size + align - 1) & !(align - 1)) - header
+}
+
+/// Insert `x86_adjust_sp` instructions allocating the `frame_size` byte stack frame of `func` at
+/// the top of the entry block, and freeing it in front of every instruction that leaves the
+/// function.
+pub fn insert_frame_allocation(func: &mut Function, isa: &TargetIsa, frame_size: u32) {
+    let bytes = frame_allocation(func, isa, frame_size) as i64;
+    if bytes == 0 {
+        return;
+    }
+    let entry = func.layout.entry_block().expect("Function has no entry block");
+    let first = func.layout.ebb_insts(entry).next().expect("Empty entry block");
+    let exits: Vec<Inst> = func.layout
+        .ebbs()
+        .filter_map(|ebb| func.layout.last_inst(ebb))
+        .filter(|&inst| func.dfg[inst].opcode().is_return())
+        .collect();
+
+    insert_adjust_sp(func, isa, first, -bytes);
+    for inst in exits {
+        insert_adjust_sp(func, isa, inst, bytes);
+    }
+}
+
+/// Insert an `x86_adjust_sp` instruction adding `offset` to the stack pointer before `inst`.
+fn insert_adjust_sp(func: &mut Function, isa: &TargetIsa, inst: Inst, offset: i64) {
+    let adjust = {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_inst(inst);
+        func.dfg.ins(&mut pos).x86_adjust_sp(offset)
+    };
+    encode_smallest(func, isa, adjust);
+}
+
+/// Give the prologue instruction `inst` its smallest encoding.
+///
+/// The prologue is inserted after the other instructions have been given their general
+/// encodings, so this is the last chance to pick a short one.
+fn encode_smallest(func: &mut Function, isa: &TargetIsa, inst: Inst) {
+    let sizing = isa.recipe_sizing();
+    *func.encodings.ensure(inst) = isa.legal_encodings(&func.dfg, &func.dfg[inst])
+        .unwrap_or_else(|_| panic!("Can't encode {} in the prologue", func.dfg[inst].opcode()))
+        .into_iter()
+        .min_by_key(|enc| sizing[enc.recipe()].bytes)
+        .unwrap();
+}
+
+/// Insert a check at the top of the entry block of `func` that traps with `stk_ovf` when
+/// allocating the `frame_size` byte stack frame would move the stack pointer below the limit
+/// stored at the address of the global variable `limit`.
+///
+/// This runs after register allocation, so the limit is loaded into a scratch register that isn't
+/// assigned to any argument.
+pub fn insert_stack_check(func: &mut Function, isa: &TargetIsa, limit: GlobalVar, frame_size: u32) {
+    let bytes = frame_allocation(func, isa, frame_size) as i64;
+    let (ty, scratch) = if isa.flags().is_64bit() {
+        (types::I64, &LIMIT_GPRS64)
+    } else {
+        (types::I32, &LIMIT_GPRS32)
+    };
+    let entry = func.layout.entry_block().expect("Function has no entry block");
+    let first = func.layout.ebb_insts(entry).next().expect("Empty entry block");
+    let reg = *scratch.iter()
+                   .find(|&&reg| {
+                             func.dfg
+                                 .ebb_args(entry)
+                                 .all(|arg| func.locations.get(arg) != Some(&ValueLoc::Reg(reg)))
+                         })
+                   .expect("No scratch register for the stack limit");
+
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+    let (values, cmp, insts) = {
+        let dfg = &mut func.dfg;
+        let pos = &mut Cursor::new(&mut func.layout);
+        pos.goto_inst(first);
+        let addr = dfg.ins(pos).globalsym_addr(ty, limit);
+        let mut values = vec![addr, dfg.ins(pos).load(ty, flags, addr, 0)];
+        if bytes != 0 {
+            let last = values[1];
+            values.push(dfg.ins(pos).iadd_imm(last, bytes));
+        }
+        let cmp = dfg.ins(pos).x86_cmp_sp(values[values.len() - 1]);
+        dfg.ins(pos).trapif(IntCC::UnsignedLessThan, cmp, TrapCode::StackOverflow);
+
+        // Collect the inserted instructions from the top of the entry block.
+        let mut insts = Vec::new();
+        pos.goto_top(entry);
+        while let Some(inst) = pos.next_inst() {
+            if inst == first {
+                break;
+            }
+            insts.push(inst);
+        }
+        (values, cmp, insts)
+    };
+
+    for value in values {
+        *func.locations.ensure(value) = ValueLoc::Reg(reg);
+    }
+    *func.locations.ensure(cmp) = ValueLoc::Reg(FLAG.unit(0));
+    for inst in insts {
+        encode_smallest(func, isa, inst);
+    }
+}
+
+/// Insert `x86_probe` instructions at the top of the entry block of `func`, touching each page of
+/// a `frame_size` byte stack frame from the top down.
+pub fn insert_stack_probes(func: &mut Function, isa: &TargetIsa, frame_size: u32) {
+    let page = 1u32 << isa.flags().probestack_size_log2();
+    let entry = func.layout.entry_block().expect("Function has no entry block");
+    let first = func.layout.ebb_insts(entry).next().expect("Empty entry block");
+    let mut pos = Cursor::new(&mut func.layout);
+    pos.goto_inst(first);
+
+    let mut offset = frame_size;
+    while offset > 0 {
+        offset = offset.saturating_sub(page);
+        let inst = func.dfg.ins(&mut pos).x86_probe(offset as i64);
+        *func.encodings.ensure(inst) = isa.encode(&func.dfg, &func.dfg[inst])
+            .expect("Can't encode x86_probe");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Signature, ArgumentType, CallConv, Type};
+    use ir::types::{I32, I64};
+    use settings::{self, Configurable};
+    use super::*;
+
+    /// Get the stack argument array size of a legalized signature.
+    fn argument_bytes(is_64bit: bool, call_conv: CallConv, args: &[Type]) -> Option<u32> {
+        let mut b = settings::builder();
+        b.set_bool("is_64bit", is_64bit).unwrap();
+        let mut sig = Signature::new();
+        sig.call_conv = call_conv;
+        sig.argument_types.extend(args.iter().map(|&ty| ArgumentType::new(ty)));
+        legalize_signature(&mut sig, &settings::Flags::new(&b));
+        sig.argument_bytes
+    }
+
+    #[test]
+    fn stack_arguments() {
+        assert_eq!(argument_bytes(true, CallConv::SystemV, &[I32]), Some(0));
+        // The shadow area is reserved even without stack arguments.
+        assert_eq!(argument_bytes(true, CallConv::WindowsFastcall, &[I32]), Some(32));
+        assert_eq!(argument_bytes(true, CallConv::WindowsFastcall, &[I64; 5]), Some(48));
+
+        // 32-bit fastcall only aligns the stack to 4 bytes.
+        assert_eq!(argument_bytes(false, CallConv::WindowsFastcall, &[I32; 3]), Some(4));
+        assert_eq!(argument_bytes(false, CallConv::SystemV, &[I32; 3]), Some(16));
+    }
+
+    #[test]
+    fn baldrdash_vmctx() {
+        let mut b = settings::builder();
+        b.enable("is_64bit").unwrap();
+        let mut sig = Signature::new();
+        sig.call_conv = CallConv::Baldrdash;
+        sig.argument_types.push(ArgumentType::new(I64));
+        sig.argument_types.push(ArgumentType::special(I64, ArgumentPurpose::VMContext));
+        sig.argument_types.push(ArgumentType::new(I64));
+        legalize_signature(&mut sig, &settings::Flags::new(&b));
+        assert_eq!(sig.argument_types[0].location, ArgumentLoc::Reg(7));
+        assert_eq!(sig.argument_types[1].location, ArgumentLoc::Reg(BALDRDASH_VMCTX64));
+        assert_eq!(sig.argument_types[2].location, ArgumentLoc::Reg(6));
+        assert!(callee_saved_registers(64, CallConv::Baldrdash).contains(&BALDRDASH_VMCTX64));
+        assert!(callee_saved_registers(32, CallConv::Baldrdash).contains(&BALDRDASH_VMCTX32));
+    }
+
+    #[test]
+    fn callee_saved() {
+        assert_eq!(callee_saved_registers(64, CallConv::SystemV).len(), 6);
+        // `rdi` is only preserved on Windows.
+        assert!(callee_saved_registers(64, CallConv::WindowsFastcall).contains(&7));
+        assert!(!callee_saved_registers(64, CallConv::SystemV).contains(&7));
+        assert_eq!(callee_saved_registers(32, CallConv::WindowsFastcall),
+                   callee_saved_registers(32, CallConv::SystemV));
+    }
+}
@@ -0,0 +1,2671 @@
+//! Emitting binary Intel machine code.
+//!
+//! # Relocations
+//!
+//! The Intel encoding recipes that reference symbols leave a 4-byte or 8-byte hole in the
+//! instruction which is filled in by the linker. Position-independent code only uses the
+//! PC-relative relocations, including the ones that go through the GOT or the PLT.
+//!
+//! The PC-relative relocations are applied at the start of the hole, so they have an addend of -4
+//! to make the offset relative to the end of the instruction. Branches to EBBs are reported with
+//! the `PCRel1` or `PCRel4` kind.
+//!
+//! The jump tables follow the function's code, so the recipes that address them write the final
+//! value into the instruction. They also report a `reloc_jt()` relocation, which is applied
+//! relative to the start of the function like the absolute jump table entries.
+//!
+//! # Safepoints
+//!
+//! The `safepoint` recipe doesn't emit any bytes. It reports the stack map of the live references
+//! to the code sink at the offset of the following instruction, which is usually a call.
+
+use binemit::{CodeSink, Reloc, Addend, Stackmap, bad_encoding, value_reg, value_stack};
+use ir::{Function, ExternalName, Inst, InstructionData, Opcode, Ebb, Value, TrapCode, JumpTable,
+         MemFlags, SourceLoc};
+use ir::condcodes::{CondCode, IntCC, FloatCC};
+use isa::RegUnit;
+use regalloc::diversion::RegDiversions;
+
+include!(concat!(env!("OUT_DIR"), "/binemit-intel.rs"));
+
+/// Intel relocation kinds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelocKind {
+    /// A 4-byte absolute address.
+    Abs4,
+    /// An 8-byte absolute address.
+    Abs8,
+    /// A 1-byte offset relative to the end of the instruction.
+    PCRel1,
+    /// A 4-byte offset relative to the end of the instruction.
+    PCRel4,
+    /// A 4-byte offset to the symbol's GOT entry, relative to the end of the instruction.
+    GOTPCRel4,
+    /// A 4-byte offset to the symbol's PLT entry, relative to the end of the instruction.
+    PLTRel4,
+}
+
+/// Names of the relocation kinds, indexed by `RelocKind`.
+pub static RELOC_NAMES: [&str; 6] = ["Abs4", "Abs8", "PCRel1", "PCRel4", "GOTPCRel4", "PLTRel4"];
+
+impl From<RelocKind> for Reloc {
+    fn from(kind: RelocKind) -> Reloc {
+        Reloc(kind as u16)
+    }
+}
+
+// Emitting the prefixes and opcode bytes.
+//
+// The encoding bits are `op | (rrr << 8) | (w << 11) | (pp << 12)`, as computed by `OP()` and
+// `MP()` in `meta/isa/intel/recipes.py`.
+// The `rex` argument holds the R, X, and B extension bits of the register operands, as computed by
+// the `rex*()` functions. The REX.W bit comes from the encoding bits.
+
+const BASE_REX: u8 = 0b0100_0000;
+
+/// Mandatory prefix bytes for the `pp` field in the encoding bits.
+const PREFIX: [u8; 3] = [0x66, 0xf3, 0xf2];
+
+/// Get the REX prefix for the encoding `bits` and the register extension bits `rex`.
+fn rex_prefix(bits: u16, rex: u8) -> u8 {
+    let w = ((bits >> 11) & 1) as u8;
+    BASE_REX | (w << 3) | rex
+}
+
+/// Get the REX extension bits for an instruction with a single register operand, which is encoded
+/// in the r/m field, the SIB base field, or the low bits of the opcode.
+fn rex1(reg_b: RegUnit) -> u8 {
+    ((reg_b >> 3) & 1) as u8
+}
+
+/// Get the REX extension bits for an instruction with register operands in the r/m and reg fields
+/// of the ModR/M byte.
+fn rex2(rm: RegUnit, reg: RegUnit) -> u8 {
+    let b = ((rm >> 3) & 1) as u8;
+    let r = ((reg >> 3) & 1) as u8;
+    (r << 2) | b
+}
+
+/// Get the REX extension bits for an instruction with a base and an index register in the SIB
+/// byte, and a register operand in the reg field of the ModR/M byte.
+fn rex3(base: RegUnit, reg: RegUnit, index: RegUnit) -> u8 {
+    let b = ((base >> 3) & 1) as u8;
+    let x = ((index >> 3) & 1) as u8;
+    let r = ((reg >> 3) & 1) as u8;
+    (r << 2) | (x << 1) | b
+}
+
+/// Emit the mandatory prefix selected by the `pp` field of the encoding bits.
+fn put_prefix<CS: CodeSink + ?Sized>(bits: u16, sink: &mut CS) {
+    let pp = (bits >> 12) & 3;
+    debug_assert!(pp != 0, "Missing mandatory prefix in {:#x}", bits);
+    sink.put1(PREFIX[pp as usize - 1]);
+}
+
+/// Check that an instruction without a REX prefix doesn't need one.
+fn check_no_rex(bits: u16, rex: u8) {
+    debug_assert_eq!(rex_prefix(bits, rex),
+                     BASE_REX,
+                     "Can't encode REX bits {:#x} without a REX prefix",
+                     rex_prefix(bits, rex));
+}
+
+// Emit single-byte opcode.
+fn put_op1<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    check_no_rex(bits, rex);
+    sink.put1(bits as u8);
+}
+
+// Emit single-byte opcode with REX prefix.
+fn put_rexop1<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    sink.put1(rex_prefix(bits, rex));
+    sink.put1(bits as u8);
+}
+
+// Emit two-byte opcode: 0F XX
+fn put_op2<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    check_no_rex(bits, rex);
+    sink.put1(0x0f);
+    sink.put1(bits as u8);
+}
+
+// Emit two-byte opcode: REX 0F XX
+fn put_rexop2<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    sink.put1(rex_prefix(bits, rex));
+    sink.put1(0x0f);
+    sink.put1(bits as u8);
+}
+
+// Emit LOCK prefix and two-byte opcode: F0 0F XX
+fn put_lkop2<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    check_no_rex(bits, rex);
+    sink.put1(0xf0);
+    sink.put1(0x0f);
+    sink.put1(bits as u8);
+}
+
+// Emit LOCK prefix and two-byte opcode with REX prefix: F0 REX 0F XX
+fn put_lkrexop2<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    sink.put1(0xf0);
+    sink.put1(rex_prefix(bits, rex));
+    sink.put1(0x0f);
+    sink.put1(bits as u8);
+}
+
+// Emit mandatory prefix and two-byte opcode: PP 0F XX
+fn put_mp2<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    check_no_rex(bits, rex);
+    put_prefix(bits, sink);
+    sink.put1(0x0f);
+    sink.put1(bits as u8);
+}
+
+// Emit mandatory prefix, REX, and two-byte opcode: PP REX 0F XX
+fn put_rexmp2<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    put_prefix(bits, sink);
+    sink.put1(rex_prefix(bits, rex));
+    sink.put1(0x0f);
+    sink.put1(bits as u8);
+}
+
+// Emit mandatory prefix and three-byte opcode: PP 0F 3A XX
+fn put_mp3<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    check_no_rex(bits, rex);
+    put_prefix(bits, sink);
+    sink.put1(0x0f);
+    sink.put1(0x3a);
+    sink.put1(bits as u8);
+}
+
+// Emit mandatory prefix, REX, and three-byte opcode: PP REX 0F 3A XX
+fn put_rexmp3<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    put_prefix(bits, sink);
+    sink.put1(rex_prefix(bits, rex));
+    sink.put1(0x0f);
+    sink.put1(0x3a);
+    sink.put1(bits as u8);
+}
+
+/// Emit a ModR/M byte for reg-reg operands.
+fn modrm_rr<CS: CodeSink + ?Sized>(rm: RegUnit, reg: RegUnit, sink: &mut CS) {
+    let reg = reg as u8 & 7;
+    let rm = rm as u8 & 7;
+    sink.put1(0b11000000 | (reg << 3) | rm);
+}
+
+/// Emit a ModR/M byte where the reg bits are part of the opcode.
+fn modrm_r_bits<CS: CodeSink + ?Sized>(rm: RegUnit, bits: u16, sink: &mut CS) {
+    let reg = (bits >> 8) as u8 & 7;
+    let rm = rm as u8 & 7;
+    sink.put1(0b11000000 | (reg << 3) | rm);
+}
+
+/// Emit a mode 01 ModR/M byte with a SIB byte following, and an 8-bit displacement after that.
+fn modrm_sib_disp8<CS: CodeSink + ?Sized>(reg: RegUnit, sink: &mut CS) {
+    let reg = reg as u8 & 7;
+    sink.put1(0b01000100 | (reg << 3));
+}
+
+/// Emit a mode 10 ModR/M byte with a SIB byte following, and a 32-bit displacement after that.
+fn modrm_sib_disp32<CS: CodeSink + ?Sized>(reg: RegUnit, sink: &mut CS) {
+    let reg = reg as u8 & 7;
+    sink.put1(0b10000100 | (reg << 3));
+}
+
+/// Emit a mode 00 ModR/M byte addressing `rip` plus a 32-bit displacement.
+fn modrm_riprel<CS: CodeSink + ?Sized>(reg: RegUnit, sink: &mut CS) {
+    let reg = reg as u8 & 7;
+    sink.put1(0b00000101 | (reg << 3));
+}
+
+/// Emit a SIB byte with a base register and no index.
+fn sib_noindex<CS: CodeSink + ?Sized>(base: RegUnit, sink: &mut CS) {
+    let base = base as u8 & 7;
+    // The index register 100 means no index.
+    sink.put1(0b00100000 | base);
+}
+
+/// Emit a SIB byte addressing `base + (index << scale)`.
+fn sib<CS: CodeSink + ?Sized>(scale: u8, index: RegUnit, base: RegUnit, sink: &mut CS) {
+    debug_assert!(scale < 4);
+    let index = index as u8 & 7;
+    let base = base as u8 & 7;
+    sink.put1((scale << 6) | (index << 3) | base);
+}
+
+/// Emit an 8-bit branch displacement to `destination`, relative to the end of the instruction.
+fn disp1<CS: CodeSink + ?Sized>(destination: Ebb, func: &Function, sink: &mut CS) {
+    let delta = func.offsets[destination].wrapping_sub(sink.offset() + 1) as i32;
+    debug_assert!(delta >= -128 && delta < 128,
+                  "Branch displacement {} out of range",
+                  delta);
+    sink.reloc_ebb(RelocKind::PCRel1.into(), func.offsets[destination]);
+    sink.put1(delta as u8);
+}
+
+/// Emit a 32-bit branch displacement to `destination`, relative to the end of the instruction.
+fn disp4<CS: CodeSink + ?Sized>(destination: Ebb, func: &Function, sink: &mut CS) {
+    let delta = func.offsets[destination].wrapping_sub(sink.offset() + 4);
+    sink.reloc_ebb(RelocKind::PCRel4.into(), func.offsets[destination]);
+    sink.put4(delta);
+}
+
+/// The addend of a PC-relative relocation of a 4-byte hole at the end of the instruction.
+const PCREL4_ADDEND: Addend = -4;
+
+/// Get the name of the symbol referenced by a `func_addr` or `globalsym_addr` instruction.
+fn symbol_name(func: &Function, inst: Inst) -> &ExternalName {
+    match func.dfg[inst] {
+        InstructionData::FuncAddr { func_ref, .. } => &func.dfg.ext_funcs[func_ref].name,
+        InstructionData::UnaryGlobalVar { global_var, .. } => {
+            &func.dfg.global_vars[global_var].name
+        }
+        _ => bad_encoding(func, inst),
+    }
+}
+
+/// Get the offset of the stack slot holding `value` from the stack pointer.
+fn stack_offset(func: &Function, value: Value) -> u32 {
+    func.stack_offsets[value_stack(func, value)]
+}
+
+/// The `rsp` register, which can only be encoded as a base register with a SIB byte.
+const RSP: RegUnit = 4;
+
+/// The `r11` scratch register used by absolute tail calls.
+const R11: RegUnit = 11;
+
+/// Get the `jcc` condition code for an integer comparison.
+fn icc2opc(cond: IntCC) -> u16 {
+    use ir::condcodes::IntCC::*;
+    match cond {
+        Equal => 0x4,
+        NotEqual => 0x5,
+        SignedLessThan => 0xc,
+        SignedGreaterThanOrEqual => 0xd,
+        SignedGreaterThan => 0xf,
+        SignedLessThanOrEqual => 0xe,
+        UnsignedLessThan => 0x2,
+        UnsignedGreaterThanOrEqual => 0x3,
+        UnsignedGreaterThan => 0x7,
+        UnsignedLessThanOrEqual => 0x6,
+    }
+}
+
+/// Get the `setcc` condition code for a floating point comparison after `ucomiss` or `ucomisd`.
+///
+/// Only the conditions in `supported_floatccs` in `meta/isa/intel/recipes.py` are supported.
+fn fcc2opc(cond: FloatCC) -> u16 {
+    use ir::condcodes::FloatCC::*;
+    match cond {
+        Ordered => 0xb,
+        Unordered => 0xa,
+        OrderedNotEqual => 0x5,
+        UnorderedOrEqual => 0x4,
+        GreaterThan => 0x7,
+        GreaterThanOrEqual => 0x3,
+        UnorderedOrLessThan => 0x2,
+        UnorderedOrLessThanOrEqual => 0x6,
+        _ => panic!("{} not supported by setcc", cond),
+    }
+}
+
+// Operand formats shared by the recipe pairs.
+//
+// The `put` argument emits the prefixes and the opcode.
+
+/// Two-address binary operation with the result tied to the first operand in the r/m field.
+fn emit_rr<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &RegDiversions,
+                                  sink: &mut CS,
+                                  put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, args[0]);
+        let in_reg1 = value_reg(func, divert, args[1]);
+        put(func.encodings[inst].bits(), rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Two-address binary operation with the result tied to the first operand in the reg field.
+fn emit_rrx<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &RegDiversions,
+                                   sink: &mut CS,
+                                   put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, args[0]);
+        let in_reg1 = value_reg(func, divert, args[1]);
+        put(func.encodings[inst].bits(), rex2(in_reg1, in_reg0), sink);
+        modrm_rr(in_reg1, in_reg0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Copy the low bit of a `b1` register to the carry flag with `bt reg, 0`.
+fn put_bt0<CS: CodeSink + ?Sized>(reg: RegUnit, sink: &mut CS, put: fn(u16, u8, &mut CS)) {
+    // 0F BA /4 ib
+    put(0x4ba, rex1(reg), sink);
+    modrm_r_bits(reg, 0x4ba, sink);
+    sink.put1(0);
+}
+
+/// Set a `b1` register to the carry flag with `setc` and `movzx`.
+fn put_setc<CS: CodeSink + ?Sized>(reg: RegUnit, sink: &mut CS, put: fn(u16, u8, &mut CS)) {
+    // setc reg8
+    put(0x92, rex1(reg), sink);
+    modrm_rr(reg, 0, sink);
+
+    // movzx reg32, reg8
+    put(0xb6, rex2(reg, reg), sink);
+    modrm_rr(reg, reg, sink);
+}
+
+/// Two-address arithmetic producing a carry or borrow flag in the second result.
+///
+/// The `put_op2` function emits the two-byte opcodes of the `setc` and `movzx` instructions.
+fn emit_rout<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &RegDiversions,
+                                    sink: &mut CS,
+                                    put: fn(u16, u8, &mut CS),
+                                    put_op2: fn(u16, u8, &mut CS)) {
+    if let InstructionData::BinaryOverflow { args, second_result, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, args[0]);
+        let in_reg1 = value_reg(func, divert, args[1]);
+        let out_reg1 = value_reg(func, divert, second_result.unwrap());
+        put(func.encodings[inst].bits(), rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+        put_setc(out_reg1, sink, put_op2);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Two-address arithmetic consuming a carry or borrow flag in the third operand.
+fn emit_rin<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &RegDiversions,
+                                   sink: &mut CS,
+                                   put: fn(u16, u8, &mut CS),
+                                   put_op2: fn(u16, u8, &mut CS)) {
+    if let InstructionData::Ternary { args, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, args[0]);
+        let in_reg1 = value_reg(func, divert, args[1]);
+        let in_reg2 = value_reg(func, divert, args[2]);
+        put_bt0(in_reg2, sink, put_op2);
+        put(func.encodings[inst].bits(), rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Two-address arithmetic with both a carry or borrow input and output.
+fn emit_rio<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &RegDiversions,
+                                   sink: &mut CS,
+                                   put: fn(u16, u8, &mut CS),
+                                   put_op2: fn(u16, u8, &mut CS)) {
+    if let InstructionData::TernaryOverflow { ref data, second_result, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, data.args[0]);
+        let in_reg1 = value_reg(func, divert, data.args[1]);
+        let in_reg2 = value_reg(func, divert, data.args[2]);
+        let out_reg1 = value_reg(func, divert, second_result.unwrap());
+        put_bt0(in_reg2, sink, put_op2);
+        put(func.encodings[inst].bits(), rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+        put_setc(out_reg1, sink, put_op2);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Two-address arithmetic that jumps over a `ud2` unless it overflowed.
+fn emit_rtrap<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &RegDiversions,
+                                     sink: &mut CS,
+                                     put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::BinaryTrap { opcode, args, code, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, args[0]);
+        let in_reg1 = value_reg(func, divert, args[1]);
+        put(func.encodings[inst].bits(), rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+
+        // `jno` over the `ud2` for the signed operations, `jnc` for the unsigned ones.
+        let cc = match opcode {
+            Opcode::SaddOverflowTrap | Opcode::SsubOverflowTrap => 0x1,
+            _ => 0x3,
+        };
+        sink.put1(0x70 | cc);
+        sink.put1(2);
+        put_ud2(code, func.srcloc(inst), sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Shift or rotate by the count in `cl`.
+fn emit_rc<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &RegDiversions,
+                                  sink: &mut CS,
+                                  put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let in_reg0 = value_reg(func, divert, args[0]);
+        put(bits, rex1(in_reg0), sink);
+        modrm_r_bits(in_reg0, bits, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Division of `rdx:rax` by the register in the third operand.
+///
+/// The `div` and `idiv` instructions fault when the divisor is zero, so they are reported as
+/// trap sites.
+fn emit_div<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &RegDiversions,
+                                   sink: &mut CS,
+                                   put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::TernaryOverflow { ref data, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let in_reg2 = value_reg(func, divert, data.args[2]);
+        sink.trap(TrapCode::IntegerDivisionByZero, func.srcloc(inst));
+        put(bits, rex1(in_reg2), sink);
+        modrm_r_bits(in_reg2, bits, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Widening multiplication of `rax` by the register in the second operand.
+fn emit_mulx<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &RegDiversions,
+                                    sink: &mut CS,
+                                    put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::BinaryOverflow { args, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let in_reg1 = value_reg(func, divert, args[1]);
+        put(bits, rex1(in_reg1), sink);
+        modrm_r_bits(in_reg1, bits, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Binary operation with an 8-bit or 32-bit immediate, depending on `imm_bytes`.
+fn emit_ri<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &RegDiversions,
+                                  sink: &mut CS,
+                                  put: fn(u16, u8, &mut CS),
+                                  imm_bytes: u8) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let in_reg0 = value_reg(func, divert, arg);
+        put(bits, rex1(in_reg0), sink);
+        modrm_r_bits(in_reg0, bits, sink);
+        let imm: i64 = imm.into();
+        if imm_bytes == 1 {
+            sink.put1(imm as u8);
+        } else {
+            sink.put4(imm as u32);
+        }
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Integer constant with the register in the low bits of the opcode.
+fn emit_pu_id<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &RegDiversions,
+                                     sink: &mut CS,
+                                     put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        let bits = func.encodings[inst].bits() | (out_reg0 & 7);
+        put(bits, rex1(out_reg0), sink);
+        let imm: i64 = imm.into();
+        sink.put4(imm as u32);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Boolean constant with the register in the low bits of the opcode, emitted as 0 or 1.
+fn emit_pu_bool<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &RegDiversions,
+                                       sink: &mut CS,
+                                       put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::UnaryBool { imm, .. } = func.dfg[inst] {
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        let bits = func.encodings[inst].bits() | (out_reg0 & 7);
+        put(bits, rex1(out_reg0), sink);
+        sink.put4(imm as u32);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Unary operation with the operand in the reg field and the result in the r/m field.
+fn emit_umr<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &RegDiversions,
+                                   sink: &mut CS,
+                                   put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, arg);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        put(func.encodings[inst].bits(), rex2(out_reg0, in_reg0), sink);
+        modrm_rr(out_reg0, in_reg0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Unary operation with the result in the reg field and the operand in the r/m field.
+fn emit_urm<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &RegDiversions,
+                                   sink: &mut CS,
+                                   put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, arg);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        put(func.encodings[inst].bits(), rex2(in_reg0, out_reg0), sink);
+        modrm_rr(in_reg0, out_reg0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Register diversion with the source in the reg field and the destination in the r/m field when
+/// `src_in_reg` is set, or the other way around.
+fn emit_rmov<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    sink: &mut CS,
+                                    put: fn(u16, u8, &mut CS),
+                                    src_in_reg: bool) {
+    if let InstructionData::RegMove { src, dst, .. } = func.dfg[inst] {
+        let (rm, reg) = if src_in_reg { (dst, src) } else { (src, dst) };
+        put(func.encodings[inst].bits(), rex2(rm, reg), sink);
+        modrm_rr(rm, reg, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Emit the ModR/M and SIB bytes and the displacement for a `base + disp` address.
+fn put_base_disp<CS: CodeSink + ?Sized>(reg: RegUnit,
+                                        base: RegUnit,
+                                        disp: i32,
+                                        disp_bytes: u8,
+                                        sink: &mut CS) {
+    if disp_bytes == 1 {
+        modrm_sib_disp8(reg, sink);
+        sib_noindex(base, sink);
+        sink.put1(disp as u8);
+    } else {
+        modrm_sib_disp32(reg, sink);
+        sib_noindex(base, sink);
+        sink.put4(disp as u32);
+    }
+}
+
+/// Emit the ModR/M and SIB bytes and the displacement for a `base + (index << shift) + disp`
+/// address.
+fn put_index_disp<CS: CodeSink + ?Sized>(reg: RegUnit,
+                                         base: RegUnit,
+                                         index: RegUnit,
+                                         shift: u8,
+                                         disp: i32,
+                                         disp_bytes: u8,
+                                         sink: &mut CS) {
+    if disp_bytes == 1 {
+        modrm_sib_disp8(reg, sink);
+        sib(shift, index, base, sink);
+        sink.put1(disp as u8);
+    } else {
+        modrm_sib_disp32(reg, sink);
+        sib(shift, index, base, sink);
+        sink.put4(disp as u32);
+    }
+}
+
+/// Report a memory access as a trap site unless it has the `notrap` flag.
+///
+/// Heap accesses may rely on a guard page to catch out-of-bounds addresses, and the runtime needs
+/// to know which instructions can fault that way.
+fn trap_heap_access<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           flags: MemFlags,
+                                           sink: &mut CS) {
+    if !flags.notrap() {
+        sink.trap(TrapCode::HeapOutOfBounds, func.srcloc(inst));
+    }
+}
+
+/// Load from a register base address plus a displacement.
+fn emit_ld<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &RegDiversions,
+                                  sink: &mut CS,
+                                  put: fn(u16, u8, &mut CS),
+                                  disp_bytes: u8) {
+    if let InstructionData::Load { flags, arg, offset, .. } = func.dfg[inst] {
+        trap_heap_access(func, inst, flags, sink);
+        let base = value_reg(func, divert, arg);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        put(func.encodings[inst].bits(), rex2(base, out_reg0), sink);
+        put_base_disp(out_reg0, base, offset, disp_bytes, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Store to a register base address plus a displacement.
+fn emit_st<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &RegDiversions,
+                                  sink: &mut CS,
+                                  put: fn(u16, u8, &mut CS),
+                                  disp_bytes: u8) {
+    if let InstructionData::Store { flags, args, offset, .. } = func.dfg[inst] {
+        trap_heap_access(func, inst, flags, sink);
+        let in_reg0 = value_reg(func, divert, args[0]);
+        let base = value_reg(func, divert, args[1]);
+        put(func.encodings[inst].bits(), rex2(base, in_reg0), sink);
+        put_base_disp(in_reg0, base, offset, disp_bytes, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Atomic load from the address in a register.
+fn emit_ald<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &RegDiversions,
+                                   sink: &mut CS,
+                                   put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::AtomicLoad { arg, .. } = func.dfg[inst] {
+        let base = value_reg(func, divert, arg);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        put(func.encodings[inst].bits(), rex2(base, out_reg0), sink);
+        put_base_disp(out_reg0, base, 0, 1, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Atomic memory operation on the address in the first register operand, with the last register
+/// operand in the reg field of the ModR/M byte.
+fn emit_amr<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &RegDiversions,
+                                   sink: &mut CS,
+                                   put: fn(u16, u8, &mut CS)) {
+    let (addr, arg) = match func.dfg[inst] {
+        InstructionData::AtomicRmw { args, .. } => (args[0], args[1]),
+        InstructionData::AtomicCas { args, .. } => (args[0], args[2]),
+        _ => bad_encoding(func, inst),
+    };
+    let base = value_reg(func, divert, addr);
+    let in_reg = value_reg(func, divert, arg);
+    put(func.encodings[inst].bits(), rex2(base, in_reg), sink);
+    put_base_disp(in_reg, base, 0, 1, sink);
+}
+
+/// Load from a base register plus a scaled index register plus a displacement.
+fn emit_ld_idx<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &RegDiversions,
+                                      sink: &mut CS,
+                                      put: fn(u16, u8, &mut CS),
+                                      disp_bytes: u8) {
+    if let InstructionData::LoadComplex { ref data, .. } = func.dfg[inst] {
+        trap_heap_access(func, inst, data.flags, sink);
+        let base = value_reg(func, divert, data.args[0]);
+        let index = value_reg(func, divert, data.args[1]);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        put(func.encodings[inst].bits(),
+            rex3(base, out_reg0, index),
+            sink);
+        put_index_disp(out_reg0,
+                       base,
+                       index,
+                       data.shift,
+                       data.offset,
+                       disp_bytes,
+                       sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Store to a base register plus a scaled index register plus a displacement.
+fn emit_st_idx<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &RegDiversions,
+                                      sink: &mut CS,
+                                      put: fn(u16, u8, &mut CS),
+                                      disp_bytes: u8) {
+    if let InstructionData::StoreComplex { ref data, .. } = func.dfg[inst] {
+        trap_heap_access(func, inst, data.flags, sink);
+        let in_reg0 = value_reg(func, divert, data.args[0]);
+        let base = value_reg(func, divert, data.args[1]);
+        let index = value_reg(func, divert, data.args[2]);
+        put(func.encodings[inst].bits(), rex3(base, in_reg0, index), sink);
+        put_index_disp(in_reg0,
+                       base,
+                       index,
+                       data.shift,
+                       data.offset,
+                       disp_bytes,
+                       sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Add an 8-bit or 32-bit immediate to the stack pointer.
+fn emit_adjust_sp<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         sink: &mut CS,
+                                         put: fn(u16, u8, &mut CS),
+                                         imm_bytes: u8) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let imm: i64 = imm.into();
+        put(bits, rex1(RSP), sink);
+        modrm_r_bits(RSP, bits, sink);
+        if imm_bytes == 1 {
+            sink.put1(imm as u8);
+        } else {
+            sink.put4(imm as u32);
+        }
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Compare the stack pointer to a register with `cmp rsp, r`.
+fn emit_cmp_sp<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &RegDiversions,
+                                      sink: &mut CS,
+                                      put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let reg = value_reg(func, divert, arg);
+        put(func.encodings[inst].bits(), rex2(RSP, reg), sink);
+        modrm_rr(RSP, reg, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Store a register to its spill slot, addressed relative to the stack pointer.
+fn emit_spill<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &RegDiversions,
+                                     sink: &mut CS,
+                                     put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, arg);
+        let offset = stack_offset(func, func.dfg.first_result(inst));
+        put(func.encodings[inst].bits(), rex2(RSP, in_reg0), sink);
+        put_base_disp(in_reg0, RSP, offset as i32, 4, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Load a register from a spill slot, addressed relative to the stack pointer.
+fn emit_fill<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &RegDiversions,
+                                    sink: &mut CS,
+                                    put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let offset = stack_offset(func, arg);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        put(func.encodings[inst].bits(), rex2(RSP, out_reg0), sink);
+        put_base_disp(out_reg0, RSP, offset as i32, 4, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Test a register against itself and branch with an 8-bit or 32-bit displacement.
+fn emit_tjcc<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &RegDiversions,
+                                    sink: &mut CS,
+                                    put: fn(u16, u8, &mut CS),
+                                    disp_bytes: u8) {
+    if let InstructionData::Branch { opcode, destination, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, func.dfg.inst_args(inst)[0][0]);
+        put(func.encodings[inst].bits(), rex2(in_reg0, in_reg0), sink);
+        modrm_rr(in_reg0, in_reg0, sink);
+
+        // `jz` or `jnz`.
+        let cc = if opcode == Opcode::Brz { 0x4 } else { 0x5 };
+        put_jcc(cc, destination, func, disp_bytes, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Emit a `ud2` instruction and report it as a trap site.
+fn put_ud2<CS: CodeSink + ?Sized>(code: TrapCode, srcloc: SourceLoc, sink: &mut CS) {
+    sink.trap(code, srcloc);
+    sink.put1(0x0f);
+    sink.put1(0x0b);
+}
+
+/// Test a register and jump over a `ud2` unless the trap condition holds.
+fn emit_ttrap<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &RegDiversions,
+                                     sink: &mut CS,
+                                     put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::CondTrap { opcode, arg, code, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, arg);
+        put(func.encodings[inst].bits(), rex2(in_reg0, in_reg0), sink);
+        modrm_rr(in_reg0, in_reg0, sink);
+
+        // `jnz` over the `ud2` for `trapz`, `jz` for `trapnz`.
+        let cc = if opcode == Opcode::Trapz { 0x5 } else { 0x4 };
+        sink.put1(0x70 | cc);
+        sink.put1(2);
+        put_ud2(code, func.srcloc(inst), sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Compare two registers and branch with an 8-bit or 32-bit displacement.
+fn emit_cmpjcc<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &RegDiversions,
+                                      sink: &mut CS,
+                                      put: fn(u16, u8, &mut CS),
+                                      disp_bytes: u8) {
+    if let InstructionData::BranchIcmp { cond, destination, .. } = func.dfg[inst] {
+        let args = func.dfg.inst_args(inst)[0];
+        let in_reg0 = value_reg(func, divert, args[0]);
+        let in_reg1 = value_reg(func, divert, args[1]);
+        put(func.encodings[inst].bits(), rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+        put_jcc(icc2opc(cond), destination, func, disp_bytes, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Emit a `jcc` instruction with the condition code `cc`: `7x cb` or `0F 8x cd`.
+fn put_jcc<CS: CodeSink + ?Sized>(cc: u16,
+                                  destination: Ebb,
+                                  func: &Function,
+                                  disp_bytes: u8,
+                                  sink: &mut CS) {
+    if disp_bytes == 1 {
+        sink.put1(0x70 | cc as u8);
+        disp1(destination, func, sink);
+    } else {
+        sink.put1(0x0f);
+        sink.put1(0x80 | cc as u8);
+        disp4(destination, func, sink);
+    }
+}
+
+/// Test the condition register and conditionally move the second operand over the third.
+///
+/// The `put_test` function emits the `test` instruction with the opcode in the encoding bits
+/// replaced.
+fn emit_tcmov<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &RegDiversions,
+                                     sink: &mut CS,
+                                     put_test: fn(u16, u8, &mut CS),
+                                     put_cmov: fn(u16, u8, &mut CS)) {
+    if let InstructionData::Ternary { args, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let cond = value_reg(func, divert, args[0]);
+        let in_reg1 = value_reg(func, divert, args[1]);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+
+        // test cond, cond
+        put_test((bits & 0xff00) | 0x85, rex2(cond, cond), sink);
+        modrm_rr(cond, cond, sink);
+
+        // cmovnz out, in1
+        put_cmov(bits, rex2(in_reg1, out_reg0), sink);
+        modrm_rr(in_reg1, out_reg0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Set the result register to 0 or 1 by testing the CPU flags with `setcc` and `movzx`.
+///
+/// The `put` function emits the two-byte opcodes of both instructions.
+fn emit_seti<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &RegDiversions,
+                                    sink: &mut CS,
+                                    put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::IntCond { cond, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+
+        // setcc out8
+        put(bits | icc2opc(cond), rex1(out_reg0), sink);
+        modrm_rr(out_reg0, 0, sink);
+
+        // movzx out32, out8
+        put(0xb6, rex2(out_reg0, out_reg0), sink);
+        modrm_rr(out_reg0, out_reg0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Compare two registers, and set the result register to 0 or 1.
+///
+/// The `put_cmp` function emits the `cmp` opcode, and `put_op2` emits the two-byte opcodes of the
+/// `setcc` and `movzx` instructions.
+fn emit_icscc<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &RegDiversions,
+                                     sink: &mut CS,
+                                     put_cmp: fn(u16, u8, &mut CS),
+                                     put_op2: fn(u16, u8, &mut CS)) {
+    if let InstructionData::IntCompare { cond, args, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, args[0]);
+        let in_reg1 = value_reg(func, divert, args[1]);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+
+        // cmp in0, in1
+        put_cmp(func.encodings[inst].bits(), rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+
+        // setcc out8
+        put_op2(0x90 | icc2opc(cond), rex1(out_reg0), sink);
+        modrm_rr(out_reg0, 0, sink);
+
+        // movzx out32, out8
+        put_op2(0xb6, rex2(out_reg0, out_reg0), sink);
+        modrm_rr(out_reg0, out_reg0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Conditionally move the second operand over the third with `cmovcc`, testing the CPU flags in
+/// the first operand.
+fn emit_cmov<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &RegDiversions,
+                                    sink: &mut CS,
+                                    put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::IntSelect { cond, args, .. } = func.dfg[inst] {
+        let in_reg1 = value_reg(func, divert, args[1]);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+
+        // cmovcc out, in1
+        put(func.encodings[inst].bits() | icc2opc(cond),
+            rex2(in_reg1, out_reg0),
+            sink);
+        modrm_rr(in_reg1, out_reg0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Materialize a symbol address as an absolute immediate of `imm_bytes` bytes, leaving a hole for
+/// the relocation.
+fn emit_symaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &RegDiversions,
+                                       sink: &mut CS,
+                                       put: fn(u16, u8, &mut CS),
+                                       imm_bytes: u8) {
+    let name = symbol_name(func, inst);
+    let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+    let bits = func.encodings[inst].bits() | (out_reg0 & 7);
+    put(bits, rex1(out_reg0), sink);
+    if imm_bytes == 4 {
+        sink.reloc_external(RelocKind::Abs4.into(), name, 0);
+        sink.put4(0);
+    } else {
+        sink.reloc_external(RelocKind::Abs8.into(), name, 0);
+        sink.put8(0);
+    }
+}
+
+/// Compute a symbol address relative to `rip`, leaving a hole for the `kind` relocation.
+fn emit_riprel<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &RegDiversions,
+                                      sink: &mut CS,
+                                      kind: RelocKind) {
+    let name = symbol_name(func, inst);
+    let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+    put_rexop1(func.encodings[inst].bits(), rex2(0, out_reg0), sink);
+    modrm_riprel(out_reg0, sink);
+    sink.reloc_external(kind.into(), name, PCREL4_ADDEND);
+    sink.put4(0);
+}
+
+/// Call or tail call a function with `call rel32` or `jmp rel32`, leaving a hole for the `kind`
+/// relocation.
+fn emit_call<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    sink: &mut CS,
+                                    kind: RelocKind) {
+    if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+        put_op1(func.encodings[inst].bits(), 0, sink);
+        sink.reloc_external(kind.into(),
+                            &func.dfg.ext_funcs[func_ref].name,
+                            PCREL4_ADDEND);
+        sink.put4(0);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Call or tail call a function at an absolute address with `movabs r11, imm64` followed by
+/// `call r11` or `jmp r11`, leaving a hole for an Abs8 relocation.
+fn emit_call_abs<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+    if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+        // REX.W B8+r io: The register is encoded in the opcode byte.
+        put_rexop1(0x8b8 | (R11 & 7), rex1(R11), sink);
+        sink.reloc_external(RelocKind::Abs8.into(), &func.dfg.ext_funcs[func_ref].name, 0);
+        sink.put8(0);
+        let bits = func.encodings[inst].bits();
+        put_rexop1(bits, rex1(R11), sink);
+        modrm_r_bits(R11, bits, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Call a function through the callee address in a register with `call r/m`.
+fn emit_call_r<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &RegDiversions,
+                                      sink: &mut CS,
+                                      put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::IndirectCall { .. } = func.dfg[inst] {
+        let callee = value_reg(func, divert, func.dfg.inst_args(inst)[0][0]);
+        let bits = func.encodings[inst].bits();
+        put(bits, rex1(callee), sink);
+        modrm_r_bits(callee, bits, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Get the jump table referenced by a `jump_table_base` instruction.
+fn jump_table(func: &Function, inst: Inst) -> JumpTable {
+    match func.dfg[inst] {
+        InstructionData::BranchTableBase { table, .. } => table,
+        _ => bad_encoding(func, inst),
+    }
+}
+
+/// Load a jump table entry from `[base + index*size]`.
+fn emit_jt_entry<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &RegDiversions,
+                                        sink: &mut CS,
+                                        put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::BranchTableEntry { args, imm, .. } = func.dfg[inst] {
+        let index = value_reg(func, divert, args[0]);
+        let base = value_reg(func, divert, args[1]);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        put(func.encodings[inst].bits(),
+            rex3(base, out_reg0, index),
+            sink);
+        put_index_disp(out_reg0,
+                       base,
+                       index,
+                       imm.trailing_zeros() as u8,
+                       0,
+                       1,
+                       sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Jump to the address in a register with `jmp r/m`.
+fn emit_indirect_jmp<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &RegDiversions,
+                                            sink: &mut CS,
+                                            put: fn(u16, u8, &mut CS)) {
+    if let InstructionData::BranchTable { arg, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let in_reg0 = value_reg(func, divert, arg);
+        put(bits, rex1(in_reg0), sink);
+        modrm_r_bits(in_reg0, bits, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Binary floating point operation with the result tied to the first operand in the reg field.
+fn emit_fa<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &RegDiversions,
+                                  sink: &mut CS,
+                                  put: fn(u16, u8, &mut CS)) {
+    emit_rrx(func, inst, divert, sink, put)
+}
+
+/// Compare two XMM registers, and set the result register to 0 or 1.
+///
+/// The `put_cmp` function emits the `ucomiss` or `ucomisd` opcode, and `put_op2` emits the two-byte
+/// opcodes of the `setcc` and `movzx` instructions.
+fn emit_fcscc<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &RegDiversions,
+                                     sink: &mut CS,
+                                     put_cmp: fn(u16, u8, &mut CS),
+                                     put_op2: fn(u16, u8, &mut CS)) {
+    if let InstructionData::FloatCompare { cond, args, .. } = func.dfg[inst] {
+        let in_reg0 = value_reg(func, divert, args[0]);
+        let in_reg1 = value_reg(func, divert, args[1]);
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+
+        // ucomiss in0, in1
+        put_cmp(func.encodings[inst].bits(), rex2(in_reg1, in_reg0), sink);
+        modrm_rr(in_reg1, in_reg0, sink);
+
+        // setcc out8
+        put_op2(0x90 | fcc2opc(cond), rex1(out_reg0), sink);
+        modrm_rr(out_reg0, 0, sink);
+
+        // movzx out32, out8
+        put_op2(0xb6, rex2(out_reg0, out_reg0), sink);
+        modrm_rr(out_reg0, out_reg0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+/// Round an XMM register with the rounding mode immediate taken from the encoding bits.
+fn emit_furmi_rnd<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &RegDiversions,
+                                         sink: &mut CS,
+                                         put: fn(u16, u8, &mut CS)) {
+    emit_urm(func, inst, divert, sink, put);
+    let bits = func.encodings[inst].bits();
+    sink.put1(((bits >> 8) & 7) as u8);
+}
+
+// The recipe emitters called by `emit_inst()`.
+
+fn recipe_op1rr<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    emit_rr(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1rr<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_rr(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1rout<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    emit_rout(func, inst, divert, sink, put_op1, put_op2)
+}
+
+fn recipe_rexop1rout<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_rout(func, inst, divert, sink, put_rexop1, put_rexop2)
+}
+
+fn recipe_op1rin<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    emit_rin(func, inst, divert, sink, put_op1, put_op2)
+}
+
+fn recipe_rexop1rin<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_rin(func, inst, divert, sink, put_rexop1, put_rexop2)
+}
+
+fn recipe_op1rio<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    emit_rio(func, inst, divert, sink, put_op1, put_op2)
+}
+
+fn recipe_rexop1rio<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_rio(func, inst, divert, sink, put_rexop1, put_rexop2)
+}
+
+fn recipe_op1rtrap<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_rtrap(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1rtrap<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_rtrap(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op2rr<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    emit_rrx(func, inst, divert, sink, put_op2)
+}
+
+fn recipe_rexop2rr<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_rrx(func, inst, divert, sink, put_rexop2)
+}
+
+fn recipe_op1rc<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    emit_rc(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1rc<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_rc(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1div<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    emit_div(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1div<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_div(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1mulx<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    emit_mulx(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1mulx<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_mulx(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1rib<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    emit_ri(func, inst, divert, sink, put_op1, 1)
+}
+
+fn recipe_rexop1rib<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_ri(func, inst, divert, sink, put_rexop1, 1)
+}
+
+fn recipe_op1rid<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    emit_ri(func, inst, divert, sink, put_op1, 4)
+}
+
+fn recipe_rexop1rid<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_ri(func, inst, divert, sink, put_rexop1, 4)
+}
+
+fn recipe_op1pu_id<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_pu_id(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1pu_id<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_pu_id(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1pu_bool<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_pu_bool(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1pu_bool<CS: CodeSink + ?Sized>(func: &Function,
+                                               inst: Inst,
+                                               divert: &mut RegDiversions,
+                                               sink: &mut CS) {
+    emit_pu_bool(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_rexop1u_id<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        put_rexop1(bits, rex1(out_reg0), sink);
+        modrm_r_bits(out_reg0, bits, sink);
+        let imm: i64 = imm.into();
+        sink.put4(imm as u32);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_rexop1pu_iq<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+        let bits = func.encodings[inst].bits() | (out_reg0 & 7);
+        put_rexop1(bits, rex1(out_reg0), sink);
+        let imm: i64 = imm.into();
+        sink.put8(imm as u64);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_op1umr<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    emit_umr(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1umr<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_umr(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1rmov<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         _divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    emit_rmov(func, inst, sink, put_op1, true)
+}
+
+fn recipe_rexop1rmov<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            _divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_rmov(func, inst, sink, put_rexop1, true)
+}
+
+fn recipe_op1lddisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_ld(func, inst, divert, sink, put_op1, 1)
+}
+
+fn recipe_rexop1lddisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                               inst: Inst,
+                                               divert: &mut RegDiversions,
+                                               sink: &mut CS) {
+    emit_ld(func, inst, divert, sink, put_rexop1, 1)
+}
+
+fn recipe_op1lddisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_ld(func, inst, divert, sink, put_op1, 4)
+}
+
+fn recipe_rexop1lddisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    emit_ld(func, inst, divert, sink, put_rexop1, 4)
+}
+
+fn recipe_op1stdisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_st(func, inst, divert, sink, put_op1, 1)
+}
+
+fn recipe_rexop1stdisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                               inst: Inst,
+                                               divert: &mut RegDiversions,
+                                               sink: &mut CS) {
+    emit_st(func, inst, divert, sink, put_rexop1, 1)
+}
+
+fn recipe_op1stdisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_st(func, inst, divert, sink, put_op1, 4)
+}
+
+fn recipe_rexop1stdisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    emit_st(func, inst, divert, sink, put_rexop1, 4)
+}
+
+fn recipe_op1ldidxdisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                               inst: Inst,
+                                               divert: &mut RegDiversions,
+                                               sink: &mut CS) {
+    emit_ld_idx(func, inst, divert, sink, put_op1, 1)
+}
+
+fn recipe_rexop1ldidxdisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                                  inst: Inst,
+                                                  divert: &mut RegDiversions,
+                                                  sink: &mut CS) {
+    emit_ld_idx(func, inst, divert, sink, put_rexop1, 1)
+}
+
+fn recipe_op1ldidxdisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    emit_ld_idx(func, inst, divert, sink, put_op1, 4)
+}
+
+fn recipe_rexop1ldidxdisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                                   inst: Inst,
+                                                   divert: &mut RegDiversions,
+                                                   sink: &mut CS) {
+    emit_ld_idx(func, inst, divert, sink, put_rexop1, 4)
+}
+
+fn recipe_op1stidxdisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                               inst: Inst,
+                                               divert: &mut RegDiversions,
+                                               sink: &mut CS) {
+    emit_st_idx(func, inst, divert, sink, put_op1, 1)
+}
+
+fn recipe_rexop1stidxdisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                                  inst: Inst,
+                                                  divert: &mut RegDiversions,
+                                                  sink: &mut CS) {
+    emit_st_idx(func, inst, divert, sink, put_rexop1, 1)
+}
+
+fn recipe_op1stidxdisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    emit_st_idx(func, inst, divert, sink, put_op1, 4)
+}
+
+fn recipe_rexop1stidxdisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                                   inst: Inst,
+                                                   divert: &mut RegDiversions,
+                                                   sink: &mut CS) {
+    emit_st_idx(func, inst, divert, sink, put_rexop1, 4)
+}
+
+fn recipe_op1ald<CS: CodeSink + ?Sized>(func: &Function,
+                 inst: Inst,
+                 divert: &mut RegDiversions,
+                 sink: &mut CS) {
+    emit_ald(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1ald<CS: CodeSink + ?Sized>(func: &Function,
+                    inst: Inst,
+                    divert: &mut RegDiversions,
+                    sink: &mut CS) {
+    emit_ald(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1ast<CS: CodeSink + ?Sized>(func: &Function,
+                 inst: Inst,
+                 divert: &mut RegDiversions,
+                 sink: &mut CS) {
+    emit_amr(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1ast<CS: CodeSink + ?Sized>(func: &Function,
+                    inst: Inst,
+                    divert: &mut RegDiversions,
+                    sink: &mut CS) {
+    emit_amr(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1armw<CS: CodeSink + ?Sized>(func: &Function,
+                  inst: Inst,
+                  divert: &mut RegDiversions,
+                  sink: &mut CS) {
+    emit_amr(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1armw<CS: CodeSink + ?Sized>(func: &Function,
+                     inst: Inst,
+                     divert: &mut RegDiversions,
+                     sink: &mut CS) {
+    emit_amr(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_lkop2armw<CS: CodeSink + ?Sized>(func: &Function,
+                    inst: Inst,
+                    divert: &mut RegDiversions,
+                    sink: &mut CS) {
+    emit_amr(func, inst, divert, sink, put_lkop2)
+}
+
+fn recipe_lkrexop2armw<CS: CodeSink + ?Sized>(func: &Function,
+                       inst: Inst,
+                       divert: &mut RegDiversions,
+                       sink: &mut CS) {
+    emit_amr(func, inst, divert, sink, put_lkrexop2)
+}
+
+fn recipe_lkop2acas<CS: CodeSink + ?Sized>(func: &Function,
+                    inst: Inst,
+                    divert: &mut RegDiversions,
+                    sink: &mut CS) {
+    emit_amr(func, inst, divert, sink, put_lkop2)
+}
+
+fn recipe_lkrexop2acas<CS: CodeSink + ?Sized>(func: &Function,
+                       inst: Inst,
+                       divert: &mut RegDiversions,
+                       sink: &mut CS) {
+    emit_amr(func, inst, divert, sink, put_lkrexop2)
+}
+
+fn recipe_op1probe<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          _divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let offset: i64 = imm.into();
+        put_op1(bits, 0, sink);
+        put_base_disp((bits >> 8) & 7, RSP, offset as i32, 4, sink);
+        sink.put1(0);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_op1adjustsp_ib<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                _divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    emit_adjust_sp(func, inst, sink, put_op1, 1)
+}
+
+fn recipe_rexop1adjustsp_ib<CS: CodeSink + ?Sized>(func: &Function,
+                                                   inst: Inst,
+                                                   _divert: &mut RegDiversions,
+                                                   sink: &mut CS) {
+    emit_adjust_sp(func, inst, sink, put_rexop1, 1)
+}
+
+fn recipe_op1adjustsp_id<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                _divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    emit_adjust_sp(func, inst, sink, put_op1, 4)
+}
+
+fn recipe_rexop1adjustsp_id<CS: CodeSink + ?Sized>(func: &Function,
+                                                   inst: Inst,
+                                                   _divert: &mut RegDiversions,
+                                                   sink: &mut CS) {
+    emit_adjust_sp(func, inst, sink, put_rexop1, 4)
+}
+
+fn recipe_op1cmpsp<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_cmp_sp(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1cmpsp<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_cmp_sp(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1spill<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_spill(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1spill<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_spill(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1fill<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    emit_fill(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1fill<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_fill(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1jmpb<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         _divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::Jump { destination, .. } = func.dfg[inst] {
+        sink.put1(0xeb);
+        disp1(destination, func, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_op1jmpd<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         _divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::Jump { destination, .. } = func.dfg[inst] {
+        sink.put1(0xe9);
+        disp4(destination, func, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_op1tjccb<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_tjcc(func, inst, divert, sink, put_op1, 1)
+}
+
+fn recipe_rexop1tjccb<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_tjcc(func, inst, divert, sink, put_rexop1, 1)
+}
+
+fn recipe_op1tjccd<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_tjcc(func, inst, divert, sink, put_op1, 4)
+}
+
+fn recipe_rexop1tjccd<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_tjcc(func, inst, divert, sink, put_rexop1, 4)
+}
+
+fn recipe_op1cmpjccb<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_cmpjcc(func, inst, divert, sink, put_op1, 1)
+}
+
+fn recipe_rexop1cmpjccb<CS: CodeSink + ?Sized>(func: &Function,
+                                               inst: Inst,
+                                               divert: &mut RegDiversions,
+                                               sink: &mut CS) {
+    emit_cmpjcc(func, inst, divert, sink, put_rexop1, 1)
+}
+
+fn recipe_op1cmpjccd<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_cmpjcc(func, inst, divert, sink, put_op1, 4)
+}
+
+fn recipe_rexop1cmpjccd<CS: CodeSink + ?Sized>(func: &Function,
+                                               inst: Inst,
+                                               divert: &mut RegDiversions,
+                                               sink: &mut CS) {
+    emit_cmpjcc(func, inst, divert, sink, put_rexop1, 4)
+}
+
+fn recipe_op2tcmov<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_tcmov(func, inst, divert, sink, put_op1, put_op2)
+}
+
+fn recipe_rexop2tcmov<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_tcmov(func, inst, divert, sink, put_rexop1, put_rexop2)
+}
+
+fn recipe_op1rcmp<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    emit_rr(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1rcmp<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_rr(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op2seti<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    emit_seti(func, inst, divert, sink, put_op2)
+}
+
+fn recipe_rexop2seti<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_seti(func, inst, divert, sink, put_rexop2)
+}
+
+fn recipe_op1icscc<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_icscc(func, inst, divert, sink, put_op1, put_op2)
+}
+
+fn recipe_rexop1icscc<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_icscc(func, inst, divert, sink, put_rexop1, put_rexop2)
+}
+
+fn recipe_op2cmov<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    emit_cmov(func, inst, divert, sink, put_op2)
+}
+
+fn recipe_rexop2cmov<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_cmov(func, inst, divert, sink, put_rexop2)
+}
+
+fn recipe_op2trap<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         _divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::Trap { code, .. } = func.dfg[inst] {
+        sink.trap(code, func.srcloc(inst));
+        put_op2(func.encodings[inst].bits(), 0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_op1ttrap<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_ttrap(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1ttrap<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_ttrap(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1trapif<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           _divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    if let InstructionData::IntCondTrap { cond, code, .. } = func.dfg[inst] {
+        // Jump over the `ud2` when the trap condition doesn't hold.
+        put_op1(func.encodings[inst].bits() | icc2opc(cond.inverse()), 0, sink);
+        sink.put1(2);
+        put_ud2(code, func.srcloc(inst), sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_op1ret<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::Return { .. } = func.dfg[inst] {
+        put_op1(func.encodings[inst].bits(), 0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_safepoint<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    if let InstructionData::Return { .. } = func.dfg[inst] {
+        let refs = func.dfg.inst_args(inst)[1];
+        sink.add_stackmap(&Stackmap::new(refs, func, divert));
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_op1fnaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_symaddr(func, inst, divert, sink, put_op1, 4)
+}
+
+fn recipe_rexop1fnaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                              inst: Inst,
+                                              divert: &mut RegDiversions,
+                                              sink: &mut CS) {
+    emit_symaddr(func, inst, divert, sink, put_rexop1, 8)
+}
+
+fn recipe_op1gvaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_symaddr(func, inst, divert, sink, put_op1, 4)
+}
+
+fn recipe_rexop1gvaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                              inst: Inst,
+                                              divert: &mut RegDiversions,
+                                              sink: &mut CS) {
+    emit_symaddr(func, inst, divert, sink, put_rexop1, 8)
+}
+
+fn recipe_rexop1pcrel_fnaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                                    inst: Inst,
+                                                    divert: &mut RegDiversions,
+                                                    sink: &mut CS) {
+    emit_riprel(func, inst, divert, sink, RelocKind::PCRel4)
+}
+
+fn recipe_rexop1pcrel_gvaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                                    inst: Inst,
+                                                    divert: &mut RegDiversions,
+                                                    sink: &mut CS) {
+    emit_riprel(func, inst, divert, sink, RelocKind::PCRel4)
+}
+
+fn recipe_rexop1got_fnaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                                  inst: Inst,
+                                                  divert: &mut RegDiversions,
+                                                  sink: &mut CS) {
+    emit_riprel(func, inst, divert, sink, RelocKind::GOTPCRel4)
+}
+
+fn recipe_rexop1got_gvaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                                  inst: Inst,
+                                                  divert: &mut RegDiversions,
+                                                  sink: &mut CS) {
+    emit_riprel(func, inst, divert, sink, RelocKind::GOTPCRel4)
+}
+
+fn recipe_op1tcall<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          _divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_call(func, inst, sink, RelocKind::PCRel4)
+}
+
+fn recipe_op1tcall_plt<CS: CodeSink + ?Sized>(func: &Function,
+                                              inst: Inst,
+                                              _divert: &mut RegDiversions,
+                                              sink: &mut CS) {
+    emit_call(func, inst, sink, RelocKind::PLTRel4)
+}
+
+fn recipe_rexop1tcall_abs<CS: CodeSink + ?Sized>(func: &Function,
+                                                 inst: Inst,
+                                                 _divert: &mut RegDiversions,
+                                                 sink: &mut CS) {
+    emit_call_abs(func, inst, sink)
+}
+
+fn recipe_op1call<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         _divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    emit_call(func, inst, sink, RelocKind::PCRel4)
+}
+
+fn recipe_op1call_plt<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             _divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_call(func, inst, sink, RelocKind::PLTRel4)
+}
+
+fn recipe_rexop1call_abs<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                _divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    emit_call_abs(func, inst, sink)
+}
+
+fn recipe_op1call_r<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_call_r(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1call_r<CS: CodeSink + ?Sized>(func: &Function,
+                                              inst: Inst,
+                                              divert: &mut RegDiversions,
+                                              sink: &mut CS) {
+    emit_call_r(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_rexop1jt_base<CS: CodeSink + ?Sized>(func: &Function,
+                                               inst: Inst,
+                                               divert: &mut RegDiversions,
+                                               sink: &mut CS) {
+    let table = jump_table(func, inst);
+    let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+    put_rexop1(func.encodings[inst].bits(), rex2(0, out_reg0), sink);
+    modrm_riprel(out_reg0, sink);
+    let disp = func.jt_offsets[table].wrapping_sub(sink.offset() + 4);
+    sink.reloc_jt(RelocKind::PCRel4.into(), table);
+    sink.put4(disp);
+}
+
+fn recipe_op1jt_base<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    let table = jump_table(func, inst);
+    let out_reg0 = value_reg(func, divert, func.dfg.first_result(inst));
+    let bits = func.encodings[inst].bits() | (out_reg0 & 7);
+    put_op1(bits, rex1(out_reg0), sink);
+    sink.reloc_jt(RelocKind::Abs4.into(), table);
+    sink.put4(func.jt_offsets[table]);
+}
+
+fn recipe_op1jt_entry<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_jt_entry(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1jt_entry<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    emit_jt_entry(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_op1indirect_jmp<CS: CodeSink + ?Sized>(func: &Function,
+                                                 inst: Inst,
+                                                 divert: &mut RegDiversions,
+                                                 sink: &mut CS) {
+    emit_indirect_jmp(func, inst, divert, sink, put_op1)
+}
+
+fn recipe_rexop1indirect_jmp<CS: CodeSink + ?Sized>(func: &Function,
+                                                    inst: Inst,
+                                                    divert: &mut RegDiversions,
+                                                    sink: &mut CS) {
+    emit_indirect_jmp(func, inst, divert, sink, put_rexop1)
+}
+
+fn recipe_mp2fa<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    emit_fa(func, inst, divert, sink, put_mp2)
+}
+
+fn recipe_rexmp2fa<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_fa(func, inst, divert, sink, put_rexmp2)
+}
+
+fn recipe_mp2frurm<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_urm(func, inst, divert, sink, put_mp2)
+}
+
+fn recipe_rexmp2frurm<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_urm(func, inst, divert, sink, put_rexmp2)
+}
+
+fn recipe_mp2rfurm<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_urm(func, inst, divert, sink, put_mp2)
+}
+
+fn recipe_rexmp2rfurm<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_urm(func, inst, divert, sink, put_rexmp2)
+}
+
+fn recipe_mp2rfumr<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_umr(func, inst, divert, sink, put_mp2)
+}
+
+fn recipe_rexmp2rfumr<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_umr(func, inst, divert, sink, put_rexmp2)
+}
+
+fn recipe_mp2urm<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    emit_urm(func, inst, divert, sink, put_mp2)
+}
+
+fn recipe_rexmp2urm<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_urm(func, inst, divert, sink, put_rexmp2)
+}
+
+fn recipe_mp2furm<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    emit_urm(func, inst, divert, sink, put_mp2)
+}
+
+fn recipe_rexmp2furm<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_urm(func, inst, divert, sink, put_rexmp2)
+}
+
+fn recipe_mp3furmi_rnd<CS: CodeSink + ?Sized>(func: &Function,
+                                              inst: Inst,
+                                              divert: &mut RegDiversions,
+                                              sink: &mut CS) {
+    emit_furmi_rnd(func, inst, divert, sink, put_mp3)
+}
+
+fn recipe_rexmp3furmi_rnd<CS: CodeSink + ?Sized>(func: &Function,
+                                                 inst: Inst,
+                                                 divert: &mut RegDiversions,
+                                                 sink: &mut CS) {
+    emit_furmi_rnd(func, inst, divert, sink, put_rexmp3)
+}
+
+fn recipe_op2furm<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    emit_urm(func, inst, divert, sink, put_op2)
+}
+
+fn recipe_rexop2furm<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_urm(func, inst, divert, sink, put_rexop2)
+}
+
+fn recipe_op2frmov<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          _divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_rmov(func, inst, sink, put_op2, false)
+}
+
+fn recipe_rexop2frmov<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             _divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_rmov(func, inst, sink, put_rexop2, false)
+}
+
+fn recipe_mp2fspill<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_spill(func, inst, divert, sink, put_mp2)
+}
+
+fn recipe_rexmp2fspill<CS: CodeSink + ?Sized>(func: &Function,
+                                              inst: Inst,
+                                              divert: &mut RegDiversions,
+                                              sink: &mut CS) {
+    emit_spill(func, inst, divert, sink, put_rexmp2)
+}
+
+fn recipe_mp2ffill<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_fill(func, inst, divert, sink, put_mp2)
+}
+
+fn recipe_rexmp2ffill<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_fill(func, inst, divert, sink, put_rexmp2)
+}
+
+fn recipe_op2flddisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_ld(func, inst, divert, sink, put_op2, 1)
+}
+
+fn recipe_rexop2flddisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    emit_ld(func, inst, divert, sink, put_rexop2, 1)
+}
+
+fn recipe_op2flddisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                              inst: Inst,
+                                              divert: &mut RegDiversions,
+                                              sink: &mut CS) {
+    emit_ld(func, inst, divert, sink, put_op2, 4)
+}
+
+fn recipe_rexop2flddisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                                 inst: Inst,
+                                                 divert: &mut RegDiversions,
+                                                 sink: &mut CS) {
+    emit_ld(func, inst, divert, sink, put_rexop2, 4)
+}
+
+fn recipe_op2fstdisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_st(func, inst, divert, sink, put_op2, 1)
+}
+
+fn recipe_rexop2fstdisp8<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    emit_st(func, inst, divert, sink, put_rexop2, 1)
+}
+
+fn recipe_op2fstdisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                              inst: Inst,
+                                              divert: &mut RegDiversions,
+                                              sink: &mut CS) {
+    emit_st(func, inst, divert, sink, put_op2, 4)
+}
+
+fn recipe_rexop2fstdisp32<CS: CodeSink + ?Sized>(func: &Function,
+                                                 inst: Inst,
+                                                 divert: &mut RegDiversions,
+                                                 sink: &mut CS) {
+    emit_st(func, inst, divert, sink, put_rexop2, 4)
+}
+
+fn recipe_op2fspill<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    emit_spill(func, inst, divert, sink, put_op2)
+}
+
+fn recipe_rexop2fspill<CS: CodeSink + ?Sized>(func: &Function,
+                                              inst: Inst,
+                                              divert: &mut RegDiversions,
+                                              sink: &mut CS) {
+    emit_spill(func, inst, divert, sink, put_rexop2)
+}
+
+fn recipe_op2ffill<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_fill(func, inst, divert, sink, put_op2)
+}
+
+fn recipe_rexop2ffill<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_fill(func, inst, divert, sink, put_rexop2)
+}
+
+fn recipe_op2fcscc<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_fcscc(func, inst, divert, sink, put_op2, put_op2)
+}
+
+fn recipe_rexop2fcscc<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_fcscc(func, inst, divert, sink, put_rexop2, put_rexop2)
+}
+
+fn recipe_mp2fcscc<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    emit_fcscc(func, inst, divert, sink, put_mp2, put_op2)
+}
+
+fn recipe_rexmp2fcscc<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    emit_fcscc(func, inst, divert, sink, put_rexmp2, put_rexop2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use settings::{self, Configurable};
+    use isa;
+    use binemit::{CodeOffset, Addend};
+    use ir::{Function, ExternalName, InstructionData, Opcode, ValueLoc, Signature, ExtFuncData,
+             JumpTable, Cursor, InstBuilder, StackSlotData, StackSlotKind, VariableArgs};
+    use ir::types;
+    use regalloc::diversion::RegDiversions;
+
+    /// A code sink that records the external relocations, trap sites, and stack maps next to the
+    /// machine code.
+    struct RelocSink {
+        code: Vec<u8>,
+        relocs: Vec<(CodeOffset, Reloc, String, Addend)>,
+        traps: Vec<(CodeOffset, TrapCode, SourceLoc)>,
+        stackmaps: Vec<(CodeOffset, Stackmap)>,
+    }
+
+    impl CodeSink for RelocSink {
+        fn offset(&self) -> CodeOffset {
+            self.code.offset()
+        }
+
+        fn put1(&mut self, x: u8) {
+            self.code.put1(x);
+        }
+
+        fn put2(&mut self, x: u16) {
+            self.code.put2(x);
+        }
+
+        fn put4(&mut self, x: u32) {
+            self.code.put4(x);
+        }
+
+        fn put8(&mut self, x: u64) {
+            self.code.put8(x);
+        }
+
+        fn reloc_ebb(&mut self, _reloc: Reloc, _ebb_offset: CodeOffset) {}
+
+        fn reloc_external(&mut self, reloc: Reloc, name: &ExternalName, addend: Addend) {
+            let offset = self.offset();
+            self.relocs.push((offset, reloc, name.to_string(), addend));
+        }
+
+        fn reloc_jt(&mut self, _reloc: Reloc, _jt: JumpTable) {}
+
+        fn trap(&mut self, code: TrapCode, srcloc: SourceLoc) {
+            let offset = self.offset();
+            self.traps.push((offset, code, srcloc));
+        }
+
+        fn add_stackmap(&mut self, map: &Stackmap) {
+            let offset = self.offset();
+            self.stackmaps.push((offset, map.clone()));
+        }
+    }
+
+    #[test]
+    fn names() {
+        let r: Reloc = RelocKind::GOTPCRel4.into();
+        assert_eq!(RELOC_NAMES[r.0 as usize], "GOTPCRel4");
+        let r: Reloc = RelocKind::PLTRel4.into();
+        assert_eq!(RELOC_NAMES[r.0 as usize], "PLTRel4");
+    }
+
+    #[test]
+    fn add() {
+        let flags = settings::Flags::new(&settings::builder());
+        let isa = isa::lookup("i686").unwrap().finish(flags);
+
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        let arg0 = func.dfg.append_ebb_arg(ebb, types::I32);
+        let arg1 = func.dfg.append_ebb_arg(ebb, types::I32);
+        let inst = func.dfg.make_inst(InstructionData::Binary {
+                                          opcode: Opcode::Iadd,
+                                          ty: types::I32,
+                                          args: [arg0, arg1],
+                                      });
+        func.dfg.make_inst_results(inst, types::I32);
+        func.layout.append_ebb(ebb);
+        func.layout.append_inst(inst, ebb);
+        let result = func.dfg.first_result(inst);
+
+        // add eax, ecx
+        *func.locations.ensure(arg0) = ValueLoc::Reg(0);
+        *func.locations.ensure(arg1) = ValueLoc::Reg(1);
+        *func.locations.ensure(result) = ValueLoc::Reg(0);
+        *func.encodings.ensure(inst) = isa.encode(&func.dfg, &func.dfg[inst]).unwrap();
+
+        let mut code = Vec::new();
+        isa.emit_inst(&func, inst, &mut RegDiversions::new(), &mut code);
+        assert_eq!(code, [0x01, 0xc8]);
+        assert_eq!(code.len(),
+                   isa.recipe_sizing()[func.encodings[inst].recipe()].bytes as usize);
+    }
+
+    #[test]
+    fn fnaddr() {
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_64bit", true).unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&shared_builder));
+
+        let mut func = Function::new();
+        let sig = func.dfg.signatures.push(Signature::new());
+        let fref = func.dfg
+            .ext_funcs
+            .push(ExtFuncData::new(ExternalName::testcase("foo"), sig));
+        let ebb = func.dfg.make_ebb();
+        let inst = func.dfg.make_inst(InstructionData::FuncAddr {
+                                          opcode: Opcode::FuncAddr,
+                                          ty: types::I64,
+                                          func_ref: fref,
+                                      });
+        func.dfg.make_inst_results(inst, types::I64);
+        func.layout.append_ebb(ebb);
+        func.layout.append_inst(inst, ebb);
+        let result = func.dfg.first_result(inst);
+
+        // movabs r9, foo
+        *func.locations.ensure(result) = ValueLoc::Reg(9);
+        *func.encodings.ensure(inst) = isa.encode(&func.dfg, &func.dfg[inst]).unwrap();
+
+        let mut sink = RelocSink {
+            code: Vec::new(),
+            relocs: Vec::new(),
+            traps: Vec::new(),
+            stackmaps: Vec::new(),
+        };
+        isa.emit_inst(&func, inst, &mut RegDiversions::new(), &mut sink);
+        assert_eq!(sink.code, [0x49, 0xb9, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(sink.relocs,
+                   [(2, RelocKind::Abs8.into(), "foo".to_string(), 0)]);
+    }
+
+    #[test]
+    fn tail_call_abs() {
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_64bit", true).unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&shared_builder));
+
+        let mut func = Function::new();
+        let sig = func.dfg.signatures.push(Signature::new());
+        let fref = func.dfg
+            .ext_funcs
+            .push(ExtFuncData::new(ExternalName::testcase("foo"), sig));
+        let ebb = func.dfg.make_ebb();
+        let inst = {
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb);
+            func.dfg.ins(pos).return_call(fref, VariableArgs::new())
+        };
+
+        // movabs r11, foo; jmp r11
+        *func.encodings.ensure(inst) = isa.encode(&func.dfg, &func.dfg[inst]).unwrap();
+        let mut sink = RelocSink {
+            code: Vec::new(),
+            relocs: Vec::new(),
+            traps: Vec::new(),
+            stackmaps: Vec::new(),
+        };
+        isa.emit_inst(&func, inst, &mut RegDiversions::new(), &mut sink);
+        assert_eq!(sink.code,
+                   [0x49, 0xbb, 0, 0, 0, 0, 0, 0, 0, 0, 0x41, 0xff, 0xe3]);
+        assert_eq!(sink.relocs,
+                   [(2, RelocKind::Abs8.into(), "foo".to_string(), 0)]);
+        assert_eq!(sink.code.len(),
+                   isa.recipe_sizing()[func.encodings[inst].recipe()].bytes as usize);
+    }
+
+    #[test]
+    fn trapnz() {
+        let flags = settings::Flags::new(&settings::builder());
+        let isa = isa::lookup("i686").unwrap().finish(flags);
+
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        let arg0 = func.dfg.append_ebb_arg(ebb, types::I32);
+        let inst = func.dfg.make_inst(InstructionData::CondTrap {
+                                          opcode: Opcode::Trapnz,
+                                          ty: types::VOID,
+                                          arg: arg0,
+                                          code: TrapCode::HeapOutOfBounds,
+                                      });
+        func.layout.append_ebb(ebb);
+        func.layout.append_inst(inst, ebb);
+        func.set_srcloc(inst, SourceLoc::new(0x1234));
+
+        // test ecx, ecx; jz +2; ud2
+        *func.locations.ensure(arg0) = ValueLoc::Reg(1);
+        *func.encodings.ensure(inst) = isa.encode(&func.dfg, &func.dfg[inst]).unwrap();
+
+        let mut sink = RelocSink {
+            code: Vec::new(),
+            relocs: Vec::new(),
+            traps: Vec::new(),
+            stackmaps: Vec::new(),
+        };
+        isa.emit_inst(&func, inst, &mut RegDiversions::new(), &mut sink);
+        assert_eq!(sink.code, [0x85, 0xc9, 0x74, 0x02, 0x0f, 0x0b]);
+        assert_eq!(sink.traps,
+                   [(4, TrapCode::HeapOutOfBounds, SourceLoc::new(0x1234))]);
+        assert_eq!(sink.code.len(),
+                   isa.recipe_sizing()[func.encodings[inst].recipe()].bytes as usize);
+    }
+
+    #[test]
+    fn load_traps() {
+        let flags = settings::Flags::new(&settings::builder());
+        let isa = isa::lookup("i686").unwrap().finish(flags);
+
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        let arg0 = func.dfg.append_ebb_arg(ebb, types::I32);
+        func.layout.append_ebb(ebb);
+        let mut notrap = MemFlags::new();
+        notrap.set_notrap();
+        let mut insts = Vec::new();
+        for &flags in &[MemFlags::new(), notrap] {
+            let inst = func.dfg.make_inst(InstructionData::Load {
+                                              opcode: Opcode::Load,
+                                              ty: types::I32,
+                                              flags: flags,
+                                              arg: arg0,
+                                              offset: 8.into(),
+                                          });
+            func.dfg.make_inst_results(inst, types::I32);
+            func.layout.append_inst(inst, ebb);
+            func.set_srcloc(inst, SourceLoc::new(insts.len() as u32));
+            let result = func.dfg.first_result(inst);
+            *func.locations.ensure(result) = ValueLoc::Reg(0);
+            *func.encodings.ensure(inst) = isa.encode(&func.dfg, &func.dfg[inst]).unwrap();
+            insts.push(inst);
+        }
+        *func.locations.ensure(arg0) = ValueLoc::Reg(1);
+
+        // mov eax, [ecx+8] with a 32-bit displacement, since that is the first encoding.
+        let mut sink = RelocSink {
+            code: Vec::new(),
+            relocs: Vec::new(),
+            traps: Vec::new(),
+            stackmaps: Vec::new(),
+        };
+        for &inst in &insts {
+            isa.emit_inst(&func, inst, &mut RegDiversions::new(), &mut sink);
+        }
+        assert_eq!(sink.code[..7], [0x8b, 0x84, 0x21, 0x08, 0, 0, 0]);
+        assert_eq!(sink.code[..7], sink.code[7..]);
+        assert_eq!(sink.traps,
+                   [(0, TrapCode::HeapOutOfBounds, SourceLoc::new(0))]);
+    }
+
+    #[test]
+    fn safepoint() {
+        let flags = settings::Flags::new(&settings::builder());
+        let isa = isa::lookup("i686").unwrap().finish(flags);
+
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        let arg0 = func.dfg.append_ebb_arg(ebb, types::R32);
+        let arg1 = func.dfg.append_ebb_arg(ebb, types::R32);
+        let ss0 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 4));
+        let mut refs = VariableArgs::new();
+        refs.push(arg0);
+        refs.push(arg1);
+        let inst = {
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb);
+            func.dfg.ins(pos).safepoint(refs)
+        };
+        *func.encodings.ensure(inst) = isa.encode(&func.dfg, &func.dfg[inst]).unwrap();
+        *func.locations.ensure(arg0) = ValueLoc::Reg(3);
+        *func.locations.ensure(arg1) = ValueLoc::Stack(ss0);
+        *func.stack_offsets.ensure(ss0) = 12;
+
+        let mut sink = RelocSink {
+            code: Vec::new(),
+            relocs: Vec::new(),
+            traps: Vec::new(),
+            stackmaps: Vec::new(),
+        };
+        let mut divert = RegDiversions::new();
+        divert.regmove(arg0, 3, 6);
+        isa.emit_inst(&func, inst, &mut divert, &mut sink);
+        assert!(sink.code.is_empty());
+        assert_eq!(sink.stackmaps,
+                   [(0,
+                     Stackmap {
+                         registers: vec![6],
+                         stack_offsets: vec![12],
+                     })]);
+    }
+}
@@ -1,8 +1,12 @@
 //! Encoding tables for Intel ISAs.
 
-use ir::InstructionData;
+use ir::{Opcode, DataFlowGraph, InstructionData, AtomicOrdering};
+use ir::condcodes::FloatCC;
 use ir::types;
+use predicates;
 use isa::enc_tables::{Level1Entry, Level2Entry};
 use isa::constraints::*;
+use isa::encoding::{RecipeSizing, BranchRange};
+use super::registers::*;
 
 include!(concat!(env!("OUT_DIR"), "/encoding-intel.rs"));
@@ -0,0 +1,328 @@
+//! Intel custom legalizations.
+//!
+//! These legalization routines are used instead of the generic expansions for the opcodes listed
+//! in `CUSTOM`.
+
+use ir::{Cursor, DataFlowGraph, InstructionData, InstBuilder, Opcode, AtomicOrdering,
+         VariableArgs};
+use ir::TrapCode;
+use ir::condcodes::{FloatCC, IntCC, CondCode};
+use isa::{TargetIsa, LegalizeFn};
+use legalizer::libcall::expand_as_libcall;
+
+/// Custom legalization routines, indexed by the code in `Legalize::Custom(code)`.
+pub static CUSTOM: [(Opcode, LegalizeFn); 17] = [(Opcode::Fcmp, fcmp),
+                                                 (Opcode::Umulhi, umulhi),
+                                                 (Opcode::Smulhi, smulhi),
+                                                 (Opcode::Udiv, udiv),
+                                                 (Opcode::Sdiv, sdiv),
+                                                 (Opcode::Urem, urem),
+                                                 (Opcode::Srem, srem),
+                                                 (Opcode::AtomicStore, atomic_store),
+                                                 (Opcode::AtomicAnd, atomic_cas_loop),
+                                                 (Opcode::AtomicOr, atomic_cas_loop),
+                                                 (Opcode::AtomicXor, atomic_cas_loop),
+                                                 (Opcode::Ceil, round),
+                                                 (Opcode::Floor, round),
+                                                 (Opcode::Trunc, round),
+                                                 (Opcode::Nearest, round),
+                                                 (Opcode::FcvtToSintSat, fcvt_to_sint_sat),
+                                                 (Opcode::FcvtToUintSat, fcvt_to_uint_sat)];
+
+/// Rewrite a floating point comparison in terms of the conditions that `ucomiss` and `ucomisd`
+/// can test with a single `setcc`.
+///
+/// The unordered result sets the ZF, PF, and CF flags, so the conditions that test CF or ZF
+/// without PF are only available in one ordered or unordered form. The others are expressed by
+/// swapping the operands, and the ordered `eq` and unordered `ne` conditions, which need to test
+/// PF too, are computed from two comparisons.
+fn fcmp(pos: &mut Cursor, dfg: &mut DataFlowGraph, _isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let (cond, x, y) = match dfg[inst] {
+        InstructionData::FloatCompare { cond, args, .. } => {
+            (cond, dfg.resolve_aliases(args[0]), dfg.resolve_aliases(args[1]))
+        }
+        _ => panic!("Expected fcmp: {:?}", dfg[inst]),
+    };
+    match cond {
+        FloatCC::LessThan |
+        FloatCC::LessThanOrEqual |
+        FloatCC::UnorderedOrGreaterThan |
+        FloatCC::UnorderedOrGreaterThanOrEqual => {
+            dfg.replace(inst).fcmp(cond.reverse(), y, x);
+        }
+        FloatCC::Equal => {
+            let ord = dfg.ins(pos).fcmp(FloatCC::Ordered, x, y);
+            let eq = dfg.ins(pos).fcmp(FloatCC::UnorderedOrEqual, x, y);
+            dfg.replace(inst).band(ord, eq);
+        }
+        FloatCC::NotEqual => {
+            let uno = dfg.ins(pos).fcmp(FloatCC::Unordered, x, y);
+            let ne = dfg.ins(pos).fcmp(FloatCC::OrderedNotEqual, x, y);
+            dfg.replace(inst).bor(uno, ne);
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Expand `umulhi` into `x86_umulx`, keeping the high part of the product.
+fn umulhi(pos: &mut Cursor, dfg: &mut DataFlowGraph, _isa: &TargetIsa) -> bool {
+    expand_mulhi(pos, dfg, false)
+}
+
+/// Expand `smulhi` into `x86_smulx`, keeping the high part of the product.
+fn smulhi(pos: &mut Cursor, dfg: &mut DataFlowGraph, _isa: &TargetIsa) -> bool {
+    expand_mulhi(pos, dfg, true)
+}
+
+/// Expand a high multiplication into the one-operand `mul` or `imul` instruction, which computes
+/// the double-width product in `rdx:rax`.
+fn expand_mulhi(pos: &mut Cursor, dfg: &mut DataFlowGraph, signed: bool) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let (x, y) = match dfg[inst] {
+        InstructionData::Binary { args, .. } => {
+            (dfg.resolve_aliases(args[0]), dfg.resolve_aliases(args[1]))
+        }
+        _ => panic!("Expected high multiplication: {:?}", dfg[inst]),
+    };
+    let (_, hi) = if signed {
+        dfg.ins(pos).x86_smulx(x, y)
+    } else {
+        dfg.ins(pos).x86_umulx(x, y)
+    };
+    dfg.replace(inst).copy(hi);
+    true
+}
+
+/// Expand `udiv` into `x86_udivmodx` with a zero high numerator word.
+fn udiv(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    expand_divrem(pos, dfg, isa, false, false)
+}
+
+/// Expand `sdiv` into `x86_sdivmodx` with a sign-extended numerator.
+fn sdiv(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    expand_divrem(pos, dfg, isa, true, false)
+}
+
+/// Expand `urem` into `x86_udivmodx` with a zero high numerator word.
+fn urem(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    expand_divrem(pos, dfg, isa, false, true)
+}
+
+/// Expand `srem` into `x86_sdivmodx` with a sign-extended numerator.
+fn srem(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    expand_divrem(pos, dfg, isa, true, true)
+}
+
+/// Expand a division or remainder into the double-width `div` or `idiv` instruction.
+///
+/// The numerator is extended into `rdx:rax` like the `xor edx, edx` or `cdq` instruction would
+/// before the division. The hardware division traps when the divisor is zero, and `idiv` also
+/// traps when the quotient overflows. That matches the `sdiv` semantics, but `srem` must return 0
+/// for `INT_MIN % -1`, so the remainder of a division by -1 is computed as a division by 1
+/// instead. Both have a remainder of 0.
+///
+/// When the `avoid_div_traps` setting is enabled, the division is preceded by explicit checks
+/// that trap with `int_divz` when the divisor is zero, and with `int_ovf` when `sdiv` overflows,
+/// so the hardware division never faults.
+fn expand_divrem(pos: &mut Cursor,
+                 dfg: &mut DataFlowGraph,
+                 isa: &TargetIsa,
+                 signed: bool,
+                 rem: bool)
+                 -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let (x, y) = match dfg[inst] {
+        InstructionData::Binary { args, .. } => {
+            (dfg.resolve_aliases(args[0]), dfg.resolve_aliases(args[1]))
+        }
+        _ => panic!("Expected division: {:?}", dfg[inst]),
+    };
+    let ty = dfg.value_type(x);
+
+    if isa.flags().avoid_div_traps() {
+        dfg.ins(pos).trapz(y, TrapCode::IntegerDivisionByZero);
+        if signed && !rem {
+            // The quotient overflows when `x = INT_MIN` and `y = -1`, which is when both
+            // `x ^ INT_MIN` and `y + 1` are zero.
+            let xmin = dfg.ins(pos).bxor_imm(x, -1i64 << (ty.bits() - 1));
+            let yp1 = dfg.ins(pos).iadd_imm(y, 1);
+            let ovf = dfg.ins(pos).bor(xmin, yp1);
+            dfg.ins(pos).trapz(ovf, TrapCode::IntegerOverflow);
+        }
+    }
+
+    let (q, r) = if signed {
+        let shift = dfg.ins(pos).iconst(ty, ty.bits() as i64 - 1);
+        let xhi = dfg.ins(pos).sshr(x, shift);
+        let d = if rem {
+            // `y + 1` is zero when `y = -1`.
+            let ynot = dfg.ins(pos).iadd_imm(y, 1);
+            let one = dfg.ins(pos).iconst(ty, 1);
+            dfg.ins(pos).select(ynot, y, one)
+        } else {
+            y
+        };
+        dfg.ins(pos).x86_sdivmodx(x, xhi, d)
+    } else {
+        let xhi = dfg.ins(pos).iconst(ty, 0);
+        dfg.ins(pos).x86_udivmodx(x, xhi, y)
+    };
+    dfg.replace(inst).copy(if rem { r } else { q });
+    true
+}
+
+/// Expand a sequentially consistent `atomic_store` into an `atomic_xchg`.
+///
+/// A `mov` to memory only has release semantics. The `xchg` instruction is implicitly locked, so
+/// it is also a full barrier.
+fn atomic_store(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let (ordering, p, x) = match dfg[inst] {
+        InstructionData::AtomicRmw { ordering, args, .. } => (ordering, args[0], args[1]),
+        _ => panic!("Expected atomic_store: {:?}", dfg[inst]),
+    };
+    if ordering != AtomicOrdering::SeqCst || !isa.flags().enable_atomics() {
+        return false;
+    }
+    dfg.replace(inst).atomic_xchg(ordering, p, x);
+    true
+}
+
+/// Expand an `atomic_and`, `atomic_or`, or `atomic_xor` into a `lock cmpxchg` loop.
+///
+/// The `lock and`, `lock or`, and `lock xor` instructions don't return the old value, so it is
+/// computed by retrying the compare-and-swap until the memory didn't change:
+///
+/// ```cton
+///     v1 = atomic_load relaxed p
+///     jump ebb1(v1)
+///
+/// ebb1(old):
+///     new = band old, x
+///     prev = atomic_cas ord, p, old, new
+///     br_icmp ne prev, old, ebb1(prev)
+///     jump ebb2
+///
+/// ebb2:
+///     a = copy old
+/// ```
+fn atomic_cas_loop(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let (ordering, p, x) = match dfg[inst] {
+        InstructionData::AtomicRmw { ordering, args, .. } => {
+            (ordering, dfg.resolve_aliases(args[0]), dfg.resolve_aliases(args[1]))
+        }
+        _ => panic!("Expected atomic operation: {:?}", dfg[inst]),
+    };
+    if !isa.flags().enable_atomics() {
+        return false;
+    }
+    let opcode = dfg[inst].opcode();
+    let ty = dfg.value_type(x);
+    let orig_ebb = pos.current_ebb().expect("need EBB");
+
+    let done = dfg.make_ebb();
+    pos.insert_ebb(done);
+
+    let retry = dfg.make_ebb();
+    let old = dfg.append_ebb_arg(retry, ty);
+    pos.goto_bottom(orig_ebb);
+    let init = dfg.ins(pos).atomic_load(ty, AtomicOrdering::Relaxed, p);
+    let mut init_args = VariableArgs::new();
+    init_args.push(init);
+    dfg.ins(pos).jump(retry, init_args);
+
+    pos.insert_ebb(retry);
+    let new = match opcode {
+        Opcode::AtomicAnd => dfg.ins(pos).band(old, x),
+        Opcode::AtomicOr => dfg.ins(pos).bor(old, x),
+        _ => dfg.ins(pos).bxor(old, x),
+    };
+    let prev = dfg.ins(pos).atomic_cas(ordering, p, old, new);
+    let mut retry_args = VariableArgs::new();
+    retry_args.push(prev);
+    dfg.ins(pos).br_icmp(IntCC::NotEqual, prev, old, retry, retry_args);
+    dfg.ins(pos).jump(done, VariableArgs::new());
+
+    pos.goto_inst(inst);
+    dfg.replace(inst).copy(old);
+    true
+}
+
+/// Expand a floating point rounding instruction into a call to the C library.
+///
+/// The `roundss` and `roundsd` instructions need SSE4.1, and the older alternatives either don't
+/// handle values that don't fit in an integer or depend on the current rounding mode.
+fn round(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    expand_as_libcall(inst, dfg, isa)
+}
+
+/// Expand `fcvt_to_sint_sat` into a `cvttss2si` or `cvttsd2si` conversion with a fixup.
+///
+/// The conversion produces the integer indefinite value `INT_MIN` for NaN and out-of-range inputs.
+/// When that happens, the saturated result is picked with conditional moves: `INT_MAX` for positive
+/// inputs, 0 for NaN, and `INT_MIN` for the rest.
+fn fcvt_to_sint_sat(pos: &mut Cursor, dfg: &mut DataFlowGraph, _isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let x = match dfg[inst] {
+        InstructionData::Unary { arg, .. } => dfg.resolve_aliases(arg),
+        _ => panic!("Expected fcvt_to_sint_sat: {:?}", dfg[inst]),
+    };
+    let ty = dfg[inst].ctrl_typevar(dfg);
+    let fty = dfg.value_type(x);
+    let bits = ty.bits();
+
+    let izero = dfg.ins(pos).iconst(ty, 0);
+    let fzero = dfg.ins(pos).fcvt_from_sint(fty, izero);
+    let min = dfg.ins(pos).iconst(ty, -1i64 << (bits - 1));
+    let max = dfg.ins(pos).iconst(ty, !(-1i64 << (bits - 1)));
+
+    let positive = dfg.ins(pos).fcmp(FloatCC::GreaterThan, x, fzero);
+    let bound = dfg.ins(pos).select(positive, max, min);
+    let nan = dfg.ins(pos).fcmp(FloatCC::Unordered, x, x);
+    let bound = dfg.ins(pos).select(nan, izero, bound);
+
+    let result = dfg.ins(pos).fcvt_to_sint(ty, x);
+    let valid = dfg.ins(pos).bxor(result, min);
+    dfg.replace(inst).select(valid, result, bound);
+    true
+}
+
+/// Expand `fcvt_to_uint_sat` into signed conversions.
+///
+/// There is no unsigned conversion before AVX-512, so inputs in the upper half of the unsigned
+/// range are converted after subtracting `2^(N-1)`, and the high bit is put back with an `xor`.
+/// Inputs that are still too large produce the integer indefinite value, which becomes 0 after the
+/// `xor`. The saturated results are picked with conditional moves.
+fn fcvt_to_uint_sat(pos: &mut Cursor, dfg: &mut DataFlowGraph, _isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let x = match dfg[inst] {
+        InstructionData::Unary { arg, .. } => dfg.resolve_aliases(arg),
+        _ => panic!("Expected fcvt_to_uint_sat: {:?}", dfg[inst]),
+    };
+    let ty = dfg[inst].ctrl_typevar(dfg);
+    let fty = dfg.value_type(x);
+    let bits = ty.bits();
+
+    let izero = dfg.ins(pos).iconst(ty, 0);
+    let fzero = dfg.ins(pos).fcvt_from_sint(fty, izero);
+    let min = dfg.ins(pos).iconst(ty, -1i64 << (bits - 1));
+    let max = dfg.ins(pos).iconst(ty, -1);
+
+    // `fmin` is `-2^(N-1)`, which is exact in both float formats.
+    let fmin = dfg.ins(pos).fcvt_from_sint(fty, min);
+    let xhigh = dfg.ins(pos).fadd(x, fmin);
+    let high = dfg.ins(pos).fcvt_to_sint(ty, xhigh);
+    let high = dfg.ins(pos).bxor(high, min);
+    let high = dfg.ins(pos).select(high, high, max);
+    let low = dfg.ins(pos).fcvt_to_sint(ty, x);
+
+    let is_high = dfg.ins(pos).fcmp(FloatCC::GreaterThanOrEqual, xhigh, fzero);
+    let result = dfg.ins(pos).select(is_high, high, low);
+    let positive = dfg.ins(pos).fcmp(FloatCC::GreaterThan, x, fzero);
+    dfg.replace(inst).select(positive, result, izero);
+    true
+}
@@ -1,14 +1,25 @@
 //! Intel Instruction Set Architectures.
 
 pub mod settings;
+pub mod binemit;
+pub mod unwind;
+mod abi;
 mod enc_tables;
+mod legalize;
 mod registers;
 
 use super::super::settings as shared_settings;
-use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding};
+use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding,
+                      legal_encodings, custom_action, allowed_types};
 use isa::Builder as IsaBuilder;
-use isa::{TargetIsa, RegInfo, Encoding, Legalize, RecipeConstraints};
-use ir::{InstructionData, DataFlowGraph};
+use isa::{TargetIsa, RegInfo, RegUnit, Encoding, Legalize, LegalizeFn, RecipeConstraints,
+          RecipeSizing, UnwindInfo};
+use binemit::{CodeSink, Reloc};
+use ir::{Function, Inst, InstructionData, DataFlowGraph, Signature, CallConv, GlobalVar};
+use regalloc::AllocatableSet;
+use regalloc::diversion::RegDiversions;
+use std::boxed::Box;
+use std::vec::Vec;
 
 #[allow(dead_code)]
 struct Isa {
@@ -22,6 +33,7 @@ pub fn isa_builder() -> IsaBuilder {
     IsaBuilder {
         setup: settings::builder(),
         constructor: isa_constructor,
+        is_64bit: None,
     }
 }
 
@@ -49,11 +61,18 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn isa_flags(&self) -> shared_settings::Iter {
+        self.isa_flags.iter()
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
 
     fn encode(&self, dfg: &DataFlowGraph, inst: &InstructionData) -> Result<Encoding, Legalize> {
+        if !allowed_types(&self.shared_flags, dfg, inst) {
+            return Err(Legalize::Expand);
+        }
         lookup_enclist(inst.ctrl_typevar(dfg),
                        inst.opcode(),
                        self.cpumode,
@@ -61,17 +80,240 @@ impl TargetIsa for Isa {
             .and_then(|enclist_offset| {
                 general_encoding(enclist_offset,
                                  &enc_tables::ENCLISTS[..],
-                                 |instp| enc_tables::check_instp(inst, instp),
+                                 |instp| enc_tables::check_instp(inst, dfg, instp),
                                  |isap| self.isa_flags.numbered_predicate(isap as usize))
                     .ok_or(Legalize::Expand)
             })
+            .map_err(|action| custom_action(action, inst.opcode(), &legalize::CUSTOM))
+    }
+
+    fn legal_encodings(&self,
+                       dfg: &DataFlowGraph,
+                       inst: &InstructionData)
+                       -> Result<Vec<Encoding>, Legalize> {
+        if !allowed_types(&self.shared_flags, dfg, inst) {
+            return Err(Legalize::Expand);
+        }
+        lookup_enclist(inst.ctrl_typevar(dfg),
+                       inst.opcode(),
+                       self.cpumode,
+                       &enc_tables::LEVEL2[..])
+            .and_then(|enclist_offset| {
+                let encodings = legal_encodings(enclist_offset,
+                                                &enc_tables::ENCLISTS[..],
+                                                |instp| enc_tables::check_instp(inst, dfg, instp),
+                                                |isap| {
+                                                    self.isa_flags
+                                                        .numbered_predicate(isap as usize)
+                                                });
+                if encodings.is_empty() {
+                    Err(Legalize::Expand)
+                } else {
+                    Ok(encodings)
+                }
+            })
+            .map_err(|action| custom_action(action, inst.opcode(), &legalize::CUSTOM))
     }
 
     fn recipe_names(&self) -> &'static [&'static str] {
         &enc_tables::RECIPE_NAMES[..]
     }
 
+    fn reloc_names(&self) -> &'static [&'static str] {
+        &binemit::RELOC_NAMES[..]
+    }
+
+    fn jump_table_reloc(&self) -> Option<Reloc> {
+        if self.shared_flags.is_64bit() {
+            Some(binemit::RelocKind::Abs8.into())
+        } else {
+            Some(binemit::RelocKind::Abs4.into())
+        }
+    }
+
+    fn stack_alignment(&self) -> u32 {
+        // Both the 32-bit and 64-bit System V ABIs require 16-byte alignment at calls. The
+        // frame is kept 16-byte aligned for all calling conventions.
+        self.shared_flags.stack_alignment(16)
+    }
+
+    fn if_conversion_limit(&self) -> usize {
+        // `cmov` is cheap compared to a mispredicted branch.
+        3
+    }
+
     fn recipe_constraints(&self) -> &'static [RecipeConstraints] {
         &enc_tables::RECIPE_CONSTRAINTS
     }
+
+    fn recipe_sizing(&self) -> &'static [RecipeSizing] {
+        &enc_tables::RECIPE_SIZING
+    }
+
+    fn insert_stack_probes(&self, func: &mut Function, frame_size: u32) {
+        abi::insert_stack_probes(func, self, frame_size)
+    }
+
+    fn insert_frame_allocation(&self, func: &mut Function, frame_size: u32) {
+        abi::insert_frame_allocation(func, self, frame_size)
+    }
+
+    fn insert_stack_check(&self, func: &mut Function, limit: GlobalVar, frame_size: u32) {
+        abi::insert_stack_check(func, self, limit, frame_size)
+    }
+
+    fn emit_inst(&self,
+                 func: &Function,
+                 inst: Inst,
+                 divert: &mut RegDiversions,
+                 sink: &mut CodeSink) {
+        binemit::emit_inst(func, inst, divert, sink)
+    }
+
+    fn allocatable_registers(&self) -> AllocatableSet {
+        let mut regs = AllocatableSet::new();
+        // Reserve the stack pointer `rsp`.
+        regs.take(registers::GPR, registers::GPR.unit(4));
+        if self.shared_flags.preserve_frame_pointers() {
+            // Reserve the frame pointer `rbp`.
+            regs.take(registers::GPR, registers::GPR.unit(5));
+        }
+        // The REX-prefixed registers `r8`-`r15` and `xmm8`-`xmm15` are only available in 64-bit
+        // mode.
+        if !self.shared_flags.is_64bit() {
+            for reg in 8..16 {
+                regs.take(registers::GPR, registers::GPR.unit(reg));
+                regs.take(registers::FPR, registers::FPR.unit(reg));
+            }
+        }
+        regs
+    }
+
+    fn custom_legalization(&self, code: u8) -> LegalizeFn {
+        legalize::CUSTOM[code as usize].1
+    }
+
+    fn legalize_signature(&self, sig: &mut Signature, _current: bool) {
+        abi::legalize_signature(sig, &self.shared_flags)
+    }
+
+    fn callee_saved_registers(&self, call_conv: CallConv) -> &'static [RegUnit] {
+        let bits = if self.shared_flags.is_64bit() { 64 } else { 32 };
+        abi::callee_saved_registers(bits, call_conv)
+    }
+
+    fn create_unwind_info(&self, func: &Function) -> Option<UnwindInfo> {
+        unwind::create_unwind_info(func, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use settings::{self, Configurable};
+    use isa;
+    use ir::{DataFlowGraph, InstructionData, Opcode, Signature, ArgumentType};
+    use ir::types;
+    use super::registers;
+
+    fn encstr(isa: &isa::TargetIsa, enc: isa::Encoding) -> String {
+        isa.display_enc(enc).to_string()
+    }
+
+    #[test]
+    fn cpu_features() {
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_64bit", true).unwrap();
+        let shared_flags = settings::Flags::new(&shared_builder);
+
+        let mut dfg = DataFlowGraph::new();
+        let ebb = dfg.make_ebb();
+        let arg64 = dfg.append_ebb_arg(ebb, types::I64);
+        let popcnt = InstructionData::Unary {
+            opcode: Opcode::Popcnt,
+            ty: types::I8,
+            arg: arg64,
+        };
+        let ctz = InstructionData::Unary {
+            opcode: Opcode::Ctz,
+            ty: types::I8,
+            arg: arg64,
+        };
+
+        // The baseline CPU doesn't have POPCNT or BMI1, so the instructions must be expanded.
+        let mut isa_builder = isa::lookup("intel").unwrap();
+        isa_builder.set_bool("baseline", true).unwrap();
+        let isa = isa_builder.finish(shared_flags.clone());
+        assert_eq!(isa.encode(&dfg, &popcnt), Err(isa::Legalize::Expand));
+        assert_eq!(isa.encode(&dfg, &ctz), Err(isa::Legalize::Expand));
+
+        // Haswell has both.
+        let mut isa_builder = isa::lookup("intel").unwrap();
+        isa_builder.set_bool("haswell", true).unwrap();
+        let isa = isa_builder.finish(shared_flags);
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &popcnt).unwrap()), "RexMp2urm#28b8");
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &ctz).unwrap()), "RexMp2urm#28bc");
+    }
+
+    #[test]
+    fn isa_flags() {
+        let mut isa_builder = isa::lookup("intel").unwrap();
+        let default = isa_builder.iter().find(|s| s.name == "has_popcnt").unwrap();
+        assert_eq!(default.value, settings::SettingValue::Bool(false));
+
+        // Presets aren't listed, but the settings they enable are.
+        isa_builder.set_bool("haswell", true).unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(&settings::builder()));
+        let popcnt = isa.isa_flags().find(|s| s.name == "has_popcnt").unwrap();
+        assert_eq!(popcnt.kind, settings::SettingKind::Bool);
+        assert_eq!(popcnt.default, settings::SettingValue::Bool(false));
+        assert_eq!(popcnt.value, settings::SettingValue::Bool(true));
+        assert!(isa.isa_flags().all(|s| s.name != "haswell"));
+    }
+
+    #[test]
+    fn frame_settings() {
+        let mut shared_builder = settings::builder();
+        shared_builder.enable("is_64bit").unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&shared_builder));
+        assert_eq!(isa.stack_alignment(), 16);
+        assert!(isa.allocatable_registers().is_avail(registers::GPR, 5));
+
+        shared_builder.set("stack_align_log2", "2").unwrap();
+        shared_builder.enable("preserve_frame_pointers").unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&shared_builder));
+        assert_eq!(isa.stack_alignment(), 4);
+        assert!(!isa.allocatable_registers().is_avail(registers::GPR, 5));
+
+
+        // The seventh argument is passed on the stack, and the area isn't padded to 16 bytes.
+        let mut sig = Signature::new();
+        sig.argument_types = vec![ArgumentType::new(types::I64); 7];
+        isa.legalize_signature(&mut sig, false);
+        assert_eq!(sig.argument_bytes, Some(8));
+    }
+
+    #[test]
+    fn recipe_constraints() {
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_64bit", true).unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&shared_builder));
+        let recipe = |name| isa.recipe_names().iter().position(|&n| n == name).unwrap();
+
+        // Shifts take the count in `%rcx`, and the result is tied to the first operand.
+        let rc = &isa.recipe_constraints()[recipe("RexOp1rc")];
+        assert!(rc.fixed_ins);
+        assert!(!rc.fixed_outs);
+        assert!(rc.tied_ops);
+        assert!(rc.clobbers_flags);
+        assert_eq!(rc.ins[0].fixed_reg(), None);
+        assert_eq!(rc.ins[1].fixed_reg(), Some(1));
+        assert_eq!(rc.outs[0].tied_input(), Some(0));
+        assert_eq!(rc.outs[0].regclass.name, "GPR");
+
+        let umr = &isa.recipe_constraints()[recipe("RexOp1umr")];
+        assert!(!umr.fixed_ins);
+        assert!(!umr.fixed_outs);
+        assert!(!umr.tied_ops);
+        assert_eq!(umr.outs[0].kind, isa::ConstraintKind::Reg);
+    }
 }
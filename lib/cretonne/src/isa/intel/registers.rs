@@ -25,6 +25,8 @@ mod tests {
 
         assert_eq!(INFO.parse_regunit("xmm0"), Some(16));
         assert_eq!(INFO.parse_regunit("xmm15"), Some(31));
+
+        assert_eq!(INFO.parse_regunit("rflags"), Some(32));
     }
 
     #[test]
@@ -45,6 +47,7 @@ mod tests {
         assert_eq!(uname(15), "%r15");
         assert_eq!(uname(16), "%xmm0");
         assert_eq!(uname(31), "%xmm15");
+        assert_eq!(uname(32), "%rflags");
     }
 
     #[test]
@@ -59,4 +62,24 @@ mod tests {
         assert_eq!(FPR.intersect(GPR), None);
         assert_eq!(FPR.intersect(ABCD), None);
     }
+
+    #[test]
+    fn toprcs() {
+        assert_eq!(INFO.toprc(GPR).name, "GPR");
+        assert_eq!(INFO.toprc(ABCD).name, "GPR");
+        assert_eq!(INFO.toprc(FPR).name, "FPR");
+        assert!(GPR.is_toprc());
+        assert!(!ABCD.is_toprc());
+
+        let names: Vec<_> = INFO.toprcs().map(|rc| rc.to_string()).collect();
+        assert_eq!(names, ["GPR", "FPR", "FLAG"]);
+
+        assert_eq!(INFO.rc_by_name("ABCD").map(|rc| rc.index), Some(ABCD.index));
+        assert!(INFO.rc_by_name("XMM").is_none());
+
+        assert_eq!(INFO.bank_containing_regclass(ABCD).name, "IntRegs");
+        assert_eq!(INFO.bank_containing_regclass(FPR).name, "FloatRegs");
+        assert!(INFO.bank_containing_regclass(FPR).contains(16));
+        assert_eq!(INFO.bank_containing_regclass(FLAG).name, "FlagRegs");
+    }
 }
@@ -1,9 +1,46 @@
 //! Intel Settings.
 
-use settings::{self, detail, Builder};
+use settings::{self, detail, Builder, Iter};
 use std::fmt;
 
 // Include code generated by `lib/cretonne/meta/gen_settings.py`. This file contains a public
 // `Flags` struct with an impl for all of the settings defined in
 // `lib/cretonne/meta/cretonne/settings.py`.
 include!(concat!(env!("OUT_DIR"), "/settings-intel.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::{builder, Flags};
+    use settings::{self, Configurable};
+
+    #[test]
+    fn presets() {
+        let shared = settings::Flags::new(&settings::builder());
+
+        let b = builder();
+        let f = Flags::new(&shared, &b);
+        assert_eq!(f.has_sse2(), false);
+        assert_eq!(f.use_sse41(), false);
+
+        let mut b = builder();
+        b.set_bool("baseline", true).unwrap();
+        b.set("has_sse41", "1").unwrap();
+        let f = Flags::new(&shared, &b);
+        assert_eq!(f.has_sse2(), true);
+        assert_eq!(f.has_sse3(), false);
+        assert_eq!(f.use_sse41(), true);
+        assert_eq!(f.has_avx(), false);
+
+        let mut b = builder();
+        b.set_bool("haswell", true).unwrap();
+        let f = Flags::new(&shared, &b);
+        assert_eq!(f.has_sse42(), true);
+        assert_eq!(f.use_popcnt(), true);
+        assert_eq!(f.has_lzcnt(), true);
+        assert_eq!(f.has_movbe(), true);
+        assert_eq!(f.has_avx2(), true);
+        assert_eq!(f.has_bmi1(), true);
+        assert_eq!(f.has_bmi2(), true);
+        assert_eq!(f.use_avx(), true);
+    }
+}
@@ -0,0 +1,417 @@
+//! Intel unwind information.
+//!
+//! The prologue inserted by `prologue_epilogue` allocates the stack frame and saves the used
+//! callee-saved registers in spill slots with `spill` instructions at the top of the entry block.
+//! The stack pointer is used to address the frame, so there is no frame register.
+//!
+//! The unwind information can be serialized in two formats:
+//!
+//! - Windows x64 `UNWIND_INFO` structures, which are only defined for 64-bit code.
+//! - DWARF call frame instructions for `.eh_frame` or `.debug_frame` sections. The CIE
+//!   instructions returned by `write_cie_instructions()` describe the state on function entry, and
+//!   the FDE instructions returned by `write_fde_instructions()` describe the prologue.
+//!
+//! For the `baldrdash` calling convention, the words pushed by the embedder's prologue are
+//! described as a stack allocation at code offset 0. The frame itself is allocated by the
+//! `x86_adjust_sp` instruction at the top of the entry block.
+
+use binemit::CodeOffset;
+use ir::{Function, Opcode, ValueLoc, ArgumentLoc, ArgumentPurpose};
+use isa::{TargetIsa, RegUnit, UnwindInfo, UnwindCode, UnwindOp};
+use isa::intel::abi;
+use isa::intel::registers::{GPR, FPR};
+use stack_layout::layout_stack;
+use std::vec::Vec;
+
+/// Create the unwind information for `func` after prologue and epilogue insertion.
+///
+/// Returns `None` if the function has no entry block.
+pub fn create_unwind_info(func: &Function, isa: &TargetIsa) -> Option<UnwindInfo> {
+    let entry = match func.layout.entry_block() {
+        Some(ebb) => ebb,
+        None => return None,
+    };
+    let pointer_bytes = if isa.flags().is_64bit() { 8 } else { 4 };
+
+    // The return address is pushed by the caller, and the Baldrdash prologue pushes more words
+    // before our code runs.
+    let pushed = abi::frame_header_bytes(func, isa) - pointer_bytes;
+    let layout = layout_stack(func, isa.stack_alignment());
+    let allocation = abi::frame_allocation(func, isa, layout.frame_size);
+    let stack_size = pushed + allocation;
+
+    let mut codes = Vec::new();
+    if pushed > 0 {
+        codes.push(UnwindCode {
+                       offset: 0,
+                       op: UnwindOp::StackAlloc { size: pushed },
+                   });
+    }
+
+    // The callee-saved registers are passed to the entry block as `csr` arguments.
+    let csrs: Vec<_> = func.signature
+        .argument_types
+        .iter()
+        .zip(func.dfg.ebb_args(entry))
+        .filter_map(|(abi, arg)| match (abi.purpose, abi.location) {
+                        (ArgumentPurpose::CalleeSaved, ArgumentLoc::Reg(reg)) => Some((arg, reg)),
+                        _ => None,
+                    })
+        .collect();
+
+    // The prologue ends with the frame allocation or the last `spill` of a callee-saved register.
+    // The stack probes before the spills don't change the frame.
+    let sizing = isa.recipe_sizing();
+    let mut offset: CodeOffset = 0;
+    let mut prologue_size = 0;
+    for inst in func.layout.ebb_insts(entry) {
+        let enc = func.encodings.get(inst).cloned().unwrap_or_default();
+        if enc.is_legal() {
+            offset += sizing[enc.recipe()].bytes as CodeOffset;
+        }
+        match func.dfg[inst].opcode() {
+            Opcode::X86AdjustSp => {
+                codes.push(UnwindCode {
+                               offset: offset,
+                               op: UnwindOp::StackAlloc { size: allocation },
+                           });
+                prologue_size = offset;
+                continue;
+            }
+            Opcode::X86Probe => continue,
+            Opcode::Spill => {}
+            _ => break,
+        }
+        let arg = func.dfg.inst_args(inst)[0][0];
+        let reg = match csrs.iter().find(|&&(csr, _)| csr == arg) {
+            Some(&(_, reg)) => reg,
+            None => break,
+        };
+        let slot = match func.locations.get(func.dfg.first_result(inst)) {
+            Some(&ValueLoc::Stack(ss)) => ss,
+            _ => break,
+        };
+        codes.push(UnwindCode {
+                       offset: offset,
+                       op: UnwindOp::SaveReg {
+                           reg: reg,
+                           offset: layout.offsets[slot],
+                       },
+                   });
+        prologue_size = offset;
+    }
+
+    Some(UnwindInfo {
+             pointer_bytes: pointer_bytes as u8,
+             prologue_size: prologue_size,
+             frame_register: None,
+             stack_size: stack_size,
+             codes: codes,
+         })
+}
+
+// Windows x64 unwind operation codes.
+const UWOP_ALLOC_LARGE: u8 = 1;
+const UWOP_ALLOC_SMALL: u8 = 2;
+const UWOP_SAVE_NONVOL: u8 = 4;
+const UWOP_SAVE_NONVOL_FAR: u8 = 5;
+const UWOP_SAVE_XMM128: u8 = 8;
+const UWOP_SAVE_XMM128_FAR: u8 = 9;
+
+/// Serialize `info` as a Windows x64 `UNWIND_INFO` structure.
+///
+/// The unwind codes are emitted in reverse code order as required by the format, and the array
+/// is padded to an even number of slots. There are no exception handlers, so the structure ends
+/// after the unwind codes.
+pub fn write_windows_unwind_info(info: &UnwindInfo) -> Vec<u8> {
+    assert_eq!(info.pointer_bytes, 8, "Windows unwind info is only defined for 64-bit code");
+    assert!(info.prologue_size <= 0xff, "Prologue too large for Windows unwind info");
+
+    // Each unwind code is one or more 16-bit slots.
+    let mut slots: Vec<u16> = Vec::new();
+    for code in info.codes.iter().rev() {
+        let offset = code.offset as u16;
+        let push_op = |slots: &mut Vec<u16>, op: u8, op_info: u8| {
+            slots.push(offset | (op as u16) << 8 | (op_info as u16) << 12);
+        };
+        match code.op {
+            UnwindOp::StackAlloc { size } => {
+                assert_eq!(size % 8, 0, "Unaligned stack allocation");
+                if size <= 128 {
+                    push_op(&mut slots, UWOP_ALLOC_SMALL, (size / 8 - 1) as u8);
+                } else if size / 8 <= 0xffff {
+                    push_op(&mut slots, UWOP_ALLOC_LARGE, 0);
+                    slots.push((size / 8) as u16);
+                } else {
+                    push_op(&mut slots, UWOP_ALLOC_LARGE, 1);
+                    slots.push(size as u16);
+                    slots.push((size >> 16) as u16);
+                }
+            }
+            UnwindOp::SaveReg { reg, offset } => {
+                let (num, scale, near, far) = if FPR.contains(reg) {
+                    (reg - FPR.first, 16, UWOP_SAVE_XMM128, UWOP_SAVE_XMM128_FAR)
+                } else {
+                    (reg - GPR.first, 8, UWOP_SAVE_NONVOL, UWOP_SAVE_NONVOL_FAR)
+                };
+                if offset % scale == 0 && offset / scale <= 0xffff {
+                    push_op(&mut slots, near, num as u8);
+                    slots.push((offset / scale) as u16);
+                } else {
+                    push_op(&mut slots, far, num as u8);
+                    slots.push(offset as u16);
+                    slots.push((offset >> 16) as u16);
+                }
+            }
+        }
+    }
+
+    // Version 1, no flags, no frame register.
+    let mut bytes = vec![1, info.prologue_size as u8, slots.len() as u8, 0];
+    if slots.len() % 2 != 0 {
+        slots.push(0);
+    }
+    for slot in slots {
+        bytes.push(slot as u8);
+        bytes.push((slot >> 8) as u8);
+    }
+    bytes
+}
+
+// DWARF call frame instructions.
+const DW_CFA_ADVANCE_LOC: u8 = 0x40;
+const DW_CFA_OFFSET: u8 = 0x80;
+const DW_CFA_ADVANCE_LOC1: u8 = 0x02;
+const DW_CFA_ADVANCE_LOC2: u8 = 0x03;
+const DW_CFA_ADVANCE_LOC4: u8 = 0x04;
+const DW_CFA_OFFSET_EXTENDED: u8 = 0x05;
+const DW_CFA_DEF_CFA: u8 = 0x0c;
+const DW_CFA_DEF_CFA_OFFSET: u8 = 0x0e;
+
+/// Get the DWARF register number of `reg`.
+fn dwarf_register(reg: RegUnit, pointer_bytes: u8) -> u64 {
+    if FPR.contains(reg) {
+        let base = if pointer_bytes == 8 { 17 } else { 21 };
+        return base + (reg - FPR.first) as u64;
+    }
+    let num = reg - GPR.first;
+    if pointer_bytes == 4 {
+        return num as u64;
+    }
+    // The x86-64 numbering swaps some of the legacy registers.
+    match num {
+        1 => 2, // rcx
+        2 => 1, // rdx
+        4 => 7, // rsp
+        5 => 6, // rbp
+        6 => 4, // rsi
+        7 => 5, // rdi
+        n => n as u64,
+    }
+}
+
+/// Get the DWARF register number of the return address column.
+fn return_address_register(pointer_bytes: u8) -> u64 {
+    if pointer_bytes == 8 { 16 } else { 8 }
+}
+
+/// Append `value` to `bytes` as an unsigned LEB128 number.
+fn write_uleb128(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Get the data alignment factor to use in the CIE for code with `pointer_bytes` pointers.
+///
+/// The code alignment factor is 1.
+pub fn data_alignment_factor(pointer_bytes: u8) -> i64 {
+    -(pointer_bytes as i64)
+}
+
+/// Get the DWARF call frame instructions for the CIE, describing the state on function entry.
+///
+/// The canonical frame address is the value of the stack pointer before the call, and the return
+/// address is stored just below it.
+pub fn write_cie_instructions(pointer_bytes: u8) -> Vec<u8> {
+    let mut bytes = vec![DW_CFA_DEF_CFA];
+    write_uleb128(&mut bytes, dwarf_register(GPR.unit(4), pointer_bytes));
+    write_uleb128(&mut bytes, pointer_bytes as u64);
+    write_offset(&mut bytes, return_address_register(pointer_bytes), 1);
+    bytes
+}
+
+/// Get the DWARF call frame instructions for the FDE of the function described by `info`.
+///
+/// The instructions assume the CIE returned by `write_cie_instructions()`.
+pub fn write_fde_instructions(info: &UnwindInfo) -> Vec<u8> {
+    let ptr = info.pointer_bytes as u32;
+    let mut bytes = Vec::new();
+    let mut loc = 0;
+    let mut cfa_offset = ptr;
+    for code in &info.codes {
+        advance_loc(&mut bytes, code.offset - loc);
+        loc = code.offset;
+        match code.op {
+            UnwindOp::StackAlloc { size } => {
+                cfa_offset += size;
+                bytes.push(DW_CFA_DEF_CFA_OFFSET);
+                write_uleb128(&mut bytes, cfa_offset as u64);
+            }
+            UnwindOp::SaveReg { reg, offset } => {
+                let dist = cfa_offset - offset;
+                assert_eq!(dist % ptr, 0, "Misaligned register save");
+                write_offset(&mut bytes,
+                             dwarf_register(reg, info.pointer_bytes),
+                             (dist / ptr) as u64);
+            }
+        }
+    }
+    bytes
+}
+
+/// Emit a `DW_CFA_advance_loc` instruction with the smallest encoding for `delta`.
+fn advance_loc(bytes: &mut Vec<u8>, delta: CodeOffset) {
+    if delta == 0 {
+        return;
+    }
+    if delta < 0x40 {
+        bytes.push(DW_CFA_ADVANCE_LOC | delta as u8);
+    } else if delta <= 0xff {
+        bytes.push(DW_CFA_ADVANCE_LOC1);
+        bytes.push(delta as u8);
+    } else if delta <= 0xffff {
+        bytes.push(DW_CFA_ADVANCE_LOC2);
+        bytes.extend_from_slice(&[delta as u8, (delta >> 8) as u8]);
+    } else {
+        bytes.push(DW_CFA_ADVANCE_LOC4);
+        bytes.extend_from_slice(&[delta as u8,
+                                  (delta >> 8) as u8,
+                                  (delta >> 16) as u8,
+                                  (delta >> 24) as u8]);
+    }
+}
+
+/// Emit an instruction saying that `reg` is saved at `CFA - factored * pointer_bytes`.
+fn write_offset(bytes: &mut Vec<u8>, reg: u64, factored: u64) {
+    if reg < 0x40 {
+        bytes.push(DW_CFA_OFFSET | reg as u8);
+    } else {
+        bytes.push(DW_CFA_OFFSET_EXTENDED);
+        write_uleb128(bytes, reg);
+    }
+    write_uleb128(bytes, factored);
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{CallConv, Cursor, InstBuilder, StackSlotData, StackSlotKind, VariableArgs};
+    use isa::{self, UnwindInfo, UnwindCode, UnwindOp};
+    use isa::intel::registers::{GPR, FPR};
+    use settings::{self, Configurable};
+    use super::*;
+
+    fn make_info(stack_size: u32, saves: &[(CodeOffset, RegUnit, u32)]) -> UnwindInfo {
+        let mut codes = vec![UnwindCode {
+                                 offset: 0,
+                                 op: UnwindOp::StackAlloc { size: stack_size },
+                             }];
+        for &(offset, reg, slot) in saves {
+            codes.push(UnwindCode {
+                           offset: offset,
+                           op: UnwindOp::SaveReg {
+                               reg: reg,
+                               offset: slot,
+                           },
+                       });
+        }
+        UnwindInfo {
+            pointer_bytes: 8,
+            prologue_size: saves.last().map_or(0, |s| s.0),
+            frame_register: None,
+            stack_size: stack_size,
+            codes: codes,
+        }
+    }
+
+    #[test]
+    fn windows() {
+        // Save `rbx` and `xmm6`.
+        let info = make_info(24, &[(8, GPR.unit(3), 0), (17, FPR.unit(6), 16)]);
+        assert_eq!(write_windows_unwind_info(&info),
+                   vec![1, 17, 5, 0,
+                        // UWOP_SAVE_XMM128 xmm6, 16 / 16
+                        17, 0x68, 1, 0,
+                        // UWOP_SAVE_NONVOL rbx, 0
+                        8, 0x34, 0, 0,
+                        // UWOP_ALLOC_SMALL (24 - 8) / 8
+                        0, 0x22,
+                        // Padding
+                        0, 0]);
+
+        // Large allocations need extra slots.
+        let info = make_info(0x1000, &[]);
+        assert_eq!(write_windows_unwind_info(&info),
+                   vec![1, 0, 2, 0, 0, 0x01, 0x00, 0x02]);
+    }
+
+    #[test]
+    fn dwarf() {
+        assert_eq!(write_cie_instructions(8), vec![DW_CFA_DEF_CFA, 7, 8, DW_CFA_OFFSET | 16, 1]);
+        assert_eq!(write_cie_instructions(4), vec![DW_CFA_DEF_CFA, 4, 4, DW_CFA_OFFSET | 8, 1]);
+
+        // The CFA is 32 bytes above the stack pointer after the allocation, so `rbx` at offset 8
+        // is saved at CFA-24.
+        let info = make_info(24, &[(8, GPR.unit(3), 8), (16, GPR.unit(12), 16)]);
+        assert_eq!(write_fde_instructions(&info),
+                   vec![DW_CFA_DEF_CFA_OFFSET,
+                        32,
+                        DW_CFA_ADVANCE_LOC | 8,
+                        DW_CFA_OFFSET | 3,
+                        3,
+                        DW_CFA_ADVANCE_LOC | 8,
+                        DW_CFA_OFFSET | 12,
+                        2]);
+    }
+
+    #[test]
+    fn baldrdash_prologue() {
+        let mut b = settings::builder();
+        b.enable("is_64bit").unwrap();
+        b.set("baldrdash_prologue_words", "2").unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&b));
+
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+        let stack_size = |func: &Function| create_unwind_info(func, &*isa).unwrap().stack_size;
+        assert_eq!(stack_size(&func), 0);
+        func.signature.call_conv = CallConv::Baldrdash;
+        assert_eq!(stack_size(&func), 16);
+
+        // The 16-byte frame sits below the return address and the two prologue words, so it
+        // needs 8 bytes of padding.
+        func.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+        assert_eq!(stack_size(&func), 40);
+        func.signature.call_conv = CallConv::SystemV;
+        assert_eq!(stack_size(&func), 24);
+    }
+
+    #[test]
+    fn leb128() {
+        let mut bytes = Vec::new();
+        write_uleb128(&mut bytes, 624485);
+        assert_eq!(bytes, vec![0xe5, 0x8e, 0x26]);
+    }
+}
@@ -9,9 +9,9 @@
 //!
 //! The target ISA is built from the following information:
 //!
-//! - The name of the target ISA as a string. Cretonne is a cross-compiler, so the ISA to target
-//!   can be selected dynamically. Individual ISAs can be left out when Cretonne is compiled, so a
-//!   string is used to identify the proper sub-module.
+//! - The name of the target ISA or a target triple as a string. Cretonne is a cross-compiler, so
+//!   the ISA to target can be selected dynamically. Individual ISAs can be left out when Cretonne
+//!   is compiled, so a string is used to identify the proper sub-module.
 //! - Values for settings that apply to all ISAs. This is represented by a `settings::Flags`
 //!   instance.
 //! - Values for ISA-specific settings.
@@ -26,63 +26,135 @@
 //! let shared_builder = settings::builder();
 //! let shared_flags = settings::Flags::new(&shared_builder);
 //!
-//! match isa::lookup("riscv") {
-//!     None => {
+//! match isa::lookup("riscv64-unknown-linux-gnu") {
+//!     Err(_) => {
 //!         // The RISC-V target ISA is not available.
 //!     }
-//!     Some(mut isa_builder) => {
+//!     Ok(mut isa_builder) => {
 //!         isa_builder.set("supports_m", "on");
 //!         let isa = isa_builder.finish(shared_flags);
+//!         assert!(isa.flags().is_64bit());
 //!     }
 //! }
 //! ```
 //!
+//! The ISAs can be selected by their short names `riscv`, `intel`, `arm32`, and `arm64`. Target
+//! triples like `x86_64-unknown-linux-gnu` are identified by their architecture, which also
+//! determines the `is_64bit` setting. This is also true for short names with an explicit size like
+//! `riscv32`.
+//!
 //! The configured target ISA trait object is a `Box<TargetIsa>` which can be used for multiple
 //! concurrent function compilations.
 
-pub use isa::encoding::Encoding;
+pub use isa::encoding::{Encoding, RecipeSizing, BranchRange};
 pub use isa::registers::{RegInfo, RegUnit, RegClass, RegClassIndex};
 pub use isa::constraints::{RecipeConstraints, OperandConstraint, ConstraintKind};
+pub use isa::unwind::{UnwindInfo, UnwindCode, UnwindOp, DisplayUnwindInfo};
 
-use settings;
-use ir::{InstructionData, DataFlowGraph, Signature};
+use settings::{self, Configurable};
+use binemit::{CodeSink, Reloc};
+use regalloc::AllocatableSet;
+use regalloc::diversion::RegDiversions;
+use ir::{Function, Inst, InstructionData, DataFlowGraph, Cursor, Signature, CallConv, GlobalVar};
+use std::fmt;
+use std::boxed::Box;
+use std::vec::Vec;
 
+#[cfg(feature = "riscv")]
 pub mod riscv;
+#[cfg(feature = "intel")]
 pub mod intel;
+#[cfg(feature = "arm32")]
 pub mod arm32;
+#[cfg(feature = "arm64")]
 pub mod arm64;
 pub mod registers;
 mod encoding;
 mod enc_tables;
 mod constraints;
+mod unwind;
 
-/// Look for a supported ISA with the given `name`.
+/// Look for a supported ISA with the given `name`, which is either the short name of an ISA or a
+/// target triple.
 /// Return a builder that can create a corresponding `TargetIsa`.
-pub fn lookup(name: &str) -> Option<Builder> {
-    match name {
-        "riscv" => riscv_builder(),
-        "intel" => intel_builder(),
-        "arm32" => arm32_builder(),
-        "arm64" => arm64_builder(),
-        _ => None,
+pub fn lookup(name: &str) -> Result<Builder, LookupError> {
+    // Only the architecture part of a target triple is needed.
+    let arch = name.split('-').next().unwrap_or(name);
+    let (builder, is_64bit) = match arch {
+        "riscv" => (riscv_builder(), None),
+        "riscv32" => (riscv_builder(), Some(false)),
+        "riscv64" => (riscv_builder(), Some(true)),
+        "intel" => (intel_builder(), None),
+        "i386" | "i486" | "i586" | "i686" | "x86" => (intel_builder(), Some(false)),
+        "x86_64" | "amd64" => (intel_builder(), Some(true)),
+        "arm32" | "arm" | "armv7" => (arm32_builder(), Some(false)),
+        "arm64" | "aarch64" => (arm64_builder(), Some(true)),
+        _ => return Err(LookupError::Unknown),
+    };
+    builder.map(|mut builder| {
+                    builder.is_64bit = is_64bit;
+                    builder
+                })
+}
+
+/// Describes the reason for a target lookup failure.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LookupError {
+    /// The name doesn't identify a known ISA.
+    Unknown,
+
+    /// The ISA is known, but support for it was not compiled in.
+    Unsupported,
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LookupError::Unknown => f.write_str("unknown ISA"),
+            LookupError::Unsupported => f.write_str("unsupported ISA"),
+        }
     }
 }
 
 // Make a builder for RISC-V.
-fn riscv_builder() -> Option<Builder> {
-    Some(riscv::isa_builder())
+#[cfg(feature = "riscv")]
+fn riscv_builder() -> Result<Builder, LookupError> {
+    Ok(riscv::isa_builder())
+}
+
+#[cfg(not(feature = "riscv"))]
+fn riscv_builder() -> Result<Builder, LookupError> {
+    Err(LookupError::Unsupported)
 }
 
-fn intel_builder() -> Option<Builder> {
-    Some(intel::isa_builder())
+#[cfg(feature = "intel")]
+fn intel_builder() -> Result<Builder, LookupError> {
+    Ok(intel::isa_builder())
 }
 
-fn arm32_builder() -> Option<Builder> {
-    Some(arm32::isa_builder())
+#[cfg(not(feature = "intel"))]
+fn intel_builder() -> Result<Builder, LookupError> {
+    Err(LookupError::Unsupported)
 }
 
-fn arm64_builder() -> Option<Builder> {
-    Some(arm64::isa_builder())
+#[cfg(feature = "arm32")]
+fn arm32_builder() -> Result<Builder, LookupError> {
+    Ok(arm32::isa_builder())
+}
+
+#[cfg(not(feature = "arm32"))]
+fn arm32_builder() -> Result<Builder, LookupError> {
+    Err(LookupError::Unsupported)
+}
+
+#[cfg(feature = "arm64")]
+fn arm64_builder() -> Result<Builder, LookupError> {
+    Ok(arm64::isa_builder())
+}
+
+#[cfg(not(feature = "arm64"))]
+fn arm64_builder() -> Result<Builder, LookupError> {
+    Err(LookupError::Unsupported)
 }
 
 /// Builder for a `TargetIsa`.
@@ -90,14 +162,36 @@ fn arm64_builder() -> Option<Builder> {
 pub struct Builder {
     setup: settings::Builder,
     constructor: fn(settings::Flags, &settings::Builder) -> Box<TargetIsa>,
+    /// The `is_64bit` setting implied by the target triple, if any.
+    is_64bit: Option<bool>,
 }
 
 impl Builder {
     /// Combine the ISA-specific settings with the provided ISA-independent settings and allocate a
     /// fully configured `TargetIsa` trait object.
+    ///
+    /// When the ISA was looked up with a target triple, its `is_64bit` setting overrides the one
+    /// in `shared_flags`.
     pub fn finish(self, shared_flags: settings::Flags) -> Box<TargetIsa> {
+        let shared_flags = match self.is_64bit {
+            Some(is_64bit) if is_64bit != shared_flags.is_64bit() => {
+                let mut builder = shared_flags.to_builder();
+                builder
+                    .set_bool("is_64bit", is_64bit)
+                    .expect("is_64bit is a shared setting");
+                settings::Flags::new(&builder)
+            }
+            _ => shared_flags,
+        };
         (self.constructor)(shared_flags, &self.setup)
     }
+
+    /// Iterate over the ISA-specific settings and their current values.
+    ///
+    /// This can be used to list the available settings before the ISA is configured.
+    pub fn iter(&self) -> settings::Iter {
+        self.setup.iter()
+    }
 }
 
 impl settings::Configurable for Builder {
@@ -113,7 +207,8 @@ impl settings::Configurable for Builder {
 /// After determining that an instruction doesn't have an encoding, how should we proceed to
 /// legalize it?
 ///
-/// These actions correspond to the transformation groups defined in `meta/cretonne/legalize.py`.
+/// The `Narrow` and `Expand` actions correspond to the transformation groups defined in
+/// `meta/cretonne/legalize.py`.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Legalize {
     /// Legalize in terms of narrower types.
@@ -121,19 +216,70 @@ pub enum Legalize {
 
     /// Expanding in terms of other instructions using the same types.
     Expand,
+
+    /// Legalize with the ISA-specific routine returned by `TargetIsa::custom_legalization()`.
+    Custom(u8),
+}
+
+impl fmt::Display for Legalize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Legalize::Narrow => f.write_str("narrow"),
+            Legalize::Expand => f.write_str("expand"),
+            Legalize::Custom(code) => write!(f, "custom{}", code),
+        }
+    }
+}
+
+/// A custom legalization routine provided by a target ISA.
+///
+/// The routine is called with the cursor pointing at an instruction without a legal encoding. It
+/// returns `true` if the instruction was replaced, or `false` if the generic legalization actions
+/// should be tried instead.
+pub type LegalizeFn = fn(&mut Cursor, &mut DataFlowGraph, &TargetIsa) -> bool;
+
+/// The default custom legalization routine which leaves the instruction alone.
+fn no_custom_legalization(_pos: &mut Cursor, _dfg: &mut DataFlowGraph, _isa: &TargetIsa) -> bool {
+    false
 }
 
 /// Methods that are specialized to a target ISA.
-pub trait TargetIsa {
+///
+/// ISA instances are immutable, so they can be shared between compilation threads.
+pub trait TargetIsa: Send + Sync {
     /// Get the name of this ISA.
     fn name(&self) -> &'static str;
 
     /// Get the ISA-independent flags that were used to make this trait object.
     fn flags(&self) -> &settings::Flags;
 
+    /// Iterate over the ISA-specific settings that were used to make this trait object.
+    ///
+    /// Together with the shared settings returned by `flags()`, these make up the full
+    /// configuration of the ISA.
+    fn isa_flags(&self) -> settings::Iter;
+
     /// Get a data structure describing the registers in this ISA.
     fn register_info(&self) -> RegInfo;
 
+    /// Get the alignment of the stack pointer at function calls, in bytes.
+    ///
+    /// This is always a power of two. It is the largest alignment the stack frame layout can
+    /// guarantee for a stack slot, and the size of the outgoing argument area of a call is rounded
+    /// up to a multiple of it. Embedders generating their own calls into compiled code must also
+    /// respect this alignment.
+    fn stack_alignment(&self) -> u32;
+
+    /// Get the number of instructions it is worth executing speculatively to avoid a conditional
+    /// branch.
+    ///
+    /// The if-conversion pass replaces short branch diamonds with `select` instructions when the
+    /// code on both sides is no longer than this. ISAs without conditional moves should return 0,
+    /// which is the default.
+    fn if_conversion_limit(&self) -> usize {
+        0
+    }
+
     /// Encode an instruction after determining it is legal.
     ///
     /// If `inst` can legally be encoded in this ISA, produce the corresponding `Encoding` object.
@@ -142,6 +288,15 @@ pub trait TargetIsa {
     /// This is also the main entry point for determining if an instruction is legal.
     fn encode(&self, dfg: &DataFlowGraph, inst: &InstructionData) -> Result<Encoding, Legalize>;
 
+    /// Get all the legal encodings of an instruction.
+    ///
+    /// The encodings are ordered from the most specific to the most general, so the last one is
+    /// the encoding returned by `encode()`. The list is never empty when `Ok` is returned.
+    fn legal_encodings(&self,
+                       dfg: &DataFlowGraph,
+                       inst: &InstructionData)
+                       -> Result<Vec<Encoding>, Legalize>;
+
     /// Get a static array of names associated with encoding recipes in this ISA. Encoding recipes
     /// are numbered starting from 0, corresponding to indexes into the name array.
     ///
@@ -154,6 +309,50 @@ pub trait TargetIsa {
     /// The constraints describe which registers can be used with an encoding recipe.
     fn recipe_constraints(&self) -> &'static [RecipeConstraints];
 
+    /// Get a static array of code size information associated with encoding recipes in this ISA.
+    ///
+    /// The sizes are used by branch relaxation to compute code offsets and pick branch encodings
+    /// that can reach their destination.
+    fn recipe_sizing(&self) -> &'static [RecipeSizing];
+
+    /// Emit binary machine code for a single instruction into the `sink` trait object.
+    ///
+    /// The instruction must have a legal encoding, and its operands must be assigned to locations
+    /// that satisfy the encoding recipe's constraints. The registers are looked up in
+    /// `func.locations` as modified by `divert`, which is updated if `inst` is a `regmove`.
+    ///
+    /// The default implementation is used by the ISAs that have encodings but can't emit machine
+    /// code yet. It fills the space of the instruction with zero bytes, so the emitted code still
+    /// has the size computed by branch relaxation.
+    fn emit_inst(&self,
+                 func: &Function,
+                 inst: Inst,
+                 _divert: &mut RegDiversions,
+                 sink: &mut CodeSink) {
+        let enc = func.encodings.get(inst).cloned().unwrap_or_default();
+        if enc.is_legal() {
+            for _ in 0..self.recipe_sizing()[enc.recipe()].bytes {
+                sink.put1(0);
+            }
+        }
+    }
+
+    /// Get a static array of names associated with relocation kinds in this ISA. A `Reloc(n)`
+    /// corresponds to the name at index `n`.
+    ///
+    /// The default implementation returns an empty list for ISAs without relocations.
+    fn reloc_names(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Get the relocation kind for the absolute EBB addresses in the jump tables of code that
+    /// isn't position-independent.
+    ///
+    /// The default implementation returns `None` for ISAs without absolute relocations.
+    fn jump_table_reloc(&self) -> Option<Reloc> {
+        None
+    }
+
     /// Create an object that can display an ISA-dependent encoding properly.
     fn display_enc(&self, enc: Encoding) -> encoding::DisplayEncoding {
         encoding::DisplayEncoding {
@@ -177,8 +376,110 @@ pub trait TargetIsa {
     ///   broken into smaller integer types.
     /// - Vector types can be bit-cast and broken down into smaller vectors or scalars.
     ///
+    /// Special purpose arguments like the `link` register or the struct return pointer are
+    /// assigned to the locations required by the calling convention, so the register allocator
+    /// and the prologue generation know where the incoming values live.
+    ///
+    /// The `current` flag is set when `sig` is the signature of the function being compiled rather
+    /// than a called function. ISAs that pass the return address in a register add a `link`
+    /// argument to the current function's signature if it doesn't have one already.
+    ///
     /// The legalizer will adapt argument and return values as necessary at all ABI boundaries.
-    fn legalize_signature(&self, _sig: &mut Signature) {
-        unimplemented!()
+    ///
+    /// When the calling convention of `sig` has special rules for the stack argument array, the
+    /// ISA should also set `sig.argument_bytes`. Otherwise, the legalizer computes it from the
+    /// assigned stack locations and `stack_alignment()`.
+    ///
+    /// The default implementation leaves the signature unchanged.
+    fn legalize_signature(&self, _sig: &mut Signature, _current: bool) {}
+
+    /// Get the custom legalization routine for the `Legalize::Custom(code)` action.
+    ///
+    /// ISAs that return custom legalization actions from `encode()` must implement this. The
+    /// default implementation returns a routine that doesn't change anything, so the legalizer
+    /// falls back to the generic actions.
+    fn custom_legalization(&self, _code: u8) -> LegalizeFn {
+        no_custom_legalization
+    }
+
+    /// Get the set of registers that the register allocator can assign to values.
+    ///
+    /// Registers with a dedicated purpose like a stack pointer or a hardwired zero register are
+    /// reserved and not included. The default implementation makes all registers allocatable.
+    fn allocatable_registers(&self) -> AllocatableSet {
+        AllocatableSet::new()
+    }
+
+    /// Get the registers that are preserved across calls using the `call_conv` calling
+    /// convention.
+    ///
+    /// A function must save and restore these registers if it uses them. All other allocatable
+    /// registers are clobbered by calls. The default implementation returns an empty list.
+    fn callee_saved_registers(&self, _call_conv: CallConv) -> &'static [RegUnit] {
+        &[]
+    }
+
+    /// Insert stack probes in the prologue of `func` for a stack frame of `frame_size` bytes.
+    ///
+    /// This is called by the prologue insertion when the frame is larger than the guard region
+    /// given by the `probestack_size_log2` setting. The probes must touch each page of the frame
+    /// from the top down before anything else uses it. The default implementation does nothing
+    /// for ISAs that don't need to probe their stacks.
+    fn insert_stack_probes(&self, _func: &mut Function, _frame_size: u32) {}
+
+    /// Allocate a stack frame of `frame_size` bytes in the prologue of `func`, and free it before
+    /// every instruction that leaves the function.
+    ///
+    /// This is called by the prologue insertion after the callee-saved registers are saved and the
+    /// stack probes are inserted, so the allocation comes first in the entry block. The default
+    /// implementation does nothing for ISAs that don't allocate their stack frames yet.
+    fn insert_frame_allocation(&self, _func: &mut Function, _frame_size: u32) {}
+
+    /// Insert a stack overflow check at the top of the entry block of `func`, before the
+    /// `frame_size` byte stack frame is allocated.
+    ///
+    /// The lowest address the stack pointer may reach is stored at the address of the global
+    /// variable `limit`, and the check traps with `stk_ovf` when the stack pointer would go below
+    /// it. The default implementation does nothing for ISAs that don't allocate their stack frames
+    /// yet.
+    fn insert_stack_check(&self, _func: &mut Function, _limit: GlobalVar, _frame_size: u32) {}
+
+    /// Create the unwind information for `func`, describing the stack frame set up by its
+    /// prologue.
+    ///
+    /// This must be called after prologue and epilogue insertion. The default implementation
+    /// returns `None` for ISAs that can't describe their stack frames yet.
+    fn create_unwind_info(&self, _func: &Function) -> Option<UnwindInfo> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use settings;
+    use super::{lookup, LookupError};
+
+    fn is_64bit(name: &str) -> bool {
+        lookup(name)
+            .unwrap()
+            .finish(settings::Flags::new(&settings::builder()))
+            .flags()
+            .is_64bit()
+    }
+
+    #[test]
+    fn triples() {
+        assert_eq!(lookup("x86_64-unknown-linux-gnu").unwrap().is_64bit, Some(true));
+        assert_eq!(lookup("i686-pc-windows-msvc").unwrap().is_64bit, Some(false));
+        assert_eq!(lookup("riscv32").unwrap().is_64bit, Some(false));
+        assert_eq!(lookup("aarch64-apple-ios").unwrap().is_64bit, Some(true));
+        assert_eq!(lookup("intel").unwrap().is_64bit, None);
+        assert_eq!(lookup("mips-unknown-linux-gnu").err(), Some(LookupError::Unknown));
+        assert_eq!(lookup("").err(), Some(LookupError::Unknown));
+
+        assert!(is_64bit("x86_64-unknown-linux-gnu"));
+        assert!(is_64bit("riscv64"));
+        assert!(!is_64bit("riscv32"));
+        assert!(!is_64bit("intel"));
     }
 }
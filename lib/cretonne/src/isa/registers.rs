@@ -2,6 +2,7 @@
 
 use entity_map::EntityRef;
 use std::fmt;
+use std::slice;
 
 /// Register units are the smallest units of register allocation.
 ///
@@ -46,7 +47,7 @@ pub struct RegBank {
 
 impl RegBank {
     /// Does this bank contain `regunit`?
-    fn contains(&self, regunit: RegUnit) -> bool {
+    pub fn contains(&self, regunit: RegUnit) -> bool {
         regunit >= self.first_unit && regunit - self.first_unit < self.units
     }
 
@@ -105,6 +106,13 @@ pub struct RegClassData {
     /// The index of this class in the ISA's RegInfo description.
     pub index: u8,
 
+    /// The index of the register bank holding this class in the ISA's RegInfo description.
+    pub bank: u8,
+
+    /// The index of the top-level register class containing this class. This is the class's own
+    /// index for a top-level class.
+    pub toprc: u8,
+
     /// How many register units to allocate per register.
     pub width: u8,
 
@@ -146,11 +154,34 @@ impl RegClassData {
         self.subclasses & (1 << other.into().0) != 0
     }
 
+    /// Does this register class contain the register starting at `regunit`?
+    pub fn contains(&self, regunit: RegUnit) -> bool {
+        let word = (regunit / 32) as usize;
+        word < self.mask.len() && self.mask[word] & (1 << (regunit % 32)) != 0
+    }
+
     /// Get a specific register unit in this class.
     pub fn unit(&self, offset: usize) -> RegUnit {
         let uoffset = offset * self.width as usize;
         self.first + uoffset as RegUnit
     }
+
+    /// Is this a top-level register class?
+    pub fn is_toprc(&self) -> bool {
+        self.toprc == self.index
+    }
+}
+
+impl fmt::Display for RegClassData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl fmt::Debug for RegClassData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name)
+    }
 }
 
 /// A small reference to a register class.
@@ -197,6 +228,12 @@ impl RegInfo {
         self.banks.iter().find(|b| b.contains(regunit))
     }
 
+    /// Get the top-level register class containing `regunit`.
+    pub fn toprc_containing_regunit(&self, regunit: RegUnit) -> Option<RegClass> {
+        // The classes are ordered topologically, so the first match is a top-level class.
+        self.classes.iter().find(|rc| rc.contains(regunit))
+    }
+
     /// Try to parse a regunit name. The name is not expected to begin with `%`.
     pub fn parse_regunit(&self, name: &str) -> Option<RegUnit> {
         self.banks.iter().filter_map(|b| b.parse_regunit(name)).next()
@@ -214,6 +251,40 @@ impl RegInfo {
     pub fn rc(&self, idx: RegClassIndex) -> RegClass {
         &self.classes[idx.index()]
     }
+
+    /// Look up a register class by name.
+    pub fn rc_by_name(&self, name: &str) -> Option<RegClass> {
+        self.classes.iter().find(|rc| rc.name == name)
+    }
+
+    /// Get the top-level register class containing `rc`.
+    pub fn toprc(&self, rc: RegClass) -> RegClass {
+        &self.classes[rc.toprc as usize]
+    }
+
+    /// Iterate over the top-level register classes. They are disjoint, and every register class is
+    /// a subclass of one of them.
+    pub fn toprcs(&self) -> TopRegClasses {
+        TopRegClasses { classes: self.classes.iter() }
+    }
+
+    /// Get the register bank holding the registers in `rc`.
+    pub fn bank_containing_regclass(&self, rc: RegClass) -> &RegBank {
+        &self.banks[rc.bank as usize]
+    }
+}
+
+/// Iterator over the top-level register classes of an ISA.
+pub struct TopRegClasses {
+    classes: slice::Iter<'static, RegClassData>,
+}
+
+impl Iterator for TopRegClasses {
+    type Item = RegClass;
+
+    fn next(&mut self) -> Option<RegClass> {
+        self.classes.find(|rc| rc.is_toprc())
+    }
 }
 
 /// Temporary object that holds enough information to print a register unit.
@@ -4,15 +4,30 @@
 //! entry point.
 //!
 //! This doesn't support the soft-float ABI at the moment.
+//!
+//! Small integer arguments with a `uext` or `sext` flag are extended to the full register width.
+//! The return address is passed in `x1`, so `link` arguments and return values are assigned to it.
+//! The function being compiled gets a `link` argument if its signature doesn't have one, so it can
+//! return with `return_reg`. Other special purpose arguments are passed like normal arguments.
+//!
+//! The registers `s0`-`s11` and `fs0`-`fs11` are preserved across calls.
 
-use abi::{ArgAction, ArgAssigner, legalize_args};
-use ir::{Signature, ArgumentType, ArgumentLoc};
+use abi::{ArgAction, ArgAssigner, ValueConversion, legalize_args};
+use ir::{Signature, Type, ArgumentType, ArgumentLoc, ArgumentPurpose, ArgumentExtension};
+use ir::types;
+use isa::RegUnit;
 use isa::riscv::registers::{GPR, FPR};
 use settings as shared_settings;
 
+/// Callee-saved registers: `s0`-`s11` (`x8`, `x9`, `x18`-`x27`), and `fs0`-`fs11` (`f8`, `f9`,
+/// `f18`-`f27`).
+static CSR: [RegUnit; 24] = [8, 9, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 40, 41, 50, 51, 52, 53,
+                             54, 55, 56, 57, 58, 59];
+
 struct Args {
     pointer_bits: u16,
     pointer_bytes: u32,
+    pointer_type: Type,
     regs: u32,
     offset: u32,
 }
@@ -22,6 +37,7 @@ impl Args {
         Args {
             pointer_bits: bits,
             pointer_bytes: bits as u32 / 8,
+            pointer_type: if bits == 64 { types::I64 } else { types::I32 },
             regs: 0,
             offset: 0,
         }
@@ -36,10 +52,15 @@ impl ArgAssigner for Args {
 
         let ty = arg.value_type;
 
+        // The return address lives in `ra`.
+        if arg.purpose == ArgumentPurpose::Link {
+            return ArgAction::Assign(ArgumentLoc::Reg(GPR.unit(1)));
+        }
+
         // Check for a legal type.
         // RISC-V doesn't have SIMD at all, so break all vectors down.
         if !ty.is_scalar() {
-            return ArgAction::Split;
+            return ValueConversion::VectorSplit.into();
         }
 
         // Large integers and booleans are broken down to fit in a register.
@@ -47,7 +68,16 @@ impl ArgAssigner for Args {
             // Align registers and stack to a multiple of two pointers.
             self.regs = align(self.regs, 2);
             self.offset = align(self.offset, 2 * self.pointer_bytes);
-            return ArgAction::Split;
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Small integers are extended to the size of a pointer register.
+        if ty.is_int() && ty.bits() < self.pointer_bits {
+            match arg.extension {
+                ArgumentExtension::None => {}
+                ArgumentExtension::Uext => return ValueConversion::Uext(self.pointer_type).into(),
+                ArgumentExtension::Sext => return ValueConversion::Sext(self.pointer_type).into(),
+            }
         }
 
         if self.regs < 8 {
@@ -69,7 +99,10 @@ impl ArgAssigner for Args {
 }
 
 /// Legalize `sig` for RISC-V.
-pub fn legalize_signature(sig: &mut Signature, flags: &shared_settings::Flags) {
+///
+/// When `current` is set, `sig` is the signature of the function being compiled, and it receives
+/// the return address as a `link` argument.
+pub fn legalize_signature(sig: &mut Signature, flags: &shared_settings::Flags, current: bool) {
     let bits = if flags.is_64bit() { 64 } else { 32 };
 
     let mut args = Args::new(bits);
@@ -77,4 +110,17 @@ pub fn legalize_signature(sig: &mut Signature, flags: &shared_settings::Flags) {
 
     let mut rets = Args::new(bits);
     legalize_args(&mut sig.return_types, &mut rets);
+
+    if current && sig.special_arg_index(ArgumentPurpose::Link).is_none() {
+        let mut link = ArgumentType::special(args.pointer_type, ArgumentPurpose::Link);
+        link.location = ArgumentLoc::Reg(GPR.unit(1));
+        sig.argument_types.push(link);
+    }
+}
+
+/// Get the registers preserved across calls.
+///
+/// RISC-V has a single calling convention, so this is the same for all signatures.
+pub fn callee_saved_registers() -> &'static [RegUnit] {
+    &CSR
 }
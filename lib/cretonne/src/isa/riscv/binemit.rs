@@ -0,0 +1,991 @@
+//! Emitting binary RISC-V machine code.
+//!
+//! The 32-bit instructions use the R, I, S, SB, and UJ instruction formats from the base ISA. The
+//! 'C' extension adds 16-bit instructions with their own formats, which can only encode some of
+//! the registers and smaller immediates. The encoding recipes guarantee that the operands fit.
+//!
+//! # Relocations
+//!
+//! The RISC-V relocations are applied at the start of the instruction, and the offset is split
+//! into the immediate fields of the instruction format. A direct call leaves a zero offset in the
+//! `jal` instruction for the linker to fill in.
+
+use binemit::{CodeSink, Reloc, bad_encoding, value_reg, value_stack};
+use ir::{Function, Inst, InstructionData, Ebb, Value, AtomicOrdering, TrapCode, SourceLoc};
+use isa::RegUnit;
+use regalloc::diversion::RegDiversions;
+
+include!(concat!(env!("OUT_DIR"), "/binemit-riscv.rs"));
+
+/// RISC-V relocation kinds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelocKind {
+    /// A 12-bit PC-relative offset in a conditional branch with the SB format.
+    Branch,
+    /// A 20-bit PC-relative offset in a `jal` instruction with the UJ format.
+    Jal,
+    /// An 8-bit PC-relative offset in a compressed branch with the CB format.
+    RvcBranch,
+    /// An 11-bit PC-relative offset in a compressed jump with the CJ format.
+    RvcJump,
+    /// A 4-byte absolute address.
+    Abs4,
+    /// An 8-byte absolute address.
+    Abs8,
+}
+
+/// Names of the relocation kinds, indexed by `RelocKind`.
+pub static RELOC_NAMES: [&str; 6] = ["Branch", "Jal", "RvcBranch", "RvcJump", "Abs4", "Abs8"];
+
+impl From<RelocKind> for Reloc {
+    fn from(kind: RelocKind) -> Reloc {
+        Reloc(kind as u16)
+    }
+}
+
+/// The `ebreak` instruction following the branch of a conditional trap.
+const EBREAK: u32 = 0x0010_0073;
+
+/// Get the 5-bit register number of `reg`.
+fn regnum(reg: RegUnit) -> u32 {
+    debug_assert!(reg < 32, "Bad register unit {}", reg);
+    reg as u32
+}
+
+/// Get the 3-bit register number of `reg` in `x8`-`x15` for a compressed instruction.
+fn regnum8(reg: RegUnit) -> u32 {
+    debug_assert!(reg >= 8 && reg < 16,
+                  "Register unit {} can't be encoded in a compressed instruction",
+                  reg);
+    reg as u32 - 8
+}
+
+/// Get the PC-relative displacement from the current instruction to `destination`, and report the
+/// `kind` relocation for it.
+fn displacement<CS: CodeSink + ?Sized>(destination: Ebb,
+                                       func: &Function,
+                                       kind: RelocKind,
+                                       sink: &mut CS)
+                                       -> i64 {
+    let offset = func.offsets[destination];
+    sink.reloc_ebb(kind.into(), offset);
+    offset as i64 - sink.offset() as i64
+}
+
+/// Get the offset of the stack slot of `value` from the stack pointer.
+fn stack_offset(func: &Function, value: Value) -> i64 {
+    let offset = func.stack_offsets[value_stack(func, value)] as i64;
+    assert!(offset < 2048, "Stack offset {} out of range", offset);
+    offset
+}
+
+/// R-type instructions.
+///
+///   31     24  19  14     11 6
+///   funct7 rs2 rs1 funct3 rd opcode
+///       25  20  15     12  7      0
+///
+/// Encoding bits: `opcode[6:2] | (funct3 << 5) | (funct7 << 8)`.
+fn put_r<CS: CodeSink + ?Sized>(bits: u16, rs1: u32, rs2: u32, rd: u32, sink: &mut CS) {
+    let bits = bits as u32;
+    let opcode5 = bits & 0x1f;
+    let funct3 = (bits >> 5) & 0x7;
+    let funct7 = (bits >> 8) & 0x7f;
+
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= rd << 7;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= rs2 << 20;
+    i |= funct7 << 25;
+
+    sink.put4(i);
+}
+
+/// Add the aq and rl bits for `ordering` to the encoding bits of an atomic memory operation.
+///
+/// The aq and rl bits are the low bits of funct7, so they are bits 9 and 8 of the encoding bits.
+fn amo_bits(bits: u16, ordering: AtomicOrdering) -> u16 {
+    let mut bits = bits;
+    if ordering.is_acquire() {
+        bits |= 1 << 9;
+    }
+    if ordering.is_release() {
+        bits |= 1 << 8;
+    }
+    bits
+}
+
+/// R-type instructions with a shift amount instead of rs2.
+///
+///   31     25    19  14     11 6
+///   funct7 shamt rs1 funct3 rd opcode
+///       25    20  15     12  7      0
+///
+/// Both funct7 and shamt contribute to bit 25. In RV64, this is the 6-bit shift amount.
+///
+/// Encoding bits: `opcode[6:2] | (funct3 << 5) | (funct7 << 8)`.
+fn put_rshamt<CS: CodeSink + ?Sized>(bits: u16, rs1: u32, shamt: i64, rd: u32, sink: &mut CS) {
+    let bits = bits as u32;
+    let opcode5 = bits & 0x1f;
+    let funct3 = (bits >> 5) & 0x7;
+    let funct7 = (bits >> 8) & 0x7f;
+    let shamt = shamt as u32 & 0x3f;
+
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= rd << 7;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= shamt << 20;
+    i |= funct7 << 25;
+
+    sink.put4(i);
+}
+
+/// I-type instructions.
+///
+///   31  19  14     11 6
+///   imm rs1 funct3 rd opcode
+///    20  15     12  7      0
+///
+/// Encoding bits: `opcode[6:2] | (funct3 << 5)`
+fn put_i<CS: CodeSink + ?Sized>(bits: u16, rs1: u32, imm: i64, rd: u32, sink: &mut CS) {
+    let bits = bits as u32;
+    let opcode5 = bits & 0x1f;
+    let funct3 = (bits >> 5) & 0x7;
+
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= rd << 7;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= (imm as u32) << 20;
+
+    sink.put4(i);
+}
+
+/// S-type instructions.
+///
+///   31        24  19  14     11       6
+///   imm[11:5] rs2 rs1 funct3 imm[4:0] opcode
+///          25  20  15     12        7      0
+///
+/// Encoding bits: `opcode[6:2] | (funct3 << 5)`
+fn put_s<CS: CodeSink + ?Sized>(bits: u16, rs1: u32, rs2: u32, imm: i64, sink: &mut CS) {
+    let bits = bits as u32;
+    let opcode5 = bits & 0x1f;
+    let funct3 = (bits >> 5) & 0x7;
+    let imm = imm as u32;
+
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= (imm & 0x1f) << 7;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= rs2 << 20;
+    i |= (imm >> 5) << 25;
+
+    sink.put4(i);
+}
+
+/// SB-type branch instructions.
+///
+///   31     30        24  19  14     11       7     6
+///   imm[12] imm[10:5] rs2 rs1 funct3 imm[4:1] imm[11] opcode
+///        31        25  20  15     12        8       7      0
+///
+/// Encoding bits: `opcode[6:2] | (funct3 << 5)`
+fn put_sb<CS: CodeSink + ?Sized>(bits: u16, disp: i64, rs1: u32, rs2: u32, sink: &mut CS) {
+    debug_assert!(disp >= -(1 << 12) && disp < (1 << 12) && disp & 1 == 0,
+                  "Bad branch displacement {}",
+                  disp);
+    let bits = bits as u32;
+    let opcode5 = bits & 0x1f;
+    let funct3 = (bits >> 5) & 0x7;
+    let disp = disp as u32;
+
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= ((disp >> 11) & 0x1) << 7;
+    i |= ((disp >> 1) & 0xf) << 8;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= rs2 << 20;
+    i |= ((disp >> 5) & 0x3f) << 25;
+    i |= ((disp >> 12) & 0x1) << 31;
+
+    sink.put4(i);
+}
+
+/// U-type instructions.
+///
+///   31         11 6
+///   imm[31:12] rd opcode
+///           12  7      0
+///
+/// Encoding bits: `opcode[6:2]`
+fn put_u<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rd: u32, sink: &mut CS) {
+    let bits = bits as u32;
+    let opcode5 = bits & 0x1f;
+
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= rd << 7;
+    i |= imm as u32 & 0xfffff000;
+
+    sink.put4(i);
+}
+
+/// UJ-type jump instructions.
+///
+///   31      30        20      19         11 6
+///   imm[20] imm[10:1] imm[11] imm[19:12] rd opcode
+///        31        21      20         12  7      0
+///
+/// Encoding bits: `opcode[6:2]`
+fn put_uj<CS: CodeSink + ?Sized>(bits: u16, disp: i64, rd: u32, sink: &mut CS) {
+    debug_assert!(disp >= -(1 << 20) && disp < (1 << 20) && disp & 1 == 0,
+                  "Bad jump displacement {}",
+                  disp);
+    let bits = bits as u32;
+    let opcode5 = bits & 0x1f;
+    let disp = disp as u32;
+
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= rd << 7;
+    i |= ((disp >> 12) & 0xff) << 12;
+    i |= ((disp >> 11) & 0x1) << 20;
+    i |= ((disp >> 1) & 0x3ff) << 21;
+    i |= ((disp >> 20) & 0x1) << 31;
+
+    sink.put4(i);
+}
+
+/// Emit an `ebreak` instruction and report it as a trap site.
+fn put_ebreak<CS: CodeSink + ?Sized>(code: TrapCode, srcloc: SourceLoc, sink: &mut CS) {
+    sink.trap(code, srcloc);
+    sink.put4(EBREAK);
+}
+
+/// The fixed bits of a compressed instruction: The quadrant, funct3, and bit 12.
+///
+///   15     12  1
+///   funct3 b12 quadrant
+///       13  12        0
+///
+/// Encoding bits: `quadrant | (funct3 << 2) | (funct2 << 5) | (funct2b << 7) | (bit12 << 9)`
+fn c_base(bits: u16) -> u16 {
+    let quadrant = bits & 0x3;
+    let funct3 = (bits >> 2) & 0x7;
+    let bit12 = (bits >> 9) & 0x1;
+    quadrant | (bit12 << 12) | (funct3 << 13)
+}
+
+/// CR-type compressed instructions.
+///
+///   15     12  11     6   1
+///   funct3 b12 rd/rs1 rs2 quadrant
+///       13  12      7   2        0
+fn put_cr<CS: CodeSink + ?Sized>(bits: u16, rd: u32, rs2: u32, sink: &mut CS) {
+    sink.put2(c_base(bits) | (rd << 7) as u16 | (rs2 << 2) as u16);
+}
+
+/// CA-type compressed arithmetic instructions on registers in `x8`-`x15`.
+///
+///   15     12  11     9       6       4    1
+///   funct3 b12 funct2 rd'/rs1' funct2b rs2' quadrant
+///       13  12     10        7       5    2        0
+fn put_ca<CS: CodeSink + ?Sized>(bits: u16, rd: u32, rs2: u32, sink: &mut CS) {
+    let funct2 = (bits >> 5) & 0x3;
+    let funct2b = (bits >> 7) & 0x3;
+    sink.put2(c_base(bits) | (funct2 << 10) | (rd << 7) as u16 | (funct2b << 5) |
+              (rs2 << 2) as u16);
+}
+
+/// CI-type compressed instructions with a 6-bit immediate.
+///
+///   15     12     11     6        1
+///   funct3 imm[5] rd/rs1 imm[4:0] quadrant
+///       13     12      7        2        0
+///
+/// The encoding bits can't have bit 12 set.
+fn put_ci<CS: CodeSink + ?Sized>(bits: u16, rd: u32, imm: i64, sink: &mut CS) {
+    let imm = imm as u16;
+    sink.put2(c_base(bits) | (((imm >> 5) & 0x1) << 12) | (rd << 7) as u16 |
+              ((imm & 0x1f) << 2));
+}
+
+/// CB-type compressed instructions with a 6-bit immediate and a register in `x8`-`x15`.
+///
+///   15     12     11     9        6        1
+///   funct3 imm[5] funct2 rd'/rs1' imm[4:0] quadrant
+///       13     12     10        7        2        0
+fn put_cbi<CS: CodeSink + ?Sized>(bits: u16, rd: u32, imm: i64, sink: &mut CS) {
+    let funct2 = (bits >> 5) & 0x3;
+    let imm = imm as u16;
+    sink.put2(c_base(bits) | (((imm >> 5) & 0x1) << 12) | (funct2 << 10) | (rd << 7) as u16 |
+              ((imm & 0x1f) << 2));
+}
+
+/// CB-type compressed branches.
+///
+///   15     12     11       9    6        4        2      1
+///   funct3 off[8] off[4:3] rs1' off[7:6] off[2:1] off[5] quadrant
+///       13     12       10    7        5        3      2        0
+fn put_cb<CS: CodeSink + ?Sized>(bits: u16, disp: i64, rs1: u32, sink: &mut CS) {
+    debug_assert!(disp >= -(1 << 8) && disp < (1 << 8) && disp & 1 == 0,
+                  "Bad branch displacement {}",
+                  disp);
+    let disp = disp as u16;
+
+    let mut i = c_base(bits);
+    i |= ((disp >> 5) & 0x1) << 2;
+    i |= ((disp >> 1) & 0x3) << 3;
+    i |= ((disp >> 6) & 0x3) << 5;
+    i |= (rs1 << 7) as u16;
+    i |= ((disp >> 3) & 0x3) << 10;
+    i |= ((disp >> 8) & 0x1) << 12;
+
+    sink.put2(i);
+}
+
+/// CJ-type compressed jumps.
+///
+///   15     12      11     10       8       7      6      5        2      1
+///   funct3 off[11] off[4] off[9:8] off[10] off[6] off[7] off[3:1] off[5] quadrant
+///       13      12     11        9       8      7      6        3      2        0
+fn put_cj<CS: CodeSink + ?Sized>(bits: u16, disp: i64, sink: &mut CS) {
+    debug_assert!(disp >= -(1 << 11) && disp < (1 << 11) && disp & 1 == 0,
+                  "Bad jump displacement {}",
+                  disp);
+    let disp = disp as u16;
+
+    let mut i = c_base(bits);
+    i |= ((disp >> 5) & 0x1) << 2;
+    i |= ((disp >> 1) & 0x7) << 3;
+    i |= ((disp >> 7) & 0x1) << 6;
+    i |= ((disp >> 6) & 0x1) << 7;
+    i |= ((disp >> 10) & 0x1) << 8;
+    i |= ((disp >> 8) & 0x3) << 9;
+    i |= ((disp >> 4) & 0x1) << 11;
+    i |= ((disp >> 11) & 0x1) << 12;
+
+    sink.put2(i);
+}
+
+fn recipe_r<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        put_r(func.encodings[inst].bits(),
+              regnum(value_reg(func, divert, args[0])),
+              regnum(value_reg(func, divert, args[1])),
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_ramo<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::AtomicRmw { ordering, args, .. } = func.dfg[inst] {
+        put_r(amo_bits(func.encodings[inst].bits(), ordering),
+              regnum(value_reg(func, divert, args[0])),
+              regnum(value_reg(func, divert, args[1])),
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_ramoz<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::AtomicRmw { ordering, args, .. } = func.dfg[inst] {
+        put_r(amo_bits(func.encodings[inst].bits(), ordering),
+              regnum(value_reg(func, divert, args[0])),
+              regnum(value_reg(func, divert, args[1])),
+              0,
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_rlr<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::AtomicLoad { ordering, arg, .. } = func.dfg[inst] {
+        put_r(amo_bits(func.encodings[inst].bits(), ordering),
+              regnum(value_reg(func, divert, arg)),
+              0,
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_rshamt<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        put_rshamt(func.encodings[inst].bits(),
+                   regnum(value_reg(func, divert, arg)),
+                   imm.into(),
+                   regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+                   sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_rext<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        let result = func.dfg.first_result(inst);
+        let rs1 = regnum(value_reg(func, divert, arg));
+        let rd = regnum(value_reg(func, divert, result));
+        let shamt = (func.dfg.value_type(result).bits() - func.dfg.value_type(arg).bits()) as i64;
+        // slli rd, rs1, shamt
+        put_rshamt((bits & 0x1f) | (0b001 << 5), rs1, shamt, rd, sink);
+        // srli rd, rd, shamt or srai rd, rd, shamt
+        put_rshamt(bits, rd, shamt, rd, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_i<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        put_i(func.encodings[inst].bits(),
+              regnum(value_reg(func, divert, arg)),
+              imm.into(),
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_icopy<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        put_i(func.encodings[inst].bits(),
+              regnum(value_reg(func, divert, arg)),
+              0,
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_irmov<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       _divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::RegMove { src, dst, .. } = func.dfg[inst] {
+        put_i(func.encodings[inst].bits(), regnum(src), 0, regnum(dst), sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_iz<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::Nullary { .. } = func.dfg[inst] {
+        put_i(func.encodings[inst].bits(),
+              0,
+              0,
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_iimm<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        put_i(func.encodings[inst].bits(),
+              0,
+              imm.into(),
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_u<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        put_u(func.encodings[inst].bits(),
+              imm.into(),
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_ricmp<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::IntCompare { args, .. } = func.dfg[inst] {
+        put_r(func.encodings[inst].bits(),
+              regnum(value_reg(func, divert, args[0])),
+              regnum(value_reg(func, divert, args[1])),
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_sb<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::BranchIcmp { destination, .. } = func.dfg[inst] {
+        let disp = displacement(destination, func, RelocKind::Branch, sink);
+        put_sb(func.encodings[inst].bits(),
+               disp,
+               regnum(value_reg(func, divert, func.dfg.inst_args(inst)[0][0])),
+               regnum(value_reg(func, divert, func.dfg.inst_args(inst)[0][1])),
+               sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_sbzero<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::Branch { destination, .. } = func.dfg[inst] {
+        let disp = displacement(destination, func, RelocKind::Branch, sink);
+        put_sb(func.encodings[inst].bits(),
+               disp,
+               regnum(value_reg(func, divert, func.dfg.inst_args(inst)[0][0])),
+               0,
+               sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_iebreak<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         _divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::Trap { code, .. } = func.dfg[inst] {
+        sink.trap(code, func.srcloc(inst));
+        put_i(func.encodings[inst].bits(), 0, 1, 0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_sbtrap<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::CondTrap { arg, code, .. } = func.dfg[inst] {
+        // Branch over the 4-byte `ebreak`.
+        put_sb(func.encodings[inst].bits(),
+               8,
+               regnum(value_reg(func, divert, arg)),
+               0,
+               sink);
+        put_ebreak(code, func.srcloc(inst), sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_cbzero<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::Branch { destination, .. } = func.dfg[inst] {
+        let disp = displacement(destination, func, RelocKind::RvcBranch, sink);
+        put_cb(func.encodings[inst].bits(),
+               disp,
+               regnum8(value_reg(func, divert, func.dfg.inst_args(inst)[0][0])),
+               sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_cr<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        put_cr(func.encodings[inst].bits(),
+               regnum(value_reg(func, divert, args[0])),
+               regnum(value_reg(func, divert, args[1])),
+               sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_ca<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        put_ca(func.encodings[inst].bits(),
+               regnum8(value_reg(func, divert, args[0])),
+               regnum8(value_reg(func, divert, args[1])),
+               sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_ci<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        put_ci(func.encodings[inst].bits(),
+               regnum(value_reg(func, divert, arg)),
+               imm.into(),
+               sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_cishamt<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        put_ci(func.encodings[inst].bits(),
+               regnum(value_reg(func, divert, arg)),
+               imm.into(),
+               sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_cbshamt<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        put_cbi(func.encodings[inst].bits(),
+                regnum8(value_reg(func, divert, arg)),
+                imm.into(),
+                sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_cbi<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        put_cbi(func.encodings[inst].bits(),
+                regnum8(value_reg(func, divert, arg)),
+                imm.into(),
+                sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_ciz<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::Nullary { .. } = func.dfg[inst] {
+        put_ci(func.encodings[inst].bits(),
+               regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+               0,
+               sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_crcopy<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        put_cr(func.encodings[inst].bits(),
+               regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+               regnum(value_reg(func, divert, arg)),
+               sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_crrmov<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::RegMove { src, dst, .. } = func.dfg[inst] {
+        put_cr(func.encodings[inst].bits(), regnum(dst), regnum(src), sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_crret<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::ReturnReg { .. } = func.dfg[inst] {
+        put_cr(func.encodings[inst].bits(),
+               regnum(value_reg(func, divert, func.dfg.inst_args(inst)[0][0])),
+               0,
+               sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_uj<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    _divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::Jump { destination, .. } = func.dfg[inst] {
+        let disp = displacement(destination, func, RelocKind::Jal, sink);
+        put_uj(func.encodings[inst].bits(), disp, 0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_cj<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    _divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::Jump { destination, .. } = func.dfg[inst] {
+        let disp = displacement(destination, func, RelocKind::RvcJump, sink);
+        put_cj(func.encodings[inst].bits(), disp, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_iret<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::ReturnReg { .. } = func.dfg[inst] {
+        // jalr x0, rs1, 0
+        put_i(func.encodings[inst].bits(),
+              regnum(value_reg(func, divert, func.dfg.inst_args(inst)[0][0])),
+              0,
+              0,
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_ujcall<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+        // The callee address is not known yet. This is `jal x0, 0` until it is relocated.
+        sink.reloc_external(RelocKind::Jal.into(),
+                            &func.dfg.ext_funcs[func_ref].name,
+                            0);
+        put_uj(func.encodings[inst].bits(), 0, 0, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_icall<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::IndirectCall { .. } = func.dfg[inst] {
+        // jalr x0, rs1, 0
+        put_i(func.encodings[inst].bits(),
+              regnum(value_reg(func, divert, func.dfg.inst_args(inst)[0][0])),
+              0,
+              0,
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_ujlink<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+        // The callee address is not known yet. This is `jal x1, 0` until it is relocated.
+        sink.reloc_external(RelocKind::Jal.into(),
+                            &func.dfg.ext_funcs[func_ref].name,
+                            0);
+        put_uj(func.encodings[inst].bits(), 0, 1, sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_ilink<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::IndirectCall { .. } = func.dfg[inst] {
+        // jalr x1, rs1, 0
+        put_i(func.encodings[inst].bits(),
+              regnum(value_reg(func, divert, func.dfg.inst_args(inst)[0][0])),
+              0,
+              1,
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_iload<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::Load { arg, offset, .. } = func.dfg[inst] {
+        // lw rd, offset(rs1)
+        put_i(func.encodings[inst].bits(),
+              regnum(value_reg(func, divert, arg)),
+              offset.into(),
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_sstore<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::Store { args, offset, .. } = func.dfg[inst] {
+        // sw rs2, offset(rs1)
+        put_s(func.encodings[inst].bits(),
+              regnum(value_reg(func, divert, args[1])),
+              regnum(value_reg(func, divert, args[0])),
+              offset.into(),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_gpsp<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        // sw rs2, offset(sp)
+        put_s(func.encodings[inst].bits(),
+              2,
+              regnum(value_reg(func, divert, arg)),
+              stack_offset(func, func.dfg.first_result(inst)),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+fn recipe_gpfi<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        // lw rd, offset(sp)
+        put_i(func.encodings[inst].bits(),
+              2,
+              stack_offset(func, arg),
+              regnum(value_reg(func, divert, func.dfg.first_result(inst))),
+              sink);
+    } else {
+        bad_encoding(func, inst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use settings;
+    use isa;
+    use ir::{Function, InstructionData, Opcode, ValueLoc};
+    use ir::types;
+    use regalloc::diversion::RegDiversions;
+
+    #[test]
+    fn add() {
+        let flags = settings::Flags::new(&settings::builder());
+        let isa = isa::lookup("riscv32").unwrap().finish(flags);
+
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        let arg0 = func.dfg.append_ebb_arg(ebb, types::I32);
+        let arg1 = func.dfg.append_ebb_arg(ebb, types::I32);
+        let inst = func.dfg.make_inst(InstructionData::Binary {
+                                          opcode: Opcode::Iadd,
+                                          ty: types::I32,
+                                          args: [arg0, arg1],
+                                      });
+        func.dfg.make_inst_results(inst, types::I32);
+        func.layout.append_ebb(ebb);
+        func.layout.append_inst(inst, ebb);
+        let result = func.dfg.first_result(inst);
+
+        // add x10, x11, x12
+        *func.locations.ensure(arg0) = ValueLoc::Reg(11);
+        *func.locations.ensure(arg1) = ValueLoc::Reg(12);
+        *func.locations.ensure(result) = ValueLoc::Reg(10);
+        *func.encodings.ensure(inst) = isa.encode(&func.dfg, &func.dfg[inst]).unwrap();
+
+        let mut code = Vec::new();
+        isa.emit_inst(&func, inst, &mut RegDiversions::new(), &mut code);
+        assert_eq!(code, [0x33, 0x85, 0xc5, 0x00]);
+        assert_eq!(code.len(),
+                   isa.recipe_sizing()[func.encodings[inst].recipe()].bytes as usize);
+    }
+}
@@ -1,10 +1,12 @@
 //! Encoding tables for RISC-V.
 
-use ir::{Opcode, InstructionData};
+use ir::{Opcode, DataFlowGraph, InstructionData};
+use ir::condcodes::IntCC;
 use ir::types;
 use predicates;
 use isa::enc_tables::{Level1Entry, Level2Entry};
 use isa::constraints::*;
+use isa::encoding::{RecipeSizing, BranchRange};
 use super::registers::*;
 
 // Include the generated encoding tables:
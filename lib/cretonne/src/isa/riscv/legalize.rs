@@ -0,0 +1,374 @@
+//! RISC-V custom legalizations.
+//!
+//! These legalization routines are used instead of the generic expansions for the opcodes listed
+//! in `CUSTOM`.
+//!
+//! The set-less-than instructions only test the `slt` and `ult` conditions, so the other integer
+//! comparisons are built from them.
+//!
+//! The multiplication and division instructions are only encoded with the 'M' extension. Without
+//! it, multiplications by a constant become shifts and adds, and the other multiplications and
+//! divisions call the runtime library.
+//!
+//! RV64 keeps 32-bit values sign-extended in 64-bit registers, so zero-extending them takes a pair
+//! of shifts.
+//!
+//! The 'A' extension has no compare-and-swap instruction, so `atomic_cas` is a loop around a
+//! load-reserved and a store-conditional.
+//!
+//! Constants that don't fit in a single `addi` or `lui` are built from several instructions.
+
+use ir::{Cursor, DataFlowGraph, InstructionData, InstBuilder, Opcode, Value, ValueDef,
+         VariableArgs};
+use ir::types::{I32, I64};
+use ir::condcodes::{IntCC, CondCode};
+use isa::{TargetIsa, LegalizeFn};
+use legalizer::libcall::expand_as_libcall;
+use std::vec::Vec;
+
+/// Custom legalization routines, indexed by the code in `Legalize::Custom(code)`.
+pub static CUSTOM: [(Opcode, LegalizeFn); 10] = [(Opcode::Icmp, icmp),
+                                                 (Opcode::BrIcmp, br_icmp),
+                                                 (Opcode::Imul, imul),
+                                                 (Opcode::Udiv, libcall),
+                                                 (Opcode::Sdiv, libcall),
+                                                 (Opcode::Urem, libcall),
+                                                 (Opcode::Srem, libcall),
+                                                 (Opcode::Uextend, uextend),
+                                                 (Opcode::AtomicCas, atomic_cas),
+                                                 (Opcode::Iconst, iconst)];
+
+/// The largest number of shifted terms to add when multiplying by a constant without the 'M'
+/// extension. Multiplications by constants that need more terms call the runtime library.
+const MAX_MUL_TERMS: usize = 8;
+
+/// Express an integer comparison with the set-less-than instructions.
+///
+/// The RISC-V set-less-than instructions only test the `slt` and `ult` conditions. The `sgt`,
+/// `sle`, `ugt`, and `ule` conditions are expressed by swapping the operands. Equality is tested
+/// by comparing the difference of the operands against 1 or 0, and the `sge` and `uge` conditions
+/// by inverting the result of the opposite comparison.
+fn icmp(pos: &mut Cursor, dfg: &mut DataFlowGraph, _isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let (cond, x, y) = match dfg[inst] {
+        InstructionData::IntCompare { cond, args, .. } => {
+            (cond, dfg.resolve_aliases(args[0]), dfg.resolve_aliases(args[1]))
+        }
+        _ => panic!("Expected icmp: {:?}", dfg[inst]),
+    };
+    let ty = dfg.value_type(x);
+    match cond {
+        IntCC::SignedGreaterThan |
+        IntCC::SignedLessThanOrEqual |
+        IntCC::UnsignedGreaterThan |
+        IntCC::UnsignedLessThanOrEqual => {
+            dfg.replace(inst).icmp(cond.reverse(), y, x);
+        }
+        IntCC::Equal => {
+            // x == y  <=>  (x ^ y) < 1
+            let diff = dfg.ins(pos).bxor(x, y);
+            let one = dfg.ins(pos).iconst(ty, 1);
+            dfg.replace(inst).icmp(IntCC::UnsignedLessThan, diff, one);
+        }
+        IntCC::NotEqual => {
+            // x != y  <=>  0 < (x ^ y)
+            let diff = dfg.ins(pos).bxor(x, y);
+            let zero = dfg.ins(pos).iconst(ty, 0);
+            dfg.replace(inst).icmp(IntCC::UnsignedLessThan, zero, diff);
+        }
+        IntCC::SignedGreaterThanOrEqual |
+        IntCC::UnsignedGreaterThanOrEqual => {
+            // x >= y  <=>  !(x < y)  <=>  bint(x < y) < 1
+            let less = dfg.ins(pos).icmp(cond.inverse(), x, y);
+            let less = dfg.ins(pos).bint(ty, less);
+            let one = dfg.ins(pos).iconst(ty, 1);
+            dfg.replace(inst).icmp(IntCC::UnsignedLessThan, less, one);
+        }
+        IntCC::SignedLessThan |
+        IntCC::UnsignedLessThan => return false,
+    }
+    true
+}
+
+/// Canonicalize the condition code of a compare-and-branch instruction.
+///
+/// The branch instructions only test the `eq`, `ne`, `slt`, `sge`, `ult`, and `uge` conditions, so
+/// the other conditions are expressed by swapping the operands like for `icmp`.
+fn br_icmp(pos: &mut Cursor, dfg: &mut DataFlowGraph, _isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let swap = match dfg[inst] {
+        InstructionData::BranchIcmp { ref mut cond, .. } => {
+            match *cond {
+                IntCC::SignedGreaterThan |
+                IntCC::SignedLessThanOrEqual |
+                IntCC::UnsignedGreaterThan |
+                IntCC::UnsignedLessThanOrEqual => {
+                    *cond = cond.reverse();
+                    true
+                }
+                _ => false,
+            }
+        }
+        _ => panic!("Expected br_icmp: {:?}", dfg[inst]),
+    };
+    if swap {
+        dfg.inst_args_mut(inst)[0].swap(0, 1);
+    }
+    swap
+}
+
+/// Expand a multiplication without the 'M' extension.
+///
+/// A multiplication by a constant is rewritten as a sum of shifted copies of the other operand.
+/// The constant is recoded in non-adjacent form, so a run of ones costs a subtraction and an
+/// addition instead of one addition per bit.
+fn imul(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let args = match dfg[inst] {
+        InstructionData::Binary { args, .. } => {
+            [dfg.resolve_aliases(args[0]), dfg.resolve_aliases(args[1])]
+        }
+        _ => panic!("Expected imul: {:?}", dfg[inst]),
+    };
+    let bits = dfg.value_type(args[0]).bits() as u32;
+    let (x, c) = match (iconst_value(dfg, args[0]), iconst_value(dfg, args[1])) {
+        (_, Some(c)) => (args[0], c),
+        (Some(c), None) => (args[1], c),
+        (None, None) => return expand_as_libcall(inst, dfg, isa),
+    };
+
+    let mut terms = mul_terms(c, bits);
+    if terms.len() > MAX_MUL_TERMS {
+        return expand_as_libcall(inst, dfg, isa);
+    }
+    let ty = dfg.value_type(x);
+    let (last, negative) = match terms.pop() {
+        Some(term) => term,
+        None => {
+            // Multiplication by zero.
+            dfg.replace(inst).null(ty);
+            return true;
+        }
+    };
+
+    // Start from a positive term so the others can be added or subtracted, or from zero if all
+    // the terms are negative. The last term is added by the instruction replacing the
+    // multiplication.
+    let mut acc = match terms.iter().position(|&(_, negative)| !negative) {
+        Some(i) => shifted(pos, dfg, x, terms.remove(i).0),
+        None if terms.is_empty() && !negative => {
+            if last == 0 {
+                dfg.replace(inst).copy(x);
+            } else {
+                dfg.replace(inst).ishl_imm(x, last as i64);
+            }
+            return true;
+        }
+        None => dfg.ins(pos).null(ty),
+    };
+    for (shift, negative) in terms {
+        let term = shifted(pos, dfg, x, shift);
+        acc = if negative {
+            dfg.ins(pos).isub(acc, term)
+        } else {
+            dfg.ins(pos).iadd(acc, term)
+        };
+    }
+    let term = shifted(pos, dfg, x, last);
+    if negative {
+        dfg.replace(inst).isub(acc, term);
+    } else {
+        dfg.replace(inst).iadd(acc, term);
+    }
+    true
+}
+
+/// Insert an instruction shifting `x` left by `shift` bits, unless `shift` is 0.
+fn shifted(pos: &mut Cursor, dfg: &mut DataFlowGraph, x: Value, shift: u32) -> Value {
+    if shift == 0 {
+        x
+    } else {
+        dfg.ins(pos).ishl_imm(x, shift as i64)
+    }
+}
+
+/// Call the runtime library for a division or remainder without the 'M' extension.
+fn libcall(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    expand_as_libcall(inst, dfg, isa)
+}
+
+/// Zero-extend a 32-bit value to 64 bits in RV64.
+///
+/// The sign-extended value is shifted left and then right by 32 bits to clear the high half.
+fn uextend(pos: &mut Cursor, dfg: &mut DataFlowGraph, _isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let arg = match dfg[inst] {
+        InstructionData::Unary { arg, .. } => dfg.resolve_aliases(arg),
+        _ => panic!("Expected uextend: {:?}", dfg[inst]),
+    };
+    if dfg.value_type(arg) != I32 || dfg[inst].ctrl_typevar(dfg) != I64 {
+        return false;
+    }
+    let wide = dfg.ins(pos).sextend(I64, arg);
+    let high = dfg.ins(pos).ishl_imm(wide, 32);
+    dfg.replace(inst).ushr_imm(high, 32);
+    true
+}
+
+/// Expand `atomic_cas` into a load-reserved/store-conditional loop with the 'A' extension.
+///
+/// ```cton
+///     jump ebb1
+///
+/// ebb1:
+///     old = riscv_lr ord, p
+///     br_icmp ne old, e, ebb2
+///     s = riscv_sc ord, p, x
+///     brnz s, ebb1
+///     jump ebb2
+///
+/// ebb2:
+///     a = copy old
+/// ```
+fn atomic_cas(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let (ordering, p, e, x) = match dfg[inst] {
+        InstructionData::AtomicCas { ordering, args, .. } => {
+            (ordering,
+             dfg.resolve_aliases(args[0]),
+             dfg.resolve_aliases(args[1]),
+             dfg.resolve_aliases(args[2]))
+        }
+        _ => panic!("Expected atomic_cas: {:?}", dfg[inst]),
+    };
+    let ty = dfg.value_type(x);
+
+    // The load-reserved instruction is only encoded when the 'A' extension is enabled.
+    let lr = InstructionData::AtomicLoad {
+        opcode: Opcode::RiscvLr,
+        ty: ty,
+        ordering: ordering,
+        arg: p,
+    };
+    if isa.encode(dfg, &lr).is_err() {
+        return false;
+    }
+
+    let orig_ebb = pos.current_ebb().expect("need EBB");
+    let done = dfg.make_ebb();
+    pos.insert_ebb(done);
+
+    let retry = dfg.make_ebb();
+    pos.goto_bottom(orig_ebb);
+    dfg.ins(pos).jump(retry, VariableArgs::new());
+
+    pos.insert_ebb(retry);
+    let old = dfg.ins(pos).riscv_lr(ty, ordering, p);
+    dfg.ins(pos).br_icmp(IntCC::NotEqual, old, e, done, VariableArgs::new());
+    let failed = dfg.ins(pos).riscv_sc(ordering, p, x);
+    dfg.ins(pos).brnz(failed, retry, VariableArgs::new());
+    dfg.ins(pos).jump(done, VariableArgs::new());
+
+    pos.goto_inst(inst);
+    dfg.replace(inst).copy(old);
+    true
+}
+
+/// Materialize a constant that doesn't fit in the immediate of an `addi` or a `lui`.
+///
+/// The low 12 bits are added with an `iadd_imm` to a constant with the low bits clear. That is a
+/// `lui` when it fits in 32 bits. Otherwise, the high bits are materialized without their trailing
+/// zeros and shifted into place.
+fn iconst(pos: &mut Cursor, dfg: &mut DataFlowGraph, _isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let imm: i64 = match dfg[inst] {
+        InstructionData::UnaryImm { imm, .. } => imm.into(),
+        _ => panic!("Expected iconst: {:?}", dfg[inst]),
+    };
+    let ty = dfg[inst].ctrl_typevar(dfg);
+
+    // The high bits of a 32-bit constant wrap around, so they always fit in a `lui`.
+    let (low, high) = if ty == I32 {
+        let value = imm as i32 as i64;
+        let low = (value << 52) >> 52;
+        (low, value.wrapping_sub(low) as u32 as i64)
+    } else {
+        let low = (imm << 52) >> 52;
+        (low, imm.wrapping_sub(low))
+    };
+
+    if high == 0 {
+        dfg.replace(inst).iconst(ty, low);
+    } else if ty == I32 || high == high as i32 as i64 {
+        if low == 0 {
+            dfg.replace(inst).iconst(ty, high);
+        } else {
+            let base = dfg.ins(pos).iconst(ty, high);
+            dfg.replace(inst).iadd_imm(base, low);
+        }
+    } else {
+        let shift = high.trailing_zeros() as i64;
+        let upper = dfg.ins(pos).iconst(ty, high >> shift);
+        if low == 0 {
+            dfg.replace(inst).ishl_imm(upper, shift);
+        } else {
+            let base = dfg.ins(pos).ishl_imm(upper, shift);
+            dfg.replace(inst).iadd_imm(base, low);
+        }
+    }
+    true
+}
+
+/// Get the value of `value` if it is defined by an `iconst` instruction.
+fn iconst_value(dfg: &DataFlowGraph, value: Value) -> Option<i64> {
+    if let ValueDef::Res(inst, 0) = dfg.value_def(value) {
+        if let InstructionData::UnaryImm { opcode: Opcode::Iconst, imm, .. } = dfg[inst] {
+            return Some(imm.into());
+        }
+    }
+    None
+}
+
+/// Recode the low `bits` bits of `c` in non-adjacent form.
+///
+/// Return the non-zero digits from the least significant one as `(shift, negative)` pairs, so `c`
+/// is the sum of `±(1 << shift)` modulo `2^bits`.
+fn mul_terms(c: i64, bits: u32) -> Vec<(u32, bool)> {
+    let mut m = if bits < 64 {
+        c as u64 & ((1 << bits) - 1)
+    } else {
+        c as u64
+    };
+    let mut terms = Vec::new();
+    let mut shift = 0;
+    // Digits at or above `bits` are multiples of `2^bits`, so they can be dropped.
+    while m != 0 && shift < bits {
+        if m & 1 != 0 {
+            let negative = m & 2 != 0;
+            terms.push((shift, negative));
+            m = if negative { m.wrapping_add(1) } else { m - 1 };
+        }
+        m >>= 1;
+        shift += 1;
+    }
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mul_terms;
+
+    #[test]
+    fn non_adjacent_form() {
+        assert_eq!(mul_terms(0, 32), vec![]);
+        assert_eq!(mul_terms(1, 32), vec![(0, false)]);
+        assert_eq!(mul_terms(10, 32), vec![(1, false), (3, false)]);
+        // 7 = 8 - 1.
+        assert_eq!(mul_terms(7, 32), vec![(0, true), (3, false)]);
+        // -1 = 2^32 - 1 wraps around to a single negative term.
+        assert_eq!(mul_terms(-1, 32), vec![(0, true)]);
+        assert_eq!(mul_terms(-1, 64), vec![(0, true)]);
+        assert_eq!(mul_terms(0x7fff_ffff, 32), vec![(0, true), (31, false)]);
+    }
+}
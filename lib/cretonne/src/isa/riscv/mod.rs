@@ -2,14 +2,23 @@
 
 pub mod settings;
 mod abi;
+mod binemit;
 mod enc_tables;
+mod legalize;
 mod registers;
 
 use super::super::settings as shared_settings;
-use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding};
+use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding,
+                      legal_encodings, custom_action, allowed_types};
 use isa::Builder as IsaBuilder;
-use isa::{TargetIsa, RegInfo, Encoding, Legalize, RecipeConstraints};
-use ir::{InstructionData, DataFlowGraph, Signature};
+use isa::{TargetIsa, RegInfo, RegUnit, Encoding, Legalize, LegalizeFn, RecipeConstraints,
+          RecipeSizing};
+use binemit::{CodeSink, Reloc};
+use ir::{Function, Inst, InstructionData, DataFlowGraph, Signature, CallConv};
+use regalloc::AllocatableSet;
+use regalloc::diversion::RegDiversions;
+use std::boxed::Box;
+use std::vec::Vec;
 
 #[allow(dead_code)]
 struct Isa {
@@ -23,6 +32,7 @@ pub fn isa_builder() -> IsaBuilder {
     IsaBuilder {
         setup: settings::builder(),
         constructor: isa_constructor,
+        is_64bit: None,
     }
 }
 
@@ -50,11 +60,18 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn isa_flags(&self) -> shared_settings::Iter {
+        self.isa_flags.iter()
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
 
     fn encode(&self, dfg: &DataFlowGraph, inst: &InstructionData) -> Result<Encoding, Legalize> {
+        if !allowed_types(&self.shared_flags, dfg, inst) {
+            return Err(Legalize::Expand);
+        }
         lookup_enclist(inst.ctrl_typevar(dfg),
                        inst.opcode(),
                        self.cpumode,
@@ -62,23 +79,102 @@ impl TargetIsa for Isa {
             .and_then(|enclist_offset| {
                 general_encoding(enclist_offset,
                                  &enc_tables::ENCLISTS[..],
-                                 |instp| enc_tables::check_instp(inst, instp),
+                                 |instp| enc_tables::check_instp(inst, dfg, instp),
                                  |isap| self.isa_flags.numbered_predicate(isap as usize))
                     .ok_or(Legalize::Expand)
             })
+            .map_err(|action| custom_action(action, inst.opcode(), &legalize::CUSTOM))
+    }
+
+    fn legal_encodings(&self,
+                       dfg: &DataFlowGraph,
+                       inst: &InstructionData)
+                       -> Result<Vec<Encoding>, Legalize> {
+        if !allowed_types(&self.shared_flags, dfg, inst) {
+            return Err(Legalize::Expand);
+        }
+        lookup_enclist(inst.ctrl_typevar(dfg),
+                       inst.opcode(),
+                       self.cpumode,
+                       &enc_tables::LEVEL2[..])
+            .and_then(|enclist_offset| {
+                let encodings = legal_encodings(enclist_offset,
+                                                &enc_tables::ENCLISTS[..],
+                                                |instp| enc_tables::check_instp(inst, dfg, instp),
+                                                |isap| {
+                                                    self.isa_flags
+                                                        .numbered_predicate(isap as usize)
+                                                });
+                if encodings.is_empty() {
+                    Err(Legalize::Expand)
+                } else {
+                    Ok(encodings)
+                }
+            })
+            .map_err(|action| custom_action(action, inst.opcode(), &legalize::CUSTOM))
     }
 
     fn recipe_names(&self) -> &'static [&'static str] {
         &enc_tables::RECIPE_NAMES[..]
     }
 
+    fn reloc_names(&self) -> &'static [&'static str] {
+        &binemit::RELOC_NAMES[..]
+    }
+
+    fn jump_table_reloc(&self) -> Option<Reloc> {
+        if self.shared_flags.is_64bit() {
+            Some(binemit::RelocKind::Abs8.into())
+        } else {
+            Some(binemit::RelocKind::Abs4.into())
+        }
+    }
+
+    fn stack_alignment(&self) -> u32 {
+        self.shared_flags.stack_alignment(16)
+    }
+
     fn recipe_constraints(&self) -> &'static [RecipeConstraints] {
         &enc_tables::RECIPE_CONSTRAINTS
     }
 
-    fn legalize_signature(&self, sig: &mut Signature) {
+    fn recipe_sizing(&self) -> &'static [RecipeSizing] {
+        &enc_tables::RECIPE_SIZING
+    }
+
+    fn emit_inst(&self,
+                 func: &Function,
+                 inst: Inst,
+                 divert: &mut RegDiversions,
+                 sink: &mut CodeSink) {
+        binemit::emit_inst(func, inst, divert, sink)
+    }
+
+    fn allocatable_registers(&self) -> AllocatableSet {
+        let mut regs = AllocatableSet::new();
+        // Reserve the zero register `x0`, the stack pointer `x2`, the global pointer `x3`, and the
+        // thread pointer `x4`.
+        for &reg in &[0, 2, 3, 4] {
+            regs.take(registers::GPR, registers::GPR.unit(reg));
+        }
+        if self.shared_flags.preserve_frame_pointers() {
+            // Reserve the frame pointer `s0`.
+            regs.take(registers::GPR, registers::GPR.unit(8));
+        }
+        regs
+    }
+
+    fn custom_legalization(&self, code: u8) -> LegalizeFn {
+        legalize::CUSTOM[code as usize].1
+    }
+
+    fn legalize_signature(&self, sig: &mut Signature, current: bool) {
         // We can pass in `self.isa_flags` too, if we need it.
-        abi::legalize_signature(sig, &self.shared_flags)
+        abi::legalize_signature(sig, &self.shared_flags, current)
+    }
+
+    fn callee_saved_registers(&self, _call_conv: CallConv) -> &'static [RegUnit] {
+        abi::callee_saved_registers()
     }
 }
 
@@ -88,6 +184,7 @@ mod tests {
     use isa;
     use ir::{DataFlowGraph, InstructionData, Opcode};
     use ir::{types, immediates};
+    use ir::condcodes::IntCC;
 
     fn encstr(isa: &isa::TargetIsa, enc: isa::Encoding) -> String {
         isa.display_enc(enc).to_string()
@@ -192,7 +289,26 @@ mod tests {
             args: [arg32, arg32],
         };
 
-        assert_eq!(isa.encode(&dfg, &mul32), Err(isa::Legalize::Expand));
+        // Without it, the custom legalization expands the multiplication.
+        assert_eq!(isa.encode(&dfg, &mul32), Err(isa::Legalize::Custom(2)));
+
+        // Integer comparisons use a custom legalization.
+        let icmp32 = InstructionData::IntCompare {
+            opcode: Opcode::Icmp,
+            ty: types::B1,
+            cond: IntCC::SignedGreaterThan,
+            args: [arg32, arg32],
+        };
+        assert_eq!(isa.encode(&dfg, &icmp32), Err(isa::Legalize::Custom(0)));
+
+        // But they are still narrowed first when the type is too wide.
+        let icmp64 = InstructionData::IntCompare {
+            opcode: Opcode::Icmp,
+            ty: types::B1,
+            cond: IntCC::SignedGreaterThan,
+            args: [arg64, arg64],
+        };
+        assert_eq!(isa.encode(&dfg, &icmp64), Err(isa::Legalize::Narrow));
     }
 
     #[test]
@@ -1,6 +1,6 @@
 //! RISC-V Settings.
 
-use settings::{self, detail, Builder};
+use settings::{self, detail, Builder, Iter};
 use std::fmt;
 
 // Include code generated by `lib/cretonne/meta/gen_settings.py`. This file contains a public
@@ -24,6 +24,7 @@ mod tests {
                     supports_a = false\n\
                     supports_f = false\n\
                     supports_d = false\n\
+                    supports_c = false\n\
                     enable_m = true\n");
         // Predicates are not part of the Display output.
         assert_eq!(f.full_float(), false);
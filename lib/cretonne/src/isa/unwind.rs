@@ -0,0 +1,106 @@
+//! Unwind information.
+//!
+//! Embedders that throw exceptions through compiled code or sample the call stack in a profiler
+//! need to unwind the stack frames of compiled functions. The `UnwindInfo` struct describes the
+//! stack frame set up by a function's prologue in a target-independent way. The target ISAs
+//! provide serializers for the native unwind table formats, like Windows `UNWIND_INFO` or DWARF
+//! call frame information.
+
+use binemit::CodeOffset;
+use isa::{RegInfo, RegUnit};
+use std::fmt;
+use std::vec::Vec;
+
+/// An operation performed by the prologue that an unwinder must undo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnwindOp {
+    /// Allocate stack space by decrementing the stack pointer.
+    StackAlloc {
+        /// Number of bytes allocated.
+        size: u32,
+    },
+
+    /// Save a register in the stack frame.
+    SaveReg {
+        /// The saved register.
+        reg: RegUnit,
+        /// Offset of the save slot from the stack pointer after the stack has been allocated.
+        offset: u32,
+    },
+}
+
+/// A prologue operation and the code offset where it takes effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnwindCode {
+    /// Offset of the end of the prologue instruction from the beginning of the function.
+    pub offset: CodeOffset,
+
+    /// The operation performed by the instruction.
+    pub op: UnwindOp,
+}
+
+/// Description of the stack frame of a compiled function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnwindInfo {
+    /// Size of a pointer and of the return address in bytes.
+    pub pointer_bytes: u8,
+
+    /// Size of the prologue in bytes. The stack frame is fully set up after this many bytes of
+    /// code.
+    pub prologue_size: CodeOffset,
+
+    /// The register used to address the stack frame instead of the stack pointer, if any.
+    pub frame_register: Option<RegUnit>,
+
+    /// Total number of bytes allocated on the stack by the prologue, not counting the return
+    /// address.
+    pub stack_size: u32,
+
+    /// The prologue operations in code order.
+    pub codes: Vec<UnwindCode>,
+}
+
+impl UnwindInfo {
+    /// Create an object that can display the unwind information with register names from `regs`.
+    pub fn display<'a>(&'a self, regs: &'a RegInfo) -> DisplayUnwindInfo<'a> {
+        DisplayUnwindInfo {
+            info: self,
+            regs: regs,
+        }
+    }
+}
+
+/// Temporary object that holds enough information to print unwind information.
+pub struct DisplayUnwindInfo<'a> {
+    info: &'a UnwindInfo,
+    regs: &'a RegInfo,
+}
+
+impl<'a> fmt::Display for DisplayUnwindInfo<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let info = self.info;
+        write!(f,
+               "prologue_size={} stack_size={}",
+               info.prologue_size,
+               info.stack_size)?;
+        if let Some(reg) = info.frame_register {
+            write!(f, " frame_register={}", self.regs.display_regunit(reg))?;
+        }
+        writeln!(f, "")?;
+        for code in &info.codes {
+            match code.op {
+                UnwindOp::StackAlloc { size } => {
+                    writeln!(f, "  @{}: stack_alloc {}", code.offset, size)?
+                }
+                UnwindOp::SaveReg { reg, offset } => {
+                    writeln!(f,
+                             "  @{}: save_reg {}, {}",
+                             code.offset,
+                             self.regs.display_regunit(reg),
+                             offset)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
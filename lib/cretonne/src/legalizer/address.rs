@@ -0,0 +1,170 @@
+//! Folding of address arithmetic into complex memory accesses.
+//!
+//! Front ends compute the address of an array element with explicit `ishl_imm` and `iadd`
+//! instructions, but targets like Intel can add a scaled index register to the address as part
+//! of the memory access. Before any encodings are assigned, loads and stores whose address is
+//! computed by an `iadd` are rewritten as `load_complex` and `store_complex` instructions:
+//!
+//! ```cton
+//!     v10 = ishl_imm v2, 2
+//!     v11 = iadd v1, v10
+//!     v12 = load.i32 v11, 8
+//!     ; Becomes:
+//!     v12 = load_complex.i32 v1, v2, 2, 8
+//! ```
+//!
+//! The address arithmetic is only folded when the memory access is its only use, so the folded
+//! instructions can be removed. The rewritten instruction is kept only if the ISA has an encoding
+//! for it, so targets without complex addressing modes never see these instructions.
+
+use entity_map::EntityMap;
+use ir::{Function, DataFlowGraph, Ebb, Inst, InstructionData, Opcode, Value, ValueDef};
+use ir::instructions::{LoadComplexData, StoreComplexData};
+use isa::TargetIsa;
+use std::boxed::Box;
+use std::vec::Vec;
+
+/// A way of computing an address as `base + (index << shift)`.
+struct AddressMode {
+    base: Value,
+    index: Value,
+    shift: u8,
+    /// The `ishl_imm` instruction that was folded into the shift, if any.
+    scale_inst: Option<Inst>,
+}
+
+/// Fold the address arithmetic of loads and stores in `func` into complex memory accesses where
+/// `isa` can encode them.
+pub fn fold_addresses(func: &mut Function, isa: &TargetIsa) {
+    let mut uses = EntityMap::new();
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for &ebb in &ebbs {
+        for inst in func.layout.ebb_insts(ebb) {
+            for part in &func.dfg.inst_args(inst) {
+                for &arg in part.iter() {
+                    *uses.ensure(func.dfg.resolve_aliases(arg)) += 1;
+                }
+            }
+        }
+    }
+
+    for &ebb in &ebbs {
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        for inst in insts {
+            let addr = match func.dfg[inst] {
+                InstructionData::Load { opcode: Opcode::Load, arg, .. } => arg,
+                InstructionData::Store { opcode: Opcode::Store, args, .. } => args[1],
+                _ => continue,
+            };
+            let (add, modes) = match address_modes(&func.dfg, &uses, addr) {
+                Some(found) => found,
+                None => continue,
+            };
+            for mode in modes {
+                let folded = complex_access(&func.dfg[inst], &mode);
+                if isa.encode(&func.dfg, &folded).is_ok() {
+                    func.dfg[inst] = folded;
+                    func.layout.remove_inst(add);
+                    if let Some(shl) = mode.scale_inst {
+                        func.layout.remove_inst(shl);
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Build the complex version of the load or store `data` using the address mode `mode`.
+fn complex_access(data: &InstructionData, mode: &AddressMode) -> InstructionData {
+    match *data {
+        InstructionData::Load { ty, flags, offset, .. } => {
+            InstructionData::LoadComplex {
+                opcode: Opcode::LoadComplex,
+                ty: ty,
+                data: Box::new(LoadComplexData {
+                                   flags: flags,
+                                   args: [mode.base, mode.index],
+                                   shift: mode.shift,
+                                   offset: offset,
+                               }),
+            }
+        }
+        InstructionData::Store { ty, flags, args, offset, .. } => {
+            InstructionData::StoreComplex {
+                opcode: Opcode::StoreComplex,
+                ty: ty,
+                data: Box::new(StoreComplexData {
+                                   flags: flags,
+                                   args: [args[0], mode.base, mode.index],
+                                   shift: mode.shift,
+                                   offset: offset,
+                               }),
+            }
+        }
+        _ => panic!("Expected load or store: {:?}", data),
+    }
+}
+
+/// Get the instruction defining `value`, if it is an instruction result.
+fn def_inst(dfg: &DataFlowGraph, value: Value) -> Option<Inst> {
+    match dfg.value_def(value) {
+        ValueDef::Res(inst, _) => Some(inst),
+        ValueDef::Arg(..) => None,
+    }
+}
+
+/// Match the address `addr` against `iadd(p, ishl_imm(q, shift))` and `iadd(p, q)`.
+///
+/// Return the `iadd` instruction and the possible address modes, preferring the ones that fold
+/// more instructions. Return `None` if `addr` isn't computed by an `iadd` that is only used by the
+/// memory access.
+fn address_modes(dfg: &DataFlowGraph,
+                 uses: &EntityMap<Value, u32>,
+                 addr: Value)
+                 -> Option<(Inst, Vec<AddressMode>)> {
+    let addr = dfg.resolve_aliases(addr);
+    if uses.get(addr) != Some(&1) {
+        return None;
+    }
+    let add = match dfg.value_def(addr) {
+        ValueDef::Res(inst, _) => inst,
+        ValueDef::Arg(..) => return None,
+    };
+    let (x, y) = match dfg[add] {
+        InstructionData::Binary { opcode: Opcode::Iadd, args, .. } => {
+            (dfg.resolve_aliases(args[0]), dfg.resolve_aliases(args[1]))
+        }
+        _ => return None,
+    };
+
+    // Either operand of the `iadd` can be the scaled index.
+    let mut modes = Vec::new();
+    for &(base, index) in &[(x, y), (y, x)] {
+        if uses.get(index) != Some(&1) {
+            continue;
+        }
+        let shl = match def_inst(dfg, index) {
+            Some(shl) => shl,
+            None => continue,
+        };
+        if let InstructionData::BinaryImm { opcode: Opcode::IshlImm, arg, imm, .. } = dfg[shl] {
+            let shift: i64 = imm.into();
+            if (shift as u64) < 0x100 {
+                modes.push(AddressMode {
+                               base: base,
+                               index: dfg.resolve_aliases(arg),
+                               shift: shift as u8,
+                               scale_inst: Some(shl),
+                           });
+            }
+        }
+    }
+    modes.push(AddressMode {
+                   base: x,
+                   index: y,
+                   shift: 0,
+                   scale_inst: None,
+               });
+    Some((add, modes))
+}
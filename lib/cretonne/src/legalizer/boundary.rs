@@ -0,0 +1,528 @@
+//! Legalize ABI boundaries.
+//!
+//! This legalizer sub-module contains code for dealing with ABI boundaries:
+//!
+//! - Function arguments passed to the entry block.
+//! - Function arguments passed to call instructions.
+//! - Return values from call instructions.
+//! - Return values passed to return instructions.
+//! - Tail calls that the calling conventions don't allow.
+//!
+//! The ABI boundary legalization happens in two phases:
+//!
+//! 1. The `legalize_signatures` function rewrites all the preamble signatures with ABI information
+//!    and possibly new argument types. It also rewrites the entry block arguments to match.
+//! 2. The `handle_call_abi` and `handle_return_abi` functions rewrite call and return instructions
+//!    to match the new ABI signatures.
+//!
+//! Between the two phases, preamble signatures and call/return arguments don't match. This
+//! intermediate state doesn't type check.
+
+use abi::{legalize_abi_value, ValueConversion};
+use entity_map::EntityMap;
+use ir::{Function, Cursor, DataFlowGraph, InstructionData, InstBuilder, Ebb, Opcode, Type,
+         Value, ValueDef, ValueLoc, Signature, ArgumentType, ArgumentLoc, ArgumentPurpose,
+         StackSlot, StackSlotData, StackSlotKind, VariableArgs};
+use ir::types;
+use isa::TargetIsa;
+use std::vec::Vec;
+
+/// Legalize all the function signatures in `func`.
+///
+/// This changes all signatures to be ABI-compliant with full `ArgumentLoc` annotations, and
+/// computes the size of their stack argument arrays. It also rewrites the entry block arguments to
+/// match the legalized function signature. Calls and return instructions are not changed, so this
+/// can leave the function in a state with type discrepancies.
+pub fn legalize_signatures(func: &mut Function, isa: &TargetIsa) {
+    legalize_signature(&mut func.signature, isa, true);
+    for sig in func.dfg.signatures.keys() {
+        legalize_signature(&mut func.dfg.signatures[sig], isa, false);
+    }
+
+    if let Some(entry) = func.layout.entry_block() {
+        legalize_entry_arguments(func, entry);
+    }
+}
+
+/// Legalize a single signature and compute the size of its stack argument array, unless the ISA
+/// already did that for a calling convention with special stack rules.
+///
+/// The `current` flag is set for the signature of the function being legalized.
+pub fn legalize_signature(sig: &mut Signature, isa: &TargetIsa, current: bool) {
+    sig.argument_bytes = None;
+    isa.legalize_signature(sig, current);
+    if sig.argument_bytes.is_none() {
+        sig.compute_argument_bytes(isa.stack_alignment());
+    }
+}
+
+/// Legalize the entry block arguments after `func`'s signature has been legalized.
+///
+/// The legalized signature may contain more arguments than the original signature, and the
+/// argument types have been changed. This function goes through the arguments to the entry EBB and
+/// replaces them with arguments of the right type for the ABI.
+///
+/// The original entry EBB arguments are computed from the new ABI arguments by code inserted at
+/// the top of the entry block. The special purpose arguments that the ISA added to the signature,
+/// like the `link` register, are appended as new entry EBB arguments.
+fn legalize_entry_arguments(func: &mut Function, entry: Ebb) {
+    let abi_types = func.signature.argument_types.clone();
+    let mut pos = Cursor::new(&mut func.layout);
+    pos.goto_top(entry);
+    pos.next_inst();
+
+    // Keep track of the argument types in the ABI-legalized signature.
+    let mut abi_arg = 0;
+
+    // Process the EBB arguments one at a time, possibly replacing one argument with multiple new
+    // ones. We do this by detaching the entry EBB arguments first.
+    let old_args: Vec<Value> = func.dfg.detach_ebb_args(entry).collect();
+    for old_arg in old_args {
+        let arg_type = func.dfg.value_type(old_arg);
+        let matches = match abi_types.get(abi_arg) {
+            Some(abi_type) => abi_type.value_type == arg_type,
+            // Leave extra arguments alone, the verifier will complain about them.
+            None => true,
+        };
+        if matches {
+            // No value translation is necessary, this argument matches the ABI type.
+            func.dfg.attach_ebb_arg(entry, old_arg);
+            abi_arg += 1;
+        } else {
+            // Compute the value we want for `old_arg` from the legalized ABI arguments.
+            let converted = {
+                let mut get_arg = |dfg: &mut DataFlowGraph, ty| {
+                    let abi_type = abi_types[abi_arg];
+                    if ty == abi_type.value_type {
+                        abi_arg += 1;
+                        Ok(dfg.append_ebb_arg(entry, ty))
+                    } else {
+                        Err(abi_type)
+                    }
+                };
+                convert_from_abi(&mut func.dfg, &mut pos, arg_type, &mut get_arg)
+            };
+            func.dfg.change_to_alias(old_arg, converted);
+        }
+    }
+
+    for abi_type in abi_types.iter().skip(abi_arg) {
+        if abi_type.purpose != ArgumentPurpose::Normal {
+            func.dfg.append_ebb_arg(entry, abi_type.value_type);
+        }
+    }
+}
+
+/// Compute original value of type `ty` from the legalized ABI arguments.
+///
+/// The conversion is recursive, controlled by the `get_arg` closure which is called to retrieve an
+/// ABI argument. It returns:
+///
+/// - `Ok(arg)` if the requested type matches the next ABI argument.
+/// - `Err(arg_type)` if further conversions are needed from the ABI argument `arg_type`.
+fn convert_from_abi<GetArg>(dfg: &mut DataFlowGraph,
+                            pos: &mut Cursor,
+                            ty: Type,
+                            get_arg: &mut GetArg)
+                            -> Value
+    where GetArg: FnMut(&mut DataFlowGraph, Type) -> Result<Value, ArgumentType>
+{
+    // Terminate the recursion when we get the desired type.
+    let arg_type = match get_arg(dfg, ty) {
+        Ok(v) => return v,
+        Err(t) => t,
+    };
+
+    // Reconstruct how `ty` was legalized into the `arg_type` argument.
+    match legalize_abi_value(ty, &arg_type) {
+        // Construct a `ty` by concatenating two ABI integers.
+        ValueConversion::IntSplit => {
+            let abi_ty = ty.half_width().expect("Invalid type for conversion");
+            let lo = convert_from_abi(dfg, pos, abi_ty, get_arg);
+            let hi = convert_from_abi(dfg, pos, abi_ty, get_arg);
+            dfg.ins(pos).iconcat_lohi(lo, hi)
+        }
+        // Construct a `ty` by concatenating two halves of a vector.
+        ValueConversion::VectorSplit => {
+            let abi_ty = ty.half_vector().expect("Invalid type for conversion");
+            let lo = convert_from_abi(dfg, pos, abi_ty, get_arg);
+            let hi = convert_from_abi(dfg, pos, abi_ty, get_arg);
+            dfg.ins(pos).vconcat(lo, hi)
+        }
+        // ABI argument is a sign- or zero-extended version of the value we want.
+        ValueConversion::Uext(abi_ty) |
+        ValueConversion::Sext(abi_ty) => {
+            let arg = convert_from_abi(dfg, pos, abi_ty, get_arg);
+            dfg.ins(pos).ireduce(ty, arg)
+        }
+    }
+}
+
+/// Convert `value` to match the ABI arguments at the start of `abi_args`.
+///
+/// The converted values are appended to `args`. Returns the number of ABI arguments that were
+/// used.
+fn convert_to_abi(dfg: &mut DataFlowGraph,
+                  pos: &mut Cursor,
+                  value: Value,
+                  abi_args: &[ArgumentType],
+                  args: &mut VariableArgs)
+                  -> usize {
+    // Terminate the recursion when the value matches the next ABI argument.
+    let ty = dfg.value_type(value);
+    if ty == abi_args[0].value_type {
+        args.push(value);
+        return 1;
+    }
+
+    match legalize_abi_value(ty, &abi_args[0]) {
+        ValueConversion::IntSplit => {
+            let (lo, hi) = dfg.ins(pos).isplit_lohi(value);
+            let n = convert_to_abi(dfg, pos, lo, abi_args, args);
+            n + convert_to_abi(dfg, pos, hi, &abi_args[n..], args)
+        }
+        ValueConversion::VectorSplit => {
+            let (lo, hi) = dfg.ins(pos).vsplit(value);
+            let n = convert_to_abi(dfg, pos, lo, abi_args, args);
+            n + convert_to_abi(dfg, pos, hi, &abi_args[n..], args)
+        }
+        ValueConversion::Uext(abi_ty) => {
+            let arg = dfg.ins(pos).uextend(abi_ty, value);
+            convert_to_abi(dfg, pos, arg, abi_args, args)
+        }
+        ValueConversion::Sext(abi_ty) => {
+            let arg = dfg.ins(pos).sextend(abi_ty, value);
+            convert_to_abi(dfg, pos, arg, abi_args, args)
+        }
+    }
+}
+
+/// Check if the types of `values` match the ABI argument types in `abi_args`.
+fn check_arg_types<I>(dfg: &DataFlowGraph, values: I, abi_args: &[ArgumentType]) -> bool
+    where I: ExactSizeIterator<Item = Value>
+{
+    values.len() == abi_args.len() &&
+    values.zip(abi_args).all(|(v, abi)| dfg.value_type(v) == abi.value_type)
+}
+
+/// Insert ABI conversions for the call instruction at `pos`.
+///
+/// The arguments are converted to match the legalized signature of the callee, and the call
+/// results are converted back to the types that the rest of the function expects.
+///
+/// Returns `true` if any instructions were inserted.
+pub fn handle_call_abi(dfg: &mut DataFlowGraph, pos: &mut Cursor) -> bool {
+    let inst = pos.current_inst().expect("Cursor must point to a call instruction");
+    let sig_ref = dfg.call_signature(inst).expect("Call instruction expected");
+    let abi_args = dfg.signatures[sig_ref].argument_types.clone();
+    let abi_rets = dfg.signatures[sig_ref].return_types.clone();
+
+    let args_ok = check_arg_types(dfg, dfg.inst_args(inst)[1].iter().cloned(), &abi_args);
+    let old_results: Vec<Value> = dfg.inst_results(inst).collect();
+    // Tail calls return their results directly to our caller.
+    let results_ok = dfg[inst].opcode().is_return() ||
+                     check_arg_types(dfg, old_results.iter().cloned(), &abi_rets);
+    if args_ok && results_ok {
+        return false;
+    }
+
+    // Convert the call arguments in place.
+    if !args_ok {
+        let old_args = dfg.inst_args(inst)[1].to_vec();
+        let mut args = VariableArgs::new();
+        let mut abi_arg = 0;
+        for arg in old_args {
+            abi_arg += convert_to_abi(dfg, pos, arg, &abi_args[abi_arg..], &mut args);
+        }
+        dfg.set_inst_variable_args(inst, &args);
+    }
+    if results_ok {
+        return true;
+    }
+
+    // The first result is a direct value which can't be turned into an alias. When its type
+    // doesn't need to change, the call instruction is kept, and the secondary results are
+    // recreated with the legalized types. Otherwise, a new call is inserted, and `inst` is turned
+    // into the instruction computing the first result.
+    let old_types: Vec<Type> = old_results.iter().map(|&v| dfg.value_type(v)).collect();
+    let keep_call = old_types.is_empty() || old_types[0] == abi_rets[0].value_type;
+    dfg.detach_secondary_results(inst);
+    let call = if keep_call {
+        dfg.make_inst_results(inst, types::VOID);
+        // Insert the result conversions after the call.
+        pos.next_inst();
+        inst
+    } else {
+        let fixed = dfg.inst_args(inst)[0].to_vec();
+        let varargs: VariableArgs = dfg.inst_variable_args(inst).iter().cloned().collect();
+        match dfg[inst] {
+            InstructionData::Call { opcode, func_ref, .. } => {
+                dfg.ins(pos).Call(opcode, types::VOID, func_ref, varargs).0
+            }
+            InstructionData::IndirectCall { opcode, sig_ref, .. } => {
+                dfg.ins(pos)
+                    .IndirectCall(opcode, types::VOID, sig_ref, fixed[0], varargs)
+                    .0
+            }
+            _ => panic!("{} is not a call", dfg[inst].opcode()),
+        }
+    };
+
+    let new_results: Vec<Value> = dfg.inst_results(call).collect();
+    let first = if keep_call { 1 } else { 0 };
+    let mut abi_res = first;
+    for (resno, (&old, &ty)) in old_results.iter().zip(&old_types).enumerate().skip(first) {
+        let converted = {
+            let mut get_res = |dfg: &mut DataFlowGraph, ty| {
+                let res = new_results[abi_res];
+                if ty == dfg.value_type(res) {
+                    abi_res += 1;
+                    Ok(res)
+                } else {
+                    Err(abi_rets[abi_res])
+                }
+            };
+            convert_from_abi(dfg, pos, ty, &mut get_res)
+        };
+        if resno == 0 {
+            // Move the final conversion instruction into `inst` so it defines the first result.
+            let conv = match dfg.value_def(converted) {
+                ValueDef::Res(conv, 0) => conv,
+                _ => panic!("Unexpected conversion of {}", converted),
+            };
+            dfg[inst] = dfg[conv].clone();
+            pos.goto_inst(conv);
+            pos.remove_inst();
+        } else {
+            dfg.change_to_alias(old, converted);
+        }
+    }
+    true
+}
+
+/// Replace the tail call at `pos` with a normal call followed by a `return` instruction, unless a
+/// function with the signature `sig` can make the tail call.
+///
+/// The signatures must have been legalized.
+///
+/// Returns `true` if the tail call was replaced.
+pub fn handle_tail_call(dfg: &mut DataFlowGraph, pos: &mut Cursor, sig: &Signature) -> bool {
+    let inst = pos.current_inst().expect("Cursor must point to a tail call instruction");
+    let sig_ref = dfg.call_signature(inst).expect("Call instruction expected");
+    if sig.can_tail_call(&dfg.signatures[sig_ref]) {
+        return false;
+    }
+
+    let fixed = dfg.inst_args(inst)[0].to_vec();
+    let varargs: VariableArgs = dfg.inst_variable_args(inst).iter().cloned().collect();
+    let call = match dfg[inst] {
+        InstructionData::Call { func_ref, .. } => {
+            dfg.ins(pos).Call(Opcode::Call, types::VOID, func_ref, varargs).0
+        }
+        InstructionData::IndirectCall { sig_ref, .. } => {
+            dfg.ins(pos)
+                .IndirectCall(Opcode::CallIndirect, types::VOID, sig_ref, fixed[0], varargs)
+                .0
+        }
+        _ => panic!("{} is not a call", dfg[inst].opcode()),
+    };
+    let mut rets = VariableArgs::new();
+    for value in dfg.inst_results(call) {
+        rets.push(value);
+    }
+    dfg.replace(inst).return_(rets);
+    true
+}
+
+/// Insert ABI conversions for the return instruction at `pos`.
+///
+/// The return values are converted to match the legalized signature `sig` of the function.
+///
+/// The ABI may require some special-purpose function arguments to be returned, like the `sret`
+/// pointer. The ISA appends those to the legalized return types, and their values are simply
+/// passed through from the corresponding arguments of the `entry` block.
+///
+/// When the signature has a `link` argument, a `return` instruction is changed to a `return_reg`
+/// that jumps to the return address in the `link` argument.
+///
+/// Returns `true` if any instructions were inserted.
+pub fn handle_return_abi(dfg: &mut DataFlowGraph,
+                         pos: &mut Cursor,
+                         entry: Ebb,
+                         sig: &Signature)
+                         -> bool {
+    let inst = pos.current_inst().expect("Cursor must point to a return instruction");
+    let link = match dfg[inst].opcode() {
+        Opcode::Return => sig.special_arg_index(ArgumentPurpose::Link),
+        _ => None,
+    };
+    let old_rets = dfg.inst_args(inst)[1].to_vec();
+    if link.is_none() && check_arg_types(dfg, old_rets.iter().cloned(), &sig.return_types) {
+        return false;
+    }
+
+    // Count the special-purpose return values at the end of the legalized signature.
+    let special_rets = sig.return_types
+        .iter()
+        .rev()
+        .take_while(|ret| ret.purpose != ArgumentPurpose::Normal)
+        .count();
+    let abi_rets = &sig.return_types[0..sig.return_types.len() - special_rets];
+
+    let mut rets = VariableArgs::new();
+    let mut abi_ret = 0;
+    for ret in old_rets {
+        abi_ret += convert_to_abi(dfg, pos, ret, &abi_rets[abi_ret..], &mut rets);
+    }
+
+    for ret in &sig.return_types[abi_rets.len()..] {
+        let idx = sig.special_arg_index(ret.purpose)
+            .expect("Special return value without a matching argument");
+        let arg = dfg.ebb_args(entry).nth(idx).expect("Missing special entry argument");
+        rets.push(arg);
+    }
+
+    match link {
+        Some(idx) => {
+            let link = dfg.ebb_args(entry).nth(idx).expect("Missing link entry argument");
+            dfg.replace(inst).return_reg(link, rets);
+        }
+        None => dfg.set_inst_variable_args(inst, &rets),
+    }
+    true
+}
+
+/// Assign the call arguments that are passed on the stack to outgoing argument stack slots.
+///
+/// Each stack argument is copied by a `spill` instruction, and the new value is assigned to an
+/// `OutgoingArg` stack slot at the offset given by the legalized signature.
+///
+/// Returns `true` if any instructions were inserted.
+pub fn spill_call_arguments(dfg: &mut DataFlowGraph,
+                            pos: &mut Cursor,
+                            stack_slots: &mut EntityMap<StackSlot, StackSlotData>,
+                            locations: &mut EntityMap<Value, ValueLoc>)
+                            -> bool {
+    let inst = pos.current_inst().expect("Cursor must point to a call instruction");
+    let sig_ref = dfg.call_signature(inst).expect("Call instruction expected");
+
+    // Collect the stack arguments that are not yet in an outgoing argument slot.
+    let mut spills = Vec::new();
+    for (argno, (&arg, abi)) in dfg.inst_args(inst)[1]
+            .iter()
+            .zip(&dfg.signatures[sig_ref].argument_types)
+            .enumerate() {
+        if let ArgumentLoc::Stack(offset) = abi.location {
+            let spilled = match locations.get(arg) {
+                Some(&ValueLoc::Stack(ss)) => stack_slots[ss].kind == StackSlotKind::OutgoingArg,
+                _ => false,
+            };
+            if !spilled {
+                spills.push((argno, arg, offset, abi.value_type.bits() as u32 / 8));
+            }
+        }
+    }
+
+    for &(argno, arg, offset, size) in &spills {
+        let ss = get_outgoing_arg(stack_slots, size, offset);
+        let spill = dfg.ins(pos).spill(arg);
+        *locations.ensure(spill) = ValueLoc::Stack(ss);
+        dfg.inst_variable_args_mut(inst)[argno] = spill;
+    }
+    !spills.is_empty()
+}
+
+/// Get an outgoing argument stack slot of `size` bytes at `offset`, creating it if needed.
+fn get_outgoing_arg(stack_slots: &mut EntityMap<StackSlot, StackSlotData>,
+                    size: u32,
+                    offset: u32)
+                    -> StackSlot {
+    let existing = stack_slots.keys().find(|&ss| {
+        let slot = &stack_slots[ss];
+        slot.kind == StackSlotKind::OutgoingArg && slot.offset == offset && slot.size == size
+    });
+    existing.unwrap_or_else(|| stack_slots.push(StackSlotData::outgoing_arg(size, offset)))
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, InstBuilder, Opcode, ArgumentType, ArgumentExtension, Signature,
+             ExtFuncData, ExternalName, StackSlotKind, Value, ValueDef, ValueLoc, VariableArgs};
+    use ir::types;
+    use isa;
+    use settings;
+
+    #[test]
+    fn call_boundary() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut func = Function::new();
+        for &ty in &[types::I64, types::I8, types::I32] {
+            func.signature.argument_types.push(ArgumentType::new(ty));
+        }
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(types::I64));
+        let mut small = ArgumentType::new(types::I8);
+        small.extension = ArgumentExtension::Sext;
+        sig.argument_types.push(small);
+        for _ in 0..6 {
+            sig.argument_types.push(ArgumentType::new(types::I32));
+        }
+        sig.return_types.push(ArgumentType::new(types::I64));
+        let sig = func.dfg.signatures.push(sig);
+        let fref = func.dfg.ext_funcs.push(ExtFuncData::new(ExternalName::testcase("f"), sig));
+
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I64);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I8);
+        let v2 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let mut args = VariableArgs::new();
+            args.push(v0);
+            args.push(v1);
+            for _ in 0..6 {
+                args.push(v2);
+            }
+            let call = dfg.ins(pos).call(fref, args);
+            let v3 = dfg.first_result(call);
+            dfg.ins(pos).iadd_imm(v3, 1);
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+        ::legalize_function(&mut func, &*isa).unwrap();
+
+        let call = func.layout
+            .ebb_insts(ebb0)
+            .find(|&inst| func.dfg[inst].opcode() == Opcode::Call)
+            .unwrap();
+        let opcode = |v: Value| match func.dfg.value_def(func.dfg.resolve_aliases(v)) {
+            ValueDef::Res(inst, _) => func.dfg[inst].opcode(),
+            ValueDef::Arg(..) => panic!("{} is an EBB argument", v),
+        };
+
+        // The call arguments are split, extended, and spilled to the outgoing argument area.
+        // On RV32, the `i64` argument is split in two, and the last `i32` goes on the stack. The
+        // halves are the ones our own caller passed in.
+        let args = func.dfg.inst_args(call)[1].to_vec();
+        assert_eq!(args.len(), 9);
+        let entry_args: Vec<Value> = func.dfg.ebb_args(ebb0).collect();
+        assert_eq!(&args[0..2], &entry_args[0..2]);
+        assert_eq!(opcode(args[2]), Opcode::Sextend);
+        assert_eq!(opcode(args[8]), Opcode::Spill);
+        match func.locations[args[8]] {
+            ValueLoc::Stack(ss) => {
+                assert_eq!(func.stack_slots[ss].kind, StackSlotKind::OutgoingArg);
+                assert_eq!(func.stack_slots[ss].offset, 0);
+            }
+            _ => panic!("Stack argument not spilled"),
+        }
+
+        // The `i64` result is returned in two registers, and the narrowed `iadd_imm` uses them
+        // directly.
+        assert_eq!(func.dfg.inst_results(call).count(), 2);
+        assert!(func.layout
+                    .ebb_insts(ebb0)
+                    .all(|inst| func.dfg[inst].opcode() != Opcode::IconcatLohi));
+    }
+}
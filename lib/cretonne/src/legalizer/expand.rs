@@ -0,0 +1,435 @@
+//! Hand-written expansions for missing hardware operations.
+//!
+//! Bit counting, bit and byte reversal, rotations, and the floating point sign bit operations don't
+//! have native instructions on all targets. The expansions here rewrite them in terms of basic
+//! integer arithmetic and bitwise operations. They can't be expressed as patterns in
+//! `meta/base/legalize.py` because the masks and shift amounts depend on the controlling type.
+//!
+//! The legalizer only expands instructions that don't have an encoding for the target ISA, so a
+//! target with a native instruction keeps it, even if its encoding is gated by an ISA setting.
+//!
+//! Compare-and-branch instructions are split into a comparison and a branch on targets that don't
+//! have them, and `select` instructions are expanded into branches on targets without a
+//! conditional move. The `load_complex` and `store_complex` instructions are expanded into the
+//! address arithmetic and a plain memory access on targets without complex addressing modes, and
+//! offsets that are too large for a load or store are added to the address.
+//!
+//! An `atomic_sub` is an `atomic_add` of the negated operand on targets without it. When the
+//! `enable_atomics` setting is off, the code is assumed to be single-threaded, and the atomic
+//! memory operations are expanded into plain loads and stores.
+
+use ir::{Cursor, DataFlowGraph, Inst, InstructionData, Opcode, InstBuilder, MemFlags, Type, Value,
+         VariableArgs};
+use ir::condcodes::IntCC;
+use ir::types::{I8, I32, I64};
+use isa::TargetIsa;
+
+/// Expand the instruction pointed to by `pos` if it is one of the operations handled in this
+/// module.
+///
+/// Only scalar types are supported.
+///
+/// Return `true` if the instruction was replaced.
+pub fn expand_custom(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let opcode = dfg[inst].opcode();
+    let ty = dfg[inst].ctrl_typevar(dfg);
+
+    // The complex memory accesses are expanded for any value type.
+    match dfg[inst] {
+        InstructionData::LoadComplex { ref data, .. } => {
+            let data = data.clone();
+            let addr = complex_address(pos, dfg, data.args, data.shift);
+            dfg.replace(inst).load(ty, data.flags, addr, data.offset);
+            return true;
+        }
+        InstructionData::StoreComplex { ref data, .. } => {
+            let data = data.clone();
+            let addr = complex_address(pos, dfg, [data.args[1], data.args[2]], data.shift);
+            dfg.replace(inst).store(data.flags, data.args[0], addr, data.offset);
+            return true;
+        }
+        // An offset that doesn't fit in the address mode of a load or store is added to the
+        // address first.
+        InstructionData::Load { flags, arg, offset, .. } if opcode == Opcode::Load &&
+                                                            offset != 0 => {
+            let addr = dfg.ins(pos).iadd_imm(arg, offset as i64);
+            dfg.replace(inst).load(ty, flags, addr, 0);
+            return true;
+        }
+        InstructionData::Store { flags, args, offset, .. } if opcode == Opcode::Store &&
+                                                              offset != 0 => {
+            let addr = dfg.ins(pos).iadd_imm(args[1], offset as i64);
+            dfg.replace(inst).store(flags, args[0], addr, 0);
+            return true;
+        }
+        InstructionData::AtomicLoad { .. } |
+        InstructionData::AtomicRmw { .. } |
+        InstructionData::AtomicCas { .. } => return expand_atomic(pos, dfg, isa),
+        _ => {}
+    }
+
+    if !ty.is_scalar() {
+        return false;
+    }
+    let bits = ty.bits() as i64;
+
+    match dfg[inst].clone() {
+        InstructionData::Unary { arg, .. } => {
+            let x = dfg.resolve_aliases(arg);
+            match opcode {
+                Opcode::Bnot => {
+                    dfg.replace(inst).bxor_imm(x, -1);
+                }
+                Opcode::Popcnt => {
+                    let count = popcnt(pos, dfg, x, bits);
+                    dfg.replace(inst).ireduce(I8, count);
+                }
+                Opcode::Clz => {
+                    let count = clz(pos, dfg, x, bits);
+                    dfg.replace(inst).ireduce(I8, count);
+                }
+                Opcode::Cls => {
+                    // The bits following the sign bit which are identical to it are leading zeros
+                    // in `x ^ (x >> 1)`, except for the sign bit itself which is always cleared.
+                    let sign = dfg.ins(pos).sshr_imm(x, 1);
+                    let diff = dfg.ins(pos).bxor(x, sign);
+                    let count = clz(pos, dfg, diff, bits);
+                    let count = dfg.ins(pos).iadd_imm(count, -1);
+                    dfg.replace(inst).ireduce(I8, count);
+                }
+                Opcode::Ctz => {
+                    // Count the ones in the mask of trailing zeros: `!x & (x - 1)`.
+                    let dec = dfg.ins(pos).iadd_imm(x, -1);
+                    let inv = dfg.ins(pos).bxor_imm(x, -1);
+                    let zeros = dfg.ins(pos).band(inv, dec);
+                    let count = popcnt(pos, dfg, zeros, bits);
+                    dfg.replace(inst).ireduce(I8, count);
+                }
+                Opcode::Bitrev => {
+                    let (hi, lo) = swap_groups(pos, dfg, x, bits, 1);
+                    dfg.replace(inst).bor(hi, lo);
+                }
+                Opcode::Bswap => {
+                    if bits == 8 {
+                        dfg.replace(inst).copy(x);
+                    } else {
+                        let (hi, lo) = swap_groups(pos, dfg, x, bits, 8);
+                        dfg.replace(inst).bor(hi, lo);
+                    }
+                }
+                Opcode::Fneg | Opcode::Fabs => {
+                    let int_ty = match float_bits_type(ty) {
+                        Some(t) => t,
+                        None => return false,
+                    };
+                    let sign = sign_bit(bits);
+                    let xi = dfg.ins(pos).bitcast(int_ty, x);
+                    let ai = if opcode == Opcode::Fneg {
+                        dfg.ins(pos).bxor_imm(xi, sign)
+                    } else {
+                        dfg.ins(pos).band_imm(xi, !sign)
+                    };
+                    dfg.replace(inst).bitcast(ty, ai);
+                }
+                _ => return false,
+            }
+        }
+        InstructionData::Binary { args, .. } => {
+            let x = dfg.resolve_aliases(args[0]);
+            let y = dfg.resolve_aliases(args[1]);
+            match opcode {
+                Opcode::Rotl | Opcode::Rotr => {
+                    // Shift amounts are masked to the width of `x`, so the complementary shift
+                    // amount is simply `-y`.
+                    let amount_ty = dfg.value_type(y);
+                    let zero = dfg.ins(pos).iconst(amount_ty, 0);
+                    let neg = dfg.ins(pos).isub(zero, y);
+                    let (left, right) = if opcode == Opcode::Rotl {
+                        (y, neg)
+                    } else {
+                        (neg, y)
+                    };
+                    let hi = dfg.ins(pos).ishl(x, left);
+                    let lo = dfg.ins(pos).ushr(x, right);
+                    dfg.replace(inst).bor(hi, lo);
+                }
+                Opcode::Fcopysign => {
+                    let int_ty = match float_bits_type(ty) {
+                        Some(t) => t,
+                        None => return false,
+                    };
+                    let sign = sign_bit(bits);
+                    let xi = dfg.ins(pos).bitcast(int_ty, x);
+                    let yi = dfg.ins(pos).bitcast(int_ty, y);
+                    let magnitude = dfg.ins(pos).band_imm(xi, !sign);
+                    let sign = dfg.ins(pos).band_imm(yi, sign);
+                    let ai = dfg.ins(pos).bor(magnitude, sign);
+                    dfg.replace(inst).bitcast(ty, ai);
+                }
+                _ => return false,
+            }
+        }
+        InstructionData::BinaryImm { arg, imm, .. } => {
+            let x = dfg.resolve_aliases(arg);
+            let amount: i64 = imm.into();
+            let amount = amount & (bits - 1);
+            let (left, right) = match opcode {
+                Opcode::RotlImm => (amount, (bits - amount) & (bits - 1)),
+                Opcode::RotrImm => ((bits - amount) & (bits - 1), amount),
+                Opcode::IshlImm | Opcode::UshrImm | Opcode::SshrImm => {
+                    // Only the low bits of the shift amount are used, and the masked amount may
+                    // have an encoding when the original one doesn't.
+                    if amount == imm.into() {
+                        return false;
+                    }
+                    match opcode {
+                        Opcode::IshlImm => dfg.replace(inst).ishl_imm(x, amount),
+                        Opcode::UshrImm => dfg.replace(inst).ushr_imm(x, amount),
+                        _ => dfg.replace(inst).sshr_imm(x, amount),
+                    };
+                    return true;
+                }
+                _ => return false,
+            };
+            let hi = dfg.ins(pos).ishl_imm(x, left);
+            let lo = dfg.ins(pos).ushr_imm(x, right);
+            dfg.replace(inst).bor(hi, lo);
+        }
+        InstructionData::Ternary { args, .. } if opcode == Opcode::Select => {
+            expand_select(pos, dfg, inst, args);
+        }
+        InstructionData::BranchIcmp { cond, destination, .. } => {
+            let args = dfg.inst_args(inst)[0].to_vec();
+            let varargs = dfg.inst_variable_args(inst).iter().cloned().collect();
+            let x = dfg.resolve_aliases(args[0]);
+            let y = dfg.resolve_aliases(args[1]);
+            let cmp = dfg.ins(pos).icmp(cond, x, y);
+            dfg.replace(inst).brnz(cmp, destination, varargs);
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Expand the `select` instruction `inst` into a conditional branch.
+///
+/// The instruction `v = select c, x, y` becomes:
+///
+/// ```cton
+///     brnz c, ebb1(x)
+///     jump ebb1(y)
+///
+/// ebb1(v1):
+///     v = copy v1
+/// ```
+fn expand_select(pos: &mut Cursor, dfg: &mut DataFlowGraph, inst: Inst, args: [Value; 3]) {
+    let (c, x, y) = (args[0], args[1], args[2]);
+    let orig_ebb = pos.current_ebb().expect("need EBB");
+
+    let join = dfg.make_ebb();
+    let arg = dfg.append_ebb_arg(join, dfg.value_type(x));
+    pos.insert_ebb(join);
+
+    pos.goto_bottom(orig_ebb);
+    let mut then_args = VariableArgs::new();
+    then_args.push(x);
+    dfg.ins(pos).brnz(c, join, then_args);
+    let mut else_args = VariableArgs::new();
+    else_args.push(y);
+    dfg.ins(pos).jump(join, else_args);
+
+    pos.goto_inst(inst);
+    dfg.replace(inst).copy(arg);
+}
+
+/// Expand an atomic memory operation.
+///
+/// Without the `enable_atomics` setting, the operation is a load followed by the store of the new
+/// value, and the result is the old value.
+fn expand_atomic(pos: &mut Cursor, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let opcode = dfg[inst].opcode();
+    let ty = dfg[inst].ctrl_typevar(dfg);
+
+    if isa.flags().enable_atomics() {
+        if let InstructionData::AtomicRmw { ordering, args, .. } = dfg[inst] {
+            if opcode == Opcode::AtomicSub {
+                let zero = dfg.ins(pos).iconst(ty, 0);
+                let neg = dfg.ins(pos).isub(zero, args[1]);
+                dfg.replace(inst).atomic_add(ordering, args[0], neg);
+                return true;
+            }
+        }
+        return false;
+    }
+
+    let flags = MemFlags::new();
+    match dfg[inst].clone() {
+        InstructionData::AtomicLoad { arg, .. } => {
+            dfg.replace(inst).load(ty, flags, arg, 0);
+        }
+        InstructionData::AtomicRmw { args, .. } if opcode == Opcode::AtomicStore => {
+            dfg.replace(inst).store(flags, args[1], args[0], 0);
+        }
+        InstructionData::AtomicRmw { args, .. } => {
+            let (p, x) = (dfg.resolve_aliases(args[0]), dfg.resolve_aliases(args[1]));
+            let old = dfg.ins(pos).load(ty, flags, p, 0);
+            let new = match opcode {
+                Opcode::AtomicXchg => x,
+                Opcode::AtomicAdd => dfg.ins(pos).iadd(old, x),
+                Opcode::AtomicSub => dfg.ins(pos).isub(old, x),
+                Opcode::AtomicAnd => dfg.ins(pos).band(old, x),
+                Opcode::AtomicOr => dfg.ins(pos).bor(old, x),
+                Opcode::AtomicXor => dfg.ins(pos).bxor(old, x),
+                _ => return false,
+            };
+            dfg.ins(pos).store(flags, new, p, 0);
+            dfg.replace(inst).copy(old);
+        }
+        InstructionData::AtomicCas { args, .. } => {
+            let p = dfg.resolve_aliases(args[0]);
+            let old = dfg.ins(pos).load(ty, flags, p, 0);
+            let eq = dfg.ins(pos).icmp(IntCC::Equal, old, args[1]);
+            let new = dfg.ins(pos).select(eq, args[2], old);
+            dfg.ins(pos).store(flags, new, p, 0);
+            dfg.replace(inst).copy(old);
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Compute the address `p + (q << shift)` of a complex memory access with base and index
+/// `args = [p, q]`.
+fn complex_address(pos: &mut Cursor,
+                   dfg: &mut DataFlowGraph,
+                   args: [Value; 2],
+                   shift: u8)
+                   -> Value {
+    let base = dfg.resolve_aliases(args[0]);
+    let mut index = dfg.resolve_aliases(args[1]);
+    if shift != 0 {
+        index = dfg.ins(pos).ishl_imm(index, shift as i64);
+    }
+    dfg.ins(pos).iadd(base, index)
+}
+
+/// Get the integer type with the same size as the scalar float type `ty`.
+fn float_bits_type(ty: Type) -> Option<Type> {
+    if !ty.is_float() {
+        return None;
+    }
+    match ty.bits() {
+        32 => Some(I32),
+        64 => Some(I64),
+        _ => None,
+    }
+}
+
+/// Get the sign bit of a `bits`-wide integer as an immediate.
+fn sign_bit(bits: i64) -> i64 {
+    1 << (bits - 1)
+}
+
+/// Get a `bits`-wide mask with the low `width` bits set in every group of `2 * width` bits.
+///
+/// For example, `group_mask(8, 2)` is `0b00110011`.
+fn group_mask(bits: i64, width: i64) -> i64 {
+    let mut mask = 0u64;
+    let mut shift = 0;
+    while shift < bits {
+        mask |= ((1u64 << width) - 1) << shift;
+        shift += 2 * width;
+    }
+    mask as i64
+}
+
+/// Insert instructions counting the one bits in the `bits`-wide integer `x`.
+///
+/// This sums the bits in parallel in groups of growing size. The count ends up in the low byte of
+/// the returned value, and the high bytes are garbage.
+fn popcnt(pos: &mut Cursor, dfg: &mut DataFlowGraph, x: Value, bits: i64) -> Value {
+    // Count the bits in each pair.
+    let hi = dfg.ins(pos).ushr_imm(x, 1);
+    let hi = dfg.ins(pos).band_imm(hi, group_mask(bits, 1));
+    let pairs = dfg.ins(pos).isub(x, hi);
+
+    // Sum the pairs in each nibble.
+    let lo = dfg.ins(pos).band_imm(pairs, group_mask(bits, 2));
+    let hi = dfg.ins(pos).ushr_imm(pairs, 2);
+    let hi = dfg.ins(pos).band_imm(hi, group_mask(bits, 2));
+    let nibbles = dfg.ins(pos).iadd(lo, hi);
+
+    // Sum the nibbles in each byte.
+    let hi = dfg.ins(pos).ushr_imm(nibbles, 4);
+    let sum = dfg.ins(pos).iadd(nibbles, hi);
+    let mut sum = dfg.ins(pos).band_imm(sum, group_mask(bits, 4));
+
+    // Sum the bytes into the low byte.
+    let mut shift = 8;
+    while shift < bits {
+        let hi = dfg.ins(pos).ushr_imm(sum, shift);
+        sum = dfg.ins(pos).iadd(sum, hi);
+        shift *= 2;
+    }
+    sum
+}
+
+/// Insert instructions counting the leading zeros in the `bits`-wide integer `x`.
+///
+/// The leading one bit is smeared into all the lower bits, so only the leading zeros remain zero.
+fn clz(pos: &mut Cursor, dfg: &mut DataFlowGraph, x: Value, bits: i64) -> Value {
+    let mut smear = x;
+    let mut shift = 1;
+    while shift < bits {
+        let lo = dfg.ins(pos).ushr_imm(smear, shift);
+        smear = dfg.ins(pos).bor(smear, lo);
+        shift *= 2;
+    }
+    let zeros = dfg.ins(pos).bxor_imm(smear, -1);
+    popcnt(pos, dfg, zeros, bits)
+}
+
+/// Insert instructions reversing the order of the `width`-bit groups in the `bits`-wide integer
+/// `x`.
+///
+/// Adjacent groups of bits are swapped, starting with `width`-bit groups and doubling the group
+/// size. A `width` of 1 reverses the bits, and a `width` of 8 reverses the bytes. The final swap
+/// is not inserted. Instead, the two halves to be or'ed together are returned.
+fn swap_groups(pos: &mut Cursor,
+               dfg: &mut DataFlowGraph,
+               x: Value,
+               bits: i64,
+               width: i64)
+               -> (Value, Value) {
+    let mut x = x;
+    let mut width = width;
+    loop {
+        let mask = group_mask(bits, width);
+        let hi = dfg.ins(pos).ushr_imm(x, width);
+        let hi = dfg.ins(pos).band_imm(hi, mask);
+        let lo = dfg.ins(pos).band_imm(x, mask);
+        let lo = dfg.ins(pos).ishl_imm(lo, width);
+        width *= 2;
+        if width >= bits {
+            return (hi, lo);
+        }
+        x = dfg.ins(pos).bor(hi, lo);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::group_mask;
+
+    #[test]
+    fn masks() {
+        assert_eq!(group_mask(8, 1), 0x55);
+        assert_eq!(group_mask(8, 2), 0x33);
+        assert_eq!(group_mask(8, 4), 0x0f);
+        assert_eq!(group_mask(32, 8), 0x00ff00ff);
+        assert_eq!(group_mask(64, 1), 0x5555555555555555);
+        assert_eq!(group_mask(64, 32), 0xffffffff);
+    }
+}
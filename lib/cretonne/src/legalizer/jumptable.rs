@@ -0,0 +1,117 @@
+//! Legalization of `br_table` through jump tables in memory.
+//!
+//! Targets without a native jump table instruction dispatch `br_table` by loading the
+//! destination from the jump table, which is emitted after the function's code. The index is
+//! checked against the table size first, since `br_table` falls through for indexes outside the
+//! table:
+//!
+//! ```cton
+//!     br_table v1, jt0
+//!     jump ebb3
+//!     ; Becomes (64-bit position-independent code):
+//!     v2 = uextend.i64 v1
+//!     v3 = iconst.i64 4
+//!     br_icmp uge v2, v3, ebb4
+//!     v4 = jump_table_base.i64 jt0
+//!     v5 = jump_table_entry v2, v4, 4, jt0
+//!     v6 = iadd v4, v5
+//!     indirect_jump_table_br v6, jt0
+//!
+//! ebb4:
+//!     jump ebb3
+//! ```
+//!
+//! The missing entries of a jump table also fall through, so they are filled in with the
+//! fall-through EBB in a copy of the table.
+
+use binemit::jump_table_entry_size;
+use ir::{Function, DataFlowGraph, Cursor, Ebb, InstBuilder, InstructionData, Opcode, Type,
+         Value, VariableArgs};
+use ir::condcodes::IntCC;
+use ir::types::{I32, I64};
+use isa::TargetIsa;
+
+/// Expand the `br_table` instructions in `func` into explicit jump table accesses if `isa` can
+/// encode them.
+pub fn expand_br_tables(func: &mut Function, isa: &TargetIsa) {
+    let addr_ty = if isa.flags().is_64bit() { I64 } else { I32 };
+    let entry_size = jump_table_entry_size(isa);
+    let relative = isa.flags().is_pic();
+
+    let mut pos = Cursor::new(&mut func.layout);
+    while let Some(_ebb) = pos.next_ebb() {
+        while let Some(inst) = pos.next_inst() {
+            let (x, table) = match func.dfg[inst] {
+                InstructionData::BranchTable { opcode: Opcode::BrTable, arg, table, .. } => {
+                    (arg, table)
+                }
+                _ => continue,
+            };
+            if isa.encode(&func.dfg, &func.dfg[inst]).is_ok() {
+                continue;
+            }
+            let probe = InstructionData::BranchTableBase {
+                opcode: Opcode::JumpTableBase,
+                ty: addr_ty,
+                table: table,
+            };
+            if isa.encode(&func.dfg, &probe).is_err() {
+                continue;
+            }
+
+            // Split the EBB after the `br_table` so the fall-through code can be branched to.
+            let cont = func.dfg.make_ebb();
+            pos.next_inst();
+            pos.insert_ebb(cont);
+
+            let mut table = table;
+            if func.jump_tables[table].has_holes() {
+                let mut data = func.jump_tables[table].clone();
+                for idx in 0..data.len() {
+                    if data.get_entry(idx).is_none() {
+                        data.set_entry(idx, cont);
+                    }
+                }
+                table = func.jump_tables.push(data);
+            }
+            let len = func.jump_tables[table].len() as i64;
+
+            pos.goto_inst(inst);
+            let idx = bounds_check(&mut func.dfg, &mut pos, x, len, addr_ty, cont);
+            let base = func.dfg.ins(&mut pos).jump_table_base(addr_ty, table);
+            let entry = func.dfg.ins(&mut pos).jump_table_entry(idx, base, entry_size, table);
+            let addr = if relative {
+                func.dfg.ins(&mut pos).iadd(base, entry)
+            } else {
+                entry
+            };
+            func.dfg.replace(inst).indirect_jump_table_br(addr, table);
+        }
+    }
+}
+
+/// Branch to `cont` unless `x < len`, and return `x` converted to `addr_ty`.
+///
+/// Narrow indexes are extended before the comparison, and wide ones are reduced after it.
+fn bounds_check(dfg: &mut DataFlowGraph,
+                pos: &mut Cursor,
+                x: Value,
+                len: i64,
+                addr_ty: Type,
+                cont: Ebb)
+                -> Value {
+    let ty = dfg.value_type(x);
+    let x = if ty.bits() < addr_ty.bits() {
+        dfg.ins(pos).uextend(addr_ty, x)
+    } else {
+        x
+    };
+    let cmp_ty = dfg.value_type(x);
+    let limit = dfg.ins(pos).iconst(cmp_ty, len);
+    dfg.ins(pos).br_icmp(IntCC::UnsignedGreaterThanOrEqual, x, limit, cont, VariableArgs::new());
+    if cmp_ty == addr_ty {
+        x
+    } else {
+        dfg.ins(pos).ireduce(addr_ty, x)
+    }
+}
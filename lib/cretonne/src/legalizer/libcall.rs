@@ -0,0 +1,52 @@
+//! Expansion of instructions into runtime library calls.
+//!
+//! Targets without a native instruction for an operation can call a helper function in the
+//! runtime library instead. The helpers are identified by a `LibCall` which also gives their
+//! conventional symbol names.
+//!
+//! The helper is imported into the function the first time it is needed, with the name
+//! `ExternalName::libcall()` so the embedder can bind it when resolving relocations. The call
+//! instruction replacing the original instruction is legalized like any other call when the
+//! legalizer doubles back.
+
+use ir::{DataFlowGraph, ExtFuncData, FuncRef, ExternalName, Inst, InstBuilder, LibCall,
+         VariableArgs};
+use ir::types::{I32, I64};
+use isa::TargetIsa;
+use super::boundary::legalize_signature;
+
+/// Replace `inst` with a call to the runtime library function that implements it.
+///
+/// Return `false` if there is no library function for the instruction.
+pub fn expand_as_libcall(inst: Inst, dfg: &mut DataFlowGraph, isa: &TargetIsa) -> bool {
+    let ty = dfg[inst].ctrl_typevar(dfg);
+    let libcall = match LibCall::for_inst(dfg[inst].opcode(), ty) {
+        Some(libcall) => libcall,
+        None => return false,
+    };
+
+    let mut args = VariableArgs::new();
+    for &arg in dfg.inst_args(inst)[0] {
+        args.push(arg);
+    }
+    let fref = import_libcall(dfg, isa, libcall);
+    dfg.replace(inst).call(fref, args);
+    true
+}
+
+/// Get a reference to the runtime library function `libcall`, importing it if the function
+/// doesn't already reference it.
+///
+/// The imported function has the signature given by `LibCall::signature()`, legalized for `isa`.
+pub fn import_libcall(dfg: &mut DataFlowGraph, isa: &TargetIsa, libcall: LibCall) -> FuncRef {
+    let name = ExternalName::libcall(libcall);
+    if let Some(fref) = dfg.ext_funcs.keys().find(|&f| dfg.ext_funcs[f].name == name) {
+        return fref;
+    }
+
+    let pointer_type = if isa.flags().is_64bit() { I64 } else { I32 };
+    let mut sig = libcall.signature(pointer_type);
+    legalize_signature(&mut sig, isa, false);
+    let sig = dfg.signatures.push(sig);
+    dfg.ext_funcs.push(ExtFuncData::new(name, sig))
+}
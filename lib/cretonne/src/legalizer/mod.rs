@@ -0,0 +1,198 @@
+//! Legalize instructions.
+//!
+//! A legal instruction is one that can be mapped directly to a machine code instruction for the
+//! target ISA. The `legalize_function()` function takes as input any function and transforms it
+//! into an equivalent function using only legal instructions.
+//!
+//! The characteristics of legal instructions depend on the target ISA, so any given instruction
+//! can be legal for one ISA and illegal for another.
+//!
+//! Besides transforming instructions, the legalizer also fills out the `function.encodings` map
+//! which provides a legal encoding recipe for every instruction.
+//!
+//! The legalizer does not deal with register allocation constraints. These constraints are derived
+//! from the encoding recipes, and solved later by the register allocator.
+
+use ir::{Function, Cursor, DataFlowGraph, Inst, InstructionData, Opcode, InstBuilder};
+use ir::condcodes::IntCC;
+use isa::{TargetIsa, Legalize};
+use result::{CtonError, CtonResult};
+use verifier;
+
+mod address;
+mod boundary;
+mod expand;
+mod jumptable;
+pub mod libcall;
+mod narrow;
+mod split;
+
+/// Legalize `func` for `isa`.
+///
+/// - Legalize the function signatures, and convert the values passed across ABI boundaries: entry
+///   block arguments, call arguments and results, and return values.
+/// - Fold address arithmetic into complex loads and stores where `isa` can encode them.
+/// - Expand `br_table` instructions into loads from the jump tables where `isa` can't encode them.
+/// - Transform any instructions that don't have a legal representation in `isa`.
+/// - Remove the splits and concatenations of values that are too wide for `isa`.
+/// - Fill out `func.encodings`.
+///
+/// Instructions are transformed repeatedly until they all have a legal encoding. An instruction
+/// that can't be transformed by any of the legalization actions is reported as an error.
+pub fn legalize_function(func: &mut Function, isa: &TargetIsa) -> CtonResult {
+    boundary::legalize_signatures(func, isa);
+    address::fold_addresses(func, isa);
+    jumptable::expand_br_tables(func, isa);
+
+    // TODO: This is very simplified and incomplete.
+    func.encodings.resize(func.dfg.num_insts());
+    let entry = func.layout.entry_block();
+    let mut pos = Cursor::new(&mut func.layout);
+    while let Some(_ebb) = pos.next_ebb() {
+        // Keep track of the cursor position before the instruction being processed, so we can
+        // double back when replacing instructions.
+        let mut prev_pos = pos.position();
+
+        while let Some(inst) = pos.next_inst() {
+            // Check for ABI boundaries that need to be converted to the legalized signature.
+            let boundary = match func.dfg[inst].opcode() {
+                Opcode::Call | Opcode::CallIndirect => {
+                    boundary::handle_call_abi(&mut func.dfg, &mut pos) ||
+                    boundary::spill_call_arguments(&mut func.dfg,
+                                                   &mut pos,
+                                                   &mut func.stack_slots,
+                                                   &mut func.locations)
+                }
+                Opcode::ReturnCall | Opcode::ReturnCallIndirect => {
+                    boundary::handle_tail_call(&mut func.dfg, &mut pos, &func.signature) ||
+                    boundary::handle_call_abi(&mut func.dfg, &mut pos)
+                }
+                Opcode::Return | Opcode::ReturnReg => {
+                    boundary::handle_return_abi(&mut func.dfg,
+                                                &mut pos,
+                                                entry.expect("Function has no entry block"),
+                                                &func.signature)
+                }
+                _ => false,
+            };
+            if boundary {
+                // Double back and assign encodings to the inserted instructions.
+                pos.set_position(prev_pos);
+                continue;
+            }
+
+            match isa.encode(&func.dfg, &func.dfg[inst]) {
+                Ok(encoding) => *func.encodings.ensure(inst) = encoding,
+                Err(action) => {
+                    // We should transform the instruction into legal equivalents.
+                    // Possible strategies are:
+                    // 1. Legalize::Expand: Expand instruction into sequence of legal instructions.
+                    //    Possibly iteratively. ()
+                    // 2. Legalize::Narrow: Split the controlling type variable into high and low
+                    //    parts. This applies both to SIMD vector types which can be halved and to
+                    //    integer types such as `i64` used on a 32-bit ISA. ().
+                    // 3. TODO: Promote the controlling type variable to a larger type. This
+                    //    typically means expressing `i8` and `i16` arithmetic in terms if `i32`
+                    //    operations on RISC targets. (It may or may not be beneficial to promote
+                    //    small vector types versus splitting them.)
+                    // 4. Convert to library calls. For example, integer division on an ISA
+                    //    without a divide instruction. This is done by custom legalizations with
+                    //    the `libcall` module.
+                    //
+                    // 5. Legalize::Custom: Call an ISA-specific legalization routine. This is
+                    //    used for instructions that need special treatment on the target, and the
+                    //    generic actions are tried if the routine doesn't change anything.
+                    //
+                    // All the patterns produce equivalent code, so when there is no pattern for
+                    // the requested action, we try the other one. For example, `iadd_imm.i64` on
+                    // a 32-bit ISA is first expanded into `iconst` and `iadd`, and the `iadd` is
+                    // narrowed when we double back.
+                    //
+                    // Narrowing splits an integer controlling type into halves, so it is only
+                    // attempted for types that can be split. The hand-written narrowing
+                    // transformations in the `narrow` module are only used when the ISA asked for
+                    // narrowing. The hand-written expansions in the `expand` module are tried
+                    // after the generated ones.
+                    let split = can_split(&func.dfg, inst);
+                    let changed = match action {
+                        Legalize::Custom(code) => {
+                            isa.custom_legalization(code)(&mut pos, &mut func.dfg, isa) ||
+                            expand(&mut pos, &mut func.dfg) ||
+                            expand::expand_custom(&mut pos, &mut func.dfg, isa) ||
+                            split && narrow(&mut pos, &mut func.dfg)
+                        }
+                        Legalize::Expand => {
+                            expand(&mut pos, &mut func.dfg) ||
+                            expand::expand_custom(&mut pos, &mut func.dfg, isa) ||
+                            split && narrow(&mut pos, &mut func.dfg)
+                        }
+                        Legalize::Narrow => {
+                            split &&
+                            (narrow(&mut pos, &mut func.dfg) ||
+                             narrow::narrow_custom(&mut pos, &mut func.dfg)) ||
+                            expand(&mut pos, &mut func.dfg) ||
+                            expand::expand_custom(&mut pos, &mut func.dfg, isa)
+                        }
+                    };
+                    // If the current instruction was replaced, we need to double back and revisit
+                    // the expanded sequence. This is both to assign encodings and possible to
+                    // expand further.
+                    // There's a risk of infinite looping here if the legalization patterns are
+                    // unsound. Should we attempt to detect that?
+                    //
+                    // If no pattern applies, the function can't be compiled for `isa`. The splits
+                    // and concatenations created by narrowing are the exception. They are removed
+                    // by `resolve_splits()` below.
+                    if changed {
+                        pos.set_position(prev_pos);
+                    } else if !is_split_or_concat(func.dfg[inst].opcode()) {
+                        return Err(no_encoding(func, inst));
+                    }
+                }
+            }
+
+            // Remember this position in case we need to double back.
+            prev_pos = pos.position();
+        }
+    }
+
+    split::resolve_splits(func);
+
+    // The splits that couldn't be resolved are left without an encoding.
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if !func.encodings.get(inst).map_or(false, |enc| enc.is_legal()) {
+                return Err(no_encoding(func, inst));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Is `opcode` one of the value splits or concatenations inserted by narrowing?
+fn is_split_or_concat(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::IsplitLohi | Opcode::IconcatLohi | Opcode::Vsplit | Opcode::Vconcat => true,
+        _ => false,
+    }
+}
+
+/// Report that `inst` couldn't be legalized.
+fn no_encoding(func: &Function, inst: Inst) -> CtonError {
+    CtonError::Verifier(verifier::Error {
+                            location: inst.into(),
+                            message: format!("{} has no legal encoding", func.dfg[inst].opcode()),
+                        })
+}
+
+// Include legalization patterns that were generated by `gen_legalizer.py` from the `XForms` in
+// `meta/cretonne/legalize.py`.
+//
+// Concretely, this defines private functions `narrow()`, and `expand()`.
+include!(concat!(env!("OUT_DIR"), "/legalizer.rs"));
+
+/// Can the controlling type of `inst` be narrowed by splitting it into two halves?
+fn can_split(dfg: &DataFlowGraph, inst: Inst) -> bool {
+    let ty = dfg[inst].ctrl_typevar(dfg);
+    ty.is_int() && ty.half_width().is_some()
+}
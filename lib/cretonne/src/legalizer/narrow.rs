@@ -0,0 +1,262 @@
+//! Hand-written narrowing transformations.
+//!
+//! Most instructions operating on integers that are too wide for the target ISA are narrowed by
+//! the patterns in `meta/base/legalize.py`. The comparisons, shifts, extensions, and constants here
+//! can't be expressed as simple patterns because the replacement sequence depends on the condition
+//! code, an immediate operand, or the type of an argument. The patterns can't express the branches on wide integers either, since
+//! the EBB arguments are passed along unchanged.
+//!
+//! All of these transformations split a double-width integer into its low and high halves with
+//! `isplit_lohi` and join the result with `iconcat_lohi`, just like the generated patterns.
+
+use ir::{Cursor, DataFlowGraph, InstructionData, Opcode, InstBuilder, Type, Value};
+use ir::condcodes::IntCC;
+
+/// Narrow the instruction pointed to by `pos` if it is a comparison, a shift, an extension, a
+/// constant, or a conditional branch of a scalar integer type.
+///
+/// The controlling type must be an integer type that can be split in halves.
+///
+/// Return `true` if the instruction was replaced.
+pub fn narrow_custom(pos: &mut Cursor, dfg: &mut DataFlowGraph) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let ty = dfg[inst].ctrl_typevar(dfg);
+    if !ty.is_scalar() {
+        return false;
+    }
+    let half = ty.half_width().expect("can't split type");
+
+    let opcode = dfg[inst].opcode();
+    let (al, ah) = match (opcode, dfg[inst].clone()) {
+        (Opcode::Icmp, InstructionData::IntCompare { cond, args, .. }) => {
+            let x = dfg.resolve_aliases(args[0]);
+            let y = dfg.resolve_aliases(args[1]);
+            narrow_icmp(pos, dfg, cond, x, y);
+            return true;
+        }
+        (Opcode::Brz, InstructionData::Branch { destination, .. }) |
+        (Opcode::Brnz, InstructionData::Branch { destination, .. }) => {
+            // An integer is zero when the bitwise or of its halves is zero.
+            let x = dfg.resolve_aliases(dfg.inst_args(inst)[0][0]);
+            let varargs = dfg.inst_variable_args(inst).iter().cloned().collect();
+            let (xl, xh) = dfg.ins(pos).isplit_lohi(x);
+            let any = dfg.ins(pos).bor(xl, xh);
+            if opcode == Opcode::Brz {
+                dfg.replace(inst).brz(any, destination, varargs);
+            } else {
+                dfg.replace(inst).brnz(any, destination, varargs);
+            }
+            return true;
+        }
+        (Opcode::Ishl, InstructionData::Binary { args, .. }) |
+        (Opcode::Ushr, InstructionData::Binary { args, .. }) |
+        (Opcode::Sshr, InstructionData::Binary { args, .. }) => {
+            let x = dfg.resolve_aliases(args[0]);
+            let y = dfg.resolve_aliases(args[1]);
+            narrow_shift(pos, dfg, opcode, half, x, y)
+        }
+        (Opcode::IshlImm, InstructionData::BinaryImm { arg, imm, .. }) |
+        (Opcode::UshrImm, InstructionData::BinaryImm { arg, imm, .. }) |
+        (Opcode::SshrImm, InstructionData::BinaryImm { arg, imm, .. }) => {
+            let x = dfg.resolve_aliases(arg);
+            let amount: i64 = imm.into();
+            narrow_shift_imm(pos, dfg, opcode, half, x, amount)
+        }
+        (Opcode::Uextend, InstructionData::Unary { arg, .. }) |
+        (Opcode::Sextend, InstructionData::Unary { arg, .. }) => {
+            let x = dfg.resolve_aliases(arg);
+            narrow_extend(pos, dfg, opcode, half, x)
+        }
+        (Opcode::Iconst, InstructionData::UnaryImm { imm, .. }) => {
+            narrow_iconst(pos, dfg, half, imm.into())
+        }
+        _ => return false,
+    };
+    dfg.replace(inst).iconcat_lohi(al, ah);
+    true
+}
+
+/// Replace the `icmp` instruction at `pos` with comparisons of the halves of `x` and `y`.
+///
+/// The high halves decide the result, unless they are equal. Then the low halves are compared as
+/// unsigned numbers.
+fn narrow_icmp(pos: &mut Cursor, dfg: &mut DataFlowGraph, cond: IntCC, x: Value, y: Value) {
+    let inst = pos.current_inst().expect("need instruction");
+    let (xl, xh) = dfg.ins(pos).isplit_lohi(x);
+    let (yl, yh) = dfg.ins(pos).isplit_lohi(y);
+    match cond {
+        IntCC::Equal => {
+            let hi = dfg.ins(pos).icmp(IntCC::Equal, xh, yh);
+            let lo = dfg.ins(pos).icmp(IntCC::Equal, xl, yl);
+            dfg.replace(inst).band(hi, lo);
+        }
+        IntCC::NotEqual => {
+            let hi = dfg.ins(pos).icmp(IntCC::NotEqual, xh, yh);
+            let lo = dfg.ins(pos).icmp(IntCC::NotEqual, xl, yl);
+            dfg.replace(inst).bor(hi, lo);
+        }
+        _ => {
+            let hi = dfg.ins(pos).icmp(cond.without_equal(), xh, yh);
+            let hi_eq = dfg.ins(pos).icmp(IntCC::Equal, xh, yh);
+            let lo = dfg.ins(pos).icmp(cond.unsigned(), xl, yl);
+            let lo_decides = dfg.ins(pos).band(hi_eq, lo);
+            dfg.replace(inst).bor(hi, lo_decides);
+        }
+    }
+}
+
+/// Insert constants for the halves of the integer `imm`.
+///
+/// The immediate of an `iconst` is sign-extended to the width of its type, so the high half of a
+/// 128-bit constant is all zeros or all ones.
+fn narrow_iconst(pos: &mut Cursor,
+                 dfg: &mut DataFlowGraph,
+                 half: Type,
+                 imm: i64)
+                 -> (Value, Value) {
+    let bits = half.bits() as u32;
+    let (lo, hi) = if bits >= 64 {
+        (imm, imm >> 63)
+    } else {
+        let shift = 64 - bits;
+        ((imm << shift) >> shift, ((imm >> bits) << shift) >> shift)
+    };
+    (dfg.ins(pos).iconst(half, lo), dfg.ins(pos).iconst(half, hi))
+}
+
+/// Insert instructions computing the halves of the extension of `x`.
+///
+/// The low half is `x` extended to the `half` type, and the high half is zero or a copy of the
+/// sign bit.
+fn narrow_extend(pos: &mut Cursor,
+                 dfg: &mut DataFlowGraph,
+                 opcode: Opcode,
+                 half: Type,
+                 x: Value)
+                 -> (Value, Value) {
+    let signed = opcode == Opcode::Sextend;
+    let lo = if dfg.value_type(x) == half {
+        x
+    } else if signed {
+        dfg.ins(pos).sextend(half, x)
+    } else {
+        dfg.ins(pos).uextend(half, x)
+    };
+    let hi = if signed {
+        dfg.ins(pos).sshr_imm(lo, half.bits() as i64 - 1)
+    } else {
+        dfg.ins(pos).iconst(half, 0)
+    };
+    (lo, hi)
+}
+
+/// Insert instructions computing the halves of the shift of `x` by the variable amount `y`.
+///
+/// The shift amount is masked to the width of `x`, so the halves are computed for both the cases
+/// where the amount is smaller than the width of a half and where it isn't, and the right results
+/// are picked with `select`.
+fn narrow_shift(pos: &mut Cursor,
+                dfg: &mut DataFlowGraph,
+                opcode: Opcode,
+                half: Type,
+                x: Value,
+                y: Value)
+                -> (Value, Value) {
+    let bits = half.bits() as i64;
+    let (xl, xh) = dfg.ins(pos).isplit_lohi(x);
+
+    // Only the low bits of the shift amount matter.
+    let amount = if dfg.value_type(y).bits() > half.bits() {
+        dfg.ins(pos).isplit_lohi(y).0
+    } else {
+        y
+    };
+    let s = dfg.ins(pos).band_imm(amount, bits - 1);
+    let big = dfg.ins(pos).band_imm(amount, bits);
+
+    // The bits moving from one half to the other are shifted by `bits - s`. This is done in two
+    // steps because a single shift by `bits` would be masked to a shift by 0.
+    let inv = dfg.ins(pos).bxor_imm(s, bits - 1);
+
+    match opcode {
+        Opcode::Ishl => {
+            let zero = dfg.ins(pos).iconst(half, 0);
+            let lo = dfg.ins(pos).ishl(xl, s);
+            let hi = dfg.ins(pos).ishl(xh, s);
+            let carry = dfg.ins(pos).ushr_imm(xl, 1);
+            let carry = dfg.ins(pos).ushr(carry, inv);
+            let hi = dfg.ins(pos).bor(hi, carry);
+            let al = dfg.ins(pos).select(big, zero, lo);
+            let ah = dfg.ins(pos).select(big, lo, hi);
+            (al, ah)
+        }
+        Opcode::Ushr | Opcode::Sshr => {
+            let lo = dfg.ins(pos).ushr(xl, s);
+            let carry = dfg.ins(pos).ishl_imm(xh, 1);
+            let carry = dfg.ins(pos).ishl(carry, inv);
+            let lo = dfg.ins(pos).bor(lo, carry);
+            let (hi, fill) = if opcode == Opcode::Ushr {
+                (dfg.ins(pos).ushr(xh, s), dfg.ins(pos).iconst(half, 0))
+            } else {
+                (dfg.ins(pos).sshr(xh, s), dfg.ins(pos).sshr_imm(xh, bits - 1))
+            };
+            let al = dfg.ins(pos).select(big, hi, lo);
+            let ah = dfg.ins(pos).select(big, fill, hi);
+            (al, ah)
+        }
+        _ => panic!("{} is not a shift", opcode),
+    }
+}
+
+/// Insert instructions computing the halves of the shift of `x` by the immediate `amount`.
+fn narrow_shift_imm(pos: &mut Cursor,
+                    dfg: &mut DataFlowGraph,
+                    opcode: Opcode,
+                    half: Type,
+                    x: Value,
+                    amount: i64)
+                    -> (Value, Value) {
+    let bits = half.bits() as i64;
+    let amount = amount & (2 * bits - 1);
+    let (xl, xh) = dfg.ins(pos).isplit_lohi(x);
+    if amount == 0 {
+        return (xl, xh);
+    }
+
+    match opcode {
+        Opcode::IshlImm => {
+            if amount >= bits {
+                let zero = dfg.ins(pos).iconst(half, 0);
+                (zero, dfg.ins(pos).ishl_imm(xl, amount - bits))
+            } else {
+                let lo = dfg.ins(pos).ishl_imm(xl, amount);
+                let hi = dfg.ins(pos).ishl_imm(xh, amount);
+                let carry = dfg.ins(pos).ushr_imm(xl, bits - amount);
+                (lo, dfg.ins(pos).bor(hi, carry))
+            }
+        }
+        Opcode::UshrImm | Opcode::SshrImm => {
+            let signed = opcode == Opcode::SshrImm;
+            if amount >= bits {
+                if signed {
+                    let lo = dfg.ins(pos).sshr_imm(xh, amount - bits);
+                    (lo, dfg.ins(pos).sshr_imm(xh, bits - 1))
+                } else {
+                    let lo = dfg.ins(pos).ushr_imm(xh, amount - bits);
+                    (lo, dfg.ins(pos).iconst(half, 0))
+                }
+            } else {
+                let lo = dfg.ins(pos).ushr_imm(xl, amount);
+                let carry = dfg.ins(pos).ishl_imm(xh, bits - amount);
+                let lo = dfg.ins(pos).bor(lo, carry);
+                let hi = if signed {
+                    dfg.ins(pos).sshr_imm(xh, amount)
+                } else {
+                    dfg.ins(pos).ushr_imm(xh, amount)
+                };
+                (lo, hi)
+            }
+        }
+        _ => panic!("{} is not a shift", opcode),
+    }
+}
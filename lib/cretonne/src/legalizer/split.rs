@@ -0,0 +1,241 @@
+//! Value splitting.
+//!
+//! Values whose type is too wide for the target ISA are split into halves by the legalizer: `i64`
+//! values on a 32-bit ISA, `i128` values, and vectors that don't fit in a register. The narrowing
+//! patterns and the ABI boundary conversions take a value apart with `isplit_lohi` or `vsplit`,
+//! and they put the halves back together with `iconcat_lohi` or `vconcat`. These instructions
+//! can't be encoded, so once everything else has been legalized, `resolve_splits()` removes them:
+//!
+//! - A split of a concatenation is replaced by the halves that were concatenated.
+//! - An EBB argument that is split is replaced by two arguments holding its halves, and the
+//!   branches to the EBB pass the halves of their argument instead.
+//! - Concatenations whose results are no longer used are removed.
+//!
+//! A split or concatenation that remains after this computes a value that can't be represented in
+//! the target ISA, and it is left without an encoding.
+
+use ir::{Function, Cursor, Ebb, Inst, InstBuilder, InstructionData, Opcode, Value, ValueDef};
+use ir::instructions::BranchInfo;
+use std::collections::{HashMap, HashSet};
+use std::vec::Vec;
+
+/// Remove the splits and concatenations of values in `func`.
+pub fn resolve_splits(func: &mut Function) {
+    let mut splits = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if concat_opcode(func.dfg[inst].opcode()).is_some() {
+                splits.push(inst);
+            }
+        }
+    }
+
+    // The first result of a split is a direct value which can't be turned into an alias, so its
+    // uses are rewritten once all the splits have been resolved.
+    //
+    // A split of the result of another split can only be resolved after that one, so keep going
+    // until no more splits can be resolved.
+    let mut replaced = HashMap::new();
+    loop {
+        let mut unresolved = Vec::new();
+        let mut progress = false;
+        while let Some(inst) = splits.pop() {
+            match split_halves(func, &replaced, inst, &mut splits) {
+                Some((lo, hi)) => {
+                    let secondary: Vec<Value> = func.dfg.detach_secondary_results(inst).collect();
+                    func.dfg.change_to_alias(secondary[0], hi);
+                    replaced.insert(func.dfg.first_result(inst), lo);
+                    func.layout.remove_inst(inst);
+                    progress = true;
+                }
+                None => unresolved.push(inst),
+            }
+        }
+        if !progress {
+            break;
+        }
+        splits = unresolved;
+    }
+
+    replace_uses(func, &replaced);
+    remove_dead_concats(func);
+}
+
+/// Get the opcode of the concatenation that is undone by the split `opcode`.
+fn concat_opcode(opcode: Opcode) -> Option<Opcode> {
+    match opcode {
+        Opcode::IsplitLohi => Some(Opcode::IconcatLohi),
+        Opcode::Vsplit => Some(Opcode::Vconcat),
+        _ => None,
+    }
+}
+
+/// Find the halves of the value split by `inst`.
+///
+/// Returns `None` if the value is neither a concatenation nor an EBB argument that can be split.
+fn split_halves(func: &mut Function,
+                replaced: &HashMap<Value, Value>,
+                inst: Inst,
+                splits: &mut Vec<Inst>)
+                -> Option<(Value, Value)> {
+    let (concat, arg) = match func.dfg[inst] {
+        InstructionData::UnarySplit { opcode, arg, .. } => {
+            match concat_opcode(opcode) {
+                Some(concat) => (concat, arg),
+                None => return None,
+            }
+        }
+        _ => return None,
+    };
+    let arg = resolve(func, replaced, arg);
+    match func.dfg.value_def(arg) {
+        ValueDef::Res(def, _) => {
+            match func.dfg[def] {
+                InstructionData::Binary { opcode, args, .. } if opcode == concat => {
+                    Some((args[0], args[1]))
+                }
+                _ => None,
+            }
+        }
+        ValueDef::Arg(ebb, num) => {
+            if func.layout.entry_block() == Some(ebb) {
+                // The entry block arguments are determined by the function signature.
+                None
+            } else {
+                Some(split_ebb_arg(func, ebb, num, arg, concat, splits))
+            }
+        }
+    }
+}
+
+/// Replace the argument `arg` of `ebb` with its halves, and return them.
+///
+/// The low half takes the place of `arg`, and the high half is appended to the EBB arguments. The
+/// branches to `ebb` are changed to pass the halves of their argument in the same positions, and
+/// the splits inserted before them are added to `splits`.
+fn split_ebb_arg(func: &mut Function,
+                 ebb: Ebb,
+                 num: usize,
+                 arg: Value,
+                 concat: Opcode,
+                 splits: &mut Vec<Inst>)
+                 -> (Value, Value) {
+    let ty = func.dfg.value_type(arg);
+    let half = if concat == Opcode::Vconcat {
+        ty.half_vector()
+    } else {
+        ty.half_width()
+    };
+    let half = half.expect("can't split type");
+    let lo = func.dfg.replace_ebb_arg(arg, half);
+    let hi = func.dfg.append_ebb_arg(ebb, half);
+
+    // The old argument becomes a concatenation of the halves, which is removed once its other
+    // uses have been resolved.
+    let joined = {
+        let dfg = &mut func.dfg;
+        let pos = &mut Cursor::new(&mut func.layout);
+        pos.goto_top(ebb);
+        pos.next_inst();
+        if concat == Opcode::Vconcat {
+            dfg.ins(pos).vconcat(lo, hi)
+        } else {
+            dfg.ins(pos).iconcat_lohi(lo, hi)
+        }
+    };
+    func.dfg.change_to_alias(arg, joined);
+
+    let mut branches = Vec::new();
+    for pred in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(pred) {
+            if let BranchInfo::SingleDest(dest, _) = func.dfg.analyze_branch(inst) {
+                if dest == ebb {
+                    branches.push(inst);
+                }
+            }
+        }
+    }
+
+    for branch in branches {
+        let value = func.dfg.inst_variable_args(branch)[num];
+        let (value_lo, value_hi) = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.goto_inst(branch);
+            if concat == Opcode::Vconcat {
+                dfg.ins(pos).vsplit(value)
+            } else {
+                dfg.ins(pos).isplit_lohi(value)
+            }
+        };
+        if let ValueDef::Res(split, _) = func.dfg.value_def(value_lo) {
+            splits.push(split);
+        }
+        func.dfg.inst_variable_args_mut(branch)[num] = value_lo;
+        func.dfg.append_inst_arg(branch, value_hi);
+    }
+
+    (lo, hi)
+}
+
+/// Resolve the aliases of `value`, and the split results that have been `replaced`.
+fn resolve(func: &Function, replaced: &HashMap<Value, Value>, value: Value) -> Value {
+    let mut value = func.dfg.resolve_aliases(value);
+    while let Some(&new_value) = replaced.get(&value) {
+        value = func.dfg.resolve_aliases(new_value);
+    }
+    value
+}
+
+/// Rewrite the uses of the values in `replaced` to use their replacements instead.
+fn replace_uses(func: &mut Function, replaced: &HashMap<Value, Value>) {
+    if replaced.is_empty() {
+        return;
+    }
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            let args: Vec<Value> = func.dfg
+                .inst_args(inst)
+                .iter()
+                .flat_map(|part| part.iter())
+                .map(|&arg| resolve(func, replaced, arg))
+                .collect();
+            let mut args = args.into_iter();
+            for part in &mut func.dfg.inst_args_mut(inst) {
+                for arg in part.iter_mut() {
+                    *arg = args.next().expect("same arguments");
+                }
+            }
+        }
+    }
+}
+
+/// Remove the concatenations whose results are not used.
+fn remove_dead_concats(func: &mut Function) {
+    loop {
+        let mut used = HashSet::new();
+        let mut concats = Vec::new();
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                func.dfg[inst].each_arg(&func.dfg.value_lists, |arg| {
+                    used.insert(func.dfg.resolve_aliases(arg));
+                });
+                match func.dfg[inst].opcode() {
+                    Opcode::IconcatLohi | Opcode::Vconcat => concats.push(inst),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut removed = false;
+        for inst in concats {
+            if !used.contains(&func.dfg.first_result(inst)) {
+                func.layout.remove_inst(inst);
+                removed = true;
+            }
+        }
+        if !removed {
+            return;
+        }
+    }
+}
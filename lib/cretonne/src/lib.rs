@@ -1,32 +1,117 @@
 //! Cretonne code generation library.
+//!
+//! The library uses `std` by default. Without the `std` feature and with the `core` feature, it is
+//! a `no_std` crate that only depends on `alloc`, so it can be embedded in kernels and other
+//! environments without an operating system. The `Session` type, pass timing, and printing
+//! functions to stderr after each pass are only available with `std`.
 
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-pub use context::Context;
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+#[cfg(not(feature = "std"))]
+extern crate hashbrown;
+
+pub use block_order::order_ebbs;
+pub use bounds_checks::eliminate_bounds_checks;
+pub use cancel::CancellationToken;
+pub use cold::{prune_noreturn, sink_cold_ebbs};
+pub use combine::combine_function;
+pub use context::{Context, Snapshot, CompileObserver};
+pub use dead_stores::eliminate_dead_stores;
+pub use fold::fold_constants;
+pub use if_conversion::convert_ifs;
+pub use inline::{inline_call, can_inline, function_size};
 pub use legalizer::legalize_function;
-pub use verifier::verify_function;
-pub use write::write_function;
+pub use legalizer::libcall::import_libcall;
+pub use mem_usage::MemUsage;
+pub use postopt::do_postopt;
+pub use prologue_epilogue::{insert_prologue_epilogue, used_callee_saved_registers};
+pub use redundant_loads::eliminate_redundant_loads;
+pub use result::{CtonError, CtonResult};
+#[cfg(feature = "std")]
+pub use session::{Session, PooledContext};
+pub use shadow_memory::instrument_heap_accesses;
+pub use simple_preopt::do_preopt;
+pub use stable_hash::StableHasher;
+pub use split_edge::{is_critical_edge, split_critical_edge};
+pub use straighten::{straighten_layout, remove_fallthroughs};
+pub use structural::equivalent;
+pub use type_fixer::{check_types, fix_types, TypeMismatch};
+pub use verifier::{verify_function, verify_context, verify_encoded, verify_liveness,
+                   verify_locations};
+pub use write::{write_function, write_annotated_function, write_instruction, Annotations};
 
 /// Version number of the cretonne crate.
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
+pub mod alias_analysis;
+pub mod binemit;
 pub mod cfg;
+pub mod debuginfo;
 pub mod dominator_tree;
 pub mod entity_list;
 pub mod entity_map;
 pub mod ir;
 pub mod isa;
+pub mod loop_analysis;
 pub mod regalloc;
+pub mod serialize;
 pub mod settings;
 pub mod sparse_map;
+pub mod stack_layout;
+pub mod timing;
 pub mod verifier;
 
 mod abi;
+mod block_order;
+mod bounds_checks;
+mod cancel;
+mod cold;
+mod combine;
 mod constant_hash;
 mod context;
+mod dead_stores;
+mod divconst_magic_numbers;
+mod fold;
+mod if_conversion;
+mod inline;
 mod legalizer;
+mod mem_usage;
 mod packed_option;
 mod partition_slice;
+mod postopt;
 mod predicates;
+mod prologue_epilogue;
+mod redundant_loads;
 mod ref_slice;
+mod result;
+#[cfg(feature = "std")]
+mod session;
+mod shadow_memory;
+mod simple_preopt;
+mod split_edge;
+mod stable_hash;
+mod straighten;
+mod structural;
+mod type_fixer;
 mod write;
+
+/// The parts of `std` used by this crate, taken from `core` and `alloc` in a `no_std` build.
+#[cfg(not(feature = "std"))]
+mod std {
+    pub use core::*;
+    pub use alloc::{boxed, fmt, slice, str, string, vec};
+
+    pub mod collections {
+        pub use alloc::collections::{BTreeMap, BTreeSet};
+        pub use hashbrown::{HashMap, HashSet};
+    }
+
+    pub mod sync {
+        pub use alloc::sync::Arc;
+        pub use core::sync::atomic;
+    }
+}
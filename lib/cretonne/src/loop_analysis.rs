@@ -0,0 +1,279 @@
+//! A loop analysis represented as mappings of loops to their header EBB and parent in the loop
+//! tree.
+//!
+//! A natural loop is identified by its header EBB, which dominates the sources of all the back
+//! edges going to it. Loops are nested in a loop tree, and each EBB belongs to the innermost loop
+//! containing it.
+
+use cfg::ControlFlowGraph;
+use dominator_tree::DominatorTree;
+use entity_map::{EntityMap, EntityRef, PrimaryEntityData, Keys};
+use ir::{Function, Ebb, Inst, Layout};
+use mem_usage::MemUsage;
+use std::vec::Vec;
+
+/// A small reference to a loop found by the loop analysis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Loop(u32);
+
+impl EntityRef for Loop {
+    fn new(index: usize) -> Self {
+        Loop(index as u32)
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+struct LoopData {
+    header: Ebb,
+    parent: Option<Loop>,
+}
+
+impl PrimaryEntityData for LoopData {}
+
+/// The loop tree of a single function.
+pub struct LoopAnalysis {
+    loops: EntityMap<Loop, LoopData>,
+    ebb_loop_map: EntityMap<Ebb, Option<Loop>>,
+}
+
+/// Methods for querying the loop analysis.
+impl LoopAnalysis {
+    /// Get all the loops in the function.
+    ///
+    /// Outer loops come before the loops nested inside them.
+    pub fn loops(&self) -> Keys<Loop> {
+        self.loops.keys()
+    }
+
+    /// Get the header EBB of `lp`.
+    pub fn loop_header(&self, lp: Loop) -> Ebb {
+        self.loops[lp].header
+    }
+
+    /// Get the loop immediately containing `lp`, or `None` for an outermost loop.
+    pub fn loop_parent(&self, lp: Loop) -> Option<Loop> {
+        self.loops[lp].parent
+    }
+
+    /// Get the innermost loop containing `ebb`, or `None` if `ebb` is not in a loop.
+    pub fn innermost_loop(&self, ebb: Ebb) -> Option<Loop> {
+        self.ebb_loop_map.get(ebb).cloned().unwrap_or(None)
+    }
+
+    /// If `ebb` is a loop header, get the loop it is the header of.
+    pub fn is_loop_header(&self, ebb: Ebb) -> Option<Loop> {
+        self.innermost_loop(ebb)
+            .and_then(|lp| if self.loop_header(lp) == ebb {
+                          Some(lp)
+                      } else {
+                          None
+                      })
+    }
+
+    /// Is `child` nested inside `parent`? A loop is considered to be nested inside itself.
+    pub fn is_child_loop(&self, child: Loop, parent: Loop) -> bool {
+        let mut lp = Some(child);
+        while let Some(l) = lp {
+            if l == parent {
+                return true;
+            }
+            lp = self.loop_parent(l);
+        }
+        false
+    }
+
+    /// Get the number of loops containing `ebb`.
+    pub fn loop_depth(&self, ebb: Ebb) -> usize {
+        let mut depth = 0;
+        let mut lp = self.innermost_loop(ebb);
+        while let Some(l) = lp {
+            depth += 1;
+            lp = self.loop_parent(l);
+        }
+        depth
+    }
+}
+
+impl LoopAnalysis {
+    /// Allocate a new blank loop analysis. Use `compute` to compute the loop tree for a function.
+    pub fn new() -> LoopAnalysis {
+        LoopAnalysis {
+            loops: EntityMap::new(),
+            ebb_loop_map: EntityMap::new(),
+        }
+    }
+
+    /// Clear all the data structures contained in the loop analysis.
+    pub fn clear(&mut self) {
+        self.loops.clear();
+        self.ebb_loop_map.clear();
+    }
+
+    /// Get the memory used by the loop analysis.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.loops.mem_usage() + self.ebb_loop_map.mem_usage()
+    }
+
+    /// Allocate and compute a loop analysis.
+    pub fn with_function(func: &Function,
+                         cfg: &ControlFlowGraph,
+                         domtree: &DominatorTree)
+                         -> LoopAnalysis {
+        let mut la = LoopAnalysis::new();
+        la.compute(func, cfg, domtree);
+        la
+    }
+
+    /// Reset and compute the loop tree of `func`.
+    ///
+    /// The control flow graph and dominator tree must be up to date.
+    pub fn compute(&mut self, func: &Function, cfg: &ControlFlowGraph, domtree: &DominatorTree) {
+        self.loops.clear();
+        self.ebb_loop_map.clear();
+        self.ebb_loop_map.resize(func.dfg.num_ebbs());
+        self.find_loop_headers(func, cfg, domtree);
+        self.discover_loop_ebbs(func, cfg, domtree);
+    }
+
+    /// Create a loop for every EBB that is the target of a back edge.
+    ///
+    /// The headers are visited in reverse post-order, so outer loops are created before the loops
+    /// nested inside them.
+    fn find_loop_headers(&mut self,
+                         func: &Function,
+                         cfg: &ControlFlowGraph,
+                         domtree: &DominatorTree) {
+        let mut ebbs: Vec<Ebb> = func.layout
+            .ebbs()
+            .filter(|&ebb| domtree.is_reachable(ebb))
+            .collect();
+        ebbs.sort_by(|&a, &b| domtree.rpo_cmp(a, b));
+        for ebb in ebbs {
+            let is_header = cfg.get_predecessors(ebb)
+                .iter()
+                .any(|&(_, branch)| ebb_dominates(domtree, &func.layout, ebb, branch));
+            if is_header {
+                let lp = self.loops.push(LoopData {
+                                             header: ebb,
+                                             parent: None,
+                                         });
+                self.ebb_loop_map[ebb] = Some(lp);
+            }
+        }
+    }
+
+    /// Assign EBBs to their innermost loop, and link the loops into a tree.
+    ///
+    /// Each loop is discovered by walking backwards from its back edges until the header is
+    /// reached. Inner loops are processed first, so an EBB that already belongs to a loop is part
+    /// of an inner loop which gets nested inside the current one.
+    fn discover_loop_ebbs(&mut self,
+                          func: &Function,
+                          cfg: &ControlFlowGraph,
+                          domtree: &DominatorTree) {
+        let loops: Vec<Loop> = self.loops.keys().collect();
+        let mut stack = Vec::new();
+        for &lp in loops.iter().rev() {
+            let header = self.loop_header(lp);
+            for &(pred, branch) in cfg.get_predecessors(header) {
+                if ebb_dominates(domtree, &func.layout, header, branch) {
+                    stack.push(pred);
+                }
+            }
+            while let Some(ebb) = stack.pop() {
+                let outer = match self.ebb_loop_map[ebb] {
+                    None => {
+                        self.ebb_loop_map[ebb] = Some(lp);
+                        ebb
+                    }
+                    Some(inner) => {
+                        // Find the outermost loop containing `ebb` discovered so far.
+                        let mut outermost = inner;
+                        while let Some(parent) = self.loop_parent(outermost) {
+                            outermost = parent;
+                        }
+                        if outermost == lp {
+                            continue;
+                        }
+                        self.loops[outermost].parent = Some(lp);
+                        self.loop_header(outermost)
+                    }
+                };
+                for &(pred, _) in cfg.get_predecessors(outer) {
+                    if domtree.is_reachable(pred) {
+                        stack.push(pred);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Does the EBB `a` dominate the instruction `b`?
+fn ebb_dominates(domtree: &DominatorTree, layout: &Layout, a: Ebb, b: Inst) -> bool {
+    match layout.ebb_insts(a).next() {
+        Some(first) => domtree.dominates(first, b, layout),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cfg::ControlFlowGraph;
+    use dominator_tree::DominatorTree;
+    use ir::{Function, Cursor, InstBuilder, VariableArgs};
+    use ir::types;
+    use super::LoopAnalysis;
+
+    #[test]
+    fn nested_loops() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let ebb3 = func.dfg.make_ebb();
+        let cond = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).jump(ebb1, VariableArgs::new());
+
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).jump(ebb2, VariableArgs::new());
+
+            pos.insert_ebb(ebb2);
+            dfg.ins(pos).brnz(cond, ebb2, VariableArgs::new());
+            dfg.ins(pos).brnz(cond, ebb1, VariableArgs::new());
+            dfg.ins(pos).jump(ebb3, VariableArgs::new());
+
+            pos.insert_ebb(ebb3);
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+        let la = LoopAnalysis::with_function(&func, &cfg, &domtree);
+
+        let loops: Vec<_> = la.loops().collect();
+        assert_eq!(loops.len(), 2);
+        let (outer, inner) = (loops[0], loops[1]);
+        assert_eq!(la.loop_header(outer), ebb1);
+        assert_eq!(la.loop_header(inner), ebb2);
+        assert_eq!(la.loop_parent(inner), Some(outer));
+        assert_eq!(la.loop_parent(outer), None);
+        assert!(la.is_child_loop(inner, outer));
+        assert!(!la.is_child_loop(outer, inner));
+
+        assert_eq!(la.innermost_loop(ebb0), None);
+        assert_eq!(la.innermost_loop(ebb1), Some(outer));
+        assert_eq!(la.innermost_loop(ebb2), Some(inner));
+        assert_eq!(la.innermost_loop(ebb3), None);
+        assert_eq!(la.is_loop_header(ebb2), Some(inner));
+        assert_eq!(la.is_loop_header(ebb3), None);
+        assert_eq!(la.loop_depth(ebb2), 2);
+    }
+}
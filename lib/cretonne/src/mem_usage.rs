@@ -0,0 +1,80 @@
+//! Memory footprint reporting.
+//!
+//! The compiler data structures keep their memory when they are cleared, so a `Context` that once
+//! compiled a huge function holds on to enough memory for compiling it again. That is the right
+//! choice for most workloads, but a long-running JIT may want to release the memory after an
+//! unusually large function. The `mem_usage()` methods on `Context`, `Function`, and the data
+//! structures they contain report how many bytes are allocated, and how many of them are in use,
+//! so an embedder can decide when to drop a context and create a new one.
+//!
+//! The reported numbers cover the tables that grow with the size of the function. Small
+//! allocations owned by individual entities, like the boxed payloads of some instructions and the
+//! parameter lists of signatures, are not counted, and neither is the allocator's own overhead.
+
+use std::mem;
+use std::ops::{Add, AddAssign};
+use std::vec::Vec;
+
+/// The heap memory used by a data structure.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemUsage {
+    /// Number of bytes allocated.
+    pub allocated: usize,
+
+    /// Number of allocated bytes holding live data.
+    pub used: usize,
+}
+
+impl MemUsage {
+    /// Get the memory used by the elements of `vec`.
+    pub fn of_vec<T>(vec: &Vec<T>) -> MemUsage {
+        MemUsage {
+            allocated: vec.capacity() * mem::size_of::<T>(),
+            used: vec.len() * mem::size_of::<T>(),
+        }
+    }
+
+    /// Get the number of bytes allocated but not in use.
+    pub fn unused(&self) -> usize {
+        self.allocated - self.used
+    }
+}
+
+impl Add for MemUsage {
+    type Output = MemUsage;
+
+    fn add(self, other: MemUsage) -> MemUsage {
+        MemUsage {
+            allocated: self.allocated + other.allocated,
+            used: self.used + other.used,
+        }
+    }
+}
+
+impl AddAssign for MemUsage {
+    fn add_assign(&mut self, other: MemUsage) {
+        self.allocated += other.allocated;
+        self.used += other.used;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemUsage;
+
+    #[test]
+    fn vec() {
+        let mut v: Vec<u32> = Vec::with_capacity(10);
+        v.push(1);
+        v.push(2);
+        let usage = MemUsage::of_vec(&v);
+        assert_eq!(usage.used, 8);
+        assert_eq!(usage.allocated, v.capacity() * 4);
+        assert_eq!(usage.unused(), usage.allocated - 8);
+
+        let mut sum = usage + usage;
+        assert_eq!(sum.used, 16);
+        sum += MemUsage::default();
+        assert_eq!(sum, usage + usage);
+    }
+}
@@ -0,0 +1,255 @@
+//! A peephole pass that runs after register allocation.
+//!
+//! Once the registers are known, some instruction sequences can be replaced by cheaper encodings:
+//!
+//! - An `icmp` immediately followed by a `brz` or `brnz` testing its result is fused into a single
+//!   `br_icmp` compare-and-branch instruction. This only happens when the comparison has no other
+//!   uses, and the ISA can encode the `br_icmp` with the registers assigned to the compared values.
+//! - A `copy` whose argument and result were assigned the same register doesn't move anything.
+//!   They are removed and their results are replaced by their arguments.
+//!
+//! Values diverted by `regmove` instructions are tracked while scanning each EBB, so the current
+//! register of a value is used when comparing registers and checking operand constraints.
+//!
+//! The rewritten instructions keep the register assignments of their operands, and they are given
+//! new encodings in place. The liveness analysis computed by the register allocator is not updated.
+//!
+//! Intel instructions with memory operands could also absorb a load here, but the IL doesn't have
+//! memory access instructions yet, and there are no Intel encodings to choose from.
+
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use entity_map::EntityMap;
+use ir::{Function, DataFlowGraph, Ebb, Inst, InstructionData, Opcode, Value, ValueLoc};
+use ir::condcodes::CondCode;
+use ir::instructions::ValueList;
+use ir::types::VOID;
+use isa::{TargetIsa, Encoding, ConstraintKind, RegUnit};
+use regalloc::diversion::RegDiversions;
+
+/// Run the post-optimization pass on `func` after register allocation for `isa`.
+pub fn do_postopt(func: &mut Function, isa: &TargetIsa) {
+    let mut uses = EntityMap::new();
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for &ebb in &ebbs {
+        for inst in func.layout.ebb_insts(ebb) {
+            count_uses(&func.dfg, inst, &mut uses);
+        }
+    }
+
+    let mut moves = HashMap::new();
+    let mut divert = RegDiversions::new();
+    for &ebb in &ebbs {
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        let mut prev = None;
+        divert.clear();
+        for inst in insts {
+            if let Some(arg) = nop_copy(func, &divert, inst) {
+                moves.insert(func.dfg.first_result(inst), arg);
+                func.layout.remove_inst(inst);
+                continue;
+            }
+            if let Some(cmp) = prev {
+                if fuse_compare_branch(func, isa, &divert, &uses, cmp, inst) {
+                    func.layout.remove_inst(cmp);
+                }
+            }
+            divert.apply(&func.dfg[inst]);
+            prev = Some(inst);
+        }
+    }
+
+    if !moves.is_empty() {
+        replace_moved_values(func, &moves);
+    }
+}
+
+/// Increment the use counts of the arguments to `inst`.
+fn count_uses(dfg: &DataFlowGraph, inst: Inst, uses: &mut EntityMap<Value, u32>) {
+    for part in &dfg.inst_args(inst) {
+        for &arg in part.iter() {
+            *uses.ensure(dfg.resolve_aliases(arg)) += 1;
+        }
+    }
+}
+
+/// Get the register currently holding `value`, if any.
+fn value_reg(func: &Function, divert: &RegDiversions, value: Value) -> Option<RegUnit> {
+    match divert.location(func.dfg.resolve_aliases(value), &func.locations) {
+        ValueLoc::Reg(reg) => Some(reg),
+        _ => None,
+    }
+}
+
+/// If `inst` is a `copy` between two values in the same register, get its argument.
+fn nop_copy(func: &Function, divert: &RegDiversions, inst: Inst) -> Option<Value> {
+    let arg = match func.dfg[inst] {
+        InstructionData::Unary { opcode: Opcode::Copy, arg, .. } => func.dfg.resolve_aliases(arg),
+        _ => return None,
+    };
+    // The later uses of the result would read a diverted argument from the wrong register.
+    if divert.diversion(arg).is_some() {
+        return None;
+    }
+    let reg = value_reg(func, divert, arg)?;
+    if value_reg(func, divert, func.dfg.first_result(inst)) == Some(reg) {
+        Some(arg)
+    } else {
+        None
+    }
+}
+
+/// Replace the `cmp` and `branch` instruction pair with a `br_icmp` instruction if possible.
+///
+/// The `branch` instruction is rewritten in place, and the caller must remove `cmp` when this
+/// function returns `true`.
+fn fuse_compare_branch(func: &mut Function,
+                       isa: &TargetIsa,
+                       divert: &RegDiversions,
+                       uses: &EntityMap<Value, u32>,
+                       cmp: Inst,
+                       branch: Inst)
+                       -> bool {
+    let (cond, args) = match func.dfg[cmp] {
+        InstructionData::IntCompare { opcode: Opcode::Icmp, cond, args, .. } => (cond, args),
+        _ => return false,
+    };
+    let result = func.dfg.first_result(cmp);
+    if uses.get(result) != Some(&1) {
+        return false;
+    }
+    let (cond, destination) = match func.dfg[branch] {
+        InstructionData::Branch { opcode, destination, .. } => {
+            if func.dfg.resolve_aliases(func.dfg.inst_args(branch)[0][0]) != result {
+                return false;
+            }
+            match opcode {
+                Opcode::Brz => (cond.inverse(), destination),
+                Opcode::Brnz => (cond, destination),
+                _ => return false,
+            }
+        }
+        _ => return false,
+    };
+
+    let mut fused_args = ValueList::default();
+    {
+        let varargs = func.dfg.inst_variable_args(branch).to_vec();
+        fused_args.extend(args.iter().cloned().chain(varargs), &mut func.dfg.value_lists);
+    }
+    let fused = InstructionData::BranchIcmp {
+        opcode: Opcode::BrIcmp,
+        ty: VOID,
+        cond: cond,
+        destination: destination,
+        args: fused_args,
+    };
+    let enc = match isa.encode(&func.dfg, &fused) {
+        Ok(enc) => enc,
+        Err(_) => return false,
+    };
+    if !operands_fit(func, isa, divert, enc, &args) {
+        return false;
+    }
+
+    func.dfg[branch] = fused;
+    *func.encodings.ensure(branch) = enc;
+    true
+}
+
+/// Check that the locations assigned to `args` satisfy the operand constraints of `enc`.
+fn operands_fit(func: &Function,
+                isa: &TargetIsa,
+                divert: &RegDiversions,
+                enc: Encoding,
+                args: &[Value])
+                -> bool {
+    let constraints = &isa.recipe_constraints()[enc.recipe()];
+    constraints
+        .ins
+        .iter()
+        .zip(args)
+        .all(|(constraint, &arg)| match (constraint.kind, value_reg(func, divert, arg)) {
+                 (ConstraintKind::Reg, Some(reg)) => constraint.regclass.contains(reg),
+                 (ConstraintKind::FixedReg(fixed), Some(reg)) => fixed == reg,
+                 _ => false,
+             })
+}
+
+/// Replace the uses of the results of removed copies with the copied values.
+fn replace_moved_values(func: &mut Function, moves: &HashMap<Value, Value>) {
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for ebb in ebbs {
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        for inst in insts {
+            let dfg = &mut func.dfg;
+            let mut args: Vec<Value> = dfg.inst_args(inst)
+                .iter()
+                .flat_map(|part| part.iter().cloned())
+                .collect();
+            let mut changed = false;
+            for arg in &mut args {
+                let orig = dfg.resolve_aliases(*arg);
+                let mut value = orig;
+                // A copy of a copy is replaced by the original value.
+                while let Some(&src) = moves.get(&value) {
+                    value = dfg.resolve_aliases(src);
+                }
+                if value != orig {
+                    *arg = value;
+                    changed = true;
+                }
+            }
+            if changed {
+                let mut new_args = args.into_iter();
+                for part in &mut dfg.inst_args_mut(inst) {
+                    for arg in part.iter_mut() {
+                        *arg = new_args.next().unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, InstBuilder, Opcode, ValueLoc, VariableArgs};
+    use ir::types;
+    use isa;
+    use settings;
+    use super::do_postopt;
+
+    #[test]
+    fn nop_copy() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let (v1, v2, v3);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            v1 = dfg.ins(pos).copy(v0);
+            v2 = dfg.ins(pos).copy(v1);
+            v3 = dfg.ins(pos).iadd(v2, v0);
+            let mut args = VariableArgs::new();
+            args.push(v3);
+            dfg.ins(pos).return_(args);
+        }
+        *func.locations.ensure(v0) = ValueLoc::Reg(10);
+        *func.locations.ensure(v1) = ValueLoc::Reg(10);
+        *func.locations.ensure(v2) = ValueLoc::Reg(11);
+        *func.locations.ensure(v3) = ValueLoc::Reg(10);
+
+        // The first copy is a no-op, and the second one is really moving the value.
+        do_postopt(&mut func, &*isa);
+        let insts: Vec<_> = func.layout.ebb_insts(ebb0).collect();
+        assert_eq!(insts.len(), 3);
+        assert_eq!(func.dfg[insts[0]].opcode(), Opcode::Copy);
+        assert_eq!(func.dfg.inst_args(insts[0])[0], [v0]);
+        assert_eq!(func.dfg.first_result(insts[0]), v2);
+    }
+}
@@ -9,6 +9,8 @@
 //! Some of these predicates may be unused in certain ISA configurations, so we suppress the
 //! dead_code warning.
 
+use ir::{DataFlowGraph, FuncRef, GlobalVar, MemFlags};
+
 /// Check that `x` can be represented as a `wd`-bit signed integer with `sc` low zero bits.
 #[allow(dead_code)]
 pub fn is_signed_int<T: Into<i64>>(x: T, wd: u8, sc: u8) -> bool {
@@ -25,6 +27,75 @@ pub fn is_unsigned_int<T: Into<i64>>(x: T, wd: u8, sc: u8) -> bool {
     u == (u & m)
 }
 
+/// Check that the low `wd` bits of `x` are a bitmask immediate.
+///
+/// A bitmask immediate consists of a repeated element of 2, 4, 8, 16, 32, or 64 bits, where each
+/// element is a rotated run of ones. The values 0 and all ones are not bitmask immediates.
+#[allow(dead_code)]
+pub fn is_bitmask_imm<T: Into<i64>>(x: T, wd: u8) -> bool {
+    fn mask(bits: u8) -> u64 {
+        if bits == 64 { !0 } else { (1 << bits) - 1 }
+    }
+
+    // Is `v` a non-empty run of ones?
+    fn is_run(v: u64) -> bool {
+        if v == 0 {
+            return false;
+        }
+        let t = v >> v.trailing_zeros();
+        t & (t + 1) == 0
+    }
+
+    let v = x.into() as u64 & mask(wd);
+    let mut size = 2;
+    while size <= wd {
+        let elt = v & mask(size);
+        let mut repl = elt;
+        let mut bits = size;
+        while bits < wd {
+            repl |= repl << bits;
+            bits *= 2;
+        }
+        if repl == v {
+            // A run of ones is rotated across the element boundary when the low bit is set. Then
+            // the zeros form a run instead.
+            return if elt & 1 == 0 {
+                is_run(elt)
+            } else {
+                is_run(!elt & mask(size))
+            };
+        }
+        size *= 2;
+    }
+    false
+}
+
+/// Check that `x` is equal to `y`.
+#[allow(dead_code)]
+pub fn is_equal<T: PartialEq>(x: T, y: T) -> bool {
+    x == y
+}
+
+/// Check that the external function `func_ref` is defined in the same object as the current
+/// function.
+#[allow(dead_code)]
+pub fn is_colocated_func(func_ref: FuncRef, dfg: &DataFlowGraph) -> bool {
+    dfg.ext_funcs[func_ref].colocated
+}
+
+/// Check that the global variable `global_var` is defined in the same object as the current
+/// function.
+#[allow(dead_code)]
+pub fn is_colocated_data(global_var: GlobalVar, dfg: &DataFlowGraph) -> bool {
+    dfg.global_vars[global_var].colocated
+}
+
+/// Check that the memory flags `flags` promise an aligned access.
+#[allow(dead_code)]
+pub fn is_aligned(flags: MemFlags) -> bool {
+    flags.aligned()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -51,6 +122,25 @@ mod tests {
         assert!(is_unsigned_int(x3, 32, 4));
     }
 
+    #[test]
+    fn bitmask_imm() {
+        assert!(is_bitmask_imm(0xff_i64, 32));
+        assert!(is_bitmask_imm(0x5555_5555_i64, 32));
+        assert!(is_bitmask_imm(0x8000_0001_u32, 32));
+        assert!(is_bitmask_imm(0xff_i64, 64));
+        assert!(is_bitmask_imm(0x00ff_00ff_00ff_00ff_i64, 64));
+        assert!(is_bitmask_imm(-16_i64, 64));
+
+        // Only the low 32 bits matter.
+        assert!(is_bitmask_imm(-16_i64, 32));
+
+        assert!(!is_bitmask_imm(0_i64, 32));
+        assert!(!is_bitmask_imm(0xffff_ffff_i64, 32));
+        assert!(!is_bitmask_imm(-1_i64, 64));
+        assert!(!is_bitmask_imm(0x1234_i64, 32));
+        assert!(!is_bitmask_imm(0x0f0f_00ff_i64, 32));
+    }
+
     #[test]
     fn cvt_imm64() {
         use ir::immediates::Imm64;
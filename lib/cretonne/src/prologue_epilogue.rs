@@ -0,0 +1,271 @@
+//! Prologue and epilogue insertion.
+//!
+//! A function must preserve the callee-saved registers of its calling convention, but the
+//! register allocator is free to use them. This pass runs after register allocation and saves
+//! exactly the callee-saved registers the function uses.
+//!
+//! Each used callee-saved register is represented in the IL as a `csr` argument appended to the
+//! function signature and the entry block. The incoming value is saved to a spill slot by a
+//! `spill` instruction at the top of the entry block, and it is reloaded by a `fill` instruction
+//! in front of every instruction that leaves the function. Normal returns also return the reloaded
+//! value as a `csr` return value, so the restored register is visibly live out of the function.
+//!
+//! The `spill` and `fill` instructions are assigned to the callee-saved register and encoded. The
+//! liveness analysis computed by the register allocator is not updated.
+//!
+//! Stack frames larger than the guard region given by the `probestack_size_log2` setting are
+//! probed by the instructions that the ISA's `insert_stack_probes()` hook inserts at the top of
+//! the entry block, before the callee-saved registers are saved to the frame. The ISA's
+//! `insert_frame_allocation()` hook then allocates the frame at the very top of the entry block,
+//! and frees it in front of every instruction that leaves the function, after the callee-saved
+//! registers are restored.
+//!
+//! A function with a `stack_limit` checks for stack overflow with the instructions that the ISA's
+//! `insert_stack_check()` hook inserts above everything else, before the frame is allocated.
+//!
+//! The register allocator assigns the ABI boundary values to the registers required by the
+//! signature, but an entry block argument passed on the stack can still end up in a callee-saved
+//! register. The incoming register value can't be saved in that case, and the pass returns an
+//! error.
+
+use ir::{Function, Cursor, InstBuilder, InstructionData, Inst, Type, Value, ValueDef, ValueLoc,
+         ArgumentType, ArgumentLoc, ArgumentPurpose, StackSlotData, StackSlotKind};
+use ir::entities::AnyEntity;
+use ir::types;
+use isa::{TargetIsa, RegInfo, RegUnit};
+use result::{CtonError, CtonResult};
+use stack_layout::layout_stack;
+use verifier;
+use std::vec::Vec;
+
+/// Get the callee-saved registers that are used by `func` after register allocation, in register
+/// unit order.
+///
+/// A register is used if a value defined in the function is assigned to it, or if a `regmove`
+/// instruction diverts a value to it.
+pub fn used_callee_saved_registers(func: &Function, isa: &TargetIsa) -> Vec<RegUnit> {
+    used_registers(func, isa).into_iter().map(|(reg, _)| reg).collect()
+}
+
+/// Get the used callee-saved registers along with the type needed to save each of them.
+fn used_registers(func: &Function, isa: &TargetIsa) -> Vec<(RegUnit, Type)> {
+    let csrs = isa.callee_saved_registers(func.signature.call_conv);
+    let pointer_type = if isa.flags().is_64bit() {
+        types::I64
+    } else {
+        types::I32
+    };
+    let mut used = Vec::new();
+    {
+        // The whole register is saved, whatever the type of the values using it.
+        let mut add = |reg: RegUnit, ty: Type| if csrs.contains(&reg) &&
+                                                   !used.iter().any(|&(r, _)| r == reg) {
+            used.push((reg, if ty.is_float() { types::F64 } else { pointer_type }));
+        };
+
+        for ebb in func.layout.ebbs() {
+            let defs = func.dfg
+                .ebb_args(ebb)
+                .chain(func.layout
+                           .ebb_insts(ebb)
+                           .flat_map(|inst| func.dfg.inst_results(inst)));
+            for value in defs {
+                if let Some(&ValueLoc::Reg(reg)) = func.locations.get(value) {
+                    add(reg, func.dfg.value_type(value));
+                }
+            }
+            for inst in func.layout.ebb_insts(ebb) {
+                if let InstructionData::RegMove { arg, dst, .. } = func.dfg[inst] {
+                    add(dst, func.dfg.value_type(arg));
+                }
+            }
+        }
+    }
+    used.sort_by_key(|&(reg, _)| reg);
+    used
+}
+
+/// Insert code saving and restoring the callee-saved registers used by `func`, probe large stack
+/// frames, allocate the stack frame, and check the stack limit.
+///
+/// This must be called after register allocation for `isa`.
+pub fn insert_prologue_epilogue(func: &mut Function, isa: &TargetIsa) -> CtonResult {
+    save_callee_saved_registers(func, isa)?;
+
+    // The spill slots of the callee-saved registers are part of the frame too.
+    let frame_size = layout_stack(func, isa.stack_alignment()).frame_size;
+    let guard_size = 1u32.checked_shl(isa.flags().probestack_size_log2() as u32);
+    if guard_size.map_or(false, |size| frame_size > size) {
+        isa.insert_stack_probes(func, frame_size);
+    }
+    isa.insert_frame_allocation(func, frame_size);
+    if let Some(limit) = func.stack_limit {
+        isa.insert_stack_check(func, limit, frame_size);
+    }
+    Ok(())
+}
+
+/// Save and restore the callee-saved registers used by `func`.
+fn save_callee_saved_registers(func: &mut Function, isa: &TargetIsa) -> CtonResult {
+    let used = used_registers(func, isa);
+    if used.is_empty() {
+        return Ok(());
+    }
+
+    let entry = func.layout.entry_block().expect("Function has no entry block");
+    let first = func.layout.ebb_insts(entry).next().expect("Empty entry block");
+    let exits: Vec<Inst> = func.layout
+        .ebbs()
+        .filter_map(|ebb| func.layout.last_inst(ebb))
+        .filter(|&inst| func.dfg[inst].opcode().is_return())
+        .collect();
+
+    // The incoming values of the callee-saved registers must not be clobbered before they are
+    // saved, and the restored values must not clobber the return values.
+    let regs = isa.register_info();
+    for &(reg, _) in &used {
+        for value in func.dfg.ebb_args(entry) {
+            check_boundary(func, value, reg, entry.into(), &regs)?;
+        }
+        for &inst in &exits {
+            let args = func.dfg.inst_args(inst);
+            for &value in args.iter().flat_map(|vals| vals.iter()) {
+                check_boundary(func, value, reg, inst.into(), &regs)?;
+            }
+        }
+    }
+
+    for (reg, ty) in used {
+        let mut abi = ArgumentType::special(ty, ArgumentPurpose::CalleeSaved);
+        abi.location = ArgumentLoc::Reg(reg);
+        func.signature.return_types.push(abi);
+
+        // Save the incoming value at the top of the entry block.
+        let incoming = func.append_param(abi).expect("Function has no entry block");
+        *func.locations.ensure(incoming) = ValueLoc::Reg(reg);
+        let saved = {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_inst(first);
+            func.dfg.ins(&mut pos).spill(incoming)
+        };
+        let bytes = ty.bits() as u32 / 8;
+        let slot = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, bytes));
+        *func.locations.ensure(saved) = ValueLoc::Stack(slot);
+        encode(func, isa, saved);
+
+        // Restore it before leaving the function.
+        for &inst in &exits {
+            let restored = {
+                let mut pos = Cursor::new(&mut func.layout);
+                pos.goto_inst(inst);
+                func.dfg.ins(&mut pos).fill(saved)
+            };
+            *func.locations.ensure(restored) = ValueLoc::Reg(reg);
+            encode(func, isa, restored);
+            match func.dfg[inst] {
+                InstructionData::Return { .. } |
+                InstructionData::ReturnReg { .. } => func.dfg.append_inst_arg(inst, restored),
+                // A tail call only needs the register restored.
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that the ABI boundary `value` isn't assigned to the callee-saved register `reg`.
+fn check_boundary(func: &Function,
+                  value: Value,
+                  reg: RegUnit,
+                  location: AnyEntity,
+                  regs: &RegInfo)
+                  -> CtonResult {
+    if func.locations.get(value) != Some(&ValueLoc::Reg(reg)) {
+        return Ok(());
+    }
+    Err(CtonError::Verifier(verifier::Error {
+                                location: location,
+                                message: format!("{} is assigned to the callee-saved register {}",
+                                                 value,
+                                                 regs.display_regunit(reg)),
+                            }))
+}
+
+/// Assign an encoding to the instruction defining `value`.
+fn encode(func: &mut Function, isa: &TargetIsa, value: Value) {
+    let inst = match func.dfg.value_def(value) {
+        ValueDef::Res(inst, _) => inst,
+        ValueDef::Arg(..) => panic!("{} is not an instruction result", value),
+    };
+    match isa.encode(&func.dfg, &func.dfg[inst]) {
+        Ok(encoding) => *func.encodings.ensure(inst) = encoding,
+        Err(_) => {
+            panic!("Can't encode {}.{} for a callee-saved register",
+                   func.dfg[inst].opcode(),
+                   func.dfg.value_type(value))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, InstBuilder, ValueLoc, VariableArgs, ArgumentPurpose};
+    use ir::types;
+    use isa;
+    use settings;
+    use super::*;
+
+    #[test]
+    fn save_restore() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let link = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let (sum, ret) = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let sum = dfg.ins(pos).iadd(link, link);
+            (sum, dfg.ins(pos).return_reg(link, VariableArgs::new()))
+        };
+        *func.locations.ensure(link) = ValueLoc::Reg(1);
+        *func.locations.ensure(sum) = ValueLoc::Reg(10);
+        assert_eq!(used_callee_saved_registers(&func, &*isa), vec![]);
+
+        // `s0` is callee-saved.
+        *func.locations.ensure(sum) = ValueLoc::Reg(8);
+        assert_eq!(used_callee_saved_registers(&func, &*isa), vec![8]);
+        assert_eq!(insert_prologue_epilogue(&mut func, &*isa), Ok(()));
+        assert_eq!(func.signature.argument_types.len(), 1);
+        assert_eq!(func.signature.argument_types[0].purpose, ArgumentPurpose::CalleeSaved);
+        assert_eq!(func.signature.return_types.len(), 1);
+        assert_eq!(func.dfg.ebb_args(ebb0).count(), 2);
+        assert_eq!(func.dfg.inst_args(ret)[1].len(), 1);
+        assert_eq!(func.stack_slots.len(), 1);
+    }
+
+    #[test]
+    fn boundary_conflict() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let link = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let arg = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).return_reg(link, VariableArgs::new());
+        }
+        *func.locations.ensure(link) = ValueLoc::Reg(1);
+        *func.locations.ensure(arg) = ValueLoc::Reg(9);
+
+        // The incoming value of `s1` was overwritten by the argument.
+        match insert_prologue_epilogue(&mut func, &*isa) {
+            Err(CtonError::Verifier(e)) => {
+                assert_eq!(e.message, "vx1 is assigned to the callee-saved register %x9")
+            }
+            res => panic!("Unexpected {:?}", res),
+        }
+    }
+}
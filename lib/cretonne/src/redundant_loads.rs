@@ -0,0 +1,202 @@
+//! Redundant load elimination.
+//!
+//! A load from a memory location that was just loaded from or stored to produces a value that is
+//! already available in an SSA value. This pass replaces such loads with copies of the available
+//! value:
+//!
+//! ```cton
+//!     store v1, v2, 8
+//!     v3 = load.i32 v2, 8     ; Becomes `v3 = copy v1`.
+//!     v4 = load.i32 v2, 8     ; Becomes `v4 = copy v1`.
+//! ```
+//!
+//! Two accesses are considered to be the same memory location when they use the same base address
+//! value or stack slot, the same offset, and the same type. The available values are propagated
+//! forward through each EBB, and into EBBs with a single predecessor. Loads with the `readonly`
+//! flag can't be invalidated by stores or calls, so they are reused by any dominated load of the
+//! same location.
+//!
+//! Stores and calls invalidate the available values that they may clobber, as determined by the
+//! alias analysis.
+
+use std::collections::HashMap;
+use std::vec::Vec;
+use alias_analysis::{AliasAnalysis, Base, MemAccess};
+use cfg::ControlFlowGraph;
+use dominator_tree::DominatorTree;
+use ir::{Function, Ebb, Inst, Value, InstructionData, InstBuilder};
+use ir::instructions::{BranchInfo, CallInfo};
+
+/// Replace the redundant loads in `func` with copies of the values that are already available.
+///
+/// The alias analysis `aa` must have been computed for `func`.
+pub fn eliminate_redundant_loads(func: &mut Function,
+                                 cfg: &ControlFlowGraph,
+                                 domtree: &DominatorTree,
+                                 aa: &AliasAnalysis) {
+    // Visit the EBBs in reverse post-order so predecessors and dominating loads are seen first.
+    let mut ebbs: Vec<Ebb> = func.layout.ebbs().filter(|&ebb| domtree.is_reachable(ebb)).collect();
+    ebbs.sort_by(|&a, &b| domtree.rpo_cmp(a, b));
+
+    // The available values at the top of EBBs with a single predecessor.
+    let mut ebb_avail: HashMap<Ebb, Vec<Avail>> = HashMap::new();
+
+    // All the `readonly` loads seen so far, keyed by base and offset.
+    let mut readonly: HashMap<(Base, i64), Vec<Inst>> = HashMap::new();
+
+    for ebb in ebbs {
+        let mut avail = ebb_avail.remove(&ebb).unwrap_or_default();
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        for inst in insts {
+            if let Some(access) = MemAccess::from_inst(func, inst) {
+                if access.is_store {
+                    visit_store(func, aa, &mut avail, inst, access);
+                } else {
+                    visit_load(func, domtree, &mut avail, &mut readonly, inst, access);
+                }
+                continue;
+            }
+
+            let opcode = func.dfg[inst].opcode();
+            let is_call = match func.dfg[inst].analyze_call(&func.dfg.value_lists) {
+                CallInfo::NotACall => false,
+                _ => true,
+            };
+            if is_call || opcode.can_store() {
+                // Anything that escapes may be clobbered.
+                avail.retain(|a| a.readonly() || !aa.may_escape(&a.access));
+            }
+
+            if let BranchInfo::SingleDest(dest, _) = func.dfg.analyze_branch(inst) {
+                if cfg.get_predecessors(dest).len() == 1 {
+                    ebb_avail.insert(dest, avail.clone());
+                }
+            }
+        }
+    }
+}
+
+/// A value that is known to be stored in memory.
+#[derive(Clone, Debug)]
+struct Avail {
+    access: MemAccess,
+    value: Value,
+}
+
+impl Avail {
+    /// Was the value loaded from `readonly` memory, so it can't be clobbered?
+    fn readonly(&self) -> bool {
+        !self.access.is_store && self.access.flags.readonly()
+    }
+}
+
+/// Do `a` and `b` access the same location?
+fn same_location(a: &MemAccess, b: &MemAccess) -> bool {
+    a.base == b.base && a.offset == b.offset && a.size == b.size
+}
+
+/// Replace the load `inst` with a copy of an available value, or make its result available.
+fn visit_load(func: &mut Function,
+              domtree: &DominatorTree,
+              avail: &mut Vec<Avail>,
+              readonly: &mut HashMap<(Base, i64), Vec<Inst>>,
+              inst: Inst,
+              access: MemAccess) {
+    let ty = func.dfg.value_type(func.dfg.first_result(inst));
+    let ro = access.flags.readonly();
+    let key = (access.base, access.offset);
+
+    let mut value = avail
+        .iter()
+        .find(|a| same_location(&a.access, &access) && func.dfg.value_type(a.value) == ty)
+        .map(|a| a.value);
+    if value.is_none() && ro {
+        if let Some(loads) = readonly.get(&key) {
+            value = loads
+                .iter()
+                .map(|&load| (load, func.dfg.first_result(load)))
+                .find(|&(load, v)| {
+                          func.dfg.value_type(v) == ty &&
+                          domtree.dominates(load, inst, &func.layout)
+                      })
+                .map(|(_, v)| v);
+        }
+    }
+
+    match value {
+        Some(value) => {
+            func.dfg.replace(inst).copy(value);
+        }
+        None => {
+            if ro {
+                readonly.entry(key).or_insert_with(Vec::new).push(inst);
+            }
+            avail.push(Avail {
+                           access: access,
+                           value: func.dfg.first_result(inst),
+                       });
+        }
+    }
+}
+
+/// Invalidate the available values clobbered by the store `inst`, and make its value available.
+fn visit_store(func: &Function,
+               aa: &AliasAnalysis,
+               avail: &mut Vec<Avail>,
+               inst: Inst,
+               access: MemAccess) {
+    avail.retain(|a| !aa.may_alias(&a.access, &access));
+    let value = match func.dfg[inst] {
+        InstructionData::Store { args, .. } => args[0],
+        InstructionData::StackStore { arg, .. } => arg,
+        _ => panic!("{} is not a store", inst),
+    };
+    avail.push(Avail {
+                   access: access,
+                   value: value,
+               });
+}
+
+#[cfg(test)]
+mod tests {
+    use alias_analysis::BasicAliasAnalysis;
+    use cfg::ControlFlowGraph;
+    use dominator_tree::DominatorTree;
+    use ir::{Function, Cursor, InstBuilder, MemFlags, Opcode, VariableArgs};
+    use ir::types;
+    use verifier::verify_function;
+    use super::eliminate_redundant_loads;
+
+    #[test]
+    fn forward_store() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I64);
+        let (v2, v3);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).store(MemFlags::new(), v0, v1, 0);
+            v2 = dfg.ins(pos).load(types::I32, MemFlags::new(), v1, 0);
+            // Different offset.
+            v3 = dfg.ins(pos).load(types::I32, MemFlags::new(), v1, 4);
+            let mut args = VariableArgs::new();
+            args.push(v2);
+            args.push(v3);
+            dfg.ins(pos).return_(args);
+        }
+
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+        let aa = BasicAliasAnalysis::with_function(&func);
+        eliminate_redundant_loads(&mut func, &cfg, &domtree, &aa);
+        verify_function(&func).unwrap();
+
+        let insts: Vec<_> = func.layout.ebb_insts(ebb0).collect();
+        assert_eq!(func.dfg[insts[1]].opcode(), Opcode::Copy);
+        assert_eq!(func.dfg.inst_args(insts[1])[0], [v0]);
+        assert_eq!(func.dfg[insts[2]].opcode(), Opcode::Load);
+    }
+}
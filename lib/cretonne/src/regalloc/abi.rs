@@ -0,0 +1,46 @@
+//! ABI boundaries.
+//!
+//! The legalized signatures determine where values are passed between functions. The arguments to
+//! the entry block arrive in the locations given by the function's own signature, the arguments
+//! to calls and return instructions must be placed where the callee or the caller expects them,
+//! and the results of calls appear in the locations given by the callee's signature.
+//!
+//! The register allocator treats an ABI register location like a fixed register constraint on the
+//! value.
+
+use ir::{Function, DataFlowGraph, Inst, ArgumentType};
+use isa::{RegInfo, OperandConstraint, ConstraintKind};
+
+/// Get the ABI descriptions of the variable arguments to `inst`: The arguments passed to a call,
+/// or the values returned by a return instruction.
+pub fn arguments(func: &Function, inst: Inst) -> &[ArgumentType] {
+    match func.dfg.call_signature(inst) {
+        Some(sig) => &func.dfg.signatures[sig].argument_types,
+        None if func.dfg[inst].opcode().is_return() => &func.signature.return_types,
+        None => &[],
+    }
+}
+
+/// Get the ABI descriptions of the results of `inst` if it is a call.
+///
+/// A tail call doesn't return to this function, so it has no results.
+pub fn results(dfg: &DataFlowGraph, inst: Inst) -> &[ArgumentType] {
+    match dfg.call_signature(inst) {
+        Some(sig) if !dfg[inst].opcode().is_return() => &dfg.signatures[sig].return_types,
+        _ => &[],
+    }
+}
+
+/// Get the fixed register constraint corresponding to `abi`, if it is passed in a register.
+pub fn constraint(abi: &ArgumentType, reginfo: &RegInfo) -> Option<OperandConstraint> {
+    abi.location
+        .reg()
+        .map(|regunit| {
+                 OperandConstraint {
+                     kind: ConstraintKind::FixedReg(regunit),
+                     regclass: reginfo
+                         .toprc_containing_regunit(regunit)
+                         .expect("ABI register is not in a register class"),
+                 }
+             })
+}
@@ -120,6 +120,8 @@ mod tests {
     const GPR: RegClass = &RegClassData {
         name: "GPR",
         index: 0,
+        bank: 0,
+        toprc: 0,
         width: 1,
         first: 28,
         subclasses: 0,
@@ -128,6 +130,8 @@ mod tests {
     const DPR: RegClass = &RegClassData {
         name: "DPR",
         index: 0,
+        bank: 0,
+        toprc: 0,
         width: 2,
         first: 28,
         subclasses: 0,
@@ -0,0 +1,438 @@
+//! Constructing conventional SSA form and coalescing EBB arguments.
+//!
+//! Conventional SSA form is a subset of SSA form where the values related by EBB arguments don't
+//! interfere. Values passed as EBB arguments are collected into *congruence classes*: An EBB
+//! argument and all the values passed to it by the predecessor branches belong to the same class.
+//! When the values in a congruence class don't interfere, they can all be assigned the same
+//! location, and the branches don't need any copies to move the EBB arguments in place.
+//!
+//! The congruence classes are represented as virtual registers, see the `virtregs` module.
+//!
+//! Every value starts out in its own congruence class. For each EBB argument, this pass tries to
+//! merge the class of the argument with the classes of the values passed to it. When two values
+//! in the classes would interfere, a `copy` of the branch argument is inserted before the branch
+//! instead, and the copy is merged into the class. If the EBB argument itself is live across the
+//! copy, the EBB argument is also copied at the top of its EBB, and the copy replaces all its
+//! uses. Copies are only inserted where they are needed.
+//!
+//! The interference checks use the dominator forest technique from Budimlić et al., "Fast Copy
+//! Coalescing and Live-Range Identification". The values in a class are kept sorted by a preorder
+//! of the dominator tree. When two classes are merged, the sorted lists are traversed together
+//! while keeping a stack of the definitions that dominate the current value. Each value only needs
+//! to be checked for interference with the closest dominating value.
+//!
+//! The coloring pass tries to assign the same register to all the values in a congruence class.
+
+use cfg::ControlFlowGraph;
+use dominator_tree::DominatorTree;
+use entity_map::EntityMap;
+use ir::{Ebb, Inst, Value, Function, Cursor, InstBuilder, ProgramOrder, ProgramPoint, ValueDef};
+use ir::instructions::BranchInfo;
+use isa::TargetIsa;
+use regalloc::affinity::Affinity;
+use regalloc::liveness::Liveness;
+use regalloc::virtregs::VirtRegs;
+use std::cmp::Ordering;
+use std::vec::Vec;
+
+/// Congruence classes of values related by EBB arguments, and scratch space for computing them.
+///
+/// These data structures can be reused between invocations.
+pub struct Coalescing {
+    /// The congruence classes. The values in each class are sorted by the dominator tree preorder
+    /// of their definitions.
+    virtregs: VirtRegs,
+
+    /// Dominator tree preorder number of each EBB, and the largest preorder number of the EBBs it
+    /// dominates.
+    preorder: EntityMap<Ebb, (u32, u32)>,
+
+    /// Stack of dominating values used by the interference check.
+    stack: Vec<(Value, bool)>,
+
+    /// Copies of branch arguments that still interfere with the class of the EBB argument.
+    failed: Vec<Value>,
+
+    /// Instructions using the EBB argument being isolated.
+    users: Vec<Inst>,
+}
+
+impl Coalescing {
+    /// Allocate scratch space for the coalescing pass.
+    pub fn new() -> Coalescing {
+        Coalescing {
+            virtregs: VirtRegs::new(),
+            preorder: EntityMap::new(),
+            stack: Vec::new(),
+            failed: Vec::new(),
+            users: Vec::new(),
+        }
+    }
+
+    /// Clear all data structures in this coalescing pass.
+    pub fn clear(&mut self) {
+        self.virtregs.clear();
+        self.preorder.clear();
+        self.stack.clear();
+        self.failed.clear();
+        self.users.clear();
+    }
+
+    /// Get the congruence classes computed by the last call to `run`.
+    pub fn virtregs(&self) -> &VirtRegs {
+        &self.virtregs
+    }
+
+    /// Convert `func` to conventional SSA form and compute the congruence classes.
+    ///
+    /// The function must be legalized for `isa`, and the `liveness` analysis must be up to date.
+    /// It is kept up to date, and the values in a congruence class are given the same affinity.
+    ///
+    /// Return the number of copies inserted.
+    pub fn run(&mut self,
+               isa: &TargetIsa,
+               func: &mut Function,
+               cfg: &ControlFlowGraph,
+               domtree: &DominatorTree,
+               liveness: &mut Liveness)
+               -> usize {
+        self.virtregs.clear();
+        self.compute_preorder(func, domtree);
+
+        let mut copies = 0;
+        let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+        for ebb in ebbs {
+            if !domtree.is_reachable(ebb) || func.dfg.num_ebb_args(ebb) == 0 {
+                continue;
+            }
+            let params: Vec<Value> = func.dfg.ebb_args(ebb).collect();
+            for (idx, &param) in params.iter().enumerate() {
+                self.failed.clear();
+                for &(pred, branch) in cfg.get_predecessors(ebb) {
+                    if !domtree.is_reachable(pred) {
+                        continue;
+                    }
+                    let arg = branch_arg(func, branch, idx);
+                    if self.join(func, liveness, param, arg) {
+                        continue;
+                    }
+
+                    // The argument interferes with the class. Pass a copy instead.
+                    let copy = copy_branch_arg(isa, func, cfg, liveness, branch, idx, arg);
+                    copies += 1;
+                    if !self.join(func, liveness, param, copy) {
+                        self.failed.push(copy);
+                    }
+                }
+
+                // The EBB argument is live across some of the copies. Shorten its live range.
+                if !self.failed.is_empty() {
+                    self.isolate_param(isa, func, cfg, liveness, ebb, param);
+                    copies += 1;
+                    for i in 0..self.failed.len() {
+                        let copy = self.failed[i];
+                        self.join(func, liveness, param, copy);
+                    }
+                }
+            }
+        }
+
+        // The copies were given live ranges as they were inserted, but the live ranges of the
+        // copied values must be shortened.
+        if copies > 0 {
+            liveness.compute(isa, func, cfg);
+        }
+        self.unify_affinities(liveness);
+        copies
+    }
+
+    /// Give the values in each congruence class the same affinity.
+    ///
+    /// Values that are only passed as EBB arguments don't have a register affinity of their own.
+    /// They should go in the same register as the rest of their class.
+    fn unify_affinities(&self, liveness: &mut Liveness) {
+        for class in self.virtregs.iter() {
+            let affinity = class
+                .iter()
+                .filter_map(|&v| liveness.get(v))
+                .map(|lr| lr.affinity)
+                .find(|affinity| match *affinity {
+                          Affinity::Reg(_) => true,
+                          _ => false,
+                      });
+            if let Some(affinity) = affinity {
+                for &value in class {
+                    if let Some(lr) = liveness.get_mut(value) {
+                        if let Affinity::Any = lr.affinity {
+                            lr.affinity = affinity;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Compute a preorder of the dominator tree, and remember the range of preorder numbers
+    /// dominated by each EBB.
+    fn compute_preorder(&mut self, func: &Function, domtree: &DominatorTree) {
+        self.preorder.clear();
+        self.preorder.resize(func.dfg.num_ebbs());
+
+        let mut children = EntityMap::<Ebb, Vec<Ebb>>::new();
+        children.resize(func.dfg.num_ebbs());
+        for ebb in func.layout.ebbs() {
+            if let Some(idom) = domtree.idom(ebb) {
+                let parent = func.layout.inst_ebb(idom).expect("idom not in layout");
+                children[parent].push(ebb);
+            }
+        }
+
+        // Number the EBBs in a depth-first preorder.
+        let mut order = Vec::new();
+        let mut stack: Vec<Ebb> = func.layout.entry_block().into_iter().collect();
+        while let Some(ebb) = stack.pop() {
+            self.preorder[ebb] = (order.len() as u32, order.len() as u32);
+            order.push(ebb);
+            stack.extend(children[ebb].iter().rev());
+        }
+
+        // Children are numbered after their parents, so visit them first to find the last
+        // dominated EBB.
+        for &ebb in order.iter().rev() {
+            for &child in &children[ebb] {
+                let last = self.preorder[child].1;
+                if last > self.preorder[ebb].1 {
+                    self.preorder[ebb].1 = last;
+                }
+            }
+        }
+    }
+
+    /// Try to merge the congruence classes of `a` and `b`.
+    ///
+    /// Return false if the merged class would contain interfering values.
+    fn join(&mut self, func: &Function, liveness: &Liveness, a: Value, b: Value) -> bool {
+        if self.virtregs.same(a, b) {
+            return true;
+        }
+        if self.interferes(func, liveness, a, b) {
+            return false;
+        }
+        let preorder = &self.preorder;
+        self.virtregs.union(a, b, |x, y| def_cmp(preorder, func, x, y));
+        true
+    }
+
+    /// Would any of the values in the classes of `a` and `b` interfere with each other?
+    fn interferes(&mut self, func: &Function, liveness: &Liveness, a: Value, b: Value) -> bool {
+        // Values that have never been merged are alone in their class.
+        let (single_a, single_b) = ([a], [b]);
+        let mut a = self.virtregs.congruence_class(a);
+        if a.is_empty() {
+            a = &single_a;
+        }
+        let mut b = self.virtregs.congruence_class(b);
+        if b.is_empty() {
+            b = &single_b;
+        }
+
+        let stack = &mut self.stack;
+        stack.clear();
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            // Visit the values of both classes in dominator tree preorder.
+            let take_a = j == b.len() ||
+                         (i < a.len() &&
+                          def_cmp(&self.preorder, func, a[i], b[j]) != Ordering::Greater);
+            let (value, in_a) = if take_a {
+                i += 1;
+                (a[i - 1], true)
+            } else {
+                j += 1;
+                (b[j - 1], false)
+            };
+
+            // Pop the values that don't dominate `value`. They can't dominate the following values
+            // either.
+            while let Some(&(top, _)) = stack.last() {
+                if def_dominates(&self.preorder, func, top, value) {
+                    break;
+                }
+                stack.pop();
+            }
+
+            // Values from the same class are known not to interfere. Otherwise, the closest
+            // dominating value is the only one that can be live at the definition of `value`.
+            if let Some(&(top, top_in_a)) = stack.last() {
+                if top_in_a != in_a && live_after_def(func, liveness, top, value) {
+                    return true;
+                }
+            }
+            stack.push((value, in_a));
+        }
+        false
+    }
+
+    /// Insert a copy of the EBB argument `param` at the top of `ebb`, and replace all the other
+    /// uses of `param` with the copy.
+    ///
+    /// The live range of `param` becomes as short as possible, so it only interferes with the
+    /// values that are live into `ebb`.
+    fn isolate_param(&mut self,
+                     isa: &TargetIsa,
+                     func: &mut Function,
+                     cfg: &ControlFlowGraph,
+                     liveness: &mut Liveness,
+                     ebb: Ebb,
+                     param: Value) {
+        let copy = {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_top(ebb);
+            pos.next_inst();
+            func.dfg.ins(&mut pos).copy(param)
+        };
+        let copy_inst = def_inst(func, copy);
+        encode(isa, func, copy_inst);
+
+        self.users.clear();
+        for e in &func.layout {
+            for inst in func.layout.ebb_insts(e) {
+                if inst == copy_inst {
+                    continue;
+                }
+                let mut used = false;
+                for part in &mut func.dfg.inst_args_mut(inst) {
+                    for arg in part.iter_mut() {
+                        if *arg == param {
+                            *arg = copy;
+                            used = true;
+                        }
+                    }
+                }
+                if used {
+                    self.users.push(inst);
+                }
+            }
+        }
+
+        let affinity = liveness.remove(param).expect("EBB argument has no live range").affinity;
+        liveness.create_dead(param, ebb, affinity);
+        liveness.extend_to_use(param, copy_inst, func, cfg);
+        liveness.create_dead(copy, copy_inst, affinity);
+        for &user in &self.users {
+            liveness.extend_to_use(copy, user, func, cfg);
+        }
+    }
+}
+
+/// Insert a copy of `arg` before `branch`, and pass the copy as EBB argument `idx` instead.
+fn copy_branch_arg(isa: &TargetIsa,
+                   func: &mut Function,
+                   cfg: &ControlFlowGraph,
+                   liveness: &mut Liveness,
+                   branch: Inst,
+                   idx: usize,
+                   arg: Value)
+                   -> Value {
+    let copy = {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_inst(branch);
+        func.dfg.ins(&mut pos).copy(arg)
+    };
+    func.dfg.inst_variable_args_mut(branch)[idx] = copy;
+    let inst = def_inst(func, copy);
+    encode(isa, func, inst);
+
+    let affinity = liveness.get(arg).expect("EBB argument has no live range").affinity;
+    liveness.create_dead(copy, inst, affinity);
+    liveness.extend_to_use(copy, branch, func, cfg);
+    copy
+}
+
+/// Assign an encoding to the new `copy` instruction `inst`.
+fn encode(isa: &TargetIsa, func: &mut Function, inst: Inst) {
+    match isa.encode(&func.dfg, &func.dfg[inst]) {
+        Ok(encoding) => *func.encodings.ensure(inst) = encoding,
+        Err(_) => {
+            panic!("Can't encode copy.{}",
+                   func.dfg.value_type(func.dfg.first_result(inst)))
+        }
+    }
+}
+
+/// Get the instruction defining `value`.
+fn def_inst(func: &Function, value: Value) -> Inst {
+    match func.dfg.value_def(value) {
+        ValueDef::Res(inst, _) => inst,
+        ValueDef::Arg(..) => panic!("{} is not an instruction result", value),
+    }
+}
+
+/// Get the value passed by `branch` as EBB argument number `idx`.
+fn branch_arg(func: &Function, branch: Inst, idx: usize) -> Value {
+    match func.dfg.analyze_branch(branch) {
+        BranchInfo::SingleDest(_, args) => args[idx],
+        _ => panic!("{} doesn't pass EBB arguments", func.dfg[branch].opcode()),
+    }
+}
+
+/// Get the EBB and program point where `value` is defined.
+fn def_point(func: &Function, value: Value) -> (Ebb, ProgramPoint) {
+    match func.dfg.value_def(value) {
+        ValueDef::Res(inst, _) => {
+            (func.layout.inst_ebb(inst).expect("Instruction not in layout"), inst.into())
+        }
+        ValueDef::Arg(ebb, _) => (ebb, ebb.into()),
+    }
+}
+
+/// Compare the definitions of `a` and `b` in the dominator tree preorder given by `preorder`.
+fn def_cmp(preorder: &EntityMap<Ebb, (u32, u32)>,
+           func: &Function,
+           a: Value,
+           b: Value)
+           -> Ordering {
+    let (ebb_a, pp_a) = def_point(func, a);
+    let (ebb_b, pp_b) = def_point(func, b);
+    preorder[ebb_a]
+        .0
+        .cmp(&preorder[ebb_b].0)
+        .then_with(|| func.layout.cmp(pp_a, pp_b))
+}
+
+/// Does the definition of `a` dominate the definition of `b`?
+fn def_dominates(preorder: &EntityMap<Ebb, (u32, u32)>,
+                 func: &Function,
+                 a: Value,
+                 b: Value)
+                 -> bool {
+    let (ebb_a, pp_a) = def_point(func, a);
+    let (ebb_b, pp_b) = def_point(func, b);
+    if ebb_a == ebb_b {
+        func.layout.cmp(pp_a, pp_b) != Ordering::Greater
+    } else {
+        let (first, last) = preorder[ebb_a];
+        let num = preorder[ebb_b].0;
+        first <= num && num <= last
+    }
+}
+
+/// Is `value` still live after the definition of `other`?
+///
+/// A value that is killed by the instruction defining `other` can share a register with `other`,
+/// so they don't interfere.
+fn live_after_def(func: &Function, liveness: &Liveness, value: Value, other: Value) -> bool {
+    match func.dfg.value_def(other) {
+        ValueDef::Res(inst, _) => {
+            // Instructions with results are never terminators, so there is a next instruction.
+            let ebb = func.layout.inst_ebb(inst).expect("Instruction not in layout");
+            let next = func.layout
+                .ebb_insts(ebb)
+                .skip_while(|&i| i != inst)
+                .nth(1)
+                .expect("Value defined by the last instruction in an EBB");
+            liveness.live_at(value, next, &func.layout)
+        }
+        ValueDef::Arg(ebb, _) => liveness.live_at(value, ebb, &func.layout),
+    }
+}
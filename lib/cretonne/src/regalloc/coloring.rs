@@ -9,13 +9,10 @@
 //! The coloring pass doesn't work on arbitrary code. Certain preconditions must be satisfied:
 //!
 //! 1. All instructions must be legalized and assigned an encoding. The encoding recipe guides the
-//!    register assignments and provides exact constraints.
+//!    register assignments and provides exact constraints. An instruction without an encoding is
+//!    reported as a `RegAllocError`.
 //!
-//! 2. Instructions with tied operands must be in a coloring-friendly state. Specifically, the
-//!    values used by the tied operands must be killed by the instruction. This can be achieved by
-//!    inserting a `copy` to a new value immediately before the two-address instruction.
-//!
-//! 3. The register pressure must be lowered sufficiently by inserting spill code. Register
+//! 2. The register pressure must be lowered sufficiently by inserting spill code. Register
 //!    operands are allowed to read spilled values, but each such instance must be counted as using
 //!    a register.
 //!
@@ -34,16 +31,67 @@
 //! There are many valid topological orders of the EBBs, and the specific order can affect which
 //! coloring hints are satisfied and which are broken.
 //!
+//! # Operand constraints
+//!
+//! The encoding recipe of every instruction constrains the locations of its fixed value operands
+//! and results. The result constraints are satisfied directly when a register is picked for each
+//! defined value. The operand constraints are satisfied indirectly: The affinity of a live range
+//! is the intersection of the register classes required by all its uses, and values are colored
+//! from their affinity when possible. The coloring pass checks that the operands of each
+//! instruction ended up in acceptable locations.
+//!
+//! Tied and fixed register operands are handled by inserting copies immediately before the
+//! instruction, but only when they are necessary:
+//!
+//! - A fixed register operand needs a copy when its value was assigned to another register. The
+//!   copy is assigned the fixed register. Registers used by fixed constraints in the function are
+//!   avoided when coloring other values, so the fixed register is normally available. When it
+//!   isn't, the value occupying it is temporarily diverted to another register with a `regmove`
+//!   instruction, and moved back after the instruction.
+//! - A result tied to an operand overwrites the operand's register, so the operand needs a copy
+//!   when its value is still live after the instruction.
+//!
+//! Only the registers returned by `TargetIsa::allocatable_registers()` are assigned to values.
+//!
+//! # ABI boundaries
+//!
+//! The legalized function signature and the signatures of called functions determine the
+//! registers that values are passed in:
+//!
+//! - The arguments to the entry block are pre-colored with the registers they arrive in.
+//! - The arguments passed to calls and the values returned by return instructions are treated
+//!   like fixed register operands, and they are copied into place when necessary.
+//! - The results of calls are assigned the registers they are returned in.
+//!
+//! To avoid most of the copies, the values that are passed across an ABI boundary are given a
+//! hint for the register they are needed in. The hints propagate backwards through tied operands,
+//! and a hinted register is preferred when the value is colored. Other values avoid the registers
+//! used by ABI boundaries when possible, just like the registers used by fixed constraints.
+//!
+//! # Congruence classes
+//!
+//! The values in a congruence class are related by EBB arguments. The coalescing pass merges them
+//! into virtual registers.
+//! When a value is colored, a register already assigned to another value in its class is preferred
+//! so the EBB arguments are already in the right registers when branching.
+//!
 
 use entity_map::EntityMap;
 use dominator_tree::DominatorTree;
-use ir::{Ebb, Inst, Value, Function, Cursor, ValueLoc, DataFlowGraph};
-use isa::{TargetIsa, RegInfo, Encoding, RecipeConstraints, ConstraintKind};
+use ir::{Ebb, Inst, Value, ValueDef, Function, Cursor, ValueLoc, InstBuilder, DataFlowGraph};
+use isa::{TargetIsa, RegInfo, RegClass, RegUnit, Encoding, RecipeConstraints, OperandConstraint,
+          ConstraintKind};
+use regalloc::abi;
 use regalloc::affinity::Affinity;
 use regalloc::allocatable_set::AllocatableSet;
+use regalloc::diversion::RegDiversions;
+use regalloc::error::RegAllocError;
 use regalloc::live_value_tracker::{LiveValue, LiveValueTracker};
 use regalloc::liveness::Liveness;
+use regalloc::virtregs::VirtRegs;
 use sparse_map::SparseSet;
+use std::string::ToString;
+use std::vec::Vec;
 
 
 /// Data structures for the coloring pass.
@@ -71,13 +119,27 @@ struct Context<'a> {
     reginfo: RegInfo,
     recipe_constraints: &'a [RecipeConstraints],
 
+    // The ISA is only used for encoding the rare copies we insert.
+    isa: &'a TargetIsa,
+
     // References to contextual data structures we need.
     domtree: &'a DominatorTree,
     liveness: &'a mut Liveness,
+    virtregs: &'a VirtRegs,
 
     // Pristine set of registers that the allocator can use.
     // This set remains immutable, we make clones.
     usable_regs: AllocatableSet,
+
+    // Registers used by fixed register constraints and ABI boundaries in the function. They
+    // should be avoided when there is a choice.
+    fixed_regs: &'a [RegUnit],
+
+    // Registers preferred by the values passed across ABI boundaries.
+    hints: &'a EntityMap<Value, Option<RegUnit>>,
+
+    // Values temporarily diverted to other registers in the current EBB.
+    divert: RegDiversions,
 }
 
 impl Coloring {
@@ -90,19 +152,31 @@ impl Coloring {
     }
 
     /// Run the coloring algorithm over `func`.
+    ///
+    /// Return an error if the constraints of an instruction can't be satisfied.
     pub fn run(&mut self,
                isa: &TargetIsa,
                func: &mut Function,
                domtree: &DominatorTree,
                liveness: &mut Liveness,
-               tracker: &mut LiveValueTracker) {
+               virtregs: &VirtRegs,
+               tracker: &mut LiveValueTracker)
+               -> Result<(), RegAllocError> {
+        // Forget the EBBs visited in the previous function.
+        self.visited.clear();
+        let fixed_regs = collect_fixed_regs(func, isa.recipe_constraints());
+        let hints = collect_abi_hints(func, isa.recipe_constraints());
         let mut ctx = Context {
             reginfo: isa.register_info(),
             recipe_constraints: isa.recipe_constraints(),
+            isa: isa,
             domtree: domtree,
             liveness: liveness,
-            // TODO: Ask the target ISA about reserved registers etc.
-            usable_regs: AllocatableSet::new(),
+            virtregs: virtregs,
+            usable_regs: isa.allocatable_registers(),
+            fixed_regs: &fixed_regs,
+            hints: &hints,
+            divert: RegDiversions::new(),
         };
         ctx.run(self, func, tracker)
     }
@@ -110,14 +184,19 @@ impl Coloring {
 
 impl<'a> Context<'a> {
     /// Run the coloring algorithm.
-    fn run(&mut self, data: &mut Coloring, func: &mut Function, tracker: &mut LiveValueTracker) {
+    fn run(&mut self,
+           data: &mut Coloring,
+           func: &mut Function,
+           tracker: &mut LiveValueTracker)
+           -> Result<(), RegAllocError> {
         // Just visit blocks in layout order, letting `process_ebb` enforce a topological ordering.
         // TODO: Once we have a loop tree, we could visit hot blocks first.
         let mut next = func.layout.entry_block();
         while let Some(ebb) = next {
-            self.process_ebb(ebb, data, func, tracker);
+            self.process_ebb(ebb, data, func, tracker)?;
             next = func.layout.next_ebb(ebb);
         }
+        Ok(())
     }
 
     /// Process `ebb`, but only after ensuring that the immediate dominator has been processed.
@@ -128,7 +207,8 @@ impl<'a> Context<'a> {
                    mut ebb: Ebb,
                    data: &mut Coloring,
                    func: &mut Function,
-                   tracker: &mut LiveValueTracker) {
+                   tracker: &mut LiveValueTracker)
+                   -> Result<(), RegAllocError> {
         // The stack is just a scratch space for this algorithm. We leave it empty when returning.
         assert!(data.stack.is_empty());
 
@@ -143,30 +223,40 @@ impl<'a> Context<'a> {
 
         // Pop off blocks in topological order.
         while let Some(ebb) = data.stack.pop() {
-            self.visit_ebb(ebb, func, tracker);
+            if let Err(e) = self.visit_ebb(ebb, func, tracker) {
+                data.stack.clear();
+                return Err(e);
+            }
         }
+        Ok(())
     }
 
     /// Visit `ebb`, assuming that the immediate dominator has already been visited.
-    fn visit_ebb(&mut self, ebb: Ebb, func: &mut Function, tracker: &mut LiveValueTracker) {
-        let mut regs = self.visit_ebb_header(ebb, func, tracker);
+    fn visit_ebb(&mut self,
+                 ebb: Ebb,
+                 func: &mut Function,
+                 tracker: &mut LiveValueTracker)
+                 -> Result<(), RegAllocError> {
+        let mut regs = self.visit_ebb_header(ebb, func, tracker)?;
+        self.divert.clear();
 
         // Now go through the instructions in `ebb` and color the values they define.
-        let mut pos = Cursor::new(&mut func.layout);
-        pos.goto_top(ebb);
-        while let Some(inst) = pos.next_inst() {
-            let encoding = func.encodings[inst];
-            assert!(encoding.is_legal(), "Illegal: {}", func.dfg[inst].opcode());
-            self.visit_inst(inst,
-                            encoding,
-                            &mut pos,
-                            &mut func.dfg,
-                            tracker,
-                            &mut regs,
-                            &mut func.locations);
+        // Copies may be inserted before the current instruction, so don't hold on to a cursor.
+        let mut next = func.layout.ebb_insts(ebb).next();
+        while let Some(inst) = next {
+            // An instruction the legalizer couldn't encode has no operand constraints to satisfy.
+            let encoding = func.encodings.get(inst).cloned().unwrap_or_default();
+            if !encoding.is_legal() {
+                let constraint = format!("{} encoding", func.dfg[inst].opcode());
+                return Err(RegAllocError::new(inst, constraint).with_live(tracker.live()));
+            }
+            self.visit_inst(inst, encoding, func, tracker, &mut regs)
+                .map_err(|e| e.with_live(tracker.live()))?;
             tracker.drop_dead(inst);
+            next = func.layout.next_inst(inst);
         }
-
+        assert!(self.divert.is_empty(), "Diversions left at the end of {}", ebb);
+        Ok(())
     }
 
     /// Visit the `ebb` header.
@@ -176,7 +266,7 @@ impl<'a> Context<'a> {
                         ebb: Ebb,
                         func: &mut Function,
                         tracker: &mut LiveValueTracker)
-                        -> AllocatableSet {
+                        -> Result<AllocatableSet, RegAllocError> {
         // Reposition the live value tracker and deal with the EBB arguments.
         let (liveins, args) =
             tracker.ebb_top(ebb, &func.dfg, self.liveness, &func.layout, self.domtree);
@@ -184,11 +274,14 @@ impl<'a> Context<'a> {
         // The live-ins have already been assigned a register. Reconstruct the allocatable set.
         let mut regs = self.livein_regs(liveins, func);
 
-        // TODO: Arguments to the entry block are pre-colored by the ABI. We should probably call
-        // a whole other function for that case.
-        self.color_args(args, &mut regs, &mut func.locations);
+        let colored = if func.layout.entry_block() == Some(ebb) {
+            self.color_entry_args(ebb, args, &mut regs, func)
+        } else {
+            self.color_args(ebb, args, &mut regs, &mut func.locations)
+        };
+        colored.map_err(|e| e.with_live(tracker.live()))?;
 
-        regs
+        Ok(regs)
     }
 
     /// Initialize a set of allocatable registers from the values that are live-in to a block.
@@ -221,19 +314,66 @@ impl<'a> Context<'a> {
     /// It is assumed that any live-in register values have already been taken out of the register
     /// set.
     fn color_args(&self,
+                  ebb: Ebb,
                   args: &[LiveValue],
                   regs: &mut AllocatableSet,
-                  locations: &mut EntityMap<Value, ValueLoc>) {
+                  locations: &mut EntityMap<Value, ValueLoc>)
+                  -> Result<(), RegAllocError> {
         for lv in args {
             // Only look at the register arguments.
             if let Affinity::Reg(rc_index) = lv.affinity {
                 let regclass = self.reginfo.rc(rc_index);
                 // TODO: Fall back to a top-level super-class. Sub-classes are only hints.
-                let regunit = regs.iter(regclass).next().expect("Out of registers for arguments");
+                let regunit = match self.pick_reg(lv.value, regclass, regs, locations) {
+                    Some(regunit) => regunit,
+                    None => return Err(RegAllocError::new(ebb, regclass.name.to_string())),
+                };
                 regs.take(regclass, regunit);
                 *locations.ensure(lv.value) = ValueLoc::Reg(regunit);
             }
         }
+        Ok(())
+    }
+
+    /// Color the live arguments to the entry block.
+    ///
+    /// The arguments passed in registers are pre-colored with the registers assigned by the
+    /// legalized function signature. The other arguments are colored like normal EBB arguments.
+    fn color_entry_args(&self,
+                        ebb: Ebb,
+                        args: &[LiveValue],
+                        regs: &mut AllocatableSet,
+                        func: &mut Function)
+                        -> Result<(), RegAllocError> {
+        let mut others = Vec::new();
+
+        for lv in args {
+            if let Affinity::Reg(rc_index) = lv.affinity {
+                let abi_reg = match func.dfg.value_def(lv.value) {
+                    ValueDef::Arg(_, num) => {
+                        func.signature.argument_types.get(num).and_then(|abi| abi.location.reg())
+                    }
+                    ValueDef::Res(..) => panic!("{} is not an entry block argument", lv.value),
+                };
+                match abi_reg {
+                    Some(regunit) => {
+                        regs.take(self.abi_regclass(regunit), regunit);
+                        *func.locations.ensure(lv.value) = ValueLoc::Reg(regunit);
+                    }
+                    None => others.push((lv.value, self.reginfo.rc(rc_index))),
+                }
+            }
+        }
+
+        for (value, regclass) in others {
+            let regunit = match self.pick_reg(value, regclass, regs, &func.locations) {
+                Some(regunit) => regunit,
+                None => return Err(RegAllocError::new(ebb, regclass.name.to_string())),
+            };
+            regs.take(regclass, regunit);
+            *func.locations.ensure(value) = ValueLoc::Reg(regunit);
+        }
+        Ok(())
     }
 
     /// Color the values defined by `inst` and insert any necessary shuffle code to satisfy
@@ -241,35 +381,62 @@ impl<'a> Context<'a> {
     ///
     /// Update `regs` to reflect the allocated registers after `inst`, including removing any dead
     /// or killed values from the set.
-    fn visit_inst(&self,
+    ///
+    /// The live values of a returned error are left for the caller to fill in.
+    fn visit_inst(&mut self,
                   inst: Inst,
                   encoding: Encoding,
-                  _pos: &mut Cursor,
-                  dfg: &mut DataFlowGraph,
+                  func: &mut Function,
                   tracker: &mut LiveValueTracker,
-                  regs: &mut AllocatableSet,
-                  locations: &mut EntityMap<Value, ValueLoc>) {
-        // First update the live value tracker with this instruction.
-        // Get lists of values that are killed and defined by `inst`.
-        let (kills, defs) = tracker.process_inst(inst, dfg, self.liveness);
-
+                  regs: &mut AllocatableSet)
+                  -> Result<(), RegAllocError> {
         // Get the operand constraints for `inst` that we are trying to satisfy.
         let constraints = self.recipe_constraints[encoding.recipe()].clone();
+        let fixed = self.fixed_operands(inst, &constraints, func);
 
-        // Get rid of the killed values.
+        // Move other values out of the fixed registers needed by the operands.
+        self.divert_fixed_operands(inst, &fixed, tracker.live(), func, regs)?;
+
+        // Update the live value tracker with this instruction.
+        // Get lists of values that are killed and defined by `inst`.
+        let (kills, defs) = tracker.process_inst(inst, &func.dfg, self.liveness);
+
+        // Copy tied and fixed register operands into place. The copies are killed by `inst`.
+        let copies = self.constrain_operands(inst, &constraints, &fixed, kills, func, regs)?;
+
+        // The fixed value operands must now be in acceptable locations.
+        for (&arg, opcst) in func.dfg.inst_args(inst)[0].iter().zip(constraints.ins) {
+            self.check_operand(inst, arg, opcst, &func.locations);
+        }
+        for &(idx, _, regunit) in &fixed {
+            let arg = inst_arg(&func.dfg, inst, idx);
+            assert!(self.divert.location(arg, &func.locations) == ValueLoc::Reg(regunit),
+                    "{} operand {} is not in {}",
+                    inst,
+                    arg,
+                    self.reginfo.display_regunit(regunit));
+        }
+
+        // Get rid of the killed values. Diverted values don't need to be moved back.
         for lv in kills {
             if let Affinity::Reg(rc_index) = lv.affinity {
                 let regclass = self.reginfo.rc(rc_index);
-                if let ValueLoc::Reg(regunit) = locations[lv.value] {
+                if let ValueLoc::Reg(regunit) = self.divert.location(lv.value, &func.locations) {
                     regs.free(regclass, regunit);
                 }
+                self.divert.remove(lv.value);
             }
         }
+        for &(_, regclass, regunit) in &copies {
+            regs.free(regclass, regunit);
+        }
+        let divert = &self.divert;
+        let locations = &mut func.locations;
+        let abi_results = abi::results(&func.dfg, inst);
 
         // Process the defined values with fixed constraints.
-        // TODO: Handle constraints on call return values.
         assert_eq!(defs.len(),
-                   constraints.outs.len(),
+                   constraints.outs.len() + abi_results.len(),
                    "Can't handle variable results");
         for (lv, opcst) in defs.iter().zip(constraints.outs) {
             match lv.affinity {
@@ -290,35 +457,83 @@ impl<'a> Context<'a> {
                                     opcst.regclass.name);
                             // Try to grab a register from the preferred class, but fall back to
                             // the actual constraint if we have to.
-                            let regunit = regs.iter(pref_rc)
-                                .next()
-                                .or_else(|| regs.iter(opcst.regclass).next())
-                                .expect("Ran out of registers");
+                            let regunit = match self.pick_reg(lv.value, pref_rc, regs, locations)
+                                      .or_else(|| self.free_reg(opcst.regclass, regs)) {
+                                Some(regunit) => regunit,
+                                None => {
+                                    return Err(RegAllocError::new(inst,
+                                                                  opcst.regclass
+                                                                      .name
+                                                                      .to_string()))
+                                }
+                            };
                             regs.take(opcst.regclass, regunit);
                             *locations.ensure(lv.value) = ValueLoc::Reg(regunit);
                         }
                         ConstraintKind::Tied(arg_index) => {
                             // This def must use the same register as a fixed instruction argument.
-                            let loc = locations[dfg[inst].arguments()[0][arg_index as usize]];
+                            let arg = func.dfg.inst_args(inst)[0][arg_index as usize];
+                            let loc = divert.location(arg, locations);
                             *locations.ensure(lv.value) = loc;
                             // Mark the reused register. It's not really clear if we support tied
                             // stack operands. We could do that for some Intel read-modify-write
                             // encodings.
                             if let ValueLoc::Reg(regunit) = loc {
-                                // This is going to assert out unless the incoming value at
-                                // `arg_index` was killed. Tied operands must be fixed to
-                                // ensure that before running the coloring pass.
+                                // The incoming value at `arg_index` was killed, or it was
+                                // replaced by a copy.
                                 regs.take(opcst.regclass, regunit);
                             }
                         }
-                        ConstraintKind::FixedReg(_regunit) => unimplemented!(),
+                        ConstraintKind::FixedReg(regunit) => {
+                            // TODO: Divert the value occupying the fixed register. It can't be
+                            // moved back until the result dies.
+                            if !regs.is_avail(opcst.regclass, regunit) {
+                                return Err(self.fixed_reg_error(inst, regunit));
+                            }
+                            regs.take(opcst.regclass, regunit);
+                            *locations.ensure(lv.value) = ValueLoc::Reg(regunit);
+                        }
                         ConstraintKind::Stack => {
                             panic!("{}:{} should be a stack value", lv.value, pref_rc.name)
                         }
                     }
                 }
-                Affinity::Stack => unimplemented!(),
-                Affinity::Any => unimplemented!(),
+                // Stack values get their spill slot assigned by the spilling pass.
+                Affinity::Stack => {
+                    assert!(opcst.kind == ConstraintKind::Stack,
+                            "{} is a stack value, but {} defines it in a register",
+                            lv.value,
+                            func.dfg[inst].opcode());
+                }
+                Affinity::Any => panic!("{} has no affinity", lv.value),
+            }
+        }
+
+        // The results of a call appear in the registers given by the callee's signature.
+        for (lv, abi) in defs[constraints.outs.len()..].iter().zip(abi_results) {
+            if let Affinity::Reg(rc_index) = lv.affinity {
+                let (regclass, regunit) = match abi.location.reg() {
+                    Some(regunit) => {
+                        // TODO: Divert the value occupying the return register.
+                        let regclass = self.abi_regclass(regunit);
+                        if !regs.is_avail(regclass, regunit) {
+                            return Err(self.fixed_reg_error(inst, regunit));
+                        }
+                        (regclass, regunit)
+                    }
+                    // TODO: Results returned on the stack.
+                    None => {
+                        let regclass = self.reginfo.rc(rc_index);
+                        match self.pick_reg(lv.value, regclass, regs, locations) {
+                            Some(regunit) => (regclass, regunit),
+                            None => {
+                                return Err(RegAllocError::new(inst, regclass.name.to_string()))
+                            }
+                        }
+                    }
+                };
+                regs.take(regclass, regunit);
+                *locations.ensure(lv.value) = ValueLoc::Reg(regunit);
             }
         }
 
@@ -333,5 +548,406 @@ impl<'a> Context<'a> {
                 }
             }
         }
+
+        // Move the diverted values back to their own registers.
+        self.undivert(inst, func, regs);
+        Ok(())
+    }
+
+    /// Get the value operands of `inst` that must be in fixed registers.
+    ///
+    /// These are the operands with a fixed register constraint in the encoding recipe, and the
+    /// arguments to calls and return instructions that the ABI passes in registers. Each operand
+    /// is identified by its index into the fixed and variable arguments of `inst`, and it comes
+    /// with the register class and the register it must be in.
+    fn fixed_operands(&self,
+                      inst: Inst,
+                      constraints: &RecipeConstraints,
+                      func: &Function)
+                      -> Vec<(usize, RegClass, RegUnit)> {
+        let mut fixed = Vec::new();
+        for (idx, opcst) in constraints.ins.iter().enumerate() {
+            if let ConstraintKind::FixedReg(regunit) = opcst.kind {
+                fixed.push((idx, opcst.regclass, regunit));
+            }
+        }
+
+        let args = func.dfg.inst_args(inst);
+        let abi_args = abi::arguments(func, inst);
+        for (idx, abi) in abi_args.iter().take(args[1].len()).enumerate() {
+            if let Some(regunit) = abi.location.reg() {
+                fixed.push((args[0].len() + idx, self.abi_regclass(regunit), regunit));
+            }
+        }
+        fixed
+    }
+
+    /// Divert the live values occupying registers needed by the `fixed` operands of `inst`.
+    ///
+    /// The `live` values are the values live before `inst`, and the diverted values are moved to
+    /// other available registers with `regmove` instructions inserted before `inst`.
+    fn divert_fixed_operands(&mut self,
+                             inst: Inst,
+                             fixed: &[(usize, RegClass, RegUnit)],
+                             live: &[LiveValue],
+                             func: &mut Function,
+                             regs: &mut AllocatableSet)
+                             -> Result<(), RegAllocError> {
+        for &(idx, regclass, regunit) in fixed {
+            let arg = inst_arg(&func.dfg, inst, idx);
+            if self.divert.location(arg, &func.locations) == ValueLoc::Reg(regunit) ||
+               regs.is_avail(regclass, regunit) {
+                continue;
+            }
+
+            // Find the value that is occupying `regunit`.
+            let occupant = live.iter()
+                .filter_map(|lv| match lv.affinity {
+                                Affinity::Reg(rci) => Some((lv.value, self.reginfo.rc(rci))),
+                                _ => None,
+                            })
+                .find(|&(v, _)| {
+                          self.divert.location(v, &func.locations) == ValueLoc::Reg(regunit)
+                      });
+            let (value, rc) = match occupant {
+                Some(occupant) => occupant,
+                None => return Err(self.fixed_reg_error(inst, regunit)),
+            };
+            // The occupant needs another register from its own class.
+            let to = match self.free_reg(rc, regs) {
+                Some(to) => to,
+                None => return Err(RegAllocError::new(inst, rc.name.to_string())),
+            };
+            self.insert_regmove(inst, value, regunit, to, func);
+            regs.free(rc, regunit);
+            regs.take(rc, to);
+        }
+        Ok(())
+    }
+
+    /// Move the values that are still diverted after `inst` back to their original registers.
+    fn undivert(&mut self, inst: Inst, func: &mut Function, regs: &mut AllocatableSet) {
+        if self.divert.is_empty() {
+            return;
+        }
+        let opcode = func.dfg[inst].opcode();
+        // Nothing runs after leaving the function, so the values don't need to be moved back.
+        if opcode.is_return() {
+            self.divert.clear();
+            return;
+        }
+        // TODO: Diversions around branches must be undone on every outgoing edge.
+        assert!(!opcode.is_branch() && !opcode.is_terminator(),
+                "Can't move diverted values back after {}",
+                opcode);
+        let before = match func.layout.next_inst(inst) {
+            Some(next) => next,
+            None => panic!("{} must be followed by a terminator", inst),
+        };
+        for d in self.divert.all().to_vec() {
+            let rc = match self.liveness.get(d.value).map(|lr| lr.affinity) {
+                Some(Affinity::Reg(rci)) => self.reginfo.rc(rci),
+                _ => panic!("Diverted {} has no register affinity", d.value),
+            };
+            assert!(regs.is_avail(rc, d.from),
+                    "Can't move {} back to {}, it is used by another value",
+                    d.value,
+                    self.reginfo.display_regunit(d.from));
+            self.insert_regmove(before, d.value, d.to, d.from, func);
+            regs.free(rc, d.to);
+            regs.take(rc, d.from);
+        }
+    }
+
+    /// Insert a `regmove` instruction before `inst` moving `value` from `from` to `to`, and record
+    /// the diversion.
+    fn insert_regmove(&mut self,
+                      inst: Inst,
+                      value: Value,
+                      from: RegUnit,
+                      to: RegUnit,
+                      func: &mut Function) {
+        let regmove = {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_inst(inst);
+            func.dfg.ins(&mut pos).regmove(value, from, to)
+        };
+        match self.isa.encode(&func.dfg, &func.dfg[regmove]) {
+            Ok(encoding) => *func.encodings.ensure(regmove) = encoding,
+            Err(_) => panic!("Can't encode regmove.{}", func.dfg.value_type(value)),
+        }
+        self.divert.regmove(value, from, to);
+    }
+
+    /// Insert copies before `inst` for the tied and `fixed` register operands that need them.
+    ///
+    /// Returns the copies along with their assigned registers. They are all killed by `inst`.
+    fn constrain_operands(&mut self,
+                          inst: Inst,
+                          constraints: &RecipeConstraints,
+                          fixed: &[(usize, RegClass, RegUnit)],
+                          kills: &[LiveValue],
+                          func: &mut Function,
+                          regs: &mut AllocatableSet)
+                          -> Result<Vec<(Value, RegClass, RegUnit)>, RegAllocError> {
+        let mut copies = Vec::new();
+
+        // A value in the wrong register is copied to the fixed register.
+        for &(idx, regclass, regunit) in fixed {
+            let arg = inst_arg(&func.dfg, inst, idx);
+            if self.divert.location(arg, &func.locations) == ValueLoc::Reg(regunit) {
+                continue;
+            }
+            // Any other value in the fixed register was diverted by `divert_fixed_operands`.
+            assert!(regs.is_avail(regclass, regunit),
+                    "{} operand {} needs {}, which is used by another value",
+                    inst,
+                    arg,
+                    self.reginfo.display_regunit(regunit));
+            let copy = self.insert_copy(inst, idx, regclass, regunit, func);
+            regs.take(regclass, regunit);
+            copies.push((copy, regclass, regunit));
+        }
+
+        // A value that is live after `inst` can't be overwritten by a tied result.
+        for opcst in constraints.outs {
+            if let ConstraintKind::Tied(idx) = opcst.kind {
+                let idx = idx as usize;
+                let arg = func.dfg.inst_args(inst)[0][idx];
+                if kills.iter().any(|lv| lv.value == arg) ||
+                   copies.iter().any(|&(copy, _, _)| copy == arg) {
+                    continue;
+                }
+                let regunit = match self.free_reg(opcst.regclass, regs) {
+                    Some(regunit) => regunit,
+                    None => return Err(RegAllocError::new(inst, opcst.regclass.name.to_string())),
+                };
+                let copy = self.insert_copy(inst, idx, opcst.regclass, regunit, func);
+                regs.take(opcst.regclass, regunit);
+                copies.push((copy, opcst.regclass, regunit));
+            }
+        }
+
+        Ok(copies)
+    }
+
+    /// Create an error for `inst` needing the fixed register `regunit`, which is occupied.
+    fn fixed_reg_error(&self, inst: Inst, regunit: RegUnit) -> RegAllocError {
+        RegAllocError::new(inst, self.reginfo.display_regunit(regunit).to_string())
+    }
+
+    /// Insert a copy of the operand `idx` of `inst` immediately before `inst`, and make `inst` use
+    /// the copy instead. The copy is assigned to `regunit`.
+    ///
+    /// The operand index counts the fixed arguments of `inst` followed by the variable arguments.
+    fn insert_copy(&mut self,
+                   inst: Inst,
+                   idx: usize,
+                   rc: RegClass,
+                   regunit: RegUnit,
+                   func: &mut Function)
+                   -> Value {
+        let arg = inst_arg(&func.dfg, inst, idx);
+        let copy = {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_inst(inst);
+            func.dfg.ins(&mut pos).copy(arg)
+        };
+        set_inst_arg(&mut func.dfg, inst, idx, copy);
+        let copy_inst = match func.dfg.value_def(copy) {
+            ValueDef::Res(copy_inst, _) => copy_inst,
+            ValueDef::Arg(..) => panic!("{} is not an instruction result", copy),
+        };
+        match self.isa.encode(&func.dfg, &func.dfg[copy_inst]) {
+            Ok(encoding) => *func.encodings.ensure(copy_inst) = encoding,
+            Err(_) => panic!("Can't encode copy.{}", func.dfg.value_type(copy)),
+        }
+        *func.locations.ensure(copy) = ValueLoc::Reg(regunit);
+
+        // The copy is only live from its definition to `inst`.
+        let ebb = func.layout.inst_ebb(inst).expect("Instruction not in layout");
+        self.liveness.create_dead(copy, copy_inst, Affinity::Reg(rc.into()));
+        self.liveness
+            .get_mut(copy)
+            .expect("Copy has no live range")
+            .extend_in_ebb(ebb, inst, &func.layout);
+        copy
+    }
+
+    /// Pick an available register in `rc` for `value`.
+    ///
+    /// A register assigned to another value in the congruence class of `value` is preferred,
+    /// followed by the register hinted by an ABI boundary.
+    fn pick_reg(&self,
+                value: Value,
+                rc: RegClass,
+                regs: &AllocatableSet,
+                locations: &EntityMap<Value, ValueLoc>)
+                -> Option<RegUnit> {
+        self.congruent_reg(value, rc, regs, locations)
+            .or_else(|| self.hinted_reg(value, rc, regs))
+            .or_else(|| self.free_reg(rc, regs))
+    }
+
+    /// Get the register hinted for `value` if it is in `rc` and available.
+    fn hinted_reg(&self, value: Value, rc: RegClass, regs: &AllocatableSet) -> Option<RegUnit> {
+        match self.hints.get(value) {
+            Some(&Some(regunit)) if rc.contains(regunit) && regs.is_avail(rc, regunit) => {
+                Some(regunit)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the top-level register class containing the ABI register `regunit`.
+    fn abi_regclass(&self, regunit: RegUnit) -> RegClass {
+        self.reginfo
+            .toprc_containing_regunit(regunit)
+            .expect("ABI register is not in a register class")
+    }
+
+    /// Pick an available register in `rc`, avoiding the registers used by fixed constraints when
+    /// possible.
+    fn free_reg(&self, rc: RegClass, regs: &AllocatableSet) -> Option<RegUnit> {
+        regs.iter(rc)
+            .find(|regunit| !self.fixed_regs.contains(regunit))
+            .or_else(|| regs.iter(rc).next())
+    }
+
+    /// Find a register in `rc` that is available in `regs` and already assigned to another value
+    /// in the congruence class of `value`.
+    fn congruent_reg(&self,
+                     value: Value,
+                     rc: RegClass,
+                     regs: &AllocatableSet,
+                     locations: &EntityMap<Value, ValueLoc>)
+                     -> Option<RegUnit> {
+        self.virtregs
+            .congruence_class(value)
+            .iter()
+            .filter_map(|&v| match locations.get(v) {
+                            Some(&ValueLoc::Reg(regunit)) => Some(regunit),
+                            _ => None,
+                        })
+            .find(|&regunit| rc.contains(regunit) && regs.is_avail(rc, regunit))
+    }
+
+    /// Check that the location of the operand `arg` of `inst` satisfies the constraint `opcst`.
+    fn check_operand(&self,
+                     inst: Inst,
+                     arg: Value,
+                     opcst: &OperandConstraint,
+                     locations: &EntityMap<Value, ValueLoc>) {
+        let loc = self.divert.location(arg, locations);
+        let ok = match (opcst.kind, loc) {
+            (ConstraintKind::Reg, ValueLoc::Reg(regunit)) => opcst.regclass.contains(regunit),
+            (ConstraintKind::FixedReg(fixed), ValueLoc::Reg(regunit)) => fixed == regunit,
+            (ConstraintKind::Stack, ValueLoc::Stack(_)) => true,
+            _ => false,
+        };
+        assert!(ok,
+                "{} operand {} is in {}, which doesn't satisfy the {} constraint",
+                inst,
+                arg,
+                loc.display(&self.reginfo),
+                opcst.regclass.name);
+    }
+}
+
+/// Collect the registers used by fixed register constraints and ABI boundaries in `func`.
+fn collect_fixed_regs(func: &Function, recipe_constraints: &[RecipeConstraints]) -> Vec<RegUnit> {
+    let mut fixed_regs = Vec::new();
+    {
+        let mut add = |regunit| if !fixed_regs.contains(&regunit) {
+            fixed_regs.push(regunit);
+        };
+        let sig = &func.signature;
+        for abi in sig.argument_types.iter().chain(&sig.return_types) {
+            abi.location.reg().map(&mut add);
+        }
+        for ebb in &func.layout {
+            for inst in func.layout.ebb_insts(ebb) {
+                for abi in abi::arguments(func, inst).iter().chain(abi::results(&func.dfg, inst)) {
+                    abi.location.reg().map(&mut add);
+                }
+                let encoding = func.encodings[inst];
+                if !encoding.is_legal() {
+                    continue;
+                }
+                let constraints = &recipe_constraints[encoding.recipe()];
+                for opcst in constraints.ins.iter().chain(constraints.outs) {
+                    if let ConstraintKind::FixedReg(regunit) = opcst.kind {
+                        add(regunit);
+                    }
+                }
+            }
+        }
+    }
+    fixed_regs
+}
+
+/// Collect the register hints for the values in `func` that are passed across ABI boundaries.
+///
+/// The arguments to calls and return instructions are hinted with the register the ABI passes them
+/// in. The hints are propagated backwards to the operands tied to hinted results, since those
+/// values end up in the same register.
+fn collect_abi_hints(func: &Function,
+                     recipe_constraints: &[RecipeConstraints])
+                     -> EntityMap<Value, Option<RegUnit>> {
+    let mut hints = EntityMap::new();
+    let mut insts = Vec::new();
+    for ebb in &func.layout {
+        insts.extend(func.layout.ebb_insts(ebb));
+    }
+
+    for &inst in insts.iter().rev() {
+        let args = func.dfg.inst_args(inst);
+        for (&arg, abi) in args[1].iter().zip(abi::arguments(func, inst)) {
+            if let Some(regunit) = abi.location.reg() {
+                let hint: &mut Option<RegUnit> = hints.ensure(arg);
+                if hint.is_none() {
+                    *hint = Some(regunit);
+                }
+            }
+        }
+
+        let encoding = func.encodings.get(inst).cloned().unwrap_or_default();
+        if !encoding.is_legal() {
+            continue;
+        }
+        let constraints = &recipe_constraints[encoding.recipe()];
+        for (res, opcst) in func.dfg.inst_results(inst).zip(constraints.outs) {
+            if let ConstraintKind::Tied(idx) = opcst.kind {
+                if let Some(&Some(regunit)) = hints.get(res) {
+                    let hint: &mut Option<RegUnit> = hints.ensure(args[0][idx as usize]);
+                    if hint.is_none() {
+                        *hint = Some(regunit);
+                    }
+                }
+            }
+        }
+    }
+    hints
+}
+
+/// Get the value operand `idx` of `inst`, counting the fixed arguments followed by the variable
+/// arguments.
+fn inst_arg(dfg: &DataFlowGraph, inst: Inst, idx: usize) -> Value {
+    let args = dfg.inst_args(inst);
+    if idx < args[0].len() {
+        args[0][idx]
+    } else {
+        args[1][idx - args[0].len()]
+    }
+}
+
+/// Replace the value operand `idx` of `inst` with `value`.
+fn set_inst_arg(dfg: &mut DataFlowGraph, inst: Inst, idx: usize, value: Value) {
+    let args = dfg.inst_args_mut(inst);
+    let num_fixed = args[0].len();
+    if idx < num_fixed {
+        args[0][idx] = value;
+    } else {
+        args[1][idx - num_fixed] = value;
     }
 }
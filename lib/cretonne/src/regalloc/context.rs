@@ -4,19 +4,35 @@
 //! the register allocator algorithm. This doesn't preserve any data between functions, but it
 //! avoids allocating data structures independently for each function begin compiled.
 
+use cancel::CancellationToken;
 use dominator_tree::DominatorTree;
-use ir::Function;
+use ir::{Function, Opcode};
+use mem_usage::MemUsage;
+use regalloc::coalescing::Coalescing;
 use regalloc::coloring::Coloring;
+use regalloc::dead_spills::DeadSpills;
+use regalloc::rematerialize::Rematerializer;
+use regalloc::safepoints::emit_safepoints;
+use regalloc::spilling::Spilling;
+use regalloc::stack_coloring::StackColoring;
 use regalloc::live_value_tracker::LiveValueTracker;
 use regalloc::liveness::Liveness;
+use regalloc::virtregs::VirtRegs;
 use isa::TargetIsa;
 use cfg::ControlFlowGraph;
+use result::CtonResult;
+use timing;
 
 /// Persistent memory allocations for register allocation.
 pub struct Context {
     liveness: Liveness,
     tracker: LiveValueTracker,
+    coalescing: Coalescing,
+    spilling: Spilling,
+    remat: Rematerializer,
     coloring: Coloring,
+    dead_spills: DeadSpills,
+    stack_coloring: StackColoring,
 }
 
 impl Context {
@@ -28,30 +44,135 @@ impl Context {
         Context {
             liveness: Liveness::new(),
             tracker: LiveValueTracker::new(),
+            coalescing: Coalescing::new(),
+            spilling: Spilling::new(),
+            remat: Rematerializer::new(),
             coloring: Coloring::new(),
+            dead_spills: DeadSpills::new(),
+            stack_coloring: StackColoring::new(),
         }
     }
 
+    /// Clear all data structures in this register allocation context.
+    ///
+    /// The liveness analysis and virtual registers of the last function are discarded. The
+    /// allocated memory is kept for the next function.
+    pub fn clear(&mut self) {
+        self.liveness.clear();
+        self.tracker.clear();
+        self.coalescing.clear();
+    }
+
+    /// Get the memory used by the liveness analysis and the live value tracker, which are the
+    /// largest data structures of the register allocator.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.liveness.mem_usage() + self.tracker.mem_usage()
+    }
+
+    /// Get the liveness analysis computed by the last call to `run`.
+    pub fn liveness(&self) -> &Liveness {
+        &self.liveness
+    }
+
+    /// Get the virtual registers computed by the last call to `run`.
+    pub fn virtregs(&self) -> &VirtRegs {
+        self.coalescing.virtregs()
+    }
+
     /// Allocate registers in `func`.
     ///
     /// After register allocation, all values in `func` have been assigned to a register or stack
     /// location that is consistent with instruction encoding constraints.
+    ///
+    /// The `cancel` token is checked between the phases of the algorithm. If the allocation is
+    /// cancelled, `func` is left in an inconsistent state.
+    ///
+    /// If the constraints of an instruction can't be satisfied, a `CtonError::RegAlloc` error
+    /// naming the instruction is returned, and `func` is also left in an inconsistent state.
     pub fn run(&mut self,
                isa: &TargetIsa,
                func: &mut Function,
                cfg: &ControlFlowGraph,
-               domtree: &DominatorTree) {
+               domtree: &DominatorTree,
+               cancel: &CancellationToken)
+               -> CtonResult {
         // `Liveness` and `Coloring` are self-clearing.
         // Tracker state (dominator live sets) is actually reused between the spilling and coloring
         // phases.
         self.tracker.clear();
 
+        // The register allocator assigns locations to the original values, so instructions must
+        // not use value aliases.
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                func.dfg.resolve_aliases_in_arguments(inst);
+            }
+        }
+
         // First pass: Liveness analysis.
         self.liveness.compute(isa, func, cfg);
+        cancel.check()?;
 
-        // TODO: Second pass: Spilling.
+        // Convert to conventional SSA form by inserting copies of interfering EBB arguments.
+        self.coalescing.run(isa, func, cfg, domtree, &mut self.liveness);
+        cancel.check()?;
+
+        // Second pass: Spilling.
+        let spills = self.spilling
+            .run(isa, func, cfg, domtree, &mut self.liveness, &mut self.tracker)?;
+        if spills > 0 {
+            // The spiller changed the code, so the live ranges and the live sets saved by the
+            // tracker are out of date.
+            self.liveness.compute(isa, func, cfg);
+            self.tracker.clear();
+        }
+        cancel.check()?;
+
+        // Recompute constants instead of reloading them. This changes the live ranges of the
+        // spilled values.
+        let remats = self.remat.run(isa, func);
+        if remats > 0 {
+            self.liveness.compute(isa, func, cfg);
+            timing::add_count(timing::Counter::ValuesRematerialized, remats as u64);
+        }
 
         // Third pass: Reload and coloring.
-        self.coloring.run(isa, func, domtree, &mut self.liveness, &mut self.tracker);
+        self.coloring.run(isa,
+                          func,
+                          domtree,
+                          &mut self.liveness,
+                          self.coalescing.virtregs(),
+                          &mut self.tracker)?;
+        cancel.check()?;
+
+        // Fourth pass: Record the live references at calls and safepoints.
+        emit_safepoints(isa, func, &self.liveness);
+
+        // Fifth pass: Remove spills that are never filled. The live ranges of the spilled values
+        // end at the removed spills, so they are out of date.
+        if self.dead_spills.run(func) > 0 {
+            self.liveness.compute(isa, func, cfg);
+        }
+
+        // Sixth pass: Share spill slots between values that don't interfere.
+        self.stack_coloring.run(func, &self.liveness);
+
+        if timing::is_enabled() {
+            timing::add_count(timing::Counter::ValuesSpilled, count_spills(func));
+        }
+        Ok(())
+    }
+}
+
+/// Count the `spill` instructions in `func`.
+fn count_spills(func: &Function) -> u64 {
+    let mut count = 0;
+    for ebb in &func.layout {
+        for inst in func.layout.ebb_insts(ebb) {
+            if func.dfg[inst].opcode() == Opcode::Spill {
+                count += 1;
+            }
+        }
     }
+    count
 }
@@ -0,0 +1,135 @@
+//! Dead spill elimination.
+//!
+//! After register allocation, some `spill` instructions may turn out to be useless. This happens
+//! when the spilled value is never read back with a `fill` or used directly from its stack slot,
+//! for example because the value was rematerialized or all its reloads were cleaned up.
+//!
+//! This late pass removes such dead spills from the layout. The result values of removed spills
+//! get their locations reset to `ValueLoc::Unassigned`, so the stack slots they were assigned to
+//! don't need to be reserved in the stack frame unless other values still use them.
+
+use entity_map::EntityMap;
+use ir::{Function, Cursor, Opcode, Value, ValueLoc};
+
+/// Scratch space for the dead spill elimination pass.
+///
+/// These data structures can be reused between invocations.
+pub struct DeadSpills {
+    /// Values used by at least one instruction in the layout.
+    used: EntityMap<Value, bool>,
+}
+
+impl DeadSpills {
+    /// Allocate scratch space for dead spill elimination.
+    pub fn new() -> DeadSpills {
+        DeadSpills { used: EntityMap::new() }
+    }
+
+    /// Remove all the `spill` instructions in `func` whose result is never used.
+    ///
+    /// Return the number of spill instructions removed.
+    pub fn run(&mut self, func: &mut Function) -> usize {
+        self.used.clear();
+        self.compute_uses(func);
+
+        let mut removed = 0;
+        let mut pos = Cursor::new(&mut func.layout);
+        while let Some(_ebb) = pos.next_ebb() {
+            let mut next = pos.next_inst();
+            while let Some(inst) = next {
+                if func.dfg[inst].opcode() == Opcode::Spill {
+                    let value = func.dfg.first_result(inst);
+                    if !self.is_used(value) {
+                        assert_eq!(pos.remove_inst(), inst);
+                        if func.locations.is_valid(value) {
+                            func.locations[value] = ValueLoc::Unassigned;
+                        }
+                        removed += 1;
+                        next = pos.current_inst();
+                        continue;
+                    }
+                }
+                next = pos.next_inst();
+            }
+        }
+        removed
+    }
+
+    /// Mark all the values used as arguments by instructions in the layout.
+    ///
+    /// Value aliases are resolved so uses of an alias count as uses of the original value.
+    fn compute_uses(&mut self, func: &Function) {
+        let used = &mut self.used;
+        for ebb in &func.layout {
+            for inst in func.layout.ebb_insts(ebb) {
+                func.dfg[inst].each_arg(&func.dfg.value_lists, |arg| {
+                    *used.ensure(func.dfg.resolve_aliases(arg)) = true;
+                });
+            }
+        }
+    }
+
+    /// Is `value` used by any instruction?
+    fn is_used(&self, value: Value) -> bool {
+        self.used.get(value).cloned().unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, InstBuilder, StackSlotData, StackSlotKind, ValueLoc,
+             VariableArgs};
+    use ir::types;
+    use super::DeadSpills;
+
+    #[test]
+    fn remove_dead_spills() {
+        let mut func = Function::new();
+        let ss0 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 4));
+        let ss1 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 4));
+        let ebb0 = func.dfg.make_ebb();
+        let (v1, s1, s2, s3, s4, f, ret);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            v1 = dfg.ins(pos).iconst(types::I32, 3);
+            // Dead spill in the middle of the EBB.
+            s1 = dfg.ins(pos).spill(v1);
+            // Live spill, filled below.
+            s2 = dfg.ins(pos).spill(v1);
+            // Two consecutive dead spills.
+            s3 = dfg.ins(pos).spill(v1);
+            s4 = dfg.ins(pos).spill(v1);
+            f = dfg.ins(pos).fill(s2);
+            let mut rvals = VariableArgs::new();
+            rvals.push(f);
+            ret = dfg.ins(pos).return_(rvals);
+        }
+        *func.locations.ensure(s1) = ValueLoc::Stack(ss0);
+        *func.locations.ensure(s2) = ValueLoc::Stack(ss1);
+
+        let mut dead_spills = DeadSpills::new();
+        assert_eq!(dead_spills.run(&mut func), 3);
+
+        let insts: Vec<_> = func.layout.ebb_insts(ebb0).collect();
+        assert_eq!(insts.len(), 4);
+        let results: Vec<_> = insts[0..3].iter().map(|&i| func.dfg.first_result(i)).collect();
+        assert_eq!(results, [v1, s2, f]);
+        assert_eq!(insts[3], ret);
+        for &v in &[s1, s3, s4] {
+            assert!(!results.contains(&v));
+        }
+        assert!(match func.locations[s1] {
+                    ValueLoc::Unassigned => true,
+                    _ => false,
+                });
+        assert!(match func.locations[s2] {
+                    ValueLoc::Stack(ss) => ss == ss1,
+                    _ => false,
+                });
+
+        // Running the pass again finds nothing to remove.
+        assert_eq!(dead_spills.run(&mut func), 0);
+    }
+}
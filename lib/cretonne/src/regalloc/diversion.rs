@@ -0,0 +1,155 @@
+//! Register diversions.
+//!
+//! Normally, a value is assigned to a single register or stack location by the register allocator.
+//! Sometimes, it is necessary to move register values to a different register in order to satisfy
+//! instruction constraints.
+//!
+//! These register diversions are local to an EBB. No values can be diverted when entering a new
+//! EBB.
+
+use entity_map::EntityMap;
+use ir::{Value, ValueLoc, InstructionData};
+use isa::RegUnit;
+use std::vec::Vec;
+
+/// A diversion of a value from its original register location to a new register.
+///
+/// In IL, a diversion is represented by a `regmove` instruction, possibly a chain of them for the
+/// same value.
+///
+/// When tracking diversions, the `from` field is the original assigned value location, and `to` is
+/// the current one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Diversion {
+    /// The value that is diverted.
+    pub value: Value,
+    /// The original register value location.
+    pub from: RegUnit,
+    /// The current register value location.
+    pub to: RegUnit,
+}
+
+/// Keep track of the diversions in an EBB.
+///
+/// The register allocator uses this to know where values are while it inserts `regmove`
+/// instructions, and code emission uses it to find the current register of a value.
+pub struct RegDiversions {
+    current: Vec<Diversion>,
+}
+
+impl RegDiversions {
+    /// Create a new empty diversion tracker.
+    pub fn new() -> RegDiversions {
+        RegDiversions { current: Vec::new() }
+    }
+
+    /// Clear the tracker, preparing for a new EBB.
+    pub fn clear(&mut self) {
+        self.current.clear()
+    }
+
+    /// Are there any diversions?
+    pub fn is_empty(&self) -> bool {
+        self.current.is_empty()
+    }
+
+    /// Get the current diversion of `value`, if any.
+    pub fn diversion(&self, value: Value) -> Option<&Diversion> {
+        self.current.iter().find(|d| d.value == value)
+    }
+
+    /// Get all current diversions.
+    pub fn all(&self) -> &[Diversion] {
+        self.current.as_slice()
+    }
+
+    /// Get the current location of `value`. Fall back to the assignment map for values that are
+    /// not diverted.
+    pub fn location(&self, value: Value, locations: &EntityMap<Value, ValueLoc>) -> ValueLoc {
+        match self.diversion(value) {
+            Some(d) => ValueLoc::Reg(d.to),
+            None => locations.get(value).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Record the effect of a `regmove` instruction moving `value` from the `from` register to the
+    /// `to` register.
+    ///
+    /// A diversion back to the original register cancels the diversion.
+    pub fn regmove(&mut self, value: Value, from: RegUnit, to: RegUnit) {
+        if let Some(i) = self.current.iter().position(|d| d.value == value) {
+            assert_eq!(self.current[i].to, from, "Bad regmove chain for {}", value);
+            if self.current[i].from != to {
+                self.current[i].to = to;
+            } else {
+                self.current.swap_remove(i);
+            }
+        } else {
+            self.current.push(Diversion {
+                                  value: value,
+                                  from: from,
+                                  to: to,
+                              });
+        }
+    }
+
+    /// Apply the effect of `inst` to the diversions, if it is a `regmove` instruction.
+    pub fn apply(&mut self, inst: &InstructionData) {
+        if let InstructionData::RegMove { arg, src, dst, .. } = *inst {
+            self.regmove(arg, src, dst);
+        }
+    }
+
+    /// Drop the diversion of `value`, if any.
+    ///
+    /// This is used when a diverted value is killed, and it doesn't need to be moved back to its
+    /// original register. Returns the register the value was diverted to.
+    pub fn remove(&mut self, value: Value) -> Option<RegUnit> {
+        self.current
+            .iter()
+            .position(|d| d.value == value)
+            .map(|i| self.current.swap_remove(i).to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity_map::{EntityMap, EntityRef};
+    use ir::{Value, ValueLoc};
+
+    #[test]
+    fn inserts() {
+        let mut divs = RegDiversions::new();
+        let v1 = Value::new(1);
+        let v2 = Value::new(2);
+        let mut locations = EntityMap::new();
+        *locations.ensure(v1) = ValueLoc::Reg(10);
+        *locations.ensure(v2) = ValueLoc::Reg(11);
+
+        divs.regmove(v1, 10, 12);
+        assert_eq!(divs.diversion(v1),
+                   Some(&Diversion {
+                             value: v1,
+                             from: 10,
+                             to: 12,
+                         }));
+        assert_eq!(divs.diversion(v2), None);
+        assert_eq!(divs.location(v1, &locations), ValueLoc::Reg(12));
+        assert_eq!(divs.location(v2, &locations), ValueLoc::Reg(11));
+
+        // Chained moves keep the original register.
+        divs.regmove(v1, 12, 11);
+        assert_eq!(divs.diversion(v1).unwrap().from, 10);
+        assert_eq!(divs.diversion(v1).unwrap().to, 11);
+
+        // Moving back to the original register cancels the diversion.
+        divs.regmove(v1, 11, 10);
+        assert!(divs.is_empty());
+
+        divs.regmove(v2, 11, 13);
+        assert_eq!(divs.remove(v2), Some(13));
+        assert_eq!(divs.remove(v2), None);
+        assert!(divs.is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+//! Register allocation errors.
+//!
+//! The spilling pass guarantees that the coloring pass doesn't run out of registers, but only when
+//! the instruction constraints can be satisfied at all. An instruction whose operands need more
+//! registers than the ISA has, or a fixed register constraint that conflicts with another one,
+//! can't be allocated. The allocator reports these cases as a `RegAllocError` instead of
+//! panicking.
+
+use ir::Value;
+use ir::entities::AnyEntity;
+use regalloc::affinity::Affinity;
+use regalloc::live_value_tracker::LiveValue;
+use std::fmt;
+use std::string::String;
+use std::vec::Vec;
+
+/// A register allocation failure.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RegAllocError {
+    /// The instruction or EBB that couldn't be allocated.
+    pub location: AnyEntity,
+
+    /// The constraint that couldn't be satisfied. This is the name of a register class or a fixed
+    /// register.
+    pub constraint: String,
+
+    /// The values that were live in registers at `location`.
+    pub live: Vec<Value>,
+}
+
+impl RegAllocError {
+    /// Create an error for `location` with no live values yet.
+    pub fn new<L: Into<AnyEntity>>(location: L, constraint: String) -> RegAllocError {
+        RegAllocError {
+            location: location.into(),
+            constraint: constraint,
+            live: Vec::new(),
+        }
+    }
+
+    /// Record the values in `live` that have a register affinity as the live values.
+    pub fn with_live(mut self, live: &[LiveValue]) -> RegAllocError {
+        self.live = live.iter()
+            .filter(|lv| match lv.affinity {
+                        Affinity::Reg(_) => true,
+                        _ => false,
+                    })
+            .map(|lv| lv.value)
+            .collect();
+        self
+    }
+}
+
+impl fmt::Display for RegAllocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}: can't satisfy the {} constraint; live values:",
+               self.location,
+               self.constraint)?;
+        if self.live.is_empty() {
+            return write!(f, " none");
+        }
+        for (i, value) in self.live.iter().enumerate() {
+            write!(f, "{} {}", if i == 0 { "" } else { "," }, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity_map::EntityRef;
+    use ir::{Inst, Value};
+
+    #[test]
+    fn display() {
+        let mut e = RegAllocError::new(Inst::new(3), "GPR".to_string());
+        assert_eq!(e.to_string(), "inst3: can't satisfy the GPR constraint; live values: none");
+        e.live = vec![Value::direct_with_number(1).unwrap(),
+                      Value::table_with_number(4).unwrap()];
+        assert_eq!(e.to_string(),
+                   "inst3: can't satisfy the GPR constraint; live values: v1, vx4");
+    }
+}
@@ -8,11 +8,13 @@ use dominator_tree::DominatorTree;
 use entity_list::{EntityList, ListPool};
 use ir::instructions::BranchInfo;
 use ir::{Inst, Ebb, Value, DataFlowGraph, ProgramOrder, ExpandedProgramPoint};
+use mem_usage::MemUsage;
 use partition_slice::partition_slice;
 use regalloc::affinity::Affinity;
 use regalloc::liveness::Liveness;
 
 use std::collections::HashMap;
+use std::vec::Vec;
 
 type ValueList = EntityList<Value>;
 
@@ -125,6 +127,11 @@ impl LiveValueTracker {
         self.idom_pool.clear();
     }
 
+    /// Get the memory used by the set of live values and the pool of immediate dominator sets.
+    pub fn mem_usage(&self) -> MemUsage {
+        MemUsage::of_vec(&self.live.values) + self.idom_pool.mem_usage()
+    }
+
     /// Get the set of currently live values.
     ///
     /// Between calls to `process_inst()` and `drop_dead()`, this includes both values killed and
@@ -217,7 +224,7 @@ impl LiveValueTracker {
                         -> (&[LiveValue], &[LiveValue]) {
         // Save a copy of the live values before any branches or jumps that could be somebody's
         // immediate dominator.
-        match dfg[inst].analyze_branch() {
+        match dfg.analyze_branch(inst) {
             BranchInfo::NotABranch => {}
             _ => self.save_idom_live_set(inst),
         }
@@ -26,6 +26,11 @@
 //! number of live values at every program point and insert spill code until the number of
 //! registers needed is small enough.
 //!
+//! The passes that run after the liveness analysis insert new instructions, and they must keep the
+//! live ranges up to date as they go. New values get a live range with `create_dead`, and
+//! `extend_to_use` extends an existing live range to reach a new use. The analysis doesn't have to
+//! be recomputed for these changes.
+//!
 //!
 //! # Alternative algorithms
 //!
@@ -177,11 +182,15 @@
 
 use cfg::ControlFlowGraph;
 use ir::dfg::ValueDef;
-use ir::{Function, Value, Inst, Ebb};
-use isa::{TargetIsa, RecipeConstraints};
+use ir::{Function, Value, Inst, Ebb, Layout, ProgramPoint, ExpandedProgramPoint};
+use isa::{TargetIsa, RegInfo, RecipeConstraints};
+use mem_usage::MemUsage;
+use regalloc::abi;
 use regalloc::liverange::LiveRange;
 use regalloc::affinity::Affinity;
 use sparse_map::SparseMap;
+use std::slice;
+use std::vec::Vec;
 
 /// A set of live ranges, indexed by value number.
 type LiveRangeSet = SparseMap<Value, LiveRange>;
@@ -191,7 +200,8 @@ type LiveRangeSet = SparseMap<Value, LiveRange>;
 fn get_or_create<'a>(lrset: &'a mut LiveRangeSet,
                      value: Value,
                      func: &Function,
-                     recipe_constraints: &[RecipeConstraints])
+                     recipe_constraints: &[RecipeConstraints],
+                     reg_info: &RegInfo)
                      -> &'a mut LiveRange {
     // It would be better to use `get_mut()` here, but that leads to borrow checker fighting
     // which can probably only be resolved by non-lexical lifetimes.
@@ -204,17 +214,35 @@ fn get_or_create<'a>(lrset: &'a mut LiveRangeSet,
             ValueDef::Res(inst, rnum) => {
                 def = inst.into();
                 // Initialize the affinity from the defining instruction's result constraints.
-                // Don't do this for call return values which are always tied to a single register.
-                affinity = recipe_constraints.get(func.encodings[inst].recipe())
-                    .and_then(|rc| rc.outs.get(rnum))
-                    .map(Affinity::new)
-                    .unwrap_or_default();
+                // Call return values get the register they are returned in.
+                let outs = recipe_constraints.get(func.encodings[inst].recipe())
+                    .map(|rc| rc.outs)
+                    .unwrap_or(&[]);
+                affinity = match outs.get(rnum) {
+                    Some(constraint) => Affinity::new(constraint),
+                    None => {
+                        abi::results(&func.dfg, inst)
+                            .get(rnum - outs.len())
+                            .and_then(|abi| abi::constraint(abi, reg_info))
+                            .map(|constraint| Affinity::new(&constraint))
+                            .unwrap_or_default()
+                    }
+                };
             }
-            ValueDef::Arg(ebb, _) => {
+            ValueDef::Arg(ebb, num) => {
                 def = ebb.into();
-                // Don't apply any affinity to EBB arguments.
-                // They could be in a register or on the stack.
-                affinity = Default::default();
+                // Don't apply any affinity to EBB arguments, except for the entry block arguments
+                // passed in registers. The others could be in a register or on the stack.
+                affinity = if func.layout.entry_block() == Some(ebb) {
+                    func.signature
+                        .argument_types
+                        .get(num)
+                        .and_then(|abi| abi::constraint(abi, reg_info))
+                        .map(|constraint| Affinity::new(&constraint))
+                        .unwrap_or_default()
+                } else {
+                    Default::default()
+                };
             }
         };
         lrset.insert(LiveRange::new(value, def, affinity));
@@ -284,11 +312,79 @@ impl Liveness {
         }
     }
 
+    /// Clear all data structures in this liveness analysis.
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+        self.worklist.clear();
+    }
+
+    /// Get the memory used by the live ranges.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.ranges.mem_usage() + MemUsage::of_vec(&self.worklist)
+    }
+
     /// Get the live range for `value`, if it exists.
     pub fn get(&self, value: Value) -> Option<&LiveRange> {
         self.ranges.get(value)
     }
 
+    /// Get a mutable reference to the live range for `value`, if it exists.
+    pub fn get_mut(&mut self, value: Value) -> Option<&mut LiveRange> {
+        self.ranges.get_mut(value)
+    }
+
+    /// Iterate over all the live ranges that have been computed, in no particular order.
+    pub fn iter(&self) -> slice::Iter<LiveRange> {
+        self.ranges.values()
+    }
+
+    /// Is `value` live at the program point `pp`?
+    ///
+    /// Values without a live range are never live.
+    pub fn live_at<PP: Into<ProgramPoint>>(&self, value: Value, pp: PP, layout: &Layout) -> bool {
+        let pp = pp.into();
+        let ebb = match pp.into() {
+            ExpandedProgramPoint::Inst(inst) => {
+                layout.inst_ebb(inst).expect("Instruction not in layout")
+            }
+            ExpandedProgramPoint::Ebb(ebb) => ebb,
+        };
+        self.get(value).map_or(false, |lr| lr.is_live_at(ebb, pp, layout))
+    }
+
+    /// Create a new live range for `value` which is defined at `def` and has no uses yet.
+    ///
+    /// This is used for values defined by instructions inserted after the liveness analysis was
+    /// computed.
+    pub fn create_dead<PP>(&mut self, value: Value, def: PP, affinity: Affinity)
+        where PP: Into<ProgramPoint>
+    {
+        let old = self.ranges.insert(LiveRange::new(value, def.into(), affinity));
+        assert!(old.is_none(), "{} already has a live range", value);
+    }
+
+    /// Extend the live range of `value` so it reaches the instruction `user` which uses it.
+    ///
+    /// If `user` is in another EBB, the value becomes live-in to the EBBs on the paths from the
+    /// existing live range to `user`, so the control flow graph must be up to date.
+    pub fn extend_to_use(&mut self,
+                         value: Value,
+                         user: Inst,
+                         func: &Function,
+                         cfg: &ControlFlowGraph) {
+        let ebb = func.layout.inst_ebb(user).expect("Instruction not in layout");
+        let lr = self.ranges.get_mut(value).expect("Value has no live range");
+        extend_to_use(lr, ebb, user, &mut self.worklist, func, cfg);
+    }
+
+    /// Remove the live range of `value`, if any.
+    ///
+    /// This is used when all the uses and the definition of `value` have been removed from the
+    /// function.
+    pub fn remove(&mut self, value: Value) -> Option<LiveRange> {
+        self.ranges.remove(value)
+    }
+
     /// Compute the live ranges of all SSA values used in `func`.
     /// This clears out any existing analysis stored in this data structure.
     pub fn compute(&mut self, isa: &TargetIsa, func: &Function, cfg: &ControlFlowGraph) {
@@ -301,14 +397,18 @@ impl Liveness {
         // The liveness computation needs to visit all uses, but the order doesn't matter.
         // TODO: Perhaps this traversal of the function could be combined with a dead code
         // elimination pass if we visit a post-order of the dominator tree?
-        // TODO: Resolve value aliases while we're visiting instructions?
         for ebb in func.layout.ebbs() {
+            // Make sure we have created live ranges for dead EBB arguments.
+            for arg in func.dfg.ebb_args(ebb) {
+                get_or_create(&mut self.ranges, arg, func, recipe_constraints, &reg_info);
+            }
+
             for inst in func.layout.ebb_insts(ebb) {
                 // Make sure we have created live ranges for dead defs.
                 // TODO: When we implement DCE, we can use the absence of a live range to indicate
                 // an unused value.
                 for def in func.dfg.inst_results(inst) {
-                    get_or_create(&mut self.ranges, def, func, recipe_constraints);
+                    get_or_create(&mut self.ranges, def, func, recipe_constraints, &reg_info);
                 }
 
                 // The instruction encoding is used to compute affinities.
@@ -317,24 +417,88 @@ impl Liveness {
                 // TODO: Should we fail here if the instruction doesn't have a valid encoding?
                 let mut operand_constraints =
                     recipe_constraints.get(recipe).map(|c| c.ins).unwrap_or(&[]).iter();
+                // ABI descriptions of the variable arguments to calls and returns.
+                let num_fixed = func.dfg.inst_args(inst)[0].len();
+                let mut abi_arguments = abi::arguments(func, inst).iter();
+                let mut num_args = 0;
 
-                func.dfg[inst].each_arg(|arg| {
+                func.dfg[inst].each_arg(&func.dfg.value_lists, |arg| {
                     // Get the live range, create it as a dead range if necessary.
-                    let lr = get_or_create(&mut self.ranges, arg, func, recipe_constraints);
+                    let lr =
+                        get_or_create(&mut self.ranges, arg, func, recipe_constraints, &reg_info);
 
                     // Extend the live range to reach this use.
                     extend_to_use(lr, ebb, inst, &mut self.worklist, func, cfg);
 
-                    // Apply operand constraint, ignoring any variable arguments after the fixed
-                    // operands described by `operand_constraints`. Variable arguments are either
-                    // EBB arguments or call/return ABI arguments. EBB arguments need to be
-                    // resolved by the coloring algorithm, and ABI constraints require specific
-                    // registers or stack slots which the affinities don't model anyway.
+                    // Apply operand constraint. The variable arguments after the fixed operands
+                    // described by `operand_constraints` are either EBB arguments or call/return
+                    // ABI arguments. EBB arguments need to be resolved by the coloring algorithm,
+                    // and ABI arguments passed in registers are constrained to those registers.
                     if let Some(constraint) = operand_constraints.next() {
                         lr.affinity.merge(constraint, &reg_info);
+                    } else if num_args >= num_fixed {
+                        let constraint = abi_arguments
+                            .next()
+                            .and_then(|abi| abi::constraint(abi, &reg_info));
+                        if let Some(constraint) = constraint {
+                            lr.affinity.merge(&constraint, &reg_info);
+                        }
                     }
+                    num_args += 1;
                 });
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cfg::ControlFlowGraph;
+    use ir::{Function, Cursor, InstBuilder, VariableArgs};
+    use ir::types;
+    use isa;
+    use settings;
+    use super::Liveness;
+
+    #[test]
+    fn incremental() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v1 = dfg.ins(pos).iadd(v0, v0);
+            let mut args = VariableArgs::new();
+            args.push(v1);
+            dfg.ins(pos).return_(args);
+        }
+        let insts: Vec<_> = func.layout.ebb_insts(ebb0).collect();
+        let (add, ret) = (insts[0], insts[1]);
+        // Unencoded instructions don't constrain the affinities.
+        func.encodings.resize(func.dfg.num_insts());
+        let cfg = ControlFlowGraph::with_function(&func);
+        let mut liveness = Liveness::new();
+        liveness.compute(&*isa, &func, &cfg);
+        assert!(liveness.live_at(v0, ebb0, &func.layout));
+        assert!(liveness.live_at(v0, add, &func.layout));
+        assert!(!liveness.live_at(v0, ret, &func.layout));
+
+        // Insert a new use of `v0` before the return.
+        let v2;
+        {
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.goto_inst(ret);
+            v2 = func.dfg.ins(pos).copy(v0);
+        }
+        let copy = func.layout.ebb_insts(ebb0).nth(1).unwrap();
+        liveness.create_dead(v2, copy, Default::default());
+        liveness.extend_to_use(v0, copy, &func, &cfg);
+        assert!(liveness.live_at(v0, copy, &func.layout));
+        assert!(!liveness.live_at(v0, ret, &func.layout));
+        assert!(liveness.live_at(v2, copy, &func.layout));
+        assert!(!liveness.live_at(v2, ret, &func.layout));
+    }
+}
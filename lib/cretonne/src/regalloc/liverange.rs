@@ -108,6 +108,7 @@
 //!
 
 use std::cmp::Ordering;
+use std::vec::Vec;
 use ir::{Inst, Ebb, Value, ProgramPoint, ProgramOrder};
 use regalloc::affinity::Affinity;
 use sparse_map::SparseMapValue;
@@ -365,6 +366,56 @@ impl LiveRange {
     pub fn livein_local_end<PO: ProgramOrder>(&self, ebb: Ebb, order: &PO) -> Option<Inst> {
         self.find_ebb_interval(ebb, order).ok().map(|n| self.liveins[n].end)
     }
+
+    /// Is this live range live at the program point `pp` which belongs to `ebb`?
+    ///
+    /// A value is live at the program point where it is defined and at its last use in each EBB.
+    pub fn is_live_at<PO: ProgramOrder>(&self, ebb: Ebb, pp: ProgramPoint, order: &PO) -> bool {
+        if order.cmp(pp, self.def_begin) != Ordering::Less &&
+           order.cmp(pp, self.def_end) != Ordering::Greater {
+            return true;
+        }
+        match self.livein_local_end(ebb, order) {
+            Some(end) => order.cmp(pp, end) != Ordering::Greater,
+            None => false,
+        }
+    }
+
+    /// Get the number of local intervals in this live range, including the def interval.
+    fn num_intervals(&self) -> usize {
+        1 + self.liveins.len()
+    }
+
+    /// Get the begin and end points of local interval `n`.
+    ///
+    /// The def interval is number 0, followed by the live-in intervals in program order.
+    fn interval(&self, n: usize) -> (ProgramPoint, ProgramPoint) {
+        if n == 0 {
+            (self.def_begin, self.def_end)
+        } else {
+            let li = self.liveins[n - 1];
+            (li.begin.into(), li.end.into())
+        }
+    }
+
+    /// Does this live range interfere with `other`?
+    ///
+    /// Two live ranges interfere if any of their intervals overlap, using the rules for interval
+    /// end points described in the [module documentation](index.html#register-interference).
+    pub fn overlaps<PO: ProgramOrder>(&self, other: &LiveRange, order: &PO) -> bool {
+        for i in 0..self.num_intervals() {
+            let (a_begin, a_end) = self.interval(i);
+            for j in 0..other.num_intervals() {
+                let (b_begin, b_end) = other.interval(j);
+                if a_begin == b_begin ||
+                   (order.cmp(a_begin, b_end) == Ordering::Less &&
+                    order.cmp(b_begin, a_end) == Ordering::Less) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
 }
 
 /// Allow a `LiveRange` to be stored in a `SparseMap` indexed by values.
@@ -615,5 +666,77 @@ mod tests {
         assert_eq!(lr.liveins[0].end, i41);
     }
 
+    #[test]
+    fn overlaps() {
+        let v0 = Value::new(0);
+        let v1 = Value::new(1);
+        let e10 = Ebb::new(10);
+        let i11 = Inst::new(11);
+        let i12 = Inst::new(12);
+        let i13 = Inst::new(13);
+        let e20 = Ebb::new(20);
+        let i21 = Inst::new(21);
+        let i22 = Inst::new(22);
+
+        // i11-i12 and i12-i13 don't interfere.
+        let mut lr0 = LiveRange::new(v0, i11.into(), Default::default());
+        lr0.extend_in_ebb(e10, i12, PO);
+        let mut lr1 = LiveRange::new(v1, i12.into(), Default::default());
+        lr1.extend_in_ebb(e10, i13, PO);
+        assert!(!lr0.overlaps(&lr1, PO));
+        assert!(!lr1.overlaps(&lr0, PO));
+
+        // i11-i13 and i12-i12 do interfere.
+        lr0.extend_in_ebb(e10, i13, PO);
+        let lr2 = LiveRange::new(v1, i12.into(), Default::default());
+        assert!(lr0.overlaps(&lr2, PO));
+        assert!(lr2.overlaps(&lr0, PO));
+
+        // i12-i13 and i12-i12 do interfere.
+        assert!(lr1.overlaps(&lr2, PO));
+
+        // Live-in intervals are also considered.
+        let lr3 = LiveRange::new(v1, i22.into(), Default::default());
+        assert!(!lr0.overlaps(&lr3, PO));
+        lr0.extend_in_ebb(e20, i21, PO);
+        assert!(!lr0.overlaps(&lr3, PO));
+        lr0.extend_in_ebb(e20, i22, PO);
+        assert!(!lr0.overlaps(&lr3, PO));
+        let mut lr4 = LiveRange::new(v1, i21.into(), Default::default());
+        lr4.extend_in_ebb(e20, i22, PO);
+        assert!(lr0.overlaps(&lr4, PO));
+        assert!(lr4.overlaps(&lr0, PO));
+    }
+
+    #[test]
+    fn live_at() {
+        let v0 = Value::new(0);
+        let e10 = Ebb::new(10);
+        let i11 = Inst::new(11);
+        let i12 = Inst::new(12);
+        let i13 = Inst::new(13);
+        let e20 = Ebb::new(20);
+        let i21 = Inst::new(21);
+        let e30 = Ebb::new(30);
+        let i31 = Inst::new(31);
+        let mut lr = LiveRange::new(v0, i12.into(), Default::default());
+        assert!(!lr.is_live_at(e10, i11.into(), PO));
+        assert!(lr.is_live_at(e10, i12.into(), PO));
+        assert!(!lr.is_live_at(e10, i13.into(), PO));
+
+        lr.extend_in_ebb(e10, i13, PO);
+        lr.extend_in_ebb(e30, i31, PO);
+        assert!(lr.is_live_at(e10, i13.into(), PO));
+        assert!(!lr.is_live_at(e20, e20.into(), PO));
+        assert!(!lr.is_live_at(e20, i21.into(), PO));
+        assert!(lr.is_live_at(e30, e30.into(), PO));
+        assert!(lr.is_live_at(e30, i31.into(), PO));
+
+        // Coalesced live-in intervals.
+        lr.extend_in_ebb(e20, i21, PO);
+        assert!(lr.is_live_at(e20, i21.into(), PO));
+        assert!(lr.is_live_at(e30, i31.into(), PO));
+    }
+
     // TODO: Add more tests that exercise the binary search algorithm.
 }
@@ -6,9 +6,22 @@ pub mod liverange;
 pub mod liveness;
 pub mod allocatable_set;
 pub mod live_value_tracker;
+pub mod coalescing;
 pub mod coloring;
+pub mod diversion;
+pub mod dead_spills;
+pub mod spilling;
+pub mod rematerialize;
+pub mod safepoints;
+pub mod stack_coloring;
+pub mod affinity;
+pub mod pressure;
+pub mod abi;
+pub mod virtregs;
 
-mod affinity;
 mod context;
+mod error;
 
+pub use self::allocatable_set::AllocatableSet;
 pub use self::context::Context;
+pub use self::error::RegAllocError;
@@ -0,0 +1,212 @@
+//! Register pressure tracking.
+//!
+//! SSA-based register allocation depends on a spilling phase that lowers the register pressure
+//! sufficiently to guarantee that the coloring phase can assign a register to every value. The
+//! `Pressure` data structure counts the number of registers in use in each register class so the
+//! spiller can tell when too many values want a register.
+//!
+//! Register classes can alias each other. A subclass like the Intel `ABCD` registers is also part
+//! of the larger `GPR` class, so a value that needs a register from the subclass is counted against
+//! the subclass and all of its superclasses. This guarantees that the limits of both classes are
+//! respected.
+//!
+//! A value that only needs a `GPR` register may still end up in an `ABCD` register, so the count
+//! for a subclass is a lower bound on the number of its registers in use.
+
+use isa::{RegInfo, RegClass, RegClassIndex};
+use isa::registers::RegClassData;
+use regalloc::AllocatableSet;
+use std::fmt;
+use std::vec::Vec;
+
+/// Number of registers in use and available in a single register class.
+#[derive(Clone, Copy)]
+struct ClassPressure {
+    /// Number of values in this class or one of its subclasses.
+    count: usize,
+
+    /// Number of allocatable registers in the class.
+    limit: usize,
+}
+
+/// Register pressure tracker.
+///
+/// The tracker keeps a count of registers in use for every register class in the ISA.
+pub struct Pressure {
+    /// All the register classes, ordered topologically.
+    classes: &'static [RegClassData],
+
+    /// Pressure per register class, indexed by class index.
+    pressure: Vec<ClassPressure>,
+}
+
+impl Pressure {
+    /// Create a new register pressure tracker for the register classes in `reginfo`.
+    ///
+    /// The limit of each register class is the number of registers in the class that are available
+    /// in `usable`.
+    pub fn new(reginfo: &RegInfo, usable: &AllocatableSet) -> Pressure {
+        Pressure {
+            classes: reginfo.classes,
+            pressure: reginfo
+                .classes
+                .iter()
+                .map(|rc| {
+                         ClassPressure {
+                             count: 0,
+                             limit: usable.iter(rc).count(),
+                         }
+                     })
+                .collect(),
+        }
+    }
+
+    /// Get the number of registers in use in `rc`, including registers used by values that need a
+    /// register from a subclass of `rc`.
+    pub fn count(&self, rc: RegClass) -> usize {
+        self.pressure[rc.index as usize].count
+    }
+
+    /// Get the number of allocatable registers in `rc`.
+    pub fn limit(&self, rc: RegClass) -> usize {
+        self.pressure[rc.index as usize].limit
+    }
+
+    /// Can another register be taken from `rc` without exceeding the limit of `rc` or any of its
+    /// superclasses?
+    pub fn can_take(&self, rc: RegClass) -> bool {
+        self.classes
+            .iter()
+            .zip(&self.pressure)
+            .filter(|&(super_rc, _)| super_rc.has_subclass(rc))
+            .all(|(_, p)| p.count < p.limit)
+    }
+
+    /// Take a register from `rc`.
+    ///
+    /// This is allowed to exceed the limits of the register classes. Use `overcommitted()` to
+    /// find out.
+    pub fn take(&mut self, rc: RegClass) {
+        for (super_rc, p) in self.classes.iter().zip(&mut self.pressure) {
+            if super_rc.has_subclass(rc) {
+                p.count += 1;
+            }
+        }
+    }
+
+    /// Free a register previously taken from `rc`.
+    pub fn free(&mut self, rc: RegClass) {
+        for (super_rc, p) in self.classes.iter().zip(&mut self.pressure) {
+            if super_rc.has_subclass(rc) {
+                assert!(p.count > 0, "No {} registers in use", super_rc.name);
+                p.count -= 1;
+            }
+        }
+    }
+
+    /// Reset the counts of all register classes to 0.
+    pub fn reset(&mut self) {
+        for p in &mut self.pressure {
+            p.count = 0;
+        }
+    }
+
+    /// Get a register class that has more registers in use than it has available, if any.
+    ///
+    /// Superclasses are reported before their subclasses.
+    pub fn overcommitted(&self) -> Option<RegClassIndex> {
+        self.classes
+            .iter()
+            .zip(&self.pressure)
+            .find(|&(_, p)| p.count > p.limit)
+            .map(|(rc, _)| rc.into())
+    }
+}
+
+impl fmt::Display for Pressure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (rc, p) in self.classes.iter().zip(&self.pressure) {
+            write!(f, " {}:{}/{}", rc.name, p.count, p.limit)?;
+            if p.count > p.limit {
+                write!(f, "!")?;
+            }
+        }
+        write!(f, " ]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use isa::RegInfo;
+    use isa::registers::RegClassData;
+    use regalloc::AllocatableSet;
+    use super::Pressure;
+
+    // A GPR class with 8 registers and a subclass with the first 4.
+    static CLASSES: [RegClassData; 2] = [RegClassData {
+                                             name: "GPR",
+                                             index: 0,
+                                             bank: 0,
+                                             toprc: 0,
+                                             width: 1,
+                                             first: 0,
+                                             subclasses: 0b11,
+                                             mask: [0xff, 0, 0],
+                                         },
+                                         RegClassData {
+                                             name: "ABCD",
+                                             index: 1,
+                                             bank: 0,
+                                             toprc: 0,
+                                             width: 1,
+                                             first: 0,
+                                             subclasses: 0b10,
+                                             mask: [0x0f, 0, 0],
+                                         }];
+
+    #[test]
+    fn subclasses() {
+        let reginfo = RegInfo {
+            banks: &[],
+            classes: &CLASSES,
+        };
+        let gpr = &CLASSES[0];
+        let abcd = &CLASSES[1];
+        let mut usable = AllocatableSet::new();
+        usable.take(gpr, 7);
+        let mut pressure = Pressure::new(&reginfo, &usable);
+        assert_eq!(pressure.limit(gpr), 7);
+        assert_eq!(pressure.limit(abcd), 4);
+
+        // Values in the subclass also use registers from the superclass.
+        for _ in 0..4 {
+            assert!(pressure.can_take(abcd));
+            pressure.take(abcd);
+        }
+        assert_eq!(pressure.count(gpr), 4);
+        assert_eq!(pressure.count(abcd), 4);
+        assert!(!pressure.can_take(abcd));
+        assert!(pressure.can_take(gpr));
+        assert_eq!(pressure.overcommitted(), None);
+
+        pressure.take(abcd);
+        assert_eq!(pressure.overcommitted(), Some(abcd.into()));
+        assert_eq!(pressure.to_string(), "[ GPR:5/7 ABCD:5/4! ]");
+        pressure.free(abcd);
+
+        // Values in the superclass don't use registers from the subclass.
+        for _ in 0..3 {
+            pressure.take(gpr);
+        }
+        assert_eq!(pressure.count(abcd), 4);
+        assert!(!pressure.can_take(gpr));
+        assert!(!pressure.can_take(abcd));
+        pressure.take(gpr);
+        assert_eq!(pressure.overcommitted(), Some(gpr.into()));
+
+        pressure.reset();
+        assert_eq!(pressure.count(gpr), 0);
+        assert_eq!(pressure.overcommitted(), None);
+    }
+}
@@ -0,0 +1,153 @@
+//! Constant rematerialization.
+//!
+//! Values that are cheap to compute from scratch don't need to be reloaded from a spill slot.
+//! Constants and stack slot addresses are computed by a single instruction without any value
+//! arguments, so computing them again at the point of use is cheaper than a `fill`, and it doesn't
+//! extend the live range of any other value:
+//!
+//! ```cton
+//!     v1 = iconst.i32 42
+//!     v2 = spill v1
+//!     ...
+//!     v3 = fill v2            ; Becomes `v3 = iconst.i32 42`.
+//! ```
+//!
+//! This pass replaces such fills with a copy of the instruction that defined the spilled value,
+//! effectively sinking the constant to its use. The spills that are no longer needed are removed
+//! later by the dead spill elimination pass.
+//!
+//! A fill is only rematerialized when the copied instruction has a legal encoding in the target
+//! ISA. Constants that would need multiple instructions are reloaded from the stack as before.
+
+use ir::{Function, DataFlowGraph, Inst, InstructionData, Opcode, Value, ValueDef};
+use isa::TargetIsa;
+use std::vec::Vec;
+
+/// Scratch space for the rematerialization pass.
+///
+/// These data structures can be reused between invocations.
+pub struct Rematerializer {
+    /// The `fill` instructions found in the function.
+    fills: Vec<Inst>,
+}
+
+impl Rematerializer {
+    /// Allocate scratch space for rematerialization.
+    pub fn new() -> Rematerializer {
+        Rematerializer { fills: Vec::new() }
+    }
+
+    /// Replace the `fill` instructions in `func` that reload a rematerializable value with a copy
+    /// of the instruction defining the value.
+    ///
+    /// The function must be legalized for `isa`. The rematerialized instructions get new
+    /// encodings.
+    ///
+    /// Return the number of fills that were rematerialized.
+    pub fn run(&mut self, isa: &TargetIsa, func: &mut Function) -> usize {
+        self.fills.clear();
+        for ebb in &func.layout {
+            for inst in func.layout.ebb_insts(ebb) {
+                if func.dfg[inst].opcode() == Opcode::Fill {
+                    self.fills.push(inst);
+                }
+            }
+        }
+
+        let mut count = 0;
+        for &fill in &self.fills {
+            let def = match remat_source(&func.dfg, fill) {
+                Some(def) => def,
+                None => continue,
+            };
+            let data = func.dfg[def].clone();
+            if let Ok(encoding) = isa.encode(&func.dfg, &data) {
+                func.dfg[fill] = data;
+                *func.encodings.ensure(fill) = encoding;
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+/// Can the result of an instruction with this opcode be computed again instead of being reloaded
+/// from a spill slot?
+fn is_rematerializable(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::Iconst | Opcode::F32const | Opcode::F64const | Opcode::Bconst | Opcode::Null |
+        Opcode::StackAddr => true,
+        _ => false,
+    }
+}
+
+/// Find the instruction that can be copied to rematerialize the value reloaded by `fill`.
+///
+/// The filled value is traced back through any `spill` and `fill` instructions to the original
+/// definition.
+fn remat_source(dfg: &DataFlowGraph, fill: Inst) -> Option<Inst> {
+    let mut value: Value = match dfg[fill] {
+        InstructionData::Unary { arg, .. } => arg,
+        _ => return None,
+    };
+    loop {
+        let inst = match dfg.value_def(dfg.resolve_aliases(value)) {
+            ValueDef::Res(inst, 0) => inst,
+            _ => return None,
+        };
+        match dfg[inst] {
+            InstructionData::Unary { opcode: Opcode::Spill, arg, .. } |
+            InstructionData::Unary { opcode: Opcode::Fill, arg, .. } => value = arg,
+            ref data if is_rematerializable(data.opcode()) => return Some(inst),
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, InstBuilder, Opcode, VariableArgs};
+    use ir::types;
+    use isa;
+    use settings;
+    use super::Rematerializer;
+
+    #[test]
+    fn remat_null() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let zero = dfg.ins(pos).null(types::I32);
+            let s0 = dfg.ins(pos).spill(zero);
+            let f0 = dfg.ins(pos).fill(s0);
+            // Spilled again after being reloaded.
+            let s1 = dfg.ins(pos).spill(f0);
+            let f1 = dfg.ins(pos).fill(s1);
+            // Not a constant.
+            let s2 = dfg.ins(pos).spill(v0);
+            let f2 = dfg.ins(pos).fill(s2);
+            let mut args = VariableArgs::new();
+            args.push(f1);
+            args.push(f2);
+            dfg.ins(pos).return_(args);
+        }
+
+        let mut remat = Rematerializer::new();
+        assert_eq!(remat.run(&*isa, &mut func), 2);
+        let opcodes: Vec<_> = func.layout.ebb_insts(ebb0).map(|i| func.dfg[i].opcode()).collect();
+        assert_eq!(opcodes,
+                   [Opcode::Null,
+                    Opcode::Spill,
+                    Opcode::Null,
+                    Opcode::Spill,
+                    Opcode::Null,
+                    Opcode::Spill,
+                    Opcode::Fill,
+                    Opcode::Return]);
+    }
+}
@@ -0,0 +1,162 @@
+//! Garbage collection safepoints.
+//!
+//! A garbage collector walking the stack frames of a function that uses reference types needs a
+//! stack map at every call, telling it where the live references are. After register allocation,
+//! this pass inserts a `safepoint` instruction before each call, and fills in the arguments of all
+//! the safepoints with the references that are live across them:
+//!
+//! ```cton
+//!     safepoint v3, v7
+//!     v9 = call fn0(v3, v8)
+//! ```
+//!
+//! A reference that is passed to the call but not used after it is left out, since the caller's
+//! frame no longer holds it. The binary emitter turns each safepoint into a stack map with the
+//! final locations of its arguments.
+//!
+//! Explicit safepoints in the input, in loop headers for example, should be written without
+//! arguments since this pass replaces them. The safepoint arguments don't extend any live ranges,
+//! so the liveness analysis is still valid after this pass.
+
+use entity_map::EntityRef;
+use ir::{Function, Layout, Ebb, Inst, Value, Opcode, InstructionData, InstBuilder, Cursor,
+         ProgramOrder, ProgramPoint, VariableArgs};
+use ir::instructions::ValueList;
+use ir::types::VOID;
+use isa::TargetIsa;
+use regalloc::liverange::LiveRange;
+use regalloc::liveness::Liveness;
+use sparse_map::SparseMapValue;
+use std::cmp::Ordering;
+use std::vec::Vec;
+
+/// Insert safepoints before the calls in `func` and compute the arguments of all the safepoints.
+///
+/// Safepoints are only inserted when `func` has reference values and `isa` can encode the
+/// `safepoint` instruction.
+///
+/// Return the number of safepoints inserted.
+pub fn emit_safepoints(isa: &TargetIsa, func: &mut Function, liveness: &Liveness) -> usize {
+    let mut refs: Vec<&LiveRange> = liveness
+        .iter()
+        .filter(|lr| func.dfg.value_type(lr.key()).is_ref())
+        .collect();
+    refs.sort_by_key(|lr| lr.key().index());
+    let probe = InstructionData::Return {
+        opcode: Opcode::Safepoint,
+        ty: VOID,
+        args: ValueList::default(),
+    };
+    let encoding = isa.encode(&func.dfg, &probe).ok();
+    let insert = !refs.is_empty() && encoding.is_some();
+
+    // Find the safepoints and the calls needing one, along with the references live across them.
+    let mut points = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst].opcode() {
+                Opcode::Call | Opcode::CallIndirect if insert => {}
+                Opcode::Safepoint => {}
+                _ => continue,
+            }
+            let live: Vec<Value> = refs.iter()
+                .filter(|lr| live_across(lr, ebb, inst, &func.layout))
+                .map(|lr| lr.key())
+                .collect();
+            points.push((inst, live));
+        }
+    }
+
+    let mut inserted = 0;
+    for (inst, live) in points {
+        let mut args = VariableArgs::new();
+        for value in live {
+            args.push(value);
+        }
+        if func.dfg[inst].opcode() == Opcode::Safepoint {
+            func.dfg.replace(inst).safepoint(args);
+        } else {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_inst(inst);
+            let safepoint = func.dfg.ins(&mut pos).safepoint(args);
+            *func.encodings.ensure(safepoint) = encoding.expect("Can't encode safepoints");
+            inserted += 1;
+        }
+    }
+    inserted
+}
+
+/// Is the value of `lr` live across `inst` in `ebb`, so it is still live after `inst`?
+fn live_across(lr: &LiveRange, ebb: Ebb, inst: Inst, layout: &Layout) -> bool {
+    let pp = ProgramPoint::from(inst);
+    if lr.def() == pp || !lr.is_live_at(ebb, pp, layout) {
+        return false;
+    }
+    // Find the end of the local interval containing `inst`. It is either the interval where the
+    // value is defined, or the interval where it is live-in to `ebb`.
+    let end = if layout.cmp(lr.def(), pp) == Ordering::Less &&
+                 layout.cmp(pp, lr.def_local_end()) != Ordering::Greater {
+        lr.def_local_end()
+    } else {
+        lr.livein_local_end(ebb, layout)
+            .expect("Live value must be live-in")
+            .into()
+    };
+    end != pp
+}
+
+#[cfg(test)]
+mod tests {
+    use cfg::ControlFlowGraph;
+    use ir::{Function, Cursor, InstBuilder, Signature, ArgumentType, Opcode, VariableArgs};
+    use ir::types;
+    use isa;
+    use regalloc::liveness::Liveness;
+    use settings::{self, Configurable};
+    use super::emit_safepoints;
+
+    #[test]
+    fn calls() {
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_64bit", true).unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&shared_builder));
+
+        let mut func = Function::new();
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(types::R64));
+        let sig0 = func.dfg.signatures.push(sig);
+        let ebb0 = func.dfg.make_ebb();
+        let r0 = func.dfg.append_ebb_arg(ebb0, types::R64);
+        let r1 = func.dfg.append_ebb_arg(ebb0, types::R64);
+        let callee = func.dfg.append_ebb_arg(ebb0, types::I64);
+        let call;
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let mut args = VariableArgs::new();
+            args.push(r0);
+            call = dfg.ins(pos).call_indirect(sig0, callee, args);
+            let mut rvals = VariableArgs::new();
+            rvals.push(r1);
+            dfg.ins(pos).return_(rvals);
+        }
+        func.encodings.resize(func.dfg.num_insts());
+
+        let cfg = ControlFlowGraph::with_function(&func);
+        let mut liveness = Liveness::new();
+        liveness.compute(&*isa, &func, &cfg);
+        assert_eq!(emit_safepoints(&*isa, &mut func, &liveness), 1);
+
+        // The reference passed to the call is not live across it.
+        let insts: Vec<_> = func.layout.ebb_insts(ebb0).collect();
+        assert_eq!(insts.len(), 3);
+        assert_eq!(insts[1], call);
+        assert_eq!(func.dfg[insts[0]].opcode(), Opcode::Safepoint);
+        assert_eq!(func.dfg.inst_args(insts[0])[1], [r1]);
+        assert!(func.encodings[insts[0]].is_legal());
+
+        // Running the pass again updates the existing safepoint.
+        assert_eq!(emit_safepoints(&*isa, &mut func, &liveness), 1);
+    }
+}
@@ -0,0 +1,675 @@
+//! Spilling pass.
+//!
+//! The spilling pass is the first to run after the liveness analysis. Its primary function is to
+//! ensure that the register pressure never exceeds the number of available registers by moving
+//! some SSA values to spill slots on the stack. This is what guarantees that the coloring pass can
+//! assign a register to every register value without running out.
+//!
+//! The EBBs are visited in a topological order of the dominator tree, and the register pressure
+//! in each register class is measured at every instruction. When an instruction needs
+//! more registers than are available, a value that is live across the instruction is spilled. The
+//! victim is the value whose next use is furthest away.
+//!
+//! A spilled value is moved to the stack right after its definition, and a new value is reloaded
+//! from the stack before each of its uses:
+//!
+//! ```cton
+//!     v1 = iadd v2, v3
+//!     v4 = spill v1           ; Inserted after the definition.
+//!     ...
+//!     v5 = fill v4            ; Inserted before each use.
+//!     v6 = isub v5, v3
+//! ```
+//!
+//! The live range of `v1` ends at the `spill`, and the filled values are only live until the
+//! instruction using them. Every spilled value gets its own spill slot. The stack slot coloring
+//! pass merges the spill slots later.
+//!
+//! # Live range splitting
+//!
+//! Spilling a value for its entire lifetime is wasteful when the register pressure is only high
+//! in a part of it. When the victim has already been used before the instruction that needs its
+//! register, its live range is split instead: The `spill` is inserted in the gap between its uses,
+//! right before the instruction, and only the uses dominated by the split point are reloaded with
+//! `fill` instructions. The earlier uses keep reading the value from its register:
+//!
+//! ```cton
+//!     v1 = iadd v2, v3
+//!     v6 = isub v1, v3        ; Uses the register value.
+//!     v4 = spill v1           ; Inserted at the point of high register pressure.
+//!     ...
+//!     v5 = fill v4            ; Inserted before each later use.
+//!     v7 = imul v5, v3
+//! ```
+//!
+//! A live range is only split when none of the uses that are not dominated by the split point can
+//! be reached from it, so the value is no longer live in a register after the split. Otherwise,
+//! the value is spilled as a whole.
+//!
+//! Splitting costs some compile time, so it is skipped when the `opt_level` setting is `none`.
+//!
+//! The live ranges of the spilled values are not updated, but the live ranges of the split values
+//! are recomputed. The liveness analysis must be recomputed after spilling.
+//!
+//! # Calls
+//!
+//! The register allocator doesn't know which registers a callee clobbers, so every register value
+//! that is live across a call instruction is spilled or split right before the call, and reloaded
+//! after it. The call arguments and results are not affected.
+//!
+//! # Emergency spills
+//!
+//! The values used by an instruction normally can't be spilled to make room for it. When all the
+//! candidates are used by the instruction, a used value that is still live after the instruction
+//! is spilled anyway. It is reloaded right before the instruction, and the reloaded value is killed
+//! by the instruction, so its register can be reused for the defined values. When there is no
+//! such value either, the spilling pass fails with a `RegAllocError`.
+//!
+//! Spilling can't help an instruction whose register operands alone need more registers than are
+//! available. The operands of all the instructions in an EBB are checked before the EBB is
+//! processed, and such an instruction also makes the spilling pass fail.
+
+use cfg::ControlFlowGraph;
+use dominator_tree::DominatorTree;
+use ir::{Ebb, Inst, Value, Function, Cursor, InstBuilder, StackSlotData, StackSlotKind, ValueDef,
+         ValueLoc};
+use isa::{TargetIsa, RegInfo, RegClassIndex};
+use regalloc::affinity::Affinity;
+use regalloc::error::RegAllocError;
+use regalloc::live_value_tracker::{LiveValue, LiveValueTracker};
+use regalloc::liveness::Liveness;
+use regalloc::pressure::Pressure;
+use settings::OptLevel;
+use sparse_map::SparseSet;
+use std::string::ToString;
+use std::vec::Vec;
+
+/// Data structures for the spilling pass.
+///
+/// These are scratch space data structures that can be reused between invocations.
+pub struct Spilling {
+    /// Set of visited EBBs.
+    visited: SparseSet<Ebb>,
+
+    /// Stack of EBBs to be visited next.
+    stack: Vec<Ebb>,
+
+    /// Values that have been spilled. Their live ranges are out of date.
+    spilled: SparseSet<Value>,
+
+    /// Values that have been split in the current EBB. They don't need a register after the split
+    /// point.
+    split: SparseSet<Value>,
+
+    /// Number of live ranges split in the function.
+    num_splits: usize,
+
+    /// Instructions using the value being spilled.
+    users: Vec<Inst>,
+
+    /// Values used by the register operands of an instruction.
+    operands: Vec<Value>,
+
+    /// Register values that are live across a call.
+    across_call: Vec<Value>,
+
+    /// EBBs reachable from a split point.
+    reachable: SparseSet<Ebb>,
+
+    /// Worklist for the reachability search.
+    worklist: Vec<Ebb>,
+}
+
+/// Bundle of references that the spilling algorithm needs.
+struct Context<'a> {
+    isa: &'a TargetIsa,
+    reginfo: RegInfo,
+
+    // Split live ranges instead of spilling them whole when possible.
+    split_ranges: bool,
+
+    // References to contextual data structures we need.
+    cfg: &'a ControlFlowGraph,
+    domtree: &'a DominatorTree,
+    liveness: &'a mut Liveness,
+
+    // Registers needed at the current program point.
+    pressure: Pressure,
+}
+
+impl Spilling {
+    /// Allocate scratch space data structures for the spilling pass.
+    pub fn new() -> Spilling {
+        Spilling {
+            visited: SparseSet::new(),
+            stack: Vec::new(),
+            spilled: SparseSet::new(),
+            split: SparseSet::new(),
+            num_splits: 0,
+            users: Vec::new(),
+            operands: Vec::new(),
+            across_call: Vec::new(),
+            reachable: SparseSet::new(),
+            worklist: Vec::new(),
+        }
+    }
+
+    /// Run the spilling algorithm over `func`.
+    ///
+    /// Return the number of values that were spilled or split. If any values were spilled or
+    /// split, `liveness` must be recomputed before it is used again.
+    ///
+    /// Return an error if an instruction or EBB needs more registers than are available.
+    pub fn run(&mut self,
+               isa: &TargetIsa,
+               func: &mut Function,
+               cfg: &ControlFlowGraph,
+               domtree: &DominatorTree,
+               liveness: &mut Liveness,
+               tracker: &mut LiveValueTracker)
+               -> Result<usize, RegAllocError> {
+        // Forget the EBBs visited and the values spilled in the previous function.
+        self.visited.clear();
+        self.spilled.clear();
+        self.num_splits = 0;
+
+        let reginfo = isa.register_info();
+        let pressure = Pressure::new(&reginfo, &isa.allocatable_registers());
+        let mut ctx = Context {
+            isa: isa,
+            reginfo: reginfo,
+            split_ranges: isa.flags().opt_level() != OptLevel::None,
+            cfg: cfg,
+            domtree: domtree,
+            liveness: liveness,
+            pressure: pressure,
+        };
+        ctx.run(self, func, tracker)?;
+        Ok(self.spilled.len() + self.num_splits)
+    }
+}
+
+impl<'a> Context<'a> {
+    /// Run the spilling algorithm.
+    fn run(&mut self,
+           data: &mut Spilling,
+           func: &mut Function,
+           tracker: &mut LiveValueTracker)
+           -> Result<(), RegAllocError> {
+        // Visit blocks in layout order, letting `process_ebb` enforce a topological ordering.
+        let mut next = func.layout.entry_block();
+        while let Some(ebb) = next {
+            self.process_ebb(ebb, data, func, tracker)?;
+            next = func.layout.next_ebb(ebb);
+        }
+        Ok(())
+    }
+
+    /// Process `ebb`, but only after ensuring that the immediate dominator has been processed.
+    fn process_ebb(&mut self,
+                   mut ebb: Ebb,
+                   data: &mut Spilling,
+                   func: &mut Function,
+                   tracker: &mut LiveValueTracker)
+                   -> Result<(), RegAllocError> {
+        // The stack is just a scratch space for this algorithm. We leave it empty when returning.
+        assert!(data.stack.is_empty());
+
+        // Trace up the dominator tree until we reach a dominator that has already been visited.
+        while data.visited.insert(ebb).is_none() {
+            data.stack.push(ebb);
+            match self.domtree.idom(ebb) {
+                Some(idom) => ebb = func.layout.inst_ebb(idom).expect("idom not in layout"),
+                None => break,
+            }
+        }
+
+        // Pop off blocks in topological order.
+        while let Some(ebb) = data.stack.pop() {
+            if let Err(e) = self.visit_ebb(ebb, data, func, tracker) {
+                data.stack.clear();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Visit `ebb`, assuming that the immediate dominator has already been visited.
+    fn visit_ebb(&mut self,
+                 ebb: Ebb,
+                 data: &mut Spilling,
+                 func: &mut Function,
+                 tracker: &mut LiveValueTracker)
+                 -> Result<(), RegAllocError> {
+        data.split.clear();
+        let num_liveins = tracker
+            .ebb_top(ebb, &func.dfg, self.liveness, &func.layout, self.domtree)
+            .0
+            .len();
+
+        // The EBB arguments arrive in registers, so only the live-in values can be spilled to make
+        // room for them.
+        while let Some(rci) = self.overcommitted(data, tracker.live()) {
+            let victim = match self.pick_victim(data,
+                                                func,
+                                                &tracker.live()[0..num_liveins],
+                                                rci,
+                                                ebb,
+                                                None) {
+                Some(victim) => victim,
+                None => {
+                    let e = RegAllocError::new(ebb, self.reginfo.rc(rci).name.to_string());
+                    return Err(e.with_live(tracker.live()));
+                }
+            };
+            self.spill_value(victim, data, func);
+        }
+
+        // Spilling other values doesn't help an instruction whose register operands don't fit in
+        // the registers at the same time.
+        for inst in func.layout.ebb_insts(ebb) {
+            self.check_operands(inst, data, func)?;
+        }
+
+        let mut prev = None;
+        loop {
+            let inst = {
+                let mut pos = Cursor::new(&mut func.layout);
+                match prev {
+                    Some(inst) => pos.goto_inst(inst),
+                    None => pos.goto_top(ebb),
+                }
+                match pos.next_inst() {
+                    Some(inst) => inst,
+                    None => break,
+                }
+            };
+            self.visit_inst(ebb, inst, data, func, tracker)?;
+            tracker.drop_dead(inst);
+            prev = Some(inst);
+        }
+        Ok(())
+    }
+
+    /// Make sure that there are enough registers for the values that are live across `inst`
+    /// and the values defined by `inst`.
+    fn visit_inst(&mut self,
+                  ebb: Ebb,
+                  inst: Inst,
+                  data: &mut Spilling,
+                  func: &mut Function,
+                  tracker: &mut LiveValueTracker)
+                  -> Result<(), RegAllocError> {
+        let (num_kills, num_defs) = {
+            let (kills, defs) = tracker.process_inst(inst, &func.dfg, self.liveness);
+            (kills.len(), defs.len())
+        };
+
+        // The live values are partitioned as `[survivors, kills, defs]`. The killed registers can
+        // be reused for the defined values.
+        let first_def = tracker.live().len() - num_defs;
+        let first_kill = first_def - num_kills;
+
+        loop {
+            let rci = {
+                let live = tracker.live();
+                let needed = live[0..first_kill].iter().chain(&live[first_def..]);
+                match self.overcommitted(data, needed) {
+                    Some(rci) => rci,
+                    None => break,
+                }
+            };
+            let victim = {
+                let survivors = &tracker.live()[0..first_kill];
+                self.pick_victim(data, func, survivors, rci, ebb, Some(inst))
+                    .or_else(|| self.pick_emergency_victim(data, func, survivors, rci, ebb, inst))
+            };
+            let victim = match victim {
+                Some(victim) => victim,
+                None => {
+                    let e = RegAllocError::new(inst, self.reginfo.rc(rci).name.to_string());
+                    return Err(e.with_live(tracker.live()));
+                }
+            };
+            if self.split_ranges && self.can_split(victim, inst, data, func) {
+                self.split_value(victim, inst, data, func);
+            } else {
+                self.spill_value(victim, data, func);
+            }
+        }
+
+        // The callee may clobber any register, so the register values that are live across a
+        // call are moved to the stack.
+        if func.dfg.call_signature(inst).is_some() {
+            data.across_call.clear();
+            for lv in &tracker.live()[0..first_kill] {
+                if let Affinity::Reg(_) = lv.affinity {
+                    if !data.spilled.contains_key(lv.value) && !data.split.contains_key(lv.value) {
+                        data.across_call.push(lv.value);
+                    }
+                }
+            }
+            for i in 0..data.across_call.len() {
+                let value = data.across_call[i];
+                if self.split_ranges && self.can_split(value, inst, data, func) {
+                    self.split_value(value, inst, data, func);
+                } else {
+                    self.spill_value(value, data, func);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that there are enough registers for the register operands of `inst`.
+    fn check_operands(&mut self,
+                      inst: Inst,
+                      data: &mut Spilling,
+                      func: &Function)
+                      -> Result<(), RegAllocError> {
+        // Collect the values used by register operands, counting each value once.
+        data.operands.clear();
+        self.pressure.reset();
+        let args = func.dfg.inst_args(inst);
+        for &arg in args[0].iter().chain(args[1].iter()) {
+            if data.operands.contains(&arg) {
+                continue;
+            }
+            if let Some(Affinity::Reg(rci)) = self.liveness.get(arg).map(|lr| lr.affinity) {
+                self.pressure.take(self.reginfo.rc(rci));
+                data.operands.push(arg);
+            }
+        }
+        match self.pressure.overcommitted() {
+            Some(rci) => {
+                let mut e = RegAllocError::new(inst, self.reginfo.rc(rci).name.to_string());
+                e.live = data.operands.clone();
+                Err(e)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Count the registers needed for the register values in `live` that haven't been spilled.
+    ///
+    /// Return a register class that doesn't have enough registers, if any.
+    fn overcommitted<'v, I>(&mut self, data: &Spilling, live: I) -> Option<RegClassIndex>
+        where I: IntoIterator<Item = &'v LiveValue>
+    {
+        self.pressure.reset();
+        for lv in live {
+            if let Affinity::Reg(rci) = lv.affinity {
+                if !data.spilled.contains_key(lv.value) && !data.split.contains_key(lv.value) {
+                    self.pressure.take(self.reginfo.rc(rci));
+                }
+            }
+        }
+        self.pressure.overcommitted()
+    }
+
+    /// Pick a value in the register class `rc` or one of its subclasses to spill from the
+    /// `candidates`.
+    ///
+    /// The candidates used by `inst` can't be spilled. The next uses are counted from `inst`, or
+    /// from the top of `ebb` when `inst` is `None`.
+    fn pick_victim(&self,
+                   data: &Spilling,
+                   func: &Function,
+                   candidates: &[LiveValue],
+                   rc: RegClassIndex,
+                   ebb: Ebb,
+                   inst: Option<Inst>)
+                   -> Option<Value> {
+        let rc = self.reginfo.rc(rc);
+        let mut best: Option<(Value, usize)> = None;
+        for lv in candidates {
+            match lv.affinity {
+                Affinity::Reg(rci) if rc.has_subclass(rci) => {}
+                _ => continue,
+            }
+            if data.spilled.contains_key(lv.value) || data.split.contains_key(lv.value) {
+                continue;
+            }
+            if let Some(inst) = inst {
+                if uses_value(func, inst, lv.value) {
+                    continue;
+                }
+            }
+            let distance = next_use_distance(func, lv.value, ebb, inst);
+            if best.map_or(true, |(_, d)| distance > d) {
+                best = Some((lv.value, distance));
+            }
+        }
+        best.map(|(value, _)| value)
+    }
+
+    /// Pick a value in the register class `rc` or one of its subclasses to spill from the
+    /// `candidates` that are used by `inst`.
+    ///
+    /// This is the emergency spill for instructions whose own operands are using all the
+    /// registers. The `candidates` are live after `inst`, so the value reloaded for `inst` is
+    /// killed by `inst`, and its register becomes available for the values defined by `inst`.
+    fn pick_emergency_victim(&self,
+                             data: &Spilling,
+                             func: &Function,
+                             candidates: &[LiveValue],
+                             rc: RegClassIndex,
+                             ebb: Ebb,
+                             inst: Inst)
+                             -> Option<Value> {
+        let rc = self.reginfo.rc(rc);
+        let mut best: Option<(Value, usize)> = None;
+        for lv in candidates {
+            match lv.affinity {
+                Affinity::Reg(rci) if rc.has_subclass(rci) => {}
+                _ => continue,
+            }
+            if data.spilled.contains_key(lv.value) || data.split.contains_key(lv.value) ||
+               !uses_value(func, inst, lv.value) {
+                continue;
+            }
+            let distance = next_use_distance(func, lv.value, ebb, Some(inst));
+            if best.map_or(true, |(_, d)| distance > d) {
+                best = Some((lv.value, distance));
+            }
+        }
+        best.map(|(value, _)| value)
+    }
+
+    /// Spill `value` by inserting a `spill` after its definition and replacing all its uses with
+    /// new values reloaded by `fill` instructions.
+    fn spill_value(&mut self, value: Value, data: &mut Spilling, func: &mut Function) {
+        let affinity = self.liveness.get(value).expect("Spilled value has no live range").affinity;
+
+        // Insert the spill right after the definition.
+        let stack_value = {
+            let mut pos = Cursor::new(&mut func.layout);
+            match func.dfg.value_def(value) {
+                ValueDef::Res(inst, _) => {
+                    pos.goto_inst(inst);
+                    pos.next_inst();
+                }
+                ValueDef::Arg(ebb, _) => {
+                    pos.goto_top(ebb);
+                    pos.next_inst();
+                }
+            }
+            func.dfg.ins(&mut pos).spill(value)
+        };
+        let spill = def_inst(func, stack_value);
+        self.assign_spill_slot(func, stack_value);
+
+        // Reload the value before each other use.
+        collect_users(func, value, &mut data.users);
+        for &user in data.users.iter().filter(|&&user| user != spill) {
+            self.reload(value, stack_value, user, affinity, func);
+        }
+
+        data.spilled.insert(value);
+    }
+
+    /// Can the live range of `value` be split right before `inst`?
+    ///
+    /// This is only worthwhile when `value` has been used before the split point, and it is only
+    /// possible when none of the uses that are not dominated by the split point can be reached
+    /// from it.
+    fn can_split(&self, value: Value, inst: Inst, data: &mut Spilling, func: &Function) -> bool {
+        collect_users(func, value, &mut data.users);
+        let layout = &func.layout;
+        let domtree = self.domtree;
+        let mut earlier = data.users
+            .iter()
+            .filter(|&&user| !domtree.dominates(inst, user, layout))
+            .peekable();
+        if earlier.peek().is_none() {
+            return false;
+        }
+
+        // Search the EBBs reachable from the split point for earlier uses.
+        let ebb = layout.inst_ebb(inst).expect("Instruction not in layout");
+        data.reachable.clear();
+        data.worklist.clear();
+        data.worklist.extend(self.cfg.get_successors(ebb));
+        while let Some(succ) = data.worklist.pop() {
+            if data.reachable.insert(succ).is_none() {
+                data.worklist.extend(self.cfg.get_successors(succ));
+            }
+        }
+        earlier
+            .filter_map(|&user| layout.inst_ebb(user))
+            .all(|user_ebb| !data.reachable.contains_key(user_ebb))
+    }
+
+    /// Split the live range of `value` by inserting a `spill` before `inst`, and replace the uses
+    /// dominated by `inst` with new values reloaded by `fill` instructions.
+    ///
+    /// The users of `value` must have been collected by `can_split()`.
+    fn split_value(&mut self, value: Value, inst: Inst, data: &mut Spilling, func: &mut Function) {
+        let (def, affinity) = {
+            let lr = self.liveness.get(value).expect("Split value has no live range");
+            (lr.def(), lr.affinity)
+        };
+
+        let stack_value = {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_inst(inst);
+            func.dfg.ins(&mut pos).spill(value)
+        };
+        let spill = def_inst(func, stack_value);
+        self.assign_spill_slot(func, stack_value);
+
+        // The live range of `value` now ends at the spill, and at the earlier uses.
+        self.liveness.remove(value);
+        self.liveness.create_dead(value, def, affinity);
+        self.liveness.extend_to_use(value, spill, func, self.cfg);
+        for &user in &data.users {
+            if self.domtree.dominates(inst, user, &func.layout) {
+                self.reload(value, stack_value, user, affinity, func);
+            } else {
+                self.liveness.extend_to_use(value, user, func, self.cfg);
+            }
+        }
+
+        data.split.insert(value);
+        data.num_splits += 1;
+    }
+
+    /// Assign a new spill slot to `stack_value` which was just defined by a `spill` instruction,
+    /// and encode the spill.
+    fn assign_spill_slot(&mut self, func: &mut Function, stack_value: Value) {
+        let spill = def_inst(func, stack_value);
+        // Boolean values still need a whole byte.
+        let bytes = func.dfg.value_type(stack_value).bits().max(8) as u32 / 8;
+        let slot = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::SpillSlot, bytes));
+        *func.locations.ensure(stack_value) = ValueLoc::Stack(slot);
+        self.encode(func, spill);
+        self.liveness.create_dead(stack_value, spill, Affinity::Stack);
+    }
+
+    /// Reload `value` from `stack_value` with a `fill` before `user`, and make `user` use the
+    /// reloaded value instead.
+    fn reload(&mut self,
+              value: Value,
+              stack_value: Value,
+              user: Inst,
+              affinity: Affinity,
+              func: &mut Function) {
+        let reloaded = {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_inst(user);
+            func.dfg.ins(&mut pos).fill(stack_value)
+        };
+        for part in &mut func.dfg.inst_args_mut(user) {
+            for arg in part.iter_mut() {
+                if *arg == value {
+                    *arg = reloaded;
+                }
+            }
+        }
+        let fill = def_inst(func, reloaded);
+        self.encode(func, fill);
+        self.liveness.create_dead(reloaded, fill, affinity);
+        self.liveness.extend_to_use(reloaded, user, func, self.cfg);
+        self.liveness.extend_to_use(stack_value, fill, func, self.cfg);
+    }
+
+    /// Assign an encoding to the new instruction `inst`.
+    fn encode(&self, func: &mut Function, inst: Inst) {
+        match self.isa.encode(&func.dfg, &func.dfg[inst]) {
+            Ok(encoding) => *func.encodings.ensure(inst) = encoding,
+            Err(_) => {
+                panic!("Can't encode {}.{}",
+                       func.dfg[inst].opcode(),
+                       func.dfg.value_type(func.dfg.first_result(inst)))
+            }
+        }
+    }
+}
+
+/// Does `inst` use `value` as an argument?
+fn uses_value(func: &Function, inst: Inst, value: Value) -> bool {
+    func.dfg
+        .inst_args(inst)
+        .iter()
+        .any(|args| args.contains(&value))
+}
+
+/// Collect the instructions using `value` in layout order.
+fn collect_users(func: &Function, value: Value, users: &mut Vec<Inst>) {
+    users.clear();
+    for ebb in &func.layout {
+        for inst in func.layout.ebb_insts(ebb) {
+            if uses_value(func, inst, value) {
+                users.push(inst);
+            }
+        }
+    }
+}
+
+/// Get the instruction defining `value`.
+fn def_inst(func: &Function, value: Value) -> Inst {
+    match func.dfg.value_def(value) {
+        ValueDef::Res(inst, _) => inst,
+        ValueDef::Arg(..) => panic!("{} is not an instruction result", value),
+    }
+}
+
+/// Get the number of instructions in `ebb` between the current position and the next use of
+/// `value`.
+///
+/// The current position is after `inst`, or at the top of `ebb` when `inst` is `None`. Values that
+/// aren't used again in `ebb` are infinitely far away.
+fn next_use_distance(func: &Function, value: Value, ebb: Ebb, inst: Option<Inst>) -> usize {
+    let mut insts = func.layout.ebb_insts(ebb);
+    if let Some(inst) = inst {
+        for i in insts.by_ref() {
+            if i == inst {
+                break;
+            }
+        }
+    }
+    insts
+        .position(|i| uses_value(func, i, value))
+        .unwrap_or(usize::MAX)
+}
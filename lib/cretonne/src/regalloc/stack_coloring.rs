@@ -0,0 +1,183 @@
+//! Stack slot coloring.
+//!
+//! The spiller assigns spilled values to spill slots without trying to reuse them, so a function
+//! with a lot of spill code can end up with a very large stack frame. This pass merges spill slots
+//! whose values never interfere, so they can share the same memory in the stack frame.
+//!
+//! Two spill slots can be merged when:
+//!
+//! 1. They have the same size and alignment, and
+//! 2. None of the live ranges of the values assigned to one slot overlap any of the live ranges of
+//!    the values assigned to the other slot.
+//!
+//! Slots are merged greedily in the order they were created. The spill slots that become unused
+//! are left in the function's stack slot table, but no values are assigned to them any longer, so
+//! they don't need space in the stack frame.
+
+use entity_map::EntityMap;
+use ir::{Function, StackSlot, StackSlotKind, Value, ValueLoc};
+use regalloc::liveness::Liveness;
+use sparse_map::SparseMapValue;
+use std::vec::Vec;
+
+/// Scratch space for the stack slot coloring pass.
+///
+/// These data structures can be reused between invocations.
+pub struct StackColoring {
+    /// The values assigned to each spill slot.
+    slot_values: EntityMap<StackSlot, Vec<Value>>,
+
+    /// Spill slots that are kept. The other spill slots are merged into these.
+    colors: Vec<StackSlot>,
+}
+
+impl StackColoring {
+    /// Allocate scratch space for stack slot coloring.
+    pub fn new() -> StackColoring {
+        StackColoring {
+            slot_values: EntityMap::new(),
+            colors: Vec::new(),
+        }
+    }
+
+    /// Merge non-interfering spill slots in `func`.
+    ///
+    /// The live ranges in `liveness` must be up to date for all the values assigned to spill
+    /// slots.
+    ///
+    /// Return the number of spill slots that were merged into another slot.
+    pub fn run(&mut self, func: &mut Function, liveness: &Liveness) -> usize {
+        self.slot_values.clear();
+        self.colors.clear();
+        self.slot_values.resize(func.stack_slots.len());
+
+        // Collect the values assigned to each spill slot.
+        for lr in liveness.iter() {
+            let value = lr.key();
+            if let Some(&ValueLoc::Stack(ss)) = func.locations.get(value) {
+                if func.stack_slots[ss].kind == StackSlotKind::SpillSlot {
+                    self.slot_values[ss].push(value);
+                }
+            }
+        }
+
+        // Assign each spill slot the first compatible color, or make it a new color.
+        let mut merged = 0;
+        for ss in func.stack_slots.keys() {
+            if self.slot_values[ss].is_empty() {
+                continue;
+            }
+            match self.find_color(ss, func, liveness) {
+                Some(color) => {
+                    for i in 0..self.slot_values[ss].len() {
+                        let value = self.slot_values[ss][i];
+                        func.locations[value] = ValueLoc::Stack(color);
+                        self.slot_values[color].push(value);
+                    }
+                    self.slot_values[ss].clear();
+                    merged += 1;
+                }
+                None => self.colors.push(ss),
+            }
+        }
+        merged
+    }
+
+    /// Find an existing color that the values in `ss` can be merged into.
+    fn find_color(&self,
+                  ss: StackSlot,
+                  func: &Function,
+                  liveness: &Liveness)
+                  -> Option<StackSlot> {
+        let data = &func.stack_slots[ss];
+        self.colors
+            .iter()
+            .cloned()
+            .find(|&color| {
+                let cdata = &func.stack_slots[color];
+                cdata.size == data.size && cdata.align == data.align &&
+                !self.interferes(ss, color, func, liveness)
+            })
+    }
+
+    /// Do any of the values assigned to `a` interfere with the values assigned to `b`?
+    fn interferes(&self,
+                  a: StackSlot,
+                  b: StackSlot,
+                  func: &Function,
+                  liveness: &Liveness)
+                  -> bool {
+        self.slot_values[a].iter().any(|&va| {
+            let lra = liveness.get(va).expect("missing live range for spilled value");
+            self.slot_values[b].iter().any(|&vb| {
+                let lrb = liveness.get(vb).expect("missing live range for spilled value");
+                lra.overlaps(lrb, &func.layout)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cfg::ControlFlowGraph;
+    use ir::{Function, Cursor, InstBuilder, StackSlotData, StackSlotKind, ValueLoc};
+    use ir::types;
+    use isa;
+    use regalloc::liveness::Liveness;
+    use settings;
+    use super::StackColoring;
+
+    #[test]
+    fn merge_slots() {
+        let mut func = Function::new();
+        let ss0 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 4));
+        let ss1 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 4));
+        let ss2 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 4));
+        let ss3 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 8));
+        let ebb0 = func.dfg.make_ebb();
+        let (s1, s2, s3, s4);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v1 = dfg.ins(pos).iconst(types::I32, 3);
+            let v2 = dfg.ins(pos).iconst(types::I64, 3);
+            s1 = dfg.ins(pos).spill(v1);
+            dfg.ins(pos).fill(s1);
+            // `s2` doesn't interfere with `s1`.
+            s2 = dfg.ins(pos).spill(v1);
+            // `s3` interferes with `s2`.
+            s3 = dfg.ins(pos).spill(v1);
+            // `s4` doesn't interfere with `s1`, but it has a different size.
+            s4 = dfg.ins(pos).spill(v2);
+            dfg.ins(pos).fill(s2);
+            dfg.ins(pos).fill(s3);
+            dfg.ins(pos).fill(s4);
+        }
+        *func.locations.ensure(s1) = ValueLoc::Stack(ss0);
+        *func.locations.ensure(s2) = ValueLoc::Stack(ss1);
+        *func.locations.ensure(s3) = ValueLoc::Stack(ss2);
+        *func.locations.ensure(s4) = ValueLoc::Stack(ss3);
+
+        // Liveness analysis works without encodings, but the table must cover all instructions.
+        let num_insts = func.dfg.num_insts();
+        func.encodings.resize(num_insts);
+
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let cfg = ControlFlowGraph::with_function(&func);
+        let mut liveness = Liveness::new();
+        liveness.compute(&*isa, &func, &cfg);
+
+        let mut coloring = StackColoring::new();
+        assert_eq!(coloring.run(&mut func, &liveness), 1);
+
+        let slot = |loc| match loc {
+            ValueLoc::Stack(ss) => ss,
+            _ => panic!("expected a stack location"),
+        };
+        assert_eq!(slot(func.locations[s1]), ss0);
+        assert_eq!(slot(func.locations[s2]), ss0);
+        assert_eq!(slot(func.locations[s3]), ss2);
+        assert_eq!(slot(func.locations[s4]), ss3);
+    }
+}
@@ -0,0 +1,203 @@
+//! Virtual registers.
+//!
+//! A virtual register is a set of SSA values that the register allocator tries to assign to the
+//! same location. The coalescing pass builds the virtual registers while converting the function
+//! to conventional SSA form: An EBB argument and the values passed to it are merged into the same
+//! virtual register when their live ranges don't interfere. The coloring pass prefers a register
+//! already assigned to another member of the virtual register, and the location verifier checks
+//! that the members of a virtual register really don't interfere.
+//!
+//! The virtual registers are represented by a union-find data structure over values. Each virtual
+//! register is identified by its *representative*, which is the member with the lowest value
+//! number. The representative doesn't depend on the order the values were merged in.
+//!
+//! The members of a virtual register are stored with its representative. When two virtual
+//! registers are merged, the members of the absorbed one are linked directly to the new
+//! representative, so finding the representative of a value never follows a chain of links.
+
+use entity_map::{EntityMap, EntityRef};
+use ir::Value;
+use std::cmp::Ordering;
+use std::mem;
+use std::slice;
+use std::vec::Vec;
+
+/// Virtual registers as a union-find data structure over values.
+///
+/// Values that have never been merged with another value form their own virtual register.
+pub struct VirtRegs {
+    /// The representative of each value that has been merged with another value.
+    rep: EntityMap<Value, Option<Value>>,
+
+    /// The members of each virtual register, stored with its representative.
+    members: EntityMap<Value, Vec<Value>>,
+
+    /// The representatives of all the virtual registers with more than one member, including the
+    /// ones that have since been absorbed into other virtual registers.
+    reps: Vec<Value>,
+}
+
+impl VirtRegs {
+    /// Create a new set of virtual registers where every value is on its own.
+    pub fn new() -> VirtRegs {
+        VirtRegs {
+            rep: EntityMap::new(),
+            members: EntityMap::new(),
+            reps: Vec::new(),
+        }
+    }
+
+    /// Forget all the virtual registers.
+    pub fn clear(&mut self) {
+        self.rep.clear();
+        self.members.clear();
+        self.reps.clear();
+    }
+
+    /// Get the representative of the virtual register containing `value`.
+    pub fn find(&self, value: Value) -> Value {
+        self.rep.get(value).cloned().and_then(|r| r).unwrap_or(value)
+    }
+
+    /// Are `a` and `b` in the same virtual register?
+    pub fn same(&self, a: Value, b: Value) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    /// Get the values in the same virtual register as `value`, including `value` itself.
+    ///
+    /// The returned slice is empty for values that have never been merged with another value.
+    pub fn congruence_class(&self, value: Value) -> &[Value] {
+        self.members
+            .get(self.find(value))
+            .map(|members| members.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Iterate over the members of the virtual registers with more than one member.
+    pub fn iter(&self) -> Iter {
+        Iter {
+            virtregs: self,
+            reps: self.reps.iter(),
+        }
+    }
+
+    /// Merge the virtual registers containing `a` and `b`, and return the new representative.
+    ///
+    /// The members of a virtual register are kept in the order given by `order`. The caller must
+    /// use the same ordering for every merge.
+    pub fn union<F>(&mut self, a: Value, b: Value, mut order: F) -> Value
+        where F: FnMut(Value, Value) -> Ordering
+    {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra == rb {
+            return ra;
+        }
+        let (rep, other) = if ra.index() < rb.index() { (ra, rb) } else { (rb, ra) };
+        let new_class = self.members.get(rep).map_or(true, |members| members.is_empty());
+
+        let va = self.take_members(ra);
+        let vb = self.take_members(rb);
+        let mut merged = Vec::with_capacity(va.len() + vb.len());
+        let (mut i, mut j) = (0, 0);
+        while i < va.len() || j < vb.len() {
+            if j == vb.len() || (i < va.len() && order(va[i], vb[j]) != Ordering::Greater) {
+                merged.push(va[i]);
+                i += 1;
+            } else {
+                merged.push(vb[j]);
+                j += 1;
+            }
+        }
+
+        // Link the members of the absorbed virtual register to the new representative.
+        let absorbed = if other == ra { &va } else { &vb };
+        for &value in absorbed {
+            *self.rep.ensure(value) = Some(rep);
+        }
+        if new_class {
+            self.reps.push(rep);
+        }
+        *self.members.ensure(rep) = merged;
+        rep
+    }
+
+    /// Take the members of the virtual register represented by `rep`.
+    fn take_members(&mut self, rep: Value) -> Vec<Value> {
+        match self.members.get(rep) {
+            Some(members) if !members.is_empty() => {}
+            _ => return vec![rep],
+        }
+        mem::replace(self.members.ensure(rep), Vec::new())
+    }
+}
+
+/// Iterator over the members of the virtual registers with more than one member.
+pub struct Iter<'a> {
+    virtregs: &'a VirtRegs,
+    reps: slice::Iter<'a, Value>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [Value];
+
+    fn next(&mut self) -> Option<&'a [Value]> {
+        for &rep in self.reps.by_ref() {
+            if self.virtregs.find(rep) == rep {
+                return Some(&self.virtregs.members[rep]);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::Value;
+
+    #[test]
+    fn union_find() {
+        let mut vregs = VirtRegs::new();
+        let v: Vec<Value> = (0..6).map(Value::new).collect();
+        let by_number = |a: Value, b: Value| a.index().cmp(&b.index());
+
+        assert_eq!(vregs.find(v[3]), v[3]);
+        assert_eq!(vregs.congruence_class(v[3]), &[]);
+        assert_eq!(vregs.iter().count(), 0);
+
+        // The representative is the lowest value number, whatever the merge order.
+        assert_eq!(vregs.union(v[4], v[2], by_number), v[2]);
+        assert_eq!(vregs.union(v[5], v[3], by_number), v[3]);
+        assert_eq!(vregs.find(v[4]), v[2]);
+        assert!(vregs.same(v[5], v[3]));
+        assert!(!vregs.same(v[2], v[3]));
+        assert_eq!(vregs.iter().count(), 2);
+
+        assert_eq!(vregs.union(v[5], v[4], by_number), v[2]);
+        assert_eq!(vregs.find(v[3]), v[2]);
+        assert_eq!(vregs.congruence_class(v[5]), &[v[2], v[3], v[4], v[5]]);
+        assert_eq!(vregs.iter().collect::<Vec<_>>(), vec![&[v[2], v[3], v[4], v[5]]]);
+
+        // Merging values in the same virtual register does nothing.
+        assert_eq!(vregs.union(v[3], v[4], by_number), v[2]);
+        assert_eq!(vregs.congruence_class(v[2]).len(), 4);
+
+        vregs.clear();
+        assert_eq!(vregs.find(v[4]), v[4]);
+        assert_eq!(vregs.iter().count(), 0);
+    }
+
+    #[test]
+    fn member_order() {
+        let mut vregs = VirtRegs::new();
+        let v: Vec<Value> = (0..4).map(Value::new).collect();
+        // Order the members by decreasing value number.
+        let reverse = |a: Value, b: Value| b.index().cmp(&a.index());
+
+        vregs.union(v[0], v[2], reverse);
+        vregs.union(v[1], v[3], reverse);
+        vregs.union(v[0], v[1], reverse);
+        assert_eq!(vregs.congruence_class(v[0]), &[v[3], v[2], v[1], v[0]]);
+    }
+}
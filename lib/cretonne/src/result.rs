@@ -0,0 +1,56 @@
+//! Result and error types representing the outcome of compiling a function.
+
+use regalloc::RegAllocError;
+use std::fmt;
+use verifier;
+
+/// A compilation error.
+///
+/// When Cretonne fails to compile a function, it will return one of these error codes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CtonError {
+    /// An IL verifier error.
+    ///
+    /// This always represents a bug, either in the code that generated IL for Cretonne, or a bug
+    /// in Cretonne itself.
+    Verifier(verifier::Error),
+
+    /// The register allocator couldn't satisfy the constraints of an instruction.
+    ///
+    /// This happens when an instruction needs more registers than are available, or when its
+    /// fixed register constraints conflict.
+    RegAlloc(RegAllocError),
+
+    /// The compilation was cancelled by the embedder.
+    Cancelled,
+
+    /// The function is too large for one of its branches to reach its destination with any of the
+    /// branch encodings available in the ISA.
+    CodeTooLarge,
+}
+
+/// A Cretonne compilation result.
+pub type CtonResult = Result<(), CtonError>;
+
+impl From<verifier::Error> for CtonError {
+    fn from(e: verifier::Error) -> CtonError {
+        CtonError::Verifier(e)
+    }
+}
+
+impl From<RegAllocError> for CtonError {
+    fn from(e: RegAllocError) -> CtonError {
+        CtonError::RegAlloc(e)
+    }
+}
+
+impl fmt::Display for CtonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CtonError::Verifier(ref e) => e.fmt(f),
+            CtonError::RegAlloc(ref e) => e.fmt(f),
+            CtonError::Cancelled => write!(f, "compilation cancelled"),
+            CtonError::CodeTooLarge => write!(f, "code too large for branch ranges"),
+        }
+    }
+}
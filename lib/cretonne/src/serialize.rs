@@ -0,0 +1,1731 @@
+//! Binary serialization of functions.
+//!
+//! The textual IL is easy to read, but it is slow to parse and it doesn't preserve the entity
+//! numbers. Embedders that keep functions in a compilation cache, and tools that store large
+//! corpora of functions, can use `encode_function()` to write a `Function` in a compact binary
+//! format instead. `decode_function()` reads it back with all the same entity numbers, so the
+//! decoded function prints the same text and compiles to the same code as the original.
+//!
+//! Everything in the `Function` is serialized: the entities, the layout, the preamble tables, the
+//! instruction encodings and value locations, and the source locations and other annotations.
+//!
+//! The data starts with the magic bytes `cton` followed by the format version. The version is
+//! bumped whenever the encoding changes, including changes to the list of opcodes, and
+//! `decode_function()` rejects data written with a different version. A cache should treat that
+//! error as a miss.
+//!
+//! Integers are written as LEB128 variable-length numbers, and signed integers are zigzag encoded
+//! first. Entity references are written as their index. The decoder checks that entity references
+//! are in range and that the values are linked consistently, so malformed data results in a
+//! `DecodeError` rather than a panic. It doesn't run the verifier, though.
+
+use entity_map::{EntityMap, EntityRef};
+use ir::{Function, ExternalName, Signature, ArgumentType, ArgumentExtension, ArgumentPurpose,
+         ArgumentLoc, CallConv, ExtFuncData, GlobalVarData, StackSlotData, StackSlotKind,
+         JumpTableData, HeapData, InstructionData, Opcode, Type, TrapCode, MemFlags,
+         AtomicOrdering, ValueLoc, SourceLoc, ValueLabel, DataFlowGraph, Layout};
+use ir::{Ebb, Inst, Value, StackSlot, JumpTable, FuncRef, SigRef, Heap, GlobalVar};
+use ir::condcodes::{IntCC, FloatCC};
+use ir::immediates::{Imm64, Ieee32, Ieee64};
+use ir::instructions::{InstructionFormat, UnaryImmVectorData, TernaryOverflowData,
+                       LoadComplexData, StoreComplexData, ValueList, ValueListPool};
+use ir::types;
+use isa::Encoding;
+use packed_option::{PackedOption, ReservedValue};
+use std::fmt;
+use std::result;
+use std::str;
+use std::boxed::Box;
+use std::vec::Vec;
+
+/// The magic bytes at the start of a serialized function.
+const MAGIC: &'static [u8; 4] = b"cton";
+
+/// The version of the serialization format written by `encode_function()`.
+pub const FORMAT_VERSION: u32 = 9;
+
+/// An error reading a serialized function.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    /// The data doesn't start with the magic bytes of a serialized function.
+    BadMagic,
+
+    /// The function was serialized with another version of the format.
+    Version(u32),
+
+    /// The data ends in the middle of the function.
+    Truncated,
+
+    /// There is more data after the end of the function.
+    TrailingData,
+
+    /// The data is malformed.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::BadMagic => write!(f, "not a serialized function"),
+            DecodeError::Version(v) => {
+                write!(f,
+                       "unsupported format version {}, expected {}",
+                       v,
+                       FORMAT_VERSION)
+            }
+            DecodeError::Truncated => write!(f, "unexpected end of data"),
+            DecodeError::TrailingData => write!(f, "data after the end of the function"),
+            DecodeError::Invalid(msg) => write!(f, "invalid data: {}", msg),
+        }
+    }
+}
+
+/// Result of decoding serialized data.
+pub type Result<T> = result::Result<T, DecodeError>;
+
+/// Serialize `func` in the binary format.
+pub fn encode_function(func: &Function) -> Vec<u8> {
+    let mut enc = Encoder::new();
+    enc.bytes.extend_from_slice(MAGIC);
+    enc.uint(FORMAT_VERSION as u64);
+    func.encode(&mut enc);
+    enc.finish()
+}
+
+/// Deserialize a function written by `encode_function()`.
+pub fn decode_function(bytes: &[u8]) -> Result<Function> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(DecodeError::BadMagic);
+    }
+    let mut dec = Decoder::new(&bytes[MAGIC.len()..]);
+    let version = dec.u32()?;
+    if version != FORMAT_VERSION {
+        return Err(DecodeError::Version(version));
+    }
+    let func = Function::decode(&mut dec)?;
+    if !dec.is_empty() {
+        return Err(DecodeError::TrailingData);
+    }
+    Ok(func)
+}
+
+/// A type that can be written by an `Encoder` and read back by a `Decoder`.
+pub trait Serialize: Sized {
+    /// Write `self` to `enc`.
+    fn encode(&self, enc: &mut Encoder);
+
+    /// Read a value written by `encode()`.
+    fn decode(dec: &mut Decoder) -> Result<Self>;
+}
+
+/// Writer of serialized data.
+pub struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    /// Create an empty encoder.
+    pub fn new() -> Encoder {
+        Encoder { bytes: Vec::new() }
+    }
+
+    /// Get the serialized data.
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Write a byte.
+    pub fn u8(&mut self, x: u8) {
+        self.bytes.push(x);
+    }
+
+    /// Write a boolean.
+    pub fn bool(&mut self, x: bool) {
+        self.u8(x as u8);
+    }
+
+    /// Write an unsigned integer.
+    pub fn uint(&mut self, mut x: u64) {
+        while x >= 0x80 {
+            self.u8(x as u8 | 0x80);
+            x >>= 7;
+        }
+        self.u8(x as u8);
+    }
+
+    /// Write a signed integer.
+    pub fn int(&mut self, x: i64) {
+        self.uint(((x << 1) ^ (x >> 63)) as u64);
+    }
+
+    /// Write a length-prefixed byte string.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.uint(bytes.len() as u64);
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Write an entity reference.
+    pub fn entity<E: EntityRef>(&mut self, e: E) {
+        self.uint(e.index() as u64);
+    }
+
+    /// Write an optional entity reference.
+    pub fn packed<E: EntityRef + ReservedValue>(&mut self, e: PackedOption<E>) {
+        match e.expand() {
+            None => self.uint(0),
+            Some(e) => self.uint(e.index() as u64 + 1),
+        }
+    }
+
+    /// Write an optional unsigned integer.
+    fn option_u32(&mut self, x: Option<u32>) {
+        match x {
+            None => self.uint(0),
+            Some(x) => self.uint(x as u64 + 1),
+        }
+    }
+
+    /// Write a list of values.
+    fn values(&mut self, values: &[Value]) {
+        self.uint(values.len() as u64);
+        for &v in values {
+            self.entity(v);
+        }
+    }
+
+    /// Write the entries of the secondary map `map`.
+    fn map<K, V, F>(&mut self, map: &EntityMap<K, V>, mut f: F)
+        where K: EntityRef,
+              F: FnMut(&mut Encoder, &V)
+    {
+        self.uint(map.keys().count() as u64);
+        for k in map.keys() {
+            f(self, &map[k]);
+        }
+    }
+}
+
+/// The number of entities of each kind in the function being decoded.
+///
+/// The decoder uses these to check the entity references it reads.
+#[derive(Default)]
+pub struct EntityCounts {
+    /// Number of EBBs.
+    pub ebbs: usize,
+    /// Number of instructions.
+    pub insts: usize,
+    /// Number of entries in the extended value table.
+    pub extended_values: usize,
+    /// Number of stack slots.
+    pub stack_slots: usize,
+    /// Number of jump tables.
+    pub jump_tables: usize,
+    /// Number of heaps.
+    pub heaps: usize,
+    /// Number of signatures.
+    pub signatures: usize,
+    /// Number of external functions.
+    pub ext_funcs: usize,
+    /// Number of global variables.
+    pub global_vars: usize,
+}
+
+/// Reader of serialized data.
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    opcodes: Vec<Opcode>,
+
+    /// Number of entities in the function, used to check entity references.
+    pub counts: EntityCounts,
+}
+
+impl<'a> Decoder<'a> {
+    /// Create a decoder reading `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Decoder<'a> {
+        Decoder {
+            bytes: bytes,
+            pos: 0,
+            opcodes: Opcode::all(),
+            counts: EntityCounts::default(),
+        }
+    }
+
+    /// Have all the bytes been read?
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+
+    /// Read a byte.
+    pub fn u8(&mut self) -> Result<u8> {
+        match self.bytes.get(self.pos) {
+            Some(&b) => {
+                self.pos += 1;
+                Ok(b)
+            }
+            None => Err(DecodeError::Truncated),
+        }
+    }
+
+    /// Read a boolean.
+    pub fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::Invalid("bad boolean")),
+        }
+    }
+
+    /// Read an unsigned integer.
+    pub fn uint(&mut self) -> Result<u64> {
+        let mut x = 0;
+        let mut shift = 0;
+        loop {
+            let b = self.u8()?;
+            if shift == 63 && b > 1 {
+                return Err(DecodeError::Invalid("integer overflow"));
+            }
+            x |= ((b & 0x7f) as u64) << shift;
+            if b < 0x80 {
+                return Ok(x);
+            }
+            shift += 7;
+            if shift > 63 {
+                return Err(DecodeError::Invalid("integer overflow"));
+            }
+        }
+    }
+
+    /// Read a signed integer.
+    pub fn int(&mut self) -> Result<i64> {
+        let x = self.uint()?;
+        Ok((x >> 1) as i64 ^ -((x & 1) as i64))
+    }
+
+    /// Read an unsigned integer that must fit in 32 bits.
+    pub fn u32(&mut self) -> Result<u32> {
+        let x = self.uint()?;
+        if x > u32::max_value() as u64 {
+            return Err(DecodeError::Invalid("integer overflow"));
+        }
+        Ok(x as u32)
+    }
+
+    /// Read an unsigned integer that must fit in 16 bits.
+    pub fn u16(&mut self) -> Result<u16> {
+        let x = self.uint()?;
+        if x > u16::max_value() as u64 {
+            return Err(DecodeError::Invalid("integer overflow"));
+        }
+        Ok(x as u16)
+    }
+
+    /// Read a length-prefixed byte string.
+    pub fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.len()?;
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Read the length of a sequence of items which take at least one byte each.
+    ///
+    /// Checking the length against the remaining data prevents huge allocations for bad lengths.
+    pub fn len(&mut self) -> Result<usize> {
+        let len = self.uint()?;
+        if len > (self.bytes.len() - self.pos) as u64 {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(len as usize)
+    }
+
+    /// Read a reference to one of the first `count` entities of type `E`.
+    pub fn entity<E: EntityRef>(&mut self, count: usize) -> Result<E> {
+        let index = self.uint()?;
+        if index >= count as u64 {
+            return Err(DecodeError::Invalid("entity reference out of range"));
+        }
+        Ok(E::new(index as usize))
+    }
+
+    /// Read an optional reference to one of the first `count` entities of type `E`.
+    pub fn packed<E: EntityRef + ReservedValue>(&mut self,
+                                                count: usize)
+                                                -> Result<PackedOption<E>> {
+        let index = self.uint()?;
+        if index > count as u64 {
+            return Err(DecodeError::Invalid("entity reference out of range"));
+        }
+        Ok(if index == 0 {
+               None.into()
+           } else {
+               Some(E::new(index as usize - 1)).into()
+           })
+    }
+
+    /// Read an EBB reference.
+    pub fn ebb(&mut self) -> Result<Ebb> {
+        let count = self.counts.ebbs;
+        self.entity(count)
+    }
+
+    /// Read an instruction reference.
+    pub fn inst(&mut self) -> Result<Inst> {
+        let count = self.counts.insts;
+        self.entity(count)
+    }
+
+    /// Read a value reference.
+    ///
+    /// A value is either the first result of an instruction or an extended value table entry.
+    pub fn value(&mut self) -> Result<Value> {
+        let bits = self.uint()?;
+        let count = if bits % 2 == 0 {
+            self.counts.insts
+        } else {
+            self.counts.extended_values
+        };
+        if bits / 2 >= count as u64 {
+            return Err(DecodeError::Invalid("value reference out of range"));
+        }
+        Ok(Value::new(bits as usize))
+    }
+
+    /// Read an optional value reference.
+    pub fn packed_value(&mut self) -> Result<PackedOption<Value>> {
+        // Values are biased by one like the other optional entities.
+        let bits = match self.uint()? {
+            0 => return Ok(None.into()),
+            n => n - 1,
+        };
+        let count = if bits % 2 == 0 {
+            self.counts.insts
+        } else {
+            self.counts.extended_values
+        };
+        if bits / 2 >= count as u64 {
+            return Err(DecodeError::Invalid("value reference out of range"));
+        }
+        Ok(Some(Value::new(bits as usize)).into())
+    }
+
+    /// Read a stack slot reference.
+    pub fn stack_slot(&mut self) -> Result<StackSlot> {
+        let count = self.counts.stack_slots;
+        self.entity(count)
+    }
+
+    /// Read a jump table reference.
+    pub fn jump_table(&mut self) -> Result<JumpTable> {
+        let count = self.counts.jump_tables;
+        self.entity(count)
+    }
+
+    /// Read a heap reference.
+    pub fn heap(&mut self) -> Result<Heap> {
+        let count = self.counts.heaps;
+        self.entity(count)
+    }
+
+    /// Read a signature reference.
+    pub fn sig_ref(&mut self) -> Result<SigRef> {
+        let count = self.counts.signatures;
+        self.entity(count)
+    }
+
+    /// Read an external function reference.
+    pub fn func_ref(&mut self) -> Result<FuncRef> {
+        let count = self.counts.ext_funcs;
+        self.entity(count)
+    }
+
+    /// Read a global variable reference.
+    pub fn global_var(&mut self) -> Result<GlobalVar> {
+        let count = self.counts.global_vars;
+        self.entity(count)
+    }
+
+    /// Read an opcode.
+    pub fn opcode(&mut self) -> Result<Opcode> {
+        let n = self.uint()?;
+        if n == 0 || n > self.opcodes.len() as u64 {
+            return Err(DecodeError::Invalid("unknown opcode"));
+        }
+        Ok(self.opcodes[n as usize - 1])
+    }
+
+    /// Read an optional unsigned integer.
+    fn option_u32(&mut self) -> Result<Option<u32>> {
+        let x = self.u32()?;
+        Ok(if x == 0 { None } else { Some(x - 1) })
+    }
+
+    /// Read a list of values, and return a value list in `pool` holding the `fixed` values
+    /// followed by the values read.
+    fn value_list(&mut self, fixed: &[Value], pool: &mut ValueListPool) -> Result<ValueList> {
+        let mut list = ValueList::default();
+        list.extend(fixed.iter().cloned(), pool);
+        for _ in 0..self.len()? {
+            let v = self.value()?;
+            list.push(v, pool);
+        }
+        Ok(list)
+    }
+
+    /// Read a secondary map with `decode` reading each entry.
+    fn map<K, V, F>(&mut self, mut decode: F) -> Result<EntityMap<K, V>>
+        where K: EntityRef,
+              V: Clone + Default,
+              F: FnMut(&mut Decoder<'a>) -> Result<V>
+    {
+        let len = self.len()?;
+        let mut map = EntityMap::with_capacity(len);
+        for k in map.keys() {
+            map[k] = decode(self)?;
+        }
+        Ok(map)
+    }
+
+    /// Read a value of the enum type listed in `variants`, written as its index in that list.
+    fn variant<T: Copy>(&mut self, variants: &[T], what: &'static str) -> Result<T> {
+        let n = self.uint()?;
+        variants
+            .get(n as usize)
+            .cloned()
+            .ok_or(DecodeError::Invalid(what))
+    }
+}
+
+// The variants of the fieldless enums, in the order of their discriminants.
+const INT_CCS: [IntCC; 10] = [IntCC::Equal,
+                              IntCC::NotEqual,
+                              IntCC::SignedLessThan,
+                              IntCC::SignedGreaterThanOrEqual,
+                              IntCC::SignedGreaterThan,
+                              IntCC::SignedLessThanOrEqual,
+                              IntCC::UnsignedLessThan,
+                              IntCC::UnsignedGreaterThanOrEqual,
+                              IntCC::UnsignedGreaterThan,
+                              IntCC::UnsignedLessThanOrEqual];
+
+const FLOAT_CCS: [FloatCC; 14] = [FloatCC::Ordered,
+                                  FloatCC::Unordered,
+                                  FloatCC::Equal,
+                                  FloatCC::NotEqual,
+                                  FloatCC::OrderedNotEqual,
+                                  FloatCC::UnorderedOrEqual,
+                                  FloatCC::LessThan,
+                                  FloatCC::LessThanOrEqual,
+                                  FloatCC::GreaterThan,
+                                  FloatCC::GreaterThanOrEqual,
+                                  FloatCC::UnorderedOrLessThan,
+                                  FloatCC::UnorderedOrLessThanOrEqual,
+                                  FloatCC::UnorderedOrGreaterThan,
+                                  FloatCC::UnorderedOrGreaterThanOrEqual];
+
+const ORDERINGS: [AtomicOrdering; 5] = [AtomicOrdering::Relaxed,
+                                        AtomicOrdering::Acquire,
+                                        AtomicOrdering::Release,
+                                        AtomicOrdering::AcqRel,
+                                        AtomicOrdering::SeqCst];
+
+const CALL_CONVS: [CallConv; 3] = [CallConv::SystemV,
+                                   CallConv::WindowsFastcall,
+                                   CallConv::Baldrdash];
+
+const EXTENSIONS: [ArgumentExtension; 3] = [ArgumentExtension::None,
+                                            ArgumentExtension::Uext,
+                                            ArgumentExtension::Sext];
+
+const PURPOSES: [ArgumentPurpose; 5] = [ArgumentPurpose::Normal,
+                                        ArgumentPurpose::StructReturn,
+                                        ArgumentPurpose::Link,
+                                        ArgumentPurpose::VMContext,
+                                        ArgumentPurpose::CalleeSaved];
+
+const STACK_SLOT_KINDS: [StackSlotKind; 3] = [StackSlotKind::ExplicitSlot,
+                                              StackSlotKind::SpillSlot,
+                                              StackSlotKind::OutgoingArg];
+
+// The scalar types that can be used as vector lanes. Vector types are written as the lane type
+// plus the log2 of the lane count.
+const LANE_TYPES: [Type; 15] = [types::VOID,
+                                types::B1,
+                                types::B8,
+                                types::B16,
+                                types::B32,
+                                types::B64,
+                                types::I8,
+                                types::I16,
+                                types::I32,
+                                types::I64,
+                                types::F32,
+                                types::F64,
+                                types::R32,
+                                types::R64,
+                                types::I128];
+
+// The special types which are neither lanes nor vectors.
+const SPECIAL_TYPES: [Type; 2] = [types::IFLAGS, types::FFLAGS];
+
+impl Serialize for Type {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.u8(self.index() as u8);
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<Type> {
+        let bits = dec.u8()?;
+        if let Some(&ty) = SPECIAL_TYPES.iter().find(|t| t.index() as u8 == bits) {
+            return Ok(ty);
+        }
+        let lane = LANE_TYPES
+            .iter()
+            .cloned()
+            .find(|t| t.index() as u8 == bits & 0x0f)
+            .ok_or(DecodeError::Invalid("bad type"))?;
+        match bits >> 4 {
+            0 => Ok(lane),
+            log2_lanes => lane.by(1 << log2_lanes).ok_or(DecodeError::Invalid("bad type")),
+        }
+    }
+}
+
+impl Serialize for ExternalName {
+    fn encode(&self, enc: &mut Encoder) {
+        match *self {
+            ExternalName::User { namespace, index } => {
+                enc.u8(0);
+                enc.uint(namespace as u64);
+                enc.uint(index as u64);
+            }
+            ExternalName::TestCase(ref name) => {
+                enc.u8(1);
+                enc.bytes(name.as_bytes());
+            }
+        }
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<ExternalName> {
+        match dec.u8()? {
+            0 => Ok(ExternalName::user(dec.u32()?, dec.u32()?)),
+            1 => {
+                let name = str::from_utf8(dec.bytes()?)
+                    .map_err(|_| DecodeError::Invalid("bad UTF-8 in name"))?;
+                Ok(ExternalName::testcase(name))
+            }
+            _ => Err(DecodeError::Invalid("bad external name")),
+        }
+    }
+}
+
+impl Serialize for ArgumentType {
+    fn encode(&self, enc: &mut Encoder) {
+        self.value_type.encode(enc);
+        enc.uint(self.extension as u64);
+        enc.bool(self.inreg);
+        enc.uint(self.purpose as u64);
+        match self.location {
+            ArgumentLoc::Unassigned => enc.u8(0),
+            ArgumentLoc::Reg(ru) => {
+                enc.u8(1);
+                enc.uint(ru as u64);
+            }
+            ArgumentLoc::Stack(offset) => {
+                enc.u8(2);
+                enc.uint(offset as u64);
+            }
+        }
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<ArgumentType> {
+        let value_type = Type::decode(dec)?;
+        let extension = dec.variant(&EXTENSIONS, "bad argument extension")?;
+        let inreg = dec.bool()?;
+        let purpose = dec.variant(&PURPOSES, "bad argument purpose")?;
+        let location = match dec.u8()? {
+            0 => ArgumentLoc::Unassigned,
+            1 => ArgumentLoc::Reg(dec.u16()?),
+            2 => ArgumentLoc::Stack(dec.u32()?),
+            _ => return Err(DecodeError::Invalid("bad argument location")),
+        };
+        Ok(ArgumentType {
+               value_type: value_type,
+               extension: extension,
+               inreg: inreg,
+               purpose: purpose,
+               location: location,
+           })
+    }
+}
+
+impl Serialize for Signature {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.uint(self.argument_types.len() as u64);
+        for arg in &self.argument_types {
+            arg.encode(enc);
+        }
+        enc.uint(self.return_types.len() as u64);
+        for ret in &self.return_types {
+            ret.encode(enc);
+        }
+        enc.option_u32(self.argument_bytes);
+        enc.uint(self.call_conv as u64);
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<Signature> {
+        let mut sig = Signature::new();
+        for _ in 0..dec.len()? {
+            sig.argument_types.push(ArgumentType::decode(dec)?);
+        }
+        for _ in 0..dec.len()? {
+            sig.return_types.push(ArgumentType::decode(dec)?);
+        }
+        sig.argument_bytes = dec.option_u32()?;
+        sig.call_conv = dec.variant(&CALL_CONVS, "bad calling convention")?;
+        Ok(sig)
+    }
+}
+
+impl Serialize for ExtFuncData {
+    fn encode(&self, enc: &mut Encoder) {
+        self.name.encode(enc);
+        enc.entity(self.signature);
+        enc.bool(self.cold);
+        enc.bool(self.noreturn);
+        enc.bool(self.colocated);
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<ExtFuncData> {
+        Ok(ExtFuncData {
+               name: ExternalName::decode(dec)?,
+               signature: dec.sig_ref()?,
+               cold: dec.bool()?,
+               noreturn: dec.bool()?,
+               colocated: dec.bool()?,
+           })
+    }
+}
+
+impl Serialize for GlobalVarData {
+    fn encode(&self, enc: &mut Encoder) {
+        self.name.encode(enc);
+        enc.bool(self.colocated);
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<GlobalVarData> {
+        Ok(GlobalVarData {
+               name: ExternalName::decode(dec)?,
+               colocated: dec.bool()?,
+           })
+    }
+}
+
+impl Serialize for StackSlotData {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.uint(self.kind as u64);
+        enc.uint(self.size as u64);
+        enc.option_u32(self.align);
+        enc.uint(self.offset as u64);
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<StackSlotData> {
+        Ok(StackSlotData {
+               kind: dec.variant(&STACK_SLOT_KINDS, "bad stack slot kind")?,
+               size: dec.u32()?,
+               align: dec.option_u32()?,
+               offset: dec.u32()?,
+           })
+    }
+}
+
+impl Serialize for HeapData {
+    fn encode(&self, enc: &mut Encoder) {
+        self.name.encode(enc);
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<HeapData> {
+        Ok(HeapData { name: ExternalName::decode(dec)? })
+    }
+}
+
+impl Serialize for JumpTableData {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.uint(self.len() as u64);
+        for idx in 0..self.len() {
+            enc.packed(self.get_entry(idx).into());
+        }
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<JumpTableData> {
+        let mut table = JumpTableData::new();
+        let len = dec.len()?;
+        for idx in 0..len {
+            let count = dec.counts.ebbs;
+            if let Some(ebb) = dec.packed::<Ebb>(count)?.expand() {
+                table.set_entry(idx, ebb);
+            }
+        }
+        // Grow the table to its full length when it ends with a hole.
+        if len > table.len() {
+            table.set_entry(len - 1, Ebb::new(0));
+            table.clear_entry(len - 1);
+        }
+        Ok(table)
+    }
+}
+
+impl Serialize for TrapCode {
+    fn encode(&self, enc: &mut Encoder) {
+        let code = match *self {
+            TrapCode::StackOverflow => 0,
+            TrapCode::HeapOutOfBounds => 1,
+            TrapCode::IntegerOverflow => 2,
+            TrapCode::IntegerDivisionByZero => 3,
+            TrapCode::BadConversionToInteger => 4,
+            TrapCode::User(code) => 5 + code as u64,
+        };
+        enc.uint(code);
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<TrapCode> {
+        Ok(match dec.uint()? {
+               0 => TrapCode::StackOverflow,
+               1 => TrapCode::HeapOutOfBounds,
+               2 => TrapCode::IntegerOverflow,
+               3 => TrapCode::IntegerDivisionByZero,
+               4 => TrapCode::BadConversionToInteger,
+               code if code - 5 <= u16::max_value() as u64 => TrapCode::User((code - 5) as u16),
+               _ => return Err(DecodeError::Invalid("bad trap code")),
+           })
+    }
+}
+
+impl Serialize for MemFlags {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.u8(self.notrap() as u8 | (self.aligned() as u8) << 1 | (self.readonly() as u8) << 2);
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<MemFlags> {
+        let bits = dec.u8()?;
+        if bits > 7 {
+            return Err(DecodeError::Invalid("bad memory flags"));
+        }
+        let mut flags = MemFlags::new();
+        if bits & 1 != 0 {
+            flags.set_notrap();
+        }
+        if bits & 2 != 0 {
+            flags.set_aligned();
+        }
+        if bits & 4 != 0 {
+            flags.set_readonly();
+        }
+        Ok(flags)
+    }
+}
+
+/// Write the instruction `inst` whose value lists are in `pool`.
+///
+/// This is not a `Serialize` implementation because the instruction arguments may live in the
+/// value list pool of the data flow graph.
+pub fn encode_inst(enc: &mut Encoder, inst: &InstructionData, pool: &ValueListPool) {
+    use ir::InstructionData::*;
+    enc.uint(inst.opcode() as u64);
+    match *inst {
+        Nullary { ty, .. } => ty.encode(enc),
+        Unary { ty, arg, .. } => {
+            ty.encode(enc);
+            enc.entity(arg);
+        }
+        UnaryImm { ty, imm, .. } => {
+            ty.encode(enc);
+            enc.int(imm.into());
+        }
+        UnaryBool { ty, imm, .. } => {
+            ty.encode(enc);
+            enc.bool(imm);
+        }
+        UnaryIeee32 { ty, imm, .. } => {
+            ty.encode(enc);
+            enc.uint(imm.bits() as u64);
+        }
+        UnaryIeee64 { ty, imm, .. } => {
+            ty.encode(enc);
+            enc.uint(imm.bits());
+        }
+        UnaryImmVector { ty, ref data, .. } => {
+            ty.encode(enc);
+            enc.bytes(&data.imm);
+        }
+        UnarySplit { ty, second_result, arg, .. } => {
+            ty.encode(enc);
+            enc.packed(second_result);
+            enc.entity(arg);
+        }
+        Binary { ty, args, .. } |
+        IntCompare { ty, args, .. } |
+        FloatCompare { ty, args, .. } |
+        InsertLane { ty, args, .. } |
+        AtomicRmw { ty, args, .. } => {
+            ty.encode(enc);
+            enc.values(&args);
+            match *inst {
+                IntCompare { cond, .. } => enc.uint(cond as u64),
+                FloatCompare { cond, .. } => enc.uint(cond as u64),
+                InsertLane { lane, .. } => enc.u8(lane),
+                AtomicRmw { ordering, .. } => enc.uint(ordering as u64),
+                _ => {}
+            }
+        }
+        BinaryImm { ty, arg, imm, .. } |
+        BinaryImmRev { ty, arg, imm, .. } => {
+            ty.encode(enc);
+            enc.entity(arg);
+            enc.int(imm.into());
+        }
+        BinaryOverflow { ty, second_result, args, .. } => {
+            ty.encode(enc);
+            enc.packed(second_result);
+            enc.values(&args);
+        }
+        Ternary { ty, args, .. } => {
+            ty.encode(enc);
+            enc.values(&args);
+        }
+        TernaryOverflow { ty, second_result, ref data, .. } => {
+            ty.encode(enc);
+            enc.packed(second_result);
+            enc.values(&data.args);
+        }
+        ExtractLane { ty, lane, arg, .. } => {
+            ty.encode(enc);
+            enc.u8(lane);
+            enc.entity(arg);
+        }
+        IntCond { ty, cond, arg, .. } => {
+            ty.encode(enc);
+            enc.uint(cond as u64);
+            enc.entity(arg);
+        }
+        FloatCond { ty, cond, arg, .. } => {
+            ty.encode(enc);
+            enc.uint(cond as u64);
+            enc.entity(arg);
+        }
+        IntSelect { ty, cond, args, .. } => {
+            ty.encode(enc);
+            enc.uint(cond as u64);
+            enc.values(&args);
+        }
+        Jump { ty, destination, ref args, .. } => {
+            ty.encode(enc);
+            enc.entity(destination);
+            enc.values(args.as_slice(pool));
+        }
+        Branch { ty, destination, ref args, .. } => {
+            let args = args.as_slice(pool);
+            ty.encode(enc);
+            enc.entity(args[0]);
+            enc.entity(destination);
+            enc.values(&args[1..]);
+        }
+        BranchIcmp { ty, cond, destination, ref args, .. } => {
+            let args = args.as_slice(pool);
+            ty.encode(enc);
+            enc.uint(cond as u64);
+            enc.values(&args[..2]);
+            enc.entity(destination);
+            enc.values(&args[2..]);
+        }
+        BranchTable { ty, arg, table, .. } => {
+            ty.encode(enc);
+            enc.entity(arg);
+            enc.entity(table);
+        }
+        BranchTableEntry { ty, args, imm, table, .. } => {
+            ty.encode(enc);
+            enc.values(&args);
+            enc.u8(imm);
+            enc.entity(table);
+        }
+        BranchTableBase { ty, table, .. } => {
+            ty.encode(enc);
+            enc.entity(table);
+        }
+        Trap { ty, code, .. } => {
+            ty.encode(enc);
+            code.encode(enc);
+        }
+        CondTrap { ty, arg, code, .. } => {
+            ty.encode(enc);
+            enc.entity(arg);
+            code.encode(enc);
+        }
+        IntCondTrap { ty, cond, arg, code, .. } => {
+            ty.encode(enc);
+            enc.uint(cond as u64);
+            enc.entity(arg);
+            code.encode(enc);
+        }
+        BinaryTrap { ty, args, code, .. } => {
+            ty.encode(enc);
+            enc.values(&args);
+            code.encode(enc);
+        }
+        Call { ty, second_result, func_ref, ref args, .. } => {
+            ty.encode(enc);
+            enc.packed(second_result);
+            enc.entity(func_ref);
+            enc.values(args.as_slice(pool));
+        }
+        IndirectCall { ty, second_result, sig_ref, ref args, .. } => {
+            let args = args.as_slice(pool);
+            ty.encode(enc);
+            enc.packed(second_result);
+            enc.entity(args[0]);
+            enc.entity(sig_ref);
+            enc.values(&args[1..]);
+        }
+        FuncAddr { ty, func_ref, .. } => {
+            ty.encode(enc);
+            enc.entity(func_ref);
+        }
+        Return { ty, ref args, .. } => {
+            ty.encode(enc);
+            enc.values(args.as_slice(pool));
+        }
+        ReturnReg { ty, ref args, .. } => {
+            let args = args.as_slice(pool);
+            ty.encode(enc);
+            enc.entity(args[0]);
+            enc.values(&args[1..]);
+        }
+        HeapAddr { ty, heap, arg, imm, .. } => {
+            ty.encode(enc);
+            enc.entity(heap);
+            enc.entity(arg);
+            enc.uint(imm as u64);
+        }
+        UnaryGlobalVar { ty, global_var, .. } => {
+            ty.encode(enc);
+            enc.entity(global_var);
+        }
+        Load { ty, flags, arg, offset, .. } => {
+            ty.encode(enc);
+            flags.encode(enc);
+            enc.entity(arg);
+            enc.int(offset as i64);
+        }
+        Store { ty, flags, args, offset, .. } => {
+            ty.encode(enc);
+            flags.encode(enc);
+            enc.values(&args);
+            enc.int(offset as i64);
+        }
+        LoadComplex { ty, ref data, .. } => {
+            ty.encode(enc);
+            data.flags.encode(enc);
+            enc.values(&data.args);
+            enc.u8(data.shift);
+            enc.int(data.offset as i64);
+        }
+        StoreComplex { ty, ref data, .. } => {
+            ty.encode(enc);
+            data.flags.encode(enc);
+            enc.values(&data.args);
+            enc.u8(data.shift);
+            enc.int(data.offset as i64);
+        }
+        AtomicLoad { ty, ordering, arg, .. } => {
+            ty.encode(enc);
+            enc.uint(ordering as u64);
+            enc.entity(arg);
+        }
+        AtomicCas { ty, ordering, args, .. } => {
+            ty.encode(enc);
+            enc.uint(ordering as u64);
+            enc.values(&args);
+        }
+        StackLoad { ty, stack_slot, offset, .. } => {
+            ty.encode(enc);
+            enc.entity(stack_slot);
+            enc.uint(offset as u64);
+        }
+        StackStore { ty, arg, stack_slot, offset, .. } => {
+            ty.encode(enc);
+            enc.entity(arg);
+            enc.entity(stack_slot);
+            enc.uint(offset as u64);
+        }
+        RegMove { ty, arg, src, dst, .. } => {
+            ty.encode(enc);
+            enc.entity(arg);
+            enc.uint(src as u64);
+            enc.uint(dst as u64);
+        }
+    }
+}
+
+/// Read an instruction, and put its value lists in `pool`.
+pub fn decode_inst(dec: &mut Decoder, pool: &mut ValueListPool) -> Result<InstructionData> {
+    use ir::InstructionData::*;
+    let opcode = dec.opcode()?;
+    let ty = Type::decode(dec)?;
+    Ok(match opcode.format() {
+           InstructionFormat::Nullary => Nullary { opcode: opcode, ty: ty },
+           InstructionFormat::Unary => {
+               Unary {
+                   opcode: opcode,
+                   ty: ty,
+                   arg: dec.value()?,
+               }
+           }
+           InstructionFormat::UnaryImm => {
+               UnaryImm {
+                   opcode: opcode,
+                   ty: ty,
+                   imm: Imm64::new(dec.int()?),
+               }
+           }
+           InstructionFormat::UnaryBool => {
+               UnaryBool {
+                   opcode: opcode,
+                   ty: ty,
+                   imm: dec.bool()?,
+               }
+           }
+           InstructionFormat::UnaryIeee32 => {
+               UnaryIeee32 {
+                   opcode: opcode,
+                   ty: ty,
+                   imm: Ieee32::from_bits(dec.u32()?),
+               }
+           }
+           InstructionFormat::UnaryIeee64 => {
+               UnaryIeee64 {
+                   opcode: opcode,
+                   ty: ty,
+                   imm: Ieee64::from_bits(dec.uint()?),
+               }
+           }
+           InstructionFormat::UnaryImmVector => {
+               UnaryImmVector {
+                   opcode: opcode,
+                   ty: ty,
+                   data: Box::new(UnaryImmVectorData { imm: dec.bytes()?.to_vec() }),
+               }
+           }
+           InstructionFormat::UnarySplit => {
+               UnarySplit {
+                   opcode: opcode,
+                   ty: ty,
+                   second_result: dec.packed_value()?,
+                   arg: dec.value()?,
+               }
+           }
+           InstructionFormat::Binary => {
+               Binary {
+                   opcode: opcode,
+                   ty: ty,
+                   args: decode_args(dec)?,
+               }
+           }
+           InstructionFormat::IntCompare => {
+               let args = decode_args(dec)?;
+               IntCompare {
+                   opcode: opcode,
+                   ty: ty,
+                   cond: dec.variant(&INT_CCS, "bad condition code")?,
+                   args: args,
+               }
+           }
+           InstructionFormat::FloatCompare => {
+               let args = decode_args(dec)?;
+               FloatCompare {
+                   opcode: opcode,
+                   ty: ty,
+                   cond: dec.variant(&FLOAT_CCS, "bad condition code")?,
+                   args: args,
+               }
+           }
+           InstructionFormat::IntCond => {
+               IntCond {
+                   opcode: opcode,
+                   ty: ty,
+                   cond: dec.variant(&INT_CCS, "bad condition code")?,
+                   arg: dec.value()?,
+               }
+           }
+           InstructionFormat::FloatCond => {
+               FloatCond {
+                   opcode: opcode,
+                   ty: ty,
+                   cond: dec.variant(&FLOAT_CCS, "bad condition code")?,
+                   arg: dec.value()?,
+               }
+           }
+           InstructionFormat::IntSelect => {
+               IntSelect {
+                   opcode: opcode,
+                   ty: ty,
+                   cond: dec.variant(&INT_CCS, "bad condition code")?,
+                   args: decode_args3(dec)?,
+               }
+           }
+           InstructionFormat::InsertLane => {
+               let args = decode_args(dec)?;
+               InsertLane {
+                   opcode: opcode,
+                   ty: ty,
+                   lane: dec.u8()?,
+                   args: args,
+               }
+           }
+           InstructionFormat::AtomicRmw => {
+               let args = decode_args(dec)?;
+               AtomicRmw {
+                   opcode: opcode,
+                   ty: ty,
+                   ordering: dec.variant(&ORDERINGS, "bad atomic ordering")?,
+                   args: args,
+               }
+           }
+           InstructionFormat::BinaryImm => {
+               BinaryImm {
+                   opcode: opcode,
+                   ty: ty,
+                   arg: dec.value()?,
+                   imm: Imm64::new(dec.int()?),
+               }
+           }
+           InstructionFormat::BinaryImmRev => {
+               BinaryImmRev {
+                   opcode: opcode,
+                   ty: ty,
+                   arg: dec.value()?,
+                   imm: Imm64::new(dec.int()?),
+               }
+           }
+           InstructionFormat::BinaryOverflow => {
+               BinaryOverflow {
+                   opcode: opcode,
+                   ty: ty,
+                   second_result: dec.packed_value()?,
+                   args: decode_args(dec)?,
+               }
+           }
+           InstructionFormat::Ternary => {
+               Ternary {
+                   opcode: opcode,
+                   ty: ty,
+                   args: decode_args3(dec)?,
+               }
+           }
+           InstructionFormat::TernaryOverflow => {
+               TernaryOverflow {
+                   opcode: opcode,
+                   ty: ty,
+                   second_result: dec.packed_value()?,
+                   data: Box::new(TernaryOverflowData { args: decode_args3(dec)? }),
+               }
+           }
+           InstructionFormat::ExtractLane => {
+               ExtractLane {
+                   opcode: opcode,
+                   ty: ty,
+                   lane: dec.u8()?,
+                   arg: dec.value()?,
+               }
+           }
+           InstructionFormat::Jump => {
+               Jump {
+                   opcode: opcode,
+                   ty: ty,
+                   destination: dec.ebb()?,
+                   args: dec.value_list(&[], pool)?,
+               }
+           }
+           InstructionFormat::Branch => {
+               let arg = dec.value()?;
+               Branch {
+                   opcode: opcode,
+                   ty: ty,
+                   destination: dec.ebb()?,
+                   args: dec.value_list(&[arg], pool)?,
+               }
+           }
+           InstructionFormat::BranchIcmp => {
+               let cond = dec.variant(&INT_CCS, "bad condition code")?;
+               let args = decode_args(dec)?;
+               BranchIcmp {
+                   opcode: opcode,
+                   ty: ty,
+                   cond: cond,
+                   destination: dec.ebb()?,
+                   args: dec.value_list(&args, pool)?,
+               }
+           }
+           InstructionFormat::BranchTable => {
+               BranchTable {
+                   opcode: opcode,
+                   ty: ty,
+                   arg: dec.value()?,
+                   table: dec.jump_table()?,
+               }
+           }
+           InstructionFormat::BranchTableEntry => {
+               BranchTableEntry {
+                   opcode: opcode,
+                   ty: ty,
+                   args: decode_args(dec)?,
+                   imm: dec.u8()?,
+                   table: dec.jump_table()?,
+               }
+           }
+           InstructionFormat::BranchTableBase => {
+               BranchTableBase {
+                   opcode: opcode,
+                   ty: ty,
+                   table: dec.jump_table()?,
+               }
+           }
+           InstructionFormat::Trap => {
+               Trap {
+                   opcode: opcode,
+                   ty: ty,
+                   code: TrapCode::decode(dec)?,
+               }
+           }
+           InstructionFormat::CondTrap => {
+               CondTrap {
+                   opcode: opcode,
+                   ty: ty,
+                   arg: dec.value()?,
+                   code: TrapCode::decode(dec)?,
+               }
+           }
+           InstructionFormat::IntCondTrap => {
+               IntCondTrap {
+                   opcode: opcode,
+                   ty: ty,
+                   cond: dec.variant(&INT_CCS, "bad condition code")?,
+                   arg: dec.value()?,
+                   code: TrapCode::decode(dec)?,
+               }
+           }
+           InstructionFormat::BinaryTrap => {
+               BinaryTrap {
+                   opcode: opcode,
+                   ty: ty,
+                   args: decode_args(dec)?,
+                   code: TrapCode::decode(dec)?,
+               }
+           }
+           InstructionFormat::Call => {
+               Call {
+                   opcode: opcode,
+                   ty: ty,
+                   second_result: dec.packed_value()?,
+                   func_ref: dec.func_ref()?,
+                   args: dec.value_list(&[], pool)?,
+               }
+           }
+           InstructionFormat::IndirectCall => {
+               let second_result = dec.packed_value()?;
+               let arg = dec.value()?;
+               IndirectCall {
+                   opcode: opcode,
+                   ty: ty,
+                   second_result: second_result,
+                   sig_ref: dec.sig_ref()?,
+                   args: dec.value_list(&[arg], pool)?,
+               }
+           }
+           InstructionFormat::FuncAddr => {
+               FuncAddr {
+                   opcode: opcode,
+                   ty: ty,
+                   func_ref: dec.func_ref()?,
+               }
+           }
+           InstructionFormat::Return => {
+               Return {
+                   opcode: opcode,
+                   ty: ty,
+                   args: dec.value_list(&[], pool)?,
+               }
+           }
+           InstructionFormat::ReturnReg => {
+               let arg = dec.value()?;
+               ReturnReg {
+                   opcode: opcode,
+                   ty: ty,
+                   args: dec.value_list(&[arg], pool)?,
+               }
+           }
+           InstructionFormat::HeapAddr => {
+               HeapAddr {
+                   opcode: opcode,
+                   ty: ty,
+                   heap: dec.heap()?,
+                   arg: dec.value()?,
+                   imm: dec.u32()?,
+               }
+           }
+           InstructionFormat::UnaryGlobalVar => {
+               UnaryGlobalVar {
+                   opcode: opcode,
+                   ty: ty,
+                   global_var: dec.global_var()?,
+               }
+           }
+           InstructionFormat::Load => {
+               Load {
+                   opcode: opcode,
+                   ty: ty,
+                   flags: MemFlags::decode(dec)?,
+                   arg: dec.value()?,
+                   offset: decode_offset(dec)?,
+               }
+           }
+           InstructionFormat::Store => {
+               Store {
+                   opcode: opcode,
+                   ty: ty,
+                   flags: MemFlags::decode(dec)?,
+                   args: decode_args(dec)?,
+                   offset: decode_offset(dec)?,
+               }
+           }
+           InstructionFormat::LoadComplex => {
+               LoadComplex {
+                   opcode: opcode,
+                   ty: ty,
+                   data: Box::new(LoadComplexData {
+                                      flags: MemFlags::decode(dec)?,
+                                      args: decode_args(dec)?,
+                                      shift: dec.u8()?,
+                                      offset: decode_offset(dec)?,
+                                  }),
+               }
+           }
+           InstructionFormat::StoreComplex => {
+               StoreComplex {
+                   opcode: opcode,
+                   ty: ty,
+                   data: Box::new(StoreComplexData {
+                                      flags: MemFlags::decode(dec)?,
+                                      args: decode_args3(dec)?,
+                                      shift: dec.u8()?,
+                                      offset: decode_offset(dec)?,
+                                  }),
+               }
+           }
+           InstructionFormat::AtomicLoad => {
+               AtomicLoad {
+                   opcode: opcode,
+                   ty: ty,
+                   ordering: dec.variant(&ORDERINGS, "bad atomic ordering")?,
+                   arg: dec.value()?,
+               }
+           }
+           InstructionFormat::AtomicCas => {
+               AtomicCas {
+                   opcode: opcode,
+                   ty: ty,
+                   ordering: dec.variant(&ORDERINGS, "bad atomic ordering")?,
+                   args: decode_args3(dec)?,
+               }
+           }
+           InstructionFormat::StackLoad => {
+               StackLoad {
+                   opcode: opcode,
+                   ty: ty,
+                   stack_slot: dec.stack_slot()?,
+                   offset: dec.u32()?,
+               }
+           }
+           InstructionFormat::StackStore => {
+               StackStore {
+                   opcode: opcode,
+                   ty: ty,
+                   arg: dec.value()?,
+                   stack_slot: dec.stack_slot()?,
+                   offset: dec.u32()?,
+               }
+           }
+           InstructionFormat::RegMove => {
+               RegMove {
+                   opcode: opcode,
+                   ty: ty,
+                   arg: dec.value()?,
+                   src: dec.u16()?,
+                   dst: dec.u16()?,
+               }
+           }
+       })
+}
+
+/// Read the two value operands of an instruction, written as a list by `Encoder::values()`.
+fn decode_args(dec: &mut Decoder) -> Result<[Value; 2]> {
+    if dec.uint()? != 2 {
+        return Err(DecodeError::Invalid("wrong number of arguments"));
+    }
+    Ok([dec.value()?, dec.value()?])
+}
+
+/// Read the three value operands of an instruction.
+fn decode_args3(dec: &mut Decoder) -> Result<[Value; 3]> {
+    if dec.uint()? != 3 {
+        return Err(DecodeError::Invalid("wrong number of arguments"));
+    }
+    Ok([dec.value()?, dec.value()?, dec.value()?])
+}
+
+/// Read a signed 32-bit address offset.
+fn decode_offset(dec: &mut Decoder) -> Result<i32> {
+    let x = dec.int()?;
+    if x < i32::min_value() as i64 || x > i32::max_value() as i64 {
+        return Err(DecodeError::Invalid("integer overflow"));
+    }
+    Ok(x as i32)
+}
+
+impl Serialize for Function {
+    fn encode(&self, enc: &mut Encoder) {
+        self.name.encode(enc);
+        self.signature.encode(enc);
+
+        // The jump table entries refer to EBBs, so they are written after the data flow graph.
+        // Their number has to come first, since the instructions refer to them.
+        enc.uint(self.stack_slots.len() as u64);
+        enc.uint(self.heaps.len() as u64);
+        enc.uint(self.jump_tables.len() as u64);
+        for ss in self.stack_slots.keys() {
+            self.stack_slots[ss].encode(enc);
+        }
+        for heap in self.heaps.keys() {
+            self.heaps[heap].encode(enc);
+        }
+        self.dfg.encode(enc);
+        enc.packed(self.stack_limit.into());
+        for jt in self.jump_tables.keys() {
+            self.jump_tables[jt].encode(enc);
+        }
+
+        // The layout is written as the EBBs in order, each with its instructions.
+        enc.uint(self.layout.ebbs().count() as u64);
+        for ebb in self.layout.ebbs() {
+            enc.entity(ebb);
+            enc.uint(self.layout.ebb_insts(ebb).count() as u64);
+            for inst in self.layout.ebb_insts(ebb) {
+                enc.entity(inst);
+            }
+        }
+
+        enc.map(&self.encodings, |enc, e| {
+            enc.uint(e.recipe() as u64);
+            enc.uint(e.bits() as u64);
+        });
+        enc.map(&self.locations, |enc, loc| match *loc {
+            ValueLoc::Unassigned => enc.u8(0),
+            ValueLoc::Reg(ru) => {
+                enc.u8(1);
+                enc.uint(ru as u64);
+            }
+            ValueLoc::Stack(ss) => {
+                enc.u8(2);
+                enc.entity(ss);
+            }
+        });
+        enc.map(&self.edge_weights, |enc, &w| enc.option_u32(w));
+        enc.map(&self.srclocs, |enc, loc| enc.uint(loc.bits() as u64));
+        enc.map(&self.value_labels,
+                |enc, &label| enc.option_u32(label.map(|l| l.index())));
+        enc.map(&self.offsets, |enc, &offset| enc.uint(offset as u64));
+        enc.map(&self.stack_offsets, |enc, &offset| enc.uint(offset as u64));
+        enc.map(&self.jt_offsets, |enc, &offset| enc.uint(offset as u64));
+    }
+
+    fn decode(dec: &mut Decoder) -> Result<Function> {
+        let mut func = Function::with_name_signature(ExternalName::decode(dec)?,
+                                                     Signature::decode(dec)?);
+
+        dec.counts.stack_slots = dec.len()?;
+        dec.counts.heaps = dec.len()?;
+        dec.counts.jump_tables = dec.len()?;
+        for _ in 0..dec.counts.stack_slots {
+            func.stack_slots.push(StackSlotData::decode(dec)?);
+        }
+        for _ in 0..dec.counts.heaps {
+            func.heaps.push(HeapData::decode(dec)?);
+        }
+        func.dfg = DataFlowGraph::decode(dec)?;
+        let count = dec.counts.global_vars;
+        func.stack_limit = dec.packed(count)?.into();
+        for _ in 0..dec.counts.jump_tables {
+            func.jump_tables.push(JumpTableData::decode(dec)?);
+        }
+
+        func.layout = decode_layout(dec)?;
+
+        func.encodings = dec.map(|dec| Ok(Encoding::new(dec.u16()?, dec.u16()?)))?;
+        func.locations = dec.map(|dec| match dec.u8()? {
+                                     0 => Ok(ValueLoc::Unassigned),
+                                     1 => Ok(ValueLoc::Reg(dec.u16()?)),
+                                     2 => Ok(ValueLoc::Stack(dec.stack_slot()?)),
+                                     _ => Err(DecodeError::Invalid("bad value location")),
+                                 })?;
+        func.edge_weights = dec.map(|dec| dec.option_u32())?;
+        func.srclocs = dec.map(|dec| Ok(SourceLoc::new(dec.u32()?)))?;
+        func.value_labels = dec.map(|dec| Ok(dec.option_u32()?.map(ValueLabel::new)))?;
+        func.offsets = dec.map(|dec| dec.u32())?;
+        func.stack_offsets = dec.map(|dec| dec.u32())?;
+        func.jt_offsets = dec.map(|dec| dec.u32())?;
+        Ok(func)
+    }
+}
+
+/// Read the EBBs in the layout and their instructions.
+fn decode_layout(dec: &mut Decoder) -> Result<Layout> {
+    let mut layout = Layout::new();
+    for _ in 0..dec.len()? {
+        let ebb = dec.ebb()?;
+        if layout.is_ebb_inserted(ebb) {
+            return Err(DecodeError::Invalid("EBB inserted twice"));
+        }
+        layout.append_ebb(ebb);
+        for _ in 0..dec.len()? {
+            let inst = dec.inst()?;
+            if layout.inst_ebb(inst).is_some() {
+                return Err(DecodeError::Invalid("instruction inserted twice"));
+            }
+            layout.append_inst(inst, ebb);
+        }
+    }
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_function, decode_function, DecodeError, FORMAT_VERSION};
+    use entity_map::EntityRef;
+    use ir::{Function, Cursor, InstBuilder, ExternalName, Signature, ArgumentType, ExtFuncData,
+             GlobalVarData, StackSlotData, StackSlotKind, JumpTableData, SourceLoc, ValueLabel,
+             Value, Inst, TrapCode, VariableArgs};
+    use ir::condcodes::IntCC;
+    use ir::immediates::Ieee64;
+    use ir::types::*;
+
+    fn args(values: &[Value]) -> VariableArgs {
+        let mut args = VariableArgs::new();
+        for &v in values {
+            args.push(v);
+        }
+        args
+    }
+
+    fn make_function() -> Function {
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(I32));
+        sig.return_types.push(ArgumentType::new(I32));
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig.clone());
+        let ss0 = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+        let sig0 = func.dfg.signatures.push(sig);
+        let fn0 = func.dfg
+            .ext_funcs
+            .push(ExtFuncData::new(ExternalName::user(1, 7), sig0));
+        let gv0 = func.dfg
+            .global_vars
+            .push(GlobalVarData::new(ExternalName::testcase("limit")));
+        func.stack_limit = Some(gv0);
+
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let arg0 = func.dfg.append_ebb_arg(ebb0, I32);
+        let arg1 = func.dfg.append_ebb_arg(ebb1, I32);
+        let mut jt = JumpTableData::new();
+        jt.set_entry(1, ebb1);
+        jt.set_entry(3, ebb2);
+        let jt0 = func.jump_tables.push(jt);
+        let (iconst, call) = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v1 = dfg.ins(pos).iconst(I32, -3);
+            let iconst = v1.unwrap_direct();
+            let v2 = dfg.ins(pos).icmp(IntCC::SignedLessThan, arg0, v1);
+            dfg.ins(pos).stack_store(arg0, ss0, 4u32);
+            dfg.ins(pos).br_table(arg0, jt0);
+            dfg.ins(pos).brnz(v2, ebb1, args(&[v1]));
+            dfg.ins(pos).jump(ebb2, args(&[]));
+            pos.insert_ebb(ebb1);
+            let v3 = dfg.ins(pos).f64const(Ieee64::new(-0.5));
+            let v4 = dfg.ins(pos).fcvt_to_sint(I32, v3);
+            let v5 = dfg.ins(pos).iadd(arg1, v4);
+            let flags = dfg.ins(pos).ifcmp(arg1, v4);
+            dfg.ins(pos).trueif(IntCC::UnsignedLessThan, flags);
+            dfg.ins(pos).trapif(IntCC::SignedGreaterThan, flags, TrapCode::IntegerOverflow);
+            dfg.ins(pos).return_(args(&[v5]));
+            pos.insert_ebb(ebb2);
+            let call = dfg.ins(pos).call(fn0, args(&[arg0]));
+            let res = dfg.first_result(call);
+            dfg.ins(pos).return_(args(&[res]));
+            (iconst, call)
+        };
+        func.set_srcloc(iconst, SourceLoc::new(0x1234));
+        func.set_srcloc(call, SourceLoc::new(7));
+        func.set_edge_weight(call, 3);
+        func.set_value_label(arg0, ValueLabel::new(2));
+
+        // An alias that isn't used anywhere.
+        func.dfg.make_value_alias(arg1);
+        func
+    }
+
+    #[test]
+    fn round_trip() {
+        let func = make_function();
+        let bytes = encode_function(&func);
+        let decoded = decode_function(&bytes).unwrap();
+        assert_eq!(decoded.to_string(), func.to_string());
+        assert_eq!(decoded.dfg.num_insts(), func.dfg.num_insts());
+        assert_eq!(decoded.srclocs.get(Inst::new(0)), Some(&SourceLoc::new(0x1234)));
+        assert_eq!(decoded.jump_tables.len(), 1);
+        assert_eq!(decoded.stack_limit, func.stack_limit);
+        assert_eq!(encode_function(&decoded), bytes);
+
+        // The text format is much larger.
+        assert!(bytes.len() * 2 < func.to_string().len());
+    }
+
+    #[test]
+    fn errors() {
+        let bytes = encode_function(&make_function());
+        assert_eq!(decode_function(b"").err(), Some(DecodeError::BadMagic));
+        assert_eq!(decode_function(b"notcton").err(), Some(DecodeError::BadMagic));
+
+        let mut other_version = bytes.clone();
+        other_version[4] = FORMAT_VERSION as u8 + 1;
+        assert_eq!(decode_function(&other_version).err(),
+                   Some(DecodeError::Version(FORMAT_VERSION + 1)));
+
+        for len in 4..bytes.len() {
+            assert!(decode_function(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(decode_function(&trailing).err(), Some(DecodeError::TrailingData));
+
+        // Flipping bits anywhere must not panic.
+        for i in 5..bytes.len() {
+            for bit in 0..8 {
+                let mut corrupt = bytes.clone();
+                corrupt[i] ^= 1 << bit;
+                let _ = decode_function(&corrupt);
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Compilation sessions.
+//!
+//! A `Session` holds the configuration shared by all the functions compiled by an embedder: the
+//! target ISA and its settings. It also keeps a pool of compilation contexts, so the memory they
+//! hold on to can be reused by whichever thread compiles the next function.
+//!
+//! The session is `Sync`, so a single instance can be shared by all the worker threads, typically
+//! behind an `Arc`. Each thread gets a context with `Session::context()`, and the context is
+//! returned to the pool when the `PooledContext` guard is dropped.
+
+use cancel::CancellationToken;
+use context::Context;
+use isa::TargetIsa;
+use settings;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+/// Configuration and reusable compilation contexts shared by concurrent compilations.
+pub struct Session {
+    isa: Box<TargetIsa>,
+    pool: Mutex<Vec<Context>>,
+}
+
+impl Session {
+    /// Create a new session compiling for `isa`.
+    pub fn new(isa: Box<TargetIsa>) -> Session {
+        Session {
+            isa: isa,
+            pool: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Get the target ISA.
+    pub fn isa(&self) -> &TargetIsa {
+        &*self.isa
+    }
+
+    /// Get the shared settings that were used to configure the target ISA.
+    pub fn flags(&self) -> &settings::Flags {
+        self.isa.flags()
+    }
+
+    /// Get a compilation context from the pool, or allocate a new one if the pool is empty.
+    ///
+    /// The context is returned to the pool when the returned guard is dropped. It is cleared
+    /// before it is handed out again, so the next function can be built in its `func` field, and
+    /// its `cancel` token is replaced, so a cancelled compilation doesn't affect the next one.
+    pub fn context(&self) -> PooledContext {
+        let mut ctx = self.pool.lock().unwrap().pop().unwrap_or_else(Context::new);
+        ctx.clear();
+        ctx.cancel = CancellationToken::new();
+        PooledContext {
+            session: self,
+            ctx: Some(ctx),
+        }
+    }
+
+    /// Get the number of idle contexts in the pool.
+    pub fn pooled_contexts(&self) -> usize {
+        self.pool.lock().unwrap().len()
+    }
+}
+
+/// A compilation context borrowed from a `Session`.
+///
+/// This dereferences to the `Context`, and returns it to the session's pool when dropped.
+pub struct PooledContext<'a> {
+    session: &'a Session,
+    ctx: Option<Context>,
+}
+
+impl<'a> Deref for PooledContext<'a> {
+    type Target = Context;
+
+    fn deref(&self) -> &Context {
+        self.ctx.as_ref().expect("Context already returned")
+    }
+}
+
+impl<'a> DerefMut for PooledContext<'a> {
+    fn deref_mut(&mut self) -> &mut Context {
+        self.ctx.as_mut().expect("Context already returned")
+    }
+}
+
+impl<'a> Drop for PooledContext<'a> {
+    fn drop(&mut self) {
+        if let Some(ctx) = self.ctx.take() {
+            // Don't panic while unwinding if another thread poisoned the lock.
+            if let Ok(mut pool) = self.session.pool.lock() {
+                pool.push(ctx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cancel::CancellationToken;
+    use context::Context;
+    use ir::{Function, Cursor, InstBuilder, VariableArgs};
+    use ir::types;
+    use isa::{self, TargetIsa};
+    use settings;
+    use std::sync::Arc;
+    use std::thread;
+    use super::Session;
+
+    // Everything an embedder shares between compilation threads must be `Sync`, and the contexts
+    // must be `Send` so they can move between threads through the pool. This fails to compile if
+    // a type gains interior mutability or a non-thread-safe pointer.
+    #[test]
+    fn thread_safety() {
+        fn shared<T: Send + Sync + ?Sized>() {}
+        fn sent<T: Send>() {}
+        shared::<TargetIsa>();
+        shared::<settings::Flags>();
+        shared::<Session>();
+        shared::<CancellationToken>();
+        sent::<Context>();
+    }
+
+    fn make_function() -> Function {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let arg = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).iadd(arg, arg);
+            dfg.ins(pos).return_reg(v0, VariableArgs::new());
+        }
+        func
+    }
+
+    #[test]
+    fn pool() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let session = Arc::new(Session::new(isa));
+        assert_eq!(session.isa().name(), "riscv");
+        assert_eq!(session.pooled_contexts(), 0);
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let session = session.clone();
+                thread::spawn(move || for _ in 0..4 {
+                                  let mut ctx = session.context();
+                                  ctx.func = make_function();
+                                  ctx.legalize(session.isa()).unwrap();
+                                  ctx.flowgraph();
+                                  ctx.regalloc(session.isa()).unwrap();
+                              })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        // Every thread returned its context, and at most one context per thread was allocated.
+        let pooled = session.pooled_contexts();
+        assert!(pooled >= 1 && pooled <= 4);
+
+        // A cancelled context is handed out with a fresh token.
+        {
+            let ctx = session.context();
+            assert_eq!(session.pooled_contexts(), pooled - 1);
+            ctx.cancel.cancel();
+        }
+        let mut ctx = session.context();
+        assert_eq!(ctx.func.layout.entry_block(), None);
+        ctx.func = make_function();
+        assert_eq!(ctx.legalize(session.isa()), Ok(()));
+    }
+}
@@ -7,23 +7,26 @@
 //! settings as well as computed predicate flags.
 //!
 //! The `Flags` struct is immutable once it has been created. A `Builder` instance is used to
-//! create it.
+//! create it. Since it is immutable, the same `Flags` can be read by any number of compilation
+//! threads.
 //!
 //! # Example
 //! ```
 //! use cretonne::settings::{self, Configurable};
 //!
 //! let mut b = settings::builder();
-//! b.set("opt_level", "fastest");
+//! b.set("opt_level", "speed_and_size");
 //!
 //! let f = settings::Flags::new(&b);
-//! assert_eq!(f.opt_level(), settings::OptLevel::Fastest);
+//! assert_eq!(f.opt_level(), settings::OptLevel::SpeedAndSize);
 //! ```
 
 use std::fmt;
 use std::result;
+use std::vec::Vec;
 
 use constant_hash::{probe, simple_hash};
+use ir::Type;
 
 /// A string-based configurator for settings groups.
 ///
@@ -39,6 +42,24 @@ pub trait Configurable {
     ///
     /// If the identified setting isn't a boolean, a `BadType` error is returned.
     fn set_bool(&mut self, name: &str, value: bool) -> Result<()>;
+
+    /// Enable a boolean setting or apply a preset by name.
+    ///
+    /// If the identified setting isn't a boolean or a preset, a `BadType` error is returned.
+    fn enable(&mut self, name: &str) -> Result<()> {
+        self.set_bool(name, true)
+    }
+
+    /// Apply a setting given as a `name=value` string, or enable the setting `name` when the
+    /// string has no `=`, like the options of a `set` command in a test file.
+    ///
+    /// Spaces around the name and the value are ignored.
+    fn set_option(&mut self, option: &str) -> Result<()> {
+        match option.find('=') {
+            Some(pos) => self.set(option[..pos].trim(), option[pos + 1..].trim()),
+            None => self.enable(option.trim()),
+        }
+    }
 }
 
 /// Collect settings values based on a template.
@@ -62,6 +83,11 @@ impl Builder {
         &self.bytes[..]
     }
 
+    /// Iterate over the settings and their current values.
+    pub fn iter(&self) -> Iter {
+        Iter::new(self.template, &self.bytes)
+    }
+
     /// Set the value of a single bit.
     fn set_bit(&mut self, offset: usize, bit: u8, value: bool) {
         let byte = &mut self.bytes[offset];
@@ -73,6 +99,13 @@ impl Builder {
         }
     }
 
+    /// Apply a preset. The argument is a slice of (mask, value) bytes.
+    fn apply_preset(&mut self, values: &[(u8, u8)]) {
+        for (byte, &(mask, value)) in self.bytes.iter_mut().zip(values) {
+            *byte = value | (*byte & !mask);
+        }
+    }
+
     /// Look up a descriptor by name.
     fn lookup(&self, name: &str) -> Result<(usize, detail::Detail)> {
         match probe(self.template, name, simple_hash(name)) {
@@ -104,11 +137,21 @@ impl Configurable for Builder {
     fn set_bool(&mut self, name: &str, value: bool) -> Result<()> {
         use self::detail::Detail;
         let (offset, detail) = self.lookup(name)?;
-        if let Detail::Bool { bit } = detail {
-            self.set_bit(offset, bit, value);
-            Ok(())
-        } else {
-            Err(Error::BadType)
+        match detail {
+            Detail::Bool { bit } => {
+                self.set_bit(offset, bit, value);
+                Ok(())
+            }
+            Detail::Preset => {
+                // A preset can be applied, but not un-applied.
+                if !value {
+                    return Err(Error::BadValue);
+                }
+                let presets = self.template.presets;
+                self.apply_preset(&presets[offset..offset + self.bytes.len()]);
+                Ok(())
+            }
+            _ => Err(Error::BadType),
         }
     }
 
@@ -128,6 +171,7 @@ impl Configurable for Builder {
                 self.bytes[offset] = parse_enum_value(value,
                                                       self.template.enums(last, enumerators))?;
             }
+            Detail::Preset => return Err(Error::BadType),
         }
         Ok(())
     }
@@ -146,9 +190,115 @@ pub enum Error {
     BadValue,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::BadName => write!(f, "unknown setting"),
+            Error::BadType => write!(f, "wrong setting type"),
+            Error::BadValue => write!(f, "invalid setting value"),
+        }
+    }
+}
+
 /// A result returned when changing a setting.
 pub type Result<T> = result::Result<T, Error>;
 
+/// The kind of a setting, as returned by the settings iterators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingKind {
+    /// A boolean setting.
+    Bool,
+    /// A numerical setting in the range 0-255.
+    Num,
+    /// An enumerated setting that can take one of the listed values.
+    Enum(&'static [&'static str]),
+}
+
+/// The value of a setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingValue {
+    /// The value of a boolean setting.
+    Bool(bool),
+    /// The value of a numerical setting.
+    Num(u8),
+    /// The value of an enumerated setting.
+    Enum(&'static str),
+}
+
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SettingValue::Bool(b) => write!(f, "{}", b),
+            SettingValue::Num(n) => write!(f, "{}", n),
+            SettingValue::Enum(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// A setting in a settings group, along with its default and current values.
+///
+/// The values are displayed in the `name=value` form accepted by `Configurable::set_option()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Setting {
+    /// The name of the setting.
+    pub name: &'static str,
+    /// The kind of setting.
+    pub kind: SettingKind,
+    /// The default value of the setting.
+    pub default: SettingValue,
+    /// The current value of the setting.
+    pub value: SettingValue,
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+/// An iterator over the settings in a settings group, in the order they are defined.
+///
+/// Presets are not settings in their own right, so they are not included.
+pub struct Iter<'a> {
+    template: &'static detail::Template,
+    bytes: &'a [u8],
+    next: usize,
+}
+
+impl<'a> Iter<'a> {
+    /// Create an iterator over the settings in `template` with values from `bytes`.
+    ///
+    /// This is mostly for use by the generated `Flags::iter()` method.
+    pub fn new(template: &'static detail::Template, bytes: &'a [u8]) -> Iter<'a> {
+        Iter {
+            template: template,
+            bytes: bytes,
+            next: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Setting;
+
+    fn next(&mut self) -> Option<Setting> {
+        while let Some(d) = self.template.descriptors.get(self.next) {
+            self.next += 1;
+            let offset = d.offset as usize;
+            if let Some(kind) = self.template.kind(d.detail) {
+                return Some(Setting {
+                                name: d.name,
+                                kind: kind,
+                                default: self.template
+                                    .value(d.detail, self.template.defaults[offset]),
+                                value: self.template.value(d.detail, self.bytes[offset]),
+                            });
+            }
+        }
+        None
+    }
+}
+
 /// Implementation details for generated code.
 ///
 /// This module holds definitions that need to be public so the can be instantiated by generated
@@ -156,6 +306,7 @@ pub type Result<T> = result::Result<T, Error>;
 pub mod detail {
     use std::fmt;
     use constant_hash;
+    use super::{SettingKind, SettingValue};
 
     /// An instruction group template.
     pub struct Template {
@@ -169,16 +320,44 @@ pub mod detail {
         pub hash_table: &'static [u16],
         /// Default values.
         pub defaults: &'static [u8],
+        /// Pairs of (mask, value) bytes for each preset.
+        pub presets: &'static [(u8, u8)],
     }
 
     impl Template {
         /// Get enumerators corresponding to a `Details::Enum`.
-        pub fn enums(&self, last: u8, enumerators: u16) -> &[&'static str] {
+        pub fn enums(&self, last: u8, enumerators: u16) -> &'static [&'static str] {
             let from = enumerators as usize;
             let len = last as usize + 1;
             &self.enumerators[from..from + len]
         }
 
+        /// Get the kind of setting described by `detail`, or `None` for a preset.
+        pub fn kind(&self, detail: Detail) -> Option<SettingKind> {
+            match detail {
+                Detail::Bool { .. } => Some(SettingKind::Bool),
+                Detail::Num => Some(SettingKind::Num),
+                Detail::Enum { last, enumerators } => {
+                    Some(SettingKind::Enum(self.enums(last, enumerators)))
+                }
+                Detail::Preset => None,
+            }
+        }
+
+        /// Decode the value of the setting described by `detail` from its settings byte.
+        ///
+        /// The setting must not be a preset.
+        pub fn value(&self, detail: Detail, byte: u8) -> SettingValue {
+            match detail {
+                Detail::Bool { bit } => SettingValue::Bool((byte & (1 << bit)) != 0),
+                Detail::Num => SettingValue::Num(byte),
+                Detail::Enum { last, enumerators } => {
+                    SettingValue::Enum(self.enums(last, enumerators)[byte as usize])
+                }
+                Detail::Preset => panic!("Presets have no value"),
+            }
+        }
+
         /// Format a setting value as a TOML string. This is mostly for use by the generated
         /// `Display` implementation.
         pub fn format_toml_value(&self,
@@ -197,6 +376,8 @@ pub mod detail {
                         write!(f, "{}", byte)
                     }
                 }
+                // Presets aren't stored in the settings bytes, so there's nothing to print.
+                Detail::Preset => Ok(()),
             }
         }
     }
@@ -251,6 +432,11 @@ pub mod detail {
             /// First enumerator in the ENUMERATORS table.
             enumerators: u16,
         },
+
+        /// A preset is not an individual setting, it is a collection of settings applied at once.
+        ///
+        /// The `Descriptor::offset` field refers to the `PRESETS` table.
+        Preset,
     }
 }
 
@@ -258,11 +444,42 @@ pub mod detail {
 // with an impl for all of the settings defined in `meta/cretonne/settings.py`.
 include!(concat!(env!("OUT_DIR"), "/settings.rs"));
 
+impl Flags {
+    /// Get a builder initialized with the values of these flags.
+    ///
+    /// This can be used to create a modified copy of an immutable `Flags` struct.
+    pub fn to_builder(&self) -> Builder {
+        let mut b = builder();
+        b.bytes.copy_from_slice(&self.bytes);
+        b
+    }
+
+    /// Get the stack alignment in bytes, given the alignment required by the ISA's ABI.
+    ///
+    /// This is `default` unless it is overridden by the `stack_align_log2` setting.
+    pub fn stack_alignment(&self, default: u32) -> u32 {
+        match self.stack_align_log2() {
+            0 => default,
+            n => 1u32.checked_shl(n as u32).expect("stack_align_log2 is too large"),
+        }
+    }
+
+    /// Is the type `ty` allowed by the `enable_float` and `enable_simd` settings?
+    ///
+    /// Vector types require `enable_simd`, and types with a floating point lane type require
+    /// `enable_float`.
+    pub fn allows_type(&self, ty: Type) -> bool {
+        (ty.is_scalar() || self.enable_simd()) &&
+        (!ty.lane_type().is_float() || self.enable_float())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{builder, Flags};
+    use super::{builder, Flags, Builder, Setting, SettingKind, SettingValue};
     use super::Error::*;
     use super::Configurable;
+    use super::detail::{Template, Descriptor, Detail};
 
     #[test]
     fn display_default() {
@@ -270,16 +487,57 @@ mod tests {
         let f = Flags::new(&b);
         assert_eq!(f.to_string(),
                    "[shared]\n\
-                    opt_level = \"default\"\n\
+                    opt_level = \"none\"\n\
+                    enable_verifier = true\n\
                     is_64bit = false\n\
                     is_compressed = false\n\
+                    is_pic = false\n\
+                    avoid_div_traps = false\n\
                     enable_float = true\n\
                     enable_simd = true\n\
-                    enable_atomics = true\n");
-        assert_eq!(f.opt_level(), super::OptLevel::Default);
+                    enable_atomics = true\n\
+                    stack_align_log2 = 0\n\
+                    preserve_frame_pointers = false\n\
+                    baldrdash_prologue_words = 0\n\
+                    probestack_size_log2 = 12\n");
+        assert_eq!(f.opt_level(), super::OptLevel::None);
         assert_eq!(f.enable_simd(), true);
     }
 
+    #[test]
+    fn iter() {
+        let mut b = builder();
+        b.set("opt_level", "speed").unwrap();
+        let f = Flags::new(&b);
+        let settings: Vec<Setting> = f.iter().collect();
+        assert_eq!(settings.len(), f.to_string().lines().count() - 1);
+        assert_eq!(settings[0],
+                   Setting {
+                       name: "opt_level",
+                       kind: SettingKind::Enum(&["none", "speed", "speed_and_size"]),
+                       default: SettingValue::Enum("none"),
+                       value: SettingValue::Enum("speed"),
+                   });
+        assert_eq!(settings[0].to_string(), "opt_level=speed");
+
+        let ps = settings
+            .iter()
+            .find(|s| s.name == "probestack_size_log2")
+            .unwrap();
+        assert_eq!(ps.kind, SettingKind::Num);
+        assert_eq!(ps.value, SettingValue::Num(12));
+
+        // The builder sees the same settings.
+        assert_eq!(b.iter().collect::<Vec<_>>(), settings);
+    }
+
+    #[test]
+    fn iter_presets() {
+        let b = Builder::new(&PRESET_TEMPLATE);
+        let names: Vec<_> = b.iter().map(|s| s.name).collect();
+        assert_eq!(names, ["a", "b"]);
+    }
+
     #[test]
     fn modify_bool() {
         let mut b = builder();
@@ -298,11 +556,74 @@ mod tests {
         assert_eq!(b.set("enable_simd", ""), Err(BadValue));
         assert_eq!(b.set("enable_simd", "best"), Err(BadValue));
         assert_eq!(b.set("opt_level", "true"), Err(BadValue));
-        assert_eq!(b.set("opt_level", "best"), Ok(()));
+        assert_eq!(b.set("opt_level", "speed"), Ok(()));
         assert_eq!(b.set("enable_simd", "0"), Ok(()));
 
         let f = Flags::new(&b);
         assert_eq!(f.enable_simd(), false);
-        assert_eq!(f.opt_level(), super::OptLevel::Best);
+        assert_eq!(f.opt_level(), super::OptLevel::Speed);
+    }
+
+    #[test]
+    fn set_option() {
+        let mut b = builder();
+        assert_eq!(b.set_option("is_pic"), Ok(()));
+        assert_eq!(b.set_option("enable_simd = false"), Ok(()));
+        assert_eq!(b.set_option("opt_level=speed_and_size"), Ok(()));
+        assert_eq!(b.set_option("probestack_size_log2=16"), Ok(()));
+        assert_eq!(b.set_option("not_there"), Err(BadName));
+        assert_eq!(b.set_option("not_there=1"), Err(BadName));
+        assert_eq!(b.set_option("opt_level"), Err(BadType));
+        assert_eq!(b.set_option("probestack_size_log2=256"), Err(BadValue));
+        assert_eq!(b.set_option("enable_float="), Err(BadValue));
+        assert_eq!(BadType.to_string(), "wrong setting type");
+
+        let f = Flags::new(&b);
+        assert_eq!(f.is_pic(), true);
+        assert_eq!(f.enable_simd(), false);
+        assert_eq!(f.opt_level(), super::OptLevel::SpeedAndSize);
+        assert_eq!(f.probestack_size_log2(), 16);
+    }
+
+    // A template with two boolean settings and a preset that enables both of them.
+    static PRESET_DESCRIPTORS: [Descriptor; 3] =
+        [Descriptor {
+             name: "a",
+             offset: 0,
+             detail: Detail::Bool { bit: 0 },
+         },
+         Descriptor {
+             name: "b",
+             offset: 0,
+             detail: Detail::Bool { bit: 1 },
+         },
+         Descriptor {
+             name: "ab",
+             offset: 0,
+             detail: Detail::Preset,
+         }];
+
+    static PRESET_TEMPLATE: Template = Template {
+        name: "presets",
+        descriptors: &PRESET_DESCRIPTORS,
+        enumerators: &[],
+        hash_table: &[0, 2, 0xffff, 1],
+        defaults: &[0x00],
+        presets: &[(0x03, 0x03)],
+    };
+
+    #[test]
+    fn apply_preset() {
+        let mut b = Builder::new(&PRESET_TEMPLATE);
+        assert_eq!(b.set_bool("b", true), Ok(()));
+        assert_eq!(b.state_for("presets"), &[0x02]);
+        assert_eq!(b.set_bool("ab", false), Err(BadValue));
+        assert_eq!(b.set("ab", "true"), Err(BadType));
+        assert_eq!(b.enable("ab"), Ok(()));
+        assert_eq!(b.state_for("presets"), &[0x03]);
+
+        // Settings can still be changed after the preset has been applied.
+        assert_eq!(b.set_bool("a", false), Ok(()));
+        assert_eq!(b.state_for("presets"), &[0x02]);
     }
 }
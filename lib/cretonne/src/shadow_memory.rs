@@ -0,0 +1,111 @@
+//! Shadow memory instrumentation of heap accesses.
+//!
+//! This opt-in pass helps embedders debug the code generated for unsafe front ends. It precedes
+//! every `load` and `store` to a heap with a check of the shadow memory in the style of
+//! AddressSanitizer, and traps with `heap_oob` when the accessed bytes are poisoned.
+//!
+//! The shadow memory is owned by the embedder, and the function finds it through a global
+//! variable. Each 8-byte granule of memory at address `a` has one shadow byte `k` at `shadow + (a
+//! >> 3)`, interpreted as a signed byte:
+//!
+//! - `k == 0` means that all 8 bytes of the granule are addressable.
+//! - `1 <= k <= 7` means that only the first `k` bytes are addressable.
+//! - `k < 0` means that the whole granule is poisoned.
+//!
+//! An access of fewer than 8 bytes is checked against the shadow byte of its first byte. Accesses
+//! of 8 bytes or more are assumed to be aligned to 8 bytes, and all the shadow bytes they cover
+//! must be 0.
+//!
+//! The shadow bytes are read with a single pointer-sized little-endian load, so the embedder must
+//! make the shadow memory readable a few bytes past the shadow byte of the last heap granule. Only
+//! accesses whose address is computed by a `heap_addr` instruction are instrumented, so the pass
+//! must run before legalization folds the address arithmetic into complex loads and stores.
+
+use alias_analysis::{Base, MemAccess, MemClass};
+use ir::{Function, Cursor, GlobalVar, Inst, InstBuilder, MemFlags, TrapCode, Value};
+use std::vec::Vec;
+
+/// Insert a shadow memory check before every heap access in `func`.
+///
+/// The address of the shadow memory is the address of the global variable `shadow`.
+pub fn instrument_heap_accesses(func: &mut Function, shadow: GlobalVar) {
+    let mut accesses = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if let Some(access) = MemAccess::from_inst(func, inst) {
+                if let (MemClass::Heap(_), Base::Addr(addr)) = (access.class, access.base) {
+                    accesses.push((inst, addr, access.offset, access.size));
+                }
+            }
+        }
+    }
+
+    for (inst, addr, offset, size) in accesses {
+        insert_check(func, shadow, inst, addr, offset, size);
+    }
+}
+
+/// Insert the check of the `size` bytes at `addr + offset` accessed by `inst`.
+fn insert_check(func: &mut Function,
+                shadow: GlobalVar,
+                inst: Inst,
+                addr: Value,
+                offset: i64,
+                size: u32) {
+    let ty = func.dfg.value_type(addr);
+    let bits = ty.bits() as i64;
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+
+    let dfg = &mut func.dfg;
+    let pos = &mut Cursor::new(&mut func.layout);
+    pos.goto_inst(inst);
+
+    let addr = if offset != 0 {
+        dfg.ins(pos).iadd_imm(addr, offset)
+    } else {
+        addr
+    };
+    let index = dfg.ins(pos).ushr_imm(addr, 3);
+    let base = dfg.ins(pos).globalsym_addr(ty, shadow);
+    let shadow_addr = dfg.ins(pos).iadd(base, index);
+    let word = dfg.ins(pos).load(ty, flags, shadow_addr, 0);
+
+    let poisoned = if size >= 8 {
+        // Keep only the shadow bytes covering the access. They must all be zero.
+        let shadow_bytes = (size as i64 / 8).min(bits / 8);
+        if shadow_bytes == bits / 8 {
+            word
+        } else {
+            dfg.ins(pos).ishl_imm(word, bits - 8 * shadow_bytes)
+        }
+    } else {
+        // Sign-extend the shadow byte `k`, and compute a limit that is `k` for a partially
+        // addressable granule, 16 when `k == 0`, and at most 0 when `k < 0`:
+        //
+        //   limit = ((k - 1) & 15) + 1 + ((k >> (bits - 1)) << 4)
+        //
+        // The access is bad when the offset of its last byte in the granule is `>= limit`.
+        let k = dfg.ins(pos).ishl_imm(word, bits - 8);
+        let k = dfg.ins(pos).sshr_imm(k, bits - 8);
+        let km1 = dfg.ins(pos).iadd_imm(k, -1);
+        let low = dfg.ins(pos).band_imm(km1, 15);
+        let low = dfg.ins(pos).iadd_imm(low, 1);
+        let sign = dfg.ins(pos).sshr_imm(k, bits - 1);
+        let sign = dfg.ins(pos).ishl_imm(sign, 4);
+        let limit = dfg.ins(pos).iadd(low, sign);
+
+        let first = dfg.ins(pos).band_imm(addr, 7);
+        let last = dfg.ins(pos).iadd_imm(first, size as i64 - 1);
+
+        // `last >= limit` is the sign bit of `limit - last - 1`.
+        let diff = dfg.ins(pos).isub(limit, last);
+        let diff = dfg.ins(pos).iadd_imm(diff, -1);
+        dfg.ins(pos).ushr_imm(diff, bits - 1)
+    };
+    let trap = dfg.ins(pos).trapnz(poisoned, TrapCode::HeapOutOfBounds);
+
+    // Report the trap at the source location of the heap access.
+    let srcloc = func.srcloc(inst);
+    func.set_srcloc(trap, srcloc);
+}
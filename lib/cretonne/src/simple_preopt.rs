@@ -0,0 +1,310 @@
+//! A simple peephole pass that runs before the main optimizations.
+//!
+//! The pre-optimization pass rewrites single instructions into cheaper or more canonical forms:
+//!
+//! - Constant operands are folded into the immediate forms of binary instructions, so `iadd v1,
+//!   v2` becomes `iadd_imm v1, 10` when `v2` is an `iconst`. Unlike the combiner, this also
+//!   happens when the constant has other uses. The `iconst` instruction is left alone.
+//! - Algebraic identities like `x + 0` and `x * 1` are replaced with a `copy` of `x`, and
+//!   multiplications by powers of two become shifts.
+//! - Divisions and remainders by a constant are replaced by a multiplication by a magic number and
+//!   shifts, see `divconst_magic_numbers`. Hardware division is slow, and the new sequence can't
+//!   trap. Powers of two only need shifts and masks.
+//! - Integer comparisons with a constant on the left-hand side are reversed so the constant is on
+//!   the right-hand side.
+//!
+//! All rewrites happen in place, so the results of the rewritten instructions are preserved. The
+//! division sequences insert new instructions before the rewritten one.
+//! Constants that become unused are left for a dead code elimination pass to clean up.
+
+use divconst_magic_numbers::{magic_u, magic_s};
+use ir::{Function, Cursor, DataFlowGraph, Inst, InstructionData, InstBuilder, Opcode, Value,
+         ValueDef};
+use ir::condcodes::CondCode;
+
+/// Run the pre-optimization pass on `func`.
+pub fn do_preopt(func: &mut Function) {
+    let mut pos = Cursor::new(&mut func.layout);
+    while let Some(_ebb) = pos.next_ebb() {
+        while let Some(inst) = pos.next_inst() {
+            // A rewritten instruction may be simplified further, like `imul` becoming `imul_imm`
+            // and then `ishl_imm`.
+            while simplify(&mut pos, &mut func.dfg, inst) {}
+        }
+    }
+}
+
+/// Get the value of the `iconst` instruction defining `value`, if any.
+fn iconst_value(dfg: &DataFlowGraph, value: Value) -> Option<i64> {
+    if let ValueDef::Res(inst, 0) = dfg.value_def(dfg.resolve_aliases(value)) {
+        if let InstructionData::UnaryImm { opcode: Opcode::Iconst, imm, .. } = dfg[inst] {
+            return Some(imm.into());
+        }
+    }
+    None
+}
+
+/// Zero-extend the low `bits` bits of `x`.
+fn zext(x: i64, bits: u32) -> u64 {
+    let shift = 64 - bits;
+    ((x as u64) << shift) >> shift
+}
+
+/// Sign-extend the low `bits` bits of `x`.
+fn sext(x: i64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    (x << shift) >> shift
+}
+
+/// If `x` is a power of two when interpreted as a `bits`-wide unsigned integer, get its log2.
+fn log2(x: i64, bits: u32) -> Option<i64> {
+    let x = zext(x, bits);
+    if x.is_power_of_two() {
+        Some(x.trailing_zeros() as i64)
+    } else {
+        None
+    }
+}
+
+/// Try to rewrite `inst` into a simpler form.
+///
+/// Returns `true` if `inst` was changed.
+fn simplify(pos: &mut Cursor, dfg: &mut DataFlowGraph, inst: Inst) -> bool {
+    let opcode = dfg[inst].opcode();
+    match dfg[inst].clone() {
+        InstructionData::Binary { args, .. } => {
+            let (x, y) = (args[0], args[1]);
+            if let Some(imm) = iconst_value(dfg, y) {
+                let bits = dfg.value_type(x).lane_bits() as u32;
+                match opcode {
+                    // The immediate forms don't allow the divisors that would trap.
+                    Opcode::Udiv | Opcode::Urem if zext(imm, bits) == 0 => return false,
+                    Opcode::Sdiv | Opcode::Srem if sext(imm, bits) == 0 => return false,
+                    Opcode::Sdiv | Opcode::Srem if sext(imm, bits) == -1 => return false,
+                    Opcode::Iadd => dfg.replace(inst).iadd_imm(x, imm),
+                    Opcode::Isub => dfg.replace(inst).iadd_imm(x, imm.wrapping_neg()),
+                    Opcode::Imul => dfg.replace(inst).imul_imm(x, imm),
+                    Opcode::Udiv => dfg.replace(inst).udiv_imm(x, imm),
+                    Opcode::Sdiv => dfg.replace(inst).sdiv_imm(x, imm),
+                    Opcode::Urem => dfg.replace(inst).urem_imm(x, imm),
+                    Opcode::Srem => dfg.replace(inst).srem_imm(x, imm),
+                    Opcode::Band => dfg.replace(inst).band_imm(x, imm),
+                    Opcode::Bor => dfg.replace(inst).bor_imm(x, imm),
+                    Opcode::Bxor => dfg.replace(inst).bxor_imm(x, imm),
+                    Opcode::Rotl => dfg.replace(inst).rotl_imm(x, imm),
+                    Opcode::Rotr => dfg.replace(inst).rotr_imm(x, imm),
+                    Opcode::Ishl => dfg.replace(inst).ishl_imm(x, imm),
+                    Opcode::Ushr => dfg.replace(inst).ushr_imm(x, imm),
+                    Opcode::Sshr => dfg.replace(inst).sshr_imm(x, imm),
+                    _ => return false,
+                };
+                return true;
+            }
+            if let Some(imm) = iconst_value(dfg, x) {
+                match opcode {
+                    Opcode::Iadd => dfg.replace(inst).iadd_imm(y, imm),
+                    Opcode::Isub => dfg.replace(inst).isub_imm(imm, y),
+                    Opcode::Imul => dfg.replace(inst).imul_imm(y, imm),
+                    Opcode::Band => dfg.replace(inst).band_imm(y, imm),
+                    Opcode::Bor => dfg.replace(inst).bor_imm(y, imm),
+                    Opcode::Bxor => dfg.replace(inst).bxor_imm(y, imm),
+                    _ => return false,
+                };
+                return true;
+            }
+            false
+        }
+        InstructionData::BinaryImm { arg, imm, .. } => {
+            let imm: i64 = imm.into();
+            let bits = dfg.value_type(arg).lane_bits() as u32;
+            match (opcode, imm) {
+                (Opcode::IaddImm, 0) |
+                (Opcode::ImulImm, 1) |
+                (Opcode::UdivImm, 1) |
+                (Opcode::SdivImm, 1) |
+                (Opcode::BandImm, -1) |
+                (Opcode::BorImm, 0) |
+                (Opcode::BxorImm, 0) |
+                (Opcode::RotlImm, 0) |
+                (Opcode::RotrImm, 0) |
+                (Opcode::IshlImm, 0) |
+                (Opcode::UshrImm, 0) |
+                (Opcode::SshrImm, 0) => {
+                    dfg.replace(inst).copy(arg);
+                }
+                (Opcode::ImulImm, 0) |
+                (Opcode::BandImm, 0) |
+                (Opcode::UremImm, 1) |
+                (Opcode::SremImm, 1) => {
+                    let ty = dfg.value_type(arg);
+                    dfg.replace(inst).iconst(ty, 0);
+                }
+                (Opcode::ImulImm, _) => {
+                    match log2(imm, bits) {
+                        Some(shift) => dfg.replace(inst).ishl_imm(arg, shift),
+                        None => return false,
+                    };
+                }
+                (Opcode::UdivImm, _) |
+                (Opcode::UremImm, _) |
+                (Opcode::SdivImm, _) |
+                (Opcode::SremImm, _) => return divide_by_const(pos, dfg, inst, arg, imm),
+                _ => return false,
+            }
+            true
+        }
+        InstructionData::IntCompare { cond, args, .. } if iconst_value(dfg, args[0]).is_some() &&
+                                                           iconst_value(dfg, args[1]).is_none() => {
+            dfg.replace(inst).icmp(cond.reverse(), args[1], args[0]);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The last instruction in a sequence computing a quotient.
+///
+/// A division is replaced by the last instruction, and a remainder needs more instructions after
+/// it.
+enum Quotient {
+    Copy(Value),
+    Ushr(Value, i64),
+    Sshr(Value, i64),
+    Iadd(Value, Value),
+    Ineg(Value),
+}
+
+impl Quotient {
+    /// Insert the instruction at `pos`.
+    fn insert(self, pos: &mut Cursor, dfg: &mut DataFlowGraph) -> Value {
+        match self {
+            Quotient::Copy(q) => q,
+            Quotient::Ushr(q, s) => dfg.ins(pos).ushr_imm(q, s),
+            Quotient::Sshr(q, s) => dfg.ins(pos).sshr_imm(q, s),
+            Quotient::Iadd(q, t) => dfg.ins(pos).iadd(q, t),
+            Quotient::Ineg(q) => dfg.ins(pos).isub_imm(0, q),
+        }
+    }
+
+    /// Replace `inst` with the instruction.
+    fn replace(self, dfg: &mut DataFlowGraph, inst: Inst) {
+        match self {
+            Quotient::Copy(q) => dfg.replace(inst).copy(q),
+            Quotient::Ushr(q, s) => dfg.replace(inst).ushr_imm(q, s),
+            Quotient::Sshr(q, s) => dfg.replace(inst).sshr_imm(q, s),
+            Quotient::Iadd(q, t) => dfg.replace(inst).iadd(q, t),
+            Quotient::Ineg(q) => dfg.replace(inst).isub_imm(0, q),
+        };
+    }
+}
+
+/// Replace the division or remainder `inst` of `x` by the constant `d`.
+///
+/// Returns `true` if `inst` was changed.
+fn divide_by_const(pos: &mut Cursor,
+                   dfg: &mut DataFlowGraph,
+                   inst: Inst,
+                   x: Value,
+                   d: i64)
+                   -> bool {
+    let opcode = dfg[inst].opcode();
+    let ty = dfg.value_type(x);
+    let bits = ty.lane_bits() as u32;
+
+    let q = match opcode {
+        Opcode::UdivImm | Opcode::UremImm => {
+            if let Some(k) = log2(d, bits) {
+                if opcode == Opcode::UdivImm {
+                    dfg.replace(inst).ushr_imm(x, k);
+                } else {
+                    dfg.replace(inst).band_imm(x, ((1u64 << k) - 1) as i64);
+                }
+                return true;
+            }
+            if zext(d, bits) == 0 {
+                return false;
+            }
+
+            let magic = magic_u(zext(d, bits), bits);
+            let shift = magic.shift as i64;
+            let mul = dfg.ins(pos).iconst(ty, sext(magic.mul as i64, bits));
+            let q = dfg.ins(pos).umulhi(x, mul);
+            if magic.add {
+                let t = dfg.ins(pos).isub(x, q);
+                let t = dfg.ins(pos).ushr_imm(t, 1);
+                let t = dfg.ins(pos).iadd(t, q);
+                Quotient::Ushr(t, shift - 1)
+            } else {
+                Quotient::Ushr(q, shift)
+            }
+        }
+        Opcode::SdivImm | Opcode::SremImm => {
+            let d = sext(d, bits);
+            match d {
+                0 | -1 => return false,
+                1 => Quotient::Copy(x),
+                _ => {
+                    if let Some(k) = log2(d.wrapping_abs(), bits) {
+                        // Add `2^k - 1` to negative dividends so the quotient is rounded towards
+                        // zero.
+                        let sign = if k == 1 {
+                            x
+                        } else {
+                            dfg.ins(pos).sshr_imm(x, k - 1)
+                        };
+                        let bias = dfg.ins(pos).ushr_imm(sign, bits as i64 - k);
+                        let t = dfg.ins(pos).iadd(x, bias);
+                        if d > 0 {
+                            Quotient::Sshr(t, k)
+                        } else {
+                            Quotient::Ineg(dfg.ins(pos).sshr_imm(t, k))
+                        }
+                    } else {
+                        let magic = magic_s(d, bits);
+                        let mul = dfg.ins(pos).iconst(ty, magic.mul);
+                        let mut q = dfg.ins(pos).smulhi(x, mul);
+                        if d > 0 && magic.mul < 0 {
+                            q = dfg.ins(pos).iadd(q, x);
+                        } else if d < 0 && magic.mul > 0 {
+                            q = dfg.ins(pos).isub(q, x);
+                        }
+                        if magic.shift > 0 {
+                            q = dfg.ins(pos).sshr_imm(q, magic.shift as i64);
+                        }
+                        // Add one to negative quotients.
+                        let t = dfg.ins(pos).ushr_imm(q, bits as i64 - 1);
+                        Quotient::Iadd(q, t)
+                    }
+                }
+            }
+        }
+        _ => return false,
+    };
+
+    if opcode == Opcode::UdivImm || opcode == Opcode::SdivImm {
+        q.replace(dfg, inst);
+    } else {
+        // The remainder is `x - q * d`.
+        let q = q.insert(pos, dfg);
+        let t = dfg.ins(pos).imul_imm(q, d);
+        dfg.replace(inst).isub(x, t);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::log2;
+
+    #[test]
+    fn powers_of_two() {
+        assert_eq!(log2(1, 32), Some(0));
+        assert_eq!(log2(8, 32), Some(3));
+        assert_eq!(log2(6, 32), None);
+        assert_eq!(log2(0, 32), None);
+        // The sign-extended immediate -128 is 2^7 as an 8-bit integer.
+        assert_eq!(log2(-128, 8), Some(7));
+        assert_eq!(log2(-128, 16), None);
+        assert_eq!(log2(i64::min_value(), 64), Some(63));
+    }
+}
@@ -36,9 +36,11 @@
 //!   contain their own key.
 
 use entity_map::{EntityRef, EntityMap};
+use mem_usage::MemUsage;
 use std::mem;
 use std::slice;
 use std::u32;
+use std::vec::Vec;
 
 /// Trait for extracting keys from values stored in a `SparseMap`.
 ///
@@ -86,6 +88,11 @@ impl<K, V> SparseMap<K, V>
         self.dense.clear();
     }
 
+    /// Get the memory used by this mapping.
+    pub fn mem_usage(&self) -> MemUsage {
+        self.sparse.mem_usage() + MemUsage::of_vec(&self.dense)
+    }
+
     /// Returns a reference to the value corresponding to the key.
     pub fn get(&self, key: K) -> Option<&V> {
         if let Some(idx) = self.sparse.get(key).cloned() {
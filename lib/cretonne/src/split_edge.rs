@@ -0,0 +1,146 @@
+//! Splitting critical edges.
+//!
+//! A *critical edge* in the control flow graph goes from a basic block with multiple successors to
+//! an EBB with multiple predecessors. Code can't be inserted on a critical edge without also
+//! affecting the other paths through its source or destination, so passes that need to insert
+//! code on an edge, like spilling or edge-specific register moves, split the edge first.
+//!
+//! An edge is split by inserting a new EBB that takes the same arguments as the destination EBB.
+//! The branch is retargeted to the new EBB, which forwards its arguments to the original
+//! destination. The control flow graph and dominator tree are updated incrementally.
+
+use cfg::{ControlFlowGraph, BasicBlock};
+use dominator_tree::DominatorTree;
+use ir::{Function, Cursor, Ebb, Type, InstBuilder, InstructionData, VariableArgs};
+use ir::instructions::BranchInfo;
+use std::vec::Vec;
+
+/// Is the control flow graph edge from `pred` to `succ` critical?
+pub fn is_critical_edge(func: &Function,
+                        cfg: &ControlFlowGraph,
+                        pred: BasicBlock,
+                        succ: Ebb)
+                        -> bool {
+    // A branch in the middle of an EBB falls through to the next instruction, and a branch table
+    // has multiple destinations.
+    let multiple_succs = match func.dfg.analyze_branch(pred.1) {
+        BranchInfo::Table(_) => true,
+        _ => func.layout.last_inst(pred.0) != Some(pred.1),
+    };
+    multiple_succs && cfg.get_predecessors(succ).len() > 1
+}
+
+/// Split the control flow graph edge from `pred` to `succ` by inserting a new EBB.
+///
+/// The branch instruction in `pred` must be a jump or a conditional branch to `succ`. Edges from a
+/// `br_table` instruction can't be split since the jump table may be shared by other branches.
+///
+/// The new EBB is appended to the layout, and it ends with a jump to `succ`. Neither the branch
+/// nor the new jump is encoded, so this should happen before legalization.
+///
+/// Returns the new EBB.
+pub fn split_critical_edge(func: &mut Function,
+                           cfg: &mut ControlFlowGraph,
+                           domtree: &mut DominatorTree,
+                           pred: BasicBlock,
+                           succ: Ebb)
+                           -> Ebb {
+    let (ebb, branch) = pred;
+    let new_ebb = func.dfg.make_ebb();
+
+    let arg_types: Vec<Type> = match func.dfg.analyze_branch(branch) {
+        BranchInfo::SingleDest(dest, args) => {
+            assert_eq!(dest, succ, "{} doesn't branch to {}", branch, succ);
+            args.iter().map(|&arg| func.dfg.value_type(arg)).collect()
+        }
+        _ => panic!("Can't split the edge from {} to {}", branch, succ),
+    };
+    match func.dfg[branch] {
+        InstructionData::Jump { ref mut destination, .. } |
+        InstructionData::Branch { ref mut destination, .. } |
+        InstructionData::BranchIcmp { ref mut destination, .. } => *destination = new_ebb,
+        _ => unreachable!(),
+    }
+
+    let mut args = VariableArgs::new();
+    for ty in arg_types {
+        args.push(func.dfg.append_ebb_arg(new_ebb, ty));
+    }
+    func.layout.append_ebb(new_ebb);
+    {
+        let pos = &mut Cursor::new(&mut func.layout);
+        pos.goto_bottom(new_ebb);
+        func.dfg.ins(pos).jump(succ, args);
+    }
+
+    cfg.recompute_ebb(func, ebb);
+    cfg.recompute_ebb(func, new_ebb);
+    domtree.split_edge(func, cfg, pred, new_ebb, succ);
+    new_ebb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cfg::ControlFlowGraph;
+    use dominator_tree::DominatorTree;
+    use ir::{Function, InstBuilder, Cursor, VariableArgs, types};
+    use verifier::verify_context;
+
+    #[test]
+    fn split_loop_edge() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let cond = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let arg = func.dfg.append_ebb_arg(ebb1, types::I32);
+
+        let jmp_ebb0_ebb1;
+        let br_ebb1_ebb1;
+        let jmp_ebb1_ebb2;
+        {
+            let dfg = &mut func.dfg;
+            let cur = &mut Cursor::new(&mut func.layout);
+
+            cur.insert_ebb(ebb0);
+            let mut args = VariableArgs::new();
+            args.push(cond);
+            jmp_ebb0_ebb1 = dfg.ins(cur).jump(ebb1, args);
+
+            cur.insert_ebb(ebb1);
+            let mut args = VariableArgs::new();
+            args.push(arg);
+            br_ebb1_ebb1 = dfg.ins(cur).brnz(arg, ebb1, args);
+            jmp_ebb1_ebb2 = dfg.ins(cur).jump(ebb2, VariableArgs::new());
+
+            cur.insert_ebb(ebb2);
+            dfg.ins(cur).return_(VariableArgs::new());
+        }
+
+        let mut cfg = ControlFlowGraph::with_function(&func);
+        let mut domtree = DominatorTree::with_function(&func, &cfg);
+
+        // The jump in `ebb0` is its only successor, and `ebb2` has a single predecessor.
+        assert!(!is_critical_edge(&func, &cfg, (ebb0, jmp_ebb0_ebb1), ebb1));
+        assert!(!is_critical_edge(&func, &cfg, (ebb1, jmp_ebb1_ebb2), ebb2));
+        assert!(is_critical_edge(&func, &cfg, (ebb1, br_ebb1_ebb1), ebb1));
+
+        let ebb3 =
+            split_critical_edge(&mut func, &mut cfg, &mut domtree, (ebb1, br_ebb1_ebb1), ebb1);
+        assert_eq!(func.dfg.num_ebb_args(ebb3), 1);
+        assert_eq!(cfg.get_successors(ebb1), &[ebb3, ebb2]);
+        assert_eq!(cfg.get_predecessors(ebb3), &[(ebb1, br_ebb1_ebb1)]);
+        assert!(!is_critical_edge(&func, &cfg, (ebb1, br_ebb1_ebb1), ebb3));
+        assert_eq!(domtree.idom(ebb3), Some(br_ebb1_ebb1));
+        assert_eq!(domtree.idom(ebb1), Some(jmp_ebb0_ebb1));
+
+        // The incremental updates must match a full recomputation.
+        assert_eq!(verify_context(&func, &cfg, &domtree, None), Ok(()));
+        let new_cfg = ControlFlowGraph::with_function(&func);
+        let new_domtree = DominatorTree::with_function(&func, &new_cfg);
+        for ebb in func.layout.ebbs() {
+            assert_eq!(domtree.idom(ebb), new_domtree.idom(ebb));
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Stable hashing of functions and compiled code.
+//!
+//! Embedders that cache compiled code need a hash of the input function that doesn't change
+//! between runs, hosts, or compiler versions, unlike the `std::collections` hashers which are
+//! randomly seeded and unspecified. The `StableHasher` implements the 64-bit FNV-1a hash, and it
+//! only hashes the bytes it is given in little-endian order, so the result doesn't depend on the
+//! host's pointer size or byte order.
+//!
+//! Compilation is deterministic: The same function compiled with the same ISA and settings always
+//! produces the same machine code. Hashing the emitted code with `binemit::HashSink` gives a cheap
+//! way of comparing compilation results.
+
+use std::fmt;
+use std::hash::Hasher;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A hasher giving the same result for the same input on all platforms.
+///
+/// This also implements `fmt::Write` so text can be hashed without allocating a string.
+#[derive(Clone, Copy, Debug)]
+pub struct StableHasher {
+    hash: u64,
+}
+
+impl StableHasher {
+    /// Create a new hasher.
+    pub fn new() -> StableHasher {
+        StableHasher { hash: FNV_OFFSET_BASIS }
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> StableHasher {
+        StableHasher::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.hash ^= b as u64;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, x: u16) {
+        self.write(&[x as u8, (x >> 8) as u8]);
+    }
+
+    fn write_u32(&mut self, x: u32) {
+        self.write_u16(x as u16);
+        self.write_u16((x >> 16) as u16);
+    }
+
+    fn write_u64(&mut self, x: u64) {
+        self.write_u32(x as u32);
+        self.write_u32((x >> 32) as u32);
+    }
+
+    fn write_usize(&mut self, x: usize) {
+        self.write_u64(x as u64);
+    }
+}
+
+impl fmt::Write for StableHasher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StableHasher;
+    use ir::{Function, ExternalName};
+    use std::hash::Hasher;
+
+    fn fnv(bytes: &[u8]) -> u64 {
+        let mut h = StableHasher::new();
+        h.write(bytes);
+        h.finish()
+    }
+
+    #[test]
+    fn known_values() {
+        // Reference values of the 64-bit FNV-1a hash.
+        assert_eq!(fnv(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn little_endian() {
+        let mut h = StableHasher::new();
+        h.write_u32(0x7261_626f);
+        h.write_u16(0x6f66);
+        assert_eq!(h.finish(), fnv(b"obarfo"));
+    }
+
+    #[test]
+    fn function() {
+        let mut func = Function::new();
+        assert_eq!(func.stable_hash(None), fnv(func.to_string().as_bytes()));
+        assert_eq!(func.stable_hash(None), Function::new().stable_hash(None));
+        func.name = ExternalName::testcase("foo");
+        assert!(func.stable_hash(None) != Function::new().stable_hash(None));
+    }
+}
@@ -0,0 +1,152 @@
+//! Stack frame layout.
+//!
+//! This module assigns offsets to the stack slots in a function. The offsets are relative to the
+//! bottom of the local part of the stack frame, which is aligned to the target's stack alignment
+//! as returned by `TargetIsa::stack_alignment()`.
+//!
+//! Stack slots without an explicit alignment are aligned to the smallest power of two that can
+//! hold them. All alignments are capped at the stack alignment. Slots are placed in order of decreasing alignment so
+//! no padding is required between them.
+//!
+//! Outgoing argument slots are placed at their fixed offsets at the bottom of the frame, where
+//! called functions expect to find their stack arguments. The other slots go above them.
+
+use entity_map::EntityMap;
+use ir::{Function, StackSlot, StackSlotData, StackSlotKind, ValueLoc};
+use std::vec::Vec;
+
+/// The computed layout of a stack frame.
+pub struct StackLayout {
+    /// Byte offset of each stack slot from the bottom of the frame.
+    ///
+    /// Spill slots that are not used by any value are not allocated. Their offset is 0.
+    pub offsets: EntityMap<StackSlot, u32>,
+
+    /// Total size of the frame in bytes. This is a multiple of the stack alignment.
+    pub frame_size: u32,
+}
+
+/// Get the alignment of `slot` in a frame aligned to `stack_align` bytes.
+///
+/// The frame itself is only aligned to `stack_align`, so larger alignments can't be honored. They
+/// are clamped to the stack alignment.
+fn slot_alignment(slot: &StackSlotData, stack_align: u32) -> u32 {
+    slot.align
+        .unwrap_or_else(|| slot.size.next_power_of_two())
+        .min(stack_align)
+}
+
+/// Compute a stack frame layout for `func` with the frame aligned to `stack_align` bytes.
+///
+/// All explicit stack slots are allocated, but spill slots only get space in the frame when a
+/// value location refers to them.
+pub fn layout_stack(func: &Function, stack_align: u32) -> StackLayout {
+    assert!(stack_align.is_power_of_two(),
+            "Stack alignment must be a power of two");
+
+    let mut used = EntityMap::<StackSlot, bool>::with_capacity(func.stack_slots.len());
+    for ss in func.stack_slots.keys() {
+        used[ss] = func.stack_slots[ss].kind == StackSlotKind::ExplicitSlot;
+    }
+    for value in func.locations.keys() {
+        if let ValueLoc::Stack(ss) = func.locations[value] {
+            used[ss] = true;
+        }
+    }
+
+    // Outgoing arguments have fixed offsets at the bottom of the frame.
+    let mut offsets = EntityMap::with_capacity(func.stack_slots.len());
+    let mut offset = 0;
+    for ss in func.stack_slots.keys() {
+        let slot = &func.stack_slots[ss];
+        if slot.kind == StackSlotKind::OutgoingArg {
+            offsets[ss] = slot.offset;
+            offset = offset.max(slot.offset + slot.size);
+        }
+    }
+
+    // Sort by decreasing alignment, keeping the slot order stable otherwise.
+    let mut slots: Vec<_> = func.stack_slots
+        .keys()
+        .filter(|&ss| used[ss] && func.stack_slots[ss].kind != StackSlotKind::OutgoingArg)
+        .collect();
+    slots.sort_by_key(|&ss| !slot_alignment(&func.stack_slots[ss], stack_align));
+
+    for ss in slots {
+        let slot = &func.stack_slots[ss];
+        let align = slot_alignment(slot, stack_align);
+        offset = (offset + align - 1) & !(align - 1);
+        offsets[ss] = offset;
+        offset += slot.size;
+    }
+
+    StackLayout {
+        offsets: offsets,
+        frame_size: (offset + stack_align - 1) & !(stack_align - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, StackSlotData, StackSlotKind, ValueLoc};
+    use ir::types;
+    use super::layout_stack;
+
+    #[test]
+    fn empty() {
+        let func = Function::new();
+        assert_eq!(layout_stack(&func, 16).frame_size, 0);
+    }
+
+    #[test]
+    fn alignment() {
+        let mut func = Function::new();
+        let ss0 = func.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 3));
+        let ss1 = func.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+        let mut data = StackSlotData::new(StackSlotKind::ExplicitSlot, 4);
+        data.align = Some(16);
+        let ss2 = func.stack_slots.push(data);
+        // An unused spill slot takes no space.
+        func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 32));
+        let ss4 = func.stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, 2));
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I16);
+        *func.locations.ensure(v0) = ValueLoc::Stack(ss4);
+
+        let layout = layout_stack(&func, 16);
+        assert_eq!(layout.offsets[ss2], 0);
+        assert_eq!(layout.offsets[ss1], 8);
+        assert_eq!(layout.offsets[ss0], 16);
+        assert_eq!(layout.offsets[ss4], 20);
+        assert_eq!(layout.frame_size, 32);
+
+        // With a smaller stack alignment, the 8-byte slot is only 4-byte aligned.
+        func.stack_slots[ss2].align = None;
+        let layout = layout_stack(&func, 4);
+        assert_eq!(layout.offsets[ss0], 0);
+        assert_eq!(layout.offsets[ss1], 4);
+        assert_eq!(layout.offsets[ss2], 12);
+        assert_eq!(layout.offsets[ss4], 16);
+        assert_eq!(layout.frame_size, 20);
+
+        // An explicit alignment larger than the stack alignment is clamped.
+        func.stack_slots[ss2].align = Some(32);
+        let layout = layout_stack(&func, 16);
+        assert_eq!(layout.offsets[ss2], 0);
+        assert_eq!(layout.frame_size, 32);
+    }
+
+    #[test]
+    fn outgoing_args() {
+        let mut func = Function::new();
+        let ss0 = func.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+        let ss1 = func.stack_slots.push(StackSlotData::outgoing_arg(4, 4));
+        let ss2 = func.stack_slots.push(StackSlotData::outgoing_arg(4, 0));
+
+        let layout = layout_stack(&func, 8);
+        assert_eq!(layout.offsets[ss2], 0);
+        assert_eq!(layout.offsets[ss1], 4);
+        assert_eq!(layout.offsets[ss0], 8);
+        assert_eq!(layout.frame_size, 16);
+    }
+}
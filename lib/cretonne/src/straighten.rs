@@ -0,0 +1,165 @@
+//! Straightening the EBB layout.
+//!
+//! The legalizer and the other passes that rewrite control flow tend to produce chains of EBBs
+//! connected by unconditional jumps. This late layout pass cleans them up:
+//!
+//! - An EBB whose only predecessor is the `jump` terminating another EBB is merged into that EBB.
+//!   Its arguments become aliases of the values passed by the jump.
+//! - A `jump` to the next EBB in the layout is turned into a `fallthrough`, which doesn't emit any
+//!   code. Fall-throughs have no encoding, so they don't take any space.
+//!
+//! The pass should run when the EBB order is final. `Context::compile()` runs it after register
+//! allocation, just before branch relaxation. Passes that move EBBs around after it must turn the
+//! fall-throughs back into jumps with `remove_fallthroughs()`.
+
+use cfg::ControlFlowGraph;
+use ir::{Function, Ebb, Inst, InstructionData, Layout, Opcode};
+use std::vec::Vec;
+
+/// Merge the EBB chains in `func` and turn jumps to the next EBB into fall-throughs.
+pub fn straighten_layout(func: &mut Function) {
+    merge_ebbs(func);
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for ebb in ebbs {
+        let inst = match func.layout.last_inst(ebb) {
+            Some(inst) => inst,
+            None => continue,
+        };
+        if let Some(dest) = jump_destination(func, inst) {
+            if func.layout.next_ebb(ebb) == Some(dest) {
+                set_jump_opcode(func, inst, Opcode::Fallthrough);
+                // A fall-through takes no space, so it doesn't keep the encoding of the jump.
+                if func.encodings.is_valid(inst) {
+                    func.encodings[inst] = Default::default();
+                }
+            }
+        }
+    }
+}
+
+/// Turn all the `fallthrough` instructions in `func` back into jumps.
+pub fn remove_fallthroughs(func: &mut Function) {
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for ebb in ebbs {
+        if let Some(inst) = func.layout.last_inst(ebb) {
+            if func.dfg[inst].opcode() == Opcode::Fallthrough {
+                set_jump_opcode(func, inst, Opcode::Jump);
+            }
+        }
+    }
+}
+
+/// Get the destination of `inst` if it is a `jump` instruction.
+fn jump_destination(func: &Function, inst: Inst) -> Option<Ebb> {
+    match func.dfg[inst] {
+        InstructionData::Jump { opcode: Opcode::Jump, destination, .. } => Some(destination),
+        _ => None,
+    }
+}
+
+/// Change the opcode of the `jump` or `fallthrough` instruction `inst`.
+fn set_jump_opcode(func: &mut Function, inst: Inst, new_opcode: Opcode) {
+    if let InstructionData::Jump { ref mut opcode, .. } = func.dfg[inst] {
+        *opcode = new_opcode;
+    }
+}
+
+/// Merge each EBB with a single predecessor into the EBB jumping to it.
+fn merge_ebbs(func: &mut Function) {
+    // Merging EBBs doesn't change the number of predecessors of the remaining EBBs, and the
+    // branch instructions stay the same.
+    let cfg = ControlFlowGraph::with_function(func);
+    let entry = func.layout.entry_block();
+
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for ebb in ebbs {
+        if !func.layout.is_ebb_inserted(ebb) {
+            // This EBB has already been merged into a predecessor.
+            continue;
+        }
+        // The merged EBB ends with the terminator of its successor, so keep going.
+        while let Some(jump) = func.layout.last_inst(ebb) {
+            let dest = match jump_destination(func, jump) {
+                Some(dest) => dest,
+                None => break,
+            };
+            let preds = cfg.get_predecessors(dest);
+            if dest == ebb || Some(dest) == entry || preds.len() != 1 || preds[0].1 != jump {
+                break;
+            }
+
+            let args: Vec<_> = func.dfg.detach_ebb_args(dest).collect();
+            let values = func.dfg.inst_variable_args(jump).to_vec();
+            for (&arg, &value) in args.iter().zip(values.iter()) {
+                func.dfg.change_to_alias(arg, value);
+            }
+
+            func.layout.remove_inst(jump);
+            move_insts(&mut func.layout, dest, ebb);
+            func.layout.remove_ebb(dest);
+        }
+    }
+}
+
+/// Move all the instructions in `from` to the end of `to`.
+fn move_insts(layout: &mut Layout, from: Ebb, to: Ebb) {
+    let insts: Vec<Inst> = layout.ebb_insts(from).collect();
+    for inst in insts {
+        layout.remove_inst(inst);
+        layout.append_inst(inst, to);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, Ebb, InstBuilder, Opcode, VariableArgs};
+    use ir::types;
+    use verifier::verify_function;
+    use super::{straighten_layout, remove_fallthroughs};
+
+    #[test]
+    fn merge_chain() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let ebb3 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb1, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let mut args = VariableArgs::new();
+            args.push(v0);
+            dfg.ins(pos).jump(ebb1, args);
+
+            // `ebb1` only has one predecessor, so it is merged into `ebb0`.
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).brz(v1, ebb3, VariableArgs::new());
+            dfg.ins(pos).jump(ebb2, VariableArgs::new());
+
+            // `ebb2` is merged into `ebb0` too, and the jump becomes a fall-through.
+            pos.insert_ebb(ebb2);
+            dfg.ins(pos).jump(ebb3, VariableArgs::new());
+
+            pos.insert_ebb(ebb3);
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+
+        straighten_layout(&mut func);
+        verify_function(&func).unwrap();
+        let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(ebbs, [ebb0, ebb3]);
+        assert_eq!(func.dfg.resolve_aliases(v1), v0);
+        let opcodes: Vec<Opcode> = func.layout
+            .ebb_insts(ebb0)
+            .map(|inst| func.dfg[inst].opcode())
+            .collect();
+        assert_eq!(opcodes, [Opcode::Brz, Opcode::Fallthrough]);
+
+        remove_fallthroughs(&mut func);
+        let last = func.layout.last_inst(ebb0).unwrap();
+        assert_eq!(func.dfg[last].opcode(), Opcode::Jump);
+    }
+}
@@ -0,0 +1,371 @@
+//! Structural hashing and equivalence of functions.
+//!
+//! The entity numbers in a function depend on the order its entities were created in, so two
+//! functions with the same instructions can look different when they were produced by different
+//! pass orders, or by different front ends. Two functions are *structurally equivalent* when they
+//! are the same up to the numbering of their entities. `equivalent()` checks this, and
+//! `Function::structural_hash()` computes a hash that is the same for equivalent functions, so it
+//! can key a translation cache.
+//!
+//! Both are computed from a canonical form of the function written with the `serialize` encoder.
+//! In the canonical form, the EBBs are numbered in layout order, and the values in the order they
+//! appear in the function body. The preamble entities are numbered in the order they are first
+//! referenced, and the ones that aren't referenced at all are numbered last, in declaration order.
+//! Value aliases are resolved.
+//!
+//! The canonical form covers the signature, the preamble declarations, the EBBs and instructions
+//! in the layout, and the instruction encodings and value locations. The function name is not
+//! part of it, and neither are the source locations, value labels, edge weights, and code offsets.
+
+use entity_map::EntityRef;
+use ir::{Function, Ebb, Inst, Value, StackSlot, JumpTable, FuncRef, SigRef, Heap, GlobalVar,
+         InstructionData, ValueLoc};
+use ir::instructions::{ValueList, ValueListPool};
+use serialize::{self, Encoder, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::vec::Vec;
+
+/// Are `a` and `b` the same function, except for the numbering of their entities?
+///
+/// The function names don't have to match. See the module documentation for the details of what
+/// is compared.
+pub fn equivalent(a: &Function, b: &Function) -> bool {
+    canonical_form(a) == canonical_form(b)
+}
+
+/// Get the canonical form of `func`.
+///
+/// Equivalent functions have identical canonical forms.
+pub fn canonical_form(func: &Function) -> Vec<u8> {
+    let mut canon = Canonicalizer {
+        func: func,
+        enc: Encoder::new(),
+        value_lists: ValueListPool::new(),
+        ebbs: Numbering::new(),
+        values: Numbering::new(),
+        stack_slots: Numbering::new(),
+        jump_tables: Numbering::new(),
+        signatures: Numbering::new(),
+        ext_funcs: Numbering::new(),
+        heaps: Numbering::new(),
+        global_vars: Numbering::new(),
+    };
+
+    func.signature.encode(&mut canon.enc);
+
+    // Branches can refer to EBBs later in the layout, so number all of them first.
+    canon.ebbs.number_all(func.layout.ebbs());
+    canon.enc.uint(func.layout.ebbs().count() as u64);
+    for ebb in func.layout.ebbs() {
+        canon.ebb(ebb);
+    }
+    canon.preamble();
+    canon.enc.finish()
+}
+
+/// Canonical numbers for entities of one kind, assigned in order of first appearance.
+struct Numbering<E> {
+    numbers: HashMap<E, usize>,
+    order: Vec<E>,
+}
+
+impl<E: EntityRef + Hash> Numbering<E> {
+    fn new() -> Numbering<E> {
+        Numbering {
+            numbers: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// Get the canonical entity for `e`, numbering it if this is its first appearance.
+    fn get(&mut self, e: E) -> E {
+        let next = self.order.len();
+        let number = *self.numbers.entry(e).or_insert(next);
+        if number == next {
+            self.order.push(e);
+        }
+        E::new(number)
+    }
+
+    /// Number all the entities in `entities` that don't have a number yet.
+    fn number_all<I: Iterator<Item = E>>(&mut self, entities: I) {
+        for e in entities {
+            self.get(e);
+        }
+    }
+}
+
+struct Canonicalizer<'a> {
+    func: &'a Function,
+    enc: Encoder,
+    /// Value lists of the canonical instructions.
+    value_lists: ValueListPool,
+    ebbs: Numbering<Ebb>,
+    values: Numbering<Value>,
+    stack_slots: Numbering<StackSlot>,
+    jump_tables: Numbering<JumpTable>,
+    signatures: Numbering<SigRef>,
+    ext_funcs: Numbering<FuncRef>,
+    heaps: Numbering<Heap>,
+    global_vars: Numbering<GlobalVar>,
+}
+
+impl<'a> Canonicalizer<'a> {
+    /// Write `ebb` with its arguments and instructions.
+    fn ebb(&mut self, ebb: Ebb) {
+        let func = self.func;
+        self.enc.uint(func.dfg.num_ebb_args(ebb) as u64);
+        for arg in func.dfg.ebb_args(ebb) {
+            self.def(arg);
+        }
+        self.enc.uint(func.layout.ebb_insts(ebb).count() as u64);
+        for inst in func.layout.ebb_insts(ebb) {
+            self.inst(inst);
+        }
+    }
+
+    /// Write `inst` with its encoding and results.
+    fn inst(&mut self, inst: Inst) {
+        let func = self.func;
+        let encoding = func.encodings.get(inst).cloned().unwrap_or_default();
+        self.enc.uint(encoding.recipe() as u64);
+        self.enc.uint(encoding.bits() as u64);
+        let data = self.inst_data(&func.dfg[inst]);
+        serialize::encode_inst(&mut self.enc, &data, &self.value_lists);
+        self.enc.uint(func.dfg.inst_results(inst).count() as u64);
+        for res in func.dfg.inst_results(inst) {
+            self.def(res);
+        }
+    }
+
+    /// Write the definition of `value` with its type and location.
+    fn def(&mut self, value: Value) {
+        let func = self.func;
+        let number = self.values.get(value);
+        self.enc.entity(number);
+        func.dfg.value_type(value).encode(&mut self.enc);
+        match func.locations.get(value).cloned().unwrap_or_default() {
+            ValueLoc::Unassigned => self.enc.u8(0),
+            ValueLoc::Reg(ru) => {
+                self.enc.u8(1);
+                self.enc.uint(ru as u64);
+            }
+            ValueLoc::Stack(ss) => {
+                let ss = self.stack_slots.get(ss);
+                self.enc.u8(2);
+                self.enc.entity(ss);
+            }
+        }
+    }
+
+    /// Copy the instruction `data` with all the entity references replaced by canonical ones.
+    fn inst_data(&mut self, data: &InstructionData) -> InstructionData {
+        let mut data = data.clone();
+
+        // The secondary results are numbered when the instruction results are written.
+        if let Some(second_result) = data.second_result_mut() {
+            *second_result = None.into();
+        }
+        {
+            let dfg = &self.func.dfg;
+            let values = &mut self.values;
+
+            // The copy gets its own value list so the arguments of the function are unchanged.
+            self.value_lists.clear();
+            if let Some(args) = data.value_list_mut() {
+                let mut copy = ValueList::default();
+                copy.extend(args.as_slice(&dfg.value_lists).iter().cloned(),
+                            &mut self.value_lists);
+                *args = copy;
+            }
+            data.each_arg_mut(&mut self.value_lists,
+                              |arg| *arg = values.get(dfg.resolve_aliases(*arg)));
+        }
+
+        match data {
+            InstructionData::Jump { ref mut destination, .. } |
+            InstructionData::Branch { ref mut destination, .. } |
+            InstructionData::BranchIcmp { ref mut destination, .. } => {
+                *destination = self.ebbs.get(*destination);
+            }
+            InstructionData::BranchTable { ref mut table, .. } |
+            InstructionData::BranchTableEntry { ref mut table, .. } |
+            InstructionData::BranchTableBase { ref mut table, .. } => {
+                *table = self.jump_tables.get(*table);
+            }
+            InstructionData::Call { ref mut func_ref, .. } => {
+                *func_ref = self.ext_funcs.get(*func_ref);
+            }
+            InstructionData::FuncAddr { ref mut func_ref, .. } => {
+                *func_ref = self.ext_funcs.get(*func_ref);
+            }
+            InstructionData::IndirectCall { ref mut sig_ref, .. } => {
+                *sig_ref = self.signatures.get(*sig_ref);
+            }
+            InstructionData::HeapAddr { ref mut heap, .. } => {
+                *heap = self.heaps.get(*heap);
+            }
+            InstructionData::UnaryGlobalVar { ref mut global_var, .. } => {
+                *global_var = self.global_vars.get(*global_var);
+            }
+            InstructionData::StackLoad { ref mut stack_slot, .. } |
+            InstructionData::StackStore { ref mut stack_slot, .. } => {
+                *stack_slot = self.stack_slots.get(*stack_slot);
+            }
+            _ => {}
+        }
+        data
+    }
+
+    /// Write the preamble declarations in canonical order.
+    ///
+    /// Jump tables refer to EBBs, and external functions refer to signatures, so they are written
+    /// before the entities they refer to get their final numbering.
+    fn preamble(&mut self) {
+        let func = self.func;
+
+        self.jump_tables.number_all(func.jump_tables.keys());
+        self.enc.uint(self.jump_tables.order.len() as u64);
+        for i in 0..self.jump_tables.order.len() {
+            let table = &func.jump_tables[self.jump_tables.order[i]];
+            self.enc.uint(table.len() as u64);
+            for idx in 0..table.len() {
+                let dest = table.get_entry(idx).map(|ebb| self.ebbs.get(ebb));
+                self.enc.packed(dest.into());
+            }
+        }
+
+        self.ext_funcs.number_all(func.dfg.ext_funcs.keys());
+        self.enc.uint(self.ext_funcs.order.len() as u64);
+        for i in 0..self.ext_funcs.order.len() {
+            let mut data = func.dfg.ext_funcs[self.ext_funcs.order[i]].clone();
+            data.signature = self.signatures.get(data.signature);
+            data.encode(&mut self.enc);
+        }
+
+        self.signatures.number_all(func.dfg.signatures.keys());
+        self.enc.uint(self.signatures.order.len() as u64);
+        for &sig in &self.signatures.order {
+            func.dfg.signatures[sig].encode(&mut self.enc);
+        }
+
+        self.global_vars.number_all(func.dfg.global_vars.keys());
+        self.enc.uint(self.global_vars.order.len() as u64);
+        for &gv in &self.global_vars.order {
+            func.dfg.global_vars[gv].encode(&mut self.enc);
+        }
+        let stack_limit = func.stack_limit.map(|gv| self.global_vars.get(gv));
+        self.enc.packed(stack_limit.into());
+
+        self.heaps.number_all(func.heaps.keys());
+        self.enc.uint(self.heaps.order.len() as u64);
+        for &heap in &self.heaps.order {
+            func.heaps[heap].encode(&mut self.enc);
+        }
+
+        self.stack_slots.number_all(func.stack_slots.keys());
+        self.enc.uint(self.stack_slots.order.len() as u64);
+        for &ss in &self.stack_slots.order {
+            func.stack_slots[ss].encode(&mut self.enc);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::equivalent;
+    use ir::{Function, Cursor, InstBuilder, ExternalName, Signature, ArgumentType, StackSlotData,
+             StackSlotKind, SourceLoc, Value, VariableArgs};
+    use ir::types::*;
+
+    fn args(values: &[Value]) -> VariableArgs {
+        let mut args = VariableArgs::new();
+        for &v in values {
+            args.push(v);
+        }
+        args
+    }
+
+    /// Make the same function with entities created in different orders, depending on `shuffle`.
+    fn make_function(shuffle: bool, imm: i64) -> Function {
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(I32));
+        sig.return_types.push(ArgumentType::new(I32));
+        let mut func = Function::with_name_signature(ExternalName::testcase("f"), sig);
+
+        let (ebb0, ebb1, ss0, ss1);
+        if shuffle {
+            func.dfg.make_ebb();
+            ebb1 = func.dfg.make_ebb();
+            ebb0 = func.dfg.make_ebb();
+            ss1 = func.stack_slots
+                .push(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+            ss0 = func.stack_slots
+                .push(StackSlotData::new(StackSlotKind::ExplicitSlot, 4));
+        } else {
+            ebb0 = func.dfg.make_ebb();
+            ebb1 = func.dfg.make_ebb();
+            ss0 = func.stack_slots
+                .push(StackSlotData::new(StackSlotKind::ExplicitSlot, 4));
+            ss1 = func.stack_slots
+                .push(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+        }
+        let (arg0, arg1);
+        if shuffle {
+            arg1 = func.dfg.append_ebb_arg(ebb1, I32);
+            arg0 = func.dfg.append_ebb_arg(ebb0, I32);
+        } else {
+            arg0 = func.dfg.append_ebb_arg(ebb0, I32);
+            arg1 = func.dfg.append_ebb_arg(ebb1, I32);
+        }
+
+        let dfg = &mut func.dfg;
+        let pos = &mut Cursor::new(&mut func.layout);
+        pos.insert_ebb(ebb0);
+        let v1 = dfg.ins(pos).iconst(I32, imm);
+        dfg.ins(pos).stack_store(arg0, ss0, 0u32);
+        dfg.ins(pos).brnz(arg0, ebb1, args(&[v1]));
+        dfg.ins(pos).return_(args(&[v1]));
+        pos.insert_ebb(ebb1);
+        let v1 = if shuffle { dfg.make_value_alias(v1) } else { v1 };
+        let v2 = dfg.ins(pos).iadd(arg1, v1);
+        dfg.ins(pos).stack_store(v2, ss1, 0u32);
+        dfg.ins(pos).return_(args(&[v2]));
+        func
+    }
+
+    #[test]
+    fn renumbered() {
+        let a = make_function(false, 5);
+        let mut b = make_function(true, 5);
+        assert!(a.to_string() != b.to_string());
+        assert!(equivalent(&a, &b));
+        assert_eq!(a.structural_hash(), b.structural_hash());
+
+        // Names and source locations don't matter.
+        b.name = ExternalName::testcase("g");
+        let inst = b.layout.ebb_insts(b.layout.entry_block().unwrap()).next().unwrap();
+        b.set_srcloc(inst, SourceLoc::new(3));
+        assert!(equivalent(&a, &b));
+        assert_eq!(a.structural_hash(), b.structural_hash());
+    }
+
+    #[test]
+    fn different() {
+        let a = make_function(false, 5);
+        let b = make_function(true, 6);
+        assert!(!equivalent(&a, &b));
+        assert!(a.structural_hash() != b.structural_hash());
+
+        // Swapping the sizes of the stack slots changes the function.
+        let mut c = make_function(true, 5);
+        let slots: Vec<_> = c.stack_slots.keys().collect();
+        c.stack_slots[slots[0]].size = 4;
+        c.stack_slots[slots[1]].size = 8;
+        assert!(!equivalent(&a, &c));
+
+        assert!(equivalent(&Function::new(), &Function::new()));
+        assert!(!equivalent(&a, &Function::new()));
+    }
+}
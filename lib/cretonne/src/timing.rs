@@ -0,0 +1,395 @@
+//! Pass timing and statistics.
+//!
+//! This module records the time spent in each compiler pass along with a few counters describing
+//! what the passes did. Collection is opt-in and per thread: nothing is recorded until
+//! `set_enabled(true)` is called on the compilation thread.
+//!
+//! A pass is timed by holding on to the token returned by `start_pass()` while it runs. Passes can
+//! be nested, and the time spent in a nested pass is subtracted from the self time of the pass
+//! that started it.
+//!
+//! The statistics accumulate until `take_current()` is called. Calling it after compiling each
+//! function gives per-function statistics which can be combined into an aggregate report with
+//! `PassTimes::add()`.
+//!
+//! Statistics are only collected when the library is built with the `std` feature.
+
+use std::fmt;
+use std::time::Duration;
+
+/// A compiler pass that can be timed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    /// Computing the control flow graph, dominator tree, and loop analysis.
+    Flowgraph,
+    /// Running the verifier.
+    Verifier,
+    /// Pre-optimization peephole pass.
+    Preopt,
+    /// Constant folding.
+    Fold,
+    /// Instruction combining.
+    Combine,
+    /// Redundant load elimination.
+    RedundantLoads,
+    /// Pruning and sinking cold code.
+    ColdCode,
+    /// If-conversion.
+    IfConversion,
+    /// Dead stack store elimination.
+    DeadStores,
+    /// Profile-guided EBB ordering.
+    BlockOrder,
+    /// Shadow memory instrumentation of heap accesses.
+    ShadowMemory,
+    /// Legalization.
+    Legalize,
+    /// Register allocation.
+    Regalloc,
+    /// Post-regalloc peephole pass.
+    Postopt,
+    /// Prologue and epilogue insertion.
+    PrologueEpilogue,
+    /// Merging EBB chains and inserting fall-throughs.
+    Straighten,
+    /// Branch relaxation.
+    BranchRelaxation,
+}
+
+const NUM_PASSES: usize = 17;
+
+const PASS_NAMES: [&'static str; NUM_PASSES] = ["flowgraph",
+                                                 "verifier",
+                                                 "preopt",
+                                                 "constant folding",
+                                                 "combine",
+                                                 "redundant loads",
+                                                 "cold code",
+                                                 "if-conversion",
+                                                 "dead stores",
+                                                 "block ordering",
+                                                 "shadow memory",
+                                                 "legalizer",
+                                                 "regalloc",
+                                                 "postopt",
+                                                 "prologue/epilogue",
+                                                 "straightening",
+                                                 "branch relaxation"];
+
+impl Pass {
+    /// Get a human-readable name for the pass.
+    pub fn name(self) -> &'static str {
+        PASS_NAMES[self as usize]
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A statistic counted by the compiler passes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    /// Instructions replaced by a constant by the constant folding pass.
+    InstsFolded,
+    /// Values spilled to the stack by the register allocator.
+    ValuesSpilled,
+    /// Reloads of spilled constants that were replaced by computing the constant again.
+    ValuesRematerialized,
+}
+
+const NUM_COUNTERS: usize = 3;
+
+const COUNTER_NAMES: [&'static str; NUM_COUNTERS] = ["instructions folded",
+                                                     "values spilled",
+                                                     "values rematerialized"];
+
+impl Counter {
+    /// Get a human-readable name for the counter.
+    pub fn name(self) -> &'static str {
+        COUNTER_NAMES[self as usize]
+    }
+}
+
+impl fmt::Display for Counter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The time spent in a single pass.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PassTime {
+    /// Total time spent running the pass, including nested passes.
+    pub total: Duration,
+
+    /// Time spent in passes nested inside this one.
+    pub child: Duration,
+}
+
+impl PassTime {
+    /// Get the time spent in the pass itself, excluding nested passes.
+    pub fn self_time(&self) -> Duration {
+        self.total - self.child
+    }
+}
+
+/// Accumulated pass times and counters.
+#[derive(Clone, Debug, Default)]
+pub struct PassTimes {
+    passes: [PassTime; NUM_PASSES],
+    counts: [u64; NUM_COUNTERS],
+}
+
+impl PassTimes {
+    /// Create an empty set of statistics.
+    pub fn new() -> PassTimes {
+        PassTimes::default()
+    }
+
+    /// Get the time spent in `pass`.
+    pub fn pass_time(&self, pass: Pass) -> PassTime {
+        self.passes[pass as usize]
+    }
+
+    /// Get the value of `counter`.
+    pub fn count(&self, counter: Counter) -> u64 {
+        self.counts[counter as usize]
+    }
+
+    /// Get the total time spent in all passes.
+    pub fn total(&self) -> Duration {
+        self.passes.iter().fold(Duration::new(0, 0), |sum, p| sum + p.self_time())
+    }
+
+    /// Add the statistics in `other` to this set.
+    ///
+    /// This is used to aggregate the statistics for multiple functions.
+    pub fn add(&mut self, other: &PassTimes) {
+        for (p, o) in self.passes.iter_mut().zip(other.passes.iter()) {
+            p.total += o.total;
+            p.child += o.child;
+        }
+        for (c, o) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *c += *o;
+        }
+    }
+}
+
+/// Write a report listing the time spent in each pass that ran, followed by the counters.
+impl fmt::Display for PassTimes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "======== ========  ==================================")?;
+        writeln!(f, "   Total     Self  Pass")?;
+        writeln!(f, "-------- --------  ----------------------------------")?;
+        for (time, name) in self.passes.iter().zip(PASS_NAMES.iter()) {
+            if time.total == Duration::new(0, 0) {
+                continue;
+            }
+            writeln!(f,
+                     "{:8} {:8}  {}",
+                     Millis(time.total),
+                     Millis(time.self_time()),
+                     name)?;
+        }
+        writeln!(f, "======== ========  ==================================")?;
+        for (count, name) in self.counts.iter().zip(COUNTER_NAMES.iter()) {
+            writeln!(f, "{:17}  {}", count, name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Display a duration as milliseconds with three decimals.
+struct Millis(Duration);
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = self.0.as_secs() as f64 * 1e3 + self.0.subsec_nanos() as f64 * 1e-6;
+        format!("{:.3}", ms).fmt(f)
+    }
+}
+
+#[cfg(feature = "std")]
+pub use self::details::{set_enabled, is_enabled, start_pass, add_count, take_current,
+                        TimingToken};
+#[cfg(not(feature = "std"))]
+pub use self::dummy::{set_enabled, is_enabled, start_pass, add_count, take_current,
+                      TimingToken};
+
+#[cfg(feature = "std")]
+mod details {
+    use super::{Pass, Counter, PassTimes};
+    use std::cell::RefCell;
+    use std::mem;
+    use std::time::Instant;
+
+    /// The statistics collection state of a thread.
+    struct State {
+        enabled: bool,
+        current_pass: Option<Pass>,
+        times: PassTimes,
+    }
+
+    thread_local! {
+        static STATE: RefCell<State> = RefCell::new(State {
+                                                        enabled: false,
+                                                        current_pass: None,
+                                                        times: PassTimes::new(),
+                                                    });
+    }
+
+    /// Start or stop collecting statistics on the current thread.
+    ///
+    /// The statistics collected so far are kept.
+    pub fn set_enabled(enable: bool) {
+        STATE.with(|s| s.borrow_mut().enabled = enable);
+    }
+
+    /// Is statistics collection enabled on the current thread?
+    pub fn is_enabled() -> bool {
+        STATE.with(|s| s.borrow().enabled)
+    }
+
+    /// Start timing `pass`.
+    ///
+    /// The pass is timed until the returned token is dropped. When statistics collection is
+    /// disabled, this does nothing.
+    pub fn start_pass(pass: Pass) -> TimingToken {
+        let prev = STATE.with(|s| {
+            let mut s = s.borrow_mut();
+            if s.enabled {
+                Some(s.current_pass.replace(pass))
+            } else {
+                None
+            }
+        });
+        TimingToken {
+            active: prev.map(|prev| {
+                                 ActivePass {
+                                     pass: pass,
+                                     prev: prev,
+                                     start: Instant::now(),
+                                 }
+                             }),
+        }
+    }
+
+    /// Add `n` to `counter` if statistics collection is enabled.
+    pub fn add_count(counter: Counter, n: u64) {
+        STATE.with(|s| {
+            let mut s = s.borrow_mut();
+            if s.enabled {
+                s.times.counts[counter as usize] += n;
+            }
+        });
+    }
+
+    /// Take the statistics collected on the current thread, and start over with an empty set.
+    pub fn take_current() -> PassTimes {
+        STATE.with(|s| mem::replace(&mut s.borrow_mut().times, PassTimes::new()))
+    }
+
+    /// A token returned by `start_pass()`. The pass is timed until the token is dropped.
+    pub struct TimingToken {
+        active: Option<ActivePass>,
+    }
+
+    struct ActivePass {
+        pass: Pass,
+        prev: Option<Pass>,
+        start: Instant,
+    }
+
+    impl Drop for TimingToken {
+        fn drop(&mut self) {
+            if let Some(ref active) = self.active {
+                let elapsed = active.start.elapsed();
+                STATE.with(|s| {
+                    let mut s = s.borrow_mut();
+                    s.current_pass = active.prev;
+                    s.times.passes[active.pass as usize].total += elapsed;
+                    if let Some(prev) = active.prev {
+                        s.times.passes[prev as usize].child += elapsed;
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Without `std` there is no clock and no thread-local storage, so nothing is ever recorded.
+#[cfg(not(feature = "std"))]
+mod dummy {
+    use super::{Pass, Counter, PassTimes};
+
+    /// Start or stop collecting statistics. This does nothing without `std`.
+    pub fn set_enabled(_enable: bool) {}
+
+    /// Is statistics collection enabled? Never without `std`.
+    pub fn is_enabled() -> bool {
+        false
+    }
+
+    /// Start timing `pass`. This does nothing without `std`.
+    pub fn start_pass(_pass: Pass) -> TimingToken {
+        TimingToken
+    }
+
+    /// Add `n` to `counter`. This does nothing without `std`.
+    pub fn add_count(_counter: Counter, _n: u64) {}
+
+    /// Take the statistics collected so far, which are always empty without `std`.
+    pub fn take_current() -> PassTimes {
+        PassTimes::new()
+    }
+
+    /// A token returned by `start_pass()`.
+    pub struct TimingToken;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn nested_passes() {
+        let zero = Duration::new(0, 0);
+
+        // Nothing is recorded by default.
+        drop(start_pass(Pass::Legalize));
+        add_count(Counter::InstsFolded, 1);
+        assert_eq!(take_current().count(Counter::InstsFolded), 0);
+
+        set_enabled(true);
+        {
+            let _outer = start_pass(Pass::Regalloc);
+            let _inner = start_pass(Pass::Verifier);
+            add_count(Counter::ValuesSpilled, 2);
+        }
+        set_enabled(false);
+
+        let times = take_current();
+        let regalloc = times.pass_time(Pass::Regalloc);
+        let verifier = times.pass_time(Pass::Verifier);
+        assert_eq!(regalloc.child, verifier.total);
+        assert_eq!(verifier.child, zero);
+        assert_eq!(times.pass_time(Pass::Legalize).total, zero);
+        assert_eq!(times.count(Counter::ValuesSpilled), 2);
+
+        let mut sum = PassTimes::new();
+        sum.add(&times);
+        sum.add(&times);
+        assert_eq!(sum.count(Counter::ValuesSpilled), 4);
+        assert_eq!(sum.pass_time(Pass::Regalloc).total, regalloc.total * 2);
+
+        let report = sum.to_string();
+        assert!(report.contains("regalloc"));
+        assert!(!report.contains("legalizer"));
+        assert!(report.contains("4  values spilled"));
+        assert_eq!(take_current().count(Counter::ValuesSpilled), 0);
+    }
+}
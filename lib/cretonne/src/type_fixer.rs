@@ -0,0 +1,257 @@
+//! Type fixer for front ends.
+//!
+//! Cretonne doesn't have implicit type conversions, so every instruction argument must have
+//! exactly the type required by the instruction, the destination EBB, or the call signature. The
+//! verifier rejects a function at the first type error it finds. This module helps front ends
+//! while they are being developed:
+//!
+//! - `check_types` reports all the argument type mismatches in a function along with the
+//!   conversion instructions that could fix each of them.
+//! - `fix_types` inserts the conversions where the choice is obvious, and reports the mismatches
+//!   it couldn't fix.
+//!
+//! Only the arguments with a fixed expected type are checked. Arguments constrained to a type set,
+//! like the controlling type variable operand, are left for the verifier.
+
+use ir::{Function, Cursor, DataFlowGraph, Inst, InstBuilder, Opcode, Type, Value,
+         ArgumentExtension};
+use ir::instructions::{BranchInfo, CallInfo, ResolvedConstraint};
+use std::fmt::{self, Display, Formatter};
+use std::vec::Vec;
+
+/// An instruction argument with the wrong type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypeMismatch {
+    /// The instruction using the value.
+    pub inst: Inst,
+
+    /// Index of the argument in the fixed and variable arguments of `inst`.
+    pub arg: usize,
+
+    /// The value passed as the argument.
+    pub value: Value,
+
+    /// The type of `value`.
+    pub actual: Type,
+
+    /// The type expected by the instruction.
+    pub expected: Type,
+
+    /// Conversion instructions that can turn `actual` into `expected`, if any.
+    ///
+    /// When there is a choice between an unsigned and a signed conversion, the unsigned one comes
+    /// first.
+    pub conversions: &'static [Opcode],
+}
+
+impl Display for TypeMismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f,
+               "{}: arg {} ({}) has type {}, expected {}",
+               self.inst,
+               self.arg,
+               self.value,
+               self.actual,
+               self.expected)?;
+        if let Some((first, rest)) = self.conversions.split_first() {
+            write!(f, "; convert with {}", first)?;
+            for opcode in rest {
+                write!(f, " or {}", opcode)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get the conversion instructions that can convert a value of type `from` to type `to`.
+fn conversions(from: Type, to: Type) -> &'static [Opcode] {
+    if from.lane_count() != to.lane_count() {
+        return if from.bits() == to.bits() && !from.is_bool() && !to.is_bool() {
+                   &[Opcode::Bitcast]
+               } else {
+                   &[]
+               };
+    }
+
+    let (from_bits, to_bits) = (from.lane_bits(), to.lane_bits());
+    if from.is_int() && to.is_int() {
+        if from_bits < to_bits {
+            &[Opcode::Uextend, Opcode::Sextend]
+        } else {
+            &[Opcode::Ireduce]
+        }
+    } else if from.is_float() && to.is_float() {
+        if from_bits < to_bits {
+            &[Opcode::Fpromote]
+        } else {
+            &[Opcode::Fdemote]
+        }
+    } else if from.is_bool() && to.is_int() {
+        &[Opcode::Bint]
+    } else if from.is_int() && to.is_float() {
+        &[Opcode::FcvtFromUint, Opcode::FcvtFromSint]
+    } else if from.is_float() && to.is_int() {
+        &[Opcode::FcvtToUint, Opcode::FcvtToSint]
+    } else {
+        &[]
+    }
+}
+
+/// Get the expected types of the arguments to `inst`, or `None` where the type is not fixed.
+fn expected_types(func: &Function, inst: Inst) -> Vec<Option<Type>> {
+    let dfg = &func.dfg;
+    let data = &dfg[inst];
+    let constraints = data.opcode().constraints();
+    let ctrl_type = data.ctrl_typevar(dfg);
+
+    let mut types: Vec<Option<Type>> = (0..dfg.inst_args(inst)[0].len())
+        .map(|i| match constraints.value_argument_constraint(i, ctrl_type) {
+                 ResolvedConstraint::Bound(ty) => Some(ty),
+                 ResolvedConstraint::Free(_) => None,
+             })
+        .collect();
+
+    let sig = match data.analyze_call(&dfg.value_lists) {
+        CallInfo::Direct(fref, _) => Some(&dfg.signatures[dfg.ext_funcs[fref].signature]),
+        CallInfo::Indirect(sig, _) => Some(&dfg.signatures[sig]),
+        CallInfo::NotACall => None,
+    };
+    if let BranchInfo::SingleDest(ebb, _) = data.analyze_branch(&dfg.value_lists) {
+        types.extend(dfg.ebb_args(ebb).map(|arg| Some(dfg.value_type(arg))));
+    } else if let Some(sig) = sig {
+        types.extend(sig.argument_types.iter().map(|arg| Some(arg.value_type)));
+    } else if let Opcode::Return | Opcode::ReturnReg = data.opcode() {
+        types.extend(func.signature.return_types.iter().map(|arg| Some(arg.value_type)));
+    }
+    types
+}
+
+/// Find the mismatched argument types of `inst`.
+fn check_inst(func: &Function, inst: Inst, mismatches: &mut Vec<TypeMismatch>) {
+    let dfg = &func.dfg;
+    let args = dfg.inst_args(inst);
+    let values = args[0].iter().chain(args[1].iter());
+    for (arg, (&value, expected)) in values.zip(expected_types(func, inst)).enumerate() {
+        let actual = dfg.value_type(value);
+        match expected {
+            Some(expected) if actual != expected => {
+                mismatches.push(TypeMismatch {
+                                    inst: inst,
+                                    arg: arg,
+                                    value: value,
+                                    actual: actual,
+                                    expected: expected,
+                                    conversions: conversions(actual, expected),
+                                })
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Report all the instruction arguments in `func` that don't have the expected type.
+pub fn check_types(func: &Function) -> Vec<TypeMismatch> {
+    let mut mismatches = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            check_inst(func, inst, &mut mismatches);
+        }
+    }
+    mismatches
+}
+
+/// Insert conversions for the instruction arguments in `func` that don't have the expected type.
+///
+/// A conversion is inserted when there is only one possible conversion instruction. When there is
+/// a choice between an unsigned and a signed conversion, `extension` decides. With
+/// `ArgumentExtension::None`, such mismatches are not fixed.
+///
+/// Returns the mismatches that were not fixed.
+pub fn fix_types(func: &mut Function, extension: ArgumentExtension) -> Vec<TypeMismatch> {
+    let mut unfixed = Vec::new();
+    for mismatch in check_types(func) {
+        let opcode = match (mismatch.conversions, extension) {
+            (&[opcode], _) => opcode,
+            (&[unsigned, _], ArgumentExtension::Uext) => unsigned,
+            (&[_, signed], ArgumentExtension::Sext) => signed,
+            _ => {
+                unfixed.push(mismatch);
+                continue;
+            }
+        };
+
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_inst(mismatch.inst);
+        let (conv, dfg) = func.dfg.ins(&mut pos).Unary(opcode, mismatch.expected, mismatch.value);
+        let value = dfg.first_result(conv);
+        set_argument(dfg, mismatch.inst, mismatch.arg, value);
+    }
+    unfixed
+}
+
+/// Replace argument number `arg` of `inst`, counting both fixed and variable arguments.
+fn set_argument(dfg: &mut DataFlowGraph, inst: Inst, arg: usize, value: Value) {
+    let args = dfg.inst_args_mut(inst);
+    let fixed = args[0].len();
+    if arg < fixed {
+        args[0][arg] = value;
+    } else {
+        args[1][arg - fixed] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, InstBuilder, Opcode, ArgumentType, ArgumentExtension, VariableArgs};
+    use ir::types;
+    use verifier::verify_function;
+    use super::{check_types, fix_types};
+
+    #[test]
+    fn type_fixer() {
+        let mut func = Function::new();
+        func.signature.return_types.push(ArgumentType::new(types::F64));
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I64);
+        let v2 = func.dfg.append_ebb_arg(ebb0, types::F32);
+        func.dfg.append_ebb_arg(ebb1, types::I64);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            // The second argument is an `i64`.
+            dfg.ins(pos).iadd(v0, v1);
+            let mut args = VariableArgs::new();
+            args.push(v0);
+            dfg.ins(pos).jump(ebb1, args);
+            pos.insert_ebb(ebb1);
+            let mut rets = VariableArgs::new();
+            rets.push(v2);
+            dfg.ins(pos).return_(rets);
+        }
+
+        let mismatches = check_types(&func);
+        assert_eq!(mismatches.len(), 3);
+        assert_eq!(mismatches[0].arg, 1);
+        assert_eq!(mismatches[0].value, v1);
+        assert_eq!(mismatches[0].conversions, &[Opcode::Ireduce]);
+        assert!(mismatches[1].to_string().ends_with("has type i32, expected i64; convert with \
+                                                     uextend or sextend"));
+        assert_eq!(mismatches[2].conversions, &[Opcode::Fpromote]);
+
+        // Without an extension policy, the jump argument can't be fixed.
+        let unfixed = fix_types(&mut func, ArgumentExtension::None);
+        assert_eq!(unfixed, &mismatches[1..2]);
+        assert!(verify_function(&func).is_err());
+
+        assert_eq!(fix_types(&mut func, ArgumentExtension::Sext), []);
+        assert_eq!(check_types(&func), []);
+        assert_eq!(verify_function(&func), Ok(()));
+        let text = func.to_string();
+        assert!(text.contains("ireduce.i32 vx1"));
+        assert!(text.contains("sextend.i64 vx0"));
+        assert!(text.contains("fpromote.f64 vx2"));
+    }
+}
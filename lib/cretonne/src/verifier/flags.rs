@@ -0,0 +1,156 @@
+//! Verify CPU flags values.
+//!
+//! The value types `iflags` and `fflags` represent CPU flags which usually live in a single
+//! special-purpose register, so they can't be used as freely as other value types:
+//!
+//! - At most one flags value can be live at a time.
+//! - A flags value can't be live across an instruction that defines another flags value, since
+//!   that instruction overwrites the flags register.
+//! - When a target ISA is given, a flags value can't be live across an instruction whose
+//!   encoding clobbers the flags register, like most Intel arithmetic.
+
+use cfg::ControlFlowGraph;
+use entity_map::EntityMap;
+use ir::{Function, Ebb, Inst, Value};
+use isa::TargetIsa;
+use packed_option::PackedOption;
+use std::vec::Vec;
+use verifier::{Error, Result};
+
+/// Verify that CPU flags are used correctly in `func`.
+///
+/// The control flow graph `cfg` must be up to date with `func`. When `isa` is given, the
+/// instruction encodings are also checked for clobbering the flags register.
+pub fn verify_flags(func: &Function,
+                    cfg: &ControlFlowGraph,
+                    isa: Option<&TargetIsa>)
+                    -> Result<()> {
+    let mut verifier = FlagsVerifier {
+        func: func,
+        cfg: cfg,
+        isa: isa,
+        livein: EntityMap::new(),
+    };
+    verifier.check()
+}
+
+struct FlagsVerifier<'a> {
+    func: &'a Function,
+    cfg: &'a ControlFlowGraph,
+    isa: Option<&'a TargetIsa>,
+
+    /// The single flags value that is live-in to each EBB, if any.
+    livein: EntityMap<Ebb, PackedOption<Value>>,
+}
+
+impl<'a> FlagsVerifier<'a> {
+    fn check(&mut self) -> Result<()> {
+        // Propagate the live-in flags values backwards through the CFG until nothing changes.
+        // Visiting the EBBs in reverse layout order makes this converge quickly for most
+        // functions.
+        let ebbs: Vec<Ebb> = self.func.layout.ebbs().collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &ebb in ebbs.iter().rev() {
+                let livein = self.visit_ebb(ebb)?;
+                if self.livein_value(ebb) != livein {
+                    *self.livein.ensure(ebb) = livein.into();
+                    changed = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the flags value that is currently known to be live-in to `ebb`.
+    fn livein_value(&self, ebb: Ebb) -> Option<Value> {
+        self.livein.get(ebb).and_then(|v| v.expand())
+    }
+
+    /// Scan `ebb` backwards and compute the flags value that is live-in to it.
+    fn visit_ebb(&self, ebb: Ebb) -> Result<Option<Value>> {
+        let dfg = &self.func.dfg;
+
+        // The flags value that is live out of `ebb` must be the same for all successors.
+        let mut live: Option<Value> = None;
+        for &succ in self.cfg.get_successors(ebb) {
+            if let Some(value) = self.livein_value(succ) {
+                match live {
+                    Some(other) if other != value => {
+                        return err!(ebb,
+                                    "flags values {} and {} are both live out of the EBB",
+                                    other,
+                                    value)
+                    }
+                    _ => live = Some(value),
+                }
+            }
+        }
+
+        for inst in self.func.layout.ebb_insts(ebb).rev() {
+            // A definition of a flags value ends its live range, and it clobbers any other flags
+            // value that is live here.
+            for res in dfg.inst_results(inst) {
+                if !dfg.value_type(res).is_flags() {
+                    continue;
+                }
+                match live {
+                    Some(value) if value == res => live = None,
+                    Some(value) => {
+                        return err!(inst, "{} clobbers live flags value {}", res, value);
+                    }
+                    None => {}
+                }
+            }
+
+            if let Some(value) = live {
+                if self.clobbers_flags(inst) {
+                    return err!(inst, "encoding clobbers live flags value {}", value);
+                }
+            }
+
+            self.visit_uses(inst, &mut live)?;
+        }
+
+        // A flags value defined as an argument of this EBB is not live-in.
+        if let Some(value) = live {
+            if dfg.ebb_args(ebb).any(|arg| arg == value) {
+                live = None;
+            }
+        }
+
+        Ok(live)
+    }
+
+    /// Does the encoding of `inst` clobber the flags register?
+    ///
+    /// Instructions without an encoding are assumed to preserve the flags.
+    fn clobbers_flags(&self, inst: Inst) -> bool {
+        match (self.isa, self.func.encodings.get(inst)) {
+            (Some(isa), Some(&enc)) if enc.is_legal() => {
+                isa.recipe_constraints()[enc.recipe()].clobbers_flags
+            }
+            _ => false,
+        }
+    }
+
+    /// Record the flags values used by `inst` as `live`.
+    fn visit_uses(&self, inst: Inst, live: &mut Option<Value>) -> Result<()> {
+        let dfg = &self.func.dfg;
+        for part in &dfg.inst_args(inst) {
+            for &arg in part.iter() {
+                if !dfg.value_type(arg).is_flags() {
+                    continue;
+                }
+                match *live {
+                    Some(value) if value != arg => {
+                        return err!(inst, "flags values {} and {} are both live", value, arg);
+                    }
+                    _ => *live = Some(arg),
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+//! Verify a liveness analysis.
+//!
+//! The liveness analysis used by the register allocator computes live ranges incrementally by
+//! walking backwards from each use. This module checks the result against an independent
+//! computation:
+//!
+//! - Every value used by an instruction has a live range which covers the use.
+//! - The live-in sets computed by a simple data-flow fixpoint iteration match the EBBs where the
+//!   live ranges say each value is live-in.
+
+use cfg::ControlFlowGraph;
+use entity_map::EntityMap;
+use ir::{Function, Ebb, Inst, Value, ValueDef, ProgramOrder, ProgramPoint};
+use regalloc::liverange::LiveRange;
+use regalloc::liveness::Liveness;
+use sparse_map::SparseMapValue;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::vec::Vec;
+use verifier::{Error, Result};
+
+/// Verify that `liveness` is a correct liveness analysis of `func`.
+///
+/// The control flow graph `cfg` must be up to date with `func`.
+pub fn verify_liveness(func: &Function,
+                       cfg: &ControlFlowGraph,
+                       liveness: &Liveness)
+                       -> Result<()> {
+    let verifier = LivenessVerifier {
+        func: func,
+        cfg: cfg,
+        liveness: liveness,
+    };
+    verifier.check_defs()?;
+    verifier.check_uses()?;
+    verifier.check_liveins()
+}
+
+struct LivenessVerifier<'a> {
+    func: &'a Function,
+    cfg: &'a ControlFlowGraph,
+    liveness: &'a Liveness,
+}
+
+impl<'a> LivenessVerifier<'a> {
+    /// Get the EBB containing the definition of `value`.
+    fn def_ebb(&self, value: Value) -> Option<Ebb> {
+        match self.func.dfg.value_def(value) {
+            ValueDef::Res(inst, _) => self.func.layout.inst_ebb(inst),
+            ValueDef::Arg(ebb, _) => Some(ebb),
+        }
+    }
+
+    /// Check that all live ranges start at the definition of their value.
+    fn check_defs(&self) -> Result<()> {
+        for lr in self.liveness.iter() {
+            let value = lr.key();
+            let def: ProgramPoint = match self.func.dfg.value_def(value) {
+                ValueDef::Res(inst, _) => inst.into(),
+                ValueDef::Arg(ebb, _) => ebb.into(),
+            };
+            if lr.def() != def {
+                return err!(value, "live range is defined at {}, expected {}", lr.def(), def);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that all uses of values are covered by their live ranges.
+    fn check_uses(&self) -> Result<()> {
+        for ebb in self.func.layout.ebbs() {
+            for inst in self.func.layout.ebb_insts(ebb) {
+                for part in &self.func.dfg.inst_args(inst) {
+                    for &arg in part.iter() {
+                        let lr = match self.liveness.get(arg) {
+                            Some(lr) => lr,
+                            None => return err!(inst, "{} has no live range", arg),
+                        };
+                        if !self.live_at_use(lr, ebb, inst) {
+                            return err!(inst, "{} is not live at this use", arg);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Does `lr` reach the use in `inst` which belongs to `ebb`?
+    fn live_at_use(&self, lr: &LiveRange, ebb: Ebb, inst: Inst) -> bool {
+        let layout = &self.func.layout;
+        let end = if self.def_ebb(lr.key()) == Some(ebb) {
+            lr.def_local_end()
+        } else {
+            match lr.livein_local_end(ebb, layout) {
+                Some(end) => end.into(),
+                None => return false,
+            }
+        };
+        layout.cmp(inst, end) != Ordering::Greater
+    }
+
+    /// Compute the live-in sets with a data-flow fixpoint and compare them to `liveness`.
+    fn check_liveins(&self) -> Result<()> {
+        let livein = self.compute_liveins();
+        let layout = &self.func.layout;
+
+        for ebb in layout.ebbs() {
+            let expected = livein.get(ebb);
+            for lr in self.liveness.iter() {
+                let value = lr.key();
+                let actual = lr.livein_local_end(ebb, layout).is_some();
+                if actual != expected.map_or(false, |set| set.contains(&value)) {
+                    return if actual {
+                               err!(ebb, "{} should not be live-in", value)
+                           } else {
+                               err!(ebb, "{} should be live-in", value)
+                           };
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compute the set of values that are live-in to each EBB.
+    ///
+    /// This solves the classic live variables data-flow equations:
+    ///
+    /// - `livein(ebb) = uses(ebb) ∪ (liveout(ebb) - defs(ebb))`, and
+    /// - `liveout(ebb) = ∪ livein(succ)` over the CFG successors of `ebb`.
+    ///
+    /// Here, `uses(ebb)` are the values used in `ebb` before any definition in `ebb`. In SSA form,
+    /// that is all the values used in `ebb` which are not defined in `ebb`.
+    fn compute_liveins(&self) -> EntityMap<Ebb, HashSet<Value>> {
+        let layout = &self.func.layout;
+        let dfg = &self.func.dfg;
+        let mut livein: EntityMap<Ebb, HashSet<Value>> = EntityMap::new();
+
+        // Start out with the upward exposed uses in each EBB.
+        for ebb in layout.ebbs() {
+            let set = livein.ensure(ebb);
+            for inst in layout.ebb_insts(ebb) {
+                for part in &dfg.inst_args(inst) {
+                    for &arg in part.iter() {
+                        if self.def_ebb(arg) != Some(ebb) {
+                            set.insert(arg);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Propagate backwards through the CFG until nothing changes. Visiting the EBBs in
+        // reverse layout order makes this converge quickly for most functions.
+        let ebbs: Vec<Ebb> = layout.ebbs().collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &ebb in ebbs.iter().rev() {
+                let mut new_values = Vec::new();
+                for &succ in self.cfg.get_successors(ebb) {
+                    for &value in &livein[succ] {
+                        if self.def_ebb(value) != Some(ebb) && !livein[ebb].contains(&value) {
+                            new_values.push(value);
+                        }
+                    }
+                }
+                if !new_values.is_empty() {
+                    livein[ebb].extend(new_values);
+                    changed = true;
+                }
+            }
+        }
+
+        livein
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cfg::ControlFlowGraph;
+    use ir::{Function, Cursor, InstBuilder, VariableArgs};
+    use ir::types;
+    use isa;
+    use regalloc::liveness::Liveness;
+    use settings;
+    use super::verify_liveness;
+
+    #[test]
+    fn liveness() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).brz(v0, ebb2, VariableArgs::new());
+            dfg.ins(pos).jump(ebb1, VariableArgs::new());
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).jump(ebb2, VariableArgs::new());
+            pos.insert_ebb(ebb2);
+            let v2 = dfg.ins(pos).iadd(v0, v1);
+            dfg.ins(pos).return_reg(v2, VariableArgs::new());
+        }
+        ::legalize_function(&mut func, &*isa).unwrap();
+        let cfg = ControlFlowGraph::with_function(&func);
+        let mut liveness = Liveness::new();
+        liveness.compute(&*isa, &func, &cfg);
+        assert_eq!(verify_liveness(&func, &cfg, &liveness), Ok(()));
+
+        // Compute liveness with a stale CFG that is missing the edge from `ebb1` to `ebb2`.
+        let mut stale_cfg = ControlFlowGraph::new();
+        let jump = func.layout.last_inst(ebb1).unwrap();
+        func.layout.remove_inst(jump);
+        stale_cfg.compute(&func);
+        func.layout.append_inst(jump, ebb1);
+        liveness.compute(&*isa, &func, &stale_cfg);
+        assert!(verify_liveness(&func, &cfg, &liveness)
+                    .unwrap_err()
+                    .message
+                    .contains("should be live-in"));
+    }
+}
@@ -0,0 +1,437 @@
+//! Verify value locations after register allocation.
+//!
+//! The register allocator must leave the function in a state where:
+//!
+//! - Every value that is live across an instruction has been assigned a location.
+//! - The value operands and results of every encoded instruction are in locations that satisfy
+//!   the operand constraints of the encoding recipe.
+//! - The values passed across ABI boundaries in registers are in the registers required by the
+//!   legalized signatures.
+//! - No two values are in the same register at the same program point.
+//! - The values in a virtual register don't interfere.
+//!
+//! Values can be temporarily diverted to other registers by `regmove` instructions. The diversions
+//! are tracked while scanning each EBB, and they are local to the EBB.
+
+use ir::{Function, Inst, Value, ValueLoc, InstructionData, ExpandedProgramPoint, ArgumentType};
+use ir::entities::AnyEntity;
+use isa::{TargetIsa, RegInfo, RegUnit, OperandConstraint, ConstraintKind};
+use regalloc::abi;
+use regalloc::affinity::Affinity;
+use regalloc::diversion::RegDiversions;
+use regalloc::liveness::Liveness;
+use regalloc::virtregs::VirtRegs;
+use sparse_map::SparseMapValue;
+use std::cmp;
+use std::string::String;
+use std::vec::Vec;
+use verifier::{Error, Result};
+
+/// Verify the value locations in `func` after register allocation.
+///
+/// The `liveness` analysis and the `virtregs` must be the ones used by the register allocator, as
+/// returned by `regalloc::Context::liveness()` and `regalloc::Context::virtregs()`.
+pub fn verify_locations(isa: &TargetIsa,
+                        func: &Function,
+                        liveness: &Liveness,
+                        virtregs: &VirtRegs)
+                        -> Result<()> {
+    let verifier = LocationVerifier {
+        isa: isa,
+        reginfo: isa.register_info(),
+        func: func,
+        liveness: liveness,
+        virtregs: virtregs,
+    };
+    verifier.check_assigned()?;
+    verifier.check_constraints()?;
+    verifier.check_interference()?;
+    verifier.check_virtregs()
+}
+
+struct LocationVerifier<'a> {
+    isa: &'a TargetIsa,
+    reginfo: RegInfo,
+    func: &'a Function,
+    liveness: &'a Liveness,
+    virtregs: &'a VirtRegs,
+}
+
+impl<'a> LocationVerifier<'a> {
+    /// Get the location assigned to `value`.
+    fn loc(&self, value: Value) -> ValueLoc {
+        self.func.locations.get(value).cloned().unwrap_or_default()
+    }
+
+    /// Get the range of register units currently occupied by `value`, taking diversions into
+    /// account.
+    fn units(&self, value: Value, divert: &RegDiversions) -> Option<(RegUnit, RegUnit)> {
+        match divert.location(value, &self.func.locations) {
+            ValueLoc::Reg(reg) => {
+                let width = match self.liveness.get(value).map(|lr| lr.affinity) {
+                    Some(Affinity::Reg(rci)) => self.reginfo.rc(rci).width,
+                    _ => 1,
+                };
+                Some((reg, reg + width as RegUnit))
+            }
+            _ => None,
+        }
+    }
+
+    /// Check that all values with a non-empty live range have a location.
+    fn check_assigned(&self) -> Result<()> {
+        for lr in self.liveness.iter() {
+            if lr.is_dead() {
+                continue;
+            }
+            if let ValueLoc::Unassigned = self.loc(lr.key()) {
+                return err!(lr.key(), "is live but has no assigned location");
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the locations of encoded instructions' fixed operands and results against the
+    /// recipe constraints, and the locations of values passed across ABI boundaries against the
+    /// legalized signatures.
+    fn check_constraints(&self) -> Result<()> {
+        let dfg = &self.func.dfg;
+        let recipe_constraints = self.isa.recipe_constraints();
+        let mut divert = RegDiversions::new();
+
+        // The entry block arguments arrive in the registers given by the function signature. Dead
+        // arguments may not have a location.
+        if let Some(entry) = self.func.layout.entry_block() {
+            for (arg, abi) in dfg.ebb_args(entry).zip(&self.func.signature.argument_types) {
+                if self.loc(arg) != ValueLoc::Unassigned {
+                    self.check_abi(entry.into(), arg, abi, &divert)?;
+                }
+            }
+        }
+
+        for ebb in self.func.layout.ebbs() {
+            divert.clear();
+            for inst in self.func.layout.ebb_insts(ebb) {
+                if let InstructionData::RegMove { arg, src, .. } = dfg[inst] {
+                    if divert.location(arg, &self.func.locations) != ValueLoc::Reg(src) {
+                        return err!(inst,
+                                    "{} is in {}, not {}",
+                                    arg,
+                                    divert
+                                        .location(arg, &self.func.locations)
+                                        .display(&self.reginfo),
+                                    self.reginfo.display_regunit(src));
+                    }
+                }
+                let encoding = match self.func.encodings.get(inst) {
+                    Some(&enc) if enc.is_legal() => enc,
+                    _ => {
+                        divert.apply(&dfg[inst]);
+                        continue;
+                    }
+                };
+                let constraints = &recipe_constraints[encoding.recipe()];
+                let args = &dfg.inst_args(inst)[0];
+
+                for (&arg, cst) in args.iter().zip(constraints.ins) {
+                    self.check_operand(inst, arg, cst, args, &divert)?;
+                }
+                for (res, cst) in dfg.inst_results(inst).zip(constraints.outs) {
+                    self.check_operand(inst, res, cst, args, &divert)?;
+                }
+
+                let varargs = &dfg.inst_args(inst)[1];
+                for (&arg, abi) in varargs.iter().zip(abi::arguments(self.func, inst)) {
+                    self.check_abi(inst.into(), arg, abi, &divert)?;
+                }
+                let results = dfg.inst_results(inst).skip(constraints.outs.len());
+                for (res, abi) in results.zip(abi::results(dfg, inst)) {
+                    self.check_abi(inst.into(), res, abi, &divert)?;
+                }
+                divert.apply(&dfg[inst]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the location of `value` satisfies `cst`.
+    ///
+    /// The fixed value arguments of `inst` are needed for tied operands.
+    fn check_operand(&self,
+                     inst: Inst,
+                     value: Value,
+                     cst: &OperandConstraint,
+                     args: &[Value],
+                     divert: &RegDiversions)
+                     -> Result<()> {
+        let loc = divert.location(value, &self.func.locations);
+        let ok = match (cst.kind, loc) {
+            (ConstraintKind::Reg, ValueLoc::Reg(reg)) => cst.regclass.contains(reg),
+            (ConstraintKind::FixedReg(fixed), ValueLoc::Reg(reg)) => reg == fixed,
+            (ConstraintKind::Tied(num), _) => {
+                match (loc, divert.location(args[num as usize], &self.func.locations)) {
+                    (ValueLoc::Reg(a), ValueLoc::Reg(b)) => a == b,
+                    (ValueLoc::Stack(a), ValueLoc::Stack(b)) => a == b,
+                    _ => false,
+                }
+            }
+            (ConstraintKind::Stack, ValueLoc::Stack(_)) => true,
+            _ => false,
+        };
+
+        if ok {
+            return Ok(());
+        }
+        let expected = match cst.kind {
+            ConstraintKind::Reg => format!("a {} register", cst.regclass.name),
+            ConstraintKind::FixedReg(reg) => format!("{}", self.reginfo.display_regunit(reg)),
+            ConstraintKind::Tied(num) => format!("the location of {}", args[num as usize]),
+            ConstraintKind::Stack => String::from("a stack slot"),
+        };
+        err!(inst,
+             "{} is in {}, but the encoding requires {}",
+             value,
+             loc.display(&self.reginfo),
+             expected)
+    }
+
+    /// Check that `value` is in the register required by `abi`, if it is passed in a register.
+    fn check_abi(&self,
+                 location: AnyEntity,
+                 value: Value,
+                 abi: &ArgumentType,
+                 divert: &RegDiversions)
+                 -> Result<()> {
+        let reg = match abi.location.reg() {
+            Some(reg) => reg,
+            None => return Ok(()),
+        };
+        let loc = divert.location(value, &self.func.locations);
+        if loc == ValueLoc::Reg(reg) {
+            return Ok(());
+        }
+        err!(location,
+             "{} is in {}, but the ABI requires {}",
+             value,
+             loc.display(&self.reginfo),
+             self.reginfo.display_regunit(reg))
+    }
+
+    /// Check that no two values are in the same register at the same program point.
+    ///
+    /// Each EBB is scanned in order while keeping track of the register values that are live at
+    /// the current instruction and any diversions.
+    fn check_interference(&self) -> Result<()> {
+        let layout = &self.func.layout;
+        let dfg = &self.func.dfg;
+        let mut divert = RegDiversions::new();
+        // Live register values along with the instruction where they are killed.
+        let mut live: Vec<(Value, Inst)> = Vec::new();
+
+        for ebb in layout.ebbs() {
+            divert.clear();
+            live.clear();
+
+            // Start with the live-in values and the EBB arguments.
+            for lr in self.liveness.iter() {
+                let value = lr.key();
+                let end = if lr.def() == ebb.into() {
+                    match lr.def_local_end().into() {
+                        ExpandedProgramPoint::Inst(end) => end,
+                        ExpandedProgramPoint::Ebb(_) => continue,
+                    }
+                } else {
+                    match lr.livein_local_end(ebb, layout) {
+                        Some(end) => end,
+                        None => continue,
+                    }
+                };
+                if self.units(value, &divert).is_some() {
+                    self.check_unit_free(value, &live, &divert)?;
+                    live.push((value, end));
+                }
+            }
+
+            for inst in layout.ebb_insts(ebb) {
+                if let InstructionData::RegMove { arg, src, dst, .. } = dfg[inst] {
+                    divert.regmove(arg, src, dst);
+                    self.check_unit_free(arg, &live, &divert)?;
+                }
+
+                // Values killed by `inst` make room for its results.
+                live.retain(|&(_, end)| end != inst);
+
+                for value in dfg.inst_results(inst) {
+                    if self.units(value, &divert).is_none() {
+                        continue;
+                    }
+                    self.check_unit_free(value, &live, &divert)?;
+                    let lr = match self.liveness.get(value) {
+                        Some(lr) => lr,
+                        None => return err!(inst, "{} has no live range", value),
+                    };
+                    if let ExpandedProgramPoint::Inst(end) = lr.def_local_end().into() {
+                        if end != inst {
+                            live.push((value, end));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the values in each virtual register don't interfere.
+    ///
+    /// Dead values don't interfere with anything.
+    fn check_virtregs(&self) -> Result<()> {
+        for class in self.virtregs.iter() {
+            for (i, &a) in class.iter().enumerate() {
+                let lra = match self.liveness.get(a) {
+                    Some(lr) if !lr.is_dead() => lr,
+                    _ => continue,
+                };
+                for &b in &class[i + 1..] {
+                    match self.liveness.get(b) {
+                        Some(lrb) if !lrb.is_dead() && lra.overlaps(lrb, &self.func.layout) => {
+                            return err!(b, "interferes with {} in the same virtual register", a);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the registers of `value` are not used by any of the other `live` values.
+    fn check_unit_free(&self,
+                       value: Value,
+                       live: &[(Value, Inst)],
+                       divert: &RegDiversions)
+                       -> Result<()> {
+        let (lo, hi) = match self.units(value, divert) {
+            Some(units) => units,
+            None => return Ok(()),
+        };
+        for &(other, _) in live {
+            if other == value {
+                continue;
+            }
+            if let Some((olo, ohi)) = self.units(other, divert) {
+                if lo < ohi && olo < hi {
+                    return err!(value,
+                                "shares {} with the interfering {}",
+                                self.reginfo.display_regunit(cmp::max(lo, olo)),
+                                other);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cancel::CancellationToken;
+    use cfg::ControlFlowGraph;
+    use dominator_tree::DominatorTree;
+    use entity_map::EntityRef;
+    use ir::{Function, Cursor, InstBuilder, ValueLoc, VariableArgs, ArgumentType,
+             ArgumentPurpose};
+    use ir::types;
+    use isa;
+    use regalloc;
+    use regalloc::virtregs::VirtRegs;
+    use settings;
+    use super::verify_locations;
+
+    #[test]
+    fn riscv() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v2 = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v2 = dfg.ins(pos).iadd(v0, v1);
+            dfg.ins(pos).return_reg(v2, VariableArgs::new());
+            v2
+        };
+        ::legalize_function(&mut func, &*isa).unwrap();
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+        let mut ctx = regalloc::Context::new();
+        ctx.run(&*isa, &mut func, &cfg, &domtree, &CancellationToken::new()).unwrap();
+        assert_eq!(verify_locations(&*isa, &func, ctx.liveness(), ctx.virtregs()), Ok(()));
+
+        // Put the two live arguments in the same register.
+        let v1_loc = func.locations[v1];
+        func.locations[v1] = func.locations[v0];
+        assert!(verify_locations(&*isa, &func, ctx.liveness(), ctx.virtregs())
+                    .unwrap_err()
+                    .message
+                    .contains("shares"));
+
+        // Forget the location of the result.
+        func.locations[v1] = v1_loc;
+        func.locations[v2] = ValueLoc::Unassigned;
+        assert!(verify_locations(&*isa, &func, ctx.liveness(), ctx.virtregs())
+                    .unwrap_err()
+                    .message
+                    .contains("no assigned location"));
+
+        // Merge the two live arguments into a virtual register.
+        func.locations[v2] = ValueLoc::Reg(1);
+        let mut virtregs = VirtRegs::new();
+        virtregs.union(v0, v1, |a, b| a.index().cmp(&b.index()));
+        assert_eq!(verify_locations(&*isa, &func, ctx.liveness(), &virtregs)
+                       .unwrap_err()
+                       .message,
+                   "interferes with vx0 in the same virtual register");
+    }
+
+    #[test]
+    fn abi() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut func = Function::new();
+        func.signature.argument_types.push(ArgumentType::new(types::I32));
+        func.signature
+            .argument_types
+            .push(ArgumentType::special(types::I32, ArgumentPurpose::Link));
+        func.signature.return_types.push(ArgumentType::new(types::I32));
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let link = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v1 = dfg.ins(pos).iadd_imm(v0, 1);
+            let mut rets = VariableArgs::new();
+            rets.push(v1);
+            dfg.ins(pos).return_reg(link, rets);
+            v1
+        };
+        ::legalize_function(&mut func, &*isa).unwrap();
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+        let mut ctx = regalloc::Context::new();
+        ctx.run(&*isa, &mut func, &cfg, &domtree, &CancellationToken::new()).unwrap();
+        assert_eq!(verify_locations(&*isa, &func, ctx.liveness(), ctx.virtregs()), Ok(()));
+
+        // The argument arrives in `a0`, and the result is computed in place.
+        assert_eq!(func.locations[v0], ValueLoc::Reg(10));
+        assert_eq!(func.locations[v1], ValueLoc::Reg(10));
+
+        // Return the result in the wrong register.
+        func.locations[v1] = ValueLoc::Reg(11);
+        assert_eq!(verify_locations(&*isa, &func, ctx.liveness(), ctx.virtregs())
+                       .unwrap_err()
+                       .message,
+                   "v0 is in %x11, but the ABI requires %x10");
+    }
+}
@@ -0,0 +1,1116 @@
+//! A verifier for ensuring that functions are well formed.
+//! It verifies:
+//!
+//!   EBB integrity
+//!
+//!    - All instructions reached from the `ebb_insts` iterator must belong to
+//!      the EBB as reported by `inst_ebb()`.
+//!    - Every EBB must end in a terminator instruction, and no other instruction
+//!      can be a terminator.
+//!    - A `fallthrough` instruction must branch to the next EBB in the layout.
+//!    - Every value in the `ebb_args` iterator belongs to the EBB as reported by `value_ebb`.
+//!
+//!    - Every EBB in the layout must contain at least one instruction.
+//!
+//!   Instruction integrity
+//!
+//!    - The instruction format must match the opcode.
+//!    - All result values must refer back to the instruction that defines them.
+//!    - All referenced entities must exist. (Values, EBBs, jump tables, function references,
+//!      signatures, heaps, global variables, and the stack limit)
+//! TODO:
+//!    - All result values must be created for multi-valued instructions.
+//!    - Instructions with no results must have a VOID `first_type()`.
+//!
+//!   SSA form
+//!
+//!    - Values must be defined by an instruction that exists and that is inserted in
+//!      an EBB, or be an argument of an existing EBB.
+//!    - Values used by an instruction must dominate the instruction.
+//!    - The entry block can't be a branch destination. Its arguments are provided by the
+//!      caller, all other EBB arguments are supplied by branches from its predecessors.
+//!
+//!   Control flow graph and dominator tree integrity:
+//!
+//!    - All predecessors in the CFG must be branches to the EBB.
+//!    - All branches to an EBB must be present in the CFG.
+//! TODO:
+//!    - A recomputed dominator tree is identical to the existing one.
+//!
+//!   Type checking
+//!
+//!    - Compare input and output values against the opcode's type constraints.
+//!      For polymorphic opcodes, determine the controlling type variable first.
+//!    - Branches and jumps must pass arguments to destination EBBs that match the
+//!      expected types exactly. The number of arguments must match.
+//!    - All EBBs in a jump_table must be inserted in the layout and take no arguments. A
+//!      `br_table` falls through when there is no entry for its index, so like all other
+//!      non-terminators, it can't be the last instruction in its EBB.
+//!    - Signatures can have at most one `sret` argument.
+//!    - Tail calls must call a function returning the same types as the current function.
+//!    - When an ISA is given, the `enable_float` and `enable_simd` settings restrict the types
+//!      used by the function signature, EBB arguments, and instruction results.
+//! TODO:
+//!    - Function calls are type checked against their signature.
+//!    - The entry block must take arguments that match the signature of the current
+//!      function.
+//!    - All return instructions must have return value operands matching the current
+//!      function signature.
+//!
+//!   Ad hoc checking
+//!
+//!    - Stack slot loads and stores must be in-bounds.
+//!    - Loads and stores with the `aligned` flag through a `stack_addr` address must be aligned
+//!      to the access size, and the stack slot alignment can't be smaller than the access size.
+//!    - Immediate constraints for certain opcodes, like `udiv_imm v3, 0`.
+//!    - Extend / truncate instructions have more type constraints: Source type can't be
+//!      larger / smaller than result type.
+//!    - `Insertlane` and `extractlane` instructions have immediate lane numbers that must be in
+//!      range for their polymorphic type.
+//!    - Swizzle and shuffle instructions take a variable number of lane arguments. The number
+//!      of arguments must match the destination type, and the lane indexes must be in range.
+//!
+//!   CPU flags
+//!
+//!    - At most one flags value can be live at a time.
+//!    - A flags value must still be valid where it is used: No instruction defining another flags
+//!      value can appear between the definition and the use of a flags value.
+//!    - No instruction whose encoding clobbers the flags (like most Intel arithmetic) can appear
+//!      between the definition and the use of a flags value. Encoding recipes are marked with
+//!      `RecipeConstraints::clobbers_flags`. This is only checked when an ISA is given.
+//!
+//! The liveness analysis used by the register allocator can be checked separately with
+//! `verify_liveness`, and after register allocation, the value locations can be checked with
+//! `verify_locations`.
+
+use cfg::ControlFlowGraph;
+use dominator_tree::DominatorTree;
+use entity_map::EntityRef;
+use ir::{types, Function, ValueDef, Ebb, Inst, Opcode, Value, Type, Signature,
+         ArgumentPurpose, InstructionData};
+use ir::instructions::{InstructionFormat, BranchInfo, CallInfo, ResolvedConstraint};
+use ir::entities::AnyEntity;
+use isa::TargetIsa;
+use settings;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::result;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// A verifier error.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Error {
+    /// The entity causing the verifier error.
+    pub location: AnyEntity,
+    /// Error message.
+    pub message: String,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Verifier result.
+pub type Result<T> = result::Result<T, Error>;
+
+// Create an `Err` variant of `Result<X>` from a location and `format!` arguments.
+macro_rules! err {
+    ( $loc:expr, $msg:expr ) => {
+        Err(Error {
+            location: $loc.into(),
+            message: String::from($msg),
+        })
+    };
+
+    ( $loc:expr, $fmt:expr, $( $arg:expr ),+ ) => {
+        Err(Error {
+            location: $loc.into(),
+            message: format!( $fmt, $( $arg ),+ ),
+        })
+    };
+}
+
+pub use self::liveness::verify_liveness;
+pub use self::locations::verify_locations;
+
+mod flags;
+mod liveness;
+mod locations;
+
+/// Verify `func`.
+///
+/// The SSA dominance checks need a control flow graph and a dominator tree which are computed
+/// from scratch. Use `verify_context` to verify existing ones instead.
+pub fn verify_function(func: &Function) -> Result<()> {
+    let verifier = Verifier::new(func);
+    verifier.run()?;
+    let cfg = ControlFlowGraph::with_function(func);
+    let domtree = DominatorTree::with_function(func, &cfg);
+    verifier.ssa_dominance(&domtree)?;
+    flags::verify_flags(func, &cfg, None)
+}
+
+/// Verify `func` along with the control flow graph `cfg` and dominator tree `domtree` which were
+/// computed for it.
+///
+/// This also catches a stale `cfg` which hasn't been updated after changes to the function.
+///
+/// When `isa` is given, the instruction encodings recorded in `func.encodings` are checked against
+/// the legal encodings for the ISA. This catches passes that modify instructions without updating
+/// their encodings. The types used by the function are also checked against the `enable_float`
+/// and `enable_simd` settings, and no encoding may clobber a live CPU flags value.
+pub fn verify_context(func: &Function,
+                      cfg: &ControlFlowGraph,
+                      domtree: &DominatorTree,
+                      isa: Option<&TargetIsa>)
+                      -> Result<()> {
+    let verifier = Verifier::new(func);
+    verifier.run()?;
+    verifier.cfg_integrity(cfg)?;
+    verifier.ssa_dominance(domtree)?;
+    flags::verify_flags(func, cfg, isa)?;
+    if let Some(isa) = isa {
+        verifier.allowed_types(isa.flags())?;
+        verifier.encodings(isa)?;
+    }
+    Ok(())
+}
+
+/// Verify that every instruction in `func` has a legal encoding.
+///
+/// The legalizer leaves the instructions that it can't transform into legal equivalents without an
+/// encoding, and the register allocator and the following passes can't handle those.
+pub fn verify_encoded(func: &Function) -> Result<()> {
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.encodings.get(inst) {
+                Some(enc) if enc.is_legal() => {}
+                _ => return err!(inst, "{} has no legal encoding", func.dfg[inst].opcode()),
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Verifier<'a> {
+    func: &'a Function,
+}
+
+impl<'a> Verifier<'a> {
+    pub fn new(func: &'a Function) -> Verifier {
+        Verifier { func: func }
+    }
+
+    /// Check that `cfg` matches a freshly computed control flow graph.
+    fn cfg_integrity(&self, cfg: &ControlFlowGraph) -> Result<()> {
+        let expected = ControlFlowGraph::with_function(self.func);
+        let num_ebbs = cfg.ebbs().count();
+
+        for ebb in self.func.layout.ebbs() {
+            if ebb.index() >= num_ebbs {
+                return err!(ebb, "missing from the cfg");
+            }
+
+            let got_succs: BTreeSet<Ebb> = cfg.get_successors(ebb).iter().cloned().collect();
+            let want_succs: BTreeSet<Ebb> =
+                expected.get_successors(ebb).iter().cloned().collect();
+            let missing: Vec<_> = want_succs.difference(&got_succs).collect();
+            if !missing.is_empty() {
+                return err!(ebb, "cfg lacked the successor(s) {:?}", missing);
+            }
+            let excess: Vec<_> = got_succs.difference(&want_succs).collect();
+            if !excess.is_empty() {
+                return err!(ebb, "cfg had unexpected successor(s) {:?}", excess);
+            }
+
+            let got_preds: HashSet<(Ebb, Inst)> =
+                cfg.get_predecessors(ebb).iter().cloned().collect();
+            let want_preds: HashSet<(Ebb, Inst)> =
+                expected.get_predecessors(ebb).iter().cloned().collect();
+            let missing: Vec<_> = want_preds.difference(&got_preds).collect();
+            if !missing.is_empty() {
+                return err!(ebb, "cfg lacked the predecessor(s) {:?}", missing);
+            }
+            let excess: Vec<_> = got_preds.difference(&want_preds).collect();
+            if !excess.is_empty() {
+                return err!(ebb, "cfg had unexpected predecessor(s) {:?}", excess);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that every value used by an instruction is defined in a position that dominates the
+    /// use.
+    ///
+    /// Uses in unreachable EBBs are not checked since dominance is ill defined there.
+    fn ssa_dominance(&self, domtree: &DominatorTree) -> Result<()> {
+        let dfg = &self.func.dfg;
+        let layout = &self.func.layout;
+
+        for ebb in layout.ebbs() {
+            if !domtree.is_reachable(ebb) {
+                continue;
+            }
+            for inst in layout.ebb_insts(ebb) {
+                for &arg in dfg.inst_args(inst).iter().flat_map(|part| part.iter()) {
+                    // The EBB and instruction that must dominate `inst`.
+                    let (def_ebb, def_inst) = match dfg.value_def(arg) {
+                        ValueDef::Res(def_inst, _) => {
+                            if def_inst == inst {
+                                return err!(inst, "uses its own result {}", arg);
+                            }
+                            match layout.inst_ebb(def_inst) {
+                                Some(def_ebb) => (def_ebb, def_inst),
+                                None => {
+                                    return err!(inst,
+                                                "uses value {} from non-inserted {}",
+                                                arg,
+                                                def_inst)
+                                }
+                            }
+                        }
+                        ValueDef::Arg(def_ebb, _) => {
+                            if !layout.is_ebb_inserted(def_ebb) {
+                                return err!(inst,
+                                            "uses value {} from non-inserted {}",
+                                            arg,
+                                            def_ebb);
+                            }
+                            let first = layout.ebb_insts(def_ebb)
+                                .next()
+                                .expect("EBBs were verified to be non-empty");
+                            (def_ebb, first)
+                        }
+                    };
+                    if !domtree.is_reachable(def_ebb) ||
+                       !domtree.dominates(def_inst, inst, layout) {
+                        return err!(inst, "uses value {} from non-dominating {}", arg, def_ebb);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the function only uses types allowed by the `enable_float` and `enable_simd`
+    /// settings in `flags`.
+    fn allowed_types(&self, flags: &settings::Flags) -> Result<()> {
+        let dfg = &self.func.dfg;
+        let sig = &self.func.signature;
+        for arg in sig.argument_types.iter().chain(&sig.return_types) {
+            if !flags.allows_type(arg.value_type) {
+                return err!(AnyEntity::Function,
+                            "signature uses disallowed type {}",
+                            arg.value_type);
+            }
+        }
+
+        for ebb in self.func.layout.ebbs() {
+            for arg in dfg.ebb_args(ebb) {
+                let ty = dfg.value_type(arg);
+                if !flags.allows_type(ty) {
+                    return err!(ebb, "argument {} has disallowed type {}", arg, ty);
+                }
+            }
+            for inst in self.func.layout.ebb_insts(ebb) {
+                for res in dfg.inst_results(inst) {
+                    let ty = dfg.value_type(res);
+                    if !flags.allows_type(ty) {
+                        return err!(inst, "result {} has disallowed type {}", res, ty);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the recorded instruction encodings are still legal for `isa`.
+    ///
+    /// Instructions without an encoding are not checked. They have not been legalized yet, or
+    /// they don't need an encoding.
+    fn encodings(&self, isa: &TargetIsa) -> Result<()> {
+        let dfg = &self.func.dfg;
+
+        for ebb in self.func.layout.ebbs() {
+            for inst in self.func.layout.ebb_insts(ebb) {
+                let encoding = match self.func.encodings.get(inst) {
+                    Some(&enc) if enc.is_legal() => enc,
+                    _ => continue,
+                };
+                match isa.legal_encodings(dfg, &dfg[inst]) {
+                    Ok(legal) => {
+                        if !legal.contains(&encoding) {
+                            let names: Vec<String> = legal.iter()
+                                .map(|&enc| isa.display_enc(enc).to_string())
+                                .collect();
+                            return err!(inst,
+                                        "encoding {} is not one of the legal encodings [{}]",
+                                        isa.display_enc(encoding),
+                                        names.join(", "));
+                        }
+                    }
+                    Err(action) => {
+                        return err!(inst,
+                                    "has encoding {}, but it needs to {}",
+                                    isa.display_enc(encoding),
+                                    action);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ebb_integrity(&self, ebb: Ebb, inst: Inst) -> Result<()> {
+
+        let is_terminator = self.func.dfg[inst].opcode().is_terminator();
+        let is_last_inst = self.func.layout.last_inst(ebb) == Some(inst);
+
+        if is_terminator && !is_last_inst {
+            // Terminating instructions only occur at the end of blocks.
+            return err!(inst,
+                        "a terminator instruction was encountered before the end of {}",
+                        ebb);
+        }
+        if is_last_inst && !is_terminator {
+            return err!(ebb, "block does not end in a terminator instruction!");
+        }
+        if self.func.dfg[inst].opcode() == Opcode::Fallthrough {
+            if let BranchInfo::SingleDest(dest, _) = self.func.dfg.analyze_branch(inst) {
+                if self.func.layout.next_ebb(ebb) != Some(dest) {
+                    return err!(inst, "fallthrough to {} which is not the next EBB", dest);
+                }
+            }
+        }
+
+        // Instructions belong to the correct ebb.
+        let inst_ebb = self.func.layout.inst_ebb(inst);
+        if inst_ebb != Some(ebb) {
+            return err!(inst, "should belong to {} not {:?}", ebb, inst_ebb);
+        }
+
+        // Arguments belong to the correct ebb.
+        for arg in self.func.dfg.ebb_args(ebb) {
+            match self.func.dfg.value_def(arg) {
+                ValueDef::Arg(arg_ebb, _) => {
+                    if ebb != arg_ebb {
+                        return err!(arg, "does not belong to {}", ebb);
+                    }
+                }
+                _ => {
+                    return err!(arg, "expected an argument, found a result");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn instruction_integrity(&self, inst: Inst) -> Result<()> {
+        let inst_data = &self.func.dfg[inst];
+
+        // The instruction format matches the opcode
+        if inst_data.opcode().format() != InstructionFormat::from(inst_data) {
+            return err!(inst, "instruction opcode doesn't match instruction format");
+        }
+
+        // All results point back to this instruction.
+        for (num, res) in self.func.dfg.inst_results(inst).enumerate() {
+            if !self.func.dfg.value_is_valid(res) {
+                return err!(inst, "invalid result value {}", res);
+            }
+            let def = self.func.dfg.value_def(res);
+            if def != ValueDef::Res(inst, num) {
+                return err!(res, "should be result #{} of {}, found {:?}", num, inst, def);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn verify_entity_references(&self, inst: Inst) -> Result<()> {
+        let dfg = &self.func.dfg;
+        let inst_data = &dfg[inst];
+
+        for part in &inst_data.arguments(&dfg.value_lists) {
+            self.verify_values(inst, part)?;
+        }
+
+        match inst_data.analyze_branch(&dfg.value_lists) {
+            BranchInfo::NotABranch => {}
+            BranchInfo::SingleDest(ebb, _) => self.verify_ebb(inst, ebb)?,
+            BranchInfo::Table(jt) => {
+                if !self.func.jump_tables.is_valid(jt) {
+                    return err!(inst, "invalid jump table reference {}", jt);
+                }
+                for (_, ebb) in self.func.jump_tables[jt].entries() {
+                    self.verify_ebb(inst, ebb)?;
+                }
+            }
+        }
+
+        match inst_data.analyze_call(&dfg.value_lists) {
+            CallInfo::NotACall => {}
+            CallInfo::Direct(func_ref, _) => {
+                if !dfg.ext_funcs.is_valid(func_ref) {
+                    return err!(inst, "invalid function reference {}", func_ref);
+                }
+                let sig_ref = dfg.ext_funcs[func_ref].signature;
+                if !dfg.signatures.is_valid(sig_ref) {
+                    return err!(func_ref, "invalid signature reference {}", sig_ref);
+                }
+            }
+            CallInfo::Indirect(sig_ref, _) => {
+                if !dfg.signatures.is_valid(sig_ref) {
+                    return err!(inst, "invalid signature reference {}", sig_ref);
+                }
+            }
+        }
+
+        match *inst_data {
+            InstructionData::HeapAddr { heap, .. } if !self.func.heaps.is_valid(heap) => {
+                return err!(inst, "invalid heap reference {}", heap);
+            }
+            InstructionData::FuncAddr { func_ref, .. } if !dfg.ext_funcs.is_valid(func_ref) => {
+                return err!(inst, "invalid function reference {}", func_ref);
+            }
+            InstructionData::UnaryGlobalVar { global_var, .. } if
+                !dfg.global_vars.is_valid(global_var) => {
+                return err!(inst, "invalid global variable reference {}", global_var);
+            }
+            InstructionData::BranchTableEntry { table, .. } |
+            InstructionData::BranchTableBase { table, .. } if
+                !self.func.jump_tables.is_valid(table) => {
+                return err!(inst, "invalid jump table reference {}", table);
+            }
+            _ => {}
+        }
+
+        match *inst_data {
+            InstructionData::StackLoad { stack_slot, .. } |
+            InstructionData::StackStore { stack_slot, .. } if
+                !self.func.stack_slots.is_valid(stack_slot) => {
+                return err!(inst, "invalid stack slot reference {}", stack_slot);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Check the types of the fixed arguments and results of `inst` against the opcode's type
+    /// constraints.
+    fn typecheck(&self, inst: Inst) -> Result<()> {
+        let ctrl_type = self.ctrl_type(inst)?;
+        let dfg = &self.func.dfg;
+        let constraints = dfg[inst].opcode().constraints();
+
+        for (i, res) in dfg.inst_results(inst).take(constraints.fixed_results()).enumerate() {
+            let expected = constraints.result_type(i, ctrl_type);
+            let actual = dfg.value_type(res);
+            if actual != expected {
+                return err!(inst,
+                            "result {} ({}) has type {}, expected {}",
+                            i,
+                            res,
+                            actual,
+                            expected);
+            }
+        }
+
+        for (i, &arg) in dfg.inst_args(inst)[0].iter().enumerate() {
+            let actual = dfg.value_type(arg);
+            match constraints.value_argument_constraint(i, ctrl_type) {
+                ResolvedConstraint::Bound(expected) => {
+                    if actual != expected {
+                        return err!(inst,
+                                    "arg {} ({}) has type {}, expected {}",
+                                    i,
+                                    arg,
+                                    actual,
+                                    expected);
+                    }
+                }
+                ResolvedConstraint::Free(type_set) => {
+                    if !type_set.contains(actual) {
+                        return err!(inst,
+                                    "arg {} ({}) with type {} failed to satisfy type set",
+                                    i,
+                                    arg,
+                                    actual);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Determine the controlling type variable of `inst`, and check that it is in the allowed
+    /// type set. Returns `VOID` for non-polymorphic instructions.
+    fn ctrl_type(&self, inst: Inst) -> Result<Type> {
+        let dfg = &self.func.dfg;
+        let inst_data = &dfg[inst];
+        let constraints = inst_data.opcode().constraints();
+
+        if let Some(type_set) = constraints.ctrl_typeset() {
+            let ctrl_type = if constraints.use_typevar_operand() {
+                dfg.value_type(inst_data.typevar_operand(&dfg.value_lists)
+                                   .expect("Polymorphic opcode must have a typevar operand"))
+            } else {
+                dfg.value_type(dfg.first_result(inst))
+            };
+            if !type_set.contains(ctrl_type) {
+                return err!(inst, "has an invalid controlling type {}", ctrl_type);
+            }
+            Ok(ctrl_type)
+        } else {
+            Ok(types::VOID)
+        }
+    }
+
+    /// Check that the arguments passed by a branch instruction match the destination EBB.
+    fn branch_arguments(&self, inst: Inst) -> Result<()> {
+        let dfg = &self.func.dfg;
+        if let BranchInfo::SingleDest(ebb, args) = dfg.analyze_branch(inst) {
+            let expected = dfg.num_ebb_args(ebb);
+            if args.len() != expected {
+                return err!(inst,
+                            "passes {} arguments to {}, which expects {}",
+                            args.len(),
+                            ebb,
+                            expected);
+            }
+            for (i, (&arg, ebb_arg)) in args.iter().zip(dfg.ebb_args(ebb)).enumerate() {
+                let actual = dfg.value_type(arg);
+                let expected = dfg.value_type(ebb_arg);
+                if actual != expected {
+                    return err!(inst,
+                                "arg {} ({}) has type {}, but {} expects {}",
+                                i,
+                                arg,
+                                actual,
+                                ebb,
+                                expected);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a tail call calls a function returning the same types as the current function.
+    fn tail_call_returns(&self, inst: Inst) -> Result<()> {
+        let dfg = &self.func.dfg;
+        if !dfg[inst].opcode().is_return() {
+            return Ok(());
+        }
+        if let Some(sig) = dfg.call_signature(inst) {
+            let expected = &self.func.signature.return_types;
+            let actual = &dfg.signatures[sig].return_types;
+            if actual.len() != expected.len() ||
+               actual.iter().zip(expected).any(|(a, e)| a.value_type != e.value_type) {
+                return err!(inst,
+                            "tail call with {} doesn't return the same types as the function",
+                            sig);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that all jump table entries are EBBs in the layout that take no arguments.
+    fn jump_tables(&self) -> Result<()> {
+        for jt in self.func.jump_tables.keys() {
+            for (idx, ebb) in self.func.jump_tables[jt].entries() {
+                if ebb.index() >= self.func.dfg.num_ebbs() {
+                    return err!(jt, "entry {} is an invalid EBB reference {}", idx, ebb);
+                }
+                if !self.func.layout.is_ebb_inserted(ebb) {
+                    return err!(jt, "entry {} points to {} which is not in the layout", idx, ebb);
+                }
+                if self.func.dfg.num_ebb_args(ebb) != 0 {
+                    return err!(jt, "entry {} points to {} which takes arguments", idx, ebb);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the function signature and all imported signatures are well formed.
+    fn signatures(&self) -> Result<()> {
+        check_signature(&self.func.signature, AnyEntity::Function)?;
+        for sig in self.func.dfg.signatures.keys() {
+            check_signature(&self.func.dfg.signatures[sig], sig.into())?;
+        }
+        Ok(())
+    }
+
+    /// Check that an `aligned` load or store through a `stack_addr` address is consistent with
+    /// the alignment of the stack slot.
+    fn aligned_access(&self, inst: Inst) -> Result<()> {
+        let dfg = &self.func.dfg;
+        let (flags, addr, offset, value) = match dfg[inst] {
+            InstructionData::Load { flags, arg, offset, .. } => {
+                (flags, arg, offset, dfg.first_result(inst))
+            }
+            InstructionData::Store { flags, args, offset, .. } => {
+                (flags, args[1], offset, args[0])
+            }
+            _ => return Ok(()),
+        };
+        if !flags.aligned() {
+            return Ok(());
+        }
+        let size = (dfg.value_type(value).bits() as i64 + 7) / 8;
+
+        // Follow constant offsets back to the `stack_addr` instruction computing the address.
+        let mut offset = offset as i64;
+        let mut addr = dfg.resolve_aliases(addr);
+        loop {
+            let def = match dfg.value_def(addr) {
+                ValueDef::Res(def, 0) => def,
+                _ => return Ok(()),
+            };
+            match dfg[def] {
+                InstructionData::BinaryImm { opcode: Opcode::IaddImm, arg, imm, .. } => {
+                    let imm: i64 = imm.into();
+                    offset = offset.wrapping_add(imm);
+                    addr = dfg.resolve_aliases(arg);
+                }
+                InstructionData::StackLoad {
+                    opcode: Opcode::StackAddr,
+                    stack_slot,
+                    offset: base,
+                    ..
+                } => {
+                    let slot = &self.func.stack_slots[stack_slot];
+                    let align = slot.align.unwrap_or_else(|| slot.size.next_power_of_two());
+                    if (align as i64) < size {
+                        return err!(inst,
+                                    "aligned {}-byte access to {} with {}-byte alignment",
+                                    size,
+                                    stack_slot,
+                                    align);
+                    }
+                    let offset = offset.wrapping_add(base as i64);
+                    if offset % size != 0 {
+                        return err!(inst,
+                                    "aligned {}-byte access at misaligned offset {} in {}",
+                                    size,
+                                    offset,
+                                    stack_slot);
+                    }
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn verify_ebb(&self, inst: Inst, ebb: Ebb) -> Result<()> {
+        if ebb.index() >= self.func.dfg.num_ebbs() {
+            return err!(inst, "invalid EBB reference {}", ebb);
+        }
+        if !self.func.layout.is_ebb_inserted(ebb) {
+            return err!(inst, "{} is not inserted in the layout", ebb);
+        }
+        if self.func.layout.entry_block() == Some(ebb) {
+            return err!(inst, "{} is the entry block and can't be a branch destination", ebb);
+        }
+        Ok(())
+    }
+
+    fn verify_values(&self, inst: Inst, values: &[Value]) -> Result<()> {
+        for &v in values {
+            if !self.func.dfg.value_is_valid(v) {
+                return err!(inst, "invalid value reference {}", v);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the stack limit refers to an existing global variable.
+    fn stack_limit(&self) -> Result<()> {
+        match self.func.stack_limit {
+            Some(gv) if !self.func.dfg.global_vars.is_valid(gv) => {
+                err!(AnyEntity::Function, "stack limit is an invalid global variable {}", gv)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn run(&self) -> Result<()> {
+        self.signatures()?;
+        self.jump_tables()?;
+        self.stack_limit()?;
+        for ebb in self.func.layout.ebbs() {
+            if self.func.layout.last_inst(ebb).is_none() {
+                return err!(ebb, "block does not end in a terminator instruction!");
+            }
+            for inst in self.func.layout.ebb_insts(ebb) {
+                self.ebb_integrity(ebb, inst)?;
+                self.instruction_integrity(inst)?;
+                self.verify_entity_references(inst)?;
+                self.typecheck(inst)?;
+                self.branch_arguments(inst)?;
+                self.tail_call_returns(inst)?;
+                self.aligned_access(inst)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that `sig` has at most one struct return pointer.
+fn check_signature(sig: &Signature, loc: AnyEntity) -> Result<()> {
+    let srets = sig.argument_types
+        .iter()
+        .filter(|arg| arg.purpose == ArgumentPurpose::StructReturn)
+        .count();
+    if srets > 1 {
+        return err!(loc, "signature has {} sret arguments", srets);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Verifier, Error, verify_function, verify_context};
+    use cfg::ControlFlowGraph;
+    use dominator_tree::DominatorTree;
+    use ir::{Function, Cursor, InstBuilder, JumpTableData, Value, ValueDef, VariableArgs};
+    use ir::{ArgumentType, ArgumentPurpose};
+    use ir::instructions::{InstructionData, Opcode};
+    use ir::condcodes::IntCC;
+    use ir::types;
+    use isa::{self, Legalize};
+    use legalize_function;
+    use settings::{self, Configurable};
+
+    macro_rules! assert_err_with_msg {
+        ($e:expr, $msg:expr) => (
+            match $e {
+                Ok(_) => { panic!("Expected an error!") },
+                Err(Error { message, .. } ) => {
+                    if !message.contains($msg) {
+                       panic!(format!("'{}' did not contain the substring '{}'", message, $msg));
+                    }
+                }
+            }
+        )
+    }
+
+    #[test]
+    fn empty() {
+        let func = Function::new();
+        let verifier = Verifier::new(&func);
+        assert_eq!(verifier.run(), Ok(()));
+    }
+
+    #[test]
+    fn bad_instruction_format() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        let nullary_with_bad_opcode = func.dfg.make_inst(InstructionData::Nullary {
+            opcode: Opcode::Jump,
+            ty: types::VOID,
+        });
+        func.layout.append_inst(nullary_with_bad_opcode, ebb0);
+        let verifier = Verifier::new(&func);
+        assert_err_with_msg!(verifier.run(), "instruction format");
+    }
+
+    #[test]
+    fn empty_ebb() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        let verifier = Verifier::new(&func);
+        assert_err_with_msg!(verifier.run(), "terminator");
+    }
+
+    #[test]
+    fn bad_references() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).brz(v0, ebb1, VariableArgs::new());
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+        // `ebb1` is not inserted in the layout.
+        assert_err_with_msg!(Verifier::new(&func).run(), "not inserted");
+
+        func.layout.append_ebb(ebb1);
+        let ret = {
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.goto_bottom(ebb1);
+            func.dfg.ins(pos).return_(VariableArgs::new())
+        };
+        assert_eq!(Verifier::new(&func).run(), Ok(()));
+
+        // Return a value that doesn't exist.
+        func.dfg.set_inst_variable_args(ret, &[Value::table_with_number(100).unwrap()]);
+        assert_err_with_msg!(Verifier::new(&func).run(), "invalid value reference vx100");
+    }
+
+    #[test]
+    fn duplicate_sret() {
+        let mut func = Function::new();
+        let sret = ArgumentType::special(types::I64, ArgumentPurpose::StructReturn);
+        func.signature.argument_types.push(sret);
+        assert_eq!(Verifier::new(&func).run(), Ok(()));
+
+        func.signature.argument_types.push(sret);
+        assert_err_with_msg!(Verifier::new(&func).run(), "2 sret arguments");
+    }
+
+    #[test]
+    fn stale_cfg() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).jump(ebb1, VariableArgs::new());
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+        assert_eq!(verify_context(&func, &cfg, &domtree, None), Ok(()));
+
+        // Add a branch without updating the CFG.
+        let jump = func.layout.last_inst(ebb0).unwrap();
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.goto_inst(jump);
+            dfg.ins(pos).brz(v0, ebb1, VariableArgs::new());
+        }
+        assert_err_with_msg!(verify_context(&func, &cfg, &domtree, None),
+                             "cfg lacked the predecessor(s)");
+
+        // Add an EBB that the CFG doesn't know about.
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+        assert_eq!(verify_context(&func, &cfg, &domtree, None), Ok(()));
+        let ebb2 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb2);
+        {
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.goto_bottom(ebb2);
+            func.dfg.ins(pos).return_(VariableArgs::new());
+        }
+        assert_err_with_msg!(verify_context(&func, &cfg, &domtree, None), "missing from the cfg");
+    }
+
+    #[test]
+    fn dominance() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb1, types::I32);
+        let (sum, brz) = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let mut args = VariableArgs::new();
+            args.push(v0);
+            let brz = dfg.ins(pos).brz(v0, ebb1, args);
+            let sum = dfg.ins(pos).iadd(v0, v0);
+            dfg.ins(pos).jump(ebb2, VariableArgs::new());
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).return_(VariableArgs::new());
+            pos.insert_ebb(ebb2);
+            dfg.ins(pos).return_(VariableArgs::new());
+            (sum, brz)
+        };
+        assert_eq!(verify_function(&func), Ok(()));
+
+        // `ebb1` doesn't dominate `ebb2`.
+        let ret2 = func.layout.last_inst(ebb2).unwrap();
+        func.dfg.set_inst_variable_args(ret2, &[v1]);
+        assert_err_with_msg!(verify_function(&func), "uses value vx1 from non-dominating ebb1");
+
+        // The branch uses a value defined after it in the same EBB.
+        func.dfg.set_inst_variable_args(ret2, &[]);
+        func.dfg.inst_args_mut(brz)[0][0] = sum;
+        assert_err_with_msg!(verify_function(&func), "from non-dominating ebb0");
+    }
+
+    #[test]
+    fn branch_to_entry() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).jump(ebb0, VariableArgs::new());
+        }
+        assert_err_with_msg!(verify_function(&func), "can't be a branch destination");
+    }
+
+    #[test]
+    fn branch_arguments() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        func.dfg.append_ebb_arg(ebb1, types::I64);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).jump(ebb1, VariableArgs::new());
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+        assert_err_with_msg!(verify_function(&func), "passes 0 arguments to ebb1, which expects 1");
+
+        let jump = func.layout.ebb_insts(ebb0).next().unwrap();
+        func.dfg.append_inst_arg(jump, v0);
+        assert_err_with_msg!(verify_function(&func), "has type i32, but ebb1 expects i64");
+    }
+
+    #[test]
+    fn jump_table_arguments() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        func.dfg.append_ebb_arg(ebb1, types::I32);
+        let mut jt_data = JumpTableData::new();
+        jt_data.set_entry(1, ebb1);
+        let jt = func.jump_tables.push(jt_data);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).br_table(v0, jt);
+            let mut args = VariableArgs::new();
+            args.push(v0);
+            dfg.ins(pos).jump(ebb1, args);
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+        assert_err_with_msg!(verify_function(&func),
+                             "entry 1 points to ebb1 which takes arguments");
+    }
+
+    #[test]
+    fn stale_encoding() {
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&settings::builder()));
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let inst = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v1 = dfg.ins(pos).iadd(v0, v0);
+            dfg.ins(pos).return_reg(v1, VariableArgs::new());
+            match dfg.value_def(v1) {
+                ValueDef::Res(inst, _) => inst,
+                ValueDef::Arg(..) => panic!("{} should be an instruction result", v1),
+            }
+        };
+        legalize_function(&mut func, &*isa).unwrap();
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+        assert_eq!(verify_context(&func, &cfg, &domtree, Some(&*isa)), Ok(()));
+
+        // Change the opcode without updating the encoding.
+        func.dfg[inst] = InstructionData::Binary {
+            opcode: Opcode::Bxor,
+            ty: types::I32,
+            args: [v0, v0],
+        };
+        assert_eq!(verify_context(&func, &cfg, &domtree, None), Ok(()));
+        assert_err_with_msg!(verify_context(&func, &cfg, &domtree, Some(&*isa)),
+                             "is not one of the legal encodings");
+    }
+
+    #[test]
+    fn disallowed_types() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let inst = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v1 = dfg.ins(pos).bitcast(types::F32, v0);
+            dfg.ins(pos).return_reg(v0, VariableArgs::new());
+            match dfg.value_def(v1) {
+                ValueDef::Res(inst, _) => inst,
+                ValueDef::Arg(..) => panic!("{} should be an instruction result", v1),
+            }
+        };
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+
+        let mut b = settings::builder();
+        b.enable("is_64bit").unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&b));
+        assert!(isa.encode(&func.dfg, &func.dfg[inst]).is_ok());
+        assert_eq!(verify_context(&func, &cfg, &domtree, Some(&*isa)), Ok(()));
+
+        b.set("enable_float", "false").unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&b));
+        assert_eq!(isa.encode(&func.dfg, &func.dfg[inst]), Err(Legalize::Expand));
+        assert_err_with_msg!(verify_context(&func, &cfg, &domtree, Some(&*isa)),
+                             "has disallowed type f32");
+    }
+
+    #[test]
+    fn clobbered_flags() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let flags = dfg.ins(pos).ifcmp(v0, v1);
+            dfg.ins(pos).iadd(v0, v1);
+            dfg.ins(pos).trueif(IntCC::Equal, flags);
+            dfg.ins(pos).return_(VariableArgs::new());
+        }
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+
+        // Without encodings, the `iadd` doesn't touch the flags.
+        let isa = isa::lookup("i686").unwrap().finish(settings::Flags::new(&settings::builder()));
+        assert_eq!(verify_context(&func, &cfg, &domtree, Some(&*isa)), Ok(()));
+
+        // The Intel `add` instruction overwrites the flags compared by `ifcmp`.
+        let insts: Vec<_> = func.layout.ebb_insts(ebb0).collect();
+        for inst in insts {
+            if let Ok(enc) = isa.encode(&func.dfg, &func.dfg[inst]) {
+                *func.encodings.ensure(inst) = enc;
+            }
+        }
+        assert_eq!(verify_function(&func), Ok(()));
+        assert_err_with_msg!(verify_context(&func, &cfg, &domtree, Some(&*isa)),
+                             "encoding clobbers live flags value");
+    }
+}
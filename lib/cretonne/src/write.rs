@@ -3,15 +3,54 @@
 //! The `write` module provides the `write_function` function which converts an IL `Function` to an
 //! equivalent textual representation. This textual representation can be read back by the
 //! `cretonne-reader` crate.
-
-use ir::{Function, Ebb, Inst, Value, Type};
+//!
+//! The `write_annotated_function` variant can also add the results of analyses like the control
+//! flow graph, the dominator tree, and liveness as comments on the EBB header lines. The comments
+//! are ignored by the reader.
+
+use cfg::ControlFlowGraph;
+use dominator_tree::DominatorTree;
+use entity_map::EntityRef;
+use ir::{types, Function, Ebb, Inst, Value, Type, MemFlags};
 use isa::{TargetIsa, RegInfo};
-use std::fmt::{Result, Error, Write};
+use regalloc::liveness::Liveness;
+use sparse_map::SparseMapValue;
+use std::fmt::{self, Result, Error, Write};
 use std::result;
+use std::string::String;
+use std::vec::Vec;
+
+/// Analysis results that can be written as comments along with a function.
+///
+/// All the analyses must be up to date for the function being written.
+#[derive(Clone, Copy, Default)]
+pub struct Annotations<'a> {
+    /// Write the CFG predecessors of each EBB.
+    pub cfg: Option<&'a ControlFlowGraph>,
+
+    /// Write the immediate dominator of each EBB.
+    pub domtree: Option<&'a DominatorTree>,
+
+    /// Write the values that are live-in to each EBB.
+    pub liveness: Option<&'a Liveness>,
+
+    /// Write the code offset of each EBB from `func.offsets`.
+    pub offsets: bool,
+}
 
 /// Write `func` to `w` as equivalent text.
 /// Use `isa` to emit ISA-dependent annotations.
 pub fn write_function(w: &mut Write, func: &Function, isa: Option<&TargetIsa>) -> Result {
+    write_annotated_function(w, func, isa, &Annotations::default())
+}
+
+/// Write `func` to `w` as equivalent text, adding the analysis results in `annotations` as
+/// comments on the EBB headers.
+pub fn write_annotated_function(w: &mut Write,
+                                func: &Function,
+                                isa: Option<&TargetIsa>,
+                                annotations: &Annotations)
+                                -> Result {
     let regs = isa.map(TargetIsa::register_info);
     let regs = regs.as_ref();
 
@@ -22,7 +61,10 @@ pub fn write_function(w: &mut Write, func: &Function, isa: Option<&TargetIsa>) -
         if any {
             writeln!(w, "")?;
         }
-        write_ebb(w, func, isa, ebb)?;
+        write_annotated_ebb_header(w, func, ebb, annotations)?;
+        for inst in func.layout.ebb_insts(ebb) {
+            write_instruction(w, func, isa, inst)?;
+        }
         any = true;
     }
     writeln!(w, "}}")
@@ -49,6 +91,21 @@ fn write_preamble(w: &mut Write,
         writeln!(w, "    {} = {}", ss, func.stack_slots[ss])?;
     }
 
+    for heap in func.heaps.keys() {
+        any = true;
+        writeln!(w, "    {} = {}", heap, func.heaps[heap])?;
+    }
+
+    for gv in func.dfg.global_vars.keys() {
+        any = true;
+        writeln!(w, "    {} = {}", gv, func.dfg.global_vars[gv])?;
+    }
+
+    if let Some(gv) = func.stack_limit {
+        any = true;
+        writeln!(w, "    stack_limit = {}", gv)?;
+    }
+
     // Write out all signatures before functions since function declarations can refer to
     // signatures.
     for sig in func.dfg.signatures.keys() {
@@ -82,7 +139,11 @@ pub fn write_arg(w: &mut Write, func: &Function, arg: Value) -> Result {
     write!(w, "{}: {}", arg, func.dfg.value_type(arg))
 }
 
-pub fn write_ebb_header(w: &mut Write, func: &Function, ebb: Ebb) -> Result {
+fn write_annotated_ebb_header(w: &mut Write,
+                              func: &Function,
+                              ebb: Ebb,
+                              annotations: &Annotations)
+                              -> Result {
     // Write out the basic block header, outdented:
     //
     //    ebb1:
@@ -97,25 +158,79 @@ pub fn write_ebb_header(w: &mut Write, func: &Function, ebb: Ebb) -> Result {
 
     let mut args = func.dfg.ebb_args(ebb);
     match args.next() {
-        None => return writeln!(w, "{}:", ebb),
+        None => write!(w, "{}:", ebb)?,
         Some(arg) => {
             write!(w, "{}(", ebb)?;
             write_arg(w, func, arg)?;
+            // Remaining arguments.
+            for arg in args {
+                write!(w, ", ")?;
+                write_arg(w, func, arg)?;
+            }
+            write!(w, "):")?;
         }
     }
-    // Remaining arguments.
-    for arg in args {
-        write!(w, ", ")?;
-        write_arg(w, func, arg)?;
-    }
-    writeln!(w, "):")
+    write_ebb_annotations(w, func, ebb, annotations)?;
+    writeln!(w, "")
 }
 
-pub fn write_ebb(w: &mut Write, func: &Function, isa: Option<&TargetIsa>, ebb: Ebb) -> Result {
-    write_ebb_header(w, func, ebb)?;
-    for inst in func.layout.ebb_insts(ebb) {
-        write_instruction(w, func, isa, inst)?;
+// Write the analysis results for `ebb` as a comment at the end of the EBB header line:
+//
+//    ebb3(vx1: i32): ; preds=[ebb1:inst4, ebb2:inst9] idom=ebb1:inst4 livein=[v2, v7] offset=24
+//
+fn write_ebb_annotations(w: &mut Write,
+                         func: &Function,
+                         ebb: Ebb,
+                         annotations: &Annotations)
+                         -> Result {
+    if annotations.cfg.is_none() && annotations.domtree.is_none() &&
+       annotations.liveness.is_none() && !annotations.offsets {
+        return Ok(());
+    }
+    write!(w, " ;")?;
+
+    if let Some(cfg) = annotations.cfg {
+        write!(w, " preds=[")?;
+        for (i, &(pred, branch)) in cfg.get_predecessors(ebb).iter().enumerate() {
+            if i > 0 {
+                write!(w, ", ")?;
+            }
+            write!(w, "{}:{}", pred, branch)?;
+        }
+        write!(w, "]")?;
     }
+
+    if let Some(domtree) = annotations.domtree {
+        if !domtree.is_reachable(ebb) {
+            write!(w, " unreachable")?;
+        } else if let Some(idom) = domtree.idom(ebb) {
+            match func.layout.inst_ebb(idom) {
+                Some(idom_ebb) => write!(w, " idom={}:{}", idom_ebb, idom)?,
+                None => write!(w, " idom={}", idom)?,
+            }
+        }
+    }
+
+    if let Some(liveness) = annotations.liveness {
+        let mut livein = liveness.iter()
+            .filter(|lr| lr.livein_local_end(ebb, &func.layout).is_some())
+            .map(|lr| lr.key())
+            .collect::<Vec<Value>>();
+        livein.sort_by_key(|v| v.index());
+        write!(w, " livein=[")?;
+        for (i, v) in livein.into_iter().enumerate() {
+            if i > 0 {
+                write!(w, ", ")?;
+            }
+            write!(w, "{}", v)?;
+        }
+        write!(w, "]")?;
+    }
+
+    if annotations.offsets {
+        write!(w, " offset={}", func.offsets.get(ebb).cloned().unwrap_or_default())?;
+    }
+
     Ok(())
 }
 
@@ -157,7 +272,7 @@ fn type_suffix(func: &Function, inst: Inst) -> Option<Type> {
 
 // Write out any value aliases appearing in `inst`.
 fn write_value_aliases(w: &mut Write, func: &Function, inst: Inst, indent: usize) -> Result {
-    for &arg in func.dfg[inst].arguments().iter().flat_map(|x| x.iter()) {
+    for &arg in func.dfg.inst_args(inst).iter().flat_map(|x| x.iter()) {
         let resolved = func.dfg.resolve_aliases(arg);
         if resolved != arg {
             writeln!(w, "{1:0$}{2} -> {3}", indent, "", arg, resolved)?;
@@ -166,20 +281,32 @@ fn write_value_aliases(w: &mut Write, func: &Function, inst: Inst, indent: usize
     Ok(())
 }
 
-fn write_instruction(w: &mut Write,
-                     func: &Function,
-                     isa: Option<&TargetIsa>,
-                     inst: Inst)
-                     -> Result {
+/// Write the instruction `inst` in `func` to `w` as a line of text, preceded by any value aliases
+/// it uses. Use `isa` to write the encoding and the value locations.
+pub fn write_instruction(w: &mut Write,
+                         func: &Function,
+                         isa: Option<&TargetIsa>,
+                         inst: Inst)
+                         -> Result {
     // Indent all instructions to col 24 if any encodings are present.
     let indent = if func.encodings.is_empty() { 4 } else { 24 };
 
     // Value aliases come out on lines before the instruction using them.
     write_value_aliases(w, func, inst, indent)?;
 
+    let mut s = String::with_capacity(16);
+
+    // Source location goes first.
+    let srcloc = func.srcloc(inst);
+    if !srcloc.is_default() {
+        write!(s, "{}", srcloc)?;
+    }
+
     // Write out encoding info.
     if let Some(enc) = func.encodings.get(inst).cloned() {
-        let mut s = String::with_capacity(16);
+        if !s.is_empty() {
+            s.push(' ');
+        }
         if let Some(isa) = isa {
             write!(s, "[{}", isa.display_enc(enc))?;
             // Write value locations, if we have them.
@@ -193,11 +320,14 @@ fn write_instruction(w: &mut Write,
         } else {
             write!(s, "[{}]", enc)?;
         }
-        // Align instruction following ISA annotation to col 24.
-        write!(w, "{:23} ", s)?;
-    } else {
+    }
+
+    if s.is_empty() {
         // No annotations, simply indent.
         write!(w, "{1:0$}", indent, "")?;
+    } else {
+        // Align instruction following the annotations to col 24, or col 4 without encodings.
+        write!(w, "{1:0$} ", indent - 1, s)?;
     }
 
     // Write out the result values, if any.
@@ -223,77 +353,322 @@ fn write_instruction(w: &mut Write,
     }
 
     // Then the operands, depending on format.
+    let regs = isa.map(TargetIsa::register_info);
+    write_operands(w, func, regs.as_ref(), inst)?;
+
+    // Explain why an instruction without an encoding isn't encoded.
+    if let Some(isa) = isa {
+        write_encoding_comment(w, func, isa, inst)?;
+    }
+    writeln!(w, "")
+}
+
+/// Write the operands of `inst` to `w`, depending on the instruction format.
+///
+/// Register units are written by name when `regs` is available.
+fn write_operands(w: &mut Write, func: &Function, regs: Option<&RegInfo>, inst: Inst) -> Result {
     use ir::instructions::InstructionData::*;
+    let pool = &func.dfg.value_lists;
     match func.dfg[inst] {
-        Nullary { .. } => writeln!(w, ""),
-        Unary { arg, .. } => writeln!(w, " {}", arg),
-        UnaryImm { imm, .. } => writeln!(w, " {}", imm),
-        UnaryIeee32 { imm, .. } => writeln!(w, " {}", imm),
-        UnaryIeee64 { imm, .. } => writeln!(w, " {}", imm),
-        UnaryImmVector { ref data, .. } => writeln!(w, " {}", data),
-        UnarySplit { arg, .. } => writeln!(w, " {}", arg),
-        Binary { args, .. } => writeln!(w, " {}, {}", args[0], args[1]),
-        BinaryImm { arg, imm, .. } => writeln!(w, " {}, {}", arg, imm),
-        BinaryImmRev { imm, arg, .. } => writeln!(w, " {}, {}", imm, arg),
-        BinaryOverflow { args, .. } => writeln!(w, " {}, {}", args[0], args[1]),
-        Ternary { args, .. } => writeln!(w, " {}, {}, {}", args[0], args[1], args[2]),
-        TernaryOverflow { ref data, .. } => writeln!(w, " {}", data),
-        InsertLane { lane, args, .. } => writeln!(w, " {}, {}, {}", args[0], lane, args[1]),
-        ExtractLane { lane, arg, .. } => writeln!(w, " {}, {}", arg, lane),
-        IntCompare { cond, args, .. } => writeln!(w, " {}, {}, {}", cond, args[0], args[1]),
-        FloatCompare { cond, args, .. } => writeln!(w, " {}, {}, {}", cond, args[0], args[1]),
-        Jump { ref data, .. } => writeln!(w, " {}", data),
-        Branch { ref data, .. } => writeln!(w, " {}", data),
-        BranchTable { arg, table, .. } => writeln!(w, " {}, {}", arg, table),
-        Call { ref data, .. } => writeln!(w, " {}({})", data.func_ref, data.varargs),
-        IndirectCall { ref data, .. } => {
-            writeln!(w, " {}, {}({})", data.sig_ref, data.arg, data.varargs)
+        Nullary { .. } => Ok(()),
+        Unary { arg, .. } => write!(w, " {}", arg),
+        UnaryImm { imm, .. } => {
+            if func.dfg[inst].ctrl_typevar(&func.dfg).lane_type() == types::I128 {
+                write!(w, " {}", imm.display_i128())
+            } else {
+                write!(w, " {}", imm)
+            }
+        }
+        UnaryBool { imm, .. } => write!(w, " {}", imm),
+        UnaryIeee32 { imm, .. } => write!(w, " {}", imm),
+        UnaryIeee64 { imm, .. } => write!(w, " {}", imm),
+        UnaryImmVector { ref data, .. } => write!(w, " {}", data),
+        UnarySplit { arg, .. } => write!(w, " {}", arg),
+        Binary { args, .. } => write!(w, " {}, {}", args[0], args[1]),
+        BinaryImm { arg, imm, .. } => write!(w, " {}, {}", arg, imm),
+        BinaryImmRev { imm, arg, .. } => write!(w, " {}, {}", imm, arg),
+        BinaryOverflow { args, .. } => write!(w, " {}, {}", args[0], args[1]),
+        Ternary { args, .. } => write!(w, " {}, {}, {}", args[0], args[1], args[2]),
+        TernaryOverflow { ref data, .. } => write!(w, " {}", data),
+        InsertLane { lane, args, .. } => write!(w, " {}, {}, {}", args[0], lane, args[1]),
+        ExtractLane { lane, arg, .. } => write!(w, " {}, {}", arg, lane),
+        IntCompare { cond, args, .. } => write!(w, " {}, {}, {}", cond, args[0], args[1]),
+        FloatCompare { cond, args, .. } => write!(w, " {}, {}, {}", cond, args[0], args[1]),
+        IntCond { cond, arg, .. } => write!(w, " {}, {}", cond, arg),
+        FloatCond { cond, arg, .. } => write!(w, " {}, {}", cond, arg),
+        IntSelect { cond, args, .. } => {
+            write!(w, " {}, {}, {}, {}", cond, args[0], args[1], args[2])
+        }
+        Jump { destination, ref args, .. } => {
+            write!(w, " {}", destination)?;
+            write_ebb_args(w, args.as_slice(pool))
+        }
+        Branch { destination, ref args, .. } => {
+            let args = args.as_slice(pool);
+            write!(w, " {}, {}", args[0], destination)?;
+            write_ebb_args(w, &args[1..])
+        }
+        BranchIcmp { cond, destination, ref args, .. } => {
+            let args = args.as_slice(pool);
+            write!(w, " {}, {}, {}, {}", cond, args[0], args[1], destination)?;
+            write_ebb_args(w, &args[2..])
+        }
+        BranchTable { arg, table, .. } => write!(w, " {}, {}", arg, table),
+        BranchTableEntry { args, imm, table, .. } => {
+            write!(w, " {}, {}, {}, {}", args[0], args[1], imm, table)
+        }
+        BranchTableBase { table, .. } => write!(w, " {}", table),
+        Trap { code, .. } => write!(w, " {}", code),
+        CondTrap { arg, code, .. } => write!(w, " {}, {}", arg, code),
+        IntCondTrap { cond, arg, code, .. } => write!(w, " {}, {}, {}", cond, arg, code),
+        BinaryTrap { args, code, .. } => write!(w, " {}, {}, {}", args[0], args[1], code),
+        Call { func_ref, ref args, .. } => {
+            write!(w, " {}({})", func_ref, DisplayValues(args.as_slice(pool)))
+        }
+        IndirectCall { sig_ref, ref args, .. } => {
+            let args = args.as_slice(pool);
+            write!(w, " {}, {}({})", sig_ref, args[0], DisplayValues(&args[1..]))
+        }
+        FuncAddr { func_ref, .. } => write!(w, " {}", func_ref),
+        Return { ref args, .. } => {
+            let args = args.as_slice(pool);
+            if args.is_empty() {
+                Ok(())
+            } else {
+                write!(w, " {}", DisplayValues(args))
+            }
         }
-        Return { ref data, .. } => {
-            if data.varargs.is_empty() {
-                writeln!(w, "")
+        ReturnReg { ref args, .. } => {
+            let args = args.as_slice(pool);
+            if args.len() == 1 {
+                write!(w, " {}", args[0])
             } else {
-                writeln!(w, " {}", data.varargs)
+                write!(w, " {}, {}", args[0], DisplayValues(&args[1..]))
             }
         }
-        ReturnReg { ref data, .. } => {
-            if data.varargs.is_empty() {
-                writeln!(w, " {}", data.arg)
+        HeapAddr { heap, arg, imm, .. } => write!(w, " {}, {}, {}", heap, arg, imm),
+        UnaryGlobalVar { global_var, .. } => write!(w, " {}", global_var),
+        Load { flags, arg, offset, .. } => {
+            write!(w, " {}, {}", arg, offset)?;
+            write_memflags(w, flags)
+        }
+        Store { flags, args, offset, .. } => {
+            write!(w, " {}, {}, {}", args[0], args[1], offset)?;
+            write_memflags(w, flags)
+        }
+        LoadComplex { ref data, .. } => {
+            write!(w, " {}, {}, {}, {}", data.args[0], data.args[1], data.shift, data.offset)?;
+            write_memflags(w, data.flags)
+        }
+        StoreComplex { ref data, .. } => {
+            write!(w,
+                   " {}, {}, {}, {}, {}",
+                   data.args[0],
+                   data.args[1],
+                   data.args[2],
+                   data.shift,
+                   data.offset)?;
+            write_memflags(w, data.flags)
+        }
+        AtomicLoad { ordering, arg, .. } => write!(w, " {}, {}", ordering, arg),
+        AtomicRmw { ordering, args, .. } => write!(w, " {}, {}, {}", ordering, args[0], args[1]),
+        AtomicCas { ordering, args, .. } => {
+            write!(w, " {}, {}, {}, {}", ordering, args[0], args[1], args[2])
+        }
+        StackLoad { stack_slot, offset, .. } => write!(w, " {}, {}", stack_slot, offset),
+        StackStore { arg, stack_slot, offset, .. } => {
+            write!(w, " {}, {}, {}", arg, stack_slot, offset)
+        }
+        RegMove { arg, src, dst, .. } => {
+            if let Some(regs) = regs {
+                write!(w,
+                       " {}, {} -> {}",
+                       arg,
+                       regs.display_regunit(src),
+                       regs.display_regunit(dst))
+            } else {
+                write!(w, " {}, {} -> {}", arg, src, dst)
+            }
+        }
+    }
+}
+
+/// Write the arguments passed to a branch destination, if any.
+fn write_ebb_args(w: &mut Write, args: &[Value]) -> Result {
+    if args.is_empty() {
+        Ok(())
+    } else {
+        write!(w, "({})", DisplayValues(args))
+    }
+}
+
+/// Displayable list of values separated by commas.
+struct DisplayValues<'a>(&'a [Value]);
+
+impl<'a> fmt::Display for DisplayValues<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result {
+        for (i, val) in self.0.iter().enumerate() {
+            if i == 0 {
+                write!(f, "{}", val)?;
             } else {
-                writeln!(w, " {}, {}", data.arg, data.varargs)
+                write!(f, ", {}", val)?;
             }
         }
+        Ok(())
+    }
+}
+
+/// Write the memory operation `flags` following the operands.
+fn write_memflags(w: &mut Write, flags: MemFlags) -> Result {
+    if flags.is_empty() {
+        Ok(())
+    } else {
+        write!(w, ", {}", flags)
+    }
+}
+
+/// Write a comment for `inst` if it doesn't have a legal encoding.
+///
+/// When `isa` can't encode the instruction, the comment names the legalization action that
+/// would be applied to it. Instructions that could be encoded, but haven't been assigned an
+/// encoding in a function that has encodings, are marked as `unencoded`.
+fn write_encoding_comment(w: &mut Write,
+                          func: &Function,
+                          isa: &TargetIsa,
+                          inst: Inst)
+                          -> Result {
+    if func.encodings.get(inst).map_or(false, |enc| enc.is_legal()) {
+        return Ok(());
+    }
+    match isa.encode(&func.dfg, &func.dfg[inst]) {
+        Err(action) => write!(w, " ; {}", action),
+        Ok(_) if !func.encodings.is_empty() => write!(w, " ; unencoded"),
+        Ok(_) => Ok(()),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use ir::{Function, FunctionName, StackSlotData};
+    use cfg::ControlFlowGraph;
+    use dominator_tree::DominatorTree;
+    use ir::{Function, ExternalName, StackSlotData, StackSlotKind, InstBuilder, Cursor,
+             VariableArgs};
     use ir::types;
+    use isa;
+    use settings::{self, Configurable};
+    use super::{write_annotated_function, write_function, Annotations};
 
     #[test]
     fn basic() {
         let mut f = Function::new();
         assert_eq!(f.to_string(), "function \"\"() {\n}\n");
 
-        f.name = FunctionName::new("foo".to_string());
+        f.name = ExternalName::testcase("foo");
         assert_eq!(f.to_string(), "function foo() {\n}\n");
 
-        f.stack_slots.push(StackSlotData::new(4));
+        f.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 4));
         assert_eq!(f.to_string(),
-                   "function foo() {\n    ss0 = stack_slot 4\n}\n");
+                   "function foo() {\n    ss0 = explicit_slot 4\n}\n");
 
         let ebb = f.dfg.make_ebb();
         f.layout.append_ebb(ebb);
         assert_eq!(f.to_string(),
-                   "function foo() {\n    ss0 = stack_slot 4\n\nebb0:\n}\n");
+                   "function foo() {\n    ss0 = explicit_slot 4\n\nebb0:\n}\n");
 
         f.dfg.append_ebb_arg(ebb, types::I8);
         assert_eq!(f.to_string(),
-                   "function foo() {\n    ss0 = stack_slot 4\n\nebb0(vx0: i8):\n}\n");
+                   "function foo() {\n    ss0 = explicit_slot 4\n\nebb0(vx0: i8):\n}\n");
 
         f.dfg.append_ebb_arg(ebb, types::F32.by(4).unwrap());
         assert_eq!(f.to_string(),
-                   "function foo() {\n    ss0 = stack_slot 4\n\nebb0(vx0: i8, vx1: f32x4):\n}\n");
+                   "function foo() {\n    ss0 = explicit_slot 4\n\nebb0(vx0: i8, vx1: f32x4):\n}\n");
+    }
+
+    #[test]
+    fn annotations() {
+        let mut f = Function::new();
+        let ebb0 = f.dfg.make_ebb();
+        let cond = f.dfg.append_ebb_arg(ebb0, types::I32);
+        let ebb1 = f.dfg.make_ebb();
+        let ebb2 = f.dfg.make_ebb();
+        {
+            let dfg = &mut f.dfg;
+            let cur = &mut Cursor::new(&mut f.layout);
+            cur.insert_ebb(ebb0);
+            dfg.ins(cur).brnz(cond, ebb2, VariableArgs::new());
+            dfg.ins(cur).jump(ebb1, VariableArgs::new());
+            cur.insert_ebb(ebb1);
+            dfg.ins(cur).jump(ebb2, VariableArgs::new());
+            cur.insert_ebb(ebb2);
+            dfg.ins(cur).return_(VariableArgs::new());
+        }
+
+        let cfg = ControlFlowGraph::with_function(&f);
+        let domtree = DominatorTree::with_function(&f, &cfg);
+        let mut text = String::new();
+        write_annotated_function(&mut text,
+                                 &f,
+                                 None,
+                                 &Annotations {
+                                     cfg: Some(&cfg),
+                                     domtree: Some(&domtree),
+                                     liveness: None,
+                                     offsets: false,
+                                 })
+            .unwrap();
+        assert_eq!(text,
+                   "function \"\"() {\n\
+                    ebb0(vx0: i32): ; preds=[]\n    \
+                    brnz vx0, ebb2\n    \
+                    jump ebb1\n\n\
+                    ebb1: ; preds=[ebb0:inst1] idom=ebb0:inst1\n    \
+                    jump ebb2\n\n\
+                    ebb2: ; preds=[ebb0:inst0, ebb1:inst2] idom=ebb0:inst0\n    \
+                    return\n\
+                    }\n");
+    }
+
+    #[test]
+    fn encoding_comments() {
+        let mut f = Function::new();
+        let ebb0 = f.dfg.make_ebb();
+        {
+            let dfg = &mut f.dfg;
+            let cur = &mut Cursor::new(&mut f.layout);
+            cur.insert_ebb(ebb0);
+            let v0 = dfg.append_ebb_arg(ebb0, types::I32);
+            let v1 = dfg.append_ebb_arg(ebb0, types::I64);
+            let v2 = dfg.ins(cur).iadd(v0, v0);
+            dfg.ins(cur).isub(v2, v0);
+            dfg.ins(cur).iadd(v1, v1);
+            dfg.ins(cur).imul(v2, v2);
+        }
+
+        let mut flags = settings::builder();
+        flags.set_bool("is_64bit", false).unwrap();
+        let isa = isa::lookup("riscv").unwrap().finish(settings::Flags::new(&flags));
+
+        let mut text = String::new();
+        write_function(&mut text, &f, Some(&*isa)).unwrap();
+        assert_eq!(text,
+                   "function \"\"() {\n\
+                    ebb0(vx0: i32, vx1: i64):\n    \
+                    v0 = iadd vx0, vx0\n    \
+                    v1 = isub v0, vx0\n    \
+                    v2 = iadd vx1, vx1 ; narrow\n    \
+                    v3 = imul v0, v0 ; custom2\n\
+                    }\n");
+
+        // Once the function has encodings, encodable instructions without one are flagged.
+        let iadd = f.layout.ebb_insts(ebb0).next().unwrap();
+        let enc = isa.encode(&f.dfg, &f.dfg[iadd]).unwrap();
+        f.encodings.resize(f.dfg.num_insts());
+        f.encodings[iadd] = enc;
+        let mut text = String::new();
+        write_function(&mut text, &f, Some(&*isa)).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert!(lines[2].starts_with("[R#") && lines[2].ends_with(" v0 = iadd vx0, vx0"));
+        assert!(lines[3].ends_with(" v1 = isub v0, vx0 ; unencoded"));
+        assert!(lines[4].ends_with(" v2 = iadd vx1, vx1 ; narrow"));
     }
 }
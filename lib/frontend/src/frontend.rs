@@ -0,0 +1,616 @@
+//! A frontend for building Cretonne IL from other languages.
+
+use cretonne::entity_map::{EntityMap, EntityRef};
+use cretonne::ir::{Ebb, Inst, Value, Type, Function, DataFlowGraph, InstructionData,
+                   InstBuilderBase, StackSlot, StackSlotData, JumpTable, JumpTableData, SigRef,
+                   Signature, FuncRef, ExtFuncData, GlobalVar, GlobalVarData, Heap, HeapData};
+use cretonne::ir::dfg::Values;
+use cretonne::ir::instructions::BranchInfo;
+use cretonne::ir::types::VOID;
+use ssa::{SSABuilder, Block};
+
+/// Reusable state for building functions with `FunctionBuilder`.
+///
+/// The `ILBuilder` holds the data structures used while translating a function. Keeping it around
+/// between functions avoids reallocating them every time.
+pub struct ILBuilder<Variable>
+    where Variable: EntityRef
+{
+    ssa: SSABuilder<Variable>,
+    ebbs: EntityMap<Ebb, EbbData>,
+    types: EntityMap<Variable, Type>,
+}
+
+/// Builder state for a single EBB.
+#[derive(Clone, Default)]
+struct EbbData {
+    /// Does the EBB have no instructions yet?
+    pristine: bool,
+    /// Has the EBB been terminated?
+    filled: bool,
+}
+
+/// The position where instructions are currently inserted.
+#[derive(Clone, Copy)]
+struct Position {
+    ebb: Ebb,
+    block: Block,
+}
+
+impl<Variable> ILBuilder<Variable>
+    where Variable: EntityRef
+{
+    /// Create a new, empty `ILBuilder`.
+    pub fn new() -> ILBuilder<Variable> {
+        ILBuilder {
+            ssa: SSABuilder::new(),
+            ebbs: EntityMap::new(),
+            types: EntityMap::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.ssa.clear();
+        self.ebbs.clear();
+        self.types.clear();
+    }
+}
+
+/// Builder for the instructions of a Cretonne IL function.
+///
+/// The `FunctionBuilder` appends instructions at the end of the current EBB, and keeps track of
+/// the control flow between EBBs as branches are inserted. Front ends can use *variables*
+/// instead of SSA values: a variable is declared with a type, defined with `def_var()` any number
+/// of times, and read with `use_var()`. The builder inserts the EBB arguments needed to merge the
+/// definitions reaching an EBB.
+///
+/// The `Variable` type is chosen by the front end. It must be an `EntityRef`, so variables are
+/// numbered densely from 0.
+///
+/// An EBB must be *sealed* with `seal_block()` once all the branches to it have been inserted.
+/// Sealing EBBs as early as possible keeps the number of provisional EBB arguments down. Every
+/// EBB must be sealed and terminated before the function is finalized.
+pub struct FunctionBuilder<'a, Variable: 'a>
+    where Variable: EntityRef
+{
+    /// The function being built.
+    pub func: &'a mut Function,
+
+    builder: &'a mut ILBuilder<Variable>,
+    position: Option<Position>,
+}
+
+/// Instruction builder that appends to the current EBB of a `FunctionBuilder`.
+///
+/// This is returned by `FunctionBuilder::ins()` and provides the per-opcode methods of the
+/// `InstBuilder` trait.
+pub struct FuncInstBuilder<'short, 'long: 'short, Variable: 'long>
+    where Variable: EntityRef
+{
+    builder: &'short mut FunctionBuilder<'long, Variable>,
+    ebb: Ebb,
+}
+
+impl<'short, 'long, Variable> FuncInstBuilder<'short, 'long, Variable>
+    where Variable: EntityRef
+{
+    fn build(self,
+             data: InstructionData,
+             ctrl_typevar: Option<Type>)
+             -> (Inst, &'short mut DataFlowGraph) {
+        let inst = self.builder.func.dfg.make_inst(data);
+        if let Some(ty) = ctrl_typevar {
+            self.builder.func.dfg.make_inst_results(inst, ty);
+        }
+        self.builder.func.layout.append_inst(inst, self.ebb);
+        self.builder.builder.ebbs[self.ebb].pristine = false;
+
+        let opcode = self.builder.func.dfg[inst].opcode();
+        self.builder.declare_successors(inst);
+        if opcode.is_terminator() {
+            self.builder.builder.ebbs[self.ebb].filled = true;
+        } else if opcode.is_branch() {
+            // The instructions following a conditional branch have a single predecessor.
+            let pos = self.builder.position.as_mut().unwrap();
+            pos.block = self.builder.builder.ssa.declare_ebb_body_block(pos.block);
+        }
+        (inst, &mut self.builder.func.dfg)
+    }
+}
+
+impl<'short, 'long, Variable> InstBuilderBase<'short> for FuncInstBuilder<'short, 'long, Variable>
+    where Variable: EntityRef
+{
+    fn data_flow_graph(&self) -> &DataFlowGraph {
+        &self.builder.func.dfg
+    }
+
+    fn data_flow_graph_mut(&mut self) -> &mut DataFlowGraph {
+        &mut self.builder.func.dfg
+    }
+
+    fn simple_instruction(self, data: InstructionData) -> (Inst, &'short mut DataFlowGraph) {
+        self.build(data, None)
+    }
+
+    fn complex_instruction(self,
+                           data: InstructionData,
+                           ctrl_typevar: Type)
+                           -> (Inst, &'short mut DataFlowGraph) {
+        self.build(data, Some(ctrl_typevar))
+    }
+}
+
+impl<'a, Variable> FunctionBuilder<'a, Variable>
+    where Variable: EntityRef
+{
+    /// Create a new builder appending to `func`, using the data structures in `builder`.
+    ///
+    /// Any state left in `builder` from a previous function is discarded.
+    pub fn new(func: &'a mut Function,
+               builder: &'a mut ILBuilder<Variable>)
+               -> FunctionBuilder<'a, Variable> {
+        builder.clear();
+        FunctionBuilder {
+            func: func,
+            builder: builder,
+            position: None,
+        }
+    }
+
+    /// Create a new EBB.
+    ///
+    /// The EBB is added to the layout when the builder first switches to it.
+    pub fn create_ebb(&mut self) -> Ebb {
+        let ebb = self.func.dfg.make_ebb();
+        self.builder.ssa.declare_ebb_header_block(ebb);
+        *self.builder.ebbs.ensure(ebb) = EbbData {
+            pristine: true,
+            filled: false,
+        };
+        ebb
+    }
+
+    /// Start appending instructions to `ebb`.
+    ///
+    /// The current EBB must be terminated or still empty, and `ebb` must not be terminated.
+    pub fn switch_to_block(&mut self, ebb: Ebb) {
+        debug_assert!(self.position.is_none() || self.is_pristine() || self.is_filled(),
+                      "The current EBB must be terminated before switching to {}",
+                      ebb);
+        assert!(!self.builder.ebbs[ebb].filled,
+                "{} is already terminated",
+                ebb);
+        if !self.func.layout.is_ebb_inserted(ebb) {
+            self.func.layout.append_ebb(ebb);
+        }
+        self.position = Some(Position {
+                                 ebb: ebb,
+                                 block: self.builder.ssa.header_block(ebb),
+                             });
+    }
+
+    /// Declare that all the branches to `ebb` have been inserted.
+    ///
+    /// No more branches to `ebb` may be inserted afterwards.
+    pub fn seal_block(&mut self, ebb: Ebb) {
+        self.builder.ssa.seal_ebb_header_block(self.func, ebb);
+    }
+
+    /// Seal all the EBBs that haven't been sealed yet.
+    ///
+    /// This is simpler than sealing each EBB as soon as possible, but it creates more provisional
+    /// EBB arguments.
+    pub fn seal_all_blocks(&mut self) {
+        self.builder.ssa.seal_all_ebb_header_blocks(self.func);
+    }
+
+    /// Declare the type of `var`. This must be done before the variable is defined or used.
+    pub fn declare_var(&mut self, var: Variable, ty: Type) {
+        *self.builder.types.ensure(var) = ty;
+    }
+
+    /// Get the current value of `var` at the end of the current EBB.
+    pub fn use_var(&mut self, var: Variable) -> Value {
+        let ty = self.builder.types.get(var).cloned().unwrap_or(VOID);
+        assert!(ty != VOID, "Variable {} is used before it is declared", var.index());
+        let block = self.position().block;
+        self.builder.ssa.use_var(self.func, var, ty, block)
+    }
+
+    /// Define `var` to have the value `val` from this point in the current EBB.
+    pub fn def_var(&mut self, var: Variable, val: Value) {
+        debug_assert_eq!(self.builder.types.get(var).cloned(),
+                         Some(self.func.dfg.value_type(val)),
+                         "Variable {} defined with the wrong type",
+                         var.index());
+        let block = self.position().block;
+        self.builder.ssa.def_var(var, val, block);
+    }
+
+    /// Get an instruction builder that appends to the current EBB.
+    pub fn ins<'short>(&'short mut self) -> FuncInstBuilder<'short, 'a, Variable> {
+        let ebb = self.position().ebb;
+        assert!(!self.builder.ebbs[ebb].filled,
+                "Cannot append instructions to {} after its terminator",
+                ebb);
+        FuncInstBuilder {
+            builder: self,
+            ebb: ebb,
+        }
+    }
+
+    /// Get the EBB where instructions are currently appended.
+    pub fn current_ebb(&self) -> Ebb {
+        self.position().ebb
+    }
+
+    /// Does the current EBB have no instructions yet?
+    pub fn is_pristine(&self) -> bool {
+        self.builder.ebbs[self.position().ebb].pristine
+    }
+
+    /// Has the current EBB been terminated?
+    pub fn is_filled(&self) -> bool {
+        self.builder.ebbs[self.position().ebb].filled
+    }
+
+    /// Is the current EBB unreachable?
+    ///
+    /// This is the case when the EBB is sealed without any predecessors, and it isn't the entry
+    /// EBB. Instructions appended to an unreachable EBB are dead code.
+    pub fn is_unreachable(&self) -> bool {
+        let ebb = self.position().ebb;
+        self.func.layout.entry_block() != Some(ebb) && self.builder.ssa.is_sealed(ebb) &&
+        self.builder.ssa.predecessors(ebb).is_empty()
+    }
+
+    /// Append an argument of type `ty` to `ebb`.
+    ///
+    /// The explicit EBB arguments must be added before the EBB is used.
+    pub fn append_ebb_arg(&mut self, ebb: Ebb, ty: Type) -> Value {
+        debug_assert!(self.builder.ebbs[ebb].pristine,
+                      "{} already has instructions",
+                      ebb);
+        self.func.dfg.append_ebb_arg(ebb, ty)
+    }
+
+    /// Append arguments to `ebb` matching the arguments of the function signature.
+    ///
+    /// This is typically used for the entry EBB.
+    pub fn append_ebb_args_for_function_args(&mut self, ebb: Ebb) {
+        for i in 0..self.func.signature.argument_types.len() {
+            let ty = self.func.signature.argument_types[i].value_type;
+            self.append_ebb_arg(ebb, ty);
+        }
+    }
+
+    /// Get the arguments of `ebb`.
+    pub fn ebb_args<'f>(&'f self, ebb: Ebb) -> Values<'f> {
+        self.func.dfg.ebb_args(ebb)
+    }
+
+    /// Create a stack slot in the function.
+    pub fn create_stack_slot(&mut self, data: StackSlotData) -> StackSlot {
+        self.func.stack_slots.push(data)
+    }
+
+    /// Create a jump table in the function.
+    ///
+    /// Jump tables shouldn't be shared between `br_table` instructions, since the edges are split
+    /// when the destinations need EBB arguments.
+    pub fn create_jump_table(&mut self, data: JumpTableData) -> JumpTable {
+        self.func.jump_tables.push(data)
+    }
+
+    /// Declare a signature that can be used by indirect calls.
+    pub fn import_signature(&mut self, signature: Signature) -> SigRef {
+        self.func.dfg.signatures.push(signature)
+    }
+
+    /// Declare an external function that can be called.
+    pub fn import_function(&mut self, data: ExtFuncData) -> FuncRef {
+        self.func.dfg.ext_funcs.push(data)
+    }
+
+    /// Create a global variable in the function.
+    pub fn create_global_var(&mut self, data: GlobalVarData) -> GlobalVar {
+        self.func.dfg.global_vars.push(data)
+    }
+
+    /// Create a heap in the function.
+    pub fn create_heap(&mut self, data: HeapData) -> Heap {
+        self.func.heaps.push(data)
+    }
+
+    /// Finish building the function.
+    ///
+    /// All the EBBs must be sealed, and all the EBBs in the layout must be terminated. The
+    /// `ILBuilder` is cleared so it can be reused.
+    pub fn finalize(&mut self) {
+        for ebb in self.func.layout.ebbs() {
+            debug_assert!(self.builder.ssa.is_sealed(ebb), "{} is not sealed", ebb);
+            debug_assert!(self.func
+                              .layout
+                              .last_inst(ebb)
+                              .map_or(false, |inst| self.func.dfg[inst].opcode().is_terminator()),
+                          "{} is not terminated",
+                          ebb);
+        }
+        self.builder.clear();
+        self.position = None;
+    }
+
+    fn position(&self) -> Position {
+        self.position.expect("No current EBB; call switch_to_block() first")
+    }
+
+    /// Declare the current block as a predecessor of the destinations of `inst`, if it is a
+    /// branch.
+    fn declare_successors(&mut self, inst: Inst) {
+        let block = self.position().block;
+        let dests = match self.func.dfg.analyze_branch(inst) {
+            BranchInfo::NotABranch => Vec::new(),
+            BranchInfo::SingleDest(dest, _) => vec![dest],
+            BranchInfo::Table(jt) => {
+                let mut dests = Vec::new();
+                for (_, dest) in self.func.jump_tables[jt].entries() {
+                    if !dests.contains(&dest) {
+                        dests.push(dest);
+                    }
+                }
+                dests
+            }
+        };
+        for dest in dests {
+            self.builder.ssa.declare_ebb_predecessor(dest, block, inst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cretonne::entity_map::EntityRef;
+    use cretonne::ir::{Function, Signature, ArgumentType, ExternalName, InstBuilder, VariableArgs,
+                       JumpTableData};
+    use cretonne::ir::condcodes::IntCC;
+    use cretonne::ir::types::I32;
+    use cretonne::verify_function;
+    use super::{ILBuilder, FunctionBuilder};
+
+    // An opaque reference to a variable.
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    struct Variable(u32);
+
+    impl EntityRef for Variable {
+        fn new(index: usize) -> Self {
+            Variable(index as u32)
+        }
+
+        fn index(self) -> usize {
+            self.0 as usize
+        }
+    }
+
+    fn new_function() -> Function {
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(I32));
+        sig.return_types.push(ArgumentType::new(I32));
+        Function::with_name_signature(ExternalName::testcase("sample"), sig)
+    }
+
+    fn args(vals: &[::cretonne::ir::Value]) -> VariableArgs {
+        let mut args = VariableArgs::new();
+        for &v in vals {
+            args.push(v);
+        }
+        args
+    }
+
+    fn check(func: &Function) {
+        if let Err(e) = verify_function(func) {
+            panic!("{}\n{}", func, e);
+        }
+    }
+
+    #[test]
+    fn straight_line() {
+        let mut func = new_function();
+        let mut il_builder = ILBuilder::<Variable>::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+            let x = Variable::new(0);
+            let y = Variable::new(1);
+            builder.declare_var(x, I32);
+            builder.declare_var(y, I32);
+
+            let ebb0 = builder.create_ebb();
+            builder.append_ebb_args_for_function_args(ebb0);
+            builder.switch_to_block(ebb0);
+            builder.seal_block(ebb0);
+            let arg = builder.ebb_args(ebb0).next().unwrap();
+            builder.def_var(x, arg);
+            let v = builder.ins().iconst(I32, 2);
+            builder.def_var(y, v);
+            let arg1 = builder.use_var(x);
+            let arg2 = builder.use_var(y);
+            let sum = builder.ins().iadd(arg1, arg2);
+            builder.def_var(x, sum);
+            let ret = builder.use_var(x);
+            builder.ins().return_(args(&[ret]));
+            builder.finalize();
+        }
+        check(&func);
+        assert_eq!(func.to_string(),
+                   "function sample(i32) -> i32 {
+ebb0(vx0: i32):
+    v0 = iconst.i32 2
+    v1 = iadd vx0, v0
+    return v1
+}
+");
+    }
+
+    #[test]
+    fn loop_variables() {
+        // x = arg; y = 0;
+        // loop { if x == 0 break; y = y + x; x = x - 1; }
+        // return y;
+        let mut func = new_function();
+        let mut il_builder = ILBuilder::<Variable>::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+            let x = Variable::new(0);
+            let y = Variable::new(1);
+            builder.declare_var(x, I32);
+            builder.declare_var(y, I32);
+
+            let entry = builder.create_ebb();
+            let header = builder.create_ebb();
+            let exit = builder.create_ebb();
+
+            builder.append_ebb_args_for_function_args(entry);
+            builder.switch_to_block(entry);
+            builder.seal_block(entry);
+            let arg = builder.ebb_args(entry).next().unwrap();
+            builder.def_var(x, arg);
+            let zero = builder.ins().iconst(I32, 0);
+            builder.def_var(y, zero);
+            builder.ins().jump(header, VariableArgs::new());
+
+            // The loop header isn't sealed until the back edge is inserted.
+            builder.switch_to_block(header);
+            let xv = builder.use_var(x);
+            builder.ins().brz(xv, exit, VariableArgs::new());
+            let xv = builder.use_var(x);
+            let yv = builder.use_var(y);
+            let sum = builder.ins().iadd(yv, xv);
+            builder.def_var(y, sum);
+            let one = builder.ins().iconst(I32, 1);
+            let dec = builder.ins().isub(xv, one);
+            builder.def_var(x, dec);
+            builder.ins().jump(header, VariableArgs::new());
+            builder.seal_block(header);
+
+            builder.switch_to_block(exit);
+            builder.seal_block(exit);
+            let yv = builder.use_var(y);
+            builder.ins().return_(args(&[yv]));
+            builder.finalize();
+        }
+        check(&func);
+        // Both variables are merged in the loop header.
+        assert_eq!(func.dfg.num_ebb_args(func.layout.entry_block().unwrap()), 1);
+        let header = func.layout.ebbs().nth(1).unwrap();
+        assert_eq!(func.dfg.num_ebb_args(header), 2);
+    }
+
+    #[test]
+    fn trivial_ebb_args_removed() {
+        // A variable that isn't modified in a loop doesn't need an EBB argument.
+        let mut func = new_function();
+        let mut il_builder = ILBuilder::<Variable>::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+            let x = Variable::new(0);
+            builder.declare_var(x, I32);
+
+            let entry = builder.create_ebb();
+            let header = builder.create_ebb();
+            let exit = builder.create_ebb();
+
+            builder.append_ebb_args_for_function_args(entry);
+            builder.switch_to_block(entry);
+            let arg = builder.ebb_args(entry).next().unwrap();
+            builder.def_var(x, arg);
+            builder.ins().jump(header, VariableArgs::new());
+
+            builder.switch_to_block(header);
+            let xv = builder.use_var(x);
+            builder.ins().br_icmp(IntCC::Equal, xv, xv, exit, VariableArgs::new());
+            builder.ins().jump(header, VariableArgs::new());
+
+            builder.switch_to_block(exit);
+            let xv = builder.use_var(x);
+            builder.ins().return_(args(&[xv]));
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+        check(&func);
+        for ebb in func.layout.ebbs() {
+            if func.layout.entry_block() != Some(ebb) {
+                assert_eq!(func.dfg.num_ebb_args(ebb), 0, "{}", func);
+            }
+        }
+    }
+
+    #[test]
+    fn undefined_variable() {
+        // A variable used without a definition reads as zero.
+        let mut func = new_function();
+        let mut il_builder = ILBuilder::<Variable>::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+            let x = Variable::new(0);
+            builder.declare_var(x, I32);
+
+            let ebb0 = builder.create_ebb();
+            builder.append_ebb_args_for_function_args(ebb0);
+            builder.switch_to_block(ebb0);
+            builder.seal_block(ebb0);
+            let xv = builder.use_var(x);
+            builder.ins().return_(args(&[xv]));
+            builder.finalize();
+        }
+        check(&func);
+        assert_eq!(func.to_string(),
+                   "function sample(i32) -> i32 {
+ebb0(vx0: i32):
+    v0 = iconst.i32 0
+    return v0
+}
+");
+    }
+
+    #[test]
+    fn br_table_edges_split() {
+        // Values can't be passed through a jump table, so a new EBB is inserted.
+        let mut func = new_function();
+        let mut il_builder = ILBuilder::<Variable>::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+            let x = Variable::new(0);
+            builder.declare_var(x, I32);
+
+            let entry = builder.create_ebb();
+            let case = builder.create_ebb();
+            let exit = builder.create_ebb();
+
+            builder.append_ebb_args_for_function_args(entry);
+            builder.switch_to_block(entry);
+            builder.seal_block(entry);
+            let arg = builder.ebb_args(entry).next().unwrap();
+            builder.def_var(x, arg);
+            let mut jt_data = JumpTableData::new();
+            jt_data.set_entry(0, case);
+            jt_data.set_entry(1, exit);
+            let jt = builder.create_jump_table(jt_data);
+            builder.ins().br_table(arg, jt);
+            builder.ins().jump(exit, VariableArgs::new());
+
+            builder.switch_to_block(case);
+            builder.seal_block(case);
+            let one = builder.ins().iconst(I32, 1);
+            builder.def_var(x, one);
+            builder.ins().jump(exit, VariableArgs::new());
+
+            builder.switch_to_block(exit);
+            builder.seal_block(exit);
+            let xv = builder.use_var(x);
+            builder.ins().return_(args(&[xv]));
+            builder.finalize();
+        }
+        check(&func);
+        assert_eq!(func.layout.ebbs().count(), 4, "{}", func);
+    }
+}
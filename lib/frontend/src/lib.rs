@@ -0,0 +1,71 @@
+//! Cretonne IL builder library.
+//!
+//! Front ends translating a source language to Cretonne IL usually think in terms of mutable
+//! variables rather than SSA values. The cton_frontend library provides a `FunctionBuilder` which
+//! lets them declare, define and use variables freely. The builder computes the SSA form on the
+//! fly by inserting EBB arguments where definitions from different predecessors meet, using the
+//! algorithm from "Simple and Efficient Construction of Static Single Assignment Form" by Braun
+//! et al.
+//!
+//! Instructions are inserted with the same generated `InstBuilder` methods as the data flow graph
+//! cursor API:
+//!
+//! ```
+//! extern crate cretonne;
+//! extern crate cton_frontend;
+//!
+//! use cretonne::entity_map::EntityRef;
+//! use cretonne::ir::{Function, Signature, ArgumentType, ExternalName, InstBuilder, VariableArgs};
+//! use cretonne::ir::types::I32;
+//! use cton_frontend::{ILBuilder, FunctionBuilder};
+//!
+//! #[derive(Copy, Clone, PartialEq, Eq)]
+//! struct Variable(u32);
+//!
+//! impl EntityRef for Variable {
+//!     fn new(index: usize) -> Self {
+//!         Variable(index as u32)
+//!     }
+//!     fn index(self) -> usize {
+//!         self.0 as usize
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut sig = Signature::new();
+//!     sig.return_types.push(ArgumentType::new(I32));
+//!     let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+//!     let mut il_builder = ILBuilder::<Variable>::new();
+//!     {
+//!         let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+//!         let x = Variable::new(0);
+//!         builder.declare_var(x, I32);
+//!
+//!         let entry = builder.create_ebb();
+//!         let exit = builder.create_ebb();
+//!         builder.switch_to_block(entry);
+//!         builder.seal_block(entry);
+//!         let v = builder.ins().iconst(I32, 42);
+//!         builder.def_var(x, v);
+//!         builder.ins().jump(exit, VariableArgs::new());
+//!
+//!         builder.switch_to_block(exit);
+//!         builder.seal_block(exit);
+//!         let x_val = builder.use_var(x);
+//!         let mut rets = VariableArgs::new();
+//!         rets.push(x_val);
+//!         builder.ins().return_(rets);
+//!         builder.finalize();
+//!     }
+//!     assert!(cretonne::verify_function(&func).is_ok());
+//! }
+//! ```
+
+#![deny(missing_docs)]
+
+extern crate cretonne;
+
+pub use frontend::{ILBuilder, FunctionBuilder, FuncInstBuilder};
+
+mod frontend;
+mod ssa;
@@ -0,0 +1,377 @@
+//! SSA construction for variables that are defined and used across EBBs.
+//!
+//! This module implements the algorithm described in "Simple and Efficient Construction of Static
+//! Single Assignment Form" by Braun et al. (CC 2013). Definitions of a variable are recorded per
+//! *block*, and a use looks for the nearest definition by walking the predecessors backwards.
+//! When several definitions can reach an EBB header, an EBB argument is created to merge them,
+//! and the corresponding values are appended to the branches in the predecessors.
+//!
+//! An EBB is *sealed* when all of its predecessors are known. Uses in an EBB that isn't sealed yet
+//! create a provisional EBB argument that is completed or removed when the EBB is sealed.
+
+use cretonne::entity_map::{EntityMap, EntityRef, PrimaryEntityData};
+use cretonne::ir::{Ebb, Value, Inst, Type, Function, Cursor, InstBuilder, JumpTableData,
+                   VariableArgs};
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use cretonne::ir::instructions::BranchInfo;
+use cretonne::ir::types::{F32, F64};
+use std::collections::HashMap;
+use std::u32;
+
+/// A basic block in the sense of the SSA construction algorithm.
+///
+/// An EBB is made of a header block which can have many predecessors, followed by a sequence of
+/// body blocks that each have a single predecessor: the block containing the conditional branch
+/// that ends it.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Block(u32);
+
+impl EntityRef for Block {
+    fn new(index: usize) -> Self {
+        assert!(index < (u32::MAX as usize));
+        Block(index as u32)
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Data recorded for an EBB header block.
+struct EbbHeaderBlockData<Variable> {
+    /// The predecessor blocks and the branch instructions jumping to this EBB from them.
+    predecessors: Vec<(Block, Inst)>,
+    /// Are all the predecessors known?
+    sealed: bool,
+    /// The EBB this block is the header of.
+    ebb: Ebb,
+    /// Provisional EBB arguments created for variables used before the EBB was sealed.
+    undef_variables: Vec<(Variable, Value)>,
+}
+
+enum BlockData<Variable> {
+    /// The header block of an EBB.
+    EbbHeader(EbbHeaderBlockData<Variable>),
+    /// A block following a conditional branch in the middle of an EBB.
+    EbbBody { predecessor: Block },
+}
+
+impl<Variable> PrimaryEntityData for BlockData<Variable> {}
+
+/// Incremental SSA construction state for the variables of a single function.
+///
+/// The `SSABuilder` doesn't own the function. All methods that modify it take it as an argument,
+/// and the same function must be passed every time.
+pub struct SSABuilder<Variable>
+    where Variable: EntityRef
+{
+    /// The last definition of each variable in each block where it is defined or was looked up.
+    variables: EntityMap<Variable, HashMap<Block, Value>>,
+
+    /// All the blocks created so far.
+    blocks: EntityMap<Block, BlockData<Variable>>,
+
+    /// The header block of each EBB.
+    ebb_headers: EntityMap<Ebb, Option<Block>>,
+}
+
+impl<Variable> SSABuilder<Variable>
+    where Variable: EntityRef
+{
+    /// Create a new, empty `SSABuilder`.
+    pub fn new() -> SSABuilder<Variable> {
+        SSABuilder {
+            variables: EntityMap::new(),
+            blocks: EntityMap::new(),
+            ebb_headers: EntityMap::new(),
+        }
+    }
+
+    /// Clear all data so the builder can be used for a new function.
+    pub fn clear(&mut self) {
+        self.variables.clear();
+        self.blocks.clear();
+        self.ebb_headers.clear();
+    }
+
+    /// Declare a new EBB and return the header block representing it.
+    pub fn declare_ebb_header_block(&mut self, ebb: Ebb) -> Block {
+        let block = self.blocks
+            .push(BlockData::EbbHeader(EbbHeaderBlockData {
+                                           predecessors: Vec::new(),
+                                           sealed: false,
+                                           ebb: ebb,
+                                           undef_variables: Vec::new(),
+                                       }));
+        *self.ebb_headers.ensure(ebb) = Some(block);
+        block
+    }
+
+    /// Declare a new body block following the conditional branch at the end of `predecessor`.
+    pub fn declare_ebb_body_block(&mut self, predecessor: Block) -> Block {
+        self.blocks.push(BlockData::EbbBody { predecessor: predecessor })
+    }
+
+    /// Get the header block of `ebb`.
+    pub fn header_block(&self, ebb: Ebb) -> Block {
+        self.ebb_headers
+            .get(ebb)
+            .and_then(|&b| b)
+            .expect("EBB has not been declared")
+    }
+
+    /// Record that the branch instruction `inst` at the end of `pred` jumps to `ebb`.
+    ///
+    /// The EBB must not be sealed yet.
+    pub fn declare_ebb_predecessor(&mut self, ebb: Ebb, pred: Block, inst: Inst) {
+        let header = self.header_block(ebb);
+        let data = self.header_data_mut(header);
+        assert!(!data.sealed, "Cannot add a predecessor to sealed {}", ebb);
+        data.predecessors.push((pred, inst));
+    }
+
+    /// Get the predecessors of `ebb` as (block, branch instruction) pairs.
+    pub fn predecessors(&self, ebb: Ebb) -> &[(Block, Inst)] {
+        match self.blocks[self.header_block(ebb)] {
+            BlockData::EbbHeader(ref data) => &data.predecessors,
+            BlockData::EbbBody { .. } => unreachable!(),
+        }
+    }
+
+    /// Have all the predecessors of `ebb` been declared?
+    pub fn is_sealed(&self, ebb: Ebb) -> bool {
+        match self.blocks[self.header_block(ebb)] {
+            BlockData::EbbHeader(ref data) => data.sealed,
+            BlockData::EbbBody { .. } => unreachable!(),
+        }
+    }
+
+    /// Record that `var` is defined as `val` in `block`.
+    pub fn def_var(&mut self, var: Variable, val: Value, block: Block) {
+        self.variables.ensure(var).insert(block, val);
+    }
+
+    /// Get the value of `var` of type `ty` at the current end of `block`.
+    ///
+    /// This may create EBB arguments and add branch arguments in `func` to merge the definitions
+    /// reaching `block`. If no definition reaches `block` at all, the variable is given a zero
+    /// value at the top of the entry EBB.
+    pub fn use_var(&mut self, func: &mut Function, var: Variable, ty: Type, block: Block) -> Value {
+        if let Some(&val) = self.variables.ensure(var).get(&block) {
+            return func.dfg.resolve_aliases(val);
+        }
+
+        let (ebb, sealed, single_pred, num_preds) = match self.blocks[block] {
+            BlockData::EbbBody { predecessor } => {
+                let val = self.use_var(func, var, ty, predecessor);
+                self.def_var(var, val, block);
+                return val;
+            }
+            BlockData::EbbHeader(ref data) => {
+                (data.ebb,
+                 data.sealed,
+                 data.predecessors.first().map(|&(pred, _)| pred),
+                 data.predecessors.len())
+            }
+        };
+
+        let val = if !sealed {
+            // The predecessors aren't all known yet, so create a provisional argument that is
+            // resolved when the EBB is sealed.
+            let val = func.dfg.append_ebb_arg(ebb, ty);
+            self.header_data_mut(block)
+                .undef_variables
+                .push((var, val));
+            val
+        } else if num_preds == 0 {
+            emit_zero(func, ebb, ty)
+        } else if num_preds == 1 {
+            self.use_var(func, var, ty, single_pred.unwrap())
+        } else {
+            // Define the variable before visiting the predecessors so loops terminate.
+            let val = func.dfg.append_ebb_arg(ebb, ty);
+            self.def_var(var, val, block);
+            self.resolve_ebb_arg(func, var, ty, block, val)
+        };
+        self.def_var(var, val, block);
+        val
+    }
+
+    /// Declare that all the predecessors of `ebb` are known.
+    ///
+    /// The provisional EBB arguments created for uses in `ebb` are completed or removed.
+    pub fn seal_ebb_header_block(&mut self, func: &mut Function, ebb: Ebb) {
+        let block = self.header_block(ebb);
+        let undef_variables = {
+            let data = self.header_data_mut(block);
+            assert!(!data.sealed, "{} is already sealed", ebb);
+            data.sealed = true;
+            data.undef_variables.drain(..).collect::<Vec<_>>()
+        };
+        for (var, arg) in undef_variables {
+            let ty = func.dfg.value_type(arg);
+            self.resolve_ebb_arg(func, var, ty, block, arg);
+        }
+    }
+
+    /// Seal all the EBBs that aren't sealed yet.
+    pub fn seal_all_ebb_header_blocks(&mut self, func: &mut Function) {
+        let ebbs: Vec<Ebb> = self.blocks
+            .keys()
+            .filter_map(|block| match self.blocks[block] {
+                            BlockData::EbbHeader(ref data) if !data.sealed => Some(data.ebb),
+                            _ => None,
+                        })
+            .collect();
+        for ebb in ebbs {
+            self.seal_ebb_header_block(func, ebb);
+        }
+    }
+
+    /// Look up the value of `var` in all the predecessors of the sealed header `block`, where
+    /// `arg` is the EBB argument standing for the variable.
+    ///
+    /// If the predecessors all agree on a single value, `arg` is removed and turned into an alias
+    /// of that value. Otherwise the values are appended to the predecessor branches. Returns the
+    /// value to use for the variable.
+    fn resolve_ebb_arg(&mut self,
+                       func: &mut Function,
+                       var: Variable,
+                       ty: Type,
+                       block: Block,
+                       arg: Value)
+                       -> Value {
+        let (ebb, predecessors) = match self.blocks[block] {
+            BlockData::EbbHeader(ref data) => (data.ebb, data.predecessors.clone()),
+            BlockData::EbbBody { .. } => unreachable!(),
+        };
+
+        let mut values = Vec::with_capacity(predecessors.len());
+        let mut unique = None;
+        let mut trivial = true;
+        for &(pred, _) in &predecessors {
+            let val = self.use_var(func, var, ty, pred);
+            values.push(val);
+            if val == arg {
+                continue;
+            }
+            match unique {
+                None => unique = Some(val),
+                Some(u) if u == val => {}
+                Some(_) => trivial = false,
+            }
+        }
+
+        if trivial {
+            // The only value flowing in besides `arg` itself. If there is none, the EBB is only
+            // reachable from itself, and the variable is never defined.
+            let replacement = match unique {
+                Some(val) => val,
+                None => emit_zero(func, ebb, ty),
+            };
+            func.dfg.remove_ebb_arg(arg);
+            func.dfg.change_to_alias(arg, replacement);
+            replacement
+        } else {
+            // The predecessor list may change as jump tables are split, but the order of the
+            // existing entries is preserved.
+            for (idx, val) in values.into_iter().enumerate() {
+                let (pred, inst) = match self.blocks[block] {
+                    BlockData::EbbHeader(ref data) => data.predecessors[idx],
+                    BlockData::EbbBody { .. } => unreachable!(),
+                };
+                self.append_branch_arg(func, block, idx, pred, inst, val);
+            }
+            arg
+        }
+    }
+
+    /// Append `val` to the arguments passed to the EBB of `dest_block` by the branch `inst`.
+    ///
+    /// A jump table can't pass arguments, so the edge is split with a new EBB that jumps to the
+    /// destination with the arguments. The new jump replaces the predecessor number `idx` of the
+    /// `dest_block` header.
+    fn append_branch_arg(&mut self,
+                         func: &mut Function,
+                         dest_block: Block,
+                         idx: usize,
+                         pred: Block,
+                         inst: Inst,
+                         val: Value) {
+        let jt = match func.dfg.analyze_branch(inst) {
+            BranchInfo::SingleDest(..) => {
+                func.dfg.append_inst_arg(inst, val);
+                return;
+            }
+            BranchInfo::Table(jt) => jt,
+            BranchInfo::NotABranch => panic!("{} is not a branch", inst),
+        };
+
+        // Split the jump table edge.
+        let dest_ebb = self.header_data_mut(dest_block).ebb;
+        let middle_ebb = func.dfg.make_ebb();
+        func.layout.append_ebb(middle_ebb);
+        let middle_block = self.declare_ebb_header_block(middle_ebb);
+        {
+            let data = self.header_data_mut(middle_block);
+            data.predecessors.push((pred, inst));
+            data.sealed = true;
+        }
+        retarget_jump_table(&mut func.jump_tables[jt], dest_ebb, middle_ebb);
+
+        let mut args = VariableArgs::new();
+        args.push(val);
+        let jump = {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_bottom(middle_ebb);
+            func.dfg.ins(&mut pos).jump(dest_ebb, args)
+        };
+        self.header_data_mut(dest_block).predecessors[idx] = (middle_block, jump);
+    }
+
+    fn header_data_mut(&mut self, block: Block) -> &mut EbbHeaderBlockData<Variable> {
+        match self.blocks[block] {
+            BlockData::EbbHeader(ref mut data) => data,
+            BlockData::EbbBody { .. } => panic!("Block {:?} is not an EBB header", block),
+        }
+    }
+}
+
+/// Redirect the entries of `table` that point to `from` to `to` instead.
+fn retarget_jump_table(table: &mut JumpTableData, from: Ebb, to: Ebb) {
+    for entry in table.as_mut_slice() {
+        if entry.expand() == Some(from) {
+            *entry = to.into();
+        }
+    }
+}
+
+/// Insert a zero value of type `ty` at the top of `ebb` and return it.
+fn emit_zero(func: &mut Function, ebb: Ebb, ty: Type) -> Value {
+    let mut pos = Cursor::new(&mut func.layout);
+    pos.goto_top(ebb);
+    pos.next_inst();
+    if ty.lane_count() > 1 {
+        let lane = make_zero(func.dfg.ins(&mut pos), ty.lane_type());
+        func.dfg.ins(&mut pos).splat(ty, lane)
+    } else {
+        make_zero(func.dfg.ins(&mut pos), ty)
+    }
+}
+
+/// Create a zero scalar of type `ty` with `ins`.
+fn make_zero<'f, B: InstBuilder<'f>>(ins: B, ty: Type) -> Value {
+    if ty.is_int() {
+        ins.iconst(ty, 0)
+    } else if ty.is_bool() {
+        ins.bconst(ty, false)
+    } else if ty.is_ref() {
+        ins.null(ty)
+    } else if ty == F32 {
+        ins.f32const(Ieee32::from_bits(0))
+    } else if ty == F64 {
+        ins.f64const(Ieee64::from_bits(0))
+    } else {
+        panic!("Cannot create a zero value of type {}", ty)
+    }
+}
@@ -0,0 +1,72 @@
+//! Module backends.
+//!
+//! A `Backend` receives the compiled functions and data objects of a `Module`, and decides where
+//! their bytes go. The module calls the backend in two phases:
+//!
+//! 1. When a function or data object is defined, the backend gets its bytes. It can allocate
+//!    memory or a section offset for it at this point.
+//! 2. When the module is finalized, the backend gets the relocations of each definition with the
+//!    targets resolved to the module's declarations. All the definitions exist at this point, so a
+//!    JIT backend can patch in their addresses, and an object file backend can emit symbol
+//!    relocations. Once all the pending definitions are relocated, the backend is asked to
+//!    `publish()` them, which is when a JIT backend makes its code executable.
+
+use cretonne::binemit::TrapTable;
+use cretonne::isa::TargetIsa;
+use data::DataDescription;
+use module::{FuncId, DataId, FunctionDeclaration, DataDeclaration, ModuleDeclarations,
+             Relocation, ModuleResult};
+
+/// A place to put the compiled functions and data objects of a `Module`.
+pub trait Backend {
+    /// The result of the whole module, like the bytes of an object file.
+    type Product;
+
+    /// Get the target ISA the module's functions are compiled for.
+    fn isa(&self) -> &TargetIsa;
+
+    /// Define the function `id` with the machine code in `code`.
+    ///
+    /// The relocations in `code` have not been applied yet. The trap sites of the function are in
+    /// `traps`.
+    fn define_function(&mut self,
+                       id: FuncId,
+                       decl: &FunctionDeclaration,
+                       code: Vec<u8>,
+                       traps: TrapTable)
+                       -> ModuleResult<()>;
+
+    /// Define the data object `id` with the initial contents in `data`.
+    ///
+    /// The relocations in `data` have not been applied yet.
+    fn define_data(&mut self,
+                   id: DataId,
+                   decl: &DataDeclaration,
+                   data: &DataDescription)
+                   -> ModuleResult<()>;
+
+    /// Apply the relocations `relocs` to the function `id`.
+    ///
+    /// Every function and data object referenced by `relocs` with a definable linkage has been
+    /// defined in the backend.
+    fn finalize_function(&mut self,
+                         id: FuncId,
+                         relocs: &[Relocation],
+                         decls: &ModuleDeclarations)
+                         -> ModuleResult<()>;
+
+    /// Apply the relocations `relocs` to the data object `id`.
+    fn finalize_data(&mut self,
+                     id: DataId,
+                     relocs: &[Relocation],
+                     decls: &ModuleDeclarations)
+                     -> ModuleResult<()>;
+
+    /// Make the definitions finalized since the last call available for use.
+    ///
+    /// This is called at the end of `Module::finalize()`. The default implementation does nothing.
+    fn publish(&mut self) {}
+
+    /// Consume the backend and produce the result of the finalized module.
+    fn finish(self, decls: &ModuleDeclarations) -> Self::Product;
+}
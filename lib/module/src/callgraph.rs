@@ -0,0 +1,209 @@
+//! A call graph for a collection of functions.
+//!
+//! Cretonne compiles one function at a time, but a module compiling a collection of functions can
+//! use the call graph to pick a compilation order and to drive interprocedural analyses like the
+//! inliner in the `inline` module.
+//!
+//! Functions are identified by their index in the slice passed to `CallGraph::with_functions`.
+//! There is an edge from a caller to a callee when the caller declares an external function
+//! reference with the same name as the callee. Declared references to functions that are not part
+//! of the collection are ignored.
+//!
+//! The strongly connected components of the call graph are available in bottom-up order: Every
+//! function appears after all the functions it calls, except for the functions in its own
+//! component which call each other recursively.
+
+use cretonne::ir::{Function, ExternalName};
+use std::cmp;
+use std::collections::HashMap;
+
+/// Index of a function in the collection the call graph was built from.
+pub type FuncIndex = usize;
+
+/// The call graph of a collection of functions.
+pub struct CallGraph {
+    /// The callees of each function, sorted and without duplicates.
+    callees: Vec<Vec<FuncIndex>>,
+}
+
+impl CallGraph {
+    /// Compute the call graph of `funcs`.
+    pub fn with_functions(funcs: &[Function]) -> CallGraph {
+        let mut by_name: HashMap<&ExternalName, FuncIndex> = HashMap::new();
+        for (idx, func) in funcs.iter().enumerate() {
+            by_name.entry(&func.name).or_insert(idx);
+        }
+
+        let callees = funcs.iter()
+            .map(|func| {
+                let mut callees: Vec<FuncIndex> = func.dfg
+                    .ext_funcs
+                    .keys()
+                    .filter_map(|fref| by_name.get(&func.dfg.ext_funcs[fref].name).cloned())
+                    .collect();
+                callees.sort();
+                callees.dedup();
+                callees
+            })
+            .collect();
+
+        CallGraph { callees: callees }
+    }
+
+    /// Get the number of functions in the call graph.
+    pub fn len(&self) -> usize {
+        self.callees.len()
+    }
+
+    /// Is the call graph empty?
+    pub fn is_empty(&self) -> bool {
+        self.callees.is_empty()
+    }
+
+    /// Get the functions that `func` may call.
+    pub fn callees(&self, func: FuncIndex) -> &[FuncIndex] {
+        &self.callees[func]
+    }
+
+    /// Compute the strongly connected components of the call graph in bottom-up order.
+    ///
+    /// Each component is a list of functions that call each other, directly or indirectly. A
+    /// component consisting of a single function that doesn't call itself is not recursive.
+    /// Callees always appear in the same component as their caller or in an earlier one.
+    pub fn bottom_up_sccs(&self) -> Vec<Vec<FuncIndex>> {
+        Tarjan::new(self).run()
+    }
+}
+
+/// State for Tarjan's strongly connected components algorithm.
+///
+/// Tarjan's algorithm produces the components in reverse topological order, which is exactly the
+/// bottom-up order we want. The depth-first search uses an explicit stack so deep call chains
+/// don't overflow the native stack.
+struct Tarjan<'a> {
+    graph: &'a CallGraph,
+    /// DFS visitation number of each function, or `None` if not yet visited.
+    index: Vec<Option<usize>>,
+    /// Lowest visitation number reachable from each function.
+    lowlink: Vec<usize>,
+    /// Is the function currently on `stack`?
+    on_stack: Vec<bool>,
+    /// Functions visited but not yet assigned to a component.
+    stack: Vec<FuncIndex>,
+    next_index: usize,
+    sccs: Vec<Vec<FuncIndex>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn new(graph: &'a CallGraph) -> Tarjan<'a> {
+        let n = graph.len();
+        Tarjan {
+            graph: graph,
+            index: vec![None; n],
+            lowlink: vec![0; n],
+            on_stack: vec![false; n],
+            stack: Vec::new(),
+            next_index: 0,
+            sccs: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Vec<Vec<FuncIndex>> {
+        for func in 0..self.graph.len() {
+            if self.index[func].is_none() {
+                self.search(func);
+            }
+        }
+        self.sccs
+    }
+
+    fn visit(&mut self, func: FuncIndex) {
+        self.index[func] = Some(self.next_index);
+        self.lowlink[func] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(func);
+        self.on_stack[func] = true;
+    }
+
+    /// Depth-first search from `root`.
+    fn search(&mut self, root: FuncIndex) {
+        // Work list of (function, next callee number to visit).
+        let mut work = vec![(root, 0)];
+        self.visit(root);
+
+        while let Some(&mut (func, ref mut next)) = work.last_mut() {
+            if let Some(&callee) = self.graph.callees(func).get(*next) {
+                *next += 1;
+                match self.index[callee] {
+                    None => {
+                        self.visit(callee);
+                        work.push((callee, 0));
+                    }
+                    Some(idx) => {
+                        if self.on_stack[callee] {
+                            self.lowlink[func] = cmp::min(self.lowlink[func], idx);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            // All callees of `func` have been visited.
+            work.pop();
+            if let Some(&(caller, _)) = work.last() {
+                self.lowlink[caller] = cmp::min(self.lowlink[caller], self.lowlink[func]);
+            }
+            if Some(self.lowlink[func]) == self.index[func] {
+                let mut scc = Vec::new();
+                loop {
+                    let f = self.stack.pop().expect("Function missing from Tarjan stack");
+                    self.on_stack[f] = false;
+                    scc.push(f);
+                    if f == func {
+                        break;
+                    }
+                }
+                scc.reverse();
+                self.sccs.push(scc);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cretonne::ir::{Function, ExternalName, ExtFuncData, Signature};
+    use super::CallGraph;
+
+    fn func(name: &str, callees: &[&str]) -> Function {
+        let mut func = Function::with_name_signature(ExternalName::testcase(name),
+                                                     Signature::new());
+        let sig = func.dfg.signatures.push(Signature::new());
+        for &callee in callees {
+            func.dfg.ext_funcs.push(ExtFuncData::new(ExternalName::testcase(callee), sig));
+        }
+        func
+    }
+
+    #[test]
+    fn empty() {
+        let cg = CallGraph::with_functions(&[]);
+        assert!(cg.is_empty());
+        assert!(cg.bottom_up_sccs().is_empty());
+    }
+
+    #[test]
+    fn bottom_up() {
+        let funcs = [func("main", &["a", "b", "printf"]),
+                     func("a", &["b", "c"]),
+                     func("b", &["c", "b"]),
+                     func("c", &["d"]),
+                     func("d", &["c"])];
+        let cg = CallGraph::with_functions(&funcs);
+        assert_eq!(cg.callees(0), &[1, 2]);
+        assert_eq!(cg.callees(2), &[2, 3]);
+
+        // `c` and `d` are mutually recursive, `b` calls itself.
+        assert_eq!(cg.bottom_up_sccs(), vec![vec![3, 4], vec![2], vec![1], vec![0]]);
+    }
+}
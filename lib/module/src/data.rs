@@ -0,0 +1,71 @@
+//! Data object descriptions.
+
+use cretonne::binemit::{CodeOffset, Addend};
+use module::{FuncId, DataId};
+
+/// The initial contents of a data object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Init {
+    /// The data object is `size` bytes of zeros.
+    Zeros {
+        /// Size of the data object in bytes.
+        size: usize,
+    },
+    /// The data object is initialized with `contents`.
+    Bytes {
+        /// The initial bytes of the data object.
+        contents: Box<[u8]>,
+    },
+}
+
+/// The contents of a data object and the addresses of functions and data objects stored in it.
+///
+/// The addresses are written with the ISA's absolute pointer-sized relocation when the module is
+/// finalized. The bytes they overwrite should be zeros.
+#[derive(Clone, Debug)]
+pub struct DataDescription {
+    /// The initial contents.
+    pub init: Init,
+    /// Offsets where the addresses of functions are stored.
+    pub function_relocs: Vec<(CodeOffset, FuncId)>,
+    /// Offsets where the addresses of data objects plus an addend are stored.
+    pub data_relocs: Vec<(CodeOffset, DataId, Addend)>,
+}
+
+impl DataDescription {
+    /// Create a description of a data object that is `size` bytes of zeros.
+    pub fn zeros(size: usize) -> DataDescription {
+        DataDescription::with_init(Init::Zeros { size: size })
+    }
+
+    /// Create a description of a data object initialized with `contents`.
+    pub fn bytes(contents: Box<[u8]>) -> DataDescription {
+        DataDescription::with_init(Init::Bytes { contents: contents })
+    }
+
+    fn with_init(init: Init) -> DataDescription {
+        DataDescription {
+            init: init,
+            function_relocs: Vec::new(),
+            data_relocs: Vec::new(),
+        }
+    }
+
+    /// Get the size of the data object in bytes.
+    pub fn size(&self) -> usize {
+        match self.init {
+            Init::Zeros { size } => size,
+            Init::Bytes { ref contents } => contents.len(),
+        }
+    }
+
+    /// Store the address of the function `func` at `offset`.
+    pub fn write_function_addr(&mut self, offset: CodeOffset, func: FuncId) {
+        self.function_relocs.push((offset, func));
+    }
+
+    /// Store the address of the data object `data` plus `addend` at `offset`.
+    pub fn write_data_addr(&mut self, offset: CodeOffset, data: DataId, addend: Addend) {
+        self.data_relocs.push((offset, data, addend));
+    }
+}
@@ -0,0 +1,173 @@
+//! Inlining small functions in a collection of functions.
+//!
+//! The `inline_call()` function in the cretonne library splices one callee into one call site.
+//! This module drives it for a whole collection of functions, using the call graph to inline
+//! callees into their callers bottom-up.
+
+use callgraph::{CallGraph, FuncIndex};
+use cretonne::{inline_call, can_inline, function_size};
+use cretonne::ir::{Function, ExternalName, Inst, Opcode};
+use cretonne::ir::instructions::CallInfo;
+use std::collections::HashMap;
+
+/// Inline the calls to small functions in `funcs`.
+///
+/// The functions call each other by name, as described in the `callgraph` module. Functions with
+/// at most `max_size` instructions are inlined into their callers, except for calls between
+/// functions in the same strongly connected component of the call graph. Callees are processed
+/// before their callers, so the size of a callee includes the calls already inlined into it.
+///
+/// Returns the number of inlined call sites.
+pub fn inline_small_functions(funcs: &mut [Function], max_size: usize) -> usize {
+    let mut by_name: HashMap<ExternalName, FuncIndex> = HashMap::new();
+    for (idx, func) in funcs.iter().enumerate() {
+        by_name.entry(func.name.clone()).or_insert(idx);
+    }
+
+    let mut inlined = 0;
+    let sccs = CallGraph::with_functions(funcs).bottom_up_sccs();
+    let mut scc_of = vec![0; funcs.len()];
+    for (num, scc) in sccs.iter().enumerate() {
+        for &func in scc {
+            scc_of[func] = num;
+        }
+    }
+
+    for scc in &sccs {
+        for &caller in scc {
+            // Inlining a call appends new instructions to the layout, so restart the scan after
+            // each one. The inlined code is scanned too, but it can only call functions in earlier
+            // components which don't call back here.
+            loop {
+                let site = find_call_site(funcs, caller, &by_name, |callee| {
+                    scc_of[callee] != scc_of[caller] && can_inline(&funcs[callee]) &&
+                    function_size(&funcs[callee]) <= max_size
+                });
+                let (call, callee) = match site {
+                    Some(site) => site,
+                    None => break,
+                };
+                let callee_func = funcs[callee].clone();
+                inline_call(&mut funcs[caller], call, &callee_func);
+                inlined += 1;
+            }
+        }
+    }
+
+    inlined
+}
+
+/// Find a direct call in `funcs[caller]` to a function in `funcs` that satisfies `pred`.
+fn find_call_site<P>(funcs: &[Function],
+                     caller: FuncIndex,
+                     by_name: &HashMap<ExternalName, FuncIndex>,
+                     pred: P)
+                     -> Option<(Inst, FuncIndex)>
+    where P: Fn(FuncIndex) -> bool
+{
+    let func = &funcs[caller];
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if func.dfg[inst].opcode() != Opcode::Call {
+                continue;
+            }
+            if let CallInfo::Direct(fref, _) = func.dfg[inst].analyze_call(&func.dfg.value_lists) {
+                if let Some(&callee) = by_name.get(&func.dfg.ext_funcs[fref].name) {
+                    if pred(callee) {
+                        return Some((inst, callee));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use cretonne::function_size;
+    use cretonne::ir::{Function, ExternalName, Signature, ArgumentType, ExtFuncData, Cursor,
+                       InstBuilder, VariableArgs};
+    use cretonne::ir::types::I32;
+    use cretonne::verifier::verify_function;
+    use super::inline_small_functions;
+
+    fn sig_i32() -> Signature {
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(I32));
+        sig.return_types.push(ArgumentType::new(I32));
+        sig
+    }
+
+    // function callee(i32) -> i32 {
+    // ebb0(v0: i32):
+    //     brz v0, ebb1
+    //     v1 = iadd_imm v0, 1
+    //     return v1
+    // ebb1:
+    //     v2 = iconst.i32 0
+    //     return v2
+    // }
+    fn callee() -> Function {
+        let mut func = Function::with_name_signature(ExternalName::testcase("callee"), sig_i32());
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).brz(v0, ebb1, VariableArgs::new());
+            let v1 = dfg.ins(pos).iadd_imm(v0, 1);
+            let mut rets = VariableArgs::new();
+            rets.push(v1);
+            dfg.ins(pos).return_(rets);
+
+            pos.insert_ebb(ebb1);
+            let v2 = dfg.ins(pos).iconst(I32, 0);
+            let mut rets = VariableArgs::new();
+            rets.push(v2);
+            dfg.ins(pos).return_(rets);
+        }
+        func
+    }
+
+    // function caller(i32) -> i32 {
+    //     fn0 = function callee(i32) -> i32
+    // ebb0(v0: i32):
+    //     v1 = call fn0(v0)
+    //     v2 = imul v1, v1
+    //     return v2
+    // }
+    fn caller() -> Function {
+        let mut func = Function::with_name_signature(ExternalName::testcase("caller"), sig_i32());
+        let sig = func.dfg.signatures.push(sig_i32());
+        let fref = func.dfg.ext_funcs.push(ExtFuncData::new(ExternalName::testcase("callee"), sig));
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let mut args = VariableArgs::new();
+            args.push(v0);
+            let call = dfg.ins(pos).call(fref, args);
+            let v1 = dfg.first_result(call);
+            let v2 = dfg.ins(pos).imul(v1, v1);
+            let mut rets = VariableArgs::new();
+            rets.push(v2);
+            dfg.ins(pos).return_(rets);
+        }
+        func
+    }
+
+    #[test]
+    fn small_functions() {
+        let mut funcs = [caller(), callee()];
+        assert_eq!(function_size(&funcs[1]), 5);
+        assert_eq!(inline_small_functions(&mut funcs, 4), 0);
+        assert_eq!(inline_small_functions(&mut funcs, 5), 1);
+        verify_function(&funcs[0]).unwrap();
+        assert_eq!(function_size(&funcs[0]), 8);
+    }
+}
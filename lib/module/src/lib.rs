@@ -0,0 +1,31 @@
+//! Cretonne module library.
+//!
+//! Cretonne compiles one function at a time. The cton_module library adds a `Module` layer on top
+//! of that for compiling a collection of functions and data objects that reference each other.
+//! The module keeps track of the declared names and their linkage, compiles the functions, and
+//! resolves the relocations between them when the module is finalized.
+//!
+//! The `CallGraph` of a collection of functions orders them bottom-up, which is used by the
+//! `inline_small_functions()` driver.
+//!
+//! The machine code and data are handed to a `Backend` which decides where the bytes go: a JIT
+//! backend copies them into executable memory, and an object file backend writes them into
+//! sections with relocations.
+
+#![deny(missing_docs)]
+
+extern crate cretonne;
+
+pub use backend::Backend;
+pub use callgraph::{CallGraph, FuncIndex};
+pub use data::{DataDescription, Init};
+pub use inline::inline_small_functions;
+pub use module::{Module, ModuleDeclarations, FuncId, DataId, FuncOrDataId, Linkage,
+                 FunctionDeclaration, DataDeclaration, Relocation, RelocTarget, ModuleError,
+                 ModuleResult, FUNCTION_NAMESPACE, DATA_NAMESPACE};
+
+mod backend;
+mod callgraph;
+mod data;
+mod inline;
+mod module;
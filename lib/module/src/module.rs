@@ -0,0 +1,862 @@
+//! Declaring, defining, and linking functions and data objects.
+//!
+//! Every function and data object in a module is declared with a name and a `Linkage` before it
+//! can be referenced. Declaring the same name again returns the same identifier, with the linkages
+//! merged. Functions refer to each other through `ExternalName::User` names in the
+//! `FUNCTION_NAMESPACE` and `DATA_NAMESPACE` namespaces, which `declare_func_in_func()` and
+//! `declare_data_in_func()` add to a function's preamble. Names given as strings are looked up
+//! among the declarations, and the ones that aren't found are left for the backend to resolve.
+//! The runtime library functions called by the legalizer are also left to the backend, under
+//! their conventional symbol names.
+//!
+//! A definition is compiled and passed to the backend right away, while its relocations are kept
+//! until `finalize()`. That way the functions can be defined in any order, even when they call
+//! each other recursively.
+
+use backend::Backend;
+use cretonne::{Context, CtonError};
+use cretonne::binemit::{CodeOffset, Reloc, Addend, RelocSink, TrapTable, NullStackmapSink};
+use cretonne::entity_map::{EntityMap, EntityRef, PrimaryEntityData, Keys};
+use cretonne::ir::{ExternalName, Function, FuncRef, GlobalVar, ExtFuncData, GlobalVarData,
+                   Signature, JumpTable};
+use cretonne::isa::TargetIsa;
+use data::DataDescription;
+use std::collections::HashMap;
+use std::fmt;
+use std::u32;
+
+/// The `ExternalName::User` namespace of the functions declared in a module.
+pub const FUNCTION_NAMESPACE: u32 = 0;
+
+/// The `ExternalName::User` namespace of the data objects declared in a module.
+pub const DATA_NAMESPACE: u32 = 1;
+
+/// A function declared in a module.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct FuncId(u32);
+
+impl EntityRef for FuncId {
+    fn new(index: usize) -> FuncId {
+        assert!(index < (u32::MAX as usize));
+        FuncId(index as u32)
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A data object declared in a module.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct DataId(u32);
+
+impl EntityRef for DataId {
+    fn new(index: usize) -> DataId {
+        assert!(index < (u32::MAX as usize));
+        DataId(index as u32)
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A declared name, which is either a function or a data object.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FuncOrDataId {
+    /// A function.
+    Func(FuncId),
+    /// A data object.
+    Data(DataId),
+}
+
+/// Linkage of a declared function or data object.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Linkage {
+    /// Defined outside the module.
+    Import,
+    /// Defined inside the module, but not visible outside it.
+    Local,
+    /// Defined inside the module, and visible outside it.
+    Export,
+}
+
+impl Linkage {
+    /// Combine the linkages of two declarations of the same name.
+    ///
+    /// An import is satisfied by a local definition, and a name declared as exported anywhere is
+    /// exported.
+    pub fn merge(a: Linkage, b: Linkage) -> Linkage {
+        match (a, b) {
+            (Linkage::Export, _) |
+            (_, Linkage::Export) => Linkage::Export,
+            (Linkage::Local, _) |
+            (_, Linkage::Local) => Linkage::Local,
+            (Linkage::Import, Linkage::Import) => Linkage::Import,
+        }
+    }
+
+    /// Can a function or data object with this linkage be defined in the module?
+    pub fn is_definable(self) -> bool {
+        self != Linkage::Import
+    }
+}
+
+/// The declaration of a function.
+#[derive(Clone, Debug)]
+pub struct FunctionDeclaration {
+    /// The symbol name.
+    pub name: String,
+    /// The linkage, merged from all the declarations.
+    pub linkage: Linkage,
+    /// The call signature.
+    pub signature: Signature,
+}
+
+impl PrimaryEntityData for FunctionDeclaration {}
+
+/// The declaration of a data object.
+#[derive(Clone, Debug)]
+pub struct DataDeclaration {
+    /// The symbol name.
+    pub name: String,
+    /// The linkage, merged from all the declarations.
+    pub linkage: Linkage,
+    /// Can the data object be modified at runtime?
+    pub writable: bool,
+}
+
+impl PrimaryEntityData for DataDeclaration {}
+
+/// The target of a relocation.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RelocTarget {
+    /// A function declared in the module.
+    Function(FuncId),
+    /// A data object declared in the module.
+    Data(DataId),
+    /// A symbol that isn't declared in the module.
+    Symbol(String),
+    /// An address in the code of the function being relocated, from a branch or a jump table.
+    ///
+    /// The emitted bytes already hold the final value of a PC-relative relocation, and the offset
+    /// from the start of the function for an absolute one. The addend is always 0.
+    Internal,
+}
+
+/// A relocation in a function or data object, with its target resolved.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Relocation {
+    /// Offset of the relocation from the start of the function or data object.
+    pub offset: CodeOffset,
+    /// The ISA-specific relocation kind.
+    pub reloc: Reloc,
+    /// The symbol whose address is used.
+    pub target: RelocTarget,
+    /// Addend to add to the address of the symbol.
+    pub addend: Addend,
+}
+
+/// An error from declaring, defining, or linking a module.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ModuleError {
+    /// A function references an `ExternalName::User` name that wasn't declared in the module.
+    Undeclared(String),
+
+    /// A name was declared again as a different kind of entity, or with a different signature.
+    IncompatibleDeclaration(String),
+
+    /// A function or data object was defined twice.
+    DuplicateDefinition(String),
+
+    /// A function or data object with the `Import` linkage was defined.
+    InvalidImportDefinition(String),
+
+    /// A function or data object with a definable linkage is referenced, but was never defined.
+    Undefined(String),
+
+    /// A function failed to compile.
+    Compilation(CtonError),
+
+    /// The backend failed.
+    Backend(String),
+}
+
+/// The result of a module operation.
+pub type ModuleResult<T> = Result<T, ModuleError>;
+
+impl From<CtonError> for ModuleError {
+    fn from(e: CtonError) -> ModuleError {
+        ModuleError::Compilation(e)
+    }
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ModuleError::Undeclared(ref name) => write!(f, "undeclared name: {}", name),
+            ModuleError::IncompatibleDeclaration(ref name) => {
+                write!(f, "incompatible declaration of {}", name)
+            }
+            ModuleError::DuplicateDefinition(ref name) => {
+                write!(f, "duplicate definition of {}", name)
+            }
+            ModuleError::InvalidImportDefinition(ref name) => {
+                write!(f, "imported {} can't be defined", name)
+            }
+            ModuleError::Undefined(ref name) => write!(f, "{} is referenced but not defined", name),
+            ModuleError::Compilation(ref e) => e.fmt(f),
+            ModuleError::Backend(ref msg) => write!(f, "backend error: {}", msg),
+        }
+    }
+}
+
+/// The declarations of all the functions and data objects in a module.
+pub struct ModuleDeclarations {
+    names: HashMap<String, FuncOrDataId>,
+    functions: EntityMap<FuncId, FunctionDeclaration>,
+    data_objects: EntityMap<DataId, DataDeclaration>,
+}
+
+impl ModuleDeclarations {
+    fn new() -> ModuleDeclarations {
+        ModuleDeclarations {
+            names: HashMap::new(),
+            functions: EntityMap::new(),
+            data_objects: EntityMap::new(),
+        }
+    }
+
+    /// Look up a declared name.
+    pub fn get_name(&self, name: &str) -> Option<FuncOrDataId> {
+        self.names.get(name).cloned()
+    }
+
+    /// Iterate over all the declared functions.
+    pub fn function_ids(&self) -> Keys<FuncId> {
+        self.functions.keys()
+    }
+
+    /// Iterate over all the declared data objects.
+    pub fn data_ids(&self) -> Keys<DataId> {
+        self.data_objects.keys()
+    }
+
+    /// Get the declaration of the function `id`.
+    pub fn get_function_decl(&self, id: FuncId) -> &FunctionDeclaration {
+        &self.functions[id]
+    }
+
+    /// Get the declaration of the data object `id`.
+    pub fn get_data_decl(&self, id: DataId) -> &DataDeclaration {
+        &self.data_objects[id]
+    }
+
+    /// Resolve the name of an external entity referenced by a function.
+    pub fn resolve(&self, name: &ExternalName) -> ModuleResult<RelocTarget> {
+        if let Some(libcall) = name.as_libcall() {
+            return Ok(RelocTarget::Symbol(libcall.symbol().to_string()));
+        }
+        match *name {
+            ExternalName::User { namespace, index } => {
+                let index = index as usize;
+                if namespace == FUNCTION_NAMESPACE && index < self.functions.len() {
+                    Ok(RelocTarget::Function(FuncId::new(index)))
+                } else if namespace == DATA_NAMESPACE && index < self.data_objects.len() {
+                    Ok(RelocTarget::Data(DataId::new(index)))
+                } else {
+                    Err(ModuleError::Undeclared(name.to_string()))
+                }
+            }
+            ExternalName::TestCase(ref name) => {
+                Ok(match self.get_name(name) {
+                       Some(FuncOrDataId::Func(id)) => RelocTarget::Function(id),
+                       Some(FuncOrDataId::Data(id)) => RelocTarget::Data(id),
+                       None => RelocTarget::Symbol(name.clone()),
+                   })
+            }
+        }
+    }
+}
+
+/// The definition state of a function or data object.
+#[derive(Clone, Debug)]
+enum Definition {
+    /// Not defined yet.
+    Undefined,
+    /// Defined in the backend, with the relocations that haven't been applied.
+    Defined(Vec<Relocation>),
+    /// Defined and relocated.
+    Finalized,
+}
+
+impl Default for Definition {
+    fn default() -> Definition {
+        Definition::Undefined
+    }
+}
+
+impl Definition {
+    fn is_defined(&self) -> bool {
+        match *self {
+            Definition::Undefined => false,
+            _ => true,
+        }
+    }
+}
+
+/// Collect the relocations of a function as it is emitted, and resolve their targets.
+struct RelocCollector<'a> {
+    decls: &'a ModuleDeclarations,
+    relocs: Vec<Relocation>,
+    error: Option<ModuleError>,
+}
+
+impl<'a> RelocCollector<'a> {
+    fn add(&mut self, offset: CodeOffset, reloc: Reloc, target: RelocTarget, addend: Addend) {
+        self.relocs.push(Relocation {
+                             offset: offset,
+                             reloc: reloc,
+                             target: target,
+                             addend: addend,
+                         });
+    }
+}
+
+impl<'a> RelocSink for RelocCollector<'a> {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, _ebb_offset: CodeOffset) {
+        self.add(offset, reloc, RelocTarget::Internal, 0);
+    }
+
+    fn reloc_external(&mut self,
+                      offset: CodeOffset,
+                      reloc: Reloc,
+                      name: &ExternalName,
+                      addend: Addend) {
+        match self.decls.resolve(name) {
+            Ok(target) => self.add(offset, reloc, target, addend),
+            Err(e) => {
+                if self.error.is_none() {
+                    self.error = Some(e);
+                }
+            }
+        }
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, _jt: JumpTable) {
+        self.add(offset, reloc, RelocTarget::Internal, 0);
+    }
+}
+
+/// A collection of functions and data objects that are compiled and linked together.
+pub struct Module<B: Backend> {
+    decls: ModuleDeclarations,
+    functions: EntityMap<FuncId, Definition>,
+    data_objects: EntityMap<DataId, Definition>,
+    backend: B,
+}
+
+impl<B: Backend> Module<B> {
+    /// Create a new empty module that puts its definitions in `backend`.
+    pub fn new(backend: B) -> Module<B> {
+        Module {
+            decls: ModuleDeclarations::new(),
+            functions: EntityMap::new(),
+            data_objects: EntityMap::new(),
+            backend: backend,
+        }
+    }
+
+    /// Get the target ISA of the backend.
+    pub fn isa(&self) -> &TargetIsa {
+        self.backend.isa()
+    }
+
+    /// Get the declarations in the module.
+    pub fn declarations(&self) -> &ModuleDeclarations {
+        &self.decls
+    }
+
+    /// Get the backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Get the backend for modification.
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Declare a function named `name`.
+    ///
+    /// If the function was declared before, the signatures must match, and the linkages are
+    /// merged.
+    pub fn declare_function(&mut self,
+                            name: &str,
+                            linkage: Linkage,
+                            signature: &Signature)
+                            -> ModuleResult<FuncId> {
+        match self.decls.get_name(name) {
+            Some(FuncOrDataId::Func(id)) => {
+                let decl = &mut self.decls.functions[id];
+                if decl.signature != *signature {
+                    return Err(ModuleError::IncompatibleDeclaration(name.to_string()));
+                }
+                decl.linkage = Linkage::merge(decl.linkage, linkage);
+                Ok(id)
+            }
+            Some(FuncOrDataId::Data(..)) => {
+                Err(ModuleError::IncompatibleDeclaration(name.to_string()))
+            }
+            None => {
+                let id = self.decls.functions.push(FunctionDeclaration {
+                                                       name: name.to_string(),
+                                                       linkage: linkage,
+                                                       signature: signature.clone(),
+                                                   });
+                self.functions.ensure(id);
+                self.decls.names.insert(name.to_string(), FuncOrDataId::Func(id));
+                Ok(id)
+            }
+        }
+    }
+
+    /// Declare a data object named `name`.
+    ///
+    /// If the data object was declared before, the linkages are merged, and it is writable if any
+    /// of the declarations are.
+    pub fn declare_data(&mut self,
+                        name: &str,
+                        linkage: Linkage,
+                        writable: bool)
+                        -> ModuleResult<DataId> {
+        match self.decls.get_name(name) {
+            Some(FuncOrDataId::Data(id)) => {
+                let decl = &mut self.decls.data_objects[id];
+                decl.linkage = Linkage::merge(decl.linkage, linkage);
+                decl.writable = decl.writable || writable;
+                Ok(id)
+            }
+            Some(FuncOrDataId::Func(..)) => {
+                Err(ModuleError::IncompatibleDeclaration(name.to_string()))
+            }
+            None => {
+                let id = self.decls.data_objects.push(DataDeclaration {
+                                                          name: name.to_string(),
+                                                          linkage: linkage,
+                                                          writable: writable,
+                                                      });
+                self.data_objects.ensure(id);
+                self.decls.names.insert(name.to_string(), FuncOrDataId::Data(id));
+                Ok(id)
+            }
+        }
+    }
+
+    /// Add a reference to the function `id` to the preamble of `func`.
+    ///
+    /// The reference is colocated unless the function is imported.
+    pub fn declare_func_in_func(&self, id: FuncId, func: &mut Function) -> FuncRef {
+        let decl = &self.decls.functions[id];
+        let signature = func.dfg.signatures.push(decl.signature.clone());
+        let name = ExternalName::user(FUNCTION_NAMESPACE, id.index() as u32);
+        let mut data = ExtFuncData::new(name, signature);
+        data.colocated = decl.linkage.is_definable();
+        func.dfg.ext_funcs.push(data)
+    }
+
+    /// Add a reference to the data object `id` to the preamble of `func`.
+    ///
+    /// The reference is colocated unless the data object is imported.
+    pub fn declare_data_in_func(&self, id: DataId, func: &mut Function) -> GlobalVar {
+        let decl = &self.decls.data_objects[id];
+        let mut data = GlobalVarData::new(ExternalName::user(DATA_NAMESPACE, id.index() as u32));
+        data.colocated = decl.linkage.is_definable();
+        func.dfg.global_vars.push(data)
+    }
+
+    /// Compile the function in `ctx` and define it as the function `id`.
+    ///
+    /// Return the size of the function's code in bytes.
+    pub fn define_function(&mut self, id: FuncId, ctx: &mut Context) -> ModuleResult<CodeOffset> {
+        self.check_definable(&self.decls.functions[id].name,
+                             self.decls.functions[id].linkage,
+                             &self.functions[id])?;
+
+        let size = ctx.compile(self.backend.isa())?;
+        let mut code = vec![0; size as usize];
+        let mut traps = TrapTable::new();
+        let relocs = {
+            let mut relocs = RelocCollector {
+                decls: &self.decls,
+                relocs: Vec::new(),
+                error: None,
+            };
+            // The buffer holds the number of bytes returned by `compile()`.
+            unsafe {
+                ctx.emit_to_memory(code.as_mut_ptr(),
+                                   &mut relocs,
+                                   &mut traps,
+                                   &mut NullStackmapSink {},
+                                   self.backend.isa());
+            }
+            if let Some(e) = relocs.error {
+                return Err(e);
+            }
+            relocs.relocs
+        };
+
+        self.backend.define_function(id, &self.decls.functions[id], code, traps)?;
+        self.functions[id] = Definition::Defined(relocs);
+        Ok(size)
+    }
+
+    /// Define the data object `id` as described by `data`.
+    pub fn define_data(&mut self, id: DataId, data: &DataDescription) -> ModuleResult<()> {
+        self.check_definable(&self.decls.data_objects[id].name,
+                             self.decls.data_objects[id].linkage,
+                             &self.data_objects[id])?;
+
+        let mut relocs = Vec::new();
+        if !data.function_relocs.is_empty() || !data.data_relocs.is_empty() {
+            // The jump table relocation is the absolute pointer-sized one.
+            let reloc = match self.backend.isa().jump_table_reloc() {
+                Some(reloc) => reloc,
+                None => {
+                    return Err(ModuleError::Backend(format!("{} doesn't have absolute \
+                                                             relocations",
+                                                            self.backend.isa().name())))
+                }
+            };
+            for &(offset, func) in &data.function_relocs {
+                relocs.push(Relocation {
+                                offset: offset,
+                                reloc: reloc,
+                                target: RelocTarget::Function(func),
+                                addend: 0,
+                            });
+            }
+            for &(offset, data, addend) in &data.data_relocs {
+                relocs.push(Relocation {
+                                offset: offset,
+                                reloc: reloc,
+                                target: RelocTarget::Data(data),
+                                addend: addend,
+                            });
+            }
+        }
+
+        self.backend.define_data(id, &self.decls.data_objects[id], data)?;
+        self.data_objects[id] = Definition::Defined(relocs);
+        Ok(())
+    }
+
+    fn check_definable(&self, name: &str, linkage: Linkage, def: &Definition) -> ModuleResult<()> {
+        if !linkage.is_definable() {
+            Err(ModuleError::InvalidImportDefinition(name.to_string()))
+        } else if def.is_defined() {
+            Err(ModuleError::DuplicateDefinition(name.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that the target of `reloc` is defined if it needs to be.
+    fn check_target(&self, reloc: &Relocation) -> ModuleResult<()> {
+        match reloc.target {
+            RelocTarget::Function(id) => {
+                let decl = &self.decls.functions[id];
+                if decl.linkage.is_definable() && !self.functions[id].is_defined() {
+                    return Err(ModuleError::Undefined(decl.name.clone()));
+                }
+            }
+            RelocTarget::Data(id) => {
+                let decl = &self.decls.data_objects[id];
+                if decl.linkage.is_definable() && !self.data_objects[id].is_defined() {
+                    return Err(ModuleError::Undefined(decl.name.clone()));
+                }
+            }
+            RelocTarget::Symbol(_) |
+            RelocTarget::Internal => {}
+        }
+        Ok(())
+    }
+
+    /// Apply the relocations of all the definitions that haven't been finalized yet.
+    ///
+    /// All the functions and data objects with a definable linkage that are referenced by the
+    /// definitions must have been defined. Otherwise, nothing is finalized.
+    ///
+    /// The backend publishes the finalized definitions at the end.
+    pub fn finalize(&mut self) -> ModuleResult<()> {
+        for id in self.functions.keys() {
+            if let Definition::Defined(ref relocs) = self.functions[id] {
+                for reloc in relocs {
+                    self.check_target(reloc)?;
+                }
+            }
+        }
+        for id in self.data_objects.keys() {
+            if let Definition::Defined(ref relocs) = self.data_objects[id] {
+                for reloc in relocs {
+                    self.check_target(reloc)?;
+                }
+            }
+        }
+
+        for id in self.functions.keys() {
+            if let Definition::Defined(ref relocs) = self.functions[id] {
+                self.backend.finalize_function(id, relocs, &self.decls)?;
+            } else {
+                continue;
+            }
+            self.functions[id] = Definition::Finalized;
+        }
+        for id in self.data_objects.keys() {
+            if let Definition::Defined(ref relocs) = self.data_objects[id] {
+                self.backend.finalize_data(id, relocs, &self.decls)?;
+            } else {
+                continue;
+            }
+            self.data_objects[id] = Definition::Finalized;
+        }
+        self.backend.publish();
+        Ok(())
+    }
+
+    /// Finalize the module, and consume it to produce the result of the backend.
+    pub fn finish(mut self) -> ModuleResult<B::Product> {
+        self.finalize()?;
+        Ok(self.backend.finish(&self.decls))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use backend::Backend;
+    use cretonne::Context;
+    use cretonne::binemit::TrapTable;
+    use cretonne::ir::{Function, Signature, ArgumentType, ExternalName, ExtFuncData, Cursor,
+                       InstBuilder, VariableArgs, LibCall};
+    use cretonne::ir::types;
+    use cretonne::isa::{self, TargetIsa};
+    use cretonne::settings::{self, Configurable};
+    use data::DataDescription;
+    use super::*;
+
+    /// A backend that logs the definitions and relocations it receives.
+    struct TestBackend {
+        isa: Box<TargetIsa>,
+        log: Vec<String>,
+    }
+
+    impl TestBackend {
+        fn new() -> TestBackend {
+            let mut shared_builder = settings::builder();
+            shared_builder.set_bool("is_64bit", true).unwrap();
+            TestBackend {
+                isa: isa::lookup("intel").unwrap().finish(settings::Flags::new(&shared_builder)),
+                log: Vec::new(),
+            }
+        }
+
+        fn log_relocs(&mut self, name: &str, relocs: &[Relocation], decls: &ModuleDeclarations) {
+            for reloc in relocs {
+                let target = match reloc.target {
+                    RelocTarget::Function(id) => decls.get_function_decl(id).name.clone(),
+                    RelocTarget::Data(id) => decls.get_data_decl(id).name.clone(),
+                    RelocTarget::Symbol(ref name) => name.clone(),
+                    RelocTarget::Internal => "internal".to_string(),
+                };
+                let kind = self.isa.reloc_names()[reloc.reloc.0 as usize];
+                self.log.push(format!("{}: {} {}{:+}", name, kind, target, reloc.addend));
+            }
+        }
+    }
+
+    impl Backend for TestBackend {
+        type Product = Vec<String>;
+
+        fn isa(&self) -> &TargetIsa {
+            &*self.isa
+        }
+
+        fn define_function(&mut self,
+                           _id: FuncId,
+                           decl: &FunctionDeclaration,
+                           _code: Vec<u8>,
+                           _traps: TrapTable)
+                           -> ModuleResult<()> {
+            self.log.push(format!("define {}", decl.name));
+            Ok(())
+        }
+
+        fn define_data(&mut self,
+                       _id: DataId,
+                       decl: &DataDeclaration,
+                       data: &DataDescription)
+                       -> ModuleResult<()> {
+            self.log.push(format!("define {} {} bytes", decl.name, data.size()));
+            Ok(())
+        }
+
+        fn finalize_function(&mut self,
+                             id: FuncId,
+                             relocs: &[Relocation],
+                             decls: &ModuleDeclarations)
+                             -> ModuleResult<()> {
+            let name = decls.get_function_decl(id).name.clone();
+            self.log_relocs(&name, relocs, decls);
+            Ok(())
+        }
+
+        fn finalize_data(&mut self,
+                         id: DataId,
+                         relocs: &[Relocation],
+                         decls: &ModuleDeclarations)
+                         -> ModuleResult<()> {
+            let name = decls.get_data_decl(id).name.clone();
+            self.log_relocs(&name, relocs, decls);
+            Ok(())
+        }
+
+        fn finish(self, _decls: &ModuleDeclarations) -> Vec<String> {
+            self.log
+        }
+    }
+
+    fn signature() -> Signature {
+        let mut sig = Signature::new();
+        sig.return_types.push(ArgumentType::new(types::I64));
+        sig
+    }
+
+    /// Make a function returning `iconst 0`.
+    fn constant() -> Function {
+        let mut func = Function::new();
+        func.signature = signature();
+        let ebb0 = func.dfg.make_ebb();
+        let dfg = &mut func.dfg;
+        let pos = &mut Cursor::new(&mut func.layout);
+        pos.insert_ebb(ebb0);
+        let v0 = dfg.ins(pos).iconst(types::I64, 0);
+        let mut rvals = VariableArgs::new();
+        rvals.push(v0);
+        dfg.ins(pos).return_(rvals);
+        func
+    }
+
+    /// Make a function returning the address of the function `name`.
+    fn address_of(name: ExternalName) -> Function {
+        let mut func = Function::new();
+        func.signature = signature();
+        let sig = func.dfg.signatures.push(signature());
+        let fn0 = func.dfg.ext_funcs.push(ExtFuncData::new(name, sig));
+        let ebb0 = func.dfg.make_ebb();
+        let dfg = &mut func.dfg;
+        let pos = &mut Cursor::new(&mut func.layout);
+        pos.insert_ebb(ebb0);
+        let v0 = dfg.ins(pos).func_addr(types::I64, fn0);
+        let mut rvals = VariableArgs::new();
+        rvals.push(v0);
+        dfg.ins(pos).return_(rvals);
+        func
+    }
+
+    #[test]
+    fn link() {
+        let mut module = Module::new(TestBackend::new());
+        let f = module.declare_function("f", Linkage::Export, &signature()).unwrap();
+        let g = module.declare_function("g", Linkage::Local, &signature()).unwrap();
+        let d = module.declare_data("d", Linkage::Local, false).unwrap();
+
+        // Redeclaring an import of a local function doesn't change it.
+        assert_eq!(module.declare_function("g", Linkage::Import, &signature()), Ok(g));
+        assert_eq!(module.declarations().get_function_decl(g).linkage, Linkage::Local);
+        assert_eq!(module.declarations().get_name("d"), Some(FuncOrDataId::Data(d)));
+
+        // Return the sum of the addresses of `g` and `d`.
+        let mut ctx = Context::new();
+        ctx.func.signature = signature();
+        let fn_g = module.declare_func_in_func(g, &mut ctx.func);
+        let gv_d = module.declare_data_in_func(d, &mut ctx.func);
+        assert!(ctx.func.dfg.ext_funcs[fn_g].colocated);
+        assert!(ctx.func.dfg.global_vars[gv_d].colocated);
+        {
+            let ebb0 = ctx.func.dfg.make_ebb();
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).func_addr(types::I64, fn_g);
+            let v1 = dfg.ins(pos).globalsym_addr(types::I64, gv_d);
+            let v2 = dfg.ins(pos).iadd(v0, v1);
+            let mut rvals = VariableArgs::new();
+            rvals.push(v2);
+            dfg.ins(pos).return_(rvals);
+        }
+        assert!(module.define_function(f, &mut ctx).unwrap() > 0);
+        assert_eq!(module.finalize(), Err(ModuleError::Undefined("g".to_string())));
+
+        ctx.func = constant();
+        module.define_function(g, &mut ctx).unwrap();
+        assert_eq!(module.finalize(), Err(ModuleError::Undefined("d".to_string())));
+
+        let mut data = DataDescription::zeros(16);
+        data.write_function_addr(8, f);
+        module.define_data(d, &data).unwrap();
+        assert_eq!(module.finish().unwrap(),
+                   ["define f",
+                    "define g",
+                    "define d 16 bytes",
+                    "f: PCRel4 g-4",
+                    "f: PCRel4 d-4",
+                    "d: Abs8 f+0"]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut module = Module::new(TestBackend::new());
+        let f = module.declare_function("f", Linkage::Local, &signature()).unwrap();
+        let imp = module.declare_function("imp", Linkage::Import, &signature()).unwrap();
+        let d = module.declare_data("d", Linkage::Export, true).unwrap();
+
+        assert_eq!(module.declare_function("f", Linkage::Local, &Signature::new()),
+                   Err(ModuleError::IncompatibleDeclaration("f".to_string())));
+        assert_eq!(module.declare_data("f", Linkage::Local, false),
+                   Err(ModuleError::IncompatibleDeclaration("f".to_string())));
+        assert_eq!(module.declare_function("d", Linkage::Local, &signature()),
+                   Err(ModuleError::IncompatibleDeclaration("d".to_string())));
+
+        let mut ctx = Context::new();
+        ctx.func = constant();
+        assert_eq!(module.define_function(imp, &mut ctx),
+                   Err(ModuleError::InvalidImportDefinition("imp".to_string())));
+        module.define_function(f, &mut ctx).unwrap();
+        ctx.func = constant();
+        assert_eq!(module.define_function(f, &mut ctx),
+                   Err(ModuleError::DuplicateDefinition("f".to_string())));
+        module.define_data(d, &DataDescription::bytes(Box::new([1, 2, 3]))).unwrap();
+        assert_eq!(module.define_data(d, &DataDescription::zeros(4)),
+                   Err(ModuleError::DuplicateDefinition("d".to_string())));
+
+        // A user-defined name that wasn't declared in the module.
+        ctx.func = address_of(ExternalName::user(FUNCTION_NAMESPACE, 7));
+        let g = module.declare_function("g", Linkage::Local, &signature()).unwrap();
+        assert_eq!(module.define_function(g, &mut ctx),
+                   Err(ModuleError::Undeclared("u0:7".to_string())));
+
+        // Imported functions and undeclared symbols don't need to be defined.
+        ctx.func = address_of(ExternalName::user(FUNCTION_NAMESPACE, imp.index() as u32));
+        module.define_function(g, &mut ctx).unwrap();
+        ctx.func = address_of(ExternalName::testcase("memcpy"));
+        let h = module.declare_function("h", Linkage::Local, &signature()).unwrap();
+        module.define_function(h, &mut ctx).unwrap();
+        ctx.func = address_of(ExternalName::libcall(LibCall::Memset));
+        let i = module.declare_function("i", Linkage::Local, &signature()).unwrap();
+        module.define_function(i, &mut ctx).unwrap();
+        assert_eq!(module.finish().unwrap()[5..],
+                   ["g: Abs8 imp+0".to_string(),
+                    "h: Abs8 memcpy+0".to_string(),
+                    "i: Abs8 memset+0".to_string()]);
+    }
+}
@@ -0,0 +1,188 @@
+//! A format-independent description of an object file.
+//!
+//! The backend collects the sections, symbols, and relocations of the module in an `Artifact`,
+//! which the ELF and Mach-O writers then lay out in their own formats.
+
+/// The sections of an object file.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SectionKind {
+    /// Executable code.
+    Text,
+    /// Read-only data.
+    ReadOnlyData,
+    /// Writable data.
+    Data,
+}
+
+/// All the sections, in the order they appear in the object file.
+pub const SECTIONS: [SectionKind; 3] = [SectionKind::Text,
+                                        SectionKind::ReadOnlyData,
+                                        SectionKind::Data];
+
+impl SectionKind {
+    /// Get the index of this section in `SECTIONS`.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Get the alignment of the section in bytes.
+    pub fn align(self) -> u64 {
+        match self {
+            SectionKind::Text => 16,
+            SectionKind::ReadOnlyData | SectionKind::Data => 8,
+        }
+    }
+}
+
+/// A symbol in the object file.
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    /// The name, without any format-specific prefix.
+    pub name: String,
+    /// The section and offset of the definition, or `None` for an undefined symbol.
+    pub definition: Option<(SectionKind, u64)>,
+    /// Size of the definition in bytes.
+    pub size: u64,
+    /// Is the symbol visible outside the object file?
+    pub global: bool,
+    /// Is the symbol a function?
+    pub function: bool,
+}
+
+/// A relocation kind, independent of the object file format.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RelocKind {
+    /// A 4-byte absolute address.
+    Abs4,
+    /// An 8-byte absolute address.
+    Abs8,
+    /// A 4-byte PC-relative offset.
+    PCRel4,
+    /// A 4-byte PC-relative offset to the symbol's GOT entry.
+    GOTPCRel4,
+    /// A 4-byte PC-relative offset to the symbol's PLT entry.
+    PLTRel4,
+}
+
+/// The target of a relocation.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RelocTo {
+    /// A symbol, given by its index in `Artifact::symbols`.
+    Symbol(usize),
+    /// The start of a section.
+    Section(SectionKind),
+}
+
+/// A relocation in one of the sections.
+#[derive(Clone, Debug)]
+pub struct Reloc {
+    /// The section containing the relocation.
+    pub section: SectionKind,
+    /// Offset of the relocation in the section.
+    pub offset: u64,
+    /// The kind of relocation.
+    pub kind: RelocKind,
+    /// The relocation target.
+    pub target: RelocTo,
+    /// Addend to add to the target address. For PC-relative relocations, the address of the
+    /// relocation itself is subtracted from the sum.
+    pub addend: i64,
+}
+
+/// The contents of an object file.
+#[derive(Clone, Debug, Default)]
+pub struct Artifact {
+    /// The bytes of each section, indexed by `SectionKind::index()`.
+    pub sections: [Vec<u8>; 3],
+    /// The symbols.
+    pub symbols: Vec<Symbol>,
+    /// The relocations.
+    pub relocs: Vec<Reloc>,
+}
+
+impl Artifact {
+    /// Get the offsets of the sections when they are laid out one after the other from `start`,
+    /// each aligned as required.
+    ///
+    /// Also return the end offset of the last section.
+    pub fn layout(&self, start: u64) -> ([u64; 3], u64) {
+        let mut offsets = [0; 3];
+        let mut end = start;
+        for &section in &SECTIONS {
+            let align = section.align();
+            end = (end + align - 1) & !(align - 1);
+            offsets[section.index()] = end;
+            end += self.sections[section.index()].len() as u64;
+        }
+        (offsets, end)
+    }
+
+    /// Get the indexes of the symbols ordered with the local symbols first, then the defined
+    /// global symbols, and the undefined symbols last.
+    ///
+    /// Both ELF and Mach-O want the local symbols first in the symbol table.
+    pub fn symbol_order(&self) -> Vec<usize> {
+        let rank = |sym: &Symbol| if !sym.global {
+            0
+        } else if sym.definition.is_some() {
+            1
+        } else {
+            2
+        };
+        let mut order: Vec<usize> = (0..self.symbols.len()).collect();
+        order.sort_by_key(|&idx| rank(&self.symbols[idx]));
+        order
+    }
+}
+
+/// Append `x` to `out` in little-endian byte order.
+pub fn put2(out: &mut Vec<u8>, x: u16) {
+    out.push(x as u8);
+    out.push((x >> 8) as u8);
+}
+
+/// Append `x` to `out` in little-endian byte order.
+pub fn put4(out: &mut Vec<u8>, x: u32) {
+    put2(out, x as u16);
+    put2(out, (x >> 16) as u16);
+}
+
+/// Append `x` to `out` in little-endian byte order.
+pub fn put8(out: &mut Vec<u8>, x: u64) {
+    put4(out, x as u32);
+    put4(out, (x >> 32) as u32);
+}
+
+/// Pad `out` with zeros to `len` bytes.
+pub fn pad_to(out: &mut Vec<u8>, len: u64) {
+    debug_assert!(out.len() as u64 <= len);
+    out.resize(len as usize, 0);
+}
+
+/// Append `name` to `out` with a zero-padded fixed width of 16 bytes.
+pub fn put_name16(out: &mut Vec<u8>, name: &str) {
+    let start = out.len();
+    out.extend_from_slice(name.as_bytes());
+    pad_to(out, start as u64 + 16);
+}
+
+/// A string table under construction.
+pub struct StringTable {
+    /// The bytes of the table.
+    pub bytes: Vec<u8>,
+}
+
+impl StringTable {
+    /// Create a string table holding just the empty string at offset 0.
+    pub fn new() -> StringTable {
+        StringTable { bytes: vec![0] }
+    }
+
+    /// Add a zero-terminated string, and return its offset in the table.
+    pub fn add(&mut self, s: &str) -> u32 {
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend_from_slice(s.as_bytes());
+        self.bytes.push(0);
+        offset
+    }
+}
@@ -0,0 +1,387 @@
+//! Defining functions and data objects in an object file.
+
+use artifact::{self, Artifact, SectionKind, RelocTo, Symbol};
+use cretonne::binemit::{Reloc, TrapTable};
+use cretonne::entity_map::EntityMap;
+use cretonne::isa::TargetIsa;
+use cretonne::isa::intel::binemit::RelocKind;
+use cton_module::{Backend, FuncId, DataId, FunctionDeclaration, DataDeclaration,
+                  ModuleDeclarations, DataDescription, Init, Linkage, Relocation, RelocTarget,
+                  ModuleError, ModuleResult};
+use elf;
+use macho;
+use std::collections::HashMap;
+
+/// The object file formats that can be written.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ObjectFormat {
+    /// The ELF format used on Linux and most other Unix systems.
+    Elf,
+    /// The Mach-O format used on macOS.
+    MachO,
+}
+
+/// The location of a definition in the object file.
+#[derive(Copy, Clone, Debug)]
+struct Placed {
+    section: SectionKind,
+    offset: u64,
+    size: u64,
+}
+
+/// The target of a relocation, before the symbol table is built.
+#[derive(Clone, Debug)]
+enum Target {
+    Function(FuncId),
+    Data(DataId),
+    Symbol(String),
+    Section(SectionKind),
+}
+
+/// A relocation whose target hasn't been assigned a symbol index yet.
+#[derive(Clone, Debug)]
+struct PendingReloc {
+    section: SectionKind,
+    offset: u64,
+    kind: artifact::RelocKind,
+    target: Target,
+    addend: i64,
+}
+
+/// A `Backend` that writes the functions and data objects of a module to an object file.
+///
+/// The object file bytes are the product of the finished module. Functions and data objects with
+/// the `Export` linkage become global symbols, the `Local` ones become local symbols, and imports
+/// become undefined symbols to be resolved by the linker.
+pub struct ObjectBackend {
+    isa: Box<TargetIsa>,
+    format: ObjectFormat,
+    sections: [Vec<u8>; 3],
+    functions: EntityMap<FuncId, Option<Placed>>,
+    data_objects: EntityMap<DataId, Option<Placed>>,
+    relocs: Vec<PendingReloc>,
+}
+
+impl ObjectBackend {
+    /// Create a backend writing an object file in `format` for `isa`.
+    ///
+    /// The ISA must be 64-bit Intel.
+    pub fn new(isa: Box<TargetIsa>, format: ObjectFormat) -> ObjectBackend {
+        assert_eq!(isa.name(), "intel", "Object files are only supported for the Intel ISA");
+        assert!(isa.flags().is_64bit(), "Object files are only supported for 64-bit code");
+        ObjectBackend {
+            isa: isa,
+            format: format,
+            sections: [Vec::new(), Vec::new(), Vec::new()],
+            functions: EntityMap::new(),
+            data_objects: EntityMap::new(),
+            relocs: Vec::new(),
+        }
+    }
+
+    /// Append `bytes` to `section`, and return where they were placed.
+    fn append(&mut self, section: SectionKind, bytes: &[u8]) -> Placed {
+        let contents = &mut self.sections[section.index()];
+        let align = section.align() as usize;
+        let offset = (contents.len() + align - 1) & !(align - 1);
+        contents.resize(offset, 0);
+        contents.extend_from_slice(bytes);
+        Placed {
+            section: section,
+            offset: offset as u64,
+            size: bytes.len() as u64,
+        }
+    }
+
+    /// Convert an ISA relocation kind.
+    fn reloc_kind(&self, reloc: Reloc) -> ModuleResult<artifact::RelocKind> {
+        let kind = if reloc == Reloc::from(RelocKind::Abs4) {
+            artifact::RelocKind::Abs4
+        } else if reloc == Reloc::from(RelocKind::Abs8) {
+            artifact::RelocKind::Abs8
+        } else if reloc == Reloc::from(RelocKind::PCRel4) {
+            artifact::RelocKind::PCRel4
+        } else if reloc == Reloc::from(RelocKind::GOTPCRel4) {
+            artifact::RelocKind::GOTPCRel4
+        } else if reloc == Reloc::from(RelocKind::PLTRel4) {
+            artifact::RelocKind::PLTRel4
+        } else {
+            return Err(self.unsupported(reloc));
+        };
+        if self.format == ObjectFormat::MachO && !macho::supports(kind) {
+            return Err(self.unsupported(reloc));
+        }
+        Ok(kind)
+    }
+
+    fn unsupported(&self, reloc: Reloc) -> ModuleError {
+        ModuleError::Backend(format!("{} relocations are not supported in {:?} files",
+                                     self.isa.reloc_names()[reloc.0 as usize],
+                                     self.format))
+    }
+
+    /// Record `relocs` for the definition at `offset` in `section`.
+    fn add_relocs(&mut self,
+                  section: SectionKind,
+                  offset: u64,
+                  relocs: &[Relocation])
+                  -> ModuleResult<()> {
+        for reloc in relocs {
+            let at = offset + reloc.offset as u64;
+            let (target, addend) = match reloc.target {
+                RelocTarget::Function(id) => (Target::Function(id), reloc.addend),
+                RelocTarget::Data(id) => (Target::Data(id), reloc.addend),
+                RelocTarget::Symbol(ref name) => (Target::Symbol(name.clone()), reloc.addend),
+                RelocTarget::Internal => {
+                    // PC-relative relocations inside the function are already resolved, and the
+                    // absolute ones hold the offset from the start of the function.
+                    if reloc.reloc == Reloc::from(RelocKind::PCRel1) ||
+                       reloc.reloc == Reloc::from(RelocKind::PCRel4) {
+                        continue;
+                    }
+                    let bytes = &self.sections[section.index()][at as usize..];
+                    let size = if reloc.reloc == Reloc::from(RelocKind::Abs8) { 8 } else { 4 };
+                    let stored = bytes[..size]
+                        .iter()
+                        .rev()
+                        .fold(0u64, |acc, &b| (acc << 8) | b as u64);
+                    (Target::Section(section), (offset + stored) as i64)
+                }
+            };
+            let kind = self.reloc_kind(reloc.reloc)?;
+            self.relocs.push(PendingReloc {
+                                 section: section,
+                                 offset: at,
+                                 kind: kind,
+                                 target: target,
+                                 addend: addend,
+                             });
+        }
+        Ok(())
+    }
+}
+
+impl Backend for ObjectBackend {
+    type Product = Vec<u8>;
+
+    fn isa(&self) -> &TargetIsa {
+        &*self.isa
+    }
+
+    fn define_function(&mut self,
+                       id: FuncId,
+                       _decl: &FunctionDeclaration,
+                       code: Vec<u8>,
+                       _traps: TrapTable)
+                       -> ModuleResult<()> {
+        *self.functions.ensure(id) = Some(self.append(SectionKind::Text, &code));
+        Ok(())
+    }
+
+    fn define_data(&mut self,
+                   id: DataId,
+                   decl: &DataDeclaration,
+                   data: &DataDescription)
+                   -> ModuleResult<()> {
+        let section = if decl.writable {
+            SectionKind::Data
+        } else {
+            SectionKind::ReadOnlyData
+        };
+        let placed = match data.init {
+            Init::Zeros { size } => self.append(section, &vec![0; size]),
+            Init::Bytes { ref contents } => self.append(section, contents),
+        };
+        *self.data_objects.ensure(id) = Some(placed);
+        Ok(())
+    }
+
+    fn finalize_function(&mut self,
+                         id: FuncId,
+                         relocs: &[Relocation],
+                         _decls: &ModuleDeclarations)
+                         -> ModuleResult<()> {
+        let func = self.functions[id].expect("Function is not defined");
+        self.add_relocs(func.section, func.offset, relocs)
+    }
+
+    fn finalize_data(&mut self,
+                     id: DataId,
+                     relocs: &[Relocation],
+                     _decls: &ModuleDeclarations)
+                     -> ModuleResult<()> {
+        let data = self.data_objects[id].expect("Data object is not defined");
+        self.add_relocs(data.section, data.offset, relocs)
+    }
+
+    fn finish(self, decls: &ModuleDeclarations) -> Vec<u8> {
+        let mut artifact = Artifact::default();
+        let mut func_syms = HashMap::new();
+        let mut data_syms = HashMap::new();
+        let mut other_syms = HashMap::new();
+
+        // Symbols for all the declared functions and data objects.
+        for id in decls.function_ids() {
+            let decl = decls.get_function_decl(id);
+            let placed = self.functions.get(id).and_then(|f| *f);
+            func_syms.insert(id, artifact.symbols.len());
+            artifact.symbols.push(Symbol {
+                                      name: decl.name.clone(),
+                                      definition: placed.map(|p| (p.section, p.offset)),
+                                      size: placed.map_or(0, |p| p.size),
+                                      global: decl.linkage != Linkage::Local,
+                                      function: true,
+                                  });
+        }
+        for id in decls.data_ids() {
+            let decl = decls.get_data_decl(id);
+            let placed = self.data_objects.get(id).and_then(|d| *d);
+            data_syms.insert(id, artifact.symbols.len());
+            artifact.symbols.push(Symbol {
+                                      name: decl.name.clone(),
+                                      definition: placed.map(|p| (p.section, p.offset)),
+                                      size: placed.map_or(0, |p| p.size),
+                                      global: decl.linkage != Linkage::Local,
+                                      function: false,
+                                  });
+        }
+
+        for reloc in self.relocs {
+            let target = match reloc.target {
+                Target::Function(id) => RelocTo::Symbol(func_syms[&id]),
+                Target::Data(id) => RelocTo::Symbol(data_syms[&id]),
+                Target::Section(section) => RelocTo::Section(section),
+                Target::Symbol(name) => {
+                    let symbols = &mut artifact.symbols;
+                    RelocTo::Symbol(*other_syms.entry(name.clone()).or_insert_with(|| {
+                        symbols.push(Symbol {
+                                         name: name,
+                                         definition: None,
+                                         size: 0,
+                                         global: true,
+                                         function: false,
+                                     });
+                        symbols.len() - 1
+                    }))
+                }
+            };
+            artifact.relocs.push(artifact::Reloc {
+                                     section: reloc.section,
+                                     offset: reloc.offset,
+                                     kind: reloc.kind,
+                                     target: target,
+                                     addend: reloc.addend,
+                                 });
+        }
+        artifact.sections = self.sections;
+
+        match self.format {
+            ObjectFormat::Elf => elf::write(&artifact),
+            ObjectFormat::MachO => macho::write(&artifact),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cretonne::Context;
+    use cretonne::ir::{Signature, ArgumentType, MemFlags, Cursor, InstBuilder, VariableArgs};
+    use cretonne::ir::types;
+    use cretonne::isa;
+    use cretonne::settings::{self, Configurable};
+    use cton_module::{Module, Linkage, DataDescription};
+    use super::{ObjectBackend, ObjectFormat};
+
+    fn signature() -> Signature {
+        let mut sig = Signature::new();
+        sig.return_types.push(ArgumentType::new(types::I64));
+        sig
+    }
+
+    /// Build a small module, and return the object file bytes.
+    fn build(format: ObjectFormat) -> Vec<u8> {
+        let mut flag_builder = settings::builder();
+        flag_builder.set_bool("is_64bit", true).unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&flag_builder));
+        let mut module = Module::new(ObjectBackend::new(isa, format));
+        let answer = module.declare_function("answer", Linkage::Local, &signature()).unwrap();
+        let sum = module.declare_function("sum", Linkage::Export, &signature()).unwrap();
+        let table = module.declare_data("table", Linkage::Export, false).unwrap();
+        let counter = module.declare_data("counter", Linkage::Local, true).unwrap();
+        let symbol = module.declare_data("symbol", Linkage::Import, false).unwrap();
+
+        let mut ctx = Context::new();
+        ctx.func.signature = signature();
+        {
+            let ebb0 = ctx.func.dfg.make_ebb();
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).iconst(types::I64, 42);
+            let mut rvals = VariableArgs::new();
+            rvals.push(v0);
+            dfg.ins(pos).return_(rvals);
+        }
+        module.define_function(answer, &mut ctx).unwrap();
+
+        let mut ctx = Context::new();
+        ctx.func.signature = signature();
+        let gv_counter = module.declare_data_in_func(counter, &mut ctx.func);
+        let gv_symbol = module.declare_data_in_func(symbol, &mut ctx.func);
+        {
+            let ebb0 = ctx.func.dfg.make_ebb();
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).globalsym_addr(types::I64, gv_counter);
+            let v1 = dfg.ins(pos).load(types::I64, MemFlags::new(), v0, 0);
+            let v2 = dfg.ins(pos).globalsym_addr(types::I64, gv_symbol);
+            let v3 = dfg.ins(pos).load(types::I64, MemFlags::new(), v2, 0);
+            let v4 = dfg.ins(pos).iadd(v1, v3);
+            let mut rvals = VariableArgs::new();
+            rvals.push(v4);
+            dfg.ins(pos).return_(rvals);
+        }
+        module.define_function(sum, &mut ctx).unwrap();
+
+        let mut data = DataDescription::zeros(16);
+        data.write_function_addr(0, answer);
+        data.write_data_addr(8, counter, 8);
+        module.define_data(table, &data).unwrap();
+        module.define_data(counter, &DataDescription::zeros(16)).unwrap();
+
+        module.finish().unwrap()
+    }
+
+    fn contains(bytes: &[u8], needle: &[u8]) -> bool {
+        bytes.windows(needle.len()).any(|w| w == needle)
+    }
+
+    fn get4(bytes: &[u8], offset: usize) -> u32 {
+        (0..4).fold(0, |acc, i| acc | (bytes[offset + i] as u32) << (8 * i))
+    }
+
+    #[test]
+    fn elf() {
+        let obj = build(ObjectFormat::Elf);
+        assert_eq!(&obj[0..4], b"\x7fELF");
+        // ET_REL, EM_X86_64.
+        assert_eq!(get4(&obj, 16), 1 | (62 << 16));
+        for name in &["answer", "sum", "table", "counter", "symbol"] {
+            assert!(contains(&obj, format!("\0{}\0", name).as_bytes()), "missing {}", name);
+        }
+        assert!(contains(&obj, b"\0.rela.rodata\0"));
+    }
+
+    #[test]
+    fn macho() {
+        let obj = build(ObjectFormat::MachO);
+        assert_eq!(get4(&obj, 0), 0xfeed_facf);
+        // CPU_TYPE_X86_64, MH_OBJECT.
+        assert_eq!(get4(&obj, 4), 0x0100_0007);
+        assert_eq!(get4(&obj, 12), 1);
+        for name in &["answer", "sum", "table", "counter", "symbol"] {
+            assert!(contains(&obj, format!("\0_{}\0", name).as_bytes()), "missing {}", name);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Writing ELF relocatable object files for x86-64.
+//!
+//! The file starts with the ELF header, followed by the contents of the sections and the section
+//! header table at the end. The relocations use the `Elf64_Rela` format with explicit addends,
+//! and relocations to the start of a section reference the section's symbol.
+
+use artifact::{Artifact, SectionKind, RelocKind, RelocTo, SECTIONS, StringTable, put2, put4, put8,
+               pad_to};
+
+const EM_X86_64: u16 = 62;
+const ET_REL: u16 = 1;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+
+const R_X86_64_64: u32 = 1;
+const R_X86_64_PC32: u32 = 2;
+const R_X86_64_PLT32: u32 = 4;
+const R_X86_64_GOTPCREL: u32 = 9;
+const R_X86_64_32: u32 = 10;
+
+const EHDR_SIZE: u64 = 64;
+const SHDR_SIZE: u64 = 64;
+const SYM_SIZE: u64 = 24;
+const RELA_SIZE: u64 = 24;
+
+// Section header indexes. The program sections come first, in the order of `SECTIONS`.
+const FIRST_SECTION: u32 = 1;
+const FIRST_RELA: u32 = 4;
+const SYMTAB: u32 = 7;
+const STRTAB: u32 = 8;
+const SHSTRTAB: u32 = 9;
+const GNU_STACK: u32 = 10;
+const NUM_SECTIONS: u32 = 11;
+
+fn section_name(section: SectionKind) -> &'static str {
+    match section {
+        SectionKind::Text => ".text",
+        SectionKind::ReadOnlyData => ".rodata",
+        SectionKind::Data => ".data",
+    }
+}
+
+fn section_flags(section: SectionKind) -> u64 {
+    match section {
+        SectionKind::Text => SHF_ALLOC | SHF_EXECINSTR,
+        SectionKind::ReadOnlyData => SHF_ALLOC,
+        SectionKind::Data => SHF_ALLOC | SHF_WRITE,
+    }
+}
+
+fn reloc_type(kind: RelocKind) -> u32 {
+    match kind {
+        RelocKind::Abs4 => R_X86_64_32,
+        RelocKind::Abs8 => R_X86_64_64,
+        RelocKind::PCRel4 => R_X86_64_PC32,
+        RelocKind::GOTPCRel4 => R_X86_64_GOTPCREL,
+        RelocKind::PLTRel4 => R_X86_64_PLT32,
+    }
+}
+
+/// A section header.
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+impl SectionHeader {
+    fn write(&self, out: &mut Vec<u8>) {
+        put4(out, self.name);
+        put4(out, self.kind);
+        put8(out, self.flags);
+        put8(out, 0); // sh_addr
+        put8(out, self.offset);
+        put8(out, self.size);
+        put4(out, self.link);
+        put4(out, self.info);
+        put8(out, self.align);
+        put8(out, self.entsize);
+    }
+}
+
+/// Write `artifact` as an ELF object file.
+pub fn write(artifact: &Artifact) -> Vec<u8> {
+    let mut shstrtab = StringTable::new();
+    let mut strtab = StringTable::new();
+
+    // The symbol table starts with the null symbol and the section symbols.
+    let order = artifact.symbol_order();
+    let first_symbol = 1 + SECTIONS.len();
+    let mut sym_index = vec![0; artifact.symbols.len()];
+    for (pos, &idx) in order.iter().enumerate() {
+        sym_index[idx] = first_symbol + pos;
+    }
+    let first_global = first_symbol +
+                       order.iter().take_while(|&&idx| !artifact.symbols[idx].global).count();
+
+    let mut symtab = Vec::new();
+    put_symbol(&mut symtab, 0, 0, 0, 0, 0);
+    for &section in &SECTIONS {
+        put_symbol(&mut symtab,
+                   0,
+                   STT_SECTION,
+                   FIRST_SECTION as u16 + section.index() as u16,
+                   0,
+                   0);
+    }
+    for &idx in &order {
+        let sym = &artifact.symbols[idx];
+        let name = strtab.add(&sym.name);
+        let bind = if sym.global { STB_GLOBAL } else { STB_LOCAL };
+        let (kind, shndx, value) = match sym.definition {
+            Some((section, offset)) => {
+                let kind = if sym.function { STT_FUNC } else { STT_OBJECT };
+                (kind, FIRST_SECTION as u16 + section.index() as u16, offset)
+            }
+            None => (STT_NOTYPE, 0, 0),
+        };
+        put_symbol(&mut symtab, name, (bind << 4) | kind, shndx, value, sym.size);
+    }
+
+    let mut relas = [Vec::new(), Vec::new(), Vec::new()];
+    for reloc in &artifact.relocs {
+        let sym = match reloc.target {
+            RelocTo::Symbol(idx) => sym_index[idx],
+            RelocTo::Section(section) => 1 + section.index(),
+        };
+        let out = &mut relas[reloc.section.index()];
+        put8(out, reloc.offset);
+        put8(out, ((sym as u64) << 32) | reloc_type(reloc.kind) as u64);
+        put8(out, reloc.addend as u64);
+    }
+
+    // Lay out the file and build the section headers.
+    let mut headers = Vec::new();
+    headers.push(SectionHeader {
+                     name: 0,
+                     kind: 0,
+                     flags: 0,
+                     offset: 0,
+                     size: 0,
+                     link: 0,
+                     info: 0,
+                     align: 0,
+                     entsize: 0,
+                 });
+    let (offsets, mut end) = artifact.layout(EHDR_SIZE);
+    for &section in &SECTIONS {
+        headers.push(SectionHeader {
+                         name: shstrtab.add(section_name(section)),
+                         kind: SHT_PROGBITS,
+                         flags: section_flags(section),
+                         offset: offsets[section.index()],
+                         size: artifact.sections[section.index()].len() as u64,
+                         link: 0,
+                         info: 0,
+                         align: section.align(),
+                         entsize: 0,
+                     });
+    }
+    end = align8(end);
+    let rela_start = end;
+    for &section in &SECTIONS {
+        let size = relas[section.index()].len() as u64;
+        headers.push(SectionHeader {
+                         name: shstrtab.add(&format!(".rela{}", section_name(section))),
+                         kind: SHT_RELA,
+                         flags: SHF_INFO_LINK,
+                         offset: end,
+                         size: size,
+                         link: SYMTAB,
+                         info: FIRST_SECTION + section.index() as u32,
+                         align: 8,
+                         entsize: RELA_SIZE,
+                     });
+        end += size;
+    }
+    headers.push(SectionHeader {
+                     name: shstrtab.add(".symtab"),
+                     kind: SHT_SYMTAB,
+                     flags: 0,
+                     offset: end,
+                     size: symtab.len() as u64,
+                     link: STRTAB,
+                     info: first_global as u32,
+                     align: 8,
+                     entsize: SYM_SIZE,
+                 });
+    end += symtab.len() as u64;
+    headers.push(SectionHeader {
+                     name: shstrtab.add(".strtab"),
+                     kind: SHT_STRTAB,
+                     flags: 0,
+                     offset: end,
+                     size: strtab.bytes.len() as u64,
+                     link: 0,
+                     info: 0,
+                     align: 1,
+                     entsize: 0,
+                 });
+    end += strtab.bytes.len() as u64;
+    let shstrtab_name = shstrtab.add(".shstrtab");
+    // Mark the stack as non-executable.
+    let gnu_stack_name = shstrtab.add(".note.GNU-stack");
+    headers.push(SectionHeader {
+                     name: shstrtab_name,
+                     kind: SHT_STRTAB,
+                     flags: 0,
+                     offset: end,
+                     size: shstrtab.bytes.len() as u64,
+                     link: 0,
+                     info: 0,
+                     align: 1,
+                     entsize: 0,
+                 });
+    end += shstrtab.bytes.len() as u64;
+    headers.push(SectionHeader {
+                     name: gnu_stack_name,
+                     kind: SHT_PROGBITS,
+                     flags: 0,
+                     offset: end,
+                     size: 0,
+                     link: 0,
+                     info: 0,
+                     align: 1,
+                     entsize: 0,
+                 });
+    debug_assert_eq!(headers.len() as u32, NUM_SECTIONS);
+    debug_assert_eq!(headers[GNU_STACK as usize].name, gnu_stack_name);
+    debug_assert_eq!(headers[SHSTRTAB as usize].name, shstrtab_name);
+    debug_assert_eq!(headers[FIRST_RELA as usize].offset, rela_start);
+    let shoff = align8(end);
+
+    // Write everything out.
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    pad_to(&mut out, 16);
+    put2(&mut out, ET_REL);
+    put2(&mut out, EM_X86_64);
+    put4(&mut out, 1); // e_version
+    put8(&mut out, 0); // e_entry
+    put8(&mut out, 0); // e_phoff
+    put8(&mut out, shoff);
+    put4(&mut out, 0); // e_flags
+    put2(&mut out, EHDR_SIZE as u16);
+    put2(&mut out, 0); // e_phentsize
+    put2(&mut out, 0); // e_phnum
+    put2(&mut out, SHDR_SIZE as u16);
+    put2(&mut out, NUM_SECTIONS as u16);
+    put2(&mut out, SHSTRTAB as u16);
+
+    for &section in &SECTIONS {
+        pad_to(&mut out, offsets[section.index()]);
+        out.extend_from_slice(&artifact.sections[section.index()]);
+    }
+    pad_to(&mut out, rela_start);
+    for rela in &relas {
+        out.extend_from_slice(rela);
+    }
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(&strtab.bytes);
+    out.extend_from_slice(&shstrtab.bytes);
+    pad_to(&mut out, shoff);
+    for header in &headers {
+        header.write(&mut out);
+    }
+    out
+}
+
+fn align8(offset: u64) -> u64 {
+    (offset + 7) & !7
+}
+
+fn put_symbol(out: &mut Vec<u8>, name: u32, info: u8, shndx: u16, value: u64, size: u64) {
+    put4(out, name);
+    out.push(info);
+    out.push(0); // st_other
+    put2(out, shndx);
+    put8(out, value);
+    put8(out, size);
+}
@@ -0,0 +1,19 @@
+//! Cretonne object file library.
+//!
+//! The cton_object library provides a `Backend` for the `cton_module::Module` that writes the
+//! compiled functions and data objects into a relocatable object file. The object file can then be
+//! linked with a system linker, making Cretonne usable as an ahead-of-time code generator.
+//!
+//! Both ELF and Mach-O object files are supported for the 64-bit Intel ISA.
+
+#![deny(missing_docs)]
+
+extern crate cretonne;
+extern crate cton_module;
+
+pub use backend::{ObjectBackend, ObjectFormat};
+
+mod artifact;
+mod backend;
+mod elf;
+mod macho;
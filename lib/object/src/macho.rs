@@ -0,0 +1,234 @@
+//! Writing Mach-O relocatable object files for x86-64.
+//!
+//! An object file has a single unnamed segment holding all the sections, which are laid out at
+//! increasing addresses starting from 0. The load commands are followed by the section contents,
+//! the relocations, the symbol table, and the string table.
+//!
+//! Mach-O relocations don't have an addend field. The addend is stored in the relocated bytes
+//! instead, and a relocation to the start of a section stores the section-relative address. Only
+//! 8-byte absolute addresses are supported in 64-bit code, so the `Abs4` relocation kind can't be
+//! used.
+
+use artifact::{Artifact, SectionKind, RelocKind, RelocTo, SECTIONS, StringTable, put4, put8,
+               pad_to, put_name16};
+
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+const CPU_SUBTYPE_X86_64_ALL: u32 = 3;
+const MH_OBJECT: u32 = 1;
+
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_SYMTAB: u32 = 0x2;
+const LC_DYSYMTAB: u32 = 0xb;
+
+const S_ATTR_PURE_INSTRUCTIONS: u32 = 0x8000_0000;
+const S_ATTR_SOME_INSTRUCTIONS: u32 = 0x0000_0400;
+
+const N_EXT: u8 = 0x01;
+const N_SECT: u8 = 0x0e;
+
+const X86_64_RELOC_UNSIGNED: u32 = 0;
+const X86_64_RELOC_SIGNED: u32 = 1;
+const X86_64_RELOC_BRANCH: u32 = 2;
+const X86_64_RELOC_GOT_LOAD: u32 = 3;
+
+const HEADER_SIZE: u64 = 32;
+const SEGMENT_SIZE: u64 = 72;
+const SECTION_SIZE: u64 = 80;
+const SYMTAB_SIZE: u64 = 24;
+const DYSYMTAB_SIZE: u64 = 80;
+const NLIST_SIZE: u64 = 16;
+const RELOC_SIZE: u64 = 8;
+
+fn section_names(section: SectionKind) -> (&'static str, &'static str) {
+    match section {
+        SectionKind::Text => ("__text", "__TEXT"),
+        SectionKind::ReadOnlyData => ("__const", "__TEXT"),
+        SectionKind::Data => ("__data", "__DATA"),
+    }
+}
+
+fn section_flags(section: SectionKind) -> u32 {
+    match section {
+        SectionKind::Text => S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS,
+        SectionKind::ReadOnlyData | SectionKind::Data => 0,
+    }
+}
+
+/// Can a relocation of kind `kind` be represented in a Mach-O file?
+pub fn supports(kind: RelocKind) -> bool {
+    kind != RelocKind::Abs4
+}
+
+/// Get the `r_type`, `r_pcrel`, and `r_length` fields for `kind`.
+fn reloc_fields(kind: RelocKind) -> (u32, u32, u32) {
+    match kind {
+        RelocKind::Abs8 => (X86_64_RELOC_UNSIGNED, 0, 3),
+        RelocKind::PCRel4 => (X86_64_RELOC_SIGNED, 1, 2),
+        RelocKind::GOTPCRel4 => (X86_64_RELOC_GOT_LOAD, 1, 2),
+        RelocKind::PLTRel4 => (X86_64_RELOC_BRANCH, 1, 2),
+        RelocKind::Abs4 => panic!("Mach-O doesn't support 4-byte absolute relocations"),
+    }
+}
+
+/// Write `artifact` as a Mach-O object file.
+pub fn write(artifact: &Artifact) -> Vec<u8> {
+    let nsects = SECTIONS.len() as u64;
+    let sizeofcmds = SEGMENT_SIZE + nsects * SECTION_SIZE + SYMTAB_SIZE + DYSYMTAB_SIZE;
+    let (offsets, end) = artifact.layout(HEADER_SIZE + sizeofcmds);
+    let base = offsets[0];
+
+    // The sections are mapped at their file offset minus the offset of the first one.
+    let addr = |section: SectionKind| offsets[section.index()] - base;
+
+    // Symbols, in the order required by the `LC_DYSYMTAB` command.
+    let order = artifact.symbol_order();
+    let mut sym_index = vec![0; artifact.symbols.len()];
+    for (pos, &idx) in order.iter().enumerate() {
+        sym_index[idx] = pos;
+    }
+    let nlocal = order.iter().take_while(|&&idx| !artifact.symbols[idx].global).count();
+    let nundef = order.iter().filter(|&&idx| artifact.symbols[idx].definition.is_none()).count();
+    let nextdef = order.len() - nlocal - nundef;
+
+    let mut strtab = StringTable::new();
+    let mut symtab = Vec::new();
+    for &idx in &order {
+        let sym = &artifact.symbols[idx];
+        put4(&mut symtab, strtab.add(&format!("_{}", sym.name)));
+        let ext = if sym.global { N_EXT } else { 0 };
+        match sym.definition {
+            Some((section, offset)) => {
+                symtab.push(N_SECT | ext);
+                symtab.push(1 + section.index() as u8);
+                symtab.extend_from_slice(&[0, 0]);
+                put8(&mut symtab, addr(section) + offset);
+            }
+            None => {
+                symtab.push(ext);
+                symtab.extend_from_slice(&[0, 0, 0]);
+                put8(&mut symtab, 0);
+            }
+        }
+    }
+
+    // The relocation entries, and the section contents with the addends filled in.
+    let mut contents = artifact.sections.clone();
+    let mut relocs = [Vec::new(), Vec::new(), Vec::new()];
+    for reloc in &artifact.relocs {
+        let (r_type, pcrel, length) = reloc_fields(reloc.kind);
+        let (symbolnum, external, implicit) = match reloc.target {
+            RelocTo::Symbol(idx) => (sym_index[idx] as u32, 1, reloc.addend),
+            RelocTo::Section(section) => {
+                (1 + section.index() as u32, 0, addr(section) as i64 + reloc.addend)
+            }
+        };
+        let bytes = &mut contents[reloc.section.index()];
+        let at = reloc.offset as usize;
+        if pcrel != 0 {
+            // The stored addend of a PC-relative relocation is relative to the end of the field.
+            let value = (implicit + 4) as u32;
+            for i in 0..4 {
+                bytes[at + i] = (value >> (8 * i)) as u8;
+            }
+        } else {
+            let value = implicit as u64;
+            for i in 0..8 {
+                bytes[at + i] = (value >> (8 * i)) as u8;
+            }
+        }
+        let out = &mut relocs[reloc.section.index()];
+        put4(out, reloc.offset as u32);
+        put4(out,
+             symbolnum | (pcrel << 24) | (length << 25) | (external << 27) | (r_type << 28));
+    }
+
+    let reloff = (end + 3) & !3;
+    let mut reloc_offsets = [0; 3];
+    let mut symoff = reloff;
+    for &section in &SECTIONS {
+        reloc_offsets[section.index()] = symoff;
+        symoff += relocs[section.index()].len() as u64;
+    }
+    symoff = (symoff + 7) & !7;
+    let stroff = symoff + order.len() as u64 * NLIST_SIZE;
+
+    let mut out = Vec::new();
+    put4(&mut out, MH_MAGIC_64);
+    put4(&mut out, CPU_TYPE_X86_64);
+    put4(&mut out, CPU_SUBTYPE_X86_64_ALL);
+    put4(&mut out, MH_OBJECT);
+    put4(&mut out, 3); // ncmds
+    put4(&mut out, sizeofcmds as u32);
+    put4(&mut out, 0); // flags
+    put4(&mut out, 0); // reserved
+
+    put4(&mut out, LC_SEGMENT_64);
+    put4(&mut out, (SEGMENT_SIZE + nsects * SECTION_SIZE) as u32);
+    put_name16(&mut out, "");
+    put8(&mut out, 0); // vmaddr
+    put8(&mut out, end - base); // vmsize
+    put8(&mut out, base); // fileoff
+    put8(&mut out, end - base); // filesize
+    put4(&mut out, 7); // maxprot
+    put4(&mut out, 7); // initprot
+    put4(&mut out, nsects as u32);
+    put4(&mut out, 0); // flags
+
+    for &section in &SECTIONS {
+        let (sectname, segname) = section_names(section);
+        put_name16(&mut out, sectname);
+        put_name16(&mut out, segname);
+        put8(&mut out, addr(section));
+        put8(&mut out, artifact.sections[section.index()].len() as u64);
+        put4(&mut out, offsets[section.index()] as u32);
+        put4(&mut out, section.align().trailing_zeros());
+        let nreloc = relocs[section.index()].len() as u64 / RELOC_SIZE;
+        put4(&mut out,
+             if nreloc > 0 {
+                 reloc_offsets[section.index()] as u32
+             } else {
+                 0
+             });
+        put4(&mut out, nreloc as u32);
+        put4(&mut out, section_flags(section));
+        put4(&mut out, 0); // reserved1
+        put4(&mut out, 0); // reserved2
+        put4(&mut out, 0); // reserved3
+    }
+
+    put4(&mut out, LC_SYMTAB);
+    put4(&mut out, SYMTAB_SIZE as u32);
+    put4(&mut out, symoff as u32);
+    put4(&mut out, order.len() as u32);
+    put4(&mut out, stroff as u32);
+    put4(&mut out, strtab.bytes.len() as u32);
+
+    put4(&mut out, LC_DYSYMTAB);
+    put4(&mut out, DYSYMTAB_SIZE as u32);
+    put4(&mut out, 0); // ilocalsym
+    put4(&mut out, nlocal as u32);
+    put4(&mut out, nlocal as u32); // iextdefsym
+    put4(&mut out, nextdef as u32);
+    put4(&mut out, (nlocal + nextdef) as u32); // iundefsym
+    put4(&mut out, nundef as u32);
+    // The table of contents, module table, external references, indirect symbols, and dynamic
+    // relocations are all empty.
+    for _ in 0..12 {
+        put4(&mut out, 0);
+    }
+    debug_assert_eq!(out.len() as u64, HEADER_SIZE + sizeofcmds);
+
+    for &section in &SECTIONS {
+        pad_to(&mut out, offsets[section.index()]);
+        out.extend_from_slice(&contents[section.index()]);
+    }
+    pad_to(&mut out, reloff);
+    for reloc in &relocs {
+        out.extend_from_slice(reloc);
+    }
+    pad_to(&mut out, symoff);
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(&strtab.bytes);
+    out
+}
@@ -0,0 +1,225 @@
+//! Generating valid functions from fuzzer input.
+//!
+//! The fuzzer input is used as a sequence of choices, and running out of input makes all the
+//! remaining choices zero. The generated functions are always valid, so every input exercises the
+//! compiler passes instead of being rejected by the verifier.
+//!
+//! The functions only use integer arithmetic on `i32` and `i64` values, branches, and jumps, which
+//! the Intel ISA can compile. Shifts are only generated with immediate shift amounts, since the
+//! register allocator can't yet resolve all the conflicts between a tied operand and a shift amount
+//! that must be in `%rcx`. Each EBB only uses its own arguments and the values defined in it, so
+//! the values passed between EBBs are EBB arguments, and the SSA dominance requirements are always
+//! satisfied. The control flow is arbitrary, including loops, except that all the EBBs are
+//! reachable, which the register allocator requires.
+
+use cretonne::ir::{Function, Signature, ArgumentType, Cursor, DataFlowGraph, Ebb, InstBuilder,
+                   Type, Value, VariableArgs};
+use cretonne::ir::types::{I32, I64};
+
+/// The types of the generated values.
+const TYPES: [Type; 2] = [I32, I64];
+
+/// The maximum number of EBBs in a generated function.
+const MAX_EBBS: usize = 6;
+
+/// The maximum number of instructions in a generated EBB, not counting the terminator.
+const MAX_INSTS: usize = 12;
+
+/// The maximum number of function or EBB arguments.
+const MAX_ARGS: usize = 4;
+
+/// Fuzzer input used as a sequence of choices.
+struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    /// Get the next byte of input, or 0 if the input is exhausted.
+    fn byte(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&b, rest)) => {
+                self.data = rest;
+                b
+            }
+            None => 0,
+        }
+    }
+
+    /// Choose a number below `n`.
+    fn below(&mut self, n: usize) -> usize {
+        self.byte() as usize % n
+    }
+
+    /// Choose a type for a value.
+    fn ty(&mut self) -> Type {
+        TYPES[self.below(TYPES.len())]
+    }
+
+    /// Choose a list of up to `MAX_ARGS` types.
+    fn types(&mut self) -> Vec<Type> {
+        let count = self.below(MAX_ARGS + 1);
+        (0..count).map(|_| self.ty()).collect()
+    }
+
+    /// Choose an immediate operand. Small numbers are more likely to be interesting.
+    fn imm(&mut self) -> i64 {
+        let b = self.byte();
+        if b < 0x80 {
+            return b as i64 - 0x40;
+        }
+        let mut x = 0i64;
+        for _ in 0..4 {
+            x = (x << 8) | self.byte() as i64;
+        }
+        x as i32 as i64
+    }
+}
+
+/// Generate a valid function from the fuzzer input `data`.
+pub fn generate_function(data: &[u8]) -> Function {
+    let mut input = Input { data: data };
+    let mut func = Function::new();
+
+    let mut sig = Signature::new();
+    sig.argument_types = input.types().into_iter().map(ArgumentType::new).collect();
+    let return_type = input.ty();
+    sig.return_types.push(ArgumentType::new(return_type));
+
+    let num_ebbs = 1 + input.below(MAX_EBBS);
+    let ebbs: Vec<Ebb> = (0..num_ebbs).map(|_| func.dfg.make_ebb()).collect();
+    for (i, &ebb) in ebbs.iter().enumerate() {
+        let types = if i == 0 {
+            sig.argument_types.iter().map(|arg| arg.value_type).collect()
+        } else {
+            input.types()
+        };
+        for ty in types {
+            func.dfg.append_ebb_arg(ebb, ty);
+        }
+    }
+    func.signature = sig;
+
+    let dfg = &mut func.dfg;
+    let pos = &mut Cursor::new(&mut func.layout);
+    let mut reached = vec![false; num_ebbs];
+    for (i, &ebb) in ebbs.iter().enumerate() {
+        pos.insert_ebb(ebb);
+        let mut gen = EbbGenerator {
+            input: &mut input,
+            values: dfg.ebb_args(ebb).collect(),
+            reached: &mut reached,
+        };
+        let num_insts = gen.input.below(MAX_INSTS + 1);
+        for _ in 0..num_insts {
+            gen.instruction(dfg, pos, &ebbs);
+        }
+        // The last EBB must return. The other EBBs can return or jump anywhere except the entry
+        // block, which can't have predecessors. The next EBB is reachable from this one if no
+        // earlier EBB branches to it.
+        if i + 1 < num_ebbs && !gen.reached[i + 1] {
+            let args = gen.ebb_args(dfg, pos, ebbs[i + 1]);
+            dfg.ins(pos).jump(ebbs[i + 1], args);
+        } else if i + 1 == num_ebbs || gen.input.below(4) == 0 {
+            let value = gen.value(dfg, pos, return_type);
+            let mut rvals = VariableArgs::new();
+            rvals.push(value);
+            dfg.ins(pos).return_(rvals);
+        } else {
+            let dest = 1 + gen.input.below(num_ebbs - 1);
+            let args = gen.ebb_args(dfg, pos, ebbs[dest]);
+            dfg.ins(pos).jump(ebbs[dest], args);
+        }
+    }
+    func
+}
+
+/// Generates the instructions in one EBB.
+struct EbbGenerator<'a, 'b: 'a> {
+    input: &'a mut Input<'b>,
+
+    /// The values available in the EBB so far.
+    values: Vec<Value>,
+
+    /// The EBBs that are branched to so far, by index.
+    reached: &'a mut [bool],
+}
+
+impl<'a, 'b> EbbGenerator<'a, 'b> {
+    /// Choose a value of type `ty`, creating a constant if there is no such value.
+    fn value(&mut self, dfg: &mut DataFlowGraph, pos: &mut Cursor, ty: Type) -> Value {
+        let candidates: Vec<Value> = self.values
+            .iter()
+            .cloned()
+            .filter(|&v| dfg.value_type(v) == ty)
+            .collect();
+        if candidates.is_empty() || self.input.below(8) == 0 {
+            let imm = self.input.imm();
+            let value = dfg.ins(pos).iconst(ty, imm);
+            self.values.push(value);
+            value
+        } else {
+            candidates[self.input.below(candidates.len())]
+        }
+    }
+
+    /// Choose the arguments for a branch or jump to `dest`.
+    fn ebb_args(&mut self, dfg: &mut DataFlowGraph, pos: &mut Cursor, dest: Ebb) -> VariableArgs {
+        let types: Vec<Type> = dfg.ebb_args(dest).map(|v| dfg.value_type(v)).collect();
+        let mut args = VariableArgs::new();
+        for ty in types {
+            let value = self.value(dfg, pos, ty);
+            args.push(value);
+        }
+        args
+    }
+
+    /// Generate a random instruction.
+    fn instruction(&mut self, dfg: &mut DataFlowGraph, pos: &mut Cursor, ebbs: &[Ebb]) {
+        let ty = self.input.ty();
+        let op = self.input.below(12);
+        let result = match op {
+            0 => {
+                let imm = self.input.imm();
+                dfg.ins(pos).iconst(ty, imm)
+            }
+            op if op < 5 => {
+                let x = self.value(dfg, pos, ty);
+                let imm = self.input.imm();
+                match op {
+                    1 => dfg.ins(pos).iadd_imm(x, imm),
+                    2 => dfg.ins(pos).ishl_imm(x, imm),
+                    3 => dfg.ins(pos).ushr_imm(x, imm),
+                    _ => dfg.ins(pos).sshr_imm(x, imm),
+                }
+            }
+            op if op < 11 => {
+                let x = self.value(dfg, pos, ty);
+                let y = self.value(dfg, pos, ty);
+                match op {
+                    5 => dfg.ins(pos).iadd(x, y),
+                    6 => dfg.ins(pos).isub(x, y),
+                    7 => dfg.ins(pos).imul(x, y),
+                    8 => dfg.ins(pos).band(x, y),
+                    9 => dfg.ins(pos).bor(x, y),
+                    _ => dfg.ins(pos).bxor(x, y),
+                }
+            }
+            _ => {
+                // A conditional branch, unless there is nowhere to branch to.
+                if ebbs.len() > 1 {
+                    let c = self.value(dfg, pos, ty);
+                    let dest = 1 + self.input.below(ebbs.len() - 1);
+                    let args = self.ebb_args(dfg, pos, ebbs[dest]);
+                    if self.input.below(2) == 0 {
+                        dfg.ins(pos).brz(c, ebbs[dest], args);
+                    } else {
+                        dfg.ins(pos).brnz(c, ebbs[dest], args);
+                    }
+                    self.reached[dest] = true;
+                }
+                return;
+            }
+        };
+        self.values.push(result);
+    }
+}
@@ -0,0 +1,119 @@
+//! Fuzzing entry points.
+//!
+//! The functions in this module take arbitrary input from a fuzzer and panic when they find a bug.
+//! They are used by the `cargo fuzz` targets in the top-level `fuzz` directory, and they can be
+//! called from a test to reproduce a crash.
+
+use cretonne::{Context, verify_function};
+use cretonne::ir::Function;
+use cretonne::isa;
+use cretonne::settings::{self, Configurable};
+use parser::parse_functions;
+use std::str;
+
+pub use self::generate::generate_function;
+
+mod generate;
+
+/// Parse `data` as functions, and check that printing them gives text that parses back to the same
+/// functions.
+///
+/// Most inputs are rejected by the parser, which must return an error instead of panicking. The
+/// functions that parse are printed and parsed again, and printing the reparsed functions must
+/// give the same text. Running the verifier on the functions must not panic either, and the
+/// reparsed functions must pass the verifier when the original functions do.
+pub fn roundtrip(data: &[u8]) {
+    let text = match str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    let funcs = match parse_functions(text) {
+        Ok(funcs) => funcs,
+        Err(_) => return,
+    };
+    let printed = print(&funcs);
+    let reparsed = match parse_functions(&printed) {
+        Ok(funcs) => funcs,
+        Err(e) => panic!("printed functions don't parse: {}\n{}", e, printed),
+    };
+    let reprinted = print(&reparsed);
+    assert_eq!(printed, reprinted, "printing isn't stable");
+
+    for (func, refunc) in funcs.iter().zip(&reparsed) {
+        if verify_function(func).is_ok() {
+            if let Err(e) = verify_function(refunc) {
+                panic!("reparsed function fails the verifier: {}\n{}", e, refunc);
+            }
+        }
+    }
+}
+
+/// Generate a function from `data`, and compile it for 64-bit Intel with the optimizations and the
+/// verifier enabled.
+///
+/// The verifier runs after each pass, so a pass that produces invalid code is caught right away
+/// instead of causing a crash or a miscompilation later. Compilation must succeed, and the passes
+/// must not panic.
+pub fn compile(data: &[u8]) {
+    let func = generate_function(data);
+    if let Err(e) = verify_function(&func) {
+        panic!("generated function fails the verifier: {}\n{}", e, func);
+    }
+
+    let mut flag_builder = settings::builder();
+    flag_builder.set_bool("is_64bit", true).unwrap();
+    flag_builder.set_bool("enable_verifier", true).unwrap();
+    flag_builder.set("opt_level", "speed").unwrap();
+    let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&flag_builder));
+
+    let mut ctx = Context::new();
+    ctx.func = func.clone();
+    if let Err(e) = ctx.compile(&*isa) {
+        panic!("{}\n{}", e, func);
+    }
+}
+
+/// Print `funcs` one after the other.
+fn print(funcs: &[Function]) -> String {
+    funcs.iter().map(|func| func.to_string()).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{roundtrip, compile};
+
+    #[test]
+    fn valid() {
+        roundtrip(b"function foo(i32) -> i32 {
+                    ebb0(v0: i32):
+                        v1 = iadd_imm v0, 1
+                        brz v1, ebb1
+                        return v1
+                    ebb1:
+                        v2 = iconst.i32 0
+                        return v2
+                    }");
+    }
+
+    #[test]
+    fn invalid() {
+        roundtrip(b"");
+        roundtrip(b"\xff\xfe");
+        roundtrip(b"function");
+        roundtrip(b"function foo() {\nebb0:\n    v1 = iadd v0, v0\n}");
+        roundtrip(b"function foo() {\nebb0(v0: i32):\n    jump ebb7\n}");
+        roundtrip(b"function foo() {\nebb0:\n    v1 = vconst.i32x4 0\n}");
+    }
+
+    #[test]
+    fn compile_inputs() {
+        compile(b"");
+        compile(b"\x03\x01\x00\x01\x04\x02\x01\x05\x03\x0a\x00\x01\x02\x03\x02");
+        for seed in 0..64u32 {
+            let data: Vec<u8> = (0..256u32)
+                .map(|i| (seed.wrapping_mul(2654435761) ^ i.wrapping_mul(40503)) as u8)
+                .collect();
+            compile(&data);
+        }
+    }
+}
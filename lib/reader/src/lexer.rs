@@ -40,8 +40,11 @@ pub enum Token<'a> {
     JumpTable(u32), // jt2
     FuncRef(u32), // fn2
     SigRef(u32), // sig2
+    Heap(u32), // heap1
+    GlobalVar(u32), // gv3
     Name(&'a str), // %9arbitrary_alphanum, %x3, %0, %function ...
     HexSequence(&'a str), // #89AF
+    SourceLoc(&'a str), // @00c7
     Identifier(&'a str), // Unrecognized identifier (opcode, enumerator, ...)
 }
 
@@ -243,8 +246,8 @@ impl<'a> Lexer<'a> {
             // We expect a hexadecimal number to follow the colon.
             while self.next_ch() != Some(':') {}
             is_float = true;
-        } else if self.looking_at("NaN") || self.looking_at("Inf") {
-            // This is Inf or a default quiet NaN.
+        } else if self.looking_at("NaN") || self.looking_at("sNaN") || self.looking_at("Inf") {
+            // This is Inf or a default NaN.
             is_float = true;
         }
 
@@ -281,6 +284,19 @@ impl<'a> Lexer<'a> {
         }
         let text = &self.source[begin..self.pos];
 
+        // Unsigned special floating point values look like words.
+        match text {
+            "Inf" | "NaN" | "sNaN" => return token(Token::Float(text), loc),
+            _ => {}
+        }
+
+        // The special types don't have a number suffix.
+        match text {
+            "iflags" => return token(Token::Type(types::IFLAGS), loc),
+            "fflags" => return token(Token::Type(types::FFLAGS), loc),
+            _ => {}
+        }
+
         // Look for numbered well-known entities like ebb15, v45, ...
         token(split_entity_name(text)
                   .and_then(|(prefix, number)| {
@@ -302,6 +318,8 @@ impl<'a> Lexer<'a> {
             "jt" => Some(Token::JumpTable(number)),
             "fn" => Some(Token::FuncRef(number)),
             "sig" => Some(Token::SigRef(number)),
+            "heap" => Some(Token::Heap(number)),
+            "gv" => Some(Token::GlobalVar(number)),
             _ => None,
         }
     }
@@ -319,6 +337,7 @@ impl<'a> Lexer<'a> {
             "i16" => types::I16,
             "i32" => types::I32,
             "i64" => types::I64,
+            "i128" => types::I128,
             "f32" => types::F32,
             "f64" => types::F64,
             "b1" => types::B1,
@@ -326,6 +345,8 @@ impl<'a> Lexer<'a> {
             "b16" => types::B16,
             "b32" => types::B32,
             "b64" => types::B64,
+            "r32" => types::R32,
+            "r64" => types::R64,
             _ => return None,
         };
         if is_vector {
@@ -371,6 +392,22 @@ impl<'a> Lexer<'a> {
         token(Token::HexSequence(&self.source[begin..end]), loc)
     }
 
+    fn scan_srcloc(&mut self) -> Result<LocatedToken<'a>, LocatedError> {
+        let loc = self.loc();
+        let begin = self.pos + 1;
+
+        assert!(self.lookahead == Some('@'));
+
+        while let Some(c) = self.next_ch() {
+            if !char::is_digit(c, 16) {
+                break;
+            }
+        }
+
+        let end = self.pos;
+        token(Token::SourceLoc(&self.source[begin..end]), loc)
+    }
+
     /// Get the next token or a lexical error.
     ///
     /// Return None when the end of the source is encountered.
@@ -398,9 +435,17 @@ impl<'a> Lexer<'a> {
                     }
                 }
                 Some(ch) if ch.is_digit(10) => Some(self.scan_number()),
-                Some(ch) if ch.is_alphabetic() => Some(self.scan_word()),
+                Some(ch) if ch.is_alphabetic() => {
+                    if self.looking_at("NaN:") || self.looking_at("sNaN:") {
+                        // NaN with a payload.
+                        Some(self.scan_number())
+                    } else {
+                        Some(self.scan_word())
+                    }
+                }
                 Some('%') => Some(self.scan_name()),
                 Some('#') => Some(self.scan_hex_sequence()),
+                Some('@') => Some(self.scan_srcloc()),
                 Some(ch) if ch.is_whitespace() => {
                     self.next_ch();
                     continue;
@@ -513,10 +558,27 @@ mod tests {
         assert_eq!(lex.next(), None);
     }
 
+    #[test]
+    fn lex_special_floats() {
+        let mut lex = Lexer::new("Inf -Inf NaN -NaN sNaN NaN:0x1234 -sNaN:0x1 0x1.fp3 Info NaNs");
+        assert_eq!(lex.next(), token(Token::Float("Inf"), 1));
+        assert_eq!(lex.next(), token(Token::Float("-Inf"), 1));
+        assert_eq!(lex.next(), token(Token::Float("NaN"), 1));
+        assert_eq!(lex.next(), token(Token::Float("-NaN"), 1));
+        assert_eq!(lex.next(), token(Token::Float("sNaN"), 1));
+        assert_eq!(lex.next(), token(Token::Float("NaN:0x1234"), 1));
+        assert_eq!(lex.next(), token(Token::Float("-sNaN:0x1"), 1));
+        assert_eq!(lex.next(), token(Token::Float("0x1.fp3"), 1));
+        assert_eq!(lex.next(), token(Token::Identifier("Info"), 1));
+        assert_eq!(lex.next(), token(Token::Identifier("NaNs"), 1));
+        assert_eq!(lex.next(), None);
+    }
+
     #[test]
     fn lex_identifiers() {
         let mut lex = Lexer::new("v0 v00 vx01 ebb1234567890 ebb5234567890 v1x vx1 vxvx4 \
-                                  function0 function b1 i32x4 f32x5");
+                                  function0 function b1 i32x4 f32x5 r64 r64x2 i128 \
+                                  iflags fflagsx4");
         assert_eq!(lex.next(),
                    token(Token::Value(Value::direct_with_number(0).unwrap()), 1));
         assert_eq!(lex.next(), token(Token::Identifier("v00"), 1));
@@ -533,6 +595,11 @@ mod tests {
         assert_eq!(lex.next(), token(Token::Type(types::B1), 1));
         assert_eq!(lex.next(), token(Token::Type(types::I32.by(4).unwrap()), 1));
         assert_eq!(lex.next(), token(Token::Identifier("f32x5"), 1));
+        assert_eq!(lex.next(), token(Token::Type(types::R64), 1));
+        assert_eq!(lex.next(), token(Token::Identifier("r64x2"), 1));
+        assert_eq!(lex.next(), token(Token::Type(types::I128), 1));
+        assert_eq!(lex.next(), token(Token::Type(types::IFLAGS), 1));
+        assert_eq!(lex.next(), token(Token::Identifier("fflagsx4"), 1));
         assert_eq!(lex.next(), None);
     }
 
@@ -545,6 +612,18 @@ mod tests {
         assert_eq!(lex.next(), token(Token::HexSequence("789"), 1));
     }
 
+    #[test]
+    fn lex_srclocs() {
+        let mut lex = Lexer::new("@0 @00c7 v1 @");
+
+        assert_eq!(lex.next(), token(Token::SourceLoc("0"), 1));
+        assert_eq!(lex.next(), token(Token::SourceLoc("00c7"), 1));
+        assert_eq!(lex.next(),
+                   token(Token::Value(Value::direct_with_number(1).unwrap()), 1));
+        assert_eq!(lex.next(), token(Token::SourceLoc(""), 1));
+        assert_eq!(lex.next(), None);
+    }
+
     #[test]
     fn lex_names() {
         let mut lex = Lexer::new("%0 %x3 %function %123_abc %ss0 %v3 %ebb11 %_");
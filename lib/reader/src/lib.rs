@@ -14,6 +14,8 @@ pub use testfile::{TestFile, Details, Comment};
 pub use isaspec::IsaSpec;
 pub use sourcemap::SourceMap;
 
+pub mod fuzz;
+
 mod error;
 mod lexer;
 mod parser;
@@ -5,19 +5,21 @@
 //
 // ====--------------------------------------------------------------------------------------====//
 
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::u32;
 use std::mem;
-use cretonne::ir::{Function, Ebb, Opcode, Value, Type, FunctionName, StackSlotData, JumpTable,
-                   JumpTableData, Signature, ArgumentType, ArgumentExtension, ExtFuncData, SigRef,
-                   FuncRef};
-use cretonne::ir::types::VOID;
+use cretonne::ir::{Function, Ebb, Opcode, Value, Type, ExternalName, StackSlotData, JumpTable,
+                   JumpTableData, Signature, ArgumentType, ArgumentExtension, ArgumentPurpose,
+                   ExtFuncData, SigRef, FuncRef, Heap, HeapData, GlobalVar, GlobalVarData,
+                   StackSlot, MemFlags, SourceLoc};
+use cretonne::ir::types::{VOID, I128};
 use cretonne::ir::immediates::{Imm64, Ieee32, Ieee64};
 use cretonne::ir::entities::AnyEntity;
-use cretonne::ir::instructions::{InstructionFormat, InstructionData, VariableArgs,
-                                 TernaryOverflowData, JumpData, BranchData, CallData,
-                                 IndirectCallData, ReturnData, ReturnRegData};
-use cretonne::isa;
+use cretonne::ir::instructions::{InstructionFormat, InstructionData, VariableArgs, ValueList,
+                                 ValueListPool, TernaryOverflowData, LoadComplexData,
+                                 StoreComplexData};
+use cretonne::isa::{self, RegUnit};
 use cretonne::settings;
 use testfile::{TestFile, Details, Comment};
 use error::{Location, Error, Result};
@@ -65,6 +67,10 @@ pub struct Parser<'a> {
 
     // Comments collected so far.
     comments: Vec<Comment<'a>>,
+
+    // Register information for the ISA when the test file specifies a single ISA. This is used to
+    // resolve register names like `%rax`.
+    reginfo: Option<isa::RegInfo>,
 }
 
 // Context for resolving references when parsing a single function.
@@ -74,6 +80,12 @@ pub struct Parser<'a> {
 struct Context {
     function: Function,
     map: SourceMap,
+
+    // Value aliases declared so far, mapping source alias value -> source original value.
+    aliases: HashMap<Value, Value>,
+
+    // Value aliases whose original value hasn't been defined yet.
+    pending_aliases: Vec<(Value, Value, Location)>,
 }
 
 impl Context {
@@ -81,6 +93,8 @@ impl Context {
         Context {
             function: f,
             map: SourceMap::new(),
+            aliases: HashMap::new(),
+            pending_aliases: Vec::new(),
         }
     }
 
@@ -89,6 +103,14 @@ impl Context {
         self.map.def_ss(number, self.function.stack_slots.push(data), loc)
     }
 
+    // Resolve a reference to a stack slot.
+    fn get_ss(&self, number: u32, loc: &Location) -> Result<StackSlot> {
+        match self.map.get_ss(number) {
+            Some(ss) => Ok(ss),
+            None => err!(loc, "undefined stack slot ss{}", number),
+        }
+    }
+
     // Allocate a new signature and add a mapping number -> SigRef.
     fn add_sig(&mut self, number: u32, data: Signature, loc: &Location) -> Result<()> {
         self.map.def_sig(number, self.function.dfg.signatures.push(data), loc)
@@ -128,6 +150,32 @@ impl Context {
         }
     }
 
+    // Allocate a new heap and add a mapping number -> Heap.
+    fn add_heap(&mut self, number: u32, data: HeapData, loc: &Location) -> Result<()> {
+        self.map.def_heap(number, self.function.heaps.push(data), loc)
+    }
+
+    // Resolve a reference to a heap.
+    fn get_heap(&self, number: u32, loc: &Location) -> Result<Heap> {
+        match self.map.get_heap(number) {
+            Some(heap) => Ok(heap),
+            None => err!(loc, "undefined heap heap{}", number),
+        }
+    }
+
+    // Allocate a new global variable and add a mapping number -> GlobalVar.
+    fn add_gv(&mut self, number: u32, data: GlobalVarData, loc: &Location) -> Result<()> {
+        self.map.def_gv(number, self.function.dfg.global_vars.push(data), loc)
+    }
+
+    // Resolve a reference to a global variable.
+    fn get_gv(&self, number: u32, loc: &Location) -> Result<GlobalVar> {
+        match self.map.get_gv(number) {
+            Some(gv) => Ok(gv),
+            None => err!(loc, "undefined global variable gv{}", number),
+        }
+    }
+
     // Allocate a new EBB and add a mapping src_ebb -> Ebb.
     fn add_ebb(&mut self, src_ebb: Ebb, loc: &Location) -> Result<Ebb> {
         let ebb = self.function.dfg.make_ebb();
@@ -135,6 +183,64 @@ impl Context {
         self.map.def_ebb(src_ebb, ebb, loc).and(Ok(ebb))
     }
 
+    // Declare the source value `src` as an alias of the source value `original`.
+    //
+    // The same alias may be declared more than once, as long as it refers to the same original
+    // value each time. If `original` hasn't been defined yet, creating the alias is deferred
+    // until `resolve_aliases()`.
+    fn add_alias(&mut self, src: Value, original: Value, loc: &Location) -> Result<()> {
+        if let Some(&prev) = self.aliases.get(&src) {
+            if prev == original {
+                return Ok(());
+            }
+            return err!(loc, "value {} is already an alias of {}", src, prev);
+        }
+        self.aliases.insert(src, original);
+
+        match self.map.get_value(original) {
+            Some(v) => {
+                let alias = self.function.dfg.make_value_alias(v);
+                self.map.def_value(src, alias, loc)
+            }
+            None => {
+                self.pending_aliases.push((src, original, *loc));
+                Ok(())
+            }
+        }
+    }
+
+    // Create the pending value aliases whose original value has been defined by now.
+    fn create_pending_aliases(&mut self) -> Result<()> {
+        // Aliases can refer to other pending aliases, so keep going while we make progress.
+        loop {
+            let pending = mem::replace(&mut self.pending_aliases, Vec::new());
+            let num_pending = pending.len();
+            for (src, original, loc) in pending {
+                match self.map.get_value(original) {
+                    Some(v) => {
+                        let alias = self.function.dfg.make_value_alias(v);
+                        self.map.def_value(src, alias, &loc)?;
+                    }
+                    None => self.pending_aliases.push((src, original, loc)),
+                }
+            }
+            if self.pending_aliases.len() == num_pending {
+                return Ok(());
+            }
+        }
+    }
+
+    // Create all the remaining value aliases after parsing the function body.
+    fn resolve_aliases(&mut self) -> Result<()> {
+        self.create_pending_aliases()?;
+        match self.pending_aliases.first() {
+            Some(&(src, original, loc)) => {
+                err!(loc, "undefined value {} in alias {} -> {}", original, src, original)
+            }
+            None => Ok(()),
+        }
+    }
+
     // The parser creates all instructions with Ebb and Value references using the source file
     // numbering. These references need to be rewritten after parsing is complete since forward
     // references are allowed.
@@ -144,63 +250,81 @@ impl Context {
                 let loc = inst.into();
                 match self.function.dfg[inst] {
                     InstructionData::Nullary { .. } |
+                    InstructionData::Trap { .. } |
                     InstructionData::UnaryImm { .. } |
+                    InstructionData::UnaryBool { .. } |
                     InstructionData::UnaryIeee32 { .. } |
                     InstructionData::UnaryIeee64 { .. } |
-                    InstructionData::UnaryImmVector { .. } => {}
+                    InstructionData::UnaryImmVector { .. } |
+                    InstructionData::UnaryGlobalVar { .. } |
+                    InstructionData::FuncAddr { .. } |
+                    InstructionData::BranchTableBase { .. } |
+                    InstructionData::StackLoad { .. } => {}
 
                     InstructionData::Unary { ref mut arg, .. } |
                     InstructionData::UnarySplit { ref mut arg, .. } |
                     InstructionData::BinaryImm { ref mut arg, .. } |
                     InstructionData::BinaryImmRev { ref mut arg, .. } |
                     InstructionData::ExtractLane { ref mut arg, .. } |
-                    InstructionData::BranchTable { ref mut arg, .. } => {
+                    InstructionData::BranchTable { ref mut arg, .. } |
+                    InstructionData::CondTrap { ref mut arg, .. } |
+                    InstructionData::IntCondTrap { ref mut arg, .. } |
+                    InstructionData::IntCond { ref mut arg, .. } |
+                    InstructionData::FloatCond { ref mut arg, .. } |
+                    InstructionData::HeapAddr { ref mut arg, .. } |
+                    InstructionData::Load { ref mut arg, .. } |
+                    InstructionData::AtomicLoad { ref mut arg, .. } |
+                    InstructionData::StackStore { ref mut arg, .. } |
+                    InstructionData::RegMove { ref mut arg, .. } => {
                         self.map.rewrite_value(arg, loc)?;
                     }
 
                     InstructionData::Binary { ref mut args, .. } |
                     InstructionData::BinaryOverflow { ref mut args, .. } |
+                    InstructionData::BinaryTrap { ref mut args, .. } |
                     InstructionData::InsertLane { ref mut args, .. } |
                     InstructionData::IntCompare { ref mut args, .. } |
+                    InstructionData::Store { ref mut args, .. } |
+                    InstructionData::BranchTableEntry { ref mut args, .. } |
+                    InstructionData::AtomicRmw { ref mut args, .. } |
                     InstructionData::FloatCompare { ref mut args, .. } => {
                         self.map.rewrite_values(args, loc)?;
                     }
 
-                    InstructionData::Ternary { ref mut args, .. } => {
+                    InstructionData::Ternary { ref mut args, .. } |
+                    InstructionData::IntSelect { ref mut args, .. } |
+                    InstructionData::AtomicCas { ref mut args, .. } => {
                         self.map.rewrite_values(args, loc)?;
                     }
 
-                    InstructionData::TernaryOverflow { ref mut data, .. } => {
+                    InstructionData::LoadComplex { ref mut data, .. } => {
                         self.map.rewrite_values(&mut data.args, loc)?;
                     }
 
-                    InstructionData::Jump { ref mut data, .. } => {
-                        self.map.rewrite_ebb(&mut data.destination, loc)?;
-                        self.map.rewrite_values(&mut data.varargs, loc)?;
-                    }
-
-                    InstructionData::Branch { ref mut data, .. } => {
-                        self.map.rewrite_value(&mut data.arg, loc)?;
-                        self.map.rewrite_ebb(&mut data.destination, loc)?;
-                        self.map.rewrite_values(&mut data.varargs, loc)?;
+                    InstructionData::StoreComplex { ref mut data, .. } => {
+                        self.map.rewrite_values(&mut data.args, loc)?;
                     }
 
-                    InstructionData::Call { ref mut data, .. } => {
-                        self.map.rewrite_values(&mut data.varargs, loc)?;
+                    InstructionData::TernaryOverflow { ref mut data, .. } => {
+                        self.map.rewrite_values(&mut data.args, loc)?;
                     }
 
-                    InstructionData::IndirectCall { ref mut data, .. } => {
-                        self.map.rewrite_value(&mut data.arg, loc)?;
-                        self.map.rewrite_values(&mut data.varargs, loc)?;
+                    // The value arguments in value lists are rewritten below.
+                    InstructionData::Jump { ref mut destination, .. } |
+                    InstructionData::Branch { ref mut destination, .. } |
+                    InstructionData::BranchIcmp { ref mut destination, .. } => {
+                        self.map.rewrite_ebb(destination, loc)?;
                     }
 
-                    InstructionData::Return { ref mut data, .. } => {
-                        self.map.rewrite_values(&mut data.varargs, loc)?;
-                    }
+                    InstructionData::Call { .. } |
+                    InstructionData::IndirectCall { .. } |
+                    InstructionData::Return { .. } |
+                    InstructionData::ReturnReg { .. } => {}
+                }
 
-                    InstructionData::ReturnReg { ref mut data, .. } => {
-                        self.map.rewrite_value(&mut data.arg, loc)?;
-                        self.map.rewrite_values(&mut data.varargs, loc)?;
+                if self.function.dfg[inst].value_list_mut().is_some() {
+                    for part in &mut self.function.dfg.inst_args_mut(inst) {
+                        self.map.rewrite_values(part, loc)?;
                     }
                 }
             }
@@ -222,6 +346,13 @@ impl Context {
     }
 }
 
+// Create a value list in `pool` holding the `fixed` operands followed by the variable `args`.
+fn value_list(pool: &mut ValueListPool, fixed: &[Value], args: &[Value]) -> ValueList {
+    let mut list = ValueList::default();
+    list.extend(fixed.iter().chain(args).cloned(), pool);
+    list
+}
+
 impl<'a> Parser<'a> {
     /// Create a new `Parser` which reads `text`. The referenced text must outlive the parser.
     pub fn new(text: &'a str) -> Parser {
@@ -232,6 +363,7 @@ impl<'a> Parser<'a> {
             loc: Location { line_number: 0 },
             comment_entity: None,
             comments: Vec::new(),
+            reginfo: None,
         }
     }
 
@@ -367,6 +499,26 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Match and consume a heap reference.
+    fn match_heap(&mut self, err_msg: &str) -> Result<u32> {
+        if let Some(Token::Heap(heap)) = self.token() {
+            self.consume();
+            Ok(heap)
+        } else {
+            err!(self.loc, err_msg)
+        }
+    }
+
+    // Match and consume a global variable reference.
+    fn match_gv(&mut self, err_msg: &str) -> Result<u32> {
+        if let Some(Token::GlobalVar(gv)) = self.token() {
+            self.consume();
+            Ok(gv)
+        } else {
+            err!(self.loc, err_msg)
+        }
+    }
+
     // Match and consume an ebb reference.
     fn match_ebb(&mut self, err_msg: &str) -> Result<Ebb> {
         if let Some(Token::Ebb(ebb)) = self.token() {
@@ -407,6 +559,17 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Match and consume the Imm64 immediate of an `i128` constant.
+    // The immediate is sign-extended, and it can be written with all 128 bits in hexadecimal.
+    fn match_imm64_i128(&mut self, err_msg: &str) -> Result<Imm64> {
+        if let Some(Token::Integer(text)) = self.token() {
+            self.consume();
+            Imm64::from_str_i128(text).map_err(|e| self.error(e))
+        } else {
+            err!(self.loc, err_msg)
+        }
+    }
+
     // Match and consume a u8 immediate.
     // This is used for lane numbers in SIMD vectors.
     fn match_uimm8(&mut self, err_msg: &str) -> Result<u8> {
@@ -420,30 +583,101 @@ impl<'a> Parser<'a> {
         }
     }
 
-    // Match and consume an Ieee32 immediate.
-    fn match_ieee32(&mut self, err_msg: &str) -> Result<Ieee32> {
-        if let Some(Token::Float(text)) = self.token() {
+    // Match and consume a u32 immediate.
+    // This is used for the byte counts of heap range checks.
+    fn match_uimm32(&mut self, err_msg: &str) -> Result<u32> {
+        if let Some(Token::Integer(text)) = self.token() {
             self.consume();
-            // Lexer just gives us raw text that looks like a float.
-            // Parse it as an Ieee32 to check for the right number of digits and other issues.
-            text.parse().map_err(|e| self.error(e))
+            // Lexer just gives us raw text that looks like an integer.
+            // Parse it as a u32 to check for overflow and other issues.
+            text.parse().map_err(|_| self.error("expected u32 decimal immediate"))
         } else {
             err!(self.loc, err_msg)
         }
     }
 
-    // Match and consume an Ieee64 immediate.
-    fn match_ieee64(&mut self, err_msg: &str) -> Result<Ieee64> {
-        if let Some(Token::Float(text)) = self.token() {
+    // Match and consume a register unit, either as a register name like `%rax` or as a register
+    // unit number. Register names can only be used when the test file specifies a single ISA.
+    fn match_regunit(&mut self, err_msg: &str) -> Result<RegUnit> {
+        match self.token() {
+            Some(Token::Name(name)) => {
+                self.consume();
+                match self.reginfo {
+                    Some(ref reginfo) => {
+                        reginfo
+                            .parse_regunit(name)
+                            .ok_or_else(|| self.error("invalid register name"))
+                    }
+                    None => err!(self.loc, "register names require a unique ISA"),
+                }
+            }
+            Some(Token::Integer(text)) => {
+                self.consume();
+                text.parse().map_err(|_| self.error("expected register unit number"))
+            }
+            _ => err!(self.loc, err_msg),
+        }
+    }
+
+    // Match and consume an i32 immediate.
+    // This is used for the byte offsets of memory operations.
+    fn match_offset32(&mut self, err_msg: &str) -> Result<i32> {
+        if let Some(Token::Integer(text)) = self.token() {
             self.consume();
-            // Lexer just gives us raw text that looks like a float.
-            // Parse it as an Ieee64 to check for the right number of digits and other issues.
-            text.parse().map_err(|e| self.error(e))
+            // Lexer just gives us raw text that looks like an integer.
+            // Parse it as an i32 to check for overflow and other issues.
+            text.parse().map_err(|_| self.error("expected i32 decimal immediate"))
         } else {
             err!(self.loc, err_msg)
         }
     }
 
+    // Parse an optional list of memory operation flags following the operands:
+    //
+    // memflags ::= { "," flag }
+    fn optional_memflags(&mut self) -> Result<MemFlags> {
+        let mut flags = MemFlags::new();
+        while self.optional(Token::Comma) {
+            match self.token() {
+                Some(Token::Identifier(text)) if flags.set_by_name(text) => self.consume(),
+                _ => return err!(self.loc, "expected memory flag"),
+            };
+        }
+        Ok(flags)
+    }
+
+    // Match and consume an Ieee32 immediate.
+    fn match_ieee32(&mut self, err_msg: &str) -> Result<Ieee32> {
+        match self.token() {
+            // A hexadecimal float without a radix point like `0x1p3` looks like an integer to the
+            // lexer.
+            Some(Token::Float(text)) |
+            Some(Token::Integer(text)) => {
+                self.consume();
+                // Lexer just gives us raw text that looks like a float.
+                // Parse it as an Ieee32 to check for the right number of digits and other issues.
+                text.parse().map_err(|e| self.error(e))
+            }
+            _ => err!(self.loc, err_msg),
+        }
+    }
+
+    // Match and consume an Ieee64 immediate.
+    fn match_ieee64(&mut self, err_msg: &str) -> Result<Ieee64> {
+        match self.token() {
+            // A hexadecimal float without a radix point like `0x1p3` looks like an integer to the
+            // lexer.
+            Some(Token::Float(text)) |
+            Some(Token::Integer(text)) => {
+                self.consume();
+                // Lexer just gives us raw text that looks like a float.
+                // Parse it as an Ieee64 to check for the right number of digits and other issues.
+                text.parse().map_err(|e| self.error(e))
+            }
+            _ => err!(self.loc, err_msg),
+        }
+    }
+
     // Match and consume an enumerated immediate, like one of the condition codes.
     fn match_enum<T: FromStr>(&mut self, err_msg: &str) -> Result<T> {
         if let Some(Token::Identifier(text)) = self.token() {
@@ -479,10 +713,11 @@ impl<'a> Parser<'a> {
         while let Some(Token::Identifier(command)) = self.token() {
             match command {
                 "set" => {
-                    last_set_loc = Some(self.loc);
+                    let loc = self.loc;
+                    last_set_loc = Some(loc);
                     isaspec::parse_options(self.consume_line().trim().split_whitespace(),
                                            &mut flag_builder,
-                                           &self.loc)?;
+                                           &loc)?;
                 }
                 "isa" => {
                     last_set_loc = None;
@@ -497,11 +732,11 @@ impl<'a> Parser<'a> {
                         Some(w) => w,
                     };
                     let mut isa_builder = match isa::lookup(isa_name) {
-                        None => return err!(loc, "unknown ISA '{}'", isa_name),
-                        Some(b) => b,
+                        Err(e) => return err!(loc, "{} '{}'", e, isa_name),
+                        Ok(b) => b,
                     };
                     // Apply the ISA-specific settings to `isa_builder`.
-                    isaspec::parse_options(words, &mut isa_builder, &self.loc)?;
+                    isaspec::parse_options(words, &mut isa_builder, &loc)?;
 
                     // Construct a trait object with the aggregrate settings.
                     isas.push(isa_builder.finish(settings::Flags::new(&flag_builder)));
@@ -516,6 +751,9 @@ impl<'a> Parser<'a> {
             err!(loc,
                  "dangling 'set' command after ISA specification has no effect.")
         } else {
+            if isas.len() == 1 {
+                self.reginfo = Some(isas[0].register_info());
+            }
             Ok(isaspec::IsaSpec::Some(isas))
         }
     }
@@ -561,6 +799,7 @@ impl<'a> Parser<'a> {
 
         // Rewrite references to values and EBBs after parsing everything to allow forward
         // references.
+        ctx.resolve_aliases()?;
         ctx.rewrite_references()?;
 
         let details = Details {
@@ -576,12 +815,12 @@ impl<'a> Parser<'a> {
     //
     // function-spec ::= * "function" name signature
     //
-    fn parse_function_spec(&mut self) -> Result<(Location, FunctionName, Signature)> {
+    fn parse_function_spec(&mut self) -> Result<(Location, ExternalName, Signature)> {
         self.match_identifier("function", "expected 'function'")?;
         let location = self.loc;
 
         // function-spec ::= "function" * name signature
-        let name = self.parse_function_name()?;
+        let name = self.parse_external_name()?;
 
         // function-spec ::= "function" name * signature
         let sig = self.parse_signature()?;
@@ -589,17 +828,39 @@ impl<'a> Parser<'a> {
         Ok((location, name, sig))
     }
 
-    // Parse a function name.
+    // Parse an external name.
     //
     // function ::= "function" * name signature { ... }
     //
-    fn parse_function_name(&mut self) -> Result<FunctionName> {
+    // name ::= * Identifier
+    //        | * "u" namespace ":" index
+    //        | * "%" libcall
+    //
+    fn parse_external_name(&mut self) -> Result<ExternalName> {
         match self.token() {
+            Some(Token::Name(s)) => {
+                self.consume();
+                s.parse()
+                    .map(ExternalName::libcall)
+                    .map_err(|_| self.error("unknown runtime library function"))
+            }
             Some(Token::Identifier(s)) => {
                 self.consume();
-                Ok(FunctionName::new(s))
+                // A user-defined name looks like `u0:1`. Other identifiers are test case names.
+                let mut chars = s.chars();
+                let namespace = match chars.next() {
+                    Some('u') => chars.as_str().parse().ok(),
+                    _ => None,
+                };
+                match namespace {
+                    Some(namespace) if self.optional(Token::Colon) => {
+                        let index = self.match_uimm32("expected index in user-defined name")?;
+                        Ok(ExternalName::user(namespace, index))
+                    }
+                    _ => Ok(ExternalName::testcase(s)),
+                }
             }
-            _ => err!(self.loc, "expected function name"),
+            _ => err!(self.loc, "expected external name"),
         }
     }
 
@@ -620,7 +881,13 @@ impl<'a> Parser<'a> {
             sig.return_types = self.parse_argument_list()?;
         }
 
-        // TBD: calling convention.
+        // signature ::=  "(" [arglist] ")" ["->" retlist] * [call_conv]
+        if let Some(Token::Identifier(text)) = self.token() {
+            if let Ok(call_conv) = text.parse() {
+                sig.call_conv = call_conv;
+                self.consume();
+            }
+        }
 
         Ok(sig)
     }
@@ -655,6 +922,10 @@ impl<'a> Parser<'a> {
                 "uext" => arg.extension = ArgumentExtension::Uext,
                 "sext" => arg.extension = ArgumentExtension::Sext,
                 "inreg" => arg.inreg = true,
+                "sret" => arg.purpose = ArgumentPurpose::StructReturn,
+                "link" => arg.purpose = ArgumentPurpose::Link,
+                "vmctx" => arg.purpose = ArgumentPurpose::VMContext,
+                "csr" => arg.purpose = ArgumentPurpose::CalleeSaved,
                 _ => break,
             }
             self.consume();
@@ -670,6 +941,9 @@ impl<'a> Parser<'a> {
     //                   * function-decl
     //                   * signature-decl
     //                   * jump-table-decl
+    //                   * heap-decl
+    //                   * global-var-decl
+    //                   * stack-limit-decl
     //
     // The parsed decls are added to `ctx` rather than returned.
     fn parse_preamble(&mut self, ctx: &mut Context) -> Result<()> {
@@ -677,8 +951,9 @@ impl<'a> Parser<'a> {
             match self.token() {
                 Some(Token::StackSlot(..)) => {
                     self.gather_comments(ctx.function.stack_slots.next_key());
+                    let loc = self.loc;
                     self.parse_stack_slot_decl()
-                        .and_then(|(num, dat)| ctx.add_ss(num, dat, &self.loc))
+                        .and_then(|(num, dat)| ctx.add_ss(num, dat, &loc))
                 }
                 Some(Token::SigRef(..)) => {
                     self.gather_comments(ctx.function.dfg.signatures.next_key());
@@ -695,6 +970,18 @@ impl<'a> Parser<'a> {
                     self.parse_jump_table_decl()
                         .and_then(|(num, dat)| ctx.add_jt(num, dat, &self.loc))
                 }
+                Some(Token::Heap(..)) => {
+                    self.gather_comments(ctx.function.heaps.next_key());
+                    self.parse_heap_decl()
+                        .and_then(|(num, dat)| ctx.add_heap(num, dat, &self.loc))
+                }
+                Some(Token::GlobalVar(..)) => {
+                    self.gather_comments(ctx.function.dfg.global_vars.next_key());
+                    let loc = self.loc;
+                    self.parse_global_var_decl()
+                        .and_then(|(num, dat)| ctx.add_gv(num, dat, &loc))
+                }
+                Some(Token::Identifier("stack_limit")) => self.parse_stack_limit_decl(ctx),
                 // More to come..
                 _ => return Ok(()),
             }?;
@@ -703,23 +990,52 @@ impl<'a> Parser<'a> {
 
     // Parse a stack slot decl.
     //
-    // stack-slot-decl ::= * StackSlot(ss) "=" "stack_slot" Bytes {"," stack-slot-flag}
+    // stack-slot-decl ::= * StackSlot(ss) "=" stack-slot-kind Bytes {"," stack-slot-flag}
+    // stack-slot-kind ::= "explicit_slot"
+    //                   | "spill_slot"
+    //                   | "outgoing_arg"
     fn parse_stack_slot_decl(&mut self) -> Result<(u32, StackSlotData)> {
         let number = self.match_ss("expected stack slot number: ss«n»")?;
-        self.match_token(Token::Equal, "expected '=' in stack_slot decl")?;
-        self.match_identifier("stack_slot", "expected 'stack_slot'")?;
+        self.match_token(Token::Equal, "expected '=' in stack slot decl")?;
+        let kind = self.match_enum("expected stack slot kind")?;
 
-        // stack-slot-decl ::= StackSlot(ss) "=" "stack_slot" * Bytes {"," stack-slot-flag}
-        let bytes: i64 = self.match_imm64("expected byte-size in stack_slot decl")?.into();
+        // stack-slot-decl ::= StackSlot(ss) "=" stack-slot-kind * Bytes {"," stack-slot-flag}
+        let bytes: i64 = self.match_imm64("expected byte-size in stack slot decl")?.into();
         if bytes < 0 {
             return err!(self.loc, "negative stack slot size");
         }
         if bytes > u32::MAX as i64 {
             return err!(self.loc, "stack slot too large");
         }
-        let data = StackSlotData::new(bytes as u32);
+        let mut data = StackSlotData::new(kind, bytes as u32);
+
+        // stack-slot-decl ::= StackSlot(ss) "=" stack-slot-kind Bytes * {"," stack-slot-flag}
+        while self.optional(Token::Comma) {
+            match self.token() {
+                // stack-slot-flag ::= * "align" "=" Bytes
+                Some(Token::Identifier("align")) => {
+                    self.consume();
+                    self.match_token(Token::Equal, "expected '=' after 'align'")?;
+                    let align: i64 = self.match_imm64("expected alignment in bytes")?.into();
+                    if align <= 0 || align > u32::MAX as i64 || (align & (align - 1)) != 0 {
+                        return err!(self.loc, "stack slot alignment must be a power of two");
+                    }
+                    data.align = Some(align as u32);
+                }
+                // stack-slot-flag ::= * "offset" "=" Bytes
+                Some(Token::Identifier("offset")) => {
+                    self.consume();
+                    self.match_token(Token::Equal, "expected '=' after 'offset'")?;
+                    let offset: i64 = self.match_imm64("expected offset in bytes")?.into();
+                    if offset < 0 || offset > u32::MAX as i64 {
+                        return err!(self.loc, "invalid stack slot offset");
+                    }
+                    data.offset = offset as u32;
+                }
+                _ => return err!(self.loc, "expected stack slot flag"),
+            }
+        }
 
-        // TBD: stack-slot-decl ::= StackSlot(ss) "=" "stack_slot" Bytes * {"," stack-slot-flag}
         Ok((number, data))
     }
 
@@ -739,8 +1055,9 @@ impl<'a> Parser<'a> {
     //
     // Two variants:
     //
-    // function-decl ::= FuncRef(fnref) "=" function-spec
-    //                   FuncRef(fnref) "=" SigRef(sig) name
+    // function-decl ::= FuncRef(fnref) "=" function-spec { function-flag }
+    //                   FuncRef(fnref) "=" SigRef(sig) name { function-flag }
+    // function-flag ::= "cold" | "noreturn" | "colocated"
     //
     // The first variant allocates a new signature reference. The second references an existing
     // signature which must be declared first.
@@ -749,27 +1066,33 @@ impl<'a> Parser<'a> {
         let number = self.match_fn("expected function number: fn«n»")?;
         self.match_token(Token::Equal, "expected '=' in function decl")?;
 
-        let data = match self.token() {
+        let mut data = match self.token() {
             Some(Token::Identifier("function")) => {
                 let (loc, name, sig) = self.parse_function_spec()?;
                 let sigref = ctx.function.dfg.signatures.push(sig);
                 ctx.map.def_entity(sigref.into(), &loc).expect("duplicate SigRef entities created");
-                ExtFuncData {
-                    name: name,
-                    signature: sigref,
-                }
+                ExtFuncData::new(name, sigref)
             }
             Some(Token::SigRef(sig_src)) => {
                 let sig = ctx.get_sig(sig_src, &self.loc)?;
                 self.consume();
-                let name = self.parse_function_name()?;
-                ExtFuncData {
-                    name: name,
-                    signature: sig,
-                }
+                let name = self.parse_external_name()?;
+                ExtFuncData::new(name, sig)
             }
             _ => return err!(self.loc, "expected 'function' or sig«n» in function decl"),
         };
+
+        // function-decl ::= FuncRef(fnref) "=" ... * { function-flag }
+        while let Some(Token::Identifier(s)) = self.token() {
+            match s {
+                "cold" => data.cold = true,
+                "noreturn" => data.noreturn = true,
+                "colocated" => data.colocated = true,
+                _ => break,
+            }
+            self.consume();
+        }
+
         Ok((number, data))
     }
 
@@ -796,6 +1119,48 @@ impl<'a> Parser<'a> {
         err!(self.loc, "jump_table too long")
     }
 
+    // Parse a heap decl.
+    //
+    // heap-decl ::= * Heap(heap) "=" "heap" name
+    fn parse_heap_decl(&mut self) -> Result<(u32, HeapData)> {
+        let number = self.match_heap("expected heap number: heap«n»")?;
+        self.match_token(Token::Equal, "expected '=' in heap decl")?;
+        self.match_identifier("heap", "expected 'heap'")?;
+        let name = self.parse_external_name()?;
+        Ok((number, HeapData::new(name)))
+    }
+
+    // Parse a global variable decl.
+    //
+    // global-var-decl ::= * GlobalVar(gv) "=" "globalsym" name [ "colocated" ]
+    fn parse_global_var_decl(&mut self) -> Result<(u32, GlobalVarData)> {
+        let number = self.match_gv("expected global variable number: gv«n»")?;
+        self.match_token(Token::Equal, "expected '=' in global variable decl")?;
+        self.match_identifier("globalsym", "expected 'globalsym'")?;
+        let name = self.parse_external_name()?;
+        let mut data = GlobalVarData::new(name);
+        if let Some(Token::Identifier("colocated")) = self.token() {
+            self.consume();
+            data.colocated = true;
+        }
+        Ok((number, data))
+    }
+
+    // Parse the stack limit decl. The global variable must be declared before it.
+    //
+    // stack-limit-decl ::= * "stack_limit" "=" GlobalVar(gv)
+    fn parse_stack_limit_decl(&mut self, ctx: &mut Context) -> Result<()> {
+        self.match_identifier("stack_limit", "expected 'stack_limit'")?;
+        self.match_token(Token::Equal, "expected '=' in stack limit decl")?;
+        let gv = self.match_gv("expected global variable number: gv«n»")
+            .and_then(|num| ctx.get_gv(num, &self.loc))?;
+        if ctx.function.stack_limit.is_some() {
+            return err!(self.loc, "duplicate stack limit");
+        }
+        ctx.function.stack_limit = Some(gv);
+        Ok(())
+    }
+
     // jt-entry ::= * Ebb(dest) | "0"
     fn parse_jump_table_entry(&mut self) -> Result<Option<Ebb>> {
         match self.token() {
@@ -828,7 +1193,7 @@ impl<'a> Parser<'a> {
 
     // Parse an extended basic block, add contents to `ctx`.
     //
-    // extended-basic-block ::= * ebb-header { instruction }
+    // extended-basic-block ::= * ebb-header { instruction | value-alias }
     // ebb-header           ::= Ebb(ebb) [ebb-args] ":"
     //
     fn parse_extended_basic_block(&mut self, ctx: &mut Context) -> Result<()> {
@@ -842,10 +1207,11 @@ impl<'a> Parser<'a> {
             self.match_token(Token::Colon, "expected ':' after EBB arguments")?;
         }
 
-        // extended-basic-block ::= ebb-header * { instruction }
+        // extended-basic-block ::= ebb-header * { instruction | value-alias }
         while match self.token() {
             Some(Token::Value(_)) => true,
             Some(Token::Identifier(_)) => true,
+            Some(Token::SourceLoc(_)) => true,
             _ => false,
         } {
             self.parse_instruction(ctx, ebb)?;
@@ -894,22 +1260,34 @@ impl<'a> Parser<'a> {
         ctx.map.def_value(vx, value, &vx_location)
     }
 
-    // Parse an instruction, append it to `ebb`.
+    // Parse an instruction, append it to `ebb`. Also parse value aliases which begin with a value
+    // just like instructions with results.
     //
     // instruction ::= [inst-results "="] Opcode(opc) ["." Type] ...
     // inst-results ::= Value(v) { "," Value(vx) }
+    // value-alias ::= Value(v) "->" Value(src)
     //
     fn parse_instruction(&mut self, ctx: &mut Context, ebb: Ebb) -> Result<()> {
         // Collect comments for the next instruction to be allocated.
         self.gather_comments(ctx.function.dfg.next_inst());
 
+        // instruction ::= * [srcloc] [inst-results "="] Opcode(opc) ["." Type] ...
+        let srcloc = self.optional_srcloc()?;
+
         // Result value numbers.
         let mut results = Vec::new();
 
         // instruction  ::=  * [inst-results "="] Opcode(opc) ["." Type] ...
         // inst-results ::= * Value(v) { "," Value(vx) }
         if let Some(Token::Value(v)) = self.token() {
+            let v_location = self.loc;
             self.consume();
+
+            // value-alias ::= Value(v) * "->" Value(src)
+            if self.optional(Token::Arrow) {
+                let src = self.match_value("expected value after '->' in value alias")?;
+                return ctx.add_alias(v, src, &v_location);
+            }
             results.push(v);
 
             // inst-results ::= Value(v) * { "," Value(vx) }
@@ -942,7 +1320,7 @@ impl<'a> Parser<'a> {
         };
 
         // instruction ::=  [inst-results "="] Opcode(opc) ["." Type] * ...
-        let inst_data = self.parse_inst_operands(ctx, opcode)?;
+        let inst_data = self.parse_inst_operands(ctx, opcode, explicit_ctrl_type, opcode_loc)?;
 
         // We're done parsing the instruction now.
         //
@@ -953,6 +1331,9 @@ impl<'a> Parser<'a> {
         let inst = ctx.function.dfg.make_inst(inst_data);
         let num_results = ctx.function.dfg.make_inst_results(inst, ctrl_typevar);
         ctx.function.layout.append_inst(inst, ebb);
+        if !srcloc.is_default() {
+            ctx.function.set_srcloc(inst, srcloc);
+        }
         ctx.map.def_entity(inst.into(), &opcode_loc).expect("duplicate inst references created");
 
         if results.len() != num_results {
@@ -967,7 +1348,24 @@ impl<'a> Parser<'a> {
         // holds a reference to `ctx.function`.
         self.add_values(&mut ctx.map,
                         results.into_iter(),
-                        ctx.function.dfg.inst_results(inst))
+                        ctx.function.dfg.inst_results(inst))?;
+
+        // The new results may be needed by value aliases that were declared earlier.
+        ctx.create_pending_aliases()
+    }
+
+    // Parse an optional source location like `@00c7`.
+    //
+    // Return the default source location if there is none.
+    fn optional_srcloc(&mut self) -> Result<SourceLoc> {
+        if let Some(Token::SourceLoc(text)) = self.token() {
+            let srcloc = u32::from_str_radix(text, 16)
+                .map_err(|_| self.error("invalid source location"))?;
+            self.consume();
+            Ok(SourceLoc::new(srcloc))
+        } else {
+            Ok(SourceLoc::default())
+        }
     }
 
     // Type inference for polymorphic instructions.
@@ -995,7 +1393,7 @@ impl<'a> Parser<'a> {
                     // TBD: If it is defined in another block, the type should have been specified
                     // explicitly. It is unfortunate that the correctness of IL depends on the
                     // layout of the blocks.
-                    let ctrl_src_value = inst_data.typevar_operand()
+                    let ctrl_src_value = inst_data.typevar_operand(&ctx.function.dfg.value_lists)
                         .expect("Constraints <-> Format inconsistency");
                     ctx.function.dfg.value_type(match ctx.map.get_value(ctrl_src_value) {
                         Some(v) => v,
@@ -1087,7 +1485,12 @@ impl<'a> Parser<'a> {
 
     // Parse the operands following the instruction opcode.
     // This depends on the format of the opcode.
-    fn parse_inst_operands(&mut self, ctx: &Context, opcode: Opcode) -> Result<InstructionData> {
+    fn parse_inst_operands(&mut self,
+                           ctx: &mut Context,
+                           opcode: Opcode,
+                           explicit_ctrl_type: Option<Type>,
+                           opcode_loc: Location)
+                           -> Result<InstructionData> {
         Ok(match opcode.format() {
             InstructionFormat::Nullary => {
                 InstructionData::Nullary {
@@ -1106,7 +1509,18 @@ impl<'a> Parser<'a> {
                 InstructionData::UnaryImm {
                     opcode: opcode,
                     ty: VOID,
-                    imm: self.match_imm64("expected immediate integer operand")?,
+                    imm: if explicit_ctrl_type.map(Type::lane_type) == Some(I128) {
+                        self.match_imm64_i128("expected immediate integer operand")?
+                    } else {
+                        self.match_imm64("expected immediate integer operand")?
+                    },
+                }
+            }
+            InstructionFormat::UnaryBool => {
+                InstructionData::UnaryBool {
+                    opcode: opcode,
+                    ty: VOID,
+                    imm: self.match_enum("expected immediate boolean operand")?,
                 }
             }
             InstructionFormat::UnaryIeee32 => {
@@ -1124,7 +1538,7 @@ impl<'a> Parser<'a> {
                 }
             }
             InstructionFormat::UnaryImmVector => {
-                unimplemented!();
+                return err!(self.loc, "vector immediates are not supported");
             }
             InstructionFormat::UnarySplit => {
                 InstructionData::UnarySplit {
@@ -1212,10 +1626,25 @@ impl<'a> Parser<'a> {
                 InstructionData::Jump {
                     opcode: opcode,
                     ty: VOID,
-                    data: Box::new(JumpData {
-                        destination: ebb_num,
-                        varargs: args,
-                    }),
+                    destination: ebb_num,
+                    args: value_list(&mut ctx.function.dfg.value_lists, &[], &args),
+                }
+            }
+            InstructionFormat::BranchIcmp => {
+                let cond = self.match_enum("expected intcc condition code")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let lhs = self.match_value("expected SSA value first operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let rhs = self.match_value("expected SSA value second operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let ebb_num = self.match_ebb("expected branch destination EBB")?;
+                let args = self.parse_opt_value_list()?;
+                InstructionData::BranchIcmp {
+                    opcode: opcode,
+                    ty: VOID,
+                    cond: cond,
+                    destination: ebb_num,
+                    args: value_list(&mut ctx.function.dfg.value_lists, &[lhs, rhs], &args),
                 }
             }
             InstructionFormat::Branch => {
@@ -1226,11 +1655,8 @@ impl<'a> Parser<'a> {
                 InstructionData::Branch {
                     opcode: opcode,
                     ty: VOID,
-                    data: Box::new(BranchData {
-                        arg: ctrl_arg,
-                        destination: ebb_num,
-                        varargs: args,
-                    }),
+                    destination: ebb_num,
+                    args: value_list(&mut ctx.function.dfg.value_lists, &[ctrl_arg], &args),
                 }
             }
             InstructionFormat::InsertLane => {
@@ -1283,6 +1709,43 @@ impl<'a> Parser<'a> {
                     args: [lhs, rhs],
                 }
             }
+            InstructionFormat::IntCond => {
+                let cond = self.match_enum("expected intcc condition code")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value")?;
+                InstructionData::IntCond {
+                    opcode: opcode,
+                    ty: VOID,
+                    cond: cond,
+                    arg: arg,
+                }
+            }
+            InstructionFormat::FloatCond => {
+                let cond = self.match_enum("expected floatcc condition code")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value")?;
+                InstructionData::FloatCond {
+                    opcode: opcode,
+                    ty: VOID,
+                    cond: cond,
+                    arg: arg,
+                }
+            }
+            InstructionFormat::IntSelect => {
+                let cond = self.match_enum("expected intcc condition code")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let flags = self.match_value("expected SSA value flags operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let true_arg = self.match_value("expected SSA value true operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let false_arg = self.match_value("expected SSA value false operand")?;
+                InstructionData::IntSelect {
+                    opcode: opcode,
+                    ty: VOID,
+                    cond: cond,
+                    args: [flags, true_arg, false_arg],
+                }
+            }
             InstructionFormat::Call => {
                 let func_ref = self.match_fn("expected function reference")
                     .and_then(|num| ctx.get_fn(num, &self.loc))?;
@@ -1293,10 +1756,8 @@ impl<'a> Parser<'a> {
                     opcode: opcode,
                     ty: VOID,
                     second_result: None.into(),
-                    data: Box::new(CallData {
-                        func_ref: func_ref,
-                        varargs: args,
-                    }),
+                    func_ref: func_ref,
+                    args: value_list(&mut ctx.function.dfg.value_lists, &[], &args),
                 }
             }
             InstructionFormat::IndirectCall => {
@@ -1311,19 +1772,32 @@ impl<'a> Parser<'a> {
                     opcode: opcode,
                     ty: VOID,
                     second_result: None.into(),
-                    data: Box::new(IndirectCallData {
-                        sig_ref: sig_ref,
-                        arg: callee,
-                        varargs: args,
-                    }),
+                    sig_ref: sig_ref,
+                    args: value_list(&mut ctx.function.dfg.value_lists, &[callee], &args),
+                }
+            }
+            InstructionFormat::FuncAddr => {
+                let func_ref = self.match_fn("expected function reference")
+                    .and_then(|num| ctx.get_fn(num, &self.loc))?;
+                InstructionData::FuncAddr {
+                    opcode: opcode,
+                    ty: VOID,
+                    func_ref: func_ref,
                 }
             }
             InstructionFormat::Return => {
-                let args = self.parse_value_list()?;
+                // An instruction like `safepoint` may have no arguments and be followed by an
+                // instruction defining a value, so the arguments must be on the same line.
+                let args = if self.token().is_some() &&
+                              self.loc.line_number == opcode_loc.line_number {
+                    self.parse_value_list()?
+                } else {
+                    VariableArgs::new()
+                };
                 InstructionData::Return {
                     opcode: opcode,
                     ty: VOID,
-                    data: Box::new(ReturnData { varargs: args }),
+                    args: value_list(&mut ctx.function.dfg.value_lists, &[], &args),
                 }
             }
             InstructionFormat::ReturnReg => {
@@ -1336,10 +1810,7 @@ impl<'a> Parser<'a> {
                 InstructionData::ReturnReg {
                     opcode: opcode,
                     ty: VOID,
-                    data: Box::new(ReturnRegData {
-                        arg: raddr,
-                        varargs: args,
-                    }),
+                    args: value_list(&mut ctx.function.dfg.value_lists, &[raddr], &args),
                 }
             }
             InstructionFormat::BranchTable => {
@@ -1353,6 +1824,250 @@ impl<'a> Parser<'a> {
                     table: table,
                 }
             }
+            InstructionFormat::BranchTableEntry => {
+                let index = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let base = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let imm = self.match_uimm8("expected jump table entry size")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let table = self.match_jt().and_then(|num| ctx.get_jt(num, &self.loc))?;
+                InstructionData::BranchTableEntry {
+                    opcode: opcode,
+                    ty: VOID,
+                    args: [index, base],
+                    imm: imm,
+                    table: table,
+                }
+            }
+            InstructionFormat::BranchTableBase => {
+                let table = self.match_jt().and_then(|num| ctx.get_jt(num, &self.loc))?;
+                InstructionData::BranchTableBase {
+                    opcode: opcode,
+                    ty: VOID,
+                    table: table,
+                }
+            }
+            InstructionFormat::Trap => {
+                let code = self.match_enum("expected trap code")?;
+                InstructionData::Trap {
+                    opcode: opcode,
+                    ty: VOID,
+                    code: code,
+                }
+            }
+            InstructionFormat::CondTrap => {
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let code = self.match_enum("expected trap code")?;
+                InstructionData::CondTrap {
+                    opcode: opcode,
+                    ty: VOID,
+                    arg: arg,
+                    code: code,
+                }
+            }
+            InstructionFormat::IntCondTrap => {
+                let cond = self.match_enum("expected intcc condition code")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let code = self.match_enum("expected trap code")?;
+                InstructionData::IntCondTrap {
+                    opcode: opcode,
+                    ty: VOID,
+                    cond: cond,
+                    arg: arg,
+                    code: code,
+                }
+            }
+            InstructionFormat::BinaryTrap => {
+                let lhs = self.match_value("expected SSA value first operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let rhs = self.match_value("expected SSA value second operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let code = self.match_enum("expected trap code")?;
+                InstructionData::BinaryTrap {
+                    opcode: opcode,
+                    ty: VOID,
+                    args: [lhs, rhs],
+                    code: code,
+                }
+            }
+            InstructionFormat::HeapAddr => {
+                let heap = self.match_heap("expected heap operand")
+                    .and_then(|num| ctx.get_heap(num, &self.loc))?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value heap offset")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let imm = self.match_uimm32("expected byte count")?;
+                InstructionData::HeapAddr {
+                    opcode: opcode,
+                    ty: VOID,
+                    heap: heap,
+                    arg: arg,
+                    imm: imm,
+                }
+            }
+            InstructionFormat::UnaryGlobalVar => {
+                let gv = self.match_gv("expected global variable")
+                    .and_then(|num| ctx.get_gv(num, &self.loc))?;
+                InstructionData::UnaryGlobalVar {
+                    opcode: opcode,
+                    ty: VOID,
+                    global_var: gv,
+                }
+            }
+            InstructionFormat::Load => {
+                let addr = self.match_value("expected SSA value address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let offset = self.match_offset32("expected byte offset")?;
+                let flags = self.optional_memflags()?;
+                InstructionData::Load {
+                    opcode: opcode,
+                    ty: VOID,
+                    flags: flags,
+                    arg: addr,
+                    offset: offset,
+                }
+            }
+            InstructionFormat::Store => {
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let addr = self.match_value("expected SSA value address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let offset = self.match_offset32("expected byte offset")?;
+                let flags = self.optional_memflags()?;
+                InstructionData::Store {
+                    opcode: opcode,
+                    ty: VOID,
+                    flags: flags,
+                    args: [arg, addr],
+                    offset: offset,
+                }
+            }
+            InstructionFormat::LoadComplex => {
+                let base = self.match_value("expected SSA value base address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let index = self.match_value("expected SSA value index")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let shift = self.match_uimm8("expected index shift amount")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let offset = self.match_offset32("expected byte offset")?;
+                let flags = self.optional_memflags()?;
+                InstructionData::LoadComplex {
+                    opcode: opcode,
+                    ty: VOID,
+                    data: Box::new(LoadComplexData {
+                                       flags: flags,
+                                       args: [base, index],
+                                       shift: shift,
+                                       offset: offset,
+                                   }),
+                }
+            }
+            InstructionFormat::StoreComplex => {
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let base = self.match_value("expected SSA value base address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let index = self.match_value("expected SSA value index")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let shift = self.match_uimm8("expected index shift amount")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let offset = self.match_offset32("expected byte offset")?;
+                let flags = self.optional_memflags()?;
+                InstructionData::StoreComplex {
+                    opcode: opcode,
+                    ty: VOID,
+                    data: Box::new(StoreComplexData {
+                                       flags: flags,
+                                       args: [arg, base, index],
+                                       shift: shift,
+                                       offset: offset,
+                                   }),
+                }
+            }
+            InstructionFormat::AtomicLoad => {
+                let ordering = self.match_enum("expected memory ordering")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let addr = self.match_value("expected SSA value address")?;
+                InstructionData::AtomicLoad {
+                    opcode: opcode,
+                    ty: VOID,
+                    ordering: ordering,
+                    arg: addr,
+                }
+            }
+            InstructionFormat::AtomicRmw => {
+                let ordering = self.match_enum("expected memory ordering")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let addr = self.match_value("expected SSA value address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value operand")?;
+                InstructionData::AtomicRmw {
+                    opcode: opcode,
+                    ty: VOID,
+                    ordering: ordering,
+                    args: [addr, arg],
+                }
+            }
+            InstructionFormat::AtomicCas => {
+                let ordering = self.match_enum("expected memory ordering")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let addr = self.match_value("expected SSA value address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let expected = self.match_value("expected SSA value expected operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value replacement operand")?;
+                InstructionData::AtomicCas {
+                    opcode: opcode,
+                    ty: VOID,
+                    ordering: ordering,
+                    args: [addr, expected, arg],
+                }
+            }
+            InstructionFormat::StackLoad => {
+                let ss = self.match_ss("expected stack slot operand")
+                    .and_then(|num| ctx.get_ss(num, &self.loc))?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let offset = self.match_uimm32("expected stack slot offset")?;
+                InstructionData::StackLoad {
+                    opcode: opcode,
+                    ty: VOID,
+                    stack_slot: ss,
+                    offset: offset,
+                }
+            }
+            InstructionFormat::StackStore => {
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let ss = self.match_ss("expected stack slot operand")
+                    .and_then(|num| ctx.get_ss(num, &self.loc))?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let offset = self.match_uimm32("expected stack slot offset")?;
+                InstructionData::StackStore {
+                    opcode: opcode,
+                    ty: VOID,
+                    arg: arg,
+                    stack_slot: ss,
+                    offset: offset,
+                }
+            }
+            InstructionFormat::RegMove => {
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let src = self.match_regunit("expected source register")?;
+                self.match_token(Token::Arrow, "expected '->' between registers")?;
+                let dst = self.match_regunit("expected destination register")?;
+                InstructionData::RegMove {
+                    opcode: opcode,
+                    ty: VOID,
+                    arg: arg,
+                    src: src,
+                    dst: dst,
+                }
+            }
         })
     }
 }
@@ -1360,7 +2075,7 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cretonne::ir::ArgumentExtension;
+    use cretonne::ir::{ArgumentExtension, StackSlotKind, CallConv, LibCall};
     use cretonne::ir::types;
     use cretonne::ir::entities::AnyEntity;
     use testfile::{Details, Comment};
@@ -1391,6 +2106,21 @@ mod tests {
         assert_eq!(sig2.to_string(),
                    "(i8 uext inreg, f32, f64) -> i32 sext, f64");
 
+        let sig3 = Parser::new("(i32 vmctx, i64 sret, i32 link) -> i64 sret")
+            .parse_signature()
+            .unwrap();
+        assert_eq!(sig3.argument_types[0].purpose, ArgumentPurpose::VMContext);
+        assert_eq!(sig3.to_string(), "(i32 vmctx, i64 sret, i32 link) -> i64 sret");
+        assert_eq!(sig3.call_conv, CallConv::SystemV);
+
+        let sig4 = Parser::new("(i64, f64) -> i64 windows_fastcall")
+            .parse_signature()
+            .unwrap();
+        assert_eq!(sig4.call_conv, CallConv::WindowsFastcall);
+        assert_eq!(sig4.to_string(), "(i64, f64) -> i64 windows_fastcall");
+        let sig5 = Parser::new("() system_v").parse_signature().unwrap();
+        assert_eq!(sig5.call_conv, CallConv::SystemV);
+
         // `void` is not recognized as a type by the lexer. It should not appear in files.
         assert_eq!(Parser::new("() -> void").parse_signature().unwrap_err().to_string(),
                    "1: expected argument type");
@@ -1403,8 +2133,9 @@ mod tests {
     #[test]
     fn stack_slot_decl() {
         let (func, _) = Parser::new("function foo() {
-                                       ss3 = stack_slot 13
-                                       ss1 = stack_slot 1
+                                       ss3 = explicit_slot 13
+                                       ss1 = spill_slot 1, align = 4
+                                       ss2 = outgoing_arg 8, offset = 16
                                      }")
             .parse_function()
             .unwrap();
@@ -1412,21 +2143,194 @@ mod tests {
         let mut iter = func.stack_slots.keys();
         let ss0 = iter.next().unwrap();
         assert_eq!(ss0.to_string(), "ss0");
+        assert_eq!(func.stack_slots[ss0].kind, StackSlotKind::ExplicitSlot);
         assert_eq!(func.stack_slots[ss0].size, 13);
+        assert_eq!(func.stack_slots[ss0].align, None);
         let ss1 = iter.next().unwrap();
         assert_eq!(ss1.to_string(), "ss1");
+        assert_eq!(func.stack_slots[ss1].kind, StackSlotKind::SpillSlot);
         assert_eq!(func.stack_slots[ss1].size, 1);
+        assert_eq!(func.stack_slots[ss1].align, Some(4));
+        let ss2 = iter.next().unwrap();
+        assert_eq!(func.stack_slots[ss2].kind, StackSlotKind::OutgoingArg);
+        assert_eq!(func.stack_slots[ss2].size, 8);
+        assert_eq!(func.stack_slots[ss2].offset, 16);
         assert_eq!(iter.next(), None);
 
         // Catch duplicate definitions.
         assert_eq!(Parser::new("function bar() {
-                                    ss1  = stack_slot 13
-                                    ss1  = stack_slot 1
+                                    ss1  = explicit_slot 13
+                                    ss1  = explicit_slot 1
                                 }")
                        .parse_function()
                        .unwrap_err()
                        .to_string(),
                    "3: duplicate stack slot: ss1");
+
+        // Reject unknown kinds and bad alignments.
+        assert_eq!(Parser::new("function bar() {
+                                    ss1  = stack_slot 13
+                                }")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "2: expected stack slot kind");
+        assert_eq!(Parser::new("function bar() {
+                                    ss1  = explicit_slot 13, align = 3
+                                }")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "2: stack slot alignment must be a power of two");
+        assert_eq!(Parser::new("function bar() {
+                                    ss1  = explicit_slot 13, size = 3
+                                }")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "2: expected stack slot flag");
+    }
+
+    #[test]
+    fn heap_decl() {
+        let (func, _) = Parser::new("function foo() {
+                                       heap2 = heap main
+                                       heap1 = heap other
+                                     ebb0(vx0: i32):
+                                       v1 = heap_addr.i64 heap1, vx0, 8
+                                       return
+                                     }")
+            .parse_function()
+            .unwrap();
+        let mut iter = func.heaps.keys();
+        let heap0 = iter.next().unwrap();
+        assert_eq!(func.heaps[heap0].to_string(), "heap main");
+        let heap1 = iter.next().unwrap();
+        assert_eq!(func.heaps[heap1].to_string(), "heap other");
+        assert_eq!(iter.next(), None);
+        assert!(func.to_string().contains("v0 = heap_addr.i64 heap1, vx0, 8"));
+
+        // Catch duplicate definitions and undefined references.
+        assert_eq!(Parser::new("function bar() {
+                                    heap1 = heap main
+                                    heap1 = heap other
+                                }")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "3: duplicate heap: heap1");
+        assert_eq!(Parser::new("function bar() {
+                                ebb0(vx0: i32):
+                                    v1 = heap_addr.i64 heap1, vx0, 8
+                                }")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "3: undefined heap heap1");
+    }
+
+    #[test]
+    fn global_var_decl() {
+        let (func, _) = Parser::new("function foo() {
+                                       gv2 = globalsym counter colocated
+                                       gv1 = globalsym environ
+                                       fn0 = function puts(i64) colocated
+                                     ebb0:
+                                       v1 = globalsym_addr.i64 gv1
+                                       v2 = func_addr.i64 fn0
+                                       return
+                                     }")
+            .parse_function()
+            .unwrap();
+        let mut iter = func.dfg.global_vars.keys();
+        let gv0 = iter.next().unwrap();
+        assert_eq!(func.dfg.global_vars[gv0].to_string(), "globalsym counter colocated");
+        let gv1 = iter.next().unwrap();
+        assert_eq!(func.dfg.global_vars[gv1].to_string(), "globalsym environ");
+        assert_eq!(iter.next(), None);
+        let text = func.to_string();
+        assert!(text.contains("v0 = globalsym_addr.i64 gv1"));
+        assert!(text.contains("v1 = func_addr.i64 fn0"));
+        assert!(text.contains("fn0 = sig0 puts colocated"));
+
+        // Catch duplicate definitions and undefined references.
+        assert_eq!(Parser::new("function bar() {
+                                    gv1 = globalsym a
+                                    gv1 = globalsym b
+                                }")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "3: duplicate global variable: gv1");
+        assert_eq!(Parser::new("function bar() {
+                                ebb0:
+                                    v1 = globalsym_addr.i64 gv1
+                                }")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "3: undefined global variable gv1");
+    }
+
+    #[test]
+    fn stack_limit_decl() {
+        let (func, _) = Parser::new("function foo() {
+                                       gv3 = globalsym limit
+                                       stack_limit = gv3
+                                     ebb0:
+                                       return
+                                     }")
+            .parse_function()
+            .unwrap();
+        let gv0 = func.dfg.global_vars.keys().next().unwrap();
+        assert_eq!(func.stack_limit, Some(gv0));
+        assert!(func.to_string().contains("    stack_limit = gv0\n"));
+
+        // The global variable must be declared first.
+        assert_eq!(Parser::new("function bar() {
+                                    stack_limit = gv1
+                                    gv1 = globalsym limit
+                                }")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "2: undefined global variable gv1");
+    }
+
+    #[test]
+    fn external_names() {
+        let (func, _) = Parser::new("function u0:17() {
+                                       gv0 = globalsym u1:2
+                                       fn0 = function u0:3()
+                                       fn1 = function u1()
+                                       fn2 = function %memcpy()
+                                     }")
+            .parse_function()
+            .unwrap();
+        assert_eq!(func.name, ExternalName::user(0, 17));
+        let gv0 = func.dfg.global_vars.keys().next().unwrap();
+        assert_eq!(func.dfg.global_vars[gv0].name, ExternalName::user(1, 2));
+        let mut iter = func.dfg.ext_funcs.keys();
+        let fn0 = iter.next().unwrap();
+        assert_eq!(func.dfg.ext_funcs[fn0].name, ExternalName::user(0, 3));
+        let fn1 = iter.next().unwrap();
+        assert_eq!(func.dfg.ext_funcs[fn1].name, ExternalName::testcase("u1"));
+        let fn2 = iter.next().unwrap();
+        assert_eq!(func.dfg.ext_funcs[fn2].name, ExternalName::libcall(LibCall::Memcpy));
+        assert!(func.to_string().starts_with("function u0:17() {"));
+        assert!(func.to_string().contains(" %memcpy\n"));
+
+        assert_eq!(Parser::new("function %bogus() {}")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "1: unknown runtime library function");
+
+        assert_eq!(Parser::new("function u0:x() {}")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "1: expected index in user-defined name");
     }
 
     #[test]
@@ -1451,17 +2355,104 @@ mod tests {
         assert_eq!(ebb4_args.next(), None);
     }
 
+    #[test]
+    fn float_immediates() {
+        let (func, _) = Parser::new("function floats() {
+                                     ebb0:
+                                     v0 = f32const 0x1p3
+                                     v1 = f64const sNaN
+                                     }")
+            .parse_function()
+            .unwrap();
+        let mut insts = func.layout.ebb_insts(func.layout.entry_block().unwrap());
+        match func.dfg[insts.next().unwrap()] {
+            InstructionData::UnaryIeee32 { imm, .. } => assert_eq!(imm.to_string(), "0x1.000000p3"),
+            ref data => panic!("unexpected instruction {:?}", data),
+        }
+        match func.dfg[insts.next().unwrap()] {
+            InstructionData::UnaryIeee64 { imm, .. } => assert_eq!(imm.to_string(), "sNaN:0x1"),
+            ref data => panic!("unexpected instruction {:?}", data),
+        }
+
+        let err = |text| {
+            Parser::new(text)
+                .parse_function()
+                .map(|_| ())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(err("function f() {
+                          ebb0:
+                          v0 = f32const 0x1p200
+                        }"),
+                   "3: Magnitude too large");
+        assert_eq!(err("function f() {
+                          ebb0:
+                          v0 = f32const 0x1.000001p0
+                        }"),
+                   "3: Too many significant bits");
+        assert_eq!(err("function f() {
+                          ebb0:
+                          v0 = f32const 1.5
+                        }"),
+                   "3: Float must be hexadecimal");
+    }
+
+    #[test]
+    fn value_aliases() {
+        let (func, _) = Parser::new("function aliases() {
+                                     ebb0:
+                                       vx3 -> v1
+                                       v1 = iconst.i32 3
+                                       vx4 -> vx3
+                                       vx4 -> vx3
+                                       v2 = iadd vx3, vx4
+                                     }")
+            .parse_function()
+            .unwrap();
+        let ebb0 = func.layout.entry_block().unwrap();
+        let insts: Vec<_> = func.layout.ebb_insts(ebb0).collect();
+        let v1 = func.dfg.first_result(insts[0]);
+        let args = func.dfg.inst_args(insts[1])[0].to_vec();
+        assert_eq!(args.len(), 2);
+        assert!(args[0] != v1);
+        assert_eq!(func.dfg.resolve_aliases(args[0]), v1);
+        assert_eq!(func.dfg.resolve_aliases(args[1]), v1);
+        assert_eq!(func.dfg.value_type(args[1]), types::I32);
+
+        assert_eq!(Parser::new("function bar() {
+                                ebb0:
+                                    v1 = iconst.i32 3
+                                    v2 = iconst.i32 3
+                                    vx4 -> v1
+                                    vx4 -> v2
+                                }")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "6: value vx4 is already an alias of v1");
+        assert_eq!(Parser::new("function bar() {
+                                ebb0:
+                                    vx4 -> v1
+                                    vx5 -> vx4
+                                }")
+                       .parse_function()
+                       .unwrap_err()
+                       .to_string(),
+                   "3: undefined value v1 in alias vx4 -> v1");
+    }
+
     #[test]
     fn comments() {
         let (func, Details { comments, .. }) =
             Parser::new("; before
                          function comment() { ; decl
-                            ss10  = stack_slot 13 ; stackslot.
+                            ss10  = explicit_slot 13 ; stackslot.
                             ; Still stackslot.
                             jt10 = jump_table ebb0
                             ; Jumptable
                          ebb0: ; Basic block
-                         trap ; Instruction
+                         trap user42 ; Instruction
                          } ; Trailing.
                          ; More trailing.")
                 .parse_function()
@@ -1533,5 +2524,72 @@ mod tests {
                 assert_eq!(v[0].name(), "riscv");
             }
         }
+
+        // ISA-specific flags and presets.
+        match parse_test("isa intel has_sse41=1 baseline
+                          isa riscv supports_m
+                          function foo() {}")
+            .unwrap()
+            .isa_spec {
+            IsaSpec::None(_) => panic!("Expected some ISA"),
+            IsaSpec::Some(v) => {
+                assert_eq!(v.len(), 2);
+                assert_eq!(v[0].name(), "intel");
+                assert_eq!(v[1].name(), "riscv");
+            }
+        }
+
+        // Errors in ISA flags are reported on the `isa` line.
+        assert_eq!(parse_test("isa riscv
+                               isa intel has_sse41=1 has_avx512
+                               function foo() {}")
+                       .err()
+                       .unwrap()
+                       .to_string(),
+                   "2: unknown flag 'has_avx512'");
+        assert_eq!(parse_test("set enable_float=maybe
+                               isa intel
+                               function foo() {}")
+                       .err()
+                       .unwrap()
+                       .to_string(),
+                   "1: invalid setting value: 'enable_float=maybe'");
+    }
+
+    #[test]
+    fn regmove() {
+        // Register names are resolved with the unique ISA.
+        let tf = parse_test("isa riscv
+                             function foo(i32) {
+                             ebb0(v1: i32):
+                                 regmove v1, %x10 -> %x11
+                                 return
+                             }")
+                .unwrap();
+        let func = &tf.functions[0].0;
+        let ebb = func.layout.entry_block().unwrap();
+        let inst = func.layout.ebb_insts(ebb).next().unwrap();
+        match func.dfg[inst] {
+            InstructionData::RegMove { src, dst, .. } => {
+                assert_eq!(src, 10);
+                assert_eq!(dst, 11);
+            }
+            _ => panic!("Expected a regmove"),
+        }
+
+        // Without an ISA, only register unit numbers are allowed.
+        assert_eq!(parse_test("function foo(i32) {
+                               ebb0(v1: i32):
+                                   regmove v1, %x10 -> %x11
+                               }")
+                       .err()
+                       .unwrap()
+                       .to_string(),
+                   "3: register names require a unique ISA");
+        assert!(parse_test("function foo(i32) {
+                            ebb0(v1: i32):
+                                regmove v1, 10 -> 11
+                            }")
+                    .is_ok());
     }
 }
@@ -8,7 +8,7 @@
 //! clients.
 
 use std::collections::HashMap;
-use cretonne::ir::{StackSlot, JumpTable, Ebb, Value, SigRef, FuncRef};
+use cretonne::ir::{StackSlot, JumpTable, Ebb, Value, SigRef, FuncRef, Heap, GlobalVar};
 use cretonne::ir::entities::AnyEntity;
 use error::{Result, Location};
 use lexer::split_entity_name;
@@ -22,6 +22,8 @@ pub struct SourceMap {
     signatures: HashMap<u32, SigRef>, // sigNN
     functions: HashMap<u32, FuncRef>, // fnNN
     jump_tables: HashMap<u32, JumpTable>, // jtNN
+    heaps: HashMap<u32, Heap>, // heapNN
+    global_vars: HashMap<u32, GlobalVar>, // gvNN
 
     // Store locations for entities, including instructions.
     locations: HashMap<AnyEntity, Location>,
@@ -59,6 +61,16 @@ impl SourceMap {
         self.jump_tables.get(&src_num).cloned()
     }
 
+    /// Look up a heap entity by its source number.
+    pub fn get_heap(&self, src_num: u32) -> Option<Heap> {
+        self.heaps.get(&src_num).cloned()
+    }
+
+    /// Look up a global variable entity by its source number.
+    pub fn get_gv(&self, src_num: u32) -> Option<GlobalVar> {
+        self.global_vars.get(&src_num).cloned()
+    }
+
     /// Look up an entity by source name.
     /// Returns the entity reference corresponding to `name`, if it exists.
     pub fn lookup_str(&self, name: &str) -> Option<AnyEntity> {
@@ -78,6 +90,8 @@ impl SourceMap {
             "sig" => self.get_sig(num).map(AnyEntity::SigRef),
             "fn" => self.get_fn(num).map(AnyEntity::FuncRef),
             "jt" => self.get_jt(num).map(AnyEntity::JumpTable),
+            "heap" => self.get_heap(num).map(AnyEntity::Heap),
+            "gv" => self.get_gv(num).map(AnyEntity::GlobalVar),
             _ => None,
         })
     }
@@ -141,6 +155,8 @@ pub trait MutableSourceMap {
     fn def_sig(&mut self, src_num: u32, entity: SigRef, loc: &Location) -> Result<()>;
     fn def_fn(&mut self, src_num: u32, entity: FuncRef, loc: &Location) -> Result<()>;
     fn def_jt(&mut self, src_num: u32, entity: JumpTable, loc: &Location) -> Result<()>;
+    fn def_heap(&mut self, src_num: u32, entity: Heap, loc: &Location) -> Result<()>;
+    fn def_gv(&mut self, src_num: u32, entity: GlobalVar, loc: &Location) -> Result<()>;
 
     /// Define an entity without an associated source number. This can be used for instructions
     /// whose numbers never appear in source, or implicitly defined signatures.
@@ -156,6 +172,8 @@ impl MutableSourceMap for SourceMap {
             signatures: HashMap::new(),
             functions: HashMap::new(),
             jump_tables: HashMap::new(),
+            heaps: HashMap::new(),
+            global_vars: HashMap::new(),
             locations: HashMap::new(),
         }
     }
@@ -208,6 +226,22 @@ impl MutableSourceMap for SourceMap {
         }
     }
 
+    fn def_heap(&mut self, src_num: u32, entity: Heap, loc: &Location) -> Result<()> {
+        if self.heaps.insert(src_num, entity).is_some() {
+            err!(loc, "duplicate heap: heap{}", src_num)
+        } else {
+            self.def_entity(entity.into(), loc)
+        }
+    }
+
+    fn def_gv(&mut self, src_num: u32, entity: GlobalVar, loc: &Location) -> Result<()> {
+        if self.global_vars.insert(src_num, entity).is_some() {
+            err!(loc, "duplicate global variable: gv{}", src_num)
+        } else {
+            self.def_entity(entity.into(), loc)
+        }
+    }
+
     fn def_entity(&mut self, entity: AnyEntity, loc: &Location) -> Result<()> {
         if self.locations.insert(entity, loc.clone()).is_some() {
             err!(loc, "duplicate entity: {}", entity)
@@ -224,8 +258,10 @@ mod tests {
     #[test]
     fn details() {
         let tf = parse_test("function detail() {
-                               ss10 = stack_slot 13
+                               ss10 = explicit_slot 13
                                jt10 = jump_table ebb0
+                               heap3 = heap main
+                               gv5 = globalsym table
                              ebb0(v4: i32, vx7: i32):
                                v10 = iadd v4, vx7
                              }")
@@ -236,6 +272,8 @@ mod tests {
         assert_eq!(map.lookup_str("ss1"), None);
         assert_eq!(map.lookup_str("ss10").unwrap().to_string(), "ss0");
         assert_eq!(map.lookup_str("jt10").unwrap().to_string(), "jt0");
+        assert_eq!(map.lookup_str("heap3").unwrap().to_string(), "heap0");
+        assert_eq!(map.lookup_str("gv5").unwrap().to_string(), "gv0");
         assert_eq!(map.lookup_str("ebb0").unwrap().to_string(), "ebb0");
         assert_eq!(map.lookup_str("v4").unwrap().to_string(), "vx0");
         assert_eq!(map.lookup_str("vx7").unwrap().to_string(), "vx1");
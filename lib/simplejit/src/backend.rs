@@ -0,0 +1,372 @@
+//! Defining functions and data objects in the memory of the current process.
+
+use cretonne::binemit::{Reloc, TrapTable};
+use cretonne::entity_map::EntityMap;
+use cretonne::isa::{self, TargetIsa};
+use cretonne::isa::intel::binemit::RelocKind;
+use cretonne::settings::{self, Configurable};
+use cton_module::{Backend, FuncId, DataId, FunctionDeclaration, DataDeclaration,
+                  ModuleDeclarations, DataDescription, Init, Relocation, RelocTarget,
+                  ModuleError, ModuleResult};
+use libc;
+use memory::Memory;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::ptr;
+
+/// A function or data object placed in memory.
+#[derive(Clone, Copy)]
+struct Placed {
+    ptr: *mut u8,
+    size: usize,
+}
+
+/// A `Backend` that places the functions and data objects in memory, ready to be used.
+///
+/// The code is made executable when the module is finalized. After that, the function pointers
+/// returned by `get_finalized_function()` can be called until the backend is dropped. The backend
+/// is the product of a finished module, so it can outlive the module.
+///
+/// Imported functions and data objects, and symbols that aren't declared in the module, are looked
+/// up among the symbols registered with `define_symbol()` first, and then in the current process
+/// with `dlsym()`.
+pub struct SimpleJITBackend {
+    isa: Box<TargetIsa>,
+    symbols: HashMap<String, *const u8>,
+    code_memory: Memory,
+    readonly_memory: Memory,
+    writable_memory: Memory,
+    functions: EntityMap<FuncId, Option<Placed>>,
+    traps: HashMap<FuncId, TrapTable>,
+    data_objects: EntityMap<DataId, Option<Placed>>,
+}
+
+impl SimpleJITBackend {
+    /// Create a JIT backend compiling for `isa`.
+    ///
+    /// The ISA must be Intel, and the `is_64bit` setting must match the host. The code must not be
+    /// position-independent.
+    pub fn new(isa: Box<TargetIsa>) -> SimpleJITBackend {
+        assert_eq!(isa.name(), "intel", "The JIT only supports the Intel ISA");
+        assert_eq!(isa.flags().is_64bit(),
+                   cfg!(target_pointer_width = "64"),
+                   "The JIT ISA must match the host");
+        assert!(!isa.flags().is_pic(), "The JIT doesn't support position-independent code");
+        SimpleJITBackend {
+            isa: isa,
+            symbols: HashMap::new(),
+            code_memory: Memory::executable(),
+            readonly_memory: Memory::readonly(),
+            writable_memory: Memory::writable(),
+            functions: EntityMap::new(),
+            traps: HashMap::new(),
+            data_objects: EntityMap::new(),
+        }
+    }
+
+    /// Create a JIT backend compiling for the host with the default settings.
+    pub fn native() -> SimpleJITBackend {
+        let mut flag_builder = settings::builder();
+        flag_builder.set_bool("is_64bit", cfg!(target_pointer_width = "64")).unwrap();
+        let isa = isa::lookup("intel")
+            .expect("The JIT requires the Intel ISA")
+            .finish(settings::Flags::new(&flag_builder));
+        SimpleJITBackend::new(isa)
+    }
+
+    /// Define the symbol `name` at the address `ptr`.
+    ///
+    /// This is how a program makes its own functions and data available to the JIT-compiled code.
+    pub fn define_symbol(&mut self, name: &str, ptr: *const u8) {
+        self.symbols.insert(name.to_string(), ptr);
+    }
+
+    /// Get the address of the finalized function `id`.
+    ///
+    /// Cast it to a function pointer of the right type to call it.
+    pub fn get_finalized_function(&self, id: FuncId) -> *const u8 {
+        self.functions
+            .get(id)
+            .and_then(|f| *f)
+            .expect("Function is not defined")
+            .ptr
+    }
+
+    /// Get the trap sites of the function `id`, with offsets from the function's address.
+    pub fn get_trap_table(&self, id: FuncId) -> &TrapTable {
+        self.traps.get(&id).expect("Function is not defined")
+    }
+
+    /// Get the address and size of the finalized data object `id`.
+    pub fn get_finalized_data(&self, id: DataId) -> (*mut u8, usize) {
+        let data = self.data_objects
+            .get(id)
+            .and_then(|d| *d)
+            .expect("Data object is not defined");
+        (data.ptr, data.size)
+    }
+
+    /// Look up the address of an external symbol.
+    fn lookup_symbol(&self, name: &str) -> ModuleResult<*const u8> {
+        if let Some(&ptr) = self.symbols.get(name) {
+            return Ok(ptr);
+        }
+        let c_name = CString::new(name)
+            .map_err(|_| ModuleError::Backend(format!("invalid symbol name {:?}", name)))?;
+        let ptr = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c_name.as_ptr()) };
+        if ptr.is_null() {
+            Err(ModuleError::Backend(format!("can't resolve symbol {}", name)))
+        } else {
+            Ok(ptr as *const u8)
+        }
+    }
+
+    /// Get the address of the target of a relocation in the definition at `base`.
+    fn target_address(&self,
+                      target: &RelocTarget,
+                      base: *mut u8,
+                      decls: &ModuleDeclarations)
+                      -> ModuleResult<*const u8> {
+        match *target {
+            RelocTarget::Function(id) => {
+                match self.functions.get(id).and_then(|f| *f) {
+                    Some(func) => Ok(func.ptr),
+                    None => self.lookup_symbol(&decls.get_function_decl(id).name),
+                }
+            }
+            RelocTarget::Data(id) => {
+                match self.data_objects.get(id).and_then(|d| *d) {
+                    Some(data) => Ok(data.ptr),
+                    None => self.lookup_symbol(&decls.get_data_decl(id).name),
+                }
+            }
+            RelocTarget::Symbol(ref name) => self.lookup_symbol(name),
+            RelocTarget::Internal => Ok(base),
+        }
+    }
+
+    /// Apply `relocs` to the function or data object at `base`.
+    fn apply_relocs(&self,
+                    base: *mut u8,
+                    relocs: &[Relocation],
+                    decls: &ModuleDeclarations)
+                    -> ModuleResult<()> {
+        for reloc in relocs {
+            let target = self.target_address(&reloc.target, base, decls)? as i64;
+            let at = unsafe { base.offset(reloc.offset as isize) };
+            // The internal relocations already hold the offset from the start of the function, or
+            // the final displacement if they are PC-relative.
+            let value = if reloc.target == RelocTarget::Internal {
+                if reloc.reloc == Reloc::from(RelocKind::PCRel1) ||
+                   reloc.reloc == Reloc::from(RelocKind::PCRel4) {
+                    continue;
+                }
+                target.wrapping_add(read_unaligned(at, reloc.reloc))
+            } else {
+                target.wrapping_add(reloc.addend)
+            };
+
+            if reloc.reloc == Reloc::from(RelocKind::Abs4) {
+                unsafe { ptr::write_unaligned(at as *mut u32, value as u32) };
+            } else if reloc.reloc == Reloc::from(RelocKind::Abs8) {
+                unsafe { ptr::write_unaligned(at as *mut u64, value as u64) };
+            } else if reloc.reloc == Reloc::from(RelocKind::PCRel4) {
+                let disp = value.wrapping_sub(at as i64);
+                if disp != disp as i32 as i64 {
+                    return Err(ModuleError::Backend(format!("PC-relative relocation out of range \
+                                                             at {:?}",
+                                                            at)));
+                }
+                unsafe { ptr::write_unaligned(at as *mut i32, disp as i32) };
+            } else {
+                return Err(ModuleError::Backend(format!("unsupported relocation {}",
+                                                        self.isa.reloc_names()[reloc.reloc.0 as
+                                                        usize])));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read the value currently held by an absolute relocation at `at`.
+fn read_unaligned(at: *mut u8, reloc: Reloc) -> i64 {
+    unsafe {
+        if reloc == Reloc::from(RelocKind::Abs8) {
+            ptr::read_unaligned(at as *const u64) as i64
+        } else {
+            ptr::read_unaligned(at as *const u32) as i64
+        }
+    }
+}
+
+impl Backend for SimpleJITBackend {
+    type Product = SimpleJITBackend;
+
+    fn isa(&self) -> &TargetIsa {
+        &*self.isa
+    }
+
+    fn define_function(&mut self,
+                       id: FuncId,
+                       _decl: &FunctionDeclaration,
+                       code: Vec<u8>,
+                       traps: TrapTable)
+                       -> ModuleResult<()> {
+        let ptr = self.code_memory.allocate(code.len(), 16).map_err(ModuleError::Backend)?;
+        unsafe { ptr::copy_nonoverlapping(code.as_ptr(), ptr, code.len()) };
+        *self.functions.ensure(id) = Some(Placed {
+                                              ptr: ptr,
+                                              size: code.len(),
+                                          });
+        self.traps.insert(id, traps);
+        Ok(())
+    }
+
+    fn define_data(&mut self,
+                   id: DataId,
+                   decl: &DataDeclaration,
+                   data: &DataDescription)
+                   -> ModuleResult<()> {
+        let size = data.size();
+        let memory = if decl.writable {
+            &mut self.writable_memory
+        } else {
+            &mut self.readonly_memory
+        };
+        let ptr = memory.allocate(size, 8).map_err(ModuleError::Backend)?;
+        if let Init::Bytes { ref contents } = data.init {
+            unsafe { ptr::copy_nonoverlapping(contents.as_ptr(), ptr, size) };
+        }
+        *self.data_objects.ensure(id) = Some(Placed {
+                                                 ptr: ptr,
+                                                 size: size,
+                                             });
+        Ok(())
+    }
+
+    fn finalize_function(&mut self,
+                         id: FuncId,
+                         relocs: &[Relocation],
+                         decls: &ModuleDeclarations)
+                         -> ModuleResult<()> {
+        let func = self.functions[id].expect("Function is not defined");
+        self.apply_relocs(func.ptr, relocs, decls)
+    }
+
+    fn finalize_data(&mut self,
+                     id: DataId,
+                     relocs: &[Relocation],
+                     decls: &ModuleDeclarations)
+                     -> ModuleResult<()> {
+        let data = self.data_objects[id].expect("Data object is not defined");
+        self.apply_relocs(data.ptr, relocs, decls)
+    }
+
+    /// Make the new code executable and the new read-only data read-only.
+    ///
+    /// Intel processors keep the instruction cache coherent with memory writes, so there is no
+    /// need to flush it.
+    fn publish(&mut self) {
+        self.code_memory.publish();
+        self.readonly_memory.publish();
+        self.writable_memory.publish();
+    }
+
+    fn finish(self, _decls: &ModuleDeclarations) -> SimpleJITBackend {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cretonne::Context;
+    use cretonne::ir::{Signature, ArgumentType, MemFlags, Cursor, InstBuilder, VariableArgs};
+    use cretonne::ir::types;
+    use cton_module::{Module, Linkage, DataDescription};
+    use std::mem;
+    use super::SimpleJITBackend;
+
+    fn signature() -> Signature {
+        let mut sig = Signature::new();
+        sig.return_types.push(ArgumentType::new(types::I64));
+        sig
+    }
+
+    static SYMBOL: u64 = 17;
+
+    #[test]
+    fn run() {
+        let mut module = Module::new(SimpleJITBackend::native());
+        module.backend_mut().define_symbol("symbol", &SYMBOL as *const u64 as *const u8);
+        let answer = module.declare_function("answer", Linkage::Local, &signature()).unwrap();
+        let sum = module.declare_function("sum", Linkage::Export, &signature()).unwrap();
+        let table = module.declare_data("table", Linkage::Local, false).unwrap();
+        let counter = module.declare_data("counter", Linkage::Export, true).unwrap();
+        let symbol = module.declare_data("symbol", Linkage::Import, false).unwrap();
+
+        // Return 42.
+        let mut ctx = Context::new();
+        ctx.func.signature = signature();
+        {
+            let ebb0 = ctx.func.dfg.make_ebb();
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).iconst(types::I64, 42);
+            let mut rvals = VariableArgs::new();
+            rvals.push(v0);
+            dfg.ins(pos).return_(rvals);
+        }
+        module.define_function(answer, &mut ctx).unwrap();
+
+        // Return the sum of the imported symbol's value and the data object it points to, after
+        // incrementing that.
+        let mut ctx = Context::new();
+        ctx.func.signature = signature();
+        let gv_counter = module.declare_data_in_func(counter, &mut ctx.func);
+        let gv_symbol = module.declare_data_in_func(symbol, &mut ctx.func);
+        {
+            let ebb0 = ctx.func.dfg.make_ebb();
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).globalsym_addr(types::I64, gv_counter);
+            let v1 = dfg.ins(pos).load(types::I64, MemFlags::new(), v0, 0);
+            let v2 = dfg.ins(pos).iadd_imm(v1, 1);
+            dfg.ins(pos).store(MemFlags::new(), v2, v0, 0);
+            let v3 = dfg.ins(pos).globalsym_addr(types::I64, gv_symbol);
+            let v4 = dfg.ins(pos).load(types::I64, MemFlags::new(), v3, 0);
+            let v5 = dfg.ins(pos).iadd(v2, v4);
+            let mut rvals = VariableArgs::new();
+            rvals.push(v5);
+            dfg.ins(pos).return_(rvals);
+        }
+        module.define_function(sum, &mut ctx).unwrap();
+
+        let mut data = DataDescription::zeros(16);
+        data.write_function_addr(0, answer);
+        data.write_data_addr(8, counter, 8);
+        module.define_data(table, &data).unwrap();
+        let mut contents = Box::new([0; 16]);
+        contents[0] = 3;
+        module.define_data(counter, &DataDescription::bytes(contents)).unwrap();
+
+        let jit = module.finish().unwrap();
+        let answer_ptr = jit.get_finalized_function(answer);
+        let answer_fn: extern "C" fn() -> i64 = unsafe { mem::transmute(answer_ptr) };
+        assert_eq!(answer_fn(), 42);
+
+        let sum_fn: extern "C" fn() -> i64 =
+            unsafe { mem::transmute(jit.get_finalized_function(sum)) };
+        assert_eq!(sum_fn(), 3 + 1 + 17);
+        assert_eq!(sum_fn(), 3 + 2 + 17);
+
+        let (counter_ptr, counter_size) = jit.get_finalized_data(counter);
+        assert_eq!(counter_size, 16);
+        let (table_ptr, table_size) = jit.get_finalized_data(table);
+        assert_eq!(table_size, 16);
+        let table: &[usize; 2] = unsafe { &*(table_ptr as *const [usize; 2]) };
+        assert_eq!(table[0], answer_ptr as usize);
+        assert_eq!(table[1], counter_ptr as usize + 8);
+    }
+}
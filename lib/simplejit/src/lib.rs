@@ -0,0 +1,19 @@
+//! Cretonne simple JIT library.
+//!
+//! The cton_simplejit library provides a `Backend` for the `cton_module::Module` that puts the
+//! compiled functions and data objects in the memory of the current process, so the functions can
+//! be called right away. This is handy for tests and for REPL-style embedders that compile a
+//! function and run it.
+//!
+//! Only the Intel ISA is supported, and it must match the host.
+
+#![deny(missing_docs)]
+
+extern crate cretonne;
+extern crate cton_module;
+extern crate libc;
+
+pub use backend::SimpleJITBackend;
+
+mod backend;
+mod memory;
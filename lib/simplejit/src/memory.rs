@@ -0,0 +1,149 @@
+//! Memory management for the JIT.
+//!
+//! Memory is mapped from the operating system in whole pages and handed out by bumping a pointer.
+//! The pages are writable until they are published. Then they are made read-only, or readable
+//! and executable for code, and nothing is allocated from them again. That way, no page is ever
+//! both writable and executable.
+
+use libc;
+use std::cmp;
+use std::ptr;
+
+/// A block of memory mapped from the operating system.
+struct PtrLen {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl PtrLen {
+    /// Map at least `size` bytes of writable memory.
+    fn with_size(size: usize) -> Result<PtrLen, String> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let len = (size + page_size - 1) & !(page_size - 1);
+        let ptr = unsafe {
+            libc::mmap(ptr::null_mut(),
+                       len,
+                       libc::PROT_READ | libc::PROT_WRITE,
+                       libc::MAP_PRIVATE | libc::MAP_ANON,
+                       -1,
+                       0)
+        };
+        if ptr == libc::MAP_FAILED {
+            Err(format!("can't map {} bytes of memory", len))
+        } else {
+            Ok(PtrLen {
+                   ptr: ptr as *mut u8,
+                   len: len,
+               })
+        }
+    }
+}
+
+/// A collection of memory blocks with the same protection once they are published.
+pub struct Memory {
+    /// The blocks that have been published.
+    published: Vec<PtrLen>,
+    /// The blocks that are still writable. Allocations come from the last one.
+    pending: Vec<PtrLen>,
+    /// Offset of the next free byte in the last pending block.
+    position: usize,
+    /// Protection of the published blocks.
+    prot: libc::c_int,
+}
+
+impl Memory {
+    /// Create a memory collection whose published blocks get the `prot` protection.
+    fn new(prot: libc::c_int) -> Memory {
+        Memory {
+            published: Vec::new(),
+            pending: Vec::new(),
+            position: 0,
+            prot: prot,
+        }
+    }
+
+    /// Create a memory collection for code.
+    pub fn executable() -> Memory {
+        Memory::new(libc::PROT_READ | libc::PROT_EXEC)
+    }
+
+    /// Create a memory collection for read-only data.
+    pub fn readonly() -> Memory {
+        Memory::new(libc::PROT_READ)
+    }
+
+    /// Create a memory collection for writable data.
+    pub fn writable() -> Memory {
+        Memory::new(libc::PROT_READ | libc::PROT_WRITE)
+    }
+
+    /// Allocate `size` bytes aligned to `align`, which must be a power of two.
+    ///
+    /// The memory is zeroed, and it stays writable until it is published.
+    pub fn allocate(&mut self, size: usize, align: usize) -> Result<*mut u8, String> {
+        debug_assert!(align.is_power_of_two());
+        let start = (self.position + align - 1) & !(align - 1);
+        let fits = self.pending.last().map_or(false, |block| start + size <= block.len);
+        if fits {
+            self.position = start + size;
+            return Ok((self.pending.last().unwrap().ptr as usize + start) as *mut u8);
+        }
+
+        // Start a new block. The OS aligns the mapping to a page boundary, and it can't be empty.
+        let block = PtrLen::with_size(cmp::max(size, 1))?;
+        let ptr = block.ptr;
+        self.pending.push(block);
+        self.position = size;
+        Ok(ptr)
+    }
+
+    /// Apply the final protection to the pending blocks.
+    ///
+    /// New allocations are taken from fresh blocks after this.
+    pub fn publish(&mut self) {
+        for block in self.pending.drain(..) {
+            let ptr = block.ptr as *mut libc::c_void;
+            let res = unsafe { libc::mprotect(ptr, block.len, self.prot) };
+            assert_eq!(res, 0, "mprotect failed");
+            self.published.push(block);
+        }
+        self.position = 0;
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        for block in self.published.iter().chain(&self.pending) {
+            unsafe {
+                libc::munmap(block.ptr as *mut libc::c_void, block.len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Memory;
+
+    #[test]
+    fn allocate() {
+        let mut mem = Memory::readonly();
+        let a = mem.allocate(10, 1).unwrap();
+        let b = mem.allocate(8, 8).unwrap();
+        assert_eq!(b as usize, a as usize + 16);
+        unsafe {
+            assert_eq!(*b, 0);
+            *b = 1;
+        }
+
+        // A large allocation gets its own block.
+        let c = mem.allocate(1 << 20, 16).unwrap();
+        assert_eq!(c as usize & 0xfff, 0);
+
+        // Published blocks are never reused.
+        mem.publish();
+        let d = mem.allocate(8, 8).unwrap();
+        assert!(d != a && d != b && d != c);
+        assert_eq!(d as usize & 0xfff, 0);
+    }
+}
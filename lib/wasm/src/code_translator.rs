@@ -0,0 +1,879 @@
+//! This module contains the bulk of the interesting code performing the translation between
+//! WebAssembly and Cretonne IL.
+//!
+//! The translation is done in one pass, opcode by opcode. Two main data structures are used
+//! during code translations: the value stack and the control stack. The value stack mimics the
+//! execution of the WebAssembly stack machine: each instruction result is pushed onto the stack
+//! and instruction arguments are popped off the stack. Similarly, when encountering a control
+//! flow block, it is pushed onto the control stack and popped off when encountering the
+//! corresponding `End`.
+//!
+//! The translation state also records whether the current code is reachable. Operators following
+//! an unconditional branch are skipped until the `else` or `end` that makes the code reachable
+//! again.
+//!
+//! Some of the WebAssembly instructions need information about the environment for which they
+//! are being translated:
+//!
+//! - the loads and stores need the memory base address;
+//! - the `global.get` and `global.set` instructions depend on how the globals are implemented;
+//! - `memory.size` and `memory.grow` are runtime functions;
+//! - `call_indirect` has to translate the function index into the address of the callee.
+//!
+//! That is why `translate_operator` takes an object implementing the `FuncEnvironment` trait as
+//! argument.
+
+use cretonne::ir::{self, Cursor, Ebb, InstBuilder, JumpTableData, Layout, MemFlags, TrapCode,
+                   Value, VariableArgs};
+use cretonne::ir::condcodes::{IntCC, FloatCC};
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use cretonne::ir::types::*;
+use cton_frontend::FunctionBuilder;
+use environ::{FuncEnvironment, GlobalValue, WasmError, WasmResult};
+use state::{ControlStackFrame, TranslationState};
+use translation_utils::{block_results, variable_args, Local};
+use wasmparser::{Operator, MemoryImmediate};
+
+/// Get a cursor appending instructions to `ebb`, for the environment callbacks.
+fn at_bottom<'f>(layout: &'f mut Layout, ebb: Ebb) -> Cursor<'f> {
+    let mut pos = Cursor::new(layout);
+    pos.goto_bottom(ebb);
+    pos
+}
+
+/// Translates wasm operators into Cretonne IL instructions appended to the current EBB.
+pub fn translate_operator<FE: FuncEnvironment + ?Sized>(op: &Operator,
+                                                        builder: &mut FunctionBuilder<Local>,
+                                                        state: &mut TranslationState,
+                                                        environ: &mut FE)
+                                                        -> WasmResult<()> {
+    if !state.reachable {
+        translate_unreachable_operator(op, builder, state);
+        return Ok(());
+    }
+
+    // This big match treats all Wasm code operators.
+    match *op {
+        /********************************** Locals ****************************************
+         *  `local.get` and `local.set` are treated as uses and definitions of frontend
+         *  variables. The frontend takes care of the SSA construction.
+         ***********************************************************************************/
+        Operator::LocalGet { local_index } => {
+            let val = builder.use_var(Local(local_index));
+            state.push1(val);
+        }
+        Operator::LocalSet { local_index } => {
+            let val = state.pop1();
+            builder.def_var(Local(local_index), val);
+        }
+        Operator::LocalTee { local_index } => {
+            let val = state.peek1();
+            builder.def_var(Local(local_index), val);
+        }
+        /********************************** Globals ****************************************
+         *  `global.get` and `global.set` are handled by the environment.
+         ***********************************************************************************/
+        Operator::GlobalGet { global_index } => {
+            let val = match state.get_global(builder.func, global_index, environ) {
+                GlobalValue::Const(val) => val,
+                GlobalValue::Memory { gv, ty } => {
+                    let addr = builder.ins().globalsym_addr(environ.native_pointer(), gv);
+                    builder.ins().load(ty, MemFlags::new(), addr, 0)
+                }
+            };
+            state.push1(val);
+        }
+        Operator::GlobalSet { global_index } => {
+            match state.get_global(builder.func, global_index, environ) {
+                GlobalValue::Const(_) => panic!("global #{} is a constant", global_index),
+                GlobalValue::Memory { gv, .. } => {
+                    let val = state.pop1();
+                    let addr = builder.ins().globalsym_addr(environ.native_pointer(), gv);
+                    builder.ins().store(MemFlags::new(), val, addr, 0);
+                }
+            }
+        }
+        /********************************* Stack misc ***************************************
+         *  `drop`, `nop`, `unreachable` and `select`.
+         ***********************************************************************************/
+        Operator::Drop => {
+            state.pop1();
+        }
+        Operator::Select => {
+            let (arg1, arg2, cond) = state.pop3();
+            let val = builder.ins().select(cond, arg1, arg2);
+            state.push1(val);
+        }
+        Operator::Nop => {
+            // We do nothing
+        }
+        Operator::Unreachable => {
+            builder.ins().trap(TrapCode::User(0));
+            state.reachable = false;
+        }
+        /***************************** Control flow blocks **********************************
+         *  When starting a control flow block, we create a new `Ebb` that will hold the code
+         *  after the block, and we push a frame on the control stack. The block results are
+         *  passed as arguments to that `Ebb`. A loop also gets a header `Ebb` that the branches
+         *  to the loop jump back to, and an `if` gets an `Ebb` for its `else` branch.
+         *
+         *  The `End` instruction pops the last control frame from the control stack, appends
+         *  the destination `Ebb` to the layout, and replaces the values of the block on the
+         *  value stack with the `Ebb`'s arguments.
+         ***********************************************************************************/
+        Operator::Block { ty } => {
+            let results = block_results(ty)?;
+            let next = create_ebb_with_args(builder, &results);
+            state.push_block(next, results.len());
+        }
+        Operator::Loop { ty } => {
+            let results = block_results(ty)?;
+            let loop_body = builder.create_ebb();
+            let next = create_ebb_with_args(builder, &results);
+            builder.ins().jump(loop_body, VariableArgs::new());
+            state.push_loop(loop_body, next, results.len());
+            // The loop header is sealed at the `end` since there can be branches back to it.
+            builder.switch_to_block(loop_body);
+        }
+        Operator::If { ty } => {
+            let results = block_results(ty)?;
+            let val = state.pop1();
+            let else_ebb = builder.create_ebb();
+            let next = create_ebb_with_args(builder, &results);
+            builder.ins().brz(val, else_ebb, VariableArgs::new());
+            // Since the EBBs are extended, the `then` branch simply continues in the current EBB.
+            state.push_if(else_ebb, next, results.len());
+        }
+        Operator::Else => {
+            // We jump to the destination of the `if` with the values of the `then` branch, and
+            // continue in the `else` branch.
+            let i = state.control_stack.len() - 1;
+            let (destination, return_count) = {
+                let frame = &mut state.control_stack[i];
+                frame.set_branched_to_exit();
+                (frame.following_code(), frame.num_return_values())
+            };
+            let args = variable_args(state.peekn(return_count));
+            builder.ins().jump(destination, args);
+            enter_else(builder, state);
+        }
+        Operator::End => {
+            let frame = state.control_stack.pop().expect("Control stack underflow");
+            let return_count = frame.num_return_values();
+            let args = variable_args(state.peekn(return_count));
+            builder.ins().jump(frame.following_code(), args);
+            end_frame(builder, state, frame, true);
+        }
+        /**************************** Branch instructions *********************************
+         * The branch instructions all have as arguments a target nesting level, which
+         * corresponds to how many control stack frames do we have to pop to get the
+         * destination `Ebb`.
+         *
+         * The code following `br`, `br_table`, and `return` is unreachable.
+         *
+         * The `br_table` case is much more complicated because Cretonne's `br_table` instruction
+         * does not support jump arguments like all the other branch instructions. That is why, in
+         * the case where we would use jump arguments for every other branch instructions, we
+         * need to split the critical edges leaving the `br_tables` by creating one `Ebb` per
+         * table destination; the `br_table` will point to these newly created `Ebbs` and these
+         * `Ebb`s contain only a jump instruction pointing to the final destination, this time with
+         * jump arguments.
+         ***********************************************************************************/
+        Operator::Br { relative_depth } => {
+            let (destination, args) = branch_target(state, relative_depth);
+            builder.ins().jump(destination, args);
+            state.reachable = false;
+        }
+        Operator::BrIf { relative_depth } => {
+            let val = state.pop1();
+            let (destination, args) = branch_target(state, relative_depth);
+            builder.ins().brnz(val, destination, args);
+        }
+        Operator::BrTable { ref table } => {
+            let (depths, default) = table.read_table()?;
+            let val = state.pop1();
+            let mut jt = JumpTableData::new();
+            let mut dest_ebbs: Vec<(u32, Ebb)> = Vec::new();
+            for (index, &depth) in depths.iter().enumerate() {
+                let i = state.control_stack.len() - 1 - depth as usize;
+                let ebb = match dest_ebbs.iter().find(|&&(d, _)| d == depth) {
+                    Some(&(_, ebb)) => ebb,
+                    None if state.control_stack[i].num_br_values() == 0 => {
+                        // The destination doesn't take any arguments, so the table can jump
+                        // there directly.
+                        let (destination, _) = branch_target(state, depth);
+                        dest_ebbs.push((depth, destination));
+                        destination
+                    }
+                    None => {
+                        let ebb = builder.create_ebb();
+                        dest_ebbs.push((depth, ebb));
+                        ebb
+                    }
+                };
+                jt.set_entry(index, ebb);
+            }
+            let jt = builder.create_jump_table(jt);
+            builder.ins().br_table(val, jt);
+            // Indices that are out of bounds of the table fall through to the default target.
+            let (destination, args) = branch_target(state, default);
+            builder.ins().jump(destination, args);
+            // Fill in the edge EBBs passing the arguments to destinations that need them.
+            for (depth, ebb) in dest_ebbs {
+                let (destination, args) = branch_target(state, depth);
+                if destination != ebb {
+                    builder.switch_to_block(ebb);
+                    builder.seal_block(ebb);
+                    builder.ins().jump(destination, args);
+                }
+            }
+            state.reachable = false;
+        }
+        Operator::Return => {
+            let return_count = builder.func.signature.return_types.len();
+            let args = variable_args(state.peekn(return_count));
+            builder.ins().return_(args);
+            state.reachable = false;
+        }
+        /************************************ Calls ****************************************
+         * The call instructions pop off their arguments from the stack and append their
+         * return values to it. `call_indirect` needs environment support because there is an
+         * argument referring to an index in the external functions table of the module.
+         ************************************************************************************/
+        Operator::Call { function_index } => {
+            let (fref, num_args) = state.get_direct_func(builder.func, function_index, environ);
+            let ebb = builder.current_ebb();
+            let call = environ.translate_call(&mut builder.func.dfg,
+                                              &mut at_bottom(&mut builder.func.layout, ebb),
+                                              function_index as usize,
+                                              fref,
+                                              state.peekn(num_args));
+            state.popn(num_args);
+            let results: Vec<Value> = builder.func.dfg.inst_results(call).collect();
+            state.pushn(&results);
+        }
+        Operator::CallIndirect { index, table_index } => {
+            // `index` is the index of the function's signature and `table_index` is the index of
+            // the table to search the function in.
+            let (sigref, num_args) = state.get_indirect_sig(builder.func, index, environ);
+            let callee = state.pop1();
+            let ebb = builder.current_ebb();
+            let call = environ.translate_call_indirect(&mut builder.func.dfg,
+                                                       &mut at_bottom(&mut builder.func.layout,
+                                                                      ebb),
+                                                       table_index as usize,
+                                                       sigref,
+                                                       callee,
+                                                       state.peekn(num_args));
+            state.popn(num_args);
+            let results: Vec<Value> = builder.func.dfg.inst_results(call).collect();
+            state.pushn(&results);
+        }
+        /******************************* Memory management ***********************************
+         * Memory management is handled by environment. It is usually translated into calls to
+         * special functions.
+         ************************************************************************************/
+        Operator::MemoryGrow { reserved } => {
+            // The WebAssembly MVP only supports one linear memory, but we expect the reserved
+            // argument to be a memory index.
+            let heap_index = reserved as usize;
+            let heap = state.get_heap(builder.func, reserved, environ);
+            let val = state.pop1();
+            let ebb = builder.current_ebb();
+            let result = environ.translate_grow_memory(&mut builder.func.dfg,
+                                                       &mut at_bottom(&mut builder.func.layout,
+                                                                      ebb),
+                                                       heap_index,
+                                                       heap,
+                                                       val);
+            state.push1(result);
+        }
+        Operator::MemorySize { reserved } => {
+            let heap_index = reserved as usize;
+            let heap = state.get_heap(builder.func, reserved, environ);
+            let ebb = builder.current_ebb();
+            let result = environ.translate_current_memory(&mut builder.func.dfg,
+                                                          &mut at_bottom(&mut builder.func.layout,
+                                                                         ebb),
+                                                          heap_index,
+                                                          heap);
+            state.push1(result);
+        }
+        /******************************* Load instructions ***********************************
+         * Wasm specifies an integer alignment flag but we drop it in Cretonne.
+         * The memory base address is provided by the environment.
+         ************************************************************************************/
+        Operator::I32Load8U { memarg } => {
+            translate_load(memarg, I8, Some((false, I32)), builder, state, environ)
+        }
+        Operator::I32Load16U { memarg } => {
+            translate_load(memarg, I16, Some((false, I32)), builder, state, environ)
+        }
+        Operator::I32Load8S { memarg } => {
+            translate_load(memarg, I8, Some((true, I32)), builder, state, environ)
+        }
+        Operator::I32Load16S { memarg } => {
+            translate_load(memarg, I16, Some((true, I32)), builder, state, environ)
+        }
+        Operator::I64Load8U { memarg } => {
+            translate_load(memarg, I8, Some((false, I64)), builder, state, environ)
+        }
+        Operator::I64Load16U { memarg } => {
+            translate_load(memarg, I16, Some((false, I64)), builder, state, environ)
+        }
+        Operator::I64Load8S { memarg } => {
+            translate_load(memarg, I8, Some((true, I64)), builder, state, environ)
+        }
+        Operator::I64Load16S { memarg } => {
+            translate_load(memarg, I16, Some((true, I64)), builder, state, environ)
+        }
+        Operator::I64Load32S { memarg } => {
+            translate_load(memarg, I32, Some((true, I64)), builder, state, environ)
+        }
+        Operator::I64Load32U { memarg } => {
+            translate_load(memarg, I32, Some((false, I64)), builder, state, environ)
+        }
+        Operator::I32Load { memarg } => translate_load(memarg, I32, None, builder, state, environ),
+        Operator::F32Load { memarg } => translate_load(memarg, F32, None, builder, state, environ),
+        Operator::I64Load { memarg } => translate_load(memarg, I64, None, builder, state, environ),
+        Operator::F64Load { memarg } => translate_load(memarg, F64, None, builder, state, environ),
+        /****************************** Store instructions ***********************************
+         * Wasm specifies an integer alignment flag but we drop it in Cretonne.
+         * The memory base address is provided by the environment.
+         ************************************************************************************/
+        Operator::I32Store { memarg } |
+        Operator::I64Store { memarg } |
+        Operator::F32Store { memarg } |
+        Operator::F64Store { memarg } => translate_store(memarg, None, builder, state, environ),
+        Operator::I32Store8 { memarg } |
+        Operator::I64Store8 { memarg } => {
+            translate_store(memarg, Some(I8), builder, state, environ)
+        }
+        Operator::I32Store16 { memarg } |
+        Operator::I64Store16 { memarg } => {
+            translate_store(memarg, Some(I16), builder, state, environ)
+        }
+        Operator::I64Store32 { memarg } => {
+            translate_store(memarg, Some(I32), builder, state, environ)
+        }
+        /****************************** Nullary Operators ************************************/
+        Operator::I32Const { value } => {
+            let val = builder.ins().iconst(I32, value as i64);
+            state.push1(val);
+        }
+        Operator::I64Const { value } => {
+            let val = builder.ins().iconst(I64, value);
+            state.push1(val);
+        }
+        Operator::F32Const { value } => {
+            let val = builder.ins().f32const(Ieee32::from_bits(value.bits()));
+            state.push1(val);
+        }
+        Operator::F64Const { value } => {
+            let val = builder.ins().f64const(Ieee64::from_bits(value.bits()));
+            state.push1(val);
+        }
+        /******************************* Unary Operators *************************************/
+        Operator::I32Clz | Operator::I64Clz => {
+            let arg = state.pop1();
+            let val = builder.ins().clz(arg);
+            state.push1(val);
+        }
+        Operator::I32Ctz | Operator::I64Ctz => {
+            let arg = state.pop1();
+            let val = builder.ins().ctz(arg);
+            state.push1(val);
+        }
+        Operator::I32Popcnt | Operator::I64Popcnt => {
+            let arg = state.pop1();
+            let val = builder.ins().popcnt(arg);
+            state.push1(val);
+        }
+        Operator::I64ExtendI32S => {
+            let arg = state.pop1();
+            let val = builder.ins().sextend(I64, arg);
+            state.push1(val);
+        }
+        Operator::I64ExtendI32U => {
+            let arg = state.pop1();
+            let val = builder.ins().uextend(I64, arg);
+            state.push1(val);
+        }
+        Operator::I32WrapI64 => {
+            let arg = state.pop1();
+            let val = builder.ins().ireduce(I32, arg);
+            state.push1(val);
+        }
+        Operator::F32Sqrt | Operator::F64Sqrt => {
+            let arg = state.pop1();
+            let val = builder.ins().sqrt(arg);
+            state.push1(val);
+        }
+        Operator::F32Ceil | Operator::F64Ceil => {
+            let arg = state.pop1();
+            let val = builder.ins().ceil(arg);
+            state.push1(val);
+        }
+        Operator::F32Floor | Operator::F64Floor => {
+            let arg = state.pop1();
+            let val = builder.ins().floor(arg);
+            state.push1(val);
+        }
+        Operator::F32Trunc | Operator::F64Trunc => {
+            let arg = state.pop1();
+            let val = builder.ins().trunc(arg);
+            state.push1(val);
+        }
+        Operator::F32Nearest | Operator::F64Nearest => {
+            let arg = state.pop1();
+            let val = builder.ins().nearest(arg);
+            state.push1(val);
+        }
+        Operator::F32Abs | Operator::F64Abs => {
+            let arg = state.pop1();
+            let val = builder.ins().fabs(arg);
+            state.push1(val);
+        }
+        Operator::F32Neg | Operator::F64Neg => {
+            let arg = state.pop1();
+            let val = builder.ins().fneg(arg);
+            state.push1(val);
+        }
+        Operator::F64ConvertI64U | Operator::F64ConvertI32U => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_from_uint(F64, arg);
+            state.push1(val);
+        }
+        Operator::F64ConvertI64S | Operator::F64ConvertI32S => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_from_sint(F64, arg);
+            state.push1(val);
+        }
+        Operator::F32ConvertI64S | Operator::F32ConvertI32S => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_from_sint(F32, arg);
+            state.push1(val);
+        }
+        Operator::F32ConvertI64U | Operator::F32ConvertI32U => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_from_uint(F32, arg);
+            state.push1(val);
+        }
+        Operator::F64PromoteF32 => {
+            let arg = state.pop1();
+            let val = builder.ins().fpromote(F64, arg);
+            state.push1(val);
+        }
+        Operator::F32DemoteF64 => {
+            let arg = state.pop1();
+            let val = builder.ins().fdemote(F32, arg);
+            state.push1(val);
+        }
+        Operator::I64TruncF64S | Operator::I64TruncF32S => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_to_sint(I64, arg);
+            state.push1(val);
+        }
+        Operator::I32TruncF64S | Operator::I32TruncF32S => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_to_sint(I32, arg);
+            state.push1(val);
+        }
+        Operator::I64TruncF64U | Operator::I64TruncF32U => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_to_uint(I64, arg);
+            state.push1(val);
+        }
+        Operator::I32TruncF64U | Operator::I32TruncF32U => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_to_uint(I32, arg);
+            state.push1(val);
+        }
+        Operator::I64TruncSatF64S | Operator::I64TruncSatF32S => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_to_sint_sat(I64, arg);
+            state.push1(val);
+        }
+        Operator::I32TruncSatF64S | Operator::I32TruncSatF32S => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_to_sint_sat(I32, arg);
+            state.push1(val);
+        }
+        Operator::I64TruncSatF64U | Operator::I64TruncSatF32U => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_to_uint_sat(I64, arg);
+            state.push1(val);
+        }
+        Operator::I32TruncSatF64U | Operator::I32TruncSatF32U => {
+            let arg = state.pop1();
+            let val = builder.ins().fcvt_to_uint_sat(I32, arg);
+            state.push1(val);
+        }
+        Operator::F32ReinterpretI32 => {
+            let arg = state.pop1();
+            let val = builder.ins().bitcast(F32, arg);
+            state.push1(val);
+        }
+        Operator::F64ReinterpretI64 => {
+            let arg = state.pop1();
+            let val = builder.ins().bitcast(F64, arg);
+            state.push1(val);
+        }
+        Operator::I32ReinterpretF32 => {
+            let arg = state.pop1();
+            let val = builder.ins().bitcast(I32, arg);
+            state.push1(val);
+        }
+        Operator::I64ReinterpretF64 => {
+            let arg = state.pop1();
+            let val = builder.ins().bitcast(I64, arg);
+            state.push1(val);
+        }
+        /****************************** Binary Operators ************************************/
+        Operator::I32Add | Operator::I64Add => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().iadd(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32And | Operator::I64And => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().band(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32Or | Operator::I64Or => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().bor(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32Xor | Operator::I64Xor => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().bxor(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32Shl | Operator::I64Shl => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().ishl(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32ShrS | Operator::I64ShrS => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().sshr(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32ShrU | Operator::I64ShrU => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().ushr(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32Rotl | Operator::I64Rotl => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().rotl(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32Rotr | Operator::I64Rotr => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().rotr(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::F32Add | Operator::F64Add => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().fadd(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32Sub | Operator::I64Sub => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().isub(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::F32Sub | Operator::F64Sub => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().fsub(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32Mul | Operator::I64Mul => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().imul(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::F32Mul | Operator::F64Mul => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().fmul(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::F32Div | Operator::F64Div => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().fdiv(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32DivS | Operator::I64DivS => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().sdiv(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32DivU | Operator::I64DivU => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().udiv(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32RemS | Operator::I64RemS => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().srem(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::I32RemU | Operator::I64RemU => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().urem(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::F32Min | Operator::F64Min => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().fmin(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::F32Max | Operator::F64Max => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().fmax(arg1, arg2);
+            state.push1(val);
+        }
+        Operator::F32Copysign | Operator::F64Copysign => {
+            let (arg1, arg2) = state.pop2();
+            let val = builder.ins().fcopysign(arg1, arg2);
+            state.push1(val);
+        }
+        /**************************** Comparison Operators **********************************/
+        Operator::I32LtS | Operator::I64LtS => {
+            translate_icmp(IntCC::SignedLessThan, builder, state)
+        }
+        Operator::I32LtU | Operator::I64LtU => {
+            translate_icmp(IntCC::UnsignedLessThan, builder, state)
+        }
+        Operator::I32LeS | Operator::I64LeS => {
+            translate_icmp(IntCC::SignedLessThanOrEqual, builder, state)
+        }
+        Operator::I32LeU | Operator::I64LeU => {
+            translate_icmp(IntCC::UnsignedLessThanOrEqual, builder, state)
+        }
+        Operator::I32GtS | Operator::I64GtS => {
+            translate_icmp(IntCC::SignedGreaterThan, builder, state)
+        }
+        Operator::I32GtU | Operator::I64GtU => {
+            translate_icmp(IntCC::UnsignedGreaterThan, builder, state)
+        }
+        Operator::I32GeS | Operator::I64GeS => {
+            translate_icmp(IntCC::SignedGreaterThanOrEqual, builder, state)
+        }
+        Operator::I32GeU | Operator::I64GeU => {
+            translate_icmp(IntCC::UnsignedGreaterThanOrEqual, builder, state)
+        }
+        Operator::I32Eqz | Operator::I64Eqz => {
+            let arg = state.pop1();
+            let ty = builder.func.dfg.value_type(arg);
+            let zero = builder.ins().iconst(ty, 0);
+            let val = builder.ins().icmp(IntCC::Equal, arg, zero);
+            let val = builder.ins().bint(I32, val);
+            state.push1(val);
+        }
+        Operator::I32Eq | Operator::I64Eq => translate_icmp(IntCC::Equal, builder, state),
+        Operator::F32Eq | Operator::F64Eq => translate_fcmp(FloatCC::Equal, builder, state),
+        Operator::I32Ne | Operator::I64Ne => translate_icmp(IntCC::NotEqual, builder, state),
+        Operator::F32Ne | Operator::F64Ne => translate_fcmp(FloatCC::NotEqual, builder, state),
+        Operator::F32Gt | Operator::F64Gt => translate_fcmp(FloatCC::GreaterThan, builder, state),
+        Operator::F32Ge | Operator::F64Ge => {
+            translate_fcmp(FloatCC::GreaterThanOrEqual, builder, state)
+        }
+        Operator::F32Lt | Operator::F64Lt => translate_fcmp(FloatCC::LessThan, builder, state),
+        Operator::F32Le | Operator::F64Le => {
+            translate_fcmp(FloatCC::LessThanOrEqual, builder, state)
+        }
+        _ => return Err(WasmError::Unsupported(format!("operator {:?}", op))),
+    }
+    Ok(())
+}
+
+/// Deals with a Wasm instruction located in an unreachable portion of the code. Most of them
+/// are dropped but special ones like `End` or `Else` signal the potential end of the unreachable
+/// portion so the translation state must be updated accordingly.
+fn translate_unreachable_operator(op: &Operator,
+                                  builder: &mut FunctionBuilder<Local>,
+                                  state: &mut TranslationState) {
+    match *op {
+        Operator::If { .. } |
+        Operator::Block { .. } |
+        Operator::Loop { .. } => {
+            // Nested control frames in unreachable code are skipped entirely.
+            state.unreachable_depth += 1;
+        }
+        Operator::Else => {
+            if state.unreachable_depth == 0 {
+                // The `then` branch ended with a branch, but the `else` branch is reachable.
+                enter_else(builder, state);
+            }
+        }
+        Operator::End => {
+            if state.unreachable_depth > 0 {
+                state.unreachable_depth -= 1;
+            } else {
+                let frame = state.control_stack.pop().expect("Control stack underflow");
+                end_frame(builder, state, frame, false);
+            }
+        }
+        _ => {
+            // We don't translate because this is unreachable code
+        }
+    }
+}
+
+/// Switch from the `then` branch of the innermost `if` frame to its `else` branch.
+fn enter_else(builder: &mut FunctionBuilder<Local>, state: &mut TranslationState) {
+    let i = state.control_stack.len() - 1;
+    let (else_ebb, original_stack_size) = match state.control_stack[i] {
+        ControlStackFrame::If {
+            else_ebb,
+            ref mut else_seen,
+            original_stack_size,
+            ..
+        } => {
+            *else_seen = true;
+            (else_ebb, original_stack_size)
+        }
+        _ => panic!("else not following an if"),
+    };
+    state.stack.truncate(original_stack_size);
+    // The only branch to the `else` EBB is the one at the `if`.
+    builder.switch_to_block(else_ebb);
+    builder.seal_block(else_ebb);
+    state.reachable = true;
+}
+
+/// Finish translating the control frame `frame` whose `end` was reached.
+///
+/// The `reachable_end` flag tells whether the code before the `end` falls through to the
+/// destination; the caller has already inserted the jump when it does.
+fn end_frame(builder: &mut FunctionBuilder<Local>,
+             state: &mut TranslationState,
+             frame: ControlStackFrame,
+             reachable_end: bool) {
+    let mut reachable = reachable_end || frame.exit_is_branched_to();
+    if let ControlStackFrame::If {
+               else_ebb,
+               else_seen: false,
+               destination,
+               ..
+           } = frame {
+        // An `if` without an `else` continues at the destination when its condition is false.
+        // It doesn't return any values in that case.
+        builder.switch_to_block(else_ebb);
+        builder.seal_block(else_ebb);
+        builder.ins().jump(destination, VariableArgs::new());
+        reachable = true;
+    }
+    if let ControlStackFrame::Loop { header, .. } = frame {
+        // All the branches back to the loop header have been inserted.
+        builder.seal_block(header);
+    }
+    state.stack.truncate(frame.original_stack_size());
+    let destination = frame.following_code();
+    builder.seal_block(destination);
+    if reachable {
+        builder.switch_to_block(destination);
+        state.reachable = true;
+        let args: Vec<Value> = builder.ebb_args(destination).collect();
+        state.pushn(&args);
+    } else {
+        state.reachable = false;
+    }
+}
+
+/// Find the EBB targeted by a branch to the control frame at `relative_depth`, and the arguments
+/// that the branch passes.
+///
+/// The frame is marked as branched to.
+fn branch_target(state: &mut TranslationState, relative_depth: u32) -> (Ebb, VariableArgs) {
+    let i = state.control_stack.len() - 1 - relative_depth as usize;
+    let (destination, count) = {
+        let frame = &mut state.control_stack[i];
+        frame.set_branched_to_exit();
+        (frame.br_destination(), frame.num_br_values())
+    };
+    (destination, variable_args(state.peekn(count)))
+}
+
+/// Create an EBB with one argument per type in `types`.
+fn create_ebb_with_args(builder: &mut FunctionBuilder<Local>, types: &[ir::Type]) -> Ebb {
+    let ebb = builder.create_ebb();
+    for &ty in types {
+        builder.append_ebb_arg(ebb, ty);
+    }
+    ebb
+}
+
+/// Compute the address of the linear memory access at `addr` plus the offset in `memarg`.
+///
+/// Returns the base address and the offset to use in the load or store instruction.
+fn translate_addr<FE: FuncEnvironment + ?Sized>(memarg: MemoryImmediate,
+                                                access_size: u32,
+                                                addr: Value,
+                                                builder: &mut FunctionBuilder<Local>,
+                                                state: &mut TranslationState,
+                                                environ: &mut FE)
+                                                -> (Value, i32) {
+    // The WebAssembly MVP only supports one linear memory.
+    let heap = state.get_heap(builder.func, 0, environ);
+    let base = builder.ins().heap_addr(environ.native_pointer(), heap, addr, access_size);
+    if memarg.offset <= i32::max_value() as u32 {
+        (base, memarg.offset as i32)
+    } else {
+        let base = builder.ins().iadd_imm(base, memarg.offset as i64);
+        (base, 0)
+    }
+}
+
+/// Translate a load of type `ty` from linear memory, extending the loaded value to the type in
+/// `extend` with the given signedness.
+fn translate_load<FE: FuncEnvironment + ?Sized>(memarg: MemoryImmediate,
+                                                ty: ir::Type,
+                                                extend: Option<(bool, ir::Type)>,
+                                                builder: &mut FunctionBuilder<Local>,
+                                                state: &mut TranslationState,
+                                                environ: &mut FE) {
+    let addr = state.pop1();
+    let size = ty.bits() as u32 / 8;
+    let (base, offset) = translate_addr(memarg, size, addr, builder, state, environ);
+    let val = builder.ins().load(ty, MemFlags::new(), base, offset);
+    let val = match extend {
+        None => val,
+        Some((true, wide)) => builder.ins().sextend(wide, val),
+        Some((false, wide)) => builder.ins().uextend(wide, val),
+    };
+    state.push1(val);
+}
+
+/// Translate a store to linear memory, reducing the stored value to the type `narrow` if given.
+fn translate_store<FE: FuncEnvironment + ?Sized>(memarg: MemoryImmediate,
+                                                 narrow: Option<ir::Type>,
+                                                 builder: &mut FunctionBuilder<Local>,
+                                                 state: &mut TranslationState,
+                                                 environ: &mut FE) {
+    let (addr, val) = state.pop2();
+    let val = match narrow {
+        None => val,
+        Some(ty) => builder.ins().ireduce(ty, val),
+    };
+    let size = builder.func.dfg.value_type(val).bits() as u32 / 8;
+    let (base, offset) = translate_addr(memarg, size, addr, builder, state, environ);
+    builder.ins().store(MemFlags::new(), val, base, offset);
+}
+
+fn translate_icmp(cc: IntCC, builder: &mut FunctionBuilder<Local>, state: &mut TranslationState) {
+    let (arg0, arg1) = state.pop2();
+    let val = builder.ins().icmp(cc, arg0, arg1);
+    let val = builder.ins().bint(I32, val);
+    state.push1(val);
+}
+
+fn translate_fcmp(cc: FloatCC, builder: &mut FunctionBuilder<Local>, state: &mut TranslationState) {
+    let (arg0, arg1) = state.pop2();
+    let val = builder.ins().fcmp(cc, arg0, arg1);
+    let val = builder.ins().bint(I32, val);
+    state.push1(val);
+}
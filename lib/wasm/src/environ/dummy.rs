@@ -0,0 +1,318 @@
+//! "Dummy" environment for testing wasm translation.
+
+use cretonne::ir::{self, Cursor, DataFlowGraph, InstBuilder, ExternalName};
+use cretonne::ir::types::I32;
+use cretonne::settings;
+use environ::{FuncEnvironment, ModuleEnvironment, GlobalValue, WasmResult};
+use func_translator::FuncTranslator;
+use translation_utils::{FunctionIndex, GlobalIndex, MemoryIndex, SignatureIndex, TableIndex,
+                        Global, Table, Memory, variable_args};
+
+/// Compute a `ir::ExternalName` for a given wasm function index.
+pub fn get_func_name(func_index: FunctionIndex) -> ExternalName {
+    ExternalName::user(0, func_index as u32)
+}
+
+/// A collection of names under which a given entity is exported.
+pub struct Exportable<T> {
+    /// A wasm entity.
+    pub entity: T,
+
+    /// Names under which the entity is exported.
+    pub export_names: Vec<String>,
+}
+
+impl<T> Exportable<T> {
+    fn new(entity: T) -> Exportable<T> {
+        Exportable {
+            entity: entity,
+            export_names: Vec::new(),
+        }
+    }
+}
+
+/// The main state belonging to a `DummyEnvironment`. This is split out from `DummyEnvironment` so
+/// that function translation can borrow it while the environment stores the translated
+/// functions.
+pub struct DummyModuleInfo {
+    /// Compilation setting flags.
+    pub flags: settings::Flags,
+
+    /// Signatures as provided by `declare_signature`.
+    pub signatures: Vec<ir::Signature>,
+
+    /// Module and field names of imported functions as provided by `declare_func_import`.
+    pub imported_funcs: Vec<(String, String)>,
+
+    /// Functions, imported and local.
+    pub functions: Vec<Exportable<SignatureIndex>>,
+
+    /// Function bodies.
+    pub function_bodies: Vec<ir::Function>,
+
+    /// Tables as provided by `declare_table`.
+    pub tables: Vec<Exportable<Table>>,
+
+    /// Memories as provided by `declare_memory`.
+    pub memories: Vec<Exportable<Memory>>,
+
+    /// Globals as provided by `declare_global`.
+    pub globals: Vec<Exportable<Global>>,
+
+    /// The start function.
+    pub start_func: Option<FunctionIndex>,
+}
+
+impl DummyModuleInfo {
+    /// Allocates the data structures with the given flags.
+    pub fn with_flags(flags: settings::Flags) -> DummyModuleInfo {
+        DummyModuleInfo {
+            flags: flags,
+            signatures: Vec::new(),
+            imported_funcs: Vec::new(),
+            functions: Vec::new(),
+            function_bodies: Vec::new(),
+            tables: Vec::new(),
+            memories: Vec::new(),
+            globals: Vec::new(),
+            start_func: None,
+        }
+    }
+}
+
+/// This `ModuleEnvironment` implementation is a "naïve" one, doing essentially nothing and
+/// emitting placeholders when forced to. Don't try to execute code translated for this
+/// environment, essentially here for translation debug purposes.
+pub struct DummyEnvironment {
+    /// Module information.
+    pub info: DummyModuleInfo,
+
+    /// Function translation.
+    trans: FuncTranslator,
+}
+
+impl Default for DummyEnvironment {
+    /// Allocates the data structures with default flags.
+    fn default() -> DummyEnvironment {
+        DummyEnvironment::with_flags(settings::Flags::new(&settings::builder()))
+    }
+}
+
+impl DummyEnvironment {
+    /// Allocates the data structures with the given flags.
+    pub fn with_flags(flags: settings::Flags) -> DummyEnvironment {
+        DummyEnvironment {
+            info: DummyModuleInfo::with_flags(flags),
+            trans: FuncTranslator::new(),
+        }
+    }
+
+    /// Return a `DummyFuncEnvironment` for translating functions within this
+    /// `DummyEnvironment`.
+    pub fn func_env<'a>(&'a self) -> DummyFuncEnvironment<'a> {
+        DummyFuncEnvironment::new(&self.info)
+    }
+}
+
+/// The `FuncEnvironment` implementation for use by the `DummyEnvironment`.
+pub struct DummyFuncEnvironment<'dummy_environment> {
+    /// The module information the functions are translated against.
+    pub mod_info: &'dummy_environment DummyModuleInfo,
+}
+
+impl<'dummy_environment> DummyFuncEnvironment<'dummy_environment> {
+    /// Create a function environment for the module described by `mod_info`.
+    pub fn new(mod_info: &'dummy_environment DummyModuleInfo) -> Self {
+        DummyFuncEnvironment { mod_info: mod_info }
+    }
+}
+
+impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environment> {
+    fn flags(&self) -> &settings::Flags {
+        &self.mod_info.flags
+    }
+
+    fn make_global(&mut self, func: &mut ir::Function, index: GlobalIndex) -> GlobalValue {
+        // Globals live at symbolic addresses named after their index.
+        let gv = func.dfg
+            .global_vars
+            .push(ir::GlobalVarData::new(ExternalName::testcase(format!("global{}", index))));
+        GlobalValue::Memory {
+            gv: gv,
+            ty: self.mod_info.globals[index].entity.ty,
+        }
+    }
+
+    fn make_heap(&mut self, func: &mut ir::Function, index: MemoryIndex) -> ir::Heap {
+        func.heaps
+            .push(ir::HeapData::new(ExternalName::testcase(format!("memory{}", index))))
+    }
+
+    fn make_indirect_sig(&mut self, func: &mut ir::Function, index: SignatureIndex) -> ir::SigRef {
+        // A real implementation would probably change the calling convention and add `vmctx` and
+        // signature index arguments.
+        func.dfg.signatures.push(self.mod_info.signatures[index].clone())
+    }
+
+    fn make_direct_func(&mut self, func: &mut ir::Function, index: FunctionIndex) -> ir::FuncRef {
+        let sigidx = self.mod_info.functions[index].entity;
+        // A real implementation would probably add a `vmctx` argument.
+        // And maybe attempt some signature de-duplication.
+        let signature = func.dfg.signatures.push(self.mod_info.signatures[sigidx].clone());
+        let name = get_func_name(index);
+        func.dfg.ext_funcs.push(ir::ExtFuncData::new(name, signature))
+    }
+
+    fn translate_call_indirect(&mut self,
+                               dfg: &mut DataFlowGraph,
+                               pos: &mut Cursor,
+                               _table_index: TableIndex,
+                               sig_ref: ir::SigRef,
+                               callee: ir::Value,
+                               call_args: &[ir::Value])
+                               -> ir::Inst {
+        // Pretend that the table index is the address of the callee. A real implementation would
+        // load the address from the table after a bounds check and a signature check.
+        let ptr = self.native_pointer();
+        let callee = if ptr == I32 {
+            callee
+        } else {
+            dfg.ins(pos).uextend(ptr, callee)
+        };
+        dfg.ins(pos)
+            .call_indirect(sig_ref, callee, variable_args(call_args))
+    }
+
+    fn translate_grow_memory(&mut self,
+                             dfg: &mut DataFlowGraph,
+                             pos: &mut Cursor,
+                             _index: MemoryIndex,
+                             _heap: ir::Heap,
+                             _val: ir::Value)
+                             -> ir::Value {
+        // The memory never grows.
+        dfg.ins(pos).iconst(I32, -1)
+    }
+
+    fn translate_current_memory(&mut self,
+                                dfg: &mut DataFlowGraph,
+                                pos: &mut Cursor,
+                                index: MemoryIndex,
+                                _heap: ir::Heap)
+                                -> ir::Value {
+        let pages = self.mod_info.memories[index].entity.pages_count;
+        dfg.ins(pos).iconst(I32, pages as i64)
+    }
+}
+
+impl ModuleEnvironment for DummyEnvironment {
+    fn flags(&self) -> &settings::Flags {
+        &self.info.flags
+    }
+
+    fn declare_signature(&mut self, sig: &ir::Signature) {
+        self.info.signatures.push(sig.clone());
+    }
+
+    fn get_signature(&self, sig_index: SignatureIndex) -> &ir::Signature {
+        &self.info.signatures[sig_index]
+    }
+
+    fn declare_func_import(&mut self, sig_index: SignatureIndex, module: &str, field: &str) {
+        assert_eq!(self.info.functions.len(),
+                   self.info.imported_funcs.len(),
+                   "Imported functions must be declared first");
+        self.info.functions.push(Exportable::new(sig_index));
+        self.info
+            .imported_funcs
+            .push((String::from(module), String::from(field)));
+    }
+
+    fn get_num_func_imports(&self) -> usize {
+        self.info.imported_funcs.len()
+    }
+
+    fn declare_func_type(&mut self, sig_index: SignatureIndex) {
+        self.info.functions.push(Exportable::new(sig_index));
+    }
+
+    fn get_func_type(&self, func_index: FunctionIndex) -> SignatureIndex {
+        self.info.functions[func_index].entity
+    }
+
+    fn declare_global(&mut self, global: Global) {
+        self.info.globals.push(Exportable::new(global));
+    }
+
+    fn get_global(&self, global_index: GlobalIndex) -> &Global {
+        &self.info.globals[global_index].entity
+    }
+
+    fn declare_table(&mut self, table: Table) {
+        self.info.tables.push(Exportable::new(table));
+    }
+
+    fn declare_table_elements(&mut self,
+                              _table_index: TableIndex,
+                              _base: Option<GlobalIndex>,
+                              _offset: usize,
+                              _elements: Vec<FunctionIndex>) {
+        // We do nothing
+    }
+
+    fn declare_memory(&mut self, memory: Memory) {
+        self.info.memories.push(Exportable::new(memory));
+    }
+
+    fn declare_data_initialization(&mut self,
+                                   _memory_index: MemoryIndex,
+                                   _base: Option<GlobalIndex>,
+                                   _offset: usize,
+                                   _data: &[u8]) {
+        // We do nothing
+    }
+
+    fn declare_func_export(&mut self, func_index: FunctionIndex, name: &str) {
+        self.info.functions[func_index]
+            .export_names
+            .push(String::from(name));
+    }
+
+    fn declare_table_export(&mut self, table_index: TableIndex, name: &str) {
+        self.info.tables[table_index]
+            .export_names
+            .push(String::from(name));
+    }
+
+    fn declare_memory_export(&mut self, memory_index: MemoryIndex, name: &str) {
+        self.info.memories[memory_index]
+            .export_names
+            .push(String::from(name));
+    }
+
+    fn declare_global_export(&mut self, global_index: GlobalIndex, name: &str) {
+        self.info.globals[global_index]
+            .export_names
+            .push(String::from(name));
+    }
+
+    fn declare_start_func(&mut self, func_index: FunctionIndex) {
+        debug_assert!(self.info.start_func.is_none());
+        self.info.start_func = Some(func_index);
+    }
+
+    fn define_function_body(&mut self, body_bytes: &[u8]) -> WasmResult<()> {
+        let func = {
+            let mut func_environ = DummyFuncEnvironment::new(&self.info);
+            let func_index = self.info.imported_funcs.len() + self.info.function_bodies.len();
+            let name = get_func_name(func_index);
+            let sig = func_environ.mod_info.signatures[self.get_func_type(func_index)].clone();
+            let mut func = ir::Function::with_name_signature(name, sig);
+            self.trans
+                .translate(body_bytes, &mut func, &mut func_environ)?;
+            func
+        };
+        self.info.function_bodies.push(func);
+        Ok(())
+    }
+}
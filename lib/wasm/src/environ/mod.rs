@@ -0,0 +1,7 @@
+//! Support for configurable wasm translation.
+
+mod spec;
+mod dummy;
+
+pub use environ::spec::{FuncEnvironment, ModuleEnvironment, GlobalValue, WasmError, WasmResult};
+pub use environ::dummy::DummyEnvironment;
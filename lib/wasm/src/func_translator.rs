@@ -0,0 +1,287 @@
+//! Stand-alone WebAssembly to Cretonne IL translator.
+//!
+//! This module defines the `FuncTranslator` type which can translate a single WebAssembly
+//! function to Cretonne IL guided by a `FuncEnvironment` which provides information about the
+//! WebAssembly module and the runtime environment.
+
+use code_translator::translate_operator;
+use cretonne::entity_map::EntityRef;
+use cretonne::ir::{self, InstBuilder};
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use cretonne::ir::types::{I32, I64, F32, F64};
+use cton_frontend::{ILBuilder, FunctionBuilder};
+use environ::{FuncEnvironment, WasmError, WasmResult};
+use state::TranslationState;
+use translation_utils::{type_to_type, variable_args, Local};
+use wasmparser::{FunctionBody, LocalsReader, OperatorsReader};
+
+/// WebAssembly to Cretonne IL function translator.
+///
+/// A `FuncTranslator` is used to translate a binary WebAssembly function into Cretonne IL guided
+/// by a `FuncEnvironment` object. A single translator instance can be reused to translate multiple
+/// functions which will reduce heap allocation traffic.
+pub struct FuncTranslator {
+    il_builder: ILBuilder<Local>,
+    state: TranslationState,
+}
+
+impl FuncTranslator {
+    /// Create a new translator.
+    pub fn new() -> FuncTranslator {
+        FuncTranslator {
+            il_builder: ILBuilder::new(),
+            state: TranslationState::new(),
+        }
+    }
+
+    /// Translate a binary WebAssembly function.
+    ///
+    /// The `code` slice contains the binary WebAssembly *function code* as it appears in the code
+    /// section of a WebAssembly module, not including the initial size of the function code. The
+    /// slice is expected to contain two parts:
+    ///
+    /// - The declaration of *locals*, and
+    /// - The function *body* as an expression.
+    ///
+    /// See [the WebAssembly specification][wasm].
+    ///
+    /// [wasm]: https://webassembly.github.io/spec/binary/modules.html#code-section
+    ///
+    /// The Cretonne IL function `func` is expected to have the name and signature of the function
+    /// being translated, and no other contents. The WebAssembly code is assumed to be valid.
+    pub fn translate<FE: FuncEnvironment + ?Sized>(&mut self,
+                                                   code: &[u8],
+                                                   func: &mut ir::Function,
+                                                   environ: &mut FE)
+                                                   -> WasmResult<()> {
+        let body = FunctionBody::new(0, code);
+        debug_assert_eq!(func.dfg.num_ebbs(), 0, "Function must be empty");
+        debug_assert_eq!(func.dfg.num_insts(), 0, "Function must be empty");
+
+        let mut builder = FunctionBuilder::new(func, &mut self.il_builder);
+
+        // The entry EBB takes the function arguments, and the exit EBB takes the return values.
+        let entry_ebb = builder.create_ebb();
+        builder.append_ebb_args_for_function_args(entry_ebb);
+        builder.switch_to_block(entry_ebb);
+        builder.seal_block(entry_ebb);
+        let exit_ebb = builder.create_ebb();
+        let return_types: Vec<ir::Type> = builder
+            .func
+            .signature
+            .return_types
+            .iter()
+            .map(|ret| ret.value_type)
+            .collect();
+        for &ty in &return_types {
+            builder.append_ebb_arg(exit_ebb, ty);
+        }
+
+        self.state.initialize(exit_ebb, return_types.len());
+        let num_params = declare_wasm_parameters(&mut builder, entry_ebb);
+        parse_local_decls(body.get_locals_reader()?, &mut builder, num_params)?;
+        parse_function_body(body.get_operators_reader()?,
+                            &mut builder,
+                            &mut self.state,
+                            environ)?;
+        builder.finalize();
+        Ok(())
+    }
+}
+
+/// Declare locals for the WebAssembly parameters of the function, and return their number.
+///
+/// The arguments with a special purpose are added by the environment, and aren't visible to the
+/// WebAssembly code.
+fn declare_wasm_parameters(builder: &mut FunctionBuilder<Local>, entry_ebb: ir::Ebb) -> usize {
+    let args: Vec<ir::Value> = builder.ebb_args(entry_ebb).collect();
+    let mut next_local = 0;
+    for (i, &arg) in args.iter().enumerate() {
+        let (purpose, ty) = {
+            let param = &builder.func.signature.argument_types[i];
+            (param.purpose, param.value_type)
+        };
+        if purpose == ir::ArgumentPurpose::Normal {
+            let local = Local::new(next_local);
+            builder.declare_var(local, ty);
+            builder.def_var(local, arg);
+            next_local += 1;
+        }
+    }
+    next_local
+}
+
+/// Parse the local variable declarations that precede the function body.
+///
+/// The locals are numbered after the `num_params` parameters, and initialized to zero.
+fn parse_local_decls(mut reader: LocalsReader,
+                     builder: &mut FunctionBuilder<Local>,
+                     num_params: usize)
+                     -> WasmResult<()> {
+    let mut next_local = num_params;
+    for _ in 0..reader.get_count() {
+        let (count, ty) = reader.read()?;
+        let ty = type_to_type(ty)?;
+        for _ in 0..count {
+            let zero = match ty {
+                I32 | I64 => builder.ins().iconst(ty, 0),
+                F32 => builder.ins().f32const(Ieee32::from_bits(0)),
+                F64 => builder.ins().f64const(Ieee64::from_bits(0)),
+                _ => return Err(WasmError::Unsupported(format!("local of type {}", ty))),
+            };
+            let local = Local::new(next_local);
+            builder.declare_var(local, ty);
+            builder.def_var(local, zero);
+            next_local += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Parse the function body in `reader`.
+///
+/// This assumes that the local variable declarations have already been parsed and function
+/// arguments and locals are declared in the builder.
+fn parse_function_body<FE: FuncEnvironment + ?Sized>(mut reader: OperatorsReader,
+                                                     builder: &mut FunctionBuilder<Local>,
+                                                     state: &mut TranslationState,
+                                                     environ: &mut FE)
+                                                     -> WasmResult<()> {
+    // Keep going until the final `End` operator which pops the outermost block.
+    while !state.control_stack.is_empty() {
+        let op = reader.read()?;
+        translate_operator(&op, builder, state, environ)?;
+    }
+
+    // The final `End` operator left us in the exit block where we need to manually add a return
+    // instruction.
+    if state.reachable {
+        let num_returns = builder.func.signature.return_types.len();
+        let args = variable_args(state.peekn(num_returns));
+        builder.ins().return_(args);
+    }
+    state.stack.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cretonne::ir::{self, ArgumentType, ExternalName};
+    use cretonne::ir::types::I32;
+    use cretonne::verify_function;
+    use environ::DummyEnvironment;
+    use super::FuncTranslator;
+
+    /// Translate the function `body` with the given signature, and check that the result passes
+    /// the verifier.
+    fn translate(body: &[u8], args: &[ir::Type], rets: &[ir::Type]) -> ir::Function {
+        let mut trans = FuncTranslator::new();
+        let runtime = DummyEnvironment::default();
+        let mut sig = ir::Signature::new();
+        sig.argument_types
+            .extend(args.iter().map(|&ty| ArgumentType::new(ty)));
+        sig.return_types
+            .extend(rets.iter().map(|&ty| ArgumentType::new(ty)));
+        let mut func = ir::Function::with_name_signature(ExternalName::testcase("test"), sig);
+        trans
+            .translate(body, &mut func, &mut runtime.func_env())
+            .unwrap();
+        if let Err(e) = verify_function(&func) {
+            panic!("{}\n{}", func, e);
+        }
+        func
+    }
+
+    #[test]
+    fn small1() {
+        // Implicit return.
+        //
+        // (func $small1 (param i32) (result i32)
+        //     (i32.add (get_local 0) (i32.const 1))
+        // )
+        const BODY: [u8; 7] = [
+            0x00, // local decl count
+            0x20, 0x00, // get_local 0
+            0x41, 0x01, // i32.const 1
+            0x6a, // i32.add
+            0x0b, // end
+        ];
+        let func = translate(&BODY, &[I32], &[I32]);
+        assert_eq!(func.to_string(),
+                   "function test(i32) -> i32 {
+ebb0(vx0: i32):
+    v0 = iconst.i32 1
+    v1 = iadd vx0, v0
+    jump ebb1(v1)
+
+ebb1(vx1: i32):
+    return vx1
+}
+");
+    }
+
+    #[test]
+    fn small2() {
+        // Same as above, but with an explicit return instruction.
+        //
+        // (func $small2 (param i32) (result i32)
+        //     (return (i32.add (get_local 0) (i32.const 1)))
+        // )
+        const BODY: [u8; 8] = [
+            0x00, // local decl count
+            0x20, 0x00, // get_local 0
+            0x41, 0x01, // i32.const 1
+            0x6a, // i32.add
+            0x0f, // return
+            0x0b, // end
+        ];
+        let func = translate(&BODY, &[I32], &[I32]);
+        assert_eq!(func.to_string(),
+                   "function test(i32) -> i32 {
+ebb0(vx0: i32):
+    v0 = iconst.i32 1
+    v1 = iadd vx0, v0
+    return v1
+}
+");
+    }
+
+    #[test]
+    fn infloop() {
+        // An infinite loop, no return instructions.
+        //
+        // (func $infloop (result i32)
+        //     (local i32)
+        //     (loop (result i32)
+        //         (i32.add (get_local 0) (i32.const 1))
+        //         (set_local 0)
+        //         (br 0)
+        //     )
+        // )
+        const BODY: [u8; 16] = [
+            0x01, // 1 local decl.
+            0x01, 0x7f, // 1 i32 local.
+            0x03, 0x7f, // loop i32
+            0x20, 0x00, // get_local 0
+            0x41, 0x01, // i32.const 0
+            0x6a, // i32.add
+            0x21, 0x00, // set_local 0
+            0x0c, 0x00, // br 0
+            0x0b, // end
+            0x0b, // end
+        ];
+        let func = translate(&BODY, &[], &[I32]);
+        assert_eq!(func.to_string(),
+                   "function test() -> i32 {
+ebb0:
+    v0 = iconst.i32 0
+    jump ebb2(v0)
+
+ebb2(vx2: i32):
+    v2 = iconst.i32 1
+    v3 = iadd vx2, v2
+    jump ebb2(v3)
+}
+");
+    }
+}
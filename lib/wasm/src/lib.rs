@@ -0,0 +1,37 @@
+//! Performs the translation from a wasm module in binary format to the in-memory representation
+//! of the Cretonne IL. More particularly, it translates the code of all the functions bodies and
+//! interacts with an environment implementing the
+//! [`ModuleEnvironment`](trait.ModuleEnvironment.html)
+//! trait to deal with tables, globals and linear memory.
+//!
+//! The crate provides a `DummyEnvironment` struct that will allow to translate the code of the
+//! functions but will fail at execution.
+//!
+//! The main function of this module is [`translate_module`](fn.translate_module.html). The
+//! translated functions can then be compiled on multiple threads with
+//! [`compile_functions`](fn.compile_functions.html).
+
+#![deny(missing_docs)]
+
+extern crate cretonne;
+extern crate cton_frontend;
+extern crate wasmparser;
+
+#[cfg(test)]
+extern crate wat;
+
+pub use environ::{FuncEnvironment, ModuleEnvironment, GlobalValue, DummyEnvironment, WasmError,
+                  WasmResult};
+pub use func_translator::FuncTranslator;
+pub use module_translator::translate_module;
+pub use parallel::{compile_functions, CompiledFunction};
+pub use translation_utils::{FunctionIndex, GlobalIndex, MemoryIndex, SignatureIndex, TableIndex,
+                            Global, GlobalInit, Table, Memory};
+
+mod code_translator;
+mod environ;
+mod func_translator;
+mod module_translator;
+mod parallel;
+mod state;
+mod translation_utils;
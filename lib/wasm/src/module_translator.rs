@@ -0,0 +1,323 @@
+//! Translation skeleton that traverses the whole WebAssembly module and call helper functions
+//! to deal with each part of it.
+
+use environ::{ModuleEnvironment, WasmError, WasmResult};
+use translation_utils::{type_to_type, translate_signature, Global, GlobalInit, GlobalIndex,
+                        Table, Memory};
+use wasmparser::{self, ModuleReader, SectionCode, ImportSectionEntryType, ExternalKind,
+                 ElementKind, ElementItem, DataKind, InitExpr, Operator};
+
+/// Translate a sequence of bytes forming a valid Wasm binary into a list of valid Cretonne IL
+/// [`Function`](../cretonne/ir/function/struct.Function.html).
+///
+/// The module-level declarations are passed to `environ` as they are decoded, and the function
+/// bodies are handed to `ModuleEnvironment::define_function_body`, which is expected to translate
+/// them with a `FuncTranslator`.
+pub fn translate_module(data: &[u8], environ: &mut ModuleEnvironment) -> WasmResult<()> {
+    let mut reader = ModuleReader::new(data)?;
+    while !reader.eof() {
+        let section = reader.read()?;
+        match section.code {
+            SectionCode::Type => {
+                for ty in section.get_type_section_reader()? {
+                    environ.declare_signature(&translate_signature(&ty?)?);
+                }
+            }
+            SectionCode::Import => {
+                for import in section.get_import_section_reader()? {
+                    let import = import?;
+                    match import.ty {
+                        ImportSectionEntryType::Function(sig) => {
+                            environ.declare_func_import(sig as usize, import.module, import.field);
+                        }
+                        ImportSectionEntryType::Memory(ty) => {
+                            environ.declare_memory(translate_memory(ty));
+                        }
+                        ImportSectionEntryType::Global(ty) => {
+                            environ.declare_global(Global {
+                                                       ty: type_to_type(ty.content_type)?,
+                                                       mutability: ty.mutable,
+                                                       initializer: GlobalInit::Import,
+                                                   });
+                        }
+                        ImportSectionEntryType::Table(ty) => {
+                            environ.declare_table(translate_table(ty));
+                        }
+                    }
+                }
+            }
+            SectionCode::Function => {
+                for sig in section.get_function_section_reader()? {
+                    environ.declare_func_type(sig? as usize);
+                }
+            }
+            SectionCode::Table => {
+                for table in section.get_table_section_reader()? {
+                    environ.declare_table(translate_table(table?));
+                }
+            }
+            SectionCode::Memory => {
+                for memory in section.get_memory_section_reader()? {
+                    environ.declare_memory(translate_memory(memory?));
+                }
+            }
+            SectionCode::Global => {
+                for global in section.get_global_section_reader()? {
+                    let global = global?;
+                    let ty = type_to_type(global.ty.content_type)?;
+                    environ.declare_global(Global {
+                                               ty: ty,
+                                               mutability: global.ty.mutable,
+                                               initializer: translate_init_expr(&global
+                                                                                     .init_expr)?,
+                                           });
+                }
+            }
+            SectionCode::Export => {
+                for export in section.get_export_section_reader()? {
+                    let export = export?;
+                    let index = export.index as usize;
+                    match export.kind {
+                        ExternalKind::Function => environ.declare_func_export(index, export.field),
+                        ExternalKind::Table => environ.declare_table_export(index, export.field),
+                        ExternalKind::Memory => {
+                            environ.declare_memory_export(index, export.field)
+                        }
+                        ExternalKind::Global => {
+                            environ.declare_global_export(index, export.field)
+                        }
+                    }
+                }
+            }
+            SectionCode::Start => {
+                environ.declare_start_func(section.get_start_section_content()? as usize);
+            }
+            SectionCode::Element => {
+                for element in section.get_element_section_reader()? {
+                    let element = element?;
+                    let (table_index, init_expr) = match element.kind {
+                        ElementKind::Active { table_index, init_expr } => (table_index, init_expr),
+                        _ => {
+                            return Err(WasmError::Unsupported("passive elements".to_string()))
+                        }
+                    };
+                    let (base, offset) = translate_offset(&init_expr)?;
+                    let mut elements = Vec::new();
+                    for item in element.items.get_items_reader()? {
+                        match item? {
+                            ElementItem::Func(index) => elements.push(index as usize),
+                            ElementItem::Null => {
+                                return Err(WasmError::Unsupported("null elements".to_string()))
+                            }
+                        }
+                    }
+                    environ.declare_table_elements(table_index as usize, base, offset, elements);
+                }
+            }
+            SectionCode::Code => {
+                for body in section.get_code_section_reader()? {
+                    let range = body?.range();
+                    environ.define_function_body(&data[range.start..range.end])?;
+                }
+            }
+            SectionCode::Data => {
+                for segment in section.get_data_section_reader()? {
+                    let segment = segment?;
+                    let (memory_index, init_expr) = match segment.kind {
+                        DataKind::Active { memory_index, init_expr } => (memory_index, init_expr),
+                        DataKind::Passive => {
+                            return Err(WasmError::Unsupported("passive data".to_string()))
+                        }
+                    };
+                    let (base, offset) = translate_offset(&init_expr)?;
+                    environ.declare_data_initialization(memory_index as usize,
+                                                        base,
+                                                        offset,
+                                                        segment.data);
+                }
+            }
+            SectionCode::DataCount => {
+                return Err(WasmError::Unsupported("bulk memory".to_string()));
+            }
+            SectionCode::Custom { .. } => {
+                // Custom sections like the names don't affect the translation.
+            }
+        }
+    }
+    Ok(())
+}
+
+fn translate_table(ty: wasmparser::TableType) -> Table {
+    Table {
+        size: ty.limits.initial,
+        maximum: ty.limits.maximum,
+    }
+}
+
+fn translate_memory(ty: wasmparser::MemoryType) -> Memory {
+    Memory {
+        pages_count: ty.limits.initial,
+        maximum: ty.limits.maximum,
+    }
+}
+
+/// Decode the constant expression initializing a global.
+fn translate_init_expr(init_expr: &InitExpr) -> WasmResult<GlobalInit> {
+    let mut reader = init_expr.get_operators_reader();
+    let init = match reader.read()? {
+        Operator::I32Const { value } => GlobalInit::I32Const(value),
+        Operator::I64Const { value } => GlobalInit::I64Const(value),
+        Operator::F32Const { value } => GlobalInit::F32Const(value.bits()),
+        Operator::F64Const { value } => GlobalInit::F64Const(value.bits()),
+        Operator::GlobalGet { global_index } => GlobalInit::GlobalRef(global_index as usize),
+        ref op => {
+            return Err(WasmError::Unsupported(format!("initializer {:?}", op)));
+        }
+    };
+    match reader.read()? {
+        Operator::End => Ok(init),
+        ref op => Err(WasmError::Unsupported(format!("initializer {:?}", op))),
+    }
+}
+
+/// Decode the offset expression of an element or data segment.
+///
+/// Returns the index of the global added to the offset, if any, and the constant offset.
+fn translate_offset(init_expr: &InitExpr) -> WasmResult<(Option<GlobalIndex>, usize)> {
+    match translate_init_expr(init_expr)? {
+        GlobalInit::I32Const(offset) => Ok((None, offset as u32 as usize)),
+        GlobalInit::GlobalRef(index) => Ok((Some(index), 0)),
+        init => Err(WasmError::Unsupported(format!("segment offset {:?}", init))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cretonne::verify_function;
+    use environ::DummyEnvironment;
+    use super::translate_module;
+    use wat;
+
+    /// Translate the module in the text format `wat`, and check that all the functions pass the
+    /// verifier.
+    fn translate(wat: &str) -> DummyEnvironment {
+        let data = wat::parse_str(wat).unwrap();
+        let mut env = DummyEnvironment::default();
+        translate_module(&data, &mut env).unwrap();
+        for func in &env.info.function_bodies {
+            if let Err(e) = verify_function(func) {
+                panic!("{}\n{}", func, e);
+            }
+        }
+        env
+    }
+
+    #[test]
+    fn control_flow() {
+        let env = translate(r#"
+            (module
+                (func $fac (export "fac") (param i64) (result i64)
+                    (if (result i64) (i64.eqz (local.get 0))
+                        (then (i64.const 1))
+                        (else (i64.mul (local.get 0)
+                                       (call $fac (i64.sub (local.get 0) (i64.const 1)))))))
+                (func $sum (export "sum") (param i32) (result i32) (local i32)
+                    (block $done
+                        (loop $top
+                            (br_if $done (i32.eqz (local.get 0)))
+                            (local.set 1 (i32.add (local.get 1) (local.get 0)))
+                            (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                            (br $top)))
+                    (local.get 1))
+                (func $pick (param i32) (result i32)
+                    (block $b2 (result i32)
+                        (block $b1 (result i32)
+                            (block $b0 (result i32)
+                                (br_table $b0 $b1 $b2 (i32.const 10) (local.get 0)))
+                            (return (i32.const 0)))
+                        (drop)
+                        (unreachable)))
+                (func $early (param i32) (result i32)
+                    (if (local.get 0) (then (return (i32.const 1))))
+                    (block (br 0) (drop (i32.const 7)))
+                    (select (i32.const 2) (i32.const 3) (local.get 0)))
+                (func $switch (param i32) (result i32) (local i32)
+                    (block $b1
+                        (block $b0
+                            (br_table $b0 $b1 (local.get 0)))
+                        (local.set 1 (i32.const 5)))
+                    (local.get 1)))
+        "#);
+        let info = &env.info;
+        assert_eq!(info.function_bodies.len(), 5);
+        assert_eq!(info.functions[0].export_names, vec!["fac".to_string()]);
+        assert_eq!(info.functions[1].export_names, vec!["sum".to_string()]);
+        assert!(info.function_bodies[2].to_string().contains("br_table"));
+    }
+
+    #[test]
+    fn memory_and_calls() {
+        let env = translate(r#"
+            (module
+                (type $binop (func (param i32 i32) (result i32)))
+                (import "env" "print" (func $print (param i32)))
+                (import "env" "base" (global $base i32))
+                (global $counter (mut i64) (i64.const 0))
+                (memory (export "mem") 1 4)
+                (table 2 funcref)
+                (elem (i32.const 0) $add $sub)
+                (data (i32.const 16) "hello")
+                (func $add (type $binop) (i32.add (local.get 0) (local.get 1)))
+                (func $sub (type $binop) (i32.sub (local.get 0) (local.get 1)))
+                (func $main (param i32) (result f64)
+                    (i32.store8 offset=3 (local.get 0) (i32.load16_s (global.get $base)))
+                    (i64.store (i32.const 8) (i64.load32_u offset=4 (local.get 0)))
+                    (global.set $counter (i64.add (global.get $counter) (i64.const 1)))
+                    (call $print (call_indirect (type $binop)
+                                                (i32.const 1) (i32.const 2) (local.get 0)))
+                    (drop (memory.grow (memory.size)))
+                    (f64.promote_f32 (f32.convert_i32_u (local.get 0))))
+                (start $main_wrapper)
+                (func $main_wrapper (drop (call $main (i32.const 0)))))
+        "#);
+        let info = &env.info;
+        assert_eq!(info.imported_funcs,
+                   vec![("env".to_string(), "print".to_string())]);
+        assert_eq!(info.function_bodies.len(), 4);
+        assert_eq!(info.globals.len(), 2);
+        assert_eq!(info.memories[0].entity.pages_count, 1);
+        assert_eq!(info.memories[0].entity.maximum, Some(4));
+        assert_eq!(info.memories[0].export_names, vec!["mem".to_string()]);
+        assert_eq!(info.start_func, Some(4));
+
+        let main = info.function_bodies[2].to_string();
+        assert!(main.contains("heap0 = heap memory0"), "{}", main);
+        assert!(main.contains("gv0 = globalsym global0"), "{}", main);
+        assert!(main.contains("call_indirect"), "{}", main);
+        assert!(main.contains("call fn"), "{}", main);
+    }
+
+    #[test]
+    fn saturating_conversions() {
+        let env = translate(r#"
+            (module
+                (func $conv (param f32 f64) (result i32 i64)
+                    (i32.add (i32.trunc_sat_f32_s (local.get 0))
+                             (i32.trunc_sat_f64_u (local.get 1)))
+                    (i64.add (i64.trunc_sat_f64_s (local.get 1))
+                             (i64.trunc_sat_f32_u (local.get 0)))))
+        "#);
+        let conv = env.info.function_bodies[0].to_string();
+        assert!(conv.contains("fcvt_to_sint_sat.i32"), "{}", conv);
+        assert!(conv.contains("fcvt_to_uint_sat.i32"), "{}", conv);
+        assert!(conv.contains("fcvt_to_sint_sat.i64"), "{}", conv);
+        assert!(conv.contains("fcvt_to_uint_sat.i64"), "{}", conv);
+        assert!(!conv.contains("fcvt_to_sint.i"), "{}", conv);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut env = DummyEnvironment::default();
+        assert!(translate_module(b"\0asm\x02\0\0\0", &mut env).is_err());
+    }
+}
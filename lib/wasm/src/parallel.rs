@@ -0,0 +1,142 @@
+//! Compiling the functions of a module on multiple threads.
+//!
+//! The translated functions of a WebAssembly module are independent of each other, so they can be
+//! compiled concurrently. The ISA is immutable and shared by all the threads through a
+//! `Session`, and each thread compiles its functions in a context taken from the session's pool.
+//!
+//! A typical embedder translates the module with `translate_module`, and then hands the function
+//! bodies collected by its `ModuleEnvironment` to `compile_functions`:
+//!
+//! ```ignore
+//! let session = Arc::new(Session::new(isa));
+//! let mut env = DummyEnvironment::with_flags(session.flags().clone());
+//! translate_module(&data, &mut env)?;
+//! let compiled = compile_functions(&session, env.info.function_bodies, num_cpus);
+//! ```
+
+use cretonne::{binemit, CtonError, Session};
+use cretonne::ir::Function;
+use std::mem;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// A function compiled by `compile_functions`.
+pub struct CompiledFunction {
+    /// The compiled function, with the encodings and value locations assigned by the compiler.
+    pub func: Function,
+
+    /// The machine code of the function.
+    ///
+    /// Relocations are not applied, so the code refers to other functions and global data by the
+    /// placeholder values emitted for them.
+    pub code: Vec<u8>,
+}
+
+/// Compile `funcs` for the ISA of `session` on up to `threads` threads.
+///
+/// The functions are handed out to the threads one at a time, so a few large functions don't
+/// keep the other threads idle. The results are returned in the order of `funcs`.
+///
+/// # Panics
+///
+/// Panics if the compiler panics on any of the functions.
+pub fn compile_functions(session: &Arc<Session>,
+                         funcs: Vec<Function>,
+                         threads: usize)
+                         -> Vec<Result<CompiledFunction, CtonError>> {
+    let count = funcs.len();
+    let work = Arc::new(Mutex::new(funcs.into_iter().enumerate()));
+    let (tx, rx) = mpsc::channel();
+
+    let workers: Vec<_> = (0..threads.max(1).min(count))
+        .map(|_| {
+            let session = session.clone();
+            let work = work.clone();
+            let tx = tx.clone();
+            thread::spawn(move || loop {
+                              let next = work.lock().unwrap().next();
+                              let (index, func) = match next {
+                                  Some(item) => item,
+                                  None => break,
+                              };
+                              let result = compile_function(&session, func);
+                              tx.send((index, result)).unwrap();
+                          })
+        })
+        .collect();
+    drop(tx);
+
+    let mut results: Vec<_> = (0..count).map(|_| None).collect();
+    for (index, result) in rx {
+        results[index] = Some(result);
+    }
+    for worker in workers {
+        worker.join().expect("Compilation thread panicked");
+    }
+    results
+        .into_iter()
+        .map(|result| result.expect("Missing compilation result"))
+        .collect()
+}
+
+/// Compile `func` in a context from the pool of `session`.
+fn compile_function(session: &Session, func: Function) -> Result<CompiledFunction, CtonError> {
+    let isa = session.isa();
+    let mut ctx = session.context();
+    ctx.func = func;
+    let size = ctx.compile(isa)?;
+    let mut code = Vec::with_capacity(size as usize);
+    binemit::emit_function(&ctx.func, isa, &mut code);
+    Ok(CompiledFunction {
+           func: mem::replace(&mut ctx.func, Function::new()),
+           code: code,
+       })
+}
+
+#[cfg(test)]
+mod tests {
+    use cretonne::{isa, settings, Session};
+    use cretonne::settings::Configurable;
+    use environ::DummyEnvironment;
+    use module_translator::translate_module;
+    use std::sync::Arc;
+    use super::compile_functions;
+    use wat;
+
+    #[test]
+    fn compile_module() {
+        let data = wat::parse_str(r#"
+            (module
+                (func $add (param i32 i32) (result i32)
+                    (i32.add (local.get 0) (local.get 1)))
+                (func $sub (param i32 i32) (result i32)
+                    (i32.sub (local.get 0) (local.get 1)))
+                (func $xor3 (param i32 i32 i32) (result i32)
+                    (i32.xor (i32.xor (local.get 0) (local.get 1)) (local.get 2)))
+                (func $and (param i32 i32) (result i32)
+                    (i32.and (local.get 0) (local.get 1)))
+                (func $or (param i32 i32) (result i32)
+                    (i32.or (local.get 0) (local.get 1))))
+        "#)
+                .unwrap();
+        let mut flag_builder = settings::builder();
+        flag_builder.enable("is_64bit").unwrap();
+        let isa = isa::lookup("intel").unwrap().finish(settings::Flags::new(&flag_builder));
+        let session = Arc::new(Session::new(isa));
+        let mut env = DummyEnvironment::with_flags(session.flags().clone());
+        translate_module(&data, &mut env).unwrap();
+        let funcs = env.info.function_bodies;
+
+        let serial = compile_functions(&session, funcs.clone(), 1);
+        let parallel = compile_functions(&session, funcs, 3);
+        assert_eq!(parallel.len(), 5);
+        assert!(session.pooled_contexts() <= 3);
+        for (a, b) in serial.iter().zip(&parallel) {
+            let (a, b) = (a.as_ref().unwrap(), b.as_ref().unwrap());
+            assert_eq!(a.func.name, b.func.name);
+            assert!(!a.code.is_empty());
+            assert_eq!(a.code, b.code);
+        }
+        assert!(parallel[0].as_ref().unwrap().code != parallel[1].as_ref().unwrap().code);
+    }
+}
@@ -0,0 +1,364 @@
+//! WebAssembly function translation state.
+//!
+//! The `TranslationState` struct defined in this module is used to keep track of the WebAssembly
+//! value and control stacks during the translation of a single function.
+
+use cretonne::ir::{self, Ebb, Value};
+use environ::{FuncEnvironment, GlobalValue};
+use std::collections::HashMap;
+use translation_utils::{FunctionIndex, GlobalIndex, MemoryIndex, SignatureIndex};
+
+/// A control stack frame can be an `if`, a `block` or a `loop`, each one having the following
+/// fields:
+///
+/// - `destination`: reference to the `Ebb` that will hold the code after the control block;
+/// - `num_return_values`: number of values returned by the control block;
+/// - `original_stack_size`: size of the value stack at the beginning of the control block.
+///
+/// Moreover, the `if` frame has the `else_ebb` reached when its condition is false, and a flag
+/// telling whether an `else` was translated. The `loop` frame has a `header` field that
+/// references the `Ebb` that contains the beginning of the body of the loop.
+#[derive(Debug)]
+pub enum ControlStackFrame {
+    /// An `if` instruction.
+    If {
+        /// The EBB following the `end` of the `if`.
+        destination: Ebb,
+        /// The EBB holding the `else` branch, which branches to `destination` if there is none.
+        else_ebb: Ebb,
+        /// Has the `else` of this `if` been translated?
+        else_seen: bool,
+        /// Number of values returned by the `if`.
+        num_return_values: usize,
+        /// Size of the value stack when the `if` was entered.
+        original_stack_size: usize,
+        /// Does any branch target the end of the `if`?
+        exit_is_branched_to: bool,
+    },
+    /// A `block` instruction.
+    Block {
+        /// The EBB following the `end` of the block.
+        destination: Ebb,
+        /// Number of values returned by the block.
+        num_return_values: usize,
+        /// Size of the value stack when the block was entered.
+        original_stack_size: usize,
+        /// Does any branch target the end of the block?
+        exit_is_branched_to: bool,
+    },
+    /// A `loop` instruction.
+    Loop {
+        /// The EBB following the `end` of the loop.
+        destination: Ebb,
+        /// The EBB at the top of the loop, which is the target of the branches to the loop.
+        header: Ebb,
+        /// Number of values returned by the loop.
+        num_return_values: usize,
+        /// Size of the value stack when the loop was entered.
+        original_stack_size: usize,
+    },
+}
+
+/// Helper methods for the control stack objects.
+impl ControlStackFrame {
+    /// Get the number of values returned when falling through the end of the frame.
+    pub fn num_return_values(&self) -> usize {
+        match *self {
+            ControlStackFrame::If { num_return_values, .. } |
+            ControlStackFrame::Block { num_return_values, .. } |
+            ControlStackFrame::Loop { num_return_values, .. } => num_return_values,
+        }
+    }
+
+    /// Get the EBB following the end of the frame.
+    pub fn following_code(&self) -> Ebb {
+        match *self {
+            ControlStackFrame::If { destination, .. } |
+            ControlStackFrame::Block { destination, .. } |
+            ControlStackFrame::Loop { destination, .. } => destination,
+        }
+    }
+
+    /// Get the EBB that a branch to this frame jumps to.
+    ///
+    /// Branches to a loop go back to its header, and branches to other frames exit them.
+    pub fn br_destination(&self) -> Ebb {
+        match *self {
+            ControlStackFrame::If { destination, .. } |
+            ControlStackFrame::Block { destination, .. } => destination,
+            ControlStackFrame::Loop { header, .. } => header,
+        }
+    }
+
+    /// Get the number of values passed by a branch to this frame.
+    ///
+    /// Loops don't take any arguments, so branching to them passes nothing.
+    pub fn num_br_values(&self) -> usize {
+        match *self {
+            ControlStackFrame::If { num_return_values, .. } |
+            ControlStackFrame::Block { num_return_values, .. } => num_return_values,
+            ControlStackFrame::Loop { .. } => 0,
+        }
+    }
+
+    /// Get the size of the value stack when the frame was entered.
+    pub fn original_stack_size(&self) -> usize {
+        match *self {
+            ControlStackFrame::If { original_stack_size, .. } |
+            ControlStackFrame::Block { original_stack_size, .. } |
+            ControlStackFrame::Loop { original_stack_size, .. } => original_stack_size,
+        }
+    }
+
+    /// Does any branch target the code following the frame?
+    pub fn exit_is_branched_to(&self) -> bool {
+        match *self {
+            ControlStackFrame::If { exit_is_branched_to, .. } |
+            ControlStackFrame::Block { exit_is_branched_to, .. } => exit_is_branched_to,
+            ControlStackFrame::Loop { .. } => false,
+        }
+    }
+
+    /// Record that a branch targets the code following the frame.
+    ///
+    /// This has no effect on loops, whose branches go to the header.
+    pub fn set_branched_to_exit(&mut self) {
+        match *self {
+            ControlStackFrame::If { ref mut exit_is_branched_to, .. } |
+            ControlStackFrame::Block { ref mut exit_is_branched_to, .. } => {
+                *exit_is_branched_to = true
+            }
+            ControlStackFrame::Loop { .. } => {}
+        }
+    }
+}
+
+/// Contains information passed along during the translation and that records:
+///
+/// - The current value and control stacks.
+/// - Whether the current code is reachable.
+/// - The module-level entities that have already been declared in the function preamble.
+pub struct TranslationState {
+    /// The value stack.
+    pub stack: Vec<Value>,
+    /// The control stack.
+    pub control_stack: Vec<ControlStackFrame>,
+    /// Is the current translation state still reachable? This is false when translating
+    /// operators like `end`, `return`, or `unreachable`.
+    pub reachable: bool,
+    /// Number of control frames opened in unreachable code, which are not translated.
+    pub unreachable_depth: usize,
+
+    /// Map of global variables that have already been created by `FuncEnvironment::make_global`.
+    globals: HashMap<GlobalIndex, GlobalValue>,
+
+    /// Map of heaps that have been created by `FuncEnvironment::make_heap`.
+    heaps: HashMap<MemoryIndex, ir::Heap>,
+
+    /// Map of indirect call signatures that have been created by
+    /// `FuncEnvironment::make_indirect_sig()`.
+    /// Stores both the signature reference and the number of WebAssembly arguments.
+    signatures: HashMap<SignatureIndex, (ir::SigRef, usize)>,
+
+    /// Map of direct function references that have been created by
+    /// `FuncEnvironment::make_direct_func()`.
+    /// Stores both the function reference and the number of WebAssembly arguments.
+    functions: HashMap<FunctionIndex, (ir::FuncRef, usize)>,
+}
+
+impl TranslationState {
+    /// Create a new empty translation state.
+    pub fn new() -> TranslationState {
+        TranslationState {
+            stack: Vec::new(),
+            control_stack: Vec::new(),
+            reachable: true,
+            unreachable_depth: 0,
+            globals: HashMap::new(),
+            heaps: HashMap::new(),
+            signatures: HashMap::new(),
+            functions: HashMap::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        // The stacks may not be empty if the previous translation failed.
+        self.stack.clear();
+        self.control_stack.clear();
+        self.reachable = true;
+        self.unreachable_depth = 0;
+        self.globals.clear();
+        self.heaps.clear();
+        self.signatures.clear();
+        self.functions.clear();
+    }
+
+    /// Initialize the state for compiling a function with the given signature.
+    ///
+    /// This resets the state to containing only a single block representing the whole function.
+    /// The exit block is the last block in the function which will contain the return
+    /// instruction.
+    pub fn initialize(&mut self, exit_ebb: Ebb, num_return_values: usize) {
+        self.clear();
+        self.push_block(exit_ebb, num_return_values);
+    }
+
+    /// Push a value.
+    pub fn push1(&mut self, val: Value) {
+        self.stack.push(val);
+    }
+
+    /// Push multiple values.
+    pub fn pushn(&mut self, vals: &[Value]) {
+        self.stack.extend_from_slice(vals);
+    }
+
+    /// Pop one value.
+    pub fn pop1(&mut self) -> Value {
+        self.stack.pop().expect("Value stack underflow")
+    }
+
+    /// Peek at the top of the stack without popping it.
+    pub fn peek1(&self) -> Value {
+        *self.stack.last().expect("Value stack underflow")
+    }
+
+    /// Pop two values. Return them in the order they were pushed.
+    pub fn pop2(&mut self) -> (Value, Value) {
+        let v2 = self.pop1();
+        let v1 = self.pop1();
+        (v1, v2)
+    }
+
+    /// Pop three values. Return them in the order they were pushed.
+    pub fn pop3(&mut self) -> (Value, Value, Value) {
+        let v3 = self.pop1();
+        let v2 = self.pop1();
+        let v1 = self.pop1();
+        (v1, v2, v3)
+    }
+
+    /// Pop the top `n` values on the stack.
+    ///
+    /// The popped values are not returned. Use `peekn` to look at them before popping.
+    pub fn popn(&mut self, n: usize) {
+        let new_len = self.stack.len() - n;
+        self.stack.truncate(new_len);
+    }
+
+    /// Peek at the top `n` values on the stack in the order they were pushed.
+    pub fn peekn(&self, n: usize) -> &[Value] {
+        &self.stack[self.stack.len() - n..]
+    }
+
+    /// Push a block on the control stack.
+    pub fn push_block(&mut self, following_code: Ebb, num_result_types: usize) {
+        self.control_stack
+            .push(ControlStackFrame::Block {
+                      destination: following_code,
+                      original_stack_size: self.stack.len(),
+                      num_return_values: num_result_types,
+                      exit_is_branched_to: false,
+                  });
+    }
+
+    /// Push a loop on the control stack.
+    pub fn push_loop(&mut self, header: Ebb, following_code: Ebb, num_result_types: usize) {
+        self.control_stack
+            .push(ControlStackFrame::Loop {
+                      header: header,
+                      destination: following_code,
+                      original_stack_size: self.stack.len(),
+                      num_return_values: num_result_types,
+                  });
+    }
+
+    /// Push an if on the control stack.
+    pub fn push_if(&mut self, else_ebb: Ebb, following_code: Ebb, num_result_types: usize) {
+        self.control_stack
+            .push(ControlStackFrame::If {
+                      destination: following_code,
+                      else_ebb: else_ebb,
+                      else_seen: false,
+                      original_stack_size: self.stack.len(),
+                      num_return_values: num_result_types,
+                      exit_is_branched_to: false,
+                  });
+    }
+}
+
+/// Methods for handling entity references.
+impl TranslationState {
+    /// Get the `GlobalVar` reference that should be used to access the global variable `index`.
+    /// Create the reference if necessary.
+    pub fn get_global<FE: FuncEnvironment + ?Sized>(&mut self,
+                                                    func: &mut ir::Function,
+                                                    index: u32,
+                                                    environ: &mut FE)
+                                                    -> GlobalValue {
+        let index = index as GlobalIndex;
+        *self.globals
+             .entry(index)
+             .or_insert_with(|| environ.make_global(func, index))
+    }
+
+    /// Get the `Heap` reference that should be used to access linear memory `index`.
+    /// Create the reference if necessary.
+    pub fn get_heap<FE: FuncEnvironment + ?Sized>(&mut self,
+                                                  func: &mut ir::Function,
+                                                  index: u32,
+                                                  environ: &mut FE)
+                                                  -> ir::Heap {
+        let index = index as MemoryIndex;
+        *self.heaps
+             .entry(index)
+             .or_insert_with(|| environ.make_heap(func, index))
+    }
+
+    /// Get the `SigRef` reference that should be used to make an indirect call with signature
+    /// `index`. Also return the number of WebAssembly arguments in the signature.
+    ///
+    /// Create the signature if necessary.
+    pub fn get_indirect_sig<FE: FuncEnvironment + ?Sized>(&mut self,
+                                                          func: &mut ir::Function,
+                                                          index: u32,
+                                                          environ: &mut FE)
+                                                          -> (ir::SigRef, usize) {
+        let index = index as SignatureIndex;
+        *self.signatures
+             .entry(index)
+             .or_insert_with(|| {
+                                 let sig = environ.make_indirect_sig(func, index);
+                                 (sig, normal_args(&func.dfg.signatures[sig]))
+                             })
+    }
+
+    /// Get the `FuncRef` reference that should be used to make a direct call to function
+    /// `index`. Also return the number of WebAssembly arguments in the signature.
+    ///
+    /// Create the function reference if necessary.
+    pub fn get_direct_func<FE: FuncEnvironment + ?Sized>(&mut self,
+                                                         func: &mut ir::Function,
+                                                         index: u32,
+                                                         environ: &mut FE)
+                                                         -> (ir::FuncRef, usize) {
+        let index = index as FunctionIndex;
+        *self.functions
+             .entry(index)
+             .or_insert_with(|| {
+                                 let fref = environ.make_direct_func(func, index);
+                                 let sig = func.dfg.ext_funcs[fref].signature;
+                                 (fref, normal_args(&func.dfg.signatures[sig]))
+                             })
+    }
+}
+
+/// Count the number of normal arguments in `sig`.
+///
+/// Any non-normal arguments will be added by the environment.
+fn normal_args(sig: &ir::Signature) -> usize {
+    sig.argument_types
+        .iter()
+        .filter(|arg| arg.purpose == ir::ArgumentPurpose::Normal)
+        .count()
+}
@@ -0,0 +1,123 @@
+//! Helper types and functions shared by the function and module translators.
+
+use cretonne::entity_map::EntityRef;
+use cretonne::ir::{self, types};
+use environ::{WasmError, WasmResult};
+use wasmparser;
+
+/// Index of a function in the module, counting the imported functions first.
+pub type FunctionIndex = usize;
+/// Index of a global variable in the module, counting the imported globals first.
+pub type GlobalIndex = usize;
+/// Index of a linear memory in the module, counting the imported memories first.
+pub type MemoryIndex = usize;
+/// Index of a table in the module, counting the imported tables first.
+pub type TableIndex = usize;
+/// Index of a function signature in the type section.
+pub type SignatureIndex = usize;
+
+/// A WebAssembly local, used as a frontend variable.
+///
+/// The function arguments come first, followed by the declared locals.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Local(pub u32);
+
+impl EntityRef for Local {
+    fn new(index: usize) -> Self {
+        debug_assert!(index < (u32::max_value() as usize));
+        Local(index as u32)
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A WebAssembly global variable.
+#[derive(Clone, Copy, Debug)]
+pub struct Global {
+    /// The type of the value stored in the global.
+    pub ty: ir::Type,
+    /// Can the global be modified with `global.set`?
+    pub mutability: bool,
+    /// The initial value of the global.
+    pub initializer: GlobalInit,
+}
+
+/// The initial value of a global variable.
+#[derive(Clone, Copy, Debug)]
+pub enum GlobalInit {
+    /// An `i32.const`.
+    I32Const(i32),
+    /// An `i64.const`.
+    I64Const(i64),
+    /// An `f32.const`, given by its bit pattern.
+    F32Const(u32),
+    /// An `f64.const`, given by its bit pattern.
+    F64Const(u64),
+    /// The value of an imported global.
+    GlobalRef(GlobalIndex),
+    /// The global itself is imported, and initialized by the embedder.
+    Import,
+}
+
+/// A WebAssembly table of function references.
+#[derive(Clone, Copy, Debug)]
+pub struct Table {
+    /// The initial number of elements.
+    pub size: u32,
+    /// The maximum number of elements, if any.
+    pub maximum: Option<u32>,
+}
+
+/// A WebAssembly linear memory.
+#[derive(Clone, Copy, Debug)]
+pub struct Memory {
+    /// The initial size of the memory in 64 KiB pages.
+    pub pages_count: u32,
+    /// The maximum size of the memory in pages, if any.
+    pub maximum: Option<u32>,
+}
+
+/// Convert a WebAssembly value type to a Cretonne type.
+pub fn type_to_type(ty: wasmparser::Type) -> WasmResult<ir::Type> {
+    match ty {
+        wasmparser::Type::I32 => Ok(types::I32),
+        wasmparser::Type::I64 => Ok(types::I64),
+        wasmparser::Type::F32 => Ok(types::F32),
+        wasmparser::Type::F64 => Ok(types::F64),
+        _ => Err(WasmError::Unsupported(format!("value type {:?}", ty))),
+    }
+}
+
+/// Get the result types of a block with the type `ty`.
+pub fn block_results(ty: wasmparser::TypeOrFuncType) -> WasmResult<Vec<ir::Type>> {
+    match ty {
+        wasmparser::TypeOrFuncType::Type(wasmparser::Type::EmptyBlockType) => Ok(Vec::new()),
+        wasmparser::TypeOrFuncType::Type(ty) => Ok(vec![type_to_type(ty)?]),
+        wasmparser::TypeOrFuncType::FuncType(_) => {
+            Err(WasmError::Unsupported("multi-value blocks".to_string()))
+        }
+    }
+}
+
+/// Build the Cretonne signature of a WebAssembly function type.
+pub fn translate_signature(ty: &wasmparser::FuncType) -> WasmResult<ir::Signature> {
+    let mut sig = ir::Signature::new();
+    for &param in ty.params.iter() {
+        sig.argument_types.push(ir::ArgumentType::new(type_to_type(param)?));
+    }
+    for &ret in ty.returns.iter() {
+        sig.return_types.push(ir::ArgumentType::new(type_to_type(ret)?));
+    }
+    Ok(sig)
+}
+
+/// Build the argument list of a branch or call from `values`.
+pub fn variable_args(values: &[ir::Value]) -> ir::VariableArgs {
+    let mut args = ir::VariableArgs::new();
+    for &val in values {
+        args.push(val);
+    }
+    args
+}
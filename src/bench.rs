@@ -0,0 +1,170 @@
+//! The `bench` sub-command.
+//!
+//! Compile the functions in a corpus of Cretonne IL files and WebAssembly modules repeatedly, and
+//! report the compilation speed of each file followed by the time spent in each compiler pass.
+//! Files with a `.wasm` or `.wat` extension are translated by the WebAssembly frontend with the
+//! `DummyEnvironment`, and the other files are parsed as Cretonne IL.
+//!
+//! The functions are compiled for the ISA given by `--target`. Without `--target`, the functions
+//! in an IL file are compiled for the last ISA specified in the file, and WebAssembly modules
+//! can't be compiled.
+//!
+//! Only the compilation is timed, not the parsing or translation of the input files.
+
+use compile::make_isa;
+use cretonne::{self, timing};
+use cretonne::ir::Function;
+use cretonne::isa::TargetIsa;
+use cton_reader::{parse_test, IsaSpec};
+use cton_wasm::{translate_module, DummyEnvironment};
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use utils::read_to_string;
+use wat;
+use CommandResult;
+
+pub fn run(files: Vec<String>,
+           target: Option<String>,
+           settings: Vec<String>,
+           iterations: usize)
+           -> CommandResult {
+    let isa = match target {
+        Some(target) => Some(make_isa(&target, &settings)?),
+        None if settings.is_empty() => None,
+        None => return Err("--set requires a --target".to_string()),
+    };
+    if iterations == 0 {
+        return Err("--iterations must be positive".to_string());
+    }
+
+    // Discard the statistics from before this command.
+    timing::set_enabled(true);
+    timing::take_current();
+
+    let mut total = Measurement::new();
+    for file in files {
+        let m = bench_file(&file, isa.as_ref().map(|isa| &**isa), iterations)
+            .map_err(|e| format!("{}: {}", file, e))?;
+        println!("{}: {}", file, m);
+        total.add(&m);
+    }
+    println!("total: {}", total);
+    println!("\nPass times for {} iterations:", iterations);
+    print!("{}", timing::take_current());
+    timing::set_enabled(false);
+    Ok(())
+}
+
+/// The compilation speed measured for a number of functions.
+struct Measurement {
+    /// The number of functions compiled in each iteration.
+    functions: usize,
+    /// The number of instructions in the functions before compiling them.
+    insts: usize,
+    /// The number of times the functions were compiled.
+    iterations: usize,
+    /// The time spent compiling the functions in all iterations.
+    elapsed: Duration,
+}
+
+impl Measurement {
+    fn new() -> Measurement {
+        Measurement {
+            functions: 0,
+            insts: 0,
+            iterations: 0,
+            elapsed: Duration::new(0, 0),
+        }
+    }
+
+    /// Add the functions measured by `other`.
+    fn add(&mut self, other: &Measurement) {
+        self.functions += other.functions;
+        self.insts += other.insts;
+        self.iterations = other.iterations;
+        self.elapsed += other.elapsed;
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 * 1e-9;
+        let per_iteration = secs / self.iterations.max(1) as f64;
+        let rate = if per_iteration > 0.0 {
+            self.insts as f64 / per_iteration
+        } else {
+            0.0
+        };
+        write!(f,
+               "{} functions, {} instructions, {:.3} ms, {:.0} instructions/s",
+               self.functions,
+               self.insts,
+               per_iteration * 1e3,
+               rate)
+    }
+}
+
+/// Compile the functions in `file` `iterations` times for `isa`, or for the ISA specified in the
+/// file.
+fn bench_file(file: &str,
+              isa: Option<&TargetIsa>,
+              iterations: usize)
+              -> Result<Measurement, String> {
+    let path = Path::new(file);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("wasm") | Some("wat") => {
+            let isa = isa.ok_or("WebAssembly modules require a --target")?;
+            let data = wat::parse_file(path).map_err(|e| e.to_string())?;
+            let mut env = DummyEnvironment::with_flags(isa.flags().clone());
+            translate_module(&data, &mut env).map_err(|e| e.to_string())?;
+            bench_functions(&env.info.function_bodies, isa, iterations)
+        }
+        _ => {
+            let buffer = read_to_string(path).map_err(|e| e.to_string())?;
+            let testfile = parse_test(&buffer).map_err(|e| e.to_string())?;
+            let isa = match (isa, &testfile.isa_spec) {
+                (Some(isa), _) => isa,
+                (None, &IsaSpec::Some(ref isas)) => &**isas.last().expect("Empty ISA list"),
+                (None, &IsaSpec::None(_)) => return Err("no ISA specified".to_string()),
+            };
+            let funcs: Vec<Function> = testfile.functions.into_iter().map(|(f, _)| f).collect();
+            bench_functions(&funcs, isa, iterations)
+        }
+    }
+}
+
+/// Compile all of `funcs` `iterations` times.
+fn bench_functions(funcs: &[Function],
+                   isa: &TargetIsa,
+                   iterations: usize)
+                   -> Result<Measurement, String> {
+    let insts = funcs
+        .iter()
+        .map(|func| {
+                 func.layout
+                     .ebbs()
+                     .map(|ebb| func.layout.ebb_insts(ebb).count())
+                     .sum::<usize>()
+             })
+        .sum();
+
+    let mut ctx = cretonne::Context::new();
+    let mut elapsed = Duration::new(0, 0);
+    for _ in 0..iterations {
+        for func in funcs {
+            ctx.clear();
+            ctx.func = func.clone();
+            let start = Instant::now();
+            ctx.compile(isa).map_err(|e| format!("{}: {}", func.name, e))?;
+            elapsed += start.elapsed();
+        }
+    }
+
+    Ok(Measurement {
+           functions: funcs.len(),
+           insts: insts,
+           iterations: iterations,
+           elapsed: elapsed,
+       })
+}
@@ -0,0 +1,220 @@
+//! The `compile` sub-command.
+//!
+//! Compile the functions in a series of Cretonne IL files for a target ISA, and print each
+//! compiled function with its encodings, value locations, and EBB offsets, followed by the emitted
+//! machine code and its relocations. With `--disasm`, the machine code is listed next to the
+//! instructions that it was emitted for.
+//!
+//! The target ISA is given by `--target`, which accepts an ISA name or a target triple like
+//! `x86_64-unknown-linux-gnu`. The `--set` options apply to the shared settings as well as the
+//! ISA-specific settings. Without `--target`, the last ISA specified in the file is used.
+//!
+//! With `--print-after`, the functions whose name contains the given string are printed to stderr
+//! after each compiler pass.
+
+use cretonne::{self, settings, write_annotated_function, write_instruction, Annotations};
+use cretonne::binemit::{self, CodeSink, CodeOffset, Reloc, Addend, Stackmap};
+use cretonne::ir::{Function, ExternalName, JumpTable, SourceLoc, TrapCode};
+use cretonne::isa::{self, TargetIsa};
+use cretonne::regalloc::diversion::RegDiversions;
+use cretonne::settings::{Configurable, Error as SetError};
+use cton_reader::{parse_test, IsaSpec, TestOption};
+use std::fmt::{self, Write};
+use CommandResult;
+use utils::read_to_string;
+
+pub fn run(files: Vec<String>,
+           target: Option<String>,
+           settings: Vec<String>,
+           disasm: bool,
+           print_after: Option<String>)
+           -> CommandResult {
+    let isa = match target {
+        Some(target) => Some(make_isa(&target, &settings)?),
+        None if settings.is_empty() => None,
+        None => return Err("--set requires a --target".to_string()),
+    };
+    for file in files {
+        compile_file(&file, isa.as_ref().map(|isa| &**isa), disasm, &print_after)
+            .map_err(|e| format!("{}: {}", file, e))?;
+    }
+    Ok(())
+}
+
+/// Create the ISA named by `target` with `settings` applied.
+pub fn make_isa(target: &str, settings: &[String]) -> Result<Box<TargetIsa>, String> {
+    let mut flag_builder = settings::builder();
+    let mut isa_builder = isa::lookup(target).map_err(|e| format!("{}: {}", target, e))?;
+    for opt in settings.iter().map(|s| TestOption::new(s)) {
+        let result = match opt {
+            TestOption::Flag(name) => {
+                match flag_builder.enable(name) {
+                    Err(SetError::BadName) => isa_builder.enable(name),
+                    result => result,
+                }
+            }
+            TestOption::Value(name, value) => {
+                match flag_builder.set(name, value) {
+                    Err(SetError::BadName) => isa_builder.set(name, value),
+                    result => result,
+                }
+            }
+        };
+        result.map_err(|e| format!("--set {}: {}", opt, e))?;
+    }
+    Ok(isa_builder.finish(settings::Flags::new(&flag_builder)))
+}
+
+/// Compile all the functions in `file` for `isa`, or for the ISA specified in the file.
+///
+/// The functions matching `print_after` are printed after each pass.
+fn compile_file(file: &str,
+                isa: Option<&TargetIsa>,
+                disasm: bool,
+                print_after: &Option<String>)
+                -> CommandResult {
+    let buffer = read_to_string(file).map_err(|e| e.to_string())?;
+    let testfile = parse_test(&buffer).map_err(|e| e.to_string())?;
+    let isa = match (isa, &testfile.isa_spec) {
+        (Some(isa), _) => isa,
+        (None, &IsaSpec::Some(ref isas)) => &**isas.last().expect("Empty ISA list"),
+        (None, &IsaSpec::None(_)) => return Err("no ISA specified".to_string()),
+    };
+
+    let mut ctx = cretonne::Context::new();
+    ctx.print_after = print_after.clone();
+    for (func, _) in testfile.functions {
+        ctx.clear();
+        ctx.func = func;
+        println!("{}", compile_to_text(&mut ctx, isa, disasm)?);
+    }
+    Ok(())
+}
+
+/// Compile the function in `ctx` for `isa`, and describe the compiled function and its machine
+/// code as text.
+pub fn compile_to_text(ctx: &mut cretonne::Context,
+                       isa: &TargetIsa,
+                       disasm: bool)
+                       -> Result<String, String> {
+    let size = ctx.compile(isa).map_err(|e| e.to_string())?;
+    let mut sink = CompileSink::new();
+    binemit::emit_function(&ctx.func, isa, &mut sink);
+    debug_assert_eq!(sink.code.len(), size as usize);
+
+    let mut text = String::new();
+    let annotations = Annotations {
+        offsets: true,
+        ..Annotations::default()
+    };
+    write_annotated_function(&mut text, &ctx.func, Some(isa), &annotations)
+        .map_err(|e| e.to_string())?;
+    if disasm {
+        write_disasm(&mut text, &ctx.func, isa).map_err(|e| e.to_string())?;
+    }
+    write_code(&mut text, &sink, isa).map_err(|e| e.to_string())?;
+    Ok(text)
+}
+
+/// Write the machine code of each instruction in the compiled function `func` next to the
+/// instruction.
+fn write_disasm(w: &mut Write, func: &Function, isa: &TargetIsa) -> fmt::Result {
+    let mut divert = RegDiversions::new();
+    let mut code = Vec::new();
+    for ebb in func.layout.ebbs() {
+        writeln!(w, "\n; {}:", ebb)?;
+        divert.clear();
+        for inst in func.layout.ebb_insts(ebb) {
+            let offset = code.len();
+            if func.encodings.get(inst).map_or(false, |enc| enc.is_legal()) {
+                isa.emit_inst(func, inst, &mut divert, &mut code);
+            }
+            let mut line = String::new();
+            write_instruction(&mut line, func, Some(isa), inst)?;
+            writeln!(w,
+                     "; {:06x}: {:<24} {}",
+                     offset,
+                     hex_bytes(&code[offset..]),
+                     line.trim())?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the machine code and the relocations collected by `sink`.
+fn write_code(w: &mut Write, sink: &CompileSink, isa: &TargetIsa) -> fmt::Result {
+    writeln!(w, "\n; size={}", sink.code.len())?;
+    for (row, chunk) in sink.code.chunks(16).enumerate() {
+        writeln!(w, "; {:06x}: {}", row * 16, hex_bytes(chunk))?;
+    }
+    for &(offset, reloc, ref target) in &sink.relocs {
+        let kind = isa.reloc_names().get(reloc.0 as usize).cloned().unwrap_or("?");
+        writeln!(w, "; reloc {:06x}: {} {}", offset, kind, target)?;
+    }
+    Ok(())
+}
+
+/// Format `bytes` as space-separated lower case hexadecimal bytes.
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// A code sink collecting the machine code and the relocations that the linker has to apply.
+///
+/// The EBB relocations are resolved by the emitted code itself, so they are not collected.
+struct CompileSink {
+    code: Vec<u8>,
+    relocs: Vec<(CodeOffset, Reloc, String)>,
+}
+
+impl CompileSink {
+    fn new() -> CompileSink {
+        CompileSink {
+            code: Vec::new(),
+            relocs: Vec::new(),
+        }
+    }
+}
+
+impl CodeSink for CompileSink {
+    fn offset(&self) -> CodeOffset {
+        self.code.offset()
+    }
+
+    fn put1(&mut self, x: u8) {
+        self.code.put1(x)
+    }
+
+    fn put2(&mut self, x: u16) {
+        self.code.put2(x)
+    }
+
+    fn put4(&mut self, x: u32) {
+        self.code.put4(x)
+    }
+
+    fn put8(&mut self, x: u64) {
+        self.code.put8(x)
+    }
+
+    fn reloc_ebb(&mut self, _reloc: Reloc, _ebb_offset: CodeOffset) {}
+
+    fn reloc_external(&mut self, reloc: Reloc, name: &ExternalName, addend: Addend) {
+        let offset = self.offset();
+        let target = if addend == 0 {
+            name.to_string()
+        } else {
+            format!("{}{:+}", name, addend)
+        };
+        self.relocs.push((offset, reloc, target));
+    }
+
+    fn reloc_jt(&mut self, reloc: Reloc, jt: JumpTable) {
+        let offset = self.offset();
+        self.relocs.push((offset, reloc, jt.to_string()));
+    }
+
+    fn trap(&mut self, _code: TrapCode, _srcloc: SourceLoc) {}
+
+    fn add_stackmap(&mut self, _map: &Stackmap) {}
+}
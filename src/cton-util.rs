@@ -1,10 +1,14 @@
 
 extern crate cretonne;
 extern crate cton_reader;
+extern crate cton_module;
+extern crate cton_simplejit;
+extern crate cton_wasm;
 extern crate docopt;
 extern crate rustc_serialize;
 extern crate filecheck;
 extern crate num_cpus;
+extern crate wat;
 
 use cretonne::VERSION;
 use docopt::Docopt;
@@ -13,9 +17,15 @@ use std::process;
 
 mod utils;
 mod filetest;
+mod bench;
 mod cat;
+mod compile;
+mod enccov;
+mod encstats;
 mod print_cfg;
+mod reduce;
 mod rsfilecheck;
+mod wasm;
 
 const USAGE: &'static str = "
 Cretonne code generator utility
@@ -24,13 +34,32 @@ Usage:
     cton-util test [-v] <file>...
     cton-util cat <file>...
     cton-util filecheck [-v] <file>
-    cton-util print-cfg <file>...
+    cton-util print-cfg [--domtree] [--loops] <file>...
+    cton-util reduce [--verify] <file> <predicate>...
+    cton-util encstats <file>...
+    cton-util enccov [-v] [--target=<isa>] [--set=<setting>]...
+    cton-util compile [-D] [--target=<isa>] [--set=<setting>]... [--print-after=<name>] <file>...
+    cton-util wasm [-p] [-c] [-O] [-D] [--target=<isa>] [--set=<setting>]... <file>...
+    cton-util bench [--iterations=<n>] [--target=<isa>] [--set=<setting>]... <file>...
     cton-util --help | --version
 
 Options:
-    -v, --verbose  be more verbose
-    -h, --help     print this help message
-    --version      print the Cretonne version
+    -v, --verbose      be more verbose
+    -p, --print        print the translated or compiled functions
+    -c, --check        run the verifier on the translated functions
+    -O, --optimize     optimize the functions
+    -D, --disasm       list the machine code of each instruction
+    --verify           only reduce to functions that pass the verifier
+    --domtree          show the dominator tree in the CFG
+    --loops            show the loop nesting in the CFG
+    --target=<isa>     compile for the ISA or target triple <isa>
+    --set=<setting>    apply a setting to the target ISA
+    --print-after=<name>
+                       print the functions whose name contains <name> after
+                       each compiler pass
+    --iterations=<n>   compile each function <n> times [default: 10]
+    -h, --help         print this help message
+    --version          print the Cretonne version
 
 ";
 
@@ -40,8 +69,26 @@ struct Args {
     cmd_cat: bool,
     cmd_filecheck: bool,
     cmd_print_cfg: bool,
+    cmd_reduce: bool,
+    cmd_encstats: bool,
+    cmd_enccov: bool,
+    cmd_compile: bool,
+    cmd_wasm: bool,
+    cmd_bench: bool,
     arg_file: Vec<String>,
+    arg_predicate: Vec<String>,
     flag_verbose: bool,
+    flag_print: bool,
+    flag_check: bool,
+    flag_optimize: bool,
+    flag_disasm: bool,
+    flag_verify: bool,
+    flag_domtree: bool,
+    flag_loops: bool,
+    flag_target: Option<String>,
+    flag_set: Vec<String>,
+    flag_print_after: Option<String>,
+    flag_iterations: usize,
 }
 
 /// A command either succeeds or fails with an error message.
@@ -66,7 +113,37 @@ fn cton_util() -> CommandResult {
     } else if args.cmd_filecheck {
         rsfilecheck::run(args.arg_file, args.flag_verbose)
     } else if args.cmd_print_cfg {
-        print_cfg::run(args.arg_file)
+        let options = print_cfg::Options {
+            domtree: args.flag_domtree,
+            loops: args.flag_loops,
+        };
+        print_cfg::run(args.arg_file, options)
+    } else if args.cmd_reduce {
+        let file = args.arg_file.into_iter().next().expect("reduce takes one file");
+        reduce::run(file, args.arg_predicate, args.flag_verify)
+    } else if args.cmd_encstats {
+        encstats::run(args.arg_file)
+    } else if args.cmd_enccov {
+        enccov::run(args.flag_target, args.flag_set, args.flag_verbose)
+    } else if args.cmd_compile {
+        compile::run(args.arg_file,
+                     args.flag_target,
+                     args.flag_set,
+                     args.flag_disasm,
+                     args.flag_print_after)
+    } else if args.cmd_wasm {
+        let options = wasm::Options {
+            print: args.flag_print,
+            check: args.flag_check,
+            optimize: args.flag_optimize,
+            disasm: args.flag_disasm,
+        };
+        wasm::run(args.arg_file, args.flag_target, args.flag_set, options)
+    } else if args.cmd_bench {
+        bench::run(args.arg_file,
+                   args.flag_target,
+                   args.flag_set,
+                   args.flag_iterations)
     } else {
         // Debugging / shouldn't happen with proper command line handling above.
         Err(format!("Unhandled args: {:?}", args))
@@ -0,0 +1,402 @@
+//! The `enccov` sub-command.
+//!
+//! Report the encoding coverage of a target ISA. Every opcode is tried with every controlling type
+//! it accepts, and each combination is classified as:
+//!
+//! - `encoded` when the ISA has an encoding for it,
+//! - `legalized` when it has no encoding, but the legalizer rewrites it into instructions that
+//!   all have encodings, or
+//! - `unsupported` when the legalizer leaves an instruction without an encoding, or panics.
+//!
+//! The combinations are classified by legalizing a function containing a single instance of the
+//! instruction. The value operands whose types are not determined by the controlling type, like
+//! the address of a load or the argument of a conversion, are tried with all the types they accept,
+//! and the best classification is reported. The immediate operands are arbitrary, so an
+//! instruction whose encodings depend on the values of the immediates may be covered only
+//! partially. The `--set` options apply to the shared settings as well as the ISA-specific
+//! settings, so the effect of settings like `enable_float` on the coverage can be compared.
+//!
+//! The `test enccov` test command classifies the opcode and controlling type of each instruction
+//! in a function the same way, and sends a line for each opcode to filecheck.
+
+use compile::make_isa;
+use cretonne::legalize_function;
+use cretonne::ir::{Function, Cursor, Inst, InstBuilder, Opcode, Type, Value, VariableArgs,
+                   ExternalName, Signature, ExtFuncData, StackSlotData, StackSlotKind,
+                   JumpTableData, HeapData, GlobalVarData, MemFlags, AtomicOrdering, TrapCode};
+use cretonne::ir::condcodes::{IntCC, FloatCC};
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use cretonne::ir::instructions::{InstructionFormat, ResolvedConstraint, ValueTypeSet};
+use cretonne::ir::types::{self, VOID};
+use cretonne::isa::TargetIsa;
+use cton_reader::TestCommand;
+use filetest::subtest::{self, SubTest, Context, Result as STResult};
+use std::borrow::Cow;
+use std::panic::{self, AssertUnwindSafe};
+use CommandResult;
+
+/// The lane types that are tried as controlling types, along with their vectors of up to
+/// `MAX_VECTOR_BITS` bits.
+const LANE_TYPES: [Type; 13] = [types::B1, types::B8, types::B16, types::B32, types::B64,
+                                types::I8, types::I16, types::I32, types::I64, types::F32,
+                                types::F64, types::R32, types::R64];
+
+/// The size of the largest vector types that are tried. No target has wider SIMD registers.
+const MAX_VECTOR_BITS: u16 = 128;
+
+pub fn run(target: Option<String>, settings: Vec<String>, verbose: bool) -> CommandResult {
+    let isa = match target {
+        Some(target) => make_isa(&target, &settings)?,
+        None => return Err("enccov requires a --target".to_string()),
+    };
+
+    // Silence the panic messages from the legalizer while classifying the instructions.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let mut totals = [0; 3];
+    for opcode in Opcode::all() {
+        let mut types = [Vec::new(), Vec::new(), Vec::new()];
+        for ty in ctrl_types(opcode) {
+            types[coverage(&*isa, opcode, ty) as usize].push(ty);
+        }
+        for (total, tys) in totals.iter_mut().zip(&types) {
+            *total += tys.len();
+        }
+        // Fully encoded opcodes are only listed with `--verbose`.
+        if types[Coverage::Encoded as usize].len() == ctrl_types(opcode).len() && !verbose {
+            continue;
+        }
+        println!("{}", coverage_line(opcode, &types));
+    }
+    panic::set_hook(hook);
+    println!("\n{} encoded, {} legalized, {} unsupported",
+             totals[Coverage::Encoded as usize],
+             totals[Coverage::Legalized as usize],
+             totals[Coverage::Unsupported as usize]);
+    Ok(())
+}
+
+/// How well a target ISA supports an opcode and controlling type combination.
+/// The variants are ordered from the best to the worst support.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Coverage {
+    Encoded,
+    Legalized,
+    Unsupported,
+}
+
+const COVERAGES: [Coverage; 3] = [Coverage::Encoded, Coverage::Legalized, Coverage::Unsupported];
+
+impl Coverage {
+    fn name(self) -> &'static str {
+        match self {
+            Coverage::Encoded => "encoded",
+            Coverage::Legalized => "legalized",
+            Coverage::Unsupported => "unsupported",
+        }
+    }
+}
+
+/// Describe the controlling types of `opcode` classified by their coverage in `types`, indexed by
+/// `Coverage`.
+fn coverage_line(opcode: Opcode, types: &[Vec<Type>; 3]) -> String {
+    let mut line = format!("{}:", opcode);
+    for (&cov, tys) in COVERAGES.iter().zip(types) {
+        if !tys.is_empty() {
+            line.push_str(&format!(" {} {};", cov.name(), type_list(tys)));
+        }
+    }
+    line.pop();
+    line
+}
+
+/// Format `types` as a space-separated list, or `-` for the controlling type of a
+/// non-polymorphic opcode.
+fn type_list(types: &[Type]) -> String {
+    types
+        .iter()
+        .map(|&ty| if ty == VOID {
+                 "-".to_string()
+             } else {
+                 ty.to_string()
+             })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Get the controlling types accepted by `opcode`, or `VOID` if it isn't polymorphic.
+fn ctrl_types(opcode: Opcode) -> Vec<Type> {
+    match opcode.constraints().ctrl_typeset() {
+        Some(typeset) => typeset_types(&typeset),
+        None => vec![VOID],
+    }
+}
+
+/// Get the types to try for the value operands of `opcode` whose types are not determined by the
+/// controlling type, or just `VOID` if there are no such operands.
+fn free_types(opcode: Opcode, ctrl_type: Type) -> Vec<Type> {
+    let constraints = opcode.constraints();
+    for n in 0..opcode.format().num_value_operands() {
+        if let ResolvedConstraint::Free(typeset) =
+            constraints.value_argument_constraint(n, ctrl_type) {
+            return typeset_types(&typeset);
+        }
+    }
+    vec![VOID]
+}
+
+/// Get the members of `typeset`, except the vectors larger than `MAX_VECTOR_BITS`.
+fn typeset_types(typeset: &ValueTypeSet) -> Vec<Type> {
+    let mut types = Vec::new();
+    for &lane in &LANE_TYPES {
+        let mut lanes = 1;
+        while let Some(ty) = lane.by(lanes) {
+            if lanes > 1 && ty.bits() > MAX_VECTOR_BITS {
+                break;
+            }
+            if typeset.contains(ty) {
+                types.push(ty);
+            }
+            lanes *= 2;
+        }
+    }
+    types
+}
+
+/// Classify the support of `isa` for `opcode` with the controlling type `ctrl_type`.
+///
+/// The best classification for any of the free operand types is returned.
+fn coverage(isa: &TargetIsa, opcode: Opcode, ctrl_type: Type) -> Coverage {
+    free_types(opcode, ctrl_type)
+        .into_iter()
+        .map(|free_type| classify(isa, opcode, ctrl_type, free_type))
+        .min()
+        .unwrap_or(Coverage::Unsupported)
+}
+
+/// Classify the support of `isa` for `opcode` with the controlling type `ctrl_type` and the free
+/// operand type `free_type`.
+fn classify(isa: &TargetIsa, opcode: Opcode, ctrl_type: Type, free_type: Type) -> Coverage {
+    let (mut func, inst) = single_inst_function(isa, opcode, ctrl_type, free_type);
+    if isa.encode(&func.dfg, &func.dfg[inst]).is_ok() {
+        return Coverage::Encoded;
+    }
+
+    // The legalizer may panic on instructions it doesn't know how to handle.
+    let legalized = panic::catch_unwind(AssertUnwindSafe(|| {
+        legalize_function(&mut func, isa).is_ok()
+    }));
+    match legalized {
+        Ok(true) => Coverage::Legalized,
+        _ => Coverage::Unsupported,
+    }
+}
+
+/// Create a function with an instance of `opcode` with the controlling type `ctrl_type`. The
+/// value operands whose types are not determined by `ctrl_type` have the type `free_type` if they
+/// accept it.
+///
+/// The instruction is the only instruction in a second EBB whose arguments are the value operands
+/// of the instruction. The entry block simply returns, and it is the destination of branches.
+fn single_inst_function(isa: &TargetIsa,
+                        opcode: Opcode,
+                        ctrl_type: Type,
+                        free_type: Type)
+                        -> (Function, Inst) {
+    let mut func = Function::new();
+    let entry = func.dfg.make_ebb();
+    let ebb = func.dfg.make_ebb();
+
+    let constraints = opcode.constraints();
+    let format = opcode.format();
+    let args: Vec<Value> = (0..format.num_value_operands())
+        .map(|n| {
+            let ty = match constraints.value_argument_constraint(n, ctrl_type) {
+                ResolvedConstraint::Bound(ty) => ty,
+                ResolvedConstraint::Free(typeset) if typeset.contains(free_type) => free_type,
+                ResolvedConstraint::Free(typeset) => typeset.example(),
+            };
+            func.dfg.append_ebb_arg(ebb, ty)
+        })
+        .collect();
+    // The type of the first result, which is what the simple instruction formats store.
+    let result_type = if constraints.fixed_results() > 0 {
+        constraints.result_type(0, ctrl_type)
+    } else {
+        VOID
+    };
+
+    let name = ExternalName::testcase("enccov");
+    let sig = func.dfg.signatures.push(Signature::new());
+    let callee = func.dfg.ext_funcs.push(ExtFuncData::new(name.clone(), sig));
+    let slot = func.stack_slots.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 16));
+    let jt = func.jump_tables.push(JumpTableData::new());
+    let heap = func.heaps.push(HeapData::new(name.clone()));
+    let gv = func.dfg.global_vars.push(GlobalVarData::new(name));
+    let entry_size = if isa.flags().is_64bit() { 8 } else { 4 };
+    let flags = MemFlags::new();
+    let order = AtomicOrdering::SeqCst;
+    let code = TrapCode::User(0);
+
+    let dfg = &mut func.dfg;
+    let pos = &mut Cursor::new(&mut func.layout);
+    pos.insert_ebb(entry);
+    dfg.ins(pos).return_(VariableArgs::new());
+    pos.insert_ebb(ebb);
+    let ins = dfg.ins(pos);
+    let (inst, _) = match format {
+        InstructionFormat::Nullary => ins.Nullary(opcode, result_type),
+        InstructionFormat::Unary => ins.Unary(opcode, result_type, args[0]),
+        InstructionFormat::UnaryImm => ins.UnaryImm(opcode, result_type, 1.into()),
+        InstructionFormat::UnaryBool => ins.UnaryBool(opcode, result_type, true),
+        InstructionFormat::UnaryIeee32 => {
+            ins.UnaryIeee32(opcode, result_type, Ieee32::new(1.0))
+        }
+        InstructionFormat::UnaryIeee64 => {
+            ins.UnaryIeee64(opcode, result_type, Ieee64::new(1.0))
+        }
+        InstructionFormat::UnaryImmVector => {
+            ins.UnaryImmVector(opcode, result_type, vec![0; ctrl_type.bits() as usize / 8])
+        }
+        InstructionFormat::UnarySplit => ins.UnarySplit(opcode, ctrl_type, args[0]),
+        InstructionFormat::Binary => ins.Binary(opcode, result_type, args[0], args[1]),
+        InstructionFormat::BinaryImm => ins.BinaryImm(opcode, result_type, args[0], 1.into()),
+        InstructionFormat::BinaryImmRev => {
+            ins.BinaryImmRev(opcode, result_type, 1.into(), args[0])
+        }
+        InstructionFormat::BinaryOverflow => {
+            ins.BinaryOverflow(opcode, ctrl_type, args[0], args[1])
+        }
+        InstructionFormat::Ternary => {
+            ins.Ternary(opcode, result_type, args[0], args[1], args[2])
+        }
+        InstructionFormat::TernaryOverflow => {
+            ins.TernaryOverflow(opcode, ctrl_type, args[0], args[1], args[2])
+        }
+        InstructionFormat::InsertLane => {
+            ins.InsertLane(opcode, result_type, args[0], 0, args[1])
+        }
+        InstructionFormat::ExtractLane => ins.ExtractLane(opcode, result_type, args[0], 0),
+        InstructionFormat::IntCompare => {
+            ins.IntCompare(opcode, result_type, IntCC::Equal, args[0], args[1])
+        }
+        InstructionFormat::FloatCompare => {
+            ins.FloatCompare(opcode, result_type, FloatCC::Equal, args[0], args[1])
+        }
+        InstructionFormat::IntCond => ins.IntCond(opcode, result_type, IntCC::Equal, args[0]),
+        InstructionFormat::FloatCond => ins.FloatCond(opcode, result_type, FloatCC::Equal, args[0]),
+        InstructionFormat::IntSelect => {
+            ins.IntSelect(opcode, result_type, IntCC::Equal, args[0], args[1], args[2])
+        }
+        InstructionFormat::Jump => ins.Jump(opcode, result_type, entry, VariableArgs::new()),
+        InstructionFormat::Branch => {
+            ins.Branch(opcode, result_type, args[0], entry, VariableArgs::new())
+        }
+        InstructionFormat::BranchIcmp => {
+            ins.BranchIcmp(opcode,
+                           result_type,
+                           IntCC::Equal,
+                           args[0],
+                           args[1],
+                           entry,
+                           VariableArgs::new())
+        }
+        InstructionFormat::BranchTable => ins.BranchTable(opcode, result_type, args[0], jt),
+        InstructionFormat::BranchTableEntry => {
+            ins.BranchTableEntry(opcode, result_type, args[0], args[1], entry_size, jt)
+        }
+        InstructionFormat::BranchTableBase => ins.BranchTableBase(opcode, result_type, jt),
+        InstructionFormat::Trap => ins.Trap(opcode, result_type, code),
+        InstructionFormat::CondTrap => ins.CondTrap(opcode, result_type, args[0], code),
+        InstructionFormat::IntCondTrap => {
+            ins.IntCondTrap(opcode, result_type, IntCC::Equal, args[0], code)
+        }
+        InstructionFormat::BinaryTrap => {
+            ins.BinaryTrap(opcode, result_type, args[0], args[1], code)
+        }
+        InstructionFormat::Call => ins.Call(opcode, ctrl_type, callee, VariableArgs::new()),
+        InstructionFormat::IndirectCall => {
+            ins.IndirectCall(opcode, ctrl_type, sig, args[0], VariableArgs::new())
+        }
+        InstructionFormat::FuncAddr => ins.FuncAddr(opcode, result_type, callee),
+        InstructionFormat::Return => ins.Return(opcode, result_type, VariableArgs::new()),
+        InstructionFormat::ReturnReg => {
+            ins.ReturnReg(opcode, result_type, args[0], VariableArgs::new())
+        }
+        InstructionFormat::Load => ins.Load(opcode, result_type, flags, args[0], 0),
+        InstructionFormat::Store => ins.Store(opcode, result_type, flags, args[0], args[1], 0),
+        InstructionFormat::LoadComplex => {
+            ins.LoadComplex(opcode, result_type, flags, args[0], args[1], 0, 0)
+        }
+        InstructionFormat::StoreComplex => {
+            ins.StoreComplex(opcode, result_type, flags, args[0], args[1], args[2], 0, 0)
+        }
+        InstructionFormat::AtomicLoad => ins.AtomicLoad(opcode, result_type, order, args[0]),
+        InstructionFormat::AtomicRmw => {
+            ins.AtomicRmw(opcode, result_type, order, args[0], args[1])
+        }
+        InstructionFormat::AtomicCas => {
+            ins.AtomicCas(opcode, result_type, order, args[0], args[1], args[2])
+        }
+        InstructionFormat::StackLoad => ins.StackLoad(opcode, result_type, slot, 0),
+        InstructionFormat::StackStore => ins.StackStore(opcode, result_type, args[0], slot, 0),
+        InstructionFormat::HeapAddr => ins.HeapAddr(opcode, result_type, heap, args[0], 1),
+        InstructionFormat::UnaryGlobalVar => ins.UnaryGlobalVar(opcode, result_type, gv),
+        InstructionFormat::RegMove => ins.RegMove(opcode, result_type, args[0], 0, 1),
+    };
+    (func, inst)
+}
+
+/// Object implementing the `test enccov` sub-test.
+struct TestEncCov;
+
+pub fn subtest(parsed: &TestCommand) -> STResult<Box<SubTest>> {
+    assert_eq!(parsed.command, "enccov");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestEncCov))
+    }
+}
+
+impl SubTest for TestEncCov {
+    fn name(&self) -> Cow<str> {
+        Cow::from("enccov")
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> STResult<()> {
+        let isa = context.isa.expect("enccov needs an ISA");
+
+        // Classify each opcode with all the controlling types it is used with, in the order of
+        // their first use.
+        let mut opcodes: Vec<(Opcode, [Vec<Type>; 3])> = Vec::new();
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                let opcode = func.dfg[inst].opcode();
+                let ctrl_type = func.dfg[inst].ctrl_typevar(&func.dfg);
+                let idx = match opcodes.iter().position(|&(op, _)| op == opcode) {
+                    Some(idx) => idx,
+                    None => {
+                        opcodes.push((opcode, [Vec::new(), Vec::new(), Vec::new()]));
+                        opcodes.len() - 1
+                    }
+                };
+                let types = &mut opcodes[idx].1;
+                if types.iter().all(|tys| !tys.contains(&ctrl_type)) {
+                    types[coverage(isa, opcode, ctrl_type) as usize].push(ctrl_type);
+                }
+            }
+        }
+
+        let mut text = String::new();
+        for &(opcode, ref types) in &opcodes {
+            text.push_str(&coverage_line(opcode, types));
+            text.push('\n');
+        }
+        subtest::run_filecheck(&text, context)
+    }
+}
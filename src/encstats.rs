@@ -0,0 +1,188 @@
+//! The `encstats` sub-command.
+//!
+//! Legalize the functions in a series of Cretonne IL files and print histograms of the selected
+//! encodings, counting instructions and code bytes per encoding recipe and per opcode across all
+//! the files. This can be used to attribute code size changes to specific lowering decisions.
+//!
+//! Each file must specify a target ISA with an `isa` command. If the file specifies more than one
+//! ISA, the last one is used.
+//!
+//! The byte counts use the size of each encoding recipe. Branches are counted with their largest
+//! size since the functions are not laid out, so the totals are an upper bound on the code size
+//! before register allocation.
+//!
+//! The `test encstats` test command legalizes each function in a test file and sends the
+//! histograms of that function to filecheck.
+
+use cretonne;
+use cretonne::ir::Function;
+use cretonne::isa::TargetIsa;
+use cton_reader::{parse_test, IsaSpec, TestCommand};
+use filetest::subtest::{self, SubTest, Context, Result as STResult};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use CommandResult;
+use utils::read_to_string;
+
+pub fn run(files: Vec<String>) -> CommandResult {
+    let mut stats = EncodingStats::new();
+    for file in files {
+        stats.add_file(&file)?;
+    }
+    print!("{}", stats);
+    Ok(())
+}
+
+/// Instruction and byte counts for a single histogram entry.
+#[derive(Clone, Copy, Default)]
+struct Entry {
+    insts: u32,
+    bytes: u32,
+}
+
+impl Entry {
+    fn add(&mut self, bytes: u32) {
+        self.insts += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Instruction counts collected from legalized functions.
+struct EncodingStats {
+    /// Total number of instructions.
+    insts: u32,
+
+    /// Total number of code bytes in the encoded instructions.
+    bytes: u32,
+
+    /// Number of instructions without a legal encoding.
+    unencoded: u32,
+
+    /// Instructions and bytes per encoding recipe name.
+    recipes: HashMap<String, Entry>,
+
+    /// Instructions and bytes per opcode name. Instructions without an encoding count as 0 bytes.
+    opcodes: HashMap<String, Entry>,
+}
+
+impl EncodingStats {
+    fn new() -> EncodingStats {
+        EncodingStats {
+            insts: 0,
+            bytes: 0,
+            unencoded: 0,
+            recipes: HashMap::new(),
+            opcodes: HashMap::new(),
+        }
+    }
+
+    /// Legalize all the functions in `file` and count their instructions.
+    fn add_file(&mut self, file: &str) -> CommandResult {
+        let buffer = read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+        let testfile = parse_test(&buffer).map_err(|e| format!("{}: {}", file, e))?;
+        let isa = match testfile.isa_spec {
+            IsaSpec::Some(ref isas) => isas.last().expect("Empty ISA list"),
+            IsaSpec::None(_) => return Err(format!("{}: no ISA specified", file)),
+        };
+
+        let mut ctx = cretonne::Context::new();
+        for (func, _) in testfile.functions {
+            ctx.func = func;
+            ctx.legalize(&**isa).map_err(|e| format!("{}: {}", file, e))?;
+            self.add_function(&ctx.func, &**isa);
+        }
+        Ok(())
+    }
+
+    /// Count the instructions in the legalized function `func`.
+    fn add_function(&mut self, func: &Function, isa: &TargetIsa) {
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                self.insts += 1;
+                let bytes = match func.encodings.get(inst) {
+                    Some(enc) if enc.is_legal() => {
+                        let recipe = isa.recipe_names()[enc.recipe()];
+                        let bytes = isa.recipe_sizing()[enc.recipe()].bytes as u32;
+                        self.recipes
+                            .entry(recipe.to_string())
+                            .or_insert_with(Entry::default)
+                            .add(bytes);
+                        bytes
+                    }
+                    _ => {
+                        self.unencoded += 1;
+                        0
+                    }
+                };
+                self.bytes += bytes;
+                self.opcodes
+                    .entry(func.dfg[inst].opcode().to_string())
+                    .or_insert_with(Entry::default)
+                    .add(bytes);
+            }
+        }
+    }
+}
+
+/// Write the entries of `histogram`, largest code size first.
+fn write_histogram(f: &mut Formatter, histogram: &HashMap<String, Entry>) -> fmt::Result {
+    let mut entries: Vec<(&String, &Entry)> = histogram.iter().collect();
+    entries.sort_by(|a, b| (b.1.bytes, b.1.insts, a.0).cmp(&(a.1.bytes, a.1.insts, b.0)));
+    writeln!(f, "{:>8}  {:>8}  name", "insts", "bytes")?;
+    for (name, entry) in entries {
+        writeln!(f, "{:8}  {:8}  {}", entry.insts, entry.bytes, name)?;
+    }
+    Ok(())
+}
+
+impl Display for EncodingStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f,
+                 "{} instructions, {} without an encoding, {} bytes.",
+                 self.insts,
+                 self.unencoded,
+                 self.bytes)?;
+        writeln!(f, "\nRecipes:")?;
+        write_histogram(f, &self.recipes)?;
+        writeln!(f, "\nOpcodes:")?;
+        write_histogram(f, &self.opcodes)
+    }
+}
+
+/// Object implementing the `test encstats` sub-test.
+struct TestEncStats;
+
+pub fn subtest(parsed: &TestCommand) -> STResult<Box<SubTest>> {
+    assert_eq!(parsed.command, "encstats");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestEncStats))
+    }
+}
+
+impl SubTest for TestEncStats {
+    fn name(&self) -> Cow<str> {
+        Cow::from("encstats")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> STResult<()> {
+        let isa = context.isa.expect("encstats needs an ISA");
+
+        let mut ctx = cretonne::Context::new();
+        ctx.func = func.into_owned();
+        ctx.legalize(isa).map_err(|e| format!("after legalizer: {}", e))?;
+        let mut stats = EncodingStats::new();
+        stats.add_function(&ctx.func, isa);
+        subtest::run_filecheck(&stats.to_string(), context)
+    }
+}
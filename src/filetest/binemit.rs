@@ -0,0 +1,123 @@
+//! Test command for checking the machine code emitted for instructions.
+//!
+//! The `binemit` test command runs each function through the legalizer, the register allocator,
+//! and branch relaxation, and then emits its machine code. Instructions can be annotated with a
+//! `bin:` comment listing the expected bytes in hexadecimal:
+//!
+//! ```cton
+//!     v3 = iadd v1, v2 ; bin: 48 01 f7
+//! ```
+//!
+//! The bytes emitted for each annotated instruction are compared with its comment. This gives
+//! coverage of the encoding recipes without an external assembler. Instructions are emitted at
+//! their final code offsets, so branch displacements are checked too. The bytes covered by a
+//! relocation are emitted as zeros.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use cretonne;
+use cretonne::ir::Function;
+use cretonne::ir::entities::AnyEntity;
+use cretonne::regalloc::diversion::RegDiversions;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result};
+
+struct TestBinEmit;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "binemit");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestBinEmit))
+    }
+}
+
+/// Format `bytes` as space-separated lower case hexadecimal bytes.
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+impl SubTest for TestBinEmit {
+    fn name(&self) -> Cow<str> {
+        Cow::from("binemit")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("binemit needs an ISA");
+
+        // Collect the expected bytes of the annotated instructions.
+        let mut expected = HashMap::new();
+        for comment in &context.details.comments {
+            let text = comment.text.trim_left_matches(';').trim();
+            if !text.starts_with("bin:") {
+                continue;
+            }
+            let inst = match comment.entity {
+                AnyEntity::Inst(inst) => inst,
+                _ => return Err(format!("'{}' is not attached to an instruction", comment.text)),
+            };
+            let bytes: Vec<&str> = text[4..].split_whitespace().collect();
+            expected.insert(inst, bytes.join(" ").to_lowercase());
+        }
+
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+        comp_ctx.legalize(isa).map_err(|e| format!("after legalizer: {}", e))?;
+        comp_ctx.flowgraph();
+        comp_ctx.regalloc(isa).map_err(|e| format!("after regalloc: {}", e))?;
+        comp_ctx.relax_branches(isa)
+            .map_err(|e| format!("after branch relaxation: {}", e))?;
+        let func = &comp_ctx.func;
+
+        // Emit the whole function into one buffer so the instructions get their final offsets,
+        // and check the bytes emitted for each annotated instruction in code order.
+        let mut code = Vec::new();
+        let mut errors = Vec::new();
+        let mut divert = RegDiversions::new();
+        for ebb in func.layout.ebbs() {
+            divert.clear();
+            for inst in func.layout.ebb_insts(ebb) {
+                let want = expected.remove(&inst);
+                let enc = func.encodings.get(inst).cloned().unwrap_or_default();
+                if !enc.is_legal() {
+                    if want.is_some() {
+                        errors.push(format!("{} has no encoding", func.dfg[inst].opcode()));
+                    }
+                    continue;
+                }
+                let start = code.len();
+                isa.emit_inst(func, inst, &mut divert, &mut code);
+                let want = match want {
+                    Some(want) => want,
+                    None => continue,
+                };
+                let got = hex_bytes(&code[start..]);
+                if got != want {
+                    errors.push(format!("{} with encoding {} emitted '{}', expected '{}'",
+                                        func.dfg[inst].opcode(),
+                                        isa.display_enc(enc),
+                                        got,
+                                        want));
+                }
+            }
+        }
+        if !expected.is_empty() {
+            errors.push(format!("{} annotated instructions were removed", expected.len()));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Test command for checking the heap bounds check elimination pass.
+//!
+//! The `test bounds_checks` test command runs each function through
+//! `eliminate_bounds_checks()` and sends the result to filecheck.
+
+use std::borrow::Cow;
+use cretonne::{eliminate_bounds_checks, write_function, verify_function};
+use cretonne::cfg::ControlFlowGraph;
+use cretonne::dominator_tree::DominatorTree;
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestBoundsChecks;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "bounds_checks");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestBoundsChecks))
+    }
+}
+
+impl SubTest for TestBoundsChecks {
+    fn name(&self) -> Cow<str> {
+        Cow::from("bounds_checks")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let mut func = func.into_owned();
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+        eliminate_bounds_checks(&mut func, &domtree);
+        verify_function(&func).map_err(|e| format!("after bounds_checks: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &func, context.isa).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
@@ -0,0 +1,41 @@
+//! Test command for checking the instruction combiner.
+//!
+//! The `test combine` test command runs each function through `combine_function()` and sends the
+//! result to filecheck.
+
+use std::borrow::Cow;
+use cretonne::{combine_function, write_function, verify_function};
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestCombine;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "combine");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestCombine))
+    }
+}
+
+impl SubTest for TestCombine {
+    fn name(&self) -> Cow<str> {
+        Cow::from("combine")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let mut func = func.into_owned();
+        combine_function(&mut func);
+        verify_function(&func).map_err(|e| format!("after combine: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &func, context.isa).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
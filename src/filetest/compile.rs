@@ -0,0 +1,69 @@
+//! Test command for testing the whole code generator pipeline.
+//!
+//! The `compile` test command runs each function through `Context::compile()`, which legalizes
+//! the function, allocates registers, inserts the prologue and epilogue, and relaxes the branches.
+//!
+//! The compiled function is sent to `filecheck`. With the `code` option, it is followed by the
+//! machine code and the relocations, and the `disasm` option also lists the machine code of each
+//! instruction, just like `cton-util compile` prints them.
+
+use std::borrow::Cow;
+use compile::compile_to_text;
+use cretonne::{self, write_function};
+use cretonne::ir::Function;
+use cton_reader::{TestCommand, TestOption};
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestCompile {
+    /// Print the machine code after the compiled function.
+    code: bool,
+    /// List the machine code of each instruction.
+    disasm: bool,
+}
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "compile");
+    let mut test = TestCompile {
+        code: false,
+        disasm: false,
+    };
+    for opt in &parsed.options {
+        match *opt {
+            TestOption::Flag("code") => test.code = true,
+            TestOption::Flag("disasm") => test.disasm = true,
+            _ => return Err(format!("Unknown option {} on {}", opt, parsed)),
+        }
+    }
+    Ok(Box::new(test))
+}
+
+impl SubTest for TestCompile {
+    fn name(&self) -> Cow<str> {
+        Cow::from("compile")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("compile needs an ISA");
+
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+        if self.code || self.disasm {
+            let text = compile_to_text(&mut comp_ctx, isa, self.disasm)
+                .map_err(|e| format!("compile: {}", e))?;
+            return run_filecheck(&text, context);
+        }
+        comp_ctx.compile(isa).map_err(|e| format!("compile: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &comp_ctx.func, Some(isa)).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
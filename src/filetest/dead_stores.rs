@@ -0,0 +1,45 @@
+//! Test command for checking the dead stack store elimination pass.
+//!
+//! The `test dead_stores` test command runs each function through
+//! `eliminate_dead_stores()` and sends the result to filecheck.
+
+use std::borrow::Cow;
+use cretonne::{eliminate_dead_stores, write_function, verify_function};
+use cretonne::alias_analysis::BasicAliasAnalysis;
+use cretonne::cfg::ControlFlowGraph;
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestDeadStores;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "dead_stores");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestDeadStores))
+    }
+}
+
+impl SubTest for TestDeadStores {
+    fn name(&self) -> Cow<str> {
+        Cow::from("dead_stores")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let mut func = func.into_owned();
+        let cfg = ControlFlowGraph::with_function(&func);
+        let aa = BasicAliasAnalysis::with_function(&func);
+        eliminate_dead_stores(&mut func, &cfg, &aa);
+        verify_function(&func).map_err(|e| format!("after dead_stores: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &func, context.isa).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
@@ -0,0 +1,99 @@
+//! Test command for checking that compilation is deterministic.
+//!
+//! The `deterministic` test command compiles each function twice in the same context, clearing
+//! it in between, and checks that the compiled functions and the emitted machine code are
+//! identical. The code is compared byte by byte, and the relocations, traps, and stack maps are
+//! compared through `Context::code_hash()`.
+//!
+//! The compiled function is then run through filecheck, followed by a `; size=N` line with the
+//! size of the emitted code.
+
+use std::borrow::Cow;
+use cretonne::{self, binemit, write_function};
+use cretonne::ir::Function;
+use cretonne::isa::TargetIsa;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestDeterministic;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "deterministic");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestDeterministic))
+    }
+}
+
+/// The result of compiling a function.
+struct Compiled {
+    text: String,
+    code: Vec<u8>,
+    hash: u64,
+}
+
+/// Compile the function in `ctx` and emit its code.
+fn compile(ctx: &mut cretonne::Context, isa: &TargetIsa) -> Result<Compiled> {
+    ctx.compile(isa).map_err(|e| e.to_string())?;
+    let mut text = String::new();
+    write_function(&mut text, &ctx.func, Some(isa)).map_err(|e| e.to_string())?;
+    let mut code = Vec::new();
+    binemit::emit_function(&ctx.func, isa, &mut code);
+    Ok(Compiled {
+           text: text,
+           code: code,
+           hash: ctx.code_hash(isa),
+       })
+}
+
+impl SubTest for TestDeterministic {
+    fn name(&self) -> Cow<str> {
+        Cow::from("deterministic")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("deterministic needs an ISA");
+        let func = func.into_owned();
+
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.clone();
+        let first = compile(&mut comp_ctx, isa)?;
+        comp_ctx.clear();
+        comp_ctx.func = func;
+        let second = compile(&mut comp_ctx, isa)?;
+
+        if first.text != second.text {
+            return Err(format!("compiled functions differ:\n{}\n{}", first.text, second.text));
+        }
+        if let Some(offset) = first
+               .code
+               .iter()
+               .zip(&second.code)
+               .position(|(a, b)| a != b) {
+            return Err(format!("code differs at offset {}: {:02x} != {:02x}",
+                               offset,
+                               first.code[offset],
+                               second.code[offset]));
+        }
+        if first.code.len() != second.code.len() {
+            return Err(format!("code size differs: {} != {}",
+                               first.code.len(),
+                               second.code.len()));
+        }
+        if first.hash != second.hash {
+            return Err("relocations, traps, or stack maps differ".to_string());
+        }
+
+        let text = format!("{}; size={}\n", first.text, first.code.len());
+        run_filecheck(&text, context)
+    }
+}
@@ -0,0 +1,41 @@
+//! Test command for checking the constant folding pass.
+//!
+//! The `test fold` test command runs each function through `fold_constants()` and sends the
+//! result to filecheck.
+
+use std::borrow::Cow;
+use cretonne::{fold_constants, write_function, verify_function};
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestFold;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "fold");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestFold))
+    }
+}
+
+impl SubTest for TestFold {
+    fn name(&self) -> Cow<str> {
+        Cow::from("fold")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let mut func = func.into_owned();
+        fold_constants(&mut func);
+        verify_function(&func).map_err(|e| format!("after fold: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &func, context.isa).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
@@ -0,0 +1,47 @@
+//! Test command for checking the if-conversion pass.
+//!
+//! The `test if_conversion` test command runs each function through the if-conversion pass with
+//! the limit of the target ISA, and sends the result to filecheck.
+
+use std::borrow::Cow;
+use cretonne::{self, write_function};
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestIfConversion;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "if_conversion");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestIfConversion))
+    }
+}
+
+impl SubTest for TestIfConversion {
+    fn name(&self) -> Cow<str> {
+        Cow::from("if_conversion")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("if-conversion needs an ISA");
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+
+        comp_ctx.if_convert(isa).map_err(|e| format!("after if-conversion: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &comp_ctx.func, Some(isa)).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
@@ -1,10 +1,10 @@
 //! Test command for checking the IL legalizer.
 //!
 //! The `test legalizer` test command runs each function through `legalize_function()` and sends
-//! the result to filecheck.
+//! the result to filecheck. The test fails if an instruction is left without an encoding.
 
 use std::borrow::Cow;
-use cretonne::{legalize_function, write_function};
+use cretonne::{self, write_function};
 use cretonne::ir::Function;
 use cton_reader::TestCommand;
 use filetest::subtest::{SubTest, Context, Result, run_filecheck};
@@ -34,12 +34,24 @@ impl SubTest for TestLegalizer {
     }
 
     fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
-        let mut func = func.into_owned();
         let isa = context.isa.expect("legalizer needs an ISA");
-        legalize_function(&mut func, isa);
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+
+        comp_ctx.legalize(isa).map_err(|e| format!("after legalizer: {}", e))?;
+
+        let func = &comp_ctx.func;
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                if !func.encodings.get(inst).cloned().unwrap_or_default().is_legal() {
+                    return Err(format!("after legalizer: {} has no encoding",
+                                       func.dfg[inst].opcode()));
+                }
+            }
+        }
 
         let mut text = String::new();
-        write_function(&mut text, &func, Some(isa)).map_err(|e| e.to_string())?;
+        write_function(&mut text, &comp_ctx.func, Some(isa)).map_err(|e| e.to_string())?;
         run_filecheck(&text, context)
     }
 }
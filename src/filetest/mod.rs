@@ -8,17 +8,37 @@ use std::time;
 use cton_reader::TestCommand;
 use CommandResult;
 use cat;
+use enccov;
+use encstats;
 use print_cfg;
+use reduce;
 use filetest::runner::TestRunner;
 
 pub mod subtest;
 
+mod binemit;
+mod bounds_checks;
+mod combine;
+mod compile;
 mod concurrent;
+mod dead_stores;
+mod deterministic;
 mod domtree;
+mod fold;
+mod if_conversion;
 mod legalizer;
+mod postopt;
+mod preopt;
+mod prologue_epilogue;
+mod redundant_loads;
 mod regalloc;
+mod relax_branches;
+mod run;
 mod runner;
 mod runone;
+mod serialize;
+mod shadow_memory;
+mod unwind;
 mod verifier;
 
 /// The result of running the test in a file.
@@ -59,7 +79,27 @@ fn new_subtest(parsed: &TestCommand) -> subtest::Result<Box<subtest::SubTest>> {
         "domtree" => domtree::subtest(parsed),
         "verifier" => verifier::subtest(parsed),
         "legalizer" => legalizer::subtest(parsed),
+        "combine" => combine::subtest(parsed),
+        "fold" => fold::subtest(parsed),
+        "preopt" => preopt::subtest(parsed),
+        "bounds_checks" => bounds_checks::subtest(parsed),
+        "shadow_memory" => shadow_memory::subtest(parsed),
+        "dead_stores" => dead_stores::subtest(parsed),
+        "redundant_loads" => redundant_loads::subtest(parsed),
+        "if_conversion" => if_conversion::subtest(parsed),
         "regalloc" => regalloc::subtest(parsed),
+        "compile" => compile::subtest(parsed),
+        "postopt" => postopt::subtest(parsed),
+        "prologue_epilogue" => prologue_epilogue::subtest(parsed),
+        "relax_branches" => relax_branches::subtest(parsed),
+        "binemit" => binemit::subtest(parsed),
+        "unwind" => unwind::subtest(parsed),
+        "deterministic" => deterministic::subtest(parsed),
+        "run" => run::subtest(parsed),
+        "serialize" => serialize::subtest(parsed),
+        "reduce" => reduce::subtest(parsed),
+        "enccov" => enccov::subtest(parsed),
+        "encstats" => encstats::subtest(parsed),
         _ => Err(format!("unknown test command '{}'", parsed.command)),
     }
 }
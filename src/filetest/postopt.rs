@@ -0,0 +1,53 @@
+//! Test command for testing the post-optimization pass.
+//!
+//! The `postopt` test command runs each function through the legalizer and the register allocator,
+//! and then through the post-optimization pass.
+//!
+//! The resulting function is sent to `filecheck`.
+
+use std::borrow::Cow;
+use cretonne::{self, write_function};
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestPostopt;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "postopt");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestPostopt))
+    }
+}
+
+impl SubTest for TestPostopt {
+    fn name(&self) -> Cow<str> {
+        Cow::from("postopt")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("postopt needs an ISA");
+
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+
+        comp_ctx.legalize(isa).map_err(|e| format!("after legalizer: {}", e))?;
+        comp_ctx.flowgraph();
+        comp_ctx.regalloc(isa).map_err(|e| format!("after regalloc: {}", e))?;
+        comp_ctx.postopt(isa).map_err(|e| format!("after postopt: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &comp_ctx.func, Some(isa)).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
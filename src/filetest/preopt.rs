@@ -0,0 +1,41 @@
+//! Test command for checking the pre-optimization pass.
+//!
+//! The `test preopt` test command runs each function through `do_preopt()` and sends the
+//! result to filecheck.
+
+use std::borrow::Cow;
+use cretonne::{do_preopt, write_function, verify_function};
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestPreopt;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "preopt");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestPreopt))
+    }
+}
+
+impl SubTest for TestPreopt {
+    fn name(&self) -> Cow<str> {
+        Cow::from("preopt")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let mut func = func.into_owned();
+        do_preopt(&mut func);
+        verify_function(&func).map_err(|e| format!("after preopt: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &func, context.isa).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
@@ -0,0 +1,54 @@
+//! Test command for testing the prologue and epilogue insertion pass.
+//!
+//! The `prologue_epilogue` test command runs each function through the legalizer and the register
+//! allocator, and then saves and restores the callee-saved registers used by the function.
+//!
+//! The resulting function is sent to `filecheck`.
+
+use std::borrow::Cow;
+use cretonne::{self, write_function};
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestPrologueEpilogue;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "prologue_epilogue");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestPrologueEpilogue))
+    }
+}
+
+impl SubTest for TestPrologueEpilogue {
+    fn name(&self) -> Cow<str> {
+        Cow::from("prologue_epilogue")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("prologue_epilogue needs an ISA");
+
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+
+        comp_ctx.legalize(isa).map_err(|e| format!("after legalizer: {}", e))?;
+        comp_ctx.flowgraph();
+        comp_ctx.regalloc(isa).map_err(|e| format!("after regalloc: {}", e))?;
+        comp_ctx.prologue_epilogue(isa)
+            .map_err(|e| format!("after prologue/epilogue: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &comp_ctx.func, Some(isa)).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
@@ -0,0 +1,47 @@
+//! Test command for checking the redundant load elimination pass.
+//!
+//! The `test redundant_loads` test command runs each function through
+//! `eliminate_redundant_loads()` and sends the result to filecheck.
+
+use std::borrow::Cow;
+use cretonne::{eliminate_redundant_loads, write_function, verify_function};
+use cretonne::alias_analysis::BasicAliasAnalysis;
+use cretonne::cfg::ControlFlowGraph;
+use cretonne::dominator_tree::DominatorTree;
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestRedundantLoads;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "redundant_loads");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestRedundantLoads))
+    }
+}
+
+impl SubTest for TestRedundantLoads {
+    fn name(&self) -> Cow<str> {
+        Cow::from("redundant_loads")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let mut func = func.into_owned();
+        let cfg = ControlFlowGraph::with_function(&func);
+        let domtree = DominatorTree::with_function(&func, &cfg);
+        let aa = BasicAliasAnalysis::with_function(&func);
+        eliminate_redundant_loads(&mut func, &cfg, &domtree, &aa);
+        verify_function(&func).map_err(|e| format!("after redundant_loads: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &func, context.isa).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
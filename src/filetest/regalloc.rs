@@ -43,10 +43,10 @@ impl SubTest for TestRegalloc {
         comp_ctx.func = func.into_owned();
 
         // TODO: Should we have an option to skip legalization?
-        comp_ctx.legalize(isa);
+        comp_ctx.legalize(isa).map_err(|e| format!("after legalizer: {}", e))?;
 
         comp_ctx.flowgraph();
-        comp_ctx.regalloc(isa);
+        comp_ctx.regalloc(isa).map_err(|e| format!("after regalloc: {}", e))?;
 
         let mut text = String::new();
         write_function(&mut text, &comp_ctx.func, Some(isa)).map_err(|e| e.to_string())?;
@@ -0,0 +1,69 @@
+//! Test command for testing the branch relaxation pass.
+//!
+//! The `relax_branches` test command runs each function through the legalizer and the register
+//! allocator, and then relaxes the branches and computes the EBB offsets.
+//!
+//! The resulting function is sent to `filecheck` with the code offset of each EBB written as a
+//! comment on its header, followed by a `; jtN offset=M` line for each jump table and a `; size=N`
+//! line with the total code size, including the jump tables. The size is also checked against
+//! `binemit::code_size()`.
+
+use std::borrow::Cow;
+use cretonne::{self, binemit, write_annotated_function, Annotations};
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestRelaxBranches;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "relax_branches");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestRelaxBranches))
+    }
+}
+
+impl SubTest for TestRelaxBranches {
+    fn name(&self) -> Cow<str> {
+        Cow::from("relax_branches")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("relax_branches needs an ISA");
+
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+
+        comp_ctx.legalize(isa).map_err(|e| format!("after legalizer: {}", e))?;
+        comp_ctx.flowgraph();
+        comp_ctx.regalloc(isa).map_err(|e| format!("after regalloc: {}", e))?;
+        let size = comp_ctx.relax_branches(isa)
+            .map_err(|e| format!("after branch relaxation: {}", e))?;
+        let computed = binemit::code_size(&mut comp_ctx.func, isa);
+        if computed != size {
+            return Err(format!("code_size() computed {} bytes, expected {}", computed, size));
+        }
+
+        let mut text = String::new();
+        write_annotated_function(&mut text,
+                                 &comp_ctx.func,
+                                 Some(isa),
+                                 &Annotations { offsets: true, ..Annotations::default() })
+                .map_err(|e| e.to_string())?;
+        for jt in comp_ctx.func.jump_tables.keys() {
+            text.push_str(&format!("; {} offset={}\n", jt, comp_ctx.func.jt_offsets[jt]));
+        }
+        text.push_str(&format!("; size={}\n", size));
+        run_filecheck(&text, context)
+    }
+}
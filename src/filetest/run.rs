@@ -0,0 +1,97 @@
+//! Test command for running compiled functions.
+//!
+//! The `run` test command compiles each function with the simple JIT and calls it on the host.
+//! The functions must have the signature `() -> b1`, and the test fails unless they return true:
+//!
+//! ```cton
+//! function add() -> b1 {
+//! ebb0:
+//!     v1 = iconst.i32 2
+//!     v2 = iadd_imm v1, 3
+//!     br_icmp eq, v2, v1, ebb1
+//!     v3 = bconst.b1 true
+//!     return v3
+//! ebb1:
+//!     v4 = bconst.b1 false
+//!     return v4
+//! }
+//! ```
+//!
+//! The functions can only run when the ISA is the host's. For other ISAs, the test does nothing,
+//! so the same test file can list several ISAs.
+
+use std::borrow::Cow;
+use std::mem;
+use cretonne;
+use cretonne::ir::{Function, types};
+use cretonne::isa::{self, TargetIsa};
+use cton_module::{Module, Linkage};
+use cton_reader::TestCommand;
+use cton_simplejit::SimpleJITBackend;
+use filetest::subtest::{SubTest, Context, Result};
+
+struct TestRun;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "run");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestRun))
+    }
+}
+
+/// Can functions compiled for `isa` run on the host?
+fn is_host_isa(isa: &TargetIsa) -> bool {
+    cfg!(any(target_arch = "x86", target_arch = "x86_64")) && isa.name() == "intel" &&
+    isa.flags().is_64bit() == cfg!(target_pointer_width = "64") && !isa.flags().is_pic()
+}
+
+impl SubTest for TestRun {
+    fn name(&self) -> Cow<str> {
+        Cow::from("run")
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("run needs an ISA");
+        if !is_host_isa(isa) {
+            return Ok(());
+        }
+
+        let sig = &func.signature;
+        if !sig.argument_types.is_empty() || sig.return_types.len() != 1 ||
+           sig.return_types[0].value_type != types::B1 {
+            return Err(format!("can't run a function with the signature {}", sig));
+        }
+
+        // The JIT backend owns its ISA, so make a copy with the same flags.
+        let jit_isa = isa::lookup(isa.name())
+            .expect("the host ISA is available")
+            .finish(isa.flags().clone());
+        let mut module = Module::new(SimpleJITBackend::new(jit_isa));
+        let id = module
+            .declare_function(&func.name.to_string(), Linkage::Local, sig)
+            .map_err(|e| e.to_string())?;
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+        module.define_function(id, &mut comp_ctx).map_err(|e| e.to_string())?;
+        module.finalize().map_err(|e| e.to_string())?;
+
+        // The compiled function returns the `b1` as 0 or 1 in a register, which is how the host's
+        // C calling convention returns a `bool`.
+        let code = module.backend().get_finalized_function(id);
+        let result = unsafe {
+            let f: extern "C" fn() -> bool = mem::transmute(code);
+            f()
+        };
+        if result {
+            Ok(())
+        } else {
+            Err("function returned false".to_string())
+        }
+    }
+}
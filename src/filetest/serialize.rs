@@ -0,0 +1,47 @@
+//! Test command for checking the binary serialization of functions.
+//!
+//! The `serialize` test command encodes each function in the binary format, decodes it again, and
+//! checks that the decoded function prints the same as the original. The decoded function is then
+//! run through filecheck, so a file can share its directives with `test cat`.
+
+use std::borrow::Cow;
+use cretonne::ir::Function;
+use cretonne::serialize::{encode_function, decode_function};
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestSerialize;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "serialize");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestSerialize))
+    }
+}
+
+impl SubTest for TestSerialize {
+    fn name(&self) -> Cow<str> {
+        Cow::from("serialize")
+    }
+
+    fn needs_verifier(&self) -> bool {
+        false
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let bytes = encode_function(&func);
+        let decoded = decode_function(&bytes).map_err(|e| e.to_string())?;
+
+        let text = func.to_string();
+        let decoded_text = decoded.to_string();
+        if decoded_text != text {
+            return Err(format!("decoded function differs:\n{}", decoded_text));
+        }
+        if encode_function(&decoded) != bytes {
+            return Err("decoded function encodes differently".to_string());
+        }
+        run_filecheck(&decoded_text, context)
+    }
+}
@@ -0,0 +1,47 @@
+//! Test command for checking the shadow memory instrumentation pass.
+//!
+//! The `test shadow_memory` test command runs each function through
+//! `instrument_heap_accesses()` and sends the result to filecheck. The first global variable
+//! declared by the function is used as the shadow memory base.
+
+use std::borrow::Cow;
+use cretonne::{instrument_heap_accesses, write_function, verify_function};
+use cretonne::ir::Function;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestShadowMemory;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "shadow_memory");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestShadowMemory))
+    }
+}
+
+impl SubTest for TestShadowMemory {
+    fn name(&self) -> Cow<str> {
+        Cow::from("shadow_memory")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let mut func = func.into_owned();
+        let shadow = func.dfg
+            .global_vars
+            .keys()
+            .next()
+            .ok_or_else(|| "no global variable for the shadow memory".to_string())?;
+        instrument_heap_accesses(&mut func, shadow);
+        verify_function(&func).map_err(|e| format!("after shadow_memory: {}", e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &func, context.isa).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
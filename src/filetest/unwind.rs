@@ -0,0 +1,78 @@
+//! Test command for testing the unwind information.
+//!
+//! The `unwind` test command runs each function through the legalizer, the register allocator,
+//! and the prologue and epilogue insertion pass, and then creates its unwind information.
+//!
+//! The unwind information is sent to `filecheck` in a readable form, followed by the serialized
+//! formats as lines of hexadecimal bytes.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+use cretonne;
+use cretonne::ir::Function;
+use cretonne::isa::intel::unwind;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+
+struct TestUnwind;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "unwind");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestUnwind))
+    }
+}
+
+impl SubTest for TestUnwind {
+    fn name(&self) -> Cow<str> {
+        Cow::from("unwind")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("unwind needs an ISA");
+
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+
+        comp_ctx.legalize(isa).map_err(|e| format!("after legalizer: {}", e))?;
+        comp_ctx.flowgraph();
+        comp_ctx.regalloc(isa).map_err(|e| format!("after regalloc: {}", e))?;
+        comp_ctx.prologue_epilogue(isa)
+            .map_err(|e| format!("after prologue/epilogue: {}", e))?;
+
+        let info = match isa.create_unwind_info(&comp_ctx.func) {
+            Some(info) => info,
+            None => return Err(format!("no unwind information for {}", isa.name())),
+        };
+
+        let mut text = info.display(&isa.register_info()).to_string();
+        // The serializers are specific to Intel.
+        if isa.name() == "intel" {
+            if info.pointer_bytes == 8 {
+                write_bytes(&mut text, "windows", &unwind::write_windows_unwind_info(&info));
+            }
+            write_bytes(&mut text, "fde", &unwind::write_fde_instructions(&info));
+        }
+        run_filecheck(&text, context)
+    }
+}
+
+/// Write `bytes` in hexadecimal on a line starting with `name`.
+fn write_bytes(text: &mut String, name: &str, bytes: &[u8]) {
+    text.push_str(name);
+    text.push(':');
+    for byte in bytes {
+        write!(text, " {:02x}", byte).unwrap();
+    }
+    text.push('\n');
+}
@@ -1,96 +1,59 @@
 //! The `print-cfg` sub-command.
 //!
 //! Read a series of Cretonne IL files and print their control flow graphs
-//! in graphviz format.
+//! in graphviz format, optionally showing the dominator tree and the loops.
 
 use std::borrow::Cow;
-use std::fmt::{Result, Write, Display, Formatter};
 
 use CommandResult;
 use utils::read_to_string;
 use filetest::subtest::{self, SubTest, Context, Result as STResult};
 use cretonne::ir::Function;
-use cretonne::cfg::ControlFlowGraph;
-use cretonne::ir::instructions::BranchInfo;
-use cton_reader::{parse_functions, TestCommand};
+use cretonne::cfg::{dot, ControlFlowGraph};
+use cretonne::dominator_tree::DominatorTree;
+use cretonne::loop_analysis::LoopAnalysis;
+use cton_reader::{parse_functions, TestCommand, TestOption};
 
-pub fn run(files: Vec<String>) -> CommandResult {
+pub fn run(files: Vec<String>, options: Options) -> CommandResult {
     for (i, f) in files.into_iter().enumerate() {
         if i != 0 {
             println!("");
         }
-        print_cfg(f)?
+        print_cfg(f, options)?
     }
     Ok(())
 }
 
-struct CFGPrinter<'a> {
-    func: &'a Function,
-    cfg: ControlFlowGraph,
+/// Options for the `print-cfg` sub-command and the `test print-cfg` sub-test.
+#[derive(Clone, Copy, Default)]
+pub struct Options {
+    /// Show the dominator tree.
+    pub domtree: bool,
+    /// Show the loop nesting.
+    pub loops: bool,
 }
 
-impl<'a> CFGPrinter<'a> {
-    pub fn new(func: &'a Function) -> CFGPrinter<'a> {
-        CFGPrinter {
-            func: func,
-            cfg: ControlFlowGraph::with_function(func),
-        }
-    }
-
-    /// Write the CFG for this function to `w`.
-    pub fn write(&self, w: &mut Write) -> Result {
-        self.header(w)?;
-        self.ebb_nodes(w)?;
-        self.cfg_connections(w)?;
-        writeln!(w, "}}")
-    }
-
-    fn header(&self, w: &mut Write) -> Result {
-        writeln!(w, "digraph {} {{", self.func.name)?;
-        if let Some(entry) = self.func.layout.entry_block() {
-            writeln!(w, "    {{rank=min; {}}}", entry)?;
-        }
-        Ok(())
-    }
-
-    fn ebb_nodes(&self, w: &mut Write) -> Result {
-        for ebb in &self.func.layout {
-            write!(w, "    {} [shape=record, label=\"{{{}", ebb, ebb)?;
-            // Add all outgoing branch instructions to the label.
-            for inst in self.func.layout.ebb_insts(ebb) {
-                let idata = &self.func.dfg[inst];
-                match idata.analyze_branch() {
-                    BranchInfo::SingleDest(dest, _) => {
-                        write!(w, " | <{}>{} {}", inst, idata.opcode(), dest)?
-                    }
-                    BranchInfo::Table(table) => {
-                        write!(w, " | <{}>{} {}", inst, idata.opcode(), table)?
-                    }
-                    BranchInfo::NotABranch => {}
-                }
-            }
-            writeln!(w, "}}\"]")?
-        }
-        Ok(())
-    }
-
-    fn cfg_connections(&self, w: &mut Write) -> Result {
-        for ebb in &self.func.layout {
-            for &(parent, inst) in self.cfg.get_predecessors(ebb) {
-                writeln!(w, "    {}:{} -> {}", parent, inst, ebb)?;
-            }
-        }
-        Ok(())
-    }
-}
-
-impl<'a> Display for CFGPrinter<'a> {
-    fn fmt(&self, f: &mut Formatter) -> Result {
-        self.write(f)
-    }
+/// Write the control flow graph of `func` in graphviz format, with the overlays selected by
+/// `options`.
+fn cfg_to_string(func: &Function, options: Options) -> String {
+    let cfg = ControlFlowGraph::with_function(func);
+    let domtree = DominatorTree::with_function(func, &cfg);
+    let loops = if options.loops {
+        Some(LoopAnalysis::with_function(func, &cfg, &domtree))
+    } else {
+        None
+    };
+    let mut text = String::new();
+    dot::write(&mut text,
+               func,
+               &cfg,
+               if options.domtree { Some(&domtree) } else { None },
+               loops.as_ref())
+        .expect("writing to a string can't fail");
+    text
 }
 
-fn print_cfg(filename: String) -> CommandResult {
+fn print_cfg(filename: String, options: Options) -> CommandResult {
     let buffer = read_to_string(&filename).map_err(|e| format!("{}: {}", filename, e))?;
     let items = parse_functions(&buffer).map_err(|e| format!("{}: {}", filename, e))?;
 
@@ -98,22 +61,28 @@ fn print_cfg(filename: String) -> CommandResult {
         if idx != 0 {
             println!("");
         }
-        print!("{}", CFGPrinter::new(&func));
+        print!("{}", cfg_to_string(&func, options));
     }
 
     Ok(())
 }
 
 /// Object implementing the `test print-cfg` sub-test.
-struct TestPrintCfg;
+///
+/// The `domtree` and `loops` options select the same overlays as the command line options.
+struct TestPrintCfg(Options);
 
 pub fn subtest(parsed: &TestCommand) -> STResult<Box<SubTest>> {
     assert_eq!(parsed.command, "print-cfg");
-    if !parsed.options.is_empty() {
-        Err(format!("No options allowed on {}", parsed))
-    } else {
-        Ok(Box::new(TestPrintCfg))
+    let mut options = Options::default();
+    for opt in &parsed.options {
+        match *opt {
+            TestOption::Flag("domtree") => options.domtree = true,
+            TestOption::Flag("loops") => options.loops = true,
+            _ => return Err(format!("Unknown option {} on {}", opt, parsed)),
+        }
     }
+    Ok(Box::new(TestPrintCfg(options)))
 }
 
 impl SubTest for TestPrintCfg {
@@ -126,6 +95,6 @@ impl SubTest for TestPrintCfg {
     }
 
     fn run(&self, func: Cow<Function>, context: &Context) -> STResult<()> {
-        subtest::run_filecheck(&CFGPrinter::new(&func).to_string(), context)
+        subtest::run_filecheck(&cfg_to_string(&func, self.0), context)
     }
 }
@@ -0,0 +1,389 @@
+//! The `reduce` sub-command.
+//!
+//! Reduce a Cretonne IL file to a minimal test case which still satisfies a predicate command.
+//!
+//! The predicate command is run with the path of a candidate file appended to its arguments. The
+//! candidate is considered interesting when the command exits successfully. For example, a
+//! script that runs `cton-util test` on its argument and checks for a specific panic message can
+//! be used to find a minimal reproduction of a backend crash.
+//!
+//! The reducer repeatedly tries these simplifications until none of them are interesting any
+//! longer:
+//!
+//! - Removing whole functions.
+//! - Removing EBBs other than the entry block.
+//! - Removing EBB arguments along with the branch arguments passed to them. The uses of a removed
+//!   argument are replaced with a constant.
+//! - Removing single instructions.
+//! - Replacing a scalar integer or float result with a constant.
+//!
+//! Most of these simplifications can produce invalid functions. When reducing a miscompile rather
+//! than a verifier failure, the `--verify` option makes the reducer reject candidates that fail
+//! the verifier without running the predicate on them, so the predicate can't be satisfied by an
+//! unrelated bug in an invalid function.
+//!
+//! The reduced file is printed to stdout.
+//!
+//! The `test reduce` test command reduces each function in a test file for as long as the
+//! verifier keeps reporting the same error, and sends the reduced function to filecheck followed
+//! by a `; error: ...` line with the verifier error.
+
+use cretonne::verify_function;
+use cretonne::ir::{Function, Cursor, Ebb, Inst, InstBuilder, Opcode, Type, Value, types};
+use cretonne::ir::instructions::BranchInfo;
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use cton_reader::{parse_functions, TestCommand};
+use filetest::subtest::{self, SubTest, Context, Result as STResult};
+use std::borrow::Cow;
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use CommandResult;
+use utils::read_to_string;
+
+pub fn run(file: String, predicate: Vec<String>, verify: bool) -> CommandResult {
+    let buffer = read_to_string(&file).map_err(|e| format!("{}: {}", file, e))?;
+    let funcs = parse_functions(&buffer).map_err(|e| format!("{}: {}", file, e))?;
+
+    let mut reducer = Reducer {
+        header: file_header(&buffer),
+        predicate: Predicate::Command {
+            args: predicate,
+            scratch: env::temp_dir().join(format!("cton-reduce-{}.cton", process::id())),
+        },
+        verify: verify,
+        funcs: funcs,
+    };
+    if verify {
+        for func in &reducer.funcs {
+            verify_function(func).map_err(|e| format!("{}: {}: {}", file, func.name, e))?;
+        }
+    }
+    if !reducer.interesting(&reducer.funcs)? {
+        return Err(format!("{}: the predicate doesn't hold for the original file", file));
+    }
+    reducer.reduce()?;
+    print!("{}", reducer.text(&reducer.funcs));
+    Ok(())
+}
+
+/// Get the lines preceding the first function in `buffer`.
+///
+/// This includes the `test`, `set`, and `isa` lines which the predicate may depend on.
+fn file_header(buffer: &str) -> String {
+    let mut header = String::new();
+    for line in buffer.lines() {
+        if line.trim_left().starts_with("function") {
+            break;
+        }
+        header.push_str(line);
+        header.push('\n');
+    }
+    header
+}
+
+/// A single simplification of a function.
+#[derive(Clone, Copy)]
+enum Mutation {
+    RemoveEbb(Ebb),
+    RemoveEbbArg(Ebb, usize),
+    RemoveInst(Inst),
+    ReplaceWithConst(Inst),
+}
+
+impl Mutation {
+    /// Get all the mutations that could be applied to `func`.
+    fn candidates(func: &Function) -> Vec<Mutation> {
+        let mut muts = Vec::new();
+        for ebb in func.layout.ebbs().skip(1) {
+            muts.push(Mutation::RemoveEbb(ebb));
+        }
+        for ebb in func.layout.ebbs() {
+            // Removing an argument renumbers the following ones, so start from the last one.
+            for num in (0..func.dfg.num_ebb_args(ebb)).rev() {
+                muts.push(Mutation::RemoveEbbArg(ebb, num));
+            }
+        }
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                muts.push(Mutation::RemoveInst(inst));
+                muts.push(Mutation::ReplaceWithConst(inst));
+            }
+        }
+        muts
+    }
+
+    /// Apply this mutation to `func`. Return false if it doesn't apply.
+    fn apply(self, func: &mut Function) -> bool {
+        match self {
+            Mutation::RemoveEbb(ebb) => {
+                if !func.layout.is_ebb_inserted(ebb) {
+                    return false;
+                }
+                while let Some(inst) = func.layout.last_inst(ebb) {
+                    func.layout.remove_inst(inst);
+                }
+                func.layout.remove_ebb(ebb);
+                true
+            }
+            Mutation::RemoveEbbArg(ebb, num) => remove_ebb_arg(func, ebb, num),
+            Mutation::RemoveInst(inst) => {
+                if func.layout.inst_ebb(inst).is_none() {
+                    return false;
+                }
+                func.layout.remove_inst(inst);
+                true
+            }
+            Mutation::ReplaceWithConst(inst) => {
+                if func.layout.inst_ebb(inst).is_none() ||
+                   func.dfg.inst_results(inst).count() != 1 {
+                    return false;
+                }
+                let ty = func.dfg.value_type(func.dfg.first_result(inst));
+                if !has_zero_const(ty) {
+                    return false;
+                }
+                match func.dfg[inst].opcode() {
+                    Opcode::Iconst | Opcode::F32const | Opcode::F64const => return false,
+                    _ => {}
+                }
+                match ty {
+                    types::F32 => {
+                        func.dfg.replace(inst).f32const(Ieee32::new(0.0));
+                    }
+                    types::F64 => {
+                        func.dfg.replace(inst).f64const(Ieee64::new(0.0));
+                    }
+                    _ => {
+                        func.dfg.replace(inst).iconst(ty, 0);
+                    }
+                }
+                true
+            }
+        }
+    }
+}
+
+/// Can a zero constant of type `ty` be created?
+fn has_zero_const(ty: Type) -> bool {
+    ty == ty.lane_type() && (ty.is_int() || ty == types::F32 || ty == types::F64)
+}
+
+/// Remove argument number `num` from `ebb`, and replace its uses with a zero constant at the top
+/// of `ebb`. Return false if the argument can't be removed.
+///
+/// The corresponding argument is removed from all the branches to `ebb`. Arguments to the entry
+/// block are also removed from the function signature.
+fn remove_ebb_arg(func: &mut Function, ebb: Ebb, num: usize) -> bool {
+    if !func.layout.is_ebb_inserted(ebb) {
+        return false;
+    }
+    let arg = match func.dfg.ebb_args(ebb).nth(num) {
+        Some(arg) => arg,
+        None => return false,
+    };
+    let ty = func.dfg.value_type(arg);
+    if !has_zero_const(ty) {
+        return false;
+    }
+    if func.layout.entry_block() == Some(ebb) {
+        if func.signature.argument_types.len() != func.dfg.num_ebb_args(ebb) {
+            return false;
+        }
+        func.signature.argument_types.remove(num);
+    }
+
+    let zero = {
+        let pos = &mut Cursor::new(&mut func.layout);
+        pos.goto_top(ebb);
+        pos.next_inst();
+        match ty {
+            types::F32 => func.dfg.ins(pos).f32const(Ieee32::new(0.0)),
+            types::F64 => func.dfg.ins(pos).f64const(Ieee64::new(0.0)),
+            _ => func.dfg.ins(pos).iconst(ty, 0),
+        }
+    };
+    func.dfg.remove_ebb_arg(arg);
+    func.dfg.change_to_alias(arg, zero);
+
+    let insts: Vec<Inst> = func.layout
+        .ebbs()
+        .flat_map(|e| func.layout.ebb_insts(e))
+        .collect();
+    for inst in insts {
+        let args: Vec<Value> = match func.dfg.analyze_branch(inst) {
+            BranchInfo::SingleDest(dest, varargs) if dest == ebb && num < varargs.len() => {
+                varargs
+                    .iter()
+                    .enumerate()
+                    .filter(|&(idx, _)| idx != num)
+                    .map(|(_, &value)| value)
+                    .collect()
+            }
+            _ => continue,
+        };
+        func.dfg.set_inst_variable_args(inst, &args);
+    }
+    true
+}
+
+/// The property that a candidate must have to be interesting.
+enum Predicate {
+    /// The command succeeds when run on a file containing the candidate.
+    Command {
+        /// The predicate command and its arguments.
+        args: Vec<String>,
+        /// Path to the file holding the current candidate.
+        scratch: PathBuf,
+    },
+
+    /// The verifier reports this error for one of the functions.
+    VerifierError(String),
+}
+
+struct Reducer {
+    /// Text preceding the functions in the original file.
+    header: String,
+
+    /// The property preserved by the reduction.
+    predicate: Predicate,
+
+    /// Reject the candidates that fail the verifier without running the predicate.
+    verify: bool,
+
+    /// The functions reduced so far.
+    funcs: Vec<Function>,
+}
+
+impl Reducer {
+    /// Apply simplifications until no more simplifications are interesting.
+    fn reduce(&mut self) -> CommandResult {
+        let mut progress = true;
+        while progress {
+            progress = false;
+
+            // Try removing whole functions first, they are the biggest chunks.
+            let mut idx = 0;
+            while self.funcs.len() > 1 && idx < self.funcs.len() {
+                let mut candidate = self.funcs.clone();
+                candidate.remove(idx);
+                if self.interesting(&candidate)? {
+                    self.funcs = candidate;
+                    progress = true;
+                } else {
+                    idx += 1;
+                }
+            }
+
+            for idx in 0..self.funcs.len() {
+                for mutation in Mutation::candidates(&self.funcs[idx]) {
+                    let mut func = self.funcs[idx].clone();
+                    if !mutation.apply(&mut func) {
+                        continue;
+                    }
+                    let mut candidate = self.funcs.clone();
+                    candidate[idx] = func;
+                    if self.interesting(&candidate)? {
+                        self.funcs = candidate;
+                        progress = true;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the text of a file containing `funcs`.
+    fn text(&self, funcs: &[Function]) -> String {
+        let mut text = self.header.clone();
+        for (idx, func) in funcs.iter().enumerate() {
+            if idx != 0 {
+                text.push('\n');
+            }
+            text.push_str(&func.to_string());
+        }
+        text
+    }
+
+    /// Does the predicate hold for a file containing `funcs`?
+    fn interesting(&self, funcs: &[Function]) -> Result<bool, String> {
+        if self.verify && funcs.iter().any(|func| verify_function(func).is_err()) {
+            return Ok(false);
+        }
+        let (args, scratch) = match self.predicate {
+            Predicate::Command { ref args, ref scratch } => (args, scratch),
+            Predicate::VerifierError(ref error) => {
+                return Ok(funcs
+                              .iter()
+                              .any(|func| match verify_function(func) {
+                                       Err(e) => e.to_string() == *error,
+                                       Ok(()) => false,
+                                   }));
+            }
+        };
+        write_file(scratch, &self.text(funcs))
+            .map_err(|e| format!("{}: {}", scratch.display(), e))?;
+        let status = Command::new(&args[0])
+            .args(&args[1..])
+            .arg(scratch)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| format!("{}: {}", args[0], e))?;
+        Ok(status.success())
+    }
+}
+
+impl Drop for Reducer {
+    fn drop(&mut self) {
+        // The scratch file may not exist if the predicate was never run.
+        if let Predicate::Command { ref scratch, .. } = self.predicate {
+            let _ = fs::remove_file(scratch);
+        }
+    }
+}
+
+fn write_file(path: &Path, text: &str) -> ::std::io::Result<()> {
+    File::create(path)?.write_all(text.as_bytes())
+}
+
+/// Object implementing the `test reduce` sub-test.
+struct TestReduce;
+
+pub fn subtest(parsed: &TestCommand) -> STResult<Box<SubTest>> {
+    assert_eq!(parsed.command, "reduce");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestReduce))
+    }
+}
+
+impl SubTest for TestReduce {
+    fn name(&self) -> Cow<str> {
+        Cow::from("reduce")
+    }
+
+    fn needs_verifier(&self) -> bool {
+        false
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> STResult<()> {
+        let error = match verify_function(&func) {
+            Err(e) => e.to_string(),
+            Ok(()) => return Err("the function passes the verifier".to_string()),
+        };
+        let mut reducer = Reducer {
+            header: String::new(),
+            predicate: Predicate::VerifierError(error.clone()),
+            verify: false,
+            funcs: vec![func.into_owned()],
+        };
+        reducer.reduce()?;
+
+        let text = format!("{}; error: {}\n", reducer.funcs[0], error);
+        subtest::run_filecheck(&text, context)
+    }
+}
@@ -0,0 +1,95 @@
+//! The `wasm` sub-command.
+//!
+//! Translate the functions in a series of WebAssembly modules to Cretonne IL with the
+//! `DummyEnvironment`, and print or compile them. The modules can be in the binary or the text
+//! format.
+//!
+//! Without `--target`, the translated functions are printed with `--print`. The `--optimize`
+//! option runs the ISA-independent pre-optimizations on them first.
+//!
+//! With `--target`, the functions are compiled like the `compile` sub-command does, and the
+//! `--optimize` option sets the `opt_level` setting to `speed`. The compiled functions are printed
+//! with `--print`, and otherwise only their code sizes are printed.
+
+use compile::{make_isa, compile_to_text};
+use cretonne::{self, settings, do_preopt};
+use cretonne::isa::TargetIsa;
+use cton_wasm::{translate_module, DummyEnvironment};
+use std::path::Path;
+use wat;
+use CommandResult;
+
+/// Options for the `wasm` sub-command.
+pub struct Options {
+    /// Print the translated or compiled functions.
+    pub print: bool,
+    /// Run the verifier on the translated functions.
+    pub check: bool,
+    /// Optimize the translated functions.
+    pub optimize: bool,
+    /// List the machine code of each instruction in the compiled functions.
+    pub disasm: bool,
+}
+
+pub fn run(files: Vec<String>,
+           target: Option<String>,
+           mut settings: Vec<String>,
+           options: Options)
+           -> CommandResult {
+    let isa = match target {
+        Some(target) => {
+            if options.optimize {
+                // Explicit `--set opt_level=...` options come later and take precedence.
+                settings.insert(0, "opt_level=speed".to_string());
+            }
+            Some(make_isa(&target, &settings)?)
+        }
+        None if settings.is_empty() => None,
+        None => return Err("--set requires a --target".to_string()),
+    };
+    for file in files {
+        translate_file(&file, isa.as_ref().map(|isa| &**isa), &options)
+            .map_err(|e| format!("{}: {}", file, e))?;
+    }
+    Ok(())
+}
+
+/// Translate the functions in the WebAssembly module `file`, and compile them for `isa` if given.
+fn translate_file(file: &str, isa: Option<&TargetIsa>, options: &Options) -> CommandResult {
+    let data = wat::parse_file(Path::new(file)).map_err(|e| e.to_string())?;
+    let mut env = match isa {
+        Some(isa) => DummyEnvironment::with_flags(isa.flags().clone()),
+        None => DummyEnvironment::with_flags(settings::Flags::new(&settings::builder())),
+    };
+    translate_module(&data, &mut env).map_err(|e| e.to_string())?;
+
+    let mut ctx = cretonne::Context::new();
+    for func in env.info.function_bodies {
+        ctx.clear();
+        ctx.func = func;
+        if options.check {
+            ctx.flowgraph();
+            ctx.verify(isa).map_err(|e| format!("{}: {}", ctx.func.name, e))?;
+        }
+        match isa {
+            Some(isa) if options.print => {
+                let text = compile_to_text(&mut ctx, isa, options.disasm)
+                    .map_err(|e| format!("{}: {}", ctx.func.name, e))?;
+                println!("{}", text);
+            }
+            Some(isa) => {
+                let size = ctx.compile(isa).map_err(|e| format!("{}: {}", ctx.func.name, e))?;
+                println!("{}: {} bytes", ctx.func.name, size);
+            }
+            None => {
+                if options.optimize {
+                    do_preopt(&mut ctx.func);
+                }
+                if options.print {
+                    println!("{}", ctx.func);
+                }
+            }
+        }
+    }
+    Ok(())
+}
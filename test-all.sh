@@ -4,6 +4,7 @@
 #
 # - Build documentation for Rust code in 'src/tools/target/doc'.
 # - Run unit tests for all Rust crates.
+# - Build the cretonne crate without std.
 # - Make a debug build of all crates.
 # - Make a release build of cton-util.
 # - Run file-level tests with the release build of cton-util.
@@ -49,7 +50,7 @@ fi
 banner $(python --version 2>&1)
 $topdir/lib/cretonne/meta/check.sh
 
-PKGS="cretonne cretonne-reader cretonne-tools filecheck"
+PKGS="cretonne cretonne-reader cretonne-capi cretonne-frontend cretonne-module cretonne-object cretonne-simplejit cretonne-wasm cretonne-tools filecheck"
 cd "$topdir"
 for PKG in $PKGS
 do
@@ -57,6 +58,9 @@ do
     cargo test -p $PKG
 done
 
+banner "Rust cretonne no_std build"
+(cd "$topdir/lib/cretonne" && cargo build --no-default-features --features "core all-arch")
+
 # Build cton-util for parser testing.
 cd "$topdir"
 banner "Rust documentation"
@@ -71,4 +75,7 @@ cd "$topdir"
 banner "File tests"
 "$CTONUTIL" test filetests
 
+banner "WebAssembly tests"
+"$CTONUTIL" wasm -c --target=intel --set=is_64bit=1 filetests/wasm/*.wat
+
 banner "OK"
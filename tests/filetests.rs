@@ -0,0 +1,32 @@
+//! Run the file tests in the `filetests` directory.
+//!
+//! The file tests are run by `cton-util test`. Cargo builds the binaries of a package before its
+//! integration tests, so this makes `cargo test` cover the file tests too.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Get the path of the `cton-util` binary in the target directory of this test.
+fn cton_util() -> PathBuf {
+    let mut dir = env::current_exe().expect("Can't find the test executable");
+    dir.pop();
+    if dir.ends_with("deps") {
+        dir.pop();
+    }
+    dir.join(format!("cton-util{}", env::consts::EXE_SUFFIX))
+}
+
+#[test]
+fn filetests() {
+    let filetests = Path::new(env!("CARGO_MANIFEST_DIR")).join("filetests");
+    let output = Command::new(cton_util())
+        .arg("test")
+        .arg(&filetests)
+        .output()
+        .expect("Can't run cton-util");
+    assert!(output.status.success(),
+            "File tests failed:\n{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr));
+}